@@ -216,6 +216,9 @@ pub enum ErrorKind {
     /// GC error.
     #[error("GC Error: {0}")]
     GCError(String),
+    /// The block isn't on the canonical chain.
+    #[error("Block {0} is not on the canonical chain")]
+    NotOnCanonicalChain(near_primitives::hash::CryptoHash),
     /// Anything else
     #[error("Other Error: {0}")]
     Other(String),
@@ -278,7 +281,8 @@ impl Error {
             | ErrorKind::ChallengedBlockOnChain
             | ErrorKind::StorageError(_)
             | ErrorKind::GCError(_)
-            | ErrorKind::DBNotFoundErr(_) => false,
+            | ErrorKind::DBNotFoundErr(_)
+            | ErrorKind::NotOnCanonicalChain(_) => false,
             ErrorKind::InvalidBlockPastTime(_, _)
             | ErrorKind::InvalidBlockFutureTime(_)
             | ErrorKind::InvalidBlockHeight(_)
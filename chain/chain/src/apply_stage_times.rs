@@ -0,0 +1,147 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lru::LruCache;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{ShardId, TrieReadStats};
+use near_primitives::views::ChunkApplyStageTimesView;
+use once_cell::sync::Lazy;
+
+use crate::metrics;
+
+/// Stage times of the most recently applied chunks, by block hash and shard.
+/// Used only for the debug pages.
+static RECENT_STAGE_TIMES: Lazy<Mutex<LruCache<(CryptoHash, ShardId), ApplyChunkStageTimes>>> =
+    Lazy::new(|| Mutex::new(LruCache::new(10000)));
+
+/// Time spent in the stages of applying a chunk, and where the reads of the
+/// trie were served from.
+///
+/// The stage names used as the `stage` label of the metrics are part of the
+/// monitoring interface and shouldn't be changed.  There is no flat storage
+/// nor prefetching of trie nodes in this version, so all reads go through the
+/// chunk and shard caches of the trie.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ApplyChunkStageTimes {
+    /// Converting the transactions to receipts.
+    pub transactions: Duration,
+    /// Executing the local, delayed and incoming receipts.
+    pub receipts: Duration,
+    /// Computing the trie changes from the state update.
+    pub trie_update: Duration,
+    /// Computing the merkle root of the outcomes.
+    pub outcome_root: Duration,
+    /// `None` if the trie didn't read through the caches, e.g. when it
+    /// recorded a storage proof.
+    pub trie_reads: Option<TrieReadStats>,
+}
+
+impl ApplyChunkStageTimes {
+    fn stages(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("transactions", self.transactions),
+            ("receipts", self.receipts),
+            ("trie_update", self.trie_update),
+            ("outcome_root", self.outcome_root),
+        ]
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.transactions += other.transactions;
+        self.receipts += other.receipts;
+        self.trie_update += other.trie_update;
+        self.outcome_root += other.outcome_root;
+        if let Some(trie_reads) = &other.trie_reads {
+            self.trie_reads.get_or_insert_with(Default::default).add(trie_reads);
+        }
+    }
+
+    /// Exports the times of a chunk applied in the given block.
+    pub(crate) fn record_chunk(&self, block_hash: CryptoHash, shard_id: ShardId) {
+        let shard_label = shard_id.to_string();
+        for (stage, time) in self.stages() {
+            metrics::CHUNK_APPLY_STAGE_TIME
+                .with_label_values(&[&shard_label, stage])
+                .observe(time.as_secs_f64());
+        }
+        if let Some(trie_reads) = &self.trie_reads {
+            for (source, reads) in [
+                ("chunk_cache", trie_reads.chunk_cache_hits),
+                ("shard_cache", trie_reads.shard_cache_hits),
+                ("db", trie_reads.db_reads),
+            ] {
+                metrics::CHUNK_APPLY_TRIE_READS
+                    .with_label_values(&[&shard_label, source])
+                    .inc_by(reads);
+            }
+            for (source, bytes) in
+                [("cache", trie_reads.bytes - trie_reads.db_bytes), ("db", trie_reads.db_bytes)]
+            {
+                metrics::CHUNK_APPLY_TRIE_READ_BYTES
+                    .with_label_values(&[&shard_label, source])
+                    .inc_by(bytes);
+            }
+        }
+        RECENT_STAGE_TIMES.lock().unwrap().put((block_hash, shard_id), *self);
+    }
+
+    /// Exports the times of all chunks applied in a block, summed.
+    pub(crate) fn record_block(&self) {
+        for (stage, time) in self.stages() {
+            metrics::BLOCK_APPLY_STAGE_TIME.with_label_values(&[stage]).observe(time.as_secs_f64());
+        }
+    }
+
+    /// Returns the times of the chunk of the shard applied in the block, if
+    /// it was applied recently.
+    pub fn get(block_hash: &CryptoHash, shard_id: ShardId) -> Option<Self> {
+        RECENT_STAGE_TIMES.lock().unwrap().get(&(*block_hash, shard_id)).copied()
+    }
+}
+
+impl From<ApplyChunkStageTimes> for ChunkApplyStageTimesView {
+    fn from(times: ApplyChunkStageTimes) -> Self {
+        Self {
+            transactions_micros: times.transactions.as_micros() as u64,
+            receipts_micros: times.receipts.as_micros() as u64,
+            trie_update_micros: times.trie_update.as_micros() as u64,
+            outcome_root_micros: times.outcome_root.as_micros() as u64,
+            trie_reads: times.trie_reads,
+        }
+    }
+}
+
+#[test]
+fn test_apply_chunk_stage_times() {
+    let times = ApplyChunkStageTimes {
+        transactions: Duration::from_millis(1),
+        receipts: Duration::from_millis(20),
+        trie_update: Duration::from_millis(3),
+        outcome_root: Duration::from_micros(400),
+        trie_reads: Some(TrieReadStats {
+            chunk_cache_hits: 5,
+            shard_cache_hits: 3,
+            db_reads: 2,
+            bytes: 1000,
+            db_bytes: 300,
+        }),
+    };
+    let mut block_times = ApplyChunkStageTimes::default();
+    block_times.add(&times);
+    block_times.add(&ApplyChunkStageTimes { trie_reads: None, ..times });
+    block_times.add(&times);
+    assert_eq!(block_times.receipts, Duration::from_millis(60));
+    assert_eq!(block_times.outcome_root, Duration::from_micros(1200));
+    let block_reads = block_times.trie_reads.unwrap();
+    assert_eq!((block_reads.db_reads, block_reads.db_bytes), (4, 600));
+    assert_eq!(block_reads.cache_hit_rate(), Some(0.8));
+
+    let block_hash = CryptoHash::hash_bytes(b"block");
+    times.record_chunk(block_hash, 2);
+    assert_eq!(ApplyChunkStageTimes::get(&block_hash, 2), Some(times));
+    assert_eq!(ApplyChunkStageTimes::get(&block_hash, 1), None);
+    let view = ChunkApplyStageTimesView::from(times);
+    assert_eq!(view.receipts_micros, 20000);
+    assert_eq!(view.outcome_root_micros, 400);
+    assert_eq!(view.trie_reads, times.trie_reads);
+}
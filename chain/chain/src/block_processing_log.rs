@@ -0,0 +1,153 @@
+use borsh::BorshDeserialize;
+use near_primitives::utils::index_to_bytes;
+use near_primitives::views::BlockProcessingView;
+use near_store::{DBCol, Store};
+use std::io;
+use tracing::warn;
+
+/// Ring buffer in [`DBCol::BlockProcessingLog`] with the outcomes of the most
+/// recently processed blocks, so that after a crash it's known what the node
+/// was doing.
+///
+/// Each record is written with its own small write as soon as the block is
+/// processed.  Rows are keyed by the position in the buffer and hold a
+/// sequence number next to the record, which is what orders them.
+pub struct BlockProcessingLog {
+    /// `None` if the log is disabled.
+    store: Option<Store>,
+    capacity: u64,
+    next_seq: u64,
+    /// Called with every record, even if the log is disabled.
+    observer: Option<Box<dyn Fn(&BlockProcessingView) + Send + Sync>>,
+}
+
+impl BlockProcessingLog {
+    pub fn disabled() -> Self {
+        Self { store: None, capacity: 0, next_seq: 0, observer: None }
+    }
+
+    pub fn with_observer(
+        mut self,
+        observer: Box<dyn Fn(&BlockProcessingView) + Send + Sync>,
+    ) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Continues the log found in the store.  If the size of the buffer
+    /// changed, the records which still fit are moved to their new rows and
+    /// the rest are removed.
+    pub fn open(store: Store, capacity: u64) -> io::Result<Self> {
+        if capacity == 0 {
+            return Ok(Self::disabled());
+        }
+        let rows = read_rows(&store)?;
+        let next_seq = rows.first().map_or(0, |(_, seq, _)| seq + 1);
+        let oldest_kept = next_seq.saturating_sub(capacity);
+        let misplaced = |key: &[u8], seq: u64| key != index_to_bytes(seq % capacity);
+        let mut store_update = store.store_update();
+        // Deletions go first so that they don't remove the moved records.
+        for (key, seq, _) in &rows {
+            if *seq < oldest_kept || misplaced(key, *seq) {
+                store_update.delete(DBCol::BlockProcessingLog, key);
+            }
+        }
+        for (key, seq, record) in &rows {
+            if *seq >= oldest_kept && misplaced(key, *seq) {
+                let key = index_to_bytes(seq % capacity);
+                store_update.set_ser(DBCol::BlockProcessingLog, &key, &(seq, record))?;
+            }
+        }
+        store_update.commit()?;
+        Ok(Self { store: Some(store), capacity, next_seq, observer: None })
+    }
+
+    pub fn record(&mut self, record: &BlockProcessingView) {
+        if let Some(observer) = &self.observer {
+            observer(record);
+        }
+        let store = match &self.store {
+            Some(store) => store,
+            None => return,
+        };
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let mut store_update = store.store_update();
+        let key = index_to_bytes(seq % self.capacity);
+        let result = store_update
+            .set_ser(DBCol::BlockProcessingLog, &key, &(seq, record))
+            .and_then(|()| store_update.commit());
+        if let Err(err) = result {
+            warn!(target: "chain", ?err, "Failed to save the block processing log record");
+        }
+    }
+}
+
+/// Reads the records of the block processing log, most recent first.
+pub fn read_block_processing_log(store: &Store) -> io::Result<Vec<BlockProcessingView>> {
+    Ok(read_rows(store)?.into_iter().map(|(_, _, record)| record).collect())
+}
+
+/// Returns the key, sequence number and record of each row, most recent first.
+fn read_rows(store: &Store) -> io::Result<Vec<(Box<[u8]>, u64, BlockProcessingView)>> {
+    let mut rows = vec![];
+    for (key, value) in store.iter(DBCol::BlockProcessingLog) {
+        let (seq, record) = <(u64, BlockProcessingView)>::try_from_slice(&value)?;
+        rows.push((key, seq, record));
+    }
+    rows.sort_by(|(_, a, _), (_, b, _)| b.cmp(a));
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::views::BlockProcessingOutcome;
+    use near_store::test_utils::create_test_store;
+
+    fn record(height: u64) -> BlockProcessingView {
+        BlockProcessingView {
+            hash: CryptoHash::default(),
+            height,
+            timestamp: 0,
+            processing_time_micros: 0,
+            shard_apply_time_micros: vec![(0, 0)],
+            outcome: BlockProcessingOutcome::Accepted,
+        }
+    }
+
+    fn heights(store: &Store) -> Vec<u64> {
+        read_block_processing_log(store).unwrap().iter().map(|record| record.height).collect()
+    }
+
+    #[test]
+    fn test_block_processing_log() {
+        let store = create_test_store();
+        let mut log = BlockProcessingLog::open(store.clone(), 3).unwrap();
+        for height in 1..=5 {
+            log.record(&record(height));
+        }
+        assert_eq!(heights(&store), vec![5, 4, 3]);
+
+        // Reopening continues after the last record.
+        let mut log = BlockProcessingLog::open(store.clone(), 3).unwrap();
+        log.record(&record(6));
+        assert_eq!(heights(&store), vec![6, 5, 4]);
+
+        // Shrinking keeps the most recent records.
+        let mut log = BlockProcessingLog::open(store.clone(), 2).unwrap();
+        assert_eq!(heights(&store), vec![6, 5]);
+        log.record(&record(7));
+        assert_eq!(heights(&store), vec![7, 6]);
+
+        // Growing keeps all of them.
+        let mut log = BlockProcessingLog::open(store.clone(), 4).unwrap();
+        log.record(&record(8));
+        log.record(&record(9));
+        assert_eq!(heights(&store), vec![9, 8, 7, 6]);
+
+        BlockProcessingLog::disabled().record(&record(10));
+        assert_eq!(heights(&store), vec![9, 8, 7, 6]);
+    }
+}
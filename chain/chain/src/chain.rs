@@ -40,16 +40,18 @@ use near_primitives::types::{
     NumBlocks, NumShards, ShardId, StateChangesForSplitStates, StateRoot,
 };
 use near_primitives::unwrap_or_return;
-use near_primitives::utils::MaybeValidated;
+use near_primitives::utils::{to_timestamp, MaybeValidated};
 use near_primitives::views::{
-    BlockStatusView, ExecutionOutcomeWithIdView, ExecutionStatusView, FinalExecutionOutcomeView,
-    FinalExecutionOutcomeWithReceiptView, FinalExecutionStatus, LightClientBlockView,
-    SignedTransactionView,
+    BlockProcessingOutcome, BlockProcessingView, BlockStatusView, ExecutionOutcomeWithIdView,
+    ExecutionStatusView, FinalExecutionOutcomeView, FinalExecutionOutcomeWithReceiptView,
+    FinalExecutionStatus, LightClientBlockView, SignedTransactionView,
 };
 use near_store::{DBCol, ShardTries, StoreUpdate};
 
-use near_primitives::state_record::StateRecord;
+use near_primitives::state_record::StatePatchRecord;
 
+use crate::apply_stage_times::ApplyChunkStageTimes;
+use crate::block_processing_log::BlockProcessingLog;
 use crate::blocks_delay_tracker::BlocksDelayTracker;
 use crate::crypto_hash_timer::CryptoHashTimer;
 use crate::lightclient::get_epoch_block_producers_view;
@@ -80,6 +82,9 @@ pub const MAX_ORPHAN_SIZE: usize = 1024;
 /// Maximum age of orhpan to store in the chain.
 const MAX_ORPHAN_AGE_SECS: u64 = 300;
 
+/// Default limit of the total size of the orphans.
+pub const DEFAULT_MAX_ORPHAN_POOL_BYTES: usize = 64 * 1024 * 1024;
+
 // Number of orphan ancestors should be checked to request chunks
 // Orphans for which we will request for missing chunks must satisfy,
 // its NUM_ORPHAN_ANCESTORS_CHECK'th ancestor has been accepted
@@ -135,6 +140,8 @@ pub struct Orphan {
     block: MaybeValidated<Block>,
     provenance: Provenance,
     added: Instant,
+    /// Size of the serialized block.
+    size_bytes: usize,
 }
 
 impl BlockLike for Orphan {
@@ -145,9 +152,18 @@ impl BlockLike for Orphan {
     fn height(&self) -> u64 {
         self.block.header().height()
     }
+
+    fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
 }
 
 impl Orphan {
+    fn new(block: MaybeValidated<Block>, provenance: Provenance, added: Instant) -> Self {
+        let size_bytes = block.get_inner().try_to_vec().map_or(0, |bytes| bytes.len());
+        Self { block, provenance, added, size_bytes }
+    }
+
     fn prev_hash(&self) -> &CryptoHash {
         self.block.header().prev_hash()
     }
@@ -160,6 +176,8 @@ impl Orphan {
 /// or
 /// 2) size of the pool exceeds MAX_ORPHAN_SIZE and the orphan was added a long time ago
 ///    or the height is high
+/// or
+/// 3) the orphans take more than `max_bytes` and the height is the lowest
 pub struct OrphanBlockPool {
     /// A map from block hash to a orphan block
     orphans: HashMap<CryptoHash, Orphan>,
@@ -176,6 +194,9 @@ pub struct OrphanBlockPool {
     prev_hash_idx: HashMap<CryptoHash, Vec<CryptoHash>>,
     /// number of orphans that were evicted
     evicted: usize,
+    /// Total size of the orphans
+    bytes: usize,
+    max_bytes: usize,
 }
 
 impl OrphanBlockPool {
@@ -186,13 +207,24 @@ impl OrphanBlockPool {
             height_idx: HashMap::default(),
             prev_hash_idx: HashMap::default(),
             evicted: 0,
+            bytes: 0,
+            max_bytes: DEFAULT_MAX_ORPHAN_POOL_BYTES,
         }
     }
 
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+    }
+
     pub fn len(&self) -> usize {
         self.orphans.len()
     }
 
+    /// Total size of the orphans.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
     fn len_evicted(&self) -> usize {
         self.evicted
     }
@@ -206,15 +238,17 @@ impl OrphanBlockPool {
         let prev_hash_entries =
             self.prev_hash_idx.entry(*orphan.block.header().prev_hash()).or_default();
         prev_hash_entries.push(block_hash);
-        self.orphans.insert(block_hash, orphan);
+        self.bytes += orphan.size_bytes;
+        if let Some(previous) = self.orphans.insert(block_hash, orphan) {
+            self.bytes -= previous.size_bytes;
+        }
         if requested_missing_chunks {
             self.orphans_requested_missing_chunks.insert(block_hash);
         }
 
+        let old_len = self.orphans.len();
+        let mut removed_hashes: HashSet<CryptoHash> = HashSet::default();
         if self.orphans.len() > MAX_ORPHAN_SIZE {
-            let old_len = self.orphans.len();
-
-            let mut removed_hashes: HashSet<CryptoHash> = HashSet::default();
             self.orphans.retain(|_, ref mut x| {
                 let keep = x.added.elapsed() < TimeDuration::from_secs(MAX_ORPHAN_AGE_SECS);
                 if !keep {
@@ -235,12 +269,34 @@ impl OrphanBlockPool {
                     break;
                 }
             }
+            self.bytes = self.orphans.values().map(|x| x.size_bytes).sum();
+        }
+        if self.bytes > self.max_bytes {
+            let mut heights = self.height_idx.keys().cloned().collect::<Vec<u64>>();
+            heights.sort_unstable();
+            for h in heights.iter() {
+                if self.bytes <= self.max_bytes {
+                    break;
+                }
+                if let Some(hash) = self.height_idx.remove(h) {
+                    for h in hash {
+                        if let Some(orphan) = self.orphans.remove(&h) {
+                            self.bytes -= orphan.size_bytes;
+                        }
+                        removed_hashes.insert(h);
+                    }
+                }
+            }
+        }
+        if !removed_hashes.is_empty() {
             self.height_idx.retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(x)));
             self.prev_hash_idx
                 .retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(x)));
             self.orphans_requested_missing_chunks.retain(|x| !removed_hashes.contains(x));
 
-            self.evicted += old_len - self.orphans.len();
+            let evicted = old_len - self.orphans.len();
+            self.evicted += evicted;
+            metrics::ORPHANS_EVICTED_TOTAL.inc_by(evicted as u64);
         }
     }
 
@@ -273,8 +329,11 @@ impl OrphanBlockPool {
                     self.orphans_requested_missing_chunks.remove(h);
                     self.orphans.remove(h)
                 })
-                .collect()
+                .collect::<Vec<_>>()
         });
+        if let Some(orphans) = &ret {
+            self.bytes -= orphans.iter().map(|x| x.size_bytes).sum::<usize>();
+        }
 
         self.height_idx.retain(|_, ref mut xs| xs.iter().any(|x| !removed_hashes.contains(x)));
 
@@ -440,8 +499,13 @@ pub struct Chain {
     /// Block economics, relevant to changes when new block must be produced.
     pub block_economics_config: BlockEconomicsConfig,
     pub doomslug_threshold_mode: DoomslugThresholdMode,
-    pending_states_to_patch: Option<Vec<StateRecord>>,
+    pending_states_to_patch: Option<Vec<StatePatchRecord>>,
+    /// State root of the first shard after the block which applied the last
+    /// sandbox state patch.
+    last_patched_state_root: Option<StateRoot>,
     pub blocks_delay_tracker: BlocksDelayTracker,
+    /// Outcomes of the recently processed blocks, disabled unless opened by the client.
+    pub block_processing_log: BlockProcessingLog,
 }
 
 impl ChainAccess for Chain {
@@ -499,7 +563,9 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            last_patched_state_root: None,
             blocks_delay_tracker: BlocksDelayTracker::default(),
+            block_processing_log: BlockProcessingLog::disabled(),
         })
     }
 
@@ -627,7 +693,9 @@ impl Chain {
             block_economics_config: BlockEconomicsConfig::from(chain_genesis),
             doomslug_threshold_mode,
             pending_states_to_patch: None,
+            last_patched_state_root: None,
             blocks_delay_tracker: BlocksDelayTracker::default(),
+            block_processing_log: BlockProcessingLog::disabled(),
         })
     }
 
@@ -724,10 +792,8 @@ impl Chain {
             byzantine_assert!(false);
             return Err(e.into());
         }
-        self.orphans.add(
-            Orphan { block, provenance: Provenance::NONE, added: Clock::instant() },
-            requested_missing_chunks,
-        );
+        self.orphans
+            .add(Orphan::new(block, Provenance::NONE, Clock::instant()), requested_missing_chunks);
         Ok(())
     }
 
@@ -862,12 +928,27 @@ impl Chain {
             chain_store_update.commit()?;
         }
 
+        // The state changes of the canonical blocks are kept while they're
+        // retained for longer than the rest of the data, and until the ones
+        // retained before are deleted.
+        let state_changes_tail = self.store.state_changes_tail()?;
+        let retain_state_changes = gc_config.gc_state_changes_extra_epochs > 0
+            || state_changes_tail.map_or(false, |state_changes_tail| state_changes_tail < tail);
+        if retain_state_changes && state_changes_tail.is_none() {
+            let mut chain_store_update = self.store.store_update();
+            chain_store_update.update_state_changes_tail(tail);
+            chain_store_update.commit()?;
+        }
+
         // Canonical Chain Clearing
         for height in tail + 1..gc_stop_height {
             if gc_blocks_remaining == 0 {
-                return Ok(());
+                break;
             }
             let mut chain_store_update = self.store.store_update();
+            if retain_state_changes {
+                chain_store_update.retain_state_changes();
+            }
             if let Ok(blocks_current_height) =
                 chain_store_update.get_chain_store().get_all_block_hashes_by_height(height)
             {
@@ -898,6 +979,51 @@ impl Chain {
             chain_store_update.update_tail(height)?;
             chain_store_update.commit()?;
         }
+
+        if retain_state_changes {
+            let stop_height = gc_config.state_changes_stop_height(
+                gc_stop_height,
+                self.genesis.header().height(),
+                self.epoch_length,
+            );
+            self.clear_state_changes(stop_height, &mut gc_blocks_remaining)?;
+        } else if state_changes_tail.is_some() {
+            let mut chain_store_update = self.store.store_update();
+            chain_store_update.update_state_changes_tail(self.store.tail()?);
+            chain_store_update.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the state changes of the canonical blocks below `stop_height`
+    /// kept by garbage collection, from the state changes tail up to the tail
+    /// at most.
+    fn clear_state_changes(
+        &mut self,
+        stop_height: BlockHeight,
+        gc_blocks_remaining: &mut NumBlocks,
+    ) -> Result<(), Error> {
+        let state_changes_tail = match self.store.state_changes_tail()? {
+            Some(state_changes_tail) => state_changes_tail,
+            None => return Ok(()),
+        };
+        for height in state_changes_tail..stop_height.min(self.store.tail()?) {
+            if *gc_blocks_remaining == 0 {
+                break;
+            }
+            let mut chain_store_update = self.store.store_update();
+            match chain_store_update.get_block_hash_by_height(height) {
+                Ok(block_hash) => {
+                    chain_store_update.clear_state_changes(&block_hash);
+                    *gc_blocks_remaining -= 1;
+                }
+                Err(err) if matches!(err.kind(), ErrorKind::DBNotFoundErr(_)) => {}
+                Err(err) => return Err(err),
+            }
+            chain_store_update.update_state_changes_tail(height + 1);
+            chain_store_update.commit()?;
+        }
+        metrics::STATE_CHANGES_TAIL_HEIGHT.set(self.store.state_changes_retained_from()? as i64);
         Ok(())
     }
 
@@ -1330,11 +1456,17 @@ impl Chain {
     ) -> Result<Option<Tip>, Error> {
         metrics::BLOCK_PROCESSING_ATTEMPTS_TOTAL.inc();
         metrics::NUM_ORPHANS.set(self.orphans.len() as i64);
+        metrics::ORPHAN_POOL_BYTES.set(self.orphans.bytes() as i64);
+        metrics::NUM_BLOCKS_WITH_MISSING_CHUNKS.set(self.blocks_with_missing_chunks.len() as i64);
+        metrics::BLOCKS_WITH_MISSING_CHUNKS_POOL_BYTES
+            .set(self.blocks_with_missing_chunks.bytes() as i64);
         let block_hash = *block.hash();
         let _timer = CryptoHashTimer::new(block_hash);
         let success_timer = metrics::BLOCK_PROCESSING_TIME.start_timer();
 
         let block_height = block.header().height();
+        let started = Instant::now();
+        let mut shard_apply_times = vec![];
         let res = self.process_block_single_impl(
             me,
             block,
@@ -1343,6 +1475,14 @@ impl Chain {
             block_misses_chunks,
             orphan_misses_chunks,
             on_challenge,
+            &mut shard_apply_times,
+        );
+        self.record_block_processing(
+            block_hash,
+            block_height,
+            started.elapsed(),
+            shard_apply_times,
+            &res,
         );
 
         match &res {
@@ -1364,6 +1504,37 @@ impl Chain {
         res
     }
 
+    fn record_block_processing(
+        &mut self,
+        hash: CryptoHash,
+        height: BlockHeight,
+        processing_time: TimeDuration,
+        shard_apply_times: Vec<(ShardId, TimeDuration)>,
+        res: &Result<Option<Tip>, Error>,
+    ) {
+        let outcome = match res {
+            Ok(_) => BlockProcessingOutcome::Accepted,
+            Err(e) => match e.kind() {
+                // Known blocks are ignored and not worth a record.
+                ErrorKind::BlockKnown(_) => return,
+                ErrorKind::Orphan => BlockProcessingOutcome::Orphaned,
+                ErrorKind::ChunksMissing(_) => BlockProcessingOutcome::MissingChunks,
+                _ => BlockProcessingOutcome::Error(e.to_string()),
+            },
+        };
+        self.block_processing_log.record(&BlockProcessingView {
+            hash,
+            height,
+            timestamp: to_timestamp(Clock::utc()),
+            processing_time_micros: processing_time.as_micros() as u64,
+            shard_apply_time_micros: shard_apply_times
+                .into_iter()
+                .map(|(shard_id, time)| (shard_id, time.as_micros() as u64))
+                .collect(),
+            outcome,
+        });
+    }
+
     // Block processing. Unlike process_block_single() this function doesn't update metrics for
     // successful blocks processing.
     fn process_block_single_impl(
@@ -1375,6 +1546,7 @@ impl Chain {
         block_misses_chunks: &mut dyn FnMut(BlockMissingChunks),
         orphan_misses_chunks: &mut dyn FnMut(OrphanMissingChunks),
         on_challenge: &mut dyn FnMut(ChallengeBody),
+        shard_apply_times: &mut Vec<(ShardId, TimeDuration)>,
     ) -> Result<Option<Tip>, Error> {
         let block_height = block.header().height();
         let _span = tracing::debug_span!(
@@ -1412,7 +1584,7 @@ impl Chain {
 
                             let time = Clock::instant();
                             self.blocks_delay_tracker.mark_block_orphaned(block.hash(), time);
-                            let orphan = Orphan { block, provenance, added: time };
+                            let orphan = Orphan::new(block, provenance, time);
                             self.orphans.add(orphan, requested_missing_chunks);
 
                             debug!(
@@ -1439,7 +1611,7 @@ impl Chain {
                         });
                         let time = Clock::instant();
                         self.blocks_delay_tracker.mark_block_has_missing_chunks(block.hash(), time);
-                        let orphan = Orphan { block, provenance, added: time };
+                        let orphan = Orphan::new(block, provenance, time);
                         self.blocks_with_missing_chunks
                             .add_block_with_missing_chunks(orphan, missing_chunk_hashes.clone());
                         debug!(
@@ -1470,7 +1642,15 @@ impl Chain {
         // 2) apply chunks, this is where the transactions and receipts are processed. At this step,
         //    there still no change to ChainStore.
         let (apply_chunk_work, block_preprocess_info) = preprocess_res;
-        let apply_results = do_apply_chunks(apply_chunk_work);
+        let apply_results = do_apply_chunks_timed(apply_chunk_work)
+            .into_iter()
+            .map(|(time, result)| {
+                if let Ok(result) = &result {
+                    shard_apply_times.push((result.shard_uid().shard_id as ShardId, time));
+                }
+                result
+            })
+            .collect();
 
         // 3) finally, store the block on chain. Here we write all the necessary changes in chain_update,
         //    which will be committed to storage all at once.
@@ -1482,7 +1662,12 @@ impl Chain {
                 chain_update.chain_store_update.save_block_height_processed(block_height);
                 chain_update.commit()?;
 
-                self.pending_states_to_patch = None;
+                if self.pending_states_to_patch.take().is_some() {
+                    let shard_uid =
+                        self.runtime_adapter.shard_id_to_uid(0, block.header().epoch_id())?;
+                    let state_root = *self.get_chunk_extra(block.hash(), &shard_uid)?.state_root();
+                    self.last_patched_state_root = Some(state_root);
+                }
 
                 if let Some(tip) = &head {
                     if let Ok(producers) = self
@@ -1534,6 +1719,9 @@ impl Chain {
         // We are still in the process of refactoring this code to move everything in
         // ChainUpdate::process_block to this function.
         let mut chain_update = self.chain_update();
+        // The pending state patch is only cleared once the block applying it is
+        // committed, so that a block which fails to be processed doesn't lose it.
+        chain_update.states_to_patch = self.pending_states_to_patch.clone();
         chain_update.preprocess_block(me, block, provenance, on_challenge)
     }
 
@@ -2668,7 +2856,7 @@ impl Chain {
             self.doomslug_threshold_mode,
             &self.genesis,
             self.transaction_validity_period,
-            self.pending_states_to_patch.take(),
+            None,
         )
     }
 
@@ -2687,7 +2875,7 @@ impl Chain {
             self.doomslug_threshold_mode,
             &self.genesis,
             self.transaction_validity_period,
-            self.pending_states_to_patch.take(),
+            None,
         )
     }
 
@@ -2794,6 +2982,41 @@ impl Chain {
         }
     }
 
+    /// Get merkle proof that the block with hash `block_hash` is an ancestor of the final
+    /// block with hash `head_block_hash`, i.e. that it's in the block merkle root of the latter,
+    /// see `near_primitives::merkle::verify_block_ancestry_proof`.
+    ///
+    /// Fails with `ErrorKind::NotOnCanonicalChain` if either of the blocks isn't on the canonical
+    /// chain, and with `ErrorKind::DBNotFoundErr` if the node doesn't have the data of a block,
+    /// e.g. because it was garbage collected or is older than the state the node synced.
+    pub fn get_block_ancestry_proof(
+        &mut self,
+        block_hash: &CryptoHash,
+        head_block_hash: &CryptoHash,
+    ) -> Result<MerklePath, Error> {
+        let head_height = self.get_block_header(head_block_hash)?.height();
+        if head_height > self.final_head()?.height {
+            return Err(
+                ErrorKind::Other(format!("head block {} is not final", head_block_hash)).into()
+            );
+        }
+        let leaf_index = self.mut_store().get_block_merkle_tree(block_hash)?.size();
+        let tree_size = self.mut_store().get_block_merkle_tree(head_block_hash)?.size();
+        if leaf_index > tree_size {
+            return Err(ErrorKind::Other(format!(
+                "block {} is ahead of head block {}",
+                block_hash, head_block_hash
+            ))
+            .into());
+        }
+        for (hash, ordinal) in [(head_block_hash, tree_size), (block_hash, leaf_index)] {
+            if self.mut_store().get_block_hash_from_ordinal(ordinal)? != hash {
+                return Err(ErrorKind::NotOnCanonicalChain(*hash).into());
+            }
+        }
+        self.get_block_proof(block_hash, head_block_hash)
+    }
+
     /// Get merkle proof for block with hash `block_hash` in the merkle tree of `head_block_hash`.
     pub fn get_block_proof(
         &mut self,
@@ -3076,6 +3299,13 @@ impl Chain {
         self.orphans.len_evicted()
     }
 
+    /// Sets the limits of the total size of the orphans and of the blocks
+    /// waiting for chunks, above which the lowest blocks are evicted.
+    pub fn set_max_pool_bytes(&mut self, orphans: usize, blocks_with_missing_chunks: usize) {
+        self.orphans.set_max_bytes(orphans);
+        self.blocks_with_missing_chunks.set_max_bytes(blocks_with_missing_chunks);
+    }
+
     /// Check if hash is for a known orphan.
     #[inline]
     pub fn is_orphan(&self, hash: &CryptoHash) -> bool {
@@ -3238,7 +3468,7 @@ impl Chain {
 /// Sandbox node specific operations
 #[cfg(feature = "sandbox")]
 impl Chain {
-    pub fn patch_state(&mut self, records: Vec<StateRecord>) {
+    pub fn patch_state(&mut self, records: Vec<StatePatchRecord>) {
         match self.pending_states_to_patch.take() {
             None => self.pending_states_to_patch = Some(records),
             Some(mut pending) => {
@@ -3251,6 +3481,11 @@ impl Chain {
     pub fn patch_state_in_progress(&self) -> bool {
         self.pending_states_to_patch.is_some()
     }
+
+    /// State root of the first shard after the last state patch was applied.
+    pub fn last_patched_state_root(&self) -> Option<StateRoot> {
+        self.last_patched_state_root
+    }
 }
 
 /// Chain update helper, contains information that is needed to process block
@@ -3268,7 +3503,7 @@ pub struct ChainUpdate<'a> {
     genesis: &'a Block,
     #[allow(unused)]
     transaction_validity_period: BlockHeightDelta,
-    states_to_patch: Option<Vec<StateRecord>>,
+    states_to_patch: Option<Vec<StatePatchRecord>>,
 }
 
 impl<'a> ChainAccess for ChainUpdate<'a> {
@@ -3310,6 +3545,16 @@ pub enum ApplyChunkResult {
     SplitState(SplitStateResult),
 }
 
+impl ApplyChunkResult {
+    fn shard_uid(&self) -> ShardUId {
+        match self {
+            Self::SameHeight(result) => result.shard_uid,
+            Self::DifferentHeight(result) => result.shard_uid,
+            Self::SplitState(result) => result.shard_uid,
+        }
+    }
+}
+
 impl<'a> ChainUpdate<'a> {
     pub fn new(
         store: &'a mut ChainStore,
@@ -3321,7 +3566,7 @@ impl<'a> ChainUpdate<'a> {
         doomslug_threshold_mode: DoomslugThresholdMode,
         genesis: &'a Block,
         transaction_validity_period: BlockHeightDelta,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Self {
         let chain_store_update: ChainStoreUpdate<'_> = store.store_update();
         <ChainUpdate<'a>>::new_impl(
@@ -3349,7 +3594,7 @@ impl<'a> ChainUpdate<'a> {
         doomslug_threshold_mode: DoomslugThresholdMode,
         genesis: &'a Block,
         transaction_validity_period: BlockHeightDelta,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Self {
         let chain_store_update = saved_store_update.restore(store);
         <ChainUpdate<'a>>::new_impl(
@@ -3375,7 +3620,7 @@ impl<'a> ChainUpdate<'a> {
         doomslug_threshold_mode: DoomslugThresholdMode,
         genesis: &'a Block,
         transaction_validity_period: BlockHeightDelta,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
         chain_store_update: ChainStoreUpdate<'a>,
     ) -> Self {
         ChainUpdate {
@@ -3679,9 +3924,16 @@ impl<'a> ChainUpdate<'a> {
         apply_results: Vec<Result<ApplyChunkResult, Error>>,
     ) -> Result<(), Error> {
         let _span = tracing::debug_span!(target: "chain", "apply_chunk_postprocessing").entered();
-        apply_results.into_iter().try_for_each(|result| -> Result<(), Error> {
-            self.process_apply_chunk_result(result?, *block.hash(), *prev_block.hash())
-        })
+        let mut block_stage_times = ApplyChunkStageTimes::default();
+        for result in apply_results {
+            let stage_times =
+                self.process_apply_chunk_result(result?, *block.hash(), *prev_block.hash())?;
+            if let Some(stage_times) = stage_times {
+                block_stage_times.add(&stage_times);
+            }
+        }
+        block_stage_times.record_block();
+        Ok(())
     }
 
     fn get_split_state_roots(
@@ -4179,17 +4431,22 @@ impl<'a> ChainUpdate<'a> {
         result: ApplyChunkResult,
         block_hash: CryptoHash,
         prev_block_hash: CryptoHash,
-    ) -> Result<(), Error> {
-        match result {
+    ) -> Result<Option<ApplyChunkStageTimes>, Error> {
+        let stage_times = match result {
             ApplyChunkResult::SameHeight(SameHeightResult {
                 gas_limit,
                 shard_uid,
                 apply_result,
                 apply_split_result_or_state_changes,
             }) => {
+                let started = Instant::now();
                 let (outcome_root, outcome_paths) =
                     ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
                 let shard_id = shard_uid.shard_id();
+                let stage_times = ApplyChunkStageTimes {
+                    outcome_root: started.elapsed(),
+                    ..apply_result.stage_times
+                };
 
                 // Save state root after applying transactions.
                 self.chain_store_update.save_chunk_extra(
@@ -4225,12 +4482,15 @@ impl<'a> ChainUpdate<'a> {
                         apply_results_or_state_changes,
                     )?;
                 }
+                stage_times.record_chunk(block_hash, shard_id);
+                Some(stage_times)
             }
             ApplyChunkResult::DifferentHeight(DifferentHeightResult {
                 shard_uid,
                 apply_result,
                 apply_split_result_or_state_changes,
             }) => {
+                let stage_times = apply_result.stage_times;
                 let mut new_extra =
                     self.chain_store_update.get_chunk_extra(&prev_block_hash, &shard_uid)?.clone();
 
@@ -4247,6 +4507,8 @@ impl<'a> ChainUpdate<'a> {
                         apply_results_or_state_changes,
                     )?;
                 }
+                stage_times.record_chunk(block_hash, shard_uid.shard_id());
+                Some(stage_times)
             }
             ApplyChunkResult::SplitState(SplitStateResult { shard_uid, results }) => {
                 self.chain_store_update.remove_state_changes_for_split_states(
@@ -4259,9 +4521,10 @@ impl<'a> ChainUpdate<'a> {
                     &shard_uid,
                     ApplySplitStateResultOrStateChanges::ApplySplitStateResults(results),
                 )?;
+                None
             }
         };
-        Ok(())
+        Ok(stage_times)
     }
 
     /// Return a StateSyncInfo that includes the information needed for syncing state for shards needed
@@ -4741,6 +5004,25 @@ impl<'a> ChainUpdate<'a> {
             return Err(ErrorKind::InvalidChunkMask.into());
         }
 
+        // The build id of the producer is part of the header from the protocol
+        // version which added it, and only from it.
+        let protocol_version =
+            self.runtime_adapter.get_epoch_protocol_version(header.epoch_id())?;
+        let has_producer_build_id = checked_feature!(
+            "protocol_feature_producer_build_id",
+            ProducerBuildId,
+            protocol_version
+        );
+        match (header.producer_build_id().is_some(), has_producer_build_id) {
+            (true, false) => {
+                return Err(ErrorKind::Other("Unexpected producer build id".to_string()).into())
+            }
+            (false, true) => {
+                return Err(ErrorKind::Other("Missing producer build id".to_string()).into())
+            }
+            _ => {}
+        }
+
         if let Some(prev_height) = header.prev_height() {
             if prev_height != prev_header.height() {
                 return Err(ErrorKind::Other("Invalid prev_height".to_string()).into());
@@ -4759,29 +5041,12 @@ impl<'a> ChainUpdate<'a> {
         // If this is not the block we produced (hence trust in it) - validates block
         // producer, confirmation signatures and finality info.
         if *provenance != Provenance::PRODUCED {
-            // first verify aggregated signature
-            if !self.runtime_adapter.verify_approval(
-                prev_header.hash(),
-                prev_header.height(),
-                header.height(),
-                header.approvals(),
-            )? {
-                return Err(ErrorKind::InvalidApprovals.into());
-            };
-
-            let stakes = self
-                .runtime_adapter
-                .get_epoch_block_approvers_ordered(header.prev_hash())?
-                .iter()
-                .map(|(x, is_slashed)| (x.stake_this_epoch, x.stake_next_epoch, *is_slashed))
-                .collect();
-            if !Doomslug::can_approved_block_be_produced(
+            verify_header_approvals(
+                self.runtime_adapter.as_ref(),
                 self.doomslug_threshold_mode,
-                header.approvals(),
-                &stakes,
-            ) {
-                return Err(ErrorKind::NotEnoughApprovals.into());
-            }
+                header,
+                prev_header,
+            )?;
 
             let expected_last_ds_final_block = if prev_header.height() + 1 == header.height() {
                 prev_header.hash()
@@ -5181,15 +5446,57 @@ impl<'a> ChainUpdate<'a> {
     }
 }
 
+/// Verifies the approvals included in the block against the block producers
+/// of its epoch and checks they have enough stake for the block to be produced.
+pub fn verify_header_approvals(
+    runtime_adapter: &dyn RuntimeAdapter,
+    doomslug_threshold_mode: DoomslugThresholdMode,
+    header: &BlockHeader,
+    prev_header: &BlockHeader,
+) -> Result<(), Error> {
+    // first verify aggregated signature
+    if !runtime_adapter.verify_approval(
+        prev_header.hash(),
+        prev_header.height(),
+        header.height(),
+        header.approvals(),
+    )? {
+        return Err(ErrorKind::InvalidApprovals.into());
+    };
+
+    let stakes = runtime_adapter
+        .get_epoch_block_approvers_ordered(header.prev_hash())?
+        .iter()
+        .map(|(x, is_slashed)| (x.stake_this_epoch, x.stake_next_epoch, *is_slashed))
+        .collect();
+    if !Doomslug::can_approved_block_be_produced(
+        doomslug_threshold_mode,
+        header.approvals(),
+        &stakes,
+    ) {
+        return Err(ErrorKind::NotEnoughApprovals.into());
+    }
+    Ok(())
+}
+
 pub fn do_apply_chunks(
     work: Vec<Box<dyn FnOnce(&Span) -> Result<ApplyChunkResult, Error> + Send>>,
 ) -> Vec<Result<ApplyChunkResult, Error>> {
+    do_apply_chunks_timed(work).into_iter().map(|(_, result)| result).collect()
+}
+
+/// Same as [`do_apply_chunks`] but also returns how long each chunk took to apply.
+fn do_apply_chunks_timed(
+    work: Vec<Box<dyn FnOnce(&Span) -> Result<ApplyChunkResult, Error> + Send>>,
+) -> Vec<(TimeDuration, Result<ApplyChunkResult, Error>)> {
     let parent_span = tracing::debug_span!(target: "chain", "do_apply_chunks").entered();
     work.into_par_iter()
         .map(|task| {
+            let started = Instant::now();
             // As chunks can be processed in parallel, make sure they are all tracked as children of
             // a single span.
-            task(&parent_span)
+            let result = task(&parent_span);
+            (started.elapsed(), result)
         })
         .collect::<Vec<_>>()
 }
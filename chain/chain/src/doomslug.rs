@@ -311,6 +311,11 @@ impl Doomslug {
         }
     }
 
+    /// Replaces the key future approvals are signed with.
+    pub fn set_signer(&mut self, signer: Option<Arc<dyn ValidatorSigner>>) {
+        self.signer = signer;
+    }
+
     #[cfg(feature = "test_features")]
     pub fn adv_disable(&mut self) {
         self.threshold_mode = DoomslugThresholdMode::NoApprovals
@@ -1,4 +1,4 @@
-pub use chain::{check_known, collect_receipts, Chain, MAX_ORPHAN_SIZE};
+pub use chain::{check_known, collect_receipts, verify_header_approvals, Chain, MAX_ORPHAN_SIZE};
 pub use doomslug::{Doomslug, DoomslugBlockProductionReadiness, DoomslugThresholdMode};
 pub use lightclient::{create_light_client_block_view, get_epoch_block_producers_view};
 pub use near_chain_primitives::{self, Error, ErrorKind};
@@ -7,6 +7,8 @@ pub use store::{ChainStore, ChainStoreAccess, ChainStoreUpdate};
 pub use store_validator::{ErrorMessage, StoreValidator};
 pub use types::{Block, BlockHeader, BlockStatus, ChainGenesis, Provenance, RuntimeAdapter};
 
+pub mod apply_stage_times;
+pub mod block_processing_log;
 pub mod blocks_delay_tracker;
 pub mod chain;
 pub mod crypto_hash_timer;
@@ -14,7 +16,9 @@ mod doomslug;
 mod lightclient;
 mod metrics;
 pub mod migrations;
+pub mod misbehavior;
 pub mod missing_chunks;
+pub mod producer_versions;
 mod store;
 pub mod store_validator;
 pub mod test_utils;
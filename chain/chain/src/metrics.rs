@@ -1,6 +1,7 @@
 use near_metrics::{
-    try_create_histogram, try_create_histogram_vec, try_create_int_counter, try_create_int_gauge,
-    Histogram, HistogramVec, IntCounter, IntGauge,
+    try_create_histogram, try_create_histogram_vec, try_create_int_counter,
+    try_create_int_counter_vec, try_create_int_gauge, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge,
 };
 use once_cell::sync::Lazy;
 
@@ -39,6 +40,38 @@ pub static VALIDATOR_ACTIVE_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
 });
 pub static NUM_ORPHANS: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_num_orphans", "Number of orphan blocks.").unwrap());
+pub static ORPHAN_POOL_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge("near_orphan_pool_bytes", "Total size in bytes of the orphan blocks")
+        .unwrap()
+});
+pub static ORPHANS_EVICTED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_orphans_evicted_total",
+        "Number of orphan blocks evicted because the orphan pool was full",
+    )
+    .unwrap()
+});
+pub static NUM_BLOCKS_WITH_MISSING_CHUNKS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_num_blocks_with_missing_chunks",
+        "Number of blocks waiting for missing chunks",
+    )
+    .unwrap()
+});
+pub static BLOCKS_WITH_MISSING_CHUNKS_POOL_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_blocks_with_missing_chunks_pool_bytes",
+        "Total size in bytes of the blocks waiting for missing chunks",
+    )
+    .unwrap()
+});
+pub static BLOCKS_WITH_MISSING_CHUNKS_EVICTED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_blocks_with_missing_chunks_evicted_total",
+        "Number of blocks waiting for missing chunks evicted because the pool was full",
+    )
+    .unwrap()
+});
 pub static HEADER_HEAD_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_header_head_height", "Height of the header head").unwrap()
 });
@@ -50,6 +83,13 @@ pub static FORK_TAIL_HEIGHT: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_fork_tail_height", "Height of fork tail").unwrap());
 pub static GC_STOP_HEIGHT: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_gc_stop_height", "Target height of gc").unwrap());
+pub static STATE_CHANGES_TAIL_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_state_changes_tail_height",
+        "Lowest height whose state changes are kept, when they're kept for longer than the rest",
+    )
+    .unwrap()
+});
 pub static BLOCK_CHUNKS_REQUESTED_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
     try_create_histogram_vec(
         "near_block_chunks_request_delay_seconds",
@@ -79,3 +119,37 @@ pub static BLOCK_MISSING_CHUNKS_DELAY: Lazy<Histogram> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static CHUNK_APPLY_STAGE_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_apply_stage_seconds",
+        "Time spent in each stage of applying a chunk",
+        &["shard_id", "stage"],
+        Some(prometheus::exponential_buckets(0.0001, 1.6, 24).unwrap()),
+    )
+    .unwrap()
+});
+pub static BLOCK_APPLY_STAGE_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_block_apply_stage_seconds",
+        "Time spent in each stage of applying the chunks of a block, summed over the shards",
+        &["stage"],
+        Some(prometheus::exponential_buckets(0.0001, 1.6, 24).unwrap()),
+    )
+    .unwrap()
+});
+pub static CHUNK_APPLY_TRIE_READS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chunk_apply_trie_reads_total",
+        "Trie nodes and values read when applying chunks, by where they were served from",
+        &["shard_id", "source"],
+    )
+    .unwrap()
+});
+pub static CHUNK_APPLY_TRIE_READ_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chunk_apply_trie_read_bytes_total",
+        "Size of the trie nodes and values read when applying chunks, from a cache or the database",
+        &["shard_id", "source"],
+    )
+    .unwrap()
+});
@@ -0,0 +1,109 @@
+use borsh::BorshDeserialize;
+use near_primitives::challenge::EquivocationEvidence;
+use near_primitives::types::BlockHeight;
+use near_store::{DBCol, Store};
+use std::io;
+
+/// Evidence of equivocation, i.e. pairs of conflicting blocks, chunks or
+/// approvals signed by the same validator, is kept in [`DBCol::Misbehavior`]
+/// for as long as the database lives so that it can be shared later on.
+///
+/// Rows are keyed by the big endian height followed by the hash of the
+/// evidence, so that rows sort by height and the same pair is stored once.
+fn key(evidence: &EquivocationEvidence) -> Vec<u8> {
+    [evidence.height().to_be_bytes().as_ref(), evidence.hash().as_ref()].concat()
+}
+
+/// Saves the evidence unless it's there already.  Returns whether it's new.
+pub fn save_evidence(store: &Store, evidence: &EquivocationEvidence) -> io::Result<bool> {
+    let key = key(evidence);
+    if store.exists(DBCol::Misbehavior, &key)? {
+        return Ok(false);
+    }
+    let mut store_update = store.store_update();
+    store_update.set_ser(DBCol::Misbehavior, &key, evidence)?;
+    store_update.commit()?;
+    Ok(true)
+}
+
+/// Reads the evidence with heights in the range, both ends included, lowest
+/// height first.
+pub fn read_evidence(
+    store: &Store,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+) -> io::Result<Vec<EquivocationEvidence>> {
+    let mut evidence = vec![];
+    for (key, value) in store.iter(DBCol::Misbehavior) {
+        let mut height = [0; 8];
+        height.copy_from_slice(&key[..8]);
+        let height = u64::from_be_bytes(height);
+        if from_height <= height && height <= to_height {
+            evidence.push((key, EquivocationEvidence::try_from_slice(&value)?));
+        }
+    }
+    evidence.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(evidence.into_iter().map(|(_, evidence)| evidence).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::KeyType;
+    use near_primitives::block_header::Approval;
+    use near_primitives::challenge::EquivocationKind;
+    use near_primitives::hash::hash;
+    use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+    use near_store::test_utils::create_test_store;
+
+    fn double_approval(signer: &InMemoryValidatorSigner, height: BlockHeight) -> EquivocationKind {
+        EquivocationKind::Approval {
+            left: Approval::new(hash(b"left"), height - 1, height, signer),
+            right: Approval::new(hash(b"right"), height - 1, height, signer),
+        }
+    }
+
+    #[test]
+    fn test_equivocation_evidence() {
+        let signer =
+            InMemoryValidatorSigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "test");
+        let other =
+            InMemoryValidatorSigner::from_seed("other".parse().unwrap(), KeyType::ED25519, "other");
+        let evidence = |kind| {
+            EquivocationEvidence::new(signer.validator_id().clone(), signer.public_key(), kind)
+        };
+        let double_sign = evidence(double_approval(&signer, 10));
+        double_sign.verify().unwrap();
+
+        // The same approval twice, or approvals signed with another key.
+        let approval = Approval::new(hash(b"left"), 9, 10, &signer);
+        let same = evidence(EquivocationKind::Approval { left: approval.clone(), right: approval });
+        assert!(same.verify().is_err());
+        let mut forged = evidence(double_approval(&other, 10));
+        if let EquivocationKind::Approval { left, right } = &mut forged.kind {
+            left.account_id = signer.validator_id().clone();
+            right.account_id = signer.validator_id().clone();
+        }
+        assert!(forged.verify().is_err());
+
+        // The pair is stored once whatever its order.
+        let swapped = match double_approval(&signer, 10) {
+            EquivocationKind::Approval { left, right } => {
+                evidence(EquivocationKind::Approval { left: right, right: left })
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(swapped, double_sign);
+
+        let store = create_test_store();
+        assert!(save_evidence(&store, &evidence(double_approval(&signer, 300))).unwrap());
+        assert!(save_evidence(&store, &double_sign).unwrap());
+        assert!(!save_evidence(&store, &swapped).unwrap());
+        assert!(save_evidence(&store, &evidence(double_approval(&signer, 20))).unwrap());
+        let heights = |from, to| -> Vec<_> {
+            read_evidence(&store, from, to).unwrap().iter().map(|e| e.height()).collect()
+        };
+        assert_eq!(heights(0, 1000), vec![10, 20, 300]);
+        assert_eq!(heights(11, 300), vec![20, 300]);
+    }
+}
@@ -1,7 +1,7 @@
 use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::types::BlockHeight;
-use near_primitives::views::BlockStatusView;
+use near_primitives::views::{BlockStatusView, BlockWithMissingChunksView};
 use std::cmp::Ordering;
 use std::collections::{
     btree_map::{self, BTreeMap},
@@ -10,13 +10,20 @@ use std::collections::{
 };
 use tracing::warn;
 
+use crate::metrics;
+
 type BlockHash = CryptoHash;
 
 const MAX_BLOCKS_MISSING_CHUNKS: usize = 1024;
 
+/// Default limit of the total size of the blocks waiting for chunks.
+pub const DEFAULT_MAX_BLOCKS_MISSING_CHUNKS_BYTES: usize = 64 * 1024 * 1024;
+
 pub trait BlockLike {
     fn hash(&self) -> BlockHash;
     fn height(&self) -> BlockHeight;
+    /// Number of bytes the block takes in memory, roughly.
+    fn size_bytes(&self) -> usize;
 }
 
 #[derive(Debug)]
@@ -43,13 +50,25 @@ impl<T: BlockLike> Ord for HeightOrdered<T> {
 /// The reason to have a Block type parameter instead of using the
 /// `block::Block` type is to make testing easier (`block::Block` is a complex structure and I
 /// don't care about most of it).
-#[derive(Debug, Default)]
+///
+/// When the blocks waiting for chunks take more than `max_bytes` the ones
+/// with the lowest heights are evicted.
+#[derive(Debug)]
 pub struct MissingChunksPool<Block: BlockLike> {
     missing_chunks: HashMap<ChunkHash, HashSet<BlockHash>>,
     blocks_missing_chunks: HashMap<BlockHash, HashSet<ChunkHash>>,
     blocks_waiting_for_chunks: HashMap<BlockHash, Block>,
     blocks_ready_to_process: BinaryHeap<HeightOrdered<Block>>,
     height_idx: BTreeMap<BlockHeight, HashSet<BlockHash>>,
+    /// Total size of the blocks waiting for chunks.
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl<Block: BlockLike> Default for MissingChunksPool<Block> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<Block: BlockLike> MissingChunksPool<Block> {
@@ -60,9 +79,15 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
             blocks_waiting_for_chunks: Default::default(),
             blocks_ready_to_process: BinaryHeap::new(),
             height_idx: Default::default(),
+            bytes: 0,
+            max_bytes: DEFAULT_MAX_BLOCKS_MISSING_CHUNKS_BYTES,
         }
     }
 
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+    }
+
     pub fn contains(&self, block_hash: &BlockHash) -> bool {
         self.blocks_waiting_for_chunks.contains_key(block_hash)
     }
@@ -75,6 +100,11 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         self.blocks_waiting_for_chunks.len()
     }
 
+    /// Total size of the blocks waiting for chunks.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
     pub fn ready_blocks(&mut self) -> Vec<Block> {
         if self.blocks_ready_to_process.is_empty() {
             return Vec::new();
@@ -121,7 +151,29 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         let height = block.height();
         let blocks_at_height = self.height_idx.entry(height).or_insert_with(HashSet::new);
         blocks_at_height.insert(block_hash);
-        self.blocks_waiting_for_chunks.insert(block_hash, block);
+        self.bytes += block.size_bytes();
+        if let Some(previous) = self.blocks_waiting_for_chunks.insert(block_hash, block) {
+            self.bytes -= previous.size_bytes();
+        }
+
+        let mut evicted = 0;
+        while self.bytes > self.max_bytes {
+            let height = match self.height_idx.keys().next() {
+                Some(height) => *height,
+                None => break,
+            };
+            evicted += self.remove_blocks_at_height(height);
+        }
+        if evicted > 0 {
+            warn!(
+                target: "chunks",
+                evicted,
+                bytes = self.bytes,
+                max_bytes = self.max_bytes,
+                "Evicted the lowest blocks from the missing chunks pool"
+            );
+            metrics::BLOCKS_WITH_MISSING_CHUNKS_EVICTED_TOTAL.inc_by(evicted as u64);
+        }
     }
 
     pub fn accept_chunk(&mut self, chunk_hash: &ChunkHash) {
@@ -157,8 +209,33 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         result
     }
 
+    /// Lists the blocks waiting for chunks by height together with the
+    /// chunks each of them is waiting for.
+    pub fn list_missing_chunks_by_height(&self) -> Vec<BlockWithMissingChunksView> {
+        let mut result = Vec::new();
+        for (height, block_hashes) in &self.height_idx {
+            for block_hash in block_hashes {
+                let mut missing_chunks: Vec<CryptoHash> = self
+                    .blocks_missing_chunks
+                    .get(block_hash)
+                    .into_iter()
+                    .flatten()
+                    .map(|chunk_hash| chunk_hash.0)
+                    .collect();
+                missing_chunks.sort();
+                result.push(BlockWithMissingChunksView {
+                    height: *height,
+                    hash: *block_hash,
+                    missing_chunks,
+                });
+            }
+        }
+        result
+    }
+
     fn mark_block_as_ready(&mut self, block_hash: &BlockHash) {
         for block in self.blocks_waiting_for_chunks.remove(block_hash) {
+            self.bytes -= block.size_bytes();
             let height = block.height();
             if let btree_map::Entry::Occupied(mut entry) = self.height_idx.entry(height) {
                 let blocks_at_height = entry.get_mut();
@@ -175,25 +252,36 @@ impl<Block: BlockLike> MissingChunksPool<Block> {
         let heights_to_remove: Vec<BlockHeight> =
             self.height_idx.keys().copied().take_while(|h| *h < height).collect();
         for h in heights_to_remove {
-            if let Some(block_hashes) = self.height_idx.remove(&h) {
-                for block_hash in block_hashes {
-                    self.blocks_waiting_for_chunks.remove(&block_hash);
-                    if let Some(chunk_hashes) = self.blocks_missing_chunks.remove(&block_hash) {
-                        for chunk_hash in chunk_hashes {
-                            if let hash_map::Entry::Occupied(mut entry) =
-                                self.missing_chunks.entry(chunk_hash)
-                            {
-                                let blocks_for_chunk = entry.get_mut();
-                                blocks_for_chunk.remove(&block_hash);
-                                if blocks_for_chunk.is_empty() {
-                                    entry.remove_entry();
-                                }
-                            }
+            self.remove_blocks_at_height(h);
+        }
+    }
+
+    /// Removes the blocks waiting for chunks at the height and returns how
+    /// many there were.
+    fn remove_blocks_at_height(&mut self, height: BlockHeight) -> usize {
+        let block_hashes = match self.height_idx.remove(&height) {
+            Some(block_hashes) => block_hashes,
+            None => return 0,
+        };
+        for block_hash in &block_hashes {
+            if let Some(block) = self.blocks_waiting_for_chunks.remove(block_hash) {
+                self.bytes -= block.size_bytes();
+            }
+            if let Some(chunk_hashes) = self.blocks_missing_chunks.remove(block_hash) {
+                for chunk_hash in chunk_hashes {
+                    if let hash_map::Entry::Occupied(mut entry) =
+                        self.missing_chunks.entry(chunk_hash)
+                    {
+                        let blocks_for_chunk = entry.get_mut();
+                        blocks_for_chunk.remove(block_hash);
+                        if blocks_for_chunk.is_empty() {
+                            entry.remove_entry();
                         }
                     }
                 }
             }
         }
+        block_hashes.len()
     }
 }
 
@@ -230,8 +318,14 @@ mod test {
         fn height(&self) -> u64 {
             self.height
         }
+
+        fn size_bytes(&self) -> usize {
+            MOCK_BLOCK_SIZE
+        }
     }
 
+    const MOCK_BLOCK_SIZE: usize = 100;
+
     #[test]
     fn should_mark_blocks_as_ready_after_all_chunks_accepted() {
         let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::default();
@@ -302,4 +396,46 @@ mod test {
         assert!(!pool.contains(&early_block_hash));
         assert!(pool.contains(&later_block_hash));
     }
+
+    #[test]
+    fn should_evict_lowest_blocks_over_byte_limit() {
+        let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::default();
+        pool.set_max_bytes(2 * MOCK_BLOCK_SIZE);
+        let blocks: Vec<MockBlock> = (0..3).map(MockBlock::new).collect();
+        pool.add_block_with_missing_chunks(blocks[1], vec![get_chunk_hash(101)]);
+        pool.add_block_with_missing_chunks(blocks[0], vec![get_chunk_hash(100)]);
+        assert_eq!(pool.bytes(), 2 * MOCK_BLOCK_SIZE);
+
+        pool.add_block_with_missing_chunks(blocks[2], vec![get_chunk_hash(102)]);
+        assert!(!pool.contains(&blocks[0].hash));
+        assert!(pool.contains(&blocks[1].hash));
+        assert!(pool.contains(&blocks[2].hash));
+        assert_eq!(pool.bytes(), 2 * MOCK_BLOCK_SIZE);
+
+        // The chunk of the evicted block doesn't make it ready.
+        pool.accept_chunk(&get_chunk_hash(100));
+        assert_eq!(pool.ready_blocks(), vec![]);
+        pool.accept_chunk(&get_chunk_hash(101));
+        assert_eq!(pool.ready_blocks(), vec![blocks[1]]);
+        assert_eq!(pool.bytes(), MOCK_BLOCK_SIZE);
+    }
+
+    #[test]
+    fn should_list_missing_chunks_by_height() {
+        let mut pool: MissingChunksPool<MockBlock> = MissingChunksPool::default();
+        let (block, other_block) = (MockBlock::new(1), MockBlock::new(2));
+        pool.add_block_with_missing_chunks(other_block, vec![get_chunk_hash(200)]);
+        pool.add_block_with_missing_chunks(block, vec![get_chunk_hash(100), get_chunk_hash(101)]);
+        pool.accept_chunk(&get_chunk_hash(100));
+
+        let listed = pool.list_missing_chunks_by_height();
+        let listed: Vec<_> = listed
+            .iter()
+            .map(|view| (view.height, view.hash, view.missing_chunks.clone()))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![(1, block.hash, vec![get_hash(101)]), (2, other_block.hash, vec![get_hash(200)])]
+        );
+    }
 }
@@ -0,0 +1,166 @@
+//! Summary of the protocol versions and builds the producers of the last blocks
+//! of the canonical chain ran, to follow the progress of a rolling upgrade.
+//!
+//! Every block counts for the latest protocol version and the build id in its
+//! header.  A producer counts, with its stake in the epoch of the block, for
+//! the version of its last block in the window only, so that the stake of a
+//! producer which upgraded during the window isn't counted twice.
+
+use std::collections::{HashMap, HashSet};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, Balance, BlockHeight, EpochId, NumBlocks};
+use near_primitives::version::{BuildId, ProtocolVersion};
+use near_primitives::views::{ProducerVersionView, ProducerVersionsView};
+
+use crate::{ChainStoreAccess, Error, RuntimeAdapter};
+
+struct ProducedBlock {
+    height: BlockHeight,
+    latest_protocol_version: ProtocolVersion,
+    build_id: Option<BuildId>,
+    producer: AccountId,
+    stake: Balance,
+}
+
+/// Summarizes the last `num_blocks` blocks of the canonical chain up to the
+/// block with `last_hash`, the genesis block excluded.
+pub fn producer_versions(
+    chain_store: &mut dyn ChainStoreAccess,
+    runtime_adapter: &dyn RuntimeAdapter,
+    last_hash: &CryptoHash,
+    num_blocks: NumBlocks,
+) -> Result<ProducerVersionsView, Error> {
+    let genesis_height = chain_store.get_genesis_height();
+    let mut stakes: HashMap<(EpochId, AccountId), Balance> = HashMap::new();
+    let mut blocks = vec![];
+    let mut hash = *last_hash;
+    while (blocks.len() as NumBlocks) < num_blocks {
+        let header = chain_store.get_block_header(&hash)?.clone();
+        if header.height() <= genesis_height {
+            break;
+        }
+        let epoch_id = header.epoch_id().clone();
+        let producer = runtime_adapter.get_block_producer(&epoch_id, header.height())?;
+        let stake = match stakes.get(&(epoch_id.clone(), producer.clone())) {
+            Some(stake) => *stake,
+            None => {
+                let (validator, _) =
+                    runtime_adapter.get_validator_by_account_id(&epoch_id, &hash, &producer)?;
+                stakes.insert((epoch_id, producer.clone()), validator.stake());
+                validator.stake()
+            }
+        };
+        blocks.push(ProducedBlock {
+            height: header.height(),
+            latest_protocol_version: header.latest_protocol_version(),
+            build_id: header.producer_build_id(),
+            producer,
+            stake,
+        });
+        hash = *header.prev_hash();
+    }
+    Ok(summarize(&blocks))
+}
+
+/// Groups the blocks, most recent first, by version.
+fn summarize(blocks: &[ProducedBlock]) -> ProducerVersionsView {
+    let mut versions: Vec<ProducerVersionView> = vec![];
+    let mut counted = HashSet::new();
+    let mut total_stake = 0;
+    for block in blocks {
+        let index = match versions.iter().position(|version| {
+            version.latest_protocol_version == block.latest_protocol_version
+                && version.build_id == block.build_id
+        }) {
+            Some(index) => index,
+            None => {
+                versions.push(ProducerVersionView {
+                    latest_protocol_version: block.latest_protocol_version,
+                    build_id: block.build_id,
+                    num_blocks: 0,
+                    producers: vec![],
+                    stake: 0,
+                });
+                versions.len() - 1
+            }
+        };
+        let version = &mut versions[index];
+        version.num_blocks += 1;
+        if counted.insert(&block.producer) {
+            version.producers.push(block.producer.clone());
+            version.stake += block.stake;
+            total_stake += block.stake;
+        }
+    }
+    versions.sort_by(|a, b| b.num_blocks.cmp(&a.num_blocks));
+    ProducerVersionsView {
+        first_height: blocks.last().map_or(0, |block| block.height),
+        last_height: blocks.first().map_or(0, |block| block.height),
+        num_blocks: blocks.len() as NumBlocks,
+        total_stake,
+        versions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(
+        height: BlockHeight,
+        version: ProtocolVersion,
+        build: u8,
+        producer: &str,
+    ) -> ProducedBlock {
+        ProducedBlock {
+            height,
+            latest_protocol_version: version,
+            build_id: (build != 0).then(|| BuildId([build; 8])),
+            producer: producer.parse().unwrap(),
+            stake: if producer == "alice" { 30 } else { 10 },
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        // Most recent first: alice upgraded at height 4.
+        let blocks = [
+            block(6, 55, 2, "bob"),
+            block(5, 55, 2, "alice"),
+            block(4, 55, 2, "alice"),
+            block(3, 54, 1, "alice"),
+            block(2, 54, 0, "carol"),
+            block(1, 54, 1, "alice"),
+        ];
+        let view = summarize(&blocks);
+        assert_eq!((view.first_height, view.last_height, view.num_blocks), (1, 6, 6));
+        assert_eq!(view.total_stake, 50);
+        let versions: Vec<_> = view
+            .versions
+            .iter()
+            .map(|version| {
+                let producers: Vec<&str> =
+                    version.producers.iter().map(|producer| producer.as_str()).collect();
+                (
+                    version.latest_protocol_version,
+                    version.build_id.map(|id| id.0[0]),
+                    version.num_blocks,
+                    producers,
+                    version.stake,
+                )
+            })
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                (55, Some(2), 3, vec!["bob", "alice"], 40),
+                (54, Some(1), 2, vec![], 0),
+                (54, None, 1, vec!["carol"], 10),
+            ]
+        );
+
+        let empty = summarize(&[]);
+        assert_eq!((empty.num_blocks, empty.total_stake, empty.versions.len()), (0, 0, 0));
+    }
+}
@@ -32,11 +32,11 @@ use near_primitives::types::{
     StateChangesKindsExt, StateChangesRequest,
 };
 use near_primitives::utils::{get_block_shard_id, index_to_bytes, to_timestamp};
-use near_primitives::views::LightClientBlockView;
+use near_primitives::views::{LightClientBlockView, StateFingerprintView};
 use near_store::{
-    read_with_cache, DBCol, KeyForStateChanges, ShardTries, Store, StoreUpdate, WrappedTrieChanges,
-    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
-    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, TAIL_KEY,
+    read_with_cache, DBCol, KeyForStateChanges, ShardTries, Store, StoreRead, StoreUpdate,
+    WrappedTrieChanges, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
+    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, STATE_CHANGES_TAIL_KEY, TAIL_KEY,
 };
 
 use crate::types::{Block, BlockHeader, LatestKnown};
@@ -72,8 +72,6 @@ fn get_height_shard_id(height: BlockHeight, shard_id: ShardId) -> Vec<u8> {
 
 /// Accesses the chain store. Used to create atomic editable views that can be reverted.
 pub trait ChainStoreAccess {
-    /// Returns underlaying store.
-    fn store(&self) -> &Store;
     /// The chain head.
     fn head(&self) -> Result<Tip, Error>;
     /// The chain Blocks Tail height.
@@ -282,11 +280,40 @@ pub trait ChainStoreAccess {
             shard_id = runtime_adapter.get_prev_shard_ids(&candidate_hash, vec![shard_id])?[0];
         }
     }
+
+    /// Fingerprint of the state and outcome roots of the shards with a chunk
+    /// extra after the block, which are the shards the node tracked then.
+    /// Only reads the stored roots, nothing is recomputed.
+    fn get_state_fingerprint(
+        &mut self,
+        runtime_adapter: &dyn RuntimeAdapter,
+        block_hash: &CryptoHash,
+    ) -> Result<StateFingerprintView, Error> {
+        let header = self.get_block_header(block_hash)?;
+        let block_height = header.height();
+        let shard_layout = runtime_adapter.get_shard_layout(header.epoch_id())?;
+        let mut shards = vec![];
+        for shard_uid in shard_layout.get_shard_uids() {
+            match self.get_chunk_extra(block_hash, &shard_uid) {
+                Ok(chunk_extra) => shards.push((
+                    shard_uid.shard_id as ShardId,
+                    *chunk_extra.state_root(),
+                    *chunk_extra.outcome_root(),
+                )),
+                Err(err) if matches!(err.kind(), ErrorKind::DBNotFoundErr(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(StateFingerprintView::new(*block_hash, block_height, shards))
+    }
 }
 
 /// All chain-related database operations.
-pub struct ChainStore {
-    store: Store,
+///
+/// Over a [`near_store::ReadOnlyStore`] only the reads of [`ChainStoreAccess`] are
+/// available, there's no way to update such a chain store.
+pub struct ChainStore<S = Store> {
+    store: S,
     /// Genesis block height.
     genesis_height: BlockHeight,
     /// Latest known.
@@ -343,6 +370,9 @@ pub struct ChainStore {
     processed_block_heights: LruCache<Vec<u8>, ()>,
     /// Is this a non-archival node that needs to store to DBCol::TrieChanges?
     save_trie_changes: bool,
+    /// Maximum size of a single write of the commits of store updates, see
+    /// `StoreUpdate::commit_in_batches`.
+    commit_max_batch_bytes: usize,
 }
 
 pub fn option_to_not_found<T>(res: io::Result<Option<T>>, field_name: &str) -> Result<T, Error> {
@@ -353,8 +383,8 @@ pub fn option_to_not_found<T>(res: io::Result<Option<T>>, field_name: &str) -> R
     }
 }
 
-impl ChainStore {
-    pub fn new(store: Store, genesis_height: BlockHeight, save_trie_changes: bool) -> ChainStore {
+impl<S: StoreRead> ChainStore<S> {
+    pub fn new(store: S, genesis_height: BlockHeight, save_trie_changes: bool) -> Self {
         ChainStore {
             store,
             genesis_height,
@@ -385,8 +415,22 @@ impl ChainStore {
             block_ordinal_to_hash: LruCache::new(CACHE_SIZE),
             processed_block_heights: LruCache::new(CACHE_SIZE),
             save_trie_changes,
+            commit_max_batch_bytes: 0,
         }
     }
+}
+
+impl ChainStore {
+    /// Returns underlaying store.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Sets the size above which the commits of store updates write the new
+    /// trie nodes separately before the rest, 0 for single writes.
+    pub fn set_commit_max_batch_bytes(&mut self, commit_max_batch_bytes: usize) {
+        self.commit_max_batch_bytes = commit_max_batch_bytes;
+    }
 
     pub fn owned_store(&self) -> &Store {
         &self.store
@@ -632,6 +676,19 @@ impl ChainStore {
         store_update.commit().map_err(|err| err.into())
     }
 
+    /// Lowest height whose state changes garbage collection keeps, `None`
+    /// unless they were ever kept for longer than the rest of the data of
+    /// the blocks.
+    pub fn state_changes_tail(&self) -> Result<Option<BlockHeight>, Error> {
+        Ok(self.store.get_ser(DBCol::BlockMisc, STATE_CHANGES_TAIL_KEY)?)
+    }
+
+    /// Lowest height whose state changes are kept, the tail unless they're
+    /// kept for longer.
+    pub fn state_changes_retained_from(&self) -> Result<BlockHeight, Error> {
+        Ok(self.state_changes_tail()?.map_or(self.tail()?, |tail| tail.min(self.tail()?)))
+    }
+
     /// Retrieve the kinds of state changes occurred in a given block.
     ///
     /// We store different types of data, so we prefer to only expose minimal information about the
@@ -768,10 +825,7 @@ impl ChainStore {
     }
 }
 
-impl ChainStoreAccess for ChainStore {
-    fn store(&self) -> &Store {
-        &self.store
-    }
+impl<S: StoreRead> ChainStoreAccess for ChainStore<S> {
     /// The chain head.
     fn head(&self) -> Result<Tip, Error> {
         if let Some(ref tip) = self.head {
@@ -1155,6 +1209,10 @@ pub struct ChainStoreUpdateImpl<T> {
     tail: Option<BlockHeight>,
     chunk_tail: Option<BlockHeight>,
     fork_tail: Option<BlockHeight>,
+    state_changes_tail: Option<BlockHeight>,
+    /// Whether the state changes of the canonical blocks are kept by
+    /// `clear_block_data`, to be deleted by `clear_state_changes`.
+    retain_state_changes: bool,
     header_head: Option<Tip>,
     final_head: Option<Tip>,
     largest_target_height: Option<BlockHeight>,
@@ -1186,6 +1244,8 @@ impl<'a> ChainStoreUpdate<'a> {
             tail: None,
             chunk_tail: None,
             fork_tail: None,
+            state_changes_tail: None,
+            retain_state_changes: false,
             header_head: None,
             final_head: None,
             largest_target_height: None,
@@ -1201,6 +1261,11 @@ impl<'a> ChainStoreUpdate<'a> {
         }
     }
 
+    /// Returns underlaying store.
+    pub fn store(&self) -> &Store {
+        &self.chain_store.store
+    }
+
     /// Collect incoming receipts for shard `shard_id` from
     /// the block at height `last_chunk_height_included` (non-inclusive) to the block `block_hash` (inclusive)
     /// This is because the chunks for the shard are empty for the blocks in between,
@@ -1249,10 +1314,6 @@ impl<'a> ChainStoreUpdate<'a> {
 }
 
 impl<'a> ChainStoreAccess for ChainStoreUpdate<'a> {
-    fn store(&self) -> &Store {
-        &self.chain_store.store
-    }
-
     /// The chain head.
     fn head(&self) -> Result<Tip, Error> {
         if let Some(head) = &self.head {
@@ -1980,6 +2041,29 @@ impl<'a> ChainStoreUpdate<'a> {
         self.chunk_tail = Some(height);
     }
 
+    pub fn update_state_changes_tail(&mut self, height: BlockHeight) {
+        self.state_changes_tail = Some(height);
+    }
+
+    /// Makes `clear_block_data` keep the state changes of the canonical blocks.
+    pub fn retain_state_changes(&mut self) {
+        self.retain_state_changes = true;
+    }
+
+    /// Deletes the state changes of the block.
+    pub fn clear_state_changes(&mut self, block_hash: &CryptoHash) {
+        let storage_key = KeyForStateChanges::for_block(block_hash);
+        let stored_state_changes: Vec<Vec<u8>> = self
+            .chain_store
+            .store()
+            .iter_prefix(DBCol::StateChanges, storage_key.as_ref())
+            .map(|key| key.0.into())
+            .collect();
+        for key in stored_state_changes {
+            self.gc_col(DBCol::StateChanges, &key);
+        }
+    }
+
     pub fn clear_chunk_data_and_headers(
         &mut self,
         min_chunk_height: BlockHeight,
@@ -2190,15 +2274,8 @@ impl<'a> ChainStoreUpdate<'a> {
         self.gc_col(DBCol::NextBlockHashes, &block_hash_vec);
         self.gc_col(DBCol::ChallengedBlocks, &block_hash_vec);
         self.gc_col(DBCol::BlocksToCatchup, &block_hash_vec);
-        let storage_key = KeyForStateChanges::for_block(&block_hash);
-        let stored_state_changes: Vec<Vec<u8>> = self
-            .chain_store
-            .store()
-            .iter_prefix(DBCol::StateChanges, storage_key.as_ref())
-            .map(|key| key.0.into())
-            .collect();
-        for key in stored_state_changes {
-            self.gc_col(DBCol::StateChanges, &key);
+        if !(self.retain_state_changes && matches!(gc_mode, GCMode::Canonical(_))) {
+            self.clear_state_changes(&block_hash);
         }
         self.gc_col(DBCol::BlockRefCount, &block_hash_vec);
         self.gc_outcomes(&block)?;
@@ -2480,7 +2557,9 @@ impl<'a> ChainStoreUpdate<'a> {
             | DBCol::_LastBlockWithNewChunk
             | DBCol::_TransactionRefCount
             | DBCol::StateChangesForSplitStates
-            | DBCol::CachedContractCode => {
+            | DBCol::CachedContractCode
+            | DBCol::BlockProcessingLog
+            | DBCol::Misbehavior => {
                 unreachable!();
             }
         }
@@ -2624,6 +2703,11 @@ impl<'a> ChainStoreUpdate<'a> {
         Self::write_col_misc(&mut store_update, TAIL_KEY, &mut self.tail)?;
         Self::write_col_misc(&mut store_update, CHUNK_TAIL_KEY, &mut self.chunk_tail)?;
         Self::write_col_misc(&mut store_update, FORK_TAIL_KEY, &mut self.fork_tail)?;
+        Self::write_col_misc(
+            &mut store_update,
+            STATE_CHANGES_TAIL_KEY,
+            &mut self.state_changes_tail,
+        )?;
         Self::write_col_misc(&mut store_update, HEADER_HEAD_KEY, &mut self.header_head)?;
         Self::write_col_misc(&mut store_update, FINAL_HEAD_KEY, &mut self.final_head)?;
         Self::write_col_misc(
@@ -2949,7 +3033,7 @@ impl<'a> ChainStoreUpdate<'a> {
 
     pub fn commit(mut self) -> Result<(), Error> {
         let store_update = self.finalize()?;
-        store_update.commit()?;
+        store_update.commit_in_batches(self.chain_store.commit_max_batch_bytes)?;
         let ChainStoreCacheUpdate {
             blocks,
             headers,
@@ -3139,9 +3223,11 @@ mod tests {
     use near_primitives::utils::index_to_bytes;
     use near_primitives::validator_signer::InMemoryValidatorSigner;
     use near_store::test_utils::create_test_store;
-    use near_store::DBCol;
+    use near_store::{
+        create_read_only_store, create_store, DBCol, KeyForStateChanges, StoreConfig,
+    };
 
-    use crate::store::{ChainStoreAccess, GCMode};
+    use crate::store::{ChainStore, ChainStoreAccess, GCMode};
     use crate::store_validator::StoreValidator;
     use crate::test_utils::KeyValueRuntime;
     use crate::{Chain, ChainGenesis, DoomslugThresholdMode, RuntimeAdapter};
@@ -3375,6 +3461,93 @@ mod tests {
         assert_ne!(epoch_id_to_hash, epoch_id_to_hash1);
     }
 
+    /// Test that a chain store reads a database opened read-only.
+    #[test]
+    fn test_read_only_chain_store() {
+        let tmp_dir =
+            tempfile::Builder::new().prefix("_test_read_only_chain_store").tempdir().unwrap();
+        let tip = Tip {
+            height: 3,
+            last_block_hash: hash(&[3]),
+            prev_block_hash: hash(&[2]),
+            epoch_id: EpochId::default(),
+            next_epoch_id: EpochId::default(),
+        };
+        {
+            let mut chain_store = ChainStore::new(create_store(tmp_dir.path()), 0, true);
+            let mut store_update = chain_store.store_update();
+            store_update.save_final_head(&tip).unwrap();
+            store_update.commit().unwrap();
+        }
+
+        let store = create_read_only_store(tmp_dir.path(), &StoreConfig::read_write()).unwrap();
+        let chain_store = ChainStore::new(store, 0, true);
+        assert_eq!(chain_store.final_head().unwrap(), tip);
+        assert_eq!(chain_store.tail().unwrap(), 0);
+    }
+
+    /// Test that garbage collection keeps the state changes of the extra epochs it's configured
+    /// to retain, and deletes them up to the tail once they're not retained anymore.
+    #[test]
+    fn test_retain_state_changes() {
+        let mut chain = get_chain_with_epoch_length(1);
+        let runtime_adapter = chain.runtime_adapter.clone();
+        let genesis = chain.get_block_by_height(0).unwrap().clone();
+        let signer = Arc::new(InMemoryValidatorSigner::from_seed(
+            "test1".parse().unwrap(),
+            KeyType::ED25519,
+            "test1",
+        ));
+        let mut prev_block = genesis;
+        let mut blocks = vec![prev_block.clone()];
+        for i in 1..15 {
+            add_block(
+                &mut chain,
+                runtime_adapter.clone(),
+                &mut prev_block,
+                &mut blocks,
+                signer.clone(),
+                i,
+            );
+        }
+        let mut store_update = chain.store().store().store_update();
+        for block in &blocks {
+            let key = KeyForStateChanges::from_raw_key(block.hash(), b"key");
+            store_update.set(DBCol::StateChanges, key.as_ref(), b"value");
+        }
+        store_update.commit().unwrap();
+        let kept_from = |chain: &Chain| {
+            let store = chain.store().store();
+            blocks
+                .iter()
+                .position(|block| {
+                    store.iter_prefix(DBCol::StateChanges, block.hash().as_ref()).next().is_some()
+                })
+                .unwrap_or(blocks.len()) as BlockHeight
+        };
+
+        // The state changes are kept for an epoch of the genesis, 5 blocks,
+        // below the garbage collection stop height of 9.
+        let trie = chain.runtime_adapter.get_tries();
+        let gc_config = GCConfig {
+            gc_blocks_limit: 100,
+            gc_state_changes_extra_epochs: 1,
+            ..GCConfig::default()
+        };
+        chain.clear_data(trie.clone(), &gc_config).unwrap();
+        assert_eq!(chain.store().tail().unwrap(), 8);
+        assert!(chain.get_block(blocks[7].hash()).is_err());
+        assert_eq!(chain.store().state_changes_tail().unwrap(), Some(4));
+        assert_eq!(chain.store().state_changes_retained_from().unwrap(), 4);
+        assert_eq!(kept_from(&chain), 4);
+
+        // Once not retained anymore, they're deleted up to the tail.
+        let gc_config = GCConfig { gc_blocks_limit: 100, ..GCConfig::default() };
+        chain.clear_data(trie.clone(), &gc_config).unwrap();
+        assert_eq!(chain.store().state_changes_retained_from().unwrap(), 8);
+        assert_eq!(kept_from(&chain), 8);
+    }
+
     /// Test that garbage collection works properly. The blocks behind gc head should be garbage
     /// collected while the blocks that are ahead of it should not.
     #[test]
@@ -24,7 +24,7 @@ use near_primitives::serialize::to_base;
 use near_primitives::shard_layout;
 use near_primitives::shard_layout::{ShardLayout, ShardUId};
 use near_primitives::sharding::ChunkHash;
-use near_primitives::state_record::StateRecord;
+use near_primitives::state_record::StatePatchRecord;
 use near_primitives::transaction::{
     Action, ExecutionMetadata, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus,
     SignedTransaction, TransferAction,
@@ -37,8 +37,9 @@ use near_primitives::types::{
 use near_primitives::validator_signer::InMemoryValidatorSigner;
 use near_primitives::version::{ProtocolVersion, PROTOCOL_VERSION};
 use near_primitives::views::{
-    AccessKeyInfoView, AccessKeyList, CallResult, ContractCodeView, EpochValidatorInfo,
-    QueryRequest, QueryResponse, QueryResponseKind, ViewStateResult,
+    AccessKeyInfoView, AccessKeyList, AccountViewOrError, CallResult, ContractCodeView,
+    EpochValidatorInfo, EpochValidatorsPreview, QueryRequest, QueryResponse, QueryResponseKind,
+    ViewAccountsResult, ViewStateResult,
 };
 use near_store::test_utils::create_test_store;
 use near_store::{
@@ -49,7 +50,7 @@ use crate::chain::Chain;
 use crate::store::ChainStoreAccess;
 use crate::types::{
     ApplySplitStateResult, ApplyTransactionResult, BlockHeaderInfo, ChainGenesis,
-    ValidatorInfoIdentifier,
+    PreparedTransactions, TxValidationResult, ValidatorInfoIdentifier,
 };
 use crate::Doomslug;
 use crate::{BlockHeader, DoomslugThresholdMode, RuntimeAdapter};
@@ -598,6 +599,17 @@ impl RuntimeAdapter for KeyValueRuntime {
         Ok(None)
     }
 
+    fn validate_tx_full(
+        &self,
+        _gas_price: Balance,
+        _state_root: Option<StateRoot>,
+        _transaction: &SignedTransaction,
+        _epoch_id: &EpochId,
+        _current_protocol_version: ProtocolVersion,
+    ) -> Result<TxValidationResult, Error> {
+        Ok(TxValidationResult::default())
+    }
+
     fn prepare_transactions(
         &self,
         _gas_price: Balance,
@@ -607,14 +619,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         _state_root: StateRoot,
         _next_block_height: BlockHeight,
         transactions: &mut dyn PoolIterator,
-        _chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        _chain_validate: &mut dyn FnMut(&SignedTransaction) -> Result<(), InvalidTxError>,
         _current_protocol_version: ProtocolVersion,
-    ) -> Result<Vec<SignedTransaction>, Error> {
+    ) -> Result<PreparedTransactions, Error> {
         let mut res = vec![];
         while let Some(iter) = transactions.next() {
             res.push(iter.next().unwrap());
         }
-        Ok(res)
+        Ok(PreparedTransactions { transactions: res, rejected: vec![] })
     }
 
     fn epoch_sync_init_epoch_manager(
@@ -657,7 +669,7 @@ impl RuntimeAdapter for KeyValueRuntime {
         generate_storage_proof: bool,
         _is_new_chunk: bool,
         _is_first_block_with_chunk_of_version: bool,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Result<ApplyTransactionResult, Error> {
         assert!(states_to_patch.is_none(), "KeyValueRuntime does not support patch states.");
         assert!(!generate_storage_proof);
@@ -827,7 +839,9 @@ impl RuntimeAdapter for KeyValueRuntime {
             total_gas_burnt: 0,
             total_balance_burnt: 0,
             proof: None,
+            largest_receipt_proof: None,
             processed_delayed_receipts: vec![],
+            stage_times: Default::default(),
         })
     }
 
@@ -920,6 +934,39 @@ impl RuntimeAdapter for KeyValueRuntime {
                 block_height,
                 block_hash: *block_hash,
             }),
+            QueryRequest::CallFunctionWithOverrides { .. } => Ok(QueryResponse {
+                kind: QueryResponseKind::CallResultWithGas(Default::default()),
+                block_height,
+                block_hash: *block_hash,
+            }),
+            QueryRequest::ViewAccounts { account_ids } => {
+                let state = self.state.read().unwrap();
+                let amounts = state.get(state_root).map(|state| &state.amounts);
+                let accounts = account_ids
+                    .iter()
+                    .map(|account_id| {
+                        let account = match amounts.and_then(|amounts| amounts.get(account_id)) {
+                            Some(amount) => AccountViewOrError::Account(
+                                Account::new(*amount, 0, CryptoHash::default(), 0).into(),
+                            ),
+                            None => AccountViewOrError::Error(
+                                near_chain_primitives::error::QueryError::UnknownAccount {
+                                    requested_account_id: account_id.clone(),
+                                    block_height,
+                                    block_hash: *block_hash,
+                                }
+                                .to_string(),
+                            ),
+                        };
+                        (account_id.clone(), account)
+                    })
+                    .collect();
+                Ok(QueryResponse {
+                    kind: QueryResponseKind::ViewAccounts(ViewAccountsResult { accounts }),
+                    block_height,
+                    block_hash: *block_hash,
+                })
+            }
         }
     }
 
@@ -1120,6 +1167,14 @@ impl RuntimeAdapter for KeyValueRuntime {
         })
     }
 
+    fn get_validators_preview(
+        &self,
+        _block_hash: &CryptoHash,
+    ) -> Result<EpochValidatorsPreview, Error> {
+        Err(ErrorKind::Other("KeyValueRuntime doesn't support validators preview".to_string())
+            .into())
+    }
+
     fn compare_epoch_id(
         &self,
         epoch_id: &EpochId,
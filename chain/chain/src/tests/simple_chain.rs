@@ -1,12 +1,14 @@
+use crate::chain::ChainAccess;
 use crate::near_chain_primitives::error::BlockKnownError;
 use crate::test_utils::setup;
 use crate::{Block, ChainStoreAccess, ErrorKind};
+use borsh::BorshSerialize;
 use chrono;
 use chrono::TimeZone;
 use near_logger_utils::init_test_logger;
 use near_primitives::hash::CryptoHash;
 use near_primitives::time::MockClockGuard;
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use num_rational::Rational;
 use std::time::Instant;
 
@@ -104,6 +106,7 @@ fn build_chain_with_orhpans() {
         *last_block.header().next_bp_hash(),
         CryptoHash::default(),
         None,
+        BuildId::default(),
     );
     assert_eq!(chain.process_block_test(&None, block).unwrap_err().kind(), ErrorKind::Orphan);
     assert_eq!(
@@ -122,6 +125,37 @@ fn build_chain_with_orhpans() {
     );
 }
 
+#[test]
+fn evict_lowest_orphans_over_byte_limit() {
+    init_test_logger();
+    let (mut chain, _, signer) = setup();
+    let mut blocks = vec![chain.get_block(&chain.genesis().hash().clone()).unwrap().clone()];
+    for i in 1..5 {
+        let block = Block::empty(&blocks[i - 1], &*signer);
+        blocks.push(block);
+    }
+    let block_size = blocks[4].try_to_vec().unwrap().len();
+    chain.set_max_pool_bytes(2 * block_size, 0);
+
+    for block in [&blocks[4], &blocks[3]] {
+        let res = chain.process_block_test(&None, block.clone());
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::Orphan);
+    }
+    assert_eq!(chain.orphans().bytes(), 2 * block_size);
+    // Block 2 takes the pool over the limit and is the lowest orphan.
+    let res = chain.process_block_test(&None, blocks[2].clone());
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::Orphan);
+    assert_eq!(chain.orphans_len(), 2);
+    assert_eq!(chain.orphans_evicted_len(), 1);
+    assert!(!chain.is_orphan(blocks[2].hash()));
+    assert!(chain.is_orphan(blocks[3].hash()));
+    assert!(chain.is_orphan(blocks[4].hash()));
+
+    let res = chain.process_block_test(&None, blocks[1].clone());
+    assert_eq!(res.unwrap().unwrap().height, 1);
+    assert_eq!(chain.orphans().bytes(), 2 * block_size);
+}
+
 #[test]
 fn build_chain_with_skips_and_forks() {
     init_test_logger();
@@ -32,13 +32,16 @@ use near_primitives::version::{
     ProtocolVersion, MIN_GAS_PRICE_NEP_92, MIN_GAS_PRICE_NEP_92_FIX, MIN_PROTOCOL_VERSION_NEP_92,
     MIN_PROTOCOL_VERSION_NEP_92_FIX,
 };
-use near_primitives::views::{EpochValidatorInfo, QueryRequest, QueryResponse};
+use near_primitives::views::{
+    EpochValidatorInfo, EpochValidatorsPreview, QueryRequest, QueryResponse,
+};
 use near_store::{PartialStorage, ShardTries, Store, StoreUpdate, Trie, WrappedTrieChanges};
 
+use crate::apply_stage_times::ApplyChunkStageTimes;
 use crate::DoomslugThresholdMode;
 use near_primitives::epoch_manager::ShardConfig;
 use near_primitives::shard_layout::{ShardLayout, ShardUId};
-use near_primitives::state_record::StateRecord;
+use near_primitives::state_record::StatePatchRecord;
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum BlockStatus {
@@ -96,6 +99,25 @@ pub enum ApplySplitStateResultOrStateChanges {
     StateChangesForSplitStates(StateChangesForSplitStates),
 }
 
+/// Result of [`RuntimeAdapter::prepare_transactions`].
+pub struct PreparedTransactions {
+    /// Valid transactions, in the order they should be included in the chunk.
+    pub transactions: Vec<SignedTransaction>,
+    /// Transactions pulled from the pool which turned out to be invalid,
+    /// together with the reason they were rejected.
+    pub rejected: Vec<(SignedTransaction, InvalidTxError)>,
+}
+
+/// Result of [`RuntimeAdapter::validate_tx_full`].
+#[derive(Debug, Default)]
+pub struct TxValidationResult {
+    /// Every violation found, empty if the transaction is valid.
+    pub violations: Vec<InvalidTxError>,
+    /// Gas burnt for converting the transaction into a receipt, known once
+    /// the transaction passes the static checks.
+    pub gas_burnt: Option<Gas>,
+}
+
 pub struct ApplyTransactionResult {
     pub trie_changes: WrappedTrieChanges,
     pub new_root: StateRoot,
@@ -105,7 +127,12 @@ pub struct ApplyTransactionResult {
     pub total_gas_burnt: Gas,
     pub total_balance_burnt: Balance,
     pub proof: Option<PartialStorage>,
+    /// Receipt which added the most bytes to the proof, with the number of
+    /// bytes.  Only set if the proof is.
+    pub largest_receipt_proof: Option<(CryptoHash, u64)>,
     pub processed_delayed_receipts: Vec<Receipt>,
+    /// The outcome root isn't computed by the runtime so its time is zero.
+    pub stage_times: ApplyChunkStageTimes,
 }
 
 impl ApplyTransactionResult {
@@ -302,11 +329,26 @@ pub trait RuntimeAdapter: Send + Sync {
         current_protocol_version: ProtocolVersion,
     ) -> Result<Option<InvalidTxError>, Error>;
 
+    /// Validates a given signed transaction like `validate_tx` but doesn't
+    /// stop at the first problem: all violations of the runtime limits are
+    /// returned together.  The checks against the state, which need the
+    /// transaction to be within the limits, are run only if it is and if the
+    /// state root is given.
+    fn validate_tx_full(
+        &self,
+        gas_price: Balance,
+        state_root: Option<StateRoot>,
+        transaction: &SignedTransaction,
+        epoch_id: &EpochId,
+        current_protocol_version: ProtocolVersion,
+    ) -> Result<TxValidationResult, Error>;
+
     /// Returns an ordered list of valid transactions from the pool up the given limits.
     /// Pulls transactions from the given pool iterators one by one. Validates each transaction
     /// against the given `chain_validate` closure and runtime's transaction verifier.
     /// If the transaction is valid for both, it's added to the result and the temporary state
-    /// update is preserved for validation of next transactions.
+    /// update is preserved for validation of next transactions.  Otherwise it's returned
+    /// among the rejected transactions together with the validation error.
     /// Throws an `Error` with `ErrorKind::StorageError` in case the runtime throws
     /// `RuntimeError::StorageError`.
     fn prepare_transactions(
@@ -318,9 +360,9 @@ pub trait RuntimeAdapter: Send + Sync {
         state_root: StateRoot,
         next_block_height: BlockHeight,
         pool_iterator: &mut dyn PoolIterator,
-        chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        chain_validate: &mut dyn FnMut(&SignedTransaction) -> Result<(), InvalidTxError>,
         current_protocol_version: ProtocolVersion,
-    ) -> Result<Vec<SignedTransaction>, Error>;
+    ) -> Result<PreparedTransactions, Error>;
 
     /// Verify validator signature for the given epoch.
     /// Note: doesnt't account for slashed accounts within given epoch. USE WITH CAUTION.
@@ -609,7 +651,7 @@ pub trait RuntimeAdapter: Send + Sync {
         random_seed: CryptoHash,
         is_new_chunk: bool,
         is_first_block_with_chunk_of_version: bool,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Result<ApplyTransactionResult, Error> {
         let _span = tracing::debug_span!(
             target: "runtime",
@@ -655,7 +697,7 @@ pub trait RuntimeAdapter: Send + Sync {
         generate_storage_proof: bool,
         is_new_chunk: bool,
         is_first_block_with_chunk_of_version: bool,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Result<ApplyTransactionResult, Error>;
 
     fn check_state_transition(
@@ -696,6 +738,13 @@ pub trait RuntimeAdapter: Send + Sync {
         epoch_id: ValidatorInfoIdentifier,
     ) -> Result<EpochValidatorInfo, Error>;
 
+    /// Predicts the validators of the epoch after next, as if the current
+    /// epoch ended at `block_hash`.
+    fn get_validators_preview(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<EpochValidatorsPreview, Error>;
+
     /// Get the part of the state from given state root.
     /// `block_hash` is a block whose `prev_state_root` is `state_root`
     fn obtain_state_part(
@@ -4,8 +4,10 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::{
     ChunkHash, PartialEncodedChunkPart, PartialEncodedChunkV2, ReceiptProof, ShardChunkHeader,
 };
+use near_primitives::time::Clock;
 use near_primitives::types::{BlockHeight, BlockHeightDelta, ShardId};
 use std::collections::hash_map::Entry::Occupied;
+use std::time::Instant;
 use tracing::warn;
 
 // This file implements EncodedChunksCache, which provides three main functionalities:
@@ -48,6 +50,11 @@ pub struct EncodedChunksCacheEntry {
     /// validated again to make sure they are fully validated.
     /// See comments in `validate_chunk_header` for more context on partial vs full validation
     pub header_fully_validated: bool,
+    /// When the entry was created, i.e. when the header of the chunk was first seen
+    pub first_seen: Instant,
+    /// Whether parts or receipts of the chunk were requested from peers, as opposed to
+    /// only being pushed to us by the chunk producer and forwarded by other validators
+    pub parts_requested: bool,
 }
 
 pub struct EncodedChunksCache {
@@ -77,6 +84,8 @@ impl EncodedChunksCacheEntry {
             receipts: HashMap::new(),
             complete: false,
             header_fully_validated: false,
+            first_seen: Clock::instant(),
+            parts_requested: false,
         }
     }
 
@@ -130,6 +139,12 @@ impl EncodedChunksCache {
         }
     }
 
+    pub fn mark_entry_requested(&mut self, chunk_hash: &ChunkHash) {
+        if let Some(entry) = self.encoded_chunks.get_mut(chunk_hash) {
+            entry.parts_requested = true;
+        }
+    }
+
     /// Get a list of incomplete chunks whose previous block hash is `prev_block_hash`
     pub fn get_incomplete_chunks(
         &self,
@@ -97,7 +97,7 @@ use near_chain::{
 use near_network::types::{
     NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest, WrappedInstant,
 };
-use near_pool::{PoolIteratorWrapper, TransactionPool};
+use near_pool::{InsertTransactionResult, PoolIteratorWrapper, TransactionPool};
 use near_primitives::block::Tip;
 use near_primitives::hash::{hash, CryptoHash};
 use near_primitives::merkle::{merklize, verify_path, MerklePath};
@@ -116,10 +116,12 @@ use near_primitives::types::{
 use near_primitives::utils::MaybeValidated;
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::ProtocolVersion;
+use near_primitives::views::ChunkDistributionView;
 use near_primitives::{checked_feature, unwrap_or_return};
 
 use crate::chunk_cache::{EncodedChunksCache, EncodedChunksCacheEntry};
 use near_chain::near_chain_primitives::error::ErrorKind::DBNotFoundErr;
+use near_chain_configs::TransactionPoolConfig;
 pub use near_chunks_primitives::Error;
 use near_network_primitives::types::{
     AccountIdOrPeerTrackingShard, PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg,
@@ -471,6 +473,8 @@ pub struct ShardsManager {
     me: Option<AccountId>,
 
     tx_pools: HashMap<ShardId, TransactionPool>,
+    /// Limits applied to each of the transaction pools.
+    tx_pool_config: TransactionPoolConfig,
 
     runtime_adapter: Arc<dyn RuntimeAdapter>,
     peer_manager_adapter: Arc<dyn PeerManagerAdapter>,
@@ -483,6 +487,8 @@ pub struct ShardsManager {
     /// Useful to make tests deterministic and reproducible,
     /// while keeping the security of randomization of transactions in pool
     rng_seed: RngSeed,
+    /// Distribution of the last chunk completed in each shard, for the debug page.
+    recent_chunk_distribution: BTreeMap<ShardId, ChunkDistributionView>,
 }
 
 impl ShardsManager {
@@ -491,11 +497,13 @@ impl ShardsManager {
         runtime_adapter: Arc<dyn RuntimeAdapter>,
         network_adapter: Arc<dyn PeerManagerAdapter>,
         rng_seed: RngSeed,
+        tx_pool_config: TransactionPoolConfig,
     ) -> Self {
         TransactionPool::init_metrics();
         Self {
             me: me.clone(),
             tx_pools: HashMap::new(),
+            tx_pool_config,
             runtime_adapter: runtime_adapter.clone(),
             peer_manager_adapter: network_adapter,
             encoded_chunks: EncodedChunksCache::new(),
@@ -508,6 +516,7 @@ impl ShardsManager {
             chunk_forwards_cache: lru::LruCache::new(CHUNK_FORWARD_CACHE_SIZE),
             seals_mgr: SealsManager::new(me, runtime_adapter),
             rng_seed,
+            recent_chunk_distribution: BTreeMap::new(),
         }
     }
 
@@ -518,8 +527,20 @@ impl ShardsManager {
         );
     }
 
-    pub fn get_pool_iterator(&mut self, shard_id: ShardId) -> Option<PoolIteratorWrapper<'_>> {
-        self.tx_pools.get_mut(&shard_id).map(|pool| pool.pool_iterator())
+    /// Distribution of the last chunk completed in each shard, by shard id.
+    pub fn recent_chunk_distribution(&self) -> Vec<ChunkDistributionView> {
+        self.recent_chunk_distribution.values().cloned().collect()
+    }
+
+    /// Iterator over the pooled transactions of the shard, those of the `priority_accounts` first.
+    pub fn get_pool_iterator(
+        &mut self,
+        shard_id: ShardId,
+        priority_accounts: &[AccountId],
+    ) -> Option<PoolIteratorWrapper<'_>> {
+        self.tx_pools
+            .get_mut(&shard_id)
+            .map(|pool| pool.pool_iterator_with_priority(priority_accounts))
     }
 
     pub fn cares_about_shard_this_or_next_epoch(
@@ -638,9 +659,11 @@ impl ShardsManager {
         }
 
         let no_account_id = me.is_none();
+        let mut requested = false;
         for (target_account, part_ords) in bp_to_parts {
             // extra check that we are not sending request to ourselves.
             if no_account_id || me != target_account.as_ref() {
+                requested = true;
                 let request = PartialEncodedChunkRequestMsg {
                     chunk_hash: chunk_hash.clone(),
                     part_ords,
@@ -671,6 +694,9 @@ impl ShardsManager {
                 );
             }
         }
+        if requested {
+            self.encoded_chunks.mark_entry_requested(chunk_hash);
+        }
 
         Ok(())
     }
@@ -938,18 +964,24 @@ impl ShardsManager {
         self.encoded_chunks.get_chunk_headers_for_block(prev_block_hash)
     }
 
-    /// Returns true if transaction is not in the pool before call
-    pub fn insert_transaction(&mut self, shard_id: ShardId, tx: SignedTransaction) -> bool {
+    pub fn insert_transaction(
+        &mut self,
+        shard_id: ShardId,
+        tx: SignedTransaction,
+    ) -> InsertTransactionResult {
         self.pool_for_shard(shard_id).insert_transaction(tx)
     }
 
+    /// Removes the transactions included in a block from the pool and returns
+    /// how long they waited there, see [`TransactionPool::remove_transactions`].
     pub fn remove_transactions(
         &mut self,
         shard_id: ShardId,
         transactions: &Vec<SignedTransaction>,
-    ) {
-        if let Some(pool) = self.tx_pools.get_mut(&shard_id) {
-            pool.remove_transactions(transactions)
+    ) -> Vec<(CryptoHash, Duration)> {
+        match self.tx_pools.get_mut(&shard_id) {
+            Some(pool) => pool.remove_transactions(transactions),
+            None => vec![],
         }
     }
 
@@ -966,7 +998,11 @@ impl ShardsManager {
 
     fn pool_for_shard(&mut self, shard_id: ShardId) -> &mut TransactionPool {
         self.tx_pools.entry(shard_id).or_insert_with(|| {
-            TransactionPool::new(ShardsManager::random_seed(&self.rng_seed, shard_id))
+            TransactionPool::new(
+                ShardsManager::random_seed(&self.rng_seed, shard_id),
+                shard_id,
+                self.tx_pool_config.clone(),
+            )
         })
     }
 
@@ -978,6 +1014,17 @@ impl ShardsManager {
         self.pool_for_shard(shard_id).reintroduce_transactions(transactions.clone());
     }
 
+    /// Puts the transactions included in a chunk produced by this node back
+    /// into the pool and returns how long they waited there, see
+    /// [`TransactionPool::return_included_transactions`].
+    pub fn return_included_transactions(
+        &mut self,
+        shard_id: ShardId,
+        transactions: &Vec<SignedTransaction>,
+    ) -> Vec<(CryptoHash, Duration)> {
+        self.pool_for_shard(shard_id).return_included_transactions(transactions.clone())
+    }
+
     pub fn receipts_recipient_filter<T>(
         &self,
         from_shard_id: ShardId,
@@ -1597,8 +1644,13 @@ impl ShardsManager {
     ) -> Result<ProcessPartialEncodedChunkResult, Error> {
         let header = &partial_encoded_chunk.header;
         let chunk_hash = header.chunk_hash();
-        debug!(target: "chunks", "process partial encoded chunk {:?} height {} shard {}, me: {:?}",
-               chunk_hash, header.height_created(), header.shard_id(), self.me);
+        debug!(
+            target: "chunks",
+            chunk_hash = ?chunk_hash,
+            height = header.height_created(),
+            shard_id = header.shard_id(),
+            me = ?self.me,
+            "Process partial encoded chunk");
         // Verify the partial encoded chunk is valid and worth processing
         // 1.a Leave if we received known chunk
         if let Some(entry) = self.encoded_chunks.get(&chunk_hash) {
@@ -1853,11 +1905,39 @@ impl ShardsManager {
 
     /// A helper function to be called after a chunk is considered complete
     fn complete_chunk(&mut self, chunk_hash: &ChunkHash) {
+        self.record_chunk_distribution(chunk_hash);
         self.encoded_chunks.mark_entry_complete(chunk_hash);
         self.encoded_chunks.remove_from_cache_if_outside_horizon(chunk_hash);
         self.requested_partial_encoded_chunks.remove(chunk_hash);
     }
 
+    /// Records how long it took the chunk to become complete since its header was first seen
+    fn record_chunk_distribution(&mut self, chunk_hash: &ChunkHash) {
+        let entry = match self.encoded_chunks.get(chunk_hash) {
+            Some(entry) if !entry.complete => entry,
+            _ => return,
+        };
+        let shard_id = entry.header.shard_id();
+        let completion_time = Clock::instant().saturating_duration_since(entry.first_seen);
+        let shard_label = shard_id.to_string();
+        metrics::PARTIAL_ENCODED_CHUNK_COMPLETION_TIME
+            .with_label_values(&[&shard_label])
+            .observe(completion_time.as_secs_f64());
+        if entry.parts_requested {
+            metrics::PARTIAL_ENCODED_CHUNK_COMPLETED_WITH_REQUESTS
+                .with_label_values(&[&shard_label])
+                .inc();
+        }
+        let view = ChunkDistributionView {
+            shard_id,
+            height_created: entry.header.height_created(),
+            chunk_hash: chunk_hash.0,
+            completion_time_millis: completion_time.as_millis() as u64,
+            parts_requested: entry.parts_requested,
+        };
+        self.recent_chunk_distribution.insert(shard_id, view);
+    }
+
     /// Send the parts of the partial_encoded_chunk that are owned by `self.me` to the
     /// other validators that are tracking the shard.
     pub fn send_partial_encoded_chunk_to_chunk_trackers(
@@ -2132,13 +2212,16 @@ impl ShardsManager {
         Ok(())
     }
 
+    /// Sends the parts of the chunk the node produced to their owners and
+    /// stores it.  Returns the parts sent to each block producer, the node's
+    /// own excluded.
     pub fn distribute_encoded_chunk(
         &mut self,
         encoded_chunk: EncodedShardChunk,
         merkle_paths: Vec<MerklePath>,
         outgoing_receipts: Vec<Receipt>,
         chain_store: &mut ChainStore,
-    ) -> Result<(), Error> {
+    ) -> Result<Vec<(AccountId, Vec<u64>)>, Error> {
         // TODO: if the number of validators exceeds the number of parts, this logic must be changed
         let chunk_header = encoded_chunk.cloned_header();
         let prev_block_hash = chunk_header.prev_block_hash();
@@ -2157,6 +2240,7 @@ impl ShardsManager {
             .make_outgoing_receipts_proofs(&chunk_header, &outgoing_receipts)?
             .map(Arc::new)
             .collect::<Vec<_>>();
+        let mut parts_sent = vec![];
         for (to_whom, part_ords) in block_producer_mapping {
             let part_receipt_proofs = receipt_proofs
                 .iter()
@@ -2174,7 +2258,7 @@ impl ShardsManager {
 
             let partial_encoded_chunk = encoded_chunk
                 .create_partial_encoded_chunk_with_arc_receipts(
-                    part_ords,
+                    part_ords.clone(),
                     part_receipt_proofs,
                     &merkle_paths,
                 );
@@ -2186,6 +2270,7 @@ impl ShardsManager {
                         partial_encoded_chunk,
                     },
                 ));
+                parts_sent.push((to_whom, part_ords));
             }
         }
 
@@ -2195,7 +2280,7 @@ impl ShardsManager {
         // Store the chunk in the permanent storage
         self.decode_and_persist_encoded_chunk(encoded_chunk, chain_store, merkle_paths)?;
 
-        Ok(())
+        Ok(parts_sent)
     }
 }
 
@@ -2236,6 +2321,7 @@ mod test {
             runtime_adapter,
             network_adapter.clone(),
             TEST_SEED,
+            TransactionPoolConfig::default(),
         );
         let added = Clock::instant();
         shards_manager.requested_partial_encoded_chunks.insert(
@@ -2293,6 +2379,7 @@ mod test {
             runtime_adapter.clone(),
             network_adapter.clone(),
             TEST_SEED,
+            TransactionPoolConfig::default(),
         );
         let signer =
             InMemoryValidatorSigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "test");
@@ -2459,6 +2546,7 @@ mod test {
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
             TEST_SEED,
+            TransactionPoolConfig::default(),
         );
         // process chunk part 0
         let partial_encoded_chunk = fixture.make_partial_encoded_chunk(&[0]);
@@ -2531,6 +2619,7 @@ mod test {
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
             TEST_SEED,
+            TransactionPoolConfig::default(),
         );
 
         // part id > num parts
@@ -2557,6 +2646,7 @@ mod test {
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
             TEST_SEED,
+            TransactionPoolConfig::default(),
         );
         let partial_encoded_chunk = fixture.make_partial_encoded_chunk(&fixture.mock_part_ords);
         let result = shards_manager
@@ -2590,6 +2680,8 @@ mod test {
         let (forwards_count, requests_count) = count_forwards_and_requests(&fixture);
         assert!(forwards_count > 0);
         assert_eq!(requests_count, 0);
+        let chunk_hash = fixture.mock_chunk_header.chunk_hash();
+        assert!(!shards_manager.encoded_chunks.get(&chunk_hash).unwrap().parts_requested);
 
         // After some time, we should send requests if we have not been forwarded the parts
         // we need.
@@ -2604,6 +2696,8 @@ mod test {
         shards_manager.resend_chunk_requests(&head);
         let (_, requests_count) = count_forwards_and_requests(&fixture);
         assert!(requests_count > 0);
+        // The chunk is counted as needing requests whenever it's completed.
+        assert!(shards_manager.encoded_chunks.get(&chunk_hash).unwrap().parts_requested);
     }
 
     #[test]
@@ -2620,6 +2714,7 @@ mod test {
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
             test_seed,
+            TransactionPoolConfig::default(),
         );
         let header_head = Tip {
             height: 0,
@@ -2648,6 +2743,7 @@ mod test {
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
             test_seed,
+            TransactionPoolConfig::default(),
         );
         shards_manager.request_chunks(
             vec![fixture.mock_chunk_header.clone()],
@@ -2669,6 +2765,7 @@ mod test {
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
             test_seed,
+            TransactionPoolConfig::default(),
         );
         shards_manager.request_chunks(
             vec![fixture.mock_chunk_header.clone()],
@@ -2694,6 +2791,7 @@ mod test {
             fixture.mock_runtime.clone(),
             fixture.mock_network.clone(),
             TEST_SEED,
+            TransactionPoolConfig::default(),
         );
         let (most_parts, other_parts) = {
             let mut most_parts = fixture.mock_chunk_parts.clone();
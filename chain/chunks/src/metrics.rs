@@ -18,3 +18,32 @@ pub static PARTIAL_ENCODED_CHUNK_REQUEST_PROCESSING_TIME: Lazy<near_metrics::His
         )
         .unwrap()
     });
+
+pub static PARTIAL_ENCODED_CHUNK_COMPLETION_TIME: Lazy<near_metrics::HistogramVec> =
+    Lazy::new(|| {
+        near_metrics::try_create_histogram_vec(
+            "near_partial_encoded_chunk_completion_time",
+            concat!(
+                "Time from the first time the header of a chunk is seen to ",
+                "having the parts and receipts needed to reconstruct it, ",
+                "per shard, in seconds."
+            ),
+            &["shard_id"],
+            Some(prometheus::exponential_buckets(0.001, 2.0, 16).unwrap()),
+        )
+        .unwrap()
+    });
+
+pub static PARTIAL_ENCODED_CHUNK_COMPLETED_WITH_REQUESTS: Lazy<near_metrics::IntCounterVec> =
+    Lazy::new(|| {
+        near_metrics::try_create_int_counter_vec(
+            "near_partial_encoded_chunk_completed_with_requests_total",
+            concat!(
+                "Number of chunks which became complete only after their parts ",
+                "or receipts were requested from peers, per shard.  Chunks ",
+                "completed from the parts pushed to the node aren't counted."
+            ),
+            &["shard_id"],
+        )
+        .unwrap()
+    });
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use actix::Message;
 use chrono::DateTime;
-use near_primitives::time::Utc;
+use near_primitives::time::{Clock, Utc};
 
 use near_chain_configs::ProtocolConfigView;
 use near_network_primitives::types::{AccountOrPeerIdOrHash, KnownProducer, PeerInfo};
@@ -12,17 +13,21 @@ use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{MerklePath, PartialMerkleTree};
 use near_primitives::sharding::ChunkHash;
+use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{
-    AccountId, BlockHeight, BlockReference, EpochId, EpochReference, MaybeBlockId, ShardId,
-    TransactionOrReceiptId,
+    AccountId, BlockHeight, BlockHeightDelta, BlockReference, EpochId, EpochReference,
+    MaybeBlockId, NumBlocks, ShardId, TransactionOrReceiptId,
 };
 use near_primitives::utils::generate_random_string;
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeViewEnum, GasPriceView, LightClientBlockLiteView, LightClientBlockView,
-    QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView, StateChangesRequestView,
-    StateChangesView,
+    BlockHeaderView, BlockView, ChunkApplyStageTimesView, ChunkSummaryView, ChunkView,
+    DroppedTransactionView, EpochValidatorInfo, EpochValidatorsPreview, EquivocationView,
+    ExecutionOutcomeWithIdView, FinalExecutionOutcomeViewEnum, ForkView, GasPriceView,
+    LightClientBlockLiteView, LightClientBlockView, PendingReceiptsView, ProducedChunkView,
+    ProducerVersionsView, ProductionSkipView, QueryRequest, QueryResponse, ReceiptView,
+    StateChangesKindsView, StateChangesRequestView, StateChangesView, StateFingerprintView,
+    SyncTransitionView, TxConstructionInfoView, TxValidationView,
 };
 pub use near_primitives::views::{StatusResponse, StatusSyncInfo};
 
@@ -84,6 +89,8 @@ pub struct DownloadStatus {
     pub done: bool,
     pub state_requests_count: u64,
     pub last_target: Option<AccountOrPeerIdOrHash>,
+    /// Name of the state sync source which served the part, once it's done.
+    pub source: Option<&'static str>,
 }
 
 impl Clone for DownloadStatus {
@@ -96,6 +103,7 @@ impl Clone for DownloadStatus {
             done: self.done,
             state_requests_count: self.state_requests_count,
             last_target: self.last_target.clone(),
+            source: self.source,
         }
     }
 }
@@ -218,6 +226,19 @@ impl Message for GetBlockHash {
     type Result = Result<CryptoHash, GetBlockError>;
 }
 
+/// Actor message requesting block headers, without reading the blocks.
+pub enum GetBlockHeaders {
+    /// Headers of the canonical blocks at heights from `from_height` to
+    /// `to_height` inclusive, `None` for the heights without a block.
+    Range { from_height: BlockHeight, to_height: BlockHeight },
+    /// Headers of the blocks with the given hashes, in the same order.
+    Hashes(Vec<CryptoHash>),
+}
+
+impl Message for GetBlockHeaders {
+    type Result = Result<Vec<Option<BlockHeaderView>>, GetBlockError>;
+}
+
 /// Get block with the block merkle tree. Used for testing
 pub struct GetBlockWithMerkleTree(pub BlockReference);
 
@@ -231,6 +252,25 @@ impl Message for GetBlockWithMerkleTree {
     type Result = Result<(BlockView, PartialMerkleTree), GetBlockError>;
 }
 
+/// Summaries of the chunks of the block, in the order of the shards.
+pub struct GetChunkSummaries {
+    pub block_hash: CryptoHash,
+}
+
+impl Message for GetChunkSummaries {
+    type Result = Result<Vec<ChunkSummaryView>, GetBlockError>;
+}
+
+/// Fingerprint of the stored roots of the shards the node tracked after the
+/// block, see [`near_primitives::views::StateFingerprintView`].
+pub struct GetStateFingerprint {
+    pub block_hash: CryptoHash,
+}
+
+impl Message for GetStateFingerprint {
+    type Result = Result<StateFingerprintView, GetBlockError>;
+}
+
 /// Actor message requesting a chunk by chunk hash and block hash + shard id.
 pub enum GetChunk {
     Height(BlockHeight, ShardId),
@@ -286,11 +326,28 @@ pub struct Query {
     pub query_id: String,
     pub block_reference: BlockReference,
     pub request: QueryRequest,
+    /// When the query was created, to measure how long it waits for a view
+    /// client thread.
+    pub created: Instant,
+    /// Queries which don't start before the deadline are abandoned with
+    /// `QueryError::Timeout`, the caller has given up on them already.
+    pub deadline: Option<Instant>,
 }
 
 impl Query {
     pub fn new(block_reference: BlockReference, request: QueryRequest) -> Self {
-        Query { query_id: generate_random_string(10), block_reference, request }
+        Query {
+            query_id: generate_random_string(10),
+            block_reference,
+            request,
+            created: Clock::instant(),
+            deadline: None,
+        }
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
     }
 }
 
@@ -346,6 +403,10 @@ pub enum QueryError {
     },
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
+    #[error("Function calls with overrides are disabled on this node")]
+    CallOverridesDisabled,
+    #[error("Can't view {requested} accounts at once, the limit of this node is {limit}, split them into smaller batches")]
+    TooManyAccounts { requested: usize, limit: usize },
     #[error(
         "The data for block #{block_height} is garbage collected on this node, use an archival node to fetch historical data"
     )]
@@ -355,6 +416,8 @@ pub enum QueryError {
     },
     #[error("Block either has never been observed on the node or has been garbage collected: {block_reference:?}")]
     UnknownBlock { block_reference: near_primitives::types::BlockReference },
+    #[error("The node is overloaded and didn't complete the query in time. Try again later")]
+    Timeout,
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
     // expected cases, we cannot statically guarantee that no other errors will be returned
     // in the future.
@@ -407,6 +470,138 @@ impl Message for Status {
     type Result = Result<StatusResponse, StatusError>;
 }
 
+/// Re-reads the validator key file and switches to the key it contains.
+///
+/// The switch doesn't happen immediately: approvals of the heights the node
+/// has already approved stay signed with the old key and the new key is used
+/// once the head reaches `switch_height` of the response.
+pub struct ReloadValidatorKey;
+
+#[derive(Debug)]
+pub struct ReloadValidatorKeyResponse {
+    pub public_key: near_crypto::PublicKey,
+    pub switch_height: BlockHeight,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReloadValidatorKeyError {
+    #[error("Node is not running as a validator")]
+    NotAValidator,
+    #[error("Failed reading validator key from {path}: {error_message}")]
+    ReadKey { path: String, error_message: String },
+    #[error("Validator key is for {new_account_id}, but node is running as {account_id}")]
+    AccountIdMismatch { account_id: AccountId, new_account_id: AccountId },
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {error_message}")]
+    Unreachable { error_message: String },
+}
+
+impl From<near_chain_primitives::Error> for ReloadValidatorKeyError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        Self::Unreachable { error_message: error.to_string() }
+    }
+}
+
+impl Message for ReloadValidatorKey {
+    type Result = Result<ReloadValidatorKeyResponse, ReloadValidatorKeyError>;
+}
+
+/// Asks whether the transaction was recently dropped from the pool during
+/// chunk production and why.
+pub struct GetDroppedTransaction {
+    pub tx_hash: CryptoHash,
+}
+
+impl Message for GetDroppedTransaction {
+    type Result = Option<DroppedTransactionView>;
+}
+
+/// Asks whether the transaction, submitted to this node, recently expired
+/// before it was included in a chunk.
+pub struct GetExpiredTransaction {
+    pub tx_hash: CryptoHash,
+}
+
+/// Heights of the blocks an expired transaction could be included after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiredTransaction {
+    pub valid_from_height: BlockHeight,
+    pub valid_until_height: BlockHeight,
+}
+
+impl Message for GetExpiredTransaction {
+    type Result = Option<ExpiredTransaction>;
+}
+
+/// Asks how long the transaction waited in the pool before it was included in
+/// a chunk, known if this node produced or tracked the chunk recently.
+pub struct GetTransactionPoolWait {
+    pub tx_hash: CryptoHash,
+}
+
+impl Message for GetTransactionPoolWait {
+    type Result = Option<std::time::Duration>;
+}
+
+/// Asks for the recent transitions between sync states, most recent first.
+pub struct GetSyncHistory;
+
+impl Message for GetSyncHistory {
+    type Result = Vec<SyncTransitionView>;
+}
+
+/// Asks for the non-final heights at which the node knows of more than one
+/// block, lowest height first.
+pub struct GetForks;
+
+impl Message for GetForks {
+    type Result = Vec<ForkView>;
+}
+
+/// Asks for the evidence of equivocation the node has stored, lowest height
+/// first.
+pub struct GetMisbehaviorEvidence;
+
+impl Message for GetMisbehaviorEvidence {
+    type Result = Result<Vec<EquivocationView>, String>;
+}
+
+/// Asks for the blocks and chunks the node's validator was assigned and missed
+/// in the current and the previous epoch, most recent first.
+pub struct GetProductionSkips;
+
+impl Message for GetProductionSkips {
+    type Result = Vec<ProductionSkipView>;
+}
+
+/// Asks for the chunks the node's validator produced in the current and the
+/// previous epoch and whether they were included, most recent first.
+pub struct GetProducedChunks;
+
+impl Message for GetProducedChunks {
+    type Result = Vec<ProducedChunkView>;
+}
+
+/// Asks for the stage times and trie reads of applying the chunk of the shard
+/// in the block, known only if the node applied it recently.
+pub struct GetChunkApplyStats {
+    pub block_hash: CryptoHash,
+    pub shard_id: ShardId,
+}
+
+impl Message for GetChunkApplyStats {
+    type Result = Option<ChunkApplyStageTimesView>;
+}
+
+/// Asks for the protocol versions and builds of the producers of the last
+/// `last_n_blocks` blocks of the canonical chain.
+pub struct GetProducerVersions {
+    pub last_n_blocks: NumBlocks,
+}
+
+impl Message for GetProducerVersions {
+    type Result = Result<ProducerVersionsView, String>;
+}
+
 pub struct GetNextLightClientBlock {
     pub last_block_hash: CryptoHash,
 }
@@ -448,6 +643,26 @@ impl Message for GetNextLightClientBlock {
     type Result = Result<Option<LightClientBlockView>, GetNextLightClientBlockError>;
 }
 
+/// Light client blocks bringing a light client with head `last_block_hash` up
+/// to the final head of the chain: the last final block of every epoch from
+/// the next epoch of the light client, then the one of the current epoch.
+pub struct GetLightClientBlocks {
+    pub last_block_hash: CryptoHash,
+    /// Upper bound on the number of blocks returned.
+    pub limit: usize,
+}
+
+pub struct GetLightClientBlocksResponse {
+    pub blocks: Vec<LightClientBlockView>,
+    /// Hash of the last block returned when there are more blocks after the
+    /// `limit`, to ask for the next ones with it as `last_block_hash`.
+    pub next_cursor: Option<CryptoHash>,
+}
+
+impl Message for GetLightClientBlocks {
+    type Result = Result<GetLightClientBlocksResponse, GetNextLightClientBlockError>;
+}
+
 pub struct GetNetworkInfo {}
 
 impl Message for GetNetworkInfo {
@@ -513,6 +728,19 @@ pub enum TxStatusError {
     ChainError(near_chain_primitives::Error),
     MissingTransaction(CryptoHash),
     InvalidTx(InvalidTxError),
+    /// The transaction was accepted into the pool but dropped during chunk
+    /// production because it turned out to be invalid.
+    DroppedFromPool {
+        tx_hash: CryptoHash,
+        reason: InvalidTxError,
+    },
+    /// The transaction was submitted to this node but expired before it was
+    /// included in a chunk, it will never be executed.
+    Expired {
+        tx_hash: CryptoHash,
+        valid_from_height: BlockHeight,
+        valid_until_height: BlockHeight,
+    },
     InternalError(String),
     TimeoutError,
 }
@@ -529,6 +757,13 @@ impl From<TxStatusError> for String {
             }
             TxStatusError::TimeoutError => format!("Timeout error"),
             TxStatusError::InvalidTx(e) => format!("Invalid transaction: {}", e),
+            TxStatusError::DroppedFromPool { tx_hash, reason } => {
+                format!("Transaction {} was dropped from the pool: {}", tx_hash, reason)
+            }
+            TxStatusError::Expired { tx_hash, valid_from_height, valid_until_height } => format!(
+                "Transaction {} expired without being executed, it was valid from height {} to {}",
+                tx_hash, valid_from_height, valid_until_height
+            ),
         }
     }
 }
@@ -537,6 +772,72 @@ impl Message for TxStatus {
     type Result = Result<Option<FinalExecutionOutcomeViewEnum>, TxStatusError>;
 }
 
+/// Validates a transaction against the head of the chain without
+/// submitting it.
+pub struct ValidateTx {
+    pub transaction: SignedTransaction,
+}
+
+impl Message for ValidateTx {
+    type Result = Result<TxValidationView, ValidateTxError>;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ValidateTxError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {error_message}")]
+    Unreachable { error_message: String },
+}
+
+impl From<near_chain_primitives::Error> for ValidateTxError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(error_message) => {
+                Self::InternalError { error_message }
+            }
+            _ => Self::Unreachable { error_message: error.to_string() },
+        }
+    }
+}
+
+/// Checks that the block a transaction is based on is a block of this chain
+/// recent enough for the transaction to be valid, before it's submitted.
+pub struct CheckTxBlockHash {
+    pub block_hash: CryptoHash,
+}
+
+impl Message for CheckTxBlockHash {
+    type Result = Result<(), CheckTxBlockHashError>;
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CheckTxBlockHashError {
+    /// The node is in sync and doesn't know the block, it isn't a block of
+    /// this chain.
+    #[error("Block {block_hash} is unknown to this chain, the transaction may be signed for another network")]
+    UnknownBlock { block_hash: CryptoHash },
+    #[error("Block {block_hash} at height {block_height} is older than the transaction validity period of {validity_period} blocks, the head is at height {head_height}")]
+    Expired {
+        block_hash: CryptoHash,
+        block_height: BlockHeight,
+        head_height: BlockHeight,
+        validity_period: BlockHeightDelta,
+    },
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl From<near_chain_primitives::Error> for CheckTxBlockHashError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
 pub struct GetValidatorInfo {
     pub epoch_reference: EpochReference,
 }
@@ -580,6 +881,16 @@ impl Message for GetValidatorOrdered {
     type Result = Result<Vec<ValidatorStakeView>, GetValidatorInfoError>;
 }
 
+/// Predicts the validators of the epoch after next, see
+/// [`near_primitives::views::EpochValidatorsPreview`].
+pub struct GetValidatorsPreview {
+    pub block_id: MaybeBlockId,
+}
+
+impl Message for GetValidatorsPreview {
+    type Result = Result<EpochValidatorsPreview, GetValidatorInfoError>;
+}
+
 pub struct GetStateChanges {
     pub block_hash: CryptoHash,
     pub state_changes_request: StateChangesRequestView,
@@ -732,6 +1043,8 @@ pub struct GetBlockProofResponse {
 pub enum GetBlockProofError {
     #[error("Block either has never been observed on the node or has been garbage collected: {error_message}")]
     UnknownBlock { error_message: String },
+    #[error("Block {block_hash} is not on the canonical chain")]
+    NotOnCanonicalChain { block_hash: CryptoHash },
     #[error("Internal error: {error_message}")]
     InternalError { error_message: String },
     // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
@@ -748,6 +1061,9 @@ impl From<near_chain_primitives::error::Error> for GetBlockProofError {
             near_chain_primitives::error::ErrorKind::DBNotFoundErr(error_message) => {
                 Self::UnknownBlock { error_message }
             }
+            near_chain_primitives::error::ErrorKind::NotOnCanonicalChain(block_hash) => {
+                Self::NotOnCanonicalChain { block_hash }
+            }
             near_chain_primitives::error::ErrorKind::Other(error_message) => {
                 Self::InternalError { error_message }
             }
@@ -791,6 +1107,52 @@ impl Message for GetReceipt {
     type Result = Result<Option<ReceiptView>, GetReceiptError>;
 }
 
+/// Looks for the receipts for the account which aren't executed yet, in the
+/// delayed receipts queue of its shard and in the latest blocks, see
+/// [`near_primitives::views::PendingReceiptsView`].
+pub struct GetPendingReceipts {
+    pub account_id: AccountId,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetPendingReceiptsError {
+    #[error("IO Error: {0}")]
+    IOError(String),
+    #[error("Shard {shard_id} of account {account_id} isn't tracked by this node")]
+    UntrackedShard { account_id: AccountId, shard_id: ShardId },
+    // NOTE: Currently, the underlying errors are too broad, and while we tried to handle
+    // expected cases, we cannot statically guarantee that no other errors will be returned
+    // in the future.
+    // TODO #3851: Remove this variant once we can exhaustively match all the underlying errors
+    #[error("It is a bug if you receive this error type, please, report this incident: https://github.com/near/nearcore/issues/new/choose. Details: {0}")]
+    Unreachable(String),
+}
+
+impl From<near_chain_primitives::Error> for GetPendingReceiptsError {
+    fn from(error: near_chain_primitives::Error) -> Self {
+        match error.kind() {
+            near_chain_primitives::ErrorKind::IOErr(s) => Self::IOError(s),
+            _ => Self::Unreachable(error.to_string()),
+        }
+    }
+}
+
+impl Message for GetPendingReceipts {
+    type Result = Result<PendingReceiptsView, GetPendingReceiptsError>;
+}
+
+/// Reads the nonce of the access key and the block to build a transaction
+/// against at the final head, see
+/// [`near_primitives::views::TxConstructionInfoView`].
+pub struct GetTxConstructionInfo {
+    pub account_id: AccountId,
+    pub public_key: near_crypto::PublicKey,
+}
+
+impl Message for GetTxConstructionInfo {
+    type Result = Result<TxConstructionInfoView, QueryError>;
+}
+
 pub struct GetProtocolConfig(pub BlockReference);
 
 impl Message for GetProtocolConfig {
@@ -820,3 +1182,15 @@ impl From<near_chain_primitives::Error> for GetProtocolConfigError {
         }
     }
 }
+
+/// Message to the client or the view client sent on behalf of the RPC request
+/// with `request_id`.  The actor handles `msg` with the id on its span, for the
+/// work the request triggers to be found in the logs.
+pub struct WithRequestId<M> {
+    pub request_id: String,
+    pub msg: M,
+}
+
+impl<M: Message> Message for WithRequestId<M> {
+    type Result = M::Result;
+}
@@ -5,26 +5,35 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use borsh::BorshDeserialize;
 use near_primitives::time::Clock;
 use tracing::{debug, error, info, trace, warn};
 
+use near_chain::block_processing_log::BlockProcessingLog;
 use near_chain::chain::{
     ApplyStatePartsRequest, BlockCatchUpRequest, BlockMissingChunks, BlocksCatchUpState,
     OrphanMissingChunks, StateSplitRequest, TX_ROUTING_HEIGHT_HORIZON,
 };
+use near_chain::misbehavior::save_evidence;
 use near_chain::test_utils::format_hash;
-use near_chain::types::{AcceptedBlock, LatestKnown};
+use near_chain::types::{AcceptedBlock, LatestKnown, PreparedTransactions};
 use near_chain::{
     BlockStatus, Chain, ChainGenesis, ChainStoreAccess, Doomslug, DoomslugThresholdMode, ErrorKind,
     Provenance, RuntimeAdapter,
 };
-use near_chain_configs::{ClientConfig, LogSummaryStyle};
+use near_chain_configs::{ClientConfig, GCConfig, LogSummaryStyle};
 use near_chunks::{ProcessPartialEncodedChunkResult, ShardsManager};
+use near_crypto::{PublicKey, Signature};
 use near_network::types::{
     FullPeerInfo, NetworkClientResponses, NetworkRequests, PeerManagerAdapter,
 };
+use near_pool::expiry::{TransactionExpiry, ValidityWindow};
+use near_pool::InsertTransactionResult;
 use near_primitives::block::{Approval, ApprovalInner, ApprovalMessage, Block, BlockHeader, Tip};
-use near_primitives::challenge::{Challenge, ChallengeBody};
+use near_primitives::challenge::{
+    BlockDoubleSign, Challenge, ChallengeBody, EquivocationEvidence, EquivocationKind,
+};
+use near_primitives::errors::InvalidTxError;
 use near_primitives::hash::CryptoHash;
 use near_primitives::merkle::{merklize, MerklePath};
 use near_primitives::receipt::Receipt;
@@ -38,9 +47,17 @@ use near_primitives::types::{AccountId, ApprovalStake, BlockHeight, EpochId, Num
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::MaybeValidated;
 use near_primitives::validator_signer::ValidatorSigner;
-use near_primitives::views::{BlockByChunksView, ChunkInfoView};
-
+use near_primitives::views::{BlockByChunksView, ChunkInfoView, ProductionSkipReason};
+
+use crate::dropped_transactions::DroppedTransactions;
+use crate::forks::{KnownBlock, KnownForks};
+use crate::gc_pacing::GCPacing;
+use crate::produced_chunks::ProducedChunks;
+use crate::production_skips::ProductionSkips;
+use crate::shadow_production::{NextEpochAssignments, ShadowProduction};
+use crate::shutdown_report;
 use crate::sync::{BlockSync, EpochSync, HeaderSync, StateSync, StateSyncResult};
+use crate::tx_rebroadcast::ForwardedTransactions;
 use crate::{metrics, SyncStatus};
 use itertools::Itertools;
 use near_chain::chain::ChainAccess;
@@ -52,10 +69,17 @@ use near_network_primitives::types::{
 use near_o11y::log_assert;
 use near_primitives::block_header::ApprovalType;
 use near_primitives::epoch_manager::RngSeed;
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 
 const NUM_REBROADCAST_BLOCKS: usize = 30;
 
+/// Number of transactions whose wait in the pool is remembered for the `tx` RPC.
+const NUM_POOL_WAITS: usize = 10_000;
+
+/// Number of heights before a new head checked for the missed blocks of the
+/// validator, bounding the work after a long stall.
+const MAX_SKIPPED_HEIGHTS_CHECKED: BlockHeight = 1_000;
+
 /// The time we wait for the response to a Epoch Sync request before retrying
 // TODO #3488 set 30_000
 pub const EPOCH_SYNC_REQUEST_TIMEOUT: Duration = Duration::from_millis(1_000);
@@ -111,6 +135,27 @@ pub struct Client {
     /// Last time the head was updated, or our head was rebroadcasted. Used to re-broadcast the head
     /// again to prevent network from stalling if a large percentage of the network missed a block
     last_time_head_progress_made: Instant,
+    /// Transactions recently dropped from the pool during chunk production.
+    pub(crate) dropped_transactions: DroppedTransactions,
+    /// Set if `config.shadow_production` is.
+    pub(crate) shadow_production: Option<ShadowProduction>,
+    /// Blocks known at the non-final heights.
+    pub(crate) known_forks: KnownForks,
+    /// Why the validator missed the blocks and chunks it was assigned.
+    pub(crate) production_skips: ProductionSkips,
+    /// Chunks the validator produced and whether they were included.
+    pub(crate) produced_chunks: ProducedChunks,
+    /// How many blocks the garbage collection clears after a block.
+    pub(crate) gc_pacing: GCPacing,
+    /// How long the transactions recently included in the chunks this node
+    /// produced or tracked waited in the pool.
+    pub(crate) pool_waits: lru::LruCache<CryptoHash, Duration>,
+    /// Transactions forwarded to the chunk producers, to be rebroadcast if
+    /// their shard misses chunks.
+    pub(crate) forwarded_transactions: ForwardedTransactions,
+    /// Transactions submitted to this node, to tell the ones which expired
+    /// before they were included.
+    pub(crate) transaction_expiry: TransactionExpiry,
 }
 
 // Debug information about the upcoming block.
@@ -154,17 +199,28 @@ impl Client {
         } else {
             DoomslugThresholdMode::NoApprovals
         };
-        let chain = Chain::new(
+        let mut chain = Chain::new(
             runtime_adapter.clone(),
             &chain_genesis,
             doomslug_threshold_mode,
             !config.archive,
         )?;
+        chain.block_processing_log = BlockProcessingLog::open(
+            chain.store().owned_store().clone(),
+            config.block_processing_log_size,
+        )?
+        .with_observer(Box::new(shutdown_report::record_block));
+        chain.set_max_pool_bytes(
+            config.orphan_pool_max_bytes as usize,
+            config.missing_chunks_pool_max_bytes as usize,
+        );
+        chain.mut_store().set_commit_max_batch_bytes(config.store_commit_max_batch_bytes as usize);
         let shards_mgr = ShardsManager::new(
             validator_signer.as_ref().map(|x| x.validator_id().clone()),
             runtime_adapter.clone(),
             network_adapter.clone(),
             rng_seed,
+            config.transaction_pool.clone(),
         );
         let sync_status = SyncStatus::AwaitingPeers;
         let genesis_block = chain.genesis_block();
@@ -192,7 +248,11 @@ impl Client {
         );
         let block_sync =
             BlockSync::new(network_adapter.clone(), config.block_fetch_horizon, config.archive);
-        let state_sync = StateSync::new(network_adapter.clone(), config.state_sync_timeout);
+        let state_sync = StateSync::new(
+            network_adapter.clone(),
+            config.state_sync_timeout,
+            config.state_sync_sources.clone(),
+        );
         let num_block_producer_seats = config.num_block_producer_seats as usize;
         let data_parts = runtime_adapter.num_data_parts();
         let parity_parts = runtime_adapter.num_total_parts() - data_parts;
@@ -206,6 +266,8 @@ impl Client {
             validator_signer.clone(),
             doomslug_threshold_mode,
         );
+        let dropped_transactions = DroppedTransactions::new(config.dropped_transactions_log_size);
+        let shadow_production = config.shadow_production.then(ShadowProduction::new);
         Ok(Self {
             #[cfg(feature = "test_features")]
             adv_produce_blocks: false,
@@ -231,6 +293,15 @@ impl Client {
             rs: ReedSolomonWrapper::new(data_parts, parity_parts),
             rebroadcasted_blocks: lru::LruCache::new(NUM_REBROADCAST_BLOCKS),
             last_time_head_progress_made: Clock::instant(),
+            dropped_transactions,
+            shadow_production,
+            known_forks: KnownForks::default(),
+            production_skips: ProductionSkips::default(),
+            produced_chunks: ProducedChunks::default(),
+            gc_pacing: GCPacing::new(&config.gc),
+            pool_waits: lru::LruCache::new(NUM_POOL_WAITS),
+            forwarded_transactions: ForwardedTransactions::new(),
+            transaction_expiry: TransactionExpiry::new(),
         })
     }
 
@@ -259,11 +330,14 @@ impl Client {
                     shard_id,
                     true,
                 ) {
-                    self.shards_mgr.remove_transactions(
+                    let waits = self.shards_mgr.remove_transactions(
                         shard_id,
                         // By now the chunk must be in store, otherwise the block would have been orphaned
                         self.chain.get_chunk(&chunk_header.chunk_hash()).unwrap().transactions(),
                     );
+                    for (tx_hash, wait) in waits {
+                        self.pool_waits.put(tx_hash, wait);
+                    }
                 }
             }
         }
@@ -327,7 +401,7 @@ impl Client {
     }
 
     fn should_reschedule_block(
-        &self,
+        &mut self,
         head: &Tip,
         prev_hash: &CryptoHash,
         prev_prev_hash: &CryptoHash,
@@ -362,6 +436,11 @@ impl Client {
                 // block, which is the current epoch for this block, so this block cannot be applied
                 // at all yet, block production must to be rescheduled
                 debug!(target: "client", "Produce block: prev block is not caught up");
+                self.production_skips.note(
+                    next_height,
+                    None,
+                    ProductionSkipReason::StateNotCaughtUp,
+                );
                 return Ok(true);
             }
         }
@@ -372,6 +451,7 @@ impl Client {
     /// Produce block if we are block producer for given `next_height` block height.
     /// Either returns produced block (not applied) or error.
     pub fn produce_block(&mut self, next_height: BlockHeight) -> Result<Option<Block>, Error> {
+        self.production_skips.clear(next_height, None);
         let known_height = self.chain.mut_store().get_latest_known()?.height;
 
         let validator_signer = self
@@ -395,8 +475,6 @@ impl Client {
         let prev_hash = head.last_block_hash;
         let prev_height = head.height;
         let prev_prev_hash = *prev.prev_hash();
-        let prev_epoch_id = prev.epoch_id().clone();
-        let prev_next_bp_hash = *prev.next_bp_hash();
 
         // Check and update the doomslug tip here. This guarantees that our endorsement will be in the
         // doomslug witness. Have to do it before checking the ability to produce a block.
@@ -422,6 +500,15 @@ impl Client {
         let validator_pk = validator_stake.take_public_key();
         if validator_pk != validator_signer.public_key() {
             debug!(target: "client", "Local validator key {} does not match expected validator key {}, skipping block production", validator_signer.public_key(), validator_pk);
+            self.production_skips.note(
+                next_height,
+                None,
+                ProductionSkipReason::SignerError(format!(
+                    "local key {} doesn't match validator key {}",
+                    validator_signer.public_key(),
+                    validator_pk
+                )),
+            );
             #[cfg(not(feature = "test_features"))]
             return Ok(None);
             #[cfg(feature = "test_features")]
@@ -431,8 +518,14 @@ impl Client {
         }
 
         let new_chunks = self.shards_mgr.prepare_chunks(&prev_hash);
-        debug!(target: "client", "{:?} Producing block at height {}, parent {} @ {}, {} new chunks", validator_signer.validator_id(),
-               next_height, prev.height(), format_hash(head.last_block_hash), new_chunks.len());
+        debug!(
+            target: "client",
+            validator = %validator_signer.validator_id(),
+            height = next_height,
+            prev_height = prev.height(),
+            prev_hash = %format_hash(head.last_block_hash),
+            new_chunks_count = new_chunks.len(),
+            "Producing block");
 
         // If we are producing empty blocks and there are no transactions.
         if !self.config.produce_empty_blocks && new_chunks.is_empty() {
@@ -442,12 +535,6 @@ impl Client {
 
         let mut approvals_map = self.doomslug.remove_witness(&prev_hash, prev_height, next_height);
 
-        // At this point, the previous epoch hash must be available
-        let epoch_id = self
-            .runtime_adapter
-            .get_epoch_id_from_prev_block(&head.last_block_hash)
-            .expect("Epoch hash should exist at this point");
-
         let approvals = self
             .runtime_adapter
             .get_epoch_block_approvers_ordered(&prev_hash)?
@@ -463,9 +550,45 @@ impl Client {
 
         debug_assert_eq!(approvals_map.len(), 0);
 
+        let block =
+            self.build_block(prev_hash, next_height, new_chunks, approvals, &*validator_signer)?;
+
+        // Update latest known even before returning block out, to prevent race conditions.
+        self.chain.mut_store().save_latest_known(LatestKnown {
+            height: next_height,
+            seen: block.header().raw_timestamp(),
+        })?;
+
+        metrics::BLOCK_PRODUCED_TOTAL.inc();
+        self.production_skips.clear(next_height, None);
+
+        Ok(Some(block))
+    }
+
+    /// Builds and signs the block at `next_height` on top of `prev_hash` with the
+    /// new chunks and the approvals, without checking whether the validator is
+    /// the one to produce it.
+    fn build_block(
+        &mut self,
+        prev_hash: CryptoHash,
+        next_height: BlockHeight,
+        new_chunks: HashMap<ShardId, ShardChunkHeader>,
+        approvals: Vec<Option<Signature>>,
+        validator_signer: &dyn ValidatorSigner,
+    ) -> Result<Block, Error> {
+        let prev = self.chain.get_block_header(&prev_hash)?.clone();
+        let prev_epoch_id = prev.epoch_id().clone();
+        let prev_next_bp_hash = *prev.next_bp_hash();
+
+        // At this point, the previous epoch hash must be available
+        let epoch_id = self
+            .runtime_adapter
+            .get_epoch_id_from_prev_block(&prev_hash)
+            .expect("Epoch hash should exist at this point");
+
         let next_epoch_id = self
             .runtime_adapter
-            .get_next_epoch_id_from_prev_block(&head.last_block_hash)
+            .get_next_epoch_id_from_prev_block(&prev_hash)
             .expect("Epoch hash should exist at this point");
 
         let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
@@ -510,26 +633,21 @@ impl Client {
 
         let prev_header = &prev_block.header();
 
-        let next_epoch_id =
-            self.runtime_adapter.get_next_epoch_id_from_prev_block(&head.last_block_hash)?;
-
-        let minted_amount =
-            if self.runtime_adapter.is_next_block_epoch_start(&head.last_block_hash)? {
-                Some(self.runtime_adapter.get_epoch_minted_amount(&next_epoch_id)?)
-            } else {
-                None
-            };
+        let minted_amount = if self.runtime_adapter.is_next_block_epoch_start(&prev_hash)? {
+            Some(self.runtime_adapter.get_epoch_minted_amount(&next_epoch_id)?)
+        } else {
+            None
+        };
 
-        let epoch_sync_data_hash =
-            if self.runtime_adapter.is_next_block_epoch_start(&head.last_block_hash)? {
-                Some(self.runtime_adapter.get_epoch_sync_data_hash(
-                    prev_block.hash(),
-                    &epoch_id,
-                    &next_epoch_id,
-                )?)
-            } else {
-                None
-            };
+        let epoch_sync_data_hash = if self.runtime_adapter.is_next_block_epoch_start(&prev_hash)? {
+            Some(self.runtime_adapter.get_epoch_sync_data_hash(
+                prev_block.hash(),
+                &epoch_id,
+                &next_epoch_id,
+            )?)
+        } else {
+            None
+        };
 
         // Get all the current challenges.
         // TODO(2445): Enable challenges when they are working correctly.
@@ -539,7 +657,7 @@ impl Client {
         let next_epoch_protocol_version =
             self.runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
 
-        let block = Block::produce(
+        Ok(Block::produce(
             this_epoch_protocol_version,
             next_epoch_protocol_version,
             prev_header,
@@ -556,21 +674,12 @@ impl Client {
             minted_amount,
             prev_block_extra.challenges_result,
             vec![],
-            &*validator_signer,
+            validator_signer,
             next_bp_hash,
             block_merkle_root,
             timestamp_override,
-        );
-
-        // Update latest known even before returning block out, to prevent race conditions.
-        self.chain.mut_store().save_latest_known(LatestKnown {
-            height: next_height,
-            seen: block.header().raw_timestamp(),
-        })?;
-
-        metrics::BLOCK_PRODUCED_TOTAL.inc();
-
-        Ok(Some(block))
+            BuildId::new(&self.config.version),
+        ))
     }
 
     pub fn produce_chunk(
@@ -590,7 +699,13 @@ impl Client {
         let chunk_proposer =
             self.runtime_adapter.get_chunk_producer(epoch_id, next_height, shard_id).unwrap();
         if validator_signer.validator_id() != &chunk_proposer {
-            debug!(target: "client", "Not producing chunk for shard {}: chain at {}, not block producer for next block. Me: {}, proposer: {}", shard_id, next_height, validator_signer.validator_id(), chunk_proposer);
+            debug!(
+                target: "client",
+                shard_id,
+                height = next_height,
+                me = %validator_signer.validator_id(),
+                %chunk_proposer,
+                "Not producing chunk, not the chunk producer for the next height");
             return Ok(None);
         }
 
@@ -599,6 +714,11 @@ impl Client {
             if !self.chain.prev_block_is_caught_up(&prev_prev_hash, &prev_block_hash)? {
                 // See comment in similar snipped in `produce_block`
                 debug!(target: "client", "Produce chunk: prev block is not caught up");
+                self.production_skips.note(
+                    next_height,
+                    Some(shard_id),
+                    ProductionSkipReason::StateNotCaughtUp,
+                );
                 return Err(Error::ChunkProducer(
                     "State for the epoch is not downloaded yet, skipping chunk production"
                         .to_string(),
@@ -622,8 +742,49 @@ impl Client {
             .clone();
 
         let prev_block_header = self.chain.get_block_header(&prev_block_hash)?.clone();
-        let transactions = self.prepare_transactions(shard_id, &chunk_extra, &prev_block_header)?;
+        let transactions =
+            self.prepare_transactions(shard_id, next_height, &chunk_extra, &prev_block_header)?;
         let num_filtered_transactions = transactions.len();
+        let (encoded_chunk, merkle_paths, outgoing_receipts) = self.build_chunk(
+            prev_block_hash,
+            epoch_id,
+            &last_header,
+            next_height,
+            shard_id,
+            &chunk_extra,
+            transactions,
+            &*validator_signer,
+        )?;
+
+        debug!(
+            target: "client",
+            "Produced chunk at height {} for shard {} with {} txs and {} receipts, I'm {}, chunk_hash: {}",
+            next_height,
+            shard_id,
+            num_filtered_transactions,
+            outgoing_receipts.len(),
+            validator_signer.validator_id(),
+            encoded_chunk.chunk_hash().0,
+        );
+
+        metrics::CHUNK_PRODUCED_TOTAL.inc();
+        Ok(Some((encoded_chunk, merkle_paths, outgoing_receipts)))
+    }
+
+    /// Builds, encodes and signs the chunk of the shard at `next_height` on top
+    /// of `prev_block_hash` with the transactions, without checking whether
+    /// the validator is the one to produce it.
+    fn build_chunk(
+        &mut self,
+        prev_block_hash: CryptoHash,
+        epoch_id: &EpochId,
+        last_header: &ShardChunkHeader,
+        next_height: BlockHeight,
+        shard_id: ShardId,
+        chunk_extra: &ChunkExtra,
+        transactions: Vec<SignedTransaction>,
+        validator_signer: &dyn ValidatorSigner,
+    ) -> Result<(EncodedShardChunk, Vec<MerklePath>, Vec<Receipt>), Error> {
         let (tx_root, _) = merklize(&transactions);
         let outgoing_receipts = self.chain.get_outgoing_receipts_for_shard(
             prev_block_hash,
@@ -663,40 +824,197 @@ impl Client {
             &outgoing_receipts,
             outgoing_receipts_root,
             tx_root,
-            &*validator_signer,
+            validator_signer,
             &mut self.rs,
             protocol_version,
         )?;
+        Ok((encoded_chunk, merkle_paths, outgoing_receipts))
+    }
 
-        debug!(
-            target: "client",
-            "Produced chunk at height {} for shard {} with {} txs and {} receipts, I'm {}, chunk_hash: {}",
+    /// What the validator produces in the epoch after the one of the block
+    /// following `prev_hash`, if it isn't a validator of that one.
+    fn next_epoch_assignments(
+        &self,
+        prev_hash: &CryptoHash,
+        me: &AccountId,
+    ) -> Result<Option<NextEpochAssignments>, Error> {
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(prev_hash)?;
+        if self.runtime_adapter.get_validator_by_account_id(&epoch_id, prev_hash, me).is_ok() {
+            return Ok(None);
+        }
+        let next_epoch_id = self.runtime_adapter.get_next_epoch_id_from_prev_block(prev_hash)?;
+        let public_key =
+            match self.runtime_adapter.get_validator_by_account_id(&next_epoch_id, prev_hash, me) {
+                Ok((stake, _)) => stake.take_public_key(),
+                Err(_) => return Ok(None),
+            };
+        let block_producers: Vec<AccountId> = self
+            .runtime_adapter
+            .get_epoch_block_producers_ordered(&next_epoch_id, prev_hash)?
+            .into_iter()
+            .map(|(stake, _)| stake.take_account_id())
+            .collect();
+        let block_producer = block_producers.contains(me);
+        let chunk_shards = (0..self.runtime_adapter.num_shards(&epoch_id)?)
+            .filter(|&shard_id| {
+                self.runtime_adapter.will_care_about_shard(Some(me), prev_hash, shard_id, false)
+            })
+            .collect();
+        Ok(Some(NextEpochAssignments {
+            epoch_id: next_epoch_id,
+            public_key,
+            block_producer,
+            chunk_shards,
+            block_producers: block_producers.into_iter().filter(|id| id != me).collect(),
+        }))
+    }
+
+    /// Builds the block following `prev_hash` as if the validator produced it,
+    /// without approvals, and checks its signature against the staked key.
+    fn shadow_produce_block(
+        &mut self,
+        prev_hash: CryptoHash,
+        next_height: BlockHeight,
+        public_key: &PublicKey,
+        validator_signer: &dyn ValidatorSigner,
+    ) -> Result<(), Error> {
+        let approvals = self
+            .runtime_adapter
+            .get_epoch_block_approvers_ordered(&prev_hash)?
+            .into_iter()
+            .map(|_| None)
+            .collect();
+        let block =
+            self.build_block(prev_hash, next_height, HashMap::new(), approvals, validator_signer)?;
+        if !block.header().verify_block_producer(public_key) {
+            return Err(Error::BlockProducer(format!(
+                "Block signed with {} doesn't verify with the staked key {}",
+                validator_signer.public_key(),
+                public_key
+            )));
+        }
+        Ok(())
+    }
+
+    /// Builds the chunk of the shard following `prev_block` as if the
+    /// validator produced it, without transactions, and checks its signature
+    /// against the staked key.
+    fn shadow_produce_chunk(
+        &mut self,
+        prev_block: &Block,
+        next_height: BlockHeight,
+        shard_id: ShardId,
+        public_key: &PublicKey,
+        validator_signer: &dyn ValidatorSigner,
+    ) -> Result<(), Error> {
+        let prev_block_hash = *prev_block.hash();
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&prev_block_hash)?;
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
+        let chunk_extra = self
+            .chain
+            .get_chunk_extra(&prev_block_hash, &shard_uid)
+            .map_err(|err| {
+                Error::ChunkProducer(format!("No chunk extra, is the shard tracked? {}", err))
+            })?
+            .clone();
+        let last_header =
+            Chain::get_prev_chunk_header(&*self.runtime_adapter, prev_block, shard_id)?;
+        let (encoded_chunk, _, _) = self.build_chunk(
+            prev_block_hash,
+            &epoch_id,
+            &last_header,
             next_height,
             shard_id,
-            num_filtered_transactions,
-            outgoing_receipts.len(),
-            validator_signer.validator_id(),
-            encoded_chunk.chunk_hash().0,
-        );
+            &chunk_extra,
+            vec![],
+            validator_signer,
+        )?;
+        let header = encoded_chunk.cloned_header();
+        if !header.signature().verify(header.chunk_hash().as_ref(), public_key) {
+            return Err(Error::ChunkProducer(format!(
+                "Chunk signed with {} doesn't verify with the staked key {}",
+                validator_signer.public_key(),
+                public_key
+            )));
+        }
+        Ok(())
+    }
 
-        metrics::CHUNK_PRODUCED_TOTAL.inc();
-        Ok(Some((encoded_chunk, merkle_paths, outgoing_receipts)))
+    /// Builds the block and chunks the validator is assigned to in the next
+    /// epoch on top of the new head, see `ClientConfig::shadow_production`.
+    fn shadow_produce(&mut self, block: &Block, validator_signer: &dyn ValidatorSigner) {
+        let assignments =
+            match self.next_epoch_assignments(block.hash(), validator_signer.validator_id()) {
+                Ok(assignments) => assignments,
+                Err(err) => {
+                    debug!(target: "client", ?err, "Failed to get the next epoch assignments");
+                    return;
+                }
+            };
+        if let Some(shadow_production) = &mut self.shadow_production {
+            shadow_production.set_assignments(assignments.clone());
+        }
+        let assignments = match assignments {
+            Some(assignments) => assignments,
+            None => return,
+        };
+        let next_height = block.header().height() + 1;
+
+        let mut attempts = vec![];
+        if assignments.block_producer {
+            let start = Instant::now();
+            let result = self.shadow_produce_block(
+                *block.hash(),
+                next_height,
+                &assignments.public_key,
+                validator_signer,
+            );
+            attempts.push((None, start.elapsed(), result));
+        }
+        for &shard_id in &assignments.chunk_shards {
+            let start = Instant::now();
+            let result = self.shadow_produce_chunk(
+                block,
+                next_height,
+                shard_id,
+                &assignments.public_key,
+                validator_signer,
+            );
+            attempts.push((Some(shard_id), start.elapsed(), result));
+        }
+        if let Some(shadow_production) = &mut self.shadow_production {
+            for (shard_id, duration, result) in attempts {
+                let result = result.map_err(|err| err.to_string());
+                shadow_production.record(next_height, shard_id, duration, result);
+            }
+        }
     }
 
     /// Prepares an ordered list of valid transactions from the pool up the limits.
     fn prepare_transactions(
         &mut self,
         shard_id: ShardId,
+        next_height: BlockHeight,
         chunk_extra: &ChunkExtra,
         prev_block_header: &BlockHeader,
     ) -> Result<Vec<SignedTransaction>, Error> {
-        let Self { chain, shards_mgr, runtime_adapter, .. } = self;
+        let Self {
+            chain,
+            shards_mgr,
+            runtime_adapter,
+            dropped_transactions,
+            pool_waits,
+            config,
+            ..
+        } = self;
+        let priority_accounts = &config.chunk_producer.priority_accounts;
 
         let next_epoch_id =
             runtime_adapter.get_epoch_id_from_prev_block(prev_block_header.hash())?;
         let protocol_version = runtime_adapter.get_epoch_protocol_version(&next_epoch_id)?;
 
-        let transactions = if let Some(mut iter) = shards_mgr.get_pool_iterator(shard_id) {
+        let pool_iterator = shards_mgr.get_pool_iterator(shard_id, priority_accounts);
+        let transactions = if let Some(mut iter) = pool_iterator {
             let transaction_validity_period = chain.transaction_validity_period;
             runtime_adapter.prepare_transactions(
                 prev_block_header.gas_price(),
@@ -709,28 +1027,48 @@ impl Client {
                 // invalid transactions to be included.
                 prev_block_header.height() + 1,
                 &mut iter,
-                &mut |tx: &SignedTransaction| -> bool {
-                    chain
-                        .mut_store()
-                        .check_transaction_validity_period(
-                            prev_block_header,
-                            &tx.transaction.block_hash,
-                            transaction_validity_period,
-                        )
-                        .is_ok()
+                &mut |tx: &SignedTransaction| -> Result<(), InvalidTxError> {
+                    chain.mut_store().check_transaction_validity_period(
+                        prev_block_header,
+                        &tx.transaction.block_hash,
+                        transaction_validity_period,
+                    )
                 },
                 protocol_version,
             )?
         } else {
-            vec![]
+            PreparedTransactions { transactions: vec![], rejected: vec![] }
         };
+        dropped_transactions.record(shard_id, next_height, transactions.rejected);
+        let transactions = transactions.transactions;
+        if !priority_accounts.is_empty() {
+            let num_priority = transactions
+                .iter()
+                .filter(|tx| priority_accounts.contains(&tx.transaction.signer_id))
+                .count();
+            metrics::CHUNK_PRIORITY_TRANSACTIONS
+                .with_label_values(&[&shard_id.to_string()])
+                .observe(num_priority as f64);
+        }
         // Reintroduce valid transactions back to the pool. They will be removed when the chunk is
         // included into the block.
-        shards_mgr.reintroduce_transactions(shard_id, &transactions);
+        for (tx_hash, wait) in shards_mgr.return_included_transactions(shard_id, &transactions) {
+            pool_waits.put(tx_hash, wait);
+        }
         Ok(transactions)
     }
 
     pub fn send_challenges(&mut self, challenges: Vec<ChallengeBody>) {
+        for body in &challenges {
+            if let ChallengeBody::BlockDoubleSign(double_sign) = body {
+                match self.block_double_sign_evidence(double_sign) {
+                    Ok(evidence) => self.save_equivocation(evidence),
+                    Err(err) => {
+                        warn!(target: "client", ?err, "Failed to build evidence of a double sign")
+                    }
+                }
+            }
+        }
         if let Some(validator_signer) = &self.validator_signer {
             for body in challenges {
                 let challenge = Challenge::produce(body, &**validator_signer);
@@ -742,6 +1080,95 @@ impl Client {
         }
     }
 
+    fn block_double_sign_evidence(
+        &self,
+        double_sign: &BlockDoubleSign,
+    ) -> Result<EquivocationEvidence, near_chain::Error> {
+        let left = BlockHeader::try_from_slice(&double_sign.left_block_header)?;
+        let right = BlockHeader::try_from_slice(&double_sign.right_block_header)?;
+        let producer = self.runtime_adapter.get_block_producer(left.epoch_id(), left.height())?;
+        let (validator, _) = self.runtime_adapter.get_validator_by_account_id(
+            left.epoch_id(),
+            left.prev_hash(),
+            &producer,
+        )?;
+        Ok(EquivocationEvidence::new(
+            producer,
+            validator.take_public_key(),
+            EquivocationKind::Block { left, right },
+        ))
+    }
+
+    /// Looks for a chunk seen before at the same height and shard as the one
+    /// in the header, in the same epoch, and stores the evidence if both are
+    /// signed by the chunk producer.
+    fn check_chunk_double_sign(
+        &mut self,
+        header: &ShardChunkHeader,
+        previous: Option<ChunkHash>,
+    ) -> Result<(), Error> {
+        let previous = match previous {
+            Some(previous) if previous != header.chunk_hash() => previous,
+            _ => return Ok(()),
+        };
+        let other = match self.chain.mut_store().get_partial_chunk(&previous) {
+            Ok(partial_chunk) => partial_chunk.cloned_header(),
+            Err(_) => return Ok(()),
+        };
+        let epoch_id =
+            self.runtime_adapter.get_epoch_id_from_prev_block(header.prev_block_hash_ref())?;
+        if self.runtime_adapter.get_epoch_id_from_prev_block(other.prev_block_hash_ref())?
+            != epoch_id
+        {
+            return Ok(());
+        }
+        let producer = self.runtime_adapter.get_chunk_producer(
+            &epoch_id,
+            header.height_created(),
+            header.shard_id(),
+        )?;
+        let (validator, _) = self.runtime_adapter.get_validator_by_account_id(
+            &epoch_id,
+            header.prev_block_hash_ref(),
+            &producer,
+        )?;
+        self.save_equivocation(EquivocationEvidence::new(
+            producer,
+            validator.take_public_key(),
+            EquivocationKind::Chunk { left: header.clone(), right: other },
+        ));
+        Ok(())
+    }
+
+    /// Stores the evidence if it checks out, see `near_chain::misbehavior`.
+    fn save_equivocation(&self, evidence: EquivocationEvidence) {
+        let kind = evidence.kind.as_str();
+        if let Err(err) = evidence.verify() {
+            debug!(
+                target: "client",
+                signer = %evidence.signer,
+                kind,
+                %err,
+                "Rejected evidence of equivocation"
+            );
+            return;
+        }
+        match save_evidence(self.chain.store().store(), &evidence) {
+            Ok(true) => {
+                warn!(
+                    target: "client",
+                    signer = %evidence.signer,
+                    height = evidence.height(),
+                    kind,
+                    "Found conflicting messages signed by the same validator"
+                );
+                metrics::EQUIVOCATION_EVIDENCE_TOTAL.with_label_values(&[kind]).inc();
+            }
+            Ok(false) => {}
+            Err(err) => warn!(target: "client", ?err, "Failed to save evidence of equivocation"),
+        }
+    }
+
     pub fn process_block(
         &mut self,
         block: MaybeValidated<Block>,
@@ -924,6 +1351,13 @@ impl Client {
     ) -> Result<Vec<AcceptedBlock>, Error> {
         let chunk_hash = partial_encoded_chunk.chunk_hash();
         let pec_v2: MaybeValidated<PartialEncodedChunkV2> = partial_encoded_chunk.map(Into::into);
+        let header = &pec_v2.get_inner().header;
+        let previous = self
+            .chain
+            .mut_store()
+            .get_any_chunk_hash_by_height_shard(header.height_created(), header.shard_id())
+            .ok()
+            .cloned();
         let process_result = self.shards_mgr.process_partial_encoded_chunk(
             pec_v2.as_ref(),
             self.chain.head().ok().as_ref(),
@@ -931,6 +1365,9 @@ impl Client {
             &mut self.rs,
         )?;
         debug!(target:"client", "process partial encoded chunk {:?}, result: {:?}", chunk_hash, process_result);
+        if let Err(err) = self.check_chunk_double_sign(&pec_v2.get_inner().header, previous) {
+            warn!(target: "client", ?err, "Failed to check the chunk for a double sign");
+        }
 
         Ok(self.process_process_partial_encoded_chunk_result(
             pec_v2.into_inner().header,
@@ -1034,7 +1471,13 @@ impl Client {
         if Some(&next_block_producer) == self.validator_signer.as_ref().map(|x| x.validator_id()) {
             self.collect_block_approval(&approval, ApprovalType::SelfApproval);
         } else {
-            debug!(target: "client", "Sending an approval {:?} from {} to {} for {}", approval.inner, approval.account_id, next_block_producer, approval.target_height);
+            debug!(
+                target: "client",
+                approval_inner = ?approval.inner,
+                account_id = %approval.account_id,
+                %next_block_producer,
+                height = approval.target_height,
+                "Sending an approval");
             let approval_message = ApprovalMessage::new(approval, next_block_producer);
             self.network_adapter.do_send(PeerManagerMessageRequest::NetworkRequests(
                 NetworkRequests::Approval { approval_message },
@@ -1044,6 +1487,22 @@ impl Client {
         Ok(())
     }
 
+    /// Records the block among the ones known at its height, see `crate::forks`.
+    fn record_known_block(&mut self, block: &Block) {
+        let header = block.header();
+        let producer =
+            match self.runtime_adapter.get_block_producer(header.epoch_id(), header.height()) {
+                Ok(producer) => producer,
+                Err(err) => {
+                    debug!(target: "client", ?err, "Failed to find the producer of the block");
+                    return;
+                }
+            };
+        let final_height = self.chain.final_head().map_or(0, |tip| tip.height);
+        let known = KnownBlock { hash: *header.hash(), prev_hash: *header.prev_hash(), producer };
+        self.known_forks.record(header.height(), final_height, known);
+    }
+
     /// Gets called when block got accepted.
     /// Send updates over network, update tx pool and notify ourselves if it's time to produce next block.
     /// Blocks are passed in no particular order.
@@ -1084,6 +1543,7 @@ impl Client {
         };
 
         let _ = self.check_and_update_doomslug_tip();
+        self.record_known_block(&block);
 
         // If we produced the block, then it should have already been broadcasted.
         // If received the block from another node then broadcast "header first" to minimize network traffic.
@@ -1113,6 +1573,8 @@ impl Client {
                 self.chain.get_block_header(last_final_block).map_or(0, |header| header.height())
             };
             self.chain.blocks_with_missing_chunks.prune_blocks_below_height(last_finalized_height);
+            metrics::FINAL_HEAD_DISTANCE
+                .set(block.header().height().saturating_sub(last_finalized_height) as i64);
 
             {
                 let _span = tracing::info_span!(
@@ -1127,10 +1589,25 @@ impl Client {
                     self.chain.clear_archive_data(self.config.gc.gc_blocks_limit)
                 } else {
                     let tries = self.runtime_adapter.get_tries();
-                    self.chain.clear_data(tries, &self.config.gc)
+                    let gc_config = GCConfig {
+                        gc_blocks_limit: self.gc_pacing.blocks_limit(),
+                        ..self.config.gc.clone()
+                    };
+                    self.chain.clear_data(tries, &gc_config)
                 };
                 log_assert!(result.is_ok(), "Can't clear old data, {:?}", result);
+                if !self.config.archive {
+                    let gc_stop_height = self.runtime_adapter.get_gc_stop_height(block.hash());
+                    if let Ok(tail) = self.chain.tail() {
+                        self.gc_pacing.set_tail_lag(gc_stop_height.saturating_sub(tail + 1));
+                    }
+                }
+            }
+
+            if let Err(err) = self.rebroadcast_stuck_transactions(&block) {
+                warn!(target: "client", "Failed to rebroadcast stuck transactions: {}", err);
             }
+            self.expire_submitted_transactions(&block);
 
             if self.runtime_adapter.is_next_block_epoch_start(block.hash()).unwrap_or(false) {
                 let next_epoch_protocol_version = unwrap_or_return!(self
@@ -1213,6 +1690,24 @@ impl Client {
                 }
             };
 
+            if provenance != Provenance::SYNC && !self.sync_status.is_syncing() {
+                if let Err(err) =
+                    self.record_production_skips(&block, validator_signer.validator_id())
+                {
+                    warn!(target: "client", "Failed to record missed production: {}", err);
+                }
+                if status.is_new_head() {
+                    let height = block.header().height();
+                    let new_chunks: HashSet<_> = block
+                        .chunks()
+                        .iter()
+                        .filter(|chunk| chunk.height_included() == height)
+                        .map(|chunk| chunk.chunk_hash().0)
+                        .collect();
+                    self.produced_chunks.new_head(block.hash(), height, &new_chunks);
+                }
+            }
+
             if provenance != Provenance::SYNC
                 && !self.sync_status.is_syncing()
                 && !skip_produce_chunk
@@ -1229,6 +1724,8 @@ impl Client {
                         .unwrap();
 
                     if chunk_proposer == *validator_signer.validator_id() {
+                        let next_height = block.header().height() + 1;
+                        self.production_skips.clear(next_height, Some(shard_id));
                         match self.produce_chunk(
                             *block.hash(),
                             &epoch_id,
@@ -1237,22 +1734,39 @@ impl Client {
                             block.header().height() + 1,
                             shard_id,
                         ) {
-                            Ok(Some((encoded_chunk, merkle_paths, receipts))) => self
-                                .shards_mgr
-                                .distribute_encoded_chunk(
-                                    encoded_chunk,
-                                    merkle_paths,
-                                    receipts,
-                                    self.chain.mut_store(),
-                                )
-                                .expect("Failed to process produced chunk"),
+                            Ok(Some((encoded_chunk, merkle_paths, receipts))) => {
+                                let produced_at = Clock::utc();
+                                let chunk_hash = encoded_chunk.chunk_hash().0;
+                                let parts_sent = self
+                                    .shards_mgr
+                                    .distribute_encoded_chunk(
+                                        encoded_chunk,
+                                        merkle_paths,
+                                        receipts,
+                                        self.chain.mut_store(),
+                                    )
+                                    .expect("Failed to process produced chunk");
+                                self.produced_chunks.produced(
+                                    chunk_hash,
+                                    shard_id,
+                                    next_height,
+                                    &epoch_id,
+                                    produced_at,
+                                    parts_sent,
+                                );
+                            }
                             Ok(None) => {}
                             Err(err) => {
                                 error!(target: "client", "Error producing chunk {:?}", err);
+                                self.production_skips.note_error(next_height, Some(shard_id), &err);
                             }
                         }
                     }
                 }
+
+                if self.shadow_production.is_some() && status.is_new_head() {
+                    self.shadow_produce(&block, &*validator_signer);
+                }
             }
         }
         for accepted_block in self.check_incomplete_chunks(block.hash()) {
@@ -1269,6 +1783,59 @@ impl Client {
         self.chain.blocks_delay_tracker.finish_block_processing(&block_hash, &chunk_hashes);
     }
 
+    /// Records the blocks and chunks the validator was assigned and which the
+    /// new head shows were missed: the heights it skips and the chunks it
+    /// doesn't include.
+    fn record_production_skips(&mut self, block: &Block, me: &AccountId) -> Result<(), Error> {
+        let header = block.header();
+        let prev_height = self.chain.get_block_header(header.prev_hash())?.height();
+        // The skipped heights have the same previous block, hence the same epoch.
+        let epoch_id = header.epoch_id();
+        let mut missed = vec![];
+        let first_height =
+            (prev_height + 1).max(header.height().saturating_sub(MAX_SKIPPED_HEIGHTS_CHECKED));
+        for height in first_height..header.height() {
+            if &self.runtime_adapter.get_block_producer(epoch_id, height)? == me {
+                missed.push((height, None));
+            }
+        }
+        for (shard_id, included) in header.chunk_mask().iter().enumerate() {
+            let shard_id = shard_id as ShardId;
+            if !included
+                && &self.runtime_adapter.get_chunk_producer(epoch_id, header.height(), shard_id)?
+                    == me
+            {
+                missed.push((header.height(), Some(shard_id)));
+            }
+        }
+        self.production_skips.record(epoch_id, header.height(), missed);
+        Ok(())
+    }
+
+    /// Notes that the validator's block and chunks on top of the block wait for
+    /// its missing chunks.
+    fn note_missing_chunks(&mut self, block_hash: &CryptoHash) -> Result<(), Error> {
+        let me = match &self.validator_signer {
+            Some(signer) => signer.validator_id().clone(),
+            None => return Ok(()),
+        };
+        let next_height = self.chain.get_block_header(block_hash)?.height() + 1;
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(block_hash)?;
+        if self.runtime_adapter.get_block_producer(&epoch_id, next_height)? == me {
+            self.production_skips.note(next_height, None, ProductionSkipReason::MissingChunkParts);
+        }
+        for shard_id in 0..self.runtime_adapter.num_shards(&epoch_id)? {
+            if self.runtime_adapter.get_chunk_producer(&epoch_id, next_height, shard_id)? == me {
+                self.production_skips.note(
+                    next_height,
+                    Some(shard_id),
+                    ProductionSkipReason::MissingChunkParts,
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub fn request_missing_chunks(
         &mut self,
         blocks_missing_chunks: Vec<BlockMissingChunks>,
@@ -1276,6 +1843,7 @@ impl Client {
     ) {
         let now = Clock::instant();
         for BlockMissingChunks { prev_hash, missing_chunks, block_hash } in blocks_missing_chunks {
+            let _ = self.note_missing_chunks(&block_hash);
             for chunk in &missing_chunks {
                 self.chain.blocks_delay_tracker.mark_chunk_requested(
                     &chunk.chunk_hash(),
@@ -1505,7 +2073,24 @@ impl Client {
     }
 
     /// Forwards given transaction to upcoming validators.
-    fn forward_tx(&self, epoch_id: &EpochId, tx: &SignedTransaction) -> Result<(), Error> {
+    /// Forwards the transaction to the chunk producers of the next heights and
+    /// remembers it to rebroadcast it if its shard misses chunks.
+    fn forward_tx(&mut self, epoch_id: &EpochId, tx: &SignedTransaction) -> Result<(), Error> {
+        let shard_id = self.send_tx_to_chunk_producers(epoch_id, tx)?;
+        if self.config.tx_rebroadcast.is_enabled(self.validator_signer.is_some()) {
+            let height = self.chain.head()?.height;
+            self.forwarded_transactions.insert(tx, shard_id, height);
+        }
+        Ok(())
+    }
+
+    /// Sends the transaction to the chunk producers of its shard at the next
+    /// heights, returning the shard.
+    fn send_tx_to_chunk_producers(
+        &self,
+        epoch_id: &EpochId,
+        tx: &SignedTransaction,
+    ) -> Result<ShardId, Error> {
         let shard_id =
             self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id, epoch_id)?;
         let head = self.chain.head()?;
@@ -1549,6 +2134,72 @@ impl Client {
             ));
         }
 
+        Ok(shard_id)
+    }
+
+    /// Forgets the forwarded transactions included in the chunks of the new
+    /// head and sends again the ones whose shard went
+    /// `tx_rebroadcast.missed_heights` heights without a chunk since they were
+    /// sent, to the chunk producers of the heights after it.
+    fn rebroadcast_stuck_transactions(&mut self, block: &Block) -> Result<(), Error> {
+        if !self.config.tx_rebroadcast.is_enabled(self.validator_signer.is_some())
+            || self.sync_status.is_syncing()
+        {
+            return Ok(());
+        }
+        let height = block.header().height();
+        let mut heights_included = vec![];
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() == height {
+                if let Ok(chunk) = self.chain.get_chunk(&chunk_header.chunk_hash()) {
+                    self.forwarded_transactions.remove_included(chunk.transactions());
+                }
+            }
+            heights_included.push(chunk_header.height_included());
+        }
+        let stuck = self.forwarded_transactions.take_stuck(
+            height,
+            &heights_included,
+            &self.config.tx_rebroadcast,
+            self.chain.transaction_validity_period,
+        );
+        if stuck.is_empty() {
+            return Ok(());
+        }
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(block.hash())?;
+        for tx in stuck {
+            debug!(target: "client", tx_hash = %tx.get_hash(), height, "Rebroadcasting a transaction stuck by missed chunks");
+            self.send_tx_to_chunk_producers(&epoch_id, &tx)?;
+        }
+        Ok(())
+    }
+
+    /// Forgets the submitted transactions included in the chunks of the new
+    /// head and marks as expired the ones which can't be included after it.
+    fn expire_submitted_transactions(&mut self, block: &Block) {
+        let height = block.header().height();
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() == height {
+                if let Ok(chunk) = self.chain.get_chunk(&chunk_header.chunk_hash()) {
+                    self.transaction_expiry
+                        .included(chunk.transactions().iter().map(|tx| tx.get_hash()));
+                }
+            }
+        }
+        self.transaction_expiry.expire(height, Clock::instant());
+    }
+
+    /// Remembers a transaction submitted to this node, of a shard it tracks,
+    /// to tell whether it expired if it's never seen in a chunk.
+    fn record_submitted_tx(&mut self, tx: &SignedTransaction) -> Result<(), Error> {
+        let valid_from_height = self.chain.get_block_header(&tx.transaction.block_hash)?.height();
+        self.transaction_expiry.submitted(
+            tx.get_hash(),
+            ValidityWindow {
+                valid_from_height,
+                valid_until_height: valid_from_height + self.chain.transaction_validity_period,
+            },
+        );
         Ok(())
     }
 
@@ -1650,6 +2301,7 @@ impl Client {
                         );
                     } else {
                         self.forward_tx(&epoch_id, tx)?;
+                        self.record_submitted_tx(tx)?;
                         return Ok(NetworkClientResponses::RequestRouted);
                     }
                 }
@@ -1676,16 +2328,25 @@ impl Client {
                 if active_validator {
                     trace!(target: "client", account = ?me, shard_id, is_forwarded, "Recording a transaction.");
                     metrics::TRANSACTION_RECEIVED_VALIDATOR.inc();
-                    self.shards_mgr.insert_transaction(shard_id, tx.clone());
+                    if self.shards_mgr.insert_transaction(shard_id, tx.clone())
+                        == InsertTransactionResult::NoSpaceLeft
+                    {
+                        debug!(target: "client", shard_id, "Transaction pool is full, rejecting the transaction.");
+                        return Ok(NetworkClientResponses::TxPoolFull {
+                            limits: self.config.transaction_pool.clone(),
+                        });
+                    }
 
                     if !is_forwarded {
                         self.possibly_forward_tx_to_next_epoch(tx)?;
+                        self.record_submitted_tx(tx)?;
                     }
                     Ok(NetworkClientResponses::ValidTx)
                 } else if !is_forwarded {
                     trace!(target: "client", shard_id, "Forwarding a transaction.");
                     metrics::TRANSACTION_RECEIVED_NON_VALIDATOR.inc();
                     self.forward_tx(&epoch_id, tx)?;
+                    self.record_submitted_tx(tx)?;
                     Ok(NetworkClientResponses::RequestRouted)
                 } else {
                     trace!(target: "client", shard_id, "Non-validator received a forwarded transaction, dropping it.");
@@ -1779,11 +2440,12 @@ impl Client {
                 }
             };
             let state_sync_timeout = self.config.state_sync_timeout;
+            let state_sync_sources = self.config.state_sync_sources.clone();
             let epoch_id = self.chain.get_block(&sync_hash)?.header().epoch_id().clone();
             let (state_sync, new_shard_sync, blocks_catch_up_state) =
                 self.catchup_state_syncs.entry(sync_hash).or_insert_with(|| {
                     (
-                        StateSync::new(network_adapter1, state_sync_timeout),
+                        StateSync::new(network_adapter1, state_sync_timeout, state_sync_sources),
                         new_shard_sync,
                         BlocksCatchUpState::new(sync_hash, epoch_id),
                     )
@@ -2062,6 +2724,7 @@ impl Client {
             num_of_chunks_in_progress: self.chain.blocks_delay_tracker.chunks_in_progress.len(),
             num_of_orphans: self.chain.orphans().len(),
             next_blocks_by_chunks,
+            chunk_distribution: self.shards_mgr.recent_chunk_distribution(),
         }
     }
 }
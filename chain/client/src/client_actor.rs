@@ -1,32 +1,41 @@
 //! Client actor orchestrates Client and facilitates network connection.
 
 use crate::client::{Client, EPOCH_START_INFO_BLOCKS};
+use crate::disk_space::is_disk_space_warning;
 use crate::info::{
     display_sync_status, get_validator_epoch_stats, InfoHelper, ValidatorInfoHelper,
 };
 use crate::metrics::PARTIAL_ENCODED_CHUNK_RESPONSE_DELAY;
+use crate::state_change_subscriptions::{StateChangeSubscriptions, SubscribeStateChanges};
 use crate::sync::{StateSync, StateSyncResult};
-use crate::{metrics, StatusResponse};
-use actix::dev::SendError;
+use crate::sync_history::{SyncHistory, SyncObservation, STATUS_HISTORY_SIZE};
+use crate::{metrics, rocksdb_metrics, shutdown_report, StatusResponse};
+use actix::dev::{MessageResponse, SendError};
 use actix::{Actor, Addr, Arbiter, AsyncContext, Context, Handler, Message};
 use actix_rt::ArbiterHandle;
 use borsh::BorshSerialize;
 use chrono::DateTime;
+use near_chain::apply_stage_times::ApplyChunkStageTimes;
+use near_chain::block_processing_log::read_block_processing_log;
 use near_chain::chain::{
     do_apply_chunks, ApplyStatePartsRequest, ApplyStatePartsResponse, BlockCatchUpRequest,
     BlockCatchUpResponse, ChainAccess, StateSplitRequest, StateSplitResponse,
 };
 use near_chain::crypto_hash_timer::CryptoHashTimer;
+use near_chain::misbehavior::read_evidence;
 use near_chain::test_utils::format_hash;
 use near_chain::types::{AcceptedBlock, ValidatorInfoIdentifier};
 use near_chain::{
     byzantine_assert, near_chain_primitives, Block, BlockHeader, ChainGenesis, ChainStoreAccess,
     Provenance, RuntimeAdapter,
 };
-use near_chain_configs::ClientConfig;
+use near_chain_configs::{ClientConfig, StateSyncSource};
 use near_client_primitives::types::{
-    Error, GetNetworkInfo, NetworkInfoResponse, ShardSyncDownload, ShardSyncStatus, Status,
-    StatusError, StatusSyncInfo, SyncStatus,
+    Error, ExpiredTransaction, GetChunkApplyStats, GetDroppedTransaction, GetExpiredTransaction,
+    GetForks, GetMisbehaviorEvidence, GetNetworkInfo, GetProducedChunks, GetProductionSkips,
+    GetSyncHistory, GetTransactionPoolWait, NetworkInfoResponse, ReloadValidatorKey,
+    ReloadValidatorKeyError, ReloadValidatorKeyResponse, ShardSyncDownload, ShardSyncStatus,
+    Status, StatusError, StatusSyncInfo, SyncStatus, WithRequestId,
 };
 use near_network::types::{
     NetworkClientMessages, NetworkClientResponses, NetworkInfo, NetworkRequests,
@@ -35,6 +44,7 @@ use near_network::types::{
 use near_network_primitives::types::ReasonForBan;
 use near_performance_metrics;
 use near_performance_metrics_macros::{perf, perf_with_debug};
+use near_primitives::block::Tip;
 use near_primitives::block_header::ApprovalType;
 use near_primitives::epoch_manager::RngSeed;
 use near_primitives::hash::CryptoHash;
@@ -42,13 +52,15 @@ use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::state_part::PartId;
 use near_primitives::syncing::StatePartKey;
 use near_primitives::time::{Clock, Utc};
-use near_primitives::types::BlockHeight;
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
 use near_primitives::unwrap_or_return;
 use near_primitives::utils::{from_timestamp, MaybeValidated};
-use near_primitives::validator_signer::ValidatorSigner;
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::{
-    DebugBlockStatus, DebugChunkStatus, DetailedDebugStatus, EpochInfoView, ValidatorInfo,
+    ChunkApplyStageTimesView, DebugBlockStatus, DebugChunkStatus, DetailedDebugStatus,
+    DroppedTransactionView, EpochInfoView, EquivocationView, ForkView, ProducedChunkView,
+    ProductionSkipReason, ProductionSkipView, SyncTransitionView, TrackedShardsView, ValidatorInfo,
 };
 use near_store::DBCol;
 use near_telemetry::TelemetryActor;
@@ -82,8 +94,13 @@ pub struct ClientActor {
     node_id: PeerId,
     /// Last time we announced our accounts as validators.
     last_validator_announce_time: Option<Instant>,
+    /// Validator key loaded by `ReloadValidatorKey` and the head height at
+    /// which the node switches to it.
+    pending_validator_signer: Option<(BlockHeight, Arc<dyn ValidatorSigner>)>,
     /// Info helper.
     info_helper: InfoHelper,
+    /// Tests subscribed to the state changes of accounts.
+    state_change_subscriptions: StateChangeSubscriptions,
 
     /// Last time handle_block_production method was called
     block_production_next_attempt: DateTime<Utc>,
@@ -95,6 +112,8 @@ pub struct ClientActor {
     doomslug_timer_next_attempt: DateTime<Utc>,
     chunk_request_retry_next_attempt: DateTime<Utc>,
     sync_started: bool,
+    /// Recent transitions between sync states.
+    sync_history: SyncHistory,
     state_parts_task_scheduler: Box<dyn Fn(ApplyStatePartsRequest)>,
     block_catch_up_scheduler: Box<dyn Fn(BlockCatchUpRequest)>,
     state_split_scheduler: Box<dyn Fn(StateSplitRequest)>,
@@ -166,6 +185,7 @@ impl ClientActor {
             enable_doomslug,
             rng_seed,
         )?;
+        let sync_history = SyncHistory::new(&client.sync_status);
 
         let now = Utc::now();
         Ok(ClientActor {
@@ -174,6 +194,7 @@ impl ClientActor {
             client,
             network_adapter,
             node_id,
+            state_change_subscriptions: StateChangeSubscriptions::default(),
             network_info: NetworkInfo {
                 connected_peers: vec![],
                 num_connected_peers: 0,
@@ -183,8 +204,15 @@ impl ClientActor {
                 sent_bytes_per_sec: 0,
                 known_producers: vec![],
                 peer_counter: 0,
+                clock_skew_millis: None,
+                dns_seed_peers: Default::default(),
+                recent_handshake_refusals: Default::default(),
+                pinned_peers: Default::default(),
+                blacklist_audit: Default::default(),
+                discounted_shards: Default::default(),
             },
             last_validator_announce_time: None,
+            pending_validator_signer: None,
             info_helper,
             block_production_next_attempt: now,
             log_summary_timer_next_attempt: now,
@@ -192,6 +220,7 @@ impl ClientActor {
             doomslug_timer_next_attempt: now,
             chunk_request_retry_next_attempt: now,
             sync_started: false,
+            sync_history,
             state_parts_task_scheduler: create_sync_job_scheduler::<ApplyStatePartsRequest>(
                 sync_jobs_actor_addr.clone(),
             ),
@@ -247,6 +276,10 @@ impl Actor for ClientActor {
 
         // Start catchup job.
         self.catchup(ctx);
+
+        if self.client.config.enable_statistics_export {
+            self.export_column_properties(ctx);
+        }
     }
 }
 
@@ -385,7 +418,11 @@ impl ClientActor {
                     near_network_primitives::types::NetworkSandboxMessage::SandboxPatchStateStatus => {
                         NetworkClientResponses::SandboxResult(
                             near_network_primitives::types::SandboxResponse::SandboxPatchStateFinished(
-                                !self.client.chain.patch_state_in_progress(),
+                                if self.client.chain.patch_state_in_progress() {
+                                    None
+                                } else {
+                                    self.client.chain.last_patched_state_root()
+                                },
                             ),
                         )
                     }
@@ -566,8 +603,10 @@ impl ClientActor {
                                         &data,
                                     ) {
                                         Ok(()) => {
-                                            shard_sync_download.downloads[part_id as usize].done =
-                                                true;
+                                            let download = &mut shard_sync_download.downloads
+                                                [part_id as usize];
+                                            download.done = true;
+                                            download.source = Some(StateSyncSource::Peers.name());
                                         }
                                         Err(err) => {
                                             error!(target: "sync", "State sync set_state_part error, shard = {}, part = {}, hash = {}: {:?}", shard_id, part_id, hash, err);
@@ -714,6 +753,56 @@ impl ClientActor {
                 .get_epoch_protocol_version(&head.next_epoch_id)?,
         })
     }
+
+    /// Shards the node is configured to track, resolved under the shard
+    /// layouts of the current and the next epoch.
+    fn tracked_shards_view(
+        &self,
+        head: &Tip,
+    ) -> Result<Option<TrackedShardsView>, near_chain::Error> {
+        let config = &self.client.config;
+        if config.tracked_accounts.is_empty() && config.tracked_shards.is_empty() {
+            return Ok(None);
+        }
+        let resolve = |epoch_id: &EpochId| -> Result<Vec<ShardId>, near_chain::Error> {
+            let shard_layout = self.client.runtime_adapter.get_shard_layout(epoch_id)?;
+            // Tracking any shard id means tracking all of them.
+            let mut shard_ids: Vec<ShardId> = if config.tracked_shards.is_empty() {
+                config
+                    .tracked_accounts
+                    .iter()
+                    .flat_map(|pattern| pattern.shard_ids(&shard_layout))
+                    .collect()
+            } else {
+                (0..shard_layout.num_shards()).collect()
+            };
+            shard_ids.sort();
+            shard_ids.dedup();
+            Ok(shard_ids)
+        };
+        Ok(Some(TrackedShardsView {
+            tracked_accounts: config.tracked_accounts.clone(),
+            tracked_shards: config.tracked_shards.clone(),
+            shard_ids: resolve(&head.epoch_id)?,
+            next_epoch_shard_ids: resolve(&head.next_epoch_id)?,
+        }))
+    }
+}
+
+impl<M> Handler<WithRequestId<M>> for ClientActor
+where
+    M: Message,
+    Self: Handler<M>,
+    <Self as Handler<M>>::Result: MessageResponse<Self, WithRequestId<M>>,
+{
+    type Result = <Self as Handler<M>>::Result;
+
+    fn handle(&mut self, msg: WithRequestId<M>, ctx: &mut Context<Self>) -> Self::Result {
+        let _span =
+            tracing::info_span!(target: "client", "rpc_request", request_id = %msg.request_id)
+                .entered();
+        <Self as Handler<M>>::handle(self, msg.msg, ctx)
+    }
 }
 
 impl Handler<Status> for ClientActor {
@@ -767,6 +856,7 @@ impl Handler<Status> for ClientActor {
 
         let validator_account_id =
             self.client.validator_signer.as_ref().map(|vs| vs.validator_id()).cloned();
+        let validator_public_key = self.client.validator_signer.as_ref().map(|vs| vs.public_key());
 
         let mut earliest_block_hash = None;
         let mut earliest_block_height = None;
@@ -843,6 +933,11 @@ impl Handler<Status> for ClientActor {
                         gas_used: chunk.gas_used(),
                         processing_time_ms: CryptoHashTimer::get_timer_value(chunk.chunk_hash().0)
                             .map(|s| s.as_millis() as u64),
+                        apply_stage_times: ApplyChunkStageTimes::get(
+                            block.hash(),
+                            chunk.shard_id(),
+                        )
+                        .map(Into::into),
                     })
                     .collect();
 
@@ -899,11 +994,17 @@ impl Handler<Status> for ClientActor {
                 current_head_status: head.clone().into(),
                 current_header_head_status: self.client.chain.header_head()?.clone().into(),
                 orphans: self.client.chain.orphans().list_orphans_by_height(),
+                orphans_bytes: self.client.chain.orphans().bytes() as u64,
                 blocks_with_missing_chunks: self
                     .client
                     .chain
                     .blocks_with_missing_chunks
-                    .list_blocks_by_height(),
+                    .list_missing_chunks_by_height(),
+                blocks_with_missing_chunks_bytes: self
+                    .client
+                    .chain
+                    .blocks_with_missing_chunks
+                    .bytes() as u64,
                 epochs_info,
                 block_production_delay_millis: self
                     .client
@@ -911,6 +1012,19 @@ impl Handler<Status> for ClientActor {
                     .min_block_production_delay
                     .as_millis() as u64,
                 chunk_info: self.client.detailed_upcoming_blocks_info_as_web(),
+                dropped_transactions: self.client.dropped_transactions.recent(),
+                recent_block_processing: read_block_processing_log(
+                    self.client.chain.store().owned_store(),
+                )
+                .unwrap_or_else(|err| {
+                    warn!(target: "client", ?err, "Failed to read the block processing log");
+                    vec![]
+                }),
+                shadow_production: self
+                    .client
+                    .shadow_production
+                    .as_ref()
+                    .and_then(|shadow| shadow.view(&self.network_info.known_producers)),
             })
         } else {
             None
@@ -935,11 +1049,146 @@ impl Handler<Status> for ClientActor {
                 epoch_start_height: Some(epoch_start_height),
             },
             validator_account_id,
+            validator_public_key,
+            clock_skew_millis: self.network_info.clock_skew_millis,
+            sync_transitions: self.sync_history.recent(STATUS_HISTORY_SIZE),
+            tracked_shards: self.tracked_shards_view(&head)?,
             detailed_debug_status,
         })
     }
 }
 
+impl Handler<ReloadValidatorKey> for ClientActor {
+    type Result = Result<ReloadValidatorKeyResponse, ReloadValidatorKeyError>;
+
+    #[perf]
+    fn handle(&mut self, _msg: ReloadValidatorKey, _ctx: &mut Context<Self>) -> Self::Result {
+        let _span = tracing::debug_span!(target: "client", "handle", handler="ReloadValidatorKey")
+            .entered();
+        let (account_id, path) =
+            match (&self.client.validator_signer, &self.client.config.validator_key_file) {
+                (Some(vs), Some(path)) => (vs.validator_id().clone(), path.clone()),
+                _ => return Err(ReloadValidatorKeyError::NotAValidator),
+            };
+        let signer = InMemoryValidatorSigner::from_file(&path).map_err(|err| {
+            ReloadValidatorKeyError::ReadKey {
+                path: path.display().to_string(),
+                error_message: err.to_string(),
+            }
+        })?;
+        if signer.validator_id() != &account_id {
+            return Err(ReloadValidatorKeyError::AccountIdMismatch {
+                account_id,
+                new_account_id: signer.validator_id().clone(),
+            });
+        }
+        // Approvals up to the largest target height may already be out, keep
+        // signing with the old key until the chain gets past them.
+        let head = self.client.chain.head()?;
+        let switch_height =
+            std::cmp::max(head.height + 1, self.client.doomslug.get_largest_target_height());
+        let public_key = signer.public_key();
+        info!(
+            target: "client",
+            %account_id,
+            %public_key,
+            switch_height,
+            "Loaded new validator key"
+        );
+        self.pending_validator_signer = Some((switch_height, Arc::new(signer)));
+        self.maybe_switch_validator_signer();
+        Ok(ReloadValidatorKeyResponse { public_key, switch_height })
+    }
+}
+
+impl Handler<GetDroppedTransaction> for ClientActor {
+    type Result = Option<DroppedTransactionView>;
+
+    fn handle(&mut self, msg: GetDroppedTransaction, _ctx: &mut Context<Self>) -> Self::Result {
+        self.client.dropped_transactions.get(&msg.tx_hash).cloned()
+    }
+}
+
+impl Handler<GetExpiredTransaction> for ClientActor {
+    type Result = Option<ExpiredTransaction>;
+
+    fn handle(&mut self, msg: GetExpiredTransaction, _ctx: &mut Context<Self>) -> Self::Result {
+        self.client.transaction_expiry.get_expired(&msg.tx_hash).map(|window| ExpiredTransaction {
+            valid_from_height: window.valid_from_height,
+            valid_until_height: window.valid_until_height,
+        })
+    }
+}
+
+impl Handler<GetTransactionPoolWait> for ClientActor {
+    type Result = Option<Duration>;
+
+    fn handle(&mut self, msg: GetTransactionPoolWait, _ctx: &mut Context<Self>) -> Self::Result {
+        self.client.pool_waits.peek(&msg.tx_hash).copied()
+    }
+}
+
+impl Handler<SubscribeStateChanges> for ClientActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeStateChanges, _ctx: &mut Context<Self>) {
+        self.state_change_subscriptions.subscribe(msg);
+    }
+}
+
+impl Handler<GetSyncHistory> for ClientActor {
+    type Result = Vec<SyncTransitionView>;
+
+    fn handle(&mut self, _msg: GetSyncHistory, _ctx: &mut Context<Self>) -> Self::Result {
+        self.sync_history.recent(usize::MAX)
+    }
+}
+
+impl Handler<GetForks> for ClientActor {
+    type Result = Vec<ForkView>;
+
+    fn handle(&mut self, _msg: GetForks, _ctx: &mut Context<Self>) -> Self::Result {
+        let client = &mut self.client;
+        client.known_forks.forks(|height, block_hash| {
+            client.chain.get_block_hash_by_height(height).map_or(false, |hash| &hash == block_hash)
+        })
+    }
+}
+
+impl Handler<GetMisbehaviorEvidence> for ClientActor {
+    type Result = Result<Vec<EquivocationView>, String>;
+
+    fn handle(&mut self, _msg: GetMisbehaviorEvidence, _ctx: &mut Context<Self>) -> Self::Result {
+        let store = self.client.chain.store().store();
+        let evidence = read_evidence(store, 0, BlockHeight::MAX).map_err(|err| err.to_string())?;
+        Ok(evidence.iter().map(EquivocationView::from).collect())
+    }
+}
+
+impl Handler<GetProductionSkips> for ClientActor {
+    type Result = Vec<ProductionSkipView>;
+
+    fn handle(&mut self, _msg: GetProductionSkips, _ctx: &mut Context<Self>) -> Self::Result {
+        self.client.production_skips.skips()
+    }
+}
+
+impl Handler<GetProducedChunks> for ClientActor {
+    type Result = Vec<ProducedChunkView>;
+
+    fn handle(&mut self, _msg: GetProducedChunks, _ctx: &mut Context<Self>) -> Self::Result {
+        self.client.produced_chunks.chunks()
+    }
+}
+
+impl Handler<GetChunkApplyStats> for ClientActor {
+    type Result = Option<ChunkApplyStageTimesView>;
+
+    fn handle(&mut self, msg: GetChunkApplyStats, _ctx: &mut Context<Self>) -> Self::Result {
+        ApplyChunkStageTimes::get(&msg.block_hash, msg.shard_id).map(Into::into)
+    }
+}
+
 impl Handler<GetNetworkInfo> for ClientActor {
     type Result = Result<NetworkInfoResponse, String>;
 
@@ -1021,6 +1270,33 @@ impl ClientActor {
         }
     }
 
+    /// Switches to the validator key loaded by `ReloadValidatorKey` once the
+    /// head reaches the switch height.
+    fn maybe_switch_validator_signer(&mut self) {
+        let switch_height = match &self.pending_validator_signer {
+            Some((switch_height, _)) => *switch_height,
+            None => return,
+        };
+        let head = unwrap_or_return!(self.client.chain.head());
+        if head.height < switch_height {
+            return;
+        }
+        let (_, signer) = self.pending_validator_signer.take().unwrap();
+        info!(
+            target: "client",
+            account_id = %signer.validator_id(),
+            old_public_key = ?self.client.validator_signer.as_ref().map(|vs| vs.public_key()),
+            new_public_key = %signer.public_key(),
+            height = head.height,
+            "Switching validator key"
+        );
+        self.client.validator_signer = Some(signer.clone());
+        self.client.doomslug.set_signer(Some(signer.clone()));
+        self.info_helper.set_validator_signer(Some(signer));
+        // Announce the account again so that it is signed with the new key.
+        self.last_validator_announce_time = None;
+    }
+
     /// Process the sandbox fast forward request. If the change in block height is past an epoch,
     /// we fast forward to just right before the epoch, produce some blocks to get past and into
     /// a new epoch, then we continue on with the residual amount to fast forward.
@@ -1029,10 +1305,18 @@ impl ClientActor {
         &mut self,
         block_height: BlockHeight,
     ) -> Result<Option<near_chain::types::LatestKnown>, Error> {
-        let mut delta_height = std::mem::replace(&mut self.fastforward_delta, 0);
-        if delta_height == 0 {
+        if self.fastforward_delta == 0 {
             return Ok(None);
         }
+        // Pending state patches are applied by the next block, before any
+        // heights are skipped, so that patching and then fast forwarding
+        // behaves the same however quickly the two requests follow each other.
+        // Like waiting for the epoch boundary below, the block counts towards
+        // the requested delta.
+        if self.client.chain.patch_state_in_progress() {
+            return Ok(None);
+        }
+        let mut delta_height = std::mem::replace(&mut self.fastforward_delta, 0);
 
         let epoch_length = self.client.config.epoch_length;
         if epoch_length <= 3 {
@@ -1154,9 +1438,17 @@ impl ClientActor {
                     if let Err(err) = self.produce_block(height) {
                         // If there is an error, report it and let it retry on the next loop step.
                         error!(target: "client", "Block production failed: {}", err);
+                        self.client.production_skips.note_error(height, None, &err);
                     } else {
                         self.post_block_production();
                     }
+                } else {
+                    let reason = if have_all_chunks {
+                        ProductionSkipReason::NotEnoughApprovals
+                    } else {
+                        ProductionSkipReason::MissingChunkParts
+                    };
+                    self.client.production_skips.note(height, None, reason);
                 }
             }
         }
@@ -1184,6 +1476,7 @@ impl ClientActor {
         let now = Utc::now();
 
         let timer = metrics::CHECK_TRIGGERS_TIME.start_timer();
+        self.maybe_switch_validator_signer();
         if self.sync_started {
             self.doomslug_timer_next_attempt = self.run_timer(
                 self.client.config.doosmslug_step_period,
@@ -1357,6 +1650,22 @@ impl ClientActor {
             }
 
             self.info_helper.block_processed(gas_used, chunks_in_block as u64);
+            if !self.state_change_subscriptions.is_empty() {
+                let height = block.header().height();
+                match self
+                    .client
+                    .chain
+                    .store()
+                    .get_state_changes_with_cause_in_block(&accepted_block.hash)
+                {
+                    Ok(changes) => {
+                        self.state_change_subscriptions.notify(accepted_block.hash, height, changes)
+                    }
+                    Err(err) => {
+                        warn!(target: "client", ?err, "Can't read the state changes of the block")
+                    }
+                }
+            }
             self.check_send_announce_account(last_final_hash);
         }
     }
@@ -1418,8 +1727,19 @@ impl ClientActor {
                 }
             }
         }
+        let start = Clock::instant();
         let (accepted_blocks, result) = self.client.process_block(block, provenance);
+        let accepted = !accepted_blocks.is_empty();
         self.process_accepted_blocks(accepted_blocks);
+        if accepted {
+            self.client
+                .gc_pacing
+                .observe_block_processing(start.elapsed(), is_disk_space_warning());
+        }
+        shutdown_report::record_heads(
+            self.client.chain.head().ok().as_ref(),
+            self.client.chain.final_head().ok().as_ref(),
+        );
         result.map(|_| ())
     }
 
@@ -1519,8 +1839,9 @@ impl ClientActor {
     }
 
     /// Check whether need to (continue) sync.
-    /// Also return higher height with known peers at that height.
-    fn syncing_info(&self) -> Result<(bool, u64), near_chain::Error> {
+    /// Also return higher height with known peers at that height and the peer
+    /// which claimed it.
+    fn syncing_info(&self) -> Result<(bool, u64, Option<PeerId>), near_chain::Error> {
         let head = self.client.chain.head()?;
         let mut is_syncing = self.client.sync_status.is_syncing();
 
@@ -1532,7 +1853,7 @@ impl ClientActor {
             if !self.client.config.skip_sync_wait {
                 warn!(target: "client", "Sync: no peers available, disabling sync");
             }
-            return Ok((false, 0));
+            return Ok((false, 0, None));
         };
 
         if is_syncing {
@@ -1557,7 +1878,22 @@ impl ClientActor {
                 is_syncing = true;
             }
         }
-        Ok((is_syncing, full_peer_info.chain_info.height))
+        Ok((
+            is_syncing,
+            full_peer_info.chain_info.height,
+            Some(full_peer_info.peer_info.id.clone()),
+        ))
+    }
+
+    /// Records the transition to the current sync state if it changed.
+    fn record_sync_transition(&mut self, highest_height: BlockHeight, peer_id: Option<&PeerId>) {
+        let observation = SyncObservation {
+            head_height: self.client.chain.head().map_or(0, |head| head.height),
+            header_head_height: self.client.chain.header_head().map_or(0, |head| head.height),
+            highest_height,
+            highest_height_peer: peer_id,
+        };
+        self.sync_history.record(&self.client.sync_status, &observation);
     }
 
     fn needs_syncing(&self, needs_syncing: bool) -> bool {
@@ -1699,7 +2035,9 @@ impl ClientActor {
         let mut wait_period = self.client.config.sync_step_period;
 
         let currently_syncing = self.client.sync_status.is_syncing();
-        let (needs_syncing, highest_height) = unwrap_or_run_later!(self.syncing_info());
+        let (needs_syncing, highest_height, highest_height_peer) =
+            unwrap_or_run_later!(self.syncing_info());
+        let highest_height_peer = highest_height_peer.as_ref();
 
         if !self.needs_syncing(needs_syncing) {
             if currently_syncing {
@@ -1709,6 +2047,7 @@ impl ClientActor {
                     self.client.validator_signer.as_ref().map(|vs| vs.validator_id()),
                 );
                 self.client.sync_status = SyncStatus::NoSync;
+                self.record_sync_transition(highest_height, highest_height_peer);
 
                 // Initial transition out of "syncing" state.
                 // Announce this client's account id if their epoch is coming up.
@@ -1724,6 +2063,7 @@ impl ClientActor {
                 highest_height,
                 &self.network_info.highest_height_peers
             ));
+            self.record_sync_transition(highest_height, highest_height_peer);
             // Only body / state sync if header height is close to the latest.
             let header_head = unwrap_or_run_later!(self.client.chain.header_head());
 
@@ -1743,6 +2083,7 @@ impl ClientActor {
                 }
                 _ => false,
             };
+            self.record_sync_transition(highest_height, highest_height_peer);
             if sync_state {
                 let (sync_hash, mut new_shard_sync, just_enter_state_sync) =
                     match &self.client.sync_status {
@@ -1789,6 +2130,7 @@ impl ClientActor {
                     StateSyncResult::Unchanged => (),
                     StateSyncResult::Changed(fetch_block) => {
                         self.client.sync_status = SyncStatus::StateSync(sync_hash, new_shard_sync);
+                        self.record_sync_transition(highest_height, highest_height_peer);
                         if fetch_block {
                             if let Some(peer_info) =
                                 self.network_info.highest_height_peers.choose(&mut thread_rng())
@@ -1835,6 +2177,7 @@ impl ClientActor {
 
                         self.client.sync_status =
                             SyncStatus::BodySync { current_height: 0, highest_height: 0 };
+                        self.record_sync_transition(highest_height, highest_height_peer);
                     }
                 }
             }
@@ -1845,12 +2188,44 @@ impl ClientActor {
         });
     }
 
+    /// Exports the flush and compaction backlog of the store columns on repeat.
+    fn export_column_properties(&mut self, ctx: &mut Context<ClientActor>) {
+        let period = self.client.config.column_properties_export_period;
+        rocksdb_metrics::export_column_properties(self.client.chain.store().store(), period);
+        near_performance_metrics::actix::run_later(ctx, period, move |act, ctx| {
+            act.export_column_properties(ctx);
+        });
+    }
+
     /// Print current summary.
     fn log_summary(&mut self) {
         let _span = tracing::debug_span!(target: "client", "log_summary").entered();
         let _d = delay_detector::DelayDetector::new(|| "client log summary".into());
         let is_syncing = self.client.sync_status.is_syncing();
         let head = unwrap_or_return!(self.client.chain.head());
+        let me = self.client.validator_signer.as_ref().map(|signer| signer.validator_id().clone());
+        let tracked_shards = self
+            .client
+            .runtime_adapter
+            .num_shards(&head.epoch_id)
+            .map(|num_shards| {
+                (0..num_shards)
+                    .filter(|&shard_id| {
+                        self.client.runtime_adapter.cares_about_shard(
+                            me.as_ref(),
+                            &head.last_block_hash,
+                            shard_id,
+                            true,
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        shutdown_report::record_status(
+            self.client.sync_status.as_variant_name(),
+            tracked_shards,
+            self.network_info.num_connected_peers,
+        );
         let validator_info = if !is_syncing {
             let validators = unwrap_or_return!(self
                 .client
@@ -1893,6 +2268,9 @@ impl ClientActor {
                 .map(get_validator_epoch_stats)
                 .unwrap_or_default()
         };
+        if let Some(shadow_production) = &self.client.shadow_production {
+            shadow_production.update_reachability(&self.network_info.known_producers);
+        }
         let statistics = if self.client.config.enable_statistics_export {
             self.client.chain.store().get_store_statistics()
         } else {
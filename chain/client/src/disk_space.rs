@@ -0,0 +1,127 @@
+//! Monitoring of the free space of the disk the database is on.
+//!
+//! Nodes which run out of disk space die in the middle of a write, which can
+//! leave the database corrupted.  The free space is checked periodically and
+//! exported as a metric, and the node degrades in steps as it shrinks: it warns
+//! below the warning threshold, stops serving state parts and state views below
+//! the critical one, and shuts down cleanly below the shutdown one, while the
+//! database can still flush.
+//!
+//! Only the hot database exists, the `store` label of the metric leaves room
+//! for others.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bytesize::ByteSize;
+use near_chain_configs::DiskSpaceMonitorConfig;
+use tracing::{error, info, warn};
+
+use crate::metrics;
+
+/// Set while the free space is below the critical threshold.  It's for the
+/// whole process, there's a single database per process outside of tests.
+static LOW_DISK_SPACE: AtomicBool = AtomicBool::new(false);
+
+/// Set while the free space is below the warning threshold.
+static DISK_SPACE_WARNING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the node should refuse the work which takes disk space and isn't
+/// needed to follow the chain.
+pub(crate) fn is_disk_space_low() -> bool {
+    LOW_DISK_SPACE.load(Ordering::Relaxed)
+}
+
+/// Whether the free space is below the warning threshold, for the garbage
+/// collection to free space faster.
+pub(crate) fn is_disk_space_warning() -> bool {
+    DISK_SPACE_WARNING.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DiskSpaceLevel {
+    Ok,
+    Warning,
+    Critical,
+    Shutdown,
+}
+
+impl DiskSpaceLevel {
+    fn new(free_bytes: u64, config: &DiskSpaceMonitorConfig) -> Self {
+        if free_bytes < config.shutdown_free_bytes {
+            Self::Shutdown
+        } else if free_bytes < config.critical_free_bytes {
+            Self::Critical
+        } else if free_bytes < config.warning_free_bytes {
+            Self::Warning
+        } else {
+            Self::Ok
+        }
+    }
+}
+
+/// Checks the free space of the disk the database at the path is on every
+/// `check_period`, and stops the actix system once it's below the shutdown
+/// threshold unless that's turned off.
+pub fn spawn_disk_space_monitor(path: PathBuf, config: DiskSpaceMonitorConfig) {
+    actix::spawn(async move {
+        let mut level = DiskSpaceLevel::Ok;
+        loop {
+            match fs2::available_space(&path) {
+                Ok(free_bytes) => {
+                    metrics::DISK_FREE_BYTES.with_label_values(&["hot"]).set(free_bytes as i64);
+                    let new_level = DiskSpaceLevel::new(free_bytes, &config);
+                    let free = ByteSize::b(free_bytes);
+                    match new_level {
+                        DiskSpaceLevel::Ok if level != DiskSpaceLevel::Ok => {
+                            info!(target: "client", %free, "Free disk space is back above the warning threshold");
+                        }
+                        DiskSpaceLevel::Ok => {}
+                        DiskSpaceLevel::Warning => {
+                            warn!(target: "client", %free, "Free disk space is running low");
+                        }
+                        DiskSpaceLevel::Critical => {
+                            error!(target: "client", %free, "Free disk space is critically low, not serving state parts and state views");
+                        }
+                        DiskSpaceLevel::Shutdown => {
+                            error!(target: "client", %free, "Free disk space is below the shutdown threshold");
+                        }
+                    }
+                    LOW_DISK_SPACE.store(new_level >= DiskSpaceLevel::Critical, Ordering::Relaxed);
+                    DISK_SPACE_WARNING
+                        .store(new_level >= DiskSpaceLevel::Warning, Ordering::Relaxed);
+                    if new_level == DiskSpaceLevel::Shutdown && config.shutdown_on_low_disk_space {
+                        error!(target: "client", "Shutting down before the disk is full, free some disk space and restart the node");
+                        actix::System::current().stop();
+                        return;
+                    }
+                    level = new_level;
+                }
+                Err(err) => {
+                    warn!(target: "client", ?path, %err, "Can't check the free disk space");
+                }
+            }
+            actix::clock::sleep(config.check_period).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_space_level() {
+        let config = DiskSpaceMonitorConfig {
+            warning_free_bytes: 100,
+            critical_free_bytes: 50,
+            shutdown_free_bytes: 10,
+            ..DiskSpaceMonitorConfig::default()
+        };
+        assert_eq!(DiskSpaceLevel::new(100, &config), DiskSpaceLevel::Ok);
+        assert_eq!(DiskSpaceLevel::new(99, &config), DiskSpaceLevel::Warning);
+        assert_eq!(DiskSpaceLevel::new(49, &config), DiskSpaceLevel::Critical);
+        assert_eq!(DiskSpaceLevel::new(9, &config), DiskSpaceLevel::Shutdown);
+        assert_eq!(DiskSpaceLevel::new(0, &config), DiskSpaceLevel::Shutdown);
+    }
+}
@@ -0,0 +1,148 @@
+//! Bookkeeping of transactions dropped from the pool during chunk production.
+//!
+//! A transaction accepted into the pool can still turn out to be invalid when
+//! the chunk producer gets to it, e.g. because it expired or because an
+//! earlier transaction of the same signer used up the nonce or the balance.
+//! Such transactions are removed from the pool.  Every dropped transaction is
+//! counted by reason and the most recent ones are remembered so that the
+//! debug page and `tx` RPC can explain what happened to them.
+
+use near_primitives::errors::{InvalidAccessKeyError, InvalidTxError};
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::views::DroppedTransactionView;
+use tracing::debug;
+
+use crate::metrics;
+
+pub(crate) struct DroppedTransactions {
+    /// Recently dropped transactions by hash, `None` if keeping them is
+    /// disabled.
+    recent: Option<lru::LruCache<CryptoHash, DroppedTransactionView>>,
+}
+
+impl DroppedTransactions {
+    pub fn new(capacity: usize) -> Self {
+        Self { recent: (capacity > 0).then(|| lru::LruCache::new(capacity)) }
+    }
+
+    pub fn record(
+        &mut self,
+        shard_id: ShardId,
+        height: BlockHeight,
+        transactions: Vec<(SignedTransaction, InvalidTxError)>,
+    ) {
+        for (tx, reason) in transactions {
+            let hash = tx.get_hash();
+            debug!(
+                target: "client",
+                tx_hash = %hash,
+                signer_id = %tx.transaction.signer_id,
+                shard_id,
+                height,
+                %reason,
+                "Dropping invalid transaction from the pool"
+            );
+            metrics::CHUNK_TRANSACTIONS_DROPPED_TOTAL
+                .with_label_values(&[reason_label(&reason)])
+                .inc();
+            if let Some(recent) = &mut self.recent {
+                recent.put(
+                    hash,
+                    DroppedTransactionView {
+                        hash,
+                        signer_id: tx.transaction.signer_id,
+                        nonce: tx.transaction.nonce,
+                        shard_id,
+                        height,
+                        reason,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns why the transaction was dropped if it was dropped recently.
+    pub fn get(&self, tx_hash: &CryptoHash) -> Option<&DroppedTransactionView> {
+        self.recent.as_ref().and_then(|recent| recent.peek(tx_hash))
+    }
+
+    /// Returns the recently dropped transactions, most recent first.
+    pub fn recent(&self) -> Vec<DroppedTransactionView> {
+        self.recent
+            .as_ref()
+            .map(|recent| recent.iter().map(|(_, tx)| tx.clone()).collect())
+            .unwrap_or_default()
+    }
+}
+
+fn reason_label(reason: &InvalidTxError) -> &'static str {
+    match reason {
+        InvalidTxError::InvalidAccessKeyError(InvalidAccessKeyError::NotEnoughAllowance {
+            ..
+        }) => "not_enough_allowance",
+        InvalidTxError::InvalidAccessKeyError(_) => "invalid_access_key",
+        InvalidTxError::InvalidSignerId { .. } => "invalid_signer_id",
+        InvalidTxError::SignerDoesNotExist { .. } => "signer_does_not_exist",
+        InvalidTxError::InvalidNonce { .. } => "invalid_nonce",
+        InvalidTxError::NonceTooLarge { .. } => "nonce_too_large",
+        InvalidTxError::InvalidReceiverId { .. } => "invalid_receiver_id",
+        InvalidTxError::InvalidSignature => "invalid_signature",
+        InvalidTxError::NotEnoughBalance { .. } => "not_enough_balance",
+        InvalidTxError::LackBalanceForState { .. } => "lack_balance_for_state",
+        InvalidTxError::CostOverflow => "cost_overflow",
+        InvalidTxError::InvalidChain => "invalid_chain",
+        InvalidTxError::Expired => "expired",
+        InvalidTxError::ActionsValidation(_) => "actions_validation",
+        InvalidTxError::TransactionSizeExceeded { .. } => "transaction_size_exceeded",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{InMemorySigner, KeyType};
+
+    use super::*;
+
+    fn transaction(nonce: u64) -> SignedTransaction {
+        let signer = InMemorySigner::from_seed("alice".parse().unwrap(), KeyType::ED25519, "alice");
+        SignedTransaction::send_money(
+            nonce,
+            "alice".parse().unwrap(),
+            "bob".parse().unwrap(),
+            &signer,
+            1,
+            CryptoHash::default(),
+        )
+    }
+
+    #[test]
+    fn test_dropped_transactions() {
+        let mut dropped = DroppedTransactions::new(2);
+        let txs: Vec<_> = (1..=3).map(transaction).collect();
+        dropped.record(
+            0,
+            10,
+            txs.iter()
+                .map(|tx| (tx.clone(), InvalidTxError::InvalidNonce { tx_nonce: 1, ak_nonce: 5 }))
+                .collect(),
+        );
+        assert!(dropped.get(&txs[0].get_hash()).is_none());
+        let view = dropped.get(&txs[2].get_hash()).unwrap();
+        assert_eq!(view.nonce, 3);
+        assert_eq!(view.height, 10);
+        assert_eq!(view.reason, InvalidTxError::InvalidNonce { tx_nonce: 1, ak_nonce: 5 });
+        let recent: Vec<_> = dropped.recent().into_iter().map(|tx| tx.nonce).collect();
+        assert_eq!(recent, vec![3, 2]);
+    }
+
+    #[test]
+    fn test_dropped_transactions_disabled() {
+        let mut dropped = DroppedTransactions::new(0);
+        let tx = transaction(1);
+        dropped.record(0, 10, vec![(tx.clone(), InvalidTxError::Expired)]);
+        assert!(dropped.get(&tx.get_hash()).is_none());
+        assert!(dropped.recent().is_empty());
+    }
+}
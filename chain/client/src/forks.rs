@@ -0,0 +1,117 @@
+//! Blocks known at the non-final heights, to tell when there are forks near
+//! the head.
+//!
+//! Every block accepted above the final head is recorded with its producer.
+//! A height getting a second block counts as a fork event, and the heights
+//! with more than one block are listed by `/debug/api/forks`, which is the
+//! first thing to look at for double signing or blocks arriving late.  Heights
+//! are dropped once they're final.
+
+use std::collections::BTreeMap;
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight};
+use near_primitives::views::{ForkBlockView, ForkView};
+use tracing::info;
+
+use crate::metrics;
+
+/// Heights kept at most, in case finality stalls for long.
+const MAX_HEIGHTS: usize = 1000;
+
+pub(crate) struct KnownBlock {
+    pub hash: CryptoHash,
+    pub prev_hash: CryptoHash,
+    pub producer: AccountId,
+}
+
+#[derive(Default)]
+pub(crate) struct KnownForks {
+    blocks: BTreeMap<BlockHeight, Vec<KnownBlock>>,
+}
+
+impl KnownForks {
+    /// Records the block unless it's at a final height or known already.
+    pub fn record(&mut self, height: BlockHeight, final_height: BlockHeight, block: KnownBlock) {
+        self.prune(final_height);
+        if height <= final_height {
+            return;
+        }
+        let blocks = self.blocks.entry(height).or_default();
+        if blocks.iter().any(|known| known.hash == block.hash) {
+            return;
+        }
+        blocks.push(block);
+        if blocks.len() > 1 {
+            let hashes: Vec<_> = blocks.iter().map(|known| known.hash).collect();
+            info!(target: "client", height, ?hashes, "Fork: more than one block at the height");
+            metrics::FORK_EVENTS_TOTAL.inc();
+        }
+        if self.blocks.len() > MAX_HEIGHTS {
+            let lowest = *self.blocks.keys().next().unwrap();
+            self.blocks.remove(&lowest);
+        }
+    }
+
+    /// Drops the heights up to the final one.
+    pub fn prune(&mut self, final_height: BlockHeight) {
+        self.blocks = self.blocks.split_off(&(final_height + 1));
+    }
+
+    /// Heights with more than one block, lowest first, telling which blocks
+    /// are canonical with `is_canonical`.
+    pub fn forks(
+        &self,
+        mut is_canonical: impl FnMut(BlockHeight, &CryptoHash) -> bool,
+    ) -> Vec<ForkView> {
+        self.blocks
+            .iter()
+            .filter(|(_, blocks)| blocks.len() > 1)
+            .map(|(&height, blocks)| ForkView {
+                height,
+                blocks: blocks
+                    .iter()
+                    .map(|block| ForkBlockView {
+                        hash: block.hash,
+                        prev_hash: block.prev_hash,
+                        producer: block.producer.clone(),
+                        canonical: is_canonical(height, &block.hash),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+
+    fn block(seed: &[u8]) -> KnownBlock {
+        KnownBlock { hash: hash(seed), prev_hash: hash(b"prev"), producer: "test".parse().unwrap() }
+    }
+
+    #[test]
+    fn test_known_forks() {
+        let mut forks = KnownForks::default();
+        forks.record(11, 10, block(b"a"));
+        forks.record(11, 10, block(b"a"));
+        forks.record(12, 10, block(b"c"));
+        assert!(forks.forks(|_, _| true).is_empty());
+
+        forks.record(11, 10, block(b"b"));
+        forks.record(12, 10, block(b"d"));
+        // Final already.
+        forks.record(10, 10, block(b"e"));
+        let found = forks.forks(|_, block_hash| *block_hash == hash(b"b"));
+        assert_eq!(found.iter().map(|fork| fork.height).collect::<Vec<_>>(), vec![11, 12]);
+        assert_eq!(
+            found[0].blocks.iter().map(|block| block.canonical).collect::<Vec<_>>(),
+            vec![false, true]
+        );
+
+        forks.prune(11);
+        assert_eq!(forks.forks(|_, _| true)[0].height, 12);
+    }
+}
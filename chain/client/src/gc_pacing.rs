@@ -0,0 +1,178 @@
+//! Pacing of the garbage collection by the load of the node.
+//!
+//! Every block processed garbage collects up to a limit of blocks.  A fixed
+//! limit is either too slow to catch up with the backlog left by a node which
+//! was down for long, or too costly for a busy validator.  The limit starts at
+//! `gc_blocks_limit` and is adjusted every [`WINDOW`] blocks processed from the
+//! 95th percentile of their processing times, garbage collection included:
+//! while the tail is behind by more than the limit and the percentile is well
+//! within `gc_block_processing_budget`, the limit grows by a quarter, up to
+//! `gc_blocks_limit_max`, and when the percentile is over the budget the limit
+//! is halved, down to `gc_blocks_limit`.  Growing in small steps keeps the
+//! processing time from overshooting the budget much before the limit backs
+//! off.  When the disk is low on free space the limit grows until the budget is
+//! used up entirely.
+
+use std::time::Duration;
+
+use near_chain_configs::GCConfig;
+use near_primitives::types::{BlockHeightDelta, NumBlocks};
+
+use crate::metrics;
+
+/// Number of blocks processed between two adjustments of the limit.
+const WINDOW: usize = 20;
+
+pub(crate) struct GCPacing {
+    min_blocks_limit: NumBlocks,
+    max_blocks_limit: NumBlocks,
+    budget: Duration,
+    blocks_limit: NumBlocks,
+    /// Heights between the tail and the height the garbage collection stops
+    /// at, as of the last garbage collection.
+    tail_lag: BlockHeightDelta,
+    /// Processing times of the blocks since the last adjustment.
+    processing_times: Vec<Duration>,
+}
+
+impl GCPacing {
+    pub fn new(config: &GCConfig) -> Self {
+        let min_blocks_limit = config.gc_blocks_limit;
+        let pacing = Self {
+            min_blocks_limit,
+            max_blocks_limit: config.gc_blocks_limit_max.max(min_blocks_limit),
+            budget: config.gc_block_processing_budget,
+            blocks_limit: min_blocks_limit,
+            tail_lag: 0,
+            processing_times: Vec::with_capacity(WINDOW),
+        };
+        metrics::GC_BLOCKS_LIMIT.set(pacing.blocks_limit as i64);
+        pacing
+    }
+
+    /// Maximum number of blocks for the next garbage collection call.
+    pub fn blocks_limit(&self) -> NumBlocks {
+        self.blocks_limit
+    }
+
+    /// Notes how far behind the tail is after a garbage collection call.
+    pub fn set_tail_lag(&mut self, tail_lag: BlockHeightDelta) {
+        self.tail_lag = tail_lag;
+        metrics::GC_TAIL_LAG.set(tail_lag as i64);
+    }
+
+    /// Notes the time a block took to process, including the garbage
+    /// collection after it, and adjusts the limit once a window is full.
+    pub fn observe_block_processing(&mut self, duration: Duration, disk_space_low: bool) {
+        self.processing_times.push(duration);
+        if self.processing_times.len() < WINDOW {
+            return;
+        }
+        self.processing_times.sort();
+        let p95 = self.processing_times[(self.processing_times.len() * 95 - 1) / 100];
+        self.processing_times.clear();
+
+        let headroom = if disk_space_low { self.budget } else { self.budget * 3 / 4 };
+        let blocks_limit = if p95 > self.budget {
+            (self.blocks_limit / 2).max(self.min_blocks_limit)
+        } else if self.tail_lag > self.blocks_limit && p95 < headroom {
+            (self.blocks_limit + (self.blocks_limit / 4).max(1)).min(self.max_blocks_limit)
+        } else {
+            self.blocks_limit
+        };
+        if blocks_limit != self.blocks_limit {
+            tracing::debug!(
+                target: "client",
+                from = self.blocks_limit,
+                to = blocks_limit,
+                ?p95,
+                tail_lag = self.tail_lag,
+                "Changing the garbage collection pace");
+            self.blocks_limit = blocks_limit;
+            metrics::GC_BLOCKS_LIMIT.set(blocks_limit as i64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> GCConfig {
+        GCConfig {
+            gc_blocks_limit: 2,
+            gc_blocks_limit_max: 100,
+            gc_block_processing_budget: Duration::from_millis(500),
+            ..GCConfig::default()
+        }
+    }
+
+    /// Processes blocks which take 100ms plus 10ms per block garbage
+    /// collected, with a backlog of `backlog` heights, and returns the
+    /// processing times.
+    fn simulate(pacing: &mut GCPacing, backlog: u64, blocks: usize) -> Vec<Duration> {
+        let mut tail_lag = backlog;
+        let mut times = vec![];
+        for _ in 0..blocks {
+            // Every block moves the height the collection stops at by one.
+            tail_lag += 1;
+            let collected = tail_lag.min(pacing.blocks_limit());
+            tail_lag -= collected;
+            pacing.set_tail_lag(tail_lag);
+            let time = Duration::from_millis(100 + 10 * collected);
+            pacing.observe_block_processing(time, false);
+            times.push(time);
+        }
+        times
+    }
+
+    fn p95(mut times: Vec<Duration>) -> Duration {
+        times.sort();
+        times[(times.len() * 95 - 1) / 100]
+    }
+
+    #[test]
+    fn test_backlog_converges_within_budget() {
+        let config = config();
+        let mut pacing = GCPacing::new(&config);
+        let times = simulate(&mut pacing, 20_000, 2_000);
+        assert_eq!(pacing.tail_lag, 0);
+        assert!(p95(times.clone()) <= config.gc_block_processing_budget, "{:?}", p95(times));
+        // Caught up much faster than the fixed limit, which clears one
+        // height of the backlog per block.
+        assert!(pacing.blocks_limit() > 2 * config.gc_blocks_limit);
+    }
+
+    #[test]
+    fn test_backs_off_when_slow() {
+        let config = config();
+        let mut pacing = GCPacing::new(&config);
+        simulate(&mut pacing, 20_000, 400);
+        let limit = pacing.blocks_limit();
+        assert!(limit > config.gc_blocks_limit);
+        // The node gets busy, blocks take longer than the budget regardless of
+        // the garbage collection.
+        for _ in 0..WINDOW {
+            pacing.observe_block_processing(Duration::from_secs(1), false);
+        }
+        assert_eq!(pacing.blocks_limit(), (limit / 2).max(config.gc_blocks_limit));
+        for _ in 0..10 * WINDOW {
+            pacing.observe_block_processing(Duration::from_secs(1), false);
+        }
+        assert_eq!(pacing.blocks_limit(), config.gc_blocks_limit);
+    }
+
+    #[test]
+    fn test_disk_space_low_uses_whole_budget() {
+        let mut pacing = GCPacing::new(&config());
+        pacing.set_tail_lag(1_000);
+        for _ in 0..WINDOW {
+            pacing.observe_block_processing(Duration::from_millis(450), false);
+        }
+        assert_eq!(pacing.blocks_limit(), 2);
+        for _ in 0..WINDOW {
+            pacing.observe_block_processing(Duration::from_millis(450), true);
+        }
+        assert_eq!(pacing.blocks_limit(), 3);
+    }
+}
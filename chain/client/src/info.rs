@@ -1,6 +1,7 @@
+use crate::sync::format_part_sources;
 use crate::{metrics, rocksdb_metrics, SyncStatus};
 use actix::Addr;
-use near_chain_configs::{ClientConfig, LogSummaryStyle};
+use near_chain_configs::{ClientConfig, LogSummaryStyle, ValidatorMetricsConfig};
 use near_client_primitives::types::ShardSyncStatus;
 use near_network::types::NetworkInfo;
 use near_primitives::block::Tip;
@@ -10,19 +11,23 @@ use near_primitives::telemetry::{
     TelemetryAgentInfo, TelemetryChainInfo, TelemetryInfo, TelemetrySystemInfo,
 };
 use near_primitives::time::{Clock, Instant};
-use near_primitives::types::{AccountId, BlockHeight, EpochHeight, Gas, NumBlocks, ShardId};
+use near_primitives::types::{
+    AccountId, Balance, BlockHeight, EpochHeight, Gas, NumBlocks, ShardId,
+};
 use near_primitives::validator_signer::ValidatorSigner;
 use near_primitives::version::Version;
 use near_primitives::views::{CurrentEpochValidatorInfo, EpochValidatorInfo, ValidatorKickoutView};
 use near_store::db::StoreStatistics;
 use near_telemetry::{telemetry, TelemetryActor};
 use std::cmp::min;
+use std::collections::HashSet;
 use std::fmt::Write;
 use std::sync::Arc;
 use sysinfo::{get_current_pid, set_open_files_limit, Pid, ProcessExt, System, SystemExt};
 use tracing::info;
 
 const TERAGAS: f64 = 1_000_000_000_000_f64;
+const NEAR_BASE: f64 = 1_000_000_000_000_000_000_000_000_f64;
 
 pub struct ValidatorInfoHelper {
     pub is_validator: bool,
@@ -51,6 +56,10 @@ pub struct InfoHelper {
     telemetry_actor: Addr<TelemetryActor>,
     /// Log coloring enabled
     log_summary_style: LogSummaryStyle,
+    validator_metrics: ValidatorMetricsConfig,
+    /// Validators the per-validator metrics are exported for, if they're
+    /// restricted by `validator_metrics`.
+    exported_validators: HashSet<AccountId>,
 }
 
 impl InfoHelper {
@@ -72,9 +81,15 @@ impl InfoHelper {
             telemetry_actor,
             validator_signer,
             log_summary_style: client_config.log_summary_style,
+            validator_metrics: client_config.validator_metrics.clone(),
+            exported_validators: HashSet::new(),
         }
     }
 
+    pub fn set_validator_signer(&mut self, validator_signer: Option<Arc<dyn ValidatorSigner>>) {
+        self.validator_signer = validator_signer;
+    }
+
     pub fn chunk_processed(&mut self, shard_id: ShardId, gas_used: Gas) {
         metrics::TGAS_USAGE_HIST
             .with_label_values(&[&format!("{}", shard_id)])
@@ -181,24 +196,7 @@ impl InfoHelper {
         (metrics::EPOCH_HEIGHT.set(epoch_height as i64));
         (metrics::PROTOCOL_UPGRADE_BLOCK_HEIGHT.set(protocol_upgrade_block_height as i64));
 
-        // In case we can't get the list of validators for the current and the previous epoch,
-        // skip updating the per-validator metrics.
-        // Note that the metrics are set to 0 for previous epoch validators who are no longer
-        // validators.
-        for stats in validator_epoch_stats {
-            (metrics::VALIDATORS_BLOCKS_PRODUCED
-                .with_label_values(&[stats.account_id.as_str()])
-                .set(stats.num_produced_blocks as i64));
-            (metrics::VALIDATORS_BLOCKS_EXPECTED
-                .with_label_values(&[stats.account_id.as_str()])
-                .set(stats.num_expected_blocks as i64));
-            (metrics::VALIDATORS_CHUNKS_PRODUCED
-                .with_label_values(&[stats.account_id.as_str()])
-                .set(stats.num_produced_chunks as i64));
-            (metrics::VALIDATORS_CHUNKS_EXPECTED
-                .with_label_values(&[stats.account_id.as_str()])
-                .set(stats.num_expected_chunks as i64));
-        }
+        self.export_validator_metrics(validator_epoch_stats);
 
         self.started = Clock::instant();
         self.num_blocks_processed = 0;
@@ -235,6 +233,96 @@ impl InfoHelper {
         };
         telemetry(&self.telemetry_actor, content);
     }
+
+    fn export_validator_metrics(&mut self, validator_epoch_stats: Vec<ValidatorProductionStats>) {
+        // In case we can't get the list of validators for the current and the previous epoch,
+        // skip updating the per-validator metrics.
+        if validator_epoch_stats.is_empty() {
+            return;
+        }
+        let selected = if self.validator_metrics.enabled {
+            let selected = select_validators(
+                &validator_epoch_stats,
+                &self.validator_metrics,
+                &self.exported_validators,
+            );
+            for account_id in self.exported_validators.difference(&selected) {
+                let labels = [account_id.as_str()];
+                for gauge in [
+                    &*metrics::VALIDATORS_BLOCKS_PRODUCED,
+                    &*metrics::VALIDATORS_BLOCKS_EXPECTED,
+                    &*metrics::VALIDATORS_CHUNKS_PRODUCED,
+                    &*metrics::VALIDATORS_CHUNKS_EXPECTED,
+                    &*metrics::VALIDATORS_KICKED_OUT_LAST_EPOCH,
+                ] {
+                    gauge.remove_label_values(&labels).ok();
+                }
+                metrics::VALIDATORS_STAKE.remove_label_values(&labels).ok();
+            }
+            Some(selected)
+        } else {
+            None
+        };
+        // Note that the metrics are set to 0 for previous epoch validators who are no longer
+        // validators.
+        for stats in validator_epoch_stats {
+            if let Some(selected) = &selected {
+                if !selected.contains(&stats.account_id) {
+                    continue;
+                }
+                (metrics::VALIDATORS_STAKE
+                    .with_label_values(&[stats.account_id.as_str()])
+                    .set(stats.stake as f64 / NEAR_BASE));
+                (metrics::VALIDATORS_KICKED_OUT_LAST_EPOCH
+                    .with_label_values(&[stats.account_id.as_str()])
+                    .set(stats.kicked_out as i64));
+            }
+            (metrics::VALIDATORS_BLOCKS_PRODUCED
+                .with_label_values(&[stats.account_id.as_str()])
+                .set(stats.num_produced_blocks as i64));
+            (metrics::VALIDATORS_BLOCKS_EXPECTED
+                .with_label_values(&[stats.account_id.as_str()])
+                .set(stats.num_expected_blocks as i64));
+            (metrics::VALIDATORS_CHUNKS_PRODUCED
+                .with_label_values(&[stats.account_id.as_str()])
+                .set(stats.num_produced_chunks as i64));
+            (metrics::VALIDATORS_CHUNKS_EXPECTED
+                .with_label_values(&[stats.account_id.as_str()])
+                .set(stats.num_expected_chunks as i64));
+        }
+        if let Some(selected) = selected {
+            self.exported_validators = selected;
+        }
+    }
+}
+
+/// Validators whose metrics are exported when they're restricted: the ones
+/// with the most stake and the listed accounts, plus the validators kicked out
+/// at the start of the epoch whose metrics were exported in the previous one.
+fn select_validators(
+    validator_epoch_stats: &[ValidatorProductionStats],
+    config: &ValidatorMetricsConfig,
+    exported: &HashSet<AccountId>,
+) -> HashSet<AccountId> {
+    if config.top_by_stake.is_none() && config.accounts.is_empty() {
+        return validator_epoch_stats.iter().map(|stats| stats.account_id.clone()).collect();
+    }
+    let mut validators: Vec<_> =
+        validator_epoch_stats.iter().filter(|stats| !stats.kicked_out).collect();
+    validators.sort_by(|a, b| b.stake.cmp(&a.stake).then_with(|| a.account_id.cmp(&b.account_id)));
+    let mut selected: HashSet<_> = validators
+        .into_iter()
+        .take(config.top_by_stake.unwrap_or(0))
+        .map(|stats| stats.account_id.clone())
+        .collect();
+    for stats in validator_epoch_stats {
+        if config.accounts.contains(&stats.account_id)
+            || (stats.kicked_out && exported.contains(&stats.account_id))
+        {
+            selected.insert(stats.account_id.clone());
+        }
+    }
+    selected
 }
 
 pub fn display_sync_status(
@@ -286,7 +374,7 @@ pub fn display_sync_status(
             for (shard_id, shard_status) in shard_statuses {
                 write!(
                     res,
-                    "[{}: {}]",
+                    "[{}: {}",
                     shard_id,
                     match shard_status.status {
                         ShardSyncStatus::StateDownloadHeader => "header",
@@ -300,6 +388,11 @@ pub fn display_sync_status(
                     }
                 )
                 .unwrap();
+                let sources = format_part_sources(&shard_status.downloads);
+                if !sources.is_empty() {
+                    write!(res, " ({})", sources).unwrap();
+                }
+                res.push(']');
             }
             res
         }
@@ -357,6 +450,9 @@ pub struct ValidatorProductionStats {
     pub num_expected_blocks: NumBlocks,
     pub num_produced_chunks: NumBlocks,
     pub num_expected_chunks: NumBlocks,
+    pub stake: Balance,
+    /// Kicked out at the start of the epoch, all the other stats are 0 then.
+    pub kicked_out: bool,
 }
 
 impl ValidatorProductionStats {
@@ -367,6 +463,8 @@ impl ValidatorProductionStats {
             num_expected_blocks: 0,
             num_produced_chunks: 0,
             num_expected_chunks: 0,
+            stake: 0,
+            kicked_out: true,
         }
     }
     pub fn validator(info: CurrentEpochValidatorInfo) -> Self {
@@ -376,6 +474,8 @@ impl ValidatorProductionStats {
             num_expected_blocks: info.num_expected_blocks,
             num_produced_chunks: info.num_produced_chunks,
             num_expected_chunks: info.num_expected_chunks,
+            stake: info.stake,
+            kicked_out: false,
         }
     }
 }
@@ -394,3 +494,60 @@ pub fn get_validator_epoch_stats(
     }
     stats
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(account_id: &str, stake: Balance, kicked_out: bool) -> ValidatorProductionStats {
+        ValidatorProductionStats {
+            account_id: account_id.parse().unwrap(),
+            num_produced_blocks: 0,
+            num_expected_blocks: 0,
+            num_produced_chunks: 0,
+            num_expected_chunks: 0,
+            stake,
+            kicked_out,
+        }
+    }
+
+    fn selected(
+        validators: &[ValidatorProductionStats],
+        config: &ValidatorMetricsConfig,
+        exported: &[&str],
+    ) -> Vec<String> {
+        let exported = exported.iter().map(|account_id| account_id.parse().unwrap()).collect();
+        let mut selected: Vec<_> = select_validators(validators, config, &exported)
+            .into_iter()
+            .map(|account_id| account_id.to_string())
+            .collect();
+        selected.sort();
+        selected
+    }
+
+    #[test]
+    fn test_select_validators() {
+        let validators = [
+            stats("alice", 30, false),
+            stats("bob", 10, false),
+            stats("carol", 20, false),
+            stats("dave", 30, false),
+            stats("eve", 0, true),
+        ];
+        let mut config = ValidatorMetricsConfig { enabled: true, ..Default::default() };
+        assert_eq!(selected(&validators, &config, &[]), ["alice", "bob", "carol", "dave", "eve"]);
+
+        config.top_by_stake = Some(2);
+        assert_eq!(selected(&validators, &config, &[]), ["alice", "dave"]);
+        config.accounts = vec!["bob".parse().unwrap()];
+        assert_eq!(selected(&validators, &config, &[]), ["alice", "bob", "dave"]);
+        // The kicked out validator is kept only if it was exported.
+        assert_eq!(
+            selected(&validators, &config, &["eve", "carol"]),
+            ["alice", "bob", "dave", "eve"]
+        );
+
+        config.top_by_stake = None;
+        assert_eq!(selected(&validators, &config, &[]), ["bob"]);
+    }
+}
@@ -1,25 +1,46 @@
 pub use near_client_primitives::types::{
-    Error, GetBlock, GetBlockHash, GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree,
-    GetChunk, GetExecutionOutcome, GetExecutionOutcomeResponse, GetExecutionOutcomesForBlock,
-    GetGasPrice, GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt,
-    GetStateChanges, GetStateChangesInBlock, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfo, GetValidatorOrdered, Query,
-    QueryError, Status, StatusResponse, SyncStatus, TxStatus, TxStatusError,
+    CheckTxBlockHash, CheckTxBlockHashError, Error, GetBlock, GetBlockHash, GetBlockHeaders,
+    GetBlockProof, GetBlockProofResponse, GetBlockWithMerkleTree, GetChunk, GetChunkApplyStats,
+    GetChunkSummaries, GetDroppedTransaction, GetExecutionOutcome, GetExecutionOutcomeResponse,
+    GetExecutionOutcomesForBlock, GetExpiredTransaction, GetForks, GetGasPrice,
+    GetLightClientBlocks, GetLightClientBlocksResponse, GetMisbehaviorEvidence, GetNetworkInfo,
+    GetNextLightClientBlock, GetPendingReceipts, GetProducedChunks, GetProducerVersions,
+    GetProductionSkips, GetProtocolConfig, GetReceipt, GetStateChanges, GetStateChangesInBlock,
+    GetStateChangesWithCauseInBlock, GetStateChangesWithCauseInBlockForTrackedShards,
+    GetStateFingerprint, GetSyncHistory, GetTransactionPoolWait, GetTxConstructionInfo,
+    GetValidatorInfo, GetValidatorOrdered, GetValidatorsPreview, Query, QueryError,
+    ReloadValidatorKey, ReloadValidatorKeyError, ReloadValidatorKeyResponse, Status,
+    StatusResponse, SyncStatus, TxStatus, TxStatusError, ValidateTx, ValidateTxError,
+    WithRequestId,
 };
 
 pub use crate::client::Client;
 pub use crate::client_actor::{start_client, ClientActor};
+pub use crate::disk_space::spawn_disk_space_monitor;
+pub use crate::state_change_subscriptions::{StateChangesUpdate, SubscribeStateChanges};
 #[cfg(feature = "test_features")]
 pub use crate::view_client::AdversarialControls;
 pub use crate::view_client::{start_view_client, ViewClientActor};
 
 mod client;
 mod client_actor;
+mod disk_space;
+mod dropped_transactions;
+mod forks;
+mod gc_pacing;
 mod info;
 mod metrics;
+mod produced_chunks;
+mod production_skips;
 mod rocksdb_metrics;
+mod shadow_production;
+pub mod shutdown_report;
+mod state_change_subscriptions;
 pub mod sync;
+mod sync_history;
 pub mod test_utils;
 #[cfg(test)]
 mod tests;
+mod tx_rebroadcast;
+mod view_cache;
 mod view_client;
@@ -1,7 +1,7 @@
 use near_metrics::{
-    try_create_histogram, try_create_histogram_vec, try_create_int_counter,
-    try_create_int_counter_vec, try_create_int_gauge, Histogram, HistogramVec, IntCounter,
-    IntCounterVec, IntGauge, IntGaugeVec,
+    try_create_gauge, try_create_histogram, try_create_histogram_vec, try_create_int_counter,
+    try_create_int_counter_vec, try_create_int_gauge, Gauge, GaugeVec, Histogram, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 use once_cell::sync::Lazy;
 
@@ -55,6 +55,20 @@ pub static MEMORY_USAGE: Lazy<IntGauge> = Lazy::new(|| {
 pub static GC_TIME: Lazy<Histogram> = Lazy::new(|| {
     try_create_histogram("near_gc_time", "Time taken to do garbage collection").unwrap()
 });
+pub(crate) static GC_BLOCKS_LIMIT: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_gc_blocks_limit",
+        "Maximum number of blocks the next garbage collection call clears, as paced by the load",
+    )
+    .unwrap()
+});
+pub(crate) static GC_TAIL_LAG: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_gc_tail_lag",
+        "Number of heights between the tail and the height the garbage collection stops at",
+    )
+    .unwrap()
+});
 pub static AVG_TGAS_USAGE: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge(
         "near_chunk_tgas_used",
@@ -106,6 +120,23 @@ pub static VALIDATORS_BLOCKS_EXPECTED: Lazy<IntGaugeVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static VALIDATORS_STAKE: Lazy<GaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_gauge_vec(
+        "near_validators_stake",
+        "Stake of a validator in the current epoch, in NEAR",
+        &["account_id"],
+    )
+    .unwrap()
+});
+pub static VALIDATORS_KICKED_OUT_LAST_EPOCH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge_vec(
+        "near_validators_kicked_out_last_epoch",
+        "Whether a validator of the previous epoch was kicked out at the start of the current \
+         one, 0 or 1",
+        &["account_id"],
+    )
+    .unwrap()
+});
 pub static SYNC_STATUS: Lazy<IntGauge> =
     Lazy::new(|| try_create_int_gauge("near_sync_status", "Node sync status").unwrap());
 pub static EPOCH_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
@@ -119,6 +150,15 @@ pub static PROTOCOL_UPGRADE_BLOCK_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static CHUNK_PRIORITY_TRANSACTIONS: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_chunk_priority_transactions",
+        "Number of transactions of the chunk_producer.priority_accounts in the chunks produced by this node",
+        &["shard_id"],
+        Some(vec![0., 1., 2., 5., 10., 20., 50., 100., 200., 500.]),
+    )
+    .unwrap()
+});
 pub static CHUNK_SKIPPED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_chunk_skipped_total",
@@ -203,6 +243,23 @@ pub(crate) static TRANSACTION_RECEIVED_NON_VALIDATOR_FORWARDED: Lazy<IntGauge> =
     )
     .unwrap()
 });
+pub(crate) static TRANSACTIONS_REBROADCAST_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_transactions_rebroadcast_total",
+        "Number of times forwarded transactions were sent again to the chunk producers because \
+         their shard missed chunks",
+    )
+    .unwrap()
+});
+pub(crate) static CHUNK_TRANSACTIONS_DROPPED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_chunk_transactions_dropped_total",
+        "Number of transactions pulled from the pool during chunk production which were dropped \
+         as invalid, by the reason",
+        &["reason"],
+    )
+    .unwrap()
+});
 
 /// Exports neard, protocol and database versions via Prometheus metrics.
 ///
@@ -221,3 +278,146 @@ pub fn export_version(neard_version: &near_primitives::version::Version) {
         ])
         .inc();
 }
+pub static VIEW_CLIENT_QUERY_WAIT_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_view_client_query_wait_time_seconds",
+        "Time queries waited for a view client thread, by query type",
+        &["query_type"],
+        Some(prometheus::exponential_buckets(0.0001, 2.0, 20).unwrap()),
+    )
+    .unwrap()
+});
+pub static VIEW_CLIENT_QUERY_EXECUTION_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_view_client_query_execution_time_seconds",
+        "Time spent executing queries in the view client, by query type",
+        &["query_type"],
+        Some(prometheus::exponential_buckets(0.0001, 2.0, 20).unwrap()),
+    )
+    .unwrap()
+});
+pub static VIEW_CLIENT_QUERY_ABANDONED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_view_client_query_abandoned_total",
+        "Number of queries not executed because their deadline passed while they waited, by query type",
+        &["query_type"],
+    )
+    .unwrap()
+});
+pub(crate) static SYNC_STATUS_TRANSITIONS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_sync_status_transitions_total",
+        "Number of transitions of the node between sync states, by the previous and the new state",
+        &["from", "to"],
+    )
+    .unwrap()
+});
+pub(crate) static FORK_EVENTS_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_fork_events_total",
+        "Number of times a non-final height got a second or further block",
+    )
+    .unwrap()
+});
+pub(crate) static FINAL_HEAD_DISTANCE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_final_head_distance",
+        "Number of heights between the final head and the head",
+    )
+    .unwrap()
+});
+pub(crate) static SHADOW_PRODUCTION_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_shadow_production_time",
+        "Time taken to build and verify a block or chunk in shadow production mode, by kind",
+        &["kind"],
+        Some(prometheus::exponential_buckets(0.001, 2.0, 16).unwrap()),
+    )
+    .unwrap()
+});
+pub(crate) static SHADOW_PRODUCTION_FAILURES_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_shadow_production_failures_total",
+        "Number of blocks or chunks which failed to be built or verified in shadow production \
+         mode, by kind",
+        &["kind"],
+    )
+    .unwrap()
+});
+pub(crate) static SHADOW_PRODUCTION_UNREACHABLE_PRODUCERS: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_shadow_production_unreachable_producers",
+        "Number of block producers of the next epoch the node in shadow production mode has no \
+         route to",
+    )
+    .unwrap()
+});
+pub(crate) static EQUIVOCATION_EVIDENCE_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_equivocation_evidence_total",
+        "Number of pairs of conflicting messages signed by the same validator found since \
+         starting, by kind",
+        &["kind"],
+    )
+    .unwrap()
+});
+pub(crate) static DISK_FREE_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge_vec(
+        "near_disk_free_bytes",
+        "Free space of the disk the database is on, by store",
+        &["store"],
+    )
+    .unwrap()
+});
+pub(crate) static VIEW_CACHE_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_view_cache_hits_total",
+        "Number of block and chunk views served from the cache of the view client, by kind",
+        &["kind"],
+    )
+    .unwrap()
+});
+pub(crate) static VIEW_CACHE_MISSES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_view_cache_misses_total",
+        "Number of block and chunk views not found in the cache of the view client, by kind",
+        &["kind"],
+    )
+    .unwrap()
+});
+pub(crate) static VIEW_CACHE_SIZE_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_view_cache_size_bytes",
+        "Total size of the JSON encoding of the views in the cache of the view client",
+    )
+    .unwrap()
+});
+
+pub(crate) static PRODUCTION_SKIPS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_production_skips_total",
+        "Number of blocks and chunks the validator of this node was assigned and didn't produce, \
+         by the kind and the reason",
+        &["kind", "reason"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PRODUCED_CHUNKS_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_produced_chunks_total",
+        "Number of chunks the validator of this node produced, by whether the canonical chain \
+         included them",
+        &["outcome"],
+    )
+    .unwrap()
+});
+
+pub(crate) static PRODUCED_CHUNKS_INCLUSION_RATIO: Lazy<Gauge> = Lazy::new(|| {
+    try_create_gauge(
+        "near_produced_chunks_inclusion_ratio",
+        "Share of the chunks the validator of this node produced in the current and the previous \
+         epoch which the canonical chain included",
+    )
+    .unwrap()
+});
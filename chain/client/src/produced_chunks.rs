@@ -0,0 +1,187 @@
+//! Bookkeeping of the chunks this node's validator produced, to tell a chunk
+//! whose parts went out late from one the block producer left out.
+//!
+//! A chunk is recorded once its parts are handed to the network, with the
+//! block producers they were sent to.  When a block at or past the height of
+//! the chunk becomes the head, the chunk is included if that block includes
+//! it and left out otherwise: a later block can't include it since its
+//! previous block isn't the one the chunk was built on.  Outcomes are counted
+//! and the chunks of the current and the previous epoch, at most
+//! [`MAX_PRODUCED_CHUNKS`], are kept for the debug endpoint.
+
+use std::collections::{HashSet, VecDeque};
+
+use chrono::DateTime;
+use near_primitives::hash::CryptoHash;
+use near_primitives::time::{Clock, Utc};
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+use near_primitives::views::{ChunkInclusionView, ChunkPartsSentView, ProducedChunkView};
+use tracing::debug;
+
+use crate::metrics;
+
+/// Produced chunks kept at most, however many the epochs have.
+pub(crate) const MAX_PRODUCED_CHUNKS: usize = 1_000;
+
+#[derive(Default)]
+pub(crate) struct ProducedChunks {
+    /// Most recent first.
+    chunks: VecDeque<ProducedChunkView>,
+    /// The epoch of the last produced chunk and the one before it.
+    epochs: [Option<EpochId>; 2],
+}
+
+impl ProducedChunks {
+    /// Records the chunk whose parts were just sent, `parts_sent` being the
+    /// parts of each block producer.
+    pub fn produced(
+        &mut self,
+        chunk_hash: CryptoHash,
+        shard_id: ShardId,
+        height_created: BlockHeight,
+        epoch_id: &EpochId,
+        produced_at: DateTime<Utc>,
+        parts_sent: Vec<(AccountId, Vec<u64>)>,
+    ) {
+        if self.epochs[0].as_ref() != Some(epoch_id) {
+            self.epochs = [Some(epoch_id.clone()), self.epochs[0].take()];
+            let epochs = &self.epochs;
+            self.chunks.retain(|chunk| epochs.contains(&Some(chunk.epoch_id.clone())));
+        }
+        let mut parts_sent: Vec<_> = parts_sent
+            .into_iter()
+            .map(|(account_id, part_ords)| ChunkPartsSentView { account_id, part_ords })
+            .collect();
+        parts_sent.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        self.chunks.push_front(ProducedChunkView {
+            chunk_hash,
+            shard_id,
+            height_created,
+            epoch_id: epoch_id.clone(),
+            produced_at,
+            parts_sent_at: Clock::utc(),
+            parts_sent,
+            inclusion: None,
+        });
+        self.chunks.truncate(MAX_PRODUCED_CHUNKS);
+    }
+
+    /// Settles the chunks up to the height of the new head, `new_chunks`
+    /// being the hashes of the chunks the head includes.
+    pub fn new_head(
+        &mut self,
+        block_hash: &CryptoHash,
+        height: BlockHeight,
+        new_chunks: &HashSet<CryptoHash>,
+    ) {
+        let seen_at = Clock::utc();
+        let mut settled = false;
+        for chunk in self.chunks.iter_mut() {
+            if chunk.inclusion.is_some() || chunk.height_created > height {
+                continue;
+            }
+            let included = new_chunks.contains(&chunk.chunk_hash);
+            let outcome = if included { "included" } else { "not_included" };
+            debug!(
+                target: "client",
+                chunk_hash = %chunk.chunk_hash,
+                shard_id = chunk.shard_id,
+                height_created = chunk.height_created,
+                block_height = height,
+                included,
+                "Produced chunk settled");
+            metrics::PRODUCED_CHUNKS_TOTAL.with_label_values(&[outcome]).inc();
+            chunk.inclusion = Some(ChunkInclusionView {
+                included,
+                block_hash: *block_hash,
+                block_height: height,
+                seen_at,
+            });
+            settled = true;
+        }
+        if settled {
+            let (included, total) = self.inclusion_counts();
+            metrics::PRODUCED_CHUNKS_INCLUSION_RATIO.set(included as f64 / total as f64);
+        }
+    }
+
+    /// Number of the kept chunks included and of those settled.
+    fn inclusion_counts(&self) -> (usize, usize) {
+        self.chunks
+            .iter()
+            .filter_map(|chunk| chunk.inclusion.as_ref())
+            .fold((0, 0), |(included, total), inclusion| {
+                (included + inclusion.included as usize, total + 1)
+            })
+    }
+
+    /// Returns the produced chunks of the current and the previous epoch, most
+    /// recent first.
+    pub fn chunks(&self) -> Vec<ProducedChunkView> {
+        self.chunks.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use super::*;
+
+    fn epoch(i: u8) -> EpochId {
+        EpochId(hash(&[i]))
+    }
+
+    #[test]
+    fn test_produced_chunks() {
+        let mut chunks = ProducedChunks::default();
+        let parts = vec![("bob".parse().unwrap(), vec![2, 3]), ("alice".parse().unwrap(), vec![0])];
+        for (i, height) in [10, 11, 12].into_iter().enumerate() {
+            chunks.produced(hash(&[i as u8]), 0, height, &epoch(1), Clock::utc(), parts.clone());
+        }
+        let view = &chunks.chunks()[0];
+        assert_eq!(view.height_created, 12);
+        let accounts: Vec<_> =
+            view.parts_sent.iter().map(|sent| sent.account_id.as_str()).collect();
+        assert_eq!(accounts, ["alice", "bob"]);
+        assert_eq!(chunks.inclusion_counts(), (0, 0));
+
+        // The chunk at 10 is included by the block at 11, the one at 11 is left
+        // out, the one at 12 isn't settled yet.
+        let block_hash = hash(b"block");
+        chunks.new_head(&block_hash, 9, &HashSet::new());
+        assert_eq!(chunks.inclusion_counts(), (0, 0));
+        chunks.new_head(&block_hash, 11, &[hash(&[0])].into_iter().collect());
+        let inclusions: Vec<_> = chunks
+            .chunks()
+            .into_iter()
+            .map(|chunk| {
+                chunk.inclusion.map(|inclusion| (inclusion.included, inclusion.block_height))
+            })
+            .collect();
+        assert_eq!(inclusions, [None, Some((false, 11)), Some((true, 11))]);
+        assert_eq!(chunks.inclusion_counts(), (1, 2));
+        // Settled once.
+        chunks.new_head(&block_hash, 12, &[hash(&[1])].into_iter().collect());
+        assert_eq!(chunks.inclusion_counts(), (1, 3));
+
+        // Only the current and the previous epoch are kept.
+        chunks.produced(hash(&[3]), 0, 20, &epoch(2), Clock::utc(), vec![]);
+        assert_eq!(chunks.chunks().len(), 4);
+        chunks.produced(hash(&[4]), 0, 30, &epoch(3), Clock::utc(), vec![]);
+        let heights: Vec<_> = chunks.chunks().iter().map(|chunk| chunk.height_created).collect();
+        assert_eq!(heights, [30, 20]);
+
+        for height in 0..MAX_PRODUCED_CHUNKS as u64 + 10 {
+            chunks.produced(
+                hash(&height.to_le_bytes()),
+                0,
+                30 + height,
+                &epoch(3),
+                Clock::utc(),
+                vec![],
+            );
+        }
+        assert_eq!(chunks.chunks().len(), MAX_PRODUCED_CHUNKS);
+    }
+}
@@ -0,0 +1,171 @@
+//! Bookkeeping of the blocks and chunks this node's validator was assigned and
+//! didn't produce.
+//!
+//! While the validator waits for or tries to produce a block or chunk, the
+//! reason it can't is noted for the assignment.  Once a block past the height
+//! of the assignment is accepted without what the validator should have
+//! produced, the assignment is missed and the reason noted last is recorded
+//! with it.  An assignment which was never attempted is recorded as waiting
+//! for the previous block.  Missed assignments are counted by reason and those
+//! of the current and the previous epoch are kept for the debug endpoint.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::DateTime;
+use near_primitives::time::{Clock, Utc};
+use near_primitives::types::{BlockHeight, EpochId, ShardId};
+use near_primitives::views::{ProductionSkipReason, ProductionSkipView};
+use tracing::debug;
+
+use crate::metrics;
+
+/// Height of the block or chunk and the shard of the chunk.
+type Assignment = (BlockHeight, Option<ShardId>);
+
+#[derive(Default)]
+pub(crate) struct ProductionSkips {
+    /// Why the assignments which aren't missed yet couldn't be produced so far.
+    pending: HashMap<Assignment, (ProductionSkipReason, DateTime<Utc>)>,
+    /// Missed assignments, most recent first.
+    skips: VecDeque<ProductionSkipView>,
+    /// The epoch of the last recorded head and the one before it.
+    epochs: [Option<EpochId>; 2],
+}
+
+impl ProductionSkips {
+    /// Notes why the assignment can't be produced at the moment, replacing the
+    /// reason noted before.
+    pub fn note(
+        &mut self,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+        reason: ProductionSkipReason,
+    ) {
+        let entry = self
+            .pending
+            .entry((height, shard_id))
+            .or_insert_with(|| (reason.clone(), Clock::utc()));
+        if entry.0 != reason {
+            *entry = (reason, Clock::utc());
+        }
+    }
+
+    /// Notes the error producing the assignment failed with unless a more
+    /// specific reason was noted since the attempt started.
+    pub fn note_error(
+        &mut self,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+        err: &dyn std::fmt::Display,
+    ) {
+        self.pending
+            .entry((height, shard_id))
+            .or_insert_with(|| (ProductionSkipReason::Error(err.to_string()), Clock::utc()));
+    }
+
+    /// Forgets the reason noted for the assignment, to be called when an
+    /// attempt to produce it starts or when it was produced.
+    pub fn clear(&mut self, height: BlockHeight, shard_id: Option<ShardId>) {
+        self.pending.remove(&(height, shard_id));
+    }
+
+    /// Records the assignments which the new head at `head_height` shows were
+    /// missed.  Reasons noted for heights up to the head are dropped.
+    pub fn record(
+        &mut self,
+        epoch_id: &EpochId,
+        head_height: BlockHeight,
+        missed: Vec<Assignment>,
+    ) {
+        if self.epochs[0].as_ref() != Some(epoch_id) {
+            self.epochs = [Some(epoch_id.clone()), self.epochs[0].take()];
+            let epochs = &self.epochs;
+            self.skips.retain(|skip| epochs.contains(&Some(skip.epoch_id.clone())));
+        }
+        let missed_at = Clock::utc();
+        for (height, shard_id) in missed {
+            if self.skips.iter().any(|skip| skip.height == height && skip.shard_id == shard_id) {
+                // Already recorded on the other side of a reorg.
+                continue;
+            }
+            let (reason, since) = match self.pending.remove(&(height, shard_id)) {
+                Some((reason, since)) => (reason, Some(since)),
+                None => (ProductionSkipReason::PrevBlockNotReceived, None),
+            };
+            let kind = if shard_id.is_some() { "chunk" } else { "block" };
+            debug!(target: "client", height, ?shard_id, ?reason, "Missed {} production", kind);
+            metrics::PRODUCTION_SKIPS_TOTAL.with_label_values(&[kind, reason.as_str()]).inc();
+            self.skips.push_front(ProductionSkipView {
+                height,
+                shard_id,
+                epoch_id: epoch_id.clone(),
+                reason,
+                reason_since: since,
+                missed_at,
+            });
+        }
+        self.pending.retain(|(height, _), _| *height > head_height);
+    }
+
+    /// Returns the missed assignments of the current and the previous epoch,
+    /// most recent first.
+    pub fn skips(&self) -> Vec<ProductionSkipView> {
+        self.skips.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_primitives::hash::hash;
+
+    use super::*;
+
+    fn epoch(i: u8) -> EpochId {
+        EpochId(hash(&[i]))
+    }
+
+    fn reasons(skips: &ProductionSkips) -> Vec<(BlockHeight, Option<ShardId>, &'static str)> {
+        skips.skips.iter().map(|skip| (skip.height, skip.shard_id, skip.reason.as_str())).collect()
+    }
+
+    #[test]
+    fn test_production_skips() {
+        let mut skips = ProductionSkips::default();
+        skips.note(11, None, ProductionSkipReason::NotEnoughApprovals);
+        skips.note(11, Some(0), ProductionSkipReason::MissingChunkParts);
+        // A more specific reason noted during the attempt wins over its error.
+        skips.clear(11, Some(0));
+        skips.note(11, Some(0), ProductionSkipReason::StateNotCaughtUp);
+        skips.note_error(11, Some(0), &"failed");
+        skips.note_error(11, Some(1), &"failed");
+        // Produced in the end.
+        skips.note(12, None, ProductionSkipReason::NotEnoughApprovals);
+        skips.clear(12, None);
+        skips.record(&epoch(1), 12, vec![(11, None), (11, Some(0)), (11, Some(1)), (12, Some(0))]);
+        assert_eq!(
+            reasons(&skips),
+            vec![
+                (12, Some(0), "prev_block_not_received"),
+                (11, Some(1), "error"),
+                (11, Some(0), "state_not_caught_up"),
+                (11, None, "not_enough_approvals"),
+            ]
+        );
+        assert!(skips.skips[0].reason_since.is_none());
+        assert!(skips.skips[1].reason_since.is_some());
+        assert!(skips.pending.is_empty());
+
+        // Recorded again after a reorg.
+        skips.record(&epoch(1), 12, vec![(11, None)]);
+        assert_eq!(skips.skips().len(), 4);
+
+        // Only the current and the previous epoch are kept.
+        skips.record(&epoch(2), 20, vec![(20, None)]);
+        assert_eq!(skips.skips().len(), 5);
+        skips.record(&epoch(3), 30, vec![(30, None)]);
+        assert_eq!(
+            reasons(&skips),
+            vec![(30, None, "prev_block_not_received"), (20, None, "prev_block_not_received")]
+        );
+    }
+}
@@ -1,10 +1,17 @@
-use near_metrics::{try_create_gauge_vec, try_create_int_gauge};
+use crate::shutdown_report;
+use near_metrics::{
+    try_create_gauge_vec, try_create_int_counter_vec, try_create_int_gauge,
+    try_create_int_gauge_vec,
+};
 use near_store::db::{StatsValue, StoreStatistics};
+use near_store::{DBCol, Store, WriteStall};
 use once_cell::sync::Lazy;
-use prometheus::{GaugeVec, IntGauge};
+use prometheus::{GaugeVec, IntCounterVec, IntGauge, IntGaugeVec};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
 use tracing::warn;
 
 pub(crate) fn export_stats_as_metrics(stats: StoreStatistics) {
@@ -134,3 +141,120 @@ fn get_stats_summary_count_key(stat_name: &str) -> String {
 fn get_stats_summary_sum_key(stat_name: &str) -> String {
     format!("{}.sum", stat_name)
 }
+
+static PENDING_COMPACTION_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_pending_compaction_bytes",
+        "Estimated bytes compaction has to rewrite to bring all levels of the column below their \
+         target size",
+        &["column"],
+    )
+    .unwrap()
+});
+static NUM_FILES_AT_LEVEL0: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_num_files_at_level0",
+        "Number of L0 files of the column",
+        &["column"],
+    )
+    .unwrap()
+});
+static NUM_IMMUTABLE_MEM_TABLES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_num_immutable_mem_tables",
+        "Number of full memtables of the column waiting to be flushed",
+        &["column"],
+    )
+    .unwrap()
+});
+static MEM_TABLE_FLUSH_PENDING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_mem_table_flush_pending",
+        "Whether a memtable flush of the column is pending",
+        &["column"],
+    )
+    .unwrap()
+});
+static WRITE_STALL: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_rocksdb_write_stall",
+        "Write stall state of the column: 0 if writes are not limited, 1 if they're delayed, 2 if \
+         they're stopped",
+        &["column"],
+    )
+    .unwrap()
+});
+static WRITE_STALL_MILLIS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_rocksdb_write_stall_ms_total",
+        "Time the column was seen in write stall, sampled at every export of the column \
+         properties",
+        &["column"],
+    )
+    .unwrap()
+});
+
+/// Minimum time between the warnings about a column entering write stall.
+const WRITE_STALL_WARNING_PERIOD: Duration = Duration::from_secs(300);
+
+/// Write stall of every column at the previous export and the time of the last
+/// warning about it.
+static WRITE_STALLS: Lazy<Mutex<HashMap<DBCol, (WriteStall, Option<Instant>)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Exports the flush and compaction backlog of every column of the store.
+/// `period` is the time since the previous export, counted as stall time for
+/// the columns in write stall.
+pub(crate) fn export_column_properties(store: &Store, period: Duration) {
+    let db = match store.get_rocksdb() {
+        Some(db) => db,
+        None => return,
+    };
+    let mut write_stalls = WRITE_STALLS.lock().unwrap();
+    let mut column_sizes = Vec::new();
+    for col in DBCol::iter() {
+        let properties = match db.get_column_properties(col) {
+            Ok(properties) => properties,
+            Err(err) => {
+                warn!(target: "stats", ?col, "Failed to read column properties: {}", err);
+                continue;
+            }
+        };
+        column_sizes.push((col, properties.live_data_size));
+        let label: &str = col.into();
+        PENDING_COMPACTION_BYTES
+            .with_label_values(&[label])
+            .set(properties.pending_compaction_bytes as i64);
+        NUM_FILES_AT_LEVEL0.with_label_values(&[label]).set(properties.num_files_at_level0 as i64);
+        NUM_IMMUTABLE_MEM_TABLES
+            .with_label_values(&[label])
+            .set(properties.num_immutable_mem_tables as i64);
+        MEM_TABLE_FLUSH_PENDING
+            .with_label_values(&[label])
+            .set(properties.mem_table_flush_pending as i64);
+        let write_stall = properties.write_stall();
+        WRITE_STALL.with_label_values(&[label]).set(write_stall as i64);
+        let (prev_write_stall, last_warning) =
+            write_stalls.entry(col).or_insert((WriteStall::None, None));
+        if write_stall != WriteStall::None {
+            WRITE_STALL_MILLIS.with_label_values(&[label]).inc_by(period.as_millis() as u64);
+            let now = Instant::now();
+            if *prev_write_stall < write_stall
+                && last_warning.map_or(true, |last| now - last >= WRITE_STALL_WARNING_PERIOD)
+            {
+                warn!(
+                    target: "stats",
+                    ?col,
+                    write_stall = write_stall.as_ref(),
+                    pending_compaction_bytes = properties.pending_compaction_bytes,
+                    num_files_at_level0 = properties.num_files_at_level0,
+                    num_immutable_mem_tables = properties.num_immutable_mem_tables,
+                    "Column entered write stall"
+                );
+                *last_warning = Some(now);
+            }
+        }
+        *prev_write_stall = write_stall;
+    }
+    shutdown_report::record_column_sizes(column_sizes);
+}
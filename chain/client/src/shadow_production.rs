@@ -0,0 +1,167 @@
+//! Shadow production of blocks and chunks, see
+//! `ClientConfig::shadow_production`.
+//!
+//! A node which becomes a validator in the next epoch has no way to find out
+//! whether its setup works until it starts missing blocks.  In shadow
+//! production mode it builds the block and chunks it's assigned to in the
+//! next epoch on top of every new head, as if it were producing the next
+//! height, signs them with its validator key and checks the signatures against
+//! the key it staked.  Nothing is broadcast.  Chunks are built without
+//! transactions so that the transaction pool is left alone.
+//!
+//! The times taken and the failures are exported as metrics and the recent
+//! attempts are shown on the debug page, together with the block producers of
+//! the next epoch the node has no route to.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use near_crypto::PublicKey;
+use near_network_primitives::types::KnownProducer;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+use near_primitives::views::{ShadowProductionAttemptView, ShadowProductionView};
+use tracing::warn;
+
+use crate::metrics;
+
+/// Number of the most recent attempts shown on the debug page.
+const RECENT_ATTEMPTS: usize = 50;
+
+/// What the node produces in the next epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NextEpochAssignments {
+    pub epoch_id: EpochId,
+    /// Key the node staked for the next epoch.
+    pub public_key: PublicKey,
+    pub block_producer: bool,
+    pub chunk_shards: Vec<ShardId>,
+    /// Other block producers of the next epoch, which the node sends its
+    /// blocks and approvals to.
+    pub block_producers: Vec<AccountId>,
+}
+
+pub(crate) struct ShadowProduction {
+    /// `None` unless the node is a validator of the next epoch but not of the
+    /// current one.
+    assignments: Option<NextEpochAssignments>,
+    /// Most recent first.
+    recent: VecDeque<ShadowProductionAttemptView>,
+}
+
+impl ShadowProduction {
+    pub fn new() -> Self {
+        Self { assignments: None, recent: VecDeque::new() }
+    }
+
+    pub fn set_assignments(&mut self, assignments: Option<NextEpochAssignments>) {
+        self.assignments = assignments;
+    }
+
+    /// Records building and verifying the block, or the chunk of the shard.
+    pub fn record(
+        &mut self,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+        duration: Duration,
+        result: Result<(), String>,
+    ) {
+        let kind = if shard_id.is_some() { "chunk" } else { "block" };
+        metrics::SHADOW_PRODUCTION_TIME.with_label_values(&[kind]).observe(duration.as_secs_f64());
+        let error = result.err();
+        if let Some(error) = &error {
+            metrics::SHADOW_PRODUCTION_FAILURES_TOTAL.with_label_values(&[kind]).inc();
+            warn!(target: "client", height, ?shard_id, %error, "Shadow production of a {} failed", kind);
+        }
+        self.recent.push_front(ShadowProductionAttemptView {
+            height,
+            shard_id,
+            duration_millis: duration.as_millis() as u64,
+            error,
+        });
+        self.recent.truncate(RECENT_ATTEMPTS);
+    }
+
+    /// Block producers of the next epoch there's no route to.
+    pub fn unreachable_producers(&self, known_producers: &[KnownProducer]) -> Vec<AccountId> {
+        let assignments = match &self.assignments {
+            Some(assignments) => assignments,
+            None => return vec![],
+        };
+        assignments
+            .block_producers
+            .iter()
+            .filter(|account_id| {
+                !known_producers.iter().any(|producer| &producer.account_id == *account_id)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Sets the metric of the block producers of the next epoch there's no
+    /// route to.
+    pub fn update_reachability(&self, known_producers: &[KnownProducer]) {
+        metrics::SHADOW_PRODUCTION_UNREACHABLE_PRODUCERS
+            .set(self.unreachable_producers(known_producers).len() as i64);
+    }
+
+    pub fn view(&self, known_producers: &[KnownProducer]) -> Option<ShadowProductionView> {
+        let assignments = self.assignments.as_ref()?;
+        Some(ShadowProductionView {
+            next_epoch_id: assignments.epoch_id.clone(),
+            next_epoch_block_producer: assignments.block_producer,
+            next_epoch_chunk_shards: assignments.chunk_shards.clone(),
+            unreachable_producers: self.unreachable_producers(known_producers),
+            recent_attempts: self.recent.iter().cloned().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::network::PeerId;
+
+    fn account(name: &str) -> AccountId {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_shadow_production_view() {
+        let mut shadow_production = ShadowProduction::new();
+        assert_eq!(shadow_production.view(&[]), None);
+
+        let public_key = SecretKey::from_seed(KeyType::ED25519, "test").public_key();
+        shadow_production.set_assignments(Some(NextEpochAssignments {
+            epoch_id: EpochId::default(),
+            public_key: public_key.clone(),
+            block_producer: true,
+            chunk_shards: vec![0],
+            block_producers: vec![account("alice"), account("bob")],
+        }));
+        for height in 0..RECENT_ATTEMPTS as u64 + 1 {
+            shadow_production.record(height, None, Duration::from_millis(3), Ok(()));
+        }
+        shadow_production.record(100, Some(0), Duration::ZERO, Err("no chunk extra".to_string()));
+
+        let known_producers = [KnownProducer {
+            account_id: account("alice"),
+            addr: None,
+            peer_id: PeerId::new(public_key),
+            next_hops: None,
+        }];
+        let view = shadow_production.view(&known_producers).unwrap();
+        assert_eq!(view.unreachable_producers, vec![account("bob")]);
+        assert_eq!(view.recent_attempts.len(), RECENT_ATTEMPTS);
+        assert_eq!(
+            view.recent_attempts[0],
+            ShadowProductionAttemptView {
+                height: 100,
+                shard_id: Some(0),
+                duration_millis: 0,
+                error: Some("no chunk extra".to_string()),
+            }
+        );
+        assert_eq!(view.recent_attempts[1].height, RECENT_ATTEMPTS as u64);
+    }
+}
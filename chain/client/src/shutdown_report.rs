@@ -0,0 +1,288 @@
+//! Report of the state of the node written when it panics or dies of a fatal
+//! error, so that a postmortem doesn't depend on what was left in the logs.
+//!
+//! The client keeps a snapshot of what the report needs up to date as it goes:
+//! the heads and the records of the block processing log of the last
+//! [`RECENT_BLOCKS`] blocks on every block, the sync status, the tracked shards and the number of peers
+//! with the log summary, the estimated sizes of the columns with their
+//! properties.  The panic hook only serializes the snapshot with the panic
+//! message and backtrace into a buffer allocated at startup and writes it to
+//! the file opened at startup.  It never waits for a lock, a snapshot locked by
+//! the panicking thread is left out, and ignores the errors rather than
+//! panicking again.  The path of the report is the last line printed to
+//! stderr.  Only the first panic or fatal error of the process is reported.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::panic::PanicInfo;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use chrono::{DateTime, Utc};
+use near_primitives::block::Tip;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::version::{DbVersion, ProtocolVersion, Version, DB_VERSION, PROTOCOL_VERSION};
+use near_primitives::views::BlockProcessingView;
+use near_store::DBCol;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+
+/// Blocks whose processing times are in the report.
+pub const RECENT_BLOCKS: usize = 50;
+/// Size of the buffer the report is serialized into, enough for the report
+/// with a deep backtrace.
+const REPORT_BUFFER_CAPACITY: usize = 1 << 20;
+
+static NODE_STATE: Lazy<Mutex<NodeState>> = Lazy::new(|| Mutex::new(NodeState::default()));
+static REPORT_FILE: OnceCell<Mutex<ReportFile>> = OnceCell::new();
+/// Whether the report was written, or is being written, already.
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct HeadView {
+    height: BlockHeight,
+    hash: CryptoHash,
+}
+
+impl From<&Tip> for HeadView {
+    fn from(tip: &Tip) -> Self {
+        Self { height: tip.height, hash: tip.last_block_hash }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+struct NodeState {
+    head: Option<HeadView>,
+    final_head: Option<HeadView>,
+    sync_status: Option<String>,
+    tracked_shards: Vec<ShardId>,
+    num_connected_peers: Option<usize>,
+    /// The last blocks processed, the oldest first.
+    recent_blocks: VecDeque<BlockProcessingView>,
+    /// Estimated bytes of live data of every column.
+    column_sizes: BTreeMap<&'static str, u64>,
+}
+
+impl NodeState {
+    fn push_block(&mut self, record: BlockProcessingView) {
+        if self.recent_blocks.len() == RECENT_BLOCKS {
+            self.recent_blocks.pop_front();
+        }
+        self.recent_blocks.push_back(record);
+    }
+}
+
+struct ReportFile {
+    file: File,
+    path: String,
+    version: Version,
+    buffer: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct ShutdownReport<'a> {
+    /// `panic` or `fatal_error`.
+    reason: &'static str,
+    time: DateTime<Utc>,
+    message: String,
+    location: Option<String>,
+    thread: Option<String>,
+    backtrace: Option<String>,
+    version: &'a Version,
+    protocol_version: ProtocolVersion,
+    db_version: DbVersion,
+    /// `None` if the panicking thread was updating it.
+    node: Option<&'a NodeState>,
+}
+
+/// Locks the mutex unless another thread, or the panicking one, holds it.
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+fn lock_node_state() -> MutexGuard<'static, NodeState> {
+    NODE_STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Opens the report file at `path` and installs the panic hook writing the
+/// report, after the hook installed before.  The file is only truncated when
+/// a report is written, so the report of the previous crash stays until then.
+/// Does nothing if the hook was installed already.
+pub fn install(path: &Path, version: Version) -> io::Result<()> {
+    let file = std::fs::OpenOptions::new().create(true).write(true).open(path)?;
+    let report_file = ReportFile {
+        file,
+        path: path.display().to_string(),
+        version,
+        buffer: Vec::with_capacity(REPORT_BUFFER_CAPACITY),
+    };
+    if REPORT_FILE.set(Mutex::new(report_file)).is_err() {
+        return Ok(());
+    }
+    Lazy::force(&NODE_STATE);
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous_hook(info);
+        report_panic(info);
+    }));
+    Ok(())
+}
+
+fn report_panic(info: &PanicInfo<'_>) {
+    let payload = info.payload();
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    };
+    write_report(
+        "panic",
+        message,
+        info.location().map(|location| location.to_string()),
+        std::thread::current().name().map(str::to_string),
+        Some(format!("{:?}", backtrace::Backtrace::new())),
+    );
+}
+
+/// Writes the report of a fatal error which isn't a panic, unless a panic was
+/// reported already.
+pub fn report_fatal_error(message: &str) {
+    write_report("fatal_error", message.to_string(), None, None, None);
+}
+
+fn write_report(
+    reason: &'static str,
+    message: String,
+    location: Option<String>,
+    thread: Option<String>,
+    backtrace: Option<String>,
+) {
+    let report_file = match REPORT_FILE.get() {
+        Some(report_file) => report_file,
+        None => return,
+    };
+    if REPORTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let mut report_file = match try_lock(report_file) {
+        Some(report_file) => report_file,
+        None => return,
+    };
+    let ReportFile { file, path, version, buffer } = &mut *report_file;
+    let node = try_lock(&NODE_STATE);
+    let report = ShutdownReport {
+        reason,
+        time: Utc::now(),
+        message,
+        location,
+        thread,
+        backtrace,
+        version,
+        protocol_version: PROTOCOL_VERSION,
+        db_version: DB_VERSION,
+        node: node.as_deref(),
+    };
+    buffer.clear();
+    let result = serde_json::to_writer_pretty(&mut *buffer, &report)
+        .map_err(io::Error::from)
+        .and_then(|()| {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(buffer)?;
+            file.sync_data()
+        });
+    // Unlike `eprintln!`, doesn't panic if stderr is closed.
+    let _ = match result {
+        Ok(()) => writeln!(io::stderr(), "Shutdown report written to {}", path),
+        Err(err) => {
+            writeln!(io::stderr(), "Failed to write the shutdown report to {}: {}", path, err)
+        }
+    };
+}
+
+/// Records a processed block, as written to the block processing log.
+pub(crate) fn record_block(record: &BlockProcessingView) {
+    lock_node_state().push_block(record.clone());
+}
+
+pub(crate) fn record_heads(head: Option<&Tip>, final_head: Option<&Tip>) {
+    let mut state = lock_node_state();
+    if let Some(head) = head {
+        state.head = Some(head.into());
+    }
+    if let Some(final_head) = final_head {
+        state.final_head = Some(final_head.into());
+    }
+}
+
+pub(crate) fn record_status(
+    sync_status: &str,
+    tracked_shards: Vec<ShardId>,
+    num_connected_peers: usize,
+) {
+    let mut state = lock_node_state();
+    state.sync_status = Some(sync_status.to_string());
+    state.tracked_shards = tracked_shards;
+    state.num_connected_peers = Some(num_connected_peers);
+}
+
+pub(crate) fn record_column_sizes(sizes: impl IntoIterator<Item = (DBCol, u64)>) {
+    let column_sizes = sizes.into_iter().map(|(col, size)| (col.into(), size)).collect();
+    lock_node_state().column_sizes = column_sizes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+    use near_primitives::views::BlockProcessingOutcome;
+
+    #[test]
+    fn test_report() {
+        let mut state = NodeState::default();
+        for height in 0..RECENT_BLOCKS as u64 + 10 {
+            state.push_block(BlockProcessingView {
+                hash: hash(&height.to_le_bytes()),
+                height,
+                timestamp: 0,
+                processing_time_micros: 10_000,
+                shard_apply_time_micros: vec![(0, 8_000)],
+                outcome: BlockProcessingOutcome::Accepted,
+            });
+        }
+        assert_eq!(state.recent_blocks.len(), RECENT_BLOCKS);
+        assert_eq!(state.recent_blocks.front().unwrap().height, 10);
+        state.head = Some(HeadView { height: 59, hash: hash(b"head") });
+        state.column_sizes.insert(DBCol::State.into(), 1024);
+
+        let version = Version::default();
+        let report = ShutdownReport {
+            reason: "panic",
+            time: Utc::now(),
+            message: "boom".to_string(),
+            location: Some("src/lib.rs:1:1".to_string()),
+            thread: None,
+            backtrace: None,
+            version: &version,
+            protocol_version: PROTOCOL_VERSION,
+            db_version: DB_VERSION,
+            node: Some(&state),
+        };
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["reason"], "panic");
+        assert_eq!(json["message"], "boom");
+        assert_eq!(json["node"]["head"]["height"], 59);
+        assert_eq!(json["node"]["recent_blocks"][RECENT_BLOCKS - 1]["height"], 59);
+        assert_eq!(json["node"]["column_sizes"]["State"], 1024);
+        assert_eq!(json["db_version"], DB_VERSION);
+    }
+}
@@ -0,0 +1,117 @@
+//! Subscriptions to the state changes of accounts, for tests to be told about
+//! the changes they wait for rather than poll `EXPERIMENTAL_changes`.
+//!
+//! A subscriber gets the changes of its accounts for every block the client
+//! accepts, from the state changes stored when the chunks of the block are
+//! applied, so only the shards the node tracks are covered.  Subscribing is
+//! only possible in process, through the address of the client actor.
+
+use std::collections::HashSet;
+
+use actix::Message;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight, StateChanges};
+use near_primitives::views::{StateChangeWithCauseView, StateChangesView};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// The changes of the subscribed accounts made by a block, sent for every
+/// block accepted even if there's none.
+#[derive(Debug, Clone)]
+pub struct StateChangesUpdate {
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    pub changes: StateChangesView,
+}
+
+/// Subscribes to the state changes of the accounts.  The subscription ends
+/// once the receiver is dropped.
+pub struct SubscribeStateChanges {
+    pub account_ids: HashSet<AccountId>,
+    pub sender: UnboundedSender<StateChangesUpdate>,
+}
+
+impl Message for SubscribeStateChanges {
+    type Result = ();
+}
+
+#[derive(Default)]
+pub(crate) struct StateChangeSubscriptions {
+    subscribers: Vec<SubscribeStateChanges>,
+}
+
+impl StateChangeSubscriptions {
+    pub fn subscribe(&mut self, subscription: SubscribeStateChanges) {
+        self.subscribers.push(subscription);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// Sends the changes of the block to the subscribers of the accounts
+    /// they're for, dropping the subscribers which went away.
+    pub fn notify(
+        &mut self,
+        block_hash: CryptoHash,
+        block_height: BlockHeight,
+        changes: StateChanges,
+    ) {
+        let changes: Vec<(AccountId, StateChangeWithCauseView)> = changes
+            .into_iter()
+            .map(|change| (change.value.affected_account_id().clone(), change.into()))
+            .collect();
+        self.subscribers.retain(|subscriber| {
+            let update = StateChangesUpdate {
+                block_hash,
+                block_height,
+                changes: changes
+                    .iter()
+                    .filter(|(account_id, _)| subscriber.account_ids.contains(account_id))
+                    .map(|(_, change)| change.clone())
+                    .collect(),
+            };
+            subscriber.sender.send(update).is_ok()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+    use near_primitives::types::{StateChangeCause, StateChangeValue, StateChangeWithCause};
+    use near_primitives::views::StateChangeValueView;
+
+    fn deletion(account_id: &str) -> StateChangeWithCause {
+        StateChangeWithCause {
+            cause: StateChangeCause::InitialState,
+            value: StateChangeValue::AccountDeletion { account_id: account_id.parse().unwrap() },
+        }
+    }
+
+    #[test]
+    fn test_notify() {
+        let mut subscriptions = StateChangeSubscriptions::default();
+        let (sender, mut alice) = tokio::sync::mpsc::unbounded_channel();
+        let account_ids = HashSet::from(["alice.near".parse().unwrap()]);
+        subscriptions.subscribe(SubscribeStateChanges { account_ids, sender });
+        let (sender, bob) = tokio::sync::mpsc::unbounded_channel();
+        let account_ids = HashSet::from(["bob.near".parse().unwrap()]);
+        subscriptions.subscribe(SubscribeStateChanges { account_ids, sender });
+
+        subscriptions.notify(hash(b"a"), 1, vec![deletion("alice.near"), deletion("carol.near")]);
+        let update = alice.try_recv().unwrap();
+        assert_eq!((update.block_hash, update.block_height), (hash(b"a"), 1));
+        assert_eq!(update.changes.len(), 1);
+        assert!(matches!(
+            &update.changes[0].value,
+            StateChangeValueView::AccountDeletion { account_id } if account_id.as_ref() == "alice.near"
+        ));
+
+        // Gone subscribers are dropped, the others get every block.
+        drop(bob);
+        subscriptions.notify(hash(b"b"), 2, vec![]);
+        assert!(alice.try_recv().unwrap().changes.is_empty());
+        assert_eq!(subscriptions.subscribers.len(), 1);
+    }
+}
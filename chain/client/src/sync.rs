@@ -2,6 +2,7 @@ use near_chain::{check_known, near_chain_primitives, ChainStoreAccess, Error};
 use std::cmp::min;
 use std::collections::{HashMap, HashSet};
 use std::ops::Add;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration as TimeDuration;
@@ -14,10 +15,12 @@ use rand::{thread_rng, Rng};
 use tracing::{debug, error, info, warn};
 
 use near_chain::{Chain, RuntimeAdapter};
+use near_chain_configs::StateSyncSource;
 use near_network::types::{FullPeerInfo, NetworkRequests, NetworkResponses, PeerManagerAdapter};
 use near_primitives::block::Tip;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
+use near_primitives::state_part::PartId;
 use near_primitives::syncing::get_num_state_parts;
 use near_primitives::time::{Clock, Utc};
 use near_primitives::types::validator_stake::ValidatorStake;
@@ -624,6 +627,9 @@ pub struct StateSync {
 
     timeout: Duration,
 
+    /// Where the state parts are taken from, in order.
+    sources: Vec<StateSyncSource>,
+
     /// Maps shard_id to result of applying downloaded state
     state_parts_apply_results: HashMap<ShardId, Result<(), near_chain_primitives::error::Error>>,
 
@@ -632,7 +638,11 @@ pub struct StateSync {
 }
 
 impl StateSync {
-    pub fn new(network_adapter: Arc<dyn PeerManagerAdapter>, timeout: TimeDuration) -> Self {
+    pub fn new(
+        network_adapter: Arc<dyn PeerManagerAdapter>,
+        timeout: TimeDuration,
+        sources: Vec<StateSyncSource>,
+    ) -> Self {
         StateSync {
             network_adapter,
             state_sync_time: Default::default(),
@@ -640,6 +650,7 @@ impl StateSync {
             last_part_id_requested: Default::default(),
             requested_target: lru::LruCache::new(MAX_PENDING_PART as usize),
             timeout: Duration::from_std(timeout).unwrap(),
+            sources,
             state_parts_apply_results: HashMap::new(),
             split_state_roots: HashMap::new(),
         }
@@ -698,6 +709,7 @@ impl StateSync {
                     done: false,
                     state_requests_count: 0,
                     last_target: None,
+                    source: None,
                 };
                 1
             ],
@@ -739,6 +751,7 @@ impl StateSync {
                                     done: false,
                                     state_requests_count: 0,
                                     last_target: None,
+                                    source: None,
                                 };
                                 state_num_parts as usize
                             ],
@@ -779,6 +792,12 @@ impl StateSync {
                         }
                     }
                     if parts_done {
+                        info!(
+                            target: "sync",
+                            "State sync parts of shard {} downloaded from {}",
+                            shard_id,
+                            format_part_sources(&shard_sync_download.downloads)
+                        );
                         update_sync_status = true;
                         *shard_sync_download = ShardSyncDownload {
                             downloads: vec![],
@@ -1084,15 +1103,14 @@ impl StateSync {
             highest_height_peers,
         )?;
 
-        if possible_targets.is_empty() {
-            return Ok(shard_sync_download);
-        }
-
         // Downloading strategy starts here
         let mut new_shard_sync_download = shard_sync_download.clone();
 
         match shard_sync_download.status {
             ShardSyncStatus::StateDownloadHeader => {
+                if possible_targets.is_empty() {
+                    return Ok(shard_sync_download);
+                }
                 let target = possible_targets.choose(&mut thread_rng()).cloned().unwrap();
                 assert!(new_shard_sync_download.downloads[0].run_me.load(Ordering::SeqCst));
                 new_shard_sync_download.downloads[0].run_me.store(false, Ordering::SeqCst);
@@ -1117,47 +1135,48 @@ impl StateSync {
                 );
             }
             ShardSyncStatus::StateDownloadParts => {
-                let possible_targets_sampler =
+                let num_parts = new_shard_sync_download.downloads.len() as u64;
+                let mut possible_targets_sampler =
                     SamplerLimited::new(possible_targets, MAX_STATE_PART_REQUEST);
+                let sources = self.sources.clone();
 
                 // Iterate over all parts that needs to be requested (i.e. download.run_me is true).
                 // Parts are ordered such that its index match its part_id.
-                // Finally, for every part that needs to be requested it is selected one peer (target) randomly
-                // to request the part from
-                for ((part_id, download), target) in new_shard_sync_download
+                // Every part is taken from the first source which has it.  For the peers it means
+                // selecting one peer (target) randomly to request the part from, unless all of
+                // them have been sent as many requests as they can take already.
+                for (part_id, download) in new_shard_sync_download
                     .downloads
                     .iter_mut()
                     .enumerate()
                     .filter(|(_, download)| download.run_me.load(Ordering::SeqCst))
-                    .zip(possible_targets_sampler)
                 {
-                    self.sent_request_part(target.clone(), part_id as u64, shard_id, sync_hash);
-                    download.run_me.store(false, Ordering::SeqCst);
-                    download.state_requests_count += 1;
-                    download.last_target = Some(target.clone());
-                    let run_me = download.run_me.clone();
-
-                    near_performance_metrics::actix::spawn(
-                        std::any::type_name::<Self>(),
-                        self.network_adapter
-                            .send(PeerManagerMessageRequest::NetworkRequests(
-                                NetworkRequests::StateRequestPart {
-                                    shard_id,
-                                    sync_hash,
-                                    part_id: part_id as u64,
-                                    target: target.clone(),
-                                },
-                            ))
-                            .then(move |result| {
-                                if let Ok(NetworkResponses::RouteNotFound) =
-                                    result.map(|f| f.as_network_response())
+                    let part_id = PartId::new(part_id as u64, num_parts);
+                    for source in &sources {
+                        match source {
+                            StateSyncSource::LocalDir { path } => {
+                                if read_local_state_part(path, shard_id, sync_hash, part_id, chain)
                                 {
-                                    // Send a StateRequestPart on the next iteration
-                                    run_me.store(true, Ordering::SeqCst);
+                                    download.run_me.store(false, Ordering::SeqCst);
+                                    download.done = true;
+                                    download.source = Some(source.name());
+                                    break;
+                                }
+                            }
+                            StateSyncSource::Peers => {
+                                if let Some(target) = possible_targets_sampler.next() {
+                                    self.request_part_from_peer(
+                                        target,
+                                        part_id.idx,
+                                        shard_id,
+                                        sync_hash,
+                                        download,
+                                    );
+                                    break;
                                 }
-                                future::ready(())
-                            }),
-                    );
+                            }
+                        }
+                    }
                 }
             }
             _ => {}
@@ -1166,6 +1185,38 @@ impl StateSync {
         Ok(new_shard_sync_download)
     }
 
+    fn request_part_from_peer(
+        &mut self,
+        target: AccountOrPeerIdOrHash,
+        part_id: u64,
+        shard_id: ShardId,
+        sync_hash: CryptoHash,
+        download: &mut DownloadStatus,
+    ) {
+        self.sent_request_part(target.clone(), part_id, shard_id, sync_hash);
+        download.run_me.store(false, Ordering::SeqCst);
+        download.state_requests_count += 1;
+        download.last_target = Some(target.clone());
+        let run_me = download.run_me.clone();
+
+        near_performance_metrics::actix::spawn(
+            std::any::type_name::<Self>(),
+            self.network_adapter
+                .send(PeerManagerMessageRequest::NetworkRequests(
+                    NetworkRequests::StateRequestPart { shard_id, sync_hash, part_id, target },
+                ))
+                .then(move |result| {
+                    if let Ok(NetworkResponses::RouteNotFound) =
+                        result.map(|f| f.as_network_response())
+                    {
+                        // Send a StateRequestPart on the next iteration
+                        run_me.store(true, Ordering::SeqCst);
+                    }
+                    future::ready(())
+                }),
+        );
+    }
+
     pub fn run(
         &mut self,
         me: &Option<AccountId>,
@@ -1241,6 +1292,56 @@ impl StateSync {
 /// ```
 /// vec![1, 2, 1, 3, 3, 2];
 /// ```
+/// Path of the part file in a `local_dir` state sync source.
+pub fn local_state_part_path(
+    dir: &Path,
+    shard_id: ShardId,
+    sync_hash: CryptoHash,
+    part_id: PartId,
+) -> PathBuf {
+    dir.join(sync_hash.to_string())
+        .join(format!("shard_{}", shard_id))
+        .join(format!("state_part_{:06}_of_{:06}", part_id.idx, part_id.total))
+}
+
+/// Saves the part from the directory if it's there and valid.
+fn read_local_state_part(
+    dir: &Path,
+    shard_id: ShardId,
+    sync_hash: CryptoHash,
+    part_id: PartId,
+    chain: &mut Chain,
+) -> bool {
+    let path = local_state_part_path(dir, shard_id, sync_hash, part_id);
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return false,
+        Err(err) => {
+            warn!(target: "sync", "Failed to read state part {}: {}", path.display(), err);
+            return false;
+        }
+    };
+    match chain.set_state_part(shard_id, sync_hash, part_id, &data) {
+        Ok(()) => true,
+        Err(err) => {
+            error!(target: "sync", "State sync set_state_part error, shard = {}, part = {}, file = {}: {:?}", shard_id, part_id.idx, path.display(), err);
+            false
+        }
+    }
+}
+
+/// Number of parts served by every source, e.g. `local_dir: 3, peers: 5`.
+pub fn format_part_sources(downloads: &[DownloadStatus]) -> String {
+    let mut counts: Vec<(&str, usize)> = vec![];
+    for source in downloads.iter().filter_map(|download| download.source) {
+        match counts.iter_mut().find(|(name, _)| *name == source) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((source, 1)),
+        }
+    }
+    counts.iter().map(|(name, count)| format!("{}: {}", name, count)).collect::<Vec<_>>().join(", ")
+}
+
 struct SamplerLimited<T> {
     data: Vec<T>,
     limit: Vec<u64>,
@@ -1302,7 +1403,7 @@ mod test {
     use near_primitives::types::EpochId;
     use near_primitives::utils::MaybeValidated;
     use near_primitives::validator_signer::InMemoryValidatorSigner;
-    use near_primitives::version::PROTOCOL_VERSION;
+    use near_primitives::version::{BuildId, PROTOCOL_VERSION};
     use num_rational::Ratio;
     use std::collections::HashSet;
 
@@ -1325,6 +1426,48 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_state_sync_sources() {
+        let sources: Vec<StateSyncSource> =
+            serde_json::from_str(r#"[{"local_dir": {"path": "/parts"}}, "peers"]"#).unwrap();
+        assert_eq!(
+            sources,
+            vec![StateSyncSource::LocalDir { path: "/parts".into() }, StateSyncSource::Peers]
+        );
+        assert_eq!(
+            local_state_part_path(
+                Path::new("/parts"),
+                2,
+                CryptoHash::default(),
+                PartId::new(3, 12)
+            ),
+            Path::new("/parts")
+                .join(CryptoHash::default().to_string())
+                .join("shard_2")
+                .join("state_part_000003_of_000012")
+        );
+
+        let now = Clock::utc();
+        let download = |source| DownloadStatus {
+            start_time: now,
+            prev_update_time: now,
+            run_me: Arc::new(AtomicBool::new(false)),
+            error: false,
+            done: source.is_some(),
+            state_requests_count: 0,
+            last_target: None,
+            source,
+        };
+        let downloads = [
+            download(Some("local_dir")),
+            download(None),
+            download(Some("peers")),
+            download(Some("local_dir")),
+        ];
+        assert_eq!(format_part_sources(&downloads), "local_dir: 2, peers: 1");
+        assert_eq!(format_part_sources(&downloads[1..2]), "");
+    }
+
     /// Starts two chains that fork of genesis and checks that they can sync heaaders to the longest.
     #[test]
     fn test_sync_headers_fork() {
@@ -1503,6 +1646,7 @@ mod test {
                 *last_block.header().next_bp_hash(),
                 block_merkle_tree.root(),
                 None,
+                BuildId::default(),
             );
             block_merkle_tree.insert(*block.hash());
 
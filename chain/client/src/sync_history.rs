@@ -0,0 +1,159 @@
+//! History of the transitions of the node between sync states.
+//!
+//! The sync state only tells where the node is now, which doesn't help with
+//! nodes flapping between block and header sync.  Every transition is
+//! recorded with the heights the decision was based on and a description of
+//! what triggered it, the most recent ones are kept in memory for the
+//! `/debug/api/sync_history` page and `/status`, and every pair of states is
+//! counted so that flapping can be alerted on.
+
+use std::collections::VecDeque;
+
+use near_client_primitives::types::SyncStatus;
+use near_primitives::network::PeerId;
+use near_primitives::time::Clock;
+use near_primitives::types::BlockHeight;
+use near_primitives::views::SyncTransitionView;
+use tracing::info;
+
+use crate::metrics;
+
+/// Number of transitions kept in memory.
+const HISTORY_SIZE: usize = 100;
+/// Number of transitions included in `/status`.
+pub(crate) const STATUS_HISTORY_SIZE: usize = 5;
+
+/// What the sync state machine saw when it switched states.
+pub(crate) struct SyncObservation<'a> {
+    pub head_height: BlockHeight,
+    pub header_head_height: BlockHeight,
+    pub highest_height: BlockHeight,
+    /// Peer whose height the node is syncing to.
+    pub highest_height_peer: Option<&'a PeerId>,
+}
+
+pub(crate) struct SyncHistory {
+    /// Name of the last recorded state.
+    current: String,
+    /// Most recent transition first.
+    transitions: VecDeque<SyncTransitionView>,
+}
+
+impl SyncHistory {
+    pub fn new(initial: &SyncStatus) -> Self {
+        Self {
+            current: initial.as_variant_name().to_string(),
+            transitions: VecDeque::with_capacity(HISTORY_SIZE),
+        }
+    }
+
+    /// Records the transition to `status` if it's a different state than the
+    /// last recorded one.
+    pub fn record(&mut self, status: &SyncStatus, observation: &SyncObservation) {
+        let to = status.as_variant_name();
+        if self.current == to {
+            return;
+        }
+        let from = std::mem::replace(&mut self.current, to.to_string());
+        let trigger = trigger(&from, status, observation);
+        info!(target: "sync", %from, to, %trigger, "Sync state changed");
+        metrics::SYNC_STATUS_TRANSITIONS_TOTAL.with_label_values(&[from.as_str(), to]).inc();
+        if self.transitions.len() == HISTORY_SIZE {
+            self.transitions.pop_back();
+        }
+        self.transitions.push_front(SyncTransitionView {
+            timestamp: Clock::utc(),
+            from,
+            to: to.to_string(),
+            trigger,
+            head_height: observation.head_height,
+            header_head_height: observation.header_head_height,
+            highest_height: observation.highest_height,
+        });
+    }
+
+    /// Returns up to `limit` most recent transitions, most recent first.
+    pub fn recent(&self, limit: usize) -> Vec<SyncTransitionView> {
+        self.transitions.iter().take(limit).cloned().collect()
+    }
+}
+
+/// Describes why the node switched from `from` to `to`.
+fn trigger(from: &str, to: &SyncStatus, observation: &SyncObservation) -> String {
+    let SyncObservation { head_height, header_head_height, highest_height, highest_height_peer } =
+        observation;
+    match to {
+        SyncStatus::NoSync if *highest_height == 0 => "no peers with a known height".to_string(),
+        SyncStatus::NoSync => {
+            format!(
+                "head #{} caught up with the highest peer height {}",
+                head_height, highest_height
+            )
+        }
+        SyncStatus::HeaderSync { .. } => match highest_height_peer {
+            Some(peer_id) => format!(
+                "peer {} claimed height {}, header head is #{}",
+                peer_id, highest_height, header_head_height
+            ),
+            None => format!(
+                "highest peer height {} is above header head #{}",
+                highest_height, header_head_height
+            ),
+        },
+        SyncStatus::BodySync { .. } if from == "StateSync" => {
+            format!("state sync done, head is #{}", head_height)
+        }
+        SyncStatus::BodySync { .. } => format!(
+            "header head #{} is close to the highest peer height {}, head is #{}",
+            header_head_height, highest_height, head_height
+        ),
+        SyncStatus::StateSync(sync_hash, _) => format!(
+            "head #{} is too far behind the highest peer height {}, syncing state at {}",
+            head_height, highest_height, sync_hash
+        ),
+        _ => format!("switched to {}", to.as_variant_name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_history() {
+        let mut history = SyncHistory::new(&SyncStatus::NoSync);
+        let observation = SyncObservation {
+            head_height: 10,
+            header_head_height: 10,
+            highest_height: 500,
+            highest_height_peer: None,
+        };
+        let header_sync = SyncStatus::HeaderSync { current_height: 10, highest_height: 500 };
+        history.record(&header_sync, &observation);
+        // Staying in the same state isn't a transition.
+        history.record(&header_sync, &observation);
+        let body_sync = SyncStatus::BodySync { current_height: 10, highest_height: 500 };
+        let observation = SyncObservation { header_head_height: 495, ..observation };
+        history.record(&body_sync, &observation);
+
+        let recent = history.recent(STATUS_HISTORY_SIZE);
+        let names: Vec<_> = recent.iter().map(|t| (t.from.as_str(), t.to.as_str())).collect();
+        assert_eq!(names, vec![("HeaderSync", "BodySync"), ("NoSync", "HeaderSync")]);
+        assert_eq!(
+            recent[0].trigger,
+            "header head #495 is close to the highest peer height 500, head is #10"
+        );
+        assert_eq!(recent[1].header_head_height, 10);
+
+        for _ in 0..HISTORY_SIZE / 2 {
+            history.record(&SyncStatus::NoSync, &observation);
+            history.record(&body_sync, &observation);
+        }
+        assert_eq!(history.recent(usize::MAX).len(), HISTORY_SIZE);
+        assert_eq!(history.recent(1)[0].to, "BodySync");
+        assert_eq!(
+            history.recent(usize::MAX)[HISTORY_SIZE - 1].trigger,
+            "head #10 caught up with the highest peer height 500"
+        );
+    }
+}
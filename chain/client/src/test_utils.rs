@@ -13,6 +13,7 @@ use futures::{future, FutureExt};
 use near_primitives::time::Utc;
 use num_rational::Rational;
 use rand::{thread_rng, Rng};
+use tokio::sync::mpsc::UnboundedReceiver;
 
 use near_chain::test_utils::KeyValueRuntime;
 use near_chain::{
@@ -39,9 +40,10 @@ use near_primitives::types::{
     ShardId,
 };
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use near_primitives::views::{
-    AccountView, FinalExecutionOutcomeView, QueryRequest, QueryResponseKind, StateItem,
+    AccountView, FinalExecutionOutcomeView, QueryRequest, QueryResponseKind,
+    StateChangeWithCauseView, StateItem,
 };
 use near_store::test_utils::create_test_store;
 use near_store::Store;
@@ -49,7 +51,10 @@ use near_telemetry::TelemetryActor;
 
 #[cfg(feature = "test_features")]
 use crate::AdversarialControls;
-use crate::{start_view_client, Client, ClientActor, SyncStatus, ViewClientActor};
+use crate::{
+    start_view_client, Client, ClientActor, StateChangesUpdate, SubscribeStateChanges, SyncStatus,
+    ViewClientActor,
+};
 use near_chain::chain::{do_apply_chunks, BlockCatchUpRequest, StateSplitRequest};
 use near_chain::types::AcceptedBlock;
 use near_client_primitives::types::Error;
@@ -597,6 +602,12 @@ pub fn setup_mock_all_validators(
                             received_bytes_per_sec: 0,
                             known_producers: vec![],
                             peer_counter: 0,
+                            clock_skew_millis: None,
+                            dns_seed_peers: Default::default(),
+                            recent_handshake_refusals: Default::default(),
+                            pinned_peers: Default::default(),
+                            blacklist_audit: Default::default(),
+                            discounted_shards: Default::default(),
                         };
                         client_addr.do_send(NetworkClientMessages::NetworkInfo(info));
                     }
@@ -1675,6 +1686,7 @@ pub fn create_chunk(
         *last_block.header().next_bp_hash(),
         block_merkle_tree.root(),
         None,
+        BuildId::default(),
     );
     (chunk, merkle_paths, receipts, block)
 }
@@ -1729,3 +1741,33 @@ pub fn run_catchup(
     }
     Ok(result)
 }
+
+/// Subscribes to the state changes of the accounts made by the blocks the
+/// client accepts from now on.
+pub fn subscribe_state_changes(
+    client: &Addr<ClientActor>,
+    account_ids: impl IntoIterator<Item = AccountId>,
+) -> UnboundedReceiver<StateChangesUpdate> {
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    client
+        .do_send(SubscribeStateChanges { account_ids: account_ids.into_iter().collect(), sender });
+    receiver
+}
+
+/// Waits for a change of the subscribed accounts which the predicate accepts,
+/// `None` if there's none within the timeout.
+pub async fn wait_for_state_change(
+    receiver: &mut UnboundedReceiver<StateChangesUpdate>,
+    timeout: Duration,
+    mut predicate: impl FnMut(&StateChangeWithCauseView) -> bool,
+) -> Option<StateChangeWithCauseView> {
+    let wait = async {
+        while let Some(update) = receiver.recv().await {
+            if let Some(change) = update.changes.into_iter().find(|change| predicate(change)) {
+                return Some(change);
+            }
+        }
+        None
+    };
+    tokio::time::timeout(timeout, wait).await.ok().flatten()
+}
@@ -21,12 +21,12 @@ use near_network_primitives::types::{
     NetworkViewClientMessages, NetworkViewClientResponses, PeerInfo,
 };
 use near_primitives::block::{Block, BlockHeader};
-use near_primitives::time::Utc;
+use near_primitives::time::{Clock, Utc};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, BlockId, BlockReference, EpochId};
 use near_primitives::utils::to_timestamp;
 use near_primitives::validator_signer::InMemoryValidatorSigner;
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use near_primitives::views::{FinalExecutionOutcomeViewEnum, QueryRequest, QueryResponseKind};
 use num_rational::Rational;
 
@@ -55,6 +55,26 @@ fn query_client() {
     });
 }
 
+/// Queries which didn't start before their deadline fail with a timeout.
+#[test]
+fn query_client_deadline() {
+    init_test_logger();
+    run_actix(async {
+        let (_, view_client) =
+            setup_no_network(vec!["test".parse().unwrap()], "other".parse().unwrap(), true, true);
+        let query = Query::new(
+            BlockReference::latest(),
+            QueryRequest::ViewAccount { account_id: "test".parse().unwrap() },
+        )
+        .with_deadline(Clock::instant());
+        actix::spawn(view_client.send(query).then(|res| {
+            assert!(matches!(res.unwrap(), Err(QueryError::Timeout)));
+            System::current().stop();
+            future::ready(())
+        }));
+    });
+}
+
 /// When we receive health check and the latest block's timestamp is in the future, the client
 /// should not crash.
 #[test]
@@ -90,6 +110,7 @@ fn query_status_not_crash() {
                 block.header.next_bp_hash,
                 block_merkle_tree.root(),
                 None,
+                BuildId::default(),
             );
             next_block.mut_header().get_mut().inner_lite.timestamp =
                 to_timestamp(next_block.header().timestamp() + chrono::Duration::seconds(60));
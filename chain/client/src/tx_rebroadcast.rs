@@ -0,0 +1,166 @@
+//! Rebroadcast of the transactions forwarded by the node which are stuck
+//! because their shard misses chunks.
+//!
+//! A node which doesn't produce the chunks of a shard forwards the transactions
+//! of that shard it receives to the chunk producers of the next few heights.
+//! If those miss their chunks the transactions wait in pools which may never
+//! get to produce one, while the chunk producers of the later heights, which
+//! may be other validators, never heard of them.  The forwarded transactions
+//! are remembered, and once their shard goes `missed_heights` heights without a
+//! chunk after they were sent they're forwarded again, to the chunk producers
+//! of the heights after the head then.
+//!
+//! A transaction is forgotten once it's seen in a chunk of a shard the node
+//! tracks, once it's been rebroadcast `max_rebroadcasts` times, or once the
+//! transaction validity period passed since it was first forwarded.  The
+//! transactions of the shards the node doesn't track can't be seen in their
+//! chunks, those included in a chunk are only rebroadcast if the shard misses
+//! chunks again, and the chunk producers drop them as already processed.
+
+use near_chain_configs::TxRebroadcastConfig;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{BlockHeight, BlockHeightDelta, ShardId};
+
+use crate::metrics;
+
+/// Transactions remembered at most, the ones forwarded the longest ago are
+/// forgotten first.
+const MAX_FORWARDED_TRANSACTIONS: usize = 10_000;
+
+struct ForwardedTransaction {
+    tx: SignedTransaction,
+    shard_id: ShardId,
+    /// Height of the head when the transaction was first forwarded.
+    forwarded_at: BlockHeight,
+    /// Height of the head when the transaction was last sent.
+    sent_at: BlockHeight,
+    rebroadcasts: u32,
+}
+
+pub(crate) struct ForwardedTransactions {
+    transactions: lru::LruCache<CryptoHash, ForwardedTransaction>,
+}
+
+impl ForwardedTransactions {
+    pub fn new() -> Self {
+        Self { transactions: lru::LruCache::new(MAX_FORWARDED_TRANSACTIONS) }
+    }
+
+    /// Remembers a transaction forwarded at the head `height`, unless it
+    /// already is.
+    pub fn insert(&mut self, tx: &SignedTransaction, shard_id: ShardId, height: BlockHeight) {
+        let hash = tx.get_hash();
+        if self.transactions.contains(&hash) {
+            return;
+        }
+        self.transactions.put(
+            hash,
+            ForwardedTransaction {
+                tx: tx.clone(),
+                shard_id,
+                forwarded_at: height,
+                sent_at: height,
+                rebroadcasts: 0,
+            },
+        );
+    }
+
+    /// Forgets the transactions included in a chunk.
+    pub fn remove_included(&mut self, transactions: &[SignedTransaction]) {
+        for tx in transactions {
+            self.transactions.pop(&tx.get_hash());
+        }
+    }
+
+    /// The transactions to send again at the head `height`, given the heights
+    /// the last chunks of the shards were included at, indexed by shard id.
+    /// Forgets the expired transactions and the ones rebroadcast for the last
+    /// time.
+    pub fn take_stuck(
+        &mut self,
+        height: BlockHeight,
+        heights_included: &[BlockHeight],
+        config: &TxRebroadcastConfig,
+        transaction_validity_period: BlockHeightDelta,
+    ) -> Vec<SignedTransaction> {
+        let mut stuck = vec![];
+        let mut forgotten = vec![];
+        for (hash, forwarded) in self.transactions.iter_mut() {
+            if height > forwarded.forwarded_at + transaction_validity_period {
+                forgotten.push(*hash);
+                continue;
+            }
+            let height_included = match heights_included.get(forwarded.shard_id as usize) {
+                Some(height_included) => *height_included,
+                None => continue,
+            };
+            if height_included > forwarded.sent_at
+                || height < forwarded.sent_at + config.missed_heights
+            {
+                continue;
+            }
+            forwarded.sent_at = height;
+            forwarded.rebroadcasts += 1;
+            stuck.push(forwarded.tx.clone());
+            if forwarded.rebroadcasts >= config.max_rebroadcasts {
+                forgotten.push(*hash);
+            }
+        }
+        for hash in forgotten {
+            self.transactions.pop(&hash);
+        }
+        metrics::TRANSACTIONS_REBROADCAST_TOTAL.inc_by(stuck.len() as u64);
+        stuck
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{InMemorySigner, KeyType};
+
+    use super::*;
+
+    fn transaction(nonce: u64) -> SignedTransaction {
+        let signer = InMemorySigner::from_seed("alice".parse().unwrap(), KeyType::ED25519, "alice");
+        SignedTransaction::send_money(
+            nonce,
+            "alice".parse().unwrap(),
+            "bob".parse().unwrap(),
+            &signer,
+            1,
+            CryptoHash::default(),
+        )
+    }
+
+    fn nonces(txs: Vec<SignedTransaction>) -> Vec<u64> {
+        let mut nonces: Vec<u64> = txs.iter().map(|tx| tx.transaction.nonce).collect();
+        nonces.sort();
+        nonces
+    }
+
+    #[test]
+    fn test_take_stuck() {
+        let config = TxRebroadcastConfig { enabled: None, missed_heights: 3, max_rebroadcasts: 2 };
+        let mut forwarded = ForwardedTransactions::new();
+        let txs: Vec<_> = (1..=3).map(transaction).collect();
+        forwarded.insert(&txs[0], 0, 10);
+        forwarded.insert(&txs[1], 1, 10);
+        forwarded.insert(&txs[2], 0, 10);
+
+        // Shard 1 included a chunk after the transactions were sent.
+        assert!(forwarded.take_stuck(12, &[10, 11], &config, 100).is_empty());
+        assert_eq!(nonces(forwarded.take_stuck(13, &[10, 11], &config, 100)), vec![1, 3]);
+        // Sent again at 13, the shard has to miss chunks up to 16 once more.
+        assert!(forwarded.take_stuck(15, &[10, 11], &config, 100).is_empty());
+        forwarded.remove_included(&txs[2..]);
+        assert_eq!(nonces(forwarded.take_stuck(16, &[10, 11], &config, 100)), vec![1]);
+        // Rebroadcast twice already.
+        assert!(forwarded.take_stuck(19, &[10, 11], &config, 100).is_empty());
+        // Forwarding a forgotten transaction again remembers it anew.
+        forwarded.insert(&txs[0], 0, 19);
+        assert_eq!(nonces(forwarded.take_stuck(22, &[10, 11], &config, 100)), vec![1]);
+        // Expired, although shard 1 misses chunks since the second one was sent.
+        assert!(forwarded.take_stuck(200, &[0, 0], &config, 100).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+//! Cache of the block and chunk views served by the view client.
+//!
+//! Public RPC nodes are asked for the same final block and its chunks over and
+//! over, and every request reads and deserializes them from the database.  The
+//! views of final blocks and of the chunks created at final heights are kept in
+//! an LRU cache shared by the view client threads, up to a budget of bytes
+//! measured as the size of their JSON encoding, which is what RPC serves.
+//! Entries are keyed by block and chunk hash, so the views they hold never go
+//! stale.  Views of non-final blocks aren't cached, a reorg would leave orphaned
+//! blocks taking space.
+//!
+//! The views cached have the shape of the `GetBlock` and `GetChunk` responses,
+//! the options of the RPC changing them are served by separate messages.  A
+//! chunk looked up through its block has the header included in the block,
+//! which differs from the stored one, so the two are cached separately.
+
+use lru::LruCache;
+use near_primitives::hash::CryptoHash;
+use near_primitives::sharding::ChunkHash;
+use near_primitives::views::{BlockView, ChunkView};
+
+use crate::metrics;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ViewCacheKey {
+    Block(CryptoHash),
+    Chunk { chunk_hash: ChunkHash, of_block: bool },
+}
+
+#[derive(Clone)]
+enum CachedView {
+    Block(BlockView),
+    Chunk(ChunkView),
+}
+
+pub(crate) struct ViewCache {
+    entries: LruCache<ViewCacheKey, (CachedView, u64)>,
+    size_bytes: u64,
+    budget_bytes: u64,
+}
+
+impl ViewCache {
+    /// A budget of 0 turns the cache off.
+    pub fn new(budget_bytes: u64) -> Self {
+        Self { entries: LruCache::unbounded(), size_bytes: 0, budget_bytes }
+    }
+
+    pub fn get_block(&mut self, block_hash: &CryptoHash) -> Option<BlockView> {
+        match self.get(&ViewCacheKey::Block(*block_hash), "block") {
+            Some(CachedView::Block(view)) => Some(view),
+            _ => None,
+        }
+    }
+
+    /// `of_block` tells whether the chunk is looked up through its block.
+    pub fn get_chunk(&mut self, chunk_hash: &ChunkHash, of_block: bool) -> Option<ChunkView> {
+        let key = ViewCacheKey::Chunk { chunk_hash: chunk_hash.clone(), of_block };
+        match self.get(&key, "chunk") {
+            Some(CachedView::Chunk(view)) => Some(view),
+            _ => None,
+        }
+    }
+
+    pub fn put_block(&mut self, view: &BlockView) {
+        let size = serde_json::to_vec(view).map_or(0, |json| json.len() as u64);
+        self.put(ViewCacheKey::Block(view.header.hash), CachedView::Block(view.clone()), size);
+    }
+
+    pub fn put_chunk(&mut self, view: &ChunkView, of_block: bool) {
+        let size = serde_json::to_vec(view).map_or(0, |json| json.len() as u64);
+        let key = ViewCacheKey::Chunk { chunk_hash: view.header.chunk_hash.into(), of_block };
+        self.put(key, CachedView::Chunk(view.clone()), size);
+    }
+
+    fn get(&mut self, key: &ViewCacheKey, kind: &str) -> Option<CachedView> {
+        if self.budget_bytes == 0 {
+            return None;
+        }
+        let view = self.entries.get(key).map(|(view, _)| view.clone());
+        let counter =
+            if view.is_some() { &metrics::VIEW_CACHE_HITS } else { &metrics::VIEW_CACHE_MISSES };
+        counter.with_label_values(&[kind]).inc();
+        view
+    }
+
+    fn put(&mut self, key: ViewCacheKey, view: CachedView, size: u64) {
+        if size > self.budget_bytes {
+            return;
+        }
+        if let Some((_, old_size)) = self.entries.put(key, (view, size)) {
+            self.size_bytes -= old_size;
+        }
+        self.size_bytes += size;
+        while self.size_bytes > self.budget_bytes {
+            match self.entries.pop_lru() {
+                Some((_, (_, size))) => self.size_bytes -= size,
+                None => break,
+            }
+        }
+        metrics::VIEW_CACHE_SIZE_BYTES.set(self.size_bytes as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_chain::test_utils::setup;
+    use near_primitives::block::Block;
+
+    fn block_view(block: &Block) -> BlockView {
+        BlockView::from_author_block("test".parse().unwrap(), block.clone())
+    }
+
+    #[test]
+    fn test_view_cache() {
+        let (chain, _, signer) = setup();
+        let genesis = chain.genesis_block().clone();
+        let first = block_view(&Block::empty(&genesis, &*signer));
+        let second = block_view(&Block::empty_with_height(&genesis, 2, &*signer));
+        let size = serde_json::to_vec(&first).unwrap().len() as u64;
+
+        // Room for a single block.
+        let mut cache = ViewCache::new(size + size / 2);
+        cache.put_block(&first);
+        assert_eq!(cache.get_block(&first.header.hash).unwrap().header.height, 1);
+        cache.put_block(&second);
+        assert!(cache.get_block(&first.header.hash).is_none());
+        assert_eq!(cache.get_block(&second.header.hash).unwrap().header.height, 2);
+
+        let mut cache = ViewCache::new(0);
+        cache.put_block(&first);
+        assert!(cache.get_block(&first.header.hash).is_none());
+    }
+}
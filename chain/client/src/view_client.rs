@@ -3,12 +3,14 @@
 
 use near_primitives::time::Clock;
 use std::cmp::Ordering;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::hash::Hash;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
-use actix::{Actor, Addr, Handler, SyncArbiter, SyncContext};
+use actix::dev::MessageResponse;
+use actix::{Actor, Addr, Handler, Message, SyncArbiter, SyncContext};
+use borsh::BorshDeserialize;
 use tracing::{debug, error, info, trace, warn};
 
 use near_chain::types::ValidatorInfoIdentifier;
@@ -18,13 +20,16 @@ use near_chain::{
 };
 use near_chain_configs::{ClientConfig, ProtocolConfigView};
 use near_client_primitives::types::{
-    Error, GetBlock, GetBlockError, GetBlockHash, GetBlockProof, GetBlockProofError,
-    GetBlockProofResponse, GetBlockWithMerkleTree, GetChunkError, GetExecutionOutcome,
+    CheckTxBlockHash, CheckTxBlockHashError, Error, GetBlock, GetBlockError, GetBlockHash,
+    GetBlockHeaders, GetBlockProof, GetBlockProofError, GetBlockProofResponse,
+    GetBlockWithMerkleTree, GetChunkError, GetChunkSummaries, GetExecutionOutcome,
     GetExecutionOutcomeError, GetExecutionOutcomesForBlock, GetGasPrice, GetGasPriceError,
-    GetNextLightClientBlockError, GetProtocolConfig, GetProtocolConfigError, GetReceipt,
-    GetReceiptError, GetStateChangesError, GetStateChangesWithCauseInBlock,
-    GetStateChangesWithCauseInBlockForTrackedShards, GetValidatorInfoError, Query, QueryError,
-    TxStatus, TxStatusError,
+    GetLightClientBlocks, GetLightClientBlocksResponse, GetNextLightClientBlockError,
+    GetPendingReceipts, GetPendingReceiptsError, GetProducerVersions, GetProtocolConfig,
+    GetProtocolConfigError, GetReceipt, GetReceiptError, GetStateChangesError,
+    GetStateChangesWithCauseInBlock, GetStateChangesWithCauseInBlockForTrackedShards,
+    GetStateFingerprint, GetTxConstructionInfo, GetValidatorInfoError, Query, QueryError, TxStatus,
+    TxStatusError, ValidateTx, ValidateTxError, WithRequestId,
 };
 use near_network::types::{NetworkRequests, PeerManagerAdapter, PeerManagerMessageRequest};
 #[cfg(feature = "test_features")]
@@ -36,28 +41,37 @@ use near_network_primitives::types::{
 use near_performance_metrics_macros::{perf, perf_with_debug};
 use near_primitives::block::{Block, BlockHeader, GenesisId, Tip};
 use near_primitives::hash::CryptoHash;
+use near_primitives::light_client::light_client_block_hash;
 use near_primitives::merkle::{merklize, PartialMerkleTree};
 use near_primitives::network::AnnounceAccount;
-use near_primitives::sharding::ShardChunk;
+use near_primitives::receipt::{DelayedReceiptIndices, Receipt, ReceiptEnum};
+use near_primitives::sharding::{ChunkHash, ShardChunk};
 use near_primitives::syncing::{
     ShardStateSyncResponse, ShardStateSyncResponseHeader, ShardStateSyncResponseV1,
     ShardStateSyncResponseV2,
 };
+use near_primitives::transaction::Action;
+use near_primitives::trie_key::TrieKey;
 use near_primitives::types::{
     AccountId, BlockHeight, BlockId, BlockReference, EpochId, EpochReference, Finality,
     MaybeBlockId, ShardId, TransactionOrReceiptId,
 };
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
 use near_primitives::views::{
-    BlockView, ChunkView, EpochValidatorInfo, ExecutionOutcomeWithIdView,
-    FinalExecutionOutcomeView, FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView,
-    LightClientBlockView, QueryRequest, QueryResponse, ReceiptView, StateChangesKindsView,
-    StateChangesView,
+    BlockHeaderView, BlockView, ChunkSummaryView, ChunkView, EpochValidatorInfo,
+    EpochValidatorsPreview, ExecutionOutcomeWithIdView, FinalExecutionOutcomeView,
+    FinalExecutionOutcomeViewEnum, FinalExecutionStatus, GasPriceView, LightClientBlockView,
+    PendingReceiptSource, PendingReceiptView, PendingReceiptsView, ProducerVersionsView,
+    QueryRequest, QueryResponse, QueryResponseKind, ReceiptView, StateChangesKindsView,
+    StateChangesView, StateFingerprintView, TxConstructionInfoView, TxValidationView,
+    ViewAccountsResult,
 };
 
+use crate::disk_space::is_disk_space_low;
+use crate::view_cache::ViewCache;
 use crate::{
-    sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered,
+    metrics, sync, GetChunk, GetExecutionOutcomeResponse, GetNextLightClientBlock, GetStateChanges,
+    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, GetValidatorsPreview,
 };
 
 /// Max number of queries that we keep.
@@ -67,6 +81,9 @@ const REQUEST_WAIT_TIME: u64 = 1000;
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
+/// Max number of chunks read to summarize the chunks of a block.
+const MAX_CHUNK_SUMMARIES: usize = 32;
+
 /// Request and response manager across all instances of ViewClientActor.
 pub struct ViewClientRequestManager {
     /// Transaction query that needs to be forwarded to other shards
@@ -110,6 +127,8 @@ pub struct ViewClientActor {
     pub config: ClientConfig,
     request_manager: Arc<RwLock<ViewClientRequestManager>>,
     state_request_cache: Arc<Mutex<VecDeque<Instant>>>,
+    /// Views of final blocks and chunks, shared by the view client threads.
+    view_cache: Arc<Mutex<ViewCache>>,
 }
 
 impl ViewClientRequestManager {
@@ -135,6 +154,7 @@ impl ViewClientActor {
         network_adapter: Arc<dyn PeerManagerAdapter>,
         config: ClientConfig,
         request_manager: Arc<RwLock<ViewClientRequestManager>>,
+        view_cache: Arc<Mutex<ViewCache>>,
         #[cfg(feature = "test_features")] adv: Arc<RwLock<AdversarialControls>>,
     ) -> Result<Self, Error> {
         // TODO: should we create shared ChainStore that is passed to both Client and ViewClient?
@@ -154,9 +174,50 @@ impl ViewClientActor {
             config,
             request_manager,
             state_request_cache: Arc::new(Mutex::new(VecDeque::default())),
+            view_cache,
         })
     }
 
+    fn get_cached_chunk(&self, chunk_hash: &ChunkHash) -> Option<ChunkView> {
+        self.view_cache.lock().expect(POISONED_LOCK_ERR).get_chunk(chunk_hash, false)
+    }
+
+    fn get_cached_chunk_of_block(&self, block: &Block, shard_id: ShardId) -> Option<ChunkView> {
+        let chunk_hash = block.chunks().get(shard_id as usize)?.chunk_hash();
+        self.view_cache.lock().expect(POISONED_LOCK_ERR).get_chunk(&chunk_hash, true)
+    }
+
+    /// Whether the block at the height is final and canonical, for its views
+    /// to be cached.
+    fn is_final_block(&mut self, height: BlockHeight, block_hash: &CryptoHash) -> bool {
+        match self.chain.final_head() {
+            Ok(final_head) if height <= final_head.height => self
+                .chain
+                .get_block_hash_by_height(height)
+                .map_or(false, |hash| hash == *block_hash),
+            _ => false,
+        }
+    }
+
+    /// Fails with `UnknownBlock` unless the state changes of the block are
+    /// still kept, which can be for longer than the block itself.
+    fn check_state_changes_kept(
+        &mut self,
+        block_hash: &CryptoHash,
+    ) -> Result<(), GetStateChangesError> {
+        let height = self.chain.get_block_header(block_hash)?.height();
+        let retained_from = self.chain.store().state_changes_retained_from()?;
+        if height < retained_from {
+            return Err(GetStateChangesError::UnknownBlock {
+                error_message: format!(
+                    "state changes of block #{} were garbage collected, they're kept from #{}",
+                    height, retained_from
+                ),
+            });
+        }
+        Ok(())
+    }
+
     fn maybe_block_id_to_block_hash(
         &mut self,
         block_id: MaybeBlockId,
@@ -205,6 +266,30 @@ impl ViewClientActor {
     }
 
     fn handle_query(&mut self, msg: Query) -> Result<QueryResponse, QueryError> {
+        match &msg.request {
+            QueryRequest::CallFunctionWithOverrides { .. } => {
+                if !self.config.allow_view_call_overrides {
+                    return Err(QueryError::CallOverridesDisabled);
+                }
+            }
+            QueryRequest::ViewState { .. } => {
+                if is_disk_space_low() {
+                    return Err(QueryError::InternalError {
+                        error_message: "the node is low on disk space, state views are disabled"
+                            .to_string(),
+                    });
+                }
+            }
+            QueryRequest::ViewAccounts { account_ids } => {
+                if account_ids.len() > self.config.view_accounts_limit {
+                    return Err(QueryError::TooManyAccounts {
+                        requested: account_ids.len(),
+                        limit: self.config.view_accounts_limit,
+                    });
+                }
+            }
+            _ => {}
+        }
         let header = match msg.block_reference {
             BlockReference::BlockId(BlockId::Height(block_height)) => {
                 self.chain.get_header_by_height(block_height)
@@ -254,12 +339,60 @@ impl ViewClientActor {
             QueryRequest::ViewAccessKey { account_id, .. } => account_id,
             QueryRequest::ViewAccessKeyList { account_id, .. } => account_id,
             QueryRequest::CallFunction { account_id, .. } => account_id,
+            QueryRequest::CallFunctionWithOverrides { account_id, .. } => account_id,
             QueryRequest::ViewCode { account_id, .. } => account_id,
+            QueryRequest::ViewAccounts { account_ids } => {
+                return self.view_accounts(&header, account_ids);
+            }
         };
         let shard_id =
             self.runtime_adapter
                 .account_id_to_shard_id(account_id, header.epoch_id())
                 .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
+        self.query_shard(&header, shard_id, &msg.request)
+    }
+
+    /// Views the accounts shard by shard, so that the state of each shard is
+    /// set up once, all of them at the same block.
+    fn view_accounts(
+        &mut self,
+        header: &BlockHeader,
+        account_ids: &[AccountId],
+    ) -> Result<QueryResponse, QueryError> {
+        let mut shard_accounts: BTreeMap<ShardId, Vec<AccountId>> = BTreeMap::new();
+        for account_id in account_ids {
+            let shard_id = self
+                .runtime_adapter
+                .account_id_to_shard_id(account_id, header.epoch_id())
+                .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
+            shard_accounts.entry(shard_id).or_default().push(account_id.clone());
+        }
+        let mut accounts = BTreeMap::new();
+        for (shard_id, account_ids) in shard_accounts {
+            let request = QueryRequest::ViewAccounts { account_ids };
+            match self.query_shard(header, shard_id, &request)?.kind {
+                QueryResponseKind::ViewAccounts(result) => accounts.extend(result.accounts),
+                kind => {
+                    return Err(QueryError::Unreachable {
+                        error_message: format!("view_accounts got {:?}", kind),
+                    })
+                }
+            }
+        }
+        Ok(QueryResponse {
+            kind: QueryResponseKind::ViewAccounts(ViewAccountsResult { accounts }),
+            block_height: header.height(),
+            block_hash: *header.hash(),
+        })
+    }
+
+    /// Runs the query against the state of the shard after the block.
+    fn query_shard(
+        &mut self,
+        header: &BlockHeader,
+        shard_id: ShardId,
+        request: &QueryRequest,
+    ) -> Result<QueryResponse, QueryError> {
         let shard_uid = self
             .runtime_adapter
             .shard_id_to_uid(shard_id, header.epoch_id())
@@ -299,7 +432,7 @@ impl ViewClientActor {
             header.prev_hash(),
             header.hash(),
             header.epoch_id(),
-            &msg.request,
+            request,
         ) {
             Ok(query_response) => Ok(query_response),
             Err(query_error) => Err(match query_error {
@@ -349,6 +482,100 @@ impl ViewClientActor {
         }
     }
 
+    /// Receipts for the account which aren't executed yet at the head.
+    ///
+    /// Receipts sent in a block are executed by the next chunk of the shard of
+    /// their receiver, so the ones sent since its last chunk are in flight,
+    /// and the ones which didn't fit into the chunks since are in the delayed
+    /// receipts queue of the shard.  The queue is scanned up to
+    /// `pending_receipts_scan_limit` entries, and only the blocks of the
+    /// current epoch are looked at.
+    fn pending_receipts(
+        &mut self,
+        account_id: &AccountId,
+    ) -> Result<PendingReceiptsView, GetPendingReceiptsError> {
+        let head = self.chain.head()?;
+        let header = self.chain.get_block_header(&head.last_block_hash)?.clone();
+        let shard_id =
+            self.runtime_adapter.account_id_to_shard_id(account_id, header.epoch_id())?;
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, header.epoch_id())?;
+        let chunk_extra = self.chain.get_chunk_extra(header.hash(), &shard_uid).map_err(|err| {
+            match err.kind() {
+                near_chain::near_chain_primitives::ErrorKind::DBNotFoundErr(_) => {
+                    GetPendingReceiptsError::UntrackedShard {
+                        account_id: account_id.clone(),
+                        shard_id,
+                    }
+                }
+                _ => err.into(),
+            }
+        })?;
+        let state_root = *chunk_extra.state_root();
+        let trie = self.runtime_adapter.get_view_trie_for_shard(shard_id, header.prev_hash())?;
+        let read = |key: TrieKey| -> Result<Option<Vec<u8>>, GetPendingReceiptsError> {
+            trie.get(&state_root, &key.to_vec())
+                .map_err(|err| GetPendingReceiptsError::IOError(err.to_string()))
+        };
+        let decode_err =
+            |err: std::io::Error| GetPendingReceiptsError::Unreachable(err.to_string());
+
+        let mut receipts = vec![];
+        let indices = match read(TrieKey::DelayedReceiptIndices)? {
+            Some(bytes) => DelayedReceiptIndices::try_from_slice(&bytes).map_err(decode_err)?,
+            None => DelayedReceiptIndices::default(),
+        };
+        let scan_end = std::cmp::min(
+            indices.next_available_index,
+            indices.first_index.saturating_add(self.config.pending_receipts_scan_limit),
+        );
+        for index in indices.first_index..scan_end {
+            if let Some(bytes) = read(TrieKey::DelayedReceipt { index })? {
+                let receipt = Receipt::try_from_slice(&bytes).map_err(decode_err)?;
+                if &receipt.receiver_id == account_id {
+                    receipts.extend(pending_receipt_view(receipt, PendingReceiptSource::Delayed));
+                }
+            }
+        }
+
+        let num_shards = self.runtime_adapter.num_shards(header.epoch_id())?;
+        let mut block_header = header.clone();
+        loop {
+            for from_shard_id in 0..num_shards {
+                // Only known for the shards the node tracks.
+                let outgoing = match self
+                    .chain
+                    .mut_store()
+                    .get_outgoing_receipts(block_header.hash(), from_shard_id)
+                {
+                    Ok(outgoing) => outgoing.clone(),
+                    Err(_) => continue,
+                };
+                for receipt in outgoing {
+                    if &receipt.receiver_id == account_id {
+                        receipts
+                            .extend(pending_receipt_view(receipt, PendingReceiptSource::InFlight));
+                    }
+                }
+            }
+            if block_header.chunk_mask().get(shard_id as usize) == Some(&true) {
+                break;
+            }
+            match self.chain.get_block_header(block_header.prev_hash()) {
+                Ok(prev_header) if prev_header.epoch_id() == header.epoch_id() => {
+                    block_header = prev_header.clone();
+                }
+                _ => break,
+            }
+        }
+
+        Ok(PendingReceiptsView {
+            block_hash: *header.hash(),
+            block_height: header.height(),
+            receipts,
+            truncated: scan_end < indices.next_available_index,
+        })
+    }
+
     fn request_receipt_outcome(
         &mut self,
         receipt_id: CryptoHash,
@@ -514,6 +741,10 @@ impl ViewClientActor {
     }
 
     fn check_state_sync_request(&self) -> bool {
+        if is_disk_space_low() {
+            debug!(target: "sync", "Not serving state sync requests, the disk space is low");
+            return false;
+        }
         let mut cache = self.state_request_cache.lock().expect(POISONED_LOCK_ERR);
         let now = Clock::instant();
         let cutoff = now - self.config.view_client_throttle_period;
@@ -528,17 +759,88 @@ impl ViewClientActor {
         cache.push_back(now);
         true
     }
+
+    /// Returns the next light client block, given the last block known to the light client.
+    /// There are three cases:
+    ///  1. The last block known to the light client is in the same epoch as the tip:
+    ///     - Then return the last known final block, as long as it's more recent that the last known
+    ///  2. The last block known to the light client is in the epoch preceding that of the tip:
+    ///     - Same as above
+    ///  3. Otherwise, return the last final block in the epoch that follows that of the last block known
+    ///     to the light client
+    fn next_light_client_block(
+        &mut self,
+        last_epoch_id: &EpochId,
+        last_next_epoch_id: &EpochId,
+        last_height: BlockHeight,
+    ) -> Result<Option<LightClientBlockView>, near_chain::Error> {
+        let head = self.chain.head()?;
+
+        if last_epoch_id == &head.epoch_id || last_next_epoch_id == &head.epoch_id {
+            let head_header = self.chain.get_block_header(&head.last_block_hash)?;
+            let ret = Chain::create_light_client_block(
+                &head_header.clone(),
+                &*self.runtime_adapter,
+                self.chain.mut_store(),
+            )?;
+
+            if ret.inner_lite.height <= last_height {
+                Ok(None)
+            } else {
+                Ok(Some(ret))
+            }
+        } else {
+            match self.chain.mut_store().get_epoch_light_client_block(&last_next_epoch_id.0) {
+                Ok(light_block) => Ok(Some(light_block.clone())),
+                Err(e) => {
+                    if let ErrorKind::DBNotFoundErr(_) = e.kind() {
+                        Ok(None)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl Actor for ViewClientActor {
     type Context = SyncContext<Self>;
 }
 
+impl<M> Handler<WithRequestId<M>> for ViewClientActor
+where
+    M: Message,
+    Self: Handler<M>,
+    <Self as Handler<M>>::Result: MessageResponse<Self, WithRequestId<M>>,
+{
+    type Result = <Self as Handler<M>>::Result;
+
+    fn handle(&mut self, msg: WithRequestId<M>, ctx: &mut Self::Context) -> Self::Result {
+        let _span =
+            tracing::info_span!(target: "client", "rpc_request", request_id = %msg.request_id)
+                .entered();
+        <Self as Handler<M>>::handle(self, msg.msg, ctx)
+    }
+}
+
 impl Handler<Query> for ViewClientActor {
     type Result = Result<QueryResponse, QueryError>;
 
     #[perf]
     fn handle(&mut self, msg: Query, _: &mut Self::Context) -> Self::Result {
+        let query_type: &'static str = (&msg.request).into();
+        let now = Clock::instant();
+        metrics::VIEW_CLIENT_QUERY_WAIT_TIME
+            .with_label_values(&[query_type])
+            .observe(now.saturating_duration_since(msg.created).as_secs_f64());
+        if msg.deadline.map_or(false, |deadline| now >= deadline) {
+            metrics::VIEW_CLIENT_QUERY_ABANDONED_TOTAL.with_label_values(&[query_type]).inc();
+            return Err(QueryError::Timeout);
+        }
+        let _timer = metrics::VIEW_CLIENT_QUERY_EXECUTION_TIME
+            .with_label_values(&[query_type])
+            .start_timer();
         self.handle_query(msg)
     }
 }
@@ -549,33 +851,32 @@ impl Handler<GetBlock> for ViewClientActor {
 
     #[perf]
     fn handle(&mut self, msg: GetBlock, _: &mut Self::Context) -> Self::Result {
-        let block = match msg.0 {
-            BlockReference::Finality(finality) => {
-                let block_hash = self.get_block_hash_by_finality(&finality)?;
-                self.chain.get_block(&block_hash).map(Clone::clone)
-            }
+        let block_hash = match msg.0 {
+            BlockReference::Finality(finality) => self.get_block_hash_by_finality(&finality)?,
             BlockReference::BlockId(BlockId::Height(height)) => {
-                self.chain.get_block_by_height(height).map(Clone::clone)
+                self.chain.get_block_hash_by_height(height)?
             }
-            BlockReference::BlockId(BlockId::Hash(hash)) => {
-                self.chain.get_block(&hash).map(Clone::clone)
-            }
-            BlockReference::SyncCheckpoint(sync_checkpoint) => {
-                if let Some(block_hash) =
-                    self.get_block_hash_by_sync_checkpoint(&sync_checkpoint)?
-                {
-                    self.chain.get_block(&block_hash).map(Clone::clone)
-                } else {
-                    return Err(GetBlockError::NotSyncedYet);
-                }
-            }
-        }?;
+            BlockReference::BlockId(BlockId::Hash(hash)) => hash,
+            BlockReference::SyncCheckpoint(sync_checkpoint) => self
+                .get_block_hash_by_sync_checkpoint(&sync_checkpoint)?
+                .ok_or(GetBlockError::NotSyncedYet)?,
+        };
+        if let Some(view) = self.view_cache.lock().expect(POISONED_LOCK_ERR).get_block(&block_hash)
+        {
+            return Ok(view);
+        }
+        let block = self.chain.get_block(&block_hash)?.clone();
 
         let block_author = self
             .runtime_adapter
             .get_block_producer(block.header().epoch_id(), block.header().height())?;
 
-        Ok(BlockView::from_author_block(block_author, block))
+        let is_final = self.is_final_block(block.header().height(), &block_hash);
+        let view = BlockView::from_author_block(block_author, block);
+        if is_final {
+            self.view_cache.lock().expect(POISONED_LOCK_ERR).put_block(&view);
+        }
+        Ok(view)
     }
 }
 
@@ -604,6 +905,34 @@ impl Handler<GetBlockHash> for ViewClientActor {
     }
 }
 
+/// Handles retrieving block headers from the chain.  Only the header columns
+/// are read, never the blocks.
+impl Handler<GetBlockHeaders> for ViewClientActor {
+    type Result = Result<Vec<Option<BlockHeaderView>>, GetBlockError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetBlockHeaders, _: &mut Self::Context) -> Self::Result {
+        match msg {
+            GetBlockHeaders::Range { from_height, to_height } => (from_height..=to_height)
+                .map(|height| match self.chain.get_header_by_height(height) {
+                    Ok(header) => Ok(Some(BlockHeaderView::from(header.clone()))),
+                    Err(err) => match err.kind() {
+                        ErrorKind::DBNotFoundErr(_) => Ok(None),
+                        _ => Err(GetBlockError::from(err)),
+                    },
+                })
+                .collect(),
+            GetBlockHeaders::Hashes(hashes) => hashes
+                .iter()
+                .map(|hash| -> Result<_, GetBlockError> {
+                    let header = self.chain.get_block_header(hash)?;
+                    Ok(Some(BlockHeaderView::from(header.clone())))
+                })
+                .collect(),
+        }
+    }
+}
+
 impl Handler<GetBlockWithMerkleTree> for ViewClientActor {
     type Result = Result<(BlockView, PartialMerkleTree), GetBlockError>;
 
@@ -643,14 +972,26 @@ impl Handler<GetChunk> for ViewClientActor {
             })
         };
 
+        let of_block = !matches!(msg, GetChunk::ChunkHash(_));
         let chunk = match msg {
-            GetChunk::ChunkHash(chunk_hash) => self.chain.get_chunk(&chunk_hash)?.clone(),
+            GetChunk::ChunkHash(chunk_hash) => {
+                if let Some(view) = self.get_cached_chunk(&chunk_hash) {
+                    return Ok(view);
+                }
+                self.chain.get_chunk(&chunk_hash)?.clone()
+            }
             GetChunk::BlockHash(block_hash, shard_id) => {
                 let block = self.chain.get_block(&block_hash)?.clone();
+                if let Some(view) = self.get_cached_chunk_of_block(&block, shard_id) {
+                    return Ok(view);
+                }
                 get_chunk_from_block(block, shard_id, &mut self.chain)?
             }
             GetChunk::Height(height, shard_id) => {
                 let block = self.chain.get_block_by_height(height)?.clone();
+                if let Some(view) = self.get_cached_chunk_of_block(&block, shard_id) {
+                    return Ok(view);
+                }
                 get_chunk_from_block(block, shard_id, &mut self.chain)?
             }
         };
@@ -664,7 +1005,67 @@ impl Handler<GetChunk> for ViewClientActor {
             chunk_inner.shard_id(),
         )?;
 
-        Ok(ChunkView::from_author_chunk(author, chunk))
+        let is_final = self
+            .chain
+            .final_head()
+            .map_or(false, |final_head| chunk_inner.height_created() <= final_head.height);
+        let view = ChunkView::from_author_chunk(author, chunk);
+        if is_final {
+            self.view_cache.lock().expect(POISONED_LOCK_ERR).put_chunk(&view, of_block);
+        }
+        Ok(view)
+    }
+}
+
+/// Reads the chunks of the block the node has, up to `MAX_CHUNK_SUMMARIES`
+/// of them, and counts what's in them.
+impl Handler<GetChunkSummaries> for ViewClientActor {
+    type Result = Result<Vec<ChunkSummaryView>, GetBlockError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetChunkSummaries, _: &mut Self::Context) -> Self::Result {
+        let block = self.chain.get_block(&msg.block_hash)?.clone();
+        let mut summaries = Vec::with_capacity(block.chunks().len());
+        for chunk_header in block.chunks().iter() {
+            if summaries.len() >= MAX_CHUNK_SUMMARIES {
+                summaries.push(ChunkSummaryView::default());
+                continue;
+            }
+            let summary = match self.chain.get_chunk(&chunk_header.chunk_hash()) {
+                Ok(chunk) => ChunkSummaryView {
+                    num_transactions: Some(chunk.transactions().len() as u64),
+                    num_receipts: Some(chunk.receipts().len() as u64),
+                },
+                Err(_) => ChunkSummaryView::default(),
+            };
+            summaries.push(summary);
+        }
+        Ok(summaries)
+    }
+}
+
+impl Handler<GetStateFingerprint> for ViewClientActor {
+    type Result = Result<StateFingerprintView, GetBlockError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetStateFingerprint, _: &mut Self::Context) -> Self::Result {
+        Ok(self.chain.mut_store().get_state_fingerprint(&*self.runtime_adapter, &msg.block_hash)?)
+    }
+}
+
+impl Handler<GetProducerVersions> for ViewClientActor {
+    type Result = Result<ProducerVersionsView, String>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetProducerVersions, _: &mut Self::Context) -> Self::Result {
+        let head = self.chain.head().map_err(|err| err.to_string())?;
+        near_chain::producer_versions::producer_versions(
+            self.chain.mut_store(),
+            &*self.runtime_adapter,
+            &head.last_block_hash,
+            msg.last_n_blocks,
+        )
+        .map_err(|err| err.to_string())
     }
 }
 
@@ -677,6 +1078,48 @@ impl Handler<TxStatus> for ViewClientActor {
     }
 }
 
+impl Handler<ValidateTx> for ViewClientActor {
+    type Result = Result<TxValidationView, ValidateTxError>;
+
+    #[perf]
+    fn handle(&mut self, msg: ValidateTx, _: &mut Self::Context) -> Self::Result {
+        let tx = &msg.transaction;
+        let head = self.chain.head()?;
+        let head_header = self.chain.head_header()?.clone();
+        let mut violations = vec![];
+        let transaction_validity_period = self.chain.transaction_validity_period;
+        if let Err(err) = self.chain.mut_store().check_transaction_validity_period(
+            &head_header,
+            &tx.transaction.block_hash,
+            transaction_validity_period,
+        ) {
+            violations.push(err);
+        }
+        let epoch_id = self.runtime_adapter.get_epoch_id_from_prev_block(&head.last_block_hash)?;
+        let protocol_version = self.runtime_adapter.get_epoch_protocol_version(&epoch_id)?;
+        let shard_id =
+            self.runtime_adapter.account_id_to_shard_id(&tx.transaction.signer_id, &epoch_id)?;
+        let shard_uid = self.runtime_adapter.shard_id_to_uid(shard_id, &epoch_id)?;
+        // Nodes which don't track the shard of the signer have no state to
+        // check the account and access key against.
+        let state_root = self
+            .chain
+            .get_chunk_extra(&head.last_block_hash, &shard_uid)
+            .ok()
+            .map(|chunk_extra| *chunk_extra.state_root());
+        let result = self.runtime_adapter.validate_tx_full(
+            head_header.gas_price(),
+            state_root,
+            tx,
+            &epoch_id,
+            protocol_version,
+        )?;
+        let state_checked = state_root.is_some() && result.gas_burnt.is_some();
+        violations.extend(result.violations);
+        Ok(TxValidationView { violations, gas_burnt: result.gas_burnt, state_checked })
+    }
+}
+
 impl Handler<GetValidatorInfo> for ViewClientActor {
     type Result = Result<EpochValidatorInfo, GetValidatorInfoError>;
 
@@ -738,12 +1181,24 @@ impl Handler<GetValidatorOrdered> for ViewClientActor {
             })?)
     }
 }
+
+impl Handler<GetValidatorsPreview> for ViewClientActor {
+    type Result = Result<EpochValidatorsPreview, GetValidatorInfoError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetValidatorsPreview, _: &mut Self::Context) -> Self::Result {
+        let block_hash = self.maybe_block_id_to_block_hash(msg.block_id)?;
+        Ok(self.runtime_adapter.get_validators_preview(&block_hash)?)
+    }
+}
+
 /// Returns a list of change kinds per account in a store for a given block.
 impl Handler<GetStateChangesInBlock> for ViewClientActor {
     type Result = Result<StateChangesKindsView, GetStateChangesError>;
 
     #[perf]
     fn handle(&mut self, msg: GetStateChangesInBlock, _: &mut Self::Context) -> Self::Result {
+        self.check_state_changes_kept(&msg.block_hash)?;
         Ok(self
             .chain
             .store()
@@ -760,6 +1215,7 @@ impl Handler<GetStateChanges> for ViewClientActor {
 
     #[perf]
     fn handle(&mut self, msg: GetStateChanges, _: &mut Self::Context) -> Self::Result {
+        self.check_state_changes_kept(&msg.block_hash)?;
         Ok(self
             .chain
             .store()
@@ -780,6 +1236,7 @@ impl Handler<GetStateChangesWithCauseInBlock> for ViewClientActor {
         msg: GetStateChangesWithCauseInBlock,
         _: &mut Self::Context,
     ) -> Self::Result {
+        self.check_state_changes_kept(&msg.block_hash)?;
         Ok(self
             .chain
             .store()
@@ -801,6 +1258,7 @@ impl Handler<GetStateChangesWithCauseInBlockForTrackedShards> for ViewClientActo
         msg: GetStateChangesWithCauseInBlockForTrackedShards,
         _: &mut Self::Context,
     ) -> Self::Result {
+        self.check_state_changes_kept(&msg.block_hash)?;
         let state_changes_with_cause_in_block =
             self.chain.store().get_state_changes_with_cause_in_block(&msg.block_hash)?;
 
@@ -844,33 +1302,37 @@ impl Handler<GetNextLightClientBlock> for ViewClientActor {
         let last_epoch_id = last_block_header.epoch_id().clone();
         let last_next_epoch_id = last_block_header.next_epoch_id().clone();
         let last_height = last_block_header.height();
-        let head = self.chain.head()?;
+        Ok(self.next_light_client_block(&last_epoch_id, &last_next_epoch_id, last_height)?)
+    }
+}
 
-        if last_epoch_id == head.epoch_id || last_next_epoch_id == head.epoch_id {
-            let head_header = self.chain.get_block_header(&head.last_block_hash)?;
-            let ret = Chain::create_light_client_block(
-                &head_header.clone(),
-                &*self.runtime_adapter,
-                self.chain.mut_store(),
-            )?;
+/// Returns the light client blocks a light client needs to catch up, as if it
+/// asked for the next light client block until there is none.  When there are
+/// more than `limit`, the hash of the last one returned is the cursor to ask
+/// for the next ones.
+impl Handler<GetLightClientBlocks> for ViewClientActor {
+    type Result = Result<GetLightClientBlocksResponse, GetNextLightClientBlockError>;
 
-            if ret.inner_lite.height <= last_height {
-                Ok(None)
-            } else {
-                Ok(Some(ret))
-            }
-        } else {
-            match self.chain.mut_store().get_epoch_light_client_block(&last_next_epoch_id.0) {
-                Ok(light_block) => Ok(Some(light_block.clone())),
-                Err(e) => {
-                    if let ErrorKind::DBNotFoundErr(_) = e.kind() {
-                        Ok(None)
-                    } else {
-                        Err(e.into())
-                    }
-                }
+    #[perf]
+    fn handle(&mut self, msg: GetLightClientBlocks, _: &mut Self::Context) -> Self::Result {
+        let last_block_header = self.chain.get_block_header(&msg.last_block_hash)?;
+        let mut last_epoch_id = last_block_header.epoch_id().clone();
+        let mut last_next_epoch_id = last_block_header.next_epoch_id().clone();
+        let mut last_height = last_block_header.height();
+        let mut blocks = vec![];
+        while let Some(block) =
+            self.next_light_client_block(&last_epoch_id, &last_next_epoch_id, last_height)?
+        {
+            if blocks.len() >= msg.limit {
+                let next_cursor = blocks.last().map(light_client_block_hash);
+                return Ok(GetLightClientBlocksResponse { blocks, next_cursor });
             }
+            last_epoch_id = EpochId(block.inner_lite.epoch_id);
+            last_next_epoch_id = EpochId(block.inner_lite.next_epoch_id);
+            last_height = block.inner_lite.height;
+            blocks.push(block);
         }
+        Ok(GetLightClientBlocksResponse { blocks, next_cursor: None })
     }
 }
 
@@ -984,15 +1446,102 @@ impl Handler<GetReceipt> for ViewClientActor {
     }
 }
 
+impl Handler<GetPendingReceipts> for ViewClientActor {
+    type Result = Result<PendingReceiptsView, GetPendingReceiptsError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetPendingReceipts, _: &mut Self::Context) -> Self::Result {
+        self.pending_receipts(&msg.account_id)
+    }
+}
+
+impl Handler<CheckTxBlockHash> for ViewClientActor {
+    type Result = Result<(), CheckTxBlockHashError>;
+
+    #[perf]
+    fn handle(&mut self, msg: CheckTxBlockHash, _: &mut Self::Context) -> Self::Result {
+        let head = self.chain.head()?;
+        let validity_period = self.chain.transaction_validity_period;
+        let block_height = match self.chain.get_block_header(&msg.block_hash) {
+            Ok(header) => header.height(),
+            Err(err) => match err.kind() {
+                near_chain::near_chain_primitives::ErrorKind::DBNotFoundErr(_) => {
+                    // Headers are never garbage collected, so a node with a
+                    // recent header head knows all the blocks a transaction
+                    // can be based on.  One which is behind may just not have
+                    // received the block yet.
+                    let header_head = self.chain.header_head()?;
+                    let header_head_time =
+                        self.chain.get_block_header(&header_head.last_block_hash)?.timestamp();
+                    let behind = (Clock::utc() - header_head_time).to_std().unwrap_or_default();
+                    if behind > self.config.max_block_production_delay {
+                        return Ok(());
+                    }
+                    return Err(CheckTxBlockHashError::UnknownBlock { block_hash: msg.block_hash });
+                }
+                _ => return Err(err.into()),
+            },
+        };
+        if head.height > block_height.saturating_add(validity_period) {
+            return Err(CheckTxBlockHashError::Expired {
+                block_hash: msg.block_hash,
+                block_height,
+                head_height: head.height,
+                validity_period,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Handler<GetTxConstructionInfo> for ViewClientActor {
+    type Result = Result<TxConstructionInfoView, QueryError>;
+
+    #[perf]
+    fn handle(&mut self, msg: GetTxConstructionInfo, _: &mut Self::Context) -> Self::Result {
+        // The key is viewed at the final head and the transaction is built
+        // against the same block, so that the two never disagree.
+        let query = Query::new(
+            BlockReference::Finality(Finality::Final),
+            QueryRequest::ViewAccessKey { account_id: msg.account_id, public_key: msg.public_key },
+        );
+        let response = self.handle_query(query)?;
+        let nonce = match response.kind {
+            QueryResponseKind::AccessKey(access_key) => access_key.nonce,
+            kind => {
+                return Err(QueryError::Unreachable {
+                    error_message: format!("Access key query returned {:?}", kind),
+                })
+            }
+        };
+        let head = self
+            .chain
+            .head()
+            .map_err(|err| QueryError::InternalError { error_message: err.to_string() })?;
+        // The blocks since the final one already count towards the validity
+        // period of the transaction.
+        let validity_blocks_remaining = self
+            .chain
+            .transaction_validity_period
+            .saturating_sub(head.height.saturating_sub(response.block_height));
+        Ok(TxConstructionInfoView {
+            nonce,
+            recommended_nonce: nonce + 1,
+            block_hash: response.block_hash,
+            block_height: response.block_height,
+            validity_blocks_remaining,
+        })
+    }
+}
+
 impl Handler<GetBlockProof> for ViewClientActor {
     type Result = Result<GetBlockProofResponse, GetBlockProofError>;
 
     #[perf]
     fn handle(&mut self, msg: GetBlockProof, _: &mut Self::Context) -> Self::Result {
-        self.chain.check_block_final_and_canonical(&msg.block_hash)?;
-        self.chain.check_block_final_and_canonical(&msg.head_block_hash)?;
+        let block_proof =
+            self.chain.get_block_ancestry_proof(&msg.block_hash, &msg.head_block_hash)?;
         let block_header_lite = self.chain.get_block_header(&msg.block_hash)?.clone().into();
-        let block_proof = self.chain.get_block_proof(&msg.block_hash, &msg.head_block_hash)?;
         Ok(GetBlockProofResponse { block_header_lite, proof: block_proof })
     }
 }
@@ -1163,14 +1712,18 @@ impl Handler<NetworkViewClientMessages> for ViewClientActor {
                 Ok(head) => {
                     match self.runtime_adapter.num_shards(&head.epoch_id) {
                         Ok(num_shards) => {
-                            // convert config tracked shards
-                            // runtime will track all shards if config tracked shards is not empty
-                            // https://github.com/near/nearcore/issues/4930
-                            let tracked_shards = if self.config.tracked_shards.is_empty() {
-                                vec![]
-                            } else {
-                                (0..num_shards).collect()
-                            };
+                            // The shards tracked as a validator and the ones
+                            // tracked by the config, as the runtime decides.
+                            let tracked_shards = (0..num_shards)
+                                .filter(|&shard_id| {
+                                    self.runtime_adapter.cares_about_shard(
+                                        self.validator_account_id.as_ref(),
+                                        &head.last_block_hash,
+                                        shard_id,
+                                        true,
+                                    )
+                                })
+                                .collect();
                             NetworkViewClientResponses::ChainInfo {
                                 genesis_id: GenesisId {
                                     chain_id: self.config.chain_id.clone(),
@@ -1388,6 +1941,40 @@ impl Handler<GetGasPrice> for ViewClientActor {
 }
 
 /// Starts the View Client in a new arbiter (thread).
+/// The view of an action receipt, `None` for data receipts.
+fn pending_receipt_view(
+    receipt: Receipt,
+    source: PendingReceiptSource,
+) -> Option<PendingReceiptView> {
+    let action = match receipt.receipt {
+        ReceiptEnum::Action(action) => action,
+        ReceiptEnum::Data(_) => return None,
+    };
+    Some(PendingReceiptView {
+        receipt_id: receipt.receipt_id,
+        predecessor_id: receipt.predecessor_id,
+        source,
+        actions: action.actions.iter().map(action_summary).collect(),
+        deposit: action.actions.iter().map(Action::get_deposit_balance).sum(),
+        gas: action.actions.iter().map(Action::get_prepaid_gas).sum(),
+    })
+}
+
+fn action_summary(action: &Action) -> String {
+    match action {
+        Action::CreateAccount(_) => "CreateAccount".to_string(),
+        Action::DeployContract(_) => "DeployContract".to_string(),
+        Action::FunctionCall(call) => format!("FunctionCall({})", call.method_name),
+        Action::Transfer(_) => "Transfer".to_string(),
+        Action::Stake(_) => "Stake".to_string(),
+        Action::AddKey(_) => "AddKey".to_string(),
+        Action::DeleteKey(_) => "DeleteKey".to_string(),
+        Action::DeleteAccount(_) => "DeleteAccount".to_string(),
+        #[cfg(feature = "protocol_feature_chunk_only_producers")]
+        Action::StakeChunkOnly(_) => "StakeChunkOnly".to_string(),
+    }
+}
+
 pub fn start_view_client(
     validator_account_id: Option<AccountId>,
     chain_genesis: ChainGenesis,
@@ -1397,6 +1984,7 @@ pub fn start_view_client(
     #[cfg(feature = "test_features")] adv: Arc<RwLock<AdversarialControls>>,
 ) -> Addr<ViewClientActor> {
     let request_manager = Arc::new(RwLock::new(ViewClientRequestManager::new()));
+    let view_cache = Arc::new(Mutex::new(ViewCache::new(config.view_cache_size_bytes)));
     SyncArbiter::start(config.view_client_threads, move || {
         // ViewClientActor::start_in_arbiter(&Arbiter::current(), move |_ctx| {
         let validator_account_id1 = validator_account_id.clone();
@@ -1411,6 +1999,7 @@ pub fn start_view_client(
             network_adapter1,
             config1,
             request_manager1,
+            view_cache.clone(),
             #[cfg(feature = "test_features")]
             adv.clone(),
         )
@@ -20,7 +20,8 @@ use near_primitives::types::{
 };
 use near_primitives::version::{ProtocolVersion, UPGRADABILITY_FIX_PROTOCOL_VERSION};
 use near_primitives::views::{
-    CurrentEpochValidatorInfo, EpochValidatorInfo, NextEpochValidatorInfo, ValidatorKickoutView,
+    CurrentEpochValidatorInfo, EpochValidatorInfo, EpochValidatorsPreview, NextEpochValidatorInfo,
+    ValidatorKickoutView,
 };
 use near_store::{DBCol, Store, StoreUpdate};
 
@@ -238,10 +239,7 @@ impl EpochManager {
         self.save_epoch_info(&mut store_update, prev_epoch_id, Arc::new(prev_epoch_info))?;
         self.save_epoch_info(&mut store_update, epoch_id, Arc::new(epoch_info))?;
         self.save_epoch_info(&mut store_update, next_epoch_id, Arc::new(next_epoch_info))?;
-        // TODO #3488
-        // put unreachable! here to avoid warnings
-        unreachable!();
-        // Ok(store_update)
+        Ok(store_update)
     }
 
     /// # Parameters
@@ -456,13 +454,34 @@ impl EpochManager {
         rng_seed: RngSeed,
     ) -> Result<(), EpochError> {
         let epoch_summary = self.collect_blocks_info(block_info, last_block_hash)?;
+        self.save_epoch_validator_info(store_update, block_info.epoch_id(), &epoch_summary)?;
+        let next_next_epoch_info =
+            self.compute_next_next_epoch_info(block_info, epoch_summary, rng_seed)?;
+        let next_next_epoch_id = EpochId(*last_block_hash);
+        debug!(target: "epoch_manager", "next next epoch height: {}, id: {:?}, protocol version: {} shard layout: {:?}",
+               next_next_epoch_info.epoch_height(),
+               &next_next_epoch_id,
+               next_next_epoch_info.protocol_version(),
+               self.config.for_protocol_version(next_next_epoch_info.protocol_version()).shard_layout);
+        // This epoch info is computed for the epoch after next (T+2),
+        // where epoch_id of it is the hash of last block in this epoch (T).
+        self.save_epoch_info(store_update, &next_next_epoch_id, Arc::new(next_next_epoch_info))?;
+        Ok(())
+    }
+
+    /// Computes the epoch info of epoch T + 2 from the summary of epoch T ending at `block_info`.
+    fn compute_next_next_epoch_info(
+        &mut self,
+        block_info: &BlockInfo,
+        epoch_summary: EpochSummary,
+        rng_seed: RngSeed,
+    ) -> Result<EpochInfo, EpochError> {
         let epoch_info = self.get_epoch_info(block_info.epoch_id())?;
         let epoch_protocol_version = epoch_info.protocol_version();
         let validator_stake =
             epoch_info.validators_iter().map(|r| r.account_and_stake()).collect::<HashMap<_, _>>();
         let next_epoch_id = self.get_next_epoch_id_from_info(block_info)?;
         let next_epoch_info = self.get_epoch_info(&next_epoch_id)?.clone();
-        self.save_epoch_validator_info(store_update, block_info.epoch_id(), &epoch_summary)?;
 
         let EpochSummary {
             all_proposals,
@@ -489,7 +508,7 @@ impl EpochManager {
             )
         };
         let next_next_epoch_config = self.config.for_protocol_version(next_version);
-        let next_next_epoch_info = match proposals_to_epoch_info(
+        match proposals_to_epoch_info(
             next_next_epoch_config,
             rng_seed,
             &next_epoch_info,
@@ -500,31 +519,21 @@ impl EpochManager {
             next_version,
             epoch_protocol_version,
         ) {
-            Ok(next_next_epoch_info) => next_next_epoch_info,
+            Ok(next_next_epoch_info) => Ok(next_next_epoch_info),
             Err(EpochError::ThresholdError { stake_sum, num_seats }) => {
                 warn!(target: "epoch_manager", "Not enough stake for required number of seats (all validators tried to unstake?): amount = {} for {}", stake_sum, num_seats);
                 let mut epoch_info = EpochInfo::clone(&next_epoch_info);
                 *epoch_info.epoch_height_mut() += 1;
-                epoch_info
+                Ok(epoch_info)
             }
             Err(EpochError::NotEnoughValidators { num_validators, num_shards }) => {
                 warn!(target: "epoch_manager", "Not enough validators for required number of shards (all validators tried to unstake?): num_validators={} num_shards={}", num_validators, num_shards);
                 let mut epoch_info = EpochInfo::clone(&next_epoch_info);
                 *epoch_info.epoch_height_mut() += 1;
-                epoch_info
+                Ok(epoch_info)
             }
-            Err(err) => return Err(err),
-        };
-        let next_next_epoch_id = EpochId(*last_block_hash);
-        debug!(target: "epoch_manager", "next next epoch height: {}, id: {:?}, protocol version: {} shard layout: {:?}",
-               next_next_epoch_info.epoch_height(),
-               &next_next_epoch_id,
-               next_next_epoch_info.protocol_version(),
-               self.config.for_protocol_version(next_next_epoch_info.protocol_version()).shard_layout);
-        // This epoch info is computed for the epoch after next (T+2),
-        // where epoch_id of it is the hash of last block in this epoch (T).
-        self.save_epoch_info(store_update, &next_next_epoch_id, Arc::new(next_next_epoch_info))?;
-        Ok(())
+            Err(err) => Err(err),
+        }
     }
 
     pub fn record_block_info(
@@ -889,6 +898,41 @@ impl EpochManager {
         Ok(*self.get_block_info(&epoch_first_block)?.height())
     }
 
+    /// Heights of the first blocks of the epochs garbage collection keeps the data of when the
+    /// head is the block with hash `block_hash`: the epoch of the block and the
+    /// `num_epochs_to_keep - 1` epochs before it, latest first.  Garbage collection deletes the
+    /// data below the last one, see [`Self::get_gc_stop_height`].
+    pub fn get_gc_epoch_start_heights(
+        &self,
+        block_hash: &CryptoHash,
+        num_epochs_to_keep: u64,
+    ) -> Result<Vec<BlockHeight>, EpochError> {
+        // an epoch must have a first block.
+        let epoch_first_block = *self.get_block_info(block_hash)?.epoch_first_block();
+        let epoch_first_block_info = self.get_block_info(&epoch_first_block)?;
+        let mut last_block_in_prev_epoch = *epoch_first_block_info.prev_hash();
+        let mut start_heights = vec![*epoch_first_block_info.height()];
+        for _ in 1..num_epochs_to_keep {
+            let epoch_first_block =
+                *self.get_block_info(&last_block_in_prev_epoch)?.epoch_first_block();
+            let epoch_first_block_info = self.get_block_info(&epoch_first_block)?;
+            start_heights.push(*epoch_first_block_info.height());
+            last_block_in_prev_epoch = *epoch_first_block_info.prev_hash();
+        }
+        Ok(start_heights)
+    }
+
+    /// Height below which garbage collection deletes the data when the head is the block with
+    /// hash `block_hash`.
+    pub fn get_gc_stop_height(
+        &self,
+        block_hash: &CryptoHash,
+        num_epochs_to_keep: u64,
+    ) -> Result<BlockHeight, EpochError> {
+        let start_heights = self.get_gc_epoch_start_heights(block_hash, num_epochs_to_keep)?;
+        Ok(*start_heights.last().unwrap())
+    }
+
     /// Compute stake return info based on the last block hash of the epoch that is just finalized
     /// return the hashmap of account id to max_of_stakes, which is used in the calculation of account
     /// updates.
@@ -1109,48 +1153,77 @@ impl EpochManager {
         };
 
         let next_epoch_info = self.get_epoch_info(&next_epoch_id)?;
-        let mut next_validator_to_shard = (0..next_epoch_info.validators_len())
+        let next_validators = Self::next_epoch_validators_view(&next_epoch_info);
+        let prev_epoch_kickout = Self::validator_kickout_view(&next_epoch_info);
+
+        Ok(EpochValidatorInfo {
+            current_validators,
+            next_validators,
+            current_fishermen: cur_epoch_info.fishermen_iter().map(Into::into).collect(),
+            next_fishermen: next_epoch_info.fishermen_iter().map(Into::into).collect(),
+            current_proposals: all_proposals,
+            prev_epoch_kickout,
+            epoch_start_height,
+            epoch_height,
+        })
+    }
+
+    /// Predicts the validators of the epoch after next by running the same
+    /// computation as finalizing the current epoch would if it ended at
+    /// `block_hash`.  Nothing is saved.
+    ///
+    /// The random seed of the last block of the epoch isn't known in advance,
+    /// so the predicted assignment of validators to shards may differ from the
+    /// final one, while the set of validators and their stakes don't depend on it.
+    pub fn preview_validators(
+        &mut self,
+        block_hash: &CryptoHash,
+    ) -> Result<EpochValidatorsPreview, EpochError> {
+        let block_info = self.get_block_info(block_hash)?;
+        let epoch_summary = self.collect_blocks_info(&block_info, block_hash)?;
+        let epoch_info = self.compute_next_next_epoch_info(&block_info, epoch_summary, [0; 32])?;
+        Ok(EpochValidatorsPreview {
+            block_hash: *block_hash,
+            block_height: *block_info.height(),
+            epoch_height: epoch_info.epoch_height(),
+            predicted_validators: Self::next_epoch_validators_view(&epoch_info),
+            predicted_fishermen: epoch_info.fishermen_iter().map(Into::into).collect(),
+            predicted_seat_price: epoch_info.seat_price(),
+            predicted_kickout: Self::validator_kickout_view(&epoch_info),
+        })
+    }
+
+    fn next_epoch_validators_view(epoch_info: &EpochInfo) -> Vec<NextEpochValidatorInfo> {
+        let mut validator_to_shard = (0..epoch_info.validators_len())
             .map(|_| HashSet::default())
             .collect::<Vec<HashSet<ShardId>>>();
-        for (shard_id, validators) in
-            next_epoch_info.chunk_producers_settlement().iter().enumerate()
-        {
+        for (shard_id, validators) in epoch_info.chunk_producers_settlement().iter().enumerate() {
             for validator_id in validators {
-                next_validator_to_shard[*validator_id as usize].insert(shard_id as u64);
+                validator_to_shard[*validator_id as usize].insert(shard_id as u64);
             }
         }
-        let next_validators = next_epoch_info
+        epoch_info
             .validators_iter()
             .enumerate()
             .map(|(validator_id, info)| {
-                let mut shards = next_validator_to_shard[validator_id]
-                    .clone()
-                    .into_iter()
-                    .collect::<Vec<ShardId>>();
+                let mut shards =
+                    validator_to_shard[validator_id].clone().into_iter().collect::<Vec<ShardId>>();
                 shards.sort();
                 let (account_id, public_key, stake) = info.destructure();
                 NextEpochValidatorInfo { account_id, public_key, stake, shards }
             })
-            .collect();
-        let prev_epoch_kickout = next_epoch_info
+            .collect()
+    }
+
+    fn validator_kickout_view(epoch_info: &EpochInfo) -> Vec<ValidatorKickoutView> {
+        epoch_info
             .validator_kickout()
             .clone()
             .into_iter()
             .collect::<BTreeMap<_, _>>()
             .into_iter()
             .map(|(account_id, reason)| ValidatorKickoutView { account_id, reason })
-            .collect();
-
-        Ok(EpochValidatorInfo {
-            current_validators,
-            next_validators,
-            current_fishermen: cur_epoch_info.fishermen_iter().map(Into::into).collect(),
-            next_fishermen: next_epoch_info.fishermen_iter().map(Into::into).collect(),
-            current_proposals: all_proposals,
-            prev_epoch_kickout,
-            epoch_start_height,
-            epoch_height,
-        })
+            .collect()
     }
 
     /// Compare two epoch ids based on their start height. This works because finality gadget
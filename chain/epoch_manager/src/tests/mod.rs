@@ -173,6 +173,43 @@ fn test_validator_change_of_stake() {
     );
 }
 
+/// The preview made before the last block of the epoch matches the epoch
+/// info computed once the epoch is finalized.
+#[test]
+fn test_preview_validators() {
+    let amount_staked = 1_000_000;
+    let validators =
+        vec![("test1".parse().unwrap(), amount_staked), ("test2".parse().unwrap(), amount_staked)];
+    let mut epoch_manager =
+        setup_epoch_manager(validators, 2, 1, 2, 0, 90, 60, 100, default_reward_calculator());
+
+    let h = hash_range(4);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    record_block(&mut epoch_manager, h[0], h[1], 1, vec![stake("test1".parse().unwrap(), 10)]);
+    let preview = epoch_manager.preview_validators(&h[1]).unwrap();
+    assert_eq!(preview.block_hash, h[1]);
+    assert_eq!(preview.block_height, 1);
+    assert_eq!(
+        preview.predicted_validators.iter().map(|v| v.account_id.as_str()).collect::<Vec<_>>(),
+        vec!["test2"]
+    );
+    assert_eq!(
+        preview.predicted_kickout.iter().map(|k| k.account_id.as_str()).collect::<Vec<_>>(),
+        vec!["test1"]
+    );
+    // Previewing has no effect on the epoch manager.
+    assert_eq!(epoch_manager.preview_validators(&h[1]).unwrap(), preview);
+
+    record_block(&mut epoch_manager, h[1], h[2], 2, vec![]);
+    record_block(&mut epoch_manager, h[2], h[3], 3, vec![]);
+    let epoch_id = epoch_manager.get_next_epoch_id(&h[3]).unwrap();
+    let epoch_info = epoch_manager.get_epoch_info(&epoch_id).unwrap();
+    assert_eq!(preview.epoch_height, epoch_info.epoch_height());
+    assert_eq!(preview.predicted_seat_price, epoch_info.seat_price());
+    assert_eq!(preview.predicted_validators, EpochManager::next_epoch_validators_view(&epoch_info));
+    assert_eq!(preview.predicted_kickout, EpochManager::validator_kickout_view(&epoch_info));
+}
+
 /// Test handling forks across the epoch finalization.
 /// Fork with where one BP produces blocks in one chain and 2 BPs are in another chain.
 ///     |   | /--1---4------|--7---10------|---13---
@@ -2392,3 +2429,28 @@ fn test_epoch_validators_cache() {
         epoch_manager.epoch_validators_ordered_unique.get(&epoch_id).unwrap().clone();
     assert_eq!(*epoch_validators_unique, *epoch_validators_unique_in_cache);
 }
+
+#[test]
+fn test_gc_epoch_start_heights() {
+    let amount_staked = 1_000_000;
+    let validators =
+        vec![("test1".parse().unwrap(), amount_staked), ("test2".parse().unwrap(), amount_staked)];
+    let mut epoch_manager = setup_default_epoch_manager(validators, 2, 1, 10, 0, 90, 60);
+    let h = hash_range(12);
+    record_block(&mut epoch_manager, CryptoHash::default(), h[0], 0, vec![]);
+    for i in 1..12 {
+        record_block(&mut epoch_manager, h[i - 1], h[i], i as u64, vec![]);
+    }
+    // Block `h[i]` is at height `i`, so the epoch before the one starting at
+    // height `i` ends with `h[i - 1]`.
+    let mut expected = vec![epoch_manager.get_epoch_start_height(&h[11]).unwrap()];
+    for _ in 1..3 {
+        let prev_epoch_last_block = h[*expected.last().unwrap() as usize - 1];
+        expected.push(epoch_manager.get_epoch_start_height(&prev_epoch_last_block).unwrap());
+    }
+    assert!(expected.windows(2).all(|w| w[0] > w[1]));
+    assert_eq!(epoch_manager.get_gc_epoch_start_heights(&h[11], 3).unwrap(), expected);
+    assert_eq!(epoch_manager.get_gc_stop_height(&h[11], 3).unwrap(), expected[2]);
+    // There aren't that many epochs before the genesis.
+    assert!(epoch_manager.get_gc_stop_height(&h[11], 100).is_err());
+}
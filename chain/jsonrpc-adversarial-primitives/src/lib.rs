@@ -34,6 +34,12 @@ pub struct SetAdvOptionsRequest {
     pub disable_edge_pruning: Option<bool>,
 }
 
+#[cfg(feature = "ser_de")]
+#[derive(Deserialize)]
+pub struct SetNetworkFaultsRequest {
+    pub faults: Vec<near_network_primitives::types::NetworkFault>,
+}
+
 #[cfg_attr(feature = "ser_de", derive(Deserialize))]
 pub struct StartRoutingTableSyncRequest {
     pub peer_id: PeerId,
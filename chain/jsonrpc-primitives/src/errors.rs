@@ -37,6 +37,7 @@ pub enum RpcErrorKind {
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcRequestValidationErrorKind {
     MethodNotFound { method_name: String },
+    MethodDisabled { method_name: String, replacement: Option<String> },
     ParseError { error_message: String },
 }
 
@@ -143,6 +144,23 @@ impl RpcError {
             )),
         }
     }
+
+    /// Create an error for a method the node operator disabled, with the
+    /// method to use instead if there's one.
+    pub fn method_disabled(method: String, replacement: Option<String>) -> Self {
+        let message = match &replacement {
+            Some(replacement) => format!("Method {} is disabled, use {}", method, replacement),
+            None => format!("Method {} is disabled", method),
+        };
+        RpcError {
+            code: -32_601,
+            message: "Method disabled".to_owned(),
+            data: Some(Value::String(message)),
+            error_struct: Some(RpcErrorKind::RequestValidationError(
+                RpcRequestValidationErrorKind::MethodDisabled { method_name: method, replacement },
+            )),
+        }
+    }
 }
 
 impl fmt::Display for RpcError {
@@ -66,11 +66,23 @@ impl Request {
     ///
     /// The ID is taken from the request.
     pub fn reply(&self, reply: Value) -> Message {
-        Message::Response(Response { jsonrpc: Version, result: Ok(reply), id: self.id.clone() })
+        Message::Response(Response {
+            jsonrpc: Version,
+            result: Ok(reply),
+            id: self.id.clone(),
+            warning: None,
+            request_id: None,
+        })
     }
     /// Answer the request with an error.
     pub fn error(&self, error: RpcError) -> Message {
-        Message::Response(Response { jsonrpc: Version, result: Err(error), id: self.id.clone() })
+        Message::Response(Response {
+            jsonrpc: Version,
+            result: Err(error),
+            id: self.id.clone(),
+            warning: None,
+            request_id: None,
+        })
     }
 }
 
@@ -82,17 +94,30 @@ pub struct Response {
     jsonrpc: Version,
     pub result: Result<Value, RpcError>,
     pub id: Value,
+    /// Not part of JSON RPC 2.0.  Set for responses to methods which are to be
+    /// removed, to tell the caller what to use instead.
+    pub warning: Option<String>,
+    /// Not part of JSON RPC 2.0.  Set for errors, to the id of the request
+    /// the logs of the node carry, for the caller to report it.
+    pub request_id: Option<String>,
 }
 
 impl Serialize for Response {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut sub = serializer.serialize_struct("Response", 3)?;
+        let len = 3 + self.warning.is_some() as usize + self.request_id.is_some() as usize;
+        let mut sub = serializer.serialize_struct("Response", len)?;
         sub.serialize_field("jsonrpc", &self.jsonrpc)?;
         match self.result {
             Ok(ref value) => sub.serialize_field("result", value),
             Err(ref err) => sub.serialize_field("error", err),
         }?;
         sub.serialize_field("id", &self.id)?;
+        if let Some(warning) = &self.warning {
+            sub.serialize_field("warning", warning)?;
+        }
+        if let Some(request_id) = &self.request_id {
+            sub.serialize_field("request_id", request_id)?;
+        }
         sub.end()
     }
 }
@@ -117,6 +142,10 @@ struct WireResponse {
     result: Option<Value>,
     error: Option<RpcError>,
     id: Value,
+    #[serde(default)]
+    warning: Option<String>,
+    #[serde(default)]
+    request_id: Option<String>,
 }
 
 // Implementing deserialize is hard. We sidestep the difficulty by deserializing a similar
@@ -133,7 +162,13 @@ impl<'de> Deserialize<'de> for Response {
                 return Err(err);
             }
         };
-        Ok(Response { jsonrpc: Version, result, id: wr.id })
+        Ok(Response {
+            jsonrpc: Version,
+            result,
+            id: wr.id,
+            warning: wr.warning,
+            request_id: wr.request_id,
+        })
     }
 }
 
@@ -199,7 +234,13 @@ impl Message {
     }
     /// Create a top-level error (without an ID).
     pub fn error(error: RpcError) -> Self {
-        Message::Response(Response { jsonrpc: Version, result: Err(error), id: Value::Null })
+        Message::Response(Response {
+            jsonrpc: Version,
+            result: Err(error),
+            id: Value::Null,
+            warning: None,
+            request_id: None,
+        })
     }
     /// A constructor for a notification.
     pub fn notification(method: String, params: Option<Value>) -> Self {
@@ -207,7 +248,15 @@ impl Message {
     }
     /// A constructor for a response.
     pub fn response(id: Value, result: Result<Value, RpcError>) -> Self {
-        Message::Response(Response { jsonrpc: Version, result, id })
+        Message::response_with_warning(id, result, None)
+    }
+    /// A constructor for a response with a warning for the caller.
+    pub fn response_with_warning(
+        id: Value,
+        result: Result<Value, RpcError>,
+        warning: Option<String>,
+    ) -> Self {
+        Message::Response(Response { jsonrpc: Version, result, id, warning, request_id: None })
     }
     /// Returns id or Null if there is no id.
     pub fn id(&self) -> Value {
@@ -348,7 +397,13 @@ mod tests {
         // A successful response
         one(
             r#"{"jsonrpc": "2.0", "result": 42, "id": 3}"#,
-            &Message::Response(Response { jsonrpc: Version, result: Ok(json!(42)), id: json!(3) }),
+            &Message::Response(Response {
+                jsonrpc: Version,
+                result: Ok(json!(42)),
+                id: json!(3),
+                warning: None,
+                request_id: None,
+            }),
         );
         // A successful response
         one(
@@ -357,6 +412,8 @@ mod tests {
                 jsonrpc: Version,
                 result: Ok(Value::Null),
                 id: json!(3),
+                warning: None,
+                request_id: None,
             }),
         );
         // An error
@@ -366,6 +423,30 @@ mod tests {
                 jsonrpc: Version,
                 result: Err(RpcError::new(42, "Wrong!".to_owned(), None)),
                 id: Value::Null,
+                warning: None,
+                request_id: None,
+            }),
+        );
+        // A response with a warning
+        one(
+            r#"{"jsonrpc": "2.0", "result": 42, "id": 4, "warning": "Going away"}"#,
+            &Message::Response(Response {
+                jsonrpc: Version,
+                result: Ok(json!(42)),
+                id: json!(4),
+                warning: Some("Going away".to_owned()),
+                request_id: None,
+            }),
+        );
+        // An error with the id of the request
+        one(
+            r#"{"jsonrpc": "2.0", "error": {"code": 42, "message": "Wrong!"}, "id": 5, "request_id": "abc"}"#,
+            &Message::Response(Response {
+                jsonrpc: Version,
+                result: Err(RpcError::new(42, "Wrong!".to_owned(), None)),
+                id: json!(5),
+                warning: None,
+                request_id: Some("abc".to_owned()),
             }),
         );
         // A batch
@@ -476,7 +557,16 @@ mod tests {
         let id1 = req1.id.clone();
         // When we answer a message, we get the same ID
         if let Message::Response(ref resp) = req1.reply(json!([1, 2, 3])) {
-            assert_eq!(*resp, Response { jsonrpc: Version, result: Ok(json!([1, 2, 3])), id: id1 });
+            assert_eq!(
+                *resp,
+                Response {
+                    jsonrpc: Version,
+                    result: Ok(json!([1, 2, 3])),
+                    id: id1,
+                    warning: None,
+                    request_id: None,
+                }
+            );
         } else {
             panic!("Not a response");
         }
@@ -491,6 +581,8 @@ mod tests {
                     jsonrpc: Version,
                     result: Err(RpcError::new(42, "Wrong!".to_owned(), None)),
                     id: id2,
+                    warning: None,
+                    request_id: None,
                 }
             );
         } else {
@@ -506,6 +598,8 @@ mod tests {
                     jsonrpc: Version,
                     result: Err(RpcError::new(43, "Also wrong!".to_owned(), None)),
                     id: Value::Null,
+                    warning: None,
+                    request_id: None,
                 }
             );
         } else {
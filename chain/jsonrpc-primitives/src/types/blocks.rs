@@ -22,8 +22,19 @@ pub enum RpcBlockError {
 pub struct RpcBlockRequest {
     #[serde(flatten)]
     pub block_reference: near_primitives::types::BlockReference,
+    /// Whether the chunks of the block have a summary of what's in them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub include_chunk_summaries: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcStateFingerprintRequest {
+    #[serde(flatten)]
+    pub block_reference: near_primitives::types::BlockReference,
+}
+
+pub type RpcStateFingerprintResponse = near_primitives::views::StateFingerprintView;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RpcBlockResponse {
     #[serde(flatten)]
@@ -84,7 +95,30 @@ impl From<RpcBlockError> for crate::errors::RpcError {
 }
 
 impl RpcBlockRequest {
-    pub fn parse(value: Option<Value>) -> Result<RpcBlockRequest, crate::errors::RpcParseError> {
+    pub fn parse(
+        mut value: Option<Value>,
+    ) -> Result<RpcBlockRequest, crate::errors::RpcParseError> {
+        // The block reference is an enum which takes a single field.
+        let include_chunk_summaries = match value.as_mut().and_then(Value::as_object_mut) {
+            Some(params) => match params.remove("include_chunk_summaries") {
+                Some(flag) => crate::utils::parse_params::<bool>(Some(flag))?,
+                None => false,
+            },
+            None => false,
+        };
+        let block_reference = if let Ok((block_id,)) =
+            crate::utils::parse_params::<(near_primitives::types::BlockId,)>(value.clone())
+        {
+            near_primitives::types::BlockReference::BlockId(block_id)
+        } else {
+            crate::utils::parse_params::<near_primitives::types::BlockReference>(value)?
+        };
+        Ok(RpcBlockRequest { block_reference, include_chunk_summaries })
+    }
+}
+
+impl RpcStateFingerprintRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
         let block_reference = if let Ok((block_id,)) =
             crate::utils::parse_params::<(near_primitives::types::BlockId,)>(value.clone())
         {
@@ -92,6 +126,6 @@ impl RpcBlockRequest {
         } else {
             crate::utils::parse_params::<near_primitives::types::BlockReference>(value)?
         };
-        Ok(RpcBlockRequest { block_reference })
+        Ok(Self { block_reference })
     }
 }
@@ -84,3 +84,44 @@ impl From<RpcProtocolConfigError> for crate::errors::RpcError {
         Self::new_internal_or_handler_error(error_data, error_data_value)
     }
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcProtocolConfigDiffRequest {
+    pub from_protocol_version: near_primitives::types::ProtocolVersion,
+    pub to_protocol_version: near_primitives::types::ProtocolVersion,
+}
+
+impl RpcProtocolConfigDiffRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcProtocolConfigDiffRequest>(value)?)
+    }
+}
+
+pub type RpcProtocolConfigDiffResponse = near_primitives::views::RuntimeConfigDiffView;
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcProtocolConfigDiffError {
+    #[error("Protocol version {protocol_version} is above the latest one supported by the node, {latest_protocol_version}")]
+    UnknownProtocolVersion {
+        protocol_version: near_primitives::types::ProtocolVersion,
+        latest_protocol_version: near_primitives::types::ProtocolVersion,
+    },
+}
+
+impl From<RpcProtocolConfigDiffError> for crate::errors::RpcError {
+    fn from(error: RpcProtocolConfigDiffError) -> Self {
+        let error_data = Some(Value::String(error.to_string()));
+        let error_data_value = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcProtocolConfigDiffError: {:?}", err),
+                )
+            }
+        };
+
+        Self::new_internal_or_handler_error(error_data, error_data_value)
+    }
+}
@@ -0,0 +1,126 @@
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RpcHeadersRequest {
+    /// Headers of the canonical blocks at heights from `from_height` to
+    /// `to_height` inclusive.
+    Range { from_height: BlockHeight, to_height: BlockHeight },
+    /// Headers of the blocks with the given hashes.
+    Hashes { block_hashes: Vec<CryptoHash> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcHeadersResponse {
+    /// Headers in the requested order.  In range mode heights without a block
+    /// are `null`.
+    pub headers: Vec<Option<near_primitives::views::BlockHeaderView>>,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcHeadersError {
+    #[error("Block not found: {error_message}")]
+    UnknownBlock {
+        #[serde(skip_serializing)]
+        error_message: String,
+    },
+    #[error("There are no fully synchronized blocks yet")]
+    NotSyncedYet,
+    #[error("{requested} headers requested but at most {limit} can be requested at once")]
+    TooManyHeaders { requested: u64, limit: u64 },
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+}
+
+impl RpcHeadersRequest {
+    pub fn parse(value: Option<Value>) -> Result<RpcHeadersRequest, crate::errors::RpcParseError> {
+        let request = crate::utils::parse_params::<RpcHeadersRequest>(value)?;
+        if let RpcHeadersRequest::Range { from_height, to_height } = request {
+            if from_height > to_height {
+                return Err(crate::errors::RpcParseError(format!(
+                    "from_height {} is above to_height {}",
+                    from_height, to_height
+                )));
+            }
+        }
+        Ok(request)
+    }
+
+    /// Number of headers requested.
+    pub fn num_headers(&self) -> u64 {
+        match self {
+            RpcHeadersRequest::Range { from_height, to_height } => {
+                to_height.saturating_sub(*from_height).saturating_add(1)
+            }
+            RpcHeadersRequest::Hashes { block_hashes } => block_hashes.len() as u64,
+        }
+    }
+}
+
+impl From<RpcHeadersRequest> for near_client_primitives::types::GetBlockHeaders {
+    fn from(request: RpcHeadersRequest) -> Self {
+        match request {
+            RpcHeadersRequest::Range { from_height, to_height } => {
+                Self::Range { from_height, to_height }
+            }
+            RpcHeadersRequest::Hashes { block_hashes } => Self::Hashes(block_hashes),
+        }
+    }
+}
+
+impl From<near_client_primitives::types::GetBlockError> for RpcHeadersError {
+    fn from(error: near_client_primitives::types::GetBlockError) -> Self {
+        match error {
+            near_client_primitives::types::GetBlockError::UnknownBlock { error_message } => {
+                Self::UnknownBlock { error_message }
+            }
+            near_client_primitives::types::GetBlockError::NotSyncedYet => Self::NotSyncedYet,
+            near_client_primitives::types::GetBlockError::IOError { error_message } => {
+                Self::InternalError { error_message }
+            }
+            near_client_primitives::types::GetBlockError::Unreachable { ref error_message } => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcHeadersError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcHeadersError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcHeadersError> for crate::errors::RpcError {
+    fn from(error: RpcHeadersError) -> Self {
+        let error_data = match &error {
+            RpcHeadersError::UnknownBlock { error_message } => Some(Value::String(format!(
+                "DB Not Found Error: {} \n Cause: Unknown",
+                error_message
+            ))),
+            RpcHeadersError::NotSyncedYet
+            | RpcHeadersError::TooManyHeaders { .. }
+            | RpcHeadersError::InternalError { .. } => Some(Value::String(error.to_string())),
+        };
+
+        let error_data_value = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcHeadersError: {:?}", err),
+                )
+            }
+        };
+
+        Self::new_internal_or_handler_error(error_data, error_data_value)
+    }
+}
@@ -8,11 +8,30 @@ pub struct RpcLightClientExecutionProofRequest {
     pub light_client_head: near_primitives::hash::CryptoHash,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcLightClientBlockProofRequest {
+    pub block_hash: near_primitives::hash::CryptoHash,
+    pub light_client_head: near_primitives::hash::CryptoHash,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RpcLightClientNextBlockRequest {
     pub last_block_hash: near_primitives::hash::CryptoHash,
 }
 
+/// Default and maximal number of blocks of `EXPERIMENTAL_light_client_blocks`.
+pub const MAX_LIGHT_CLIENT_BLOCKS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcLightClientBlocksRequest {
+    /// The head of the light client, or the `next_cursor` of the previous
+    /// response.
+    pub last_block_hash: near_primitives::hash::CryptoHash,
+    /// Number of blocks returned at most, up to [`MAX_LIGHT_CLIENT_BLOCKS`].
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RpcLightClientExecutionProofResponse {
     pub outcome_proof: near_primitives::views::ExecutionOutcomeWithIdView,
@@ -21,12 +40,28 @@ pub struct RpcLightClientExecutionProofResponse {
     pub block_proof: near_primitives::merkle::MerklePath,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcLightClientBlockProofResponse {
+    pub block_header_lite: near_primitives::views::LightClientBlockLiteView,
+    pub block_proof: near_primitives::merkle::MerklePath,
+}
+
 #[derive(Debug, Serialize)]
 pub struct RpcLightClientNextBlockResponse {
     #[serde(flatten)]
     pub light_client_block: Option<near_primitives::views::LightClientBlockView>,
 }
 
+/// The light client blocks of the last final block of every epoch from the
+/// next epoch of the light client, ending with the last final block of the
+/// current epoch.
+#[derive(Debug, Serialize)]
+pub struct RpcLightClientBlocksResponse {
+    pub blocks: Vec<near_primitives::views::LightClientBlockView>,
+    /// Set when there are more blocks, to ask for them as `last_block_hash`.
+    pub next_cursor: Option<near_primitives::hash::CryptoHash>,
+}
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcLightClientProofError {
@@ -49,6 +84,8 @@ pub enum RpcLightClientProofError {
         transaction_or_receipt_id: near_primitives::hash::CryptoHash,
         shard_id: near_primitives::types::ShardId,
     },
+    #[error("Block {block_hash} is not on the canonical chain")]
+    NotOnCanonicalChain { block_hash: near_primitives::hash::CryptoHash },
     #[error("Internal error: {error_message}")]
     InternalError { error_message: String },
 }
@@ -73,6 +110,12 @@ impl RpcLightClientExecutionProofRequest {
     }
 }
 
+impl RpcLightClientBlockProofRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
 impl RpcLightClientNextBlockRequest {
     pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
         if let Ok((last_block_hash,)) =
@@ -85,6 +128,12 @@ impl RpcLightClientNextBlockRequest {
     }
 }
 
+impl RpcLightClientBlocksRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
 impl From<Option<near_primitives::views::LightClientBlockView>>
     for RpcLightClientNextBlockResponse
 {
@@ -132,6 +181,9 @@ impl From<near_client_primitives::types::GetBlockProofError> for RpcLightClientP
             near_client_primitives::types::GetBlockProofError::UnknownBlock { error_message } => {
                 Self::UnknownBlock { error_message }
             }
+            near_client_primitives::types::GetBlockProofError::NotOnCanonicalChain {
+                block_hash,
+            } => Self::NotOnCanonicalChain { block_hash },
             near_client_primitives::types::GetBlockProofError::InternalError { error_message } => {
                 Self::InternalError { error_message }
             }
@@ -3,6 +3,7 @@ pub mod changes;
 pub mod chunks;
 pub mod config;
 pub mod gas_price;
+pub mod headers;
 pub mod light_client;
 pub mod network_info;
 pub mod query;
@@ -66,6 +66,14 @@ pub enum RpcQueryError {
         block_height: near_primitives::types::BlockHeight,
         block_hash: near_primitives::hash::CryptoHash,
     },
+    #[error("Function calls with overrides are disabled on this node")]
+    CallOverridesDisabled,
+    #[error("Can't view {requested} accounts at once, the limit of this node is {limit}, split them into smaller batches")]
+    TooManyAccounts { requested: usize, limit: usize },
+    /// The node is overloaded, unlike the other errors this doesn't say
+    /// anything about the data.
+    #[error("The node is overloaded and didn't complete the query in time. Try again later")]
+    Timeout,
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
 }
@@ -84,9 +92,12 @@ pub enum QueryResponseKind {
     ViewAccount(near_primitives::views::AccountView),
     ViewCode(near_primitives::views::ContractCodeView),
     ViewState(near_primitives::views::ViewStateResult),
+    // Must come before `CallResult` which would otherwise match when deserializing.
+    CallResultWithGas(near_primitives::views::CallResultWithGas),
     CallResult(near_primitives::views::CallResult),
     AccessKey(near_primitives::views::AccessKeyView),
     AccessKeyList(near_primitives::views::AccessKeyList),
+    ViewAccounts(near_primitives::views::ViewAccountsResult),
 }
 
 impl RpcQueryRequest {
@@ -173,6 +184,13 @@ impl From<near_client_primitives::types::QueryError> for RpcQueryError {
                 Self::InternalError { error_message }
             }
             near_client_primitives::types::QueryError::NoSyncedBlocks => Self::NoSyncedBlocks,
+            near_client_primitives::types::QueryError::Timeout => Self::Timeout,
+            near_client_primitives::types::QueryError::CallOverridesDisabled => {
+                Self::CallOverridesDisabled
+            }
+            near_client_primitives::types::QueryError::TooManyAccounts { requested, limit } => {
+                Self::TooManyAccounts { requested, limit }
+            }
             near_client_primitives::types::QueryError::UnavailableShard { requested_shard_id } => {
                 Self::UnavailableShard { requested_shard_id }
             }
@@ -255,6 +273,12 @@ impl From<near_primitives::views::QueryResponseKind> for QueryResponseKind {
             near_primitives::views::QueryResponseKind::AccessKeyList(access_key_list) => {
                 Self::AccessKeyList(access_key_list)
             }
+            near_primitives::views::QueryResponseKind::CallResultWithGas(call_result) => {
+                Self::CallResultWithGas(call_result)
+            }
+            near_primitives::views::QueryResponseKind::ViewAccounts(accounts) => {
+                Self::ViewAccounts(accounts)
+            }
         }
     }
 }
@@ -27,6 +27,77 @@ pub enum RpcReceiptError {
     UnknownReceipt { receipt_id: near_primitives::hash::CryptoHash },
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcPendingReceiptsRequest {
+    pub account_id: near_primitives::types::AccountId,
+}
+
+/// Best effort: only the shards the node tracks are looked at, and the
+/// delayed receipts queue only up to the node's scan limit.
+pub type RpcPendingReceiptsResponse = near_primitives::views::PendingReceiptsView;
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize)]
+#[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RpcPendingReceiptsError {
+    #[error("The node reached its limits. Try again later. More details: {error_message}")]
+    InternalError { error_message: String },
+    #[error("Shard {shard_id} of account {account_id} isn't tracked by this node")]
+    UntrackedShard {
+        account_id: near_primitives::types::AccountId,
+        shard_id: near_primitives::types::ShardId,
+    },
+}
+
+impl RpcPendingReceiptsRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcPendingReceiptsRequest>(value)?)
+    }
+}
+
+impl From<near_client_primitives::types::GetPendingReceiptsError> for RpcPendingReceiptsError {
+    fn from(error: near_client_primitives::types::GetPendingReceiptsError) -> Self {
+        match error {
+            near_client_primitives::types::GetPendingReceiptsError::IOError(error_message) => {
+                Self::InternalError { error_message }
+            }
+            near_client_primitives::types::GetPendingReceiptsError::UntrackedShard {
+                account_id,
+                shard_id,
+            } => Self::UntrackedShard { account_id, shard_id },
+            near_client_primitives::types::GetPendingReceiptsError::Unreachable(
+                ref error_message,
+            ) => {
+                tracing::warn!(target: "jsonrpc", "Unreachable error occurred: {}", &error_message);
+                crate::metrics::RPC_UNREACHABLE_ERROR_COUNT
+                    .with_label_values(&["RpcPendingReceiptsError"])
+                    .inc();
+                Self::InternalError { error_message: error.to_string() }
+            }
+        }
+    }
+}
+
+impl From<actix::MailboxError> for RpcPendingReceiptsError {
+    fn from(error: actix::MailboxError) -> Self {
+        Self::InternalError { error_message: error.to_string() }
+    }
+}
+
+impl From<RpcPendingReceiptsError> for crate::errors::RpcError {
+    fn from(error: RpcPendingReceiptsError) -> Self {
+        let error_data = match serde_json::to_value(error) {
+            Ok(value) => value,
+            Err(err) => {
+                return Self::new_internal_error(
+                    None,
+                    format!("Failed to serialize RpcPendingReceiptsError: {:?}", err),
+                )
+            }
+        };
+        Self::new_internal_or_handler_error(Some(error_data.clone()), error_data)
+    }
+}
+
 impl From<ReceiptReference> for near_client_primitives::types::GetReceipt {
     fn from(receipt_reference: ReceiptReference) -> Self {
         Self { receipt_id: receipt_reference.receipt_id }
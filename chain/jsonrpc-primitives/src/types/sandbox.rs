@@ -1,11 +1,13 @@
-use near_primitives::state_record::StateRecord;
-use near_primitives::types::BlockHeightDelta;
+use near_primitives::state_record::StatePatchRecord;
+use near_primitives::types::{BlockHeightDelta, StateRoot};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct RpcSandboxPatchStateRequest {
-    pub records: Vec<StateRecord>,
+    /// Records to write and tombstones of records to remove, applied in
+    /// order within a single block.
+    pub records: Vec<StatePatchRecord>,
 }
 
 impl RpcSandboxPatchStateRequest {
@@ -15,13 +17,18 @@ impl RpcSandboxPatchStateRequest {
 }
 
 #[derive(Deserialize, Serialize, Debug)]
-pub struct RpcSandboxPatchStateResponse {}
+pub struct RpcSandboxPatchStateResponse {
+    /// State root right after the block which applied the patch.
+    pub state_root: StateRoot,
+}
 
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcSandboxPatchStateError {
     #[error("The node reached its limits. Try again later. More details: {error_message}")]
     InternalError { error_message: String },
+    #[error("Record {index} can't be patched, only Account, AccessKey, Contract and Data records can be written")]
+    UnsupportedRecord { index: usize },
 }
 
 impl From<actix::MailboxError> for RpcSandboxPatchStateError {
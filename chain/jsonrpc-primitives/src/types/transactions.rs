@@ -22,6 +22,15 @@ pub enum TransactionInfo {
     },
 }
 
+impl TransactionInfo {
+    pub fn transaction_hash(&self) -> near_primitives::hash::CryptoHash {
+        match self {
+            TransactionInfo::Transaction(tx) => tx.get_hash(),
+            TransactionInfo::TransactionId { hash, .. } => *hash,
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcTransactionError {
@@ -36,16 +45,71 @@ pub enum RpcTransactionError {
     RequestRouted { transaction_hash: near_primitives::hash::CryptoHash },
     #[error("Transaction {requested_transaction_hash} doesn't exist")]
     UnknownTransaction { requested_transaction_hash: near_primitives::hash::CryptoHash },
+    #[error("Transaction {transaction_hash} was dropped from the pool: {reason}")]
+    DroppedFromPool {
+        transaction_hash: near_primitives::hash::CryptoHash,
+        reason: near_primitives::errors::InvalidTxError,
+    },
+    #[error("Transaction {transaction_hash} expired without being executed, it was valid from height {valid_from_height} to {valid_until_height}")]
+    ExpiredNotExecuted {
+        transaction_hash: near_primitives::hash::CryptoHash,
+        valid_from_height: near_primitives::types::BlockHeight,
+        valid_until_height: near_primitives::types::BlockHeight,
+    },
     #[error("The node reached its limits. Try again later. More details: {debug_info}")]
     InternalError { debug_info: String },
     #[error("Timeout")]
     TimeoutError,
+    #[error("Transaction pool of the node is full, try again later. Limits: {limits:?}")]
+    TxPoolFull { limits: near_chain_configs::TransactionPoolConfig },
+    #[error("Transaction is based on block {block_hash}, which is {reason}")]
+    InvalidTransactionBlockHash {
+        block_hash: near_primitives::hash::CryptoHash,
+        reason: InvalidBlockHashReason,
+    },
+}
+
+/// Why the block a transaction is based on was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InvalidBlockHashReason {
+    /// The block isn't a block of this chain, the transaction was likely
+    /// signed for another network.
+    UnknownToChain,
+    /// The block is older than the transaction validity period.
+    Expired,
+}
+
+impl InvalidBlockHashReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UnknownToChain => "unknown_to_chain",
+            Self::Expired => "expired",
+        }
+    }
+}
+
+impl std::fmt::Display for InvalidBlockHashReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::UnknownToChain => "unknown to this chain, is it signed for another network?",
+            Self::Expired => "older than the transaction validity period",
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RpcTransactionResponse {
     #[serde(flatten)]
     pub final_execution_outcome: near_primitives::views::FinalExecutionOutcomeViewEnum,
+    /// How long the transaction waited in the pool of this node before it was
+    /// included in a chunk, known if this node produced or tracked the chunk.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_wait_ms: Option<u64>,
+    /// Hash of the transaction, set when its status was looked up by the
+    /// signed transaction for senders which lost the hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<near_primitives::hash::CryptoHash>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -53,6 +117,26 @@ pub struct RpcBroadcastTxSyncResponse {
     pub transaction_hash: near_primitives::hash::CryptoHash,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidateTransactionResponse {
+    #[serde(flatten)]
+    pub validation: near_primitives::views::TxValidationView,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcTxConstructionInfoRequest {
+    pub account_id: AccountId,
+    pub public_key: near_crypto::PublicKey,
+}
+
+pub type RpcTxConstructionInfoResponse = near_primitives::views::TxConstructionInfoView;
+
+impl RpcTxConstructionInfoRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<Self>(value)?)
+    }
+}
+
 impl RpcBroadcastTransactionRequest {
     pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
         let signed_transaction = crate::utils::parse_signed_transaction(value)?;
@@ -70,7 +154,19 @@ impl RpcTransactionStatusCommonRequest {
             let transaction_info = TransactionInfo::TransactionId { hash, account_id };
             Ok(Self { transaction_info })
         } else {
+            // The hash is computed from the payload, only a transaction signed
+            // by its signer can tell the status of the signer's transaction.
             let signed_transaction = crate::utils::parse_signed_transaction(value)?;
+            let hash = signed_transaction.get_hash();
+            if !signed_transaction
+                .signature
+                .verify(hash.as_ref(), &signed_transaction.transaction.public_key)
+            {
+                return Err(crate::errors::RpcParseError(format!(
+                    "Invalid signature of transaction {}",
+                    hash
+                )));
+            }
             let transaction_info = TransactionInfo::Transaction(signed_transaction);
             Ok(Self { transaction_info })
         }
@@ -89,6 +185,18 @@ impl From<near_client_primitives::types::TxStatusError> for RpcTransactionError
             near_client_primitives::types::TxStatusError::InvalidTx(context) => {
                 Self::InvalidTransaction { context }
             }
+            near_client_primitives::types::TxStatusError::DroppedFromPool { tx_hash, reason } => {
+                Self::DroppedFromPool { transaction_hash: tx_hash, reason }
+            }
+            near_client_primitives::types::TxStatusError::Expired {
+                tx_hash,
+                valid_from_height,
+                valid_until_height,
+            } => Self::ExpiredNotExecuted {
+                transaction_hash: tx_hash,
+                valid_from_height,
+                valid_until_height,
+            },
             near_client_primitives::types::TxStatusError::InternalError(debug_info) => {
                 Self::InternalError { debug_info }
             }
@@ -97,11 +205,44 @@ impl From<near_client_primitives::types::TxStatusError> for RpcTransactionError
     }
 }
 
+impl From<near_client_primitives::types::ValidateTxError> for RpcTransactionError {
+    fn from(error: near_client_primitives::types::ValidateTxError) -> Self {
+        match error {
+            near_client_primitives::types::ValidateTxError::InternalError { error_message }
+            | near_client_primitives::types::ValidateTxError::Unreachable { error_message } => {
+                Self::InternalError { debug_info: error_message }
+            }
+        }
+    }
+}
+
+impl From<near_client_primitives::types::CheckTxBlockHashError> for RpcTransactionError {
+    fn from(error: near_client_primitives::types::CheckTxBlockHashError) -> Self {
+        match error {
+            near_client_primitives::types::CheckTxBlockHashError::UnknownBlock { block_hash } => {
+                Self::InvalidTransactionBlockHash {
+                    block_hash,
+                    reason: InvalidBlockHashReason::UnknownToChain,
+                }
+            }
+            near_client_primitives::types::CheckTxBlockHashError::Expired {
+                block_hash, ..
+            } => Self::InvalidTransactionBlockHash {
+                block_hash,
+                reason: InvalidBlockHashReason::Expired,
+            },
+            near_client_primitives::types::CheckTxBlockHashError::InternalError {
+                error_message,
+            } => Self::InternalError { debug_info: error_message },
+        }
+    }
+}
+
 impl From<near_primitives::views::FinalExecutionOutcomeViewEnum> for RpcTransactionResponse {
     fn from(
         final_execution_outcome: near_primitives::views::FinalExecutionOutcomeViewEnum,
     ) -> Self {
-        Self { final_execution_outcome }
+        Self { final_execution_outcome, pool_wait_ms: None, transaction_hash: None }
     }
 }
 
@@ -4,6 +4,8 @@ use serde_json::Value;
 pub type RpcValidatorsOrderedResponse =
     Vec<near_primitives::views::validator_stake_view::ValidatorStakeView>;
 
+pub type RpcValidatorsPreviewResponse = near_primitives::views::EpochValidatorsPreview;
+
 #[derive(thiserror::Error, Debug, Serialize, Deserialize)]
 #[serde(tag = "name", content = "info", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RpcValidatorError {
@@ -26,6 +28,11 @@ pub struct RpcValidatorsOrderedRequest {
     pub block_id: near_primitives::types::MaybeBlockId,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcValidatorsPreviewRequest {
+    pub block_id: near_primitives::types::MaybeBlockId,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RpcValidatorResponse {
     #[serde(flatten)]
@@ -85,6 +92,12 @@ impl RpcValidatorsOrderedRequest {
     }
 }
 
+impl RpcValidatorsPreviewRequest {
+    pub fn parse(value: Option<Value>) -> Result<Self, crate::errors::RpcParseError> {
+        Ok(crate::utils::parse_params::<RpcValidatorsPreviewRequest>(value)?)
+    }
+}
+
 impl From<RpcValidatorError> for crate::errors::RpcError {
     fn from(error: RpcValidatorError) -> Self {
         let error_data = match &error {
@@ -10,7 +10,12 @@ use near_jsonrpc_primitives::message::{from_slice, Message};
 use near_jsonrpc_primitives::types::changes::{
     RpcStateChangesInBlockByTypeRequest, RpcStateChangesInBlockByTypeResponse,
 };
-use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
+use near_jsonrpc_primitives::types::receipts::{
+    RpcPendingReceiptsRequest, RpcPendingReceiptsResponse,
+};
+use near_jsonrpc_primitives::types::validator::{
+    RpcValidatorsOrderedRequest, RpcValidatorsPreviewRequest, RpcValidatorsPreviewResponse,
+};
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{AccountId, BlockId, BlockReference, MaybeBlockId, ShardId};
 use near_primitives::views::validator_stake_view::ValidatorStakeView;
@@ -196,6 +201,7 @@ jsonrpc_client!(pub struct JsonRpcClient {
     pub fn EXPERIMENTAL_tx_status(&self, tx: String) -> RpcRequest<serde_json::Value>;
     pub fn health(&self) -> RpcRequest<()>;
     pub fn tx(&self, hash: String, account_id: AccountId) -> RpcRequest<FinalExecutionOutcomeView>;
+    pub fn validate_tx(&self, tx: String) -> RpcRequest<serde_json::Value>;
     pub fn chunk(&self, id: ChunkId) -> RpcRequest<ChunkView>;
     pub fn validators(&self, block_id: MaybeBlockId) -> RpcRequest<EpochValidatorInfo>;
     pub fn gas_price(&self, block_id: MaybeBlockId) -> RpcRequest<GasPriceView>;
@@ -219,6 +225,20 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "query", request)
     }
 
+    /// Status of the transaction looked up by the base64 encoded signed
+    /// transaction rather than its hash.
+    pub fn tx_by_payload(&self, tx: String) -> RpcRequest<serde_json::Value> {
+        call_method(&self.client, &self.server_addr, "tx", [tx])
+    }
+
+    pub fn tx_construction_info(
+        &self,
+        request: near_jsonrpc_primitives::types::transactions::RpcTxConstructionInfoRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::transactions::RpcTxConstructionInfoResponse>
+    {
+        call_method(&self.client, &self.server_addr, "tx_construction_info", request)
+    }
+
     pub fn block_by_id(&self, block_id: BlockId) -> RpcRequest<BlockView> {
         call_method(&self.client, &self.server_addr, "block", [block_id])
     }
@@ -227,6 +247,24 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "block", request)
     }
 
+    pub fn block_with_chunk_summaries(
+        &self,
+        block_reference: BlockReference,
+    ) -> RpcRequest<BlockView> {
+        let request = near_jsonrpc_primitives::types::blocks::RpcBlockRequest {
+            block_reference,
+            include_chunk_summaries: true,
+        };
+        call_method(&self.client, &self.server_addr, "block", request)
+    }
+
+    pub fn headers(
+        &self,
+        request: near_jsonrpc_primitives::types::headers::RpcHeadersRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::headers::RpcHeadersResponse> {
+        call_method(&self.client, &self.server_addr, "headers", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_changes(
         &self,
@@ -243,6 +281,22 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_ordered", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_validators_preview(
+        &self,
+        request: RpcValidatorsPreviewRequest,
+    ) -> RpcRequest<RpcValidatorsPreviewResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_validators_preview", request)
+    }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_pending_receipts(
+        &self,
+        request: RpcPendingReceiptsRequest,
+    ) -> RpcRequest<RpcPendingReceiptsResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_pending_receipts", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_receipt(
         &self,
@@ -251,6 +305,16 @@ impl JsonRpcClient {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_receipt", request)
     }
 
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_state_fingerprint(
+        &self,
+        block_reference: BlockReference,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::blocks::RpcStateFingerprintResponse> {
+        let request =
+            near_jsonrpc_primitives::types::blocks::RpcStateFingerprintRequest { block_reference };
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_state_fingerprint", request)
+    }
+
     #[allow(non_snake_case)]
     pub fn EXPERIMENTAL_protocol_config(
         &self,
@@ -258,6 +322,14 @@ impl JsonRpcClient {
     ) -> RpcRequest<near_jsonrpc_primitives::types::config::RpcProtocolConfigResponse> {
         call_method(&self.client, &self.server_addr, "EXPERIMENTAL_protocol_config", request)
     }
+
+    #[allow(non_snake_case)]
+    pub fn EXPERIMENTAL_protocol_config_diff(
+        &self,
+        request: near_jsonrpc_primitives::types::config::RpcProtocolConfigDiffRequest,
+    ) -> RpcRequest<near_jsonrpc_primitives::types::config::RpcProtocolConfigDiffResponse> {
+        call_method(&self.client, &self.server_addr, "EXPERIMENTAL_protocol_config_diff", request)
+    }
 }
 
 fn create_client() -> Client {
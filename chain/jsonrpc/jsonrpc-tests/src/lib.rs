@@ -31,6 +31,28 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
     transaction_validity_period: NumBlocks,
     enable_doomslug: bool,
 ) -> (Addr<ViewClientActor>, String) {
+    let addr = format!("127.0.0.1:{}", open_port());
+    let view_client_addr =
+        start(node_type, transaction_validity_period, enable_doomslug, RpcConfig::new(&addr));
+    (view_client_addr, addr)
+}
+
+/// Starts the node with the RPC configured, e.g. with `listeners`, returns the
+/// address of the main server.
+pub fn start_all_with_rpc_config(
+    node_type: NodeType,
+    config: RpcConfig,
+) -> (Addr<ViewClientActor>, String) {
+    let addr = config.addr.clone();
+    (start(node_type, 100, false, config), addr)
+}
+
+fn start(
+    node_type: NodeType,
+    transaction_validity_period: NumBlocks,
+    enable_doomslug: bool,
+    config: RpcConfig,
+) -> Addr<ViewClientActor> {
     let (client_addr, view_client_addr) = setup_no_network_with_validity_period_and_no_epoch_sync(
         vec!["test1".parse().unwrap(), "test2".parse().unwrap()],
         if let NodeType::Validator = node_type {
@@ -43,13 +65,11 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
         enable_doomslug,
     );
 
-    let addr = format!("127.0.0.1:{}", open_port());
-
     #[cfg(feature = "test_features")]
     let (peer_manager_addr, routing_table_addr) = make_peer_manager_routing_table_addr_pair();
 
     start_http(
-        RpcConfig::new(&addr),
+        config,
         TEST_GENESIS_CONFIG.clone(),
         client_addr.clone(),
         view_client_addr.clone(),
@@ -58,7 +78,7 @@ pub fn start_all_with_validity_period_and_no_epoch_sync(
         #[cfg(feature = "test_features")]
         routing_table_addr,
     );
-    (view_client_addr, addr)
+    view_client_addr
 }
 
 #[macro_export]
@@ -3,7 +3,9 @@ use futures::{future, FutureExt};
 
 use near_actix_test_utils::run_actix;
 use near_jsonrpc::client::new_http_client;
+use near_jsonrpc::{RpcConfig, RpcListenerConfig};
 use near_logger_utils::init_test_logger;
+use near_network::test_utils::open_port;
 
 use near_jsonrpc_tests as test_utils;
 
@@ -26,3 +28,65 @@ fn test_status() {
         }));
     });
 }
+
+/// The GET endpoints of the methods a listener doesn't serve answer with a
+/// 404, and the reload of the validator key is only served by the listeners
+/// with `validator_key_reload`.
+#[test]
+fn test_listener_http_endpoints() {
+    init_test_logger();
+
+    run_actix(async {
+        let restricted = format!("127.0.0.1:{}", open_port());
+        let debug = format!("127.0.0.1:{}", open_port());
+        let reload = format!("127.0.0.1:{}", open_port());
+        let mut config = RpcConfig::new(&format!("127.0.0.1:{}", open_port()));
+        config.enable_validator_key_reload = true;
+        config.listeners = vec![
+            RpcListenerConfig {
+                addr: restricted.clone(),
+                allowed_methods: Some(vec!["block".to_string(), "health".to_string()]),
+                ..Default::default()
+            },
+            RpcListenerConfig {
+                addr: debug.clone(),
+                denied_methods: vec!["status".to_string()],
+                debug_pages: true,
+                ..Default::default()
+            },
+            RpcListenerConfig {
+                addr: reload.clone(),
+                validator_key_reload: true,
+                ..Default::default()
+            },
+        ];
+        let (_view_client_addr, addr) =
+            test_utils::start_all_with_rpc_config(test_utils::NodeType::NonValidator, config);
+
+        actix::spawn(async move {
+            let get = |addr: &str, path: &str| {
+                awc::Client::new().get(format!("http://{}{}", addr, path)).send()
+            };
+            let post = |addr: &str, path: &str| {
+                awc::Client::new().post(format!("http://{}{}", addr, path)).send()
+            };
+            for path in ["/status", "/health", "/network_info"] {
+                assert_eq!(get(&addr, path).await.unwrap().status(), 200, "{}", path);
+            }
+            assert_eq!(get(&restricted, "/status").await.unwrap().status(), 404);
+            assert_eq!(get(&restricted, "/network_info").await.unwrap().status(), 404);
+            assert_eq!(get(&restricted, "/health").await.unwrap().status(), 200);
+            assert_eq!(get(&debug, "/status").await.unwrap().status(), 404);
+            assert_eq!(get(&debug, "/network_info").await.unwrap().status(), 200);
+
+            // The node isn't a validator, so the reload fails once it's served.
+            let path = "/debug/api/reload_validator_key";
+            assert_eq!(post(&addr, path).await.unwrap().status(), 400);
+            assert_eq!(post(&restricted, path).await.unwrap().status(), 404);
+            assert_eq!(post(&debug, path).await.unwrap().status(), 405);
+            assert_eq!(post(&reload, path).await.unwrap().status(), 400);
+            assert_eq!(get(&reload, "/debug/api/status").await.unwrap().status(), 404);
+            System::current().stop();
+        });
+    });
+}
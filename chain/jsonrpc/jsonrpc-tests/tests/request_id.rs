@@ -0,0 +1,62 @@
+//! The ids of the RPC requests end up on the spans of the client actors.  In a
+//! test binary of its own, the logs of all the threads are captured.
+
+use actix::System;
+use serde_json::{json, Value};
+
+use near_actix_test_utils::run_actix;
+use near_jsonrpc_primitives::message::{from_slice, Message, Response};
+use near_logger_utils::TracingCapture;
+
+use near_jsonrpc_tests as test_utils;
+
+/// Sends the request with the id, returning the id of the response header and
+/// the response.
+async fn post(addr: &str, request_id: &str, method: &str, params: Value) -> (String, Response) {
+    let request = json!({"jsonrpc": "2.0", "method": method, "id": "dontcare", "params": params});
+    let mut response = awc::Client::new()
+        .post(format!("http://{}", addr))
+        .insert_header(("x-request-id", request_id))
+        .send_json(&request)
+        .await
+        .unwrap();
+    let header = response.headers().get("x-request-id").unwrap().to_str().unwrap().to_string();
+    let body = response.body().await.unwrap();
+    match from_slice(&body).unwrap() {
+        Message::Response(response) => (header, response),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn test_request_id_in_logs() {
+    let mut capture = TracingCapture::enable_global();
+
+    run_actix(async {
+        let (_view_client_addr, addr) = test_utils::start_all(test_utils::NodeType::NonValidator);
+        actix::spawn(async move {
+            let (header, response) =
+                post(&addr, "block-request", "block", json!({"block_id": 0})).await;
+            assert_eq!(header, "block-request");
+            assert!(response.result.is_ok());
+            assert_eq!(response.request_id, None);
+
+            let (header, response) = post(&addr, "bad-request", "no_such_method", json!([])).await;
+            assert_eq!(header, "bad-request");
+            assert!(response.result.is_err());
+            assert_eq!(response.request_id.as_deref(), Some("bad-request"));
+            System::current().stop();
+        });
+    });
+
+    let logs = capture.drain();
+    // The span of the request in the RPC server, and the one of the view client
+    // handling the message the server sent for it.
+    assert!(
+        logs.iter().any(|log| log == "rpc_request request_id=block-request method=block"),
+        "{:?}",
+        logs
+    );
+    assert!(logs.iter().any(|log| log == "rpc_request request_id=block-request"), "{:?}", logs);
+    assert!(logs.iter().any(|log| log.starts_with("rpc_request request_id=bad-request")));
+}
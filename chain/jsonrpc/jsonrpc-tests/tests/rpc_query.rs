@@ -7,14 +7,16 @@ use serde_json::json;
 use near_actix_test_utils::run_actix;
 use near_crypto::{KeyType, PublicKey, Signature};
 use near_jsonrpc::client::{new_client, ChunkId};
+use near_jsonrpc_primitives::types::headers::RpcHeadersRequest;
 use near_jsonrpc_primitives::types::query::QueryResponseKind;
+use near_jsonrpc_primitives::types::transactions::RpcTxConstructionInfoRequest;
 use near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest;
 use near_logger_utils::init_test_logger;
 use near_network::test_utils::WaitOrTimeoutActor;
 use near_primitives::account::{AccessKey, AccessKeyPermission};
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{BlockId, BlockReference, EpochId, ShardId, SyncCheckpoint};
-use near_primitives::views::QueryRequest;
+use near_primitives::views::{AccountViewOrError, QueryRequest};
 
 use near_jsonrpc_tests::{self as test_utils, test_with_client};
 
@@ -83,6 +85,82 @@ fn test_block_query() {
     });
 }
 
+/// Retrieve a block with the summaries of its chunks via json rpc
+#[test]
+fn test_block_with_chunk_summaries() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let block = client.block(BlockReference::BlockId(BlockId::Height(0))).await.unwrap();
+        assert!(block.chunks.iter().all(|chunk| chunk.summary.is_none()));
+        let block = client
+            .block_with_chunk_summaries(BlockReference::BlockId(BlockId::Height(0)))
+            .await
+            .unwrap();
+        assert!(!block.chunks.is_empty());
+        for chunk in &block.chunks {
+            let summary = chunk.summary.as_ref().unwrap();
+            assert_eq!((summary.num_transactions, summary.num_receipts), (Some(0), Some(0)));
+        }
+    });
+}
+
+/// Retrieve the state fingerprint of a block via json rpc
+#[test]
+fn test_state_fingerprint() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let genesis = client.block(BlockReference::BlockId(BlockId::Height(0))).await.unwrap();
+        let fingerprint = client
+            .EXPERIMENTAL_state_fingerprint(BlockReference::BlockId(BlockId::Height(0)))
+            .await
+            .unwrap();
+        assert_eq!(fingerprint.block_hash, genesis.header.hash);
+        assert_eq!(fingerprint.block_height, 0);
+        assert_eq!(fingerprint.shards.len(), genesis.chunks.len());
+        for (shard, chunk) in fingerprint.shards.iter().zip(&genesis.chunks) {
+            assert_eq!(shard.state_root, chunk.prev_state_root);
+        }
+        let by_hash = client
+            .EXPERIMENTAL_state_fingerprint(BlockReference::BlockId(BlockId::Hash(
+                genesis.header.hash,
+            )))
+            .await
+            .unwrap();
+        assert_eq!(by_hash.fingerprint, fingerprint.fingerprint);
+    });
+}
+
+/// Retrieve block headers by height range and by hashes via json rpc
+#[test]
+fn test_headers() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let genesis = client.block(BlockReference::BlockId(BlockId::Height(0))).await.unwrap();
+        let range = client
+            .headers(RpcHeadersRequest::Range { from_height: 0, to_height: 2 })
+            .await
+            .unwrap();
+        assert_eq!(range.headers.len(), 3);
+        assert_eq!(range.headers[0].as_ref().unwrap().hash, genesis.header.hash);
+        // The node doesn't produce blocks, so these heights are skipped.
+        assert!(range.headers[1].is_none());
+        assert!(range.headers[2].is_none());
+
+        let hashes = client
+            .headers(RpcHeadersRequest::Hashes { block_hashes: vec![genesis.header.hash] })
+            .await
+            .unwrap();
+        assert_eq!(hashes.headers.len(), 1);
+        assert_eq!(hashes.headers[0].as_ref().unwrap().height, 0);
+
+        let unknown = client
+            .headers(RpcHeadersRequest::Hashes { block_hashes: vec![CryptoHash::default()] })
+            .await;
+        assert!(unknown.is_err());
+        let too_many =
+            client.headers(RpcHeadersRequest::Range { from_height: 0, to_height: 10_000 }).await;
+        let s = serde_json::to_string(&too_many.unwrap_err().data.unwrap()).unwrap();
+        assert!(s.contains("at most 512"), "{}", s);
+    });
+}
+
 /// Retrieve chunk via json rpc
 #[test]
 fn test_chunk_by_hash() {
@@ -129,6 +207,43 @@ fn test_chunk_invalid_shard_id() {
     });
 }
 
+/// Connect to json rpc and query several accounts at once.
+#[test]
+fn test_query_accounts() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let account_ids = ["test1", "test2", "test"].map(|id| id.parse().unwrap()).to_vec();
+        let query_response = client
+            .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
+                block_reference: BlockReference::BlockId(BlockId::Height(0)),
+                request: QueryRequest::ViewAccounts { account_ids },
+            })
+            .await
+            .unwrap();
+        assert_eq!(query_response.block_height, 0);
+        let accounts = match query_response.kind {
+            QueryResponseKind::ViewAccounts(result) => result.accounts,
+            kind => panic!("queried accounts, but received something else: {:?}", kind),
+        };
+        let amount = |account_id: &str| match &accounts[&account_id.parse().unwrap()] {
+            AccountViewOrError::Account(account) => Ok(account.amount),
+            AccountViewOrError::Error(error) => Err(error.clone()),
+        };
+        assert_eq!(amount("test1"), Ok(1000));
+        assert_eq!(amount("test2"), Ok(1100));
+        assert_eq!(amount("test"), Err("Account test does not exist while viewing".to_string()));
+
+        let account_ids = (0..1001).map(|i| format!("test{}", i).parse().unwrap()).collect();
+        let too_many = client
+            .query(near_jsonrpc_primitives::types::query::RpcQueryRequest {
+                block_reference: BlockReference::latest(),
+                request: QueryRequest::ViewAccounts { account_ids },
+            })
+            .await;
+        let s = serde_json::to_string(&too_many.unwrap_err().data.unwrap()).unwrap();
+        assert!(s.contains("split them into smaller batches"), "{}", s);
+    });
+}
+
 /// Connect to json rpc and query account info with soft-deprecated query API.
 #[test]
 fn test_query_by_path_account() {
@@ -241,6 +356,33 @@ fn test_query_access_keys() {
     });
 }
 
+/// Retrieve the nonce and the block to build the next transaction of a key.
+#[test]
+fn test_tx_construction_info() {
+    test_with_client!(test_utils::NodeType::NonValidator, client, async move {
+        let info = client
+            .tx_construction_info(RpcTxConstructionInfoRequest {
+                account_id: "test".parse().unwrap(),
+                public_key: PublicKey::empty(KeyType::ED25519),
+            })
+            .await
+            .unwrap();
+        let genesis = client.block_by_id(BlockId::Height(0)).await.unwrap();
+        assert_eq!((info.nonce, info.recommended_nonce), (0, 1));
+        assert_eq!((info.block_hash, info.block_height), (genesis.header.hash, 0));
+        assert_eq!(info.validity_blocks_remaining, 100);
+
+        let err = client
+            .tx_construction_info(RpcTxConstructionInfoRequest {
+                account_id: "test".parse().unwrap(),
+                public_key: PublicKey::empty(KeyType::SECP256K1),
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("UNKNOWN_ACCESS_KEY"), "{:?}", err);
+    });
+}
+
 /// Connect to json rpc and query account info with soft-deprecated query API.
 #[test]
 fn test_query_by_path_access_key() {
@@ -0,0 +1,58 @@
+#![cfg(feature = "sandbox")]
+
+use actix::System;
+use serde_json::json;
+
+use near_actix_test_utils::run_actix;
+use near_jsonrpc::client::new_client;
+use near_jsonrpc_primitives::errors::RpcErrorKind;
+use near_jsonrpc_primitives::types::sandbox::RpcSandboxPatchStateResponse;
+use near_logger_utils::init_test_logger;
+use near_primitives::hash::CryptoHash;
+use near_primitives::state_record::StateRecord;
+use near_primitives::types::{BlockId, BlockReference};
+
+use near_jsonrpc_tests as test_utils;
+
+/// A patch writing a record the runtime can't write is rejected before it
+/// reaches the client, which keeps producing blocks.
+#[test]
+fn test_patch_state_unsupported_record() {
+    init_test_logger();
+
+    run_actix(async {
+        let (_view_client_addr, addr) = test_utils::start_all(test_utils::NodeType::Validator);
+        actix::spawn(async move {
+            let records = vec![
+                StateRecord::Data {
+                    account_id: "test1".parse().unwrap(),
+                    data_key: b"hello".to_vec(),
+                    value: b"world".to_vec(),
+                },
+                StateRecord::ReceivedData {
+                    account_id: "test1".parse().unwrap(),
+                    data_id: CryptoHash::default(),
+                    data: None,
+                },
+            ];
+            let error = test_utils::call_method::<RpcSandboxPatchStateResponse>(
+                &awc::Client::new(),
+                &format!("http://{}", addr),
+                "sandbox_patch_state",
+                json!({ "records": records }),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(
+                error.error_struct,
+                Some(RpcErrorKind::HandlerError(
+                    json!({"name": "UNSUPPORTED_RECORD", "info": {"index": 1}})
+                ))
+            );
+
+            let client = new_client(&format!("http://{}", addr));
+            client.block(BlockReference::BlockId(BlockId::Height(0))).await.unwrap();
+            System::current().stop();
+        });
+    });
+}
@@ -140,12 +140,17 @@ fn test_expired_tx() {
                                 actix::spawn(
                                     client
                                         .broadcast_tx_commit(to_base64(&bytes))
-                                        .map_err(|err| {
+                                        .map_err(move |err| {
+                                            let error_json = serde_json::to_value(err).unwrap();
                                             assert_eq!(
-                                                err.data.unwrap(),
-                                                serde_json::json!({"TxExecutionError": {
-                                                    "InvalidTxError": "Expired"
-                                                }})
+                                                error_json["cause"],
+                                                serde_json::json!({
+                                                    "name": "INVALID_TRANSACTION_BLOCK_HASH",
+                                                    "info": {
+                                                        "block_hash": block_hash,
+                                                        "reason": "EXPIRED",
+                                                    },
+                                                })
                                             );
                                             System::current().stop();
                                         })
@@ -187,6 +192,63 @@ fn test_replay_protection() {
     });
 }
 
+/// Test that transactions based on a block of another chain are rejected on
+/// submission.
+#[test]
+fn test_unknown_block_hash_tx() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let signer = InMemorySigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".parse().unwrap(),
+            "test2".parse().unwrap(),
+            &signer,
+            100,
+            hash(&[1]),
+        );
+        let bytes = to_base64(&tx.try_to_vec().unwrap());
+        let expected = serde_json::json!({
+            "name": "INVALID_TRANSACTION_BLOCK_HASH",
+            "info": {"block_hash": hash(&[1]), "reason": "UNKNOWN_TO_CHAIN"},
+        });
+        let err = client.broadcast_tx_async(bytes.clone()).await.unwrap_err();
+        assert_eq!(serde_json::to_value(err).unwrap()["cause"], expected);
+        let err = client.broadcast_tx_commit(bytes).await.unwrap_err();
+        assert_eq!(serde_json::to_value(err).unwrap()["cause"], expected);
+    });
+}
+
+/// Test validating transactions without submitting them.
+#[test]
+fn test_validate_tx() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let block_hash = client.block(BlockReference::latest()).await.unwrap().header.hash;
+        let signer = InMemorySigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".parse().unwrap(),
+            "test2".parse().unwrap(),
+            &signer,
+            100,
+            block_hash,
+        );
+        let result = client.validate_tx(to_base64(&tx.try_to_vec().unwrap())).await.unwrap();
+        assert_eq!(result["violations"], serde_json::json!([]));
+
+        // Based on an unknown block.
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".parse().unwrap(),
+            "test2".parse().unwrap(),
+            &signer,
+            100,
+            hash(&[1]),
+        );
+        let result = client.validate_tx(to_base64(&tx.try_to_vec().unwrap())).await.unwrap();
+        assert_eq!(result["violations"], serde_json::json!(["Expired"]));
+    });
+}
+
 #[test]
 fn test_tx_status_missing_tx() {
     test_with_client!(test_utils::NodeType::Validator, client, async move {
@@ -224,3 +286,35 @@ fn test_check_invalid_tx() {
         }
     });
 }
+
+/// Test looking up the status of a transaction by the signed transaction.
+#[test]
+fn test_tx_status_by_payload() {
+    test_with_client!(test_utils::NodeType::Validator, client, async move {
+        let block_hash = client.block(BlockReference::latest()).await.unwrap().header.hash;
+        let signer = InMemorySigner::from_seed("test1".parse().unwrap(), KeyType::ED25519, "test1");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test1".parse().unwrap(),
+            "test2".parse().unwrap(),
+            &signer,
+            100,
+            block_hash,
+        );
+        let payload = to_base64(&tx.try_to_vec().unwrap());
+        client.broadcast_tx_commit(payload.clone()).await.unwrap();
+        let result = client.tx_by_payload(payload).await.unwrap();
+        assert_eq!(result["transaction_hash"], serde_json::json!(tx.get_hash()));
+        assert_eq!(result["status"], serde_json::json!({"SuccessValue": ""}));
+
+        // The signature doesn't match the transaction.
+        let mut transaction = tx.transaction.clone();
+        transaction.nonce = 2;
+        let forged = SignedTransaction::new(tx.signature.clone(), transaction);
+        let err = client.tx_by_payload(to_base64(&forged.try_to_vec().unwrap())).await.unwrap_err();
+        assert!(err.message.contains("Parse error"), "{:?}", err);
+
+        let err = client.tx_by_payload("not a transaction".to_string()).await.unwrap_err();
+        assert!(err.message.contains("Parse error"), "{:?}", err);
+    });
+}
@@ -1,38 +1,58 @@
 #![doc = include_str!("../README.md")]
 
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use actix::Addr;
 use actix_cors::Cors;
-use actix_web::{get, http, middleware, web, App, Error as HttpError, HttpResponse, HttpServer};
+use actix_web::dev::RequestHead;
+use actix_web::{
+    get, guard, http, middleware, web, App, Error as HttpError, HttpRequest, HttpResponse,
+    HttpServer,
+};
 use futures::Future;
 use futures::FutureExt;
 use prometheus;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::time::{sleep, timeout};
-use tracing::info;
+use tracing::{info, Instrument};
 
 use near_chain_configs::GenesisConfig;
 use near_client::{
-    ClientActor, GetBlock, GetBlockProof, GetChunk, GetExecutionOutcome, GetGasPrice,
-    GetNetworkInfo, GetNextLightClientBlock, GetProtocolConfig, GetReceipt, GetStateChanges,
-    GetStateChangesInBlock, GetValidatorInfo, GetValidatorOrdered, Query, Status, TxStatus,
-    TxStatusError, ViewClientActor,
+    CheckTxBlockHash, ClientActor, GetBlock, GetBlockHash, GetBlockHeaders, GetBlockProof,
+    GetChunk, GetChunkApplyStats, GetChunkSummaries, GetDroppedTransaction, GetExecutionOutcome,
+    GetExpiredTransaction, GetForks, GetGasPrice, GetLightClientBlocks,
+    GetLightClientBlocksResponse, GetMisbehaviorEvidence, GetNetworkInfo, GetNextLightClientBlock,
+    GetPendingReceipts, GetProducedChunks, GetProducerVersions, GetProductionSkips,
+    GetProtocolConfig, GetReceipt, GetStateChanges, GetStateChangesInBlock, GetStateFingerprint,
+    GetSyncHistory, GetTransactionPoolWait, GetTxConstructionInfo, GetValidatorInfo,
+    GetValidatorOrdered, GetValidatorsPreview, Query, ReloadValidatorKey, Status, TxStatus,
+    TxStatusError, ValidateTx, ViewClientActor,
 };
 pub use near_jsonrpc_client as client;
-use near_jsonrpc_primitives::errors::RpcError;
+use near_jsonrpc_primitives::errors::{RpcError, RpcErrorKind, RpcRequestValidationErrorKind};
 use near_jsonrpc_primitives::message::{Message, Request};
 use near_jsonrpc_primitives::types::config::RpcProtocolConfigResponse;
 use near_metrics::{Encoder, TextEncoder};
 use near_network::types::{NetworkClientMessages, NetworkClientResponses};
 use near_primitives::hash::CryptoHash;
+use near_primitives::runtime::config_store::RuntimeConfigStore;
 use near_primitives::serialize::BaseEncode;
+use near_primitives::time::Clock;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::AccountId;
+use near_primitives::version::PROTOCOL_VERSION;
 use near_primitives::views::FinalExecutionOutcomeViewEnum;
 
+mod memory_profile;
 mod metrics;
+mod request_id;
+
+use request_id::{with_request, with_request_id, REQUEST_ID_HEADER};
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug)]
 pub struct RpcPollingConfig {
@@ -53,18 +73,32 @@ impl Default for RpcPollingConfig {
 pub struct RpcLimitsConfig {
     /// Maximum byte size of the json payload.
     pub json_payload_max_size: usize,
+    /// Maximum number of headers returned by a single `headers` request.
+    #[serde(default = "default_max_headers_per_request")]
+    pub max_headers_per_request: u64,
 }
 
 impl Default for RpcLimitsConfig {
     fn default() -> Self {
-        Self { json_payload_max_size: 10 * 1024 * 1024 }
+        Self {
+            json_payload_max_size: 10 * 1024 * 1024,
+            max_headers_per_request: default_max_headers_per_request(),
+        }
     }
 }
 
+fn default_max_headers_per_request() -> u64 {
+    512
+}
+
 fn default_enable_debug_rpc() -> bool {
     false
 }
 
+fn default_query_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RpcConfig {
     pub addr: String,
@@ -78,6 +112,88 @@ pub struct RpcConfig {
     // We disable it by default, as some of those endpoints might be quite CPU heavy.
     #[serde(default = "default_enable_debug_rpc")]
     pub enable_debug_rpc: bool,
+    // If true, `/debug/api/reload_validator_key` makes the node re-read its validator key.
+    // Only requests coming from localhost are served, and only by the `listeners` with
+    // `validator_key_reload`.
+    #[serde(default)]
+    pub enable_validator_key_reload: bool,
+    // If true, `/debug/api/memory_stats` serves the statistics of the allocator, which are also
+    // exported as metrics, and `/debug/api/memory_profile` writes heap profiles to
+    // `memory_profile_dir`.  Only requests coming from localhost are served.
+    #[serde(default)]
+    pub enable_memory_profiling: bool,
+    // Directory of the heap profiles, the temporary directory by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_profile_dir: Option<PathBuf>,
+    // Queries which take longer than this fail with a `TIMEOUT` error.  Queries
+    // still waiting for a view client thread at that point are never executed.
+    #[serde(default = "default_query_timeout")]
+    pub query_timeout: Duration,
+    // Methods which fail with a `METHOD_DISABLED` error, pointing to the method
+    // to use instead if they're deprecated.
+    #[serde(default)]
+    pub disabled_methods: Vec<String>,
+    // Servers started in addition to the one at `addr`, each with the methods
+    // it serves restricted.
+    #[serde(default)]
+    pub listeners: Vec<RpcListenerConfig>,
+    // If provided, `/metrics` and the `/debug` pages and endpoints are only
+    // served by an http server at that address, the servers at `addr` and of
+    // the `listeners` answer them with a 404.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_addr: Option<String>,
+    // Credentials the servers at `debug_addr` and `prometheus_addr` require,
+    // the requests without them get a 401.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_auth: Option<DebugAuthConfig>,
+}
+
+/// Credentials expected in the `Authorization` header of the requests.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugAuthConfig {
+    /// `Authorization: Bearer <token>`.
+    BearerToken(String),
+    /// HTTP basic authentication.
+    Basic { username: String, password: String },
+}
+
+impl DebugAuthConfig {
+    /// The value of the `Authorization` header of the authorized requests.
+    fn authorization(&self) -> String {
+        match self {
+            DebugAuthConfig::BearerToken(token) => format!("Bearer {}", token),
+            DebugAuthConfig::Basic { username, password } => format!(
+                "Basic {}",
+                near_primitives::serialize::to_base64(format!("{}:{}", username, password))
+            ),
+        }
+    }
+}
+
+/// An additional address the RPC is served at, e.g. to expose only the
+/// read-only methods publicly and keep the others on an internal interface.
+/// Methods it doesn't serve fail with a `METHOD_NOT_FOUND` error, as if they
+/// didn't exist.  The same goes for `GET /status`, `/health` and
+/// `/network_info`, which answer with a 404 unless the `status`, `health` and
+/// `network_info` methods are served.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RpcListenerConfig {
+    pub addr: String,
+    // If set, only these methods are served.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_methods: Option<Vec<String>>,
+    // Methods not served, even if in `allowed_methods`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_methods: Vec<String>,
+    // If true, the `/debug` pages and endpoints are served, as long as
+    // `enable_debug_rpc` is set for the ones that need it.
+    #[serde(default)]
+    pub debug_pages: bool,
+    // If true, `/debug/api/reload_validator_key` is served, as long as
+    // `enable_validator_key_reload` is set, whether or not `debug_pages` is.
+    #[serde(default)]
+    pub validator_key_reload: bool,
 }
 
 impl Default for RpcConfig {
@@ -89,6 +205,14 @@ impl Default for RpcConfig {
             polling_config: Default::default(),
             limits_config: Default::default(),
             enable_debug_rpc: false,
+            enable_validator_key_reload: false,
+            enable_memory_profiling: false,
+            memory_profile_dir: None,
+            query_timeout: default_query_timeout(),
+            disabled_methods: vec![],
+            listeners: vec![],
+            debug_addr: None,
+            debug_auth: None,
         }
     }
 }
@@ -97,6 +221,72 @@ impl RpcConfig {
     pub fn new(addr: &str) -> Self {
         RpcConfig { addr: addr.to_owned(), ..Default::default() }
     }
+
+    /// Returns the problems with the addresses the servers listen at, i.e.
+    /// the ones which can't be parsed and the ones which overlap with an
+    /// earlier one.  `addr` comes first followed by the listeners in order
+    /// and `debug_addr`.
+    pub fn listen_addr_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+        let all_addrs = std::iter::once(&self.addr)
+            .chain(self.listeners.iter().map(|listener| &listener.addr))
+            .chain(self.debug_addr.iter());
+        for addr in all_addrs {
+            let parsed = match addr.parse::<SocketAddr>() {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    errors.push(format!("invalid listen address {}: {}", addr, err));
+                    continue;
+                }
+            };
+            if let Some(other) = addrs.iter().find(|other| listen_addrs_overlap(other, &parsed)) {
+                errors.push(format!("listen address {} overlaps with {}", addr, other));
+            }
+            addrs.push(parsed);
+        }
+        errors
+    }
+}
+
+/// Whether two servers couldn't both listen at the addresses, the same port
+/// on an unspecified address covering every interface.
+fn listen_addrs_overlap(a: &SocketAddr, b: &SocketAddr) -> bool {
+    a.port() == b.port() && (a.ip() == b.ip() || a.ip().is_unspecified() || b.ip().is_unspecified())
+}
+
+/// Which methods a server handles.
+#[derive(Clone, Default)]
+struct MethodPolicy {
+    allowed: Option<HashSet<String>>,
+    denied: HashSet<String>,
+}
+
+impl MethodPolicy {
+    fn new(listener: &RpcListenerConfig) -> Self {
+        Self {
+            allowed: listener.allowed_methods.as_ref().map(|it| it.iter().cloned().collect()),
+            denied: listener.denied_methods.iter().cloned().collect(),
+        }
+    }
+
+    fn allows(&self, method: &str) -> bool {
+        !self.denied.contains(method)
+            && self.allowed.as_ref().map_or(true, |allowed| allowed.contains(method))
+    }
+}
+
+/// Methods which still work but are to be removed, with the methods to use
+/// instead.  Responses to them carry a warning so that callers find out, and
+/// `near_rpc_deprecated_calls_total` counts who still calls them.
+const DEPRECATED_METHODS: &[(&str, &str)] =
+    &[("EXPERIMENTAL_light_client_proof", "light_client_proof")];
+
+fn replacement_of_deprecated(method: &str) -> Option<&'static str> {
+    DEPRECATED_METHODS
+        .iter()
+        .find(|(deprecated, _)| *deprecated == method)
+        .map(|(_, replacement)| *replacement)
 }
 
 #[cfg(feature = "test_features")]
@@ -130,6 +320,7 @@ impl near_jsonrpc_primitives::types::transactions::RpcTransactionError {
             NetworkClientResponses::DoesNotTrackShard | NetworkClientResponses::RequestRouted => {
                 Self::DoesNotTrackShard
             }
+            NetworkClientResponses::TxPoolFull { limits } => Self::TxPoolFull { limits },
             internal_error => Self::InternalError { debug_info: format!("{:?}", internal_error) },
         }
     }
@@ -203,12 +394,20 @@ fn process_query_response(
     }
 }
 
+#[derive(Clone)]
 struct JsonRpcHandler {
     client_addr: Addr<ClientActor>,
     view_client_addr: Addr<ViewClientActor>,
     polling_config: RpcPollingConfig,
     genesis_config: GenesisConfig,
     enable_debug_rpc: bool,
+    enable_validator_key_reload: bool,
+    /// Set if `enable_memory_profiling` is.
+    heap_profiler: Option<Arc<Mutex<memory_profile::HeapProfiler>>>,
+    query_timeout: Duration,
+    max_headers_per_request: u64,
+    disabled_methods: Vec<String>,
+    method_policy: MethodPolicy,
     #[cfg(feature = "test_features")]
     peer_manager_addr: Addr<near_network::PeerManagerActor>,
     #[cfg(feature = "test_features")]
@@ -220,7 +419,20 @@ impl JsonRpcHandler {
         let id = message.id();
         match message {
             Message::Request(request) => {
-                Ok(Message::response(id, self.process_request(request).await))
+                let warning = if self.disabled_methods.contains(&request.method)
+                    || !self.method_policy.allows(&request.method)
+                {
+                    None
+                } else {
+                    replacement_of_deprecated(&request.method).map(|replacement| {
+                        format!(
+                            "{} is deprecated and will be removed, use {} instead",
+                            request.method, replacement
+                        )
+                    })
+                };
+                let result = self.process_request(request).await;
+                Ok(Message::response_with_warning(id, result, warning))
             }
             _ => Ok(Message::error(RpcError::parse_error(
                 "JSON RPC Request format was expected".to_owned(),
@@ -237,7 +449,10 @@ impl JsonRpcHandler {
         let response = self.process_request_internal(request).await;
 
         let request_method = if let Err(err) = &response {
-            if err.code == -32_601 {
+            if let Some(RpcErrorKind::RequestValidationError(
+                RpcRequestValidationErrorKind::MethodNotFound { .. },
+            )) = err.error_struct
+            {
                 "UNSUPPORTED_METHOD"
             } else {
                 &request_method
@@ -247,6 +462,9 @@ impl JsonRpcHandler {
         };
 
         metrics::HTTP_RPC_REQUEST_COUNT.with_label_values(&[request_method]).inc();
+        if replacement_of_deprecated(request_method).is_some() {
+            metrics::RPC_DEPRECATED_CALLS.with_label_values(&[request_method]).inc();
+        }
         metrics::RPC_PROCESSING_TIME
             .with_label_values(&[request_method])
             .observe(timer.elapsed().as_secs_f64());
@@ -262,6 +480,14 @@ impl JsonRpcHandler {
 
     // Processes the request but doesn't update any metrics.
     async fn process_request_internal(&self, request: Request) -> Result<Value, RpcError> {
+        if !self.method_policy.allows(&request.method) {
+            return Err(RpcError::method_not_found(request.method));
+        }
+        if self.disabled_methods.contains(&request.method) {
+            let replacement = replacement_of_deprecated(&request.method).map(str::to_owned);
+            return Err(RpcError::method_disabled(request.method, replacement));
+        }
+
         #[cfg(feature = "test_features")]
         {
             let params = request.params.clone();
@@ -333,6 +559,7 @@ impl JsonRpcHandler {
                             .map_err(|err| RpcError::serialization_error(err.to_string())),
                     )
                 }
+                "adv_set_network_faults" => Some(self.adv_set_network_faults(params).await),
                 "adv_get_peer_id" => {
                     let response = self
                         .peer_manager_addr
@@ -393,7 +620,7 @@ impl JsonRpcHandler {
                     near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest::parse(
                         request.params,
                     )?;
-                let transaction_hash = self.send_tx_async(rpc_transaction_request).await;
+                let transaction_hash = self.send_tx_async(rpc_transaction_request).await?;
                 serde_json::to_value((&transaction_hash).to_base())
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
@@ -422,6 +649,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(gas_price)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "headers" => {
+                let rpc_headers_request =
+                    near_jsonrpc_primitives::types::headers::RpcHeadersRequest::parse(
+                        request.params,
+                    )?;
+                let headers = self.headers(rpc_headers_request).await?;
+                serde_json::to_value(headers)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "health" => {
                 let health_response = self.health().await?;
                 serde_json::to_value(health_response)
@@ -466,6 +702,25 @@ impl JsonRpcHandler {
                 serde_json::to_value(rpc_transaction_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "tx_construction_info" => {
+                let rpc_tx_construction_info_request =
+                    near_jsonrpc_primitives::types::transactions::RpcTxConstructionInfoRequest::parse(
+                        request.params,
+                    )?;
+                let tx_construction_info =
+                    self.tx_construction_info(rpc_tx_construction_info_request).await?;
+                serde_json::to_value(tx_construction_info)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "validate_tx" => {
+                let rpc_transaction_request =
+                    near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest::parse(
+                        request.params,
+                    )?;
+                let validate_tx_response = self.validate_tx(rpc_transaction_request).await?;
+                serde_json::to_value(validate_tx_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "validators" => {
                 let rpc_validator_request =
                     near_jsonrpc_primitives::types::validator::RpcValidatorRequest::parse(
@@ -525,6 +780,29 @@ impl JsonRpcHandler {
                 serde_json::to_value(rpc_light_client_execution_proof_response)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_light_client_block_proof" => {
+                let rpc_light_client_block_proof_request = near_jsonrpc_primitives::types::light_client::RpcLightClientBlockProofRequest::parse(request.params)?;
+                let rpc_light_client_block_proof_response =
+                    self.light_client_block_proof(rpc_light_client_block_proof_request).await?;
+                serde_json::to_value(rpc_light_client_block_proof_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_light_client_blocks" => {
+                let rpc_light_client_blocks_request = near_jsonrpc_primitives::types::light_client::RpcLightClientBlocksRequest::parse(request.params)?;
+                let rpc_light_client_blocks_response =
+                    self.light_client_blocks(rpc_light_client_blocks_request).await?;
+                serde_json::to_value(rpc_light_client_blocks_response)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
+            "EXPERIMENTAL_pending_receipts" => {
+                let rpc_pending_receipts_request =
+                    near_jsonrpc_primitives::types::receipts::RpcPendingReceiptsRequest::parse(
+                        request.params,
+                    )?;
+                let pending_receipts = self.pending_receipts(rpc_pending_receipts_request).await?;
+                serde_json::to_value(pending_receipts)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_protocol_config" => {
                 let rpc_protocol_config_request =
                     near_jsonrpc_primitives::types::config::RpcProtocolConfigRequest::parse(
@@ -534,6 +812,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(config)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_protocol_config_diff" => {
+                let rpc_protocol_config_diff_request =
+                    near_jsonrpc_primitives::types::config::RpcProtocolConfigDiffRequest::parse(
+                        request.params,
+                    )?;
+                let diff = self.protocol_config_diff(rpc_protocol_config_diff_request).await?;
+                serde_json::to_value(diff)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_receipt" => {
                 let rpc_receipt_request =
                     near_jsonrpc_primitives::types::receipts::RpcReceiptRequest::parse(
@@ -543,6 +830,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(receipt)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_state_fingerprint" => {
+                let rpc_state_fingerprint_request =
+                    near_jsonrpc_primitives::types::blocks::RpcStateFingerprintRequest::parse(
+                        request.params,
+                    )?;
+                let fingerprint = self.state_fingerprint(rpc_state_fingerprint_request).await?;
+                serde_json::to_value(fingerprint)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             "EXPERIMENTAL_tx_status" => {
                 let rpc_transaction_status_common_request = near_jsonrpc_primitives::types::transactions::RpcTransactionStatusCommonRequest::parse(request.params)?;
                 let rpc_transaction_response =
@@ -559,6 +855,15 @@ impl JsonRpcHandler {
                 serde_json::to_value(validators)
                     .map_err(|err| RpcError::serialization_error(err.to_string()))
             }
+            "EXPERIMENTAL_validators_preview" => {
+                let rpc_validators_preview_request =
+                    near_jsonrpc_primitives::types::validator::RpcValidatorsPreviewRequest::parse(
+                        request.params,
+                    )?;
+                let preview = self.validators_preview(rpc_validators_preview_request).await?;
+                serde_json::to_value(preview)
+                    .map_err(|err| RpcError::serialization_error(err.to_string()))
+            }
             #[cfg(feature = "sandbox")]
             "sandbox_patch_state" => {
                 let sandbox_patch_state_request =
@@ -590,15 +895,42 @@ impl JsonRpcHandler {
     async fn send_tx_async(
         &self,
         request_data: near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest,
-    ) -> CryptoHash {
+    ) -> Result<CryptoHash, near_jsonrpc_primitives::types::transactions::RpcTransactionError> {
         let tx = request_data.signed_transaction;
+        self.check_tx_block_hash(&tx).await?;
         let hash = tx.get_hash().clone();
         self.client_addr.do_send(NetworkClientMessages::Transaction {
             transaction: tx,
             is_forwarded: false,
             check_only: false, // if we set true here it will not actually send the transaction
         });
-        hash
+        Ok(hash)
+    }
+
+    /// Rejects a transaction based on a block which isn't a recent block of
+    /// this chain before it's submitted.  The client would reject it as expired
+    /// either way, an unknown block usually means that the transaction was
+    /// signed for another network.
+    async fn check_tx_block_hash(
+        &self,
+        tx: &SignedTransaction,
+    ) -> Result<(), near_jsonrpc_primitives::types::transactions::RpcTransactionError> {
+        let result = self
+            .view_client_addr
+            .send(with_request_id(CheckTxBlockHash { block_hash: tx.transaction.block_hash }))
+            .await?;
+        if let Err(err) = result {
+            let err = near_jsonrpc_primitives::types::transactions::RpcTransactionError::from(err);
+            if let near_jsonrpc_primitives::types::transactions::RpcTransactionError::InvalidTransactionBlockHash {
+                reason,
+                ..
+            } = &err
+            {
+                metrics::RPC_TX_BLOCK_HASH_REJECTED_TOTAL.with_label_values(&[reason.as_str()]).inc();
+            }
+            return Err(err);
+        }
+        Ok(())
     }
 
     async fn tx_exists(
@@ -612,11 +944,11 @@ impl JsonRpcHandler {
                 // status without the information about execution outcomes.
                 match self
                     .view_client_addr
-                    .send(TxStatus {
+                    .send(with_request_id(TxStatus {
                         tx_hash,
                         signer_account_id: signer_account_id.clone(),
                         fetch_receipt: false,
-                    })
+                    }))
                     .await
                 {
                     Ok(Ok(Some(_))) => {
@@ -663,11 +995,11 @@ impl JsonRpcHandler {
             loop {
                 let tx_status_result = self
                     .view_client_addr
-                    .send(TxStatus {
+                    .send(with_request_id(TxStatus {
                         tx_hash,
                         signer_account_id: account_id.clone(),
                         fetch_receipt,
-                    })
+                    }))
                     .await;
                 match tx_status_result {
                     Ok(Ok(Some(outcome))) => break Ok(outcome),
@@ -680,6 +1012,23 @@ impl JsonRpcHandler {
                                 break Err(TxStatusError::InvalidTx(e));
                             }
                         }
+                        if let Ok(Some(dropped)) =
+                            self.client_addr.send(with_request_id(GetDroppedTransaction { tx_hash })).await
+                        {
+                            break Err(TxStatusError::DroppedFromPool {
+                                tx_hash,
+                                reason: dropped.reason,
+                            });
+                        }
+                        if let Ok(Some(expired)) =
+                            self.client_addr.send(with_request_id(GetExpiredTransaction { tx_hash })).await
+                        {
+                            break Err(TxStatusError::Expired {
+                                tx_hash,
+                                valid_from_height: expired.valid_from_height,
+                                valid_until_height: expired.valid_until_height,
+                            });
+                        }
                         break Err(err);
                     }
                     Ok(Err(err)) => break Err(err),
@@ -711,11 +1060,9 @@ impl JsonRpcHandler {
             loop {
                 match self.tx_status_fetch(tx_info.clone(), false).await {
                     Ok(tx_status) => {
-                        break Ok(
-                            near_jsonrpc_primitives::types::transactions::RpcTransactionResponse {
-                                final_execution_outcome: tx_status,
-                            },
-                        )
+                        break Ok(self
+                            .transaction_response(tx_info.transaction_hash(), tx_status)
+                            .await)
                     }
                     // If transaction is missing, keep polling.
                     Err(TxStatusError::MissingTransaction(_)) => {}
@@ -753,11 +1100,11 @@ impl JsonRpcHandler {
         let signer_account_id = tx.transaction.signer_id.clone();
         let response = self
             .client_addr
-            .send(NetworkClientMessages::Transaction {
+            .send(with_request_id(NetworkClientMessages::Transaction {
                 transaction: tx,
                 is_forwarded: false,
                 check_only,
-            })
+            }))
             .await?;
 
         // If we receive InvalidNonce error, it might be the case that the transaction was
@@ -782,6 +1129,7 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::transactions::RpcBroadcastTxSyncResponse,
         near_jsonrpc_primitives::types::transactions::RpcTransactionError,
     > {
+        self.check_tx_block_hash(&request_data.signed_transaction).await?;
         match self.send_tx(request_data.clone().signed_transaction, false).await? {
             NetworkClientResponses::ValidTx => {
                 Ok(near_jsonrpc_primitives::types::transactions::RpcBroadcastTxSyncResponse {
@@ -825,6 +1173,22 @@ impl JsonRpcHandler {
         }
     }
 
+    async fn validate_tx(
+        &self,
+        request_data: near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::transactions::RpcValidateTransactionResponse,
+        near_jsonrpc_primitives::types::transactions::RpcTransactionError,
+    > {
+        let validation = self
+            .view_client_addr
+            .send(with_request_id(ValidateTx { transaction: request_data.signed_transaction }))
+            .await??;
+        Ok(near_jsonrpc_primitives::types::transactions::RpcValidateTransactionResponse {
+            validation,
+        })
+    }
+
     async fn send_tx_commit(
         &self,
         request_data: near_jsonrpc_primitives::types::transactions::RpcBroadcastTransactionRequest,
@@ -843,17 +1207,19 @@ impl JsonRpcHandler {
             .await
         {
             Ok(outcome) => {
-                return Ok(near_jsonrpc_primitives::types::transactions::RpcTransactionResponse {
-                    final_execution_outcome: outcome,
-                });
+                return Ok(self.transaction_response(tx.get_hash(), outcome).await);
             }
             Err(TxStatusError::InvalidTx(invalid_tx_error)) => {
+                if let near_primitives::errors::InvalidTxError::Expired = invalid_tx_error {
+                    self.check_tx_block_hash(&tx).await?;
+                }
                 return Err(near_jsonrpc_primitives::types::transactions::RpcTransactionError::InvalidTransaction {
                     context: invalid_tx_error
                 });
             }
             _ => {}
         }
+        self.check_tx_block_hash(&tx).await?;
         match self.send_tx(tx.clone(), false).await? {
             NetworkClientResponses::ValidTx | NetworkClientResponses::RequestRouted => {
                 self.tx_polling(near_jsonrpc_primitives::types::transactions::TransactionInfo::Transaction(tx)).await
@@ -874,7 +1240,11 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::status::RpcHealthResponse,
         near_jsonrpc_primitives::types::status::RpcStatusError,
     > {
-        Ok(self.client_addr.send(Status { is_health_check: true, detailed: false }).await??.into())
+        Ok(self
+            .client_addr
+            .send(with_request_id(Status { is_health_check: true, detailed: false }))
+            .await??
+            .into())
     }
 
     pub async fn status(
@@ -883,7 +1253,11 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::status::RpcStatusResponse,
         near_jsonrpc_primitives::types::status::RpcStatusError,
     > {
-        Ok(self.client_addr.send(Status { is_health_check: false, detailed: false }).await??.into())
+        Ok(self
+            .client_addr
+            .send(with_request_id(Status { is_health_check: false, detailed: false }))
+            .await??
+            .into())
     }
 
     pub async fn debug(
@@ -895,7 +1269,7 @@ impl JsonRpcHandler {
         if self.enable_debug_rpc {
             Ok(Some(
                 self.client_addr
-                    .send(Status { is_health_check: false, detailed: true })
+                    .send(with_request_id(Status { is_health_check: false, detailed: true }))
                     .await??
                     .into(),
             ))
@@ -904,6 +1278,102 @@ impl JsonRpcHandler {
         }
     }
 
+    /// Returns the recent transitions between sync states, most recent first,
+    /// or `None` if the debug RPC is disabled.
+    pub async fn sync_history(
+        &self,
+    ) -> Result<Option<Vec<near_primitives::views::SyncTransitionView>>, actix::MailboxError> {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(with_request_id(GetSyncHistory)).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the non-final heights with more than one known block, or
+    /// `None` if the debug RPC is disabled.
+    pub async fn forks(
+        &self,
+    ) -> Result<Option<Vec<near_primitives::views::ForkView>>, actix::MailboxError> {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(with_request_id(GetForks)).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the evidence of equivocation stored by the node, or `None` if
+    /// the debug RPC is disabled.
+    pub async fn misbehavior(
+        &self,
+    ) -> Result<
+        Option<Result<Vec<near_primitives::views::EquivocationView>, String>>,
+        actix::MailboxError,
+    > {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(with_request_id(GetMisbehaviorEvidence)).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the blocks and chunks the node's validator missed in the current
+    /// and the previous epoch, or `None` if the debug RPC is disabled.
+    pub async fn production_skips(
+        &self,
+    ) -> Result<Option<Vec<near_primitives::views::ProductionSkipView>>, actix::MailboxError> {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(with_request_id(GetProductionSkips)).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the chunks the node's validator produced in the current and the
+    /// previous epoch, or `None` if the debug RPC is disabled.
+    pub async fn produced_chunks(
+        &self,
+    ) -> Result<Option<Vec<near_primitives::views::ProducedChunkView>>, actix::MailboxError> {
+        if self.enable_debug_rpc {
+            Ok(Some(self.client_addr.send(with_request_id(GetProducedChunks)).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the stage times and trie reads of applying the chunk of the
+    /// shard in the block, or `None` if the debug RPC is disabled.
+    pub async fn chunk_apply_stats(
+        &self,
+        block_hash: CryptoHash,
+        shard_id: near_primitives::types::ShardId,
+    ) -> Result<Option<Option<near_primitives::views::ChunkApplyStageTimesView>>, actix::MailboxError>
+    {
+        if self.enable_debug_rpc {
+            let request = GetChunkApplyStats { block_hash, shard_id };
+            Ok(Some(self.client_addr.send(with_request_id(request)).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the protocol versions and builds of the producers of the last
+    /// `last_n_blocks` blocks, or `None` if the debug RPC is disabled.
+    pub async fn producer_versions(
+        &self,
+        last_n_blocks: near_primitives::types::NumBlocks,
+    ) -> Result<
+        Option<Result<near_primitives::views::ProducerVersionsView, String>>,
+        actix::MailboxError,
+    > {
+        if self.enable_debug_rpc {
+            let request = GetProducerVersions { last_n_blocks };
+            Ok(Some(self.view_client_addr.send(with_request_id(request)).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Expose Genesis Config (with internal Runtime Config) without state records to keep the
     /// output at a reasonable size.
     ///
@@ -921,11 +1391,34 @@ impl JsonRpcHandler {
     > {
         let config_view = self
             .view_client_addr
-            .send(GetProtocolConfig(request_data.block_reference.into()))
+            .send(with_request_id(GetProtocolConfig(request_data.block_reference.into())))
             .await??;
         Ok(RpcProtocolConfigResponse { config_view })
     }
 
+    /// Differences between the runtime configs of the protocol versions, as
+    /// returned by `EXPERIMENTAL_protocol_config` for blocks of those versions.
+    pub async fn protocol_config_diff(
+        &self,
+        request_data: near_jsonrpc_primitives::types::config::RpcProtocolConfigDiffRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::config::RpcProtocolConfigDiffResponse,
+        near_jsonrpc_primitives::types::config::RpcProtocolConfigDiffError,
+    > {
+        let from = request_data.from_protocol_version;
+        let to = request_data.to_protocol_version;
+        for protocol_version in [from, to] {
+            if protocol_version > PROTOCOL_VERSION {
+                return Err(near_jsonrpc_primitives::types::config::RpcProtocolConfigDiffError::UnknownProtocolVersion {
+                    protocol_version,
+                    latest_protocol_version: PROTOCOL_VERSION,
+                });
+            }
+        }
+        let store = RuntimeConfigStore::for_chain_id(&self.genesis_config.chain_id);
+        Ok(store.diff(from, to))
+    }
+
     async fn query(
         &self,
         request_data: near_jsonrpc_primitives::types::query::RpcQueryRequest,
@@ -933,8 +1426,16 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::query::RpcQueryResponse,
         near_jsonrpc_primitives::types::query::RpcQueryError,
     > {
-        let query = Query::new(request_data.block_reference, request_data.request);
-        Ok(self.view_client_addr.send(query).await??.into())
+        let query = Query::new(request_data.block_reference, request_data.request)
+            .with_deadline(Clock::instant() + self.query_timeout);
+        match timeout(self.query_timeout, self.view_client_addr.send(with_request_id(query))).await
+        {
+            Ok(response) => Ok(response??.into()),
+            Err(_) => {
+                metrics::RPC_TIMEOUT_TOTAL.inc();
+                Err(near_jsonrpc_primitives::types::query::RpcQueryError::Timeout)
+            }
+        }
     }
 
     async fn tx_status_common(
@@ -945,7 +1446,32 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::transactions::RpcTransactionResponse,
         near_jsonrpc_primitives::types::transactions::RpcTransactionError,
     > {
-        Ok(self.tx_status_fetch(request_data.transaction_info, fetch_receipt).await?.into())
+        let tx_hash = request_data.transaction_info.transaction_hash();
+        let by_payload = matches!(
+            request_data.transaction_info,
+            near_jsonrpc_primitives::types::transactions::TransactionInfo::Transaction(_)
+        );
+        let outcome = self.tx_status_fetch(request_data.transaction_info, fetch_receipt).await?;
+        let mut response = self.transaction_response(tx_hash, outcome).await;
+        if by_payload {
+            response.transaction_hash = Some(tx_hash);
+        }
+        Ok(response)
+    }
+
+    /// Adds how long the transaction waited in the pool, if the client knows.
+    async fn transaction_response(
+        &self,
+        tx_hash: CryptoHash,
+        final_execution_outcome: FinalExecutionOutcomeViewEnum,
+    ) -> near_jsonrpc_primitives::types::transactions::RpcTransactionResponse {
+        let pool_wait =
+            self.client_addr.send(with_request_id(GetTransactionPoolWait { tx_hash })).await;
+        near_jsonrpc_primitives::types::transactions::RpcTransactionResponse {
+            final_execution_outcome,
+            pool_wait_ms: pool_wait.ok().flatten().map(|wait| wait.as_millis() as u64),
+            transaction_hash: None,
+        }
     }
 
     async fn block(
@@ -955,11 +1481,60 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::blocks::RpcBlockResponse,
         near_jsonrpc_primitives::types::blocks::RpcBlockError,
     > {
-        let block_view =
-            self.view_client_addr.send(GetBlock(request_data.block_reference.into())).await??;
+        let mut block_view = self
+            .view_client_addr
+            .send(with_request_id(GetBlock(request_data.block_reference.into())))
+            .await??;
+        if request_data.include_chunk_summaries {
+            let summaries = self
+                .view_client_addr
+                .send(with_request_id(GetChunkSummaries { block_hash: block_view.header.hash }))
+                .await??;
+            for (chunk, summary) in block_view.chunks.iter_mut().zip(summaries) {
+                chunk.summary = Some(summary);
+            }
+        }
         Ok(near_jsonrpc_primitives::types::blocks::RpcBlockResponse { block_view })
     }
 
+    async fn state_fingerprint(
+        &self,
+        request_data: near_jsonrpc_primitives::types::blocks::RpcStateFingerprintRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::blocks::RpcStateFingerprintResponse,
+        near_jsonrpc_primitives::types::blocks::RpcBlockError,
+    > {
+        let block_view = self
+            .view_client_addr
+            .send(with_request_id(GetBlock(request_data.block_reference.into())))
+            .await??;
+        Ok(self
+            .view_client_addr
+            .send(with_request_id(GetStateFingerprint { block_hash: block_view.header.hash }))
+            .await??)
+    }
+
+    async fn headers(
+        &self,
+        request_data: near_jsonrpc_primitives::types::headers::RpcHeadersRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::headers::RpcHeadersResponse,
+        near_jsonrpc_primitives::types::headers::RpcHeadersError,
+    > {
+        let requested = request_data.num_headers();
+        if requested > self.max_headers_per_request {
+            return Err(near_jsonrpc_primitives::types::headers::RpcHeadersError::TooManyHeaders {
+                requested,
+                limit: self.max_headers_per_request,
+            });
+        }
+        let headers = self
+            .view_client_addr
+            .send(with_request_id(GetBlockHeaders::from(request_data)))
+            .await??;
+        Ok(near_jsonrpc_primitives::types::headers::RpcHeadersResponse { headers })
+    }
+
     async fn chunk(
         &self,
         request_data: near_jsonrpc_primitives::types::chunks::RpcChunkRequest,
@@ -967,8 +1542,10 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::chunks::RpcChunkResponse,
         near_jsonrpc_primitives::types::chunks::RpcChunkError,
     > {
-        let chunk_view =
-            self.view_client_addr.send(GetChunk::from(request_data.chunk_reference)).await??;
+        let chunk_view = self
+            .view_client_addr
+            .send(with_request_id(GetChunk::from(request_data.chunk_reference)))
+            .await??;
         Ok(near_jsonrpc_primitives::types::chunks::RpcChunkResponse { chunk_view })
     }
 
@@ -981,7 +1558,9 @@ impl JsonRpcHandler {
     > {
         match self
             .view_client_addr
-            .send(GetReceipt { receipt_id: request_data.receipt_reference.receipt_id })
+            .send(with_request_id(GetReceipt {
+                receipt_id: request_data.receipt_reference.receipt_id,
+            }))
             .await??
         {
             Some(receipt_view) => {
@@ -1002,13 +1581,18 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockByTypeResponse,
         near_jsonrpc_primitives::types::changes::RpcStateChangesError,
     > {
-        let block = self.view_client_addr.send(GetBlock(request.block_reference.into())).await??;
+        let block_hash = self
+            .view_client_addr
+            .send(with_request_id(GetBlockHash(request.block_reference.into())))
+            .await??;
 
-        let block_hash = block.header.hash.clone();
-        let changes = self.view_client_addr.send(GetStateChangesInBlock { block_hash }).await??;
+        let changes = self
+            .view_client_addr
+            .send(with_request_id(GetStateChangesInBlock { block_hash }))
+            .await??;
 
         Ok(near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockByTypeResponse {
-            block_hash: block.header.hash,
+            block_hash,
             changes,
         })
     }
@@ -1020,19 +1604,21 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockResponse,
         near_jsonrpc_primitives::types::changes::RpcStateChangesError,
     > {
-        let block = self.view_client_addr.send(GetBlock(request.block_reference.into())).await??;
+        let block_hash = self
+            .view_client_addr
+            .send(with_request_id(GetBlockHash(request.block_reference.into())))
+            .await??;
 
-        let block_hash = block.header.hash.clone();
         let changes = self
             .view_client_addr
-            .send(GetStateChanges {
+            .send(with_request_id(GetStateChanges {
                 block_hash,
                 state_changes_request: request.state_changes_request,
-            })
+            }))
             .await??;
 
         Ok(near_jsonrpc_primitives::types::changes::RpcStateChangesInBlockResponse {
-            block_hash: block.header.hash,
+            block_hash,
             changes,
         })
     }
@@ -1046,11 +1632,36 @@ impl JsonRpcHandler {
     > {
         Ok(self
             .view_client_addr
-            .send(GetNextLightClientBlock { last_block_hash: request.last_block_hash })
+            .send(with_request_id(GetNextLightClientBlock {
+                last_block_hash: request.last_block_hash,
+            }))
             .await??
             .into())
     }
 
+    async fn light_client_blocks(
+        &self,
+        request: near_jsonrpc_primitives::types::light_client::RpcLightClientBlocksRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::light_client::RpcLightClientBlocksResponse,
+        near_jsonrpc_primitives::types::light_client::RpcLightClientNextBlockError,
+    > {
+        use near_jsonrpc_primitives::types::light_client::MAX_LIGHT_CLIENT_BLOCKS;
+        let limit =
+            request.limit.unwrap_or(MAX_LIGHT_CLIENT_BLOCKS).clamp(1, MAX_LIGHT_CLIENT_BLOCKS);
+        let GetLightClientBlocksResponse { blocks, next_cursor } = self
+            .view_client_addr
+            .send(with_request_id(GetLightClientBlocks {
+                last_block_hash: request.last_block_hash,
+                limit,
+            }))
+            .await??;
+        Ok(near_jsonrpc_primitives::types::light_client::RpcLightClientBlocksResponse {
+            blocks,
+            next_cursor,
+        })
+    }
+
     async fn light_client_execution_outcome_proof(
         &self,
         request: near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofRequest,
@@ -1064,14 +1675,14 @@ impl JsonRpcHandler {
         } = request;
 
         let execution_outcome_proof =
-            self.view_client_addr.send(GetExecutionOutcome { id }).await??;
+            self.view_client_addr.send(with_request_id(GetExecutionOutcome { id })).await??;
 
         let block_proof = self
             .view_client_addr
-            .send(GetBlockProof {
+            .send(with_request_id(GetBlockProof {
                 block_hash: execution_outcome_proof.outcome_proof.block_hash,
                 head_block_hash: light_client_head,
-            })
+            }))
             .await??;
 
         Ok(near_jsonrpc_primitives::types::light_client::RpcLightClientExecutionProofResponse {
@@ -1082,13 +1693,36 @@ impl JsonRpcHandler {
         })
     }
 
+    async fn light_client_block_proof(
+        &self,
+        request: near_jsonrpc_primitives::types::light_client::RpcLightClientBlockProofRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::light_client::RpcLightClientBlockProofResponse,
+        near_jsonrpc_primitives::types::light_client::RpcLightClientProofError,
+    > {
+        let near_jsonrpc_primitives::types::light_client::RpcLightClientBlockProofRequest {
+            block_hash,
+            light_client_head,
+        } = request;
+
+        let block_proof = self
+            .view_client_addr
+            .send(with_request_id(GetBlockProof { block_hash, head_block_hash: light_client_head }))
+            .await??;
+
+        Ok(near_jsonrpc_primitives::types::light_client::RpcLightClientBlockProofResponse {
+            block_header_lite: block_proof.block_header_lite,
+            block_proof: block_proof.proof,
+        })
+    }
+
     async fn network_info(
         &self,
     ) -> Result<
         near_jsonrpc_primitives::types::network_info::RpcNetworkInfoResponse,
         near_jsonrpc_primitives::types::network_info::RpcNetworkInfoError,
     > {
-        Ok(self.client_addr.send(GetNetworkInfo {}).await??.into())
+        Ok(self.client_addr.send(with_request_id(GetNetworkInfo {})).await??.into())
     }
 
     async fn gas_price(
@@ -1098,8 +1732,10 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::gas_price::RpcGasPriceResponse,
         near_jsonrpc_primitives::types::gas_price::RpcGasPriceError,
     > {
-        let gas_price_view =
-            self.view_client_addr.send(GetGasPrice { block_id: request_data.block_id }).await??;
+        let gas_price_view = self
+            .view_client_addr
+            .send(with_request_id(GetGasPrice { block_id: request_data.block_id }))
+            .await??;
         Ok(near_jsonrpc_primitives::types::gas_price::RpcGasPriceResponse { gas_price_view })
     }
 
@@ -1112,7 +1748,9 @@ impl JsonRpcHandler {
     > {
         let validator_info = self
             .view_client_addr
-            .send(GetValidatorInfo { epoch_reference: request_data.epoch_reference })
+            .send(with_request_id(GetValidatorInfo {
+                epoch_reference: request_data.epoch_reference,
+            }))
             .await??;
         Ok(near_jsonrpc_primitives::types::validator::RpcValidatorResponse { validator_info })
     }
@@ -1129,7 +1767,59 @@ impl JsonRpcHandler {
     > {
         let near_jsonrpc_primitives::types::validator::RpcValidatorsOrderedRequest { block_id } =
             request;
-        Ok(self.view_client_addr.send(GetValidatorOrdered { block_id }).await??.into())
+        Ok(self
+            .view_client_addr
+            .send(with_request_id(GetValidatorOrdered { block_id }))
+            .await??
+            .into())
+    }
+
+    /// Nonce of the access key and the block to build its next transaction
+    /// against, both read at the final head.
+    async fn tx_construction_info(
+        &self,
+        request: near_jsonrpc_primitives::types::transactions::RpcTxConstructionInfoRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::transactions::RpcTxConstructionInfoResponse,
+        near_jsonrpc_primitives::types::query::RpcQueryError,
+    > {
+        let near_jsonrpc_primitives::types::transactions::RpcTxConstructionInfoRequest {
+            account_id,
+            public_key,
+        } = request;
+        Ok(self
+            .view_client_addr
+            .send(with_request_id(GetTxConstructionInfo { account_id, public_key }))
+            .await??)
+    }
+
+    /// Receipts for the account which aren't executed yet, as far as this node
+    /// knows.  Best effort, only the shards the node tracks are looked at.
+    async fn pending_receipts(
+        &self,
+        request: near_jsonrpc_primitives::types::receipts::RpcPendingReceiptsRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::receipts::RpcPendingReceiptsResponse,
+        near_jsonrpc_primitives::types::receipts::RpcPendingReceiptsError,
+    > {
+        let near_jsonrpc_primitives::types::receipts::RpcPendingReceiptsRequest { account_id } =
+            request;
+        Ok(self.view_client_addr.send(with_request_id(GetPendingReceipts { account_id })).await??)
+    }
+
+    /// Predicts the validators of the epoch after next from the proposals,
+    /// kickouts and stakes known at the given block.  The prediction may
+    /// change until the current epoch ends.
+    async fn validators_preview(
+        &self,
+        request: near_jsonrpc_primitives::types::validator::RpcValidatorsPreviewRequest,
+    ) -> Result<
+        near_jsonrpc_primitives::types::validator::RpcValidatorsPreviewResponse,
+        near_jsonrpc_primitives::types::validator::RpcValidatorError,
+    > {
+        let near_jsonrpc_primitives::types::validator::RpcValidatorsPreviewRequest { block_id } =
+            request;
+        Ok(self.view_client_addr.send(with_request_id(GetValidatorsPreview { block_id })).await??)
     }
 }
 
@@ -1142,27 +1832,36 @@ impl JsonRpcHandler {
         near_jsonrpc_primitives::types::sandbox::RpcSandboxPatchStateResponse,
         near_jsonrpc_primitives::types::sandbox::RpcSandboxPatchStateError,
     > {
+        if let Some(index) =
+            patch_state_request.records.iter().position(|record| !record.is_patchable())
+        {
+            return Err(
+                near_jsonrpc_primitives::types::sandbox::RpcSandboxPatchStateError::UnsupportedRecord {
+                    index,
+                },
+            );
+        }
         self.client_addr
-            .send(NetworkClientMessages::Sandbox(
+            .send(with_request_id(NetworkClientMessages::Sandbox(
                 near_network_primitives::types::NetworkSandboxMessage::SandboxPatchState(
                     patch_state_request.records,
                 ),
-            ))
+            )))
             .await?;
 
-        timeout(self.polling_config.polling_timeout, async {
+        let state_root = timeout(self.polling_config.polling_timeout, async {
             loop {
                 let patch_state_finished = self
                     .client_addr
-                    .send(NetworkClientMessages::Sandbox(
+                    .send(with_request_id(NetworkClientMessages::Sandbox(
                         near_network_primitives::types::NetworkSandboxMessage::SandboxPatchStateStatus {},
-                    ))
+                    )))
                     .await;
                 if let Ok(NetworkClientResponses::SandboxResult(
-                              near_network_primitives::types::SandboxResponse::SandboxPatchStateFinished(true),
+                              near_network_primitives::types::SandboxResponse::SandboxPatchStateFinished(Some(state_root)),
                 )) = patch_state_finished
                 {
-                    break;
+                    break state_root;
                 }
                 let _ = sleep(self.polling_config.polling_interval).await;
             }
@@ -1170,7 +1869,7 @@ impl JsonRpcHandler {
         .await
         .expect("patch state should happen at next block, never timeout");
 
-        Ok(near_jsonrpc_primitives::types::sandbox::RpcSandboxPatchStateResponse {})
+        Ok(near_jsonrpc_primitives::types::sandbox::RpcSandboxPatchStateResponse { state_root })
     }
 
     async fn sandbox_fast_forward(
@@ -1183,11 +1882,11 @@ impl JsonRpcHandler {
         use near_network_primitives::types::SandboxResponse;
 
         self.client_addr
-            .send(NetworkClientMessages::Sandbox(
+            .send(with_request_id(NetworkClientMessages::Sandbox(
                 near_network_primitives::types::NetworkSandboxMessage::SandboxFastForward(
                     fast_forward_request.delta_height,
                 ),
-            ))
+            )))
             .await?;
 
         // Hard limit the request to timeout at an hour, since fast forwarding can take a while,
@@ -1196,9 +1895,9 @@ impl JsonRpcHandler {
             loop {
                 let fast_forward_finished = self
                     .client_addr
-                    .send(NetworkClientMessages::Sandbox(
+                    .send(with_request_id(NetworkClientMessages::Sandbox(
                         near_network_primitives::types::NetworkSandboxMessage::SandboxFastForwardStatus {},
-                    ))
+                    )))
                     .await;
 
                 match fast_forward_finished {
@@ -1229,9 +1928,11 @@ impl JsonRpcHandler {
         let height = parse_params::<u64>(params)?;
         actix::spawn(
             self.view_client_addr
-                .send(near_network_primitives::types::NetworkViewClientMessages::Adversarial(
-                    near_network_primitives::types::NetworkAdversarialMessage::AdvSetSyncInfo(
-                        height,
+                .send(with_request_id(
+                    near_network_primitives::types::NetworkViewClientMessages::Adversarial(
+                        near_network_primitives::types::NetworkAdversarialMessage::AdvSetSyncInfo(
+                            height,
+                        ),
                     ),
                 ))
                 .map(|_| ()),
@@ -1239,19 +1940,35 @@ impl JsonRpcHandler {
         Ok(Value::String("".to_string()))
     }
 
+    async fn adv_set_network_faults(&self, params: Option<Value>) -> Result<Value, RpcError> {
+        if self.genesis_config.chain_id == "mainnet" {
+            return Err(RpcError::server_error(Some(
+                "Network faults can't be injected on mainnet".to_string(),
+            )));
+        }
+        let request =
+            parse_params::<near_jsonrpc_adversarial_primitives::SetNetworkFaultsRequest>(params)?;
+        self.peer_manager_addr
+            .send(near_network::types::PeerManagerMessageRequest::SetNetworkFaults(
+                near_network::test_utils::SetNetworkFaults { faults: request.faults },
+            ))
+            .await?;
+        Ok(Value::Null)
+    }
+
     async fn adv_disable_header_sync(&self, _params: Option<Value>) -> Result<Value, RpcError> {
         actix::spawn(
             self.client_addr
-                .send(near_network::types::NetworkClientMessages::Adversarial(
+                .send(with_request_id(near_network::types::NetworkClientMessages::Adversarial(
                     near_network_primitives::types::NetworkAdversarialMessage::AdvDisableHeaderSync,
-                ))
+                )))
                 .map(|_| ()),
         );
         actix::spawn(
             self.view_client_addr
-                .send(near_network_primitives::types::NetworkViewClientMessages::Adversarial(
+                .send(with_request_id(near_network_primitives::types::NetworkViewClientMessages::Adversarial(
                     near_network_primitives::types::NetworkAdversarialMessage::AdvDisableHeaderSync,
-                ))
+                )))
                 .map(|_| ()),
         );
         Ok(Value::String("".to_string()))
@@ -1260,16 +1977,16 @@ impl JsonRpcHandler {
     async fn adv_disable_doomslug(&self, _params: Option<Value>) -> Result<Value, RpcError> {
         actix::spawn(
             self.client_addr
-                .send(NetworkClientMessages::Adversarial(
+                .send(with_request_id(NetworkClientMessages::Adversarial(
                     near_network_primitives::types::NetworkAdversarialMessage::AdvDisableDoomslug,
-                ))
+                )))
                 .map(|_| ()),
         );
         actix::spawn(
             self.view_client_addr
-                .send(near_network_primitives::types::NetworkViewClientMessages::Adversarial(
+                .send(with_request_id(near_network_primitives::types::NetworkViewClientMessages::Adversarial(
                     near_network_primitives::types::NetworkAdversarialMessage::AdvDisableDoomslug,
-                ))
+                )))
                 .map(|_| ()),
         );
         Ok(Value::String("".to_string()))
@@ -1279,11 +1996,11 @@ impl JsonRpcHandler {
         let (num_blocks, only_valid) = parse_params::<(u64, bool)>(params)?;
         actix::spawn(
             self.client_addr
-                .send(NetworkClientMessages::Adversarial(
+                .send(with_request_id(NetworkClientMessages::Adversarial(
                     near_network_primitives::types::NetworkAdversarialMessage::AdvProduceBlocks(
                         num_blocks, only_valid,
                     ),
-                ))
+                )))
                 .map(|_| ()),
         );
         Ok(Value::String("".to_string()))
@@ -1293,20 +2010,20 @@ impl JsonRpcHandler {
         let (height,) = parse_params::<(u64,)>(params)?;
         actix::spawn(
             self.client_addr
-                .send(NetworkClientMessages::Adversarial(
+                .send(with_request_id(NetworkClientMessages::Adversarial(
                     near_network_primitives::types::NetworkAdversarialMessage::AdvSwitchToHeight(
                         height,
                     ),
-                ))
+                )))
                 .map(|_| ()),
         );
         actix::spawn(
             self.view_client_addr
-                .send(near_network_primitives::types::NetworkViewClientMessages::Adversarial(
+                .send(with_request_id(near_network_primitives::types::NetworkViewClientMessages::Adversarial(
                     near_network_primitives::types::NetworkAdversarialMessage::AdvSwitchToHeight(
                         height,
                     ),
-                ))
+                )))
                 .map(|_| ()),
         );
         Ok(Value::String("".to_string()))
@@ -1315,9 +2032,9 @@ impl JsonRpcHandler {
     async fn adv_get_saved_blocks(&self, _params: Option<Value>) -> Result<Value, RpcError> {
         match self
             .client_addr
-            .send(NetworkClientMessages::Adversarial(
+            .send(with_request_id(NetworkClientMessages::Adversarial(
                 near_network_primitives::types::NetworkAdversarialMessage::AdvGetSavedBlocks,
-            ))
+            )))
             .await
         {
             Ok(result) => match result {
@@ -1331,9 +2048,9 @@ impl JsonRpcHandler {
     async fn adv_check_store(&self, _params: Option<Value>) -> Result<Value, RpcError> {
         match self
             .client_addr
-            .send(NetworkClientMessages::Adversarial(
+            .send(with_request_id(NetworkClientMessages::Adversarial(
                 near_network_primitives::types::NetworkAdversarialMessage::AdvCheckStorageConsistency,
-            ))
+            )))
             .await
         {
             Ok(result) => match result {
@@ -1346,14 +2063,32 @@ impl JsonRpcHandler {
 }
 
 fn rpc_handler(
+    request: HttpRequest,
     message: web::Json<Message>,
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
+    let header = request.headers().get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok());
+    let request_id = request_id::request_id(header);
+    let method = match &message.0 {
+        Message::Request(request) => request.method.as_str(),
+        _ => "",
+    };
+    let span = tracing::info_span!(
+        target: "jsonrpc",
+        "rpc_request",
+        request_id = %request_id,
+        method = %method
+    );
     let response = async move {
-        let message = handler.process(message.0).await?;
-        Ok(HttpResponse::Ok().json(&message))
+        let mut message = with_request(request_id.clone(), handler.process(message.0)).await?;
+        if let Message::Response(response) = &mut message {
+            if response.result.is_err() {
+                response.request_id = Some(request_id.clone());
+            }
+        }
+        Ok(HttpResponse::Ok().insert_header((REQUEST_ID_HEADER, request_id)).json(&message))
     };
-    response.boxed()
+    response.instrument(span).boxed()
 }
 
 fn status_handler(
@@ -1362,6 +2097,9 @@ fn status_handler(
     metrics::HTTP_STATUS_REQUEST_COUNT.inc();
 
     let response = async move {
+        if !handler.method_policy.allows("status") {
+            return Ok(HttpResponse::NotFound().finish());
+        }
         match handler.status().await {
             Ok(value) => Ok(HttpResponse::Ok().json(&value)),
             Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
@@ -1378,10 +2116,212 @@ async fn debug_handler(handler: web::Data<JsonRpcHandler>) -> Result<HttpRespons
     }
 }
 
+async fn sync_history_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.sync_history().await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn forks_handler(handler: web::Data<JsonRpcHandler>) -> Result<HttpResponse, HttpError> {
+    match handler.forks().await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn misbehavior_handler(
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    match handler.misbehavior().await {
+        Ok(Some(Ok(value))) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(Some(Err(err))) => Ok(HttpResponse::InternalServerError().body(err)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn production_skips_handler(
+    request: HttpRequest,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    match handler.production_skips().await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn produced_chunks_handler(
+    request: HttpRequest,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    match handler.produced_chunks().await {
+        Ok(Some(value)) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn chunk_apply_stats_handler(
+    request: HttpRequest,
+    path: web::Path<(CryptoHash, near_primitives::types::ShardId)>,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let (block_hash, shard_id) = path.into_inner();
+    match handler.chunk_apply_stats(block_hash, shard_id).await {
+        Ok(Some(Some(value))) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(Some(None)) => Ok(HttpResponse::NotFound().finish()),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+/// Number of blocks `/debug/api/producer_versions` summarizes by default, and
+/// at most.
+const DEFAULT_PRODUCER_VERSIONS_BLOCKS: u64 = 1_000;
+const MAX_PRODUCER_VERSIONS_BLOCKS: u64 = 10_000;
+
+#[derive(Deserialize)]
+struct ProducerVersionsQuery {
+    last_n_blocks: Option<u64>,
+}
+
+async fn producer_versions_handler(
+    request: HttpRequest,
+    query: web::Query<ProducerVersionsQuery>,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let last_n_blocks = query
+        .last_n_blocks
+        .unwrap_or(DEFAULT_PRODUCER_VERSIONS_BLOCKS)
+        .min(MAX_PRODUCER_VERSIONS_BLOCKS);
+    match handler.producer_versions(last_n_blocks).await {
+        Ok(Some(Ok(value))) => Ok(HttpResponse::Ok().json(&value)),
+        Ok(Some(Err(err))) => Ok(HttpResponse::InternalServerError().body(err)),
+        Ok(None) => Ok(HttpResponse::MethodNotAllowed().finish()),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn reload_validator_key_handler(
+    request: HttpRequest,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    if !handler.enable_validator_key_reload {
+        return Ok(HttpResponse::MethodNotAllowed().finish());
+    }
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    match handler.client_addr.send(with_request_id(ReloadValidatorKey)).await {
+        Ok(Ok(response)) => Ok(HttpResponse::Ok().json(&json!({
+            "public_key": response.public_key,
+            "switch_height": response.switch_height,
+        }))),
+        Ok(Err(err)) => Ok(HttpResponse::BadRequest().body(err.to_string())),
+        Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
+    }
+}
+
+async fn memory_stats_handler(
+    request: HttpRequest,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    if handler.heap_profiler.is_none() {
+        return Ok(HttpResponse::MethodNotAllowed().finish());
+    }
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    match memory_profile::allocator_stats() {
+        Ok(stats) => Ok(HttpResponse::Ok().json(&stats)),
+        Err(err) => Ok(HttpResponse::InternalServerError().body(err)),
+    }
+}
+
+async fn memory_profile_handler(
+    request: HttpRequest,
+    handler: web::Data<JsonRpcHandler>,
+) -> Result<HttpResponse, HttpError> {
+    let heap_profiler = match &handler.heap_profiler {
+        Some(heap_profiler) => heap_profiler,
+        None => return Ok(HttpResponse::MethodNotAllowed().finish()),
+    };
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let result = heap_profiler.lock().unwrap().dump();
+    match result {
+        Ok(path) => Ok(HttpResponse::Ok().json(&json!({ "path": path }))),
+        Err(err @ memory_profile::DumpError::TooSoon(_)) => {
+            Ok(HttpResponse::TooManyRequests().body(err.to_string()))
+        }
+        Err(err @ memory_profile::DumpError::Unsupported(_)) => {
+            Ok(HttpResponse::NotImplemented().body(err.to_string()))
+        }
+        Err(err @ memory_profile::DumpError::Failed(_)) => {
+            Ok(HttpResponse::InternalServerError().body(err.to_string()))
+        }
+    }
+}
+
+fn is_local_request(request: &HttpRequest) -> bool {
+    request.peer_addr().map_or(false, |addr| addr.ip().is_loopback())
+}
+
+async fn log_filter_handler(request: HttpRequest) -> Result<HttpResponse, HttpError> {
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    Ok(HttpResponse::Ok().json(&near_o11y::log_filter_status()))
+}
+
+/// Changes the log filter and returns the resulting one.
+async fn change_log_filter_handler(
+    request: HttpRequest,
+    change: web::Json<near_o11y::LogFilterChange>,
+) -> Result<HttpResponse, HttpError> {
+    if !is_local_request(&request) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let change = change.into_inner();
+    info!(target: "jsonrpc", ?change, "Changing the log filter");
+    if let Err(err) = change.apply() {
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(err) = source {
+            message = format!("{}: {}", message, err);
+            source = err.source();
+        }
+        return Ok(HttpResponse::BadRequest().body(message));
+    }
+    Ok(HttpResponse::Ok().json(&near_o11y::log_filter_status()))
+}
+
 fn health_handler(
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
     let response = async move {
+        if !handler.method_policy.allows("health") {
+            return Ok(HttpResponse::NotFound().finish());
+        }
         match handler.health().await {
             Ok(value) => Ok(HttpResponse::Ok().json(&value)),
             Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
@@ -1394,6 +2334,9 @@ fn network_info_handler(
     handler: web::Data<JsonRpcHandler>,
 ) -> impl Future<Output = Result<HttpResponse, HttpError>> {
     let response = async move {
+        if !handler.method_policy.allows("network_info") {
+            return Ok(HttpResponse::NotFound().finish());
+        }
         match handler.network_info().await {
             Ok(value) => Ok(HttpResponse::Ok().json(&value)),
             Err(_) => Ok(HttpResponse::ServiceUnavailable().finish()),
@@ -1404,6 +2347,7 @@ fn network_info_handler(
 
 pub async fn prometheus_handler() -> Result<HttpResponse, HttpError> {
     metrics::PROMETHEUS_REQUEST_COUNT.inc();
+    memory_profile::update_metrics();
 
     let mut buffer = vec![];
     let encoder = TextEncoder::new();
@@ -1425,6 +2369,8 @@ fn get_cors(cors_allowed_origins: &[String]) -> Cors {
     cors.allowed_methods(vec!["GET", "POST"])
         .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
         .allowed_header(http::header::CONTENT_TYPE)
+        .allowed_header(REQUEST_ID_HEADER)
+        .expose_headers(vec![REQUEST_ID_HEADER])
         .max_age(3600)
 }
 
@@ -1487,26 +2433,158 @@ pub fn start_http(
         polling_config,
         limits_config,
         enable_debug_rpc,
+        enable_validator_key_reload,
+        enable_memory_profiling,
+        memory_profile_dir,
+        query_timeout,
+        disabled_methods,
+        listeners,
+        debug_addr,
+        debug_auth,
     } = config;
-    let prometheus_addr = prometheus_addr.filter(|it| it != &addr);
-    let cors_allowed_origins_clone = cors_allowed_origins.clone();
-    info!(target:"network", "Starting http server at {}", addr);
+    let prometheus_addr =
+        prometheus_addr.filter(|it| it != &addr && Some(it) != debug_addr.as_ref());
+    // The value of the `Authorization` header the monitoring servers require.
+    let authorization = debug_auth.map(|auth| auth.authorization());
+    let split_debug = debug_addr.is_some();
+    let heap_profiler = enable_memory_profiling.then(|| {
+        memory_profile::export_metrics();
+        let dir = memory_profile_dir.unwrap_or_else(std::env::temp_dir);
+        Arc::new(Mutex::new(memory_profile::HeapProfiler::new(dir)))
+    });
+    let handler = JsonRpcHandler {
+        client_addr,
+        view_client_addr,
+        polling_config,
+        genesis_config,
+        enable_debug_rpc,
+        enable_validator_key_reload,
+        heap_profiler,
+        query_timeout,
+        max_headers_per_request: limits_config.max_headers_per_request,
+        disabled_methods,
+        method_policy: MethodPolicy::default(),
+        #[cfg(feature = "test_features")]
+        peer_manager_addr,
+        #[cfg(feature = "test_features")]
+        routing_table_addr,
+    };
     let mut servers = Vec::new();
-    let server = HttpServer::new(move || {
-        App::new()
+    info!(target:"network", "Starting http server at {}", addr);
+    let server = start_rpc_server(
+        &addr,
+        handler.clone(),
+        &cors_allowed_origins,
+        &limits_config,
+        !split_debug,
+        !split_debug,
+        !split_debug,
+    );
+    servers.push(("JSON RPC", server));
+
+    for listener in listeners {
+        info!(target:"network", "Starting restricted http server at {}", listener.addr);
+        let handler = JsonRpcHandler {
+            enable_debug_rpc: enable_debug_rpc && listener.debug_pages,
+            enable_validator_key_reload: enable_validator_key_reload
+                && listener.validator_key_reload,
+            heap_profiler: handler.heap_profiler.clone().filter(|_| listener.debug_pages),
+            method_policy: MethodPolicy::new(&listener),
+            ..handler.clone()
+        };
+        let server = start_rpc_server(
+            &listener.addr,
+            handler,
+            &cors_allowed_origins,
+            &limits_config,
+            listener.debug_pages && !split_debug,
+            listener.validator_key_reload && !split_debug,
+            !split_debug,
+        );
+        servers.push(("JSON RPC (restricted)", server));
+    }
+
+    if let Some(debug_addr) = debug_addr {
+        info!(target:"network", "Starting http debug server at {}", debug_addr);
+        let cors_allowed_origins = cors_allowed_origins.clone();
+        let authorization = authorization.clone();
+        let server = HttpServer::new(move || {
+            let app = App::new()
+                .wrap(get_cors(&cors_allowed_origins))
+                .data(handler.clone())
+                .wrap(middleware::Logger::default())
+                .service(
+                    web::scope("")
+                        .guard(authorized_guard(authorization.clone()))
+                        .configure(configure_metrics)
+                        .configure(configure_debug_pages),
+                );
+            if authorization.is_some() {
+                app.default_service(web::route().to(unauthorized_handler))
+            } else {
+                app
+            }
+        })
+        .bind(debug_addr)
+        .unwrap()
+        .workers(2)
+        .shutdown_timeout(5)
+        .disable_signals()
+        .run();
+        servers.push(("Debug", server));
+    }
+
+    if let Some(prometheus_addr) = prometheus_addr {
+        info!(target:"network", "Starting http monitoring server at {}", prometheus_addr);
+        // Export only the /metrics service. It's a read-only service and can have very relaxed
+        // access restrictions.
+        let server = HttpServer::new(move || {
+            let app = App::new()
+                .wrap(get_cors(&cors_allowed_origins))
+                .wrap(middleware::Logger::default())
+                .service(
+                    web::scope("")
+                        .guard(authorized_guard(authorization.clone()))
+                        .configure(configure_metrics),
+                );
+            if authorization.is_some() {
+                app.default_service(web::route().to(unauthorized_handler))
+            } else {
+                app
+            }
+        })
+        .bind(prometheus_addr)
+        .unwrap()
+        .workers(2)
+        .shutdown_timeout(5)
+        .disable_signals()
+        .run();
+        servers.push(("Prometheus Metrics", server));
+    }
+
+    servers
+}
+
+/// Starts a server of the RPC at the address, serving `/metrics` only if
+/// `metrics` is set, the `/debug` pages and endpoints only if `debug_pages`
+/// is and the reload of the validator key if either `debug_pages` or
+/// `validator_key_reload` is.
+fn start_rpc_server(
+    addr: &str,
+    handler: JsonRpcHandler,
+    cors_allowed_origins: &[String],
+    limits_config: &RpcLimitsConfig,
+    debug_pages: bool,
+    validator_key_reload: bool,
+    metrics: bool,
+) -> actix_web::dev::Server {
+    let cors_allowed_origins = cors_allowed_origins.to_vec();
+    let json_payload_max_size = limits_config.json_payload_max_size;
+    HttpServer::new(move || {
+        let app = App::new()
             .wrap(get_cors(&cors_allowed_origins))
-            .data(JsonRpcHandler {
-                client_addr: client_addr.clone(),
-                view_client_addr: view_client_addr.clone(),
-                polling_config,
-                genesis_config: genesis_config.clone(),
-                enable_debug_rpc,
-                #[cfg(feature = "test_features")]
-                peer_manager_addr: peer_manager_addr.clone(),
-                #[cfg(feature = "test_features")]
-                routing_table_addr: routing_table_addr.clone(),
-            })
-            .app_data(web::JsonConfig::default().limit(limits_config.json_payload_max_size))
+            .data(handler.clone())
+            .app_data(web::JsonConfig::default().limit(json_payload_max_size))
             .wrap(middleware::Logger::default())
             .service(web::resource("/").route(web::post().to(rpc_handler)))
             .service(
@@ -1519,42 +2597,95 @@ pub fn start_http(
                     .route(web::get().to(health_handler))
                     .route(web::head().to(health_handler)),
             )
-            .service(web::resource("/network_info").route(web::get().to(network_info_handler)))
-            .service(web::resource("/metrics").route(web::get().to(prometheus_handler)))
-            .service(web::resource("/debug/api/status").route(web::get().to(debug_handler)))
-            .service(debug_html)
-            .service(last_blocks_html)
-            .service(network_info_html)
-            .service(epoch_info_html)
-            .service(chain_n_chunk_info_html)
+            .service(web::resource("/network_info").route(web::get().to(network_info_handler)));
+        let app = if metrics { app.configure(configure_metrics) } else { app };
+        if debug_pages {
+            app.configure(configure_debug_pages)
+        } else if validator_key_reload {
+            app.configure(configure_validator_key_reload)
+        } else {
+            app
+        }
     })
     .bind(addr)
     .unwrap()
     .workers(4)
     .shutdown_timeout(5)
     .disable_signals()
-    .run();
+    .run()
+}
 
-    servers.push(("JSON RPC", server));
+fn configure_metrics(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/metrics").route(web::get().to(prometheus_handler)));
+}
 
-    if let Some(prometheus_addr) = prometheus_addr {
-        info!(target:"network", "Starting http monitoring server at {}", prometheus_addr);
-        // Export only the /metrics service. It's a read-only service and can have very relaxed
-        // access restrictions.
-        let server = HttpServer::new(move || {
-            App::new()
-                .wrap(get_cors(&cors_allowed_origins_clone))
-                .wrap(middleware::Logger::default())
-                .service(web::resource("/metrics").route(web::get().to(prometheus_handler)))
+fn configure_validator_key_reload(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/debug/api/reload_validator_key")
+            .route(web::post().to(reload_validator_key_handler)),
+    );
+}
+
+fn configure_debug_pages(cfg: &mut web::ServiceConfig) {
+    configure_validator_key_reload(cfg);
+    cfg.service(web::resource("/debug/api/status").route(web::get().to(debug_handler)))
+        .service(
+            web::resource("/debug/api/sync_history").route(web::get().to(sync_history_handler)),
+        )
+        .service(web::resource("/debug/api/misbehavior").route(web::get().to(misbehavior_handler)))
+        .service(web::resource("/debug/api/forks").route(web::get().to(forks_handler)))
+        .service(
+            web::resource("/debug/api/production_skips")
+                .route(web::get().to(production_skips_handler)),
+        )
+        .service(
+            web::resource("/debug/api/produced_chunks")
+                .route(web::get().to(produced_chunks_handler)),
+        )
+        .service(
+            web::resource("/debug/api/chunk_apply_stats/{block_hash}/{shard_id}")
+                .route(web::get().to(chunk_apply_stats_handler)),
+        )
+        .service(
+            web::resource("/debug/api/producer_versions")
+                .route(web::get().to(producer_versions_handler)),
+        )
+        .service(
+            web::resource("/debug/api/log_filter")
+                .route(web::get().to(log_filter_handler))
+                .route(web::post().to(change_log_filter_handler)),
+        )
+        .service(
+            web::resource("/debug/api/memory_stats").route(web::get().to(memory_stats_handler)),
+        )
+        .service(
+            web::resource("/debug/api/memory_profile")
+                .route(web::post().to(memory_profile_handler)),
+        )
+        .service(debug_html)
+        .service(last_blocks_html)
+        .service(network_info_html)
+        .service(epoch_info_html)
+        .service(chain_n_chunk_info_html);
+}
+
+/// Lets through the requests with the `Authorization` header, or all of them
+/// if `None`.
+fn authorized_guard(authorization: Option<String>) -> impl guard::Guard {
+    guard::fn_guard(move |head: &RequestHead| {
+        authorization.as_ref().map_or(true, |authorization| {
+            head.headers
+                .get(http::header::AUTHORIZATION)
+                .map_or(false, |value| value.as_bytes() == authorization.as_bytes())
         })
-        .bind(prometheus_addr)
-        .unwrap()
-        .workers(2)
-        .shutdown_timeout(5)
-        .disable_signals()
-        .run();
-        servers.push(("Prometheus Metrics", server));
-    }
+    })
+}
 
-    servers
+/// Answers the requests the guard of a monitoring server requiring
+/// credentials didn't let through, along with the ones for pages it doesn't
+/// serve.
+async fn unauthorized_handler() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((http::header::WWW_AUTHENTICATE, "Basic realm=\"neard\""))
+        .finish()
 }
@@ -0,0 +1,307 @@
+//! Statistics of the allocator and heap profiles of a running node, served by
+//! `/debug/api/memory_stats` and `/debug/api/memory_profile` when
+//! `enable_memory_profiling` is set.
+//!
+//! Both need neard built with jemalloc, the heap profiles also need jemalloc
+//! built with profiling, the `jemalloc_profiling` feature of neard, and the
+//! profiling activated at startup with `_RJEM_MALLOC_CONF=prof:true`.  The
+//! profiles are written in the jemalloc format, which `jeprof` reads.  A
+//! profile is written at most every [`MIN_DUMP_INTERVAL`] and only the last
+//! [`MAX_RETAINED_DUMPS`] ones are kept, so that the endpoint can't fill the
+//! disk.
+
+use crate::metrics;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Shortest time between two heap profiles.
+pub(crate) const MIN_DUMP_INTERVAL: Duration = Duration::from_secs(60);
+/// Number of heap profiles kept, the older ones written by the node are
+/// removed.
+pub(crate) const MAX_RETAINED_DUMPS: usize = 5;
+
+#[derive(Serialize, Debug, Default)]
+pub struct ArenaStats {
+    pub index: u32,
+    pub resident: u64,
+    pub small_allocated: u64,
+    pub large_allocated: u64,
+}
+
+/// Bytes of memory of the allocator, see the `stats.*` entries of the
+/// jemalloc documentation.
+#[derive(Serialize, Debug, Default)]
+pub struct AllocatorStats {
+    pub allocated: u64,
+    pub active: u64,
+    pub metadata: u64,
+    pub resident: u64,
+    pub mapped: u64,
+    pub retained: u64,
+    /// The arenas in use.
+    pub arenas: Vec<ArenaStats>,
+}
+
+#[derive(Debug)]
+pub(crate) enum DumpError {
+    /// A profile was written less than [`MIN_DUMP_INTERVAL`] ago, the next one
+    /// can be written after the duration.
+    TooSoon(Duration),
+    Unsupported(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for DumpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpError::TooSoon(retry_after) => write!(
+                f,
+                "the last heap profile was written less than {:?} ago, retry in {}s",
+                MIN_DUMP_INTERVAL,
+                retry_after.as_secs() + 1
+            ),
+            DumpError::Unsupported(message) | DumpError::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Reads and writes the `mallctl` entries of jemalloc.
+#[cfg(feature = "jemalloc")]
+mod mallctl {
+    use std::ffi::CString;
+    use std::os::raw::{c_int, c_void};
+
+    fn check(name: &str, ret: c_int) -> Result<(), String> {
+        match ret {
+            0 => Ok(()),
+            errno => Err(format!("{}: {}", name, std::io::Error::from_raw_os_error(errno))),
+        }
+    }
+
+    /// Reads the entry.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be the type of the entry.
+    pub(super) unsafe fn read<T: Copy + Default>(name: &str) -> Result<T, String> {
+        let c_name = CString::new(name).map_err(|err| err.to_string())?;
+        let mut value = T::default();
+        let mut len = std::mem::size_of::<T>();
+        let ret = tikv_jemalloc_sys::mallctl(
+            c_name.as_ptr(),
+            &mut value as *mut T as *mut c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        );
+        check(name, ret).map(|()| value)
+    }
+
+    /// Writes the entry.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be the type of the entry.
+    pub(super) unsafe fn write<T>(name: &str, mut value: T) -> Result<(), String> {
+        let c_name = CString::new(name).map_err(|err| err.to_string())?;
+        let ret = tikv_jemalloc_sys::mallctl(
+            c_name.as_ptr(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut value as *mut T as *mut c_void,
+            std::mem::size_of::<T>(),
+        );
+        check(name, ret)
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+pub(crate) fn allocator_stats() -> Result<AllocatorStats, String> {
+    // SAFETY: the `stats.*` entries read are all `size_t`.
+    let read = |name: &str| unsafe { mallctl::read::<usize>(name) }.map(|value| value as u64);
+    // The statistics are cached by jemalloc until the epoch advances.
+    // SAFETY: `epoch` is a `uint64_t`.
+    unsafe { mallctl::write::<u64>("epoch", 1) }?;
+    let mut allocator_stats = AllocatorStats {
+        allocated: read("stats.allocated")?,
+        active: read("stats.active")?,
+        metadata: read("stats.metadata")?,
+        resident: read("stats.resident")?,
+        mapped: read("stats.mapped")?,
+        retained: read("stats.retained")?,
+        arenas: vec![],
+    };
+    // SAFETY: `arenas.narenas` is an `unsigned`.
+    let narenas = unsafe { mallctl::read::<u32>("arenas.narenas") }?;
+    for index in 0..narenas {
+        let read_arena = |name: &str| read(&format!("stats.arenas.{}.{}", index, name));
+        // The arenas which were never used have no statistics.
+        if let Ok(resident) = read_arena("resident") {
+            allocator_stats.arenas.push(ArenaStats {
+                index,
+                resident,
+                small_allocated: read_arena("small.allocated").unwrap_or(0),
+                large_allocated: read_arena("large.allocated").unwrap_or(0),
+            });
+        }
+    }
+    Ok(allocator_stats)
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub(crate) fn allocator_stats() -> Result<AllocatorStats, String> {
+    Err(NOT_JEMALLOC.to_string())
+}
+
+#[cfg(not(feature = "jemalloc"))]
+const NOT_JEMALLOC: &str = "neard was built without jemalloc";
+
+/// Whether the statistics of the allocator are exported as metrics.
+static EXPORT_METRICS: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn export_metrics() {
+    EXPORT_METRICS.store(true, Ordering::Relaxed);
+}
+
+/// Updates the metrics of the allocator, called before they're gathered.
+pub(crate) fn update_metrics() {
+    if !EXPORT_METRICS.load(Ordering::Relaxed) {
+        return;
+    }
+    let stats = match allocator_stats() {
+        Ok(stats) => stats,
+        Err(_) => return,
+    };
+    for (stat, bytes) in [
+        ("allocated", stats.allocated),
+        ("active", stats.active),
+        ("metadata", stats.metadata),
+        ("resident", stats.resident),
+        ("mapped", stats.mapped),
+        ("retained", stats.retained),
+    ] {
+        metrics::JEMALLOC_BYTES.with_label_values(&[stat]).set(bytes as i64);
+    }
+    for arena in &stats.arenas {
+        metrics::JEMALLOC_ARENA_RESIDENT_BYTES
+            .with_label_values(&[&arena.index.to_string()])
+            .set(arena.resident as i64);
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+fn write_heap_profile(path: &Path) -> Result<(), DumpError> {
+    let path = std::ffi::CString::new(path.to_string_lossy().into_owned())
+        .map_err(|err| DumpError::Failed(err.to_string()))?;
+    // SAFETY: `prof.dump` takes the path as a C string, which outlives the call.
+    unsafe { mallctl::write("prof.dump", path.as_ptr()) }.map_err(|err| {
+        // jemalloc has no `prof.dump` entry without profiling, and fails to
+        // dump with it inactive.
+        // SAFETY: `opt.prof` is a `bool`.
+        if unsafe { mallctl::read::<bool>("opt.prof") }.unwrap_or(false) {
+            DumpError::Failed(format!("failed to write the heap profile: {}", err))
+        } else {
+            DumpError::Unsupported(
+                "heap profiling is inactive, it needs neard built with the jemalloc_profiling \
+                 feature and started with _RJEM_MALLOC_CONF=prof:true"
+                    .to_string(),
+            )
+        }
+    })
+}
+
+#[cfg(not(feature = "jemalloc"))]
+fn write_heap_profile(_path: &Path) -> Result<(), DumpError> {
+    Err(DumpError::Unsupported(NOT_JEMALLOC.to_string()))
+}
+
+/// Writes the heap profiles, see the module documentation.
+pub(crate) struct HeapProfiler {
+    dir: PathBuf,
+    last_dump: Option<Instant>,
+    /// Number of profiles written, which numbers their files.
+    num_dumps: u64,
+    /// The profiles kept, oldest first.
+    dumps: VecDeque<PathBuf>,
+}
+
+impl HeapProfiler {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, last_dump: None, num_dumps: 0, dumps: VecDeque::new() }
+    }
+
+    /// Writes a heap profile and returns its path.
+    pub fn dump(&mut self) -> Result<PathBuf, DumpError> {
+        self.dump_with(Instant::now(), write_heap_profile)
+    }
+
+    fn dump_with(
+        &mut self,
+        now: Instant,
+        write: impl FnOnce(&Path) -> Result<(), DumpError>,
+    ) -> Result<PathBuf, DumpError> {
+        if let Some(last_dump) = self.last_dump {
+            let elapsed = now.saturating_duration_since(last_dump);
+            if elapsed < MIN_DUMP_INTERVAL {
+                return Err(DumpError::TooSoon(MIN_DUMP_INTERVAL - elapsed));
+            }
+        }
+        std::fs::create_dir_all(&self.dir).map_err(|err| {
+            DumpError::Failed(format!("can't create {}: {}", self.dir.display(), err))
+        })?;
+        let timestamp = near_primitives::time::Clock::utc().format("%Y%m%dT%H%M%S");
+        let path = self.dir.join(format!(
+            "neard-{}-{}-{}.heap",
+            std::process::id(),
+            self.num_dumps,
+            timestamp
+        ));
+        write(&path)?;
+        self.last_dump = Some(now);
+        self.num_dumps += 1;
+        self.dumps.push_back(path.clone());
+        while self.dumps.len() > MAX_RETAINED_DUMPS {
+            if let Some(old) = self.dumps.pop_front() {
+                if let Err(err) = std::fs::remove_file(&old) {
+                    tracing::warn!(target: "jsonrpc", ?err, path = %old.display(), "Failed to remove an old heap profile");
+                }
+            }
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_rate_limit_and_retention() {
+        let dir = std::env::temp_dir().join(format!("heap-profiler-test-{}", std::process::id()));
+        let mut profiler = HeapProfiler::new(dir.clone());
+        let write = |path: &Path| {
+            std::fs::write(path, b"profile").map_err(|err| DumpError::Failed(err.to_string()))
+        };
+        let start = Instant::now();
+        let first = profiler.dump_with(start, write).unwrap();
+        assert!(first.exists());
+        match profiler.dump_with(start + Duration::from_secs(10), write) {
+            Err(DumpError::TooSoon(retry_after)) => {
+                assert_eq!(retry_after, MIN_DUMP_INTERVAL - Duration::from_secs(10))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let mut paths = vec![first];
+        for i in 1..=MAX_RETAINED_DUMPS as u32 {
+            paths.push(profiler.dump_with(start + MIN_DUMP_INTERVAL * i, write).unwrap());
+        }
+        assert_eq!(profiler.dumps.len(), MAX_RETAINED_DUMPS);
+        assert!(!paths[0].exists());
+        assert!(paths[1..].iter().all(|path| path.exists()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
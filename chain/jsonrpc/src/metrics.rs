@@ -1,4 +1,4 @@
-use near_metrics::{HistogramVec, IntCounter, IntCounterVec};
+use near_metrics::{HistogramVec, IntCounter, IntCounterVec, IntGaugeVec};
 use once_cell::sync::Lazy;
 
 pub static RPC_PROCESSING_TIME: Lazy<HistogramVec> = Lazy::new(|| {
@@ -17,6 +17,15 @@ pub static RPC_TIMEOUT_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static RPC_TX_BLOCK_HASH_REJECTED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    near_metrics::try_create_int_counter_vec(
+        "near_rpc_tx_block_hash_rejected_total",
+        "Total count of transactions rejected on submission for the block they're based on, by \
+         reason",
+        &["reason"],
+    )
+    .unwrap()
+});
 pub static PROMETHEUS_REQUEST_COUNT: Lazy<IntCounter> = Lazy::new(|| {
     near_metrics::try_create_int_counter(
         "near_http_prometheus_requests_total",
@@ -47,3 +56,27 @@ pub static RPC_ERROR_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static RPC_DEPRECATED_CALLS: Lazy<IntCounterVec> = Lazy::new(|| {
+    near_metrics::try_create_int_counter_vec(
+        "near_rpc_deprecated_calls_total",
+        "Total count of calls to deprecated RPC methods, by method",
+        &["method"],
+    )
+    .unwrap()
+});
+pub static JEMALLOC_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge_vec(
+        "near_jemalloc_bytes",
+        "Bytes of memory of jemalloc by statistic, with enable_memory_profiling",
+        &["stat"],
+    )
+    .unwrap()
+});
+pub static JEMALLOC_ARENA_RESIDENT_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge_vec(
+        "near_jemalloc_arena_resident_bytes",
+        "Resident bytes of memory of jemalloc by arena, with enable_memory_profiling",
+        &["arena"],
+    )
+    .unwrap()
+});
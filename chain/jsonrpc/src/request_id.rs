@@ -0,0 +1,90 @@
+//! Ids of the RPC requests, to correlate a request with the work it triggers
+//! in the logs.
+//!
+//! The id is the one given by the caller in the `x-request-id` header, or a
+//! new one.  The request is processed in a span with the id, and the messages
+//! sent to the client actors on its behalf are wrapped in [`WithRequestId`] for
+//! them to handle the messages in a span with the id too.  With OpenTelemetry
+//! enabled the id is an attribute of those spans.  The id is echoed in the
+//! `x-request-id` header of the response, and in the `request_id` field of an
+//! error response.
+
+use near_client::WithRequestId;
+use near_primitives::hash::hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest id accepted from the caller, longer ones are replaced by new ones.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// Id of the request being processed by the task.
+    static REQUEST_ID: String;
+}
+
+/// The id given by the caller if it's printable ASCII of a sane length, a new
+/// one otherwise.
+pub(crate) fn request_id(header: Option<&str>) -> String {
+    match header {
+        Some(id)
+            if !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|byte| byte.is_ascii_graphic()) =>
+        {
+            id.to_string()
+        }
+        _ => new_request_id(),
+    }
+}
+
+/// 16 hex digits, unique across the requests of all the nodes with high
+/// probability.
+fn new_request_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let seq = NEXT.fetch_add(1, Ordering::Relaxed);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let seed =
+        [&nanos.to_le_bytes()[..], &seq.to_le_bytes(), &std::process::id().to_le_bytes()].concat();
+    hash(&seed).as_ref()[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Runs `future`, processing the request, with `request_id` as the id the
+/// messages to the client actors are sent with.
+pub(crate) async fn with_request<F: std::future::Future>(
+    request_id: String,
+    future: F,
+) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// Wraps the message sent to a client actor with the id of the request being
+/// processed, or a new one outside of the JSON RPC requests, e.g. for
+/// `/status`.
+pub(crate) fn with_request_id<M>(msg: M) -> WithRequestId<M> {
+    let request_id = REQUEST_ID.try_with(|id| id.clone()).unwrap_or_else(|_| new_request_id());
+    WithRequestId { request_id, msg }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id() {
+        assert_eq!(request_id(Some("abc-123")), "abc-123");
+        for invalid in [None, Some(""), Some("a b"), Some("é"), Some(&"a".repeat(129)[..])] {
+            let id = request_id(invalid);
+            assert_eq!(id.len(), 16, "{:?}", invalid);
+            assert!(id.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        }
+        assert_ne!(request_id(None), request_id(None));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let msg = runtime.block_on(with_request("abc".to_string(), async { with_request_id(()) }));
+        assert_eq!(msg.request_id, "abc");
+        assert_eq!(with_request_id(()).request_id.len(), 16);
+    }
+}
@@ -1,18 +1,127 @@
-/// A blacklist for socket addresses.  Supports adding individual IP:port tuples
-/// to the blacklist or entire IPs.
+use crate::normalized_addr::NormalizedAddr;
+use std::collections::{HashMap, HashSet};
+
+/// Name of the list of the entries of a `blacklist` configured as a plain
+/// list rather than as lists by name.
+pub const DEFAULT_BLACKLIST: &str = "default";
+
+/// A list of blacklist entries as configured, e.g. the `abuse` list.  A
+/// disabled list keeps its entries but doesn't block anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedBlacklist {
+    pub name: String,
+    pub enabled: bool,
+    pub entries: Vec<String>,
+}
+
+impl NamedBlacklist {
+    /// The list of a `blacklist` configured as a plain list, always enabled.
+    pub fn default_list(entries: Vec<String>) -> Self {
+        Self { name: DEFAULT_BLACKLIST.to_string(), enabled: true, entries }
+    }
+}
+
+/// An entry which is on two lists, the rest of the occurrences of the entry
+/// on the same lists aside.  Entries are compared normalized when they're
+/// valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateBlacklistEntry {
+    pub entry: String,
+    pub first_list: String,
+    pub second_list: String,
+}
+
+/// Returns the entries which are on more than one of the lists, once per
+/// list they're on after the first one.
+pub fn duplicate_blacklist_entries(lists: &[NamedBlacklist]) -> Vec<DuplicateBlacklistEntry> {
+    let mut first_lists: HashMap<String, &str> = HashMap::new();
+    let mut duplicates = vec![];
+    for list in lists {
+        let mut seen_on_list = HashSet::new();
+        for entry in &list.entries {
+            let key = entry
+                .parse::<NormalizedAddr>()
+                .map_or_else(|_| entry.clone(), |addr| addr.to_string());
+            if !seen_on_list.insert(key.clone()) {
+                continue;
+            }
+            match first_lists.get(&key) {
+                Some(first_list) => duplicates.push(DuplicateBlacklistEntry {
+                    entry: entry.clone(),
+                    first_list: first_list.to_string(),
+                    second_list: list.name.clone(),
+                }),
+                None => {
+                    first_lists.insert(key, &list.name);
+                }
+            }
+        }
+    }
+    duplicates
+}
+
+/// A blacklist for socket addresses, made of named lists which can be
+/// enabled and disabled.  Supports adding individual IP:port tuples to the
+/// blacklist or entire IPs.  An address is blacklisted if it's on any of the
+/// enabled lists, and reported with the first of them.
 #[derive(Debug, Default, Clone)]
-pub struct Blacklist(
-    /// Only IPv6 addresses are stored.  IPv4 addresses are mapped to IPv6 before being added.
+pub struct Blacklist {
+    /// The lists in the order of the config.
+    lists: Vec<List>,
+}
+
+#[derive(Debug, Clone)]
+struct List {
+    name: String,
+    enabled: bool,
+    /// The IPs are normalized, without their ports.
     ///
-    /// Without the mapping, we could blacklist an IPv4 and still interact with that address if
-    /// it is presented as IPv6.
-    std::collections::HashMap<std::net::Ipv6Addr, PortsSet>,
-);
+    /// Without the normalization, we could blacklist an IPv4 and still interact with that
+    /// address if it is presented as IPv6.
+    addrs: HashMap<NormalizedAddr, PortsSet>,
+}
+
+impl List {
+    fn add(&mut self, addr: &str) -> Result<(), std::net::AddrParseError> {
+        let addr = addr.parse::<NormalizedAddr>()?;
+        match addr.port() {
+            None => {
+                self.addrs.entry(addr).and_modify(|ports| ports.add_all()).or_insert(PortsSet::All);
+            }
+            Some(port) => {
+                self.addrs
+                    .entry(addr.without_port())
+                    .and_modify(|ports| ports.add_port(port))
+                    .or_insert_with(|| PortsSet::new(port));
+            }
+        }
+        Ok(())
+    }
+
+    /// The entry of the list covering the address: its IP for all the ports
+    /// or the address itself.
+    fn entry_of(&self, addr: &std::net::SocketAddr) -> Option<NormalizedAddr> {
+        match self.addrs.get(&NormalizedAddr::from_ip(addr.ip()))? {
+            PortsSet::All => Some(NormalizedAddr::from_ip(addr.ip())),
+            ports if ports.contains(addr.port()) => Some(NormalizedAddr::from_socket_addr(*addr)),
+            _ => None,
+        }
+    }
+}
+
+/// The list and the entry of the list an address is blacklisted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlacklistMatch<'a> {
+    pub list: &'a str,
+    /// The entry normalized, e.g. `192.0.2.4` for `::ffff:192.0.2.4`.  An IP
+    /// on its own covers the entries for its ports.
+    pub entry: NormalizedAddr,
+}
 
 // TODO(CP-34): merge Blacklist with whitelist functionality and replace them with sth
 // like AuthorizationConfig.
 impl Blacklist {
-    /// Construct a blacklist from list of addresses.
+    /// Construct a blacklist from list of addresses, all on the default list.
     ///
     /// Arguments:
     /// - `blacklist` - list of strings in one of the following format:
@@ -30,62 +139,70 @@ impl Blacklist {
         result
     }
 
-    fn add(&mut self, addr: &str) -> Result<(), std::net::AddrParseError> {
-        match addr.parse::<PatternAddr>()? {
-            PatternAddr::Ip(ip) => {
-                self.0.entry(ip).and_modify(|ports| ports.add_all()).or_insert(PortsSet::All);
-            }
-            PatternAddr::IpPort(addr) => {
-                self.0
-                    .entry(*addr.ip())
-                    .and_modify(|ports| ports.add_port(addr.port()))
-                    .or_insert_with(|| PortsSet::new(addr.port()));
+    /// Constructs a blacklist from the lists of the config.
+    pub fn from_lists<'a>(lists: impl IntoIterator<Item = &'a NamedBlacklist>) -> Self {
+        let mut result = Self::default();
+        for named in lists {
+            let mut list =
+                List { name: named.name.clone(), enabled: named.enabled, addrs: HashMap::new() };
+            for addr in &named.entries {
+                if list.add(addr).is_err() {
+                    tracing::warn!(target: "network", list = %named.name, "{}: invalid blacklist pattern, ignoring", addr);
+                }
             }
+            result.lists.push(list);
         }
-        Ok(())
+        result
     }
 
-    /// Returns whether given address is on the blacklist.
-    pub fn contains(&self, addr: &std::net::SocketAddr) -> bool {
-        let ip = match addr.ip() {
-            std::net::IpAddr::V4(ip) => ip.to_ipv6_mapped(),
-            std::net::IpAddr::V6(ip) => ip,
+    /// Adds the address to the default list.
+    fn add(&mut self, addr: &str) -> Result<(), std::net::AddrParseError> {
+        let index = match self.lists.iter().position(|list| list.name == DEFAULT_BLACKLIST) {
+            Some(index) => index,
+            None => {
+                self.lists.push(List {
+                    name: DEFAULT_BLACKLIST.to_string(),
+                    enabled: true,
+                    addrs: HashMap::new(),
+                });
+                self.lists.len() - 1
+            }
         };
-        match self.0.get(&ip) {
+        self.lists[index].add(addr)
+    }
+
+    /// Enables or disables the list, keeping its entries.  Returns whether
+    /// there's such a list.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.lists.iter_mut().find(|list| list.name == name) {
+            Some(list) => {
+                list.enabled = enabled;
+                true
+            }
             None => false,
-            Some(ports) => ports.contains(addr.port()),
         }
     }
-}
 
-/// Used to match a socket addr by IP:Port or only by IP
-#[cfg_attr(test, derive(Debug, PartialEq))]
-enum PatternAddr {
-    Ip(std::net::Ipv6Addr),
-    IpPort(std::net::SocketAddrV6),
-}
+    /// The names of the lists with whether they're enabled.
+    pub fn lists(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.lists.iter().map(|list| (list.name.as_str(), list.enabled))
+    }
 
-impl std::str::FromStr for PatternAddr {
-    type Err = std::net::AddrParseError;
+    /// Returns the first enabled list the address is on, with the entry.
+    pub fn match_of(&self, addr: &std::net::SocketAddr) -> Option<BlacklistMatch<'_>> {
+        self.lists.iter().filter(|list| list.enabled).find_map(|list| {
+            Some(BlacklistMatch { list: list.name.as_str(), entry: list.entry_of(addr)? })
+        })
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Ok(ip_addr) = s.parse::<std::net::IpAddr>() {
-            let ip_addr_v6 = match ip_addr {
-                std::net::IpAddr::V4(ip) => ip.to_ipv6_mapped(),
-                std::net::IpAddr::V6(ip) => ip,
-            };
-            return Ok(PatternAddr::Ip(ip_addr_v6));
-        }
-        let socket_addr_v6 = match s.parse::<std::net::SocketAddr>()? {
-            std::net::SocketAddr::V4(socket_addr) => std::net::SocketAddrV6::new(
-                socket_addr.ip().to_ipv6_mapped(),
-                socket_addr.port(),
-                0,
-                0,
-            ),
-            std::net::SocketAddr::V6(socket_addr) => socket_addr,
-        };
-        Ok(PatternAddr::IpPort(socket_addr_v6))
+    /// Returns the name of the first enabled list the address is on.
+    pub fn list_of(&self, addr: &std::net::SocketAddr) -> Option<&str> {
+        self.match_of(addr).map(|blacklist_match| blacklist_match.list)
+    }
+
+    /// Returns whether given address is on the blacklist.
+    pub fn contains(&self, addr: &std::net::SocketAddr) -> bool {
+        self.list_of(addr).is_some()
     }
 }
 
@@ -124,9 +241,8 @@ mod test {
     #[test]
     fn test_pattern_addr() {
         fn parse(value: &str) -> String {
-            match value.parse() {
-                Ok(super::PatternAddr::Ip(ip)) => ip.to_string(),
-                Ok(super::PatternAddr::IpPort(addr)) => addr.to_string(),
+            match value.parse::<super::NormalizedAddr>() {
+                Ok(addr) => addr.to_string(),
                 Err(_) => "err".to_string(),
             }
         }
@@ -137,15 +253,15 @@ mod test {
         assert_eq!("err", parse("192.0.2.4.5"));
         assert_eq!("err", parse("192.0.2.4:424242"));
 
-        assert_eq!("::ffff:192.0.2.4", parse("192.0.2.4"));
-        assert_eq!("[::ffff:192.0.2.4]:0", parse("192.0.2.4:0"));
-        assert_eq!("[::ffff:192.0.2.4]:42", parse("192.0.2.4:42"));
+        assert_eq!("192.0.2.4", parse("192.0.2.4"));
+        assert_eq!("192.0.2.4:0", parse("192.0.2.4:0"));
+        assert_eq!("192.0.2.4:42", parse("192.0.2.4:42"));
 
         assert_eq!("::1", parse("::1"));
         assert_eq!("[::1]:42", parse("[::1]:42"));
 
-        assert_eq!("::ffff:127.0.0.1", parse("::ffff:127.0.0.1"));
-        assert_eq!("[::ffff:127.0.0.1]:42", parse("[::ffff:127.0.0.1]:42"));
+        assert_eq!("127.0.0.1", parse("::ffff:127.0.0.1"));
+        assert_eq!("127.0.0.1:42", parse("[::ffff:127.0.0.1]:42"));
     }
 
     #[test]
@@ -189,5 +305,237 @@ mod test {
         assert!(blacklist.contains(&SocketAddr::new(mapped_lo4, 8080)));
         assert!(blacklist.contains(&SocketAddr::new(mapped_ip, 42)));
         assert!(!blacklist.contains(&SocketAddr::new(mapped_ip, 8080)));
+
+        // The entry matched is normalized, an IP for all its ports.
+        let entry = |addr| blacklist.match_of(&addr).map(|m| m.entry.to_string());
+        assert_eq!(entry(SocketAddr::new(mapped_lo4, 42)).as_deref(), Some("127.0.0.1"));
+        assert_eq!(entry(SocketAddr::new(mapped_ip, 42)).as_deref(), Some("192.0.2.4:42"));
+        assert_eq!(entry(SocketAddr::new(lo6, 8080)), None);
+    }
+
+    #[test]
+    fn test_named_lists() {
+        use super::{Blacklist, NamedBlacklist};
+        use std::net::SocketAddr;
+
+        let list = |name: &str, enabled: bool, entries: &[&str]| NamedBlacklist {
+            name: name.to_string(),
+            enabled,
+            entries: entries.iter().map(|entry| entry.to_string()).collect(),
+        };
+        let mut blacklist = Blacklist::from_lists(&[
+            list("abuse", true, &["192.0.2.4", "192.0.2.5:42"]),
+            list("compliance", true, &["192.0.2.5"]),
+            list("incident", false, &["192.0.2.6"]),
+        ]);
+        let addr = |addr: &str| addr.parse::<SocketAddr>().unwrap();
+        assert_eq!(blacklist.list_of(&addr("192.0.2.4:1")), Some("abuse"));
+        assert_eq!(blacklist.list_of(&addr("[::ffff:192.0.2.5]:42")), Some("abuse"));
+        assert_eq!(blacklist.list_of(&addr("192.0.2.5:1")), Some("compliance"));
+        assert_eq!(blacklist.list_of(&addr("192.0.2.6:1")), None);
+
+        assert!(blacklist.set_enabled("incident", true));
+        assert!(blacklist.set_enabled("abuse", false));
+        assert!(!blacklist.set_enabled("unknown", true));
+        assert_eq!(blacklist.list_of(&addr("192.0.2.6:1")), Some("incident"));
+        assert_eq!(blacklist.list_of(&addr("192.0.2.5:42")), Some("compliance"));
+        assert!(!blacklist.contains(&addr("192.0.2.4:1")));
+        assert_eq!(
+            blacklist.lists().collect::<Vec<_>>(),
+            [("abuse", false), ("compliance", true), ("incident", true)]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_entries() {
+        use super::{duplicate_blacklist_entries, DuplicateBlacklistEntry, NamedBlacklist};
+
+        let list = |name: &str, entries: &[&str]| NamedBlacklist {
+            name: name.to_string(),
+            enabled: true,
+            entries: entries.iter().map(|entry| entry.to_string()).collect(),
+        };
+        let duplicates = duplicate_blacklist_entries(&[
+            list("abuse", &["192.0.2.4", "192.0.2.4", "192.0.2.5:42", "foo"]),
+            list("compliance", &["::ffff:192.0.2.4", "192.0.2.5", "foo"]),
+            list("incident", &["192.0.2.5:42"]),
+        ]);
+        let duplicate =
+            |entry: &str, first_list: &str, second_list: &str| DuplicateBlacklistEntry {
+                entry: entry.to_string(),
+                first_list: first_list.to_string(),
+                second_list: second_list.to_string(),
+            };
+        assert_eq!(
+            duplicates,
+            [
+                duplicate("::ffff:192.0.2.4", "abuse", "compliance"),
+                duplicate("foo", "abuse", "compliance"),
+                duplicate("192.0.2.5:42", "abuse", "incident"),
+            ]
+        );
+    }
+
+    /// Randomized checks of the parser and the matcher over mixes of valid and invalid patterns
+    /// and probe addresses.  The IPs and ports are drawn from small pools so that the patterns and
+    /// probes overlap.  The cases are reproducible from the seed in a failure message.
+    mod randomized {
+        use super::super::Blacklist;
+        use crate::normalized_addr::NormalizedAddr;
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+        use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+        const SEEDS: u64 = 200;
+        const IPS: &[&str] =
+            &["127.0.0.1", "192.0.2.4", "192.0.2.5", "::1", "2001:db8::1", "::ffff:192.0.2.4"];
+        const PORTS: &[u16] = &[0, 1, 42, 24567, 65535];
+
+        fn random_ip(rng: &mut StdRng) -> IpAddr {
+            IPS.choose(rng).unwrap().parse().unwrap()
+        }
+
+        fn random_port(rng: &mut StdRng) -> u16 {
+            if rng.gen_bool(0.8) {
+                *PORTS.choose(rng).unwrap()
+            } else {
+                rng.gen()
+            }
+        }
+
+        /// A valid pattern most of the time, garbage or a slightly broken pattern otherwise.
+        fn random_pattern(rng: &mut StdRng) -> String {
+            let ip = random_ip(rng);
+            match rng.gen_range(0, 8) {
+                0..=2 => ip.to_string(),
+                3..=5 => SocketAddr::new(ip, random_port(rng)).to_string(),
+                6 => {
+                    let suffix = ["/24", ":", ":65536", ".1", "]", "*", " "].choose(rng).unwrap();
+                    format!("{}{}", ip, suffix)
+                }
+                _ => {
+                    let len = rng.gen_range(0, 20);
+                    (0..len)
+                        .map(|_| *b"0123456789abcdef:.[]/*- ".choose(rng).unwrap() as char)
+                        .collect()
+                }
+            }
+        }
+
+        fn random_probe(rng: &mut StdRng) -> SocketAddr {
+            SocketAddr::new(random_ip(rng), random_port(rng))
+        }
+
+        fn to_v6(ip: IpAddr) -> Ipv6Addr {
+            match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            }
+        }
+
+        /// The v6-mapped form of an IPv4 probe, `None` for IPv6 ones.
+        fn mapped(addr: &SocketAddr) -> Option<SocketAddr> {
+            match addr.ip() {
+                IpAddr::V4(ip) => {
+                    Some(SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()))
+                }
+                IpAddr::V6(_) => None,
+            }
+        }
+
+        /// Reference matcher: a list of the valid patterns, parsed with the standard library only,
+        /// scanned linearly.
+        #[derive(Default)]
+        struct NaiveBlacklist(Vec<(Ipv6Addr, Option<u16>)>);
+
+        impl NaiveBlacklist {
+            fn add(&mut self, pattern: &str) {
+                if let Ok(ip) = pattern.parse::<IpAddr>() {
+                    self.0.push((to_v6(ip), None));
+                } else if let Ok(addr) = pattern.parse::<SocketAddr>() {
+                    self.0.push((to_v6(addr.ip()), Some(addr.port())));
+                }
+            }
+
+            fn contains(&self, addr: &SocketAddr) -> bool {
+                let ip = to_v6(addr.ip());
+                self.0.iter().any(|(rule_ip, rule_port)| {
+                    *rule_ip == ip && rule_port.map_or(true, |port| port == addr.port())
+                })
+            }
+        }
+
+        #[test]
+        fn test_parse_display_round_trip() {
+            for seed in 0..SEEDS {
+                let mut rng = StdRng::seed_from_u64(seed);
+                for _ in 0..50 {
+                    let pattern = random_pattern(&mut rng);
+                    let parsed = match pattern.parse::<NormalizedAddr>() {
+                        Ok(parsed) => parsed,
+                        Err(_) => continue,
+                    };
+                    let displayed = parsed.to_string();
+                    assert_eq!(
+                        displayed.parse::<NormalizedAddr>().ok(),
+                        Some(parsed),
+                        "seed {}: {:?} displayed as {:?}",
+                        seed,
+                        pattern,
+                        displayed
+                    );
+                }
+            }
+        }
+
+        #[test]
+        fn test_matcher() {
+            for seed in 0..SEEDS {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let probes: Vec<SocketAddr> = (0..30).map(|_| random_probe(&mut rng)).collect();
+                let mut blacklist = Blacklist::default();
+                let mut naive = NaiveBlacklist::default();
+                let mut matched = vec![false; probes.len()];
+                let mut patterns = vec![];
+                for _ in 0..rng.gen_range(0, 10) {
+                    let pattern = random_pattern(&mut rng);
+                    let is_valid = pattern.parse::<NormalizedAddr>().is_ok();
+                    assert_eq!(blacklist.add(&pattern).is_ok(), is_valid, "seed {}", seed);
+                    naive.add(&pattern);
+                    patterns.push(pattern);
+
+                    // An exact rule for a probe matches it.
+                    let probe = probes.choose(&mut rng).unwrap();
+                    let mut with_exact = blacklist.clone();
+                    with_exact.add(&probe.to_string()).unwrap();
+                    assert!(with_exact.contains(probe), "seed {}: {:?}", seed, patterns);
+
+                    for (probe, matched) in probes.iter().zip(&mut matched) {
+                        let contains = blacklist.contains(probe);
+                        let context = format!("seed {}: {} in {:?}", seed, probe, patterns);
+                        // Same answer as the reference matcher.
+                        assert_eq!(contains, naive.contains(probe), "{}", context);
+                        // Adding rules never unblocks an address.
+                        assert!(contains || !*matched, "{}", context);
+                        *matched = contains;
+                        // IPv4 and its v6-mapped form are the same address.
+                        if let Some(mapped) = mapped(probe) {
+                            assert_eq!(contains, blacklist.contains(&mapped), "{}", context);
+                        }
+                    }
+                }
+                // Same as building the blacklist from the list, invalid patterns ignored.
+                let from_iter = Blacklist::from_iter(patterns.iter());
+                for probe in &probes {
+                    assert_eq!(
+                        from_iter.contains(probe),
+                        blacklist.contains(probe),
+                        "seed {}",
+                        seed
+                    );
+                }
+            }
+        }
     }
 }
@@ -1,8 +1,11 @@
+use crate::blacklist::{Blacklist, NamedBlacklist};
 use crate::network_protocol::PeerInfo;
 use crate::types::ROUTED_MESSAGE_TTL;
 use near_crypto::{KeyType, PublicKey, SecretKey};
 use near_primitives::types::AccountId;
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::time::Duration;
 
 /// Configuration for the peer-to-peer manager.
@@ -13,7 +16,12 @@ pub struct NetworkConfig {
     pub account_id: Option<AccountId>,
     pub addr: Option<SocketAddr>,
     pub boot_nodes: Vec<PeerInfo>,
+    /// Domains whose TXT records list peers to connect to.
+    pub dns_seeds: Vec<String>,
     pub whitelist_nodes: Vec<PeerInfo>,
+    /// Peers, with their addresses, which the node stays connected to
+    /// regardless of the connection limits and reconnects to quickly.
+    pub pinned_peers: Vec<PeerInfo>,
     pub handshake_timeout: Duration,
     pub reconnect_delay: Duration,
     pub bootstrap_peers_period: Duration,
@@ -37,6 +45,11 @@ pub struct NetworkConfig {
     pub ban_window: Duration,
     /// Remove expired peers.
     pub peer_expiration_duration: Duration,
+    /// Maximum number of peers kept in the peer store, `None` for no limit.
+    /// Beyond it the peers seen the longest ago are evicted, until a tenth of
+    /// it is free, except the connected, whitelisted and pinned ones and
+    /// those banned within `ban_window`.
+    pub max_peer_store_entries: Option<usize>,
     /// Maximum number of peer addresses we should ever send on PeersRequest.
     pub max_send_peers: u32,
     /// Duration for checking on stats from the peers.
@@ -55,9 +68,15 @@ pub struct NetworkConfig {
     pub highest_peer_horizon: u64,
     /// Period between pushing network info to client
     pub push_info_period: Duration,
-    /// Peers on blacklist by IP:Port.
-    /// Nodes will not accept or try to establish connection to such peers.
-    pub blacklist: Vec<String>,
+    /// Peers on blacklist by IP:Port, in lists by name.
+    /// Nodes will not accept or try to establish connection to such peers
+    /// while one of the lists they're on is enabled.
+    pub blacklist: Vec<NamedBlacklist>,
+    /// Blacklist entries which are only audited: the connections with the
+    /// peers they match are counted and listed, not refused.  The entries are
+    /// matched the way the `blacklist` ones are, so that moving one there
+    /// blocks the same peers.
+    pub blacklist_audit: Vec<String>,
     /// Flag to disable outbound connections. When this flag is active, nodes will not try to
     /// establish connection with other nodes, but will accept incoming connection if other requirements
     /// are satisfied.
@@ -65,6 +84,58 @@ pub struct NetworkConfig {
     pub outbound_disabled: bool,
     /// Not clear old data, set `true` for archive nodes.
     pub archive: bool,
+    /// Skew of the local clock against the peers above which a warning is
+    /// logged.
+    pub clock_skew_warn_threshold: Duration,
+    /// Limits of the encoded size of the messages received from peers, in
+    /// bytes, keyed by the kind of message, e.g. `Block`.  They override the
+    /// defaults of the kinds listed.
+    pub max_message_sizes: HashMap<String, usize>,
+    /// Idle time of a peer connection after which TCP keepalive probes are
+    /// sent, `None` to keep the OS settings.
+    pub tcp_keepalive_time: Option<Duration>,
+    /// Time between the TCP keepalive probes.
+    pub tcp_keepalive_interval: Duration,
+    /// Number of unanswered TCP keepalive probes after which the connection
+    /// is dropped by the OS.
+    pub tcp_keepalive_retries: u32,
+    /// Time without any message from a peer after which it's pinged.
+    pub liveness_ping_after: Duration,
+    /// Time a peer has to send something after being pinged.
+    pub liveness_timeout: Duration,
+    /// Number of pings in a row a peer can leave unanswered before it's
+    /// disconnected, 0 to never disconnect peers for it.
+    pub liveness_max_missed: u32,
+    /// Recording of the messages received from peers, for debugging.
+    pub message_recorder: Option<MessageRecorderConfig>,
+    /// Compression of the messages sent to the peers which accept it as well,
+    /// `None` to neither compress nor accept compressed messages.
+    pub message_compression: Option<CompressionConfig>,
+    /// Whether the node runs on a private network, so that the peers at
+    /// private addresses aren't dialed last.
+    pub private_network: bool,
+}
+
+/// Which messages sent to the peers are compressed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Size from which the messages are compressed, in bytes.
+    pub threshold: usize,
+    /// Types of the messages sent as they are, e.g. the ones whose payload is
+    /// compressed already, by the names the metrics label them with, e.g.
+    /// `VersionedStateResponse`.
+    pub skip_message_types: Vec<String>,
+}
+
+/// Where and how much of the messages received from peers are recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageRecorderConfig {
+    /// File the messages are appended to.
+    pub path: PathBuf,
+    /// Size after which the file is rotated, in bytes.
+    pub max_file_bytes: u64,
+    /// Number of files kept, the one written to included.
+    pub max_files: usize,
 }
 
 impl NetworkConfig {
@@ -78,7 +149,9 @@ impl NetworkConfig {
             account_id: Some(seed.parse().unwrap()),
             addr: Some(format!("0.0.0.0:{}", port).parse().unwrap()),
             boot_nodes: vec![],
+            dns_seeds: vec![],
             whitelist_nodes: vec![],
+            pinned_peers: vec![],
             handshake_timeout: Duration::from_secs(60),
             reconnect_delay: Duration::from_secs(60),
             bootstrap_peers_period: Duration::from_millis(100),
@@ -91,6 +164,7 @@ impl NetworkConfig {
             archival_peer_connections_lower_bound: 10,
             ban_window: Duration::from_secs(1),
             peer_expiration_duration: Duration::from_secs(60 * 60),
+            max_peer_store_entries: None,
             max_send_peers: 512,
             peer_stats_period: Duration::from_secs(5),
             ttl_account_id_router: Duration::from_secs(60 * 60),
@@ -99,46 +173,118 @@ impl NetworkConfig {
             highest_peer_horizon: 5,
             push_info_period: Duration::from_millis(100),
             blacklist: vec![],
+            blacklist_audit: vec![],
             outbound_disabled: false,
             archive: false,
+            clock_skew_warn_threshold: Duration::from_secs(1),
+            max_message_sizes: HashMap::new(),
+            tcp_keepalive_time: Some(Duration::from_secs(60)),
+            tcp_keepalive_interval: Duration::from_secs(10),
+            tcp_keepalive_retries: 3,
+            liveness_ping_after: Duration::from_secs(30),
+            liveness_timeout: Duration::from_secs(15),
+            liveness_max_missed: 3,
+            message_recorder: None,
+            message_compression: None,
+            private_network: true,
         }
     }
 
     pub fn verify(&self) -> Result<(), anyhow::Error> {
+        match self.validation_errors().into_iter().next() {
+            Some((_, error)) => anyhow::bail!(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns all problems with the config, each with the name of the
+    /// offending field.
+    pub fn validation_errors(&self) -> Vec<(&'static str, String)> {
+        let mut errors = vec![];
         if !(self.ideal_connections_lo <= self.ideal_connections_hi) {
-            anyhow::bail!(
-                "Invalid ideal_connections values. lo({}) > hi({}).",
-                self.ideal_connections_lo,
-                self.ideal_connections_hi
-            );
+            errors.push((
+                "ideal_connections_lo",
+                format!(
+                    "Invalid ideal_connections values. lo({}) > hi({}).",
+                    self.ideal_connections_lo, self.ideal_connections_hi
+                ),
+            ));
         }
 
         if !(self.ideal_connections_hi < self.max_num_peers) {
-            anyhow::bail!(
-                "max_num_peers({}) is below ideal_connections_hi({}) which may lead to connection saturation and declining new connections.",
-                self.max_num_peers, self.ideal_connections_hi
-            );
+            errors.push((
+                "max_num_peers",
+                format!(
+                    "max_num_peers({}) is below ideal_connections_hi({}) which may lead to connection saturation and declining new connections.",
+                    self.max_num_peers, self.ideal_connections_hi
+                ),
+            ));
+        }
+
+        // The disabled lists count as well, they can be enabled at any time.
+        let mut blacklist = Blacklist::from_lists(&self.blacklist);
+        for list in &self.blacklist {
+            blacklist.set_enabled(&list.name, true);
+        }
+        for peer in &self.pinned_peers {
+            if let Some(list) = peer.addr.and_then(|addr| blacklist.list_of(&addr)) {
+                errors.push((
+                    "pinned_peers",
+                    format!("Pinned peer {} is on the {} blacklist.", peer, list),
+                ));
+            }
+        }
+
+        if self.max_peer_store_entries == Some(0) {
+            errors.push((
+                "max_peer_store_entries",
+                "max_peer_store_entries must be positive.".to_string(),
+            ));
+        }
+
+        if let Some(recorder) = &self.message_recorder {
+            if recorder.max_file_bytes == 0 || recorder.max_files == 0 {
+                errors.push((
+                    "message_recorder",
+                    "max_file_bytes and max_files of message_recorder must be positive."
+                        .to_string(),
+                ));
+            }
+        }
+
+        if let Some(compression) = &self.message_compression {
+            if compression.threshold == 0 {
+                errors.push((
+                    "message_compression",
+                    "threshold of message_compression must be positive.".to_string(),
+                ));
+            }
         }
 
         if self.outbound_disabled {
-            anyhow::bail!("Outbound connections are disabled.");
+            errors.push(("outbound_disabled", "Outbound connections are disabled.".to_string()));
         }
 
         if !(self.safe_set_size > self.minimum_outbound_peers) {
-            anyhow::bail!(
-                "safe_set_size({}) must be larger than minimum_outbound_peers({}).",
-                self.safe_set_size,
-                self.minimum_outbound_peers
-            );
+            errors.push((
+                "safe_set_size",
+                format!(
+                    "safe_set_size({}) must be larger than minimum_outbound_peers({}).",
+                    self.safe_set_size, self.minimum_outbound_peers
+                ),
+            ));
         }
 
         if UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE * 2 > self.peer_recent_time_window {
-            anyhow::bail!(
-                "Very short peer_recent_time_window({}). it should be at least twice update_interval_last_time_received_message({}).",
-                self.peer_recent_time_window.as_secs(), UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE.as_secs()
-            );
+            errors.push((
+                "peer_recent_time_window",
+                format!(
+                    "Very short peer_recent_time_window({}). it should be at least twice update_interval_last_time_received_message({}).",
+                    self.peer_recent_time_window.as_secs(), UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE.as_secs()
+                ),
+            ));
         }
-        Ok(())
+        errors
     }
 }
 
@@ -149,7 +295,12 @@ pub const UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE: Duration = Duration::from_
 
 #[cfg(test)]
 mod test {
-    use crate::types::{NetworkConfig, UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE};
+    use crate::types::{
+        MessageRecorderConfig, NamedBlacklist, NetworkConfig, PeerInfo,
+        UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE,
+    };
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::network::PeerId;
 
     #[test]
     fn test_network_config() {
@@ -175,5 +326,40 @@ mod test {
         nc.peer_recent_time_window = UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE;
         let res = nc.verify();
         assert!(res.is_err(), "{:?}", res);
+
+        let mut nc = NetworkConfig::from_seed("123", 213);
+        nc.safe_set_size = nc.minimum_outbound_peers;
+        nc.peer_recent_time_window = UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE;
+        let fields: Vec<_> = nc.validation_errors().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["safe_set_size", "peer_recent_time_window"]);
+
+        let mut nc = NetworkConfig::from_seed("123", 213);
+        let secret_key = SecretKey::from_seed(KeyType::ED25519, "pinned");
+        nc.pinned_peers = vec![PeerInfo {
+            id: PeerId::new(secret_key.public_key()),
+            addr: Some("10.0.0.1:24567".parse().unwrap()),
+            account_id: None,
+        }];
+        assert!(nc.verify().is_ok());
+        nc.blacklist = vec![NamedBlacklist::default_list(vec!["10.0.0.1".to_string()])];
+        let fields: Vec<_> = nc.validation_errors().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["pinned_peers"]);
+        nc.blacklist[0].enabled = false;
+        let fields: Vec<_> = nc.validation_errors().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["pinned_peers"]);
+
+        let mut nc = NetworkConfig::from_seed("123", 213);
+        nc.message_recorder = Some(MessageRecorderConfig {
+            path: "messages.log".into(),
+            max_file_bytes: 1 << 20,
+            max_files: 0,
+        });
+        let fields: Vec<_> = nc.validation_errors().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["message_recorder"]);
+
+        let mut nc = NetworkConfig::from_seed("123", 213);
+        nc.max_peer_store_entries = Some(0);
+        let fields: Vec<_> = nc.validation_errors().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["max_peer_store_entries"]);
     }
 }
@@ -1,4 +1,5 @@
 mod blacklist;
 pub(crate) mod config;
 mod network_protocol;
+mod normalized_addr;
 pub mod types;
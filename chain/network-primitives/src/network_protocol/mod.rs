@@ -165,7 +165,15 @@ pub struct Pong {
 
 // TODO(#1313): Use Box
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
-#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, strum::IntoStaticStr)]
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    PartialEq,
+    Eq,
+    Clone,
+    strum::IntoStaticStr,
+    strum::EnumVariantNames,
+)]
 #[allow(clippy::large_enum_variant)]
 pub enum RoutedMessageBody {
     BlockApproval(Approval),
@@ -447,6 +455,15 @@ impl StateResponseInfo {
         }
     }
 
+    /// Id of the state part carried by the response, if any.
+    pub fn part_id(&self) -> Option<u64> {
+        let part = match self {
+            Self::V1(info) => &info.state_response.part,
+            Self::V2(info) => info.state_response.part(),
+        };
+        part.as_ref().map(|(part_id, _)| *part_id)
+    }
+
     pub fn take_state_response(self) -> ShardStateSyncResponse {
         match self {
             Self::V1(info) => ShardStateSyncResponse::V1(info.state_response),
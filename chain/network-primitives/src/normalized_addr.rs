@@ -0,0 +1,296 @@
+//! Canonical form of the addresses of peers.
+//!
+//! An IPv4 address and the same address mapped to IPv6, `::ffff:a.b.c.d`, are
+//! the same host: a dual stack socket reports IPv4 peers in the mapped form.
+//! The blacklist, the whitelist, the peer store and the debug pages compare
+//! and show addresses as [`NormalizedAddr`], which keeps both in the IPv4
+//! form, so that they can't disagree on which addresses are the same.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::fmt;
+use std::io;
+use std::net::{AddrParseError, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+/// An IP address with an optional port, in the canonical form: IPv4 addresses
+/// mapped to IPv6 are IPv4 addresses and the flow info and scope id of IPv6
+/// socket addresses are dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NormalizedAddr {
+    ip: IpAddr,
+    port: Option<u16>,
+}
+
+/// The IPv4 address an IPv4-mapped IPv6 address stands for, other addresses
+/// as they are.  IPv4-compatible addresses, `::a.b.c.d`, are deprecated and
+/// stay IPv6, `::1` is one of them.
+fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.octets() {
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d] => {
+                IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+            }
+            _ => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// The address with all the bits past the prefix cleared.
+fn mask(ip: IpAddr, prefix_len: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix_len.min(32))).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(bits & mask))
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix_len.min(128))).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(bits & mask))
+        }
+    }
+}
+
+impl NormalizedAddr {
+    pub fn from_ip(ip: IpAddr) -> Self {
+        Self { ip: canonical_ip(ip), port: None }
+    }
+
+    pub fn from_socket_addr(addr: SocketAddr) -> Self {
+        Self { ip: canonical_ip(addr.ip()), port: Some(addr.port()) }
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// The address without its port, which stands for all the ports of the
+    /// IP.
+    pub fn without_port(&self) -> Self {
+        Self { ip: self.ip, port: None }
+    }
+
+    /// The socket address to dial, `None` without a port.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.port.map(|port| SocketAddr::new(self.ip, port))
+    }
+
+    /// The subnet of the address, of the first `ipv4_prefix_len` bits of an
+    /// IPv4 address or the first `ipv6_prefix_len` bits of an IPv6 one, e.g.
+    /// with 24 and 64 for the usual allocations of a single host or network.
+    /// The prefix lengths are capped at the length of the addresses.
+    pub fn subnet(&self, ipv4_prefix_len: u8, ipv6_prefix_len: u8) -> Subnet {
+        let prefix_len = match self.ip {
+            IpAddr::V4(_) => ipv4_prefix_len.min(32),
+            IpAddr::V6(_) => ipv6_prefix_len.min(128),
+        };
+        Subnet { network: mask(self.ip, prefix_len), prefix_len }
+    }
+}
+
+impl From<SocketAddr> for NormalizedAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::from_socket_addr(addr)
+    }
+}
+
+impl From<IpAddr> for NormalizedAddr {
+    fn from(ip: IpAddr) -> Self {
+        Self::from_ip(ip)
+    }
+}
+
+/// `192.0.2.4`, `192.0.2.4:24567`, `2001:db8::1` or `[2001:db8::1]:24567`.
+impl fmt::Display for NormalizedAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.socket_addr() {
+            Some(addr) => addr.fmt(f),
+            None => self.ip.fmt(f),
+        }
+    }
+}
+
+/// Parses an IP or a socket address, of either family.
+impl FromStr for NormalizedAddr {
+    type Err = AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Self::from_ip(ip));
+        }
+        Ok(Self::from_socket_addr(s.parse()?))
+    }
+}
+
+/// The IP, as a byte for the family followed by its octets, and the port.
+impl BorshSerialize for NormalizedAddr {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self.ip {
+            IpAddr::V4(ip) => {
+                0u8.serialize(writer)?;
+                ip.octets().serialize(writer)?;
+            }
+            IpAddr::V6(ip) => {
+                1u8.serialize(writer)?;
+                ip.octets().serialize(writer)?;
+            }
+        }
+        self.port.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for NormalizedAddr {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        let ip = match u8::deserialize(buf)? {
+            0 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::deserialize(buf)?)),
+            1 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::deserialize(buf)?)),
+            family => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid address family {}", family),
+                ))
+            }
+        };
+        // Encoded by someone else maybe, the mapped addresses are normalized
+        // again.
+        Ok(Self { ip: canonical_ip(ip), port: Option::<u16>::deserialize(buf)? })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for NormalizedAddr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for NormalizedAddr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The addresses sharing the first `prefix_len` bits of `network`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Subnet {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Subnet {
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn contains(&self, addr: &NormalizedAddr) -> bool {
+        addr.ip.is_ipv4() == self.network.is_ipv4()
+            && mask(addr.ip, self.prefix_len) == self.network
+    }
+}
+
+/// `192.0.2.0/24` or `2001:db8::/64`.
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> String {
+        value.parse::<NormalizedAddr>().map_or("err".to_string(), |addr| addr.to_string())
+    }
+
+    #[test]
+    fn test_parse_display() {
+        assert_eq!("err", parse("foo"));
+        assert_eq!("err", parse("192.0.2.*"));
+        assert_eq!("err", parse("192.0.2.0/24"));
+        assert_eq!("err", parse("192.0.2.4.5"));
+        assert_eq!("err", parse("192.0.2.4:424242"));
+        assert_eq!("err", parse("::ffff:192.0.2.4:42"));
+
+        assert_eq!("192.0.2.4", parse("192.0.2.4"));
+        assert_eq!("192.0.2.4:0", parse("192.0.2.4:0"));
+        assert_eq!("192.0.2.4:42", parse("192.0.2.4:42"));
+
+        assert_eq!("::1", parse("::1"));
+        assert_eq!("[::1]:42", parse("[::1]:42"));
+        assert_eq!("2001:db8::1", parse("2001:db8::1"));
+        assert_eq!("[2001:db8::1]:42", parse("[2001:db8::1]:42"));
+
+        // Mapped addresses are IPv4 ones, but for the deprecated compatible
+        // addresses.
+        assert_eq!("127.0.0.1", parse("::ffff:127.0.0.1"));
+        assert_eq!("127.0.0.1:42", parse("[::ffff:127.0.0.1]:42"));
+        assert_eq!("192.0.2.4", parse("::ffff:c000:204"));
+        for s in ["::192.0.2.4", "::ffff:0:c000:204", "64:ff9b::c000:204"] {
+            assert!(s.parse::<NormalizedAddr>().unwrap().ip().is_ipv6(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_equivalence() {
+        let v4: SocketAddr = "192.0.2.4:42".parse().unwrap();
+        let mapped: SocketAddr = "[::ffff:192.0.2.4]:42".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:42".parse().unwrap();
+        assert_eq!(NormalizedAddr::from(v4), NormalizedAddr::from(mapped));
+        assert_eq!(NormalizedAddr::from(v4.ip()), NormalizedAddr::from(mapped.ip()));
+        assert_eq!(NormalizedAddr::from(mapped).socket_addr(), Some(v4));
+        assert_eq!(NormalizedAddr::from(mapped).without_port(), NormalizedAddr::from(v4.ip()));
+        assert_ne!(NormalizedAddr::from(v4), NormalizedAddr::from(v4.ip()));
+        assert_ne!(NormalizedAddr::from(v6), NormalizedAddr::from(v4));
+        assert_eq!(NormalizedAddr::from(v6).socket_addr(), Some(v6));
+        assert_eq!(NormalizedAddr::from(v6.ip()).socket_addr(), None);
+    }
+
+    #[test]
+    fn test_subnet() {
+        let addr = |s: &str| s.parse::<NormalizedAddr>().unwrap();
+        let subnet = addr("[::ffff:192.0.2.4]:42").subnet(24, 64);
+        assert_eq!(subnet.to_string(), "192.0.2.0/24");
+        assert_eq!(subnet, addr("192.0.2.200").subnet(24, 64));
+        assert!(subnet.contains(&addr("192.0.2.255:1")));
+        assert!(subnet.contains(&addr("::ffff:192.0.2.1")));
+        assert!(!subnet.contains(&addr("192.0.3.1")));
+        // The IPv6 addresses starting with the same bits aren't in it.
+        assert!(!subnet.contains(&addr("c000:200::")));
+
+        let subnet = addr("[2001:db8:1:2:3::1]:42").subnet(24, 64);
+        assert_eq!(subnet.to_string(), "2001:db8:1:2::/64");
+        assert!(subnet.contains(&addr("2001:db8:1:2:ffff::")));
+        assert!(!subnet.contains(&addr("2001:db8:1:3::")));
+
+        assert_eq!(addr("192.0.2.4").subnet(0, 0).to_string(), "0.0.0.0/0");
+        assert_eq!(addr("192.0.2.4").subnet(33, 0).to_string(), "192.0.2.4/32");
+        assert_eq!(addr("::1").subnet(0, 200).to_string(), "::1/128");
+    }
+
+    #[test]
+    fn test_borsh() {
+        for s in ["192.0.2.4", "192.0.2.4:42", "::1", "[2001:db8::1]:42"] {
+            let addr: NormalizedAddr = s.parse().unwrap();
+            let bytes = addr.try_to_vec().unwrap();
+            assert_eq!(NormalizedAddr::try_from_slice(&bytes).unwrap(), addr);
+        }
+        // A mapped address encoded as IPv6 decodes as IPv4.
+        let mut bytes = vec![1];
+        bytes.extend_from_slice(&"::ffff:192.0.2.4".parse::<Ipv6Addr>().unwrap().octets());
+        bytes.extend_from_slice(&Some(42u16).try_to_vec().unwrap());
+        assert_eq!(NormalizedAddr::try_from_slice(&bytes).unwrap().to_string(), "192.0.2.4:42");
+        assert!(NormalizedAddr::try_from_slice(&[2, 0, 0, 0, 0, 0]).is_err());
+    }
+}
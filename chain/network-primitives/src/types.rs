@@ -34,8 +34,12 @@ pub use crate::network_protocol::{
     RoutedMessageBody, StateResponseInfo, StateResponseInfoV1, StateResponseInfoV2,
 };
 
-pub use crate::blacklist::Blacklist;
-pub use crate::config::NetworkConfig;
+pub use crate::blacklist::{
+    duplicate_blacklist_entries, Blacklist, BlacklistMatch, DuplicateBlacklistEntry,
+    NamedBlacklist, DEFAULT_BLACKLIST,
+};
+pub use crate::config::{CompressionConfig, MessageRecorderConfig, NetworkConfig};
+pub use crate::normalized_addr::{NormalizedAddr, Subnet};
 
 pub use crate::network_protocol::edge::{Edge, EdgeState, PartialEdgeInfo, SimpleEdge};
 
@@ -208,7 +212,17 @@ pub struct OutboundTcpConnect {
 
 /// Ban reason.
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq, Copy)]
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Copy,
+    strum::AsRefStr,
+    strum::EnumString,
+)]
 pub enum ReasonForBan {
     None = 0,
     BadBlock = 1,
@@ -237,12 +251,23 @@ pub struct Ban {
     pub ban_reason: ReasonForBan,
 }
 
+/// Enables and disables blacklists of the config by name, keeping their
+/// entries, e.g. once the config is reloaded.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct SetBlacklistsEnabled {
+    pub lists: Vec<(String, bool)>,
+}
+
 /// Messages from PeerManager to Peer
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
 pub enum PeerManagerRequest {
     BanPeer(ReasonForBan),
     UnregisterPeer,
+    /// The shards this node tracks changed, to be advertised to the peer.
+    UpdateTrackedShards(Vec<ShardId>),
 }
 
 #[derive(Debug, Clone)]
@@ -272,10 +297,36 @@ pub enum NetworkAdversarialMessage {
     AdvSetSyncInfo(u64),
 }
 
+/// Faults injected into the messages received from peers, to test the node
+/// under bad network conditions.
+#[cfg(feature = "test_features")]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NetworkFault {
+    /// Peer whose messages are affected, all peers if `None`.
+    #[serde(default)]
+    pub peer_id: Option<PeerId>,
+    /// Type of the affected messages, e.g. `Block` or, for routed messages,
+    /// the type of the body, e.g. `BlockApproval`.  All messages if `None`.
+    #[serde(default)]
+    pub message_type: Option<String>,
+    /// Probability of dropping a message.
+    #[serde(default)]
+    pub drop_probability: f64,
+    /// Every message is delayed by a uniformly distributed latency between
+    /// the minimum and the maximum.
+    #[serde(default)]
+    pub min_delay_ms: u64,
+    #[serde(default)]
+    pub max_delay_ms: u64,
+    /// Bandwidth of the connection in bytes per second, unlimited if `None`.
+    #[serde(default)]
+    pub bandwidth: Option<u64>,
+}
+
 #[cfg(feature = "sandbox")]
 #[derive(Debug)]
 pub enum NetworkSandboxMessage {
-    SandboxPatchState(Vec<near_primitives::state_record::StateRecord>),
+    SandboxPatchState(Vec<near_primitives::state_record::StatePatchRecord>),
     SandboxPatchStateStatus,
     SandboxFastForward(near_primitives::types::BlockHeightDelta),
     SandboxFastForwardStatus,
@@ -284,7 +335,8 @@ pub enum NetworkSandboxMessage {
 #[cfg(feature = "sandbox")]
 #[derive(Eq, PartialEq, Debug)]
 pub enum SandboxResponse {
-    SandboxPatchStateFinished(bool),
+    /// State root after the patch was applied, `None` while it's pending.
+    SandboxPatchStateFinished(Option<near_primitives::types::StateRoot>),
     SandboxFastForwardFinished(bool),
     SandboxFastForwardFailed(String),
 }
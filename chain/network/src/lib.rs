@@ -1,5 +1,10 @@
+pub use crate::peer_manager::ban_file::{BanFile, BannedPeer, BAN_FILE_VERSION};
+pub use crate::peer_manager::blacklist_audit::{stored_peers, AuditedPeer, BlacklistAudit};
+pub use crate::peer_manager::handshake_refusals::{HandshakeRefusal, HandshakeRefusalReason};
 pub use crate::peer_manager::peer_manager_actor::PeerManagerActor;
-pub use crate::peer_manager::peer_store::iter_peers_from_store;
+pub use crate::peer_manager::peer_store::{iter_peers_from_store, PeerStoreLimits};
+pub use crate::peer_manager::peer_store_purge::{PeerStorePurge, PurgeSummary};
+pub use crate::peer_manager::pinned_peers::PinnedPeerStatus;
 /// For benchmarks only
 pub use crate::routing::routing_table_actor::RoutingTableActor;
 #[cfg(feature = "test_features")]
@@ -13,7 +13,7 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::syncing::{EpochSyncFinalizationResponse, EpochSyncResponse};
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::EpochId;
+use near_primitives::types::{EpochId, ShardId};
 use std::fmt;
 use std::fmt::Formatter;
 
@@ -160,6 +160,8 @@ pub enum PeerMessage {
     EpochSyncFinalizationResponse(Box<EpochSyncFinalizationResponse>),
 
     RoutingTableSyncV2(RoutingSyncV2),
+
+    TrackedShards(Vec<ShardId>),
 }
 #[cfg(target_arch = "x86_64")] // Non-x86_64 doesn't match this requirement yet but it's not bad as it's not production-ready
 const _: () = assert!(std::mem::size_of::<PeerMessage>() <= 1144, "PeerMessage > 1144 bytes");
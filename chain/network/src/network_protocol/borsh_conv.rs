@@ -13,6 +13,8 @@ impl From<&net::Handshake> for mem::Handshake {
             sender_listen_port: x.sender_listen_port,
             sender_chain_info: x.sender_chain_info.clone(),
             partial_edge_info: x.partial_edge_info.clone(),
+            sender_timestamp: None,
+            accepts_compression: false,
         }
     }
 }
@@ -117,6 +119,7 @@ impl TryFrom<&net::PeerMessage> for mem::PeerMessage {
                 mem::PeerMessage::EpochSyncFinalizationResponse(esfr)
             }
             net::PeerMessage::RoutingTableSyncV2(rs) => mem::PeerMessage::RoutingTableSyncV2(rs),
+            net::PeerMessage::TrackedShards(ids) => mem::PeerMessage::TrackedShards(ids),
         })
     }
 }
@@ -155,6 +158,7 @@ impl From<&mem::PeerMessage> for net::PeerMessage {
                 net::PeerMessage::EpochSyncFinalizationResponse(esfr)
             }
             mem::PeerMessage::RoutingTableSyncV2(rs) => net::PeerMessage::RoutingTableSyncV2(rs),
+            mem::PeerMessage::TrackedShards(ids) => net::PeerMessage::TrackedShards(ids),
         }
     }
 }
@@ -9,7 +9,9 @@ mod _proto {
 
 pub use _proto::network as proto;
 
+use crate::peer::codec::NETWORK_MESSAGE_MAX_SIZE_BYTES;
 use ::borsh::{BorshDeserialize as _, BorshSerialize as _};
+use bytesize::MIB;
 use near_network_primitives::types::{
     Edge, PartialEdgeInfo, PeerChainInfoV2, PeerInfo, RoutedMessage, RoutedMessageBody,
 };
@@ -19,10 +21,12 @@ use near_primitives::hash::CryptoHash;
 use near_primitives::network::PeerId;
 use near_primitives::syncing::{EpochSyncFinalizationResponse, EpochSyncResponse};
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::{EpochId, ProtocolVersion};
+use near_primitives::types::{EpochId, ProtocolVersion, ShardId};
 use near_primitives::version::PEER_MIN_ALLOWED_PROTOCOL_VERSION;
 use protobuf::Message as _;
+use std::collections::HashMap;
 use std::fmt;
+use strum::IntoEnumIterator as _;
 use thiserror::Error;
 
 pub use self::borsh::{
@@ -46,6 +50,13 @@ pub struct Handshake {
     pub(crate) sender_chain_info: PeerChainInfoV2,
     /// Represents new `edge`. Contains only `none` and `Signature` from the sender.
     pub(crate) partial_edge_info: PartialEdgeInfo,
+    /// Time at which the handshake was sent, as unix timestamp in nanoseconds.
+    /// Not sent in the borsh encoding.
+    pub(crate) sender_timestamp: Option<u64>,
+    /// Whether the sender reads compressed messages, and compresses the
+    /// messages it sends to peers which read them.  Not sent in the borsh
+    /// encoding.
+    pub(crate) accepts_compression: bool,
 }
 
 impl Handshake {
@@ -65,6 +76,8 @@ impl Handshake {
             sender_listen_port: listen_port,
             sender_chain_info: chain_info,
             partial_edge_info,
+            sender_timestamp: None,
+            accepts_compression: false,
         }
     }
 }
@@ -109,6 +122,11 @@ pub enum PeerMessage {
     EpochSyncFinalizationResponse(Box<EpochSyncFinalizationResponse>),
 
     RoutingTableSyncV2(RoutingSyncV2),
+
+    /// Shards the sender tracks now, sent when they change after the
+    /// handshake, which carries the shards tracked until then.  Only sent to
+    /// peers talking a version with `ProtocolFeature::TrackedShardsMessage`.
+    TrackedShards(Vec<ShardId>),
 }
 
 impl fmt::Display for PeerMessage {
@@ -117,6 +135,157 @@ impl fmt::Display for PeerMessage {
     }
 }
 
+/// Kind of a [`PeerMessage`], which can be read from the first bytes of the
+/// message in either encoding.  Frames are checked against the size limit of
+/// their kind before they're parsed, see [`MessageSizeLimits`].
+#[derive(
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Debug,
+    Hash,
+    strum::IntoStaticStr,
+    strum::EnumString,
+    strum::EnumIter,
+)]
+pub enum PeerMessageKind {
+    Handshake,
+    HandshakeFailure,
+    LastEdge,
+    SyncRoutingTable,
+    RequestUpdateNonce,
+    ResponseUpdateNonce,
+    PeersRequest,
+    PeersResponse,
+    BlockHeadersRequest,
+    BlockHeaders,
+    BlockRequest,
+    Block,
+    Transaction,
+    Routed,
+    Disconnect,
+    Challenge,
+    EpochSyncRequest,
+    EpochSyncResponse,
+    EpochSyncFinalizationRequest,
+    EpochSyncFinalizationResponse,
+    RoutingTableSyncV2,
+    TrackedShards,
+}
+
+/// Limit of the messages which carry a few hashes, edges or peers.
+const SMALL_MESSAGE_MAX_SIZE_BYTES: usize = MIB as usize;
+
+impl PeerMessageKind {
+    /// Default limit of the encoded size of the messages of the kind.
+    ///
+    /// Neither borsh nor protobuf allocate much more than the size of their
+    /// input while parsing it, and messages aren't compressed, so the limit
+    /// bounds the size of the parsed message as well.  The limits are well
+    /// above the largest messages valid in the protocol.  Messages which
+    /// carry state parts, chunks or whole routing tables are only limited by
+    /// the length of the frame.
+    pub const fn default_max_size(self) -> usize {
+        match self {
+            PeerMessageKind::Handshake
+            | PeerMessageKind::HandshakeFailure
+            | PeerMessageKind::LastEdge
+            | PeerMessageKind::RequestUpdateNonce
+            | PeerMessageKind::ResponseUpdateNonce
+            | PeerMessageKind::PeersRequest
+            | PeerMessageKind::BlockHeadersRequest
+            | PeerMessageKind::BlockRequest
+            | PeerMessageKind::Disconnect
+            | PeerMessageKind::EpochSyncRequest
+            | PeerMessageKind::EpochSyncFinalizationRequest
+            | PeerMessageKind::TrackedShards => SMALL_MESSAGE_MAX_SIZE_BYTES,
+            PeerMessageKind::PeersResponse => 4 * MIB as usize,
+            PeerMessageKind::Transaction => 8 * MIB as usize,
+            PeerMessageKind::BlockHeaders | PeerMessageKind::Block => 64 * MIB as usize,
+            PeerMessageKind::SyncRoutingTable
+            | PeerMessageKind::Routed
+            | PeerMessageKind::Challenge
+            | PeerMessageKind::EpochSyncResponse
+            | PeerMessageKind::EpochSyncFinalizationResponse
+            | PeerMessageKind::RoutingTableSyncV2 => NETWORK_MESSAGE_MAX_SIZE_BYTES,
+        }
+    }
+
+    /// Kind of the encoded message, read from its first two bytes.
+    ///
+    /// Borsh encodes the variant as a byte from 0 to 22, with the unused 16.
+    /// Protobuf starts with the key of the `oneof` field, numbered from 4 in
+    /// the same order, as a varint of the field number shifted by 3 bits with
+    /// the wire type in the low bits.  The keys are 34 or above so the two
+    /// don't overlap, see network.proto.
+    pub(crate) fn from_encoded(data: &[u8]) -> Option<PeerMessageKind> {
+        let index = match *data.first()? {
+            tag @ 0..=15 => tag as usize,
+            16 => return None,
+            tag @ 17..=22 => tag as usize - 1,
+            byte => {
+                let key = if byte & 0x80 == 0 {
+                    byte as usize
+                } else {
+                    (byte & 0x7f) as usize | (*data.get(1)? as usize) << 7
+                };
+                // Messages are length delimited.
+                if key & 7 != 2 {
+                    return None;
+                }
+                (key >> 3).checked_sub(4)?
+            }
+        };
+        PeerMessageKind::iter().nth(index)
+    }
+}
+
+/// Limits of the encoded size of each kind of message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageSizeLimits {
+    limits: HashMap<PeerMessageKind, usize>,
+}
+
+impl Default for MessageSizeLimits {
+    fn default() -> Self {
+        Self {
+            limits: PeerMessageKind::iter().map(|kind| (kind, kind.default_max_size())).collect(),
+        }
+    }
+}
+
+impl MessageSizeLimits {
+    /// Default limits with the overrides of `NetworkConfig::max_message_sizes`,
+    /// keyed by the names of the kinds.
+    pub fn new(overrides: &HashMap<String, usize>) -> Result<Self, String> {
+        let mut limits = Self::default();
+        for (name, &limit) in overrides {
+            let kind = name
+                .parse::<PeerMessageKind>()
+                .map_err(|_| format!("{} isn't a kind of network message", name))?;
+            if limit > NETWORK_MESSAGE_MAX_SIZE_BYTES {
+                return Err(format!(
+                    "limit of {} is above the maximum size of a message, {} bytes",
+                    name, NETWORK_MESSAGE_MAX_SIZE_BYTES
+                ));
+            }
+            limits.limits.insert(kind, limit);
+        }
+        Ok(limits)
+    }
+
+    /// Limit of the messages of the kind.  Messages we send always start with
+    /// their kind, frames whose kind can't be read are held to the limit of
+    /// the small messages so that they can't be used to get around the limits.
+    pub fn get(&self, kind: Option<PeerMessageKind>) -> usize {
+        match kind {
+            Some(kind) => self.limits[&kind],
+            None => SMALL_MESSAGE_MAX_SIZE_BYTES,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, strum::IntoStaticStr)]
 pub enum Encoding {
     Borsh,
@@ -159,6 +328,37 @@ impl PeerMessage {
         })
     }
 
+    pub(crate) fn kind(&self) -> PeerMessageKind {
+        match self {
+            PeerMessage::Handshake(_) => PeerMessageKind::Handshake,
+            PeerMessage::HandshakeFailure(_, _) => PeerMessageKind::HandshakeFailure,
+            PeerMessage::LastEdge(_) => PeerMessageKind::LastEdge,
+            PeerMessage::SyncRoutingTable(_) => PeerMessageKind::SyncRoutingTable,
+            PeerMessage::RequestUpdateNonce(_) => PeerMessageKind::RequestUpdateNonce,
+            PeerMessage::ResponseUpdateNonce(_) => PeerMessageKind::ResponseUpdateNonce,
+            PeerMessage::PeersRequest => PeerMessageKind::PeersRequest,
+            PeerMessage::PeersResponse(_) => PeerMessageKind::PeersResponse,
+            PeerMessage::BlockHeadersRequest(_) => PeerMessageKind::BlockHeadersRequest,
+            PeerMessage::BlockHeaders(_) => PeerMessageKind::BlockHeaders,
+            PeerMessage::BlockRequest(_) => PeerMessageKind::BlockRequest,
+            PeerMessage::Block(_) => PeerMessageKind::Block,
+            PeerMessage::Transaction(_) => PeerMessageKind::Transaction,
+            PeerMessage::Routed(_) => PeerMessageKind::Routed,
+            PeerMessage::Disconnect => PeerMessageKind::Disconnect,
+            PeerMessage::Challenge(_) => PeerMessageKind::Challenge,
+            PeerMessage::EpochSyncRequest(_) => PeerMessageKind::EpochSyncRequest,
+            PeerMessage::EpochSyncResponse(_) => PeerMessageKind::EpochSyncResponse,
+            PeerMessage::EpochSyncFinalizationRequest(_) => {
+                PeerMessageKind::EpochSyncFinalizationRequest
+            }
+            PeerMessage::EpochSyncFinalizationResponse(_) => {
+                PeerMessageKind::EpochSyncFinalizationResponse
+            }
+            PeerMessage::RoutingTableSyncV2(_) => PeerMessageKind::RoutingTableSyncV2,
+            PeerMessage::TrackedShards(_) => PeerMessageKind::TrackedShards,
+        }
+    }
+
     pub(crate) fn msg_variant(&self) -> &'static str {
         match self {
             PeerMessage::Routed(routed_msg) => routed_msg.body_variant(),
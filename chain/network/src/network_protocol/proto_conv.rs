@@ -186,6 +186,8 @@ impl From<&Handshake> for proto::Handshake {
             sender_listen_port: x.sender_listen_port.unwrap_or(0).into(),
             sender_chain_info: MF::some((&x.sender_chain_info).into()),
             partial_edge_info: MF::some((&x.partial_edge_info).into()),
+            sender_timestamp: x.sender_timestamp.unwrap_or(0),
+            accepts_compression: x.accepts_compression,
             ..Self::default()
         }
     }
@@ -228,6 +230,8 @@ impl TryFrom<&proto::Handshake> for Handshake {
                 .map_err(Self::Error::SenderChainInfo)?,
             partial_edge_info: try_from_required(&p.partial_edge_info)
                 .map_err(Self::Error::PartialEdgeInfo)?,
+            sender_timestamp: if p.sender_timestamp == 0 { None } else { Some(p.sender_timestamp) },
+            accepts_compression: p.accepts_compression,
         })
     }
 }
@@ -490,6 +494,12 @@ impl From<&PeerMessage> for proto::PeerMessage {
                         ..Default::default()
                     })
                 }
+                PeerMessage::TrackedShards(shard_ids) => {
+                    ProtoMT::TrackedShards(proto::TrackedShards {
+                        shard_ids: shard_ids.clone(),
+                        ..Default::default()
+                    })
+                }
             }),
             ..Default::default()
         }
@@ -620,6 +630,7 @@ impl TryFrom<&proto::PeerMessage> for PeerMessage {
                 RoutingSyncV2::try_from_slice(&rts.borsh)
                     .map_err(Self::Error::RoutingTableSyncV2)?,
             ),
+            ProtoMT::TrackedShards(ts) => PeerMessage::TrackedShards(ts.shard_ids.clone()),
         })
     }
 }
@@ -5,23 +5,192 @@
 ///
 /// NOTES:
 ///     - Code has an extra logic to ban peers if they sent messages that are too large.
+///     - Messages above the size limit of their kind, see `MessageSizeLimits`,
+///       are skipped without being buffered.
+///     - A message sent to a peer which accepts compressed messages may be
+///       compressed with zstd, see `Frame`.  Its length has
+///       `COMPRESSED_FLAG` set and it starts with the first bytes of the
+///       message, to read its kind from, and the size of the message, both
+///       checked against the limit of the kind before the message is
+///       decompressed.  Only messages up to `MAX_DECOMPRESSED_SIZE_BYTES` are
+///       compressed, and the message is decompressed into a buffer which
+///       grows with the output up to the size, so that a small frame can't
+///       make the node allocate much.
+use crate::network_protocol::{MessageSizeLimits, PeerMessage, PeerMessageKind};
+use crate::peer::zstd;
 use crate::stats::metrics;
 use bytes::{Buf, BufMut, BytesMut};
 use bytesize::{GIB, MIB};
-use near_network_primitives::types::ReasonForBan;
+use near_network_primitives::types::{CompressionConfig, ReasonForBan, RoutedMessageBody};
 use near_performance_metrics::framed_write::EncoderCallBack;
+use std::collections::HashSet;
 use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use std::time::Instant;
+use strum::VariantNames;
 use tokio_util::codec::{Decoder, Encoder};
 use tracing::error;
 
 /// Maximum size of network message in encoded format.
 /// We encode length as `u32`, and therefore maximum size can't be larger than `u32::MAX`.
-const NETWORK_MESSAGE_MAX_SIZE_BYTES: usize = 512 * MIB as usize;
+pub(crate) const NETWORK_MESSAGE_MAX_SIZE_BYTES: usize = 512 * MIB as usize;
 /// Maximum capacity of write buffer in bytes.
 const MAX_WRITE_BUFFER_CAPACITY_BYTES: usize = GIB as usize;
+/// Number of bytes the kind of a message is read from.
+const MESSAGE_KIND_BYTES: usize = 2;
+/// Set in the length of the frames of compressed messages, which lengths
+/// can't reach since they're at most `NETWORK_MESSAGE_MAX_SIZE_BYTES`.
+const COMPRESSED_FLAG: u32 = 1 << 31;
+/// Bytes of a compressed frame before the compressed message: its first
+/// bytes and its size as u32.
+const COMPRESSED_HEADER_BYTES: usize = MESSAGE_KIND_BYTES + 4;
+/// The fastest level, which gets most of the size down already.
+const ZSTD_LEVEL: i32 = 1;
+/// Largest message sent compressed, larger ones are sent as they are.  A
+/// compressed frame claiming a larger size gets the peer banned, whatever the
+/// limit of the kind of the message.
+const MAX_DECOMPRESSED_SIZE_BYTES: usize = 16 * MIB as usize;
+
+/// Frame which isn't passed on to be parsed.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum FrameError {
+    /// The frame is longer than any message may be.
+    Ban(ReasonForBan),
+    /// The message is above the size limit of its kind, `None` if the kind
+    /// couldn't be read.
+    Oversized { kind: Option<PeerMessageKind>, len: usize },
+}
+
+/// A message to write.
+pub(crate) enum Frame {
+    Plain(Vec<u8>),
+    /// Written compressed unless that doesn't make it smaller.  `msg_type`
+    /// labels the metrics.
+    Compress {
+        data: Vec<u8>,
+        msg_type: &'static str,
+    },
+}
+
+/// Which messages sent to a peer are compressed, once the peer accepted
+/// compressed messages in its handshake.
+#[derive(Debug)]
+pub(crate) struct MessageCompression {
+    threshold: usize,
+    skip_message_types: HashSet<String>,
+}
+
+impl MessageCompression {
+    /// Checks the types of messages not to compress are names which
+    /// `PeerMessage::msg_variant` returns.
+    pub(crate) fn new(config: &CompressionConfig) -> Result<Self, String> {
+        for name in &config.skip_message_types {
+            let name = name.as_str();
+            let is_type = (PeerMessage::VARIANTS.contains(&name) && name != "Routed")
+                || RoutedMessageBody::VARIANTS.contains(&name);
+            if !is_type {
+                return Err(format!("{} isn't a type of network message", name));
+            }
+        }
+        Ok(Self {
+            threshold: config.threshold,
+            skip_message_types: config.skip_message_types.iter().cloned().collect(),
+        })
+    }
+
+    /// The frame of the encoded message, compressed if it's big enough and
+    /// not of a type to skip.
+    pub(crate) fn frame(&self, msg: &PeerMessage, data: Vec<u8>) -> Frame {
+        let msg_type = msg.msg_variant();
+        if data.len() < std::cmp::max(self.threshold, MESSAGE_KIND_BYTES)
+            || self.skip_message_types.contains(msg_type)
+        {
+            return Frame::Plain(data);
+        }
+        Frame::Compress { data, msg_type }
+    }
+}
+
+/// The compressed frame of the message, `None` if it isn't smaller or is
+/// above `MAX_DECOMPRESSED_SIZE_BYTES`.
+fn compress(data: &[u8], msg_type: &'static str) -> Option<Vec<u8>> {
+    if data.len() > MAX_DECOMPRESSED_SIZE_BYTES {
+        return None;
+    }
+    let started = Instant::now();
+    let compressed = zstd::compress(data, ZSTD_LEVEL);
+    metrics::PEER_MESSAGE_COMPRESSION_TIME
+        .with_label_values(&[msg_type])
+        .observe(started.elapsed().as_secs_f64());
+    let compressed = compressed.ok()?;
+    let len = COMPRESSED_HEADER_BYTES + compressed.len();
+    if len >= data.len() {
+        return None;
+    }
+    metrics::PEER_MESSAGE_COMPRESSION_SAVED_BYTES
+        .with_label_values(&[msg_type])
+        .inc_by((data.len() - len) as u64);
+    let mut frame = Vec::with_capacity(len);
+    frame.extend_from_slice(&data[..MESSAGE_KIND_BYTES]);
+    frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&compressed);
+    Some(frame)
+}
+
+/// The message of a compressed frame, which must have the size and start
+/// with the bytes its header says.
+fn decompress(
+    kind: Option<PeerMessageKind>,
+    frame: &[u8],
+    size: usize,
+) -> Result<Vec<u8>, FrameError> {
+    let started = Instant::now();
+    // Fails if the message is larger than the size.
+    let message = zstd::decompress(&frame[COMPRESSED_HEADER_BYTES..], size);
+    metrics::PEER_MESSAGE_DECOMPRESSION_TIME
+        .with_label_values(&[kind.map_or("unknown", |kind| kind.into())])
+        .observe(started.elapsed().as_secs_f64());
+    message
+        .ok()
+        .filter(|message| {
+            message.len() == size && message.starts_with(&frame[..MESSAGE_KIND_BYTES])
+        })
+        .ok_or(FrameError::Ban(ReasonForBan::Abusive))
+}
 
 #[derive(Default)]
-pub(crate) struct Codec {}
+pub(crate) struct Codec {
+    limits: Arc<MessageSizeLimits>,
+    /// Whether compressed frames are read, or ban the peer.
+    decompress: bool,
+    /// Bytes of an oversized frame yet to be skipped.
+    skip: usize,
+}
+
+impl Codec {
+    pub(crate) fn new(limits: Arc<MessageSizeLimits>) -> Self {
+        Self { limits, decompress: false, skip: 0 }
+    }
+
+    /// Reads the compressed frames as well, for the node accepting compressed
+    /// messages in its handshakes.
+    pub(crate) fn with_decompression(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// Drops what's left of the oversized frame in the buffer, returns
+    /// whether it's all gone.
+    fn skip(&mut self, buf: &mut BytesMut) -> bool {
+        let skipped = std::cmp::min(self.skip, buf.len());
+        buf.advance(skipped);
+        self.skip -= skipped;
+        if buf.is_empty() && buf.capacity() > 0 {
+            *buf = BytesMut::new();
+        }
+        self.skip == 0
+    }
+}
 
 impl EncoderCallBack for Codec {
     #[allow(unused)]
@@ -38,61 +207,116 @@ impl Encoder<Vec<u8>> for Codec {
     type Error = Error;
 
     fn encode(&mut self, item: Vec<u8>, buf: &mut BytesMut) -> Result<(), Error> {
-        if item.len() > NETWORK_MESSAGE_MAX_SIZE_BYTES {
-            // TODO(mina86): Is there some way we can know what message we’re
-            // encoding?
-            metrics::MessageDropped::InputTooLong.inc_unknown_msg();
-            return Err(Error::new(ErrorKind::InvalidInput, "Input is too long"));
+        write_frame(&item, 0, buf)
+    }
+}
+
+impl Encoder<Frame> for Codec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Frame, buf: &mut BytesMut) -> Result<(), Error> {
+        match item {
+            Frame::Plain(data) => write_frame(&data, 0, buf),
+            Frame::Compress { data, msg_type } => match compress(&data, msg_type) {
+                Some(frame) => write_frame(&frame, COMPRESSED_FLAG, buf),
+                None => write_frame(&data, 0, buf),
+            },
         }
+    }
+}
+
+/// Writes the length of the item, with the flags, and the item.
+fn write_frame(item: &[u8], flags: u32, buf: &mut BytesMut) -> Result<(), Error> {
+    if item.len() > NETWORK_MESSAGE_MAX_SIZE_BYTES {
+        // TODO(mina86): Is there some way we can know what message we’re
+        // encoding?
+        metrics::MessageDropped::InputTooLong.inc_unknown_msg();
+        return Err(Error::new(ErrorKind::InvalidInput, "Input is too long"));
+    }
 
+    #[cfg(feature = "performance_stats")]
+    {
+        let stat = near_performance_metrics::stats_enabled::get_thread_stats_logger();
+        stat.lock().unwrap().log_add_write_buffer(item.len() + 4, buf.len(), buf.capacity());
+    }
+    if buf.capacity() >= MAX_WRITE_BUFFER_CAPACITY_BYTES
+        && item.len() + 4 + buf.len() > buf.capacity()
+    {
         #[cfg(feature = "performance_stats")]
-        {
-            let stat = near_performance_metrics::stats_enabled::get_thread_stats_logger();
-            stat.lock().unwrap().log_add_write_buffer(item.len() + 4, buf.len(), buf.capacity());
-        }
-        if buf.capacity() >= MAX_WRITE_BUFFER_CAPACITY_BYTES
-            && item.len() + 4 + buf.len() > buf.capacity()
-        {
-            #[cfg(feature = "performance_stats")]
-            let tid = near_rust_allocator_proxy::get_tid();
-            #[cfg(not(feature = "performance_stats"))]
-            let tid = 0;
-            error!(target: "network", "{} throwing away message, because buffer is full item.len(): {} buf.capacity: {}",
-                   tid,
-                   item.len(), buf.capacity());
-
-            // TODO(mina86): Is there some way we can know what message
-            // we’re encoding?
-            metrics::MessageDropped::MaxCapacityExceeded.inc_unknown_msg();
-            return Err(Error::new(ErrorKind::Other, "Buf max capacity exceeded"));
-        }
-        // First four bytes is the length of the buffer.
-        buf.reserve(item.len() + 4);
-        buf.put_u32_le(item.len() as u32);
-        buf.put(&item[..]);
-        Ok(())
+        let tid = near_rust_allocator_proxy::get_tid();
+        #[cfg(not(feature = "performance_stats"))]
+        let tid = 0;
+        error!(target: "network", "{} throwing away message, because buffer is full item.len(): {} buf.capacity: {}",
+               tid,
+               item.len(), buf.capacity());
+
+        // TODO(mina86): Is there some way we can know what message
+        // we’re encoding?
+        metrics::MessageDropped::MaxCapacityExceeded.inc_unknown_msg();
+        return Err(Error::new(ErrorKind::Other, "Buf max capacity exceeded"));
     }
+    // First four bytes is the length of the buffer.
+    buf.reserve(item.len() + 4);
+    buf.put_u32_le(item.len() as u32 | flags);
+    buf.put(item);
+    Ok(())
 }
 
 impl Decoder for Codec {
-    type Item = Result<Vec<u8>, ReasonForBan>;
+    type Item = Result<Vec<u8>, FrameError>;
     type Error = Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if !self.skip(buf) {
+            return Ok(None);
+        }
         let len_buf = match buf.get(..4).and_then(|s| <[u8; 4]>::try_from(s).ok()) {
             // not enough bytes to start decoding
             None => return Ok(None),
             Some(res) => res,
         };
 
-        let len = u32::from_le_bytes(len_buf) as usize;
-        if len > NETWORK_MESSAGE_MAX_SIZE_BYTES {
+        let len_and_flags = u32::from_le_bytes(len_buf);
+        let compressed = len_and_flags & COMPRESSED_FLAG != 0;
+        let len = (len_and_flags & !COMPRESSED_FLAG) as usize;
+        if len > NETWORK_MESSAGE_MAX_SIZE_BYTES
+            || (compressed && (!self.decompress || len < COMPRESSED_HEADER_BYTES))
+        {
             // If this point is reached, abusive peer is banned.
-            return Ok(Some(Err(ReasonForBan::Abusive)));
+            return Ok(Some(Err(FrameError::Ban(ReasonForBan::Abusive))));
+        }
+
+        let kind_len = std::cmp::min(len, MESSAGE_KIND_BYTES);
+        let kind = match buf.get(4..4 + kind_len) {
+            // not enough bytes to read the kind
+            None => return Ok(None),
+            Some(kind_buf) => PeerMessageKind::from_encoded(kind_buf),
+        };
+        // The size of the message once decompressed.
+        let size = if compressed {
+            match buf.get(4 + MESSAGE_KIND_BYTES..4 + COMPRESSED_HEADER_BYTES) {
+                // not enough bytes to read the size
+                None => return Ok(None),
+                Some(size_buf) => u32::from_le_bytes(size_buf.try_into().unwrap()) as usize,
+            }
+        } else {
+            len
+        };
+        if compressed && size > MAX_DECOMPRESSED_SIZE_BYTES {
+            return Ok(Some(Err(FrameError::Ban(ReasonForBan::Abusive))));
+        }
+        if std::cmp::max(len, size) > self.limits.get(kind) {
+            self.skip = 4 + len;
+            self.skip(buf);
+            return Ok(Some(Err(FrameError::Oversized { kind, len: std::cmp::max(len, size) })));
         }
 
         if let Some(data_buf) = buf.get(4..4 + len) {
-            let res = Some(Ok(data_buf.to_vec()));
+            let res = if compressed {
+                Some(decompress(kind, data_buf, size))
+            } else {
+                Some(Ok(data_buf.to_vec()))
+            };
             buf.advance(4 + len);
             if buf.is_empty() && buf.capacity() > 0 {
                 *buf = BytesMut::new();
@@ -107,19 +331,26 @@ impl Decoder for Codec {
 
 #[cfg(test)]
 mod test {
-    use crate::peer::codec::{Codec, NETWORK_MESSAGE_MAX_SIZE_BYTES};
+    use crate::network_protocol::{Encoding, MessageSizeLimits, PeerMessageKind};
+    use crate::peer::codec::{
+        compress, Codec, Frame, FrameError, MessageCompression, COMPRESSED_FLAG,
+        COMPRESSED_HEADER_BYTES, MAX_DECOMPRESSED_SIZE_BYTES, MESSAGE_KIND_BYTES,
+        NETWORK_MESSAGE_MAX_SIZE_BYTES,
+    };
     use crate::types::{Handshake, PeerMessage, RoutingTableUpdate};
     use bytes::{BufMut, BytesMut};
     use near_crypto::{KeyType, SecretKey};
     use near_network_primitives::types::{
-        PartialEdgeInfo, PeerChainInfoV2, PeerIdOrHash, PeerInfo, ReasonForBan, RoutedMessage,
-        RoutedMessageBody,
+        CompressionConfig, PartialEdgeInfo, PeerChainInfoV2, PeerIdOrHash, PeerInfo, ReasonForBan,
+        RoutedMessage, RoutedMessageBody,
     };
     use near_primitives::block::{Approval, ApprovalInner};
     use near_primitives::hash::CryptoHash;
     use near_primitives::network::{AnnounceAccount, PeerId};
     use near_primitives::types::EpochId;
     use near_primitives::version::{PEER_MIN_ALLOWED_PROTOCOL_VERSION, PROTOCOL_VERSION};
+    use std::collections::HashMap;
+    use std::sync::Arc;
     use tokio_util::codec::{Decoder, Encoder};
 
     fn test_codec(msg: PeerMessage) {
@@ -178,6 +409,8 @@ mod test {
                 archival: false,
             },
             partial_edge_info: PartialEdgeInfo::default(),
+            sender_timestamp: None,
+            accepts_compression: false,
         };
         let msg = PeerMessage::Handshake(fake_handshake);
         test_codec(msg);
@@ -246,7 +479,10 @@ mod test {
         let mut buffer = BytesMut::new();
         buffer.reserve(4);
         buffer.put_u32_le(NETWORK_MESSAGE_MAX_SIZE_BYTES as u32 + 1);
-        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(Err(ReasonForBan::Abusive)));
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Ban(ReasonForBan::Abusive)))
+        );
     }
 
     #[test]
@@ -255,6 +491,157 @@ mod test {
         let mut buffer = BytesMut::new();
         buffer.reserve(4);
         buffer.put_u32_le(NETWORK_MESSAGE_MAX_SIZE_BYTES as u32);
-        assert_ne!(codec.decode(&mut buffer).unwrap(), Some(Err(ReasonForBan::Abusive)));
+        assert_ne!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Ban(ReasonForBan::Abusive)))
+        );
+    }
+
+    #[test]
+    fn test_oversized() {
+        let overrides = HashMap::from([("Block".to_string(), 100)]);
+        let mut codec = Codec::new(Arc::new(MessageSizeLimits::new(&overrides).unwrap()));
+        let mut buffer = BytesMut::new();
+        buffer.put_u32_le(101);
+        // Borsh encoding of a block starts with its variant, 11.
+        buffer.put_u8(11);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.put_slice(&[0; 50]);
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Oversized { kind: Some(PeerMessageKind::Block), len: 101 }))
+        );
+        assert!(buffer.is_empty());
+
+        // The rest of the frame is skipped as it comes, then frames are read
+        // as usual.
+        buffer.put_slice(&[0; 49]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.put_slice(&[0; 1]);
+        buffer.put_u32_le(100);
+        buffer.put_u8(11);
+        buffer.put_slice(&[0; 99]);
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Ok([&[11][..], &[0; 99][..]].concat()))
+        );
+
+        // Frames whose kind can't be read are held to the smallest limit.
+        let len = PeerMessageKind::Handshake.default_max_size() + 1;
+        buffer.put_u32_le(len as u32);
+        buffer.put_slice(&[16, 0]);
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Oversized { kind: None, len }))
+        );
+    }
+
+    #[test]
+    fn test_message_size_limits() {
+        let limits = |overrides: &[(&str, usize)]| {
+            MessageSizeLimits::new(
+                &overrides.iter().map(|(name, limit)| (name.to_string(), *limit)).collect(),
+            )
+        };
+        let default = limits(&[]).unwrap();
+        assert_eq!(default, MessageSizeLimits::default());
+        assert_eq!(default.get(Some(PeerMessageKind::Routed)), NETWORK_MESSAGE_MAX_SIZE_BYTES);
+        assert_eq!(
+            limits(&[("Transaction", 10)]).unwrap().get(Some(PeerMessageKind::Transaction)),
+            10
+        );
+        assert!(limits(&[("Transactions", 10)]).is_err());
+        assert!(limits(&[("Block", NETWORK_MESSAGE_MAX_SIZE_BYTES + 1)]).is_err());
+    }
+
+    #[test]
+    fn test_compression() {
+        let config = |skip: &str| CompressionConfig {
+            threshold: 100,
+            skip_message_types: vec![skip.to_string()],
+        };
+        let compression = MessageCompression::new(&config("VersionedStateResponse")).unwrap();
+        let msg = PeerMessage::PeersResponse(vec![PeerInfo::random(); 100]);
+        let data = msg.serialize(Encoding::Proto);
+        let mut codec = Codec::default().with_decompression(true);
+        let mut buffer = BytesMut::new();
+        codec.encode(compression.frame(&msg, data.clone()), &mut buffer).unwrap();
+        assert!(buffer.len() < data.len());
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(Ok(data.clone())));
+        assert!(buffer.is_empty());
+
+        // Small messages and the ones of types to skip are sent as they are.
+        let small = PeerMessage::PeersRequest;
+        assert!(matches!(
+            compression.frame(&small, small.serialize(Encoding::Proto)),
+            Frame::Plain(_)
+        ));
+        let skipping = MessageCompression::new(&config("PeersResponse")).unwrap();
+        assert!(matches!(skipping.frame(&msg, data.clone()), Frame::Plain(_)));
+        assert!(MessageCompression::new(&config("Routed")).is_err());
+        assert!(MessageCompression::new(&config("StateResponse")).is_ok());
+        assert!(MessageCompression::new(&config("PeerResponse")).is_err());
+
+        // A node which doesn't accept compressed messages bans the peer.
+        codec.encode(compression.frame(&msg, data), &mut buffer).unwrap();
+        assert_eq!(
+            Codec::default().decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Ban(ReasonForBan::Abusive)))
+        );
+    }
+
+    #[test]
+    fn test_decompression_limits() {
+        let overrides = HashMap::from([("PeersResponse".to_string(), 1000)]);
+        let mut codec = Codec::new(Arc::new(MessageSizeLimits::new(&overrides).unwrap()))
+            .with_decompression(true);
+        let data =
+            PeerMessage::PeersResponse(vec![PeerInfo::random(); 100]).serialize(Encoding::Proto);
+        assert!(data.len() > 1000);
+
+        // The size of the message once decompressed is held to the limit.
+        let mut buffer = BytesMut::new();
+        codec
+            .encode(Frame::Compress { data: data.clone(), msg_type: "PeersResponse" }, &mut buffer)
+            .unwrap();
+        assert!(buffer.len() < 1000);
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Oversized {
+                kind: Some(PeerMessageKind::PeersResponse),
+                len: data.len()
+            }))
+        );
+        assert!(buffer.is_empty());
+
+        // A message larger than its header says isn't decompressed past it.
+        let mut frame = compress(&data, "PeersResponse").unwrap();
+        frame[MESSAGE_KIND_BYTES..COMPRESSED_HEADER_BYTES].copy_from_slice(&100u32.to_le_bytes());
+        buffer.put_u32_le(frame.len() as u32 | COMPRESSED_FLAG);
+        buffer.put_slice(&frame);
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Ban(ReasonForBan::Abusive)))
+        );
+
+        // A frame of a few bytes claiming a routed message larger than any
+        // message sent compressed, though within the limit of its kind, gets
+        // the peer banned before anything is allocated for it.
+        let size = MAX_DECOMPRESSED_SIZE_BYTES + 1;
+        assert!(MessageSizeLimits::default().get(Some(PeerMessageKind::Routed)) > size);
+        let mut codec = Codec::default().with_decompression(true);
+        let mut buffer = BytesMut::new();
+        buffer.put_u32_le((COMPRESSED_HEADER_BYTES + 4) as u32 | COMPRESSED_FLAG);
+        // Borsh encoding of a routed message starts with its variant, 13.
+        buffer.put_slice(&[13, 0]);
+        buffer.put_u32_le(size as u32);
+        buffer.put_slice(&[0; 4]);
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some(Err(FrameError::Ban(ReasonForBan::Abusive)))
+        );
+
+        // Messages above it are sent as they are.
+        assert_eq!(compress(&vec![0; size], "Routed"), None);
     }
 }
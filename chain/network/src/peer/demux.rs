@@ -0,0 +1,165 @@
+//! Hand-over of the messages of a connection to the client and the view client
+//! by cost class, so that a burst of expensive messages from a peer doesn't
+//! delay its cheap ones.
+//!
+//! Messages are handed over as they're read, and the client and the view
+//! client handle them in the order they arrive in their mailboxes.  A peer
+//! sending many state parts or state part requests would fill the mailboxes
+//! with work taking seconds, and the approvals and chunk parts it sends next
+//! would wait behind all of them.  The messages of the
+//! [`MessageClass::Expensive`] class are handed over at most
+//! [`MAX_EXPENSIVE_IN_FLIGHT`] at a time per connection, the next ones wait in
+//! the connection's queue until one was handled, while the messages of the
+//! other classes go ahead of them.  At most [`MAX_QUEUED_EXPENSIVE`] messages
+//! wait, the ones above are dropped: they're all requests or responses which
+//! the node asking for them sends again.
+
+use crate::network_protocol::PeerMessage;
+use crate::stats::metrics;
+use near_network_primitives::types::RoutedMessageBody;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Expensive messages of a connection handled at the same time.
+pub(crate) const MAX_EXPENSIVE_IN_FLIGHT: usize = 2;
+/// Expensive messages of a connection waiting to be handled.
+pub(crate) const MAX_QUEUED_EXPENSIVE: usize = 64;
+
+/// How expensive a message is to handle.  The `snake_case` name is used as the
+/// label of the metrics.
+///
+/// Within a class the messages are handed over in the order they were read,
+/// which the protocol needs for:
+/// - `EpochSyncResponse` and `EpochSyncFinalizationResponse`, and the state
+///   responses, which answer the requests in order, all in `Expensive` with
+///   their requests;
+/// - `BlockHeaders`, which header sync applies in the order requested, in
+///   `Normal`.
+///
+/// The messages handled by the peer actor and the peer manager, the
+/// handshake, the edges, the routing table and the peers, never get here and
+/// are handled as they're read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum MessageClass {
+    /// Approvals, chunk parts and their requests, challenges: cheap and on the
+    /// critical path of block production.
+    Critical,
+    /// Blocks, headers, transactions and the other messages.
+    Normal,
+    /// State sync and epoch sync, blocks and headers read from the database
+    /// for a peer: each can take up to seconds.
+    Expensive,
+}
+
+impl MessageClass {
+    pub(crate) fn of(msg: &PeerMessage) -> MessageClass {
+        match msg {
+            PeerMessage::Challenge(_) => MessageClass::Critical,
+            PeerMessage::BlockRequest(_)
+            | PeerMessage::BlockHeadersRequest(_)
+            | PeerMessage::EpochSyncRequest(_)
+            | PeerMessage::EpochSyncResponse(_)
+            | PeerMessage::EpochSyncFinalizationRequest(_)
+            | PeerMessage::EpochSyncFinalizationResponse(_) => MessageClass::Expensive,
+            PeerMessage::Routed(routed) => match routed.body {
+                RoutedMessageBody::BlockApproval(_)
+                | RoutedMessageBody::PartialEncodedChunk(_)
+                | RoutedMessageBody::VersionedPartialEncodedChunk(_)
+                | RoutedMessageBody::PartialEncodedChunkForward(_)
+                | RoutedMessageBody::PartialEncodedChunkRequest(_)
+                | RoutedMessageBody::PartialEncodedChunkResponse(_) => MessageClass::Critical,
+                RoutedMessageBody::StateRequestHeader(_, _)
+                | RoutedMessageBody::StateRequestPart(_, _, _)
+                | RoutedMessageBody::StateResponse(_)
+                | RoutedMessageBody::VersionedStateResponse(_) => MessageClass::Expensive,
+                _ => MessageClass::Normal,
+            },
+            _ => MessageClass::Normal,
+        }
+    }
+}
+
+/// The expensive messages of a connection being handled and waiting.
+pub(crate) struct Demux<T> {
+    in_flight: usize,
+    queue: VecDeque<(T, Instant)>,
+}
+
+impl<T> Default for Demux<T> {
+    fn default() -> Self {
+        Self { in_flight: 0, queue: VecDeque::new() }
+    }
+}
+
+impl<T> Demux<T> {
+    /// Returns the message read at `now` if it's to be handed over now, or
+    /// else queues or drops it.  Every expensive message returned, here or by
+    /// `handled`, is to be reported to `handled` once handled.
+    pub(crate) fn push(&mut self, class: MessageClass, msg: T, now: Instant) -> Option<T> {
+        if class == MessageClass::Expensive {
+            if self.in_flight >= MAX_EXPENSIVE_IN_FLIGHT || !self.queue.is_empty() {
+                if self.queue.len() >= MAX_QUEUED_EXPENSIVE {
+                    metrics::PEER_MESSAGES_DROPPED_BY_CLASS
+                        .with_label_values(&[class.as_ref()])
+                        .inc();
+                } else {
+                    self.queue.push_back((msg, now));
+                }
+                return None;
+            }
+            self.in_flight += 1;
+        }
+        metrics::PEER_MESSAGE_QUEUEING_DELAY.with_label_values(&[class.as_ref()]).observe(0.);
+        Some(msg)
+    }
+
+    /// Reports a message of the class was handled, returning the next
+    /// expensive message to hand over if one is waiting.
+    pub(crate) fn handled(&mut self, class: MessageClass, now: Instant) -> Option<T> {
+        if class != MessageClass::Expensive {
+            return None;
+        }
+        self.in_flight = self.in_flight.saturating_sub(1);
+        let (msg, queued_at) = self.queue.pop_front()?;
+        self.in_flight += 1;
+        metrics::PEER_MESSAGE_QUEUEING_DELAY
+            .with_label_values(&[MessageClass::Expensive.as_ref()])
+            .observe(now.saturating_duration_since(queued_at).as_secs_f64());
+        Some(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_demux() {
+        let mut demux = Demux::default();
+        let now = Instant::now();
+        for i in 0..MAX_EXPENSIVE_IN_FLIGHT {
+            assert_eq!(demux.push(MessageClass::Expensive, i, now), Some(i));
+        }
+        // The next expensive ones wait, in order, the others don't.
+        assert_eq!(demux.push(MessageClass::Expensive, 100, now), None);
+        assert_eq!(demux.push(MessageClass::Critical, 101, now), Some(101));
+        assert_eq!(demux.push(MessageClass::Expensive, 102, now), None);
+        assert_eq!(demux.push(MessageClass::Normal, 103, now), Some(103));
+        assert_eq!(demux.handled(MessageClass::Critical, now), None);
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(demux.handled(MessageClass::Expensive, later), Some(100));
+        assert_eq!(demux.handled(MessageClass::Expensive, later), Some(102));
+        assert_eq!(demux.handled(MessageClass::Expensive, later), None);
+        // A free slot is taken right away.
+        assert_eq!(demux.push(MessageClass::Expensive, 104, later), Some(104));
+        assert_eq!(demux.push(MessageClass::Expensive, 105, later), None);
+
+        for i in 0..MAX_QUEUED_EXPENSIVE * 2 {
+            demux.push(MessageClass::Expensive, 1000 + i, later);
+        }
+        assert_eq!(demux.queue.len(), MAX_QUEUED_EXPENSIVE);
+    }
+}
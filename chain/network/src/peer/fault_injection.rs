@@ -0,0 +1,176 @@
+//! Faults injected into the messages received from peers, to test the node
+//! under packet loss, latency and limited bandwidth without external tools.
+//!
+//! The faults are set for the whole node through the `adv_set_network_faults`
+//! JSON RPC method and apply to messages received from ready connections,
+//! the handshakes are never affected.  For every message the first matching
+//! [`NetworkFault`] decides whether it's dropped and how long it's delayed.
+//! Only compiled with the `test_features` feature and never applied on
+//! mainnet.
+
+use near_network_primitives::types::NetworkFault;
+use near_primitives::network::PeerId;
+use rand::Rng;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Chain id on which no faults are ever injected.
+const MAINNET: &str = "mainnet";
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Fault {
+    Drop,
+    Delay(Duration),
+}
+
+/// Faults set for the node, shared by all connections.
+#[derive(Default)]
+pub(crate) struct FaultInjector {
+    faults: RwLock<Vec<NetworkFault>>,
+}
+
+impl FaultInjector {
+    pub(crate) fn set(&self, faults: Vec<NetworkFault>) {
+        *self.faults.write().unwrap() = faults;
+    }
+
+    fn find(&self, peer_id: &PeerId, message_type: &str) -> Option<NetworkFault> {
+        let faults = self.faults.read().unwrap();
+        faults
+            .iter()
+            .find(|fault| {
+                fault.peer_id.as_ref().map_or(true, |id| id == peer_id)
+                    && fault.message_type.as_ref().map_or(true, |t| t == message_type)
+            })
+            .cloned()
+    }
+}
+
+/// Faults of a single connection.
+#[derive(Default)]
+pub(crate) struct PeerFaults {
+    injector: Arc<FaultInjector>,
+    /// Time the simulated link is done receiving the delayed messages.
+    link_free_at: Option<Instant>,
+}
+
+impl PeerFaults {
+    pub(crate) fn new(injector: Arc<FaultInjector>) -> Self {
+        Self { injector, link_free_at: None }
+    }
+
+    /// Returns what happens to a message of the given type and size received
+    /// from the peer now.
+    pub(crate) fn apply(
+        &mut self,
+        chain_id: &str,
+        peer_id: &PeerId,
+        message_type: &str,
+        size: usize,
+        now: Instant,
+    ) -> Option<Fault> {
+        if chain_id == MAINNET {
+            return None;
+        }
+        let fault = self.injector.find(peer_id, message_type)?;
+        apply_fault(&fault, size, now, &mut self.link_free_at, &mut rand::thread_rng())
+    }
+}
+
+fn apply_fault(
+    fault: &NetworkFault,
+    size: usize,
+    now: Instant,
+    link_free_at: &mut Option<Instant>,
+    rng: &mut impl Rng,
+) -> Option<Fault> {
+    if fault.drop_probability > 0.0 && rng.gen_bool(fault.drop_probability.min(1.0)) {
+        return Some(Fault::Drop);
+    }
+    let mut delay = Duration::ZERO;
+    if let Some(bandwidth) = fault.bandwidth {
+        let transfer = Duration::from_secs_f64(size as f64 / bandwidth.max(1) as f64);
+        let done = link_free_at.map_or(now, |free_at| free_at.max(now)) + transfer;
+        *link_free_at = Some(done);
+        delay = done - now;
+    }
+    let latency_ms = if fault.max_delay_ms > fault.min_delay_ms {
+        rng.gen_range(fault.min_delay_ms, fault.max_delay_ms + 1)
+    } else {
+        fault.min_delay_ms
+    };
+    delay += Duration::from_millis(latency_ms);
+    (delay > Duration::ZERO).then(|| Fault::Delay(delay))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+
+    fn peer_id(seed: &str) -> PeerId {
+        PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key())
+    }
+
+    #[test]
+    fn test_find_fault() {
+        let injector = FaultInjector::default();
+        let (alice, bob) = (peer_id("alice"), peer_id("bob"));
+        assert_eq!(injector.find(&alice, "Block"), None);
+        let approvals = NetworkFault {
+            message_type: Some("BlockApproval".to_string()),
+            drop_probability: 0.2,
+            ..Default::default()
+        };
+        let bob_all = NetworkFault { peer_id: Some(bob.clone()), ..Default::default() };
+        injector.set(vec![approvals.clone(), bob_all.clone()]);
+        assert_eq!(injector.find(&alice, "BlockApproval"), Some(approvals.clone()));
+        assert_eq!(injector.find(&bob, "BlockApproval"), Some(approvals));
+        assert_eq!(injector.find(&bob, "Block"), Some(bob_all));
+        assert_eq!(injector.find(&alice, "Block"), None);
+    }
+
+    #[test]
+    fn test_apply_fault() {
+        let mut rng = rand_pcg::Pcg32::new(0, 0xa02bdbf7bb3c0a7);
+        let now = Instant::now();
+        let mut link_free_at = None;
+
+        let fault = NetworkFault { drop_probability: 1.0, ..Default::default() };
+        assert_eq!(apply_fault(&fault, 10, now, &mut link_free_at, &mut rng), Some(Fault::Drop));
+        let fault = NetworkFault::default();
+        assert_eq!(apply_fault(&fault, 10, now, &mut link_free_at, &mut rng), None);
+
+        let fault = NetworkFault { min_delay_ms: 10, max_delay_ms: 20, ..Default::default() };
+        for _ in 0..100 {
+            match apply_fault(&fault, 10, now, &mut link_free_at, &mut rng) {
+                Some(Fault::Delay(delay)) => {
+                    assert!(
+                        Duration::from_millis(10) <= delay && delay <= Duration::from_millis(20)
+                    )
+                }
+                fault => panic!("unexpected {:?}", fault),
+            }
+        }
+
+        // At 1000 bytes per second messages queue up behind each other.
+        let fault = NetworkFault { bandwidth: Some(1000), ..Default::default() };
+        let delays: Vec<_> =
+            (0..3).map(|_| apply_fault(&fault, 500, now, &mut link_free_at, &mut rng)).collect();
+        let ms = |ms| Some(Fault::Delay(Duration::from_millis(ms)));
+        assert_eq!(delays, vec![ms(500), ms(1000), ms(1500)]);
+        let later = now + Duration::from_secs(10);
+        assert_eq!(apply_fault(&fault, 500, later, &mut link_free_at, &mut rng), ms(500));
+    }
+
+    #[test]
+    fn test_no_faults_on_mainnet() {
+        let injector = Arc::new(FaultInjector::default());
+        injector.set(vec![NetworkFault { drop_probability: 1.0, ..Default::default() }]);
+        let mut faults = PeerFaults::new(injector);
+        let alice = peer_id("alice");
+        let now = Instant::now();
+        assert_eq!(faults.apply("mainnet", &alice, "Block", 10, now), None);
+        assert_eq!(faults.apply("testnet", &alice, "Block", 10, now), Some(Fault::Drop));
+    }
+}
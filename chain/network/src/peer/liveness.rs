@@ -0,0 +1,125 @@
+//! Detection of connections to peers which went away without closing them,
+//! e.g. because the machine was suspended or a NAT entry expired.
+//!
+//! A peer which hasn't sent anything for `ping_after` is pinged, and is
+//! expected to send something, the pong or any other message, within
+//! `timeout`.  Every window without a message counts as a missed check and
+//! the peer is pinged again, once `max_missed` checks are missed in a row the
+//! connection is dropped.  Peers which missed a check are used to route
+//! messages only when there's no other route.
+//!
+//! The TCP keepalive of the sockets catches the same at the OS level, for
+//! connections nothing is sent over.
+
+use std::time::{Duration, Instant};
+
+/// TCP keepalive settings of the peer sockets.  The interval and the number of
+/// retries can't be set on every platform, the OS defaults are kept there.
+pub(crate) struct TcpKeepalive {
+    pub time: Duration,
+    pub interval: Duration,
+    pub retries: u32,
+}
+
+impl TcpKeepalive {
+    pub fn set(&self, stream: &tokio::net::TcpStream) -> std::io::Result<()> {
+        let keepalive = socket2::TcpKeepalive::new().with_time(self.time);
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        let keepalive = keepalive.with_interval(self.interval).with_retries(self.retries);
+        socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct LivenessConfig {
+    pub ping_after: Duration,
+    pub timeout: Duration,
+    /// Zero turns the checks off.
+    pub max_missed: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum LivenessAction {
+    Nothing,
+    Ping,
+    Disconnect,
+}
+
+pub(crate) struct Liveness {
+    config: LivenessConfig,
+    last_received: Instant,
+    ping_sent: Option<Instant>,
+    missed: u32,
+}
+
+impl Liveness {
+    pub fn new(config: LivenessConfig, now: Instant) -> Self {
+        Self { config, last_received: now, ping_sent: None, missed: 0 }
+    }
+
+    pub fn on_message(&mut self, now: Instant) {
+        self.last_received = now;
+        self.ping_sent = None;
+        self.missed = 0;
+    }
+
+    /// Whether the peer missed the last check.
+    pub fn is_failing(&self) -> bool {
+        self.missed > 0
+    }
+
+    pub fn check(&mut self, now: Instant) -> LivenessAction {
+        if self.config.max_missed == 0 {
+            return LivenessAction::Nothing;
+        }
+        match self.ping_sent {
+            Some(ping_sent) if now.saturating_duration_since(ping_sent) >= self.config.timeout => {
+                self.missed += 1;
+                if self.missed >= self.config.max_missed {
+                    return LivenessAction::Disconnect;
+                }
+                self.ping_sent = Some(now);
+                LivenessAction::Ping
+            }
+            Some(_) => LivenessAction::Nothing,
+            None if now.saturating_duration_since(self.last_received) >= self.config.ping_after => {
+                self.ping_sent = Some(now);
+                LivenessAction::Ping
+            }
+            None => LivenessAction::Nothing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_liveness() {
+        let config = LivenessConfig {
+            ping_after: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            max_missed: 2,
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut liveness = Liveness::new(config, start);
+        assert_eq!(liveness.check(at(29)), LivenessAction::Nothing);
+        assert_eq!(liveness.check(at(30)), LivenessAction::Ping);
+        assert_eq!(liveness.check(at(35)), LivenessAction::Nothing);
+        assert_eq!(liveness.check(at(40)), LivenessAction::Ping);
+        assert!(liveness.is_failing());
+
+        // Any message resets the checks.
+        liveness.on_message(at(45));
+        assert!(!liveness.is_failing());
+        assert_eq!(liveness.check(at(74)), LivenessAction::Nothing);
+        assert_eq!(liveness.check(at(75)), LivenessAction::Ping);
+        assert_eq!(liveness.check(at(85)), LivenessAction::Ping);
+        assert_eq!(liveness.check(at(95)), LivenessAction::Disconnect);
+
+        let mut liveness = Liveness::new(LivenessConfig { max_missed: 0, ..config }, start);
+        assert_eq!(liveness.check(at(1000)), LivenessAction::Nothing);
+    }
+}
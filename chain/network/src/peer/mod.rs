@@ -1,4 +1,9 @@
 pub(crate) mod codec;
+mod demux;
+#[cfg(feature = "test_features")]
+pub(crate) mod fault_injection;
+pub(crate) mod liveness;
 pub(crate) mod peer_actor;
 mod tracker;
 mod transfer_stats;
+mod zstd;
@@ -1,6 +1,13 @@
-use crate::network_protocol::{Encoding, ParsePeerMessageError};
-use crate::peer::codec::Codec;
+use crate::network_protocol::{Encoding, ParsePeerMessageError, PeerMessageKind};
+use crate::peer::codec::{Codec, Frame, FrameError, MessageCompression};
+use crate::peer::demux::{Demux, MessageClass};
+#[cfg(feature = "test_features")]
+use crate::peer::fault_injection::{Fault, PeerFaults};
+use crate::peer::liveness::{Liveness, LivenessAction, LivenessConfig};
 use crate::peer::tracker::Tracker;
+use crate::peer_manager::clock_skew;
+use crate::peer_manager::handshake_refusals::{HandshakeRefusalReason, HandshakeRefusals};
+use crate::peer_manager::message_recorder::{MessageRecorder, RecordedEvent};
 use crate::private_actix::{
     PeersRequest, RegisterPeer, RegisterPeerResponse, SendMessage, Unregister,
 };
@@ -26,11 +33,12 @@ use near_network_primitives::types::{Edge, PartialEdgeInfo};
 use near_performance_metrics::framed_write::{FramedWrite, WriteHandler};
 use near_performance_metrics_macros::perf;
 use near_primitives::block::GenesisId;
+use near_primitives::checked_feature;
 use near_primitives::logging;
 use near_primitives::network::PeerId;
 use near_primitives::sharding::PartialEncodedChunk;
 use near_primitives::time::Clock;
-use near_primitives::utils::DisplayOption;
+use near_primitives::utils::{to_timestamp, DisplayOption};
 use near_primitives::version::{
     ProtocolVersion, PEER_MIN_ALLOWED_PROTOCOL_VERSION, PROTOCOL_VERSION,
 };
@@ -59,6 +67,11 @@ const MAX_TRANSACTIONS_PER_BLOCK_MESSAGE: usize = 1000;
 const ROUTED_MESSAGE_CACHE_SIZE: usize = 1000;
 /// Duplicated messages will be dropped if routed through the same peer multiple times.
 const DROP_DUPLICATED_MESSAGES_PERIOD: Duration = Duration::from_millis(50);
+/// Number of messages above the size limit of their kind after which the
+/// peer is banned.
+const OVERSIZED_MESSAGES_BEFORE_BAN: usize = 3;
+/// How often the liveness of a ready peer is checked.
+const LIVENESS_CHECK_PERIOD: Duration = Duration::from_secs(1);
 
 pub(crate) struct PeerActor {
     /// This node's id and address (either listening or socket address).
@@ -74,7 +87,7 @@ pub(crate) struct PeerActor {
     /// Protocol version to communicate with this peer.
     protocol_version: ProtocolVersion,
     /// Framed wrapper to send messages through the TCP connection.
-    framed: FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>,
+    framed: FramedWrite<Frame, WriteHalf, Codec, Codec>,
     /// Handshake timeout.
     handshake_timeout: Duration,
     /// Peer manager recipient to break the dependency loop.
@@ -89,6 +102,9 @@ pub(crate) struct PeerActor {
     view_client_addr: Recipient<NetworkViewClientMessages>,
     /// Tracker for requests and responses.
     tracker: Tracker,
+    /// Expensive messages for the client and the view client being handled and
+    /// waiting, see `demux`.
+    demux: Demux<PeerMessage>,
     /// This node genesis id.
     genesis_id: GenesisId,
     /// Latest chain info from the peer.
@@ -102,6 +118,8 @@ pub(crate) struct PeerActor {
     txns_since_last_block: Arc<AtomicUsize>,
     /// How many peer actors are created
     peer_counter: Arc<AtomicUsize>,
+    /// Handshakes recently refused, shared with the PeerManager.
+    handshake_refusals: Arc<HandshakeRefusals>,
     /// Cache of recently routed messages, this allows us to drop duplicates
     routed_message_cache: LruCache<(PeerId, PeerIdOrHash, Signature), Instant>,
     /// A helper data structure for limiting reading
@@ -111,6 +129,29 @@ pub(crate) struct PeerActor {
     /// Whether the PeerActor should skip protobuf support detection and use
     /// a given encoding right away.
     force_encoding: Option<Encoding>,
+    /// Time our handshake was sent, as unix timestamp in nanoseconds.
+    handshake_sent_at: Option<u64>,
+    /// Number of messages from the peer dropped for being above the size
+    /// limit of their kind.
+    oversized_messages: usize,
+    /// Whether the peer keeps answering, checked once it's ready.
+    liveness: Liveness,
+    /// Whether the peer manager was told the peer is failing the liveness
+    /// checks.
+    liveness_failing: bool,
+    /// Log the received messages are recorded to and the number of the
+    /// connection in it.
+    pub(crate) message_recorder: Option<(Arc<MessageRecorder>, u64)>,
+    /// Which messages are compressed, if this node accepts compressed
+    /// messages.  The codec reading the messages of the peer must accept them
+    /// as well.
+    pub(crate) message_compression: Option<Arc<MessageCompression>>,
+    /// Whether the messages sent to the peer are compressed, once both sides
+    /// accepted it in their handshakes.
+    compress: bool,
+    /// Faults injected into the messages received from the peer.
+    #[cfg(feature = "test_features")]
+    pub(crate) faults: PeerFaults,
 }
 
 impl Debug for PeerActor {
@@ -135,7 +176,7 @@ impl PeerActor {
         peer_addr: SocketAddr,
         peer_info: Option<PeerInfo>,
         peer_type: PeerType,
-        framed: FramedWrite<Vec<u8>, WriteHalf, Codec, Codec>,
+        framed: FramedWrite<Frame, WriteHalf, Codec, Codec>,
         handshake_timeout: Duration,
         peer_manager_addr: Recipient<PeerManagerMessageRequest>,
         peer_manager_wrapper_addr: Recipient<ActixMessageWrapper<PeerManagerMessageRequest>>,
@@ -144,8 +185,10 @@ impl PeerActor {
         partial_edge_info: Option<PartialEdgeInfo>,
         txns_since_last_block: Arc<AtomicUsize>,
         peer_counter: Arc<AtomicUsize>,
+        handshake_refusals: Arc<HandshakeRefusals>,
         throttle_controller: ThrottleController,
         force_encoding: Option<Encoding>,
+        liveness_config: LivenessConfig,
     ) -> Self {
         PeerActor {
             my_node_info,
@@ -161,16 +204,27 @@ impl PeerActor {
             client_addr,
             view_client_addr,
             tracker: Default::default(),
+            demux: Default::default(),
             genesis_id: Default::default(),
             chain_info: Default::default(),
             partial_edge_info,
             last_time_received_message_update: Clock::instant(),
             txns_since_last_block,
             peer_counter,
+            handshake_refusals,
             routed_message_cache: LruCache::new(ROUTED_MESSAGE_CACHE_SIZE),
             throttle_controller,
             protocol_buffers_supported: false,
             force_encoding,
+            handshake_sent_at: None,
+            oversized_messages: 0,
+            liveness: Liveness::new(liveness_config, Clock::instant()),
+            liveness_failing: false,
+            message_recorder: None,
+            message_compression: None,
+            compress: false,
+            #[cfg(feature = "test_features")]
+            faults: Default::default(),
         }
     }
 
@@ -234,7 +288,11 @@ impl PeerActor {
         let bytes = msg.serialize(enc);
         self.tracker.increment_sent(bytes.len() as u64);
         let bytes_len = bytes.len();
-        if !self.framed.write(bytes) {
+        let frame = match &self.message_compression {
+            Some(compression) if self.compress => compression.frame(msg, bytes),
+            _ => Frame::Plain(bytes),
+        };
+        if !self.framed.write(frame) {
             #[cfg(feature = "performance_stats")]
             let tid = near_rust_allocator_proxy::get_tid();
             #[cfg(not(feature = "performance_stats"))]
@@ -280,20 +338,25 @@ impl PeerActor {
                     tracked_shards,
                     archival,
                 }) => {
-                    let handshake = match act.protocol_version {
-                        39..=PROTOCOL_VERSION => PeerMessage::Handshake(Handshake::new(
+                    let mut handshake = match act.protocol_version {
+                        39..=PROTOCOL_VERSION => Handshake::new(
                             act.protocol_version,
                             act.my_node_id().clone(),
                             act.other_peer_id().unwrap().clone(),
                             act.my_node_info.addr_port(),
                             PeerChainInfoV2 { genesis_id, height, tracked_shards, archival },
                             act.partial_edge_info.as_ref().unwrap().clone(),
-                        )),
+                        ),
                         _ => {
                             error!(target: "network", "Trying to talk with peer with no supported version: {}", act.protocol_version);
                             return actix::fut::ready(());
                         }
                     };
+                    let now = to_timestamp(Clock::utc());
+                    handshake.sender_timestamp = Some(now);
+                    handshake.accepts_compression = act.message_compression.is_some();
+                    act.handshake_sent_at = Some(now);
+                    let handshake = PeerMessage::Handshake(handshake);
 
                     act.send_message_or_log(&handshake);
                     actix::fut::ready(())
@@ -307,6 +370,94 @@ impl PeerActor {
             .spawn(ctx);
     }
 
+    /// Checks the handshake received from the peer before asking the
+    /// PeerManager to register it.
+    fn check_handshake(&self, handshake: &Handshake) -> Result<(), HandshakeRefusalReason> {
+        if handshake.protocol_version < PEER_MIN_ALLOWED_PROTOCOL_VERSION {
+            return Err(HandshakeRefusalReason::ProtocolVersionTooLow);
+        }
+        if handshake.protocol_version > PROTOCOL_VERSION {
+            return Err(HandshakeRefusalReason::ProtocolVersionTooHigh);
+        }
+        if handshake.sender_chain_info.genesis_id != self.genesis_id {
+            return Err(HandshakeRefusalReason::GenesisMismatch);
+        }
+        if handshake.sender_peer_id == self.my_node_info.id {
+            return Err(HandshakeRefusalReason::ConnectedToSelf);
+        }
+        if handshake.target_peer_id != self.my_node_info.id {
+            return Err(HandshakeRefusalReason::InvalidTarget);
+        }
+        // Verify signature of the new edge in handshake.
+        if !Edge::partial_verify(
+            self.my_node_id(),
+            &handshake.sender_peer_id,
+            &handshake.partial_edge_info,
+        ) {
+            return Err(HandshakeRefusalReason::InvalidSignature);
+        }
+        // Check that received nonce on handshake match our proposed nonce.
+        if self.peer_type == PeerType::Outbound
+            && handshake.partial_edge_info.nonce
+                != self.partial_edge_info.as_ref().map(|edge_info| edge_info.nonce).unwrap()
+        {
+            return Err(HandshakeRefusalReason::InvalidNonce);
+        }
+        Ok(())
+    }
+
+    /// Records the refusal of the handshake and tells the peer why, unless the
+    /// peer misbehaved, in which case the connection is dropped right away.
+    fn refuse_handshake(
+        &mut self,
+        ctx: &mut Context<PeerActor>,
+        handshake: &Handshake,
+        reason: HandshakeRefusalReason,
+    ) {
+        self.handshake_refusals.record(
+            self.peer_addr,
+            handshake.sender_peer_id.clone(),
+            handshake.protocol_version,
+            reason,
+        );
+        let failure = match reason {
+            HandshakeRefusalReason::ProtocolVersionTooLow
+            | HandshakeRefusalReason::ProtocolVersionTooHigh => {
+                HandshakeFailureReason::ProtocolVersionMismatch {
+                    version: PROTOCOL_VERSION,
+                    oldest_supported_version: PEER_MIN_ALLOWED_PROTOCOL_VERSION,
+                }
+            }
+            HandshakeRefusalReason::GenesisMismatch => {
+                HandshakeFailureReason::GenesisMismatch(self.genesis_id.clone())
+            }
+            HandshakeRefusalReason::InvalidTarget => HandshakeFailureReason::InvalidTarget,
+            HandshakeRefusalReason::ConnectedToSelf => {
+                metrics::RECEIVED_INFO_ABOUT_ITSELF.inc();
+                ctx.stop();
+                return;
+            }
+            HandshakeRefusalReason::InvalidSignature => {
+                warn!(target: "network", "Received invalid signature on handshake. Disconnecting peer {}", handshake.sender_peer_id);
+                self.ban_peer(ctx, ReasonForBan::InvalidSignature);
+                return;
+            }
+            HandshakeRefusalReason::InvalidNonce
+            | HandshakeRefusalReason::Blacklisted
+            | HandshakeRefusalReason::Banned
+            | HandshakeRefusalReason::DuplicatePeerId
+            | HandshakeRefusalReason::TooManyPeers => {
+                ctx.stop();
+                return;
+            }
+        };
+        self.send_message_or_log(&PeerMessage::HandshakeFailure(
+            self.my_node_info.clone(),
+            failure,
+        ));
+        // Connection will be closed by a handshake timeout
+    }
+
     fn ban_peer(&mut self, ctx: &mut Context<PeerActor>, ban_reason: ReasonForBan) {
         warn!(target: "network", "Banning peer {} for {:?}", self.peer_info, ban_reason);
         self.peer_status = PeerStatus::Banned(ban_reason);
@@ -314,6 +465,28 @@ impl PeerActor {
         ctx.stop();
     }
 
+    /// Drops the message, and bans the peer once it sent too many of them.
+    fn on_oversized_message(
+        &mut self,
+        ctx: &mut Context<PeerActor>,
+        kind: Option<PeerMessageKind>,
+        len: usize,
+    ) {
+        let kind = kind.map_or("unknown", <&str>::from);
+        metrics::PEER_MESSAGE_OVERSIZED_BY_TYPE_TOTAL.with_label_values(&[kind]).inc();
+        self.oversized_messages += 1;
+        warn!(
+            target: "network",
+            peer_id = ?self.other_peer_id(),
+            kind,
+            len,
+            count = self.oversized_messages,
+            "Dropped a message above the size limit of its kind");
+        if self.oversized_messages >= OVERSIZED_MESSAGES_BEFORE_BAN {
+            self.ban_peer(ctx, ReasonForBan::Abusive);
+        }
+    }
+
     /// `PeerId` of the current node.
     fn my_node_id(&self) -> &PeerId {
         &self.my_node_info.id
@@ -325,16 +498,42 @@ impl PeerActor {
     }
 
     fn receive_message(&mut self, ctx: &mut Context<PeerActor>, msg: PeerMessage) {
+        let class = MessageClass::of(&msg);
+        if let Some(msg) = self.demux.push(class, msg, Clock::instant()) {
+            self.dispatch_message(ctx, class, msg);
+        }
+    }
+
+    fn dispatch_message(
+        &mut self,
+        ctx: &mut Context<PeerActor>,
+        class: MessageClass,
+        msg: PeerMessage,
+    ) {
         if msg.is_view_client_message() {
-            self.receive_view_client_message(ctx, msg);
+            self.receive_view_client_message(ctx, class, msg);
         } else if msg.is_client_message() {
-            self.receive_client_message(ctx, msg);
+            self.receive_client_message(ctx, class, msg);
         } else {
             debug_assert!(false, "expected (view) client message, got: {}", msg.msg_variant());
+            self.message_handled(ctx, class);
         }
     }
 
-    fn receive_view_client_message(&self, ctx: &mut Context<PeerActor>, msg: PeerMessage) {
+    /// Hands over the next expensive message waiting, if the handled one was
+    /// expensive.
+    fn message_handled(&mut self, ctx: &mut Context<PeerActor>, class: MessageClass) {
+        if let Some(msg) = self.demux.handled(class, Clock::instant()) {
+            self.dispatch_message(ctx, MessageClass::Expensive, msg);
+        }
+    }
+
+    fn receive_view_client_message(
+        &mut self,
+        ctx: &mut Context<PeerActor>,
+        class: MessageClass,
+        msg: PeerMessage,
+    ) {
         let mut msg_hash = None;
         let view_client_message = match msg {
             PeerMessage::Routed(message) => {
@@ -360,6 +559,7 @@ impl PeerActor {
                     }
                     body => {
                         error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", body);
+                        self.message_handled(ctx, class);
                         return;
                     }
                 }
@@ -376,6 +576,7 @@ impl PeerActor {
             }
             peer_message => {
                 error!(target: "network", "Peer receive_view_client_message received unexpected type: {:?}", peer_message);
+                self.message_handled(ctx, class);
                 return;
             }
         };
@@ -383,7 +584,8 @@ impl PeerActor {
         self.view_client_addr
             .send(view_client_message)
             .into_actor(self)
-            .then(move |res, act, _ctx| {
+            .then(move |res, act, ctx| {
+                act.message_handled(ctx, class);
                 // Ban peer if client thinks received data is bad.
                 match res {
                     Ok(NetworkViewClientResponses::TxStatus(tx_result)) => {
@@ -446,11 +648,20 @@ impl PeerActor {
     }
 
     /// Process non handshake/peer related messages.
-    fn receive_client_message(&mut self, ctx: &mut Context<PeerActor>, msg: PeerMessage) {
+    fn receive_client_message(
+        &mut self,
+        ctx: &mut Context<PeerActor>,
+        class: MessageClass,
+        msg: PeerMessage,
+    ) {
         let _span = tracing::trace_span!(target: "network", "receive_client_message").entered();
         metrics::PEER_CLIENT_MESSAGE_RECEIVED_TOTAL.inc();
-        let peer_id =
-            if let Some(peer_id) = self.other_peer_id() { peer_id.clone() } else { return };
+        let peer_id = if let Some(peer_id) = self.other_peer_id() {
+            peer_id.clone()
+        } else {
+            self.message_handled(ctx, class);
+            return;
+        };
 
         metrics::PEER_CLIENT_MESSAGE_RECEIVED_BY_TYPE_TOTAL
             .with_label_values(&[msg.msg_variant()])
@@ -524,6 +735,7 @@ impl PeerActor {
                     | RoutedMessageBody::StateRequestPart(_, _, _)
                     | RoutedMessageBody::Unused => {
                         error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", routed_message);
+                        self.message_handled(ctx, class);
                         return;
                     }
                 }
@@ -548,8 +760,10 @@ impl PeerActor {
             | PeerMessage::BlockHeadersRequest(_)
             | PeerMessage::EpochSyncRequest(_)
             | PeerMessage::EpochSyncFinalizationRequest(_)
-            | PeerMessage::RoutingTableSyncV2(_) => {
+            | PeerMessage::RoutingTableSyncV2(_)
+            | PeerMessage::TrackedShards(_) => {
                 error!(target: "network", "Peer receive_client_message received unexpected type: {:?}", msg);
+                self.message_handled(ctx, class);
                 return;
             }
         };
@@ -558,6 +772,7 @@ impl PeerActor {
             .send(network_client_msg)
             .into_actor(self)
             .then(move |res, act, ctx| {
+                act.message_handled(ctx, class);
                 // Ban peer if client thinks received data is bad.
                 match res {
                     Ok(NetworkClientResponses::InvalidTx(err)) => {
@@ -584,6 +799,8 @@ impl PeerActor {
 
     /// Hook called on every valid message received from this peer from the network.
     fn on_receive_message(&mut self) {
+        self.liveness.on_message(Clock::instant());
+        self.update_liveness_failing();
         if let Some(peer_id) = self.other_peer_id().cloned() {
             if self.last_time_received_message_update.elapsed()
                 > UPDATE_INTERVAL_LAST_TIME_RECEIVED_MESSAGE
@@ -596,6 +813,46 @@ impl PeerActor {
         }
     }
 
+    /// Tells the peer manager when the peer starts or stops failing the
+    /// liveness checks, for routing to avoid it.
+    fn update_liveness_failing(&mut self) {
+        let failing = self.liveness.is_failing();
+        if failing == self.liveness_failing {
+            return;
+        }
+        self.liveness_failing = failing;
+        if let Some(peer_id) = self.other_peer_id().cloned() {
+            let _ = self.peer_manager_addr.do_send(PeerManagerMessageRequest::PeerRequest(
+                PeerRequest::Liveness(peer_id, failing),
+            ));
+        }
+    }
+
+    /// Pings the peer if it's been silent for long, and disconnects it once it
+    /// missed too many pings in a row.
+    fn check_liveness(&mut self, ctx: &mut Context<PeerActor>) {
+        match self.liveness.check(Clock::instant()) {
+            LivenessAction::Nothing => {}
+            LivenessAction::Ping => {
+                if let Some(peer_id) = self.other_peer_id().cloned() {
+                    let _ = self.peer_manager_addr.do_send(PeerManagerMessageRequest::PeerRequest(
+                        PeerRequest::Ping(peer_id),
+                    ));
+                }
+            }
+            LivenessAction::Disconnect => {
+                info!(target: "network", peer_info = %self.peer_info, "Disconnecting peer: liveness check failed, no message received after repeated pings");
+                metrics::PEER_LIVENESS_DISCONNECTS_TOTAL.inc();
+                ctx.stop();
+                return;
+            }
+        }
+        self.update_liveness_failing();
+        near_performance_metrics::actix::run_later(ctx, LIVENESS_CHECK_PERIOD, |act, ctx| {
+            act.check_liveness(ctx)
+        });
+    }
+
     /// Update stats when receiving msg
     fn update_stats_on_receiving_message(&mut self, msg_len: usize) {
         metrics::PEER_DATA_RECEIVED_BYTES.inc_by(msg_len as u64);
@@ -619,95 +876,15 @@ impl PeerActor {
         let r = self.txns_since_last_block.load(Ordering::Acquire);
         r > MAX_TRANSACTIONS_PER_BLOCK_MESSAGE
     }
-}
-
-impl Actor for PeerActor {
-    type Context = Context<PeerActor>;
-
-    fn started(&mut self, ctx: &mut Self::Context) {
-        metrics::PEER_CONNECTIONS_TOTAL.inc();
-        // Fetch genesis hash from the client.
-        self.fetch_client_chain_info(ctx);
-
-        debug!(target: "network", "{:?}: Peer {:?} {:?} started", self.my_node_info.id, self.peer_addr, self.peer_type);
-        // Set Handshake timeout for stopping actor if peer is not ready after given period of time.
-
-        near_performance_metrics::actix::run_later(ctx, self.handshake_timeout, move |act, ctx| {
-            if act.peer_status != PeerStatus::Ready {
-                info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
-                ctx.stop();
-            }
-        });
-
-        // If outbound peer, initiate handshake.
-        if self.peer_type == PeerType::Outbound {
-            self.send_handshake(ctx);
-        }
-    }
-
-    fn stopping(&mut self, _: &mut Self::Context) -> Running {
-        self.peer_counter.fetch_sub(1, Ordering::SeqCst);
-        metrics::PEER_CONNECTIONS_TOTAL.dec();
-        debug!(target: "network", "{:?}: Peer {} disconnected. {:?}", self.my_node_info.id, self.peer_info, self.peer_status);
-        if let Some(peer_info) = self.peer_info.as_ref() {
-            if let PeerStatus::Banned(ban_reason) = self.peer_status {
-                let _ = self.peer_manager_addr.do_send(PeerManagerMessageRequest::Ban(Ban {
-                    peer_id: peer_info.id.clone(),
-                    ban_reason,
-                }));
-            } else {
-                let _ = self.peer_manager_addr.do_send(PeerManagerMessageRequest::Unregister(
-                    Unregister {
-                        peer_id: peer_info.id.clone(),
-                        peer_type: self.peer_type,
-                        // If the PeerActor is no longer in the Connecting state this means
-                        // that the connection was consolidated at some point in the past.
-                        // Only if the connection was consolidated try to remove this peer from the
-                        // peer store. This avoids a situation in which both peers are connecting to
-                        // each other, and after resolving the tie, a peer tries to remove the other
-                        // peer from the active connection if it was added in the parallel connection.
-                        remove_from_peer_store: self.peer_status != PeerStatus::Connecting,
-                    },
-                ));
-            }
-        }
-        Running::Stop
-    }
-
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
-        Arbiter::current().stop();
-    }
-}
-
-impl WriteHandler<io::Error> for PeerActor {}
-
-impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
-    #[perf]
-    fn handle(&mut self, msg: Result<Vec<u8>, ReasonForBan>, ctx: &mut Self::Context) {
-        let _span = tracing::trace_span!(target: "network", "handle").entered();
-        let msg = match msg {
-            Ok(msg) => msg,
-            Err(ban_reason) => {
-                self.ban_peer(ctx, ban_reason);
-                return;
-            }
-        };
-        // TODO(#5155) We should change our code to track size of messages received from Peer
-        // as long as it travels to PeerManager, etc.
-
-        self.update_stats_on_receiving_message(msg.len());
-        let peer_msg = match self.parse_message(&msg) {
-            Ok(msg) => msg,
-            Err(err) => {
-                debug!(target: "network", "Received invalid data {:?} from {}: {}", logging::pretty_vec(&msg), self.peer_info, err);
-                return;
-            }
-        };
-
-        if self.should_we_drop_msg(&peer_msg) {
-            return;
-        }
 
+    /// Processes a message received from the peer, `msg_len` is the size of
+    /// its encoding.
+    fn process_message(
+        &mut self,
+        ctx: &mut Context<PeerActor>,
+        peer_msg: PeerMessage,
+        msg_len: usize,
+    ) {
         // Drop duplicated messages routed within DROP_DUPLICATED_MESSAGES_PERIOD ms
         if let PeerMessage::Routed(msg) = &peer_msg {
             let key = (msg.author.clone(), msg.target.clone(), msg.signature.clone());
@@ -737,7 +914,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
             metrics::PEER_MESSAGE_RECEIVED_BY_TYPE_TOTAL.with_label_values(&labels).inc();
             metrics::PEER_MESSAGE_RECEIVED_BY_TYPE_BYTES
                 .with_label_values(&labels)
-                .inc_by(msg.len() as u64);
+                .inc_by(msg_len as u64);
         }
 
         match (self.peer_status, peer_msg) {
@@ -783,73 +960,18 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
             (PeerStatus::Connecting, PeerMessage::Handshake(handshake)) => {
                 debug!(target: "network", "{:?}: Received handshake {:?}", self.my_node_info.id, handshake);
 
-                if PEER_MIN_ALLOWED_PROTOCOL_VERSION > handshake.protocol_version
-                    || handshake.protocol_version > PROTOCOL_VERSION
-                {
-                    debug!(
-                        target: "network",
-                        version = handshake.protocol_version,
-                        "Received connection from node with unsupported PROTOCOL_VERSION.");
-                    self.send_message_or_log(&PeerMessage::HandshakeFailure(
-                        self.my_node_info.clone(),
-                        HandshakeFailureReason::ProtocolVersionMismatch {
-                            version: PROTOCOL_VERSION,
-                            oldest_supported_version: PEER_MIN_ALLOWED_PROTOCOL_VERSION,
-                        },
-                    ));
-                    return;
-                    // Connection will be closed by a handshake timeout
-                }
-                let target_version = std::cmp::min(handshake.protocol_version, PROTOCOL_VERSION);
-                self.protocol_version = target_version;
-
-                if handshake.sender_chain_info.genesis_id != self.genesis_id {
-                    debug!(target: "network", "Received connection from node with different genesis.");
-                    self.send_message_or_log(&PeerMessage::HandshakeFailure(
-                        self.my_node_info.clone(),
-                        HandshakeFailureReason::GenesisMismatch(self.genesis_id.clone()),
-                    ));
-                    return;
-                    // Connection will be closed by a handshake timeout
-                }
-
-                if handshake.sender_peer_id == self.my_node_info.id {
-                    metrics::RECEIVED_INFO_ABOUT_ITSELF.inc();
-                    debug!(target: "network", "Received info about itself. Disconnecting this peer.");
-                    ctx.stop();
-                    return;
-                }
-
-                if handshake.target_peer_id != self.my_node_info.id {
-                    debug!(target: "network", "Received handshake from {:?} to {:?} but I am {:?}", handshake.sender_peer_id, handshake.target_peer_id, self.my_node_info.id);
-                    self.send_message_or_log(&PeerMessage::HandshakeFailure(
-                        self.my_node_info.clone(),
-                        HandshakeFailureReason::InvalidTarget,
-                    ));
-                    return;
-                    // Connection will be closed by a handshake timeout
-                }
-
-                // Verify signature of the new edge in handshake.
-                if !Edge::partial_verify(
-                    self.my_node_id(),
-                    &handshake.sender_peer_id,
-                    &handshake.partial_edge_info,
-                ) {
-                    warn!(target: "network", "Received invalid signature on handshake. Disconnecting peer {}", handshake.sender_peer_id);
-                    self.ban_peer(ctx, ReasonForBan::InvalidSignature);
-                    return;
-                }
-
-                // Check that received nonce on handshake match our proposed nonce.
-                if self.peer_type == PeerType::Outbound
-                    && handshake.partial_edge_info.nonce
-                        != self.partial_edge_info.as_ref().map(|edge_info| edge_info.nonce).unwrap()
-                {
-                    warn!(target: "network", "Received invalid nonce on handshake. Disconnecting peer {}", handshake.sender_peer_id);
-                    ctx.stop();
+                if let Err(reason) = self.check_handshake(&handshake) {
+                    self.refuse_handshake(ctx, &handshake, reason);
                     return;
                 }
+                self.protocol_version = std::cmp::min(handshake.protocol_version, PROTOCOL_VERSION);
+                self.compress = self.message_compression.is_some()
+                    && handshake.accepts_compression
+                    && checked_feature!(
+                        "protocol_feature_peer_message_compression",
+                        PeerMessageCompression,
+                        self.protocol_version
+                    );
 
                 let peer_info = PeerInfo {
                     id: handshake.sender_peer_id.clone(),
@@ -859,6 +981,13 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                     account_id: None,
                 };
                 self.chain_info = handshake.sender_chain_info.clone();
+                let clock_offset = handshake.sender_timestamp.map(|timestamp| {
+                    clock_skew::peer_clock_offset(
+                        timestamp,
+                        self.handshake_sent_at,
+                        to_timestamp(Clock::utc()),
+                    )
+                });
                 self.peer_manager_wrapper_addr
                     .send(ActixMessageWrapper::new_without_size(PeerManagerMessageRequest::RegisterPeer(RegisterPeer {
                         actor: ctx.address(),
@@ -869,6 +998,7 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                         other_edge_info: handshake.partial_edge_info.clone(),
                         peer_protocol_version: self.protocol_version,
                         throttle_controller: self.throttle_controller.clone(),
+                        clock_offset,
                     }), Some(self.throttle_controller.clone())))
                     .into_actor(self)
                     .then(move |res, act, ctx| {
@@ -876,6 +1006,8 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                             Ok(RegisterPeerResponse::Accept(edge_info)) => {
                                 act.peer_info = Some(peer_info).into();
                                 act.peer_status = PeerStatus::Ready;
+                                act.liveness.on_message(Clock::instant());
+                                act.check_liveness(ctx);
                                 // Respond to handshake if it's inbound and connection was consolidated.
                                 if act.peer_type == PeerType::Inbound {
                                     act.partial_edge_info = edge_info;
@@ -888,8 +1020,12 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                                 act.send_message_or_log(&PeerMessage::LastEdge(*edge));
                                 actix::fut::ready(())
                             }
-                            _ => {
-                                info!(target: "network", "{:?}: Peer with handshake {:?} wasn't consolidated, disconnecting.", act.my_node_id(), handshake);
+                            Ok(RegisterPeerResponse::Reject(reason)) => {
+                                act.refuse_handshake(ctx, &handshake, reason);
+                                actix::fut::ready(())
+                            }
+                            Err(err) => {
+                                info!(target: "network", "{:?}: Peer with handshake {:?} wasn't consolidated, disconnecting: {}", act.my_node_id(), handshake, err);
                                 ctx.stop();
                                 actix::fut::ready(())
                             }
@@ -936,6 +1072,11 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                 debug!(target: "network", "Disconnect signal. Me: {:?} Peer: {:?}", self.my_node_info.id, self.other_peer_id());
                 ctx.stop();
             }
+            (PeerStatus::Ready, PeerMessage::TrackedShards(tracked_shards)) => {
+                debug!(target: "network", "Peer {} tracks shards {:?}", self.peer_info, tracked_shards);
+                // The peer manager picks it up with the next stats.
+                self.chain_info.tracked_shards = tracked_shards;
+            }
             (PeerStatus::Ready, PeerMessage::Handshake(_)) => {
                 // Received handshake after already have seen handshake from this peer.
                 debug!(target: "network", "Duplicate handshake from {}", self.peer_info);
@@ -955,7 +1096,11 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
                 }).spawn(ctx);
             }
             (PeerStatus::Ready, PeerMessage::PeersResponse(peers)) => {
-                debug!(target: "network", "Received peers from {}: {} peers.", self.peer_info, peers.len());
+                debug!(
+                    target: "network",
+                    peer_id = ?self.other_peer_id(),
+                    peers_count = peers.len(),
+                    "Received peers");
                 let _ =
                     self.peer_manager_wrapper_addr.do_send(ActixMessageWrapper::new_without_size(
                         PeerManagerMessageRequest::PeersResponse(PeersResponse { peers }),
@@ -1063,6 +1208,149 @@ impl StreamHandler<Result<Vec<u8>, ReasonForBan>> for PeerActor {
     }
 }
 
+impl Actor for PeerActor {
+    type Context = Context<PeerActor>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        metrics::PEER_CONNECTIONS_TOTAL.inc();
+        // Fetch genesis hash from the client.
+        self.fetch_client_chain_info(ctx);
+
+        debug!(target: "network", "{:?}: Peer {:?} {:?} started", self.my_node_info.id, self.peer_addr, self.peer_type);
+        // Set Handshake timeout for stopping actor if peer is not ready after given period of time.
+
+        near_performance_metrics::actix::run_later(ctx, self.handshake_timeout, move |act, ctx| {
+            if act.peer_status != PeerStatus::Ready {
+                info!(target: "network", "Handshake timeout expired for {}", act.peer_info);
+                ctx.stop();
+            }
+        });
+
+        // If outbound peer, initiate handshake.
+        if self.peer_type == PeerType::Outbound {
+            self.send_handshake(ctx);
+        }
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        self.peer_counter.fetch_sub(1, Ordering::SeqCst);
+        metrics::PEER_CONNECTIONS_TOTAL.dec();
+        if let Some((recorder, connection)) = &self.message_recorder {
+            recorder.record(RecordedEvent::Closed { connection: *connection });
+        }
+        debug!(
+            target: "network",
+            me = %self.my_node_info.id,
+            peer_id = ?self.other_peer_id(),
+            peer_info = %self.peer_info,
+            peer_status = ?self.peer_status,
+            "Peer disconnected");
+        if let Some(peer_info) = self.peer_info.as_ref() {
+            if let PeerStatus::Banned(ban_reason) = self.peer_status {
+                let _ = self.peer_manager_addr.do_send(PeerManagerMessageRequest::Ban(Ban {
+                    peer_id: peer_info.id.clone(),
+                    ban_reason,
+                }));
+            } else {
+                let _ = self.peer_manager_addr.do_send(PeerManagerMessageRequest::Unregister(
+                    Unregister {
+                        peer_id: peer_info.id.clone(),
+                        peer_type: self.peer_type,
+                        // If the PeerActor is no longer in the Connecting state this means
+                        // that the connection was consolidated at some point in the past.
+                        // Only if the connection was consolidated try to remove this peer from the
+                        // peer store. This avoids a situation in which both peers are connecting to
+                        // each other, and after resolving the tie, a peer tries to remove the other
+                        // peer from the active connection if it was added in the parallel connection.
+                        remove_from_peer_store: self.peer_status != PeerStatus::Connecting,
+                    },
+                ));
+            }
+        }
+        Running::Stop
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        Arbiter::current().stop();
+    }
+}
+
+impl WriteHandler<io::Error> for PeerActor {}
+
+impl StreamHandler<Result<Vec<u8>, FrameError>> for PeerActor {
+    #[perf]
+    fn handle(&mut self, msg: Result<Vec<u8>, FrameError>, ctx: &mut Self::Context) {
+        let _span =
+            tracing::trace_span!(target: "network", "handle", peer_id = ?self.other_peer_id())
+                .entered();
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(FrameError::Ban(ban_reason)) => {
+                self.ban_peer(ctx, ban_reason);
+                return;
+            }
+            Err(FrameError::Oversized { kind, len }) => {
+                self.on_oversized_message(ctx, kind, len);
+                return;
+            }
+        };
+        // TODO(#5155) We should change our code to track size of messages received from Peer
+        // as long as it travels to PeerManager, etc.
+
+        self.update_stats_on_receiving_message(msg.len());
+        let peer_msg = match self.parse_message(&msg) {
+            Ok(msg) => msg,
+            Err(err) => {
+                debug!(
+                    target: "network",
+                    data = ?logging::pretty_vec(&msg),
+                    peer_id = ?self.other_peer_id(),
+                    %err,
+                    "Received invalid data");
+                return;
+            }
+        };
+
+        if let Some((recorder, connection)) = &self.message_recorder {
+            let peer_id = match self.peer_status {
+                PeerStatus::Connecting => None,
+                _ => self.other_peer_id(),
+            };
+            recorder.record_received(*connection, peer_id, &peer_msg);
+        }
+
+        if self.should_we_drop_msg(&peer_msg) {
+            return;
+        }
+
+        #[cfg(feature = "test_features")]
+        if self.peer_status == PeerStatus::Ready {
+            if let Some(peer_id) = self.other_peer_id().cloned() {
+                let fault = self.faults.apply(
+                    &self.genesis_id.chain_id,
+                    &peer_id,
+                    peer_msg.msg_variant(),
+                    msg.len(),
+                    Clock::instant(),
+                );
+                match fault {
+                    None => {}
+                    Some(Fault::Drop) => return,
+                    Some(Fault::Delay(delay)) => {
+                        let msg_len = msg.len();
+                        ctx.run_later(delay, move |act, ctx| {
+                            act.process_message(ctx, peer_msg, msg_len)
+                        });
+                        return;
+                    }
+                }
+            }
+        }
+
+        self.process_message(ctx, peer_msg, msg.len());
+    }
+}
+
 impl Handler<SendMessage> for PeerActor {
     type Result = ();
 
@@ -1130,6 +1418,18 @@ impl Handler<PeerManagerRequest> for PeerActor {
             PeerManagerRequest::UnregisterPeer => {
                 ctx.stop();
             }
+            PeerManagerRequest::UpdateTrackedShards(tracked_shards) => {
+                // Older peers can't decode the message.
+                if self.peer_status == PeerStatus::Ready
+                    && checked_feature!(
+                        "protocol_feature_tracked_shards_message",
+                        TrackedShardsMessage,
+                        self.protocol_version
+                    )
+                {
+                    self.send_message_or_log(&PeerMessage::TrackedShards(tracked_shards));
+                }
+            }
         }
     }
 }
@@ -0,0 +1,114 @@
+//! Compression of messages with the zstd library of `zstd-sys`.
+//!
+//! Messages are decompressed with the streaming API into a buffer which grows
+//! with the output, so that the memory used is bounded by what the frame
+//! actually decompresses to, and at most one byte past the limit.
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use zstd_sys::{ZSTD_inBuffer, ZSTD_outBuffer};
+
+/// Output to make room for at a time while decompressing, the size of a zstd
+/// block.
+const DECOMPRESS_CHUNK_BYTES: usize = 128 * 1024;
+
+/// The error of the return value of a zstd function, if it's one.
+fn check(code: usize) -> Result<usize, String> {
+    // SAFETY: both functions take any value, the name is a static C string.
+    unsafe {
+        if zstd_sys::ZSTD_isError(code) == 0 {
+            return Ok(code);
+        }
+        Err(CStr::from_ptr(zstd_sys::ZSTD_getErrorName(code)).to_string_lossy().into_owned())
+    }
+}
+
+/// Compresses the data into a single zstd frame.
+pub(crate) fn compress(data: &[u8], level: i32) -> Result<Vec<u8>, String> {
+    // SAFETY: the bound of the compressed size is allocated, zstd writes at
+    // most that many bytes and returns how many it wrote.
+    unsafe {
+        let capacity = zstd_sys::ZSTD_compressBound(data.len());
+        let mut compressed = Vec::with_capacity(capacity);
+        let len = check(zstd_sys::ZSTD_compress(
+            compressed.as_mut_ptr() as *mut c_void,
+            capacity,
+            data.as_ptr() as *const c_void,
+            data.len(),
+            level,
+        ))?;
+        compressed.set_len(len);
+        Ok(compressed)
+    }
+}
+
+/// Owned decompression context.
+struct DStream(*mut zstd_sys::ZSTD_DStream);
+
+impl Drop for DStream {
+    fn drop(&mut self) {
+        // SAFETY: the context was created by `ZSTD_createDStream`.
+        unsafe { zstd_sys::ZSTD_freeDStream(self.0) };
+    }
+}
+
+/// Decompresses a single zstd frame of at most `max_size` bytes, fails if
+/// the frame is larger, truncated or followed by other data.
+pub(crate) fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    // SAFETY: `ZSTD_createDStream` returns a new context or null.
+    let stream = DStream(unsafe { zstd_sys::ZSTD_createDStream() });
+    if stream.0.is_null() {
+        return Err("failed to allocate a zstd context".to_string());
+    }
+    // SAFETY: the context is valid.
+    check(unsafe { zstd_sys::ZSTD_initDStream(stream.0) })?;
+    let mut input = ZSTD_inBuffer { src: data.as_ptr() as *const c_void, size: data.len(), pos: 0 };
+    let mut message = Vec::<u8>::new();
+    loop {
+        // Up to a byte past the limit, to tell a message at the limit from a
+        // larger one.
+        let capacity = std::cmp::min(message.len() + DECOMPRESS_CHUNK_BYTES, max_size + 1);
+        message.reserve_exact(capacity - message.len());
+        let mut output = ZSTD_outBuffer {
+            dst: message.as_mut_ptr() as *mut c_void,
+            size: capacity,
+            pos: message.len(),
+        };
+        // SAFETY: the input is the data, the output is the allocated buffer
+        // past the bytes written so far, zstd advances the positions by the
+        // bytes it read and wrote.
+        let remaining =
+            check(unsafe { zstd_sys::ZSTD_decompressStream(stream.0, &mut output, &mut input) })?;
+        // SAFETY: zstd wrote the bytes up to the output position.
+        unsafe { message.set_len(output.pos) };
+        if message.len() > max_size {
+            return Err(format!("message is larger than {} bytes", max_size));
+        }
+        if remaining == 0 {
+            // The frame is complete.
+            if input.pos < input.size {
+                return Err("data after the zstd frame".to_string());
+            }
+            return Ok(message);
+        }
+        if input.pos == input.size && output.pos < output.size {
+            return Err("truncated zstd frame".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        let data = vec![7; 1_000_000];
+        let compressed = compress(&data, 1).unwrap();
+        assert!(compressed.len() < 1000);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        assert!(decompress(&compressed, data.len() - 1).is_err());
+        assert!(decompress(&compressed[..compressed.len() - 1], data.len()).is_err());
+        assert!(decompress(&[&compressed[..], &[0]].concat(), data.len()).is_err());
+        assert!(decompress(b"not zstd", data.len()).is_err());
+    }
+}
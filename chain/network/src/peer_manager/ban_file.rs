@@ -0,0 +1,175 @@
+//! Peers banned by one node written to a file to be banned by other nodes too,
+//! see `neard network export-bans` and `neard network import-bans`.
+//!
+//! A ban lasts `ban_window` from the time the peer got banned.  The nodes
+//! importing a ban keep it until the time the exporting node would unban the
+//! peer, whatever their own `ban_window`, unless they've banned the peer for
+//! longer already.
+
+use crate::peer_manager::peer_store::PeerStore;
+use borsh::BorshDeserialize;
+use chrono::{DateTime, Utc};
+use near_network_primitives::types::{KnownPeerState, KnownPeerStatus, PeerInfo, ReasonForBan};
+use near_primitives::network::PeerId;
+use near_primitives::time::Clock;
+use near_primitives::types::AccountId;
+use near_primitives::utils::{from_timestamp, to_timestamp};
+use near_store::{DBCol, Store, StoreRead};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Version of the format of [`BanFile`].
+pub const BAN_FILE_VERSION: u32 = 1;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct BanFile {
+    /// Files with a version other than [`BAN_FILE_VERSION`] are rejected.
+    pub version: u32,
+    pub bans: Vec<BannedPeer>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct BannedPeer {
+    pub peer_id: PeerId,
+    pub addr: Option<SocketAddr>,
+    pub account_id: Option<AccountId>,
+    /// Name of the [`ReasonForBan`] variant.
+    pub reason: String,
+    pub banned_at: DateTime<Utc>,
+    /// Time the exporting node unbans the peer at.
+    pub expires_at: DateTime<Utc>,
+}
+
+impl BanFile {
+    /// Collects the peers currently banned in the peer store of the database.
+    pub fn from_store(store: &impl StoreRead, ban_window: Duration) -> anyhow::Result<Self> {
+        let ban_window = chrono::Duration::from_std(ban_window)?;
+        let now = Clock::utc();
+        let mut bans = vec![];
+        for (key, value) in store.iter(DBCol::Peers) {
+            let peer_id = PeerId::try_from_slice(&key)?;
+            let peer_state = KnownPeerState::try_from_slice(&value)?;
+            if let KnownPeerStatus::Banned(reason, banned_at) = peer_state.status {
+                let banned_at = from_timestamp(banned_at);
+                let expires_at = banned_at + ban_window;
+                if expires_at <= now {
+                    continue;
+                }
+                bans.push(BannedPeer {
+                    peer_id,
+                    addr: peer_state.peer_info.addr,
+                    account_id: peer_state.peer_info.account_id,
+                    reason: reason.as_ref().to_string(),
+                    banned_at,
+                    expires_at,
+                });
+            }
+        }
+        bans.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        Ok(Self { version: BAN_FILE_VERSION, bans })
+    }
+
+    /// Bans the peers in the peer store of the database, keeping longer bans
+    /// already there.  Bans which have expired are skipped.  Returns the
+    /// number of peers whose ban changed.
+    ///
+    /// The node must not be running, it reads the peer store only on start.
+    pub fn import_to_store(&self, store: Store, ban_window: Duration) -> anyhow::Result<usize> {
+        anyhow::ensure!(
+            self.version == BAN_FILE_VERSION,
+            "unsupported ban file version {}, expected {}",
+            self.version,
+            BAN_FILE_VERSION
+        );
+        let ban_window = chrono::Duration::from_std(ban_window)?;
+        let now = Clock::utc();
+        let mut bans = vec![];
+        for ban in &self.bans {
+            let reason = ReasonForBan::from_str(&ban.reason).map_err(|_| {
+                anyhow::anyhow!("unknown ban reason {:?} of peer {}", ban.reason, ban.peer_id)
+            })?;
+            if ban.expires_at <= now {
+                continue;
+            }
+            let peer_info = PeerInfo {
+                id: ban.peer_id.clone(),
+                addr: ban.addr,
+                account_id: ban.account_id.clone(),
+            };
+            // Shift the start of the ban so that this node unbans the peer at
+            // `expires_at` too.
+            bans.push((peer_info, reason, to_timestamp(ban.expires_at - ban_window)));
+        }
+        let mut peer_store = PeerStore::new(store, &[], Default::default())
+            .map_err(|err| anyhow::anyhow!("failed to load the peer store: {}", err))?;
+        peer_store.import_bans(bans).map_err(|err| anyhow::anyhow!("failed to save bans: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use near_crypto::{KeyType, SecretKey};
+    use near_store::test_utils::create_test_store;
+
+    const BAN_WINDOW: Duration = Duration::from_secs(3600);
+
+    fn peer_info(seed: &str, port: u16) -> PeerInfo {
+        PeerInfo {
+            id: PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key()),
+            addr: Some(SocketAddr::from(([127, 0, 0, 1], port))),
+            account_id: None,
+        }
+    }
+
+    fn banned(store: &Store, peer_info: &PeerInfo) -> Option<(ReasonForBan, u64)> {
+        let state: KnownPeerState =
+            store.get_ser(DBCol::Peers, &peer_info.id.try_to_vec().unwrap()).unwrap()?;
+        match state.status {
+            KnownPeerStatus::Banned(reason, banned_at) => Some((reason, banned_at)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_export_import_bans() {
+        let (alice, bob, carol) =
+            (peer_info("alice", 1), peer_info("bob", 2), peer_info("carol", 3));
+        let now = to_timestamp(Clock::utc());
+        let hour = BAN_WINDOW.as_nanos() as u64;
+
+        let source = create_test_store();
+        let mut peer_store = PeerStore::new(source.clone(), &[], Default::default()).unwrap();
+        peer_store
+            .import_bans(vec![
+                (alice.clone(), ReasonForBan::Abusive, now - hour / 2),
+                (bob.clone(), ReasonForBan::BadBlock, now - hour / 2),
+                // Already expired.
+                (carol.clone(), ReasonForBan::Abusive, now - 2 * hour),
+            ])
+            .unwrap();
+        let ban_file = BanFile::from_store(&source, BAN_WINDOW).unwrap();
+        let banned_ids: Vec<_> = ban_file.bans.iter().map(|ban| ban.peer_id.clone()).collect();
+        let mut expected = vec![alice.id.clone(), bob.id.clone()];
+        expected.sort();
+        assert_eq!(banned_ids, expected);
+        let json = serde_json::to_string(&ban_file).unwrap();
+        assert_eq!(serde_json::from_str::<BanFile>(&json).unwrap(), ban_file);
+
+        // Bob is banned on the target for longer than in the file already.
+        let target = create_test_store();
+        let mut peer_store = PeerStore::new(target.clone(), &[], Default::default()).unwrap();
+        peer_store.import_bans(vec![(bob.clone(), ReasonForBan::InvalidEdge, now)]).unwrap();
+        drop(peer_store);
+        // The target unbans peers after two hours rather than one.
+        assert_eq!(ban_file.import_to_store(target.clone(), 2 * BAN_WINDOW).unwrap(), 1);
+        assert_eq!(banned(&target, &alice), Some((ReasonForBan::Abusive, now - hour / 2 - hour)));
+        assert_eq!(banned(&target, &bob), Some((ReasonForBan::InvalidEdge, now)));
+        assert_eq!(banned(&target, &carol), None);
+
+        let future = BanFile { version: BAN_FILE_VERSION + 1, bans: vec![] };
+        assert!(future.import_to_store(target, BAN_WINDOW).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Blacklist entries on trial, from `network.blacklist_audit`: they refuse no
+//! one, but the node counts and lists the connections they would refuse, to
+//! tell how many good peers a broad entry would block before it's enforced.
+//! `neard network audit-blacklist` checks entries against the peers of the
+//! node without a config change.
+//!
+//! The entries are parsed and matched by the [`PeerBlacklist`] the
+//! `blacklist` goes through, with the same whitelisted and pinned peers
+//! exempt, so an entry moved from one list to the other blocks the very peers
+//! it was audited for.
+
+use crate::peer_manager::peer_store::PeerBlacklist;
+use crate::stats::metrics;
+use borsh::BorshDeserialize;
+use near_network_primitives::types::{Blacklist, KnownPeerState, PeerInfo};
+use near_primitives::network::PeerId;
+use near_store::{DBCol, StoreRead};
+use std::net::SocketAddr;
+use tracing::debug;
+
+/// Peer an audited entry matches.
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AuditedPeer {
+    pub peer_id: PeerId,
+    pub addr: SocketAddr,
+    /// The entry normalized, see [`near_network_primitives::types::BlacklistMatch`].
+    pub entry: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct BlacklistAudit(PeerBlacklist);
+
+impl BlacklistAudit {
+    /// The entries, with the peers the blacklist doesn't apply to at their
+    /// configured addresses.
+    pub fn new(entries: &[String], exempt: &[PeerInfo]) -> Self {
+        Self(PeerBlacklist::new(Blacklist::from_iter(entries), exempt))
+    }
+
+    /// The peer with the first entry matching it, if any.
+    pub fn check(&self, peer_info: &PeerInfo) -> Option<AuditedPeer> {
+        let blacklist_match = self.0.match_of(peer_info)?;
+        Some(AuditedPeer {
+            peer_id: peer_info.id.clone(),
+            addr: peer_info.addr?,
+            entry: blacklist_match.entry.to_string(),
+        })
+    }
+
+    /// The peers an entry matches.
+    pub fn audit<'a>(&self, peers: impl IntoIterator<Item = &'a PeerInfo>) -> Vec<AuditedPeer> {
+        peers.into_iter().filter_map(|peer_info| self.check(peer_info)).collect()
+    }
+
+    /// Counts the connection with the peer if an entry matches it.
+    pub(crate) fn record_connection(&self, peer_info: &PeerInfo) {
+        if let Some(audited) = self.check(peer_info) {
+            metrics::PEER_BLACKLIST_AUDIT_MATCHES.with_label_values(&[&audited.entry]).inc();
+            debug!(target: "network", peer_id = %audited.peer_id, addr = %audited.addr, entry = %audited.entry, "Accepting a peer an audited blacklist entry would refuse");
+        }
+    }
+}
+
+/// The peers of the peer store in the database, read without loading the
+/// peer store, which would write to it.
+pub fn stored_peers(store: &impl StoreRead) -> anyhow::Result<Vec<PeerInfo>> {
+    store
+        .iter(DBCol::Peers)
+        .map(|(_, value)| Ok(KnownPeerState::try_from_slice(&value)?.peer_info))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+
+    fn peer_info(seed: &str, addr: &str) -> PeerInfo {
+        let peer_id = PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key());
+        PeerInfo::new(peer_id, addr.parse().unwrap())
+    }
+
+    #[test]
+    fn test_audit() {
+        let exempt = peer_info("exempt", "192.0.2.4:24567");
+        let entries = ["192.0.2.4".to_string(), "[::ffff:192.0.2.5]:42".to_string()];
+        let audit = BlacklistAudit::new(&entries, &[exempt.clone()]);
+        let peers = [
+            exempt,
+            peer_info("a", "192.0.2.4:1"),
+            peer_info("b", "192.0.2.5:42"),
+            peer_info("c", "192.0.2.5:43"),
+        ];
+        let audited = audit.audit(&peers);
+        assert_eq!(
+            audited.iter().map(|peer| (peer.addr.port(), peer.entry.as_str())).collect::<Vec<_>>(),
+            [(1, "192.0.2.4"), (42, "192.0.2.5:42")]
+        );
+
+        // The same entries on the blacklist block the same peers.
+        let blacklist = PeerBlacklist::new(Blacklist::from_iter(&entries), &[peers[0].clone()]);
+        for peer_info in &peers {
+            assert_eq!(blacklist.contains(peer_info), audit.check(peer_info).is_some());
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Estimation of the skew of the local clock against the clocks of the peers.
+//!
+//! Every peer sends its time in the handshake.  The offset of a peer's clock
+//! is the difference between that time and the local time at which the peer
+//! sent the handshake.  On outbound connections the peer responds to our
+//! handshake, so that time is taken as the midpoint between sending ours and
+//! receiving the response, which cancels out the network delay.  On inbound
+//! connections the delay is unknown and is included in the offset.
+//!
+//! The skew of the local clock is the median of the offsets of the connected
+//! peers, so no single peer can move the estimate and a minority of peers
+//! with bad clocks can't move it outside of the offsets of the honest ones.
+
+/// Minimum number of peers with a known offset needed for an estimate.
+pub(crate) const MIN_PEERS: usize = 3;
+
+/// Returns how far the clock of the peer is ahead of the local clock, in
+/// nanoseconds.  `peer_timestamp` is the time from the peer's handshake,
+/// `sent` the local time our handshake was sent if it was sent before the
+/// peer's one, and `received` the local time the peer's handshake arrived.
+pub(crate) fn peer_clock_offset(peer_timestamp: u64, sent: Option<u64>, received: u64) -> i64 {
+    let local = match sent {
+        Some(sent) if sent <= received => sent + (received - sent) / 2,
+        _ => received,
+    };
+    peer_timestamp as i64 - local as i64
+}
+
+/// Returns how far the local clock is ahead of the clocks of the peers, in
+/// nanoseconds, or `None` if fewer than [`MIN_PEERS`] offsets are known.
+pub(crate) fn estimate_clock_skew(offsets: impl Iterator<Item = i64>) -> Option<i64> {
+    let mut offsets: Vec<i64> = offsets.collect();
+    if offsets.len() < MIN_PEERS {
+        return None;
+    }
+    offsets.sort_unstable();
+    let mid = offsets.len() / 2;
+    let median =
+        if offsets.len() % 2 == 0 { (offsets[mid - 1] + offsets[mid]) / 2 } else { offsets[mid] };
+    Some(-median)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i64 = 1_000_000_000;
+
+    #[test]
+    fn test_peer_clock_offset() {
+        // Outbound: the peer responded halfway between 100 and 300.
+        assert_eq!(peer_clock_offset(250, Some(100), 300), 50);
+        // Inbound: the peer's handshake came first.
+        assert_eq!(peer_clock_offset(250, None, 300), -50);
+    }
+
+    #[test]
+    fn test_estimate_clock_skew() {
+        assert_eq!(estimate_clock_skew([SECOND, SECOND].into_iter()), None);
+        // The local clock is 2s behind the honest peers, a single peer with a
+        // wildly wrong clock doesn't matter.
+        let offsets = [2 * SECOND, 2 * SECOND + 1, 2 * SECOND - 1, -3600 * SECOND];
+        assert_eq!(estimate_clock_skew(offsets.into_iter()), Some(-2 * SECOND + 1));
+        let offsets = [2 * SECOND, 2 * SECOND, 2 * SECOND, -3600 * SECOND, 3600 * SECOND];
+        assert_eq!(estimate_clock_skew(offsets.into_iter()), Some(-2 * SECOND));
+    }
+}
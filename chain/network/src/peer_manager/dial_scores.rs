@@ -0,0 +1,202 @@
+//! Reachability of the addresses the node dials, so that it stops wasting its
+//! outbound connection attempts on the addresses which never answer, such as
+//! the private addresses advertised by peers behind a NAT or long dead hosts.
+//!
+//! Every failed dial of an address doubles the time before it's dialed again,
+//! from [`INITIAL_DIAL_BACKOFF`] up to [`MAX_DIAL_BACKOFF`], so a dead address
+//! keeps being retried at a low rate, and a successful dial resets it.  Among
+//! the addresses which can be dialed, the ones dialed successfully recently
+//! come first, then the ones never dialed, and last the ones which failed and
+//! the private ones, which are still picked with
+//! [`RETRY_DEPRIORITIZED_PROBABILITY`].  The scores are kept in memory only.
+
+use near_network_primitives::types::NormalizedAddr;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Time before dialing an address again after its first failed dial.
+pub(crate) const INITIAL_DIAL_BACKOFF: Duration = Duration::from_secs(30);
+/// Longest time between dials of an address which keeps failing.
+pub(crate) const MAX_DIAL_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+/// How long after a successful dial an address is preferred.
+pub(crate) const RECENT_SUCCESS: Duration = Duration::from_secs(24 * 60 * 60);
+/// Probability to dial a deprioritized address while others can be dialed.
+pub(crate) const RETRY_DEPRIORITIZED_PROBABILITY: f64 = 0.1;
+
+#[derive(Debug, Default, Clone)]
+struct AddrScore {
+    consecutive_failures: u32,
+    last_failure: Option<Instant>,
+    last_success: Option<Instant>,
+}
+
+impl AddrScore {
+    fn backoff(&self) -> Duration {
+        match self.consecutive_failures {
+            0 => Duration::ZERO,
+            failures => INITIAL_DIAL_BACKOFF
+                .saturating_mul(1u32 << (failures - 1).min(20))
+                .min(MAX_DIAL_BACKOFF),
+        }
+    }
+}
+
+/// Order in which the addresses are dialed, the first ones first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum DialPriority {
+    RecentSuccess,
+    Untried,
+    /// The last dial failed, or the address is private.
+    Deprioritized,
+}
+
+#[derive(Debug)]
+pub(crate) struct DialScores {
+    scores: HashMap<NormalizedAddr, AddrScore>,
+    /// Whether the node runs on a private network, where the private
+    /// addresses are the ones to dial.
+    private_network: bool,
+}
+
+impl DialScores {
+    pub fn new(private_network: bool) -> Self {
+        Self { scores: HashMap::new(), private_network }
+    }
+
+    pub fn set_private_network(&mut self, private_network: bool) {
+        self.private_network = private_network;
+    }
+
+    pub fn dial_failed(&mut self, addr: NormalizedAddr, now: Instant) {
+        let score = self.scores.entry(addr).or_default();
+        score.consecutive_failures = score.consecutive_failures.saturating_add(1);
+        score.last_failure = Some(now);
+    }
+
+    pub fn dial_succeeded(&mut self, addr: NormalizedAddr, now: Instant) {
+        let score = self.scores.entry(addr).or_default();
+        score.consecutive_failures = 0;
+        score.last_success = Some(now);
+    }
+
+    pub fn forget(&mut self, addr: &NormalizedAddr) {
+        self.scores.remove(addr);
+    }
+
+    /// The priority of the address, `None` while it's backing off.
+    pub fn priority(&self, addr: &NormalizedAddr, now: Instant) -> Option<DialPriority> {
+        let score = match self.scores.get(addr) {
+            Some(score) => score,
+            None if !self.private_network && is_private(addr.ip()) => {
+                return Some(DialPriority::Deprioritized)
+            }
+            None => return Some(DialPriority::Untried),
+        };
+        if score.consecutive_failures > 0 {
+            let retry_at = score.last_failure.map_or(now, |failure| failure + score.backoff());
+            return (now >= retry_at).then(|| DialPriority::Deprioritized);
+        }
+        if !self.private_network && is_private(addr.ip()) {
+            return Some(DialPriority::Deprioritized);
+        }
+        match score.last_success {
+            Some(success) if now.saturating_duration_since(success) <= RECENT_SUCCESS => {
+                Some(DialPriority::RecentSuccess)
+            }
+            _ => Some(DialPriority::Untried),
+        }
+    }
+}
+
+/// Whether the address can't be reached from the internet: the private
+/// (RFC 1918), shared (RFC 6598), link-local, loopback and unspecified IPv4
+/// addresses and the unique local, link-local, loopback and unspecified IPv6
+/// ones.
+pub(crate) fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_link_local()
+                || ip.is_loopback()
+                || ip.is_unspecified()
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(addr: &str) -> NormalizedAddr {
+        addr.parse::<std::net::SocketAddr>().unwrap().into()
+    }
+
+    #[test]
+    fn test_is_private() {
+        for ip in
+            ["10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.0.1", "127.0.0.1", "100.64.0.1"]
+        {
+            assert!(is_private(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["fd00::1", "fe80::1", "::1", "::ffff:192.168.1.1"] {
+            assert!(is_private(NormalizedAddr::from_ip(ip.parse().unwrap()).ip()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "100.128.0.1", "172.32.0.1", "2001:db8::1"] {
+            assert!(!is_private(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut scores = DialScores::new(false);
+        let public = addr("1.2.3.4:24567");
+        let start = Instant::now();
+        assert_eq!(scores.priority(&public, start), Some(DialPriority::Untried));
+
+        scores.dial_failed(public.clone(), start);
+        assert_eq!(scores.priority(&public, start + INITIAL_DIAL_BACKOFF / 2), None);
+        assert_eq!(
+            scores.priority(&public, start + INITIAL_DIAL_BACKOFF),
+            Some(DialPriority::Deprioritized)
+        );
+        let mut now = start;
+        for _ in 0..30 {
+            scores.dial_failed(public.clone(), now);
+            now += MAX_DIAL_BACKOFF;
+        }
+        assert_eq!(scores.priority(&public, now - Duration::from_secs(1)), None);
+        assert_eq!(scores.priority(&public, now), Some(DialPriority::Deprioritized));
+
+        scores.dial_succeeded(public.clone(), now);
+        assert_eq!(scores.priority(&public, now), Some(DialPriority::RecentSuccess));
+        assert_eq!(
+            scores.priority(&public, now + RECENT_SUCCESS + Duration::from_secs(1)),
+            Some(DialPriority::Untried)
+        );
+    }
+
+    #[test]
+    fn test_private_addresses() {
+        let private = addr("192.168.1.1:24567");
+        let mut scores = DialScores::new(false);
+        let now = Instant::now();
+        assert_eq!(scores.priority(&private, now), Some(DialPriority::Deprioritized));
+        scores.dial_succeeded(private.clone(), now);
+        assert_eq!(scores.priority(&private, now), Some(DialPriority::Deprioritized));
+
+        scores.set_private_network(true);
+        assert_eq!(scores.priority(&private, now), Some(DialPriority::RecentSuccess));
+        scores.forget(&private);
+        assert_eq!(scores.priority(&private, now), Some(DialPriority::Untried));
+    }
+}
@@ -0,0 +1,333 @@
+//! Discovery of peers through DNS seeds.
+//!
+//! A DNS seed is a domain whose TXT records list peers of the network as
+//! `PeerId@host:port` entries, e.g.
+//! `ed25519:86EtEy7epneKyrcJwSWP7zsisTkfDRH5CFVszt4qiQYw@seed1.example.org:24567`,
+//! kept up to date by whoever runs the seed.  The seeds in `network.dns_seeds`
+//! are resolved at startup and then periodically with a jittered interval, so
+//! that nodes started together don't query them together, and the peers found
+//! are added to the peer store as candidates, like the peers learned from
+//! other peers.  Resolution runs off the peer manager thread and failures are
+//! only logged.
+
+use near_network_primitives::types::PeerInfo;
+use near_primitives::network::PeerId;
+use rand::Rng;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Maximum number of peers taken from a single seed.
+pub(crate) const MAX_PEERS_PER_SEED: usize = 32;
+/// Average interval between resolutions of the seeds.
+const RESOLVE_PERIOD: Duration = Duration::from_secs(30 * 60);
+/// Time to wait for the name server to respond.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const TYPE_TXT: u16 = 16;
+const CLASS_IN: u16 = 1;
+
+pub(crate) trait DnsResolver: Send + Sync {
+    /// Returns the TXT records of the domain.
+    fn txt(&self, domain: &str) -> io::Result<Vec<String>>;
+    /// Returns the addresses `host:port` resolves to.
+    fn addrs(&self, host_port: &str) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolver querying the first name server in `/etc/resolv.conf` for TXT
+/// records and the system resolver for addresses.
+pub(crate) struct SystemResolver;
+
+impl DnsResolver for SystemResolver {
+    fn txt(&self, domain: &str) -> io::Result<Vec<String>> {
+        let resolv_conf = std::fs::read_to_string(RESOLV_CONF)?;
+        let server = resolv_conf
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("nameserver"))
+            .find_map(|addr| addr.trim().parse().ok())
+            .map(|ip| SocketAddr::new(ip, 53))
+            .ok_or_else(|| invalid_data(format!("no name server in {}", RESOLV_CONF)))?;
+        query_txt(server, domain)
+    }
+
+    fn addrs(&self, host_port: &str) -> io::Result<Vec<SocketAddr>> {
+        Ok(host_port.to_socket_addrs()?.collect())
+    }
+}
+
+/// Peer found in a DNS seed.
+#[derive(Debug, PartialEq)]
+pub(crate) struct SeedPeer {
+    pub peer_info: PeerInfo,
+    pub seed: String,
+}
+
+/// Resolves the seeds and returns the valid peers they list, at most
+/// [`MAX_PEERS_PER_SEED`] from every seed.
+pub(crate) fn resolve_seeds(resolver: &dyn DnsResolver, seeds: &[String]) -> Vec<SeedPeer> {
+    let mut peers = vec![];
+    for seed in seeds {
+        let records = match resolver.txt(seed) {
+            Ok(records) => records,
+            Err(err) => {
+                warn!(target: "network", seed = seed.as_str(), %err, "Failed to resolve DNS seed");
+                continue;
+            }
+        };
+        // A record may list several entries separated by commas or spaces.
+        let entries = records
+            .iter()
+            .flat_map(|record| record.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|entry| !entry.is_empty());
+        let mut found = 0;
+        for entry in entries {
+            if found == MAX_PEERS_PER_SEED {
+                warn!(target: "network", seed = seed.as_str(), "DNS seed lists more than {} peers, ignoring the rest", MAX_PEERS_PER_SEED);
+                break;
+            }
+            match parse_entry(resolver, entry) {
+                Ok(peer_info) => {
+                    peers.push(SeedPeer { peer_info, seed: seed.clone() });
+                    found += 1;
+                }
+                Err(err) => {
+                    warn!(target: "network", seed = seed.as_str(), entry, %err, "Invalid DNS seed entry")
+                }
+            }
+        }
+        info!(target: "network", seed = seed.as_str(), found, "Resolved DNS seed");
+    }
+    peers
+}
+
+/// Parses a `PeerId@host:port` entry, resolving the host.
+fn parse_entry(resolver: &dyn DnsResolver, entry: &str) -> Result<PeerInfo, String> {
+    let (peer_id, host_port) =
+        entry.split_once('@').ok_or_else(|| "expected PeerId@host:port".to_string())?;
+    let peer_id = PeerId::new(peer_id.parse().map_err(|err| format!("invalid peer id: {}", err))?);
+    let addr = resolver
+        .addrs(host_port)
+        .map_err(|err| format!("can't resolve {}: {}", host_port, err))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("{} has no address", host_port))?;
+    Ok(PeerInfo { id: peer_id, addr: Some(addr), account_id: None })
+}
+
+/// Returns the time after which the seeds are resolved again.
+pub(crate) fn next_resolve_delay(rng: &mut impl Rng) -> Duration {
+    RESOLVE_PERIOD.mul_f64(rng.gen_range(0.75, 1.25))
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Asks the name server for the TXT records of the domain.
+fn query_txt(server: SocketAddr, domain: &str) -> io::Result<Vec<String>> {
+    let socket = UdpSocket::bind(if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.connect(server)?;
+    let id = rand::random();
+    socket.send(&encode_txt_query(id, domain)?)?;
+    let mut buf = [0; 4096];
+    let len = socket.recv(&mut buf)?;
+    parse_txt_response(id, &buf[..len])
+}
+
+fn encode_txt_query(id: u16, domain: &str) -> io::Result<Vec<u8>> {
+    let mut msg = vec![];
+    msg.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question.
+    msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in domain.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid_data(format!("invalid domain {}", domain)));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&TYPE_TXT.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+fn parse_txt_response(id: u16, msg: &[u8]) -> io::Result<Vec<String>> {
+    let mut reader = Reader { msg, pos: 0 };
+    let header = reader.take(12)?;
+    if u16::from_be_bytes([header[0], header[1]]) != id {
+        return Err(invalid_data("response to a different query".to_string()));
+    }
+    let rcode = header[3] & 0x0f;
+    if rcode != 0 {
+        return Err(invalid_data(format!("name server responded with code {}", rcode)));
+    }
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    let answers = u16::from_be_bytes([header[6], header[7]]);
+    for _ in 0..questions {
+        reader.skip_name()?;
+        reader.take(4)?;
+    }
+    let mut records = vec![];
+    for _ in 0..answers {
+        reader.skip_name()?;
+        let fixed = reader.take(10)?;
+        let rtype = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let rdlength = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let rdata = reader.take(rdlength)?;
+        // The answers may include the CNAMEs the domain points to.
+        if rtype != TYPE_TXT {
+            continue;
+        }
+        // The record is split into strings of at most 255 bytes.
+        let mut record = vec![];
+        let mut rdata = Reader { msg: rdata, pos: 0 };
+        while rdata.pos < rdata.msg.len() {
+            let len = rdata.take(1)?[0] as usize;
+            record.extend_from_slice(rdata.take(len)?);
+        }
+        records.push(String::from_utf8_lossy(&record).into_owned());
+    }
+    Ok(records)
+}
+
+struct Reader<'a> {
+    msg: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .msg
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid_data("truncated response".to_string()))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip_name(&mut self) -> io::Result<()> {
+        loop {
+            let len = self.take(1)?[0];
+            if len == 0 {
+                return Ok(());
+            }
+            // A pointer to a name elsewhere in the message ends the name.
+            if len & 0xc0 == 0xc0 {
+                self.take(1)?;
+                return Ok(());
+            }
+            self.take(len as usize)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MockResolver {
+        txt: HashMap<String, Vec<String>>,
+    }
+
+    impl DnsResolver for MockResolver {
+        fn txt(&self, domain: &str) -> io::Result<Vec<String>> {
+            self.txt
+                .get(domain)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such domain"))
+        }
+
+        fn addrs(&self, host_port: &str) -> io::Result<Vec<SocketAddr>> {
+            match host_port {
+                "node.example.org:24567" => Ok(vec!["10.0.0.1:24567".parse().unwrap()]),
+                _ => host_port.to_socket_addrs().map(Iterator::collect),
+            }
+        }
+    }
+
+    fn peer_id(seed: &str) -> PeerId {
+        PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key())
+    }
+
+    #[test]
+    fn test_resolve_seeds() {
+        let (alice, bob) = (peer_id("alice"), peer_id("bob"));
+        let mut resolver = MockResolver::default();
+        resolver.txt.insert(
+            "seed.example.org".to_string(),
+            vec![
+                format!("{}@node.example.org:24567, {}@1.2.3.4:24567", alice, bob),
+                "garbage".to_string(),
+                format!("{}@no-port.example.org", alice),
+            ],
+        );
+        let many = (0..MAX_PEERS_PER_SEED + 5)
+            .map(|i| format!("{}@1.2.3.4:{}", peer_id(&i.to_string()), 1000 + i))
+            .collect();
+        resolver.txt.insert("many.example.org".to_string(), many);
+        let seeds = ["seed.example.org", "missing.example.org", "many.example.org"]
+            .map(String::from)
+            .to_vec();
+
+        let peers = resolve_seeds(&resolver, &seeds);
+        assert_eq!(
+            peers[..2],
+            [
+                SeedPeer {
+                    peer_info: PeerInfo {
+                        id: alice,
+                        addr: Some("10.0.0.1:24567".parse().unwrap()),
+                        account_id: None
+                    },
+                    seed: "seed.example.org".to_string(),
+                },
+                SeedPeer {
+                    peer_info: PeerInfo {
+                        id: bob,
+                        addr: Some("1.2.3.4:24567".parse().unwrap()),
+                        account_id: None
+                    },
+                    seed: "seed.example.org".to_string(),
+                },
+            ]
+        );
+        assert_eq!(peers.len(), 2 + MAX_PEERS_PER_SEED);
+        assert!(peers[2..].iter().all(|peer| peer.seed == "many.example.org"));
+    }
+
+    #[test]
+    fn test_parse_txt_response() {
+        let query = encode_txt_query(0x1234, "seed.example.org").unwrap();
+        let mut response = query.clone();
+        // A response with two answers.
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 2;
+        // A CNAME pointing at the name of the question, which is skipped.
+        response.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 12]);
+        // A TXT record split into two strings.
+        response.extend_from_slice(&[0xc0, 12, 0, 16, 0, 1, 0, 0, 0, 60, 0, 8]);
+        response.extend_from_slice(&[3, b'a', b'b', b'c', 3, b'd', b'e', b'f']);
+        assert_eq!(parse_txt_response(0x1234, &response).unwrap(), vec!["abcdef".to_string()]);
+        assert!(parse_txt_response(0x4321, &response).is_err());
+        assert!(parse_txt_response(0x1234, &response[..response.len() - 1]).is_err());
+        // NXDOMAIN.
+        response[3] = 0x83;
+        assert!(parse_txt_response(0x1234, &response).is_err());
+    }
+
+    #[test]
+    fn test_next_resolve_delay() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let delay = next_resolve_delay(&mut rng);
+            assert!(RESOLVE_PERIOD * 3 / 4 <= delay && delay <= RESOLVE_PERIOD * 5 / 4);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Handshakes refused by this node, so that operators can tell why peers fail
+//! to connect, e.g. how many of them run a protocol version which is too old
+//! during a rolling upgrade.
+
+use crate::stats::metrics;
+use chrono::{DateTime, Utc};
+use near_primitives::network::PeerId;
+use near_primitives::time::Clock;
+use near_primitives::version::ProtocolVersion;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use tracing::debug;
+
+/// Number of refusals kept for the debug page.
+const RECENT_REFUSALS: usize = 50;
+
+/// Why a handshake was refused.  The `snake_case` name is used as the label of
+/// the `near_peer_handshake_refused_total` metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum HandshakeRefusalReason {
+    /// The peer's protocol version is below `PEER_MIN_ALLOWED_PROTOCOL_VERSION`.
+    ProtocolVersionTooLow,
+    /// The peer's protocol version is above ours.
+    ProtocolVersionTooHigh,
+    GenesisMismatch,
+    /// The handshake was meant for another node.
+    InvalidTarget,
+    /// The handshake came from this node itself.
+    ConnectedToSelf,
+    InvalidSignature,
+    InvalidNonce,
+    /// The address of the peer is blacklisted or unknown.
+    Blacklisted,
+    Banned,
+    /// This node is already connected or connecting to the peer.
+    DuplicatePeerId,
+    /// This node has as many peers as it accepts.
+    TooManyPeers,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandshakeRefusal {
+    pub timestamp: DateTime<Utc>,
+    pub addr: SocketAddr,
+    pub peer_id: PeerId,
+    /// Protocol version the peer advertised in its handshake.
+    pub protocol_version: ProtocolVersion,
+    pub reason: HandshakeRefusalReason,
+}
+
+/// The last refusals, shared by the peer actors, which record them, and the
+/// peer manager, which reports them.
+#[derive(Default)]
+pub(crate) struct HandshakeRefusals(Mutex<VecDeque<HandshakeRefusal>>);
+
+impl HandshakeRefusals {
+    pub(crate) fn record(
+        &self,
+        addr: SocketAddr,
+        peer_id: PeerId,
+        protocol_version: ProtocolVersion,
+        reason: HandshakeRefusalReason,
+    ) {
+        debug!(target: "network", %addr, %peer_id, protocol_version, reason = reason.as_ref(), "Refused handshake");
+        metrics::HANDSHAKE_REFUSED.with_label_values(&[reason.as_ref()]).inc();
+        let mut recent = self.0.lock().unwrap();
+        recent.push_front(HandshakeRefusal {
+            timestamp: Clock::utc(),
+            addr,
+            peer_id,
+            protocol_version,
+            reason,
+        });
+        recent.truncate(RECENT_REFUSALS);
+    }
+
+    /// Last refusals, most recent first.
+    pub(crate) fn recent(&self) -> Vec<HandshakeRefusal> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+
+    #[test]
+    fn test_recent_refusals() {
+        let refusals = HandshakeRefusals::default();
+        let peer_id = PeerId::new(SecretKey::from_seed(KeyType::ED25519, "peer").public_key());
+        for port in 0..RECENT_REFUSALS as u16 + 5 {
+            refusals.record(
+                SocketAddr::from(([127, 0, 0, 1], port)),
+                peer_id.clone(),
+                port as ProtocolVersion,
+                HandshakeRefusalReason::ProtocolVersionTooLow,
+            );
+        }
+        let recent = refusals.recent();
+        assert_eq!(recent.len(), RECENT_REFUSALS);
+        assert_eq!(recent[0].protocol_version, RECENT_REFUSALS as ProtocolVersion + 4);
+        assert_eq!(recent.last().unwrap().protocol_version, 5);
+        assert_eq!(HandshakeRefusalReason::TooManyPeers.as_ref(), "too_many_peers");
+    }
+}
@@ -0,0 +1,327 @@
+//! Recording of the messages received from peers, to replay them against a
+//! fresh peer manager when debugging its state machine, see
+//! [`crate::test_utils::replay`].
+//!
+//! Turned on with `network.message_recorder`, which is refused on mainnet
+//! unless the binary is a debug build.  Every message received by the peer
+//! actors is appended to the log with the time since the recording started
+//! and the connection it came from, along with the closed connections and the
+//! peers the peer manager registered and removed, and the routes it computed
+//! whenever they changed, which the replay checks it reaches too.  Each record
+//! is its borsh encoding prefixed with its length as a little endian `u32`.
+//!
+//! Once the file is `max_file_bytes` long it's rotated to `<path>.1`, the
+//! previous `<path>.1` to `<path>.2` and so on, only `max_files` files are
+//! kept.  The payloads which play no part in the decisions of the peer manager
+//! are redacted to keep the log compact: the parts of the chunks are replaced
+//! by their hashes.  The signatures of the redacted routed messages don't
+//! match them anymore, so those messages aren't replayed.
+
+use crate::network_protocol::{Encoding, PeerMessage};
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_network_primitives::types::{MessageRecorderConfig, RoutedMessageBody};
+use near_primitives::hash::hash;
+use near_primitives::network::PeerId;
+use near_primitives::sharding::{PartialEncodedChunk, PartialEncodedChunkPart};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::warn;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Record {
+    /// Time since the recording started.
+    pub elapsed_micros: u64,
+    pub event: RecordedEvent,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum RecordedEvent {
+    /// Message received on a connection, borsh encoded, from the peer if its
+    /// handshake was accepted already.
+    Received { connection: u64, peer_id: Option<PeerId>, message: Vec<u8>, redacted: bool },
+    /// The connection was closed.
+    Closed { connection: u64 },
+    /// The peer manager registered a connected peer.
+    PeerConnected(PeerId),
+    /// The peer manager removed a connected peer.
+    PeerDisconnected(PeerId),
+    /// The routes to the reachable peers changed, sorted by peer.
+    RoutingTable(Vec<(PeerId, Vec<PeerId>)>),
+}
+
+/// The routes to the peers, sorted for two routing tables to be compared.
+pub(crate) fn sorted_routes(
+    peer_forwarding: &HashMap<PeerId, Vec<PeerId>>,
+) -> Vec<(PeerId, Vec<PeerId>)> {
+    let mut routes: Vec<_> = peer_forwarding
+        .iter()
+        .map(|(peer_id, next_hops)| {
+            let mut next_hops = next_hops.clone();
+            next_hops.sort();
+            (peer_id.clone(), next_hops)
+        })
+        .collect();
+    routes.sort();
+    routes
+}
+
+struct LogFile {
+    file: File,
+    len: u64,
+}
+
+/// Log of the messages received by the node, shared by the peer manager and
+/// all peer actors.
+pub(crate) struct MessageRecorder {
+    config: MessageRecorderConfig,
+    started: Instant,
+    next_connection: AtomicU64,
+    file: Mutex<LogFile>,
+    last_routing_table: Mutex<Option<RecordedEvent>>,
+}
+
+impl MessageRecorder {
+    pub fn new(config: MessageRecorderConfig) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&config.path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            config,
+            started: Instant::now(),
+            next_connection: AtomicU64::new(0),
+            file: Mutex::new(LogFile { file, len }),
+            last_routing_table: Mutex::new(None),
+        })
+    }
+
+    /// Number identifying the records of a new connection.
+    pub fn new_connection(&self) -> u64 {
+        self.next_connection.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn record_received(&self, connection: u64, peer_id: Option<&PeerId>, msg: &PeerMessage) {
+        let (message, redacted) = match redact(msg) {
+            Some(msg) => (msg.serialize(Encoding::Borsh), true),
+            None => (msg.serialize(Encoding::Borsh), false),
+        };
+        self.record(RecordedEvent::Received {
+            connection,
+            peer_id: peer_id.cloned(),
+            message,
+            redacted,
+        });
+    }
+
+    /// Records the routes, unless they're the ones recorded last.
+    pub fn record_routing_table(&self, peer_forwarding: &HashMap<PeerId, Vec<PeerId>>) {
+        let event = RecordedEvent::RoutingTable(sorted_routes(peer_forwarding));
+        let mut last = self.last_routing_table.lock().unwrap();
+        if last.as_ref() != Some(&event) {
+            *last = Some(event.clone());
+            self.record(event);
+        }
+    }
+
+    /// Appends the event to the log.  Failing to write it is only logged, the
+    /// node keeps running without the record.
+    pub fn record(&self, event: RecordedEvent) {
+        let record = Record { elapsed_micros: self.started.elapsed().as_micros() as u64, event };
+        let bytes = record.try_to_vec().unwrap();
+        let mut buf = Vec::with_capacity(4 + bytes.len());
+        buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&bytes);
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = self.append(&mut file, &buf) {
+            warn!(target: "network", path = %self.config.path.display(), %err, "Failed to record a message");
+        }
+    }
+
+    fn append(&self, file: &mut LogFile, buf: &[u8]) -> io::Result<()> {
+        if file.len > 0 && file.len + buf.len() as u64 > self.config.max_file_bytes {
+            self.rotate(file)?;
+        }
+        file.file.write_all(buf)?;
+        file.len += buf.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&self, file: &mut LogFile) -> io::Result<()> {
+        let path = &self.config.path;
+        // Renaming over the oldest file kept deletes it.
+        for n in (1..self.config.max_files).rev() {
+            let from = if n == 1 { path.clone() } else { rotated_path(path, n - 1) };
+            if from.exists() {
+                fs::rename(from, rotated_path(path, n))?;
+            }
+        }
+        *file = LogFile { file: File::create(path)?, len: 0 };
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{}", n));
+    path.into()
+}
+
+/// The files of the log at `path`, the oldest first.
+fn log_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> =
+        (1..).map(|n| rotated_path(path, n)).take_while(|path| path.exists()).collect();
+    paths.reverse();
+    if path.exists() {
+        paths.push(path.to_path_buf());
+    }
+    paths
+}
+
+/// Reads the records of the log at `path` and its rotated files, oldest
+/// first.  A record cut short at the end of a file, by a node killed while
+/// writing it, is skipped.
+pub(crate) fn read_log(path: &Path) -> io::Result<Vec<Record>> {
+    let mut records = vec![];
+    for path in log_paths(path) {
+        let mut bytes = vec![];
+        File::open(&path)?.read_to_end(&mut bytes)?;
+        let mut rest = &bytes[..];
+        while rest.len() >= 4 {
+            let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+            if rest.len() < 4 + len {
+                break;
+            }
+            records.push(Record::try_from_slice(&rest[4..4 + len])?);
+            rest = &rest[4 + len..];
+        }
+    }
+    Ok(records)
+}
+
+fn redact_parts(parts: &mut [PartialEncodedChunkPart]) {
+    for part in parts {
+        part.part = hash(&part.part).as_ref().into();
+    }
+}
+
+/// The message with the payloads the peer manager doesn't look at redacted,
+/// `None` if it has none.
+pub(crate) fn redact(msg: &PeerMessage) -> Option<PeerMessage> {
+    let routed = match msg {
+        PeerMessage::Routed(routed) => routed,
+        _ => return None,
+    };
+    let mut routed = routed.clone();
+    match &mut routed.body {
+        RoutedMessageBody::PartialEncodedChunkResponse(response) => {
+            redact_parts(&mut response.parts)
+        }
+        RoutedMessageBody::PartialEncodedChunkForward(forward) => redact_parts(&mut forward.parts),
+        RoutedMessageBody::PartialEncodedChunk(chunk) => redact_parts(&mut chunk.parts),
+        RoutedMessageBody::VersionedPartialEncodedChunk(PartialEncodedChunk::V1(chunk)) => {
+            redact_parts(&mut chunk.parts)
+        }
+        RoutedMessageBody::VersionedPartialEncodedChunk(PartialEncodedChunk::V2(chunk)) => {
+            redact_parts(&mut chunk.parts)
+        }
+        _ => return None,
+    }
+    Some(PeerMessage::Routed(routed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey, Signature};
+    use near_network_primitives::types::{
+        PartialEncodedChunkResponseMsg, PeerIdOrHash, RoutedMessage,
+    };
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::sharding::ChunkHash;
+
+    fn peer_id(seed: &str) -> PeerId {
+        PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key())
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages.log");
+        let config =
+            MessageRecorderConfig { path: path.clone(), max_file_bytes: 200, max_files: 3 };
+        let recorder = MessageRecorder::new(config).unwrap();
+        let connection = recorder.new_connection();
+        for n in 0..30 {
+            let msg = PeerMessage::PeersRequest;
+            recorder.record_received(connection, Some(&peer_id(&n.to_string())), &msg);
+        }
+        recorder.record(RecordedEvent::Closed { connection });
+
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+        for path in log_paths(&path) {
+            assert!(fs::metadata(path).unwrap().len() <= 200);
+        }
+        // The oldest records were deleted with the files rotated out.
+        let records = read_log(&path).unwrap();
+        assert!(records.len() < 31);
+        assert_eq!(records.last().unwrap().event, RecordedEvent::Closed { connection });
+        let expected_peers: Vec<_> =
+            ((31 - records.len())..30).map(|n| Some(peer_id(&n.to_string()))).collect();
+        let peers: Vec<_> = records
+            .iter()
+            .filter_map(|record| match &record.event {
+                RecordedEvent::Received { peer_id, .. } => Some(peer_id.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(peers, expected_peers);
+        let elapsed: Vec<_> = records.iter().map(|record| record.elapsed_micros).collect();
+        assert!(elapsed.windows(2).all(|w| w[0] <= w[1]));
+
+        // A record cut short is skipped.
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[100, 0, 0, 0, 1]).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len + 5);
+        assert_eq!(read_log(&path).unwrap(), records);
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact(&PeerMessage::PeersRequest), None);
+        let part = vec![7; 1000].into_boxed_slice();
+        let response = PartialEncodedChunkResponseMsg {
+            chunk_hash: ChunkHash(CryptoHash::default()),
+            parts: vec![PartialEncodedChunkPart {
+                part_ord: 3,
+                part: part.clone(),
+                merkle_proof: vec![],
+            }],
+            receipts: vec![],
+        };
+        let msg = PeerMessage::Routed(Box::new(RoutedMessage {
+            target: PeerIdOrHash::PeerId(peer_id("target")),
+            author: peer_id("author"),
+            signature: Signature::default(),
+            ttl: 10,
+            body: RoutedMessageBody::PartialEncodedChunkResponse(response),
+        }));
+        let redacted = match redact(&msg) {
+            Some(PeerMessage::Routed(routed)) => routed,
+            other => panic!("unexpected {:?}", other),
+        };
+        match &redacted.body {
+            RoutedMessageBody::PartialEncodedChunkResponse(response) => {
+                assert_eq!(response.parts[0].part_ord, 3);
+                assert_eq!(&response.parts[0].part[..], hash(&part).as_ref());
+            }
+            body => panic!("unexpected {:?}", body),
+        }
+        assert!(msg.serialize(Encoding::Borsh).len() > 1000);
+        assert!(PeerMessage::Routed(redacted).serialize(Encoding::Borsh).len() < 1000);
+    }
+}
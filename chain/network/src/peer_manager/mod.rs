@@ -1,2 +1,12 @@
+pub(crate) mod ban_file;
+pub(crate) mod blacklist_audit;
+pub(crate) mod clock_skew;
+pub(crate) mod dial_scores;
+pub(crate) mod dns_seeds;
+pub(crate) mod handshake_refusals;
+pub(crate) mod message_recorder;
 pub(crate) mod peer_manager_actor;
 pub(crate) mod peer_store;
+pub(crate) mod peer_store_purge;
+pub(crate) mod pinned_peers;
+pub(crate) mod shard_claims;
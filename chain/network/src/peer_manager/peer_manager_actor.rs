@@ -1,7 +1,15 @@
-use crate::network_protocol::Encoding;
-use crate::peer::codec::Codec;
+use crate::network_protocol::{Encoding, MessageSizeLimits};
+use crate::peer::codec::{Codec, MessageCompression};
+use crate::peer::liveness::{LivenessConfig, TcpKeepalive};
 use crate::peer::peer_actor::PeerActor;
-use crate::peer_manager::peer_store::PeerStore;
+use crate::peer_manager::blacklist_audit::BlacklistAudit;
+use crate::peer_manager::clock_skew;
+use crate::peer_manager::dns_seeds::{self, DnsResolver, SeedPeer, SystemResolver};
+use crate::peer_manager::handshake_refusals::{HandshakeRefusalReason, HandshakeRefusals};
+use crate::peer_manager::message_recorder::{MessageRecorder, RecordedEvent};
+use crate::peer_manager::peer_store::{PeerBlacklist, PeerStore, PeerStoreLimits};
+use crate::peer_manager::pinned_peers::{self, PinnedPeers};
+use crate::peer_manager::shard_claims::{ClaimedRequest, ShardClaims};
 use crate::private_actix::{
     PeerRequestResult, PeersRequest, RegisterPeer, RegisterPeerResponse, SendMessage, StopMsg,
     Unregister, ValidateEdgeList,
@@ -28,16 +36,17 @@ use near_network_primitives::types::{
     AccountOrPeerIdOrHash, Ban, Edge, InboundTcpConnect, KnownPeerStatus, KnownProducer,
     NetworkConfig, NetworkViewClientMessages, NetworkViewClientResponses, OutboundTcpConnect,
     PeerIdOrHash, PeerInfo, PeerManagerRequest, PeerType, Ping, Pong, RawRoutedMessage,
-    ReasonForBan, RoutedMessage, RoutedMessageBody, RoutedMessageFrom, StateResponseInfo,
+    ReasonForBan, RoutedMessage, RoutedMessageBody, RoutedMessageFrom, SetBlacklistsEnabled,
+    StateResponseInfo,
 };
-use near_network_primitives::types::{Blacklist, EdgeState, PartialEdgeInfo};
+use near_network_primitives::types::{Blacklist, EdgeState, NormalizedAddr, PartialEdgeInfo};
 use near_performance_metrics::framed_write::FramedWrite;
 use near_performance_metrics_macros::perf;
 use near_primitives::checked_feature;
 use near_primitives::hash::CryptoHash;
 use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::time::Clock;
-use near_primitives::types::{AccountId, EpochId, ProtocolVersion};
+use near_primitives::types::{AccountId, EpochId, ProtocolVersion, ShardId};
 use near_primitives::utils::from_timestamp;
 use near_rate_limiter::{
     ActixMessageResponse, ActixMessageWrapper, ThrottleController, ThrottleFramedRead,
@@ -45,7 +54,7 @@ use near_rate_limiter::{
 };
 use near_store::Store;
 use rand::seq::IteratorRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, SocketAddr};
@@ -96,6 +105,8 @@ const MAX_MESSAGES_TOTAL_SIZE: usize = usize::MAX;
 const REPORT_BANDWIDTH_THRESHOLD_BYTES: usize = 10_000_000;
 /// If we received more than REPORT_BANDWIDTH_THRESHOLD_COUNT` of messages from given peer it's bandwidth stats will be reported.
 const REPORT_BANDWIDTH_THRESHOLD_COUNT: usize = 10_000;
+/// How often at most to warn about the skew of the local clock.
+const CLOCK_SKEW_WARNING_INTERVAL: Duration = Duration::from_secs(600);
 
 /// Contains information relevant to a connected peer.
 struct ConnectedPeer {
@@ -117,6 +128,9 @@ struct ConnectedPeer {
     throttle_controller: ThrottleController,
     /// Encoding used for communication.
     encoding: Option<Encoding>,
+    /// How far the clock of the peer is ahead of ours in nanoseconds, measured
+    /// during the handshake.
+    clock_offset: Option<i64>,
 }
 
 #[derive(Default)]
@@ -154,7 +168,7 @@ impl AdvHelper {
 #[derive(Clone, PartialEq, Eq)]
 struct WhitelistNode {
     id: PeerId,
-    addr: SocketAddr,
+    addr: NormalizedAddr,
     account_id: Option<AccountId>,
 }
 
@@ -164,7 +178,7 @@ impl TryFrom<&PeerInfo> for WhitelistNode {
         Ok(Self {
             id: pi.id.clone(),
             addr: if let Some(addr) = pi.addr {
-                addr.clone()
+                addr.into()
             } else {
                 bail!("addess is missing");
             },
@@ -215,6 +229,33 @@ pub struct PeerManagerActor {
     /// Whitelisted nodes, which are allowed to connect even if the connection limit has been
     /// reached.
     whitelist_nodes: Vec<WhitelistNode>,
+    /// Peers the node stays connected to regardless of the connection limits.
+    pinned_peers: PinnedPeers,
+    /// Entries of `network.blacklist_audit`, which connections are counted for.
+    blacklist_audit: BlacklistAudit,
+    /// Last time a warning about the skew of the local clock was logged.
+    last_clock_skew_warning: Option<Instant>,
+    /// Resolver used for the DNS seeds.
+    dns_resolver: Arc<dyn DnsResolver>,
+    /// Peers found in the DNS seeds when they were last resolved and the seed
+    /// which listed them.
+    dns_seed_peers: HashMap<PeerId, String>,
+    /// Handshakes recently refused by the peer actors.
+    handshake_refusals: Arc<HandshakeRefusals>,
+    /// Shards this node last advertised to its peers, `None` until the view
+    /// client was first asked.
+    advertised_tracked_shards: Option<Vec<ShardId>>,
+    /// Shards the peers claim to track but fail to serve.
+    shard_claims: ShardClaims,
+    /// Size limits of the messages received by the peer actors.
+    message_size_limits: Arc<MessageSizeLimits>,
+    /// Log the messages received by all peer actors are recorded to.
+    message_recorder: Option<Arc<MessageRecorder>>,
+    /// Compression of the messages the peer actors send, if enabled.
+    message_compression: Option<Arc<MessageCompression>>,
+    /// Faults injected into the messages received by all peer actors.
+    #[cfg(feature = "test_features")]
+    fault_injector: Arc<crate::peer::fault_injection::FaultInjector>,
 }
 
 impl Actor for PeerManagerActor {
@@ -272,6 +313,16 @@ impl Actor for PeerManagerActor {
 
         // Periodically prints bandwidth stats for each peer.
         self.report_bandwidth_stats_trigger(ctx, REPORT_BANDWIDTH_STATS_TRIGGER_INTERVAL);
+
+        // Periodically resolves the DNS seeds.
+        if !self.config.dns_seeds.is_empty() {
+            self.resolve_dns_seeds_trigger(ctx);
+        }
+
+        // Periodically reconnects to the pinned peers.
+        if !self.config.pinned_peers.is_empty() {
+            self.reconnect_pinned_peers_trigger(ctx);
+        }
     }
 
     /// Try to gracefully disconnect from connected peers.
@@ -296,14 +347,25 @@ impl PeerManagerActor {
         view_client_addr: Recipient<NetworkViewClientMessages>,
         routing_table_addr: Addr<RoutingTableActor>,
     ) -> anyhow::Result<Self> {
-        let peer_store = PeerStore::new(
+        let exempt_peers: Vec<PeerInfo> =
+            config.whitelist_nodes.iter().chain(&config.pinned_peers).cloned().collect();
+        let mut peer_store = PeerStore::new(
             store.clone(),
             &config.boot_nodes,
-            Blacklist::from_iter(config.blacklist.iter()),
+            PeerBlacklist::new(Blacklist::from_lists(&config.blacklist), &exempt_peers),
         )
         .map_err(|e| anyhow::Error::msg(e.to_string()))?;
+        peer_store.set_private_network(config.private_network);
+        peer_store
+            .set_limits(PeerStoreLimits::new(
+                config.max_peer_store_entries,
+                config.ban_window,
+                &exempt_peers,
+            ))
+            .map_err(|e| anyhow::Error::msg(e.to_string()))?;
         debug!(target: "network", len = peer_store.len(), boot_nodes = config.boot_nodes.len(), "Found known peers");
         debug!(target: "network", blacklist = ?config.blacklist, "Blacklist");
+        let blacklist_audit = BlacklistAudit::new(&config.blacklist_audit, &exempt_peers);
 
         let my_peer_id: PeerId = PeerId::new(config.public_key.clone());
         let routing_table = RoutingTableView::new(store);
@@ -318,6 +380,34 @@ impl PeerManagerActor {
             v
         };
 
+        let pinned_peers = PinnedPeers::new(&config.pinned_peers, Clock::instant());
+
+        let message_size_limits = Arc::new(
+            MessageSizeLimits::new(&config.max_message_sizes).map_err(anyhow::Error::msg)?,
+        );
+
+        let message_compression = match &config.message_compression {
+            Some(compression) => {
+                Some(Arc::new(MessageCompression::new(compression).map_err(anyhow::Error::msg)?))
+            }
+            None => None,
+        };
+
+        let message_recorder = match &config.message_recorder {
+            Some(recorder_config) => {
+                info!(target: "network", path = %recorder_config.path.display(), "Recording the messages received from peers");
+                let recorder = MessageRecorder::new(recorder_config.clone()).map_err(|err| {
+                    anyhow::anyhow!(
+                        "Failed to open the message log {}: {}",
+                        recorder_config.path.display(),
+                        err
+                    )
+                })?;
+                Some(Arc::new(recorder))
+            }
+            None => None,
+        };
+
         Ok(Self {
             my_peer_id,
             config,
@@ -336,6 +426,19 @@ impl PeerManagerActor {
             peer_counter: Arc::new(AtomicUsize::new(0)),
             adv_helper: AdvHelper::default(),
             whitelist_nodes,
+            pinned_peers,
+            blacklist_audit,
+            last_clock_skew_warning: None,
+            dns_resolver: Arc::new(SystemResolver),
+            dns_seed_peers: HashMap::new(),
+            handshake_refusals: Default::default(),
+            advertised_tracked_shards: None,
+            shard_claims: ShardClaims::default(),
+            message_size_limits,
+            message_recorder,
+            message_compression,
+            #[cfg(feature = "test_features")]
+            fault_injector: Default::default(),
         })
     }
 
@@ -356,6 +459,9 @@ impl PeerManagerActor {
                 }) => {
                     act.routing_table_view.remove_local_edges(local_edges_to_remove.iter());
                     act.routing_table_view.peer_forwarding = peer_forwarding;
+                    if let Some(recorder) = &act.message_recorder {
+                        recorder.record_routing_table(&act.routing_table_view.peer_forwarding);
+                    }
                     for peer in peers_to_ban {
                         act.ban_peer(&peer, ReasonForBan::InvalidEdge);
                     }
@@ -613,6 +719,7 @@ impl PeerManagerActor {
         addr: Addr<PeerActor>,
         peer_protocol_version: ProtocolVersion,
         throttle_controller: ThrottleController,
+        clock_offset: Option<i64>,
         ctx: &mut Context<Self>,
     ) {
         #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
@@ -637,6 +744,10 @@ impl PeerManagerActor {
             full_peer_info.partial_edge_info.signature.clone(),
         );
 
+        self.pinned_peers.set_connected(&target_peer_id, true, Clock::instant());
+        if let Some(recorder) = &self.message_recorder {
+            recorder.record(RecordedEvent::PeerConnected(target_peer_id.clone()));
+        }
         self.connected_peers.insert(
             target_peer_id.clone(),
             ConnectedPeer {
@@ -650,6 +761,7 @@ impl PeerManagerActor {
                 peer_type,
                 throttle_controller: throttle_controller.clone(),
                 encoding: None,
+                clock_offset,
             },
         );
 
@@ -773,7 +885,14 @@ impl PeerManagerActor {
 
         // If the last edge we have with this peer represent a connection addition, create the edge
         // update that represents the connection removal.
-        self.connected_peers.remove(peer_id);
+        if self.connected_peers.remove(peer_id).is_some() {
+            self.pinned_peers.set_connected(peer_id, false, Clock::instant());
+            self.shard_claims.reset(peer_id);
+            if let Some(recorder) = &self.message_recorder {
+                recorder.record(RecordedEvent::PeerDisconnected(peer_id.clone()));
+            }
+        }
+        self.routing_table_view.set_unresponsive(peer_id.clone(), false);
 
         #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
         self.routing_table_addr.do_send(RoutingTableMessages::RemovePeer(peer_id.clone()));
@@ -857,6 +976,11 @@ impl PeerManagerActor {
         let account_id = self.config.account_id.clone();
         let server_addr = self.config.addr;
         let handshake_timeout = self.config.handshake_timeout;
+        let liveness_config = LivenessConfig {
+            ping_after: self.config.liveness_ping_after,
+            timeout: self.config.liveness_timeout,
+            max_missed: self.config.liveness_max_missed,
+        };
         let client_addr = self.client_addr.clone();
         let view_client_addr = self.view_client_addr.clone();
 
@@ -880,19 +1004,38 @@ impl PeerManagerActor {
         };
 
         let txns_since_last_block = Arc::clone(&self.txns_since_last_block);
+        #[cfg(feature = "test_features")]
+        let fault_injector = self.fault_injector.clone();
 
         // Start every peer actor on separate thread.
         let arbiter = Arbiter::new();
         let peer_counter = self.peer_counter.clone();
         peer_counter.fetch_add(1, Ordering::SeqCst);
+        let handshake_refusals = self.handshake_refusals.clone();
+        let message_size_limits = self.message_size_limits.clone();
+        let message_recorder = self.message_recorder.clone();
+        let message_compression = self.message_compression.clone();
+
+        if let Some(time) = self.config.tcp_keepalive_time {
+            let keepalive = TcpKeepalive {
+                time,
+                interval: self.config.tcp_keepalive_interval,
+                retries: self.config.tcp_keepalive_retries,
+            };
+            if let Err(err) = keepalive.set(&stream) {
+                warn!(target: "network", ?peer_info, %err, "Failed to set TCP keepalive");
+            }
+        }
 
         PeerActor::start_in_arbiter(&arbiter.handle(), move |ctx| {
             let (read, write) = tokio::io::split(stream);
 
             // TODO: check if peer is banned or known based on IP address and port.
             let rate_limiter = ThrottleController::new(MAX_MESSAGES_COUNT, MAX_MESSAGES_TOTAL_SIZE);
+            let codec =
+                Codec::new(message_size_limits).with_decompression(message_compression.is_some());
             PeerActor::add_stream(
-                ThrottleFramedRead::new(read, Codec::default(), rate_limiter.clone())
+                ThrottleFramedRead::new(read, codec, rate_limiter.clone())
                     .take_while(|x| match x {
                         Ok(_) => true,
                         Err(e) => {
@@ -904,7 +1047,7 @@ impl PeerManagerActor {
                 ctx,
             );
 
-            PeerActor::new(
+            let mut peer_actor = PeerActor::new(
                 PeerInfo { id: my_peer_id, addr: Some(server_addr), account_id },
                 remote_addr,
                 peer_info,
@@ -918,22 +1061,48 @@ impl PeerManagerActor {
                 partial_edge_info,
                 txns_since_last_block,
                 peer_counter,
+                handshake_refusals,
                 rate_limiter,
                 None,
-            )
+                liveness_config,
+            );
+            peer_actor.message_recorder = message_recorder.map(|recorder| {
+                let connection = recorder.new_connection();
+                (recorder, connection)
+            });
+            peer_actor.message_compression = message_compression;
+            #[cfg(feature = "test_features")]
+            {
+                peer_actor.faults = crate::peer::fault_injection::PeerFaults::new(fault_injector);
+            }
+            peer_actor
         });
     }
 
+    /// Number of the connections, established or not, which count against the
+    /// limits, all but those with pinned peers.
+    fn num_unpinned_connections(&self) -> usize {
+        (self.connected_peers.keys().chain(self.outgoing_peers.iter()))
+            .filter(|peer_id| !self.pinned_peers.contains(peer_id))
+            .count()
+    }
+
     /// Check if it is needed to create a new outbound connection.
     /// If the number of active connections is less than `ideal_connections_lo` or
     /// (the number of outgoing connections is less than `minimum_outbound_peers`
     ///     and the total connections is less than `max_num_peers`)
+    /// The connections with pinned peers aren't counted.
     fn is_outbound_bootstrap_needed(&self) -> bool {
-        let total_connections = self.connected_peers.len() + self.outgoing_peers.len();
-        let potential_outgoing_connections = (self.connected_peers.values())
-            .filter(|connected_peer| connected_peer.peer_type == PeerType::Outbound)
+        let total_connections = self.num_unpinned_connections();
+        let potential_outgoing_connections = (self.connected_peers.iter())
+            .filter(|(peer_id, connected_peer)| {
+                connected_peer.peer_type == PeerType::Outbound
+                    && !self.pinned_peers.contains(peer_id)
+            })
             .count()
-            + self.outgoing_peers.len();
+            + (self.outgoing_peers.iter())
+                .filter(|peer_id| !self.pinned_peers.contains(peer_id))
+                .count();
 
         (total_connections < self.config.ideal_connections_lo as usize
             || (total_connections < self.config.max_num_peers as usize
@@ -942,7 +1111,7 @@ impl PeerManagerActor {
     }
 
     fn is_inbound_allowed(&self) -> bool {
-        self.connected_peers.len() + self.outgoing_peers.len() < self.config.max_num_peers as usize
+        self.num_unpinned_connections() < self.config.max_num_peers as usize
     }
 
     /// is_peer_whitelisted checks whether a peer is a whitelisted node.
@@ -952,7 +1121,7 @@ impl PeerManagerActor {
         self.whitelist_nodes
             .iter()
             .filter(|wn| wn.id == peer_info.id)
-            .filter(|wn| Some(wn.addr) == peer_info.addr)
+            .filter(|wn| peer_info.addr.map(NormalizedAddr::from) == Some(wn.addr))
             .any(|wn| wn.account_id.is_none() || wn.account_id == peer_info.account_id)
     }
 
@@ -962,7 +1131,8 @@ impl PeerManagerActor {
     /// the port of an inbound TCP connection is assigned at random.
     /// This predicate should be evaluated BEFORE the Handshake.
     fn is_ip_whitelisted(&self, ip: &IpAddr) -> bool {
-        self.whitelist_nodes.iter().any(|wn| wn.addr.ip() == *ip)
+        let ip = NormalizedAddr::from_ip(*ip);
+        self.whitelist_nodes.iter().any(|wn| wn.addr.without_port() == ip)
     }
 
     /// Returns single random peer with close to the highest height
@@ -982,10 +1152,22 @@ impl PeerManagerActor {
                 cp.full_peer_info.chain_info.height.saturating_add(self.config.highest_peer_horizon)
                     >= max_height
             })
-            .map(|cp| cp.full_peer_info.clone())
+            .map(|cp| self.reported_peer_info(cp))
             .collect::<Vec<_>>()
     }
 
+    /// Information of the peer as reported to sync, without the shards whose
+    /// claim is discounted so that the peer isn't picked for them.
+    fn reported_peer_info(&self, connected_peer: &ConnectedPeer) -> FullPeerInfo {
+        let mut full_peer_info = connected_peer.full_peer_info.clone();
+        let peer_id = &connected_peer.full_peer_info.peer_info.id;
+        full_peer_info
+            .chain_info
+            .tracked_shards
+            .retain(|&shard_id| !self.shard_claims.is_discounted(peer_id, shard_id));
+        full_peer_info
+    }
+
     /// Query current peers for more peers.
     fn query_connected_peers_for_more_peers(&mut self) {
         let mut requests = futures::stream::FuturesUnordered::new();
@@ -1109,7 +1291,7 @@ impl PeerManagerActor {
     }
 
     /// Periodically query peer actors for latest weight and traffic info.
-    fn monitor_peer_stats_trigger(&self, ctx: &mut Context<Self>, interval: Duration) {
+    fn monitor_peer_stats_trigger(&mut self, ctx: &mut Context<Self>, interval: Duration) {
         // Recompute the PEER_CONNECTIONS gauge metric.
         // TODO: it sucks that we have to wait for the next monitor_peer_stats_trigger to recompute
         // it. Actix doesn't support response message aggregation, so we would have
@@ -1119,6 +1301,9 @@ impl PeerManagerActor {
             *m.entry((p.peer_type, p.encoding)).or_insert(0) += 1;
         }
         metrics::set_peer_connections(m);
+        self.check_clock_skew();
+        self.shard_claims.expire(Clock::instant());
+        self.refresh_tracked_shards(ctx);
 
         for (peer_id, connected_peer) in self.connected_peers.iter() {
             let peer_id1 = peer_id.clone();
@@ -1135,6 +1320,10 @@ impl PeerManagerActor {
                                 //     connected_peer.addr.do_send(PeerManagerRequest::BanPeer(ReasonForBan::Abusive));
                                 // }
                             } else if let Some(connected_peer) = act.connected_peers.get_mut(&peer_id1) {
+                                // The peer advertised new shards, its claims start over.
+                                if connected_peer.full_peer_info.chain_info.tracked_shards != res.chain_info.tracked_shards {
+                                    act.shard_claims.reset(&peer_id1);
+                                }
                                 connected_peer.full_peer_info.chain_info = res.chain_info;
                                 connected_peer.sent_bytes_per_sec = res.sent_bytes_per_sec;
                                 connected_peer.received_bytes_per_sec = res.received_bytes_per_sec;
@@ -1154,12 +1343,78 @@ impl PeerManagerActor {
         });
     }
 
-    /// Check if the number of connections (excluding whitelisted ones) exceeds ideal_connections_hi.
+    /// Asks the view client for the shards this node tracks and advertises them
+    /// to the connected peers if they changed.  The peers which connect later
+    /// learn them from the handshake.
+    fn refresh_tracked_shards(&self, ctx: &mut Context<Self>) {
+        self.view_client_addr
+            .send(NetworkViewClientMessages::GetChainInfo)
+            .into_actor(self)
+            .map(|res, act, _| {
+                let tracked_shards = match res {
+                    Ok(NetworkViewClientResponses::ChainInfo { tracked_shards, .. }) => {
+                        tracked_shards
+                    }
+                    _ => return,
+                };
+                if act.advertised_tracked_shards.as_ref() == Some(&tracked_shards) {
+                    return;
+                }
+                if act.advertised_tracked_shards.is_some() {
+                    debug!(target: "network", ?tracked_shards, "Advertising the new tracked shards");
+                    for peer in act.connected_peers.values() {
+                        peer.addr.do_send(PeerManagerRequest::UpdateTrackedShards(
+                            tracked_shards.clone(),
+                        ));
+                    }
+                }
+                act.advertised_tracked_shards = Some(tracked_shards);
+            })
+            .spawn(ctx);
+    }
+
+    /// Estimated skew of the local clock against the connected peers in
+    /// nanoseconds, see [`clock_skew`].
+    fn clock_skew(&self) -> Option<i64> {
+        clock_skew::estimate_clock_skew(
+            self.connected_peers.values().filter_map(|peer| peer.clock_offset),
+        )
+    }
+
+    /// Updates the clock skew metric and warns, at most every
+    /// `CLOCK_SKEW_WARNING_INTERVAL`, if the skew is above the threshold.
+    fn check_clock_skew(&mut self) {
+        let skew = match self.clock_skew() {
+            Some(skew) => skew,
+            None => return,
+        };
+        let skew_ms = skew / 1_000_000;
+        metrics::CLOCK_SKEW.set(skew_ms);
+        if Duration::from_nanos(skew.unsigned_abs()) <= self.config.clock_skew_warn_threshold {
+            return;
+        }
+        let now = Clock::instant();
+        if let Some(last) = self.last_clock_skew_warning {
+            if now.saturating_duration_since(last) < CLOCK_SKEW_WARNING_INTERVAL {
+                return;
+            }
+        }
+        self.last_clock_skew_warning = Some(now);
+        warn!(
+            target: "network",
+            skew_ms,
+            threshold = ?self.config.clock_skew_warn_threshold,
+            "The local clock differs from the clocks of the peers, check that NTP is running"
+        );
+    }
+
+    /// Check if the number of connections (excluding whitelisted and pinned ones) exceeds
+    /// ideal_connections_hi.
     /// If so, constructs a safe set of peers and selects one random peer outside of that set
     /// and sends signal to stop connection to it gracefully.
     ///
     /// Safe set contruction process:
-    /// 1. Add all whitelisted peers to the safe set.
+    /// 1. Add all whitelisted and pinned peers to the safe set.
     /// 2. If the number of outbound connections is less or equal than minimum_outbound_connections,
     ///    add all outbound connections to the safe set.
     /// 3. Find all peers who sent us a message within the last peer_recent_time_window,
@@ -1182,8 +1437,10 @@ impl PeerManagerActor {
         let mut safe_set = HashSet::new();
 
         // If there is not enough non-whitelisted peers, return without disconnecting anyone.
-        let whitelisted_peers =
-            filter_peers(&|p| self.is_peer_whitelisted(&p.full_peer_info.peer_info));
+        let whitelisted_peers = filter_peers(&|p| {
+            self.is_peer_whitelisted(&p.full_peer_info.peer_info)
+                || self.pinned_peers.contains(&p.full_peer_info.peer_info.id)
+        });
         if self.connected_peers.len() - whitelisted_peers.len()
             <= self.config.ideal_connections_hi as usize
         {
@@ -1567,7 +1824,78 @@ impl PeerManagerActor {
                 })
                 .collect(),
             peer_counter: self.peer_counter.load(Ordering::SeqCst),
+            clock_skew_millis: self.clock_skew().map(|skew| skew / 1_000_000),
+            dns_seed_peers: self.dns_seed_peers.clone(),
+            recent_handshake_refusals: self.handshake_refusals.recent(),
+            pinned_peers: self.pinned_peers.statuses(),
+            blacklist_audit: self
+                .blacklist_audit
+                .audit(self.connected_peers.values().map(|peer| &peer.full_peer_info.peer_info)),
+            discounted_shards: (self.connected_peers.keys())
+                .map(|peer_id| (peer_id.clone(), self.shard_claims.discounted(peer_id)))
+                .filter(|(_, shards)| !shards.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Dials the pinned peers the node isn't connected to, once their backoff
+    /// elapsed, and schedules the next check.
+    fn reconnect_pinned_peers_trigger(&mut self, ctx: &mut Context<Self>) {
+        if !self.config.outbound_disabled {
+            let (connected_peers, outgoing_peers) = (&self.connected_peers, &self.outgoing_peers);
+            let peer_store = &self.peer_store;
+            let to_reconnect = self.pinned_peers.to_reconnect(Clock::instant(), |peer_id| {
+                connected_peers.contains_key(peer_id)
+                    || outgoing_peers.contains(peer_id)
+                    || peer_store.is_banned(peer_id)
+            });
+            for peer_info in to_reconnect {
+                debug!(target: "network", ?peer_info, "Reconnecting to pinned peer");
+                self.outgoing_peers.insert(peer_info.id.clone());
+                ctx.notify(PeerManagerMessageRequest::OutboundTcpConnect(OutboundTcpConnect {
+                    peer_info,
+                }));
+            }
+        }
+        near_performance_metrics::actix::run_later(
+            ctx,
+            pinned_peers::RECONNECT_CHECK_INTERVAL,
+            move |act, ctx| {
+                act.reconnect_pinned_peers_trigger(ctx);
+            },
+        );
+    }
+
+    /// Resolves the DNS seeds in the background, adds the peers they list to
+    /// the peer store and schedules the next resolution.
+    fn resolve_dns_seeds_trigger(&self, ctx: &mut Context<Self>) {
+        let resolver = self.dns_resolver.clone();
+        let seeds = self.config.dns_seeds.clone();
+        tokio::task::spawn_blocking(move || dns_seeds::resolve_seeds(resolver.as_ref(), &seeds))
+            .into_actor(self)
+            .map(|result, act, ctx| {
+                match result {
+                    Ok(peers) => act.add_dns_seed_peers(peers),
+                    Err(err) => error!(target: "network", ?err, "Failed to resolve DNS seeds"),
+                }
+                let delay = dns_seeds::next_resolve_delay(&mut thread_rng());
+                near_performance_metrics::actix::run_later(ctx, delay, move |act, ctx| {
+                    act.resolve_dns_seeds_trigger(ctx);
+                });
+            })
+            .spawn(ctx);
+    }
+
+    fn add_dns_seed_peers(&mut self, peers: Vec<SeedPeer>) {
+        let peers: Vec<_> =
+            peers.into_iter().filter(|peer| peer.peer_info.id != self.my_peer_id).collect();
+        if let Err(err) =
+            self.peer_store.add_indirect_peers(peers.iter().map(|peer| peer.peer_info.clone()))
+        {
+            error!(target: "network", ?err, "Fail to update peer store");
         }
+        self.dns_seed_peers =
+            peers.into_iter().map(|peer| (peer.peer_info.id, peer.seed)).collect();
     }
 
     fn push_network_info_trigger(&self, ctx: &mut Context<Self>, interval: Duration) {
@@ -1644,6 +1972,15 @@ impl PeerManagerActor {
                     &target,
                     RoutedMessageBody::StateRequestPart(shard_id, sync_hash, part_id),
                 ) {
+                    // Peers are picked for state parts by the shards they track.
+                    if let AccountOrPeerIdOrHash::PeerId(peer_id) = &target {
+                        self.shard_claims.requested(
+                            peer_id,
+                            shard_id,
+                            ClaimedRequest::StatePart { sync_hash, part_id },
+                            Clock::instant(),
+                        );
+                    }
                     NetworkResponses::NoResponse
                 } else {
                     NetworkResponses::RouteNotFound
@@ -1724,6 +2061,7 @@ impl PeerManagerActor {
                                     .chain_info
                                     .tracked_shards
                                     .contains(&target.shard_id)
+                                && !self.shard_claims.is_discounted(peer_id, target.shard_id)
                             {
                                 matching_peers.push(peer_id.clone());
                             }
@@ -1737,6 +2075,12 @@ impl PeerManagerActor {
                                     request.clone(),
                                 ),
                             }) {
+                                self.shard_claims.requested(
+                                    matching_peer,
+                                    target.shard_id,
+                                    ClaimedRequest::ChunkParts(request.chunk_hash.clone()),
+                                    Clock::instant(),
+                                );
                                 success = true;
                                 break;
                             }
@@ -1993,6 +2337,13 @@ impl PeerManagerActor {
         }
     }
 
+    #[cfg(feature = "test_features")]
+    #[perf]
+    fn handle_msg_set_network_faults(&mut self, msg: crate::test_utils::SetNetworkFaults) {
+        info!(target: "network", faults = ?msg.faults, "Injecting network faults");
+        self.fault_injector.set(msg.faults);
+    }
+
     #[cfg(all(feature = "test_features", feature = "protocol_feature_routing_exchange_algorithm"))]
     #[perf]
     fn handle_msg_set_routing_table(
@@ -2021,7 +2372,9 @@ impl PeerManagerActor {
             || msg
                 .stream
                 .peer_addr()
-                .map(|addr| self.is_ip_whitelisted(&addr.ip()))
+                .map(|addr| {
+                    self.is_ip_whitelisted(&addr.ip()) || self.pinned_peers.contains_ip(&addr.ip())
+                })
                 .unwrap_or(false)
         {
             self.try_connect_peer(ctx.address(), msg.stream, PeerType::Inbound, None, None);
@@ -2059,6 +2412,7 @@ impl PeerManagerActor {
                     Ok(res) => match res {
                         Ok(stream) => {
                             debug!(target: "network", peer_info = ?msg.peer_info, "Connecting");
+                            act.peer_store.dial_succeeded(addr);
                             let edge_info = act.propose_edge(&msg.peer_info.id, None);
 
                             act.try_connect_peer(
@@ -2073,12 +2427,14 @@ impl PeerManagerActor {
                         Err(err) => {
                             info!(target: "network", ?addr, ?err, "Error connecting to");
                             act.outgoing_peers.remove(&msg.peer_info.id);
+                            act.peer_store.dial_failed(addr);
                             actix::fut::ready(())
                         }
                     },
                     Err(err) => {
                         info!(target: "network", ?addr, ?err, "Error connecting to");
                         act.outgoing_peers.remove(&msg.peer_info.id);
+                        act.peer_store.dial_failed(addr);
                         actix::fut::ready(())
                     }
                 })
@@ -2088,44 +2444,43 @@ impl PeerManagerActor {
         }
     }
 
-    #[perf]
-    fn handle_msg_register_peer(
-        &mut self,
-        msg: RegisterPeer,
-        ctx: &mut Context<Self>,
-    ) -> RegisterPeerResponse {
-        let _d = delay_detector::DelayDetector::new(|| "consolidate".into());
-
-        // Check if this is a blacklisted peer.
-        if (msg.peer_info.addr.as_ref()).map_or(true, |addr| self.peer_store.is_blacklisted(addr)) {
-            debug!(target: "network", peer_info = ?msg.peer_info, "Dropping connection from blacklisted peer or unknown address");
-            return RegisterPeerResponse::Reject;
+    /// Checks whether the connection with the peer can be registered.  A too
+    /// low nonce isn't checked here since it's answered with the last edge
+    /// rather than refused.
+    fn check_register_peer(&self, msg: &RegisterPeer) -> Result<(), HandshakeRefusalReason> {
+        if msg.peer_info.addr.is_none() {
+            return Err(HandshakeRefusalReason::Blacklisted);
+        }
+        if let Some(list) = self.peer_store.blacklist_of(&msg.peer_info) {
+            metrics::PEER_BLACKLISTED_BY_LIST.with_label_values(&[list, "handshake"]).inc();
+            debug!(target: "network", peer_info = ?msg.peer_info, list, "Refusing the blacklisted peer");
+            return Err(HandshakeRefusalReason::Blacklisted);
         }
+        self.blacklist_audit.record_connection(&msg.peer_info);
 
         if self.peer_store.is_banned(&msg.peer_info.id) {
-            debug!(target: "network", id = ?msg.peer_info.id, "Dropping connection from banned peer");
-            return RegisterPeerResponse::Reject;
+            return Err(HandshakeRefusalReason::Banned);
         }
 
         // We already connected to this peer.
         if self.connected_peers.contains_key(&msg.peer_info.id) {
-            debug!(target: "network", peer_info = ?self.my_peer_id, id = ?msg.peer_info.id, "Dropping handshake (Active Peer).");
-            return RegisterPeerResponse::Reject;
+            return Err(HandshakeRefusalReason::DuplicatePeerId);
         }
 
         // This is incoming connection but we have this peer already in outgoing.
         // This only happens when both of us connect at the same time, break tie using higher peer id.
-        if msg.peer_type == PeerType::Inbound && self.outgoing_peers.contains(&msg.peer_info.id) {
-            // We pick connection that has lower id.
-            if msg.peer_info.id > self.my_peer_id {
-                debug!(target: "network", my_peer_id = ?self.my_peer_id, id = ?msg.peer_info.id, "Dropping handshake (Tied).");
-                return RegisterPeerResponse::Reject;
-            }
+        // We pick connection that has lower id.
+        if msg.peer_type == PeerType::Inbound
+            && self.outgoing_peers.contains(&msg.peer_info.id)
+            && msg.peer_info.id > self.my_peer_id
+        {
+            return Err(HandshakeRefusalReason::DuplicatePeerId);
         }
 
         if msg.peer_type == PeerType::Inbound
             && !self.is_inbound_allowed()
             && !self.is_peer_whitelisted(&msg.peer_info)
+            && !self.pinned_peers.contains(&msg.peer_info.id)
         {
             // TODO(1896): Gracefully drop inbound connection for other peer.
             debug!(target: "network",
@@ -2133,12 +2488,33 @@ impl PeerManagerActor {
                 max_num_peers = self.config.max_num_peers,
                 "Inbound connection dropped (network at max capacity)."
             );
-            return RegisterPeerResponse::Reject;
+            return Err(HandshakeRefusalReason::TooManyPeers);
         }
 
-        if msg.other_edge_info.nonce == 0 {
-            debug!(target: "network", nonce = msg.other_edge_info.nonce, "Invalid nonce. It must be greater than 0.");
-            return RegisterPeerResponse::Reject;
+        // The nonce must be greater than 0 and not bumped by too much.
+        let last_nonce = self
+            .routing_table_view
+            .get_local_edge(&msg.peer_info.id)
+            .map_or(0, |edge| edge.nonce());
+        if msg.other_edge_info.nonce == 0
+            || msg.other_edge_info.nonce >= Edge::next_nonce(last_nonce) + EDGE_NONCE_BUMP_ALLOWED
+        {
+            debug!(target: "network", nonce = msg.other_edge_info.nonce, last_nonce, ?EDGE_NONCE_BUMP_ALLOWED, ?self.my_peer_id, ?msg.peer_info.id, "Invalid nonce");
+            return Err(HandshakeRefusalReason::InvalidNonce);
+        }
+        Ok(())
+    }
+
+    #[perf]
+    fn handle_msg_register_peer(
+        &mut self,
+        msg: RegisterPeer,
+        ctx: &mut Context<Self>,
+    ) -> RegisterPeerResponse {
+        let _d = delay_detector::DelayDetector::new(|| "consolidate".into());
+
+        if let Err(reason) = self.check_register_peer(&msg) {
+            return RegisterPeerResponse::Reject(reason);
         }
 
         let last_edge = self.routing_table_view.get_local_edge(&msg.peer_info.id);
@@ -2151,11 +2527,6 @@ impl PeerManagerActor {
             return RegisterPeerResponse::InvalidNonce(last_edge.cloned().map(Box::new).unwrap());
         }
 
-        if msg.other_edge_info.nonce >= Edge::next_nonce(last_nonce) + EDGE_NONCE_BUMP_ALLOWED {
-            debug!(target: "network", nonce = msg.other_edge_info.nonce, last_nonce, ?EDGE_NONCE_BUMP_ALLOWED, ?self.my_peer_id, ?msg.peer_info.id, "Too large nonce");
-            return RegisterPeerResponse::Reject;
-        }
-
         let require_response = msg.this_edge_info.is_none();
 
         let edge_info = msg.this_edge_info.clone().unwrap_or_else(|| {
@@ -2176,6 +2547,7 @@ impl PeerManagerActor {
             msg.actor,
             msg.peer_protocol_version,
             msg.throttle_controller,
+            msg.clock_offset,
             ctx,
         );
 
@@ -2194,6 +2566,35 @@ impl PeerManagerActor {
         self.ban_peer(&msg.peer_id, msg.ban_reason);
     }
 
+    /// Enables and disables the blacklists, and disconnects the peers on the
+    /// lists enabled.
+    fn handle_msg_set_blacklists_enabled(&mut self, msg: SetBlacklistsEnabled) {
+        let mut enabled_any = false;
+        for (name, enabled) in msg.lists {
+            match self.peer_store.set_blacklist_enabled(&name, enabled) {
+                Ok(true) => {
+                    info!(target: "network", list = %name, enabled, "Blacklist updated");
+                    enabled_any |= enabled;
+                }
+                Ok(false) => {
+                    warn!(target: "network", list = %name, "Unknown blacklist, new lists are only read at startup")
+                }
+                Err(err) => {
+                    error!(target: "network", list = %name, ?err, "Failed to update the blacklist")
+                }
+            }
+        }
+        if !enabled_any {
+            return;
+        }
+        for (peer_id, peer) in &self.connected_peers {
+            if let Some(list) = self.peer_store.blacklist_of(&peer.full_peer_info.peer_info) {
+                info!(target: "network", %peer_id, list, "Disconnecting the blacklisted peer");
+                peer.addr.do_send(PeerManagerRequest::UnregisterPeer);
+            }
+        }
+    }
+
     #[perf]
     fn handle_msg_peers_request(&self, _msg: PeersRequest) -> PeerRequestResult {
         let _d = delay_detector::DelayDetector::new(|| "peers request".into());
@@ -2267,6 +2668,10 @@ impl PeerManagerActor {
                 self.handle_msg_ban(msg);
                 PeerManagerMessageResponse::Ban(())
             }
+            PeerManagerMessageRequest::SetBlacklistsEnabled(msg) => {
+                self.handle_msg_set_blacklists_enabled(msg);
+                PeerManagerMessageResponse::SetBlacklistsEnabled(())
+            }
             #[cfg(feature = "test_features")]
             #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
             PeerManagerMessageRequest::StartRoutingTableSync(msg) => {
@@ -2284,6 +2689,11 @@ impl PeerManagerActor {
                 self.handle_msg_set_routing_table(msg, ctx);
                 PeerManagerMessageResponse::SetRoutingTable(())
             }
+            #[cfg(feature = "test_features")]
+            PeerManagerMessageRequest::SetNetworkFaults(msg) => {
+                self.handle_msg_set_network_faults(msg);
+                PeerManagerMessageResponse::SetNetworkFaults(())
+            }
         }
     }
 
@@ -2306,6 +2716,34 @@ impl PeerManagerActor {
             match &msg.body {
                 RoutedMessageBody::Ping(ping) => self.handle_ping(ping.clone(), msg.hash()),
                 RoutedMessageBody::Pong(pong) => self.handle_pong(pong.clone()),
+                RoutedMessageBody::PartialEncodedChunkResponse(response) => {
+                    self.shard_claims.answered(
+                        &msg.author,
+                        &ClaimedRequest::ChunkParts(response.chunk_hash.clone()),
+                        !response.parts.is_empty() || !response.receipts.is_empty(),
+                    );
+                    return true;
+                }
+                // A response without the part can't be told apart from the
+                // header's, the request expires instead.
+                RoutedMessageBody::StateResponse(response) => {
+                    if let Some((part_id, _)) = &response.state_response.part {
+                        let request = ClaimedRequest::StatePart {
+                            sync_hash: response.sync_hash,
+                            part_id: *part_id,
+                        };
+                        self.shard_claims.answered(&msg.author, &request, true);
+                    }
+                    return true;
+                }
+                RoutedMessageBody::VersionedStateResponse(response) => {
+                    if let Some(part_id) = response.part_id() {
+                        let request =
+                            ClaimedRequest::StatePart { sync_hash: response.sync_hash(), part_id };
+                        self.shard_claims.answered(&msg.author, &request, true);
+                    }
+                    return true;
+                }
                 _ => return true,
             }
 
@@ -2347,6 +2785,17 @@ impl PeerManagerActor {
                 }
                 PeerResponse::NoResponse
             }
+            PeerRequest::Ping(peer_id) => {
+                self.send_ping(thread_rng().gen(), peer_id);
+                PeerResponse::NoResponse
+            }
+            PeerRequest::Liveness(peer_id, failing) => {
+                if failing {
+                    debug!(target: "network", ?peer_id, "Peer missed a liveness check");
+                }
+                self.routing_table_view.set_unresponsive(peer_id, failing);
+                PeerResponse::NoResponse
+            }
         }
     }
 
@@ -1,20 +1,28 @@
+use crate::peer_manager::dial_scores::{DialScores, RETRY_DEPRIORITIZED_PROBABILITY};
+use crate::stats::metrics;
 use borsh::{BorshDeserialize, BorshSerialize};
 use near_network_primitives::types::{
-    Blacklist, KnownPeerState, KnownPeerStatus, NetworkConfig, PeerInfo, ReasonForBan,
+    Blacklist, BlacklistMatch, KnownPeerState, KnownPeerStatus, NetworkConfig, NormalizedAddr,
+    PeerInfo, ReasonForBan,
 };
 use near_primitives::network::PeerId;
 use near_primitives::time::{Clock, Utc};
 use near_primitives::utils::to_timestamp;
 use near_store::{DBCol, Store};
-use rand::seq::IteratorRandom;
-use rand::thread_rng;
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::{thread_rng, Rng};
 use std::collections::hash_map::{Entry, Iter};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::net::SocketAddr;
 use std::ops::Not;
+use std::time::Duration;
 use tracing::{debug, error, info};
 
+/// Once over `max_peer_store_entries`, peers are evicted until a
+/// `1 / EVICTION_SLACK_DIVISOR` of the limit is free.
+const EVICTION_SLACK_DIVISOR: usize = 10;
+
 /// Level of trust we have about a new (PeerId, Addr) pair.
 #[derive(Eq, PartialEq, Debug, Clone)]
 enum TrustLevel {
@@ -41,6 +49,131 @@ impl VerifiedPeer {
     }
 }
 
+/// The blacklist of the peer store: the peers at blacklisted addresses aren't
+/// kept, neither when loaded at startup nor when learned from other peers, so
+/// they aren't dialed.  The whitelisted and pinned peers, at the address they
+/// are configured with, are exempt, since the operator listed them one by one
+/// while a blacklist entry may cover a whole IP.  A pinned peer is refused on
+/// the blacklist by the config validation anyway.
+#[derive(Debug, Default, Clone)]
+pub struct PeerBlacklist {
+    blacklist: Blacklist,
+    exempt: HashSet<(PeerId, NormalizedAddr)>,
+}
+
+impl PeerBlacklist {
+    pub fn new(blacklist: Blacklist, exempt: &[PeerInfo]) -> Self {
+        let exempt = exempt
+            .iter()
+            .filter_map(|peer_info| {
+                Some((peer_info.id.clone(), NormalizedAddr::from(peer_info.addr?)))
+            })
+            .collect();
+        Self { blacklist, exempt }
+    }
+
+    /// The first enabled list the address of the peer is on with the entry,
+    /// unless the peer is exempt.
+    pub fn match_of(&self, peer_info: &PeerInfo) -> Option<BlacklistMatch<'_>> {
+        let addr = peer_info.addr?;
+        if self.exempt.contains(&(peer_info.id.clone(), NormalizedAddr::from(addr))) {
+            return None;
+        }
+        self.blacklist.match_of(&addr)
+    }
+
+    /// The first enabled list the address of the peer is on, unless the peer
+    /// is exempt.
+    pub fn list_of(&self, peer_info: &PeerInfo) -> Option<&str> {
+        self.match_of(peer_info).map(|blacklist_match| blacklist_match.list)
+    }
+
+    /// Whether the peer is at a blacklisted address, and not exempt.
+    pub fn contains(&self, peer_info: &PeerInfo) -> bool {
+        self.list_of(peer_info).is_some()
+    }
+}
+
+/// How many peers the peer store keeps, see
+/// `NetworkConfig::max_peer_store_entries`.  Beyond the limit the peers seen
+/// the longest ago are evicted, but for the protected ones, which may exceed
+/// the limit on their own.  The peers loaded at start count as seen then, so
+/// that among them the ones first seen the longest ago go first.
+///
+/// Once over the limit, the store is brought a `1 / EVICTION_SLACK_DIVISOR`
+/// of it below, so that the peers are gone through once every so many new
+/// peers rather than on each.
+#[derive(Debug, Default, Clone)]
+pub struct PeerStoreLimits {
+    /// `None` for no limit.
+    max_entries: Option<usize>,
+    ban_window: Duration,
+    /// The whitelisted and pinned peers.
+    exempt: HashSet<PeerId>,
+}
+
+impl PeerStoreLimits {
+    pub fn new(max_entries: Option<usize>, ban_window: Duration, exempt: &[PeerInfo]) -> Self {
+        let exempt = exempt.iter().map(|peer_info| peer_info.id.clone()).collect();
+        Self { max_entries, ban_window, exempt }
+    }
+
+    /// Whether the peer is never evicted: it's connected, whitelisted or
+    /// pinned, or it was banned within `ban_window`.  `now` is a timestamp.
+    pub fn is_protected(&self, peer_id: &PeerId, peer_state: &KnownPeerState, now: u64) -> bool {
+        let active_ban = match peer_state.status {
+            KnownPeerStatus::Banned(_, banned_at) => {
+                u128::from(now.saturating_sub(banned_at)) <= self.ban_window.as_nanos()
+            }
+            _ => false,
+        };
+        active_ban
+            || peer_state.status == KnownPeerStatus::Connected
+            || self.exempt.contains(peer_id)
+    }
+
+    /// Whether there are more peers than `max_entries`.
+    fn is_exceeded(&self, len: usize) -> bool {
+        self.max_entries.map_or(false, |max_entries| len > max_entries)
+    }
+
+    /// The peers to evict, if there are more than `max_entries`, for at most
+    /// a slack below it to be left: the unprotected ones seen the longest ago.
+    fn to_evict<'a>(
+        &self,
+        peers: impl ExactSizeIterator<Item = (&'a PeerId, &'a KnownPeerState)>,
+        now: u64,
+    ) -> Vec<PeerId> {
+        let max_entries = match self.max_entries {
+            Some(max_entries) if peers.len() > max_entries => max_entries,
+            _ => return vec![],
+        };
+        let max_entries = max_entries - max_entries / EVICTION_SLACK_DIVISOR;
+        let mut total = 0;
+        let mut candidates = vec![];
+        for (peer_id, peer_state) in peers {
+            total += 1;
+            if !self.is_protected(peer_id, peer_state, now) {
+                candidates.push((peer_state.last_seen, peer_state.first_seen, peer_id));
+            }
+        }
+        let excess = total.saturating_sub(max_entries).min(candidates.len());
+        if excess == 0 {
+            return vec![];
+        }
+        if excess < candidates.len() {
+            candidates.select_nth_unstable(excess);
+        }
+        candidates.into_iter().take(excess).map(|(_, _, peer_id)| peer_id.clone()).collect()
+    }
+}
+
+impl From<Blacklist> for PeerBlacklist {
+    fn from(blacklist: Blacklist) -> Self {
+        Self { blacklist, exempt: HashSet::new() }
+    }
+}
+
 /// Known peers store, maintaining cache of known peers and connection to storage to save/load them.
 pub struct PeerStore {
     store: Store,
@@ -48,15 +181,20 @@ pub struct PeerStore {
     // This is a reverse index, from physical address to peer_id
     // It can happens that some peers don't have known address, so
     // they will not be present in this list, otherwise they will be present.
-    addr_peers: HashMap<SocketAddr, VerifiedPeer>,
-    blacklist: Blacklist,
+    // The addresses are normalized, so that an IPv4 address and its IPv6-mapped
+    // form are the same address.
+    addr_peers: HashMap<NormalizedAddr, VerifiedPeer>,
+    blacklist: PeerBlacklist,
+    /// Reachability of the addresses dialed, which orders the peers to dial.
+    dial_scores: DialScores,
+    limits: PeerStoreLimits,
 }
 
 impl PeerStore {
     pub(crate) fn new(
         store: Store,
         boot_nodes: &[PeerInfo],
-        blacklist: Blacklist,
+        blacklist: PeerBlacklist,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         // A mapping from `PeerId` to `KnownPeerState`.
         let mut peerid_2_state = HashMap::default();
@@ -69,7 +207,7 @@ impl PeerStore {
         boot_nodes.iter().for_each(|peer_info|{
             if !peerid_2_state.contains_key(&peer_info.id) {
                 if let Some(peer_addr) = peer_info.addr {
-                    match addr_2_peer.entry(peer_addr) {
+                    match addr_2_peer.entry(NormalizedAddr::from(peer_addr)) {
                         Entry::Occupied(entry) => {
                             // There is already a different peer_id with this address.
                             error!(target: "network", "Two boot nodes have the same address {:?}", entry.key());
@@ -100,11 +238,12 @@ impl PeerStore {
             // Mark loaded node last seen to now, to avoid deleting them as soon as they are loaded.
             let last_seen = now;
 
-            // If it’s already banned, keep it banned.  Otherwise, it’s not connected.
-            let status = if peer_state.status.is_banned() {
-                peer_state.status
-            } else {
-                KnownPeerStatus::NotConnected
+            // If it’s already banned, keep it banned.  A peer connected when
+            // the node stopped isn’t anymore, while the ones never connected
+            // stay `Unknown`, which tells the unreachable peers apart.
+            let status = match peer_state.status {
+                KnownPeerStatus::Connected => KnownPeerStatus::NotConnected,
+                status => status,
             };
 
             let peer_state = KnownPeerState {
@@ -114,9 +253,7 @@ impl PeerStore {
                 status,
             };
 
-            let is_blacklisted =
-                peer_state.peer_info.addr.as_ref().map_or(false, |addr| blacklist.contains(addr));
-            if is_blacklisted {
+            if blacklist.contains(&peer_state.peer_info) {
                 info!(target: "network", "Removing {:?} because address is blacklisted", peer_state.peer_info);
                 peers_to_delete.push(peer_id);
             } else {
@@ -136,7 +273,7 @@ impl PeerStore {
                 // Peer is not a boot node
                 Entry::Vacant(entry) => {
                     if let Some(peer_addr) = peer_state.peer_info.addr {
-                        if let Entry::Vacant(entry2) = addr_2_peer.entry(peer_addr) {
+                        if let Entry::Vacant(entry2) = addr_2_peer.entry(peer_addr.into()) {
                             // Default case, add new entry.
                             entry2.insert(VerifiedPeer::new(peer_state.peer_info.id.clone()));
                             entry.insert(peer_state);
@@ -148,14 +285,94 @@ impl PeerStore {
             }
         }
 
-        let mut peer_store =
-            PeerStore { store, peer_states: peerid_2_state, addr_peers: addr_2_peer, blacklist };
+        let mut peer_store = PeerStore {
+            store,
+            peer_states: peerid_2_state,
+            addr_peers: addr_2_peer,
+            blacklist,
+            dial_scores: DialScores::new(false),
+            limits: PeerStoreLimits::default(),
+        };
         peer_store.delete_peers(&peers_to_delete)?;
         Ok(peer_store)
     }
 
-    pub fn is_blacklisted(&self, addr: &SocketAddr) -> bool {
-        self.blacklist.contains(addr)
+    pub fn is_blacklisted(&self, peer_info: &PeerInfo) -> bool {
+        self.blacklist.contains(peer_info)
+    }
+
+    /// The blacklist the peer is on, see [`PeerBlacklist::list_of`].
+    pub fn blacklist_of(&self, peer_info: &PeerInfo) -> Option<&str> {
+        self.blacklist.list_of(peer_info)
+    }
+
+    /// Enables or disables the blacklist, keeping its entries.  Once a list is
+    /// enabled the peers on it are removed, as at startup, except for the
+    /// connected ones, which are to be disconnected.  Returns whether there's
+    /// such a list.
+    pub(crate) fn set_blacklist_enabled(
+        &mut self,
+        name: &str,
+        enabled: bool,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if !self.blacklist.blacklist.set_enabled(name, enabled) {
+            return Ok(false);
+        }
+        if enabled {
+            let to_remove: Vec<PeerId> = self
+                .peer_states
+                .iter()
+                .filter(|(_, peer_state)| {
+                    peer_state.status != KnownPeerStatus::Connected
+                        && self.blacklist.contains(&peer_state.peer_info)
+                })
+                .map(|(peer_id, _)| peer_id.clone())
+                .collect();
+            if !to_remove.is_empty() {
+                info!(target: "network", list = name, "Removing {} peers since their address is blacklisted", to_remove.len());
+            }
+            self.delete_peers(&to_remove)?;
+        }
+        Ok(true)
+    }
+
+    /// Whether the node runs on a private network, where the peers at private
+    /// addresses aren't deprioritized.
+    pub(crate) fn set_private_network(&mut self, private_network: bool) {
+        self.dial_scores.set_private_network(private_network);
+    }
+
+    /// Sets how many peers to keep, evicting the ones over the limit.
+    pub(crate) fn set_limits(
+        &mut self,
+        limits: PeerStoreLimits,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.limits = limits;
+        self.evict_over_limit()
+    }
+
+    /// Evicts the peers over `max_entries`, see [`PeerStoreLimits`].
+    fn evict_over_limit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.limits.is_exceeded(self.peer_states.len()) {
+            metrics::PEER_STORE_SIZE.set(self.peer_states.len() as i64);
+            return Ok(());
+        }
+        let to_evict = self.limits.to_evict(self.peer_states.iter(), to_timestamp(Clock::utc()));
+        if !to_evict.is_empty() {
+            debug!(target: "network", count = to_evict.len(), "Evicting peers over max_peer_store_entries");
+            metrics::PEER_STORE_EVICTED.inc_by(to_evict.len() as u64);
+            self.delete_peers(&to_evict)?;
+        }
+        metrics::PEER_STORE_SIZE.set(self.peer_states.len() as i64);
+        Ok(())
+    }
+
+    pub(crate) fn dial_failed(&mut self, addr: SocketAddr) {
+        self.dial_scores.dial_failed(addr.into(), Clock::instant());
+    }
+
+    pub(crate) fn dial_succeeded(&mut self, addr: SocketAddr) {
+        self.dial_scores.dial_succeeded(addr.into(), Clock::instant());
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -172,11 +389,13 @@ impl PeerStore {
         &mut self,
         peer_info: &PeerInfo,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.add_signed_peer(peer_info.clone())?;
+        self.add_peer(peer_info.clone(), TrustLevel::Signed)?;
         let entry = self.peer_states.get_mut(&peer_info.id).unwrap();
         entry.last_seen = to_timestamp(Utc::now());
         entry.status = KnownPeerStatus::Connected;
-        Self::save_to_db(&self.store, peer_info.id.try_to_vec()?.as_slice(), entry)
+        Self::save_to_db(&self.store, peer_info.id.try_to_vec()?.as_slice(), entry)?;
+        // Only now that it's connected the peer is safe from eviction.
+        self.evict_over_limit()
     }
 
     pub(crate) fn peer_disconnected(
@@ -206,6 +425,49 @@ impl PeerStore {
         }
     }
 
+    /// Bans the given peers unless they're already banned since a later time,
+    /// adding the ones not known yet.  `banned_at` is the timestamp the ban
+    /// window of each peer starts at.  Returns the number of peers whose ban
+    /// changed.
+    pub(crate) fn import_bans(
+        &mut self,
+        bans: impl IntoIterator<Item = (PeerInfo, ReasonForBan, u64)>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let now = Clock::utc();
+        let mut imported = 0;
+        for (peer_info, reason, banned_at) in bans {
+            if self.blacklist.contains(&peer_info) {
+                continue;
+            }
+            let peer_id = peer_info.id.clone();
+            let peer_state = match self.peer_states.entry(peer_id.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    // Only one peer can have a given address, keep the address
+                    // of the peer already known.
+                    let addr =
+                        peer_info.addr.filter(|addr| match self.addr_peers.entry((*addr).into()) {
+                            Entry::Occupied(_) => false,
+                            Entry::Vacant(addr_entry) => {
+                                addr_entry.insert(VerifiedPeer::new(peer_id.clone()));
+                                true
+                            }
+                        });
+                    entry.insert(KnownPeerState::new(PeerInfo { addr, ..peer_info }, now))
+                }
+            };
+            if let KnownPeerStatus::Banned(_, current) = peer_state.status {
+                if current >= banned_at {
+                    continue;
+                }
+            }
+            peer_state.status = KnownPeerStatus::Banned(reason, banned_at);
+            Self::save_to_db(&self.store, peer_id.try_to_vec()?.as_slice(), peer_state)?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+
     fn save_to_db(
         store: &Store,
         peer_id: &[u8],
@@ -222,7 +484,8 @@ impl PeerStore {
         for peer_id in peer_ids {
             if let Some(peer_state) = self.peer_states.remove(peer_id) {
                 if let Some(addr) = peer_state.peer_info.addr {
-                    self.addr_peers.remove(&addr);
+                    self.addr_peers.remove(&NormalizedAddr::from(addr));
+                    self.dial_scores.forget(&NormalizedAddr::from(addr));
                 }
             }
 
@@ -257,21 +520,44 @@ impl PeerStore {
     }
 
     /// Return unconnected or peers with unknown status that we can try to connect to.
-    /// Peers with unknown addresses are filtered out.
+    /// Peers with unknown addresses are filtered out, and so are the ones whose
+    /// address is backing off after failed dials.  The peers are picked by the
+    /// priority of their address, see [`crate::peer_manager::dial_scores`].
     pub(crate) fn unconnected_peer(
         &self,
         ignore_fn: impl Fn(&KnownPeerState) -> bool,
     ) -> Option<PeerInfo> {
-        self.find_peers(
-            |p| {
-                (p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
-                    && !ignore_fn(p)
-                    && p.peer_info.addr.is_some()
-            },
-            1,
-        )
-        .get(0)
-        .cloned()
+        let now = Clock::instant();
+        let mut backing_off = 0;
+        // The candidates by `DialPriority`.
+        let mut candidates: [Vec<&KnownPeerState>; 3] = Default::default();
+        for p in self.peer_states.values() {
+            if !(p.status == KnownPeerStatus::NotConnected || p.status == KnownPeerStatus::Unknown)
+                || ignore_fn(p)
+            {
+                continue;
+            }
+            if let Some(addr) = p.peer_info.addr {
+                match self.dial_scores.priority(&addr.into(), now) {
+                    Some(priority) => candidates[priority as usize].push(p),
+                    None => backing_off += 1,
+                }
+            }
+        }
+        if backing_off != 0 {
+            metrics::PEER_DIALS_SKIPPED.inc_by(backing_off);
+        }
+        let mut rng = thread_rng();
+        let [recent_success, untried, deprioritized] = &candidates;
+        let preferred = if recent_success.is_empty() { untried } else { recent_success };
+        let pool = if preferred.is_empty()
+            || (!deprioritized.is_empty() && rng.gen_bool(RETRY_DEPRIORITIZED_PROBABILITY))
+        {
+            deprioritized
+        } else {
+            preferred
+        };
+        pool.choose(&mut rng).map(|p| p.peer_info.clone())
     }
 
     /// Return healthy known peers up to given amount.
@@ -284,7 +570,9 @@ impl PeerStore {
         self.peer_states.iter()
     }
 
-    /// Removes peers that are not responding for expiration period.
+    /// Removes peers that are not responding for expiration period, then the
+    /// ones over the limit, but for the protected ones, see
+    /// [`PeerStoreLimits`].
     pub(crate) fn remove_expired(
         &mut self,
         config: &NetworkConfig,
@@ -293,14 +581,15 @@ impl PeerStore {
         let mut to_remove = vec![];
         for (peer_id, peer_status) in self.peer_states.iter() {
             let diff = (now - peer_status.last_seen()).to_std()?;
-            if peer_status.status != KnownPeerStatus::Connected
+            if !self.limits.is_protected(peer_id, peer_status, to_timestamp(now))
                 && diff > config.peer_expiration_duration
             {
                 debug!(target: "network", "Removing peer: last seen {:?}", diff);
                 to_remove.push(peer_id.clone());
             }
         }
-        self.delete_peers(&to_remove)
+        self.delete_peers(&to_remove)?;
+        self.evict_over_limit()
     }
 
     fn touch(&self, peer_id: &PeerId) -> Result<(), Box<dyn std::error::Error>> {
@@ -322,7 +611,7 @@ impl PeerStore {
         let mut touch_other = None;
 
         // If there is a peer associated with current address remove the address from it.
-        if let Some(verified_peer) = self.addr_peers.remove(&peer_addr) {
+        if let Some(verified_peer) = self.addr_peers.remove(&NormalizedAddr::from(peer_addr)) {
             self.peer_states.entry(verified_peer.peer_id).and_modify(|peer_state| {
                 peer_state.peer_info.addr = None;
                 touch_other = Some(peer_state.peer_info.id.clone());
@@ -332,13 +621,13 @@ impl PeerStore {
         // If this peer already has an address, remove that pair from the index.
         if let Some(peer_state) = self.peer_states.get_mut(&peer_info.id) {
             if let Some(cur_addr) = peer_state.peer_info.addr.take() {
-                self.addr_peers.remove(&cur_addr);
+                self.addr_peers.remove(&NormalizedAddr::from(cur_addr));
             }
         }
 
         // Add new address
         self.addr_peers
-            .insert(peer_addr, VerifiedPeer { peer_id: peer_info.id.clone(), trust_level });
+            .insert(peer_addr.into(), VerifiedPeer { peer_id: peer_info.id.clone(), trust_level });
 
         // Update peer_id addr
         self.peer_states
@@ -372,9 +661,11 @@ impl PeerStore {
                     let is_peer_trusted =
                         self.peer_states.get(&peer_info.id).map_or(false, |peer_state| {
                             peer_state.peer_info.addr.map_or(false, |current_addr| {
-                                self.addr_peers.get(&current_addr).map_or(false, |verified_peer| {
-                                    verified_peer.trust_level == TrustLevel::Signed
-                                })
+                                self.addr_peers
+                                    .get(&NormalizedAddr::from(current_addr))
+                                    .map_or(false, |verified_peer| {
+                                        verified_peer.trust_level == TrustLevel::Signed
+                                    })
                             })
                         });
                     if is_peer_trusted {
@@ -387,7 +678,7 @@ impl PeerStore {
                     // We should only update an Indirect connection if we don't know anything about the peer
                     // or about the address.
                     if !self.peer_states.contains_key(&peer_info.id)
-                        && !self.addr_peers.contains_key(&peer_addr)
+                        && !self.addr_peers.contains_key(&NormalizedAddr::from(peer_addr))
                     {
                         self.update_peer_info(peer_info, peer_addr, TrustLevel::Indirect)?;
                     }
@@ -419,19 +710,19 @@ impl PeerStore {
         let mut blacklisted: usize = 0;
         for peer_info in peers {
             total += 1;
-            let is_blacklisted =
-                peer_info.addr.as_ref().map_or(false, |addr| self.blacklist.contains(addr));
-            if is_blacklisted {
+            if let Some(list) = self.blacklist.list_of(&peer_info) {
+                metrics::PEER_BLACKLISTED_BY_LIST.with_label_values(&[list, "peer_exchange"]).inc();
                 blacklisted += 1;
             } else {
                 self.add_peer(peer_info, TrustLevel::Indirect)?;
             }
         }
         if blacklisted != 0 {
+            metrics::PEER_EXCHANGE_BLACKLISTED.inc_by(blacklisted as u64);
             info!(target: "network", "Ignored {} blacklisted peers out of {} indirect peer(s)",
                   blacklisted, total);
         }
-        Ok(())
+        self.evict_over_limit()
     }
 
     /// Adds a peer we’ve connected to but haven’t verified ID yet.
@@ -445,7 +736,8 @@ impl PeerStore {
         &mut self,
         peer_info: PeerInfo,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.add_peer(peer_info, TrustLevel::Direct)
+        self.add_peer(peer_info, TrustLevel::Direct)?;
+        self.evict_over_limit()
     }
 
     /// Adds a peer which proved to have secret key associated with the ID.
@@ -458,7 +750,8 @@ impl PeerStore {
         &mut self,
         peer_info: PeerInfo,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        self.add_peer(peer_info, TrustLevel::Signed)
+        self.add_peer(peer_info, TrustLevel::Signed)?;
+        self.evict_over_limit()
     }
 }
 
@@ -480,6 +773,7 @@ mod test {
     use std::net::{Ipv4Addr, SocketAddrV4};
 
     use super::*;
+    use near_network_primitives::types::NamedBlacklist;
 
     fn get_peer_id(seed: String) -> PeerId {
         PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed.as_str()).public_key())
@@ -536,6 +830,35 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_unconnected_peer_dial_scores() {
+        let public = PeerInfo { addr: Some("1.2.3.4:24567".parse().unwrap()), ..gen_peer_info(0) };
+        let failing = PeerInfo { addr: Some("5.6.7.8:24567".parse().unwrap()), ..gen_peer_info(0) };
+        let private = gen_peer_info(1);
+        let boot_nodes = vec![public.clone(), failing.clone(), private.clone()];
+        let mut peer_store =
+            PeerStore::new(create_test_store(), &boot_nodes, Default::default()).unwrap();
+        let ignore = |ignored: Vec<&PeerInfo>| {
+            move |p: &KnownPeerState| ignored.iter().any(|peer| peer.id == p.peer_info.id)
+        };
+
+        // The failing address is backing off.
+        peer_store.dial_failed(failing.addr.unwrap());
+        assert_eq!(peer_store.unconnected_peer(ignore(vec![&public, &private])), None);
+        // The private address is only picked when there is no public one.
+        assert_eq!(peer_store.unconnected_peer(ignore(vec![&public])), Some(private.clone()));
+        let picked: Vec<_> =
+            (0..100).filter_map(|_| peer_store.unconnected_peer(|_| false)).collect();
+        assert!(picked.contains(&public));
+        assert!(!picked.contains(&failing));
+
+        peer_store.set_private_network(true);
+        peer_store.dial_succeeded(private.addr.unwrap());
+        let picked: Vec<_> =
+            (0..100).filter_map(|_| peer_store.unconnected_peer(|_| false)).collect();
+        assert!(picked.iter().filter(|peer| **peer == private).count() > 50);
+    }
+
     fn check_exist(
         peer_store: &PeerStore,
         peer_id: &PeerId,
@@ -547,7 +870,7 @@ mod test {
                 peer_info.addr.map_or(false, |cur_addr| cur_addr == addr)
                     && peer_store
                         .addr_peers
-                        .get(&addr)
+                        .get(&NormalizedAddr::from(addr))
                         .map_or(false, |verified| verified.trust_level == level)
             } else {
                 peer_info.addr.is_none()
@@ -560,16 +883,19 @@ mod test {
     fn check_integrity(peer_store: &PeerStore) -> bool {
         peer_store.peer_states.clone().iter().all(|(k, v)| {
             if let Some(addr) = v.peer_info.addr {
-                if peer_store.addr_peers.get(&addr).map_or(true, |value| value.peer_id != *k) {
+                if peer_store
+                    .addr_peers
+                    .get(&NormalizedAddr::from(addr))
+                    .map_or(true, |value| value.peer_id != *k)
+                {
                     return false;
                 }
             }
             true
         }) && peer_store.addr_peers.clone().iter().all(|(k, v)| {
-            !peer_store
-                .peer_states
-                .get(&v.peer_id)
-                .map_or(true, |value| value.peer_info.addr.map_or(true, |addr| addr != *k))
+            !peer_store.peer_states.get(&v.peer_id).map_or(true, |value| {
+                value.peer_info.addr.map_or(true, |addr| NormalizedAddr::from(addr) != *k)
+            })
         })
     }
 
@@ -595,6 +921,39 @@ mod test {
         assert!(check_integrity(&peer_store));
     }
 
+    /// An IPv4 address and its IPv6-mapped form are the same address #A.
+    #[test]
+    fn handle_v4_mapped_address() {
+        let store = create_test_store();
+        let mut peer_store = PeerStore::new(store, &[], Default::default()).unwrap();
+
+        let peers_id = (0..3).map(|ix| get_peer_id(format!("node{}", ix))).collect::<Vec<_>>();
+        let addr = get_addr(0);
+        let mapped_addr: SocketAddr = "[::ffff:127.0.0.1]:0".parse().unwrap();
+
+        let peer_aa = get_peer_info(peers_id[0].clone(), Some(addr));
+        peer_store.peer_connected(&peer_aa).unwrap();
+
+        // Learning about a peer at a known address from other peers changes nothing.
+        let peer_ba = get_peer_info(peers_id[1].clone(), Some(mapped_addr));
+        peer_store.add_peer(peer_ba.clone(), TrustLevel::Indirect).unwrap();
+        assert!(!peer_store.peer_states.contains_key(&peers_id[1]));
+
+        peer_store.add_peer(peer_ba, TrustLevel::Direct).unwrap();
+        assert!(check_exist(&peer_store, &peers_id[0], None));
+        assert!(check_exist(&peer_store, &peers_id[1], Some((mapped_addr, TrustLevel::Direct))));
+        assert!(check_integrity(&peer_store));
+        assert_eq!(peer_store.addr_peers.len(), 1);
+
+        // Exempt from the blacklist in both forms as well.
+        let blacklist = PeerBlacklist::new(
+            Blacklist::from_iter(["::ffff:127.0.0.1"].into_iter()),
+            &[get_peer_info(peers_id[2].clone(), Some(addr))],
+        );
+        assert!(!blacklist.contains(&get_peer_info(peers_id[2].clone(), Some(mapped_addr))));
+        assert!(blacklist.contains(&get_peer_info(peers_id[1].clone(), Some(addr))));
+    }
+
     /// If we know there is a peer_id A at address #A, and then we learn about
     /// the same peer_id A at address #B, if that connection wasn't signed it is not updated,
     /// to avoid malicious actor making us forget about known peers.
@@ -738,7 +1097,7 @@ mod test {
             let blacklist = Blacklist::from_iter(
                 ["127.0.0.1:2".to_string(), "127.0.0.1:5".to_string()].into_iter(),
             );
-            let mut peer_store = PeerStore::new(store, &[], blacklist).unwrap();
+            let mut peer_store = PeerStore::new(store, &[], blacklist.into()).unwrap();
             // Peer 127.0.0.1:2 is removed since it's blacklisted.
             assert_peers(&peer_store, &[&ids[1]]);
 
@@ -757,6 +1116,66 @@ mod test {
         }
     }
 
+    #[test]
+    fn check_blacklist_exempt_peers() {
+        let ids = (0..3).map(|ix| get_peer_id(format!("node{}", ix))).collect::<Vec<_>>();
+        let infos: Vec<_> =
+            ids.iter().map(|id| get_peer_info(id.clone(), Some(get_addr(1)))).collect();
+        let whitelisted = infos[0].clone();
+        let store = create_test_store();
+        {
+            let mut peer_store = PeerStore::new(store.clone(), &[], Default::default()).unwrap();
+            peer_store.add_indirect_peers([whitelisted.clone()].into_iter()).unwrap();
+        }
+
+        // The whole IP is blacklisted, but for the whitelisted peer at its
+        // address.
+        let blacklist = PeerBlacklist::new(
+            Blacklist::from_iter(["127.0.0.1"].into_iter()),
+            &[whitelisted.clone()],
+        );
+        let mut peer_store = PeerStore::new(store, &[], blacklist).unwrap();
+        assert!(peer_store.peer_states.contains_key(&ids[0]));
+        assert!(!peer_store.is_blacklisted(&whitelisted));
+        assert!(peer_store.is_blacklisted(&infos[1]));
+        let other_addr = get_peer_info(ids[0].clone(), Some(get_addr(2)));
+        assert!(peer_store.is_blacklisted(&other_addr));
+
+        let before = metrics::PEER_EXCHANGE_BLACKLISTED.get();
+        peer_store.add_indirect_peers(infos[1..].iter().cloned()).unwrap();
+        assert!(!peer_store.peer_states.contains_key(&ids[1]));
+        assert!(!peer_store.peer_states.contains_key(&ids[2]));
+        assert!(metrics::PEER_EXCHANGE_BLACKLISTED.get() >= before + 2);
+    }
+
+    #[test]
+    fn enable_blacklist() {
+        let infos: Vec<_> = (1..4)
+            .map(|port| get_peer_info(get_peer_id(format!("node{}", port)), Some(get_addr(port))))
+            .collect();
+        let blacklist = Blacklist::from_lists(&[NamedBlacklist {
+            name: "incident".to_string(),
+            enabled: false,
+            entries: vec![get_addr(1).to_string(), get_addr(2).to_string()],
+        }]);
+        let mut peer_store = PeerStore::new(create_test_store(), &[], blacklist.into()).unwrap();
+        peer_store.add_indirect_peers(infos.iter().cloned()).unwrap();
+        peer_store.peer_connected(&infos[1]).unwrap();
+        assert_eq!(peer_store.len(), 3);
+        assert_eq!(peer_store.blacklist_of(&infos[0]), None);
+
+        assert!(!peer_store.set_blacklist_enabled("abuse", true).unwrap());
+        assert!(peer_store.set_blacklist_enabled("incident", true).unwrap());
+        assert_eq!(peer_store.blacklist_of(&infos[0]), Some("incident"));
+        // The connected peer stays until it's disconnected.
+        assert!(!peer_store.peer_states.contains_key(&infos[0].id));
+        assert!(peer_store.peer_states.contains_key(&infos[1].id));
+        assert!(peer_store.peer_states.contains_key(&infos[2].id));
+
+        assert!(peer_store.set_blacklist_enabled("incident", false).unwrap());
+        assert!(!peer_store.is_blacklisted(&infos[1]));
+    }
+
     #[test]
     fn remove_blacklisted_peers_from_store() {
         let tmp_dir = tempfile::Builder::new()
@@ -784,11 +1203,108 @@ mod test {
             let store = create_store(tmp_dir.path());
             let blacklist =
                 Blacklist::from_iter([format!("{}", peer_infos[2].addr.unwrap())].into_iter());
-            let _peer_store = PeerStore::new(store.clone(), &[], blacklist).unwrap();
+            let _peer_store = PeerStore::new(store.clone(), &[], blacklist.into()).unwrap();
         }
         assert_peers_in_store(tmp_dir.path(), &peer_ids[0..2]);
     }
 
+    fn stored_peer_ids(store: &Store) -> HashSet<PeerId> {
+        store
+            .iter(DBCol::Peers)
+            .map(|(key, _)| PeerId::try_from_slice(key.as_ref()).unwrap())
+            .collect()
+    }
+
+    const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+    #[test]
+    fn evict_over_limit_on_insert() {
+        let infos: Vec<_> = (0..5)
+            .map(|port| get_peer_info(get_peer_id(format!("node{}", port)), Some(get_addr(port))))
+            .collect();
+        let (pinned, banned, expired_ban) = (&infos[0], &infos[1], &infos[2]);
+        let now = to_timestamp(Clock::utc());
+        let store = create_test_store();
+        let mut peer_store = PeerStore::new(store.clone(), &[], Default::default()).unwrap();
+        peer_store.add_indirect_peers(infos[..3].iter().cloned()).unwrap();
+        peer_store.peer_ban(&banned.id, ReasonForBan::Abusive).unwrap();
+        peer_store.import_bans([(expired_ban.clone(), ReasonForBan::Abusive, now - DAY)]).unwrap();
+        for info in &infos[..3] {
+            peer_store.peer_states.get_mut(&info.id).unwrap().last_seen = now - 2 * DAY;
+        }
+        let limits = PeerStoreLimits::new(Some(3), Duration::from_secs(60 * 60), &[pinned.clone()]);
+        peer_store.set_limits(limits).unwrap();
+        assert_eq!(peer_store.len(), 3);
+
+        // The peer whose ban expired is the only old one evictable.
+        let before = metrics::PEER_STORE_EVICTED.get();
+        peer_store.add_indirect_peers([infos[3].clone()].into_iter()).unwrap();
+        assert!(!peer_store.peer_states.contains_key(&expired_ban.id));
+        assert!(metrics::PEER_STORE_EVICTED.get() >= before + 1);
+
+        // The pinned and banned peers survive though seen the longest ago.
+        peer_store.peer_states.get_mut(&infos[3].id).unwrap().last_seen = now - DAY;
+        peer_store.add_direct_peer(infos[4].clone()).unwrap();
+        let expected: HashSet<PeerId> =
+            [&pinned.id, &banned.id, &infos[4].id].into_iter().cloned().collect();
+        assert_eq!(peer_store.peer_states.keys().cloned().collect::<HashSet<_>>(), expected);
+        assert_eq!(stored_peer_ids(&store), expected);
+        assert!(check_integrity(&peer_store));
+    }
+
+    #[test]
+    fn evict_over_limit_in_batches() {
+        let infos: Vec<_> = (0..23)
+            .map(|port| get_peer_info(get_peer_id(format!("node{}", port)), Some(get_addr(port))))
+            .collect();
+        let store = create_test_store();
+        let mut peer_store = PeerStore::new(store.clone(), &[], Default::default()).unwrap();
+        let limits = PeerStoreLimits::new(Some(20), Duration::from_secs(60 * 60), &[]);
+        peer_store.set_limits(limits).unwrap();
+        let now = to_timestamp(Clock::utc());
+        for (i, info) in infos.iter().enumerate() {
+            peer_store.add_indirect_peers([info.clone()].into_iter()).unwrap();
+            peer_store.peer_states.get_mut(&info.id).unwrap().last_seen = now + i as u64;
+            if i < 20 {
+                assert_eq!(peer_store.len(), i + 1);
+            }
+        }
+
+        // Going over the limit evicts down to 18 peers, the three seen the
+        // longest ago, which leaves room for the next two without eviction.
+        assert_eq!(peer_store.len(), 20);
+        let expected: HashSet<PeerId> = infos[3..].iter().map(|info| info.id.clone()).collect();
+        assert_eq!(peer_store.peer_states.keys().cloned().collect::<HashSet<_>>(), expected);
+        assert_eq!(stored_peer_ids(&store), expected);
+        assert!(check_integrity(&peer_store));
+    }
+
+    #[test]
+    fn evict_over_limit_periodically() {
+        let infos: Vec<_> = (0..4)
+            .map(|port| get_peer_info(get_peer_id(format!("node{}", port)), Some(get_addr(port))))
+            .collect();
+        let (pinned, banned) = (&infos[0], &infos[1]);
+        let now = to_timestamp(Clock::utc());
+        let store = create_test_store();
+        let mut peer_store = PeerStore::new(store.clone(), &[], Default::default()).unwrap();
+        peer_store.add_indirect_peers(infos.iter().cloned()).unwrap();
+        peer_store.peer_ban(&banned.id, ReasonForBan::Abusive).unwrap();
+        for info in &infos[..3] {
+            peer_store.peer_states.get_mut(&info.id).unwrap().last_seen = now - DAY;
+        }
+        peer_store.limits =
+            PeerStoreLimits::new(Some(1), Duration::from_secs(60 * 60), &[pinned.clone()]);
+
+        // The expired peer is removed, the fresh one is evicted for the limit,
+        // while the protected ones stay, expired and over the limit.
+        let config = NetworkConfig::from_seed("test", 0);
+        peer_store.remove_expired(&config).unwrap();
+        let expected: HashSet<PeerId> = [&pinned.id, &banned.id].into_iter().cloned().collect();
+        assert_eq!(peer_store.peer_states.keys().cloned().collect::<HashSet<_>>(), expected);
+        assert_eq!(stored_peer_ids(&store), expected);
+    }
+
     fn assert_peers_in_store(store_path: &std::path::Path, expected: &[PeerId]) {
         let store = create_store(store_path);
         let stored_peers: HashSet<PeerId> = HashSet::from_iter(
@@ -807,8 +1323,9 @@ mod test {
         let cached_peers = HashSet::from_iter(peer_store.peer_states.keys());
         assert_eq!(expected_peers, cached_peers);
 
-        let expected_addresses: HashSet<&SocketAddr> = HashSet::from_iter(expected_addresses);
-        let cached_addresses = HashSet::from_iter(peer_store.addr_peers.keys());
+        let expected_addresses: HashSet<NormalizedAddr> =
+            expected_addresses.iter().map(|addr| NormalizedAddr::from(*addr)).collect();
+        let cached_addresses = HashSet::from_iter(peer_store.addr_peers.keys().copied());
         assert_eq!(expected_addresses, cached_addresses);
     }
 
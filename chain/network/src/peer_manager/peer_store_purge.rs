@@ -0,0 +1,174 @@
+//! Removes the stale peers from the peer store of a stopped node, see
+//! `neard network purge-peer-store`.
+//!
+//! The running node counts the peers it loads at start as seen then, so only
+//! the database tells how long ago a peer was last seen.  The peers protected
+//! from eviction, see [`PeerStoreLimits`], are kept whatever their age.
+
+use crate::peer_manager::peer_store::PeerStoreLimits;
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_network_primitives::types::{KnownPeerState, KnownPeerStatus};
+use near_primitives::network::PeerId;
+use near_primitives::time::Clock;
+use near_primitives::utils::to_timestamp;
+use near_store::{DBCol, Store};
+use std::fmt;
+use std::time::Duration;
+
+/// Which peers to remove.
+#[derive(Debug, Clone)]
+pub struct PeerStorePurge {
+    /// Peers last seen within it are kept.
+    pub older_than: Duration,
+    /// Only removes the peers never connected to, i.e. whose status is
+    /// `Unknown`.
+    pub unreachable_only: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PurgeSummary {
+    pub total: usize,
+    /// The removed peers never connected to.
+    pub removed_unreachable: usize,
+    /// The removed peers connected to before.
+    pub removed_not_connected: usize,
+    /// The removed peers whose ban expired.
+    pub removed_banned: usize,
+    /// The peers kept though they're stale, since they're protected.
+    pub protected: usize,
+}
+
+impl PurgeSummary {
+    pub fn removed(&self) -> usize {
+        self.removed_unreachable + self.removed_not_connected + self.removed_banned
+    }
+}
+
+impl fmt::Display for PurgeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Removed {} of {} peers:", self.removed(), self.total)?;
+        writeln!(f, "  {} never connected to", self.removed_unreachable)?;
+        writeln!(f, "  {} connected to before", self.removed_not_connected)?;
+        writeln!(f, "  {} with an expired ban", self.removed_banned)?;
+        write!(
+            f,
+            "Kept {} stale peers which are connected, whitelisted, pinned or still banned",
+            self.protected
+        )
+    }
+}
+
+impl PeerStorePurge {
+    /// Removes the stale peers from the peer store of the database, then
+    /// compacts the column.
+    ///
+    /// The node must not be running, it reads the peer store only on start.
+    pub fn run(&self, store: &Store, limits: &PeerStoreLimits) -> anyhow::Result<PurgeSummary> {
+        let now = to_timestamp(Clock::utc());
+        let older_than = self.older_than.as_nanos();
+        let mut summary = PurgeSummary::default();
+        let mut store_update = store.store_update();
+        for (key, value) in store.iter(DBCol::Peers) {
+            let peer_id = PeerId::try_from_slice(&key)?;
+            let peer_state = KnownPeerState::try_from_slice(&value)?;
+            summary.total += 1;
+            let stale = u128::from(now.saturating_sub(peer_state.last_seen)) > older_than
+                && (!self.unreachable_only || peer_state.status == KnownPeerStatus::Unknown);
+            if !stale {
+                continue;
+            }
+            if limits.is_protected(&peer_id, &peer_state, now) {
+                summary.protected += 1;
+                continue;
+            }
+            match peer_state.status {
+                KnownPeerStatus::Unknown => summary.removed_unreachable += 1,
+                KnownPeerStatus::Banned(_, _) => summary.removed_banned += 1,
+                _ => summary.removed_not_connected += 1,
+            }
+            store_update.delete(DBCol::Peers, &peer_id.try_to_vec()?);
+        }
+        store_update.commit()?;
+        if let Some(rocksdb) = store.get_rocksdb() {
+            rocksdb.compact_column(DBCol::Peers);
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+    use near_network_primitives::types::{PeerInfo, ReasonForBan};
+    use near_store::test_utils::create_test_store;
+    use std::collections::HashSet;
+
+    const HOUR: u64 = 60 * 60 * 1_000_000_000;
+
+    fn add_peer(store: &Store, seed: &str, status: KnownPeerStatus, last_seen: u64) -> PeerInfo {
+        let peer_info = PeerInfo {
+            id: PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key()),
+            addr: None,
+            account_id: None,
+        };
+        let peer_state =
+            KnownPeerState { peer_info: peer_info.clone(), status, first_seen: 0, last_seen };
+        let mut store_update = store.store_update();
+        store_update
+            .set_ser(DBCol::Peers, &peer_info.id.try_to_vec().unwrap(), &peer_state)
+            .unwrap();
+        store_update.commit().unwrap();
+        peer_info
+    }
+
+    fn stored_peers(store: &Store) -> HashSet<PeerId> {
+        store.iter(DBCol::Peers).map(|(key, _)| PeerId::try_from_slice(&key).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_purge_peer_store() {
+        let now = to_timestamp(Clock::utc());
+        let old = now - 48 * HOUR;
+        let store = create_test_store();
+        let unreachable = add_peer(&store, "unreachable", KnownPeerStatus::Unknown, old);
+        add_peer(&store, "not_connected", KnownPeerStatus::NotConnected, old);
+        add_peer(&store, "expired_ban", KnownPeerStatus::Banned(ReasonForBan::Abusive, old), old);
+        let banned = add_peer(
+            &store,
+            "banned",
+            KnownPeerStatus::Banned(ReasonForBan::Abusive, now - HOUR),
+            old,
+        );
+        let pinned = add_peer(&store, "pinned", KnownPeerStatus::Unknown, old);
+        let recent = add_peer(&store, "recent", KnownPeerStatus::Unknown, now - HOUR);
+        let limits =
+            PeerStoreLimits::new(None, Duration::from_secs(3 * 60 * 60), &[pinned.clone()]);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        let purge = PeerStorePurge { older_than: day, unreachable_only: true };
+        let summary = purge.run(&store, &limits).unwrap();
+        assert_eq!(
+            summary,
+            PurgeSummary { total: 6, removed_unreachable: 1, protected: 1, ..Default::default() }
+        );
+        assert!(!stored_peers(&store).contains(&unreachable.id));
+
+        let purge = PeerStorePurge { older_than: day, unreachable_only: false };
+        let summary = purge.run(&store, &limits).unwrap();
+        assert_eq!(
+            summary,
+            PurgeSummary {
+                total: 5,
+                removed_not_connected: 1,
+                removed_banned: 1,
+                protected: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(summary.removed(), 2);
+        let expected: HashSet<PeerId> =
+            [&banned.id, &pinned.id, &recent.id].into_iter().cloned().collect();
+        assert_eq!(stored_peers(&store), expected);
+    }
+}
@@ -0,0 +1,171 @@
+//! Peers pinned in `network.pinned_peers`, e.g. the own infrastructure of an
+//! operator, which the node stays connected to regardless of its peer limits.
+//!
+//! The connections with pinned peers aren't counted against `max_num_peers`
+//! and the ideal numbers of connections, they're accepted when the node is
+//! full and never stopped to make room.  A pinned peer the node isn't
+//! connected to is dialed again right away when it disconnects, and then with
+//! a backoff doubling from `RECONNECT_BACKOFF_MIN` up to
+//! `RECONNECT_BACKOFF_MAX` until it connects.  Bans still apply to pinned
+//! peers, and a pinned peer can't be blacklisted, which the config validation
+//! refuses.
+
+use crate::stats::metrics;
+use near_network_primitives::types::{NormalizedAddr, PeerInfo};
+use near_primitives::network::PeerId;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Interval of the checks for pinned peers to reconnect to.
+pub(crate) const RECONNECT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Pinned peer and whether the node is connected to it, for `network_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinnedPeerStatus {
+    pub peer_info: PeerInfo,
+    pub connected: bool,
+}
+
+struct PinnedPeer {
+    peer_info: PeerInfo,
+    connected: bool,
+    /// Delay after the next attempt before the one after it.
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+pub(crate) struct PinnedPeers(Vec<PinnedPeer>);
+
+impl PinnedPeers {
+    pub fn new(peers: &[PeerInfo], now: Instant) -> Self {
+        for peer_info in peers {
+            set_connected_metric(&peer_info.id, false);
+        }
+        Self(
+            peers
+                .iter()
+                .map(|peer_info| PinnedPeer {
+                    peer_info: peer_info.clone(),
+                    connected: false,
+                    backoff: RECONNECT_BACKOFF_MIN,
+                    next_attempt: now,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.0.iter().any(|peer| peer.peer_info.id == *peer_id)
+    }
+
+    /// Whether an inbound connection from the IP may come from a pinned peer,
+    /// before its handshake tells which peer it is.
+    pub fn contains_ip(&self, ip: &IpAddr) -> bool {
+        let ip = NormalizedAddr::from_ip(*ip);
+        self.0.iter().any(|peer| {
+            peer.peer_info
+                .addr
+                .map_or(false, |addr| NormalizedAddr::from(addr).without_port() == ip)
+        })
+    }
+
+    pub fn set_connected(&mut self, peer_id: &PeerId, connected: bool, now: Instant) {
+        if let Some(peer) = self.0.iter_mut().find(|peer| peer.peer_info.id == *peer_id) {
+            peer.connected = connected;
+            peer.backoff = RECONNECT_BACKOFF_MIN;
+            peer.next_attempt = now;
+            set_connected_metric(peer_id, connected);
+        }
+    }
+
+    /// The pinned peers to dial now: those neither connected nor being
+    /// connected to, whose backoff elapsed.  Their backoff is doubled.
+    pub fn to_reconnect(
+        &mut self,
+        now: Instant,
+        is_connecting: impl Fn(&PeerId) -> bool,
+    ) -> Vec<PeerInfo> {
+        let mut peers = vec![];
+        for peer in &mut self.0 {
+            if peer.connected || now < peer.next_attempt || is_connecting(&peer.peer_info.id) {
+                continue;
+            }
+            peer.next_attempt = now + peer.backoff;
+            peer.backoff = (peer.backoff * 2).min(RECONNECT_BACKOFF_MAX);
+            peers.push(peer.peer_info.clone());
+        }
+        peers
+    }
+
+    pub fn statuses(&self) -> Vec<PinnedPeerStatus> {
+        self.0
+            .iter()
+            .map(|peer| PinnedPeerStatus {
+                peer_info: peer.peer_info.clone(),
+                connected: peer.connected,
+            })
+            .collect()
+    }
+}
+
+fn set_connected_metric(peer_id: &PeerId, connected: bool) {
+    metrics::PINNED_PEER_CONNECTED.with_label_values(&[&peer_id.to_string()]).set(connected as i64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+
+    fn peer_info(seed: &str, port: u16) -> PeerInfo {
+        PeerInfo {
+            id: PeerId::new(SecretKey::from_seed(KeyType::ED25519, seed).public_key()),
+            addr: Some(format!("10.0.0.1:{}", port).parse().unwrap()),
+            account_id: None,
+        }
+    }
+
+    fn ids(peers: Vec<PeerInfo>) -> Vec<PeerId> {
+        peers.into_iter().map(|peer_info| peer_info.id).collect()
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let (a, b) = (peer_info("a", 1), peer_info("b", 2));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut pinned = PinnedPeers::new(&[a.clone(), b.clone()], start);
+        assert!(pinned.contains(&a.id));
+        assert!(pinned.contains_ip(&"10.0.0.1".parse().unwrap()));
+        assert!(!pinned.contains_ip(&"10.0.0.2".parse().unwrap()));
+        // The IPv4 address is the same when an inbound connection reports it mapped to IPv6.
+        assert!(pinned.contains_ip(&"::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!pinned.contains_ip(&"::10.0.0.1".parse().unwrap()));
+
+        // `b` is already being connected to.
+        assert_eq!(ids(pinned.to_reconnect(start, |id| *id == b.id)), vec![a.id.clone()]);
+        assert_eq!(ids(pinned.to_reconnect(start, |_| false)), vec![b.id.clone()]);
+        // The backoff of `a` doubles with every attempt: at 1, 3, 7, 15, ...
+        assert!(pinned.to_reconnect(at(0), |id| *id == b.id).is_empty());
+        let mut attempts = vec![];
+        for secs in 1..=100 {
+            if !pinned.to_reconnect(at(secs), |id| *id == b.id).is_empty() {
+                attempts.push(secs);
+            }
+        }
+        assert_eq!(attempts, vec![1, 3, 7, 15, 31, 61, 91]);
+
+        pinned.set_connected(&a.id, true, at(100));
+        assert!(pinned.to_reconnect(at(200), |id| *id == b.id).is_empty());
+        assert_eq!(
+            pinned.statuses().iter().map(|status| status.connected).collect::<Vec<_>>(),
+            vec![true, false]
+        );
+        // Dialed again right away once disconnected, with the backoff reset.
+        pinned.set_connected(&a.id, false, at(300));
+        assert_eq!(ids(pinned.to_reconnect(at(300), |id| *id == b.id)), vec![a.id.clone()]);
+        assert_eq!(ids(pinned.to_reconnect(at(301), |id| *id == b.id)), vec![a.id]);
+    }
+}
@@ -0,0 +1,169 @@
+//! Shards the peers advertise as tracked but fail to serve.
+//!
+//! A peer picked for a request because it claims to track the shard of the
+//! request is expected to answer with the data within [`RESPONSE_TIMEOUT`].  A
+//! request left unanswered or answered without the data counts as a failure
+//! of the peer for the shard, a request answered with the data clears them.
+//! After [`MAX_FAILURES`] failures in a row the claim is discounted: the peer
+//! isn't picked for the shard anymore until it advertises its shards again or
+//! reconnects.
+
+use crate::stats::metrics;
+use near_primitives::hash::CryptoHash;
+use near_primitives::network::PeerId;
+use near_primitives::sharding::ChunkHash;
+use near_primitives::types::ShardId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Time a peer has to answer a request before it counts as a failure.
+pub(crate) const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Failures in a row after which the claim of a peer is discounted.
+pub(crate) const MAX_FAILURES: u32 = 3;
+
+/// A request sent to a peer because it claims to track the shard.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum ClaimedRequest {
+    ChunkParts(ChunkHash),
+    StatePart { sync_hash: CryptoHash, part_id: u64 },
+}
+
+#[derive(Default)]
+struct PeerClaims {
+    /// Requests waiting for an answer, with their shard and when they were sent.
+    pending: HashMap<ClaimedRequest, (ShardId, Instant)>,
+    /// Failures in a row by shard.
+    failures: HashMap<ShardId, u32>,
+}
+
+impl PeerClaims {
+    fn fail(&mut self, peer_id: &PeerId, shard_id: ShardId) {
+        metrics::SHARD_CLAIM_FAILURES.inc();
+        let failures = self.failures.entry(shard_id).or_default();
+        *failures += 1;
+        if *failures == MAX_FAILURES {
+            debug!(target: "network", %peer_id, shard_id, "Discounting the peer's claim to track the shard");
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ShardClaims {
+    peers: HashMap<PeerId, PeerClaims>,
+}
+
+impl ShardClaims {
+    pub(crate) fn requested(
+        &mut self,
+        peer_id: &PeerId,
+        shard_id: ShardId,
+        request: ClaimedRequest,
+        now: Instant,
+    ) {
+        self.peers.entry(peer_id.clone()).or_default().pending.insert(request, (shard_id, now));
+    }
+
+    /// Records the answer of the peer to a request, `served` if it carried
+    /// the data.  Answers to requests which weren't recorded are ignored.
+    pub(crate) fn answered(&mut self, peer_id: &PeerId, request: &ClaimedRequest, served: bool) {
+        let claims = match self.peers.get_mut(peer_id) {
+            Some(claims) => claims,
+            None => return,
+        };
+        if let Some((shard_id, _)) = claims.pending.remove(request) {
+            if served {
+                claims.failures.remove(&shard_id);
+            } else {
+                claims.fail(peer_id, shard_id);
+            }
+        }
+    }
+
+    /// Counts the requests sent more than `RESPONSE_TIMEOUT` before `now` as
+    /// failures.
+    pub(crate) fn expire(&mut self, now: Instant) {
+        for (peer_id, claims) in self.peers.iter_mut() {
+            let expired: Vec<_> = claims
+                .pending
+                .iter()
+                .filter(|(_, (_, sent))| now.saturating_duration_since(*sent) > RESPONSE_TIMEOUT)
+                .map(|(request, (shard_id, _))| (request.clone(), *shard_id))
+                .collect();
+            for (request, shard_id) in expired {
+                claims.pending.remove(&request);
+                claims.fail(peer_id, shard_id);
+            }
+        }
+    }
+
+    pub(crate) fn is_discounted(&self, peer_id: &PeerId, shard_id: ShardId) -> bool {
+        self.peers.get(peer_id).map_or(false, |claims| {
+            claims.failures.get(&shard_id).map_or(false, |&failures| failures >= MAX_FAILURES)
+        })
+    }
+
+    /// Shards of the peer whose claim is discounted, in order.
+    pub(crate) fn discounted(&self, peer_id: &PeerId) -> Vec<ShardId> {
+        let mut shards: Vec<ShardId> = match self.peers.get(peer_id) {
+            Some(claims) => claims
+                .failures
+                .iter()
+                .filter(|(_, &failures)| failures >= MAX_FAILURES)
+                .map(|(&shard_id, _)| shard_id)
+                .collect(),
+            None => vec![],
+        };
+        shards.sort_unstable();
+        shards
+    }
+
+    /// Forgets everything about the peer, when it advertises new shards or
+    /// disconnects.
+    pub(crate) fn reset(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, SecretKey};
+
+    #[test]
+    fn test_shard_claims() {
+        let mut claims = ShardClaims::default();
+        let peer_id = PeerId::new(SecretKey::from_seed(KeyType::ED25519, "peer").public_key());
+        let now = Instant::now();
+        let chunk = |i: u8| ClaimedRequest::ChunkParts(ChunkHash(CryptoHash([i; 32])));
+
+        // Empty answers and timeouts both count.
+        claims.requested(&peer_id, 1, chunk(0), now);
+        claims.answered(&peer_id, &chunk(0), false);
+        claims.requested(&peer_id, 1, chunk(1), now);
+        claims.requested(&peer_id, 2, chunk(2), now);
+        claims.expire(now + RESPONSE_TIMEOUT);
+        assert!(!claims.is_discounted(&peer_id, 1));
+        claims.expire(now + RESPONSE_TIMEOUT * 2);
+        assert!(!claims.is_discounted(&peer_id, 1));
+        assert!(!claims.is_discounted(&peer_id, 2));
+
+        // A served request clears the failures.
+        claims.requested(&peer_id, 1, chunk(3), now);
+        claims.answered(&peer_id, &chunk(3), true);
+        for i in 0..MAX_FAILURES as u8 {
+            let request = ClaimedRequest::StatePart { sync_hash: CryptoHash([i; 32]), part_id: 0 };
+            claims.requested(&peer_id, 1, request.clone(), now);
+            claims.answered(&peer_id, &request, false);
+        }
+        assert!(claims.is_discounted(&peer_id, 1));
+        assert_eq!(claims.discounted(&peer_id), vec![1]);
+        // Answers to requests which weren't sent don't count.
+        claims.answered(&peer_id, &chunk(9), true);
+        assert!(claims.is_discounted(&peer_id, 1));
+
+        claims.reset(&peer_id);
+        assert!(!claims.is_discounted(&peer_id, 1));
+        assert_eq!(claims.discounted(&peer_id), Vec::<ShardId>::new());
+    }
+}
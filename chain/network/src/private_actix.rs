@@ -2,6 +2,7 @@
 /// They are not meant to be used outside.
 use crate::network_protocol::PeerMessage;
 use crate::peer::peer_actor::PeerActor;
+use crate::peer_manager::handshake_refusals::HandshakeRefusalReason;
 use actix::{Addr, Message};
 use conqueue::QueueSender;
 use near_network_primitives::types::{
@@ -35,6 +36,9 @@ pub struct RegisterPeer {
     pub(crate) peer_protocol_version: ProtocolVersion,
     /// A helper data structure for limiting reading, reporting bandwidth stats.
     pub(crate) throttle_controller: ThrottleController,
+    /// How far the clock of the peer is ahead of ours in nanoseconds, if the
+    /// peer sent its time in the handshake.
+    pub(crate) clock_offset: Option<i64>,
 }
 
 /// Addr<PeerActor> doesn't implement `DeepSizeOf` waiting for `deepsize` > 0.2.0.
@@ -47,6 +51,7 @@ impl deepsize::DeepSizeOf for RegisterPeer {
             + self.this_edge_info.deep_size_of_children(context)
             + self.other_edge_info.deep_size_of_children(context)
             + self.peer_protocol_version.deep_size_of_children(context)
+            + self.clock_offset.deep_size_of_children(context)
     }
 }
 
@@ -54,7 +59,7 @@ impl deepsize::DeepSizeOf for RegisterPeer {
 pub enum RegisterPeerResponse {
     Accept(Option<PartialEdgeInfo>),
     InvalidNonce(Box<Edge>),
-    Reject,
+    Reject(HandshakeRefusalReason),
 }
 
 /// Unregister message from Peer to PeerManager.
@@ -7,7 +7,7 @@ use near_primitives::network::{AnnounceAccount, PeerId};
 use near_primitives::time::Clock;
 use near_primitives::types::AccountId;
 use near_store::{DBCol, Store};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::warn;
@@ -43,6 +43,9 @@ pub struct RoutingTableView {
     pong_info: LruCache<usize, (Pong, usize)>,
     /// List of pings sent for which we haven't received any pong yet.
     waiting_pong: LruCache<PeerId, LruCache<usize, Instant>>,
+    /// Connected peers failing the liveness checks, used as next hop only if
+    /// there's no other on the shortest paths.
+    unresponsive: HashSet<PeerId>,
 }
 
 #[derive(Debug)]
@@ -67,6 +70,7 @@ impl RoutingTableView {
             ping_info: LruCache::new(PING_PONG_CACHE_SIZE),
             pong_info: LruCache::new(PING_PONG_CACHE_SIZE),
             waiting_pong: LruCache::new(PING_PONG_CACHE_SIZE),
+            unresponsive: Default::default(),
         }
     }
 
@@ -80,7 +84,9 @@ impl RoutingTableView {
     /// from `source` to `peer_id`.
     fn find_route_from_peer_id(&mut self, peer_id: &PeerId) -> Result<PeerId, FindRouteError> {
         if let Some(routes) = self.peer_forwarding.get(peer_id) {
+            let all_unresponsive = routes.iter().all(|peer_id| self.unresponsive.contains(peer_id));
             match (routes.iter())
+                .filter(|peer_id| all_unresponsive || !self.unresponsive.contains(*peer_id))
                 .map(|peer_id| {
                     (self.route_nonce.get(peer_id).cloned().unwrap_or_default(), peer_id)
                 })
@@ -110,6 +116,15 @@ impl RoutingTableView {
         }
     }
 
+    /// Marks the connected peer as failing the liveness checks or not.
+    pub(crate) fn set_unresponsive(&mut self, peer_id: PeerId, unresponsive: bool) {
+        if unresponsive {
+            self.unresponsive.insert(peer_id);
+        } else {
+            self.unresponsive.remove(&peer_id);
+        }
+    }
+
     pub(crate) fn find_route(&mut self, target: &PeerIdOrHash) -> Result<PeerId, FindRouteError> {
         match target {
             PeerIdOrHash::PeerId(peer_id) => self.find_route_from_peer_id(peer_id),
@@ -1,8 +1,8 @@
 use crate::network_protocol::Encoding;
 use near_metrics::{
-    do_create_int_counter_vec, try_create_histogram, try_create_int_counter,
-    try_create_int_counter_vec, try_create_int_gauge, Histogram, IntCounter, IntCounterVec,
-    IntGauge, IntGaugeVec,
+    do_create_int_counter_vec, try_create_histogram, try_create_histogram_vec,
+    try_create_int_counter, try_create_int_counter_vec, try_create_int_gauge, Histogram,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
 };
 use near_network_primitives::types::{PeerType, RoutedMessageBody};
 use once_cell::sync::Lazy;
@@ -28,6 +28,13 @@ pub fn set_peer_connections(values: HashMap<(PeerType, Option<Encoding>), i64>)
 pub static PEER_CONNECTIONS_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
     try_create_int_gauge("near_peer_connections_total", "Number of connected peers").unwrap()
 });
+pub static CLOCK_SKEW: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge(
+        "near_clock_skew_milliseconds",
+        "Estimated skew of the local clock against the median clock of the peers, positive if the local clock is ahead",
+    )
+    .unwrap()
+});
 pub static PEER_DATA_RECEIVED_BYTES: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter("near_peer_data_received_bytes", "Total data received from peers")
         .unwrap()
@@ -55,6 +62,21 @@ pub static PEER_MESSAGE_RECEIVED_BY_TYPE_TOTAL: Lazy<IntCounterVec> = Lazy::new(
     )
     .unwrap()
 });
+pub static PEER_MESSAGE_OVERSIZED_BY_TYPE_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_message_oversized_by_type_total",
+        "Number of messages from peers dropped for being above the size limit of their type",
+        &["type"],
+    )
+    .unwrap()
+});
+pub static PEER_LIVENESS_DISCONNECTS_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_liveness_disconnects_total",
+        "Number of peers disconnected for not answering the liveness pings",
+    )
+    .unwrap()
+});
 pub static PEER_CLIENT_MESSAGE_RECEIVED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     try_create_int_counter(
         "near_peer_client_message_received_total",
@@ -70,6 +92,52 @@ pub static PEER_CLIENT_MESSAGE_RECEIVED_BY_TYPE_TOTAL: Lazy<IntCounterVec> = Laz
     )
     .unwrap()
 });
+pub static PEER_MESSAGE_QUEUEING_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_peer_message_queueing_delay_seconds",
+        "Time the messages for the client and the view client waited behind the expensive \
+         messages of the same peer, by cost class",
+        &["class"],
+        Some(vec![0.001, 0.01, 0.05, 0.1, 0.5, 1., 2., 5., 10.]),
+    )
+    .unwrap()
+});
+pub static PEER_MESSAGES_DROPPED_BY_CLASS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_messages_dropped_by_class_total",
+        "Number of messages dropped because too many expensive messages of the same peer \
+         were waiting, by cost class",
+        &["class"],
+    )
+    .unwrap()
+});
+pub static PEER_MESSAGE_COMPRESSION_SAVED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_message_compression_saved_bytes_total",
+        "Bytes saved by compressing the messages sent to the peers, by message type",
+        &["type"],
+    )
+    .unwrap()
+});
+pub static PEER_MESSAGE_COMPRESSION_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_peer_message_compression_seconds",
+        "Time spent compressing the messages sent to the peers, including the ones sent \
+         uncompressed for not getting smaller, by message type",
+        &["type"],
+        Some(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.]),
+    )
+    .unwrap()
+});
+pub static PEER_MESSAGE_DECOMPRESSION_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_peer_message_decompression_seconds",
+        "Time spent decompressing the messages received from the peers, by kind of message",
+        &["kind"],
+        Some(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.]),
+    )
+    .unwrap()
+});
 pub static REQUEST_COUNT_BY_TYPE_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
     try_create_int_counter_vec(
         "near_requests_count_by_type_total",
@@ -113,6 +181,72 @@ pub static RECEIVED_INFO_ABOUT_ITSELF: Lazy<IntCounter> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static HANDSHAKE_REFUSED: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_handshake_refused_total",
+        "Number of handshakes refused by this node, by reason",
+        &["reason"],
+    )
+    .unwrap()
+});
+pub static SHARD_CLAIM_FAILURES: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_shard_claim_failures_total",
+        "Number of requests for the data of a shard that a peer claiming to track it \
+         didn't serve in time",
+    )
+    .unwrap()
+});
+pub static PEER_EXCHANGE_BLACKLISTED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_exchange_blacklisted_total",
+        "Number of peers learned from other peers or DNS seeds which were ignored since their address is blacklisted",
+    )
+    .unwrap()
+});
+pub static PEER_BLACKLISTED_BY_LIST: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_blacklisted_by_list_total",
+        "Number of peers refused or ignored since their address is blacklisted, by the list \
+         they're on and by where they came from: a handshake or the peer exchange",
+        &["list", "source"],
+    )
+    .unwrap()
+});
+pub static PEER_BLACKLIST_AUDIT_MATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_peer_blacklist_audit_matches_total",
+        "Number of connections accepted from peers an entry of network.blacklist_audit would \
+         refuse, by entry",
+        &["entry"],
+    )
+    .unwrap()
+});
+pub static PEER_DIALS_SKIPPED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_dials_skipped_total",
+        "Number of known peers passed over when picking a peer to dial since their address is backing off after failed dials",
+    )
+    .unwrap()
+});
+pub static PEER_STORE_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    try_create_int_gauge("near_peer_store_size", "Number of peers known to the peer store").unwrap()
+});
+pub static PEER_STORE_EVICTED: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_peer_store_evicted_total",
+        "Number of peers removed from the peer store since it was over max_peer_store_entries",
+    )
+    .unwrap()
+});
+pub static PINNED_PEER_CONNECTED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge_vec(
+        "near_pinned_peer_connected",
+        "Whether this node is connected to the pinned peer, 0 or 1",
+        &["peer_id"],
+    )
+    .unwrap()
+});
 static DROPPED_MESSAGE_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     near_metrics::try_create_int_counter_vec(
         "near_dropped_message_by_type_and_reason_count",
@@ -21,6 +21,8 @@ use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 use tracing::debug;
 
+pub mod replay;
+
 static OPENED_PORTS: Lazy<Mutex<HashSet<u16>>> = Lazy::new(|| Mutex::new(HashSet::new()));
 
 /// Returns available port.
@@ -433,6 +435,23 @@ pub struct SetAdvOptions {
     pub set_max_peers: Option<u64>,
 }
 
+/// Replaces the faults injected into the messages received from peers, see
+/// [`near_network_primitives::types::NetworkFault`].  An empty list stops
+/// injecting faults.
+#[cfg(feature = "test_features")]
+#[derive(Message, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct SetNetworkFaults {
+    pub faults: Vec<near_network_primitives::types::NetworkFault>,
+}
+
+#[cfg(all(feature = "test_features", feature = "deepsize_feature"))]
+impl deepsize::DeepSizeOf for SetNetworkFaults {
+    fn deep_size_of_children(&self, _context: &mut deepsize::Context) -> usize {
+        self.faults.capacity() * std::mem::size_of::<near_network_primitives::types::NetworkFault>()
+    }
+}
+
 #[cfg(feature = "test_features")]
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(Message, Clone, Debug)]
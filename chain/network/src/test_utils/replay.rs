@@ -0,0 +1,249 @@
+//! Replay of a log recorded with `network.message_recorder` against a fresh
+//! peer manager, to reproduce the decisions the peer manager of a node made
+//! and debug them.
+//!
+//! The peer manager is started with the key of the recorded node, for the
+//! handshakes and edges signed by its peers to stay valid, and with the
+//! outbound connections disabled: the replay makes every recorded connection
+//! to it, as an inbound one, and sends the messages received on it in their
+//! order.  Time is virtual: the recorded delays aren't waited for, the replay
+//! goes on as soon as the peer manager caught up with the records.  After every
+//! peer the recorded peer manager registered or removed, the replay waits for
+//! the replayed one to be connected to the same peers, and at the end of the
+//! log for it to compute the routes recorded last.  It fails if the peer
+//! manager doesn't get there within `REPLAY_TIMEOUT`.
+//!
+//! The handshake failures and the last edges, which only start outbound
+//! connections over, and the redacted messages aren't replayed.
+
+use crate::network_protocol::{Encoding, PeerMessage};
+use crate::peer_manager::message_recorder::{read_log, sorted_routes, RecordedEvent};
+use crate::routing::routing_table_actor::start_routing_table_actor;
+use crate::test_utils::{open_port, GetInfo};
+use crate::types::{
+    NetworkClientMessages, NetworkClientResponses, NetworkRequests, NetworkResponses,
+    PeerManagerMessageRequest,
+};
+use crate::PeerManagerActor;
+use actix::actors::mocker::Mocker;
+use actix::{Actor, Addr};
+use anyhow::{anyhow, bail, Context};
+use near_network_primitives::types::{
+    NetworkConfig, NetworkViewClientMessages, NetworkViewClientResponses,
+};
+use near_primitives::block::GenesisId;
+use near_primitives::network::PeerId;
+use near_store::test_utils::create_test_store;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+
+/// Time the peer manager has to reach each recorded state.
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Connections and routes of a peer manager, as recorded or replayed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReplayState {
+    pub connected_peers: BTreeSet<PeerId>,
+    /// Next hops to each reachable peer, sorted.
+    pub routes: Vec<(PeerId, Vec<PeerId>)>,
+}
+
+/// Replays the log at `path`, with its rotated files, against a fresh peer
+/// manager with `config`, the network config of the recorded node.  Returns
+/// the state the peer manager reached, the recorded one, with the routes it
+/// computed if none were recorded.
+pub fn replay(path: &Path, config: NetworkConfig) -> anyhow::Result<ReplayState> {
+    let system = actix::System::new();
+    let result = system.block_on(replay_in_system(path, config));
+    actix::System::current().stop();
+    result
+}
+
+async fn replay_in_system(path: &Path, mut config: NetworkConfig) -> anyhow::Result<ReplayState> {
+    let records = read_log(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let genesis_id = records
+        .iter()
+        .find_map(|record| match &record.event {
+            RecordedEvent::Received { message, .. } => {
+                match PeerMessage::deserialize(Encoding::Borsh, message) {
+                    Ok(PeerMessage::Handshake(handshake)) => {
+                        Some(handshake.sender_chain_info.genesis_id)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("The log has no handshake to tell the genesis of the chain"))?;
+
+    let addr: SocketAddr = format!("127.0.0.1:{}", open_port()).parse().unwrap();
+    config.addr = Some(addr);
+    config.boot_nodes.clear();
+    config.dns_seeds.clear();
+    config.outbound_disabled = true;
+    config.message_recorder = None;
+    let peer_manager = start_peer_manager(config, genesis_id)?;
+    wait_for_listener(addr).await?;
+
+    let mut connections: HashMap<u64, OwnedWriteHalf> = HashMap::new();
+    let mut recorded = ReplayState::default();
+    let mut routes_recorded = false;
+    for record in records {
+        match record.event {
+            RecordedEvent::Received { redacted: true, .. } => {}
+            RecordedEvent::Received { connection, message, .. } => {
+                let msg = PeerMessage::deserialize(Encoding::Borsh, &message).map_err(|err| {
+                    anyhow!("Invalid message at {}µs: {}", record.elapsed_micros, err)
+                })?;
+                if matches!(msg, PeerMessage::HandshakeFailure(..) | PeerMessage::LastEdge(_)) {
+                    continue;
+                }
+                if !connections.contains_key(&connection) {
+                    connections.insert(connection, connect(addr).await?);
+                }
+                let writer = connections.get_mut(&connection).unwrap();
+                writer.write_u32_le(message.len() as u32).await?;
+                writer.write_all(&message).await?;
+            }
+            RecordedEvent::Closed { connection } => {
+                connections.remove(&connection);
+            }
+            RecordedEvent::PeerConnected(peer_id) => {
+                recorded.connected_peers.insert(peer_id);
+                wait_for_connected_peers(&peer_manager, &recorded, record.elapsed_micros).await?;
+            }
+            RecordedEvent::PeerDisconnected(peer_id) => {
+                recorded.connected_peers.remove(&peer_id);
+                wait_for_connected_peers(&peer_manager, &recorded, record.elapsed_micros).await?;
+            }
+            RecordedEvent::RoutingTable(routes) => {
+                recorded.routes = routes;
+                routes_recorded = true;
+            }
+        }
+    }
+    if routes_recorded {
+        wait_until(&recorded.routes, || routes(&peer_manager))
+            .await
+            .context("Routes differ at the end of the log")?;
+    } else {
+        recorded.routes = routes(&peer_manager).await?;
+    }
+    Ok(recorded)
+}
+
+fn start_peer_manager(
+    config: NetworkConfig,
+    genesis_id: GenesisId,
+) -> anyhow::Result<Addr<PeerManagerActor>> {
+    let client_addr = Mocker::<NetworkClientMessages>::mock(Box::new(|_msg, _ctx| {
+        Box::new(Some(NetworkClientResponses::NoResponse))
+    }))
+    .start();
+    let view_client_addr = Mocker::<NetworkViewClientMessages>::mock(Box::new(move |msg, _ctx| {
+        let msg = msg.downcast_ref::<NetworkViewClientMessages>().unwrap();
+        match msg {
+            NetworkViewClientMessages::GetChainInfo => {
+                Box::new(Some(NetworkViewClientResponses::ChainInfo {
+                    genesis_id: genesis_id.clone(),
+                    height: 1,
+                    tracked_shards: vec![],
+                    archival: false,
+                }))
+            }
+            NetworkViewClientMessages::AnnounceAccount(accounts) => {
+                Box::new(Some(NetworkViewClientResponses::AnnounceAccount(
+                    accounts.iter().map(|(account, _)| account.clone()).collect(),
+                )))
+            }
+            _ => Box::new(Some(NetworkViewClientResponses::NoResponse)),
+        }
+    }))
+    .start();
+    let store = create_test_store();
+    let routing_table_addr =
+        start_routing_table_actor(PeerId::new(config.public_key.clone()), store.clone());
+    let peer_manager = PeerManagerActor::new(
+        store,
+        config,
+        client_addr.recipient(),
+        view_client_addr.recipient(),
+        routing_table_addr,
+    )?;
+    Ok(peer_manager.start())
+}
+
+async fn wait_for_listener(addr: SocketAddr) -> anyhow::Result<()> {
+    let deadline = Instant::now() + REPLAY_TIMEOUT;
+    while TcpStream::connect(addr).await.is_err() {
+        if Instant::now() > deadline {
+            bail!("The peer manager doesn't listen on {}", addr);
+        }
+        actix::clock::sleep(POLL_INTERVAL).await;
+    }
+    Ok(())
+}
+
+/// Opens a connection to the peer manager, whatever it sends is discarded.
+async fn connect(addr: SocketAddr) -> anyhow::Result<OwnedWriteHalf> {
+    let (mut reader, writer) = TcpStream::connect(addr).await?.into_split();
+    actix::spawn(async move {
+        let _ = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await;
+    });
+    Ok(writer)
+}
+
+async fn connected_peers(
+    peer_manager: &Addr<PeerManagerActor>,
+) -> anyhow::Result<BTreeSet<PeerId>> {
+    let info = peer_manager.send(GetInfo {}).await?;
+    Ok(info.connected_peers.into_iter().map(|peer| peer.peer_info.id).collect())
+}
+
+async fn wait_for_connected_peers(
+    peer_manager: &Addr<PeerManagerActor>,
+    recorded: &ReplayState,
+    elapsed_micros: u64,
+) -> anyhow::Result<()> {
+    wait_until(&recorded.connected_peers, || connected_peers(peer_manager))
+        .await
+        .with_context(|| format!("Connected peers differ at {}µs", elapsed_micros))
+}
+
+async fn routes(
+    peer_manager: &Addr<PeerManagerActor>,
+) -> anyhow::Result<Vec<(PeerId, Vec<PeerId>)>> {
+    let request = PeerManagerMessageRequest::NetworkRequests(NetworkRequests::FetchRoutingTable);
+    match peer_manager.send(request).await?.as_network_response() {
+        NetworkResponses::RoutingTableInfo(info) => Ok(sorted_routes(&info.peer_forwarding)),
+        response => bail!("Unexpected response {:?}", response),
+    }
+}
+
+/// Polls `get` until it returns `expected`.
+async fn wait_until<T, F, Fut>(expected: &T, mut get: F) -> anyhow::Result<()>
+where
+    T: PartialEq + Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let deadline = Instant::now() + REPLAY_TIMEOUT;
+    loop {
+        let replayed = get().await?;
+        if replayed == *expected {
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            bail!("recorded {:?}, replayed {:?}", expected, replayed);
+        }
+        actix::clock::sleep(POLL_INTERVAL).await;
+    }
+}
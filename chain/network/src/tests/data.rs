@@ -17,7 +17,7 @@ use near_primitives::sharding::{
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, BlockHeight, EpochId, StateRoot};
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use rand::distributions::Standard;
 use rand::Rng;
 use std::collections::HashMap;
@@ -62,6 +62,7 @@ pub fn make_block(
         CryptoHash::default(), // next_bp_hash
         CryptoHash::default(), // block_merkle_root
         Some(clock.utc_now()), // timestamp_override
+        BuildId::default(),    // producer_build_id
     )
 }
 
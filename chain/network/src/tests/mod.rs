@@ -3,6 +3,7 @@ mod data;
 mod network_protocol;
 mod peer_actor;
 mod peer_communication;
+mod replay;
 mod stream;
 mod util;
 
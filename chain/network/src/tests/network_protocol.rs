@@ -1,16 +1,20 @@
-use crate::network_protocol::Encoding;
+use crate::network_protocol::{Encoding, MessageSizeLimits, PeerMessageKind};
+use crate::peer::codec::{Codec, FrameError};
 use crate::tests::data;
 use crate::tests::util::{make_rng, FakeClock};
 use crate::types::{HandshakeFailureReason, PeerMessage};
 use anyhow::{bail, Context as _};
+use bytes::BytesMut;
 use near_network_primitives::types::{
     PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg, RoutedMessageBody,
 };
 use near_primitives::syncing::EpochSyncResponse;
 use near_primitives::types::EpochId;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio_util::codec::{Decoder, Encoder};
 
-#[test]
-fn serialize_deserialize() -> anyhow::Result<()> {
+fn make_msgs() -> Vec<PeerMessage> {
     let mut rng = make_rng(89028037453);
     let mut clock = FakeClock::default();
 
@@ -37,7 +41,7 @@ fn serialize_deserialize() -> anyhow::Result<()> {
             receipts: vec![],
         }),
     );
-    let msgs = [
+    vec![
         PeerMessage::Handshake(data::make_handshake(&mut rng, &chain)),
         PeerMessage::HandshakeFailure(
             data::make_peer_info(&mut rng),
@@ -63,7 +67,13 @@ fn serialize_deserialize() -> anyhow::Result<()> {
         PeerMessage::EpochSyncFinalizationRequest(epoch_id.clone()),
         // TODO: EpochSyncFinalizationResponse
         // TODO: RoutingTableSyncV2,
-    ];
+        PeerMessage::TrackedShards(vec![0, 2]),
+    ]
+}
+
+#[test]
+fn serialize_deserialize() -> anyhow::Result<()> {
+    let msgs = make_msgs();
 
     // Check that serialize;deserialize = 1
     for enc in [Encoding::Proto, Encoding::Borsh] {
@@ -86,7 +96,7 @@ fn serialize_deserialize() -> anyhow::Result<()> {
         let x = m.serialize(Encoding::Proto);
         assert!(x[0] >= 32, "serialize({},PROTO)[0] = {:?}, want >= 32", m, x.get(0));
         let y = m.serialize(Encoding::Borsh);
-        assert!(y[0] <= 21, "serialize({},BORSH)[0] = {:?}, want <= 21", m, y.get(0));
+        assert!(y[0] <= 22, "serialize({},BORSH)[0] = {:?}, want <= 22", m, y.get(0));
     }
 
     // Encodings should never be compatible.
@@ -104,3 +114,43 @@ fn serialize_deserialize() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn oversized_messages() {
+    for enc in [Encoding::Proto, Encoding::Borsh] {
+        for m in &make_msgs() {
+            let bytes = m.serialize(enc);
+            assert_eq!(
+                PeerMessageKind::from_encoded(&bytes),
+                Some(m.kind()),
+                "{m}, encoding={enc:?}"
+            );
+
+            // A frame one byte above the limit of its kind is dropped, and
+            // the next one is read.
+            let kind: &str = m.kind().into();
+            let next = match m {
+                PeerMessage::Disconnect => PeerMessage::PeersRequest,
+                _ => PeerMessage::Disconnect,
+            };
+            let overrides = HashMap::from([(kind.to_string(), bytes.len() - 1)]);
+            let mut codec = Codec::new(Arc::new(MessageSizeLimits::new(&overrides).unwrap()));
+            let mut buffer = BytesMut::new();
+            codec.encode(bytes.clone(), &mut buffer).unwrap();
+            codec.encode(next.serialize(enc), &mut buffer).unwrap();
+            assert_eq!(
+                codec.decode(&mut buffer).unwrap(),
+                Some(Err(FrameError::Oversized { kind: Some(m.kind()), len: bytes.len() })),
+                "{m}, encoding={enc:?}"
+            );
+            let decoded = codec.decode(&mut buffer).unwrap().unwrap().unwrap();
+            assert_eq!(PeerMessage::deserialize(enc, &decoded).unwrap(), next);
+
+            // At the limit it's read as usual.
+            let overrides = HashMap::from([(kind.to_string(), bytes.len())]);
+            let mut codec = Codec::new(Arc::new(MessageSizeLimits::new(&overrides).unwrap()));
+            codec.encode(bytes.clone(), &mut buffer).unwrap();
+            assert_eq!(codec.decode(&mut buffer).unwrap(), Some(Ok(bytes)));
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::peer::codec::Codec;
+use crate::peer::liveness::LivenessConfig;
 use crate::peer::peer_actor::PeerActor;
 use crate::private_actix::{PeerRequestResult, RegisterPeerResponse, SendMessage};
 use crate::tests::actix::ActixSystem;
@@ -288,8 +289,14 @@ impl PeerHandle {
                     cfg.start_handshake_with.as_ref().map(|id| cfg.partial_edge_info(id, 1)),
                     Arc::new(AtomicUsize::new(0)),
                     Arc::new(AtomicUsize::new(0)),
+                    Default::default(),
                     rate_limiter,
                     cfg.force_encoding,
+                    LivenessConfig {
+                        ping_after: time::Duration::from_secs(30),
+                        timeout: time::Duration::from_secs(15),
+                        max_missed: 0,
+                    },
                 )
             })
         })
@@ -0,0 +1,59 @@
+use crate::peer_manager::message_recorder::{MessageRecorder, RecordedEvent};
+use crate::test_utils::replay::replay;
+use crate::tests::data;
+use crate::tests::peer_actor::PeerConfig;
+use crate::tests::util::{make_rng, FakeClock};
+use crate::types::{Handshake, PeerMessage};
+use near_network_primitives::types::{MessageRecorderConfig, NetworkConfig};
+use near_primitives::network::PeerId;
+use near_primitives::version::{PEER_MIN_ALLOWED_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use std::sync::Arc;
+
+#[test]
+// Verifies that a recorded connection is made again by the replay.
+fn replay_connection() {
+    let mut rng = make_rng(921853233);
+    let mut clock = FakeClock::default();
+    let chain = Arc::new(data::Chain::make(&mut clock, &mut rng, 12));
+    let node_config = NetworkConfig::from_seed("node", 0);
+    let node_id = PeerId::new(node_config.public_key.clone());
+    let peer = PeerConfig {
+        signer: data::make_signer(&mut rng),
+        chain: chain.clone(),
+        peers: vec![],
+        start_handshake_with: None,
+        force_encoding: None,
+    };
+    let handshake = Handshake {
+        protocol_version: PROTOCOL_VERSION,
+        oldest_supported_version: PEER_MIN_ALLOWED_PROTOCOL_VERSION,
+        sender_peer_id: peer.id(),
+        target_peer_id: node_id.clone(),
+        sender_listen_port: None,
+        sender_chain_info: chain.get_info(),
+        partial_edge_info: peer.partial_edge_info(&node_id, 1),
+        sender_timestamp: None,
+        accepts_compression: false,
+    };
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("messages.log");
+    let recorder = MessageRecorder::new(MessageRecorderConfig {
+        path: path.clone(),
+        max_file_bytes: 1 << 20,
+        max_files: 2,
+    })
+    .unwrap();
+    let connection = recorder.new_connection();
+    recorder.record_received(connection, None, &PeerMessage::Handshake(handshake));
+    recorder.record(RecordedEvent::PeerConnected(peer.id()));
+    recorder.record(RecordedEvent::RoutingTable(vec![(peer.id(), vec![peer.id()])]));
+    let state = replay(&path, node_config.clone()).unwrap();
+    assert_eq!(state.connected_peers.into_iter().collect::<Vec<_>>(), vec![peer.id()]);
+    assert_eq!(state.routes, vec![(peer.id(), vec![peer.id()])]);
+
+    recorder.record(RecordedEvent::Closed { connection });
+    recorder.record(RecordedEvent::PeerDisconnected(peer.id()));
+    let state = replay(&path, node_config).unwrap();
+    assert!(state.connected_peers.is_empty());
+}
@@ -1,8 +1,12 @@
 /// Type that belong to the network protocol.
 pub use crate::network_protocol::{
-    Encoding, Handshake, HandshakeFailureReason, PeerMessage, RoutingTableUpdate,
+    Encoding, Handshake, HandshakeFailureReason, MessageSizeLimits, PeerMessage, PeerMessageKind,
+    RoutingTableUpdate,
 };
 pub use crate::network_protocol::{PartialSync, RoutingState, RoutingSyncV2, RoutingVersion2};
+use crate::peer_manager::blacklist_audit::AuditedPeer;
+use crate::peer_manager::handshake_refusals::HandshakeRefusal;
+use crate::peer_manager::pinned_peers::PinnedPeerStatus;
 use crate::private_actix::{
     PeerRequestResult, PeersRequest, RegisterPeer, RegisterPeerResponse, Unregister,
 };
@@ -11,9 +15,10 @@ use actix::{MailboxError, Message};
 use futures::future::BoxFuture;
 use near_network_primitives::types::{
     AccountIdOrPeerTrackingShard, AccountOrPeerIdOrHash, Ban, Edge, InboundTcpConnect,
-    KnownProducer, OutboundTcpConnect, PartialEdgeInfo, PartialEncodedChunkForwardMsg,
-    PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg, PeerChainInfoV2, PeerInfo, Ping,
-    Pong, ReasonForBan, RoutedMessageBody, RoutedMessageFrom, StateResponseInfo,
+    KnownProducer, NormalizedAddr, OutboundTcpConnect, PartialEdgeInfo,
+    PartialEncodedChunkForwardMsg, PartialEncodedChunkRequestMsg, PartialEncodedChunkResponseMsg,
+    PeerChainInfoV2, PeerInfo, Ping, Pong, ReasonForBan, RoutedMessageBody, RoutedMessageFrom,
+    SetBlacklistsEnabled, StateResponseInfo,
 };
 use near_primitives::block::{Approval, ApprovalMessage, Block, BlockHeader};
 use near_primitives::challenge::Challenge;
@@ -25,7 +30,10 @@ use near_primitives::syncing::{EpochSyncFinalizationResponse, EpochSyncResponse}
 use near_primitives::time::Instant;
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, BlockReference, EpochId, ShardId};
-use near_primitives::views::{KnownProducerView, NetworkInfoView, PeerInfoView, QueryRequest};
+use near_primitives::views::{
+    AuditedPeerView, DnsSeedPeerView, HandshakeRefusalView, KnownProducerView, NetworkInfoView,
+    PeerInfoView, PinnedPeerView, QueryRequest,
+};
 use std::collections::HashMap;
 use std::fmt::Debug;
 
@@ -59,6 +67,10 @@ pub enum PeerRequest {
     RouteBack(Box<RoutedMessageBody>, CryptoHash),
     UpdatePeerInfo(PeerInfo),
     ReceivedMessage(PeerId, Instant),
+    /// Asks to ping the peer, to check it's still there.
+    Ping(PeerId),
+    /// The peer started or stopped failing the liveness checks.
+    Liveness(PeerId, bool),
 }
 
 #[cfg(feature = "deepsize_feature")]
@@ -71,6 +83,8 @@ impl deepsize::DeepSizeOf for PeerRequest {
             }
             PeerRequest::UpdatePeerInfo(x) => x.deep_size_of_children(context),
             PeerRequest::ReceivedMessage(x, _) => x.deep_size_of_children(context),
+            PeerRequest::Ping(x) => x.deep_size_of_children(context),
+            PeerRequest::Liveness(x, _) => x.deep_size_of_children(context),
         }
     }
 }
@@ -119,6 +133,7 @@ pub enum PeerManagerMessageRequest {
     InboundTcpConnect(InboundTcpConnect),
     Unregister(Unregister),
     Ban(Ban),
+    SetBlacklistsEnabled(SetBlacklistsEnabled),
     #[cfg(feature = "test_features")]
     #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
     StartRoutingTableSync(crate::private_actix::StartRoutingTableSync),
@@ -127,6 +142,8 @@ pub enum PeerManagerMessageRequest {
     #[cfg(feature = "test_features")]
     #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
     SetRoutingTable(crate::test_utils::SetRoutingTable),
+    #[cfg(feature = "test_features")]
+    SetNetworkFaults(crate::test_utils::SetNetworkFaults),
 }
 
 impl PeerManagerMessageRequest {
@@ -162,6 +179,7 @@ pub enum PeerManagerMessageResponse {
     InboundTcpConnect(()),
     Unregister(()),
     Ban(()),
+    SetBlacklistsEnabled(()),
     #[cfg(feature = "test_features")]
     #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
     StartRoutingTableSync(()),
@@ -170,6 +188,8 @@ pub enum PeerManagerMessageResponse {
     #[cfg(feature = "test_features")]
     #[cfg(feature = "protocol_feature_routing_exchange_algorithm")]
     SetRoutingTable(()),
+    #[cfg(feature = "test_features")]
+    SetNetworkFaults(()),
 }
 
 impl PeerManagerMessageResponse {
@@ -362,12 +382,13 @@ impl From<&FullPeerInfo> for PeerInfoView {
     fn from(full_peer_info: &FullPeerInfo) -> Self {
         PeerInfoView {
             addr: match full_peer_info.peer_info.addr {
-                Some(socket_addr) => socket_addr.to_string(),
+                Some(socket_addr) => NormalizedAddr::from(socket_addr).to_string(),
                 None => "N/A".to_string(),
             },
             account_id: full_peer_info.peer_info.account_id.clone(),
             height: full_peer_info.chain_info.height,
             tracked_shards: full_peer_info.chain_info.tracked_shards.clone(),
+            discounted_shards: vec![],
             archival: full_peer_info.chain_info.archival,
             peer_id: full_peer_info.peer_info.id.public_key().clone(),
         }
@@ -385,6 +406,21 @@ pub struct NetworkInfo {
     /// Accounts of known block and chunk producers from routing table.
     pub known_producers: Vec<KnownProducer>,
     pub peer_counter: usize,
+    /// Estimated skew of the local clock against the peers in milliseconds,
+    /// positive if the local clock is ahead.  `None` if too few peers sent
+    /// their time.
+    pub clock_skew_millis: Option<i64>,
+    /// Peers found in the DNS seeds and the seed which listed them.
+    pub dns_seed_peers: HashMap<PeerId, String>,
+    /// Last handshakes refused by this node, most recent first.
+    pub recent_handshake_refusals: Vec<HandshakeRefusal>,
+    /// Peers from `network.pinned_peers` and whether they're connected.
+    pub pinned_peers: Vec<PinnedPeerStatus>,
+    /// Connected peers an entry of `network.blacklist_audit` matches.
+    pub blacklist_audit: Vec<AuditedPeer>,
+    /// Shards the connected peers advertise but failed to serve, see
+    /// `shard_claims`.  The peers are left out of `highest_height_peers` for them.
+    pub discounted_shards: HashMap<PeerId, Vec<ShardId>>,
 }
 
 impl From<NetworkInfo> for NetworkInfoView {
@@ -395,7 +431,14 @@ impl From<NetworkInfo> for NetworkInfoView {
             connected_peers: network_info
                 .connected_peers
                 .iter()
-                .map(|full_peer_info| full_peer_info.into())
+                .map(|full_peer_info| PeerInfoView {
+                    discounted_shards: network_info
+                        .discounted_shards
+                        .get(&full_peer_info.peer_info.id)
+                        .cloned()
+                        .unwrap_or_default(),
+                    ..PeerInfoView::from(full_peer_info)
+                })
                 .collect::<Vec<_>>(),
             known_producers: network_info
                 .known_producers
@@ -409,6 +452,47 @@ impl From<NetworkInfo> for NetworkInfoView {
                         .map(|it| it.iter().map(|peer_id| peer_id.public_key().clone()).collect()),
                 })
                 .collect(),
+            clock_skew_millis: network_info.clock_skew_millis,
+            dns_seed_peers: network_info
+                .dns_seed_peers
+                .iter()
+                .map(|(peer_id, seed)| DnsSeedPeerView {
+                    peer_id: peer_id.public_key().clone(),
+                    seed: seed.clone(),
+                })
+                .collect(),
+            recent_handshake_refusals: network_info
+                .recent_handshake_refusals
+                .iter()
+                .map(|refusal| HandshakeRefusalView {
+                    timestamp: refusal.timestamp,
+                    addr: NormalizedAddr::from(refusal.addr).to_string(),
+                    peer_id: refusal.peer_id.public_key().clone(),
+                    protocol_version: refusal.protocol_version,
+                    reason: refusal.reason.as_ref().to_string(),
+                })
+                .collect(),
+            pinned_peers: network_info
+                .pinned_peers
+                .iter()
+                .map(|status| PinnedPeerView {
+                    peer_id: status.peer_info.id.public_key().clone(),
+                    addr: status
+                        .peer_info
+                        .addr
+                        .map_or("N/A".to_string(), |addr| NormalizedAddr::from(addr).to_string()),
+                    connected: status.connected,
+                })
+                .collect(),
+            blacklist_audit: network_info
+                .blacklist_audit
+                .iter()
+                .map(|audited| AuditedPeerView {
+                    peer_id: audited.peer_id.public_key().clone(),
+                    addr: NormalizedAddr::from(audited.addr).to_string(),
+                    entry: audited.entry.clone(),
+                })
+                .collect(),
         }
     }
 }
@@ -493,6 +577,8 @@ pub enum NetworkClientResponses {
     /// The node being queried does not track the shard needed and therefore cannot provide userful
     /// response.
     DoesNotTrackShard,
+    /// The transaction wasn't added to the mempool because it reached its limits.
+    TxPoolFull { limits: near_chain_configs::TransactionPoolConfig },
     /// Ban peer for malicious behavior.
     Ban { ban_reason: ReasonForBan },
 }
@@ -548,4 +634,25 @@ mod tests {
         assert_size!(FullPeerInfo);
         assert_size!(NetworkInfo);
     }
+
+    #[test]
+    fn test_peer_info_view_addr() {
+        let peer_info = |addr: Option<&str>| FullPeerInfo {
+            peer_info: PeerInfo {
+                id: PeerId::random(),
+                addr: addr.map(|addr| addr.parse().unwrap()),
+                account_id: None,
+            },
+            chain_info: Default::default(),
+            partial_edge_info: Default::default(),
+        };
+        for (addr, view) in [
+            (Some("192.0.2.4:24567"), "192.0.2.4:24567"),
+            (Some("[::ffff:192.0.2.4]:24567"), "192.0.2.4:24567"),
+            (Some("[2001:db8::1]:24567"), "[2001:db8::1]:24567"),
+            (None, "N/A"),
+        ] {
+            assert_eq!(PeerInfoView::from(&peer_info(addr)).addr, view);
+        }
+    }
 }
@@ -0,0 +1,163 @@
+//! Transactions submitted to the node which expired before they were included
+//! in a chunk.
+//!
+//! A transaction can be included until the transaction validity period passed
+//! since the block it's based on.  After that it's never executed, but the
+//! chain knows nothing of it, so its status would stay unknown forever.  The
+//! node remembers the transactions submitted to it with the heights they're
+//! valid at, forgets the ones it sees in a chunk, and turns the others into
+//! expired ones once the head is past their last valid height.  The expired
+//! transactions are kept for [`EXPIRED_TRANSACTION_TTL`], at most
+//! [`MAX_EXPIRED_TRANSACTIONS`] of them.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::BlockHeight;
+
+use crate::metrics;
+
+/// Submitted transactions remembered at most, the ones expiring first are
+/// forgotten to make room.
+pub const MAX_PENDING_TRANSACTIONS: usize = 100_000;
+/// Expired transactions remembered at most, the ones which expired first are
+/// forgotten to make room.
+pub const MAX_EXPIRED_TRANSACTIONS: usize = 100_000;
+/// How long an expired transaction is remembered.
+pub const EXPIRED_TRANSACTION_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Heights of the blocks a transaction can be included after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidityWindow {
+    /// Height of the block the transaction is based on.
+    pub valid_from_height: BlockHeight,
+    /// Last height of the previous block of a chunk including it.
+    pub valid_until_height: BlockHeight,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ExpiredTransaction {
+    window: ValidityWindow,
+    expired_at: Instant,
+}
+
+#[derive(Default)]
+pub struct TransactionExpiry {
+    /// Submitted transactions not seen in a chunk yet.
+    pending: HashMap<CryptoHash, ValidityWindow>,
+    /// Hashes of the submitted transactions by their last valid height.  May
+    /// include transactions which aren't pending any longer.
+    pending_by_height: BTreeMap<BlockHeight, Vec<CryptoHash>>,
+    expired: HashMap<CryptoHash, ExpiredTransaction>,
+    /// Hashes of the expired transactions, in the order they expired.
+    expired_order: VecDeque<CryptoHash>,
+}
+
+impl TransactionExpiry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers a transaction submitted to the node, unless it already is.
+    pub fn submitted(&mut self, tx_hash: CryptoHash, window: ValidityWindow) {
+        if self.pending.contains_key(&tx_hash) || self.expired.contains_key(&tx_hash) {
+            return;
+        }
+        while self.pending.len() >= MAX_PENDING_TRANSACTIONS {
+            let (height, hashes) = match self.pending_by_height.iter_mut().next() {
+                Some(first) => first,
+                None => break,
+            };
+            let height = *height;
+            if let Some(hash) = hashes.pop() {
+                self.pending.remove(&hash);
+            }
+            if hashes.is_empty() {
+                self.pending_by_height.remove(&height);
+            }
+        }
+        self.pending.insert(tx_hash, window);
+        self.pending_by_height.entry(window.valid_until_height).or_default().push(tx_hash);
+    }
+
+    /// Forgets the transactions included in a chunk.
+    pub fn included(&mut self, tx_hashes: impl IntoIterator<Item = CryptoHash>) {
+        for tx_hash in tx_hashes {
+            self.pending.remove(&tx_hash);
+        }
+    }
+
+    /// Marks as expired the transactions which can't be included in the
+    /// chunks of the blocks after `head_height`, and forgets the ones which
+    /// expired more than [`EXPIRED_TRANSACTION_TTL`] ago.
+    pub fn expire(&mut self, head_height: BlockHeight, now: Instant) {
+        while let Some(height) = self.pending_by_height.keys().next().copied() {
+            if height >= head_height {
+                break;
+            }
+            for tx_hash in self.pending_by_height.remove(&height).unwrap_or_default() {
+                if let Some(window) = self.pending.remove(&tx_hash) {
+                    self.expired.insert(tx_hash, ExpiredTransaction { window, expired_at: now });
+                    self.expired_order.push_back(tx_hash);
+                    metrics::TRANSACTIONS_EXPIRED_TOTAL.inc();
+                }
+            }
+        }
+        while let Some(tx_hash) = self.expired_order.front() {
+            let expired_at = self.expired[tx_hash].expired_at;
+            if self.expired_order.len() <= MAX_EXPIRED_TRANSACTIONS
+                && now.saturating_duration_since(expired_at) <= EXPIRED_TRANSACTION_TTL
+            {
+                break;
+            }
+            self.expired.remove(tx_hash);
+            self.expired_order.pop_front();
+        }
+    }
+
+    /// The heights a transaction was valid at if it expired recently.
+    pub fn get_expired(&self, tx_hash: &CryptoHash) -> Option<ValidityWindow> {
+        self.expired.get(tx_hash).map(|expired| expired.window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+
+    fn window(valid_from_height: BlockHeight) -> ValidityWindow {
+        ValidityWindow { valid_from_height, valid_until_height: valid_from_height + 10 }
+    }
+
+    #[test]
+    fn test_expiry() {
+        let mut expiry = TransactionExpiry::new();
+        let [a, b, c] = [hash(b"a"), hash(b"b"), hash(b"c")];
+        expiry.submitted(a, window(100));
+        expiry.submitted(b, window(100));
+        expiry.submitted(c, window(105));
+        let start = Instant::now();
+
+        // Still valid in the chunks of the block after the head at 110.
+        expiry.expire(110, start);
+        assert_eq!(expiry.get_expired(&a), None);
+        expiry.included([b]);
+        expiry.expire(111, start);
+        assert_eq!(expiry.get_expired(&a), Some(window(100)));
+        assert_eq!(expiry.get_expired(&b), None);
+        assert_eq!(expiry.get_expired(&c), None);
+        expiry.expire(120, start + EXPIRED_TRANSACTION_TTL);
+        assert_eq!(expiry.get_expired(&a), Some(window(100)));
+        assert_eq!(expiry.get_expired(&c), Some(window(105)));
+
+        // Forgotten once they expired for long enough, but not submitted again.
+        expiry.expire(120, start + EXPIRED_TRANSACTION_TTL + Duration::from_secs(1));
+        assert_eq!(expiry.get_expired(&a), None);
+        assert_eq!(expiry.get_expired(&c), Some(window(105)));
+        expiry.submitted(c, window(200));
+        expiry.expire(300, start + EXPIRED_TRANSACTION_TTL);
+        assert_eq!(expiry.get_expired(&c), Some(window(105)));
+    }
+}
@@ -1,44 +1,138 @@
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::collections::{btree_map, BTreeMap, HashMap, HashSet, VecDeque};
 
 use crate::types::{PoolIterator, PoolKey, TransactionGroup};
 use borsh::BorshSerialize;
+use near_chain_configs::TransactionPoolConfig;
 use near_crypto::PublicKey;
 use near_primitives::epoch_manager::RngSeed;
 use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::time::Clock;
 use near_primitives::transaction::SignedTransaction;
-use near_primitives::types::AccountId;
+use near_primitives::types::{AccountId, ShardId};
 use std::ops::Bound;
+use std::time::{Duration, Instant};
 
+pub mod expiry;
 mod metrics;
 pub mod types;
 
+/// Result of an attempt to insert a transaction into the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertTransactionResult {
+    /// The transaction was added to the pool.
+    Success,
+    /// The transaction is already in the pool.
+    Duplicate,
+    /// The transaction wasn't added because the pool has reached one of its limits.
+    NoSpaceLeft,
+}
+
+/// How a transaction left the pool, or the pool of the chunk producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PoolExit {
+    /// Included in a chunk produced by this node.
+    Included,
+    /// Included in a chunk of a block, by another chunk producer.
+    InBlock,
+    /// Evicted to make room for other transactions.
+    Evicted,
+    /// Dropped by the chunk producer as invalid, e.g. because it expired.
+    Invalid,
+}
+
+impl PoolExit {
+    fn label(self) -> &'static str {
+        match self {
+            PoolExit::Included => "included",
+            PoolExit::InBlock => "in_block",
+            PoolExit::Evicted => "evicted",
+            PoolExit::Invalid => "invalid",
+        }
+    }
+}
+
+/// When the transaction entered the pool, to measure how long it waits there.
+#[derive(Debug, Clone, Copy)]
+struct PoolTiming {
+    inserted_at: Instant,
+    /// Whether the wait was recorded already.  Transactions included in a chunk
+    /// produced by this node stay in the pool until the chunk is in a block.
+    recorded: bool,
+}
+
+impl PoolTiming {
+    fn new() -> Self {
+        Self { inserted_at: Clock::instant(), recorded: false }
+    }
+
+    /// Records how long the transaction waited unless it was recorded already,
+    /// and returns the wait if it wasn't.
+    fn record(&mut self, shard_id: ShardId, exit: PoolExit) -> Option<Duration> {
+        if self.recorded {
+            return None;
+        }
+        self.recorded = true;
+        let wait = Clock::instant().saturating_duration_since(self.inserted_at);
+        metrics::TRANSACTION_POOL_WAIT
+            .with_label_values(&[&shard_id.to_string(), exit.label()])
+            .observe(wait.as_secs_f64());
+        Some(wait)
+    }
+}
+
+/// Bookkeeping information about a transaction stored in the pool.
+struct PoolEntry {
+    key: PoolKey,
+    signer_id: AccountId,
+    size: u64,
+    timing: PoolTiming,
+}
+
 /// Transaction pool: keeps track of transactions that were not yet accepted into the block chain.
 pub struct TransactionPool {
+    /// The shard the transactions are for, to label the metrics.
+    shard_id: ShardId,
     /// Transactions are grouped by a pair of (account ID, signer public key).
     /// NOTE: It's more efficient on average to keep transactions unsorted and with potentially
     /// conflicting nonce than to create a BTreeMap for every transaction.
     transactions: BTreeMap<PoolKey, Vec<SignedTransaction>>,
-    /// Set of all hashes to quickly check if the given transaction is in the pool.
-    unique_transactions: HashSet<CryptoHash>,
+    /// All hashes to quickly check if the given transaction is in the pool.
+    unique_transactions: HashMap<CryptoHash, PoolEntry>,
+    /// Hashes of the transactions in the pool grouped by signer, in the order they were inserted.
+    /// Used to enforce the per signer limit and to choose transactions to evict.
+    signer_transactions: HashMap<AccountId, VecDeque<CryptoHash>>,
+    /// Total size in bytes of all transactions in the pool.
+    total_size: u64,
+    /// Limits on the contents of the pool.
+    config: TransactionPoolConfig,
     /// A uniquely generated key seed to randomize PoolKey order.
     key_seed: RngSeed,
     /// The key after which the pool iterator starts. Doesn't have to be present in the pool.
     last_used_key: PoolKey,
+    /// Timings of the transactions taken out of the pool by the last pool iterator, until the
+    /// chunk producer returns the ones it included, see `return_included_transactions`.
+    drawn: HashMap<CryptoHash, PoolTiming>,
 }
 
 impl TransactionPool {
-    pub fn new(key_seed: RngSeed) -> Self {
+    pub fn new(key_seed: RngSeed, shard_id: ShardId, config: TransactionPoolConfig) -> Self {
         Self {
+            shard_id,
             key_seed,
             transactions: BTreeMap::new(),
-            unique_transactions: HashSet::new(),
+            unique_transactions: HashMap::new(),
+            signer_transactions: HashMap::new(),
+            total_size: 0,
+            config,
             last_used_key: CryptoHash::default(),
+            drawn: HashMap::new(),
         }
     }
 
     pub fn init_metrics() {
         // A `get()` call initializes a metric even if its value is zero.
         metrics::TRANSACTION_POOL_TOTAL.get();
+        metrics::TRANSACTION_POOL_SIZE.get();
     }
 
     fn key(&self, account_id: &AccountId, public_key: &PublicKey) -> PoolKey {
@@ -49,39 +143,202 @@ impl TransactionPool {
     }
 
     /// Insert a signed transaction into the pool that passed validation.
-    pub fn insert_transaction(&mut self, signed_transaction: SignedTransaction) -> bool {
-        if !self.unique_transactions.insert(signed_transaction.get_hash()) {
+    ///
+    /// If the pool is full, the oldest transactions of the signers with the most transactions in
+    /// the pool are evicted to make room for the new one.  If that's not possible, the new
+    /// transaction is rejected.
+    pub fn insert_transaction(
+        &mut self,
+        signed_transaction: SignedTransaction,
+    ) -> InsertTransactionResult {
+        self.insert_with_timing(signed_transaction, PoolTiming::new())
+    }
+
+    fn insert_with_timing(
+        &mut self,
+        signed_transaction: SignedTransaction,
+        timing: PoolTiming,
+    ) -> InsertTransactionResult {
+        let hash = signed_transaction.get_hash();
+        if self.unique_transactions.contains_key(&hash) {
             // The hash of this transaction was already seen, skip it.
-            return false;
+            return InsertTransactionResult::Duplicate;
         }
+        let signer_id = &signed_transaction.transaction.signer_id;
+        let size = signed_transaction.get_size();
+        if let Err(reason) = self.make_room(signer_id, size) {
+            metrics::TRANSACTION_POOL_REJECTED_TOTAL.with_label_values(&[reason]).inc();
+            return InsertTransactionResult::NoSpaceLeft;
+        }
+
+        let key = self.key(signer_id, &signed_transaction.transaction.public_key);
+        self.signer_transactions.entry(signer_id.clone()).or_default().push_back(hash);
+        self.unique_transactions
+            .insert(hash, PoolEntry { key, signer_id: signer_id.clone(), size, timing });
+        self.total_size += size;
         metrics::TRANSACTION_POOL_TOTAL.inc();
+        metrics::TRANSACTION_POOL_SIZE.add(size as i64);
 
-        let signer_id = &signed_transaction.transaction.signer_id;
-        let signer_public_key = &signed_transaction.transaction.public_key;
-        self.transactions
-            .entry(self.key(signer_id, signer_public_key))
-            .or_insert_with(Vec::new)
-            .push(signed_transaction);
-        true
+        self.transactions.entry(key).or_insert_with(Vec::new).push(signed_transaction);
+        InsertTransactionResult::Success
+    }
+
+    /// Evicts transactions until a transaction of the given signer and size fits into the pool
+    /// limits.  Only transactions of signers which have more transactions in the pool than the
+    /// given one are evicted, so that a single account can't push out everyone else.
+    ///
+    /// Returns the name of the limit that can't be satisfied if the transaction has to be
+    /// rejected.
+    fn make_room(&mut self, signer_id: &AccountId, size: u64) -> Result<(), &'static str> {
+        let signer_count = self.signer_transactions.get(signer_id).map_or(0, VecDeque::len);
+        if exceeds(self.config.max_transactions_per_signer, signer_count as u64 + 1) {
+            return Err("max_transactions_per_signer");
+        }
+        if exceeds(self.config.max_bytes, size) {
+            return Err("max_bytes");
+        }
+        loop {
+            let reason = if exceeds(self.config.max_transactions, self.len() as u64 + 1) {
+                "max_transactions"
+            } else if exceeds(self.config.max_bytes, self.total_size + size) {
+                "max_bytes"
+            } else {
+                return Ok(());
+            };
+            let hash = match self.most_represented_signer() {
+                Some(transactions) if transactions.len() > signer_count => {
+                    *transactions.front().expect("signer queues are never empty")
+                }
+                _ => return Err(reason),
+            };
+            self.evict_transaction(&hash);
+            metrics::TRANSACTION_POOL_EVICTED_TOTAL.with_label_values(&[reason]).inc();
+        }
+    }
+
+    /// Returns hashes of transactions of the signer with the most transactions in the pool.
+    /// Ties are broken by the account ID to keep the choice deterministic.
+    fn most_represented_signer(&self) -> Option<&VecDeque<CryptoHash>> {
+        self.signer_transactions
+            .iter()
+            .max_by(|(a_id, a), (b_id, b)| a.len().cmp(&b.len()).then_with(|| b_id.cmp(a_id)))
+            .map(|(_, transactions)| transactions)
+    }
+
+    /// Removes the transaction with the given hash from the pool.
+    fn evict_transaction(&mut self, hash: &CryptoHash) {
+        if let Some(mut pool_entry) = self.forget_transaction(hash) {
+            pool_entry.timing.record(self.shard_id, PoolExit::Evicted);
+            if let btree_map::Entry::Occupied(mut entry) = self.transactions.entry(pool_entry.key) {
+                entry.get_mut().retain(|tx| tx.get_hash() != *hash);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    /// Drops all bookkeeping of the transaction with the given hash, without touching the
+    /// transaction groups.  Returns the bookkeeping if the transaction was in the pool.
+    fn forget_transaction(&mut self, hash: &CryptoHash) -> Option<PoolEntry> {
+        let entry = self.unique_transactions.remove(hash)?;
+        if let Some(transactions) = self.signer_transactions.get_mut(&entry.signer_id) {
+            if let Some(pos) = transactions.iter().position(|h| h == hash) {
+                transactions.remove(pos);
+            }
+            if transactions.is_empty() {
+                self.signer_transactions.remove(&entry.signer_id);
+            }
+        }
+        self.total_size -= entry.size;
+        metrics::TRANSACTION_POOL_TOTAL.dec();
+        metrics::TRANSACTION_POOL_SIZE.sub(entry.size as i64);
+        Some(entry)
+    }
+
+    /// Forgets the transaction taken out of the pool by the pool iterator, keeping its timing
+    /// until the chunk producer returns it.
+    fn forget_drawn_transaction(&mut self, hash: &CryptoHash) {
+        if let Some(entry) = self.forget_transaction(hash) {
+            self.drawn.insert(*hash, entry.timing);
+        }
     }
 
     /// Returns a pool iterator wrapper that implements an iterator like trait to iterate over
     /// transaction groups in the proper order defined by the protocol.
     /// When the iterator is dropped, all remaining groups are inserted back into the pool.
     pub fn pool_iterator(&mut self) -> PoolIteratorWrapper<'_> {
-        PoolIteratorWrapper::new(self)
+        self.pool_iterator_with_priority(&[])
+    }
+
+    /// Like `pool_iterator`, but the transaction groups of the given signers come first, in turns
+    /// until they're all drained, and the other groups in the usual order after.
+    pub fn pool_iterator_with_priority(
+        &mut self,
+        priority_accounts: &[AccountId],
+    ) -> PoolIteratorWrapper<'_> {
+        self.drawn.clear();
+        let mut priority_groups = VecDeque::new();
+        for account_id in priority_accounts {
+            let keys: Vec<PoolKey> = match self.signer_transactions.get(account_id) {
+                Some(hashes) => {
+                    hashes.iter().map(|hash| self.unique_transactions[hash].key).collect()
+                }
+                None => continue,
+            };
+            for key in keys {
+                if let Some(mut transactions) = self.transactions.remove(&key) {
+                    transactions.sort_by_key(|st| std::cmp::Reverse(st.transaction.nonce));
+                    priority_groups.push_back(TransactionGroup {
+                        key,
+                        transactions,
+                        removed_transaction_hashes: vec![],
+                    });
+                }
+            }
+        }
+        PoolIteratorWrapper { pool: self, priority_groups, sorted_groups: Default::default() }
+    }
+
+    /// Puts the transactions the chunk producer included in a chunk back into the pool, where
+    /// they stay until the chunk is in a block, and records how long they waited.  The other
+    /// transactions taken out of the pool by the last pool iterator were dropped as invalid.
+    ///
+    /// Returns the waits recorded.
+    pub fn return_included_transactions(
+        &mut self,
+        transactions: Vec<SignedTransaction>,
+    ) -> Vec<(CryptoHash, Duration)> {
+        let mut waits = vec![];
+        for tx in transactions {
+            let hash = tx.get_hash();
+            let mut timing = self.drawn.remove(&hash).unwrap_or_else(PoolTiming::new);
+            if let Some(wait) = timing.record(self.shard_id, PoolExit::Included) {
+                waits.push((hash, wait));
+            }
+            self.insert_with_timing(tx, timing);
+        }
+        for (_, mut timing) in self.drawn.drain() {
+            timing.record(self.shard_id, PoolExit::Invalid);
+        }
+        waits
     }
 
     /// Quick reconciliation step - evict all transactions that already in the block
     /// or became invalid after it.
-    pub fn remove_transactions(&mut self, transactions: &[SignedTransaction]) {
+    ///
+    /// Returns how long the transactions waited in the pool, unless it was recorded already when
+    /// they were included in a chunk produced by this node.
+    pub fn remove_transactions(
+        &mut self,
+        transactions: &[SignedTransaction],
+    ) -> Vec<(CryptoHash, Duration)> {
+        let mut waits = vec![];
         let mut grouped_transactions = HashMap::new();
         for tx in transactions {
-            if self.unique_transactions.contains(&tx.get_hash()) {
-                let signer_id = &tx.transaction.signer_id;
-                let signer_public_key = &tx.transaction.public_key;
+            if let Some(entry) = self.unique_transactions.get(&tx.get_hash()) {
                 grouped_transactions
-                    .entry(self.key(signer_id, signer_public_key))
+                    .entry(entry.key)
                     .or_insert_with(HashSet::new)
                     .insert(tx.get_hash());
             }
@@ -96,11 +353,14 @@ impl TransactionPool {
                 self.transactions.remove(&key);
             }
             for hash in &hashes {
-                if self.unique_transactions.remove(&hash) {
-                    metrics::TRANSACTION_POOL_TOTAL.dec();
+                if let Some(mut entry) = self.forget_transaction(hash) {
+                    if let Some(wait) = entry.timing.record(self.shard_id, PoolExit::InBlock) {
+                        waits.push((*hash, wait));
+                    }
                 }
             }
         }
+        waits
     }
 
     /// Reintroduce transactions back during the chain reorg
@@ -113,6 +373,16 @@ impl TransactionPool {
     pub fn len(&self) -> usize {
         self.unique_transactions.len()
     }
+
+    /// Total size in bytes of all transactions in the pool.
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+}
+
+/// Whether `value` is above the optional `limit`.
+fn exceeds(limit: Option<u64>, value: u64) -> bool {
+    limit.map_or(false, |limit| value > limit)
 }
 
 /// PoolIterator is a structure to pull transactions from the pool.
@@ -122,13 +392,17 @@ pub struct PoolIteratorWrapper<'a> {
     /// Mutable reference to the pool, to avoid exposing it while the iterator exists.
     pool: &'a mut TransactionPool,
 
+    /// Queue of the transaction groups of the priority signers, which are iterated over before
+    /// the others.  Each group there is sorted by nonce.
+    priority_groups: VecDeque<TransactionGroup>,
+
     /// Queue of transaction groups. Each group there is sorted by nonce.
     sorted_groups: VecDeque<TransactionGroup>,
 }
 
 impl<'a> PoolIteratorWrapper<'a> {
     pub fn new(pool: &'a mut TransactionPool) -> Self {
-        Self { pool, sorted_groups: Default::default() }
+        Self { pool, priority_groups: Default::default(), sorted_groups: Default::default() }
     }
 }
 
@@ -140,17 +414,29 @@ impl<'a> PoolIteratorWrapper<'a> {
 /// If the pool is empty, the iterator gets the group from the front of the sorted groups queue.
 ///
 /// If this group is empty (no transactions left inside), then the iterator discards it and
-/// updates `unique_transactions` in the pool. Then gets the next one.
+/// forgets the removed transactions in the pool. Then gets the next one.
 ///
 /// Once a non-empty group is found, this group is pushed to the back of the sorted groups queue
 /// and the iterator returns a mutable reference to this group.
 ///
 /// If the sorted groups queue is empty, the iterator returns None.
 ///
+/// The groups of the priority signers, if any, are all taken out of the pool when the iterator is
+/// created and returned first, in turns, until they're empty.  They're discarded to the sorted
+/// groups queue then.
+///
 /// When the iterator is dropped, `unique_transactions` in the pool is updated for every group.
 /// And all non-empty group from the sorted groups queue are inserted back into the pool.
 impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
     fn next(&mut self) -> Option<&mut TransactionGroup> {
+        while let Some(priority_group) = self.priority_groups.pop_front() {
+            if priority_group.transactions.is_empty() {
+                self.sorted_groups.push_back(priority_group);
+            } else {
+                self.priority_groups.push_back(priority_group);
+                return Some(self.priority_groups.back_mut().expect("just pushed"));
+            }
+        }
         if !self.pool.transactions.is_empty() {
             let key = *self
                 .pool
@@ -179,9 +465,7 @@ impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
             while let Some(sorted_group) = self.sorted_groups.pop_front() {
                 if sorted_group.transactions.is_empty() {
                     for hash in sorted_group.removed_transaction_hashes {
-                        if self.pool.unique_transactions.remove(&hash) {
-                            metrics::TRANSACTION_POOL_TOTAL.dec();
-                        }
+                        self.pool.forget_drawn_transaction(&hash);
                     }
                 } else {
                     self.sorted_groups.push_back(sorted_group);
@@ -198,11 +482,9 @@ impl<'a> PoolIterator for PoolIteratorWrapper<'a> {
 /// removed from the pool's unique_transactions.
 impl<'a> Drop for PoolIteratorWrapper<'a> {
     fn drop(&mut self) {
-        for group in self.sorted_groups.drain(..) {
+        for group in self.priority_groups.drain(..).chain(self.sorted_groups.drain(..)) {
             for hash in group.removed_transaction_hashes {
-                if self.pool.unique_transactions.remove(&hash) {
-                    metrics::TRANSACTION_POOL_TOTAL.dec();
-                }
+                self.pool.forget_drawn_transaction(&hash);
             }
             if !group.transactions.is_empty() {
                 self.pool.transactions.insert(group.key, group.transactions);
@@ -253,7 +535,7 @@ mod tests {
         mut transactions: Vec<SignedTransaction>,
         expected_weight: u32,
     ) -> (Vec<u64>, TransactionPool) {
-        let mut pool = TransactionPool::new(TEST_SEED);
+        let mut pool = TransactionPool::new(TEST_SEED, 0, TransactionPoolConfig::default());
         let mut rng = thread_rng();
         transactions.shuffle(&mut rng);
         for tx in transactions {
@@ -364,7 +646,7 @@ mod tests {
             })
             .collect::<Vec<_>>();
 
-        let mut pool = TransactionPool::new(TEST_SEED);
+        let mut pool = TransactionPool::new(TEST_SEED, 0, TransactionPoolConfig::default());
         let mut rng = thread_rng();
         transactions.shuffle(&mut rng);
         for tx in transactions.clone() {
@@ -411,6 +693,35 @@ mod tests {
         assert_eq!(nonces, vec![1, 21, 3, 23, 25, 27, 29, 31]);
     }
 
+    /// The transactions of the priority signers come first, in the order of their nonces, and the
+    /// others after in the usual order.  The transactions not drawn stay in the pool.
+    #[test]
+    fn test_pool_iterator_with_priority() {
+        let mut transactions = generate_transactions("alice.near", "alice.near", 1, 5);
+        transactions.extend(generate_transactions("bob.near", "bob.near", 11, 13));
+        transactions.extend(generate_transactions("carol.near", "carol.near", 21, 23));
+        let (_, mut pool) = process_txs_to_nonces(transactions, 0);
+
+        let priority_accounts = ["carol.near".parse().unwrap(), "unknown.near".parse().unwrap()];
+        let mut res = vec![];
+        {
+            let mut pool_iter = pool.pool_iterator_with_priority(&priority_accounts);
+            while res.len() < 5 {
+                match pool_iter.next() {
+                    Some(iter) => res.extend(iter.next()),
+                    None => break,
+                }
+            }
+        }
+        let nonces: Vec<_> = res.iter().map(|tx| tx.transaction.nonce).collect();
+        assert_eq!(nonces[..3], [21, 22, 23]);
+        let mut rest = nonces[3..].to_vec();
+        rest.sort();
+        assert_eq!(rest, vec![1, 11]);
+        assert_eq!(pool.len(), 6);
+        assert_eq!(prepare_transactions(&mut pool, 6).len(), 6);
+    }
+
     /// Test pool iterator updates unique transactions.
     #[test]
     fn test_pool_iterator_removes_unique() {
@@ -465,4 +776,172 @@ mod tests {
         new_nonces.sort();
         assert_ne!(nonces, new_nonces);
     }
+
+    fn limited_pool(config: TransactionPoolConfig) -> TransactionPool {
+        TransactionPool::new(TEST_SEED, 0, config)
+    }
+
+    fn pool_signers(pool: &TransactionPool) -> Vec<(String, u64)> {
+        let mut res: Vec<_> = pool
+            .signer_transactions
+            .iter()
+            .map(|(signer_id, hashes)| (signer_id.to_string(), hashes.len() as u64))
+            .collect();
+        res.sort();
+        res
+    }
+
+    /// Transactions over the per signer limit are rejected and don't affect other signers.
+    #[test]
+    fn test_max_transactions_per_signer() {
+        let mut pool = limited_pool(TransactionPoolConfig {
+            max_transactions_per_signer: Some(3),
+            ..Default::default()
+        });
+        let results: Vec<_> = generate_transactions("alice.near", "alice.near", 1, 5)
+            .into_iter()
+            .map(|tx| pool.insert_transaction(tx))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                vec![InsertTransactionResult::Success; 3],
+                vec![InsertTransactionResult::NoSpaceLeft; 2]
+            ]
+            .concat()
+        );
+        for tx in generate_transactions("bob.near", "bob.near", 1, 3) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        assert_eq!(pool.len(), 6);
+    }
+
+    /// When the pool is full, the oldest transactions of the most represented signer are evicted.
+    #[test]
+    fn test_max_transactions_evicts_most_represented_signer() {
+        let mut pool =
+            limited_pool(TransactionPoolConfig { max_transactions: Some(5), ..Default::default() });
+        for tx in generate_transactions("alice.near", "alice.near", 1, 4) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        for tx in generate_transactions("bob.near", "bob.near", 1, 3) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        assert_eq!(pool.len(), 5);
+        assert_eq!(
+            pool_signers(&pool),
+            vec![("alice.near".to_string(), 2), ("bob.near".to_string(), 3)]
+        );
+
+        // The two oldest transactions of alice were evicted.
+        let mut nonces: Vec<_> = prepare_transactions(&mut pool, 5)
+            .into_iter()
+            .filter(|tx| tx.transaction.signer_id.as_ref() == "alice.near")
+            .map(|tx| tx.transaction.nonce)
+            .collect();
+        nonces.sort();
+        assert_eq!(nonces, vec![3, 4]);
+        assert_eq!(pool.len(), 0);
+        assert_eq!(pool.total_size(), 0);
+    }
+
+    /// The most represented signer can't push out transactions of other signers.
+    #[test]
+    fn test_max_transactions_rejects_most_represented_signer() {
+        let mut pool =
+            limited_pool(TransactionPoolConfig { max_transactions: Some(4), ..Default::default() });
+        for tx in generate_transactions("bob.near", "bob.near", 1, 1) {
+            assert_eq!(pool.insert_transaction(tx), InsertTransactionResult::Success);
+        }
+        let results: Vec<_> = generate_transactions("alice.near", "alice.near", 1, 5)
+            .into_iter()
+            .map(|tx| pool.insert_transaction(tx))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                vec![InsertTransactionResult::Success; 3],
+                vec![InsertTransactionResult::NoSpaceLeft; 2]
+            ]
+            .concat()
+        );
+        assert_eq!(
+            pool_signers(&pool),
+            vec![("alice.near".to_string(), 3), ("bob.near".to_string(), 1)]
+        );
+    }
+
+    /// The total size of transactions is tracked and limited.
+    #[test]
+    fn test_max_bytes() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 3);
+        let tx_size = transactions[0].get_size();
+        let mut pool = limited_pool(TransactionPoolConfig {
+            max_bytes: Some(tx_size * 2),
+            ..Default::default()
+        });
+        let results: Vec<_> = transactions
+            .iter()
+            .flat_map(|tx| {
+                [pool.insert_transaction(tx.clone()), pool.insert_transaction(tx.clone())]
+            })
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                InsertTransactionResult::Success,
+                InsertTransactionResult::Duplicate,
+                InsertTransactionResult::Success,
+                InsertTransactionResult::Duplicate,
+                InsertTransactionResult::NoSpaceLeft,
+                InsertTransactionResult::NoSpaceLeft,
+            ]
+        );
+        assert_eq!(pool.total_size(), tx_size * 2);
+
+        let results: Vec<_> = generate_transactions("bob.near", "bob.near", 1, 2)
+            .into_iter()
+            .map(|tx| pool.insert_transaction(tx))
+            .collect();
+        assert_eq!(
+            results,
+            vec![InsertTransactionResult::Success, InsertTransactionResult::NoSpaceLeft]
+        );
+        assert_eq!(
+            pool_signers(&pool),
+            vec![("alice.near".to_string(), 1), ("bob.near".to_string(), 1)]
+        );
+        assert_eq!(pool.total_size(), tx_size * 2);
+    }
+
+    /// Waits are recorded once per transaction, when it's included in a chunk produced by this
+    /// node or in a block, whichever comes first.
+    #[test]
+    fn test_pool_waits() {
+        let transactions = generate_transactions("alice.near", "alice.near", 1, 3);
+        let mut pool = TransactionPool::new(TEST_SEED, 0, TransactionPoolConfig::default());
+        for tx in transactions.clone() {
+            pool.insert_transaction(tx);
+        }
+
+        // The chunk producer takes all of them and includes the first two.
+        let drawn = prepare_transactions(&mut pool, 3);
+        assert_eq!(drawn.len(), 3);
+        assert_eq!(pool.len(), 0);
+        let waits = pool.return_included_transactions(drawn[..2].to_vec());
+        let hashes: Vec<_> = waits.iter().map(|(hash, _)| *hash).collect();
+        assert_eq!(hashes, vec![transactions[0].get_hash(), transactions[1].get_hash()]);
+        assert_eq!(pool.len(), 2);
+
+        // Recorded already when the chunk gets into a block.
+        assert!(pool.remove_transactions(&transactions[..1]).is_empty());
+        assert_eq!(pool.len(), 1);
+
+        let other = generate_transactions("bob.near", "bob.near", 1, 1);
+        pool.insert_transaction(other[0].clone());
+        let waits = pool.remove_transactions(&[transactions[1].clone(), other[0].clone()]);
+        assert_eq!(waits.len(), 1);
+        assert_eq!(waits[0].0, other[0].get_hash());
+        assert_eq!(pool.len(), 0);
+    }
 }
@@ -1,4 +1,4 @@
-use near_metrics::IntGauge;
+use near_metrics::{HistogramVec, IntCounter, IntCounterVec, IntGauge};
 use once_cell::sync::Lazy;
 
 pub static TRANSACTION_POOL_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
@@ -8,3 +8,51 @@ pub static TRANSACTION_POOL_TOTAL: Lazy<IntGauge> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+pub static TRANSACTION_POOL_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    near_metrics::try_create_int_gauge(
+        "near_transaction_pool_size",
+        "Total size in bytes of transactions currently in the pools tracked by the node",
+    )
+    .unwrap()
+});
+
+pub static TRANSACTION_POOL_EVICTED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    near_metrics::try_create_int_counter_vec(
+        "near_transaction_pool_evicted_total",
+        "Number of transactions evicted from the pools to make room for new ones, by the limit \
+         which was reached",
+        &["reason"],
+    )
+    .unwrap()
+});
+
+pub static TRANSACTION_POOL_REJECTED_TOTAL: Lazy<IntCounterVec> = Lazy::new(|| {
+    near_metrics::try_create_int_counter_vec(
+        "near_transaction_pool_rejected_total",
+        "Number of transactions which were not added to the pools because of their limits, by \
+         the limit which was reached",
+        &["reason"],
+    )
+    .unwrap()
+});
+
+pub static TRANSACTION_POOL_WAIT: Lazy<HistogramVec> = Lazy::new(|| {
+    near_metrics::try_create_histogram_vec(
+        "near_transaction_pool_wait_seconds",
+        "Time transactions spent in the pools until they were included in a chunk or left the \
+         pools otherwise, by shard and by how they left",
+        &["shard_id", "exit"],
+        Some(prometheus::exponential_buckets(0.05, 2.0, 14).unwrap()),
+    )
+    .unwrap()
+});
+
+pub static TRANSACTIONS_EXPIRED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    near_metrics::try_create_int_counter(
+        "near_transactions_expired_total",
+        "Number of transactions submitted to the node which expired before they were seen in a \
+         chunk",
+    )
+    .unwrap()
+});
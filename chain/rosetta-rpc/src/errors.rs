@@ -39,6 +39,16 @@ impl From<near_client::TxStatusError> for ErrorKind {
                 "Transaction is invalid, so it will never be included to the chain: {:?}",
                 err
             )),
+            near_client::TxStatusError::DroppedFromPool { reason, .. } => Self::NotFound(format!(
+                "Transaction was dropped from the pool, so it will never be included to the chain: {:?}",
+                reason
+            )),
+            near_client::TxStatusError::Expired { valid_until_height, .. } => {
+                Self::NotFound(format!(
+                    "Transaction expired at height {}, so it will never be included to the chain",
+                    valid_until_height
+                ))
+            }
             near_client::TxStatusError::InternalError(_)
             | near_client::TxStatusError::TimeoutError => {
                 // TODO: remove the statuses from TxStatusError since they are
@@ -1,11 +1,15 @@
 //! Chain Client Configuration
 use std::cmp::max;
 use std::cmp::min;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use near_primitives::types::{AccountId, BlockHeightDelta, Gas, NumBlocks, NumSeats, ShardId};
+use near_primitives::account_pattern::AccountPattern;
+use near_primitives::types::{
+    AccountId, BlockHeight, BlockHeightDelta, Gas, NumBlocks, NumSeats, ShardId,
+};
 use near_primitives::version::Version;
 
 pub const TEST_STATE_SYNC_TIMEOUT: u64 = 5;
@@ -18,6 +22,31 @@ pub enum LogSummaryStyle {
     Colored,
 }
 
+/// Where state sync gets the state parts from.  The sources are tried in the
+/// order they're listed for every part which is still missing.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateSyncSource {
+    /// Part files in the directory, at
+    /// `<path>/<sync_hash>/shard_<shard_id>/state_part_<part_id>_of_<num_parts>`
+    /// with the part id and number of parts padded to six digits.
+    LocalDir { path: PathBuf },
+    /// Peers tracking the shard.  A part not received from them before the
+    /// state sync timeout is requested again, so the sources listed after
+    /// this one are only used while there are no peers to request it from.
+    Peers,
+}
+
+impl StateSyncSource {
+    /// Name shown in the sync status.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StateSyncSource::LocalDir { .. } => "local_dir",
+            StateSyncSource::Peers => "peers",
+        }
+    }
+}
+
 /// Minimum number of epochs for which we keep store data
 pub const MIN_GC_NUM_EPOCHS_TO_KEEP: u64 = 3;
 
@@ -32,6 +61,20 @@ pub struct GCConfig {
     #[serde(default = "default_gc_blocks_limit")]
     pub gc_blocks_limit: NumBlocks,
 
+    /// Maximum number of blocks to garbage collect at a call while the
+    /// garbage collection is behind.  The limit grows from `gc_blocks_limit`
+    /// up to it as long as blocks are processed within
+    /// `gc_block_processing_budget`, and shrinks back when they aren't.  Set it
+    /// to `gc_blocks_limit` for a fixed limit.
+    #[serde(default = "default_gc_blocks_limit_max")]
+    pub gc_blocks_limit_max: NumBlocks,
+
+    /// The 95th percentile of the time to process a block, including the
+    /// garbage collection after it, which the garbage collection must keep
+    /// block processing within when it speeds up.
+    #[serde(default = "default_gc_block_processing_budget")]
+    pub gc_block_processing_budget: Duration,
+
     /// Maximum number of height to go through at each garbage collection step
     /// when cleaning forks during garbage collection.
     #[serde(default = "default_gc_fork_clean_step")]
@@ -40,14 +83,24 @@ pub struct GCConfig {
     /// Number of epochs for which we keep store data.
     #[serde(default = "default_gc_num_epochs_to_keep")]
     pub gc_num_epochs_to_keep: u64,
+
+    /// Number of epochs for which the state changes of the blocks, served by
+    /// the `EXPERIMENTAL_changes` RPC methods, are kept on top of the
+    /// `gc_num_epochs_to_keep` epochs the rest of the data is kept for.  An
+    /// epoch is counted as the epoch length of the genesis.
+    #[serde(default)]
+    pub gc_state_changes_extra_epochs: u64,
 }
 
 impl Default for GCConfig {
     fn default() -> Self {
         Self {
             gc_blocks_limit: 2,
+            gc_blocks_limit_max: 50,
+            gc_block_processing_budget: Duration::from_millis(500),
             gc_fork_clean_step: 100,
             gc_num_epochs_to_keep: DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
+            gc_state_changes_extra_epochs: 0,
         }
     }
 }
@@ -56,6 +109,14 @@ fn default_gc_blocks_limit() -> NumBlocks {
     GCConfig::default().gc_blocks_limit
 }
 
+fn default_gc_blocks_limit_max() -> NumBlocks {
+    GCConfig::default().gc_blocks_limit_max
+}
+
+fn default_gc_block_processing_budget() -> Duration {
+    GCConfig::default().gc_block_processing_budget
+}
+
 fn default_gc_fork_clean_step() -> u64 {
     GCConfig::default().gc_fork_clean_step
 }
@@ -68,6 +129,161 @@ impl GCConfig {
     pub fn gc_num_epochs_to_keep(&self) -> u64 {
         max(MIN_GC_NUM_EPOCHS_TO_KEEP, self.gc_num_epochs_to_keep)
     }
+
+    /// Height below which garbage collection deletes the state changes, given
+    /// the height below which it deletes the rest of the data.
+    pub fn state_changes_stop_height(
+        &self,
+        gc_stop_height: BlockHeight,
+        genesis_height: BlockHeight,
+        epoch_length: BlockHeightDelta,
+    ) -> BlockHeight {
+        gc_stop_height
+            .saturating_sub(self.gc_state_changes_extra_epochs.saturating_mul(epoch_length))
+            .max(genesis_height)
+    }
+}
+
+/// Thresholds of the free space of the disk the database is on, checked
+/// periodically while the node runs.
+///
+/// Below `warning_free_bytes` the node logs warnings.  Below
+/// `critical_free_bytes` it also stops serving state parts to syncing peers
+/// and state views over RPC, which both take disk space while they're
+/// computed.  Below `shutdown_free_bytes` it shuts down, unless
+/// `shutdown_on_low_disk_space` is unset, so that it doesn't die in the middle
+/// of a write when the disk is full.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DiskSpaceMonitorConfig {
+    /// Period of checking the free space.
+    #[serde(default = "default_disk_space_check_period")]
+    pub check_period: Duration,
+    #[serde(default = "default_disk_space_warning_free_bytes")]
+    pub warning_free_bytes: u64,
+    #[serde(default = "default_disk_space_critical_free_bytes")]
+    pub critical_free_bytes: u64,
+    #[serde(default = "default_disk_space_shutdown_free_bytes")]
+    pub shutdown_free_bytes: u64,
+    #[serde(default = "default_shutdown_on_low_disk_space")]
+    pub shutdown_on_low_disk_space: bool,
+}
+
+impl Default for DiskSpaceMonitorConfig {
+    fn default() -> Self {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        Self {
+            check_period: Duration::from_secs(60),
+            warning_free_bytes: 64 * GIB,
+            critical_free_bytes: 16 * GIB,
+            shutdown_free_bytes: 4 * GIB,
+            shutdown_on_low_disk_space: true,
+        }
+    }
+}
+
+fn default_disk_space_check_period() -> Duration {
+    DiskSpaceMonitorConfig::default().check_period
+}
+
+fn default_disk_space_warning_free_bytes() -> u64 {
+    DiskSpaceMonitorConfig::default().warning_free_bytes
+}
+
+fn default_disk_space_critical_free_bytes() -> u64 {
+    DiskSpaceMonitorConfig::default().critical_free_bytes
+}
+
+fn default_disk_space_shutdown_free_bytes() -> u64 {
+    DiskSpaceMonitorConfig::default().shutdown_free_bytes
+}
+
+fn default_shutdown_on_low_disk_space() -> bool {
+    DiskSpaceMonitorConfig::default().shutdown_on_low_disk_space
+}
+
+/// Limits on the size of the transaction pool of a single shard.
+///
+/// When a limit is reached, the pool evicts the oldest transactions of the
+/// signer with the most transactions in the pool to make room for new ones.
+/// A transaction from a signer which is already the most represented one is
+/// rejected instead.  `None` means no limit.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TransactionPoolConfig {
+    /// Maximum number of transactions in the pool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_transactions: Option<u64>,
+    /// Maximum total size in bytes of all transactions in the pool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// Maximum number of transactions in the pool signed by a single account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_transactions_per_signer: Option<u64>,
+}
+
+/// Rebroadcast of the transactions the node forwarded to the chunk producers
+/// of their shard, once the shard goes `missed_heights` heights without a chunk
+/// after they were sent.  They're sent to the chunk producers of the heights
+/// after the head then, at most `max_rebroadcasts` times per transaction.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TxRebroadcastConfig {
+    /// Whether the transactions are rebroadcast.  By default they are by the
+    /// nodes without a validator key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default = "default_tx_rebroadcast_missed_heights")]
+    pub missed_heights: BlockHeightDelta,
+    #[serde(default = "default_tx_rebroadcast_max_rebroadcasts")]
+    pub max_rebroadcasts: u32,
+}
+
+impl Default for TxRebroadcastConfig {
+    fn default() -> Self {
+        Self { enabled: None, missed_heights: 3, max_rebroadcasts: 3 }
+    }
+}
+
+fn default_tx_rebroadcast_missed_heights() -> BlockHeightDelta {
+    TxRebroadcastConfig::default().missed_heights
+}
+
+fn default_tx_rebroadcast_max_rebroadcasts() -> u32 {
+    TxRebroadcastConfig::default().max_rebroadcasts
+}
+
+impl TxRebroadcastConfig {
+    pub fn is_enabled(&self, is_validator: bool) -> bool {
+        self.enabled.unwrap_or(!is_validator)
+    }
+}
+
+/// Local preferences of the chunk producer of this node when it selects the
+/// transactions of the chunks it produces.  They don't affect the chunks other
+/// validators produce, nor what the protocol considers a valid chunk.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChunkProducerConfig {
+    /// Signers whose pooled transactions are considered first, still in the
+    /// order of their nonces and within the gas limit of the chunk, before the
+    /// ones of the other signers in the usual order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub priority_accounts: Vec<AccountId>,
+}
+
+/// Gauges of each validator of the current epoch, its stake and whether it was
+/// kicked out at the start of the epoch, on top of the blocks and chunks it
+/// produced and was expected to produce.  Off by default since every validator
+/// adds a series per gauge; once on, only the selected validators are
+/// exported, the produced and expected gauges included.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ValidatorMetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Number of the validators with the most stake exported.  All of them if
+    /// neither this nor `accounts` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_by_stake: Option<usize>,
+    /// Validators exported whatever their stake.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accounts: Vec<AccountId>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -106,6 +322,8 @@ pub struct ClientConfig {
     pub header_sync_expected_height_per_second: u64,
     /// How long to wait for a response during state sync
     pub state_sync_timeout: Duration,
+    /// Ordered sources of the state parts.
+    pub state_sync_sources: Vec<StateSyncSource>,
     /// Minimum number of peers to start syncing.
     pub min_num_peers: usize,
     /// Period between logging summary information.
@@ -136,8 +354,8 @@ pub struct ClientConfig {
     pub block_header_fetch_horizon: BlockHeightDelta,
     /// Garbage collection configuration.
     pub gc: GCConfig,
-    /// Accounts that this client tracks
-    pub tracked_accounts: Vec<AccountId>,
+    /// Accounts, or patterns of accounts, that this client tracks
+    pub tracked_accounts: Vec<AccountPattern>,
     /// Shards that this client tracks
     pub tracked_shards: Vec<ShardId>,
     /// Not clear old data, set `true` for archive nodes.
@@ -154,8 +372,54 @@ pub struct ClientConfig {
     /// genesis file.  The value only affects the RPCs without influencing the
     /// protocol thus changing it per-node doesn’t affect the blockchain.
     pub max_gas_burnt_view: Option<Gas>,
+    /// Whether view calls with overrides of gas, balance and storage are served.
+    pub allow_view_call_overrides: bool,
+    /// Maximum number of accounts viewed by a single `view_accounts` query.
+    pub view_accounts_limit: usize,
+    /// Maximum number of delayed receipts scanned to find the pending receipts
+    /// of an account.
+    pub pending_receipts_scan_limit: u64,
     /// Re-export storage layer statistics as prometheus metrics.
     pub enable_statistics_export: bool,
+    /// Period of exporting the flush and compaction backlog of the store
+    /// columns as prometheus metrics.
+    pub column_properties_export_period: Duration,
+    /// Limits of the per-shard transaction pools.
+    pub transaction_pool: TransactionPoolConfig,
+    /// Number of transactions dropped during chunk production which are
+    /// remembered for debugging.
+    pub dropped_transactions_log_size: usize,
+    /// Number of recently processed blocks whose outcome is kept in the
+    /// database for postmortems.
+    pub block_processing_log_size: u64,
+    /// Maximum total size in bytes of the orphan blocks, above which the
+    /// lowest ones are evicted.
+    pub orphan_pool_max_bytes: u64,
+    /// Maximum total size in bytes of the blocks waiting for missing chunks,
+    /// above which the lowest ones are evicted.
+    pub missing_chunks_pool_max_bytes: u64,
+    /// File the validator key is re-read from on `ReloadValidatorKey`.
+    pub validator_key_file: Option<PathBuf>,
+    /// Whether a node which is a validator of the next epoch but not of the
+    /// current one builds, signs and verifies the blocks and chunks it would
+    /// produce on every new head, without broadcasting them.
+    pub shadow_production: bool,
+    /// Thresholds of the free disk space below which the node warns, stops
+    /// serving state and shuts down.
+    pub disk_space_monitor: DiskSpaceMonitorConfig,
+    /// Bytes of block and chunk views the view client keeps in memory for
+    /// the requests for final blocks, 0 to not cache them.
+    pub view_cache_size_bytes: u64,
+    /// Maximum size in bytes of a single write committing the changes of a
+    /// processed block, whose new trie nodes are written in several writes
+    /// when it's exceeded.  0 to commit blocks in a single write.
+    pub store_commit_max_batch_bytes: u64,
+    /// Rebroadcast of the forwarded transactions whose shard misses chunks.
+    pub tx_rebroadcast: TxRebroadcastConfig,
+    /// Transaction selection of the chunks this node produces.
+    pub chunk_producer: ChunkProducerConfig,
+    /// Per-validator metrics of the current epoch.
+    pub validator_metrics: ValidatorMetricsConfig,
 }
 
 impl ClientConfig {
@@ -187,6 +451,7 @@ impl ClientConfig {
             header_sync_progress_timeout: Duration::from_secs(2),
             header_sync_stall_ban_timeout: Duration::from_secs(30),
             state_sync_timeout: Duration::from_secs(TEST_STATE_SYNC_TIMEOUT),
+            state_sync_sources: vec![StateSyncSource::Peers],
             header_sync_expected_height_per_second: 1,
             min_num_peers: 1,
             log_summary_period: Duration::from_secs(10),
@@ -214,7 +479,24 @@ impl ClientConfig {
             view_client_throttle_period: Duration::from_secs(1),
             trie_viewer_state_size_limit: None,
             max_gas_burnt_view: None,
+            allow_view_call_overrides: true,
+            view_accounts_limit: 1000,
+            pending_receipts_scan_limit: 10_000,
             enable_statistics_export: true,
+            column_properties_export_period: Duration::from_secs(10),
+            transaction_pool: TransactionPoolConfig::default(),
+            dropped_transactions_log_size: 1000,
+            block_processing_log_size: 1000,
+            orphan_pool_max_bytes: 64 * 1024 * 1024,
+            missing_chunks_pool_max_bytes: 64 * 1024 * 1024,
+            validator_key_file: None,
+            shadow_production: false,
+            disk_space_monitor: DiskSpaceMonitorConfig::default(),
+            view_cache_size_bytes: 32 * 1024 * 1024,
+            store_commit_max_batch_bytes: 0,
+            tx_rebroadcast: TxRebroadcastConfig::default(),
+            chunk_producer: ChunkProducerConfig::default(),
+            validator_metrics: ValidatorMetricsConfig::default(),
         }
     }
 }
@@ -430,7 +430,9 @@ impl<'de, F: FnMut(StateRecord)> DeserializeSeed<'de> for RecordsProcessor<&'_ m
     }
 }
 
-fn stream_records_from_file(
+/// Calls `callback` for every record of a records file or of a genesis file with
+/// the records inline, without loading all the records into memory.
+pub fn stream_records_from_file(
     reader: impl Read,
     mut callback: impl FnMut(StateRecord),
 ) -> serde_json::Result<()> {
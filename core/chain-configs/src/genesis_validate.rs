@@ -8,11 +8,19 @@ use num_rational::Rational;
 
 /// Validate genesis config and records. Panics if genesis is ill-formed.
 pub fn validate_genesis(genesis: &Genesis) {
+    let errors = genesis_validation_errors(genesis);
+    if !errors.is_empty() {
+        panic!("{}", errors.join("\n"));
+    }
+}
+
+/// Validate genesis config and records and return all the problems found.
+pub fn genesis_validation_errors(genesis: &Genesis) -> Vec<String> {
     let mut genesis_validator = GenesisValidator::new(&genesis.config);
     genesis.for_each_record(|record: &StateRecord| {
         genesis_validator.process_record(record);
     });
-    genesis_validator.validate();
+    genesis_validator.validate()
 }
 
 struct GenesisValidator<'a> {
@@ -22,6 +30,7 @@ struct GenesisValidator<'a> {
     account_ids: HashSet<AccountId>,
     access_key_account_ids: HashSet<AccountId>,
     contract_account_ids: HashSet<AccountId>,
+    errors: Vec<String>,
 }
 
 impl<'a> GenesisValidator<'a> {
@@ -33,6 +42,7 @@ impl<'a> GenesisValidator<'a> {
             account_ids: HashSet::new(),
             access_key_account_ids: HashSet::new(),
             contract_account_ids: HashSet::new(),
+            errors: vec![],
         }
     }
 
@@ -40,7 +50,7 @@ impl<'a> GenesisValidator<'a> {
         match record {
             StateRecord::Account { account_id, account } => {
                 if self.account_ids.contains(account_id) {
-                    panic!("Duplicate account id {} in genesis records", account_id);
+                    self.error(format!("Duplicate account id {} in genesis records", account_id));
                 }
                 self.total_supply += account.locked() + account.amount();
                 self.account_ids.insert(account_id.clone());
@@ -53,7 +63,10 @@ impl<'a> GenesisValidator<'a> {
             }
             StateRecord::Contract { account_id, .. } => {
                 if self.contract_account_ids.contains(account_id) {
-                    panic!("account {} has more than one contract deployed", account_id);
+                    self.error(format!(
+                        "account {} has more than one contract deployed",
+                        account_id
+                    ));
                 }
                 self.contract_account_ids.insert(account_id.clone());
             }
@@ -61,74 +74,79 @@ impl<'a> GenesisValidator<'a> {
         }
     }
 
-    pub fn validate(&self) {
-        let validators = self
-            .genesis_config
-            .validators
-            .clone()
-            .into_iter()
-            .map(|account_info| {
-                assert!(
-                    is_valid_staking_key(&account_info.public_key),
-                    "validator staking key is not valid"
-                );
-                (account_info.account_id, account_info.amount)
-            })
-            .collect::<HashMap<_, _>>();
-        assert_eq!(
-            validators.len(),
-            self.genesis_config.validators.len(),
-            "Duplicate account in validators"
-        );
-        assert!(!validators.is_empty(), "no validators in genesis");
+    fn error(&mut self, message: String) {
+        self.errors.push(message);
+    }
 
-        assert_eq!(self.total_supply, self.genesis_config.total_supply, "wrong total supply");
-        assert_eq!(
-            validators, self.staked_accounts,
-            "validator accounts do not match staked accounts"
-        );
-        for account_id in &self.access_key_account_ids {
-            assert!(
-                self.account_ids.contains(account_id),
-                "access key account {} does not exist",
-                account_id
+    fn check(&mut self, condition: bool, message: &str) {
+        if !condition {
+            self.error(message.to_string());
+        }
+    }
+
+    pub fn validate(mut self) -> Vec<String> {
+        let config = self.genesis_config;
+        let mut validators = HashMap::new();
+        for account_info in &config.validators {
+            self.check(
+                is_valid_staking_key(&account_info.public_key),
+                "validator staking key is not valid",
             );
+            validators.insert(account_info.account_id.clone(), account_info.amount);
+        }
+        self.check(validators.len() == config.validators.len(), "Duplicate account in validators");
+        self.check(!validators.is_empty(), "no validators in genesis");
+
+        if self.total_supply != config.total_supply {
+            self.error(format!(
+                "wrong total supply: records have {}, config has {}",
+                self.total_supply, config.total_supply
+            ));
+        }
+        let staked_accounts_match = validators == self.staked_accounts;
+        self.check(staked_accounts_match, "validator accounts do not match staked accounts");
+        let mut missing_accounts: Vec<String> = vec![];
+        for account_id in &self.access_key_account_ids {
+            if !self.account_ids.contains(account_id) {
+                missing_accounts.push(format!("access key account {} does not exist", account_id));
+            }
         }
         for account_id in &self.contract_account_ids {
-            assert!(
-                self.account_ids.contains(account_id),
-                "contract account {} does not exist",
-                account_id
-            );
+            if !self.account_ids.contains(account_id) {
+                missing_accounts.push(format!("contract account {} does not exist", account_id));
+            }
         }
-        assert!(
-            self.genesis_config.online_max_threshold > self.genesis_config.online_min_threshold,
-            "Online max threshold smaller than min threshold"
+        missing_accounts.sort();
+        self.errors.extend(missing_accounts);
+        self.check(
+            config.online_max_threshold > config.online_min_threshold,
+            "Online max threshold smaller than min threshold",
         );
-        assert!(
-            self.genesis_config.online_max_threshold <= Rational::from_integer(1),
-            "Online max threshold must be less or equal than 1"
+        self.check(
+            config.online_max_threshold <= Rational::from_integer(1),
+            "Online max threshold must be less or equal than 1",
         );
-        assert!(
-            *self.genesis_config.online_max_threshold.numer() < 10_000_000,
-            "Numerator is too large, may lead to overflow."
+        self.check(
+            *config.online_max_threshold.numer() < 10_000_000,
+            "Numerator is too large, may lead to overflow.",
         );
-        assert!(
-            *self.genesis_config.online_min_threshold.numer() < 10_000_000,
-            "Numerator is too large, may lead to overflow."
+        self.check(
+            *config.online_min_threshold.numer() < 10_000_000,
+            "Numerator is too large, may lead to overflow.",
         );
-        assert!(
-            *self.genesis_config.online_max_threshold.denom() < 10_000_000,
-            "Denominator is too large, may lead to overflow."
+        self.check(
+            *config.online_max_threshold.denom() < 10_000_000,
+            "Denominator is too large, may lead to overflow.",
         );
-        assert!(
-            *self.genesis_config.online_min_threshold.denom() < 10_000_000,
-            "Denominator is too large, may lead to overflow."
+        self.check(
+            *config.online_min_threshold.denom() < 10_000_000,
+            "Denominator is too large, may lead to overflow.",
         );
-        assert!(
-            self.genesis_config.gas_price_adjustment_rate < Rational::from_integer(1),
-            "Gas price adjustment rate must be less than 1"
+        self.check(
+            config.gas_price_adjustment_rate < Rational::from_integer(1),
+            "Gas price adjustment rate must be less than 1",
         );
+        self.errors
     }
 }
 
@@ -248,4 +266,17 @@ mod test {
         ]);
         validate_genesis(&genesis);
     }
+
+    #[test]
+    fn test_all_errors_reported() {
+        let mut genesis = Genesis::default();
+        genesis.records = GenesisRecords(vec![
+            StateRecord::Account { account_id: "test".parse().unwrap(), account: create_account() },
+            StateRecord::Account { account_id: "test".parse().unwrap(), account: create_account() },
+        ]);
+        let errors = genesis_validation_errors(&genesis);
+        assert!(errors.contains(&"Duplicate account id test in genesis records".to_string()));
+        assert!(errors.contains(&"no validators in genesis".to_string()));
+        assert!(errors.iter().any(|error| error.starts_with("wrong total supply")));
+    }
 }
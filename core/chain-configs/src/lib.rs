@@ -3,10 +3,11 @@ mod genesis_config;
 pub mod genesis_validate;
 
 pub use client_config::{
-    ClientConfig, GCConfig, LogSummaryStyle, DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
-    MIN_GC_NUM_EPOCHS_TO_KEEP, TEST_STATE_SYNC_TIMEOUT,
+    ChunkProducerConfig, ClientConfig, DiskSpaceMonitorConfig, GCConfig, LogSummaryStyle,
+    StateSyncSource, TransactionPoolConfig, TxRebroadcastConfig, ValidatorMetricsConfig,
+    DEFAULT_GC_NUM_EPOCHS_TO_KEEP, MIN_GC_NUM_EPOCHS_TO_KEEP, TEST_STATE_SYNC_TIMEOUT,
 };
 pub use genesis_config::{
-    get_initial_supply, Genesis, GenesisConfig, GenesisRecords, GenesisValidationMode,
-    ProtocolConfig, ProtocolConfigView,
+    get_initial_supply, stream_records_from_file, Genesis, GenesisConfig, GenesisRecords,
+    GenesisValidationMode, ProtocolConfig, ProtocolConfigView,
 };
@@ -20,4 +20,5 @@ pub mod randomness;
 mod signature;
 mod signer;
 mod test_utils;
+pub mod verification_pool;
 pub mod vrf;
@@ -0,0 +1,127 @@
+//! Verification of batches of signatures on a thread pool shared by the whole
+//! process: the approvals of a block, the signatures of the transactions of a
+//! chunk.
+//!
+//! Checking an ed25519 signature takes tens of microseconds, so a block with
+//! hundreds of approvals spends milliseconds on them on a single core.  The
+//! signatures are split into batches of [`BATCH_SIZE`] which are verified on
+//! the pool, small sets are verified on the calling thread where dispatching
+//! them would cost more than it saves.  The results are returned per
+//! signature, in order, so that the callers report the same signature as
+//! invalid as they would checking them one by one.
+
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+
+use crate::{PublicKey, Signature};
+
+/// Signatures verified by a task of the pool.
+pub const BATCH_SIZE: usize = 16;
+/// Below this many signatures they're verified on the calling thread.
+pub const MIN_PARALLEL_SIGNATURES: usize = 2 * BATCH_SIZE;
+
+static POOL: OnceCell<rayon::ThreadPool> = OnceCell::new();
+
+/// A signature to check, of `data` by the owner of `public_key`.
+pub struct SignatureCheck<'a> {
+    pub signature: &'a Signature,
+    pub data: &'a [u8],
+    pub public_key: &'a PublicKey,
+}
+
+impl SignatureCheck<'_> {
+    fn verify(&self) -> bool {
+        self.signature.verify(self.data, self.public_key)
+    }
+}
+
+/// Number of threads of the pool unless configured otherwise: half of the
+/// cores, leaving the others to the rest of the node.
+pub fn default_num_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |cores| cores.get() / 2).max(1)
+}
+
+/// Starts the pool with `num_threads` threads, [`default_num_threads`] if
+/// `None`.  Returns false if it was started already, by an earlier call or by
+/// a verification.
+pub fn init(num_threads: Option<usize>) -> bool {
+    let mut started = false;
+    POOL.get_or_init(|| {
+        started = true;
+        build(num_threads.unwrap_or_else(default_num_threads))
+    });
+    started
+}
+
+fn build(num_threads: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads.max(1))
+        .thread_name(|index| format!("signature-verification-{}", index))
+        .build()
+        .expect("failed to start the signature verification threads")
+}
+
+fn pool() -> &'static rayon::ThreadPool {
+    POOL.get_or_init(|| build(default_num_threads()))
+}
+
+/// Verifies the signatures, returning whether each of them is valid.
+pub fn verify_batch(checks: &[SignatureCheck<'_>]) -> Vec<bool> {
+    if checks.len() < MIN_PARALLEL_SIGNATURES || pool().current_num_threads() == 1 {
+        return checks.iter().map(SignatureCheck::verify).collect();
+    }
+    pool().install(|| {
+        checks
+            .par_chunks(BATCH_SIZE)
+            .flat_map_iter(|batch| batch.iter().map(SignatureCheck::verify))
+            .collect()
+    })
+}
+
+/// Verifies the signatures, returning the index of the first invalid one if
+/// there is one.
+pub fn find_invalid(checks: &[SignatureCheck<'_>]) -> Option<usize> {
+    verify_batch(checks).iter().position(|valid| !valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InMemorySigner, KeyType, Signer};
+
+    #[test]
+    fn test_verify_batch() {
+        let signers: Vec<_> = (0..100)
+            .map(|i| {
+                let name = format!("test{}", i);
+                let key_type = if i % 3 == 0 { KeyType::SECP256K1 } else { KeyType::ED25519 };
+                InMemorySigner::from_seed(name.parse().unwrap(), key_type, &name)
+            })
+            .collect();
+        let data: Vec<Vec<u8>> = (0..100u8).map(|i| vec![i; 32]).collect();
+        let mut signatures: Vec<_> =
+            signers.iter().zip(&data).map(|(signer, data)| signer.sign(data)).collect();
+        let public_keys: Vec<_> = signers.iter().map(|signer| signer.public_key()).collect();
+        // Signatures of other data, in several batches.
+        let invalid = [7, 40, 41, 99];
+        for &i in &invalid {
+            signatures[i] = signers[i].sign(b"other data");
+        }
+        let checks: Vec<_> = (0..100)
+            .map(|i| SignatureCheck {
+                signature: &signatures[i],
+                data: &data[i],
+                public_key: &public_keys[i],
+            })
+            .collect();
+
+        let expected: Vec<bool> = (0..100).map(|i| !invalid.contains(&i)).collect();
+        assert_eq!(verify_batch(&checks), expected);
+        assert_eq!(find_invalid(&checks), Some(7));
+        assert_eq!(find_invalid(&checks[8..40]), None);
+        assert_eq!(find_invalid(&checks[8..]), Some(40 - 8));
+        // Below the size verified in parallel.
+        assert_eq!(find_invalid(&checks[..10]), Some(7));
+        assert_eq!(verify_batch(&[]), Vec::<bool>::new());
+    }
+}
@@ -0,0 +1,207 @@
+//! The `json` log format.
+//!
+//! Every record is a single line with a JSON object with these fields:
+//!
+//! * `timestamp`: RFC 3339 timestamp in UTC,
+//! * `level`: one of `TRACE`, `DEBUG`, `INFO`, `WARN` and `ERROR`,
+//! * `target`: target of the record, e.g. `chain`,
+//! * `message`: the message, empty if the record has none,
+//! * `fields`: object with the other fields of the record; integers, floats
+//!   and booleans are JSON numbers and booleans, everything else is a string,
+//! * `spans`: the spans the record is in, from the outermost one, each an
+//!   object with the `name` and `fields` of the span,
+//! * `trace_id` and `span_id`: OpenTelemetry ids of the current span as hex
+//!   strings, present only if the node exports traces.
+//!
+//! Fields may be added to this list but the existing ones won't be changed.
+
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+pub(crate) struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+
+        let mut record = Map::new();
+        record.insert("timestamp".to_string(), timestamp.into());
+        record.insert("level".to_string(), metadata.level().to_string().into());
+        record.insert("target".to_string(), metadata.target().into());
+        record.insert("message".to_string(), visitor.message.unwrap_or_default().into());
+        record.insert("fields".to_string(), visitor.fields.into());
+        let mut spans = vec![];
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                // Span fields are recorded by `JsonFields`, i.e. as a JSON object.
+                let fields = span
+                    .extensions()
+                    .get::<FormattedFields<N>>()
+                    .and_then(|fields| {
+                        serde_json::from_str::<Map<String, Value>>(&fields.fields).ok()
+                    })
+                    .unwrap_or_default();
+                let mut entry = Map::new();
+                entry.insert("name".to_string(), span.name().into());
+                entry.insert("fields".to_string(), fields.into());
+                spans.push(Value::Object(entry));
+            }
+        }
+        record.insert("spans".to_string(), spans.into());
+        #[cfg(feature = "opentelemetry")]
+        if let Some((trace_id, span_id)) = opentelemetry_ids(ctx) {
+            record.insert("trace_id".to_string(), trace_id.into());
+            record.insert("span_id".to_string(), span_id.into());
+        }
+        writeln!(writer, "{}", Value::Object(record))
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+fn opentelemetry_ids<S, N>(ctx: &FmtContext<'_, S, N>) -> Option<(String, String)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    use opentelemetry::trace::TraceContextExt;
+
+    let span = ctx.lookup_current()?;
+    let extensions = span.extensions();
+    let data = extensions.get::<tracing_opentelemetry::OtelData>()?;
+    let span_id = data.builder.span_id?;
+    // Only root spans have the trace id in the builder, the others inherit it.
+    let trace_id = data.builder.trace_id.or_else(|| {
+        let parent = data.parent_cx.span();
+        let parent = parent.span_context();
+        parent.is_valid().then(|| parent.trace_id())
+    })?;
+    Some((hex(&trace_id.to_bytes()), hex(&span_id.to_bytes())))
+}
+
+#[cfg(feature = "opentelemetry")]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Default)]
+struct JsonVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl JsonVisitor {
+    fn record(&mut self, field: &Field, value: Value) {
+        self.fields.insert(field.name().to_string(), value);
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.record(field, value.into());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{:?}", value));
+        } else {
+            self.record(field, format!("{:?}", value).into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::fmt::format::JsonFields;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_format() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::fmt()
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonFormat)
+            .with_writer(buffer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("process_block", height = 7u64).entered();
+            tracing::info!(target: "chain", shard_id = 3u64, peer_id = %"ed25519:abc", is_ok = true, "Processed");
+        });
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let record: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(record["level"], "INFO");
+        assert_eq!(record["target"], "chain");
+        assert_eq!(record["message"], "Processed");
+        assert_eq!(
+            record["fields"],
+            serde_json::json!({"shard_id": 3, "peer_id": "ed25519:abc", "is_ok": true})
+        );
+        assert_eq!(
+            record["spans"],
+            serde_json::json!([{"name": "process_block", "fields": {"height": 7}}])
+        );
+        assert!(record["timestamp"].is_string());
+    }
+}
@@ -2,21 +2,28 @@
 
 pub use {backtrace, tracing, tracing_appender, tracing_subscriber};
 
+pub use log_filter::{
+    add_temporary_log_directive, clear_temporary_log_directives, log_filter_status,
+    LogFilterChange, LogFilterStatus, TemporaryDirectiveStatus,
+};
+pub use rate_limit::{log_rate_limit, set_log_rate_limit, DEFAULT_LOG_RATE_LIMIT};
+
+use rate_limit::RateLimitedMakeWriter;
+
+use json_format::JsonFormat;
 use once_cell::sync::OnceCell;
 use std::borrow::Cow;
-use tracing_appender::non_blocking::NonBlocking;
 use tracing_subscriber::filter::ParseError;
-use tracing_subscriber::fmt::format::{DefaultFields, Format};
-use tracing_subscriber::fmt::Layer;
-use tracing_subscriber::layer::Layered;
-#[cfg(feature = "opentelemetry")]
+use tracing_subscriber::fmt::format::{FmtSpan, JsonFields};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::reload::{Error, Handle};
 use tracing_subscriber::{EnvFilter, Registry};
 
-static ENV_FILTER_RELOAD_HANDLE: OnceCell<
-    Handle<EnvFilter, Layered<Layer<Registry, DefaultFields, Format, NonBlocking>, Registry>>,
-> = OnceCell::new();
+mod json_format;
+mod log_filter;
+mod rate_limit;
+
+static ENV_FILTER_RELOAD_HANDLE: OnceCell<Handle<EnvFilter, Registry>> = OnceCell::new();
 
 /// The default value for the `RUST_LOG` environment variable if one isn't specified otherwise.
 pub const DEFAULT_RUST_LOG: &'static str = "tokio_reactor=info,\
@@ -86,6 +93,16 @@ pub enum ColorOutput {
     Auto,
 }
 
+/// Format of the log records.
+///
+/// `Json` writes every record as a line of JSON with a stable set of fields,
+/// see the `json_format` module for the list.
+#[derive(clap::ArgEnum, Debug, Clone)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
 fn is_terminal() -> bool {
     // Crate `atty` provides a platform-independent way of checking whether the output is a tty.
     atty::is(atty::Stream::Stderr)
@@ -106,32 +123,54 @@ fn is_terminal() -> bool {
 pub fn default_subscriber(
     log_filter: EnvFilter,
     color_output: ColorOutput,
+) -> DefaultSubcriberGuard<impl tracing::Subscriber + Send + Sync> {
+    default_subscriber_with_format(log_filter, color_output, LogFormat::Text)
+}
+
+/// Same as [`default_subscriber`] but writes the records in the given format.
+/// The color output setting only applies to the text format.
+pub fn default_subscriber_with_format(
+    log_filter: EnvFilter,
+    color_output: ColorOutput,
+    log_format: LogFormat,
 ) -> DefaultSubcriberGuard<impl tracing::Subscriber + Send + Sync> {
     // Do not lock the `stderr` here to allow for things like `dbg!()` work during development.
     let stderr = std::io::stderr();
     let lined_stderr = std::io::LineWriter::new(stderr);
     let (writer, writer_guard) = tracing_appender::non_blocking(lined_stderr);
+    let writer = RateLimitedMakeWriter::new(writer);
 
     let ansi = match color_output {
         ColorOutput::Always => true,
         ColorOutput::Never => false,
         ColorOutput::Auto => std::env::var_os("NO_COLOR").is_none() && is_terminal(),
     };
+    // Synthesizing ENTER and CLOSE events lets us log durations of spans to the log.
+    let span_events = FmtSpan::ENTER | FmtSpan::CLOSE;
+    let (text_layer, json_layer) = match log_format {
+        LogFormat::Text => {
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(ansi)
+                .with_span_events(span_events)
+                .with_writer(writer);
+            (Some(layer), None)
+        }
+        LogFormat::Json => {
+            let layer = tracing_subscriber::fmt::layer()
+                .fmt_fields(JsonFields::new())
+                .event_format(JsonFormat)
+                .with_span_events(span_events)
+                .with_writer(writer);
+            (None, Some(layer))
+        }
+    };
 
-    let subscriber_builder = tracing_subscriber::FmtSubscriber::builder()
-        .with_ansi(ansi)
-        // Synthesizing ENTER and CLOSE events lets us log durations of spans to the log.
-        .with_span_events(
-            tracing_subscriber::fmt::format::FmtSpan::ENTER
-                | tracing_subscriber::fmt::format::FmtSpan::CLOSE,
-        )
-        .with_writer(writer)
-        .with_env_filter(log_filter)
-        .with_filter_reloading();
-    let reload_handle = subscriber_builder.reload_handle();
+    log_filter::set_initial_filter(&log_filter);
+    let (filter_layer, reload_handle) = tracing_subscriber::reload::Layer::new(log_filter);
     ENV_FILTER_RELOAD_HANDLE.set(reload_handle).unwrap();
+    rate_limit::spawn_summary_thread();
 
-    let subscriber = subscriber_builder.finish();
+    let subscriber = Registry::default().with(filter_layer).with(text_layer).with(json_layer);
 
     #[cfg(feature = "opentelemetry")]
     let subscriber = {
@@ -150,6 +189,30 @@ pub fn default_subscriber(
     }
 }
 
+/// A dispatcher exporting the spans passing the filter to the Jaeger agent at
+/// `endpoint`, for tools to trace a single run on their own instead of through
+/// the subscriber of the process.  Spans are exported as they close, call
+/// [`shutdown_opentelemetry`] once done to flush the last ones.
+pub fn opentelemetry_dispatch(
+    service_name: &str,
+    endpoint: &str,
+    filter: EnvFilter,
+) -> Result<tracing::Dispatch, opentelemetry::trace::TraceError> {
+    let tracer = opentelemetry_jaeger::new_pipeline()
+        .with_service_name(service_name)
+        .with_agent_endpoint(endpoint)
+        .install_simple()?;
+    let subscriber =
+        Registry::default().with(filter).with(tracing_opentelemetry::layer().with_tracer(tracer));
+    Ok(tracing::Dispatch::new(subscriber))
+}
+
+/// Exports the spans not exported yet and shuts the OpenTelemetry pipeline
+/// down.
+pub fn shutdown_opentelemetry() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
 pub enum ReloadError {
@@ -168,23 +231,18 @@ pub enum ReloadError {
 /// `rust_log` is equivalent to setting `RUST_LOG` environment variable.
 /// `verbose` indicates whether `--verbose` command-line flag is present.
 /// `verbose_module` is equivalent to the value of the `--verbose` command-line flag.
+///
+/// Temporary directives added with [`add_temporary_log_directive`] stay in effect.
 pub fn reload_env_filter(
     rust_log: Option<&str>,
     verbose_module: Option<&str>,
 ) -> Result<(), ReloadError> {
-    ENV_FILTER_RELOAD_HANDLE.get().map_or(Err(ReloadError::NoReloadHandle), |reload_handle| {
-        let mut builder = rust_log.map_or_else(
-            || EnvFilterBuilder::from_env(),
-            |rust_log| EnvFilterBuilder::new(rust_log),
-        );
-        if let Some(module) = verbose_module {
-            builder = builder.verbose(Some(module));
-        }
-        reload_handle
-            .reload(builder.finish().map_err(ReloadError::Parse)?)
-            .map_err(ReloadError::Reload)?;
-        Ok(())
-    })
+    log_filter::set_base_directives(rust_log, verbose_module)
+}
+
+fn reload_filter(filter: EnvFilter) -> Result<(), ReloadError> {
+    let reload_handle = ENV_FILTER_RELOAD_HANDLE.get().ok_or(ReloadError::NoReloadHandle)?;
+    reload_handle.reload(filter).map_err(ReloadError::Reload)
 }
 
 #[non_exhaustive]
@@ -0,0 +1,234 @@
+//! Runtime control of the log filter.
+//!
+//! The active filter is made of the base directives, which come from
+//! `RUST_LOG` and `--verbose` at startup and can be replaced with
+//! [`crate::reload_env_filter`], and of temporary directives added on top of
+//! them with [`add_temporary_log_directive`].  Temporary directives are removed
+//! once they expire, which restores the previous filter.
+
+use crate::{rate_limit, BuildEnvFilterError, EnvFilterBuilder, ReloadError};
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing_subscriber::filter::Directive;
+use tracing_subscriber::EnvFilter;
+
+struct TemporaryDirective {
+    directive: String,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct LogFilterState {
+    rust_log: Option<String>,
+    verbose_module: Option<String>,
+    temporary: Vec<TemporaryDirective>,
+    /// Directives of the active filter.
+    active: String,
+}
+
+static STATE: Lazy<Mutex<LogFilterState>> = Lazy::new(Default::default);
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct TemporaryDirectiveStatus {
+    pub directive: String,
+    pub expires_in_secs: u64,
+}
+
+/// Describes the active log filter.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct LogFilterStatus {
+    /// Directives of the active filter, temporary ones included.
+    pub directives: String,
+    pub temporary_directives: Vec<TemporaryDirectiveStatus>,
+    /// Debug and trace records per second allowed for a single target.
+    pub rate_limit_per_target: u32,
+    /// Records dropped by the rate limit, by target.
+    pub dropped_records: BTreeMap<String, u64>,
+}
+
+/// Remembers the filter the subscriber was created with as the base one, so
+/// that e.g. `--verbose` isn't lost when temporary directives are added.
+pub(crate) fn set_initial_filter(filter: &EnvFilter) {
+    let mut state = STATE.lock().unwrap();
+    state.active = filter.to_string();
+    state.rust_log = Some(state.active.clone());
+}
+
+/// Replaces the base directives keeping the temporary ones.
+pub(crate) fn set_base_directives(
+    rust_log: Option<&str>,
+    verbose_module: Option<&str>,
+) -> Result<(), ReloadError> {
+    let mut state = STATE.lock().unwrap();
+    let filter = build_filter(rust_log, verbose_module, &state.temporary)?;
+    apply(&mut state, filter)?;
+    state.rust_log = rust_log.map(str::to_string);
+    state.verbose_module = verbose_module.map(str::to_string);
+    Ok(())
+}
+
+/// Adds a directive, e.g. `network=debug`, to the active filter for the given
+/// duration.
+pub fn add_temporary_log_directive(directive: &str, duration: Duration) -> Result<(), ReloadError> {
+    let mut state = STATE.lock().unwrap();
+    state.temporary.push(TemporaryDirective {
+        directive: directive.to_string(),
+        expires_at: Instant::now() + duration,
+    });
+    let result =
+        build_filter(state.rust_log.as_deref(), state.verbose_module.as_deref(), &state.temporary)
+            .and_then(|filter| apply(&mut state, filter));
+    if result.is_err() {
+        state.temporary.pop();
+        return result;
+    }
+    drop(state);
+    std::thread::Builder::new()
+        .name("log-filter-expiry".to_string())
+        .spawn(move || {
+            std::thread::sleep(duration);
+            if let Err(err) = remove_expired_directives() {
+                tracing::warn!(target: "o11y", ?err, "Failed to remove expired log directives");
+            }
+        })
+        .expect("failed to spawn the log filter expiry thread");
+    Ok(())
+}
+
+/// Removes all temporary directives.
+pub fn clear_temporary_log_directives() -> Result<(), ReloadError> {
+    let mut state = STATE.lock().unwrap();
+    let filter = build_filter(state.rust_log.as_deref(), state.verbose_module.as_deref(), &[])?;
+    apply(&mut state, filter)?;
+    state.temporary.clear();
+    Ok(())
+}
+
+fn remove_expired_directives() -> Result<(), ReloadError> {
+    let mut state = STATE.lock().unwrap();
+    let now = Instant::now();
+    let len = state.temporary.len();
+    state.temporary.retain(|directive| directive.expires_at > now);
+    if state.temporary.len() == len {
+        return Ok(());
+    }
+    let filter =
+        build_filter(state.rust_log.as_deref(), state.verbose_module.as_deref(), &state.temporary)?;
+    apply(&mut state, filter)
+}
+
+/// A change of the log filter, as accepted by the `/debug/api/log_filter`
+/// endpoint.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum LogFilterChange {
+    /// Replaces the base directives, like `log_config.json` does.
+    Set {
+        rust_log: Option<String>,
+        verbose_module: Option<String>,
+    },
+    /// Adds a directive which is removed after the given number of seconds.
+    AddTemporary {
+        directive: String,
+        duration_secs: u64,
+    },
+    ClearTemporary,
+    /// Zero disables rate limiting.
+    SetRateLimit {
+        records_per_second: u32,
+    },
+}
+
+impl LogFilterChange {
+    pub fn apply(self) -> Result<(), ReloadError> {
+        match self {
+            Self::Set { rust_log, verbose_module } => {
+                set_base_directives(rust_log.as_deref(), verbose_module.as_deref())
+            }
+            Self::AddTemporary { directive, duration_secs } => {
+                add_temporary_log_directive(&directive, Duration::from_secs(duration_secs))
+            }
+            Self::ClearTemporary => clear_temporary_log_directives(),
+            Self::SetRateLimit { records_per_second } => {
+                rate_limit::set_log_rate_limit(records_per_second);
+                Ok(())
+            }
+        }
+    }
+}
+
+pub fn log_filter_status() -> LogFilterStatus {
+    let state = STATE.lock().unwrap();
+    let now = Instant::now();
+    LogFilterStatus {
+        directives: state.active.clone(),
+        temporary_directives: state
+            .temporary
+            .iter()
+            .map(|directive| TemporaryDirectiveStatus {
+                directive: directive.directive.clone(),
+                expires_in_secs: directive.expires_at.saturating_duration_since(now).as_secs(),
+            })
+            .collect(),
+        rate_limit_per_target: rate_limit::log_rate_limit(),
+        dropped_records: rate_limit::dropped_records(),
+    }
+}
+
+fn build_filter(
+    rust_log: Option<&str>,
+    verbose_module: Option<&str>,
+    temporary: &[TemporaryDirective],
+) -> Result<EnvFilter, ReloadError> {
+    let mut builder = rust_log
+        .map_or_else(|| EnvFilterBuilder::from_env(), |rust_log| EnvFilterBuilder::new(rust_log));
+    if let Some(module) = verbose_module {
+        builder = builder.verbose(Some(module));
+    }
+    let mut filter = builder.finish().map_err(ReloadError::Parse)?;
+    for TemporaryDirective { directive, .. } in temporary {
+        let parsed: Directive = directive.parse().map_err(|err| {
+            ReloadError::Parse(BuildEnvFilterError::CreateEnvFilter(err, directive.clone()))
+        })?;
+        filter = filter.add_directive(parsed);
+    }
+    Ok(filter)
+}
+
+fn apply(state: &mut LogFilterState, filter: EnvFilter) -> Result<(), ReloadError> {
+    let active = filter.to_string();
+    crate::reload_filter(filter)?;
+    state.active = active;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_filter() {
+        let result = build_filter(
+            Some("info"),
+            None,
+            &[TemporaryDirective {
+                directive: "network=loud".to_string(),
+                expires_at: Instant::now(),
+            }],
+        );
+        assert!(matches!(result, Err(ReloadError::Parse(_))), "{:?}", result.map(|_| ()));
+
+        let filter = build_filter(
+            Some("info"),
+            None,
+            &[TemporaryDirective {
+                directive: "network=debug".to_string(),
+                expires_at: Instant::now(),
+            }],
+        )
+        .unwrap();
+        assert!(filter.to_string().contains("network=debug"), "{}", filter);
+    }
+}
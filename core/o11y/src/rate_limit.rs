@@ -0,0 +1,183 @@
+//! Per-target rate limiting of debug and trace log records.
+//!
+//! Enabling debug logging for a hot code path can produce records faster than
+//! the disk can take them.  Records of `DEBUG` and `TRACE` level beyond
+//! [`log_rate_limit`] per second for a single target are dropped before they
+//! are written.  Records of other levels are never dropped.  The number of
+//! dropped records is logged periodically and reported by
+//! [`crate::log_filter_status`].
+
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Default number of debug and trace records per second allowed for a target.
+pub const DEFAULT_LOG_RATE_LIMIT: u32 = 1000;
+
+/// How often the number of dropped records is logged.
+const SUMMARY_PERIOD: Duration = Duration::from_secs(10);
+
+static RATE_LIMIT: AtomicU32 = AtomicU32::new(DEFAULT_LOG_RATE_LIMIT);
+static COUNTERS: Lazy<Mutex<HashMap<String, TargetCounter>>> = Lazy::new(Default::default);
+
+/// Sets the number of debug and trace records per second allowed for
+/// a single target.  Zero disables rate limiting.
+pub fn set_log_rate_limit(records_per_second: u32) {
+    RATE_LIMIT.store(records_per_second, Ordering::Relaxed);
+}
+
+pub fn log_rate_limit() -> u32 {
+    RATE_LIMIT.load(Ordering::Relaxed)
+}
+
+struct TargetCounter {
+    window_start: Instant,
+    records_in_window: u32,
+    /// Dropped since the last summary.
+    dropped_unreported: u64,
+    dropped_total: u64,
+}
+
+/// Decides whether a record should be written, counting the dropped ones.
+fn allow(target: &str, level: Level) -> bool {
+    if level < Level::DEBUG {
+        return true;
+    }
+    let limit = log_rate_limit();
+    if limit == 0 {
+        return true;
+    }
+    let now = Instant::now();
+    let mut counters = COUNTERS.lock().unwrap();
+    let counter = counters.entry(target.to_string()).or_insert(TargetCounter {
+        window_start: now,
+        records_in_window: 0,
+        dropped_unreported: 0,
+        dropped_total: 0,
+    });
+    if now.duration_since(counter.window_start) >= Duration::from_secs(1) {
+        counter.window_start = now;
+        counter.records_in_window = 0;
+    }
+    if counter.records_in_window < limit {
+        counter.records_in_window += 1;
+        true
+    } else {
+        counter.dropped_unreported += 1;
+        counter.dropped_total += 1;
+        false
+    }
+}
+
+/// Number of records dropped for each target since the node started.
+pub(crate) fn dropped_records() -> BTreeMap<String, u64> {
+    let counters = COUNTERS.lock().unwrap();
+    counters
+        .iter()
+        .filter(|(_, counter)| counter.dropped_total > 0)
+        .map(|(target, counter)| (target.clone(), counter.dropped_total))
+        .collect()
+}
+
+fn take_unreported() -> Vec<(String, u64)> {
+    let mut counters = COUNTERS.lock().unwrap();
+    counters
+        .iter_mut()
+        .filter(|(_, counter)| counter.dropped_unreported > 0)
+        .map(|(target, counter)| (target.clone(), std::mem::take(&mut counter.dropped_unreported)))
+        .collect()
+}
+
+/// Starts the thread logging the number of dropped records, once per process.
+pub(crate) fn spawn_summary_thread() {
+    static SPAWNED: Once = Once::new();
+    SPAWNED.call_once(|| {
+        std::thread::Builder::new()
+            .name("log-rate-limit".to_string())
+            .spawn(|| loop {
+                std::thread::sleep(SUMMARY_PERIOD);
+                for (target, dropped) in take_unreported() {
+                    tracing::warn!(
+                        target: "o11y",
+                        %target,
+                        dropped,
+                        period_secs = SUMMARY_PERIOD.as_secs(),
+                        "Dropped log records over the rate limit"
+                    );
+                }
+            })
+            .expect("failed to spawn the log rate limit thread");
+    });
+}
+
+/// Wraps a writer so that records over the rate limit are discarded.
+pub(crate) struct RateLimitedMakeWriter<W> {
+    inner: W,
+}
+
+impl<W> RateLimitedMakeWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self { inner }
+    }
+}
+
+pub(crate) enum RateLimitedWriter<W> {
+    Write(W),
+    Drop,
+}
+
+impl<W: io::Write> io::Write for RateLimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Write(writer) => writer.write(buf),
+            Self::Drop => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Write(writer) => writer.flush(),
+            Self::Drop => Ok(()),
+        }
+    }
+}
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for RateLimitedMakeWriter<W> {
+    type Writer = RateLimitedWriter<W::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RateLimitedWriter::Write(self.inner.make_writer())
+    }
+
+    fn make_writer_for(&'a self, metadata: &Metadata<'_>) -> Self::Writer {
+        if allow(metadata.target(), *metadata.level()) {
+            RateLimitedWriter::Write(self.inner.make_writer_for(metadata))
+        } else {
+            RateLimitedWriter::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        // The limit and the counters are global, this is the only test using them.
+        set_log_rate_limit(3);
+        let allowed = (0..5).filter(|_| allow("rate_limit_test", Level::DEBUG)).count();
+        assert_eq!(allowed, 3);
+        assert!((0..5).all(|_| allow("rate_limit_test", Level::INFO)));
+        assert!(allow("other_target", Level::TRACE));
+        assert_eq!(dropped_records().get("rate_limit_test"), Some(&2));
+        assert_eq!(take_unreported(), vec![("rate_limit_test".to_string(), 2)]);
+        assert_eq!(take_unreported(), vec![]);
+        set_log_rate_limit(DEFAULT_LOG_RATE_LIMIT);
+    }
+}
@@ -14,7 +14,7 @@ use near_primitives::test_utils::account_new;
 use near_primitives::transaction::{Action, SignedTransaction, Transaction, TransferAction};
 use near_primitives::types::{EpochId, StateRoot};
 use near_primitives::validator_signer::InMemoryValidatorSigner;
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use near_primitives_core::types::MerkleHash;
 use num_rational::Rational;
 
@@ -69,6 +69,7 @@ fn create_block() -> Block {
         CryptoHash::default(),
         CryptoHash::default(),
         None,
+        BuildId::default(),
     )
 }
 
@@ -0,0 +1,70 @@
+//! Verification of the approvals of a block with hundreds of block producers,
+//! one signature after the other and in batches on the verification pool.
+
+#[macro_use]
+extern crate bencher;
+
+use bencher::Bencher;
+use near_crypto::verification_pool::{self, SignatureCheck};
+use near_crypto::{InMemorySigner, KeyType, PublicKey, Signature, Signer};
+use near_primitives::block_header::{Approval, ApprovalInner};
+use near_primitives::hash::CryptoHash;
+
+const NUM_APPROVALS: usize = 300;
+
+struct Approvals {
+    message: Vec<u8>,
+    public_keys: Vec<PublicKey>,
+    signatures: Vec<Signature>,
+}
+
+impl Approvals {
+    fn new() -> Self {
+        let message =
+            Approval::get_data_for_sig(&ApprovalInner::Endorsement(CryptoHash::default()), 10);
+        let signers: Vec<_> = (0..NUM_APPROVALS)
+            .map(|i| {
+                let name = format!("validator{}", i);
+                InMemorySigner::from_seed(name.parse().unwrap(), KeyType::ED25519, &name)
+            })
+            .collect();
+        let public_keys = signers.iter().map(|signer| signer.public_key()).collect();
+        let signatures = signers.iter().map(|signer| signer.sign(&message)).collect();
+        Self { message, public_keys, signatures }
+    }
+
+    fn checks(&self) -> Vec<SignatureCheck<'_>> {
+        self.signatures
+            .iter()
+            .zip(&self.public_keys)
+            .map(|(signature, public_key)| SignatureCheck {
+                signature,
+                data: &self.message,
+                public_key,
+            })
+            .collect()
+    }
+}
+
+fn verify_approvals_sequentially(bench: &mut Bencher) {
+    let approvals = Approvals::new();
+    bench.iter(|| {
+        let valid = approvals
+            .signatures
+            .iter()
+            .zip(&approvals.public_keys)
+            .all(|(signature, public_key)| signature.verify(&approvals.message, public_key));
+        assert!(valid);
+    });
+}
+
+fn verify_approvals_in_batches(bench: &mut Bencher) {
+    let approvals = Approvals::new();
+    verification_pool::init(None);
+    bench.iter(|| {
+        assert_eq!(verification_pool::find_invalid(&approvals.checks()), None);
+    });
+}
+
+benchmark_group!(benches, verify_approvals_sequentially, verify_approvals_in_batches);
+benchmark_main!(benches);
@@ -0,0 +1,218 @@
+//! Patterns of account ids, for the configs and tools which select accounts.
+//!
+//! A pattern is an account id, which matches only that account, `*.parent`,
+//! which matches the accounts under `parent` at any depth but not `parent`
+//! itself, or `*`, which matches the top-level accounts.  Matching goes by
+//! whole labels: `*.bridge.near` matches `a.bridge.near` and `a.b.bridge.near`
+//! but not `xbridge.near`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::account::id::ParseAccountError;
+use crate::shard_layout::{account_id_to_shard_id, ShardLayout};
+use crate::types::{AccountId, ShardId};
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum AccountPattern {
+    Exact(AccountId),
+    /// The accounts strictly under the account, written `*.<account>`.
+    SubaccountOf(AccountId),
+    /// The top-level accounts, written `*`.
+    TopLevel,
+}
+
+impl AccountPattern {
+    pub fn matches(&self, account_id: &AccountId) -> bool {
+        match self {
+            Self::Exact(exact) => exact == account_id,
+            Self::SubaccountOf(parent) => account_id
+                .as_str()
+                .strip_suffix(parent.as_str())
+                .map_or(false, |prefix| prefix.len() > 1 && prefix.ends_with('.')),
+            Self::TopLevel => account_id.is_top_level(),
+        }
+    }
+
+    /// Whether any of the patterns matches the account.
+    pub fn any_matches(patterns: &[AccountPattern], account_id: &AccountId) -> bool {
+        patterns.iter().any(|pattern| pattern.matches(account_id))
+    }
+
+    /// The shards the accounts matching the pattern are in.  Only an exact
+    /// account id is in a single shard, the accounts matching the other
+    /// patterns can be in any shard under both kinds of shard layouts.
+    pub fn shard_ids(&self, shard_layout: &ShardLayout) -> Vec<ShardId> {
+        match self {
+            Self::Exact(account_id) => vec![account_id_to_shard_id(account_id, shard_layout)],
+            Self::SubaccountOf(_) | Self::TopLevel => (0..shard_layout.num_shards()).collect(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("invalid account pattern {pattern:?}: {error}")]
+pub struct ParseAccountPatternError {
+    pattern: String,
+    error: ParseAccountError,
+}
+
+impl FromStr for AccountPattern {
+    type Err = ParseAccountPatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let parse = |account_id: &str| {
+            account_id
+                .parse()
+                .map_err(|error| ParseAccountPatternError { pattern: pattern.to_string(), error })
+        };
+        if pattern == "*" {
+            Ok(Self::TopLevel)
+        } else if let Some(parent) = pattern.strip_prefix("*.") {
+            parse(parent).map(Self::SubaccountOf)
+        } else {
+            parse(pattern).map(Self::Exact)
+        }
+    }
+}
+
+impl fmt::Display for AccountPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(account_id) => write!(f, "{}", account_id),
+            Self::SubaccountOf(parent) => write!(f, "*.{}", parent),
+            Self::TopLevel => write!(f, "*"),
+        }
+    }
+}
+
+impl From<AccountId> for AccountPattern {
+    fn from(account_id: AccountId) -> Self {
+        Self::Exact(account_id)
+    }
+}
+
+impl TryFrom<String> for AccountPattern {
+    type Error = ParseAccountPatternError;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        pattern.parse()
+    }
+}
+
+impl From<AccountPattern> for String {
+    fn from(pattern: AccountPattern) -> Self {
+        pattern.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    fn pattern(pattern: &str) -> AccountPattern {
+        pattern.parse().unwrap()
+    }
+
+    fn account(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(pattern("alice.near"), AccountPattern::Exact(account("alice.near")));
+        assert_eq!(pattern("*.bridge.near"), AccountPattern::SubaccountOf(account("bridge.near")));
+        assert_eq!(pattern("*"), AccountPattern::TopLevel);
+        for invalid in ["", "*.", "**", "*near", "a.*.near", "alice.*", "*.*.near", "Alice"] {
+            assert!(invalid.parse::<AccountPattern>().is_err(), "{}", invalid);
+        }
+
+        let patterns: Vec<AccountPattern> =
+            serde_json::from_str(r#"["alice.near", "*.bridge.near", "*"]"#).unwrap();
+        assert_eq!(patterns, [pattern("alice.near"), pattern("*.bridge.near"), pattern("*")]);
+        assert_eq!(
+            serde_json::to_string(&patterns).unwrap(),
+            r#"["alice.near","*.bridge.near","*"]"#
+        );
+        assert!(serde_json::from_str::<AccountPattern>(r#""*.""#).is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let bridge = pattern("*.bridge.near");
+        assert!(bridge.matches(&account("a.bridge.near")));
+        assert!(bridge.matches(&account("a.b.bridge.near")));
+        assert!(!bridge.matches(&account("bridge.near")));
+        assert!(!bridge.matches(&account("xbridge.near")));
+        assert!(!bridge.matches(&account("a.xbridge.near")));
+        assert!(!bridge.matches(&account("bridge.near.a")));
+
+        assert!(pattern("*").matches(&account("near")));
+        assert!(!pattern("*").matches(&account("alice.near")));
+        assert!(!pattern("*").matches(&account("system")));
+        assert!(pattern("alice.near").matches(&account("alice.near")));
+        assert!(!pattern("alice.near").matches(&account("a.alice.near")));
+    }
+
+    /// Matching by label of account ids made of labels which are suffixes of
+    /// one another, against a reference implementation splitting the labels.
+    #[test]
+    fn test_matches_generated() {
+        const LABELS: [&str; 7] = ["a", "b", "ab", "bridge", "xbridge", "near", "x-bridge"];
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut generate = || -> AccountId {
+            loop {
+                let num_labels = rng.gen_range(1, 5);
+                let labels: Vec<&str> =
+                    (0..num_labels).map(|_| *LABELS.choose(&mut rng).unwrap()).collect();
+                if let Ok(account_id) = labels.join(".").parse() {
+                    return account_id;
+                }
+            }
+        };
+        let labels = |account_id: &AccountId| -> Vec<String> {
+            account_id.as_str().split('.').map(str::to_string).collect()
+        };
+        for _ in 0..10_000 {
+            let (account_id, other) = (generate(), generate());
+            let (account_labels, other_labels) = (labels(&account_id), labels(&other));
+
+            let exact = AccountPattern::Exact(other.clone());
+            assert_eq!(exact.matches(&account_id), account_labels == other_labels);
+            let subaccounts = AccountPattern::SubaccountOf(other.clone());
+            assert_eq!(
+                subaccounts.matches(&account_id),
+                account_labels.len() > other_labels.len()
+                    && account_labels.ends_with(&other_labels),
+                "{} {}",
+                subaccounts,
+                account_id
+            );
+            let top_level = AccountPattern::TopLevel;
+            assert_eq!(top_level.matches(&account_id), account_labels.len() == 1);
+
+            for pattern in [exact, subaccounts, top_level] {
+                assert_eq!(pattern.to_string().parse::<AccountPattern>().unwrap(), pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shard_ids() {
+        let shard_layout = ShardLayout::v1_test();
+        let num_shards = shard_layout.num_shards();
+        let near = account("near");
+        assert_eq!(
+            pattern("near").shard_ids(&shard_layout),
+            vec![account_id_to_shard_id(&near, &shard_layout)]
+        );
+        assert_eq!(pattern("*.near").shard_ids(&shard_layout).len() as u64, num_shards);
+    }
+}
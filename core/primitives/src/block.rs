@@ -23,7 +23,7 @@ use crate::sharding::{
 use crate::types::{Balance, BlockHeight, EpochId, Gas, NumBlocks, NumShards, StateRoot};
 use crate::utils::to_timestamp;
 use crate::validator_signer::{EmptyValidatorSigner, ValidatorSigner};
-use crate::version::{ProtocolVersion, SHARD_CHUNK_HEADER_UPGRADE_VERSION};
+use crate::version::{BuildId, ProtocolVersion, SHARD_CHUNK_HEADER_UPGRADE_VERSION};
 use std::ops::Index;
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -215,6 +215,7 @@ impl Block {
         next_bp_hash: CryptoHash,
         block_merkle_root: CryptoHash,
         timestamp_override: Option<DateTime<chrono::Utc>>,
+        producer_build_id: BuildId,
     ) -> Self {
         // Collect aggregate of validators and gas usage/limits from chunks.
         let mut validator_proposals = vec![];
@@ -263,7 +264,7 @@ impl Block {
         match prev {
             BlockHeader::BlockHeaderV1(_) => debug_assert_eq!(prev.block_ordinal(), 0),
             BlockHeader::BlockHeaderV2(_) => debug_assert_eq!(prev.block_ordinal(), 0),
-            BlockHeader::BlockHeaderV3(_) => {
+            BlockHeader::BlockHeaderV3(_) | BlockHeader::BlockHeaderV4(_) => {
                 debug_assert_eq!(prev.block_ordinal() + 1, block_ordinal)
             }
         };
@@ -297,6 +298,7 @@ impl Block {
             next_bp_hash,
             block_merkle_root,
             prev.height(),
+            producer_build_id,
         );
 
         Self::block_from_protocol_version(
@@ -6,6 +6,7 @@ use serde::Serialize;
 use near_crypto::{KeyType, PublicKey, Signature};
 
 use crate::challenge::ChallengesResult;
+use crate::checked_feature;
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::combine_hash;
 use crate::network::PeerId;
@@ -13,7 +14,7 @@ use crate::types::validator_stake::{ValidatorStake, ValidatorStakeIter, Validato
 use crate::types::{AccountId, Balance, BlockHeight, EpochId, MerkleHash, NumBlocks};
 use crate::utils::{from_timestamp, to_timestamp};
 use crate::validator_signer::ValidatorSigner;
-use crate::version::{get_protocol_version, ProtocolVersion, PROTOCOL_VERSION};
+use crate::version::{get_protocol_version, BuildId, ProtocolVersion, PROTOCOL_VERSION};
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
@@ -158,6 +159,53 @@ pub struct BlockHeaderInnerRestV3 {
     pub latest_protocol_version: ProtocolVersion,
 }
 
+/// Add `producer_build_id`
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct BlockHeaderInnerRestV4 {
+    /// Root hash of the chunk receipts in the given block.
+    pub chunk_receipts_root: MerkleHash,
+    /// Root hash of the chunk headers in the given block.
+    pub chunk_headers_root: MerkleHash,
+    /// Root hash of the chunk transactions in the given block.
+    pub chunk_tx_root: MerkleHash,
+    /// Root hash of the challenges in the given block.
+    pub challenges_root: MerkleHash,
+    /// The output of the randomness beacon
+    pub random_value: CryptoHash,
+    /// Validator proposals.
+    pub validator_proposals: Vec<ValidatorStake>,
+    /// Mask for new chunks included in the block
+    pub chunk_mask: Vec<bool>,
+    /// Gas price. Same for all chunks
+    pub gas_price: Balance,
+    /// Total supply of tokens in the system
+    pub total_supply: Balance,
+    /// List of challenges result from previous block.
+    pub challenges_result: ChallengesResult,
+
+    /// Last block that has full BFT finality
+    pub last_final_block: CryptoHash,
+    /// Last block that has doomslug finality
+    pub last_ds_final_block: CryptoHash,
+
+    /// The ordinal of the Block on the Canonical Chain
+    pub block_ordinal: NumBlocks,
+
+    pub prev_height: BlockHeight,
+
+    pub epoch_sync_data_hash: Option<CryptoHash>,
+
+    /// All the approvals included in this block
+    pub approvals: Vec<Option<Signature>>,
+
+    /// Latest protocol version that this block producer has.
+    pub latest_protocol_version: ProtocolVersion,
+
+    /// Build of the binary of this block producer.
+    pub producer_build_id: BuildId,
+}
+
 /// The part of the block approval that is different for endorsements and skips
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -298,6 +346,26 @@ pub struct BlockHeaderV3 {
     pub hash: CryptoHash,
 }
 
+/// V3 -> V4: Add `producer_build_id` to `inner_rest`
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+#[borsh_init(init)]
+pub struct BlockHeaderV4 {
+    pub prev_hash: CryptoHash,
+
+    /// Inner part of the block header that gets hashed, split into two parts, one that is sent
+    ///    to light clients, and the rest
+    pub inner_lite: BlockHeaderInnerLite,
+    pub inner_rest: BlockHeaderInnerRestV4,
+
+    /// Signature of the block producer.
+    pub signature: Signature,
+
+    /// Cached value of hash for this block.
+    #[borsh_skip]
+    pub hash: CryptoHash,
+}
+
 impl BlockHeaderV2 {
     pub fn init(&mut self) {
         self.hash = BlockHeader::compute_hash(
@@ -318,6 +386,16 @@ impl BlockHeaderV3 {
     }
 }
 
+impl BlockHeaderV4 {
+    pub fn init(&mut self) {
+        self.hash = BlockHeader::compute_hash(
+            self.prev_hash,
+            &self.inner_lite.try_to_vec().expect("Failed to serialize"),
+            &self.inner_rest.try_to_vec().expect("Failed to serialize"),
+        );
+    }
+}
+
 /// Versioned BlockHeader data structure.
 /// For each next version, document what are the changes between versions.
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -326,6 +404,7 @@ pub enum BlockHeader {
     BlockHeaderV1(Box<BlockHeaderV1>),
     BlockHeaderV2(Box<BlockHeaderV2>),
     BlockHeaderV3(Box<BlockHeaderV3>),
+    BlockHeaderV4(Box<BlockHeaderV4>),
 }
 
 impl BlockHeader {
@@ -370,6 +449,7 @@ impl BlockHeader {
         next_bp_hash: CryptoHash,
         block_merkle_root: CryptoHash,
         prev_height: BlockHeight,
+        producer_build_id: BuildId,
     ) -> Self {
         let inner_lite = BlockHeaderInnerLite {
             height,
@@ -445,7 +525,11 @@ impl BlockHeader {
                 signature,
                 hash,
             }))
-        } else {
+        } else if !checked_feature!(
+            "protocol_feature_producer_build_id",
+            ProducerBuildId,
+            this_epoch_protocol_version
+        ) {
             let inner_rest = BlockHeaderInnerRestV3 {
                 chunk_receipts_root,
                 chunk_headers_root,
@@ -477,6 +561,39 @@ impl BlockHeader {
                 signature,
                 hash,
             }))
+        } else {
+            let inner_rest = BlockHeaderInnerRestV4 {
+                chunk_receipts_root,
+                chunk_headers_root,
+                chunk_tx_root,
+                challenges_root,
+                random_value,
+                validator_proposals,
+                chunk_mask,
+                gas_price,
+                block_ordinal,
+                total_supply,
+                challenges_result,
+                last_final_block,
+                last_ds_final_block,
+                prev_height,
+                epoch_sync_data_hash,
+                approvals,
+                latest_protocol_version: get_protocol_version(next_epoch_protocol_version),
+                producer_build_id,
+            };
+            let (hash, signature) = signer.sign_block_header_parts(
+                prev_hash,
+                &inner_lite.try_to_vec().expect("Failed to serialize"),
+                &inner_rest.try_to_vec().expect("Failed to serialize"),
+            );
+            Self::BlockHeaderV4(Box::new(BlockHeaderV4 {
+                prev_hash,
+                inner_lite,
+                inner_rest,
+                signature,
+                hash,
+            }))
         }
     }
 
@@ -607,6 +724,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.hash,
             BlockHeader::BlockHeaderV2(header) => &header.hash,
             BlockHeader::BlockHeaderV3(header) => &header.hash,
+            BlockHeader::BlockHeaderV4(header) => &header.hash,
         }
     }
 
@@ -616,6 +734,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.prev_hash,
             BlockHeader::BlockHeaderV2(header) => &header.prev_hash,
             BlockHeader::BlockHeaderV3(header) => &header.prev_hash,
+            BlockHeader::BlockHeaderV4(header) => &header.prev_hash,
         }
     }
 
@@ -625,6 +744,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.signature,
             BlockHeader::BlockHeaderV2(header) => &header.signature,
             BlockHeader::BlockHeaderV3(header) => &header.signature,
+            BlockHeader::BlockHeaderV4(header) => &header.signature,
         }
     }
 
@@ -634,6 +754,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => header.inner_lite.height,
             BlockHeader::BlockHeaderV2(header) => header.inner_lite.height,
             BlockHeader::BlockHeaderV3(header) => header.inner_lite.height,
+            BlockHeader::BlockHeaderV4(header) => header.inner_lite.height,
         }
     }
 
@@ -643,6 +764,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(_) => None,
             BlockHeader::BlockHeaderV2(_) => None,
             BlockHeader::BlockHeaderV3(header) => Some(header.inner_rest.prev_height),
+            BlockHeader::BlockHeaderV4(header) => Some(header.inner_rest.prev_height),
         }
     }
 
@@ -652,6 +774,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.epoch_id,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.epoch_id,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.epoch_id,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_lite.epoch_id,
         }
     }
 
@@ -661,6 +784,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.next_epoch_id,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.next_epoch_id,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.next_epoch_id,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_lite.next_epoch_id,
         }
     }
 
@@ -670,6 +794,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.prev_state_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.prev_state_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.prev_state_root,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_lite.prev_state_root,
         }
     }
 
@@ -679,6 +804,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_receipts_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_receipts_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_receipts_root,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_receipts_root,
         }
     }
 
@@ -688,6 +814,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_headers_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_headers_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_headers_root,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_headers_root,
         }
     }
 
@@ -697,6 +824,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_tx_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_tx_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_tx_root,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_tx_root,
         }
     }
 
@@ -709,6 +837,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV3(header) => {
                 header.inner_rest.chunk_mask.iter().map(|&x| u64::from(x)).sum::<u64>()
             }
+            BlockHeader::BlockHeaderV4(header) => {
+                header.inner_rest.chunk_mask.iter().map(|&x| u64::from(x)).sum::<u64>()
+            }
         }
     }
 
@@ -718,6 +849,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.challenges_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.challenges_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.challenges_root,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.challenges_root,
         }
     }
 
@@ -727,6 +859,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.outcome_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.outcome_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.outcome_root,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_lite.outcome_root,
         }
     }
 
@@ -736,6 +869,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => header.inner_lite.timestamp,
             BlockHeader::BlockHeaderV2(header) => header.inner_lite.timestamp,
             BlockHeader::BlockHeaderV3(header) => header.inner_lite.timestamp,
+            BlockHeader::BlockHeaderV4(header) => header.inner_lite.timestamp,
         }
     }
 
@@ -751,6 +885,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV3(header) => {
                 ValidatorStakeIter::new(&header.inner_rest.validator_proposals)
             }
+            BlockHeader::BlockHeaderV4(header) => {
+                ValidatorStakeIter::new(&header.inner_rest.validator_proposals)
+            }
         }
     }
 
@@ -760,6 +897,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.chunk_mask,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.chunk_mask,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.chunk_mask,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.chunk_mask,
         }
     }
 
@@ -769,6 +907,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(_) => 0, // not applicable
             BlockHeader::BlockHeaderV2(_) => 0, // not applicable
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.block_ordinal,
+            BlockHeader::BlockHeaderV4(header) => header.inner_rest.block_ordinal,
         }
     }
 
@@ -778,6 +917,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => header.inner_rest.gas_price,
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.gas_price,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.gas_price,
+            BlockHeader::BlockHeaderV4(header) => header.inner_rest.gas_price,
         }
     }
 
@@ -787,6 +927,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => header.inner_rest.total_supply,
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.total_supply,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.total_supply,
+            BlockHeader::BlockHeaderV4(header) => header.inner_rest.total_supply,
         }
     }
 
@@ -796,6 +937,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.random_value,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.random_value,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.random_value,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.random_value,
         }
     }
 
@@ -805,6 +947,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.last_final_block,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.last_final_block,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.last_final_block,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.last_final_block,
         }
     }
 
@@ -814,6 +957,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.last_ds_final_block,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.last_ds_final_block,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.last_ds_final_block,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.last_ds_final_block,
         }
     }
 
@@ -823,6 +967,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.challenges_result,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.challenges_result,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.challenges_result,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.challenges_result,
         }
     }
 
@@ -832,6 +977,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.next_bp_hash,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.next_bp_hash,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.next_bp_hash,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_lite.next_bp_hash,
         }
     }
 
@@ -841,6 +987,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_lite.block_merkle_root,
             BlockHeader::BlockHeaderV2(header) => &header.inner_lite.block_merkle_root,
             BlockHeader::BlockHeaderV3(header) => &header.inner_lite.block_merkle_root,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_lite.block_merkle_root,
         }
     }
 
@@ -850,6 +997,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(_) => None,
             BlockHeader::BlockHeaderV2(_) => None,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.epoch_sync_data_hash,
+            BlockHeader::BlockHeaderV4(header) => header.inner_rest.epoch_sync_data_hash,
         }
     }
 
@@ -859,6 +1007,7 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => &header.inner_rest.approvals,
             BlockHeader::BlockHeaderV2(header) => &header.inner_rest.approvals,
             BlockHeader::BlockHeaderV3(header) => &header.inner_rest.approvals,
+            BlockHeader::BlockHeaderV4(header) => &header.inner_rest.approvals,
         }
     }
 
@@ -883,6 +1032,7 @@ impl BlockHeader {
             }
             BlockHeader::BlockHeaderV2(_header) => true,
             BlockHeader::BlockHeaderV3(_header) => true,
+            BlockHeader::BlockHeaderV4(_header) => true,
         }
     }
 
@@ -892,6 +1042,19 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV1(header) => header.inner_rest.latest_protocol_version,
             BlockHeader::BlockHeaderV2(header) => header.inner_rest.latest_protocol_version,
             BlockHeader::BlockHeaderV3(header) => header.inner_rest.latest_protocol_version,
+            BlockHeader::BlockHeaderV4(header) => header.inner_rest.latest_protocol_version,
+        }
+    }
+
+    /// Build of the binary which produced the block, `None` for the header
+    /// versions before it was added.
+    #[inline]
+    pub fn producer_build_id(&self) -> Option<BuildId> {
+        match self {
+            BlockHeader::BlockHeaderV1(_)
+            | BlockHeader::BlockHeaderV2(_)
+            | BlockHeader::BlockHeaderV3(_) => None,
+            BlockHeader::BlockHeaderV4(header) => Some(header.inner_rest.producer_build_id),
         }
     }
 
@@ -906,6 +1069,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV3(header) => {
                 header.inner_lite.try_to_vec().expect("Failed to serialize")
             }
+            BlockHeader::BlockHeaderV4(header) => {
+                header.inner_lite.try_to_vec().expect("Failed to serialize")
+            }
         }
     }
 
@@ -920,6 +1086,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV3(header) => {
                 header.inner_rest.try_to_vec().expect("Failed to serialize")
             }
+            BlockHeader::BlockHeaderV4(header) => {
+                header.inner_rest.try_to_vec().expect("Failed to serialize")
+            }
         }
     }
 }
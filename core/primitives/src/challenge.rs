@@ -1,12 +1,13 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 
-use near_crypto::Signature;
+use near_crypto::{PublicKey, Signature};
 
+use crate::block_header::{Approval, BlockHeader};
 use crate::hash::{hash, CryptoHash};
 use crate::merkle::MerklePath;
 use crate::sharding::{EncodedShardChunk, ShardChunk, ShardChunkHeader};
-use crate::types::AccountId;
+use crate::types::{AccountId, BlockHeight, ShardId};
 use crate::validator_signer::ValidatorSigner;
 
 /// Serialized TrieNodeWithSize
@@ -124,3 +125,132 @@ impl SlashedValidator {
 /// Result of checking challenge, contains which accounts to slash.
 /// If challenge is invalid this is sender, otherwise author of chunk (and possibly other participants that signed invalid blocks).
 pub type ChallengesResult = Vec<SlashedValidator>;
+
+/// Two conflicting messages signed by the same validator.  Contains
+/// everything needed to check it without access to the chain but the key the
+/// validator staked, which can be looked up on any node.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+pub struct EquivocationEvidence {
+    pub signer: AccountId,
+    /// Key the messages are signed with.
+    pub public_key: PublicKey,
+    pub kind: EquivocationKind,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Clone, Debug)]
+// Same as `ChallengeBody`.
+#[allow(clippy::large_enum_variant)]
+pub enum EquivocationKind {
+    /// Two blocks at the same height.
+    Block { left: BlockHeader, right: BlockHeader },
+    /// Two chunks of the same shard at the same height.
+    Chunk { left: ShardChunkHeader, right: ShardChunkHeader },
+    /// Two different approvals for the same target height.
+    Approval { left: Approval, right: Approval },
+}
+
+impl EquivocationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EquivocationKind::Block { .. } => "block",
+            EquivocationKind::Chunk { .. } => "chunk",
+            EquivocationKind::Approval { .. } => "approval",
+        }
+    }
+}
+
+impl EquivocationEvidence {
+    /// Builds the evidence with the messages ordered by their hashes, so that
+    /// the same pair seen in any order is stored once.
+    pub fn new(signer: AccountId, public_key: PublicKey, kind: EquivocationKind) -> Self {
+        let kind = match kind {
+            EquivocationKind::Block { left, right } if left.hash() > right.hash() => {
+                EquivocationKind::Block { left: right, right: left }
+            }
+            EquivocationKind::Chunk { left, right } if left.chunk_hash() > right.chunk_hash() => {
+                EquivocationKind::Chunk { left: right, right: left }
+            }
+            EquivocationKind::Approval { left, right }
+                if approval_hash(&left) > approval_hash(&right) =>
+            {
+                EquivocationKind::Approval { left: right, right: left }
+            }
+            kind => kind,
+        };
+        Self { signer, public_key, kind }
+    }
+
+    pub fn height(&self) -> BlockHeight {
+        match &self.kind {
+            EquivocationKind::Block { left, .. } => left.height(),
+            EquivocationKind::Chunk { left, .. } => left.height_created(),
+            EquivocationKind::Approval { left, .. } => left.target_height,
+        }
+    }
+
+    pub fn shard_id(&self) -> Option<ShardId> {
+        match &self.kind {
+            EquivocationKind::Chunk { left, .. } => Some(left.shard_id()),
+            _ => None,
+        }
+    }
+
+    /// Hashes of the two messages.
+    pub fn hashes(&self) -> (CryptoHash, CryptoHash) {
+        match &self.kind {
+            EquivocationKind::Block { left, right } => (*left.hash(), *right.hash()),
+            EquivocationKind::Chunk { left, right } => (left.chunk_hash().0, right.chunk_hash().0),
+            EquivocationKind::Approval { left, right } => {
+                (approval_hash(left), approval_hash(right))
+            }
+        }
+    }
+
+    /// Hash the evidence is stored under.
+    pub fn hash(&self) -> CryptoHash {
+        hash(&self.try_to_vec().expect("Failed to serialize"))
+    }
+
+    /// Checks that the messages conflict and are both signed with
+    /// `public_key`.
+    pub fn verify(&self) -> Result<(), String> {
+        let (conflicting, left_signed, right_signed) = match &self.kind {
+            EquivocationKind::Block { left, right } => (
+                left.hash() != right.hash() && left.height() == right.height(),
+                left.verify_block_producer(&self.public_key),
+                right.verify_block_producer(&self.public_key),
+            ),
+            EquivocationKind::Chunk { left, right } => (
+                left.chunk_hash() != right.chunk_hash()
+                    && left.height_created() == right.height_created()
+                    && left.shard_id() == right.shard_id(),
+                left.signature().verify(left.chunk_hash().as_ref(), &self.public_key),
+                right.signature().verify(right.chunk_hash().as_ref(), &self.public_key),
+            ),
+            EquivocationKind::Approval { left, right } => (
+                left.inner != right.inner
+                    && left.target_height == right.target_height
+                    && left.account_id == self.signer
+                    && right.account_id == self.signer,
+                verify_approval(left, &self.public_key),
+                verify_approval(right, &self.public_key),
+            ),
+        };
+        if !conflicting {
+            return Err("the messages don't conflict".to_string());
+        }
+        if !left_signed || !right_signed {
+            return Err(format!("the messages aren't both signed by {}", self.public_key));
+        }
+        Ok(())
+    }
+}
+
+fn approval_hash(approval: &Approval) -> CryptoHash {
+    hash(&Approval::get_data_for_sig(&approval.inner, approval.target_height))
+}
+
+fn verify_approval(approval: &Approval, public_key: &PublicKey) -> bool {
+    let data = Approval::get_data_for_sig(&approval.inner, approval.target_height);
+    approval.signature.verify(&data, public_key)
+}
@@ -2,6 +2,7 @@ pub use near_primitives_core::borsh;
 pub use near_primitives_core::num_rational;
 
 pub use near_primitives_core::account;
+pub mod account_pattern;
 pub mod block;
 pub mod block_header;
 pub mod challenge;
@@ -10,6 +11,7 @@ pub use near_primitives_core::contract;
 pub mod epoch_manager;
 pub mod errors;
 pub use near_primitives_core::hash;
+pub mod light_client;
 pub use near_primitives_core::logging;
 pub mod merkle;
 pub mod network;
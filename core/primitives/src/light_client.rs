@@ -0,0 +1,252 @@
+//! Verification of light client blocks following the rules of the light client
+//! specification.
+//!
+//! A light client knows the block producers of the epoch of its head and, from
+//! the `next_bps` of its head, of the next epoch.  A new block is valid if it
+//! is from one of these two epochs, is more than two thirds approved by its
+//! block producers and commits to the block producers it announces.  Catching
+//! up over many epochs applies this to the last final block of every epoch in
+//! turn.
+
+use borsh::BorshSerialize;
+
+use crate::block_header::{Approval, ApprovalInner, BlockHeaderInnerLite};
+use crate::hash::{hash, CryptoHash};
+use crate::merkle::combine_hash;
+use crate::types::validator_stake::{ValidatorStake, ValidatorStakeV1};
+use crate::types::Balance;
+use crate::views::{BlockHeaderInnerLiteView, LightClientBlockView};
+
+/// Returns the hash of the block of the light client block.
+pub fn light_client_block_hash(block: &LightClientBlockView) -> CryptoHash {
+    let inner_lite = BlockHeaderInnerLite::from(block.inner_lite.clone());
+    let inner_hash = combine_hash(&hash(&inner_lite.try_to_vec().unwrap()), &block.inner_rest_hash);
+    combine_hash(&inner_hash, &block.prev_block_hash)
+}
+
+/// Checks that the block is approved by more than two thirds of the stake of
+/// `block_producers`, the ordered block producers of its epoch, and that it
+/// commits to its `next_bps` if it has them.
+///
+/// Returns the hash of the block.
+pub fn verify_light_client_block(
+    block: &LightClientBlockView,
+    block_producers: &[ValidatorStake],
+) -> Result<CryptoHash, String> {
+    let block_hash = light_client_block_hash(block);
+    let next_block_hash = combine_hash(&block.next_block_inner_hash, &block_hash);
+    let message = Approval::get_data_for_sig(
+        &ApprovalInner::Endorsement(next_block_hash),
+        block.inner_lite.height + 2,
+    );
+
+    if block.approvals_after_next.len() > block_producers.len() {
+        return Err(format!(
+            "block #{} has {} approvals but there are {} block producers",
+            block.inner_lite.height,
+            block.approvals_after_next.len(),
+            block_producers.len()
+        ));
+    }
+    let total_stake: Balance = block_producers.iter().map(|bp| bp.stake()).sum();
+    let mut approved_stake: Balance = 0;
+    for (bp, approval) in block_producers.iter().zip(block.approvals_after_next.iter()) {
+        if let Some(signature) = approval {
+            if !signature.verify(&message, bp.public_key()) {
+                return Err(format!(
+                    "block #{} has an invalid approval from {}",
+                    block.inner_lite.height,
+                    bp.account_id()
+                ));
+            }
+            approved_stake += bp.stake();
+        }
+    }
+    if approved_stake * 3 <= total_stake * 2 {
+        return Err(format!(
+            "block #{} is approved by {} out of {} stake",
+            block.inner_lite.height, approved_stake, total_stake
+        ));
+    }
+
+    if let Some(next_bps) = &block.next_bps {
+        let next_bps: Vec<ValidatorStake> =
+            next_bps.iter().cloned().map(ValidatorStake::from).collect();
+        let next_bps_v1: Vec<ValidatorStakeV1> =
+            next_bps.iter().cloned().map(ValidatorStake::into_v1).collect();
+        // Before `BlockHeaderV3` the hash was computed over the unversioned stakes.
+        if hash(&next_bps.try_to_vec().unwrap()) != block.inner_lite.next_bp_hash
+            && hash(&next_bps_v1.try_to_vec().unwrap()) != block.inner_lite.next_bp_hash
+        {
+            return Err(format!(
+                "next block producers of block #{} don't match its next_bp_hash",
+                block.inner_lite.height
+            ));
+        }
+    }
+    Ok(block_hash)
+}
+
+/// Checks that `blocks` bring a light client from `head` to their last block,
+/// each one being valid for the light client at the previous one.
+///
+/// `block_producers` are the block producers of the epoch of `head` and
+/// `next_block_producers` the ones of its next epoch, announced by the
+/// `next_bps` of the light client block of `head`.
+pub fn verify_light_client_blocks(
+    head: &BlockHeaderInnerLiteView,
+    block_producers: &[ValidatorStake],
+    next_block_producers: &[ValidatorStake],
+    blocks: &[LightClientBlockView],
+) -> Result<(), String> {
+    let mut head = head.clone();
+    let mut block_producers = block_producers.to_vec();
+    let mut next_block_producers = next_block_producers.to_vec();
+    for block in blocks {
+        let height = block.inner_lite.height;
+        if height <= head.height {
+            return Err(format!("block #{} isn't after the head #{}", height, head.height));
+        }
+        let new_epoch = if block.inner_lite.epoch_id == head.epoch_id {
+            false
+        } else if block.inner_lite.epoch_id == head.next_epoch_id {
+            true
+        } else {
+            return Err(format!(
+                "block #{} is from epoch {} which isn't the epoch of the head or the next one",
+                height, block.inner_lite.epoch_id
+            ));
+        };
+        if new_epoch && block.next_bps.is_none() {
+            return Err(format!("block #{} starts an epoch without next block producers", height));
+        }
+        if new_epoch {
+            block_producers = std::mem::take(&mut next_block_producers);
+        }
+        verify_light_client_block(block, &block_producers)?;
+        if let Some(next_bps) = &block.next_bps {
+            next_block_producers = next_bps.iter().cloned().map(ValidatorStake::from).collect();
+        }
+        head = block.inner_lite.clone();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::views::validator_stake_view::ValidatorStakeView;
+    use near_crypto::{InMemorySigner, KeyType, Signer};
+
+    struct Epoch {
+        id: CryptoHash,
+        signers: Vec<InMemorySigner>,
+    }
+
+    impl Epoch {
+        fn new(index: u8, names: &[&str]) -> Self {
+            let signers = names
+                .iter()
+                .map(|name| {
+                    InMemorySigner::from_seed(name.parse().unwrap(), KeyType::ED25519, name)
+                })
+                .collect();
+            Self { id: CryptoHash([index; 32]), signers }
+        }
+
+        fn block_producers(&self) -> Vec<ValidatorStake> {
+            self.signers
+                .iter()
+                .map(|signer| {
+                    ValidatorStake::new_v1(signer.account_id.clone(), signer.public_key(), 100)
+                })
+                .collect()
+        }
+    }
+
+    /// The last final block of `epoch`, approved by the first `num_approvals`
+    /// of its block producers.
+    fn block(
+        height: u64,
+        epoch: &Epoch,
+        next_epoch: &Epoch,
+        num_approvals: usize,
+    ) -> LightClientBlockView {
+        let next_bps = next_epoch.block_producers();
+        let mut block = LightClientBlockView {
+            prev_block_hash: CryptoHash([height as u8; 32]),
+            next_block_inner_hash: CryptoHash([height as u8 + 1; 32]),
+            inner_lite: BlockHeaderInnerLiteView {
+                height,
+                epoch_id: epoch.id,
+                next_epoch_id: next_epoch.id,
+                prev_state_root: CryptoHash::default(),
+                outcome_root: CryptoHash::default(),
+                timestamp: 0,
+                timestamp_nanosec: 0,
+                next_bp_hash: hash(&next_bps.try_to_vec().unwrap()),
+                block_merkle_root: CryptoHash::default(),
+            },
+            inner_rest_hash: CryptoHash::default(),
+            next_bps: Some(next_bps.into_iter().map(ValidatorStakeView::from).collect()),
+            approvals_after_next: vec![],
+        };
+        let next_block_hash =
+            combine_hash(&block.next_block_inner_hash, &light_client_block_hash(&block));
+        let message =
+            Approval::get_data_for_sig(&ApprovalInner::Endorsement(next_block_hash), height + 2);
+        block.approvals_after_next = epoch
+            .signers
+            .iter()
+            .enumerate()
+            .map(|(i, signer)| (i < num_approvals).then(|| signer.sign(&message)))
+            .collect();
+        block
+    }
+
+    #[test]
+    fn test_verify_light_client_blocks() {
+        let epochs = [
+            Epoch::new(0, &["alice", "bob", "carol"]),
+            Epoch::new(1, &["alice", "bob", "carol"]),
+            Epoch::new(2, &["bob", "carol", "dave"]),
+            Epoch::new(3, &["carol", "dave", "erin"]),
+            Epoch::new(4, &["dave", "erin", "frank"]),
+        ];
+        let head = block(5, &epochs[0], &epochs[1], 3);
+        let blocks = vec![
+            block(15, &epochs[1], &epochs[2], 3),
+            block(25, &epochs[2], &epochs[3], 3),
+            block(35, &epochs[3], &epochs[4], 3),
+        ];
+        let verify = |blocks: &[LightClientBlockView]| {
+            verify_light_client_blocks(
+                &head.inner_lite,
+                &epochs[0].block_producers(),
+                &epochs[1].block_producers(),
+                blocks,
+            )
+        };
+        verify(&blocks).unwrap();
+        verify(&[]).unwrap();
+
+        // An epoch is skipped.
+        let err = verify(&[blocks[0].clone(), blocks[2].clone()]).unwrap_err();
+        assert!(err.contains("isn't the epoch of the head"), "{}", err);
+        // Two out of three of the stake isn't more than two thirds.
+        let mut not_approved = blocks.clone();
+        not_approved[1] = block(25, &epochs[2], &epochs[3], 2);
+        let err = verify(&not_approved).unwrap_err();
+        assert!(err.contains("approved by 200 out of 300"), "{}", err);
+        // The next block producers are replaced.
+        let mut forged = blocks.clone();
+        forged[1].next_bps = blocks[0].next_bps.clone();
+        let err = verify(&forged).unwrap_err();
+        assert!(err.contains("don't match its next_bp_hash"), "{}", err);
+        // The block is signed by the block producers of another epoch.
+        let mut wrong_signers = blocks.clone();
+        wrong_signers[2] = block(35, &epochs[4], &epochs[4], 3);
+        wrong_signers[2].inner_lite.epoch_id = epochs[3].id;
+        assert!(verify(&wrong_signers).is_err());
+    }
+}
@@ -103,6 +103,16 @@ pub fn verify_hash(root: MerkleHash, path: &MerklePath, item_hash: MerkleHash) -
     compute_root_from_path(path, item_hash) == root
 }
 
+/// Verify that the block with hash `block_hash` is an ancestor of the block with the given
+/// block merkle root, with a proof as returned by `Chain::get_block_ancestry_proof`.
+pub fn verify_block_ancestry_proof(
+    block_merkle_root: &MerkleHash,
+    block_hash: &CryptoHash,
+    proof: &MerklePath,
+) -> bool {
+    verify_hash(*block_merkle_root, proof, *block_hash)
+}
+
 pub fn compute_root_from_path(path: &MerklePath, item_hash: MerkleHash) -> MerkleHash {
     let mut res = item_hash;
     for item in path {
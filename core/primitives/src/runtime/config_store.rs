@@ -1,6 +1,8 @@
 use crate::runtime::config::RuntimeConfig;
 use crate::runtime::parameter_table::{ParameterTable, ParameterTableDiff};
 use crate::types::ProtocolVersion;
+use crate::views::{ParameterChangeView, ParameterValueView, RuntimeConfigDiffView};
+use serde_json::Value;
 use std::collections::BTreeMap;
 use std::ops::Bound;
 use std::sync::Arc;
@@ -75,6 +77,22 @@ impl RuntimeConfigStore {
         Self { store }
     }
 
+    /// Constructs the store of the chain.
+    ///
+    /// For mainnet and other chains except testnet we don't need to override runtime config for
+    /// first protocol versions.
+    /// For testnet, runtime config for genesis block was (incorrectly) different, that's why we
+    /// need to override it specifically to preserve compatibility.
+    pub fn for_chain_id(chain_id: &str) -> Self {
+        match chain_id {
+            "testnet" => {
+                let genesis_runtime_config = RuntimeConfig::initial_testnet_config();
+                Self::new(Some(&genesis_runtime_config))
+            }
+            _ => Self::new(None),
+        }
+    }
+
     /// Constructs test store.
     pub fn with_one_config(runtime_config: RuntimeConfig) -> Self {
         Self { store: BTreeMap::from_iter([(0, Arc::new(runtime_config))].iter().cloned()) }
@@ -100,6 +118,74 @@ impl RuntimeConfigStore {
             })
             .1
     }
+
+    /// Parameters which differ between the configs of the protocol versions.
+    /// They're named by their paths in the JSON representation of the config,
+    /// e.g. `wasm_config.limit_config.max_gas_burnt`, in alphabetical order.
+    pub fn diff(&self, from: ProtocolVersion, to: ProtocolVersion) -> RuntimeConfigDiffView {
+        let (mut old, mut new) = (BTreeMap::new(), BTreeMap::new());
+        flatten_json(String::new(), to_json(self.get_config(from)), &mut old);
+        flatten_json(String::new(), to_json(self.get_config(to)), &mut new);
+        let mut diff = RuntimeConfigDiffView {
+            from_protocol_version: from,
+            to_protocol_version: to,
+            changed: vec![],
+            added: vec![],
+            removed: vec![],
+        };
+        for (path, old_value) in &old {
+            match new.get(path) {
+                Some(new_value) if new_value != old_value => {
+                    diff.changed.push(ParameterChangeView {
+                        path: path.clone(),
+                        old_value: old_value.clone(),
+                        new_value: new_value.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => diff
+                    .removed
+                    .push(ParameterValueView { path: path.clone(), value: old_value.clone() }),
+            }
+        }
+        for (path, value) in new {
+            if !old.contains_key(&path) {
+                diff.added.push(ParameterValueView { path, value });
+            }
+        }
+        diff
+    }
+}
+
+fn to_json(config: &RuntimeConfig) -> Value {
+    serde_json::to_value(config).expect("RuntimeConfig serializes to JSON")
+}
+
+/// Collects the values which aren't objects or arrays by their paths, with
+/// the keys of objects and the indices of arrays separated by dots.
+fn flatten_json(path: String, value: Value, out: &mut BTreeMap<String, Value>) {
+    let join = |key: &dyn std::fmt::Display| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten_json(join(&key), value, out);
+            }
+        }
+        Value::Array(values) if !values.is_empty() => {
+            for (index, value) in values.into_iter().enumerate() {
+                flatten_json(join(&index), value, out);
+            }
+        }
+        value => {
+            out.insert(path, value);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -232,6 +318,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_diff() {
+        let store = RuntimeConfigStore::new(None);
+        let version = LowerStorageKeyLimit.protocol_version();
+        let diff = store.diff(version - 1, version);
+        let change = diff
+            .changed
+            .iter()
+            .find(|change| change.path == "wasm_config.limit_config.max_length_storage_key")
+            .unwrap();
+        assert_eq!(change.old_value, serde_json::json!(4 * 1024 * 1024));
+        assert_eq!(change.new_value, serde_json::json!(2 * 1024));
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        let paths: Vec<_> = diff.changed.iter().map(|change| change.path.clone()).collect();
+        let mut sorted = paths.clone();
+        sorted.sort();
+        assert_eq!(paths, sorted);
+        assert!(store.diff(version, version).changed.is_empty());
+
+        let mut old = BTreeMap::new();
+        flatten_json(
+            String::new(),
+            serde_json::json!({"a": {"b": [1, {"c": 2}], "d": {}}}),
+            &mut old,
+        );
+        assert_eq!(old.keys().collect::<Vec<_>>(), ["a.b.0", "a.b.1.c", "a.d"]);
+    }
+
     /// Use snapshot testing to check that the JSON representation of the
     /// configurations of each version is unchanged.
     /// If tests fail after an intended change, run `cargo insta review` accept
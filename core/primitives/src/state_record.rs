@@ -101,6 +101,62 @@ impl StateRecord {
     }
 }
 
+/// Entry of a sandbox state patch: a record to write or a record to remove.
+///
+/// Serialized untagged, so plain [`StateRecord`]s are valid patch entries.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum StatePatchRecord {
+    Set(StateRecord),
+    Delete(StateRecordTombstone),
+}
+
+impl StatePatchRecord {
+    /// Whether a sandbox patch can apply the entry: receipts and received data
+    /// can't be written.
+    pub fn is_patchable(&self) -> bool {
+        match self {
+            StatePatchRecord::Set(
+                StateRecord::Account { .. }
+                | StateRecord::Data { .. }
+                | StateRecord::Contract { .. }
+                | StateRecord::AccessKey { .. },
+            ) => true,
+            StatePatchRecord::Set(_) => false,
+            StatePatchRecord::Delete(_) => true,
+        }
+    }
+}
+
+impl From<StateRecord> for StatePatchRecord {
+    fn from(record: StateRecord) -> Self {
+        StatePatchRecord::Set(record)
+    }
+}
+
+/// Removal of a record from the state storage.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum StateRecordTombstone {
+    /// Removes the account along with its access keys, contract code and data.
+    DeleteAccount {
+        account_id: AccountId,
+    },
+    /// Removes contract data record, the key is encoded in base64.
+    DeleteData {
+        account_id: AccountId,
+        #[serde(with = "base64_format")]
+        data_key: Vec<u8>,
+    },
+    /// Removes contract code and resets the code hash of the account.
+    DeleteContract {
+        account_id: AccountId,
+    },
+    DeleteAccessKey {
+        account_id: AccountId,
+        public_key: PublicKey,
+    },
+}
+
 impl Display for StateRecord {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
@@ -7,7 +7,7 @@ use near_primitives_core::types::ProtocolVersion;
 
 use crate::account::{AccessKey, AccessKeyPermission, Account};
 use crate::block::Block;
-use crate::block_header::{BlockHeader, BlockHeaderV3};
+use crate::block_header::BlockHeader;
 use crate::errors::{EpochError, TxExecutionError};
 use crate::hash::CryptoHash;
 use crate::merkle::PartialMerkleTree;
@@ -20,7 +20,7 @@ use crate::transaction::{
 };
 use crate::types::{AccountId, Balance, BlockHeight, EpochId, EpochInfoProvider, Gas, Nonce};
 use crate::validator_signer::ValidatorSigner;
-use crate::version::PROTOCOL_VERSION;
+use crate::version::{BuildId, PROTOCOL_VERSION};
 use crate::views::FinalExecutionStatus;
 
 pub fn account_new(amount: Balance, code_hash: CryptoHash) -> Account {
@@ -256,15 +256,30 @@ impl SignedTransaction {
 }
 
 impl BlockHeader {
-    pub fn get_mut(&mut self) -> &mut BlockHeaderV3 {
+    #[cfg(not(feature = "protocol_feature_producer_build_id"))]
+    pub fn get_mut(&mut self) -> &mut crate::block_header::BlockHeaderV3 {
         match self {
-            BlockHeader::BlockHeaderV1(_) | BlockHeader::BlockHeaderV2(_) => {
+            BlockHeader::BlockHeaderV1(_)
+            | BlockHeader::BlockHeaderV2(_)
+            | BlockHeader::BlockHeaderV4(_) => {
                 panic!("old header should not appear in tests")
             }
             BlockHeader::BlockHeaderV3(header) => header,
         }
     }
 
+    #[cfg(feature = "protocol_feature_producer_build_id")]
+    pub fn get_mut(&mut self) -> &mut crate::block_header::BlockHeaderV4 {
+        match self {
+            BlockHeader::BlockHeaderV1(_)
+            | BlockHeader::BlockHeaderV2(_)
+            | BlockHeader::BlockHeaderV3(_) => {
+                panic!("old header should not appear in tests")
+            }
+            BlockHeader::BlockHeaderV4(header) => header,
+        }
+    }
+
     pub fn set_lastest_protocol_version(&mut self, latest_protocol_version: ProtocolVersion) {
         match self {
             BlockHeader::BlockHeaderV1(header) => {
@@ -276,6 +291,9 @@ impl BlockHeader {
             BlockHeader::BlockHeaderV3(header) => {
                 header.inner_rest.latest_protocol_version = latest_protocol_version;
             }
+            BlockHeader::BlockHeaderV4(header) => {
+                header.inner_rest.latest_protocol_version = latest_protocol_version;
+            }
         }
     }
 
@@ -298,6 +316,10 @@ impl BlockHeader {
                 header.hash = hash;
                 header.signature = signature;
             }
+            BlockHeader::BlockHeaderV4(header) => {
+                header.hash = hash;
+                header.signature = signature;
+            }
         }
     }
 }
@@ -439,6 +461,7 @@ impl Block {
             next_bp_hash,
             block_merkle_root,
             None,
+            BuildId::default(),
         )
     }
 }
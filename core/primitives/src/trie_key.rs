@@ -7,7 +7,7 @@ use std::mem::size_of;
 pub(crate) const ACCOUNT_DATA_SEPARATOR: &[u8; 1] = b",";
 
 /// Type identifiers used for DB key generation to store values in the key-value storage.
-pub(crate) mod col {
+pub mod col {
     /// This column id is used when storing `primitives::account::Account` type about a given
     /// `account_id`.
     pub const ACCOUNT: &[u8] = &[0];
@@ -405,6 +405,10 @@ pub mod trie_key_parsers {
         res
     }
 
+    pub fn get_raw_prefix_for_contract_codes() -> Vec<u8> {
+        col::CONTRACT_CODE.to_vec()
+    }
+
     pub fn get_raw_prefix_for_contract_data(account_id: &AccountId, prefix: &[u8]) -> Vec<u8> {
         let mut res = Vec::with_capacity(
             col::CONTRACT_DATA.len()
@@ -67,6 +67,7 @@ pub struct StoreKey(Vec<u8>);
 ///
 /// NOTE: Currently, this type is only used in the view_client and RPC to be able to transparently
 /// pretty-serialize the bytes arrays as base64-encoded strings (see `serialize.rs`).
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(
     Debug, Clone, PartialEq, Eq, DeriveAsRef, DeriveFrom, BorshSerialize, BorshDeserialize,
 )]
@@ -1012,3 +1013,39 @@ impl ops::Sub for TrieNodesCount {
         }
     }
 }
+
+/// Where the trie nodes and values read while applying a chunk were served
+/// from.  Unlike [`TrieNodesCount`] it doesn't affect the fees, it's only
+/// reported in metrics and debug pages.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrieReadStats {
+    pub chunk_cache_hits: u64,
+    pub shard_cache_hits: u64,
+    pub db_reads: u64,
+    /// Size of all the nodes and values read, wherever from.
+    pub bytes: u64,
+    pub db_bytes: u64,
+}
+
+impl TrieReadStats {
+    pub fn reads(&self) -> u64 {
+        self.chunk_cache_hits + self.shard_cache_hits + self.db_reads
+    }
+
+    /// Share of the reads served by either cache, `None` without reads.
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        match self.reads() {
+            0 => None,
+            reads => Some((self.chunk_cache_hits + self.shard_cache_hits) as f64 / reads as f64),
+        }
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.chunk_cache_hits += other.chunk_cache_hits;
+        self.shard_cache_hits += other.shard_cache_hits;
+        self.db_reads += other.db_reads;
+        self.bytes += other.bytes;
+        self.db_bytes += other.db_bytes;
+    }
+}
@@ -1,4 +1,5 @@
 use crate::types::Balance;
+use borsh::{BorshDeserialize, BorshSerialize};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -13,11 +14,55 @@ pub struct Version {
     pub rustc_version: String,
 }
 
+/// Compact identifier of the build of a binary, derived from its [`Version`],
+/// which block producers put in the headers of the blocks they produce.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BuildId(pub [u8; 8]);
+
+impl BuildId {
+    /// The first bytes of the hash of the version and the build.
+    pub fn new(version: &Version) -> Self {
+        let hash = crate::hash::hash(format!("{} {}", version.version, version.build).as_bytes());
+        let mut id = [0; 8];
+        id.copy_from_slice(&hash.as_ref()[..8]);
+        Self(id)
+    }
+}
+
+impl std::fmt::Display for BuildId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl std::str::FromStr for BuildId {
+    type Err = hex::FromHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut id = [0; 8];
+        hex::decode_to_slice(s, &mut id)?;
+        Ok(Self(id))
+    }
+}
+
+impl Serialize for BuildId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BuildId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 /// Database version.
 pub type DbVersion = u32;
 
 /// Current version of the database.
-pub const DB_VERSION: DbVersion = 31;
+pub const DB_VERSION: DbVersion = 33;
 
 use crate::upgrade_schedule::{get_protocol_version_internal, ProtocolUpgradeVotingSchedule};
 /// Protocol version type.
@@ -162,6 +207,17 @@ pub enum ProtocolFeature {
     /// alpha is min stake ratio
     #[cfg(feature = "protocol_feature_fix_staking_threshold")]
     FixStakingThreshold,
+    /// Add the build id of the producer's binary to the block header, see `BlockHeaderV4`.
+    #[cfg(feature = "protocol_feature_producer_build_id")]
+    ProducerBuildId,
+    /// Peers send `PeerMessage::TrackedShards` when the shards they track
+    /// change after the handshake.
+    #[cfg(feature = "protocol_feature_tracked_shards_message")]
+    TrackedShardsMessage,
+    /// Peers which accept compressed messages in their handshakes are sent
+    /// them, see `network.message_compression`.
+    #[cfg(feature = "protocol_feature_peer_message_compression")]
+    PeerMessageCompression,
 }
 
 /// Both, outgoing and incoming tcp connections to peers, will be rejected if `peer's`
@@ -243,6 +299,12 @@ impl ProtocolFeature {
             ProtocolFeature::RoutingExchangeAlgorithm => 117,
             #[cfg(feature = "protocol_feature_fix_staking_threshold")]
             ProtocolFeature::FixStakingThreshold => 126,
+            #[cfg(feature = "protocol_feature_producer_build_id")]
+            ProtocolFeature::ProducerBuildId => 127,
+            #[cfg(feature = "protocol_feature_tracked_shards_message")]
+            ProtocolFeature::TrackedShardsMessage => 128,
+            #[cfg(feature = "protocol_feature_peer_message_compression")]
+            ProtocolFeature::PeerMessageCompression => 128,
         }
     }
 }
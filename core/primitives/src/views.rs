@@ -3,6 +3,7 @@
 //! These types should only change when we cannot avoid this. Thus, when the counterpart internal
 //! type gets changed, the view should preserve the old shape and only re-map the necessary bits
 //! from the source structure in the relevant `From<SourceStruct>` impl.
+use std::collections::BTreeMap;
 use std::fmt;
 use std::sync::Arc;
 
@@ -13,14 +14,15 @@ use serde::{Deserialize, Serialize};
 use near_crypto::{PublicKey, Signature};
 
 use crate::account::{AccessKey, AccessKeyPermission, Account, FunctionCallPermission};
+use crate::account_pattern::AccountPattern;
 use crate::block::{Block, BlockHeader, Tip};
 use crate::block_header::{
     BlockHeaderInnerLite, BlockHeaderInnerRest, BlockHeaderInnerRestV2, BlockHeaderInnerRestV3,
-    BlockHeaderV1, BlockHeaderV2, BlockHeaderV3,
+    BlockHeaderInnerRestV4, BlockHeaderV1, BlockHeaderV2, BlockHeaderV3, BlockHeaderV4,
 };
-use crate::challenge::{Challenge, ChallengesResult};
+use crate::challenge::{Challenge, ChallengesResult, EquivocationEvidence};
 use crate::contract::ContractCode;
-use crate::errors::TxExecutionError;
+use crate::errors::{InvalidTxError, TxExecutionError};
 use crate::hash::{hash, CryptoHash};
 use crate::logging;
 use crate::merkle::MerklePath;
@@ -43,9 +45,9 @@ use crate::types::{
     AccountId, AccountWithPublicKey, Balance, BlockHeight, CompiledContractCache, EpochHeight,
     EpochId, FunctionArgs, Gas, Nonce, NumBlocks, ShardId, StateChangeCause, StateChangeKind,
     StateChangeValue, StateChangeWithCause, StateChangesRequest, StateRoot, StorageUsage, StoreKey,
-    StoreValue, ValidatorKickoutReason,
+    StoreValue, TrieReadStats, ValidatorKickoutReason,
 };
-use crate::version::{ProtocolVersion, Version};
+use crate::version::{BuildId, ProtocolVersion, Version};
 use validator_stake_view::ValidatorStakeView;
 
 /// A view of the account
@@ -224,6 +226,45 @@ pub struct CallResult {
     pub logs: Vec<String>,
 }
 
+/// Same as [`CallResult`] together with the gas burnt by the call.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default,
+)]
+pub struct CallResultWithGas {
+    pub result: Vec<u8>,
+    pub logs: Vec<String>,
+    pub gas_burnt: Gas,
+}
+
+/// Changes to the environment of a view function call.
+///
+/// They only affect the in-memory execution of the call and are never persisted.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(
+    BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default,
+)]
+pub struct CallFunctionOverrides {
+    /// Gas available to the call, capped at the node's `max_gas_burnt_view`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas: Option<Gas>,
+    /// Balance of the contract account during the call.
+    #[serde(default, with = "option_u128_dec_format", skip_serializing_if = "Option::is_none")]
+    pub balance: Option<Balance>,
+    /// Contract storage entries written on top of the real state before the call.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub storage: Vec<StorageOverride>,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct StorageOverride {
+    #[serde(rename = "key_base64", with = "base64_format")]
+    pub key: StoreKey,
+    #[serde(rename = "value_base64", with = "base64_format")]
+    pub value: StoreValue,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct QueryError {
@@ -250,6 +291,23 @@ impl FromIterator<AccessKeyInfoView> for AccessKeyList {
     }
 }
 
+/// An account of [`QueryRequest::ViewAccounts`].
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountViewOrError {
+    Account(AccountView),
+    /// Why the account couldn't be viewed, e.g. because it doesn't exist.
+    Error(String),
+}
+
+/// Accounts viewed at the same block, keyed by account id.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ViewAccountsResult {
+    pub accounts: BTreeMap<AccountId, AccountViewOrError>,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Eq, Clone)]
 pub enum QueryResponseKind {
@@ -259,11 +317,24 @@ pub enum QueryResponseKind {
     CallResult(CallResult),
     AccessKey(AccessKeyView),
     AccessKeyList(AccessKeyList),
+    CallResultWithGas(CallResultWithGas),
+    ViewAccounts(ViewAccountsResult),
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(
+    BorshSerialize,
+    BorshDeserialize,
+    Serialize,
+    Deserialize,
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    strum::IntoStaticStr,
+)]
 #[serde(tag = "request_type", rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
 pub enum QueryRequest {
     ViewAccount {
         account_id: AccountId,
@@ -289,6 +360,21 @@ pub enum QueryRequest {
         #[serde(rename = "args_base64", with = "base64_format")]
         args: FunctionArgs,
     },
+    /// Same as `CallFunction` but executed with the given `overrides`.
+    /// Nodes may refuse such calls, see `allow_view_call_overrides`.
+    CallFunctionWithOverrides {
+        account_id: AccountId,
+        method_name: String,
+        #[serde(rename = "args_base64", with = "base64_format")]
+        args: FunctionArgs,
+        #[serde(default)]
+        overrides: CallFunctionOverrides,
+    },
+    /// Accounts viewed at the same block.  Nodes refuse to view more than
+    /// their `view_accounts_limit` at once.
+    ViewAccounts {
+        account_ids: Vec<AccountId>,
+    },
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -331,6 +417,23 @@ pub struct DebugChunkStatus {
     pub gas_used: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub processing_time_ms: Option<u64>,
+    /// Known only for chunks applied recently by this node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apply_stage_times: Option<ChunkApplyStageTimesView>,
+}
+
+/// Time spent in the stages of applying a chunk, and where the reads of the
+/// trie were served from.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkApplyStageTimesView {
+    pub transactions_micros: u64,
+    pub receipts_micros: u64,
+    pub trie_update_micros: u64,
+    pub outcome_root_micros: u64,
+    /// Unknown if the trie recorded a storage proof, which bypasses the caches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trie_reads: Option<TrieReadStats>,
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -355,6 +458,10 @@ pub struct PeerInfoView {
     pub account_id: Option<AccountId>,
     pub height: BlockHeight,
     pub tracked_shards: Vec<ShardId>,
+    /// Shards among `tracked_shards` the peer failed to serve, which it isn't
+    /// asked for anymore.
+    #[serde(default)]
+    pub discounted_shards: Vec<ShardId>,
     pub archival: bool,
     pub peer_id: PublicKey,
 }
@@ -376,6 +483,59 @@ pub struct NetworkInfoView {
     pub num_connected_peers: usize,
     pub connected_peers: Vec<PeerInfoView>,
     pub known_producers: Vec<KnownProducerView>,
+    /// Estimated skew of the local clock against the peers in milliseconds,
+    /// positive if the local clock is ahead.
+    #[serde(default)]
+    pub clock_skew_millis: Option<i64>,
+    /// Candidate peers found in the DNS seeds.
+    #[serde(default)]
+    pub dns_seed_peers: Vec<DnsSeedPeerView>,
+    /// Last handshakes this node refused, most recent first.
+    #[serde(default)]
+    pub recent_handshake_refusals: Vec<HandshakeRefusalView>,
+    /// Peers this node stays connected to regardless of the peer limits.
+    #[serde(default)]
+    pub pinned_peers: Vec<PinnedPeerView>,
+    /// Connected peers an audited blacklist entry would refuse.
+    #[serde(default)]
+    pub blacklist_audit: Vec<AuditedPeerView>,
+}
+
+/// Handshake refused by this node.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct HandshakeRefusalView {
+    pub timestamp: DateTime<chrono::Utc>,
+    pub addr: String,
+    pub peer_id: PublicKey,
+    /// Protocol version the peer advertised in its handshake.
+    pub protocol_version: ProtocolVersion,
+    pub reason: String,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct PinnedPeerView {
+    pub peer_id: PublicKey,
+    pub addr: String,
+    pub connected: bool,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct AuditedPeerView {
+    pub peer_id: PublicKey,
+    pub addr: String,
+    /// The entry of `network.blacklist_audit` matching the peer.
+    pub entry: String,
+}
+
+/// Peer listed in a DNS seed.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct DnsSeedPeerView {
+    pub peer_id: PublicKey,
+    pub seed: String,
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -397,6 +557,15 @@ impl From<Tip> for BlockStatusView {
     }
 }
 
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BlockWithMissingChunksView {
+    pub height: BlockHeight,
+    pub hash: CryptoHash,
+    /// Hashes of the chunks the block is still waiting for.
+    pub missing_chunks: Vec<CryptoHash>,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EpochInfoView {
@@ -423,6 +592,362 @@ pub struct ChunkInfoView {
     pub num_of_chunks_in_progress: usize,
     pub num_of_orphans: usize,
     pub next_blocks_by_chunks: Vec<BlockByChunksView>,
+    /// Distribution of the chunk last completed in each shard, by shard id.
+    #[serde(default)]
+    pub chunk_distribution: Vec<ChunkDistributionView>,
+}
+
+/// How long it took a chunk to become available to the node, from the first
+/// time its header was seen to having the parts needed to reconstruct it.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkDistributionView {
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub chunk_hash: CryptoHash,
+    pub completion_time_millis: u64,
+    /// Whether parts had to be requested rather than all being pushed.
+    pub parts_requested: bool,
+}
+
+/// Transaction the chunk producer pulled from the pool but didn't include
+/// into the chunk because it turned out to be invalid.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DroppedTransactionView {
+    pub hash: CryptoHash,
+    pub signer_id: AccountId,
+    pub nonce: Nonce,
+    pub shard_id: ShardId,
+    /// Height of the chunk which was being produced.
+    pub height: BlockHeight,
+    pub reason: InvalidTxError,
+}
+
+/// Block or chunk built, but not broadcast, by a node in shadow production
+/// mode to check that it can produce them as a validator of the next epoch.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShadowProductionAttemptView {
+    pub height: BlockHeight,
+    /// Shard of the chunk, `None` for a block.
+    pub shard_id: Option<ShardId>,
+    pub duration_millis: u64,
+    /// Why building or verifying it failed.
+    pub error: Option<String>,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ShadowProductionView {
+    pub next_epoch_id: EpochId,
+    pub next_epoch_block_producer: bool,
+    /// Shards the node produces chunks for in the next epoch.
+    pub next_epoch_chunk_shards: Vec<ShardId>,
+    /// Block producers of the next epoch the node knows no route to.
+    pub unreachable_producers: Vec<AccountId>,
+    /// Most recent first.
+    pub recent_attempts: Vec<ShadowProductionAttemptView>,
+}
+
+/// Why the node's validator didn't produce a block or chunk it was assigned.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProductionSkipReason {
+    /// The block to build on wasn't received before the deadline.
+    PrevBlockNotReceived,
+    /// The block had to wait for the approvals of the previous height.
+    NotEnoughApprovals,
+    /// The block or chunk had to wait for the chunks of the previous block.
+    MissingChunkParts,
+    /// The state for the epoch wasn't downloaded yet.
+    StateNotCaughtUp,
+    /// The local validator key doesn't match the one of the validator.
+    SignerError(String),
+    /// Building the block or chunk failed.
+    Error(String),
+}
+
+impl ProductionSkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PrevBlockNotReceived => "prev_block_not_received",
+            Self::NotEnoughApprovals => "not_enough_approvals",
+            Self::MissingChunkParts => "missing_chunk_parts",
+            Self::StateNotCaughtUp => "state_not_caught_up",
+            Self::SignerError(_) => "signer_error",
+            Self::Error(_) => "error",
+        }
+    }
+}
+
+/// Block or chunk the node's validator was assigned and didn't produce.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProductionSkipView {
+    pub height: BlockHeight,
+    /// Shard of the chunk, `None` for a block.
+    pub shard_id: Option<ShardId>,
+    pub epoch_id: EpochId,
+    pub reason: ProductionSkipReason,
+    /// When the reason was first seen, `None` if the production was never
+    /// attempted.
+    pub reason_since: Option<DateTime<chrono::Utc>>,
+    /// When a block past the height was accepted.
+    pub missed_at: DateTime<chrono::Utc>,
+}
+
+/// Chunk the node's validator produced, with how its parts were sent out and
+/// whether the canonical chain ended up including it.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProducedChunkView {
+    pub chunk_hash: CryptoHash,
+    pub shard_id: ShardId,
+    pub height_created: BlockHeight,
+    pub epoch_id: EpochId,
+    pub produced_at: DateTime<chrono::Utc>,
+    /// When the parts were handed to the network.
+    pub parts_sent_at: DateTime<chrono::Utc>,
+    /// The parts sent to each block producer, the node's own excluded.
+    pub parts_sent: Vec<ChunkPartsSentView>,
+    /// `None` until the head reaches the height of the chunk.
+    pub inclusion: Option<ChunkInclusionView>,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkPartsSentView {
+    pub account_id: AccountId,
+    pub part_ords: Vec<u64>,
+}
+
+/// The first block of the canonical chain at or past the height of a chunk.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInclusionView {
+    /// Whether the block includes the chunk.
+    pub included: bool,
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    /// When the block became the head.
+    pub seen_at: DateTime<chrono::Utc>,
+}
+
+/// Blocks of a window of the canonical chain and the stake of their
+/// producers, by the protocol version and build the producers ran.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProducerVersionsView {
+    pub first_height: BlockHeight,
+    pub last_height: BlockHeight,
+    pub num_blocks: NumBlocks,
+    /// Stake of the producers of the blocks of the window.
+    #[serde(with = "u128_dec_format")]
+    pub total_stake: Balance,
+    /// Most blocks first.
+    pub versions: Vec<ProducerVersionView>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProducerVersionView {
+    pub latest_protocol_version: ProtocolVersion,
+    /// `None` for the blocks with a header which doesn't carry it.
+    pub build_id: Option<BuildId>,
+    pub num_blocks: NumBlocks,
+    /// The producers whose last block in the window was produced with this
+    /// version and build, and their stake.
+    pub producers: Vec<AccountId>,
+    #[serde(with = "u128_dec_format")]
+    pub stake: Balance,
+}
+
+/// Transition of the node between two sync states.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SyncTransitionView {
+    pub timestamp: DateTime<chrono::Utc>,
+    pub from: String,
+    pub to: String,
+    /// Why the node switched, e.g. which peer claimed a higher height.
+    pub trigger: String,
+    pub head_height: BlockHeight,
+    pub header_head_height: BlockHeight,
+    /// Highest height claimed by the peers.
+    pub highest_height: BlockHeight,
+}
+
+/// Block known at a height where the node knows of more than one.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ForkBlockView {
+    pub hash: CryptoHash,
+    pub prev_hash: CryptoHash,
+    pub producer: AccountId,
+    /// Whether the block is on the chain of the node's head.
+    pub canonical: bool,
+}
+
+/// Non-final height at which the node knows of more than one block.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ForkView {
+    pub height: BlockHeight,
+    pub blocks: Vec<ForkBlockView>,
+}
+
+/// Where a receipt which isn't executed yet was found.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingReceiptSource {
+    /// In the delayed receipts queue of the shard.
+    Delayed,
+    /// Sent in one of the latest blocks, with no chunk of the shard of the
+    /// receiver since to execute it.
+    InFlight,
+}
+
+/// Action receipt for an account which isn't executed yet.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PendingReceiptView {
+    pub receipt_id: CryptoHash,
+    pub predecessor_id: AccountId,
+    pub source: PendingReceiptSource,
+    /// Kinds of the actions, with the method names of the function calls.
+    pub actions: Vec<String>,
+    #[serde(with = "u128_dec_format")]
+    pub deposit: Balance,
+    pub gas: Gas,
+}
+
+/// Receipts for an account which aren't executed yet, as far as the node
+/// knows.  Only the shards the node tracks are looked at.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PendingReceiptsView {
+    /// The block at which the receipts are pending.
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    pub receipts: Vec<PendingReceiptView>,
+    /// Whether the delayed receipts queue is longer than the node scans, in
+    /// which case the rest of it may have more receipts for the account.
+    pub truncated: bool,
+}
+
+/// What a transaction of an access key needs, read at the same final block so
+/// that the nonce and the block hash agree.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxConstructionInfoView {
+    /// Current nonce of the access key.
+    pub nonce: Nonce,
+    /// Nonce to give the next transaction of the key.
+    pub recommended_nonce: Nonce,
+    /// Block to use as the `block_hash` of the transaction.
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    /// Number of blocks after the head of the node a transaction built
+    /// against the block can still be included in.
+    pub validity_blocks_remaining: NumBlocks,
+}
+
+/// Parameter of the runtime config whose value differs between two protocol
+/// versions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ParameterChangeView {
+    /// Path of the parameter in the runtime config, e.g.
+    /// `transaction_costs.action_receipt_creation_config.send_sir`.
+    pub path: String,
+    pub old_value: serde_json::Value,
+    pub new_value: serde_json::Value,
+}
+
+/// Parameter of the runtime config of only one of two protocol versions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ParameterValueView {
+    pub path: String,
+    pub value: serde_json::Value,
+}
+
+/// Differences between the runtime configs of two protocol versions, with the
+/// parameters of each list sorted by their paths.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeConfigDiffView {
+    pub from_protocol_version: ProtocolVersion,
+    pub to_protocol_version: ProtocolVersion,
+    pub changed: Vec<ParameterChangeView>,
+    /// Parameters only the config of `to_protocol_version` has.
+    pub added: Vec<ParameterValueView>,
+    /// Parameters only the config of `from_protocol_version` has.
+    pub removed: Vec<ParameterValueView>,
+}
+
+/// Result of validating a transaction without submitting it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxValidationView {
+    /// Every problem found with the transaction, empty if it's valid.
+    pub violations: Vec<InvalidTxError>,
+    /// Gas burnt for converting the transaction into a receipt, `None` if the
+    /// transaction doesn't pass the checks which don't need the state.
+    pub gas_burnt: Option<Gas>,
+    /// Whether the transaction was also checked against the signer's account
+    /// and access key.
+    pub state_checked: bool,
+}
+
+/// Conflicting messages signed by the same validator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EquivocationView {
+    /// `block`, `chunk` or `approval`.
+    pub kind: String,
+    pub height: BlockHeight,
+    pub shard_id: Option<ShardId>,
+    pub signer: AccountId,
+    pub public_key: PublicKey,
+    pub left_hash: CryptoHash,
+    pub right_hash: CryptoHash,
+    /// Borsh serialized `EquivocationEvidence` containing both signed
+    /// messages, which anyone can check with `EquivocationEvidence::verify`.
+    #[serde(with = "base64_format")]
+    pub evidence: Vec<u8>,
+}
+
+impl From<&EquivocationEvidence> for EquivocationView {
+    fn from(evidence: &EquivocationEvidence) -> Self {
+        let (left_hash, right_hash) = evidence.hashes();
+        Self {
+            kind: evidence.kind.as_str().to_string(),
+            height: evidence.height(),
+            shard_id: evidence.shard_id(),
+            signer: evidence.signer.clone(),
+            public_key: evidence.public_key.clone(),
+            left_hash,
+            right_hash,
+            evidence: evidence.try_to_vec().expect("Failed to serialize"),
+        }
+    }
+}
+
+/// Outcome of processing a single block, as kept in the block processing log.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockProcessingView {
+    pub hash: CryptoHash,
+    pub height: BlockHeight,
+    /// When processing finished, in nanoseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub processing_time_micros: u64,
+    /// Time it took to apply each shard's chunk, absent for shards whose
+    /// chunk failed to apply.
+    pub shard_apply_time_micros: Vec<(ShardId, u64)>,
+    pub outcome: BlockProcessingOutcome,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum BlockProcessingOutcome {
+    Accepted,
+    Orphaned,
+    MissingChunks,
+    Error(String),
 }
 
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
@@ -434,11 +959,37 @@ pub struct DetailedDebugStatus {
     pub current_head_status: BlockStatusView,
     pub current_header_head_status: BlockStatusView,
     pub orphans: Vec<BlockStatusView>,
-    pub blocks_with_missing_chunks: Vec<BlockStatusView>,
+    // Total size in bytes of the orphans.
+    pub orphans_bytes: u64,
+    pub blocks_with_missing_chunks: Vec<BlockWithMissingChunksView>,
+    // Total size in bytes of the blocks waiting for chunks.
+    pub blocks_with_missing_chunks_bytes: u64,
     // List of epochs - in descending order (next epoch is first).
     pub epochs_info: Vec<EpochInfoView>,
     pub block_production_delay_millis: u64,
     pub chunk_info: ChunkInfoView,
+    // Transactions recently dropped during chunk production, most recent first.
+    pub dropped_transactions: Vec<DroppedTransactionView>,
+    // Blocks recently processed by the node, most recent first.
+    pub recent_block_processing: Vec<BlockProcessingView>,
+    // Set if the node is in shadow production mode and is a validator of the
+    // next epoch only.
+    pub shadow_production: Option<ShadowProductionView>,
+}
+
+/// Shards the node tracks whether or not it validates them.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TrackedShardsView {
+    /// `tracked_accounts` from the config.
+    pub tracked_accounts: Vec<AccountPattern>,
+    /// `tracked_shards` from the config, any shard there makes the node track
+    /// all of them.
+    pub tracked_shards: Vec<ShardId>,
+    /// Shards tracked in the current epoch.
+    pub shard_ids: Vec<ShardId>,
+    /// Shards tracked in the next epoch, numbered as in its shard layout.
+    pub next_epoch_shard_ids: Vec<ShardId>,
 }
 
 // TODO: add more information to status.
@@ -462,6 +1013,19 @@ pub struct StatusResponse {
     pub sync_info: StatusSyncInfo,
     /// Validator id of the node
     pub validator_account_id: Option<AccountId>,
+    /// Public key the node currently signs blocks, chunks and approvals with
+    pub validator_public_key: Option<PublicKey>,
+    /// Estimated skew of the local clock against the peers in milliseconds,
+    /// positive if the local clock is ahead.  None if too few peers are
+    /// connected to estimate it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_skew_millis: Option<i64>,
+    /// Last few transitions between sync states, most recent first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sync_transitions: Vec<SyncTransitionView>,
+    /// Shards the node tracks, if it's configured to track any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracked_shards: Option<TrackedShardsView>,
     /// Information about last blocks, network, epoch and chain & chunk info.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detailed_debug_status: Option<DetailedDebugStatus>,
@@ -520,6 +1084,9 @@ pub struct BlockHeaderView {
     pub approvals: Vec<Option<Signature>>,
     pub signature: Signature,
     pub latest_protocol_version: ProtocolVersion,
+    /// Build of the producer's binary, for the headers which carry it.
+    #[serde(default)]
+    pub producer_build_id: Option<BuildId>,
 }
 
 impl From<BlockHeader> for BlockHeaderView {
@@ -561,6 +1128,7 @@ impl From<BlockHeader> for BlockHeaderView {
             approvals: header.approvals().to_vec(),
             signature: header.signature().clone(),
             latest_protocol_version: header.latest_protocol_version(),
+            producer_build_id: header.producer_build_id(),
         }
     }
 }
@@ -642,6 +1210,39 @@ impl From<BlockHeaderView> for BlockHeader {
             };
             header.init();
             BlockHeader::BlockHeaderV2(Box::new(header))
+        } else if let Some(producer_build_id) = view.producer_build_id {
+            let mut header = BlockHeaderV4 {
+                prev_hash: view.prev_hash,
+                inner_lite,
+                inner_rest: BlockHeaderInnerRestV4 {
+                    chunk_receipts_root: view.chunk_receipts_root,
+                    chunk_headers_root: view.chunk_headers_root,
+                    chunk_tx_root: view.chunk_tx_root,
+                    challenges_root: view.challenges_root,
+                    random_value: view.random_value,
+                    validator_proposals: view
+                        .validator_proposals
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    chunk_mask: view.chunk_mask,
+                    gas_price: view.gas_price,
+                    block_ordinal: view.block_ordinal.unwrap_or(0),
+                    total_supply: view.total_supply,
+                    challenges_result: view.challenges_result,
+                    last_final_block: view.last_final_block,
+                    last_ds_final_block: view.last_ds_final_block,
+                    prev_height: view.prev_height.unwrap_or_default(),
+                    epoch_sync_data_hash: view.epoch_sync_data_hash,
+                    approvals: view.approvals.clone(),
+                    latest_protocol_version: view.latest_protocol_version,
+                    producer_build_id,
+                },
+                signature: view.signature,
+                hash: CryptoHash::default(),
+            };
+            header.init();
+            BlockHeader::BlockHeaderV4(Box::new(header))
         } else {
             let mut header = BlockHeaderV3 {
                 prev_hash: view.prev_hash,
@@ -730,6 +1331,17 @@ impl From<BlockHeader> for BlockHeaderInnerLiteView {
                 next_bp_hash: header.inner_lite.next_bp_hash,
                 block_merkle_root: header.inner_lite.block_merkle_root,
             },
+            BlockHeader::BlockHeaderV4(header) => BlockHeaderInnerLiteView {
+                height: header.inner_lite.height,
+                epoch_id: header.inner_lite.epoch_id.0,
+                next_epoch_id: header.inner_lite.next_epoch_id.0,
+                prev_state_root: header.inner_lite.prev_state_root,
+                outcome_root: header.inner_lite.outcome_root,
+                timestamp: header.inner_lite.timestamp,
+                timestamp_nanosec: header.inner_lite.timestamp,
+                next_bp_hash: header.inner_lite.next_bp_hash,
+                block_merkle_root: header.inner_lite.block_merkle_root,
+            },
         }
     }
 }
@@ -774,6 +1386,22 @@ pub struct ChunkHeaderView {
     pub tx_root: CryptoHash,
     pub validator_proposals: Vec<ValidatorStakeView>,
     pub signature: Signature,
+    /// Only in the chunks of the `block` RPC asked for with
+    /// `include_chunk_summaries`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ChunkSummaryView>,
+}
+
+/// What's in a chunk, for showing a block without fetching each of its
+/// chunks.  The counts are `None` when the node doesn't have the chunk, which
+/// is the case for the shards it doesn't track.  The size of the chunk is the
+/// `encoded_length` of the header.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkSummaryView {
+    pub num_transactions: Option<u64>,
+    /// Receipts the chunk applies, which are the ones sent by the previous
+    /// chunks.
+    pub num_receipts: Option<u64>,
 }
 
 impl From<ShardChunkHeader> for ChunkHeaderView {
@@ -801,6 +1429,7 @@ impl From<ShardChunkHeader> for ChunkHeaderView {
             tx_root: *inner.tx_root(),
             validator_proposals: inner.validator_proposals().map(Into::into).collect(),
             signature,
+            summary: None,
         }
     }
 }
@@ -832,7 +1461,7 @@ impl From<ChunkHeaderView> for ShardChunkHeader {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BlockView {
     pub author: AccountId,
     pub header: BlockHeaderView,
@@ -849,7 +1478,7 @@ impl BlockView {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChunkView {
     pub author: AccountId,
     pub header: ChunkHeaderView,
@@ -1519,6 +2148,27 @@ pub struct EpochValidatorInfo {
     pub epoch_height: EpochHeight,
 }
 
+/// Validators the epoch manager would select for the epoch after next if the
+/// current epoch ended at `block_hash`.
+///
+/// This is only a prediction: proposals, kickouts and rewards keep changing
+/// until the current epoch actually ends.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EpochValidatorsPreview {
+    /// Block the prediction is made at.
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    /// Height of the epoch the prediction is for.
+    pub epoch_height: EpochHeight,
+    pub predicted_validators: Vec<NextEpochValidatorInfo>,
+    pub predicted_fishermen: Vec<ValidatorStakeView>,
+    #[serde(with = "u128_dec_format")]
+    pub predicted_seat_price: Balance,
+    /// Validators which would be kicked out at the end of the current epoch.
+    pub predicted_kickout: Vec<ValidatorKickoutView>,
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct ValidatorKickoutView {
@@ -1526,6 +2176,61 @@ pub struct ValidatorKickoutView {
     pub reason: ValidatorKickoutReason,
 }
 
+/// Digest of the roots a node stored for the shards it tracks after a block,
+/// for checking cheaply that nodes have the same state.  Nodes tracking
+/// different shards can compare the fingerprints of the shards they both track.
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct StateFingerprintView {
+    pub block_hash: CryptoHash,
+    pub block_height: BlockHeight,
+    /// Hash of the block hash and the fingerprints of the shards.
+    pub fingerprint: CryptoHash,
+    pub shards: Vec<ShardStateFingerprintView>,
+}
+
+#[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ShardStateFingerprintView {
+    pub shard_id: ShardId,
+    /// State root after applying the chunk of the block, or carried over from
+    /// the last chunk if the block has none.
+    pub state_root: StateRoot,
+    /// Root of the outcomes of the last chunk applied.
+    pub outcome_root: CryptoHash,
+    /// Hash of the block hash, the shard id and the roots.
+    pub fingerprint: CryptoHash,
+}
+
+impl StateFingerprintView {
+    /// The fingerprint of the shards with their state and outcome roots, in
+    /// increasing shard id.
+    pub fn new(
+        block_hash: CryptoHash,
+        block_height: BlockHeight,
+        shards: impl IntoIterator<Item = (ShardId, StateRoot, CryptoHash)>,
+    ) -> Self {
+        let shards: Vec<ShardStateFingerprintView> = shards
+            .into_iter()
+            .map(|(shard_id, state_root, outcome_root)| ShardStateFingerprintView {
+                shard_id,
+                state_root,
+                outcome_root,
+                fingerprint: CryptoHash::hash_borsh(&(
+                    block_hash,
+                    shard_id,
+                    state_root,
+                    outcome_root,
+                )),
+            })
+            .collect();
+        let shard_fingerprints: Vec<CryptoHash> =
+            shards.iter().map(|shard| shard.fingerprint).collect();
+        let fingerprint = CryptoHash::hash_borsh(&(block_hash, shard_fingerprints));
+        Self { block_hash, block_height, fingerprint, shards }
+    }
+}
+
 #[cfg_attr(feature = "deepsize_feature", derive(deepsize::DeepSizeOf))]
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct CurrentEpochValidatorInfo {
@@ -1666,7 +2371,7 @@ impl From<StateChangeKind> for StateChangeKindView {
 pub type StateChangesKindsView = Vec<StateChangeKindView>;
 
 /// See crate::types::StateChangeCause for details.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum StateChangeCauseView {
     NotWritableToDisk,
@@ -1710,7 +2415,7 @@ impl From<StateChangeCause> for StateChangeCauseView {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "type", content = "change")]
 pub enum StateChangeValueView {
     AccountUpdate {
@@ -1783,7 +2488,7 @@ impl From<StateChangeValue> for StateChangeValueView {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StateChangeWithCauseView {
     pub cause: StateChangeCauseView,
     #[serde(flatten)]
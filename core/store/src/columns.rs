@@ -234,6 +234,16 @@ pub enum DBCol {
     /// - *Rows*: BlockShardId (BlockHash || ShardId) - 40 bytes
     /// - *Column type*: StateChangesForSplitStates
     StateChangesForSplitStates = 49,
+    /// Ring buffer with the outcomes of the most recently processed blocks,
+    /// kept for postmortems.
+    /// - *Rows*: position in the buffer (u64)
+    /// - *Column type*: BlockProcessingView
+    BlockProcessingLog = 50,
+    /// Pairs of conflicting messages signed by the same validator, see
+    /// `near_chain::misbehavior`.
+    /// - *Rows*: height (u64) || hash of the evidence (CryptoHash)
+    /// - *Column type*: EquivocationEvidence
+    Misbehavior = 51,
 }
 
 impl DBCol {
@@ -311,6 +321,8 @@ const NO_GC_COLUMNS: [bool; DBCol::COUNT] = col_set(&[
     DBCol::EpochValidatorInfo, // https://github.com/nearprotocol/nearcore/pull/2952
     DBCol::EpochStart,         // https://github.com/nearprotocol/nearcore/pull/2952
     DBCol::CachedContractCode,
+    DBCol::BlockProcessingLog, // bounded by its own size
+    DBCol::Misbehavior,        // evidence is kept to be shared
 ]);
 
 const OPTIONAL_GC_COLUMNS: [bool; DBCol::COUNT] = col_set(&[
@@ -388,6 +400,8 @@ impl fmt::Display for DBCol {
             Self::EpochValidatorInfo => "epoch validator info",
             Self::HeaderHashesByHeight => "header hashes indexed by their height",
             Self::StateChangesForSplitStates => "state changes indexed by block hash and shard id",
+            Self::BlockProcessingLog => "recently processed blocks",
+            Self::Misbehavior => "conflicting messages signed by the same validator",
         };
         write!(f, "{}", desc)
     }
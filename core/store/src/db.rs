@@ -1,12 +1,13 @@
 use super::StoreConfig;
 use crate::db::refcount::merge_refcounted_records;
 use crate::{metrics, DBCol};
-use near_primitives::version::DbVersion;
+use borsh::BorshDeserialize;
+use near_primitives::version::{DbVersion, Version};
 use once_cell::sync::Lazy;
 use rocksdb::checkpoint::Checkpoint;
 use rocksdb::{
     BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, Direction, Env, IteratorMode,
-    Options, ReadOptions, WriteBatch, DB,
+    LiveFile, Options, ReadOptions, WriteBatch, DB,
 };
 use std::collections::HashMap;
 use std::io;
@@ -20,11 +21,15 @@ use tracing::{error, info, warn};
 pub(crate) mod refcount;
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct DBError(String);
+pub struct DBError {
+    message: String,
+    /// Kind of the RocksDB error, `None` for errors of our own.
+    kind: Option<rocksdb::ErrorKind>,
+}
 
 impl fmt::Display for DBError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Display::fmt(&self.0, f)
+        fmt::Display::fmt(&self.message, f)
     }
 }
 
@@ -32,7 +37,26 @@ impl std::error::Error for DBError {}
 
 impl From<rocksdb::Error> for DBError {
     fn from(err: rocksdb::Error) -> Self {
-        DBError(err.into_string())
+        DBError { kind: Some(err.kind()), message: err.into_string() }
+    }
+}
+
+impl DBError {
+    fn new(message: String) -> Self {
+        DBError { message, kind: None }
+    }
+
+    /// Returns whether the error was caused by the database being locked by
+    /// another process, e.g. a running node.
+    ///
+    /// RocksDB fails to take the `LOCK` file with an I/O error whose status
+    /// names the failed operation, see `PosixFileSystem::LockFile`.
+    pub fn is_locked(&self) -> bool {
+        self.kind == Some(rocksdb::ErrorKind::IOError)
+            && self.message.strip_prefix("IO error: ").map_or(false, |state| {
+                state.starts_with("While lock file")
+                    || state.starts_with("lock hold by current process")
+            })
     }
 }
 
@@ -43,11 +67,16 @@ impl From<DBError> for io::Error {
 }
 
 pub const VERSION_KEY: &[u8; 7] = b"VERSION";
+/// JSON of the [`Version`] of the neard which last opened the database for
+/// writing, stored in DBCol::DbVersion next to the version of the database.
+/// Missing if that neard didn't record it.
+pub const NEARD_VERSION_KEY: &[u8; 13] = b"NEARD_VERSION";
 
 pub const HEAD_KEY: &[u8; 4] = b"HEAD";
 pub const TAIL_KEY: &[u8; 4] = b"TAIL";
 pub const CHUNK_TAIL_KEY: &[u8; 10] = b"CHUNK_TAIL";
 pub const FORK_TAIL_KEY: &[u8; 9] = b"FORK_TAIL";
+pub const STATE_CHANGES_TAIL_KEY: &[u8; 18] = b"STATE_CHANGES_TAIL";
 pub const HEADER_HEAD_KEY: &[u8; 11] = b"HEADER_HEAD";
 pub const FINAL_HEAD_KEY: &[u8; 10] = b"FINAL_HEAD";
 pub const LATEST_KNOWN_KEY: &[u8; 12] = b"LATEST_KNOWN";
@@ -57,6 +86,23 @@ pub const GENESIS_STATE_ROOTS_KEY: &[u8; 19] = b"GENESIS_STATE_ROOTS";
 /// Boolean stored in DBCol::BlockMisc indicating whether the database is for an
 /// archival node.  The default value (if missing) is false.
 pub const IS_ARCHIVE_KEY: &[u8; 10] = b"IS_ARCHIVE";
+/// Boolean stored in DBCol::BlockMisc indicating whether the database is
+/// a clone made by `neard database clone`, which nodes refuse to run with
+/// unless told to.  The default value (if missing) is false.
+pub const IS_CLONE_KEY: &[u8; 8] = b"IS_CLONE";
+
+/// What a database records about itself, see [`RocksDB::get_metadata`].
+#[derive(Debug, Clone)]
+pub struct DbMetadata {
+    pub version: DbVersion,
+    /// The neard which last opened the database for writing, `None` if it
+    /// didn't record itself.
+    pub neard_version: Option<Version>,
+    /// Whether the database is of an archival node.
+    pub is_archive: bool,
+    /// Whether the database is a clone made by `neard database clone`.
+    pub is_clone: bool,
+}
 
 pub(crate) struct DBTransaction {
     pub(crate) ops: Vec<DBOp>,
@@ -107,6 +153,7 @@ pub struct RocksDB {
     db: DB,
     db_opt: Options,
     cfs: Vec<*const ColumnFamily>,
+    read_only: bool,
 
     check_free_space_counter: std::sync::atomic::AtomicU16,
     check_free_space_interval: u16,
@@ -121,7 +168,8 @@ pub struct RocksDB {
 unsafe impl Send for RocksDB {}
 unsafe impl Sync for RocksDB {}
 
-fn col_name(col: DBCol) -> String {
+/// Name of the RocksDB column family storing the column.
+pub fn col_name(col: DBCol) -> String {
     format!("col{}", col as usize)
 }
 
@@ -144,20 +192,20 @@ fn col_name(col: DBCol) -> String {
 fn ensure_max_open_files_limit(max_open_files: u32) -> Result<(), DBError> {
     let required = max_open_files as u64 + 1000;
     let (soft, hard) = rlimit::Resource::NOFILE.get().map_err(|err| {
-        DBError(format!("Unable to get limit for the number of open files (NOFILE): {err}"))
+        DBError::new(format!("Unable to get limit for the number of open files (NOFILE): {err}"))
     })?;
     if required <= soft {
         Ok(())
     } else if required <= hard {
         rlimit::Resource::NOFILE.set(required, hard).map_err(|err| {
-            DBError(format!(
+            DBError::new(format!(
                 "Unable to change limit for the number of open files (NOFILE) \
                  from ({soft}, {hard}) to ({required}, {hard}) (for configured \
                  max_open_files={max_open_files}): {err}"
             ))
         })
     } else {
-        Err(DBError(format!(
+        Err(DBError::new(format!(
             "Hard limit for the number of open files (NOFILE) is too low \
              ({hard}).  At least {required} is required (for configured \
              max_open_files={max_open_files}).  Set ‘ulimit -Hn’ accordingly \
@@ -170,8 +218,6 @@ impl RocksDB {
     /// Opens the database either in read only or in read/write mode depending
     /// on the read_only parameter specified in the store_config.
     pub fn open(path: impl AsRef<Path>, store_config: &StoreConfig) -> Result<RocksDB, DBError> {
-        use strum::IntoEnumIterator;
-
         ensure_max_open_files_limit(store_config.max_open_files)?;
 
         let (db, db_opt) = if store_config.read_only {
@@ -179,19 +225,65 @@ impl RocksDB {
         } else {
             Self::open_read_write(path.as_ref(), store_config)
         }?;
+        Ok(Self::from_db(db, db_opt, store_config.read_only))
+    }
+
+    /// Opens a secondary instance of the database, which keeps its info logs
+    /// in `secondary_path`.  Unlike a read only instance, it can follow the
+    /// writes of the node running on the database with
+    /// [`Self::try_catch_up_with_primary`].  The columns are opened without
+    /// their merge operators, the instance is meant for reading properties and
+    /// listing files.
+    pub fn open_as_secondary(
+        path: &Path,
+        secondary_path: &Path,
+        store_config: &StoreConfig,
+    ) -> Result<RocksDB, DBError> {
+        use strum::IntoEnumIterator;
+
+        let mut options = rocksdb_options(store_config);
+        // The secondary instance has to keep all the files open to see the
+        // ones the primary deletes.
+        options.set_max_open_files(-1);
+        let db =
+            DB::open_cf_as_secondary(&options, path, secondary_path, DBCol::iter().map(col_name))?;
+        Ok(Self::from_db(db, options, true))
+    }
+
+    fn from_db(db: DB, db_opt: Options, read_only: bool) -> Self {
+        use strum::IntoEnumIterator;
 
         let cfs = DBCol::iter()
             .map(|col| db.cf_handle(&col_name(col)).unwrap() as *const ColumnFamily)
             .collect();
-        Ok(Self {
+        Self {
             db,
             db_opt,
             cfs,
+            read_only,
             check_free_space_interval: 256,
             check_free_space_counter: std::sync::atomic::AtomicU16::new(0),
             free_space_threshold: bytesize::ByteSize::mb(16),
             _instance_counter: InstanceCounter::new(),
-        })
+        }
+    }
+
+    /// Applies the writes the primary instance made since the secondary
+    /// instance was opened or last caught up.
+    pub fn try_catch_up_with_primary(&self) -> Result<(), DBError> {
+        Ok(self.db.try_catch_up_with_primary()?)
+    }
+
+    /// Lists the SST files of all columns.
+    pub fn live_files(&self) -> Result<Vec<LiveFile>, DBError> {
+        Ok(self.db.live_files()?)
+    }
+
+    /// Reads an integer property of the column, `None` if RocksDB doesn't
+    /// provide it.
+    pub fn property_int_value(&self, col: DBCol, name: &str) -> Result<Option<u64>, DBError> {
+        let cf_handle = unsafe { &*self.cfs[col as usize] };
+        Ok(self.db.property_int_value_cf(cf_handle, name)?)
     }
 
     /// Opens a read only database.
@@ -256,6 +348,9 @@ pub(crate) trait Database: Sync + Send {
     fn as_rocksdb(&self) -> Option<&RocksDB> {
         None
     }
+    fn is_read_only(&self) -> bool {
+        false
+    }
     fn get_store_statistics(&self) -> Option<StoreStatistics> {
         None
     }
@@ -321,6 +416,11 @@ impl Database for RocksDB {
     }
 
     fn write(&self, transaction: DBTransaction) -> Result<(), DBError> {
+        if self.read_only {
+            return Err(DBError::new(
+                "database has been opened in read-only mode and cannot be written to".into(),
+            ));
+        }
         if let Err(check) = self.pre_write_check() {
             if check.is_io() {
                 warn!("unable to verify remaing disk space: {:?}, continueing write without verifying (this may result in unrecoverable data loss if disk space is exceeded", check)
@@ -369,6 +469,10 @@ impl Database for RocksDB {
         Some(self)
     }
 
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     fn get_store_statistics(&self) -> Option<StoreStatistics> {
         if let Some(stats_str) = self.db_opt.get_statistics() {
             match parse_statistics(&stats_str) {
@@ -601,17 +705,59 @@ impl RocksDB {
 
     /// Returns version of the database state on disk.
     pub fn get_version(path: &Path) -> Result<DbVersion, DBError> {
-        let value = RocksDB::open(path, &StoreConfig::read_only())?
-            .get(DBCol::DbVersion, VERSION_KEY)?
-            .ok_or_else(|| {
-                DBError(
-                    "Failed to read database version; \
-                     it’s not a neard database or database is corrupted."
-                        .into(),
-                )
-            })?;
+        let db = Self::open_columns_read_only(path, &[DBCol::DbVersion])?;
+        Self::read_version(&db)
+    }
+
+    /// Reads what the database records about itself.  Like
+    /// [`Self::get_version`], it opens only the columns it reads, which all
+    /// database versions have, so it works on the databases of newer neard
+    /// versions too.
+    pub fn get_metadata(path: &Path) -> Result<DbMetadata, DBError> {
+        let db = Self::open_columns_read_only(path, &[DBCol::DbVersion, DBCol::BlockMisc])?;
+        let version = Self::read_version(&db)?;
+        let neard_version = match db.get_cf(Self::cf(&db, DBCol::DbVersion), NEARD_VERSION_KEY)? {
+            Some(value) => Some(serde_json::from_slice(&value).map_err(|err| {
+                DBError::new(format!("Failed to parse the neard version {value:?}: {err}"))
+            })?),
+            None => None,
+        };
+        let flag = |key: &[u8]| -> Result<bool, DBError> {
+            match db.get_cf(Self::cf(&db, DBCol::BlockMisc), key)? {
+                Some(value) => bool::try_from_slice(&value).map_err(|err| {
+                    let key = String::from_utf8_lossy(key);
+                    DBError::new(format!("Failed to parse {key}: {err}"))
+                }),
+                None => Ok(false),
+            }
+        };
+        Ok(DbMetadata {
+            version,
+            neard_version,
+            is_archive: flag(IS_ARCHIVE_KEY)?,
+            is_clone: flag(IS_CLONE_KEY)?,
+        })
+    }
+
+    fn open_columns_read_only(path: &Path, cols: &[DBCol]) -> Result<DB, DBError> {
+        let options = Options::default();
+        Ok(DB::open_cf_for_read_only(&options, path, cols.iter().map(|col| col_name(*col)), false)?)
+    }
+
+    fn cf(db: &DB, col: DBCol) -> &ColumnFamily {
+        db.cf_handle(&col_name(col)).unwrap()
+    }
+
+    fn read_version(db: &DB) -> Result<DbVersion, DBError> {
+        let value = db.get_cf(Self::cf(db, DBCol::DbVersion), VERSION_KEY)?.ok_or_else(|| {
+            DBError::new(
+                "Failed to read database version; \
+                 it’s not a neard database or database is corrupted."
+                    .into(),
+            )
+        })?;
         serde_json::from_slice(&value).map_err(|_err| {
-            DBError(format!(
+            DBError::new(format!(
                 "Failed to parse database version: {value:?}; \
                  it’s not a neard database or database is corrupted."
             ))
@@ -650,6 +796,20 @@ impl RocksDB {
     pub fn flush(&self) -> Result<(), DBError> {
         self.db.flush().map_err(DBError::from)
     }
+
+    /// Compacts the whole column, so that the space of its deleted entries is
+    /// reclaimed.  Does nothing with `single_thread_rocksdb`, under which the
+    /// compaction would never finish.
+    pub fn compact_column(&self, col: DBCol) {
+        if cfg!(feature = "single_thread_rocksdb") {
+            return;
+        }
+        self.db.compact_range_cf(
+            unsafe { &*self.cfs[col as usize] },
+            Option::<&[u8]>::None,
+            Option::<&[u8]>::None,
+        );
+    }
 }
 
 fn available_space(path: &Path) -> io::Result<bytesize::ByteSize> {
@@ -720,6 +880,78 @@ impl TestDB {
     }
 }
 
+// Thresholds at which RocksDB slows down or stops the writes to a column.  The
+// options aren't set by `rocksdb_column_options` so they're the RocksDB
+// defaults, except for `max_write_buffer_number` which is set by
+// `optimize_level_style_compaction`.
+const LEVEL0_SLOWDOWN_WRITES_TRIGGER: u64 = 20;
+const LEVEL0_STOP_WRITES_TRIGGER: u64 = 36;
+const SOFT_PENDING_COMPACTION_BYTES_LIMIT: u64 = 64 * bytesize::GIB;
+const HARD_PENDING_COMPACTION_BYTES_LIMIT: u64 = 256 * bytesize::GIB;
+const MAX_WRITE_BUFFER_NUMBER: u64 = 6;
+
+/// Whether RocksDB slows down or stops the writes to a column until the
+/// backlog of flushes and compactions goes down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum WriteStall {
+    None,
+    Delayed,
+    Stopped,
+}
+
+/// Backlog of flushes and compactions of a column, read with the RocksDB
+/// property API.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ColumnProperties {
+    /// Bytes compaction has to rewrite to bring all levels below their target
+    /// size.
+    pub pending_compaction_bytes: u64,
+    pub num_files_at_level0: u64,
+    /// Full memtables waiting to be flushed.
+    pub num_immutable_mem_tables: u64,
+    pub mem_table_flush_pending: bool,
+    pub compaction_pending: bool,
+    /// Estimated bytes of the live data of the column.
+    pub live_data_size: u64,
+}
+
+impl ColumnProperties {
+    /// Write stall the backlog causes, with the conditions RocksDB uses.
+    pub fn write_stall(&self) -> WriteStall {
+        if self.num_immutable_mem_tables >= MAX_WRITE_BUFFER_NUMBER
+            || self.num_files_at_level0 >= LEVEL0_STOP_WRITES_TRIGGER
+            || self.pending_compaction_bytes >= HARD_PENDING_COMPACTION_BYTES_LIMIT
+        {
+            WriteStall::Stopped
+        } else if self.num_immutable_mem_tables >= MAX_WRITE_BUFFER_NUMBER - 1
+            || self.num_files_at_level0 >= LEVEL0_SLOWDOWN_WRITES_TRIGGER
+            || self.pending_compaction_bytes >= SOFT_PENDING_COMPACTION_BYTES_LIMIT
+        {
+            WriteStall::Delayed
+        } else {
+            WriteStall::None
+        }
+    }
+}
+
+impl RocksDB {
+    /// Reads the backlog of flushes and compactions of the column.
+    pub fn get_column_properties(&self, col: DBCol) -> Result<ColumnProperties, DBError> {
+        let property = |name: &str| -> Result<u64, DBError> {
+            Ok(self.property_int_value(col, name)?.unwrap_or(0))
+        };
+        Ok(ColumnProperties {
+            pending_compaction_bytes: property("rocksdb.estimate-pending-compaction-bytes")?,
+            num_files_at_level0: property("rocksdb.num-files-at-level0")?,
+            num_immutable_mem_tables: property("rocksdb.num-immutable-mem-table")?,
+            mem_table_flush_pending: property("rocksdb.mem-table-flush-pending")? != 0,
+            compaction_pending: property("rocksdb.compaction-pending")? != 0,
+            live_data_size: property("rocksdb.estimate-live-data-size")?,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum StatsValue {
     Count(i64),
@@ -765,19 +997,13 @@ fn parse_statistics(statistics: &str) -> Result<StoreStatistics, Box<dyn std::er
 #[cfg(test)]
 mod tests {
     use crate::db::StatsValue::{Count, Percentile, Sum};
-    use crate::db::{parse_statistics, rocksdb_read_options, DBError, Database, RocksDB};
+    use crate::db::{
+        parse_statistics, rocksdb_read_options, ColumnProperties, DBError, Database, RocksDB,
+        WriteStall,
+    };
     use crate::{create_store, DBCol, StoreConfig, StoreStatistics};
 
     impl RocksDB {
-        #[cfg(not(feature = "single_thread_rocksdb"))]
-        fn compact(&self, col: DBCol) {
-            self.db.compact_range_cf(
-                unsafe { &*self.cfs[col as usize] },
-                Option::<&[u8]>::None,
-                Option::<&[u8]>::None,
-            );
-        }
-
         fn get_no_empty_filtering(
             &self,
             col: DBCol,
@@ -790,6 +1016,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_column_properties() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_column_properties").tempdir().unwrap();
+        let store = create_store(tmp_dir.path());
+        let mut store_update = store.store_update();
+        store_update.set(DBCol::BlockMisc, &[1], &[1]);
+        store_update.commit().unwrap();
+        let properties =
+            store.get_rocksdb().unwrap().get_column_properties(DBCol::BlockMisc).unwrap();
+        assert_eq!(properties.write_stall(), WriteStall::None);
+
+        let backlog = |num_immutable_mem_tables, num_files_at_level0| ColumnProperties {
+            num_immutable_mem_tables,
+            num_files_at_level0,
+            ..Default::default()
+        };
+        assert_eq!(backlog(5, 0).write_stall(), WriteStall::Delayed);
+        assert_eq!(backlog(0, 36).write_stall(), WriteStall::Stopped);
+        assert_eq!(
+            ColumnProperties { pending_compaction_bytes: 64 * bytesize::GIB, ..Default::default() }
+                .write_stall(),
+            WriteStall::Delayed
+        );
+    }
+
     #[test]
     fn test_prewrite_check() {
         let tmp_dir = tempfile::Builder::new().prefix("_test_prewrite_check").tempdir().unwrap();
@@ -797,6 +1048,16 @@ mod tests {
         store.pre_write_check().unwrap()
     }
 
+    #[test]
+    fn test_is_locked() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_is_locked").tempdir().unwrap();
+        let _db = RocksDB::open(tmp_dir.path(), &StoreConfig::read_write()).unwrap();
+        let err = RocksDB::open(tmp_dir.path(), &StoreConfig::read_write()).err().unwrap();
+        assert!(err.is_locked(), "unexpected error: {}", err);
+        assert!(RocksDB::open(tmp_dir.path(), &StoreConfig::read_only()).is_ok());
+        assert!(!DBError::new("While lock file".to_string()).is_locked());
+    }
+
     #[test]
     fn test_clear_column() {
         let tmp_dir = tempfile::Builder::new().prefix("_test_clear_column").tempdir().unwrap();
@@ -818,6 +1079,40 @@ mod tests {
         assert_eq!(store.get(DBCol::State, &[1]).unwrap(), None);
     }
 
+    #[test]
+    fn test_get_metadata() {
+        use crate::migrations::{set_store_neard_version, set_store_version};
+        use near_primitives::version::Version;
+        use strum::IntoEnumIterator;
+
+        let tmp_dir = tempfile::Builder::new().prefix("_test_get_metadata").tempdir().unwrap();
+        {
+            let store = create_store(tmp_dir.path());
+            set_store_version(&store, 1000);
+            let version = Version { version: "9.9.9".to_string(), ..Version::default() };
+            set_store_neard_version(&store, &version).unwrap();
+            let mut store_update = store.store_update();
+            store_update.set_ser(DBCol::BlockMisc, crate::db::IS_ARCHIVE_KEY, &true).unwrap();
+            store_update.commit().unwrap();
+        }
+        // A newer version may drop columns, which keeps the database from
+        // being opened with this version's columns.
+        {
+            let names: Vec<_> = DBCol::iter().map(super::col_name).collect();
+            let mut db =
+                rocksdb::DB::open_cf(&rocksdb::Options::default(), tmp_dir.path(), &names).unwrap();
+            db.drop_cf(&super::col_name(DBCol::State)).unwrap();
+        }
+        assert!(RocksDB::open(tmp_dir.path(), &StoreConfig::read_only()).is_err());
+
+        assert_eq!(RocksDB::get_version(tmp_dir.path()).unwrap(), 1000);
+        let metadata = RocksDB::get_metadata(tmp_dir.path()).unwrap();
+        assert_eq!(metadata.version, 1000);
+        assert_eq!(metadata.neard_version.unwrap().version, "9.9.9");
+        assert!(metadata.is_archive);
+        assert!(!metadata.is_clone);
+    }
+
     #[test]
     fn rocksdb_merge_sanity() {
         let tmp_dir = tempfile::Builder::new().prefix("_test_snapshot_sanity").tempdir().unwrap();
@@ -863,8 +1158,8 @@ mod tests {
         #[cfg(not(feature = "single_thread_rocksdb"))]
         {
             // single_thread_rocksdb makes compact hang forever
-            rocksdb.compact(DBCol::State);
-            rocksdb.compact(DBCol::State);
+            rocksdb.compact_column(DBCol::State);
+            rocksdb.compact_column(DBCol::State);
 
             // After compaction the empty value disappears
             assert_eq!(rocksdb.get_no_empty_filtering(DBCol::State, &[1]).unwrap(), None);
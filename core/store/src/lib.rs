@@ -11,8 +11,9 @@ use lru::LruCache;
 
 pub use columns::DBCol;
 pub use db::{
-    CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY, HEADER_HEAD_KEY, HEAD_KEY,
-    LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, TAIL_KEY,
+    col_name, ColumnProperties, WriteStall, CHUNK_TAIL_KEY, FINAL_HEAD_KEY, FORK_TAIL_KEY,
+    HEADER_HEAD_KEY, HEAD_KEY, LARGEST_TARGET_HEIGHT_KEY, LATEST_KNOWN_KEY, STATE_CHANGES_TAIL_KEY,
+    TAIL_KEY,
 };
 use near_crypto::PublicKey;
 use near_o11y::log_assert;
@@ -29,11 +30,11 @@ use near_primitives::types::{AccountId, CompiledContractCache, StateRoot};
 pub use crate::db::refcount::decode_value_with_rc;
 use crate::db::refcount::encode_value_with_rc;
 use crate::db::{
-    DBOp, DBTransaction, Database, RocksDB, StoreStatistics, GENESIS_JSON_HASH_KEY,
+    DBError, DBOp, DBTransaction, Database, RocksDB, StoreStatistics, GENESIS_JSON_HASH_KEY,
     GENESIS_STATE_ROOTS_KEY,
 };
 pub use crate::trie::iterator::TrieIterator;
-pub use crate::trie::update::{TrieUpdate, TrieUpdateIterator, TrieUpdateValuePtr};
+pub use crate::trie::update::{RecordedReads, TrieUpdate, TrieUpdateIterator, TrieUpdateValuePtr};
 pub use crate::trie::{
     estimator, split_state, ApplyStatePartResult, KeyForStateChanges, PartialStorage, ShardTries,
     Trie, TrieCache, TrieCachingStorage, TrieChanges, TrieStorage, WrappedTrieChanges,
@@ -154,6 +155,104 @@ impl Store {
     pub fn get_store_statistics(&self) -> Option<StoreStatistics> {
         self.storage.get_store_statistics()
     }
+
+    /// Returns whether the underlying database rejects all writes.
+    pub fn is_read_only(&self) -> bool {
+        self.storage.is_read_only()
+    }
+}
+
+/// Read access to the database.
+///
+/// Implemented by both [`Store`] and [`ReadOnlyStore`] so that code which only
+/// reads, such as `ChainStore` or [`TrieCachingStorage`], can run over either.
+pub trait StoreRead {
+    fn get(&self, column: DBCol, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    fn iter<'a>(&'a self, column: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>;
+
+    fn iter_prefix<'a>(
+        &'a self,
+        column: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a>;
+
+    fn get_ser<T: BorshDeserialize>(&self, column: DBCol, key: &[u8]) -> io::Result<Option<T>> {
+        match self.get(column, key)? {
+            Some(bytes) => Ok(Some(T::try_from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn exists(&self, column: DBCol, key: &[u8]) -> io::Result<bool> {
+        self.get(column, key).map(|value| value.is_some())
+    }
+
+    fn iter_prefix_ser<'a, T: BorshDeserialize>(
+        &'a self,
+        column: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = io::Result<(Box<[u8]>, T)>> + 'a> {
+        Box::new(
+            self.iter_prefix(column, key_prefix)
+                .map(|(key, value)| Ok((key, T::try_from_slice(value.as_ref())?))),
+        )
+    }
+}
+
+impl StoreRead for Store {
+    fn get(&self, column: DBCol, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Store::get(self, column, key)
+    }
+
+    fn iter<'a>(&'a self, column: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        Store::iter(self, column)
+    }
+
+    fn iter_prefix<'a>(
+        &'a self,
+        column: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        Store::iter_prefix(self, column, key_prefix)
+    }
+}
+
+/// A store which can only be read from.
+///
+/// A [`Store`] opened with [`StoreConfig::read_only`] still hands out
+/// [`StoreUpdate`]s and fails only when one is committed.  This type has no way
+/// of obtaining a `StoreUpdate` at all so code which gets one can be sure it
+/// never modifies the database, which matters for tools running against a live
+/// node’s data directory.  It is read through [`StoreRead`].
+///
+/// It can only be created over a database opened in read-only mode, see
+/// [`create_read_only_store`].
+#[derive(Clone)]
+pub struct ReadOnlyStore(Store);
+
+impl ReadOnlyStore {
+    pub fn get_rocksdb(&self) -> Option<&RocksDB> {
+        self.0.get_rocksdb()
+    }
+}
+
+impl StoreRead for ReadOnlyStore {
+    fn get(&self, column: DBCol, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.0.get(column, key)
+    }
+
+    fn iter<'a>(&'a self, column: DBCol) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.0.iter(column)
+    }
+
+    fn iter_prefix<'a>(
+        &'a self,
+        column: DBCol,
+        key_prefix: &'a [u8],
+    ) -> Box<dyn Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'a> {
+        self.0.iter_prefix(column, key_prefix)
+    }
 }
 
 /// Keeps track of current changes to the database and can commit all of them to the database.
@@ -283,15 +382,98 @@ impl StoreUpdate {
             "Transaction overwrites itself: {:?}",
             self
         );
-        if let Some(tries) = self.tries {
-            // Note: avoid comparing wide pointers here to work-around
-            // https://github.com/rust-lang/rust/issues/69757
-            let addr = |arc| Arc::as_ptr(arc) as *const u8;
-            assert_eq!(addr(&tries.get_store().storage), addr(&self.storage),);
-            tries.update_cache(&self.transaction)?;
+        self.commit_in_batches(0)
+    }
+
+    /// Commits the update in several writes of at most `max_batch_bytes` of
+    /// keys and values each if it's larger, zero meaning a single write.
+    ///
+    /// Only the insertions of trie nodes, the increments of refcounts in
+    /// `DBCol::State`, are split off and written first.  Everything else,
+    /// including the decrements which can remove nodes and the updates of the
+    /// heads, is written last in a single atomic write.  The split isn't
+    /// replay-safe: a crash between the writes leaves trie nodes written for a
+    /// block whose heads aren't, the block is processed again after the
+    /// restart and increments the refcounts of its nodes a second time.
+    /// Garbage collection only decrements them once, so these nodes are never
+    /// removed by it and leak, though no node still in use is ever removed.
+    /// `neard view-state trie-gc --execute`, run once the state of the block
+    /// is garbage collected, removes them as unreachable.
+    pub fn commit_in_batches(self, max_batch_bytes: usize) -> io::Result<()> {
+        let batches = split_transaction(self.transaction, max_batch_bytes);
+        let num_batches = batches.len();
+        for (i, batch) in batches.into_iter().enumerate() {
+            if let Some(tries) = &self.tries {
+                // Note: avoid comparing wide pointers here to work-around
+                // https://github.com/rust-lang/rust/issues/69757
+                let addr = |arc| Arc::as_ptr(arc) as *const u8;
+                assert_eq!(addr(&tries.get_store().storage), addr(&self.storage),);
+                tries.update_cache(&batch)?;
+            }
+            let label = match (num_batches, i + 1 == num_batches) {
+                (1, _) => "whole",
+                (_, false) => "trie_insertions",
+                (_, true) => "final",
+            };
+            metrics::STORE_COMMIT_SIZE_BYTES
+                .with_label_values(&[label])
+                .observe(transaction_size(&batch) as f64);
+            let _timer = metrics::STORE_COMMIT_LATENCY.with_label_values(&[label]).start_timer();
+            self.storage.write(batch).map_err(io::Error::from)?;
+        }
+        Ok(())
+    }
+}
+
+fn op_size(op: &DBOp) -> usize {
+    match op {
+        DBOp::Set { key, value, .. }
+        | DBOp::Insert { key, value, .. }
+        | DBOp::UpdateRefcount { key, value, .. } => key.len() + value.len(),
+        DBOp::Delete { key, .. } => key.len(),
+        DBOp::DeleteAll { .. } => 0,
+    }
+}
+
+fn transaction_size(transaction: &DBTransaction) -> usize {
+    transaction.ops.iter().map(op_size).sum()
+}
+
+/// Splits a transaction larger than `max_batch_bytes` into the insertions of
+/// trie nodes, in transactions of at most `max_batch_bytes` unless a single one
+/// is larger, followed by a transaction with all the other operations.
+fn split_transaction(transaction: DBTransaction, max_batch_bytes: usize) -> Vec<DBTransaction> {
+    if max_batch_bytes == 0 || transaction_size(&transaction) <= max_batch_bytes {
+        return vec![transaction];
+    }
+    let mut batches = vec![];
+    let mut batch = DBTransaction { ops: vec![] };
+    let mut batch_bytes = 0;
+    let mut last = DBTransaction { ops: vec![] };
+    for op in transaction.ops {
+        let is_trie_insertion = match &op {
+            DBOp::UpdateRefcount { col, value, .. } => {
+                *col == DBCol::State && decode_value_with_rc(value).1 > 0
+            }
+            _ => false,
+        };
+        if !is_trie_insertion {
+            last.ops.push(op);
+            continue;
+        }
+        let size = op_size(&op);
+        if !batch.ops.is_empty() && batch_bytes + size > max_batch_bytes {
+            batches.push(std::mem::replace(&mut batch, DBTransaction { ops: vec![] }));
+            batch_bytes = 0;
         }
-        self.storage.write(self.transaction).map_err(io::Error::from)
+        batch_bytes += size;
+        batch.ops.push(op);
     }
+    if !batch.ops.is_empty() {
+        batches.push(batch);
+    }
+    batches.push(last);
+    batches
 }
 
 impl fmt::Debug for StoreUpdate {
@@ -313,7 +495,7 @@ impl fmt::Debug for StoreUpdate {
 }
 
 pub fn read_with_cache<'a, T: BorshDeserialize + 'a>(
-    storage: &Store,
+    storage: &impl StoreRead,
     col: DBCol,
     cache: &'a mut LruCache<Vec<u8>, T>,
     key: &[u8],
@@ -345,6 +527,13 @@ pub struct StoreConfig {
     #[serde(default = "default_enable_statistics_export")]
     pub enable_statistics_export: bool,
 
+    /// How often the backlog of flushes and compactions of every column is
+    /// read from the storage and exported as prometheus metrics, if
+    /// `enable_statistics_export` is set.
+    /// Default value: 10s.
+    #[serde(default = "default_column_properties_export_period")]
+    pub column_properties_export_period: std::time::Duration,
+
     /// Maximum number of store files being opened simultaneously.
     /// Default value: 512.
     /// The underlying storage can require simultaneously opening a large number of files.
@@ -375,6 +564,10 @@ fn default_enable_statistics_export() -> bool {
     true
 }
 
+fn default_column_properties_export_period() -> std::time::Duration {
+    std::time::Duration::from_secs(10)
+}
+
 fn default_max_open_files() -> u32 {
     StoreConfig::DEFAULT_MAX_OPEN_FILES
 }
@@ -416,6 +609,7 @@ impl StoreConfig {
             read_only: false,
             enable_statistics: false,
             enable_statistics_export: true,
+            column_properties_export_period: default_column_properties_export_period(),
             max_open_files: default_max_open_files(),
             col_state_cache_size: default_col_state_cache_size(),
             block_size: default_block_size(),
@@ -433,8 +627,29 @@ pub fn create_store(path: &Path) -> Store {
 }
 
 pub fn create_store_with_config(path: &Path, store_config: &StoreConfig) -> Store {
-    let db = RocksDB::open(path, &store_config).expect("Failed to open the database");
-    Store::new(Arc::new(db))
+    try_create_store_with_config(path, store_config).expect("Failed to open the database")
+}
+
+/// Like [`create_store_with_config`] but returns an error rather than panicking
+/// if the database can’t be opened, e.g. because another process holds its lock.
+pub fn try_create_store_with_config(
+    path: &Path,
+    store_config: &StoreConfig,
+) -> Result<Store, DBError> {
+    let db = RocksDB::open(path, &store_config)?;
+    Ok(Store::new(Arc::new(db)))
+}
+
+/// Opens the database in read-only mode regardless of `store_config.read_only`.
+///
+/// Read-only mode doesn’t require the `LOCK` file so this may be used while
+/// a node is running on the same database.
+pub fn create_read_only_store(
+    path: &Path,
+    store_config: &StoreConfig,
+) -> Result<ReadOnlyStore, DBError> {
+    let store_config = store_config.clone().with_read_only(true);
+    try_create_store_with_config(path, &store_config).map(ReadOnlyStore)
 }
 
 /// Reads an object from Trie.
@@ -646,6 +861,12 @@ pub struct StoreCompiledContractCache {
 /// we don't cache non-gas metered binaries, for example.
 impl CompiledContractCache for StoreCompiledContractCache {
     fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        if self.store.is_read_only() {
+            // The cache is only an optimisation.  Failing here would turn
+            // into a contract compilation error when running tools against
+            // a read-only database.
+            return Ok(());
+        }
         let mut store_update = self.store.store_update();
         store_update.set(DBCol::CachedContractCode, key, value);
         store_update.commit()
@@ -658,9 +879,110 @@ impl CompiledContractCache for StoreCompiledContractCache {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_no_cache_disabled() {
         #[cfg(feature = "no_cache")]
         panic!("no cache is enabled");
     }
+
+    #[test]
+    fn test_read_only_store() {
+        let tmp_dir = tempfile::Builder::new().prefix("_test_read_only_store").tempdir().unwrap();
+        {
+            let store = create_store(tmp_dir.path());
+            let mut store_update = store.store_update();
+            store_update.set(DBCol::BlockMisc, b"key", b"value");
+            store_update.commit().unwrap();
+        }
+
+        let store = create_read_only_store(tmp_dir.path(), &StoreConfig::read_write()).unwrap();
+        assert_eq!(store.get(DBCol::BlockMisc, b"key").unwrap(), Some(b"value".to_vec()));
+
+        // A `Store` over a database opened read-only fails on commit.
+        let store_config = StoreConfig::read_write().with_read_only(true);
+        let writable = try_create_store_with_config(tmp_dir.path(), &store_config).unwrap();
+        let mut store_update = writable.store_update();
+        store_update.set(DBCol::BlockMisc, b"key", b"other");
+        assert!(store_update.commit().is_err());
+        assert_eq!(store.get(DBCol::BlockMisc, b"key").unwrap(), Some(b"value".to_vec()));
+
+        let cache = StoreCompiledContractCache { store: writable };
+        cache.put(b"code", b"compiled").unwrap();
+        assert_eq!(cache.get(b"code").unwrap(), None);
+    }
+
+    /// Block processing commit: new trie nodes, a removed one and the head.
+    fn block_commit(store: &Store) -> StoreUpdate {
+        let mut store_update = store.store_update();
+        for i in 0..4 {
+            store_update.update_refcount(DBCol::State, &[i; 40], b"node", 1);
+        }
+        store_update.update_refcount(DBCol::State, &[9; 40], b"old", -1);
+        store_update.set(DBCol::BlockMisc, HEAD_KEY, b"head");
+        store_update
+    }
+
+    fn refcount(store: &Store, key: &[u8]) -> i64 {
+        store
+            .iter_raw_bytes(DBCol::State)
+            .find(|(k, _)| &**k == key)
+            .map_or(0, |(_, value)| decode_value_with_rc(&value).1)
+    }
+
+    #[test]
+    fn test_split_transaction() {
+        let store = crate::test_utils::create_test_store();
+        let transaction = block_commit(&store).transaction;
+        let insertion_size = 40 + b"node".len() + 8;
+        let batches = split_transaction(transaction, 2 * insertion_size);
+        let num_ops: Vec<usize> = batches.iter().map(|batch| batch.ops.len()).collect();
+        assert_eq!(num_ops, vec![2, 2, 2]);
+        assert!(batches[2].ops.iter().all(|op| match op {
+            DBOp::UpdateRefcount { value, .. } => decode_value_with_rc(value).1 < 0,
+            DBOp::Set { col, key, .. } => *col == DBCol::BlockMisc && key == HEAD_KEY,
+            _ => false,
+        }));
+
+        // Small enough or not split at all.
+        let transaction = block_commit(&store).transaction;
+        assert_eq!(split_transaction(transaction, 1 << 20).len(), 1);
+        let transaction = block_commit(&store).transaction;
+        assert_eq!(split_transaction(transaction, 0).len(), 1);
+    }
+
+    /// A crash between the writes of a split commit leaves the new trie nodes
+    /// without the head, and processing the block again once restarted leaves
+    /// them with a refcount one too high, which garbage collection never
+    /// brings to zero, rather than removing anything in use.
+    #[test]
+    fn test_commit_in_batches_crash() {
+        let store = crate::test_utils::create_test_store();
+        let mut store_update = store.store_update();
+        store_update.update_refcount(DBCol::State, &[9; 40], b"old", 1);
+        store_update.commit().unwrap();
+
+        let insertion_size = 40 + b"node".len() + 8;
+        let mut batches = split_transaction(block_commit(&store).transaction, insertion_size);
+        assert_eq!(batches.len(), 5);
+        batches.pop();
+        for batch in batches {
+            store.storage.write(batch).unwrap();
+        }
+        assert_eq!(store.get(DBCol::BlockMisc, HEAD_KEY).unwrap(), None);
+        assert_eq!(store.get(DBCol::State, &[0; 40]).unwrap(), Some(b"node".to_vec()));
+        assert_eq!(refcount(&store, &[9; 40]), 1);
+
+        block_commit(&store).commit_in_batches(insertion_size).unwrap();
+        assert_eq!(store.get(DBCol::BlockMisc, HEAD_KEY).unwrap(), Some(b"head".to_vec()));
+        assert_eq!(refcount(&store, &[0; 40]), 2);
+        assert_eq!(store.get(DBCol::State, &[9; 40]).unwrap(), None);
+
+        // Garbage collecting the block leaves the node behind.
+        let mut store_update = store.store_update();
+        store_update.update_refcount(DBCol::State, &[0; 40], b"node", -1);
+        store_update.commit().unwrap();
+        assert_eq!(refcount(&store, &[0; 40]), 1);
+    }
 }
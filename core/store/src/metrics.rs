@@ -10,3 +10,25 @@ pub(crate) static DATABASE_OP_LATENCY_HIST: Lazy<HistogramVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+pub(crate) static STORE_COMMIT_SIZE_BYTES: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_store_commit_size_bytes",
+        "Size of the keys and values of the writes committing store updates, by part of the \
+         update: the whole update, the insertions of trie nodes split off it or the final write",
+        &["batch"],
+        Some(vec![1e3, 1e4, 1e5, 1e6, 4e6, 1.6e7, 6.4e7, 2.56e8]),
+    )
+    .unwrap()
+});
+
+pub(crate) static STORE_COMMIT_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    try_create_histogram_vec(
+        "near_store_commit_latency_seconds",
+        "Latency of the writes committing store updates, by part of the update: the whole \
+         update, the insertions of trie nodes split off it or the final write",
+        &["batch"],
+        Some(vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0]),
+    )
+    .unwrap()
+});
@@ -6,7 +6,7 @@ use near_primitives::epoch_manager::epoch_info::{EpochInfo, EpochInfoV1};
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::AccountId;
-use near_primitives::version::DbVersion;
+use near_primitives::version::{DbVersion, Version};
 
 use crate::db::{DBError, RocksDB};
 use crate::{create_store, DBCol, Store, StoreUpdate};
@@ -30,6 +30,18 @@ pub fn set_store_version(store: &Store, db_version: u32) {
     store_update.commit().expect("Failed to write version to database");
 }
 
+/// Records the neard opening the database, which a neard too old to open the
+/// database after a migration reports.
+pub fn set_store_neard_version(store: &Store, version: &Version) -> std::io::Result<()> {
+    let mut store_update = store.store_update();
+    store_update.set(
+        DBCol::DbVersion,
+        crate::db::NEARD_VERSION_KEY,
+        &serde_json::to_vec(version).expect("Failed to serialize version"),
+    );
+    store_update.commit()
+}
+
 pub struct BatchedStoreUpdate<'a> {
     batch_size_limit: usize,
     batch_size: usize,
@@ -304,10 +304,13 @@ enum IterStep {
     Value(CryptoHash),
 }
 
-impl<'a> Iterator for TrieIterator<'a> {
-    type Item = Result<TrieItem, StorageError>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl<'a> TrieIterator<'a> {
+    /// Returns the next item with a key `include` accepts, without reading the
+    /// values of the items it skips.
+    pub fn next_filtered(
+        &mut self,
+        mut include: impl FnMut(&[u8]) -> bool,
+    ) -> Option<Result<TrieItem, StorageError>> {
         loop {
             let iter_step = self.iter_step()?;
             match iter_step {
@@ -320,18 +323,30 @@ impl<'a> Iterator for TrieIterator<'a> {
                 },
                 IterStep::Continue => {}
                 IterStep::Value(hash) => {
+                    let key = self.key();
+                    if !include(&key) {
+                        continue;
+                    }
                     return Some(
                         self.trie
                             .storage
                             .retrieve_raw_bytes(&hash)
-                            .map(|value| (self.key(), value.to_vec())),
-                    )
+                            .map(|value| (key, value.to_vec())),
+                    );
                 }
             }
         }
     }
 }
 
+impl<'a> Iterator for TrieIterator<'a> {
+    type Item = Result<TrieItem, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_filtered(|_| true)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::BTreeMap;
@@ -373,6 +388,19 @@ mod tests {
                 let result2: Vec<_> = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
                 assert_eq!(result1, result2);
             }
+            {
+                let mut iter = trie.iter(&state_root).unwrap();
+                let mut result1 = vec![];
+                while let Some(item) = iter.next_filtered(|key| key.len() % 2 == 0) {
+                    result1.push(item.unwrap());
+                }
+                let result2: Vec<_> = map
+                    .iter()
+                    .filter(|(k, _)| k.len() % 2 == 0)
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                assert_eq!(result1, result2);
+            }
             test_seek(&trie, &map, &state_root, &[]);
 
             let empty_vec = vec![];
@@ -1,6 +1,5 @@
-use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::io::{Cursor, Read, Write};
 
@@ -20,7 +19,7 @@ pub use crate::trie::shard_tries::{KeyForStateChanges, ShardTries, WrappedTrieCh
 pub use crate::trie::trie_storage::{TrieCache, TrieCachingStorage, TrieStorage};
 use crate::trie::trie_storage::{TrieMemoryPartialStorage, TrieRecordingStorage};
 use crate::StorageError;
-pub use near_primitives::types::TrieNodesCount;
+pub use near_primitives::types::{TrieNodesCount, TrieReadStats};
 
 mod insert_delete;
 pub mod iterator;
@@ -475,11 +474,7 @@ impl Trie {
     pub fn recording_reads(&self) -> Self {
         let storage =
             self.storage.as_caching_storage().expect("Storage should be TrieCachingStorage");
-        let storage = TrieRecordingStorage {
-            store: storage.store.clone(),
-            shard_uid: storage.shard_uid,
-            recorded: RefCell::new(Default::default()),
-        };
+        let storage = TrieRecordingStorage::new(storage.store.clone(), storage.shard_uid);
         Trie { storage: Box::new(storage) }
     }
 
@@ -487,8 +482,15 @@ impl Trie {
         StateRoot::default()
     }
 
+    /// Total size in bytes of the values recorded so far, if the trie records
+    /// reads.
+    pub fn recorded_storage_size(&self) -> Option<u64> {
+        Some(self.storage.as_recording_storage()?.recorded_bytes.get())
+    }
+
     pub fn recorded_storage(&self) -> Option<PartialStorage> {
         let storage = self.storage.as_recording_storage()?;
+        storage.recorded_bytes.set(0);
         let mut nodes: Vec<_> =
             storage.recorded.borrow_mut().drain().map(|(_key, value)| value).collect();
         nodes.sort();
@@ -755,6 +757,46 @@ impl Trie {
     pub fn get_trie_nodes_count(&self) -> TrieNodesCount {
         self.storage.get_trie_nodes_count()
     }
+
+    /// Where the reads of the trie were served from, `None` unless it reads
+    /// through the caches.
+    pub fn get_read_stats(&self) -> Option<TrieReadStats> {
+        Some(self.storage.as_caching_storage()?.read_stats.get())
+    }
+
+    /// Adds the hashes of all nodes and values reachable from the root to
+    /// `marked`.  Subtrees of nodes which are already marked aren't visited
+    /// again, so marking the roots of consecutive blocks costs roughly as much
+    /// as marking a single one plus the changes between them.
+    pub fn mark_reachable(
+        &self,
+        root: &StateRoot,
+        marked: &mut HashSet<CryptoHash>,
+    ) -> Result<(), StorageError> {
+        let mut stack = vec![*root];
+        while let Some(hash) = stack.pop() {
+            if hash == Trie::empty_root() || !marked.insert(hash) {
+                continue;
+            }
+            let bytes = self.storage.retrieve_raw_bytes(&hash)?;
+            let node = RawTrieNodeWithSize::decode(&bytes).map_err(|_| {
+                StorageError::StorageInconsistentState(format!("Failed to decode node {}", hash))
+            })?;
+            match node.node {
+                RawTrieNode::Leaf(_, _, value_hash) => {
+                    marked.insert(value_hash);
+                }
+                RawTrieNode::Branch(children, value) => {
+                    if let Some((_, value_hash)) = value {
+                        marked.insert(value_hash);
+                    }
+                    stack.extend(children.iter().flatten());
+                }
+                RawTrieNode::Extension(_, child) => stack.push(child),
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Methods used in the runtime-parameter-estimator for measuring trie internal
@@ -1132,7 +1174,11 @@ mod tests {
         let trie2 = tries.get_trie_for_shard(ShardUId::single_shard()).recording_reads();
         trie2.get(&root, b"dog").unwrap();
         trie2.get(&root, b"horse").unwrap();
+        let recorded_size = trie2.recorded_storage_size().unwrap();
         let partial_storage = trie2.recorded_storage();
+        let nodes = &partial_storage.as_ref().unwrap().nodes.0;
+        assert_eq!(recorded_size, nodes.iter().map(|node| node.len() as u64).sum::<u64>());
+        assert_eq!(trie2.recorded_storage_size(), Some(0));
 
         let trie3 = Trie::from_recorded_storage(partial_storage.unwrap());
 
@@ -1176,6 +1222,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_mark_reachable() {
+        let tries = create_tries();
+        let shard_uid = ShardUId::single_shard();
+        let changes = vec![
+            (b"doge".to_vec(), Some(b"coin".to_vec())),
+            (b"docu".to_vec(), Some(b"value".to_vec())),
+            (b"horse".to_vec(), Some(b"stallion".to_vec())),
+        ];
+        let root = test_populate_trie(&tries, &Trie::empty_root(), shard_uid, changes);
+        let new_root = test_populate_trie(
+            &tries,
+            &root,
+            shard_uid,
+            vec![(b"doge".to_vec(), Some(b"bone".to_vec()))],
+        );
+        let trie = tries.get_trie_for_shard(shard_uid);
+        let mut marked = HashSet::new();
+        trie.mark_reachable(&new_root, &mut marked).unwrap();
+        assert!(marked.contains(&new_root));
+        assert!(marked.contains(&hash(b"bone")));
+        assert!(marked.contains(&hash(b"stallion")));
+        assert!(!marked.contains(&root));
+        assert!(!marked.contains(&hash(b"coin")));
+        for hash in &marked {
+            assert!(trie.storage.retrieve_raw_bytes(hash).is_ok(), "{} not stored", hash);
+        }
+
+        // Marked subtrees are skipped.
+        let before = marked.len();
+        trie.mark_reachable(&new_root, &mut marked).unwrap();
+        assert_eq!(marked.len(), before);
+    }
+
     #[test]
     fn test_dump_load_trie() {
         let store = create_test_store();
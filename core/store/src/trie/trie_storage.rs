@@ -6,10 +6,10 @@ use near_primitives::hash::CryptoHash;
 
 use crate::db::refcount::decode_value_with_rc;
 use crate::trie::POISONED_LOCK_ERR;
-use crate::{DBCol, StorageError, Store};
+use crate::{DBCol, ReadOnlyStore, StorageError, Store};
 use lru::LruCache;
 use near_primitives::shard_layout::ShardUId;
-use near_primitives::types::{TrieCacheMode, TrieNodesCount};
+use near_primitives::types::{TrieCacheMode, TrieNodesCount, TrieReadStats};
 use std::cell::{Cell, RefCell};
 use std::io::ErrorKind;
 
@@ -64,6 +64,12 @@ pub trait TrieStorage {
     /// StorageError if the storage fails internally or the hash is not present.
     fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError>;
 
+    /// Get bytes of a serialized TrieNode without counting the read, caching
+    /// the node or recording it, so that the gas charged for touching trie
+    /// nodes is the same as if it wasn't read.  For the reads which aren't part
+    /// of the execution, e.g. the values recorded for replaying receipts.
+    fn retrieve_raw_bytes_uncounted(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError>;
+
     fn as_caching_storage(&self) -> Option<&TrieCachingStorage> {
         None
     }
@@ -80,30 +86,46 @@ pub trait TrieStorage {
 }
 
 /// Records every value read by retrieve_raw_bytes.
-/// Used for obtaining state parts (and challenges in the future).
-/// TODO (#6316): implement proper nodes counting logic as in TrieCachingStorage
+/// Used for obtaining state parts and storage proofs of applied chunks.
+///
+/// The reads go through a caching storage of their own, so that trie nodes are
+/// counted and chunk cache mode is handled the same way as without recording
+/// and the gas burnt doesn't change.  Its shard cache is empty so that
+/// recording doesn't evict the nodes cached for the shard.
 pub struct TrieRecordingStorage {
-    pub(crate) store: Store,
-    pub(crate) shard_uid: ShardUId,
+    pub(crate) storage: TrieCachingStorage,
     pub(crate) recorded: RefCell<HashMap<CryptoHash, Vec<u8>>>,
+    /// Total size of the recorded values.
+    pub(crate) recorded_bytes: Cell<u64>,
+}
+
+impl TrieRecordingStorage {
+    pub(crate) fn new(store: Store, shard_uid: ShardUId) -> Self {
+        Self {
+            storage: TrieCachingStorage::new(store, TrieCache::with_capacity(1), shard_uid),
+            recorded: RefCell::new(Default::default()),
+            recorded_bytes: Cell::new(0),
+        }
+    }
 }
 
 impl TrieStorage for TrieRecordingStorage {
     fn retrieve_raw_bytes(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
-        if let Some(val) = self.recorded.borrow().get(hash) {
-            return Ok(val.as_slice().into());
-        }
-        let key = TrieCachingStorage::get_key_from_shard_uid_and_hash(self.shard_uid, hash);
-        let val = self
-            .store
-            .get(DBCol::State, key.as_ref())
-            .map_err(|_| StorageError::StorageInternalError)?;
-        if let Some(val) = val {
-            self.recorded.borrow_mut().insert(*hash, val.clone());
-            Ok(val.into())
-        } else {
-            Err(StorageError::StorageInconsistentState("Trie node missing".to_string()))
+        let val = self.storage.retrieve_raw_bytes(hash)?;
+        let mut recorded = self.recorded.borrow_mut();
+        if !recorded.contains_key(hash) {
+            self.recorded_bytes.set(self.recorded_bytes.get() + val.len() as u64);
+            recorded.insert(*hash, val.to_vec());
         }
+        Ok(val)
+    }
+
+    fn retrieve_raw_bytes_uncounted(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        self.storage.retrieve_raw_bytes_uncounted(hash)
+    }
+
+    fn as_caching_storage(&self) -> Option<&TrieCachingStorage> {
+        Some(&self.storage)
     }
 
     fn as_recording_storage(&self) -> Option<&TrieRecordingStorage> {
@@ -111,7 +133,7 @@ impl TrieStorage for TrieRecordingStorage {
     }
 
     fn get_trie_nodes_count(&self) -> TrieNodesCount {
-        unimplemented!();
+        self.storage.get_trie_nodes_count()
     }
 }
 
@@ -134,6 +156,12 @@ impl TrieStorage for TrieMemoryPartialStorage {
         result
     }
 
+    fn retrieve_raw_bytes_uncounted(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        self.recorded_storage
+            .get(hash)
+            .map_or_else(|| Err(StorageError::TrieNodeMissing), |val| Ok(val.as_slice().into()))
+    }
+
     fn as_partial_storage(&self) -> Option<&TrieMemoryPartialStorage> {
         Some(self)
     }
@@ -180,6 +208,8 @@ pub struct TrieCachingStorage {
     pub(crate) db_read_nodes: Cell<u64>,
     /// Counts trie nodes retrieved from the chunk cache.
     pub(crate) mem_read_nodes: Cell<u64>,
+    /// Where all the reads were served from, for metrics only.
+    pub(crate) read_stats: Cell<TrieReadStats>,
 }
 
 impl TrieCachingStorage {
@@ -192,9 +222,20 @@ impl TrieCachingStorage {
             chunk_cache: RefCell::new(Default::default()),
             db_read_nodes: Cell::new(0),
             mem_read_nodes: Cell::new(0),
+            read_stats: Cell::new(TrieReadStats::default()),
         }
     }
 
+    /// Same as [`Self::new`] but over a store which can't be written to, the
+    /// storage only ever reads from the `State` column.
+    pub fn new_read_only(
+        store: &ReadOnlyStore,
+        shard_cache: TrieCache,
+        shard_uid: ShardUId,
+    ) -> TrieCachingStorage {
+        Self::new(store.0.clone(), shard_cache, shard_uid)
+    }
+
     pub(crate) fn get_shard_uid_and_hash_from_key(
         key: &[u8],
     ) -> Result<(ShardUId, CryptoHash), std::io::Error> {
@@ -224,6 +265,24 @@ impl TrieCachingStorage {
         self.mem_read_nodes.set(self.mem_read_nodes.get() + 1);
     }
 
+    fn update_read_stats(&self, f: impl FnOnce(&mut TrieReadStats)) {
+        let mut stats = self.read_stats.get();
+        f(&mut stats);
+        self.read_stats.set(stats);
+    }
+
+    fn read_from_db(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        let key = Self::get_key_from_shard_uid_and_hash(self.shard_uid, hash);
+        let val = self
+            .store
+            .get(DBCol::State, key.as_ref())
+            .map_err(|_| StorageError::StorageInternalError)?
+            .ok_or_else(|| {
+                StorageError::StorageInconsistentState("Trie node missing".to_string())
+            })?;
+        Ok(val.into())
+    }
+
     /// Set cache mode.
     pub fn set_mode(&self, state: TrieCacheMode) {
         self.cache_mode.set(state);
@@ -236,24 +295,31 @@ impl TrieStorage for TrieCachingStorage {
         // because we charge for reading nodes only when `CachingChunk` mode is enabled anyway.
         if let Some(val) = self.chunk_cache.borrow_mut().get(hash) {
             self.inc_mem_read_nodes();
+            self.update_read_stats(|stats| {
+                stats.chunk_cache_hits += 1;
+                stats.bytes += val.len() as u64;
+            });
             return Ok(val.clone());
         }
 
         // Try to get value from shard cache containing most recently touched nodes.
         let mut guard = self.shard_cache.0.lock().expect(POISONED_LOCK_ERR);
         let val = match guard.get(hash) {
-            Some(val) => val.clone(),
+            Some(val) => {
+                self.update_read_stats(|stats| {
+                    stats.shard_cache_hits += 1;
+                    stats.bytes += val.len() as u64;
+                });
+                val.clone()
+            }
             None => {
                 // If value is not present in cache, get it from the storage.
-                let key = Self::get_key_from_shard_uid_and_hash(self.shard_uid, hash);
-                let val = self
-                    .store
-                    .get(DBCol::State, key.as_ref())
-                    .map_err(|_| StorageError::StorageInternalError)?
-                    .ok_or_else(|| {
-                        StorageError::StorageInconsistentState("Trie node missing".to_string())
-                    })?;
-                let val: Arc<[u8]> = val.into();
+                let val = self.read_from_db(hash)?;
+                self.update_read_stats(|stats| {
+                    stats.db_reads += 1;
+                    stats.bytes += val.len() as u64;
+                    stats.db_bytes += val.len() as u64;
+                });
 
                 // Insert value to shard cache, if its size is small enough.
                 // It is fine to have a size limit for shard cache and **not** have a limit for chunk cache, because key
@@ -285,6 +351,13 @@ impl TrieStorage for TrieCachingStorage {
         Ok(val)
     }
 
+    fn retrieve_raw_bytes_uncounted(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        if let Some(val) = self.chunk_cache.borrow().get(hash) {
+            return Ok(val.clone());
+        }
+        self.read_from_db(hash)
+    }
+
     fn as_caching_storage(&self) -> Option<&TrieCachingStorage> {
         Some(self)
     }
@@ -50,6 +50,12 @@ impl TrieStorage for IncompletePartialStorage {
         }
     }
 
+    fn retrieve_raw_bytes_uncounted(&self, hash: &CryptoHash) -> Result<Arc<[u8]>, StorageError> {
+        self.recorded_storage
+            .get(hash)
+            .map_or_else(|| Err(StorageError::TrieNodeMissing), |val| Ok(val.as_slice().into()))
+    }
+
     fn as_partial_storage(&self) -> Option<&TrieMemoryPartialStorage> {
         // Make sure it's not called - it pretends to be PartialStorage but is not
         unimplemented!()
@@ -213,10 +219,12 @@ mod caching_storage_tests {
     use crate::test_utils::{create_test_store, create_tries};
     use crate::trie::trie_storage::{TrieCache, TrieCachingStorage, TRIE_LIMIT_CACHED_VALUE_SIZE};
     use crate::trie::TrieRefcountChange;
-    use crate::{Store, TrieChanges};
+    use crate::{
+        create_read_only_store, create_store, ShardTries, Store, StoreConfig, TrieChanges,
+    };
     use assert_matches::assert_matches;
     use near_primitives::hash::hash;
-    use near_primitives::types::TrieCacheMode;
+    use near_primitives::types::{TrieCacheMode, TrieReadStats};
 
     fn create_store_with_values(values: &[Vec<u8>], shard_uid: ShardUId) -> Store {
         let tries = create_tries();
@@ -257,6 +265,31 @@ mod caching_storage_tests {
         }
     }
 
+    /// Check that the storage reads the nodes of a database opened read-only.
+    #[test]
+    fn test_retrieve_read_only() {
+        let value = vec![1u8];
+        let shard_uid = ShardUId::single_shard();
+        let tmp_dir =
+            tempfile::Builder::new().prefix("_test_retrieve_read_only").tempdir().unwrap();
+        {
+            let tries = ShardTries::new(create_store(tmp_dir.path()), 0, 1);
+            let mut trie_changes = TrieChanges::empty(Trie::empty_root());
+            trie_changes.insertions = vec![TrieRefcountChange {
+                trie_node_or_value_hash: hash(&value),
+                trie_node_or_value: value.clone(),
+                rc: 1,
+            }];
+            let (store_update, _) = tries.apply_all(&trie_changes, shard_uid);
+            store_update.commit().unwrap();
+        }
+        let store = create_read_only_store(tmp_dir.path(), &StoreConfig::read_write()).unwrap();
+        let trie_caching_storage =
+            TrieCachingStorage::new_read_only(&store, TrieCache::new(), shard_uid);
+        let result = trie_caching_storage.retrieve_raw_bytes(&hash(&value));
+        assert_eq!(result.unwrap().as_ref(), value);
+    }
+
     /// Check that if item is not present in a store, retrieval returns an error.
     #[test]
     fn test_retrieve_error() {
@@ -338,6 +371,19 @@ mod caching_storage_tests {
         assert_eq!(result.unwrap().as_ref(), value);
         assert_eq!(count_delta.db_reads, 0);
         assert_eq!(count_delta.mem_reads, 1);
+
+        // The read stats tell apart the reads the counter doesn't.
+        assert_eq!(
+            trie_caching_storage.read_stats.get(),
+            TrieReadStats {
+                chunk_cache_hits: 2,
+                shard_cache_hits: 1,
+                db_reads: 1,
+                bytes: 4,
+                db_bytes: 1
+            }
+        );
+        assert_eq!(trie_caching_storage.read_stats.get().cache_hit_rate(), Some(0.75));
     }
 
     /// Check that if an item present in chunk cache gets evicted from the shard cache, it stays in the chunk cache.
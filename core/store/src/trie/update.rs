@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::iter::Peekable;
 
@@ -22,12 +23,18 @@ pub struct TrieKeyValueUpdate {
 /// key that was updated -> the update.
 pub type TrieUpdates = BTreeMap<Vec<u8>, TrieKeyValueUpdate>;
 
+/// key that was read -> the value it had, `None` if the key didn't exist.
+pub type RecordedReads = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
 /// Provides a way to access Storage and record changes with future commit.
 pub struct TrieUpdate {
     pub trie: Rc<Trie>,
     root: CryptoHash,
     committed: RawStateChanges,
     prospective: TrieUpdates,
+    /// Reads of values not written since the last commit, recorded while
+    /// [`Self::start_recording_reads`] is in effect.
+    recorded_reads: Option<RefCell<RecordedReads>>,
 }
 
 pub enum TrieUpdateValuePtr<'a> {
@@ -51,11 +58,28 @@ impl<'a> TrieUpdateValuePtr<'a> {
             }
         }
     }
+
+    /// Like [`Self::deref_value`], but the trie nodes read aren't counted,
+    /// see [`crate::TrieStorage::retrieve_raw_bytes_uncounted`].
+    fn deref_value_uncounted(&self) -> Result<Vec<u8>, StorageError> {
+        match self {
+            TrieUpdateValuePtr::MemoryRef(value) => Ok((*value).clone()),
+            TrieUpdateValuePtr::HashAndSize(trie, _, hash) => {
+                trie.storage.retrieve_raw_bytes_uncounted(hash).map(|bytes| bytes.to_vec())
+            }
+        }
+    }
 }
 
 impl TrieUpdate {
     pub fn new(trie: Rc<Trie>, root: CryptoHash) -> Self {
-        TrieUpdate { trie, root, committed: Default::default(), prospective: Default::default() }
+        TrieUpdate {
+            trie,
+            root,
+            committed: Default::default(),
+            prospective: Default::default(),
+            recorded_reads: None,
+        }
     }
 
     pub fn trie(&self) -> &Trie {
@@ -68,11 +92,14 @@ impl TrieUpdate {
             return Ok(key_value.value.as_ref().map(<Vec<u8>>::clone));
         } else if let Some(changes_with_trie_key) = self.committed.get(&key) {
             if let Some(RawStateChange { data, .. }) = changes_with_trie_key.changes.last() {
+                self.record_read(&key, data.as_deref());
                 return Ok(data.as_ref().map(<Vec<u8>>::clone));
             }
         }
 
-        self.trie.get(&self.root, &key)
+        let value = self.trie.get(&self.root, &key)?;
+        self.record_read(&key, value.as_deref());
+        Ok(value)
     }
 
     pub fn get_ref(&self, key: &TrieKey) -> Result<Option<TrieUpdateValuePtr<'_>>, StorageError> {
@@ -81,12 +108,47 @@ impl TrieUpdate {
             return Ok(key_value.value.as_ref().map(TrieUpdateValuePtr::MemoryRef));
         } else if let Some(changes_with_trie_key) = self.committed.get(&key) {
             if let Some(RawStateChange { data, .. }) = changes_with_trie_key.changes.last() {
+                self.record_read(&key, data.as_deref());
                 return Ok(data.as_ref().map(TrieUpdateValuePtr::MemoryRef));
             }
         }
-        self.trie.get_ref(&self.root, &key).map(|option| {
-            option.map(|(length, hash)| TrieUpdateValuePtr::HashAndSize(&self.trie, length, hash))
-        })
+        let value = self
+            .trie
+            .get_ref(&self.root, &key)?
+            .map(|(length, hash)| TrieUpdateValuePtr::HashAndSize(&self.trie, length, hash));
+        // Recording must not change the nodes counted for gas, nor fail the
+        // read, so the value is fetched uncounted and skipped if it can't be.
+        if self.recorded_reads.is_some() {
+            if let Ok(data) =
+                value.as_ref().map(TrieUpdateValuePtr::deref_value_uncounted).transpose()
+            {
+                self.record_read(&key, data.as_deref());
+            }
+        }
+        Ok(value)
+    }
+
+    /// Starts recording the values read with [`Self::get`] and
+    /// [`Self::get_ref`] which haven't been modified since the last commit.
+    ///
+    /// Together they make up the part of the state a piece of work, e.g. a
+    /// receipt, depends on.  Reads through iterators are not recorded.
+    pub fn start_recording_reads(&mut self) {
+        self.recorded_reads = Some(Default::default());
+    }
+
+    /// Stops recording reads and returns the values recorded so far.
+    pub fn take_recorded_reads(&mut self) -> RecordedReads {
+        self.recorded_reads.take().map(RefCell::into_inner).unwrap_or_default()
+    }
+
+    fn record_read(&self, key: &[u8], value: Option<&[u8]>) {
+        if let Some(recorded_reads) = &self.recorded_reads {
+            recorded_reads
+                .borrow_mut()
+                .entry(key.to_vec())
+                .or_insert_with(|| value.map(<[u8]>::to_vec));
+        }
     }
 
     pub fn set(&mut self, trie_key: TrieKey, value: Vec<u8>) {
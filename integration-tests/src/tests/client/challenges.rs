@@ -31,7 +31,7 @@ use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::{AccountId, EpochId, StateRoot};
 use near_primitives::utils::MaybeValidated;
 use near_primitives::validator_signer::InMemoryValidatorSigner;
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use near_store::test_utils::create_test_store;
 use nearcore::config::{GenesisExt, FISHERMEN_THRESHOLD};
 use nearcore::NightshadeRuntime;
@@ -100,6 +100,7 @@ fn test_verify_block_double_sign_challenge() {
         *b1.header().next_bp_hash(),
         block_merkle_tree.root(),
         None,
+        BuildId::default(),
     );
     let epoch_id = b1.header().epoch_id().clone();
     let valid_challenge = Challenge::produce(
@@ -401,6 +402,7 @@ fn test_verify_chunk_invalid_state_challenge() {
         *last_block.header().next_bp_hash(),
         block_merkle_tree.root(),
         None,
+        BuildId::default(),
     );
 
     let challenge_body = {
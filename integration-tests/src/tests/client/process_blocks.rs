@@ -37,7 +37,7 @@ use near_primitives::epoch_manager::RngSeed;
 use near_primitives::errors::TxExecutionError;
 use near_primitives::errors::{ActionErrorKind, InvalidTxError};
 use near_primitives::hash::{hash, CryptoHash};
-use near_primitives::merkle::verify_hash;
+use near_primitives::merkle::{verify_block_ancestry_proof, verify_hash};
 use near_primitives::receipt::DelayedReceiptIndices;
 use near_primitives::runtime::config::RuntimeConfig;
 use near_primitives::runtime::config_store::RuntimeConfigStore;
@@ -58,7 +58,7 @@ use near_primitives::types::{AccountId, BlockHeight, EpochId, NumBlocks, Protoco
 use near_primitives::utils::to_timestamp;
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
 use near_primitives::version::ProtocolFeature;
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use near_primitives::views::{
     BlockHeaderView, FinalExecutionStatus, QueryRequest, QueryResponseKind,
 };
@@ -379,6 +379,7 @@ fn receive_network_block() {
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
                 None,
+                BuildId::default(),
             );
             client.do_send(NetworkClientMessages::Block(block, PeerInfo::random().id, false));
             future::ready(())
@@ -456,6 +457,7 @@ fn produce_block_with_approvals() {
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
                 None,
+                BuildId::default(),
             );
             client.do_send(NetworkClientMessages::Block(
                 block.clone(),
@@ -646,6 +648,7 @@ fn invalid_blocks_common(is_requested: bool) {
                 last_block.header.next_bp_hash,
                 block_merkle_tree.root(),
                 None,
+                BuildId::default(),
             );
             // Send block with invalid chunk mask
             let mut block = valid_block.clone();
@@ -979,6 +982,12 @@ fn client_sync_headers() {
             received_bytes_per_sec: 0,
             known_producers: vec![],
             peer_counter: 0,
+            clock_skew_millis: None,
+            dns_seed_peers: Default::default(),
+            recent_handshake_refusals: Default::default(),
+            pinned_peers: Default::default(),
+            blacklist_audit: Default::default(),
+            discounted_shards: Default::default(),
         }));
         wait_or_panic(2000);
     });
@@ -1937,10 +1946,13 @@ fn test_incorrect_validator_key_produce_block() {
     assert!(matches!(res, Ok(None)));
 }
 
-fn test_block_merkle_proof_with_len(n: NumBlocks, rng: &mut StdRng) {
+/// Produces blocks up to height `n`, with a fork block next to about every other one.  Returns
+/// the blocks on the canonical chain and the fork blocks.
+fn produce_blocks_with_forks(n: NumBlocks, rng: &mut StdRng) -> (TestEnv, Vec<Block>, Vec<Block>) {
     let mut env = TestEnv::builder(ChainGenesis::test()).build();
     let genesis_block = env.clients[0].chain.get_block_by_height(0).unwrap().clone();
     let mut blocks = vec![genesis_block.clone()];
+    let mut fork_blocks = vec![];
     let mut cur_height = genesis_block.header().height() + 1;
     while cur_height < n {
         let should_fork = rng.gen_bool(0.5);
@@ -1952,14 +1964,15 @@ fn test_block_merkle_proof_with_len(n: NumBlocks, rng: &mut StdRng) {
             assert_eq!(next_block.header().prev_hash(), block.hash());
             // simulate blocks arriving in random order
             if rng.gen_bool(0.5) {
-                env.process_block(0, fork_block, Provenance::PRODUCED);
+                env.process_block(0, fork_block.clone(), Provenance::PRODUCED);
                 env.process_block(0, next_block.clone(), Provenance::PRODUCED);
             } else {
                 env.process_block(0, next_block.clone(), Provenance::PRODUCED);
-                env.process_block(0, fork_block, Provenance::PRODUCED);
+                env.process_block(0, fork_block.clone(), Provenance::PRODUCED);
             }
             blocks.push(block);
             blocks.push(next_block);
+            fork_blocks.push(fork_block);
             cur_height += 3;
         } else {
             let block = env.clients[0].produce_block(cur_height).unwrap().unwrap();
@@ -1968,7 +1981,11 @@ fn test_block_merkle_proof_with_len(n: NumBlocks, rng: &mut StdRng) {
             cur_height += 1;
         }
     }
+    (env, blocks, fork_blocks)
+}
 
+fn test_block_merkle_proof_with_len(n: NumBlocks, rng: &mut StdRng) {
+    let (mut env, mut blocks, _) = produce_blocks_with_forks(n, rng);
     let head = blocks.pop().unwrap();
     let root = head.header().block_merkle_root();
     // verify that the mapping from block ordinal to block hash is correct
@@ -1999,6 +2016,55 @@ fn test_block_merkle_proof() {
     }
 }
 
+fn test_block_ancestry_proof_with_len(n: NumBlocks, rng: &mut StdRng) {
+    let (mut env, blocks, fork_blocks) = produce_blocks_with_forks(n, rng);
+    let chain = &mut env.clients[0].chain;
+    let final_height = chain.final_head().unwrap().height;
+    let final_blocks: Vec<_> =
+        blocks.iter().filter(|block| block.header().height() <= final_height).collect();
+    let head = final_blocks.last().unwrap();
+    for _ in 0..20 {
+        let older = rng.gen_range(0, final_blocks.len());
+        let newer = rng.gen_range(0, final_blocks.len());
+        if older >= newer {
+            continue;
+        }
+        let (older, newer) = (final_blocks[older], final_blocks[newer]);
+        let root = newer.header().block_merkle_root();
+        let proof = chain.get_block_ancestry_proof(older.hash(), newer.hash()).unwrap();
+        assert!(verify_block_ancestry_proof(root, older.hash(), &proof));
+        assert!(!verify_block_ancestry_proof(root, newer.hash(), &proof));
+        // A final block isn't an ancestor of the blocks before it.
+        assert!(chain.get_block_ancestry_proof(newer.hash(), older.hash()).is_err());
+    }
+    for fork_block in fork_blocks {
+        let fork_hash = *fork_block.hash();
+        if fork_block.header().height() >= head.header().height() {
+            continue;
+        }
+        assert_eq!(
+            chain.get_block_ancestry_proof(&fork_hash, head.hash()).unwrap_err().kind(),
+            ErrorKind::NotOnCanonicalChain(fork_hash)
+        );
+        assert_eq!(
+            chain.get_block_ancestry_proof(blocks[0].hash(), &fork_hash).unwrap_err().kind(),
+            ErrorKind::NotOnCanonicalChain(fork_hash)
+        );
+    }
+    assert_matches!(
+        chain.get_block_ancestry_proof(&hash(b"unknown"), head.hash()).unwrap_err().kind(),
+        ErrorKind::DBNotFoundErr(_)
+    );
+}
+
+#[test]
+fn test_block_ancestry_proof() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for i in 0..50 {
+        test_block_ancestry_proof_with_len(i, &mut rng);
+    }
+}
+
 #[test]
 fn test_block_merkle_proof_same_hash() {
     let mut env = TestEnv::builder(ChainGenesis::test()).build();
@@ -3157,6 +3223,44 @@ fn test_header_version_downgrade() {
     assert!(!res.is_ok());
 }
 
+/// The header of the block at height 1 of a chain at the protocol version,
+/// converted to a header with or without the build id of the producer, is
+/// accepted only when the protocol version has it.
+#[cfg(feature = "protocol_feature_producer_build_id")]
+fn check_producer_build_id_header(
+    protocol_version: ProtocolVersion,
+    producer_build_id: Option<BuildId>,
+) -> Result<(), near_chain::Error> {
+    let mut genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+    genesis.config.protocol_version = protocol_version;
+    let chain_genesis = ChainGenesis::from(&genesis);
+    let mut env = TestEnv::builder(chain_genesis)
+        .runtime_adapters(create_nightshade_runtimes(&genesis, 1))
+        .build();
+    let mut block = env.clients[0].produce_block(1).unwrap().unwrap();
+    let mut header_view: BlockHeaderView = block.header().clone().into();
+    header_view.producer_build_id = producer_build_id;
+    *block.mut_header() = header_view.into();
+    let validator_signer =
+        InMemoryValidatorSigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+    block.mut_header().resign(&validator_signer);
+    env.clients[0].process_block(block.into(), Provenance::NONE).1.map(|_| ())
+}
+
+#[test]
+#[cfg(feature = "protocol_feature_producer_build_id")]
+fn test_producer_build_id_header_version() {
+    let version = ProtocolFeature::ProducerBuildId.protocol_version();
+    let build_id = Some(BuildId(*b"abcdefgh"));
+    check_producer_build_id_header(version - 1, None).unwrap();
+    check_producer_build_id_header(version, build_id).unwrap();
+
+    let err = check_producer_build_id_header(version - 1, build_id).unwrap_err();
+    assert_matches!(err.kind(), ErrorKind::Other(msg) if msg == "Unexpected producer build id");
+    let err = check_producer_build_id_header(version, None).unwrap_err();
+    assert_matches!(err.kind(), ErrorKind::Other(msg) if msg == "Missing producer build id");
+}
+
 #[test]
 #[should_panic(
     expected = "The client protocol version is older than the protocol version of the network"
@@ -7,7 +7,8 @@ use near_client::test_utils::TestEnv;
 use near_crypto::{InMemorySigner, KeyType};
 use near_primitives::account::Account;
 use near_primitives::serialize::{from_base64, to_base64};
-use near_primitives::state_record::StateRecord;
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::state_record::{StatePatchRecord, StateRecord, StateRecordTombstone};
 use near_primitives::transaction::{
     Action, DeployContractAction, FunctionCallAction, SignedTransaction,
 };
@@ -85,7 +86,8 @@ fn test_patch_state() {
         account_id: "test0".parse().unwrap(),
         data_key: from_base64(&state[0].key).unwrap(),
         value: b"world".to_vec(),
-    }]);
+    }
+    .into()]);
 
     do_blocks(&mut env, 9, 20);
     let state2 = env.query_state("test0".parse().unwrap());
@@ -102,8 +104,40 @@ fn test_patch_account() {
     env.clients[0].chain.patch_state(vec![StateRecord::Account {
         account_id: "test1".parse().unwrap(),
         account: test1,
-    }]);
+    }
+    .into()]);
     do_blocks(&mut env, 9, 20);
     let test1_after = env.query_account("test1".parse().unwrap());
     assert_eq!(test1_after.amount, 10);
 }
+
+#[test]
+fn test_patch_state_with_deletions() {
+    let (mut env, _signer) = test_setup();
+    let state = env.query_state("test0".parse().unwrap());
+    let mut test1: Account = env.query_account("test1".parse().unwrap()).into();
+    test1.set_amount(10);
+
+    env.clients[0].chain.patch_state(vec![
+        StatePatchRecord::Delete(StateRecordTombstone::DeleteData {
+            account_id: "test0".parse().unwrap(),
+            data_key: from_base64(&state[0].key).unwrap(),
+        }),
+        StateRecord::Account { account_id: "test1".parse().unwrap(), account: test1 }.into(),
+    ]);
+    assert!(env.clients[0].chain.patch_state_in_progress());
+    do_blocks(&mut env, 9, 10);
+    assert!(!env.clients[0].chain.patch_state_in_progress());
+
+    let block_hash = *env.clients[0].chain.get_block_by_height(9).unwrap().hash();
+    let state_root = *env.clients[0]
+        .chain
+        .get_chunk_extra(&block_hash, &ShardUId::single_shard())
+        .unwrap()
+        .state_root();
+    assert_eq!(env.clients[0].chain.last_patched_state_root(), Some(state_root));
+
+    do_blocks(&mut env, 10, 20);
+    assert!(env.query_state("test0".parse().unwrap()).is_empty());
+    assert_eq!(env.query_account("test1".parse().unwrap()).amount, 10);
+}
@@ -1,12 +1,18 @@
 use actix::{Actor, System};
 use futures::{future, FutureExt};
 
-use near_actix_test_utils::spawn_interruptible;
-use near_client::GetBlock;
-use near_network::test_utils::WaitOrTimeoutActor;
+use near_actix_test_utils::{run_actix, spawn_interruptible};
+use near_chain_configs::Genesis;
+use near_client::{GetBlock, ReloadValidatorKey, Status};
+use near_crypto::{InMemorySigner, KeyType, Signer};
+use near_logger_utils::init_integration_logger;
+use near_network::test_utils::{open_port, WaitOrTimeoutActor};
 use near_primitives::types::{BlockHeightDelta, NumSeats, NumShards};
+use nearcore::config::GenesisExt;
+use nearcore::{load_test_config, start_with_config};
 use rand::{thread_rng, Rng};
 
+use crate::test_helpers::heavy_test;
 use crate::tests::nearcore::node_cluster::NodeCluster;
 
 fn run_heavy_nodes(
@@ -86,3 +92,50 @@ fn run_nodes_1_4_4() {
 fn run_nodes_4_4_2() {
     run_heavy_nodes(4, 4, 2, 8, 32);
 }
+
+/// Replaces the validator key file of a running node, reloads it and checks
+/// that the node switches to the new key.
+#[test]
+#[cfg_attr(not(feature = "expensive_tests"), ignore)]
+fn reload_validator_key() {
+    heavy_test(|| {
+        init_integration_logger();
+
+        let genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+        let mut near_config = load_test_config("test0", open_port(), genesis);
+        near_config.client_config.min_num_peers = 0;
+        near_config.client_config.epoch_sync_enabled = false;
+
+        run_actix(async move {
+            let dir = tempfile::Builder::new().prefix("reload_validator_key").tempdir().unwrap();
+            let key_file = dir.path().join("validator_key.json");
+            let new_signer =
+                InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "new key");
+            new_signer.write_to_file(&key_file).unwrap();
+            near_config.client_config.validator_key_file = Some(key_file);
+
+            let nearcore::NearNode { client, .. } =
+                start_with_config(dir.path(), near_config).expect("start_with_config");
+            let response = client.send(ReloadValidatorKey).await.unwrap().unwrap();
+            assert_eq!(response.public_key, new_signer.public_key());
+
+            WaitOrTimeoutActor::new(
+                Box::new(move |_ctx| {
+                    let public_key = new_signer.public_key();
+                    let status = client.send(Status { is_health_check: false, detailed: false });
+                    spawn_interruptible(status.then(move |res| {
+                        if let Ok(Ok(status)) = res {
+                            if status.validator_public_key == Some(public_key) {
+                                System::current().stop();
+                            }
+                        }
+                        future::ready(())
+                    }));
+                }),
+                100,
+                40000,
+            )
+            .start();
+        });
+    });
+}
@@ -23,7 +23,7 @@ use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::ValidatorStake;
 use near_primitives::types::{BlockHeightDelta, EpochId};
 use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
-use near_primitives::version::PROTOCOL_VERSION;
+use near_primitives::version::{BuildId, PROTOCOL_VERSION};
 use nearcore::config::{GenesisExt, TESTING_INIT_STAKE};
 use nearcore::{load_test_config, start_with_config, NearConfig};
 
@@ -94,6 +94,7 @@ fn add_blocks(
             next_bp_hash,
             block_merkle_tree.root(),
             None,
+            BuildId::default(),
         );
         block_merkle_tree.insert(*block.hash());
         let _ = client.do_send(NetworkClientMessages::Block(
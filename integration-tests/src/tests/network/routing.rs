@@ -97,6 +97,30 @@ fn ping_simple() -> anyhow::Result<()> {
     start_test(runner)
 }
 
+/// Test that pings dropped by the injected faults never arrive.
+#[cfg(feature = "test_features")]
+#[test]
+fn ping_dropped_by_fault() -> anyhow::Result<()> {
+    let mut runner = Runner::new(2, 2);
+
+    runner.push(Action::AddEdge { from: 0, to: 1, force: true });
+    runner.push(Action::CheckRoutingTable(0, vec![(1, vec![1])]));
+    let drop_pings = near_network_primitives::types::NetworkFault {
+        message_type: Some("Ping".to_string()),
+        drop_probability: 1.0,
+        ..Default::default()
+    };
+    runner.push_action(set_network_faults(1, vec![drop_pings]));
+    runner.push(Action::PingTo(0, 0, 1));
+    runner.push(Action::Wait(Duration::from_millis(500)));
+    runner.push_action(set_network_faults(1, vec![]));
+    runner.push(Action::PingTo(0, 1, 1));
+    // Only the second ping arrives.
+    runner.push(Action::CheckPingPong(1, vec![(1, 0, None)], vec![]));
+
+    start_test(runner)
+}
+
 #[test]
 /// Crate 3 nodes connected in a line and try to use Ping.
 fn ping_jump() -> anyhow::Result<()> {
@@ -22,12 +22,12 @@ use near_network::test_utils::{
 
 use near_network::routing::start_routing_table_actor;
 #[cfg(feature = "test_features")]
-use near_network::test_utils::SetAdvOptions;
+use near_network::test_utils::{SetAdvOptions, SetNetworkFaults};
 use near_network::types::PeerManagerMessageRequest;
 use near_network::types::{NetworkRequests, NetworkResponses};
 use near_network::PeerManagerActor;
 use near_network_primitives::types::{
-    NetworkConfig, OutboundTcpConnect, PeerInfo, ROUTED_MESSAGE_TTL,
+    NamedBlacklist, NetworkConfig, OutboundTcpConnect, PeerInfo, ROUTED_MESSAGE_TTL,
 };
 use near_primitives::network::PeerId;
 use near_primitives::types::{AccountId, ValidatorId};
@@ -567,7 +567,7 @@ impl Runner {
         network_config.max_num_peers = config.max_num_peers;
         network_config.ttl_account_id_router = Duration::from_secs(5);
         network_config.routed_message_ttl = config.routed_message_ttl;
-        network_config.blacklist = blacklist;
+        network_config.blacklist = vec![NamedBlacklist::default_list(blacklist)];
         network_config.whitelist_nodes = whitelist;
         network_config.outbound_disabled = config.outbound_disabled;
         network_config.boot_nodes = boot_nodes;
@@ -799,6 +799,23 @@ pub fn change_account_id(node_id: usize, account_id: AccountId) -> ActionFn {
     })
 }
 
+/// Replace the faults injected into the messages received by the node.
+#[cfg(feature = "test_features")]
+pub fn set_network_faults(
+    node_id: usize,
+    faults: Vec<near_network_primitives::types::NetworkFault>,
+) -> ActionFn {
+    Box::new(move |info: &mut RunningInfo| {
+        let faults = faults.clone();
+        Box::pin(async move {
+            let pm = info.get_node(node_id)?.addr.clone();
+            pm.send(PeerManagerMessageRequest::SetNetworkFaults(SetNetworkFaults { faults }))
+                .await?;
+            Ok(ControlFlow::Break(()))
+        })
+    })
+}
+
 /// Wait for predicate to return True.
 #[allow(dead_code)]
 pub fn wait_for<T>(predicate: T) -> ActionFn
@@ -3,7 +3,7 @@ use near_primitives::{
     account::Account,
     hash::hash as sha256,
     hash::CryptoHash,
-    views::{StateItem, ViewApplyState},
+    views::{CallFunctionOverrides, StateItem, StorageOverride, ViewApplyState},
 };
 use near_primitives::{
     test_utils::MockEpochInfoProvider,
@@ -103,6 +103,75 @@ fn test_view_call_with_args() {
     assert_eq!(view_call_result.unwrap(), 3u64.to_le_bytes().to_vec());
 }
 
+#[test]
+fn test_view_call_with_overrides() {
+    let view_state = || ViewApplyState {
+        block_height: 1,
+        prev_block_hash: CryptoHash::default(),
+        block_hash: CryptoHash::default(),
+        epoch_id: EpochId::default(),
+        epoch_height: 0,
+        block_timestamp: 1,
+        current_protocol_version: PROTOCOL_VERSION,
+        cache: None,
+    };
+    let call = |method_name: &str, args: &[u8], overrides: &CallFunctionOverrides| {
+        let (viewer, root) = get_test_trie_viewer();
+        viewer.call_function_with_overrides(
+            root,
+            view_state(),
+            &"test.contract".parse().unwrap(),
+            method_name,
+            args,
+            overrides,
+            &mut vec![],
+            &MockEpochInfoProvider::default(),
+        )
+    };
+
+    let key = 42u64.to_le_bytes().to_vec();
+    let overrides = CallFunctionOverrides {
+        storage: vec![StorageOverride {
+            key: key.clone().into(),
+            value: 7u64.to_le_bytes().to_vec().into(),
+        }],
+        ..Default::default()
+    };
+    let (result, gas_burnt) = call("read_value", &key, &overrides).unwrap();
+    assert_eq!(result, 7u64.to_le_bytes().to_vec());
+    assert!(gas_burnt > 0);
+    // The override is not visible to later calls.
+    let (result, _) = call("read_value", &key, &Default::default()).unwrap();
+    assert!(result.is_empty());
+
+    let overrides = CallFunctionOverrides { balance: Some(1234), ..Default::default() };
+    let (result, _) = call("ext_account_balance", &[], &overrides).unwrap();
+    assert_eq!(result, 1234u128.to_le_bytes().to_vec());
+
+    let (_, gas_burnt) = call("run_test", &[], &Default::default()).unwrap();
+    let overrides = CallFunctionOverrides { gas: Some(gas_burnt - 1), ..Default::default() };
+    let err = call("run_test", &[], &overrides).unwrap_err();
+    assert!(err.to_string().contains("Exceeded"), "unexpected error: {}", err);
+
+    // The gas budget can't be raised above `max_gas_burnt_view`.
+    let (_, root) = get_test_trie_viewer();
+    let viewer = TrieViewer::new(None, Some(gas_burnt - 1));
+    let overrides = CallFunctionOverrides { gas: Some(u64::MAX), ..Default::default() };
+    let err = viewer
+        .call_function_with_overrides(
+            root,
+            view_state(),
+            &"test.contract".parse().unwrap(),
+            "run_test",
+            &[],
+            &overrides,
+            &mut vec![],
+            &MockEpochInfoProvider::default(),
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("Exceeded"), "unexpected error: {}", err);
+}
+
 #[test]
 fn test_view_state() {
     let (_, tries, root) = get_runtime_and_trie();
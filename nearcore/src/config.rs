@@ -1,6 +1,8 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -15,15 +17,20 @@ use tempfile::tempdir;
 use tracing::{info, warn};
 
 use near_chain_configs::{
-    get_initial_supply, ClientConfig, GCConfig, Genesis, GenesisConfig, GenesisValidationMode,
-    LogSummaryStyle,
+    get_initial_supply, ChunkProducerConfig, ClientConfig, DiskSpaceMonitorConfig, GCConfig,
+    Genesis, GenesisConfig, GenesisValidationMode, LogSummaryStyle, StateSyncSource,
+    TransactionPoolConfig, TxRebroadcastConfig, ValidatorMetricsConfig,
 };
 use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, Signer};
 #[cfg(feature = "json_rpc")]
 use near_jsonrpc::RpcConfig;
 use near_network::test_utils::open_port;
-use near_network_primitives::types::{NetworkConfig, PeerInfo, ROUTED_MESSAGE_TTL};
+use near_network_primitives::types::{
+    CompressionConfig, MessageRecorderConfig, NamedBlacklist, NetworkConfig, PeerInfo,
+    ROUTED_MESSAGE_TTL,
+};
 use near_primitives::account::{AccessKey, Account};
+use near_primitives::account_pattern::AccountPattern;
 use near_primitives::hash::CryptoHash;
 #[cfg(test)]
 use near_primitives::shard_layout::account_id_to_shard_id;
@@ -40,7 +47,7 @@ use near_primitives::version::PROTOCOL_VERSION;
 use near_rosetta_rpc::RosettaRpcConfig;
 use near_telemetry::TelemetryConfig;
 
-use crate::download_file::{run_download_file, FileDownloadError};
+use crate::download_file::{run_download_checksum, run_download_file, FileDownloadError};
 
 /// Initial balance used in tests.
 pub const TESTING_INIT_BALANCE: Balance = 1_000_000_000 * NEAR_BASE;
@@ -137,6 +144,8 @@ pub const PROTOCOL_UPGRADE_NUM_EPOCHS: EpochHeight = 2;
 
 pub const CONFIG_FILENAME: &str = "config.json";
 pub const GENESIS_CONFIG_FILENAME: &str = "genesis.json";
+/// Where `neard init` records the files it downloaded, see [`Provenance`].
+pub const PROVENANCE_FILENAME: &str = "provenance.json";
 pub const NODE_KEY_FILE: &str = "node_key.json";
 pub const VALIDATOR_KEY_FILE: &str = "validator_key.json";
 
@@ -195,6 +204,28 @@ fn default_ttl_account_id_router() -> Duration {
 fn default_peer_stats_period() -> Duration {
     Duration::from_secs(5)
 }
+/// Clock skew against the peers above which a warning is logged.
+fn default_clock_skew_warn_threshold() -> Duration {
+    Duration::from_secs(1)
+}
+fn default_tcp_keepalive_time() -> Option<Duration> {
+    Some(Duration::from_secs(60))
+}
+fn default_tcp_keepalive_interval() -> Duration {
+    Duration::from_secs(10)
+}
+fn default_tcp_keepalive_retries() -> u32 {
+    3
+}
+fn default_liveness_ping_after() -> Duration {
+    Duration::from_secs(30)
+}
+fn default_liveness_timeout() -> Duration {
+    Duration::from_secs(15)
+}
+fn default_liveness_max_missed() -> u32 {
+    3
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Network {
@@ -205,6 +236,11 @@ pub struct Network {
     pub external_address: String,
     /// Comma separated list of nodes to connect to.
     pub boot_nodes: String,
+    /// Domains whose TXT records list peers to connect to, as
+    /// `PeerId@host:port` entries.  They're resolved at startup and then
+    /// about every 30 minutes.
+    #[serde(default)]
+    pub dns_seeds: Vec<String>,
     /// Comma separated list of whitelisted nodes. Inbound connections from the nodes on
     /// the whitelist are accepted even if the limit of the inbound connection has been reached.
     /// For each whitelisted node specifying both PeerId and IP:port is required:
@@ -212,6 +248,13 @@ pub struct Network {
     ///   ed25519:86EtEy7epneKyrcJwSWP7zsisTkfDRH5CFVszt4qiQYw@31.192.22.209:24567
     #[serde(default)]
     pub whitelist_nodes: String,
+    /// Peers this node stays connected to regardless of the peer limits, as
+    /// `PeerId@IP:port` entries.  The connections with them aren't counted
+    /// against `max_num_peers`, aren't stopped to make room for other peers
+    /// and are reestablished within seconds when lost.  A pinned peer can't
+    /// be on the `blacklist`.
+    #[serde(default)]
+    pub pinned_peers: Vec<String>,
     /// Maximum number of active peers. Hard limit.
     #[serde(default = "default_max_num_peers")]
     pub max_num_peers: u32,
@@ -243,16 +286,221 @@ pub struct Network {
     pub skip_sync_wait: bool,
     /// Ban window for peers who misbehave.
     pub ban_window: Duration,
+    /// Maximum number of peers kept in the peer store.  Beyond it the peers
+    /// seen the longest ago are evicted, until a tenth of it is free, except
+    /// the connected, whitelisted and pinned ones and those banned within
+    /// `ban_window`.  `null`, the default, keeps all of them until they
+    /// expire after a week unseen.  See also `neard network purge-peer-store`.
+    #[serde(default)]
+    pub max_peer_store_entries: Option<usize>,
     /// List of addresses that will not be accepted as valid neighbors.
     /// It can be IP:Port or IP (to blacklist all connections coming from this address).
+    /// Either a list of such entries or lists of entries by name, see
+    /// [`BlacklistConfig`].
     #[serde(default)]
-    pub blacklist: Vec<String>,
+    pub blacklist: BlacklistConfig,
+    /// Entries with the syntax of the `blacklist` ones whose peers are only
+    /// counted and listed on the debug page, not refused, to tell which peers
+    /// an entry would block before it's moved to the `blacklist`.  See also
+    /// `neard network audit-blacklist`.
+    #[serde(default)]
+    pub blacklist_audit: Vec<String>,
     /// Time to persist Accounts Id in the router without removing them in seconds.
     #[serde(default = "default_ttl_account_id_router")]
     pub ttl_account_id_router: Duration,
     /// Period to check on peer status
     #[serde(default = "default_peer_stats_period")]
     pub peer_stats_period: Duration,
+    /// Warn if the clock of this node differs from the median clock of the
+    /// peers by more than this.
+    #[serde(default = "default_clock_skew_warn_threshold")]
+    pub clock_skew_warn_threshold: Duration,
+    /// Limits of the encoded size of the messages received from peers, in
+    /// bytes, keyed by the kind of message, e.g. `{"Block": 67108864}`.  The
+    /// kinds not listed keep their defaults.
+    #[serde(default)]
+    pub max_message_sizes: HashMap<String, usize>,
+    /// Idle time of a peer connection after which the OS starts sending TCP
+    /// keepalive probes.  `null` keeps the OS settings.
+    #[serde(default = "default_tcp_keepalive_time")]
+    pub tcp_keepalive_time: Option<Duration>,
+    /// Time between the TCP keepalive probes, not settable on every OS.
+    #[serde(default = "default_tcp_keepalive_interval")]
+    pub tcp_keepalive_interval: Duration,
+    /// Number of unanswered TCP keepalive probes after which the OS drops the
+    /// connection, not settable on every OS.
+    #[serde(default = "default_tcp_keepalive_retries")]
+    pub tcp_keepalive_retries: u32,
+    /// Peers which haven't sent anything for this long are pinged.
+    #[serde(default = "default_liveness_ping_after")]
+    pub liveness_ping_after: Duration,
+    /// Time a pinged peer has to send something, the pong or any other
+    /// message.
+    #[serde(default = "default_liveness_timeout")]
+    pub liveness_timeout: Duration,
+    /// Peers which leave this many pings in a row unanswered are
+    /// disconnected.  0 turns the liveness checks off.
+    #[serde(default = "default_liveness_max_missed")]
+    pub liveness_max_missed: u32,
+    /// Records the messages received from peers to a log which can be
+    /// replayed against a fresh peer manager when debugging it.  Not allowed
+    /// on mainnet unless the binary is a debug build.
+    #[serde(default)]
+    pub message_recorder: Option<MessageRecorder>,
+    /// Set on a private network, whose nodes are at private addresses such as
+    /// `10.0.0.0/8`.  Otherwise the peers advertising private or link-local
+    /// addresses are dialed after the other ones.
+    #[serde(default)]
+    pub private_network: bool,
+    /// Compresses the messages sent to the peers which set it as well, with
+    /// zstd.  `null`, the default, neither compresses nor accepts compressed
+    /// messages.  Only used with the nightly
+    /// `protocol_feature_peer_message_compression` protocol feature.
+    #[serde(default)]
+    pub message_compression: Option<MessageCompression>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MessageCompression {
+    /// Messages of this size or above are compressed, in bytes.
+    #[serde(default = "default_message_compression_threshold")]
+    pub threshold: usize,
+    /// Types of the messages never compressed, e.g. whose payload is
+    /// compressed already, by the `type` label of the metrics, e.g.
+    /// `["VersionedStateResponse"]`.
+    #[serde(default)]
+    pub skip_message_types: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MessageRecorder {
+    /// File the messages are appended to, relative to the home directory.
+    pub path: PathBuf,
+    /// Size after which the file is rotated to `<path>.1`, `<path>.1` to
+    /// `<path>.2` and so on.
+    #[serde(default = "default_message_recorder_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Number of files kept, the one written to included.
+    #[serde(default = "default_message_recorder_max_files")]
+    pub max_files: usize,
+}
+
+/// The `blacklist` of the network config: a list of entries, or lists of
+/// entries by name, e.g. `{"abuse": {"entries": ["192.0.2.4"]}}`, which
+/// rejections are labeled with.  A list with `"enabled": false` keeps its
+/// entries but doesn't block anything.  The `enabled` flags are read again on
+/// SIGHUP, the entries only at startup.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum BlacklistConfig {
+    List(Vec<String>),
+    Named(BTreeMap<String, NamedBlacklistConfig>),
+}
+
+impl Default for BlacklistConfig {
+    fn default() -> Self {
+        BlacklistConfig::List(vec![])
+    }
+}
+
+impl BlacklistConfig {
+    /// The lists, the entries of a plain list on the default list.
+    pub fn lists(&self) -> Vec<NamedBlacklist> {
+        match self {
+            BlacklistConfig::List(entries) => vec![NamedBlacklist::default_list(entries.clone())],
+            BlacklistConfig::Named(lists) => lists
+                .iter()
+                .map(|(name, list)| NamedBlacklist {
+                    name: name.clone(),
+                    enabled: list.enabled,
+                    entries: list.entries.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NamedBlacklistConfig {
+    #[serde(default = "default_blacklist_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub entries: Vec<String>,
+}
+
+fn default_blacklist_enabled() -> bool {
+    true
+}
+
+fn default_message_recorder_max_file_bytes() -> u64 {
+    1 << 30
+}
+
+fn default_message_recorder_max_files() -> usize {
+    4
+}
+
+fn default_message_compression_threshold() -> usize {
+    16 * 1024
+}
+
+/// Whether the messages may be recorded on the chain: anywhere but on
+/// mainnet, where only debug builds may.
+pub(crate) fn message_recorder_allowed(chain_id: &str) -> bool {
+    chain_id != "mainnet" || cfg!(debug_assertions)
+}
+
+impl Network {
+    /// Parses `addr`, `None` if it's empty.
+    pub(crate) fn parse_addr(&self) -> anyhow::Result<Option<SocketAddr>> {
+        if self.addr.is_empty() {
+            return Ok(None);
+        }
+        let addr = self.addr.parse().with_context(|| format!("Invalid address {}", self.addr))?;
+        Ok(Some(addr))
+    }
+
+    pub(crate) fn parse_boot_nodes(&self) -> anyhow::Result<Vec<PeerInfo>> {
+        parse_peers(&self.boot_nodes)
+    }
+
+    pub(crate) fn parse_whitelist_nodes(&self) -> anyhow::Result<Vec<PeerInfo>> {
+        let peers = parse_peers(&self.whitelist_nodes)?;
+        if let Some(peer) = peers.iter().find(|peer| peer.addr.is_none()) {
+            bail!(
+                "whitelist_nodes are required to specify both PeerId and IP:port, got {}",
+                peer.id
+            );
+        }
+        Ok(peers)
+    }
+
+    pub(crate) fn parse_pinned_peers(&self) -> anyhow::Result<Vec<PeerInfo>> {
+        let peers = parse_peers(&self.pinned_peers.join(","))?;
+        if let Some(peer) = peers.iter().find(|peer| peer.addr.is_none()) {
+            bail!("pinned_peers are required to specify both PeerId and IP:port, got {}", peer.id);
+        }
+        Ok(peers)
+    }
+
+    /// The whitelisted and pinned peers, which the blacklist doesn't apply to
+    /// at the addresses they're configured with.
+    pub fn blacklist_exempt_peers(&self) -> anyhow::Result<Vec<PeerInfo>> {
+        Ok(self.parse_whitelist_nodes()?.into_iter().chain(self.parse_pinned_peers()?).collect())
+    }
+}
+
+fn parse_peers(peers: &str) -> anyhow::Result<Vec<PeerInfo>> {
+    if peers.is_empty() {
+        return Ok(vec![]);
+    }
+    peers
+        .split(',')
+        .map(|chunk| {
+            PeerInfo::try_from(chunk)
+                .map_err(|err| anyhow!("Failed to parse PeerInfo {}: {}", chunk, err))
+        })
+        .collect()
 }
 
 impl Default for Network {
@@ -261,7 +509,9 @@ impl Default for Network {
             addr: "0.0.0.0:24567".to_string(),
             external_address: "".to_string(),
             boot_nodes: "".to_string(),
+            dns_seeds: vec![],
             whitelist_nodes: "".to_string(),
+            pinned_peers: vec![],
             max_num_peers: default_max_num_peers(),
             minimum_outbound_peers: default_minimum_outbound_connections(),
             ideal_connections_lo: default_ideal_connections_lo(),
@@ -273,9 +523,22 @@ impl Default for Network {
             reconnect_delay: Duration::from_secs(60),
             skip_sync_wait: false,
             ban_window: Duration::from_secs(3 * 60 * 60),
-            blacklist: vec![],
+            max_peer_store_entries: None,
+            blacklist: BlacklistConfig::default(),
+            blacklist_audit: vec![],
             ttl_account_id_router: default_ttl_account_id_router(),
             peer_stats_period: default_peer_stats_period(),
+            clock_skew_warn_threshold: default_clock_skew_warn_threshold(),
+            max_message_sizes: HashMap::new(),
+            tcp_keepalive_time: default_tcp_keepalive_time(),
+            tcp_keepalive_interval: default_tcp_keepalive_interval(),
+            tcp_keepalive_retries: default_tcp_keepalive_retries(),
+            liveness_ping_after: default_liveness_ping_after(),
+            liveness_timeout: default_liveness_timeout(),
+            liveness_max_missed: default_liveness_max_missed(),
+            message_recorder: None,
+            private_network: false,
+            message_compression: None,
         }
     }
 }
@@ -301,6 +564,10 @@ fn default_state_sync_timeout() -> Duration {
     Duration::from_secs(60)
 }
 
+fn default_state_sync_sources() -> Vec<StateSyncSource> {
+    vec![StateSyncSource::Peers]
+}
+
 fn default_header_sync_expected_height_per_second() -> u64 {
     10
 }
@@ -333,6 +600,42 @@ fn default_use_checkpoints_for_db_migration() -> bool {
     true
 }
 
+fn default_allow_view_call_overrides() -> bool {
+    false
+}
+
+fn default_view_accounts_limit() -> usize {
+    1000
+}
+
+fn default_pending_receipts_scan_limit() -> u64 {
+    10_000
+}
+
+fn default_dropped_transactions_log_size() -> usize {
+    1000
+}
+
+fn default_block_processing_log_size() -> u64 {
+    1000
+}
+
+fn default_orphan_pool_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_missing_chunks_pool_max_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_shutdown_report_file() -> PathBuf {
+    PathBuf::from("shutdown_report.json")
+}
+
+fn default_view_cache_size_bytes() -> u64 {
+    32 * 1024 * 1024
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Consensus {
     /// Minimum number of peers to start syncing.
@@ -372,6 +675,11 @@ pub struct Consensus {
     /// How much to wait for a state sync response before re-requesting
     #[serde(default = "default_state_sync_timeout")]
     pub state_sync_timeout: Duration,
+    /// Where to get the state parts from, in order, e.g.
+    /// `[{"local_dir": {"path": "/data/state_parts"}}, "peers"]` to use the
+    /// parts put in the directory and download only the missing ones.
+    #[serde(default = "default_state_sync_sources")]
+    pub state_sync_sources: Vec<StateSyncSource>,
     /// Expected increase of header head weight per second during header sync
     #[serde(default = "default_header_sync_expected_height_per_second")]
     pub header_sync_expected_height_per_second: u64,
@@ -405,6 +713,7 @@ impl Default for Consensus {
             header_sync_progress_timeout: default_header_sync_progress_timeout(),
             header_sync_stall_ban_timeout: default_header_sync_stall_ban_timeout(),
             state_sync_timeout: default_state_sync_timeout(),
+            state_sync_sources: default_state_sync_sources(),
             header_sync_expected_height_per_second: default_header_sync_expected_height_per_second(
             ),
             sync_check_period: default_sync_check_period(),
@@ -414,6 +723,28 @@ impl Default for Consensus {
     }
 }
 
+/// The filters take about `bits_per_key / 8` bytes per account of the tracked
+/// shards, and are built in the background at startup, the queries reading
+/// the state until they're built.  With 10 bits per key about 1% of the
+/// queries of accounts which don't exist still read the state.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AccountFilterConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_account_filter_bits_per_key")]
+    pub bits_per_key: u32,
+}
+
+fn default_account_filter_bits_per_key() -> u32 {
+    10
+}
+
+impl Default for AccountFilterConfig {
+    fn default() -> Self {
+        Self { enabled: false, bits_per_key: default_account_filter_bits_per_key() }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(default)]
 pub struct Config {
@@ -430,7 +761,14 @@ pub struct Config {
     pub telemetry: TelemetryConfig,
     pub network: Network,
     pub consensus: Consensus,
-    pub tracked_accounts: Vec<AccountId>,
+    /// Accounts whose shards the node tracks.  The shards are looked up in the
+    /// shard layout of each epoch, so tracking follows the accounts across
+    /// resharding.  Patterns like `*.bridge.near` for the accounts under an
+    /// account, or `*` for the top-level accounts, match accounts in any
+    /// shard and make the node track all of them.  Can't be set together
+    /// with `tracked_shards`.
+    pub tracked_accounts: Vec<AccountPattern>,
+    /// Any shard id here makes the node track all shards.
     pub tracked_shards: Vec<ShardId>,
     pub archive: bool,
     pub log_summary_style: LogSummaryStyle,
@@ -447,6 +785,27 @@ pub struct Config {
     /// If set, overrides value in genesis configuration.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_gas_burnt_view: Option<Gas>,
+    /// Whether to serve `call_function_with_overrides` queries.  Off by
+    /// default since such calls read arbitrary state on top of overridden
+    /// storage; their gas is still capped at `max_gas_burnt_view`.
+    #[serde(default = "default_allow_view_call_overrides")]
+    pub allow_view_call_overrides: bool,
+    /// Maximum number of accounts a `view_accounts` query may ask for.  Each
+    /// account takes about 200 bytes of the response, bigger batches are
+    /// refused with an error asking to split them.
+    #[serde(default = "default_view_accounts_limit")]
+    pub view_accounts_limit: usize,
+    /// Maximum number of entries of the delayed receipts queue of a shard the
+    /// `EXPERIMENTAL_pending_receipts` RPC scans for the receipts of an
+    /// account.  Past it the response is marked as truncated.
+    #[serde(default = "default_pending_receipts_scan_limit")]
+    pub pending_receipts_scan_limit: u64,
+    /// If set, every receipt applied by the node is written into this
+    /// directory, so that it can later be re-executed with `neard view-state
+    /// runtime-replay`.  Relative paths are resolved against the home
+    /// directory.  Meant for collecting test corpora, not for production nodes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_receipts_dir: Option<PathBuf>,
     /// Checkpoints let the user recover from interrupted DB migrations.
     #[serde(default = "default_use_checkpoints_for_db_migration")]
     pub use_db_migration_snapshot: bool,
@@ -458,6 +817,82 @@ pub struct Config {
     pub db_migration_snapshot_path: Option<PathBuf>,
     /// Different parameters to configure/optimize underlying storage.
     pub store: near_store::StoreConfig,
+    /// Limits of the per-shard transaction pools.
+    #[serde(default)]
+    pub transaction_pool: TransactionPoolConfig,
+    /// Number of transactions dropped from the pool during chunk production
+    /// which are remembered to be reported on the debug page and by the `tx`
+    /// RPC.  Zero disables it.
+    #[serde(default = "default_dropped_transactions_log_size")]
+    pub dropped_transactions_log_size: usize,
+    /// Number of recently processed blocks whose hash, height, processing
+    /// times and outcome are kept in the database, to be inspected with
+    /// `neard view-state recent-processing` after a crash.  Zero disables it.
+    #[serde(default = "default_block_processing_log_size")]
+    pub block_processing_log_size: u64,
+    /// Maximum total size in bytes of the orphan blocks kept in memory.  When
+    /// it's exceeded the orphans with the lowest heights are evicted.
+    #[serde(default = "default_orphan_pool_max_bytes")]
+    pub orphan_pool_max_bytes: u64,
+    /// Maximum total size in bytes of the blocks kept in memory while waiting
+    /// for missing chunks.  When it's exceeded the blocks with the lowest
+    /// heights are evicted.
+    #[serde(default = "default_missing_chunks_pool_max_bytes")]
+    pub missing_chunks_pool_max_bytes: u64,
+    /// If set, a node which becomes a validator in the next epoch builds and
+    /// signs the blocks and chunks it would produce on every new head,
+    /// without broadcasting them, to check its setup before the epoch starts.
+    /// The results are exported as metrics and shown on the debug page.
+    pub shadow_production: bool,
+    /// Free space of the disk the database is on below which the node warns,
+    /// stops serving state parts and state views, and shuts down.
+    #[serde(default)]
+    pub disk_space_monitor: DiskSpaceMonitorConfig,
+    /// Maximum total size in bytes of the views of final blocks and their
+    /// chunks the view client keeps in memory to serve RPC requests without
+    /// reading the database.  0 turns the cache off.
+    #[serde(default = "default_view_cache_size_bytes")]
+    pub view_cache_size_bytes: u64,
+    /// Maximum size in bytes of a single write committing the changes of a
+    /// processed block to the database.  Larger commits have their new trie
+    /// nodes written first in writes of at most this size, which bounds the
+    /// latency spikes of slow disks, and the rest, the heads included, in a
+    /// last write.  A crash between the writes leaks the new trie nodes of the
+    /// block, which `neard view-state trie-gc` removes.  0 commits blocks in a
+    /// single write.
+    #[serde(default)]
+    pub store_commit_max_batch_bytes: u64,
+    /// Rebroadcast of the transactions the node forwarded, when their shard
+    /// goes `missed_heights` heights without a chunk after they were sent, to
+    /// the chunk producers of the next heights.  On by default for the nodes
+    /// without a validator key, which don't produce chunks themselves.
+    #[serde(default)]
+    pub tx_rebroadcast: TxRebroadcastConfig,
+    /// Bloom filters of the account ids of the tracked shards, kept in memory
+    /// to answer the `view_account` queries of accounts which don't exist
+    /// without reading the state.
+    #[serde(default)]
+    pub account_filter: AccountFilterConfig,
+    /// Transaction selection of the chunks this node produces, e.g. the
+    /// `priority_accounts` whose transactions are included first.  Only the
+    /// chunks this node produces itself are affected.
+    #[serde(default)]
+    pub chunk_producer: ChunkProducerConfig,
+    /// Gauges of the stake of each validator of the current epoch and of
+    /// whether it was kicked out, for the `top_by_stake` validators and the
+    /// listed `accounts`.  Off by default, for the number of series it adds.
+    #[serde(default)]
+    pub validator_metrics: ValidatorMetricsConfig,
+    /// Number of threads verifying the signatures of the approvals of the
+    /// blocks and of the transactions of the chunks in batches.  Half of the
+    /// cores if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_verification_threads: Option<usize>,
+    /// File the JSON report of the state of the node is written to when it
+    /// panics or dies of a fatal error.  Relative paths are resolved against
+    /// the home directory.
+    #[serde(default = "default_shutdown_report_file")]
+    pub shutdown_report_file: PathBuf,
 }
 
 impl Default for Config {
@@ -484,15 +919,46 @@ impl Default for Config {
             view_client_throttle_period: default_view_client_throttle_period(),
             trie_viewer_state_size_limit: default_trie_viewer_state_size_limit(),
             max_gas_burnt_view: None,
+            allow_view_call_overrides: default_allow_view_call_overrides(),
+            view_accounts_limit: default_view_accounts_limit(),
+            pending_receipts_scan_limit: default_pending_receipts_scan_limit(),
+            record_receipts_dir: None,
             db_migration_snapshot_path: None,
             use_db_migration_snapshot: true,
             store: near_store::StoreConfig::read_write(),
+            transaction_pool: TransactionPoolConfig::default(),
+            dropped_transactions_log_size: default_dropped_transactions_log_size(),
+            block_processing_log_size: default_block_processing_log_size(),
+            orphan_pool_max_bytes: default_orphan_pool_max_bytes(),
+            missing_chunks_pool_max_bytes: default_missing_chunks_pool_max_bytes(),
+            shadow_production: false,
+            disk_space_monitor: DiskSpaceMonitorConfig::default(),
+            view_cache_size_bytes: default_view_cache_size_bytes(),
+            store_commit_max_batch_bytes: 0,
+            tx_rebroadcast: TxRebroadcastConfig::default(),
+            account_filter: AccountFilterConfig::default(),
+            chunk_producer: ChunkProducerConfig::default(),
+            validator_metrics: ValidatorMetricsConfig::default(),
+            signature_verification_threads: None,
+            shutdown_report_file: default_shutdown_report_file(),
         }
     }
 }
 
 impl Config {
     pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let (config, unrecognised_fields) = Self::from_file_with_unrecognised_fields(path)?;
+        if !unrecognised_fields.is_empty() {
+            warn!("{}: encountered unrecognised fields: {:?}", path.display(), unrecognised_fields);
+        }
+        Ok(config)
+    }
+
+    /// Reads the config and returns it together with the paths of the fields
+    /// in the file which don't correspond to any config option.
+    pub(crate) fn from_file_with_unrecognised_fields(
+        path: &Path,
+    ) -> anyhow::Result<(Self, Vec<String>)> {
         let mut unrecognised_fields = Vec::new();
         let s = std::fs::read_to_string(path)
             .with_context(|| format!("Failed to read config from {}", path.display()))?;
@@ -501,12 +967,26 @@ impl Config {
                 unrecognised_fields.push(path.to_string());
             })
             .with_context(|| format!("Failed to deserialize config from {}", path.display()))?;
+        Ok((config, unrecognised_fields))
+    }
 
-        if !unrecognised_fields.is_empty() {
-            warn!("{}: encountered unrecognised fields: {:?}", path.display(), unrecognised_fields);
+    /// Adjusts the defaults to the ones `neard init` writes for the given chain.
+    pub(crate) fn set_chain_defaults(&mut self, chain_id: &str) {
+        match chain_id {
+            "mainnet" => self.telemetry.endpoints.push(MAINNET_TELEMETRY_URL.to_string()),
+            "testnet" | "betanet" => {
+                self.telemetry.endpoints.push(NETWORK_TELEMETRY_URL.replace("{}", chain_id))
+            }
+            _ => self.network.skip_sync_wait = true,
         }
+    }
 
-        Ok(config)
+    /// The config `neard init` creates for the given chain, assuming no config
+    /// is downloaded.
+    pub fn default_for_chain(chain_id: &str) -> Self {
+        let mut config = Self::default();
+        config.set_chain_defaults(chain_id);
+        config
     }
 
     pub fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
@@ -645,6 +1125,12 @@ pub struct NearConfig {
     pub telemetry_config: TelemetryConfig,
     pub genesis: Genesis,
     pub validator_signer: Option<Arc<dyn ValidatorSigner>>,
+    /// Compile contracts deployed in the tracked shards in the background once
+    /// the node starts.  Set by `neard run --precompile-contracts`.
+    pub precompile_contracts: bool,
+    /// Run with a database made by `neard database clone`.  Set by
+    /// `neard run --allow-cloned-db`.
+    pub allow_cloned_db: bool,
 }
 
 impl NearConfig {
@@ -676,6 +1162,7 @@ impl NearConfig {
                     .consensus
                     .header_sync_expected_height_per_second,
                 state_sync_timeout: config.consensus.state_sync_timeout,
+                state_sync_sources: config.consensus.state_sync_sources.clone(),
                 min_num_peers: config.consensus.min_num_peers,
                 log_summary_period: Duration::from_secs(10),
                 produce_empty_blocks: config.consensus.produce_empty_blocks,
@@ -700,44 +1187,34 @@ impl NearConfig {
                 view_client_throttle_period: config.view_client_throttle_period,
                 trie_viewer_state_size_limit: config.trie_viewer_state_size_limit,
                 max_gas_burnt_view: config.max_gas_burnt_view,
+                allow_view_call_overrides: config.allow_view_call_overrides,
+                view_accounts_limit: config.view_accounts_limit,
+                pending_receipts_scan_limit: config.pending_receipts_scan_limit,
                 enable_statistics_export: config.store.enable_statistics_export,
+                column_properties_export_period: config.store.column_properties_export_period,
+                transaction_pool: config.transaction_pool,
+                dropped_transactions_log_size: config.dropped_transactions_log_size,
+                block_processing_log_size: config.block_processing_log_size,
+                orphan_pool_max_bytes: config.orphan_pool_max_bytes,
+                missing_chunks_pool_max_bytes: config.missing_chunks_pool_max_bytes,
+                validator_key_file: None,
+                shadow_production: config.shadow_production,
+                disk_space_monitor: config.disk_space_monitor,
+                view_cache_size_bytes: config.view_cache_size_bytes,
+                store_commit_max_batch_bytes: config.store_commit_max_batch_bytes,
+                tx_rebroadcast: config.tx_rebroadcast,
+                chunk_producer: config.chunk_producer,
+                validator_metrics: config.validator_metrics,
             },
             network_config: NetworkConfig {
                 public_key: network_key_pair.public_key,
                 secret_key: network_key_pair.secret_key,
                 account_id: validator_signer.as_ref().map(|vs| vs.validator_id().clone()),
-                addr: if config.network.addr.is_empty() {
-                    None
-                } else {
-                    Some(config.network.addr.parse().unwrap())
-                },
-                boot_nodes: if config.network.boot_nodes.is_empty() {
-                    vec![]
-                } else {
-                    config
-                        .network
-                        .boot_nodes
-                        .split(',')
-                        .map(|chunk| chunk.try_into().expect("Failed to parse PeerInfo"))
-                        .collect()
-                },
-                whitelist_nodes: (|| -> Vec<_> {
-                    let w = &config.network.whitelist_nodes;
-                    if w.is_empty() {
-                        return vec![];
-                    }
-                    let mut peers = vec![];
-                    for peer in w.split(',') {
-                        let peer: PeerInfo = peer.try_into().expect("Failed to parse PeerInfo");
-                        if peer.addr.is_none() {
-                            panic!(
-                                "whitelist_nodes are required to specify both PeerId and IP:port"
-                            )
-                        }
-                        peers.push(peer);
-                    }
-                    peers
-                }()),
+                addr: config.network.parse_addr().unwrap(),
+                boot_nodes: config.network.parse_boot_nodes().unwrap(),
+                dns_seeds: config.network.dns_seeds.clone(),
+                whitelist_nodes: config.network.parse_whitelist_nodes().unwrap(),
+                pinned_peers: config.network.parse_pinned_peers().unwrap(),
                 handshake_timeout: config.network.handshake_timeout,
                 reconnect_delay: config.network.reconnect_delay,
                 bootstrap_peers_period: Duration::from_secs(60),
@@ -753,15 +1230,39 @@ impl NearConfig {
                 ban_window: config.network.ban_window,
                 max_send_peers: 512,
                 peer_expiration_duration: Duration::from_secs(7 * 24 * 60 * 60),
+                max_peer_store_entries: config.network.max_peer_store_entries,
                 peer_stats_period: Duration::from_secs(5),
                 ttl_account_id_router: config.network.ttl_account_id_router,
                 routed_message_ttl: ROUTED_MESSAGE_TTL,
                 max_routes_to_store: MAX_ROUTES_TO_STORE,
                 highest_peer_horizon: HIGHEST_PEER_HORIZON,
                 push_info_period: Duration::from_millis(100),
-                blacklist: config.network.blacklist,
+                blacklist: config.network.blacklist.lists(),
+                blacklist_audit: config.network.blacklist_audit.clone(),
                 outbound_disabled: false,
                 archive: config.archive,
+                clock_skew_warn_threshold: config.network.clock_skew_warn_threshold,
+                max_message_sizes: config.network.max_message_sizes,
+                tcp_keepalive_time: config.network.tcp_keepalive_time,
+                tcp_keepalive_interval: config.network.tcp_keepalive_interval,
+                tcp_keepalive_retries: config.network.tcp_keepalive_retries,
+                liveness_ping_after: config.network.liveness_ping_after,
+                liveness_timeout: config.network.liveness_timeout,
+                liveness_max_missed: config.network.liveness_max_missed,
+                message_recorder: config.network.message_recorder.map(|recorder| {
+                    MessageRecorderConfig {
+                        path: recorder.path,
+                        max_file_bytes: recorder.max_file_bytes,
+                        max_files: recorder.max_files,
+                    }
+                }),
+                private_network: config.network.private_network,
+                message_compression: config.network.message_compression.map(|compression| {
+                    CompressionConfig {
+                        threshold: compression.threshold,
+                        skip_message_types: compression.skip_message_types,
+                    }
+                }),
             },
             telemetry_config: config.telemetry,
             #[cfg(feature = "json_rpc")]
@@ -770,6 +1271,8 @@ impl NearConfig {
             rosetta_rpc_config: config.rosetta_rpc,
             genesis,
             validator_signer,
+            precompile_contracts: false,
+            allow_cloned_db: false,
         }
     }
 
@@ -974,11 +1477,12 @@ pub fn init_configs(
     download_config_url: Option<&str>,
     boot_nodes: Option<&str>,
     max_gas_burnt_view: Option<Gas>,
+    force: bool,
 ) -> anyhow::Result<()> {
     fs::create_dir_all(dir).with_context(|| anyhow!("Failed to create directory {:?}", dir))?;
 
     // Check if config already exists in home dir.
-    if dir.join(CONFIG_FILENAME).exists() {
+    if dir.join(CONFIG_FILENAME).exists() && !force {
         let config = Config::from_file(&dir.join(CONFIG_FILENAME))
             .with_context(|| anyhow!("Failed to read config {}", dir.display()))?;
         let file_path = dir.join(&config.genesis_file);
@@ -986,25 +1490,36 @@ pub fn init_configs(
             anyhow!("Failed to read genesis config {}/{}", dir.display(), config.genesis_file)
         })?;
         bail!(
-            "Config is already downloaded to ‘{}’ with chain-id ‘{}’.",
+            "Config is already downloaded to ‘{}’ with chain-id ‘{}’.  Use --force to overwrite it.",
             file_path.display(),
             genesis.chain_id
         );
     }
+    if dir.join(GENESIS_CONFIG_FILENAME).exists() && !force {
+        bail!(
+            "Genesis file ‘{}’ already exists.  Use --force to overwrite it.",
+            dir.join(GENESIS_CONFIG_FILENAME).display()
+        );
+    }
 
     let mut config = Config::default();
     let chain_id = chain_id
         .and_then(|c| if c.is_empty() { None } else { Some(c.to_string()) })
         .unwrap_or_else(random_chain_id);
 
+    let mut provenance = Provenance::new(&chain_id);
     if let Some(url) = download_config_url {
-        download_config(&url.to_string(), &dir.join(CONFIG_FILENAME))
-            .context(format!("Failed to download the config file from {}", url))?;
+        provenance.files.push(
+            download_config(&url.to_string(), &dir.join(CONFIG_FILENAME))
+                .context(format!("Failed to download the config file from {}", url))?,
+        );
         config = Config::from_file(&dir.join(CONFIG_FILENAME))?;
     } else if should_download_config {
         let url = get_config_url(&chain_id);
-        download_config(&url, &dir.join(CONFIG_FILENAME))
-            .context(format!("Failed to download the config file from {}", url))?;
+        provenance.files.push(
+            download_config(&url, &dir.join(CONFIG_FILENAME))
+                .context(format!("Failed to download the config file from {}", url))?,
+        );
         config = Config::from_file(&dir.join(CONFIG_FILENAME))?;
     }
 
@@ -1016,12 +1531,13 @@ pub fn init_configs(
         config.max_gas_burnt_view = max_gas_burnt_view;
     }
 
+    config.set_chain_defaults(&chain_id);
+
     match chain_id.as_ref() {
         "mainnet" => {
             if test_seed.is_some() {
                 bail!("Test seed is not supported for MainNet");
             }
-            config.telemetry.endpoints.push(MAINNET_TELEMETRY_URL.to_string());
             config.write_to_file(&dir.join(CONFIG_FILENAME)).with_context(|| {
                 format!("Error writing config to {}", dir.join(CONFIG_FILENAME).display())
             })?;
@@ -1038,7 +1554,6 @@ pub fn init_configs(
             if test_seed.is_some() {
                 bail!("Test seed is not supported for official testnet");
             }
-            config.telemetry.endpoints.push(NETWORK_TELEMETRY_URL.replace("{}", &chain_id));
             config.write_to_file(&dir.join(CONFIG_FILENAME)).with_context(|| {
                 format!("Error writing config to {}", dir.join(CONFIG_FILENAME).display())
             })?;
@@ -1052,12 +1567,16 @@ pub fn init_configs(
                 genesis_path.to_str().with_context(|| "Genesis path must be initialized")?;
 
             if let Some(url) = download_genesis_url {
-                download_genesis(&url.to_string(), &genesis_path)
-                    .context(format!("Failed to download the genesis file from {}", url))?;
+                provenance.files.push(
+                    download_genesis(&url.to_string(), &genesis_path)
+                        .context(format!("Failed to download the genesis file from {}", url))?,
+                );
             } else if should_download_genesis {
                 let url = get_genesis_url(&chain_id);
-                download_genesis(&url, &genesis_path)
-                    .context(format!("Failed to download the genesis file from {}", url))?;
+                provenance.files.push(
+                    download_genesis(&url, &genesis_path)
+                        .context(format!("Failed to download the genesis file from {}", url))?,
+                );
             } else {
                 genesis_path_str = match genesis {
                     Some(g) => g,
@@ -1079,7 +1598,6 @@ pub fn init_configs(
         }
         _ => {
             // Create new configuration, key files and genesis for one validator.
-            config.network.skip_sync_wait = true;
             if fast {
                 config.consensus.min_block_production_delay =
                     Duration::from_millis(FAST_MIN_BLOCK_PRODUCTION_DELAY);
@@ -1163,6 +1681,13 @@ pub fn init_configs(
             info!(target: "near", "Generated node key, validator key, genesis file in {}", dir.display());
         }
     }
+
+    if !provenance.files.is_empty() {
+        let path = dir.join(PROVENANCE_FILENAME);
+        provenance
+            .write_to_file(&path)
+            .with_context(|| format!("Error writing provenance to {}", path.display()))?;
+    }
     Ok(())
 }
 
@@ -1332,33 +1857,79 @@ pub fn get_config_url(chain_id: &str) -> String {
     )
 }
 
-pub fn download_genesis(url: &str, path: &Path) -> Result<(), FileDownloadError> {
-    info!(target: "near", "Downloading genesis file from: {} ...", url);
-    let result = run_download_file(url, path);
-    if result.is_ok() {
-        info!(target: "near", "Saved the genesis file to: {} ...", path.display());
+/// A file downloaded by `neard init`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DownloadedFile {
+    pub path: PathBuf,
+    pub url: String,
+    /// SHA-256 of the data downloaded, before decompression.
+    pub sha256: String,
+    /// Whether the data matched the checksum published next to it.  `false`
+    /// if there was none.
+    pub verified: bool,
+}
+
+/// What the node was initialized from, written to [`PROVENANCE_FILENAME`] in
+/// the home directory by `neard init`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Provenance {
+    pub chain_id: String,
+    pub initialized_at: chrono::DateTime<chrono::Utc>,
+    pub files: Vec<DownloadedFile>,
+}
+
+impl Provenance {
+    fn new(chain_id: &str) -> Self {
+        Self { chain_id: chain_id.to_string(), initialized_at: Clock::utc(), files: vec![] }
+    }
+
+    fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        let str = serde_json::to_string_pretty(self)?;
+        file.write_all(str.as_bytes())
     }
-    result
 }
 
-pub fn download_config(url: &str, path: &Path) -> Result<(), FileDownloadError> {
-    info!(target: "near", "Downloading config file from: {} ...", url);
-    let result = run_download_file(url, path);
-    if result.is_ok() {
-        info!(target: "near", "Saved the config file to: {} ...", path.display());
+/// Downloads the file, checking it against the SHA-256 checksum published at
+/// `url` with `.sha256` appended if there's one.  A partial download left by
+/// an earlier attempt is resumed.
+fn download_verified_file(
+    what: &str,
+    url: &str,
+    path: &Path,
+) -> Result<DownloadedFile, FileDownloadError> {
+    info!(target: "near", "Downloading {} file from: {} ...", what, url);
+    let expected_sha256 = run_download_checksum(url)?;
+    if expected_sha256.is_none() {
+        warn!(target: "near", "No checksum published for {}, the {} file can't be verified", url, what);
     }
-    result
+    let sha256 = run_download_file(url, path, expected_sha256.as_deref())?;
+    info!(target: "near", "Saved the {} file to: {} ...", what, path.display());
+    Ok(DownloadedFile {
+        path: path.to_path_buf(),
+        url: url.to_string(),
+        sha256,
+        verified: expected_sha256.is_some(),
+    })
+}
+
+pub fn download_genesis(url: &str, path: &Path) -> Result<DownloadedFile, FileDownloadError> {
+    download_verified_file("genesis", url, path)
+}
+
+pub fn download_config(url: &str, path: &Path) -> Result<DownloadedFile, FileDownloadError> {
+    download_verified_file("config", url, path)
 }
 
 #[derive(Deserialize)]
-struct NodeKeyFile {
+pub(crate) struct NodeKeyFile {
     account_id: String,
     public_key: PublicKey,
     secret_key: near_crypto::SecretKey,
 }
 
 impl NodeKeyFile {
-    fn from_file(path: &Path) -> std::io::Result<Self> {
+    pub(crate) fn from_file(path: &Path) -> std::io::Result<Self> {
         let mut file = File::open(path)?;
         let mut content = String::new();
         file.read_to_string(&mut content)?;
@@ -1387,6 +1958,12 @@ pub fn load_config(
     genesis_validation: GenesisValidationMode,
 ) -> Result<NearConfig, anyhow::Error> {
     let config = Config::from_file(&dir.join(CONFIG_FILENAME))?;
+    if !config.tracked_accounts.is_empty() && !config.tracked_shards.is_empty() {
+        anyhow::bail!(
+            "{}: tracked_accounts and tracked_shards can't both be set",
+            dir.join(CONFIG_FILENAME).display()
+        );
+    }
     let genesis_file = dir.join(&config.genesis_file);
     let validator_file = dir.join(&config.validator_key_file);
     let validator_signer = if validator_file.exists() {
@@ -1403,7 +1980,7 @@ pub fn load_config(
     })?;
 
     let genesis_records_file = config.genesis_records_file.clone();
-    Ok(NearConfig::new(
+    let mut near_config = NearConfig::new(
         config,
         match genesis_records_file {
             Some(genesis_records_file) => Genesis::from_files(
@@ -1415,7 +1992,12 @@ pub fn load_config(
         },
         network_signer.into(),
         validator_signer,
-    ))
+    );
+    near_config.client_config.validator_key_file = Some(validator_file);
+    if let Some(recorder) = &mut near_config.network_config.message_recorder {
+        recorder.path = dir.join(&recorder.path);
+    }
+    Ok(near_config)
 }
 
 pub fn load_test_config(seed: &str, port: u16, genesis: Genesis) -> NearConfig {
@@ -1461,11 +2043,38 @@ fn test_init_config_localnet() {
         None,
         None,
         None,
+        false,
     )
     .unwrap();
     let genesis =
         Genesis::from_file(temp_dir.path().join("genesis.json"), GenesisValidationMode::UnsafeFast);
     assert_eq!(genesis.config.chain_id, "localnet");
+    // Nothing was downloaded.
+    assert!(!temp_dir.path().join(PROVENANCE_FILENAME).exists());
+
+    // The files are only overwritten with --force.
+    let reinit = |force| {
+        init_configs(
+            &temp_dir.path(),
+            Some("localnet"),
+            None,
+            Some("seed1"),
+            3,
+            false,
+            None,
+            false,
+            None,
+            false,
+            None,
+            None,
+            None,
+            force,
+        )
+    };
+    assert!(reinit(false).is_err());
+    reinit(true).unwrap();
+    let genesis =
+        Genesis::from_file(temp_dir.path().join("genesis.json"), GenesisValidationMode::UnsafeFast);
     assert_eq!(genesis.config.shard_layout.num_shards(), 3);
     assert_eq!(
         account_id_to_shard_id(
@@ -1510,9 +2119,19 @@ fn test_config_from_file() {
         // values is probably not worth it but there may be some other defaults
         // we want to ensure that they happen.
         let want_gc = if has_gc {
-            GCConfig { gc_blocks_limit: 42, gc_fork_clean_step: 420, gc_num_epochs_to_keep: 24 }
+            GCConfig {
+                gc_blocks_limit: 42,
+                gc_fork_clean_step: 420,
+                gc_num_epochs_to_keep: 24,
+                ..GCConfig::default()
+            }
         } else {
-            GCConfig { gc_blocks_limit: 2, gc_fork_clean_step: 100, gc_num_epochs_to_keep: 5 }
+            GCConfig {
+                gc_blocks_limit: 2,
+                gc_fork_clean_step: 100,
+                gc_num_epochs_to_keep: 5,
+                ..GCConfig::default()
+            }
         };
         assert_eq!(want_gc, config.gc);
 
@@ -0,0 +1,658 @@
+//! Validation of the files in the node's home directory.
+//!
+//! Deserialising `config.json` only checks that it's well-formed.  The checks
+//! here also look at the values, the genesis and the keys so that problems
+//! which would otherwise make the node panic on startup, or misbehave much
+//! later, are all reported up front.  Used by `neard validate-config`.
+
+use std::fmt;
+use std::path::Path;
+
+use near_chain_configs::genesis_validate::genesis_validation_errors;
+use near_chain_configs::{
+    GCConfig, Genesis, GenesisConfig, GenesisValidationMode, MIN_GC_NUM_EPOCHS_TO_KEEP,
+};
+use near_crypto::KeyFile;
+use near_network::types::MessageSizeLimits;
+use near_network_primitives::types::duplicate_blacklist_entries;
+use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+use serde_json::Value;
+
+use crate::config::{message_recorder_allowed, Config, NearConfig, NodeKeyFile, CONFIG_FILENAME};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The node would fail to start or run incorrectly.
+    Error,
+    /// The value is accepted but likely isn't what was intended.
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct ConfigProblem {
+    pub severity: Severity,
+    /// Name of the file the problem is in.
+    pub file: String,
+    /// JSON path of the offending field, empty if the problem is with the
+    /// file as a whole.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", severity, self.file)?;
+        if !self.path.is_empty() {
+            write!(f, ": {}", self.path)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Result of validating a home directory.
+#[derive(Default)]
+pub struct ConfigValidation {
+    pub problems: Vec<ConfigProblem>,
+    /// The config, if it could be read.
+    pub config: Option<Config>,
+    /// Chain id from the genesis, if it could be read.
+    pub chain_id: Option<String>,
+}
+
+impl ConfigValidation {
+    pub fn has_errors(&self) -> bool {
+        self.problems.iter().any(|problem| problem.severity == Severity::Error)
+    }
+
+    fn push(&mut self, severity: Severity, file: &str, path: &str, message: impl Into<String>) {
+        self.problems.push(ConfigProblem {
+            severity,
+            file: file.to_string(),
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, file: &str, path: &str, message: impl Into<String>) {
+        self.push(Severity::Error, file, path, message)
+    }
+
+    fn warning(&mut self, file: &str, path: &str, message: impl Into<String>) {
+        self.push(Severity::Warning, file, path, message)
+    }
+}
+
+/// Loads `config.json`, the genesis and the keys from the home directory and
+/// reports every problem found with them.
+pub fn validate_home_dir(dir: &Path) -> ConfigValidation {
+    let mut validation = ConfigValidation::default();
+    let (config, unrecognised_fields) =
+        match Config::from_file_with_unrecognised_fields(&dir.join(CONFIG_FILENAME)) {
+            Ok(config) => config,
+            Err(err) => {
+                validation.error(CONFIG_FILENAME, "", format!("{:#}", err));
+                return validation;
+            }
+        };
+    for field in unrecognised_fields {
+        validation.warning(CONFIG_FILENAME, &field, "unrecognised field, it is ignored");
+    }
+    validate_config(&config, &mut validation);
+
+    let validator_signer = load_validator_key(dir, &config, &mut validation);
+    let node_key = load_node_key(dir, &config, &mut validation);
+    let genesis = load_genesis(dir, &config, &mut validation);
+    if let Some(genesis) = genesis {
+        validate_config_against_genesis(&config, &genesis.config, &mut validation);
+        validation.chain_id = Some(genesis.config.chain_id.clone());
+        // The network config is only built if building it can't panic.
+        let network_parses = config.network.parse_addr().is_ok()
+            && config.network.parse_boot_nodes().is_ok()
+            && config.network.parse_whitelist_nodes().is_ok()
+            && config.network.parse_pinned_peers().is_ok();
+        if let (Some(node_key), true) = (node_key, network_parses) {
+            let near_config = NearConfig::new(config.clone(), genesis, node_key, validator_signer);
+            for (field, message) in near_config.network_config.validation_errors() {
+                validation.error(CONFIG_FILENAME, &format!("network.{}", field), message);
+            }
+        }
+    }
+    validation.config = Some(config);
+    validation
+}
+
+/// Checks the values in the config which don't depend on other files.
+pub fn validate_config(config: &Config, validation: &mut ConfigValidation) {
+    let file = CONFIG_FILENAME;
+    if let Err(err) = config.network.parse_addr() {
+        validation.error(file, "network.addr", format!("{:#}", err));
+    }
+    if let Err(err) = config.network.parse_boot_nodes() {
+        validation.error(file, "network.boot_nodes", format!("{:#}", err));
+    }
+    if let Err(err) = config.network.parse_whitelist_nodes() {
+        validation.error(file, "network.whitelist_nodes", format!("{:#}", err));
+    }
+    if let Err(err) = config.network.parse_pinned_peers() {
+        validation.error(file, "network.pinned_peers", format!("{:#}", err));
+    }
+    for dup in duplicate_blacklist_entries(&config.network.blacklist.lists()) {
+        validation.warning(
+            file,
+            "network.blacklist",
+            format!(
+                "{} is on both the {} and {} lists",
+                dup.entry, dup.first_list, dup.second_list
+            ),
+        );
+    }
+    if let Err(err) = MessageSizeLimits::new(&config.network.max_message_sizes) {
+        validation.error(file, "network.max_message_sizes", err);
+    }
+
+    #[cfg(feature = "json_rpc")]
+    if let Some(rpc) = &config.rpc {
+        for err in rpc.listen_addr_errors() {
+            validation.error(file, "rpc.listeners", err);
+        }
+        if rpc.debug_auth.is_some() && rpc.debug_addr.is_none() && rpc.prometheus_addr.is_none() {
+            validation.warning(
+                file,
+                "rpc.debug_auth",
+                "only applies to the servers at debug_addr and prometheus_addr, neither is set",
+            );
+        }
+    }
+
+    if !config.tracked_accounts.is_empty() && !config.tracked_shards.is_empty() {
+        validation.error(
+            file,
+            "tracked_accounts",
+            "can't be set together with tracked_shards, which tracks all shards",
+        );
+    }
+
+    let consensus = &config.consensus;
+    if consensus.min_block_production_delay > consensus.max_block_production_delay {
+        validation.error(
+            file,
+            "consensus.min_block_production_delay",
+            format!(
+                "is larger than max_block_production_delay ({:?} > {:?})",
+                consensus.min_block_production_delay, consensus.max_block_production_delay
+            ),
+        );
+    }
+
+    if config.gc.gc_blocks_limit == 0 {
+        validation.error(
+            file,
+            "gc_blocks_limit",
+            "must be positive, otherwise nothing is collected",
+        );
+    }
+    if config.gc.gc_blocks_limit_max < config.gc.gc_blocks_limit {
+        validation.warning(
+            file,
+            "gc_blocks_limit_max",
+            format!(
+                "is below gc_blocks_limit and the limit stays at {} blocks",
+                config.gc.gc_blocks_limit
+            ),
+        );
+    }
+    if config.gc.gc_fork_clean_step == 0 {
+        validation.error(
+            file,
+            "gc_fork_clean_step",
+            "must be positive, otherwise forks are never cleaned",
+        );
+    }
+    if config.gc.gc_num_epochs_to_keep < MIN_GC_NUM_EPOCHS_TO_KEEP {
+        validation.warning(
+            file,
+            "gc_num_epochs_to_keep",
+            format!(
+                "is below the minimum and {} epochs are kept instead",
+                MIN_GC_NUM_EPOCHS_TO_KEEP
+            ),
+        );
+    }
+    let account_filter = &config.account_filter;
+    if account_filter.enabled && !(1..=64).contains(&account_filter.bits_per_key) {
+        validation.error(
+            file,
+            "account_filter.bits_per_key",
+            format!("must be between 1 and 64, got {}", account_filter.bits_per_key),
+        );
+    }
+    if config.signature_verification_threads == Some(0) {
+        validation.error(
+            file,
+            "signature_verification_threads",
+            "must be positive, leave it unset for the default",
+        );
+    }
+    if config.archive && config.gc != GCConfig::default() {
+        validation.warning(
+            file,
+            "archive",
+            "archival nodes don't garbage collect so the gc_* options have no effect",
+        );
+    }
+}
+
+/// Checks the parts of the config which must agree with the genesis.
+pub fn validate_config_against_genesis(
+    config: &Config,
+    genesis_config: &GenesisConfig,
+    validation: &mut ConfigValidation,
+) {
+    // Shards added by resharding can be tracked before they exist.
+    let num_shards = std::iter::once(&genesis_config.shard_layout)
+        .chain(genesis_config.simple_nightshade_shard_layout.as_ref())
+        .map(|layout| layout.num_shards())
+        .max()
+        .unwrap_or_default();
+    for (index, shard_id) in config.tracked_shards.iter().enumerate() {
+        if *shard_id >= num_shards {
+            validation.warning(
+                CONFIG_FILENAME,
+                &format!("tracked_shards[{}]", index),
+                format!("shard {} doesn't exist, the chain has {} shards", shard_id, num_shards),
+            );
+        }
+    }
+    if config.network.message_recorder.is_some()
+        && !message_recorder_allowed(&genesis_config.chain_id)
+    {
+        validation.error(
+            CONFIG_FILENAME,
+            "network.message_recorder",
+            "is only allowed on mainnet in debug builds",
+        );
+    }
+}
+
+fn load_validator_key(
+    dir: &Path,
+    config: &Config,
+    validation: &mut ConfigValidation,
+) -> Option<std::sync::Arc<dyn ValidatorSigner>> {
+    let path = dir.join(&config.validator_key_file);
+    // Nodes which aren't validators don't have the key.
+    if !path.exists() {
+        return None;
+    }
+    match InMemoryValidatorSigner::from_file(&path) {
+        Ok(signer) => Some(std::sync::Arc::new(signer)),
+        Err(err) => {
+            validation.error(
+                &config.validator_key_file,
+                "",
+                format!("failed to load the validator key: {}", err),
+            );
+            None
+        }
+    }
+}
+
+fn load_node_key(
+    dir: &Path,
+    config: &Config,
+    validation: &mut ConfigValidation,
+) -> Option<KeyFile> {
+    match NodeKeyFile::from_file(&dir.join(&config.node_key_file)) {
+        Ok(key) => Some(key.into()),
+        Err(err) => {
+            validation.error(
+                &config.node_key_file,
+                "",
+                format!("failed to load the node key: {}", err),
+            );
+            None
+        }
+    }
+}
+
+fn load_genesis(dir: &Path, config: &Config, validation: &mut ConfigValidation) -> Option<Genesis> {
+    let genesis_path = dir.join(&config.genesis_file);
+    let genesis = match &config.genesis_records_file {
+        Some(records_file) => {
+            if let Err(err) = GenesisConfig::from_file(&genesis_path) {
+                validation.error(&config.genesis_file, "", format!("{:#}", err));
+                return None;
+            }
+            if !dir.join(records_file).exists() {
+                validation.error(CONFIG_FILENAME, "genesis_records_file", "file doesn't exist");
+                return None;
+            }
+            Genesis::from_files(
+                &genesis_path,
+                dir.join(records_file),
+                GenesisValidationMode::UnsafeFast,
+            )
+        }
+        None => {
+            let genesis = std::fs::read(&genesis_path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<Genesis>(&bytes)?));
+            match genesis {
+                // Records would be streamed from a file which doesn't exist.
+                Ok(genesis) if genesis.records.as_ref().is_empty() => {
+                    validation.error(&config.genesis_file, "records", "genesis has no records");
+                    return None;
+                }
+                Ok(genesis) => genesis,
+                Err(err) => {
+                    validation.error(
+                        &config.genesis_file,
+                        "",
+                        format!("failed to read the genesis: {:#}", err),
+                    );
+                    return None;
+                }
+            }
+        }
+    };
+    for error in genesis_validation_errors(&genesis) {
+        validation.error(&config.genesis_file, "", error);
+    }
+    Some(genesis)
+}
+
+/// A config field which differs from its default.
+#[derive(Debug, PartialEq)]
+pub struct ConfigDiff {
+    pub path: String,
+    /// `None` if the field isn't present in the default config.
+    pub default: Option<Value>,
+    /// `None` if the field isn't present in the config.
+    pub value: Option<Value>,
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "<missing>".to_string(),
+        };
+        write!(f, "{}: {} (default: {})", self.path, show(&self.value), show(&self.default))
+    }
+}
+
+/// Lists the fields of the config which differ from the defaults `neard init`
+/// uses for the given chain.
+pub fn diff_against_defaults(config: &Config, chain_id: &str) -> Vec<ConfigDiff> {
+    let default = serde_json::to_value(Config::default_for_chain(chain_id)).unwrap();
+    let config = serde_json::to_value(config).unwrap();
+    let mut diffs = vec![];
+    diff_values(String::new(), Some(&default), Some(&config), &mut diffs);
+    diffs
+}
+
+fn diff_values(
+    path: String,
+    default: Option<&Value>,
+    value: Option<&Value>,
+    diffs: &mut Vec<ConfigDiff>,
+) {
+    match (default, value) {
+        (Some(Value::Object(default)), Some(Value::Object(value))) => {
+            let mut keys: Vec<&String> = default.keys().chain(value.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                diff_values(path, default.get(key), value.get(key), diffs);
+            }
+        }
+        (default, value) if default != value => {
+            diffs.push(ConfigDiff { path, default: default.cloned(), value: value.cloned() })
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_configs;
+
+    fn init_localnet(dir: &Path) {
+        init_configs(
+            dir,
+            Some("localnet"),
+            None,
+            Some("seed"),
+            1,
+            false,
+            None,
+            false,
+            None,
+            false,
+            None,
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+    }
+
+    fn problem_paths(validation: &ConfigValidation) -> Vec<(Severity, &str)> {
+        validation
+            .problems
+            .iter()
+            .map(|problem| (problem.severity, problem.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_home_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        init_localnet(dir.path());
+        let validation = validate_home_dir(dir.path());
+        assert!(validation.problems.is_empty(), "{:?}", validation.problems);
+        assert_eq!(validation.chain_id.as_deref(), Some("localnet"));
+        assert_eq!(diff_against_defaults(&validation.config.unwrap(), "localnet"), vec![]);
+    }
+
+    #[test]
+    fn test_all_problems_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        init_localnet(dir.path());
+        let config_path = dir.path().join(CONFIG_FILENAME);
+        let mut config: Value =
+            serde_json::from_slice(&std::fs::read(&config_path).unwrap()).unwrap();
+        config["network"]["boot_nodes"] = "not a peer".into();
+        config["network"]["safe_set_size"] = 0.into();
+        config["gc_blocks_limit"] = 0.into();
+        config["tracked_shards"] = vec![0, 7].into();
+        config["no_such_option"] = true.into();
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+
+        let validation = validate_home_dir(dir.path());
+        assert!(validation.has_errors());
+        let mut paths = problem_paths(&validation);
+        paths.sort_by_key(|(_, path)| *path);
+        assert_eq!(
+            paths,
+            [
+                (Severity::Error, "gc_blocks_limit"),
+                (Severity::Error, "network.boot_nodes"),
+                (Severity::Warning, "no_such_option"),
+                (Severity::Warning, "tracked_shards[1]"),
+            ]
+        );
+
+        // Once the network config can be built its own checks run as well.
+        config["network"]["boot_nodes"] = "".into();
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        let validation = validate_home_dir(dir.path());
+        assert!(problem_paths(&validation).contains(&(Severity::Error, "network.safe_set_size")));
+
+        let diffs = diff_against_defaults(&validation.config.unwrap(), "localnet");
+        let diff_paths: Vec<_> = diffs.iter().map(|diff| diff.path.as_str()).collect();
+        assert_eq!(diff_paths, ["gc_blocks_limit", "network.safe_set_size", "tracked_shards"]);
+    }
+
+    #[test]
+    fn test_tracked_accounts_and_shards_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        init_localnet(dir.path());
+        let config_path = dir.path().join(CONFIG_FILENAME);
+        let mut config: Value =
+            serde_json::from_slice(&std::fs::read(&config_path).unwrap()).unwrap();
+        config["tracked_accounts"] = vec!["test0"].into();
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        assert!(validate_home_dir(dir.path()).problems.is_empty());
+        assert!(crate::load_config(dir.path(), GenesisValidationMode::Full).is_ok());
+
+        config["tracked_shards"] = vec![0].into();
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        let validation = validate_home_dir(dir.path());
+        assert_eq!(problem_paths(&validation), [(Severity::Error, "tracked_accounts")]);
+        assert!(crate::load_config(dir.path(), GenesisValidationMode::Full).is_err());
+    }
+
+    #[test]
+    fn test_pinned_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        init_localnet(dir.path());
+        let config_path = dir.path().join(CONFIG_FILENAME);
+        let mut config: Value =
+            serde_json::from_slice(&std::fs::read(&config_path).unwrap()).unwrap();
+        let peer_id = "ed25519:86EtEy7epneKyrcJwSWP7zsisTkfDRH5CFVszt4qiQYw";
+        config["network"]["pinned_peers"] = vec![format!("{}@10.0.0.1:24567", peer_id)].into();
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        assert!(validate_home_dir(dir.path()).problems.is_empty());
+
+        config["network"]["blacklist"] = vec!["10.0.0.1"].into();
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        let validation = validate_home_dir(dir.path());
+        assert_eq!(problem_paths(&validation), [(Severity::Error, "network.pinned_peers")]);
+        // The node refuses to start as well.
+        let near_config = crate::load_config(dir.path(), GenesisValidationMode::Full).unwrap();
+        assert!(near_config.network_config.verify().is_err());
+
+        config["network"]["blacklist"] = Value::Array(vec![]);
+        config["network"]["pinned_peers"] = vec![peer_id].into();
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        let validation = validate_home_dir(dir.path());
+        assert_eq!(problem_paths(&validation), [(Severity::Error, "network.pinned_peers")]);
+    }
+
+    #[test]
+    fn test_duplicate_blacklist_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        init_localnet(dir.path());
+        let config_path = dir.path().join(CONFIG_FILENAME);
+        let mut config: Value =
+            serde_json::from_slice(&std::fs::read(&config_path).unwrap()).unwrap();
+        config["network"]["blacklist"] = serde_json::json!({
+            "abuse": {"entries": ["10.0.0.1", "10.0.0.2:24567"]},
+            "maintenance": {"enabled": false, "entries": ["10.0.0.2:24567"]},
+        });
+        std::fs::write(&config_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        let validation = validate_home_dir(dir.path());
+        assert_eq!(problem_paths(&validation), [(Severity::Warning, "network.blacklist")]);
+        let near_config = crate::load_config(dir.path(), GenesisValidationMode::Full).unwrap();
+        assert_eq!(near_config.network_config.blacklist.len(), 2);
+        assert!(!near_config.network_config.blacklist[1].enabled);
+    }
+
+    #[test]
+    fn test_message_recorder_on_mainnet() {
+        let mut config = Config::default();
+        config.network.message_recorder = Some(crate::config::MessageRecorder {
+            path: "messages.log".into(),
+            max_file_bytes: 1 << 20,
+            max_files: 2,
+        });
+        for (chain_id, allowed) in [("testnet", true), ("mainnet", cfg!(debug_assertions))] {
+            let genesis_config =
+                GenesisConfig { chain_id: chain_id.to_string(), ..Default::default() };
+            let mut validation = ConfigValidation::default();
+            validate_config_against_genesis(&config, &genesis_config, &mut validation);
+            let expected: &[_] =
+                if allowed { &[] } else { &[(Severity::Error, "network.message_recorder")] };
+            assert_eq!(problem_paths(&validation), expected, "{}", chain_id);
+        }
+    }
+
+    #[test]
+    fn test_max_message_sizes() {
+        let mut config = Config::default();
+        config.network.max_message_sizes.insert("Block".to_string(), 1000);
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert!(validation.problems.is_empty());
+
+        config.network.max_message_sizes.insert("Blocks".to_string(), 1000);
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert_eq!(problem_paths(&validation), [(Severity::Error, "network.max_message_sizes")]);
+    }
+
+    #[test]
+    fn test_account_filter_bits_per_key() {
+        let mut config = Config::default();
+        config.account_filter.bits_per_key = 0;
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert!(validation.problems.is_empty());
+
+        config.account_filter.enabled = true;
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert_eq!(problem_paths(&validation), [(Severity::Error, "account_filter.bits_per_key")]);
+    }
+
+    #[cfg(feature = "json_rpc")]
+    #[test]
+    fn test_rpc_listeners() {
+        let listener = |addr: &str| near_jsonrpc::RpcListenerConfig {
+            addr: addr.to_string(),
+            ..Default::default()
+        };
+        let mut config = Config::default();
+        let rpc = config.rpc.as_mut().unwrap();
+        rpc.addr = "127.0.0.1:3030".to_string();
+        rpc.listeners = vec![listener("10.0.0.1:3030"), listener("127.0.0.1:3031")];
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert!(validation.problems.is_empty(), "{:?}", validation.problems);
+
+        // An unspecified address covers every interface.
+        config.rpc.as_mut().unwrap().listeners.push(listener("0.0.0.0:3031"));
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert_eq!(problem_paths(&validation), [(Severity::Error, "rpc.listeners")]);
+        config.rpc.as_mut().unwrap().listeners.pop();
+
+        // The debug server can't share a port with the others either.
+        let rpc = config.rpc.as_mut().unwrap();
+        rpc.debug_addr = Some("127.0.0.1:3030".to_string());
+        rpc.debug_auth = Some(near_jsonrpc::DebugAuthConfig::BearerToken("secret".to_string()));
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert_eq!(problem_paths(&validation), [(Severity::Error, "rpc.listeners")]);
+
+        // The credentials are only required by the monitoring servers.
+        config.rpc.as_mut().unwrap().debug_addr = None;
+        let mut validation = ConfigValidation::default();
+        validate_config(&config, &mut validation);
+        assert_eq!(problem_paths(&validation), [(Severity::Warning, "rpc.debug_auth")]);
+    }
+
+    #[test]
+    fn test_chain_defaults() {
+        let diffs = diff_against_defaults(&Config::default_for_chain("mainnet"), "testnet");
+        let diff_paths: Vec<_> = diffs.iter().map(|diff| diff.path.as_str()).collect();
+        assert_eq!(diff_paths, ["telemetry.endpoints"]);
+    }
+}
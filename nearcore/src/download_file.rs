@@ -1,7 +1,8 @@
 use hyper::body::HttpBody;
 use indicatif::{ProgressBar, ProgressStyle};
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 #[derive(thiserror::Error, Debug)]
 pub enum FileDownloadError {
@@ -19,34 +20,124 @@ pub enum FileDownloadError {
     RenameError(PathBuf, PathBuf, #[source] std::io::Error),
     #[error("Invalid URI")]
     UriError(#[from] hyper::http::uri::InvalidUri),
+    #[error("Server responded with {0}")]
+    HttpStatusError(hyper::StatusCode),
+    #[error("Checksum file isn't in the `sha256sum` format")]
+    InvalidChecksumFile,
+    #[error("SHA-256 checksum of the download is {actual}, expected {expected}")]
+    ChecksumMismatch { expected: String, actual: String },
     #[error("Failed to remove temporary file: {0}. Download previously failed")]
     RemoveTemporaryFileError(std::io::Error, #[source] Box<FileDownloadError>),
 }
 
-pub(crate) fn run_download_file(url: &str, path: &Path) -> Result<(), FileDownloadError> {
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async { download_file(url, path).await })
+pub(crate) fn run_download_file(
+    url: &str,
+    path: &Path,
+    expected_sha256: Option<&str>,
+) -> Result<String, FileDownloadError> {
+    run_async(download_file(url, path, expected_sha256))
 }
 
-/// Downloads resource at given `uri` and saves it to `file`.  On failure,
-/// `file` may be left in inconsistent state (i.e. may contain partial data).
-///
-/// If the downloaded file is an XZ stream (i.e. starts with the XZ 6-byte magic
-/// number), transparently decompresses the file as it’s being downloaded.
-async fn download_file_impl(
-    uri: hyper::Uri,
-    path: &std::path::Path,
-    file: tokio::fs::File,
-) -> Result<(), FileDownloadError> {
-    let mut out = AutoXzDecoder::new(path, file);
-    let https_connector = hyper_tls::HttpsConnector::new();
-    let client = hyper::Client::builder().build::<_, hyper::Body>(https_connector);
-    let mut resp = client.get(uri).await.map_err(FileDownloadError::HttpError)?;
+/// Downloads the SHA-256 checksum published for the resource at `url`, at
+/// `url` with `.sha256` appended, in the format `sha256sum` outputs.  Returns
+/// `None` if there's none.
+pub(crate) fn run_download_checksum(url: &str) -> Result<Option<String>, FileDownloadError> {
+    run_async(download_checksum(url))
+}
+
+fn run_async<T>(future: impl std::future::Future<Output = T>) -> T {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+}
+
+fn http_client() -> hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>> {
+    hyper::Client::builder().build::<_, hyper::Body>(hyper_tls::HttpsConnector::new())
+}
+
+async fn download_checksum(url: &str) -> Result<Option<String>, FileDownloadError> {
+    let uri: hyper::Uri = format!("{}.sha256", url).parse()?;
+    let resp = http_client().get(uri).await.map_err(FileDownloadError::HttpError)?;
+    match resp.status() {
+        hyper::StatusCode::OK => (),
+        hyper::StatusCode::NOT_FOUND => return Ok(None),
+        status => return Err(FileDownloadError::HttpStatusError(status)),
+    }
+    let body =
+        hyper::body::to_bytes(resp.into_body()).await.map_err(FileDownloadError::HttpError)?;
+    let checksum = String::from_utf8_lossy(&body).split_whitespace().next().map(str::to_lowercase);
+    match checksum {
+        Some(checksum) if checksum.len() == 64 && hex::decode(&checksum).is_ok() => {
+            Ok(Some(checksum))
+        }
+        _ => Err(FileDownloadError::InvalidChecksumFile),
+    }
+}
+
+/// Path the raw data is downloaded to before it's verified and decompressed,
+/// kept when the download fails so that the next attempt resumes it.
+fn partial_download_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Feeds the data already at `path` into the hasher and returns its length.
+fn hash_existing_data(path: &Path, hasher: &mut Sha256) -> Result<u64, FileDownloadError> {
+    let mut file = std::fs::File::open(path).map_err(FileDownloadError::OpenError)?;
+    let mut buffer = vec![0u8; 64 << 10];
+    let mut len = 0;
+    loop {
+        let read = std::io::Read::read(&mut file, &mut buffer)
+            .map_err(|e| FileDownloadError::WriteError(path.to_path_buf(), e))?;
+        if read == 0 {
+            break Ok(len);
+        }
+        hasher.update(&buffer[..read]);
+        len += read as u64;
+    }
+}
+
+/// Downloads resource at given `uri` to `path` as is, resuming from the data
+/// already there if the server supports range requests.  Returns the hex
+/// encoded SHA-256 of the whole data.
+async fn download_raw(uri: hyper::Uri, path: &Path) -> Result<String, FileDownloadError> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(FileDownloadError::OpenError)?;
+    let mut hasher = Sha256::new();
+    let mut offset = hash_existing_data(path, &mut hasher)?;
+
+    let mut request = hyper::Request::get(uri);
+    if offset > 0 {
+        request = request.header(hyper::header::RANGE, format!("bytes={}-", offset));
+    }
+    let request = request.body(hyper::Body::empty()).expect("the request is valid");
+    let mut resp = http_client().request(request).await.map_err(FileDownloadError::HttpError)?;
+    match resp.status() {
+        hyper::StatusCode::PARTIAL_CONTENT if offset > 0 => {
+            tracing::info!(target: "near", "Resuming the download at {} bytes", offset);
+        }
+        hyper::StatusCode::OK => {
+            if offset > 0 {
+                tracing::info!(target: "near", "The server can't resume the download, restarting it");
+                file.set_len(0)
+                    .await
+                    .map_err(|e| FileDownloadError::WriteError(path.to_path_buf(), e))?;
+                hasher = Sha256::new();
+                offset = 0;
+            }
+        }
+        // All of it was downloaded already.
+        hyper::StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+            return Ok(hex::encode(hasher.finalize()));
+        }
+        status => return Err(FileDownloadError::HttpStatusError(status)),
+    }
+
     let bar = if let Some(file_size) = resp.size_hint().upper() {
-        let bar = ProgressBar::new(file_size);
+        let bar = ProgressBar::new(offset + file_size);
         bar.set_style(
             ProgressStyle::default_bar().template(
                 "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} [{bytes_per_sec}] ({eta})"
@@ -68,38 +159,90 @@ async fn download_file_impl(
         ProgressBar::hidden()
     };
 
+    bar.set_position(offset);
     while let Some(next_chunk_result) = resp.data().await {
         let next_chunk = next_chunk_result.map_err(FileDownloadError::HttpError)?;
-        out.write_all(next_chunk.as_ref()).await?;
+        file.write_all(next_chunk.as_ref())
+            .await
+            .map_err(|e| FileDownloadError::WriteError(path.to_path_buf(), e))?;
+        hasher.update(next_chunk.as_ref());
         bar.inc(next_chunk.len() as u64);
     }
-    out.finish().await?;
+    file.flush().await.map_err(|e| FileDownloadError::WriteError(path.to_path_buf(), e))?;
     bar.finish();
-    Ok(())
+    Ok(hex::encode(hasher.finalize()))
 }
 
-/// Downloads a resource at given `url` and saves it to `path`.  On success, if
-/// file at `path` exists it will be overwritten.  On failure, file at `path` is
-/// left unchanged (if it exists).
-async fn download_file(url: &str, path: &Path) -> Result<(), FileDownloadError> {
+/// Copies the data at `from` to `file`.
+///
+/// If the data is an XZ stream (i.e. starts with the XZ 6-byte magic number),
+/// transparently decompresses it.
+async fn decompress_file(
+    from: &Path,
+    path: &Path,
+    file: tokio::fs::File,
+) -> Result<(), FileDownloadError> {
+    let mut input = tokio::fs::File::open(from).await.map_err(FileDownloadError::OpenError)?;
+    let mut out = AutoXzDecoder::new(path, file);
+    let mut buffer = vec![0u8; 64 << 10];
+    loop {
+        let read = input
+            .read(&mut buffer)
+            .await
+            .map_err(|e| FileDownloadError::WriteError(from.to_path_buf(), e))?;
+        if read == 0 {
+            break;
+        }
+        out.write_all(&buffer[..read]).await?;
+    }
+    out.finish().await
+}
+
+/// Downloads a resource at given `url` and saves it to `path`, checking it
+/// against `expected_sha256` if given.  Returns the hex encoded SHA-256 of
+/// the data downloaded, before decompression.
+///
+/// On success, if file at `path` exists it will be overwritten.  On failure,
+/// file at `path` is left unchanged (if it exists).  A download interrupted
+/// midway is kept next to `path` and resumed by the next call.
+async fn download_file(
+    url: &str,
+    path: &Path,
+    expected_sha256: Option<&str>,
+) -> Result<String, FileDownloadError> {
     let uri = url.parse()?;
+    let part_path = partial_download_path(path);
+    let sha256 = download_raw(uri, &part_path).await?;
+    if let Some(expected) = expected_sha256 {
+        if !expected.eq_ignore_ascii_case(&sha256) {
+            // Corrupted, there's no point in resuming it.
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Err(FileDownloadError::ChecksumMismatch {
+                expected: expected.to_string(),
+                actual: sha256,
+            });
+        }
+    }
 
     let (tmp_file, tmp_path) = {
         let tmp_dir = path.parent().unwrap_or(Path::new("."));
         tempfile::NamedTempFile::new_in(tmp_dir).map_err(FileDownloadError::OpenError)?.into_parts()
     };
 
-    let result = match download_file_impl(uri, &tmp_path, tokio::fs::File::from_std(tmp_file)).await
-    {
-        Err(err) => Err((tmp_path, err)),
-        Ok(()) => tmp_path.persist(path).map_err(|e| {
-            let from = e.path.to_path_buf();
-            let to = path.to_path_buf();
-            (e.path, FileDownloadError::RenameError(from, to, e.error))
-        }),
-    };
-
-    result.map_err(|(tmp_path, err)| match tmp_path.close() {
+    let result =
+        match decompress_file(&part_path, &tmp_path, tokio::fs::File::from_std(tmp_file)).await {
+            Err(err) => Err((tmp_path, err)),
+            Ok(()) => tmp_path.persist(path).map_err(|e| {
+                let from = e.path.to_path_buf();
+                let to = path.to_path_buf();
+                (e.path, FileDownloadError::RenameError(from, to, e.error))
+            }),
+        };
+    // The data is complete either way, if it can't be decompressed it won't
+    // be any better the next time.
+    let _ = tokio::fs::remove_file(&part_path).await;
+
+    result.map(|()| sha256).map_err(|(tmp_path, err)| match tmp_path.close() {
         Ok(()) => err,
         Err(close_err) => FileDownloadError::RemoveTemporaryFileError(close_err, Box::new(err)),
     })
@@ -289,9 +432,9 @@ mod tests {
 
         let tmp_file = tempfile::NamedTempFile::new().unwrap();
 
-        let res = download_file(&format!("http://localhost:{}", port), tmp_file.path())
+        let res = download_file(&format!("http://localhost:{}", port), tmp_file.path(), None)
             .await
-            .map(|()| std::fs::read(tmp_file.path()).unwrap());
+            .map(|_| std::fs::read(tmp_file.path()).unwrap());
 
         match (res, expected) {
             (Ok(res), Ok(expected)) => assert_eq!(&res, expected),
@@ -322,6 +465,70 @@ mod tests {
         check_file_download(payload, Err("Failed to decompress XZ stream: lzma data error")).await;
     }
 
+    /// Serves the payload, honouring the `Range` header.
+    fn serve_with_ranges(payload: &'static [u8]) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::task::spawn(async move {
+            let make_svc = make_service_fn(move |_conn| async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| async move {
+                    let offset = request
+                        .headers()
+                        .get(hyper::header::RANGE)
+                        .and_then(|range| range.to_str().ok())
+                        .and_then(|range| range.strip_prefix("bytes="))
+                        .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+                    let response = match offset {
+                        None => Response::new(Body::from(payload)),
+                        Some(offset) if offset >= payload.len() => Response::builder()
+                            .status(hyper::StatusCode::RANGE_NOT_SATISFIABLE)
+                            .body(Body::empty())
+                            .unwrap(),
+                        Some(offset) => Response::builder()
+                            .status(hyper::StatusCode::PARTIAL_CONTENT)
+                            .body(Body::from(&payload[offset..]))
+                            .unwrap(),
+                    };
+                    Ok::<_, Infallible>(response)
+                }))
+            });
+            let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+        format!("http://localhost:{}", port)
+    }
+
+    #[tokio::test]
+    async fn test_file_download_resume_and_verify() {
+        let payload = b"A quick brown fox jumps over a lazy dog";
+        let expected_sha256 = hex::encode(Sha256::digest(payload));
+        let url = serve_with_ranges(payload);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("genesis.json");
+
+        // Interrupted after the first few bytes.
+        std::fs::write(partial_download_path(&path), &payload[..10]).unwrap();
+        let sha256 = download_file(&url, &path, Some(&expected_sha256)).await.unwrap();
+        assert_eq!(sha256, expected_sha256);
+        assert_eq!(std::fs::read(&path).unwrap(), payload);
+        assert!(!partial_download_path(&path).exists());
+
+        // Downloaded completely but not moved in place.
+        std::fs::write(partial_download_path(&path), payload).unwrap();
+        download_file(&url, &path, Some(&expected_sha256)).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), payload);
+
+        // Corrupted, the file is left unchanged.
+        std::fs::write(&path, b"old").unwrap();
+        std::fs::write(partial_download_path(&path), b"Another").unwrap();
+        let err = download_file(&url, &path, Some(&expected_sha256)).await.unwrap_err();
+        assert!(matches!(err, FileDownloadError::ChecksumMismatch { .. }), "{}", err);
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert!(!partial_download_path(&path).exists());
+    }
+
     fn auto_xz_test_write_file(
         buffer: &[u8],
         chunk_size: usize,
@@ -1,27 +1,33 @@
 pub use crate::config::{init_configs, load_config, load_test_config, NearConfig, NEAR_BASE};
 use crate::migrations::migrate_30_to_31;
-pub use crate::runtime::NightshadeRuntime;
+pub use crate::precompile_contracts::{
+    precompile_contracts, PrecompileContractsOpts, PrecompileContractsStats,
+};
+pub use crate::runtime::{build_state_for_split_shards, NightshadeRuntime};
 pub use crate::shard_tracker::TrackedConfig;
 use actix::{Actor, Addr, Arbiter};
 use actix_rt::ArbiterHandle;
 use actix_web;
 use anyhow::Context;
 use near_chain::ChainGenesis;
+pub use near_client::shutdown_report;
 #[cfg(feature = "test_features")]
 use near_client::AdversarialControls;
 use near_client::{start_client, start_view_client, ClientActor, ViewClientActor};
 use near_network::routing::start_routing_table_actor;
 use near_network::test_utils::NetworkRecipient;
 use near_network::PeerManagerActor;
+use near_primitives::block::Tip;
 use near_primitives::network::PeerId;
-use near_primitives::version::DbVersion;
+use near_primitives::version::{DbVersion, Version};
 #[cfg(feature = "rosetta_rpc")]
 use near_rosetta_rpc::start_rosetta_rpc;
 #[cfg(feature = "performance_stats")]
 use near_rust_allocator_proxy::reset_memory_usage_max;
 use near_store::db::RocksDB;
 use near_store::migrations::{
-    get_store_version, migrate_28_to_29, migrate_29_to_30, set_store_version,
+    get_store_version, migrate_28_to_29, migrate_29_to_30, set_store_neard_version,
+    set_store_version,
 };
 use near_store::{create_store, create_store_with_config, DBCol, Store};
 use near_telemetry::TelemetryActor;
@@ -33,9 +39,11 @@ use tracing::{error, info, trace};
 
 pub mod append_only_map;
 pub mod config;
+pub mod config_validate;
 mod download_file;
 mod metrics;
 pub mod migrations;
+mod precompile_contracts;
 mod runtime;
 mod shard_tracker;
 
@@ -107,6 +115,61 @@ fn create_db_checkpoint(path: &Path, near_config: &NearConfig) -> anyhow::Result
     Ok(checkpoint_path)
 }
 
+/// The database was migrated by a newer neard to a version this one can't
+/// open.
+#[derive(thiserror::Error, Debug)]
+pub struct UnsupportedDbVersion {
+    pub path: PathBuf,
+    pub db_version: DbVersion,
+    /// The latest version this neard supports.
+    pub supported_version: DbVersion,
+    /// The neard which last opened the database, if it recorded itself.
+    pub neard_version: Option<Version>,
+    /// The database migration snapshot and its version, if one exists.
+    pub migration_snapshot: Option<(PathBuf, DbVersion)>,
+}
+
+impl std::fmt::Display for UnsupportedDbVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "The database at '{}' has version {}, this neard supports versions up to {}: \
+             a newer neard migrated it.",
+            self.path.display(),
+            self.db_version,
+            self.supported_version
+        )?;
+        match &self.neard_version {
+            Some(version) => writeln!(
+                f,
+                "It was last opened by neard {} (build {}), run that release or a newer one.",
+                version.version, version.build
+            )?,
+            None => {
+                writeln!(f, "Run a neard release supporting database version {}.", self.db_version)?
+            }
+        }
+        match &self.migration_snapshot {
+            Some((path, version)) if *version <= self.supported_version => write!(
+                f,
+                "The database migration snapshot at '{}' has version {}, which this neard can \
+                 open: to go back to it, replace the contents of '{}' with the snapshot.",
+                path.display(),
+                version,
+                self.path.display()
+            ),
+            Some((path, version)) => write!(
+                f,
+                "The database migration snapshot at '{}' has version {}, which this neard can't \
+                 open either.",
+                path.display(),
+                version
+            ),
+            None => write!(f, "There is no database migration snapshot from before the migration."),
+        }
+    }
+}
+
 /// Function checks current version of the database and applies migrations to the database.
 fn apply_store_migrations(path: &Path, near_config: &NearConfig) -> anyhow::Result<()> {
     let db_version = get_store_version(&path)?;
@@ -114,11 +177,22 @@ fn apply_store_migrations(path: &Path, near_config: &NearConfig) -> anyhow::Resu
         return Ok(());
     }
 
-    anyhow::ensure!(
-        db_version < near_primitives::version::DB_VERSION,
-        "DB version {db_version} is created by a newer version of neard, \
-         please update neard"
-    );
+    if db_version > near_primitives::version::DB_VERSION {
+        let snapshot_path = db_checkpoint_path(path, near_config);
+        let migration_snapshot = if store_path_exists(&snapshot_path) {
+            get_store_version(&snapshot_path).ok().map(|version| (snapshot_path, version))
+        } else {
+            None
+        };
+        return Err(UnsupportedDbVersion {
+            path: path.to_path_buf(),
+            db_version,
+            supported_version: near_primitives::version::DB_VERSION,
+            neard_version: RocksDB::get_metadata(path)?.neard_version,
+            migration_snapshot,
+        }
+        .into());
+    }
 
     // For given db version, latest neard release which supported that version.
     // If you’re removing support for a database version from neard put an entry
@@ -179,6 +253,22 @@ fn apply_store_migrations(path: &Path, near_config: &NearConfig) -> anyhow::Resu
         info!(target: "near", "Migrate DB from version 30 to 31");
         migrate_30_to_31(path, &near_config);
     }
+    if db_version <= 31 {
+        // version 31 => 32: add DBCol::BlockProcessingLog
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        // Nevertheless need to bump db version, because db_version 31 binary can't open db_version 32 db
+        info!(target: "near", "Migrate DB from version 31 to 32");
+        let store = create_store(path);
+        set_store_version(&store, 32);
+    }
+    if db_version <= 32 {
+        // version 32 => 33: add DBCol::Misbehavior
+        // Does not need to do anything since open db with option `create_missing_column_families`
+        // Nevertheless need to bump db version, because db_version 32 binary can't open db_version 33 db
+        info!(target: "near", "Migrate DB from version 32 to 33");
+        let store = create_store(path);
+        set_store_version(&store, 33);
+    }
 
     #[cfg(feature = "nightly_protocol")]
     {
@@ -228,6 +318,7 @@ fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> anyhow::
     if !store_exists {
         set_store_version(&store, near_primitives::version::DB_VERSION);
     }
+    set_store_neard_version(&store, &near_config.client_config.version)?;
 
     // Check if the storage is an archive and if it is make sure we are too.
     // If the store is not marked as archive but we are an archival node that is
@@ -245,12 +336,21 @@ fn init_and_migrate_store(home_dir: &Path, near_config: &NearConfig) -> anyhow::
         update.commit()?;
     }
 
+    let store_is_clone: bool =
+        store.get_ser(DBCol::BlockMisc, near_store::db::IS_CLONE_KEY)?.unwrap_or_default();
+    anyhow::ensure!(
+        !store_is_clone || near_config.allow_cloned_db,
+        "The database is a clone made by `neard database clone`, run with --allow-cloned-db \
+         if the node is meant to use it."
+    );
+
     Ok(store)
 }
 
 pub struct NearNode {
     pub client: Addr<ClientActor>,
     pub view_client: Addr<ViewClientActor>,
+    pub network: Addr<PeerManagerActor>,
     pub arbiters: Vec<ArbiterHandle>,
     pub rpc_servers: Vec<(&'static str, actix_web::dev::Server)>,
 }
@@ -268,6 +368,22 @@ pub fn start_with_config_and_synchronization(
 ) -> anyhow::Result<NearNode> {
     let store = init_and_migrate_store(home_dir, &config)?;
 
+    if !near_crypto::verification_pool::init(config.config.signature_verification_threads) {
+        tracing::debug!(target: "near", "The signature verification threads are running already");
+    }
+    let shutdown_report_path = home_dir.join(&config.config.shutdown_report_file);
+    if let Err(err) = near_client::shutdown_report::install(
+        &shutdown_report_path,
+        config.client_config.version.clone(),
+    ) {
+        tracing::warn!(
+            target: "near",
+            path = %shutdown_report_path.display(),
+            %err,
+            "Failed to open the shutdown report file, no report will be written"
+        );
+    }
+
     let runtime = Arc::new(NightshadeRuntime::with_config(
         home_dir,
         store.clone(),
@@ -275,6 +391,20 @@ pub fn start_with_config_and_synchronization(
         config.client_config.trie_viewer_state_size_limit,
         config.client_config.max_gas_burnt_view,
     ));
+    runtime.start_account_filters();
+    if config.precompile_contracts {
+        precompile_contracts::spawn_precompile_contracts(
+            home_dir,
+            &config,
+            store.clone(),
+            runtime.clone(),
+        );
+    }
+
+    near_client::spawn_disk_space_monitor(
+        get_store_path(home_dir),
+        config.client_config.disk_space_monitor.clone(),
+    );
 
     let telemetry = TelemetryActor::new(config.telemetry_config.clone()).start();
     let chain_genesis = ChainGenesis::from(&config.genesis);
@@ -313,6 +443,11 @@ pub fn start_with_config_and_synchronization(
     let client_actor1 = client_actor.clone().recipient();
     let view_client1 = view_client.clone().recipient();
     config.network_config.verify().with_context(|| "start_with_config")?;
+    if config.network_config.message_recorder.is_some()
+        && !crate::config::message_recorder_allowed(&config.genesis.config.chain_id)
+    {
+        anyhow::bail!("network.message_recorder is only allowed on mainnet in debug builds");
+    }
     let network_config = config.network_config;
     let routing_table_addr =
         start_routing_table_actor(PeerId::new(network_config.public_key.clone()), store.clone());
@@ -356,7 +491,7 @@ pub fn start_with_config_and_synchronization(
         ));
     }
 
-    network_adapter.set_recipient(network_actor.recipient());
+    network_adapter.set_recipient(network_actor.clone().recipient());
 
     rpc_servers.shrink_to_fit();
 
@@ -369,6 +504,7 @@ pub fn start_with_config_and_synchronization(
     Ok(NearNode {
         client: client_actor,
         view_client,
+        network: network_actor,
         rpc_servers,
         arbiters: vec![client_arbiter_handle, arbiter.handle()],
     })
@@ -516,3 +652,78 @@ pub fn recompress_storage(home_dir: &Path, opts: RecompressOpts) -> anyhow::Resu
     info!(target: "recompress", dest_dir = ?opts.dest_dir, "Database recompressed");
     Ok(())
 }
+
+/// Clones the database of the home directory into `dest_dir` as a RocksDB
+/// checkpoint, which hard links the files of the database rather than copying
+/// them, and marks the clone so that nodes don't run with it by accident.
+/// Returns the head of the clone.
+///
+/// The node has to be stopped while the checkpoint is created, which takes
+/// seconds.  `dest_dir` must not exist and has to be on the file system of the
+/// database.
+pub fn clone_database(home_dir: &Path, dest_dir: &Path) -> anyhow::Result<Tip> {
+    let config_path = home_dir.join(config::CONFIG_FILENAME);
+    let config = config::Config::from_file(&config_path)
+        .map_err(|err| anyhow::anyhow!("{}: {}", config_path.display(), err))?;
+    let src_dir = home_dir.join(STORE_PATH);
+    anyhow::ensure!(
+        store_path_exists(&src_dir),
+        "{}: source storage doesn’t exist",
+        src_dir.display()
+    );
+    anyhow::ensure!(!dest_dir.exists(), "{}: directory already exists", dest_dir.display());
+    let dest_parent = match dest_dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    anyhow::ensure!(
+        same_file_system(&src_dir, dest_parent)?,
+        "{} isn’t on the file system of {}, so the database can’t be hard linked there.\n\
+         Clone it to a directory on the same file system, or take a file system snapshot \
+         of {} and copy it instead.",
+        dest_dir.display(),
+        src_dir.display(),
+        src_dir.display()
+    );
+    let db_version = get_store_version(&src_dir)?;
+    anyhow::ensure!(
+        db_version == near_primitives::version::DB_VERSION,
+        "{}: expected DB version {} but got {}",
+        src_dir.display(),
+        near_primitives::version::DB_VERSION,
+        db_version
+    );
+
+    let db = RocksDB::open(&src_dir, &config.store.clone().with_read_only(false)).with_context(
+        || format!("{}: can’t open the database, is the node running?", src_dir.display()),
+    )?;
+    info!(target: "near", src = %src_dir.display(), dest = %dest_dir.display(), "Cloning database");
+    db.checkpoint()?.create_checkpoint(dest_dir)?;
+    core::mem::drop(db);
+
+    let dest_store = create_store_with_config(dest_dir, &config.store.with_read_only(false));
+    let head: Tip = dest_store
+        .get_ser(DBCol::BlockMisc, near_store::HEAD_KEY)?
+        .with_context(|| format!("{}: the database has no head", src_dir.display()))?;
+    let mut update = dest_store.store_update();
+    update.set_ser(DBCol::BlockMisc, near_store::db::IS_CLONE_KEY, &true)?;
+    update.commit()?;
+    info!(target: "near", dest = %dest_dir.display(), height = head.height, "Database cloned");
+    Ok(head)
+}
+
+#[cfg(unix)]
+fn same_file_system(a: &Path, b: &Path) -> anyhow::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let dev = |path: &Path| {
+        fs::metadata(path)
+            .map(|metadata| metadata.dev())
+            .with_context(|| path.display().to_string())
+    };
+    Ok(dev(a)? == dev(b)?)
+}
+
+#[cfg(not(unix))]
+fn same_file_system(_a: &Path, _b: &Path) -> anyhow::Result<bool> {
+    Ok(true)
+}
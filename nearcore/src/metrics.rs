@@ -1,4 +1,7 @@
-use near_metrics::{try_create_histogram_vec, HistogramVec};
+use near_metrics::{
+    try_create_histogram_vec, try_create_int_counter_vec, try_create_int_gauge_vec, HistogramVec,
+    IntCounterVec, IntGaugeVec,
+};
 use once_cell::sync::Lazy;
 
 pub static APPLY_CHUNK_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
@@ -24,3 +27,21 @@ pub static SECONDS_PER_PETAGAS: Lazy<HistogramVec> = Lazy::new(|| {
     )
     .unwrap()
 });
+
+pub static ACCOUNT_FILTER_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    try_create_int_gauge_vec(
+        "near_account_filter_size_bytes",
+        "Memory taken by the bloom filter of the account ids of a shard",
+        &["shard_id"],
+    )
+    .unwrap()
+});
+
+pub static ACCOUNT_FILTER_SHORT_CIRCUITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    try_create_int_counter_vec(
+        "near_account_filter_short_circuits_total",
+        "Queries of accounts which don't exist on a shard answered by its account filter without reading the state",
+        &["shard_id"],
+    )
+    .unwrap()
+});
@@ -0,0 +1,431 @@
+//! Compilation of deployed contracts ahead of their first call.
+//!
+//! After an upgrade to a binary with a different VM, the first call to every
+//! contract has to compile it, which shows up as a latency spike.  The code
+//! here walks contracts deployed in the tracked shards and populates the
+//! compiled contract cache for the current VM kind and protocol version.
+//!
+//! Progress is checkpointed to a file in the home directory after every batch
+//! of contracts so that an interrupted run resumes where it stopped.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+
+use near_chain::{ChainStore, ChainStoreAccess, RuntimeAdapter};
+use near_primitives::contract::ContractCode;
+use near_primitives::trie_key::{trie_key_parsers, TrieKey};
+use near_primitives::types::{AccountId, BlockHeight, CompiledContractCache, ShardId};
+use near_primitives::version::ProtocolVersion;
+use near_store::{Store, StoreCompiledContractCache};
+use near_vm_runner::{precompile_contract, ContractPrecompilatonResult};
+
+use crate::{NearConfig, NightshadeRuntime};
+
+/// Name of the file in the home directory which records progress of an
+/// interrupted run.
+const PROGRESS_FILENAME: &str = "precompile_contracts_progress.json";
+
+/// Number of contracts compiled in parallel between progress checkpoints.
+const BATCH_SIZE: usize = 64;
+
+pub struct PrecompileContractsOpts {
+    /// Shards to compile contracts of.  If empty, all tracked shards are
+    /// processed.
+    pub shard_ids: Vec<ShardId>,
+    /// If set, only contracts of these accounts are compiled.
+    pub account_ids: Option<Vec<AccountId>>,
+    /// Ignore progress recorded by a previous, interrupted run.
+    pub restart: bool,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct PrecompileContractsStats {
+    /// Contracts which were compiled and put into the cache.
+    pub compiled: u64,
+    /// Contracts which already were in the cache.
+    pub already_cached: u64,
+    /// Contracts which failed to compile.
+    pub failed: u64,
+}
+
+impl std::fmt::Display for PrecompileContractsStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "compiled: {}, already cached: {}, failed to compile: {}",
+            self.compiled, self.already_cached, self.failed
+        )
+    }
+}
+
+impl std::ops::AddAssign for PrecompileContractsStats {
+    fn add_assign(&mut self, other: Self) {
+        self.compiled += other.compiled;
+        self.already_cached += other.already_cached;
+        self.failed += other.failed;
+    }
+}
+
+/// Last processed account of every shard.
+#[derive(Default, Serialize, Deserialize)]
+struct Progress {
+    last_account_ids: BTreeMap<ShardId, AccountId>,
+}
+
+impl Progress {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(anyhow::anyhow!("{}: {}", path.display(), err)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .and_then(|()| std::fs::rename(&tmp_path, path))
+            .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
+    }
+}
+
+/// Compiles contracts deployed in the tracked shards of a stopped node.
+///
+/// Opens the node's database for writing, so it fails if the node is running.
+/// Use `neard run --precompile-contracts` to compile contracts in a running
+/// node instead.
+pub fn precompile_contracts(
+    home_dir: &Path,
+    near_config: &NearConfig,
+    opts: PrecompileContractsOpts,
+) -> anyhow::Result<PrecompileContractsStats> {
+    let store_path = crate::get_store_path(home_dir);
+    anyhow::ensure!(
+        crate::store_path_exists(&store_path),
+        "{}: storage doesn’t exist",
+        store_path.display()
+    );
+    let db_version = near_store::migrations::get_store_version(&store_path)?;
+    anyhow::ensure!(
+        db_version == near_primitives::version::DB_VERSION,
+        "{}: expected DB version {} but got {}; run the node once to migrate the database",
+        store_path.display(),
+        near_primitives::version::DB_VERSION,
+        db_version
+    );
+    let store = near_store::try_create_store_with_config(&store_path, &near_config.config.store)
+        .map_err(|err| {
+            if err.is_locked() {
+                anyhow::anyhow!(
+                    "{}: database is locked, most likely by a running neard; stop the node or \
+                     restart it with `neard run --precompile-contracts`",
+                    store_path.display()
+                )
+            } else {
+                anyhow::anyhow!("{}: {}", store_path.display(), err)
+            }
+        })?;
+    let runtime = NightshadeRuntime::with_config(home_dir, store.clone(), near_config, None, None);
+    ContractPrecompiler::new(
+        home_dir,
+        store,
+        &runtime,
+        near_config.genesis.config.genesis_height,
+        near_config.client_config.archive,
+    )
+    .run(opts)
+}
+
+/// Compiles contracts deployed in the tracked shards in a background thread of
+/// a running node.
+pub(crate) fn spawn_precompile_contracts(
+    home_dir: &Path,
+    near_config: &NearConfig,
+    store: Store,
+    runtime: Arc<NightshadeRuntime>,
+) {
+    let home_dir = home_dir.to_path_buf();
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let is_archival = near_config.client_config.archive;
+    let opts = PrecompileContractsOpts { shard_ids: vec![], account_ids: None, restart: false };
+    let spawned = std::thread::Builder::new().name("precompile".to_string()).spawn(move || {
+        let result =
+            ContractPrecompiler::new(&home_dir, store, &runtime, genesis_height, is_archival)
+                .run(opts);
+        match result {
+            Ok(stats) => info!(target: "precompile", %stats, "Finished precompiling contracts"),
+            Err(err) => error!(target: "precompile", "Failed to precompile contracts: {:#}", err),
+        }
+    });
+    if let Err(err) = spawned {
+        error!(target: "precompile", "Failed to spawn contract precompilation thread: {}", err);
+    }
+}
+
+struct ContractPrecompiler<'a> {
+    progress_path: PathBuf,
+    chain_store: ChainStore,
+    cache: StoreCompiledContractCache,
+    runtime: &'a NightshadeRuntime,
+    batch_size: usize,
+    /// Fails after compiling this many batches, as if interrupted.  Only set
+    /// by the tests.
+    max_batches: Option<usize>,
+}
+
+impl<'a> ContractPrecompiler<'a> {
+    fn new(
+        home_dir: &Path,
+        store: Store,
+        runtime: &'a NightshadeRuntime,
+        genesis_height: BlockHeight,
+        is_archival: bool,
+    ) -> Self {
+        Self {
+            progress_path: home_dir.join(PROGRESS_FILENAME),
+            chain_store: ChainStore::new(store.clone(), genesis_height, !is_archival),
+            cache: StoreCompiledContractCache { store },
+            runtime,
+            batch_size: BATCH_SIZE,
+            max_batches: None,
+        }
+    }
+
+    fn run(mut self, opts: PrecompileContractsOpts) -> anyhow::Result<PrecompileContractsStats> {
+        let head = self.chain_store.head()?;
+        let protocol_version = self.runtime.get_epoch_protocol_version(&head.epoch_id)?;
+        let protocol_config = self.runtime.get_protocol_config(&head.epoch_id)?;
+        let wasm_config = &protocol_config.runtime_config.wasm_config;
+        let num_shards = self.runtime.num_shards(&head.epoch_id)?;
+        let shard_ids =
+            if opts.shard_ids.is_empty() { (0..num_shards).collect() } else { opts.shard_ids };
+        let mut progress =
+            if opts.restart { Progress::default() } else { Progress::load(&self.progress_path)? };
+        let account_ids = opts.account_ids.map(|mut account_ids| {
+            account_ids.sort();
+            account_ids.dedup();
+            account_ids
+        });
+
+        info!(target: "precompile", height = head.height, protocol_version, "Precompiling contracts");
+        let mut total = PrecompileContractsStats::default();
+        let mut num_batches = 0;
+        for shard_id in shard_ids {
+            anyhow::ensure!(
+                shard_id < num_shards,
+                "shard {} doesn’t exist; there are {} shards",
+                shard_id,
+                num_shards
+            );
+            let shard_uid = self.runtime.shard_id_to_uid(shard_id, &head.epoch_id)?;
+            let state_root =
+                match self.chain_store.get_chunk_extra(&head.last_block_hash, &shard_uid) {
+                    Ok(chunk_extra) => *chunk_extra.state_root(),
+                    Err(_) => {
+                        info!(target: "precompile", shard_id, "Shard is not tracked, skipping");
+                        continue;
+                    }
+                };
+            let trie = self.runtime.get_tries().get_view_trie_for_shard(shard_uid);
+            let last_account_id = progress.last_account_ids.get(&shard_id).cloned();
+            let mut batch = Vec::with_capacity(self.batch_size);
+            let mut stats = PrecompileContractsStats::default();
+            let mut flush = |batch: &mut Vec<(AccountId, Vec<u8>)>,
+                             progress: &mut Progress|
+             -> anyhow::Result<()> {
+                let last = match batch.last() {
+                    Some((account_id, _)) => account_id.clone(),
+                    None => return Ok(()),
+                };
+                stats +=
+                    self.precompile_batch(std::mem::take(batch), wasm_config, protocol_version)?;
+                progress.last_account_ids.insert(shard_id, last);
+                progress.save(&self.progress_path)?;
+                info!(target: "precompile", shard_id, %stats, "Precompiling contracts");
+                num_batches += 1;
+                anyhow::ensure!(
+                    self.max_batches.map_or(true, |max_batches| num_batches < max_batches),
+                    "interrupted after batch {}",
+                    num_batches
+                );
+                Ok(())
+            };
+
+            match &account_ids {
+                Some(account_ids) => {
+                    for account_id in account_ids {
+                        if last_account_id.as_ref().map_or(false, |last| account_id <= last)
+                            || self.runtime.account_id_to_shard_id(account_id, &head.epoch_id)?
+                                != shard_id
+                        {
+                            continue;
+                        }
+                        let key = TrieKey::ContractCode { account_id: account_id.clone() };
+                        if let Some(code) = trie.get(&state_root, &key.to_vec())? {
+                            batch.push((account_id.clone(), code));
+                        }
+                        if batch.len() >= self.batch_size {
+                            flush(&mut batch, &mut progress)?;
+                        }
+                    }
+                }
+                None => {
+                    let prefix = trie_key_parsers::get_raw_prefix_for_contract_codes();
+                    let mut iter = trie.iter(&state_root)?;
+                    match &last_account_id {
+                        Some(account_id) => iter.seek(
+                            TrieKey::ContractCode { account_id: account_id.clone() }.to_vec(),
+                        )?,
+                        None => iter.seek(&prefix)?,
+                    }
+                    for item in iter {
+                        let (key, code) = item?;
+                        if !key.starts_with(&prefix) {
+                            break;
+                        }
+                        let account_id =
+                            trie_key_parsers::parse_account_id_from_contract_code_key(&key)?;
+                        if last_account_id.as_ref() == Some(&account_id) {
+                            continue;
+                        }
+                        batch.push((account_id, code));
+                        if batch.len() >= self.batch_size {
+                            flush(&mut batch, &mut progress)?;
+                        }
+                    }
+                }
+            }
+            flush(&mut batch, &mut progress)?;
+            info!(target: "precompile", shard_id, %stats, "Finished precompiling contracts of a shard");
+            total += stats;
+        }
+
+        // The run is complete so the next one should start from scratch.
+        if let Err(err) = std::fs::remove_file(&self.progress_path) {
+            anyhow::ensure!(
+                err.kind() == std::io::ErrorKind::NotFound,
+                "{}: {}",
+                self.progress_path.display(),
+                err
+            );
+        }
+        Ok(total)
+    }
+
+    /// Compiles a batch of contracts in parallel.  Uses up to half of the rayon
+    /// threads so that a running node keeps functioning normally.
+    fn precompile_batch(
+        &self,
+        batch: Vec<(AccountId, Vec<u8>)>,
+        wasm_config: &near_primitives::config::VMConfig,
+        protocol_version: ProtocolVersion,
+    ) -> anyhow::Result<PrecompileContractsStats> {
+        let cache: &dyn CompiledContractCache = &self.cache;
+        // Split the batch into at most as many jobs as half of the threads.
+        let max_threads = std::cmp::max(rayon::current_num_threads() / 2, 1);
+        let results: Vec<_> = batch
+            .into_par_iter()
+            .with_min_len((self.batch_size + max_threads - 1) / max_threads)
+            .map(|(account_id, code)| {
+                let code = ContractCode::new(code, None);
+                let result = precompile_contract(&code, wasm_config, protocol_version, Some(cache));
+                (account_id, result)
+            })
+            .collect();
+
+        let mut stats = PrecompileContractsStats::default();
+        for (account_id, result) in results {
+            match result {
+                Ok(Ok(ContractPrecompilatonResult::ContractCompiled)) => stats.compiled += 1,
+                Ok(Ok(ContractPrecompilatonResult::ContractAlreadyInCache)) => {
+                    stats.already_cached += 1
+                }
+                Ok(Ok(ContractPrecompilatonResult::CacheNotAvailable)) => {
+                    unreachable!("the cache is always given")
+                }
+                Ok(Err(err)) => {
+                    debug!(target: "precompile", %account_id, "Contract failed to compile: {}", err);
+                    stats.failed += 1;
+                }
+                Err(err) => {
+                    anyhow::bail!("failed to cache contract of {}: {:?}", account_id, err)
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode};
+    use near_chain_configs::Genesis;
+    use near_primitives::hash::hash;
+    use near_primitives::state_record::StateRecord;
+    use near_store::test_utils::create_test_store;
+
+    #[test]
+    fn test_resume_interrupted_run() {
+        let home_dir = tempfile::Builder::new().prefix("precompile").tempdir().unwrap();
+        let accounts: Vec<AccountId> = ["test0", "alice.near", "bob.near", "carol.near"]
+            .iter()
+            .map(|account_id| account_id.parse().unwrap())
+            .collect();
+        let mut genesis = Genesis::test(accounts.clone(), 1);
+        // Different code for every account, so that none is already cached
+        // by compiling another one.
+        for (i, account_id) in accounts[1..].iter().enumerate() {
+            let code = near_test_contracts::sized_contract(100 * (i + 1));
+            for record in genesis.records.as_mut() {
+                if let StateRecord::Account { account_id: record_account_id, account } = record {
+                    if record_account_id == account_id {
+                        account.set_code_hash(hash(&code));
+                    }
+                }
+            }
+            genesis
+                .records
+                .as_mut()
+                .push(StateRecord::Contract { account_id: account_id.clone(), code });
+        }
+        let store = create_test_store();
+        let runtime = Arc::new(NightshadeRuntime::test(home_dir.path(), store.clone(), &genesis));
+        Chain::new(
+            runtime.clone(),
+            &ChainGenesis::from(&genesis),
+            DoomslugThresholdMode::NoApprovals,
+            true,
+        )
+        .unwrap();
+        let opts =
+            || PrecompileContractsOpts { shard_ids: vec![], account_ids: None, restart: false };
+        let genesis_height = genesis.config.genesis_height;
+        let precompiler = || ContractPrecompiler {
+            batch_size: 1,
+            ..ContractPrecompiler::new(
+                home_dir.path(),
+                store.clone(),
+                &runtime,
+                genesis_height,
+                false,
+            )
+        };
+
+        let err =
+            ContractPrecompiler { max_batches: Some(1), ..precompiler() }.run(opts()).unwrap_err();
+        assert_eq!(err.to_string(), "interrupted after batch 1");
+        let progress = Progress::load(&home_dir.path().join(PROGRESS_FILENAME)).unwrap();
+        assert_eq!(progress.last_account_ids.get(&0), Some(&accounts[1]));
+
+        // The rerun starts after the contract compiled before the interruption.
+        let stats = precompiler().run(opts()).unwrap();
+        assert_eq!((stats.compiled, stats.already_cached, stats.failed), (2, 0, 0));
+        assert!(!home_dir.path().join(PROGRESS_FILENAME).exists());
+    }
+}
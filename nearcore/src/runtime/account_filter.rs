@@ -0,0 +1,423 @@
+//! Bloom filters over the account ids of the tracked shards, for the
+//! `view_account` queries of accounts which don't exist, typos and scans, to
+//! be answered without walking the trie.
+//!
+//! The filter of a shard is built in the background at startup from the state
+//! after the last final block, plus the accounts changed by the blocks applied
+//! above it before the node stopped, which are read from the state changes
+//! column.  The accounts changed by the chunks applied afterwards are added as
+//! they're applied, before their blocks are saved.  Deleted accounts are never
+//! removed, and the filters only grow.
+//!
+//! A negative answer of the filter is trusted for the canonical blocks at or
+//! above the final block the filter was built from: they descend from it, so
+//! every account in their state is in that state or was changed by a block the
+//! filter saw.  Any other query, a positive answer and the queries of a shard
+//! without a filter, e.g. a shard split by a resharding or synced from state
+//! parts, read the trie as usual.
+//!
+//! The number of accounts isn't known upfront, so the filter of a shard is a
+//! list of bloom filters of doubling capacities, a new one being added when the
+//! last one is full.  The false positive rate is about the one of a single
+//! filter times the number of filters.
+
+use super::NightshadeRuntime;
+use crate::metrics;
+use near_chain::RuntimeAdapter;
+use near_primitives::block::Tip;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::{account_id_to_shard_id, get_block_shard_uid, ShardUId};
+use near_primitives::trie_key::{col, trie_key_parsers, TrieKey};
+use near_primitives::types::chunk_extra::ChunkExtra;
+use near_primitives::types::{
+    AccountId, BlockHeight, EpochId, RawStateChangesWithTrieKey, StateRoot,
+};
+use near_primitives::utils::index_to_bytes;
+use near_store::{DBCol, TrieIterator, FINAL_HEAD_KEY, HEAD_KEY};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+
+/// Keys the first bloom filter of a shard is sized for.
+const FIRST_CAPACITY: usize = 1 << 16;
+
+/// Accounts of the state inserted under one lock of the filters.
+const BUILD_BATCH_SIZE: usize = 10_000;
+
+/// One bloom filter, `capacity` keys of `bits_per_key` bits.
+struct Bloom {
+    bits: Vec<u64>,
+    num_keys: usize,
+    capacity: usize,
+}
+
+impl Bloom {
+    fn new(capacity: usize, bits_per_key: u32) -> Self {
+        let num_bits = (capacity * bits_per_key as usize).max(64);
+        Self { bits: vec![0; (num_bits + 63) / 64], num_keys: 0, capacity }
+    }
+
+    /// Positions of the bits of the key, by double hashing.
+    fn positions(&self, hashes: (u64, u64), num_hashes: u32) -> impl Iterator<Item = usize> {
+        let num_bits = self.bits.len() as u64 * 64;
+        let (h1, h2) = hashes;
+        (0..num_hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+
+    fn insert(&mut self, hashes: (u64, u64), num_hashes: u32) {
+        for position in self.positions(hashes, num_hashes).collect::<Vec<_>>() {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+        self.num_keys += 1;
+    }
+
+    fn contains(&self, hashes: (u64, u64), num_hashes: u32) -> bool {
+        self.positions(hashes, num_hashes)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// The bloom filters of a shard.
+pub(crate) struct AccountFilter {
+    bits_per_key: u32,
+    num_hashes: u32,
+    blooms: Vec<Bloom>,
+}
+
+impl AccountFilter {
+    pub fn new(bits_per_key: u32) -> Self {
+        // The number of hashes minimizing the false positive rate.
+        let num_hashes = ((bits_per_key as f64 * std::f64::consts::LN_2).round() as u32).max(1);
+        Self { bits_per_key, num_hashes, blooms: vec![Bloom::new(FIRST_CAPACITY, bits_per_key)] }
+    }
+
+    fn hashes(account_id: &AccountId) -> (u64, u64) {
+        let mut hashes = [0; 2];
+        for (seed, hash) in hashes.iter_mut().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            account_id.as_ref().hash(&mut hasher);
+            *hash = hasher.finish();
+        }
+        // An even step would only reach half of the positions.
+        (hashes[0], hashes[1] | 1)
+    }
+
+    pub fn insert(&mut self, account_id: &AccountId) {
+        let hashes = Self::hashes(account_id);
+        // Most changed accounts already exist, they'd fill the filters.
+        if self.blooms.iter().any(|bloom| bloom.contains(hashes, self.num_hashes)) {
+            return;
+        }
+        let last = self.blooms.last().unwrap();
+        if last.num_keys >= last.capacity {
+            let capacity = last.capacity * 2;
+            self.blooms.push(Bloom::new(capacity, self.bits_per_key));
+        }
+        self.blooms.last_mut().unwrap().insert(hashes, self.num_hashes);
+    }
+
+    /// False only if the account was never inserted.
+    pub fn may_contain(&self, account_id: &AccountId) -> bool {
+        let hashes = Self::hashes(account_id);
+        self.blooms.iter().any(|bloom| bloom.contains(hashes, self.num_hashes))
+    }
+
+    pub fn num_bytes(&self) -> usize {
+        self.blooms.iter().map(|bloom| bloom.bits.len() * 8).sum()
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Height of the final block the filters were built from, once they're
+    /// built.
+    base_height: Option<BlockHeight>,
+    shards: HashMap<ShardUId, AccountFilter>,
+}
+
+/// The filters of the tracked shards, shared by the chain, which adds the
+/// accounts of the applied chunks, and the view client.
+pub(crate) struct AccountFilters {
+    bits_per_key: u32,
+    inner: RwLock<Inner>,
+}
+
+impl AccountFilters {
+    pub fn new(bits_per_key: u32) -> Self {
+        Self { bits_per_key, inner: RwLock::default() }
+    }
+
+    /// Starts filling a filter for the shard, the accounts changed in it are
+    /// inserted from now on.
+    pub fn add_shard(&self, shard_uid: ShardUId) {
+        self.inner.write().unwrap().shards.insert(shard_uid, AccountFilter::new(self.bits_per_key));
+    }
+
+    /// Drops the filter of the shard, whose state is being replaced without
+    /// the filter seeing its accounts, e.g. from state parts.
+    pub fn remove_shard(&self, shard_uid: &ShardUId) {
+        if self.inner.write().unwrap().shards.remove(shard_uid).is_some() {
+            info!(target: "runtime", ?shard_uid, "account filter dropped");
+            set_size_metric(shard_uid, 0);
+        }
+    }
+
+    pub fn insert<'a>(
+        &self,
+        shard_uid: &ShardUId,
+        account_ids: impl Iterator<Item = &'a AccountId>,
+    ) {
+        let mut inner = self.inner.write().unwrap();
+        if let Some(filter) = inner.shards.get_mut(shard_uid) {
+            for account_id in account_ids {
+                filter.insert(account_id);
+            }
+            set_size_metric(shard_uid, filter.num_bytes());
+        }
+    }
+
+    /// Inserts the accounts changed by a chunk applied on the shard.
+    pub fn record_changes(&self, shard_uid: &ShardUId, changes: &[RawStateChangesWithTrieKey]) {
+        let account_ids = changes.iter().filter_map(|change| match &change.trie_key {
+            TrieKey::Account { account_id } => Some(account_id),
+            _ => None,
+        });
+        self.insert(shard_uid, account_ids);
+    }
+
+    /// Trusts the negative answers of the filters from now on, for the
+    /// canonical blocks from `height`.
+    pub fn set_built(&self, height: BlockHeight) {
+        self.inner.write().unwrap().base_height = Some(height);
+    }
+
+    /// False if the account certainly doesn't exist in the state of the shard
+    /// after the block.  `is_canonical` tells whether the block is on the
+    /// canonical chain.
+    pub fn may_exist(
+        &self,
+        shard_uid: &ShardUId,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        is_canonical: impl FnOnce() -> bool,
+    ) -> bool {
+        let inner = self.inner.read().unwrap();
+        let filter = match (inner.base_height, inner.shards.get(shard_uid)) {
+            (Some(base_height), Some(filter)) if block_height >= base_height => filter,
+            _ => return true,
+        };
+        if filter.may_contain(account_id) || !is_canonical() {
+            return true;
+        }
+        metrics::ACCOUNT_FILTER_SHORT_CIRCUITS
+            .with_label_values(&[&shard_uid.shard_id.to_string()])
+            .inc();
+        false
+    }
+}
+
+fn set_size_metric(shard_uid: &ShardUId, num_bytes: usize) {
+    metrics::ACCOUNT_FILTER_SIZE
+        .with_label_values(&[&shard_uid.shard_id.to_string()])
+        .set(num_bytes as i64);
+}
+
+impl NightshadeRuntime {
+    /// Whether there's no account with the id in the state of the shard after
+    /// the block according to the filter, in which case the trie needn't be
+    /// read.
+    pub(crate) fn account_certainly_missing(
+        &self,
+        shard_uid: &ShardUId,
+        account_id: &AccountId,
+        block_height: BlockHeight,
+        block_hash: &CryptoHash,
+    ) -> bool {
+        let filters = match &self.account_filters {
+            Some(filters) => filters,
+            None => return false,
+        };
+        !filters.may_exist(shard_uid, account_id, block_height, || {
+            let canonical: Option<CryptoHash> = self
+                .store
+                .get_ser(DBCol::BlockHeight, &index_to_bytes(block_height))
+                .ok()
+                .flatten();
+            canonical.as_ref() == Some(block_hash)
+        })
+    }
+
+    /// Starts building the account filters, if enabled, in a thread of its
+    /// own.  To be called before the chain starts applying blocks, for the
+    /// filters to see all the accounts they change.
+    pub fn start_account_filters(self: &Arc<Self>) {
+        let filters = match &self.account_filters {
+            Some(filters) => filters.clone(),
+            None => return,
+        };
+        let (final_head, head) = match (self.read_tip(FINAL_HEAD_KEY), self.read_tip(HEAD_KEY)) {
+            (Some(final_head), Some(head)) => (final_head, head),
+            _ => {
+                info!(target: "runtime", "no chain yet, the account filters aren't built");
+                return;
+            }
+        };
+        let mut state_roots = vec![];
+        match self.get_shard_layout(&final_head.epoch_id) {
+            Ok(shard_layout) => {
+                for shard_uid in shard_layout.get_shard_uids() {
+                    let key = get_block_shard_uid(&final_head.last_block_hash, &shard_uid);
+                    if let Ok(Some(chunk_extra)) =
+                        self.store.get_ser::<ChunkExtra>(DBCol::ChunkExtra, &key)
+                    {
+                        filters.add_shard(shard_uid);
+                        state_roots.push((shard_uid, *chunk_extra.state_root()));
+                    }
+                }
+            }
+            Err(err) => {
+                error!(target: "runtime", ?err, "failed to build the account filters");
+                return;
+            }
+        }
+        let runtime = self.clone();
+        std::thread::spawn(move || {
+            info!(target: "runtime", height = final_head.height, "building the account filters");
+            match runtime.build_account_filters(&filters, &state_roots, &final_head, &head) {
+                Ok(()) => {
+                    filters.set_built(final_head.height);
+                    info!(target: "runtime", "account filters built");
+                }
+                Err(err) => {
+                    error!(target: "runtime", ?err, "failed to build the account filters")
+                }
+            }
+        });
+    }
+
+    fn read_tip(&self, key: &[u8]) -> Option<Tip> {
+        self.store.get_ser(DBCol::BlockMisc, key).ok().flatten()
+    }
+
+    /// Inserts the accounts of the state after the final block and the ones
+    /// changed by the blocks applied above it, up to the head.
+    fn build_account_filters(
+        &self,
+        filters: &AccountFilters,
+        state_roots: &[(ShardUId, StateRoot)],
+        final_head: &Tip,
+        head: &Tip,
+    ) -> anyhow::Result<()> {
+        for (shard_uid, state_root) in state_roots {
+            let trie = self.tries.get_view_trie_for_shard(*shard_uid);
+            let mut iter = TrieIterator::new(&trie, state_root)?;
+            iter.seek(&[col::ACCOUNT])?;
+            let mut batch = vec![];
+            for item in iter {
+                let (key, _) = item?;
+                if key.first() != Some(&col::ACCOUNT) {
+                    break;
+                }
+                batch.push(trie_key_parsers::parse_account_id_from_account_key(&key)?);
+                if batch.len() == BUILD_BATCH_SIZE {
+                    filters.insert(shard_uid, batch.iter());
+                    batch.clear();
+                }
+            }
+            filters.insert(shard_uid, batch.iter());
+        }
+
+        for height in final_head.height + 1..=head.height {
+            let blocks: Option<HashMap<EpochId, HashSet<CryptoHash>>> =
+                self.store.get_ser(DBCol::BlockPerHeight, &index_to_bytes(height))?;
+            for (epoch_id, block_hashes) in blocks.unwrap_or_default() {
+                let shard_layout = self.get_shard_layout(&epoch_id)?;
+                for block_hash in block_hashes {
+                    let mut prefix = block_hash.as_ref().to_vec();
+                    prefix.push(col::ACCOUNT);
+                    for (key, _) in self.store.iter_prefix(DBCol::StateChanges, &prefix) {
+                        let account_id = trie_key_parsers::parse_account_id_from_account_key(
+                            &key[block_hash.as_ref().len()..],
+                        )?;
+                        let shard_id = account_id_to_shard_id(&account_id, &shard_layout);
+                        let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+                        filters.insert(&shard_uid, std::iter::once(&account_id));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::types::{RawStateChange, StateChangeCause};
+
+    fn account(i: usize) -> AccountId {
+        format!("account{}.near", i).parse().unwrap()
+    }
+
+    fn change(account_id: AccountId, data: Option<Vec<u8>>) -> RawStateChangesWithTrieKey {
+        RawStateChangesWithTrieKey {
+            trie_key: TrieKey::Account { account_id },
+            changes: vec![RawStateChange { cause: StateChangeCause::InitialState, data }],
+        }
+    }
+
+    #[test]
+    fn test_account_filter() {
+        let mut filter = AccountFilter::new(10);
+        let num_keys = 3 * FIRST_CAPACITY;
+        for i in 0..num_keys {
+            filter.insert(&account(i));
+        }
+        assert!((0..num_keys).all(|i| filter.may_contain(&account(i))));
+        // The first filter and one of twice its capacity.
+        assert_eq!(filter.blooms.len(), 2);
+        let false_positives =
+            (num_keys..2 * num_keys).filter(|&i| filter.may_contain(&account(i))).count();
+        assert!(false_positives < num_keys / 25, "{} false positives", false_positives);
+
+        let num_bytes = filter.num_bytes();
+        filter.insert(&account(0));
+        assert_eq!(filter.num_bytes(), num_bytes);
+    }
+
+    #[test]
+    fn test_account_filters() {
+        let shard_uid = ShardUId { version: 0, shard_id: 0 };
+        let filters = AccountFilters::new(10);
+        filters.add_shard(shard_uid);
+        filters.insert(&shard_uid, [account(0)].iter());
+
+        // Everything may exist until the filters are built.
+        assert!(filters.may_exist(&shard_uid, &account(1), 10, || true));
+        filters.set_built(10);
+        assert!(filters.may_exist(&shard_uid, &account(0), 10, || true));
+        assert!(!filters.may_exist(&shard_uid, &account(1), 10, || true));
+        // Below the base and off the canonical chain, the filter doesn't know.
+        assert!(filters.may_exist(&shard_uid, &account(1), 9, || true));
+        assert!(filters.may_exist(&shard_uid, &account(1), 11, || false));
+
+        // A created account, and a deleted one, which still may exist.
+        filters.record_changes(&shard_uid, &[change(account(1), Some(vec![]))]);
+        assert!(filters.may_exist(&shard_uid, &account(1), 11, || true));
+        filters.record_changes(&shard_uid, &[change(account(1), None)]);
+        assert!(filters.may_exist(&shard_uid, &account(1), 12, || true));
+
+        // The shards after a resharding have no filter.
+        let child_shard_uid = ShardUId { version: 1, shard_id: 0 };
+        filters.record_changes(&child_shard_uid, &[change(account(2), Some(vec![]))]);
+        assert!(filters.may_exist(&child_shard_uid, &account(3), 12, || true));
+
+        // Nor a shard synced from state parts.
+        filters.remove_shard(&shard_uid);
+        assert!(filters.may_exist(&shard_uid, &account(3), 12, || true));
+    }
+}
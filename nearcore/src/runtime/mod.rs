@@ -2,17 +2,21 @@ use crate::metrics;
 use crate::migrations::load_migration_data;
 use crate::shard_tracker::{ShardTracker, TrackedConfig};
 use crate::NearConfig;
+use account_filter::AccountFilters;
 use borsh::ser::BorshSerialize;
 use borsh::BorshDeserialize;
 use errors::FromStateViewerErrors;
+use near_chain::apply_stage_times::ApplyChunkStageTimes;
 use near_chain::types::{
-    ApplySplitStateResult, ApplyTransactionResult, BlockHeaderInfo, ValidatorInfoIdentifier,
+    ApplySplitStateResult, ApplyTransactionResult, BlockHeaderInfo, PreparedTransactions,
+    TxValidationResult, ValidatorInfoIdentifier,
 };
 use near_chain::{BlockHeader, Doomslug, DoomslugThresholdMode, Error, ErrorKind, RuntimeAdapter};
 use near_chain_configs::{
     Genesis, GenesisConfig, ProtocolConfig, DEFAULT_GC_NUM_EPOCHS_TO_KEEP,
     MIN_GC_NUM_EPOCHS_TO_KEEP,
 };
+use near_crypto::verification_pool::{self, SignatureCheck};
 use near_crypto::{PublicKey, Signature};
 use near_epoch_manager::EpochManager;
 use near_pool::types::PoolIterator;
@@ -33,7 +37,7 @@ use near_primitives::shard_layout::{
 };
 use near_primitives::sharding::ChunkHash;
 use near_primitives::state_part::PartId;
-use near_primitives::state_record::{state_record_to_account_id, StateRecord};
+use near_primitives::state_record::{state_record_to_account_id, StatePatchRecord, StateRecord};
 use near_primitives::syncing::{get_num_state_parts, STATE_PART_MEMORY_LIMIT};
 use near_primitives::transaction::SignedTransaction;
 use near_primitives::types::validator_stake::{ValidatorStake, ValidatorStakeIter};
@@ -44,8 +48,9 @@ use near_primitives::types::{
 };
 use near_primitives::version::ProtocolVersion;
 use near_primitives::views::{
-    AccessKeyInfoView, CallResult, EpochValidatorInfo, QueryRequest, QueryResponse,
-    QueryResponseKind, ViewApplyState, ViewStateResult,
+    AccessKeyInfoView, AccountViewOrError, CallFunctionOverrides, CallResult, CallResultWithGas,
+    EpochValidatorInfo, EpochValidatorsPreview, QueryRequest, QueryResponse, QueryResponseKind,
+    ViewAccountsResult, ViewApplyState, ViewStateResult,
 };
 use near_store::split_state::get_delayed_receipts;
 use near_store::{
@@ -55,21 +60,22 @@ use near_store::{
 };
 use near_vm_runner::precompile_contract;
 use node_runtime::adapter::ViewRuntimeAdapter;
-use node_runtime::config::RuntimeConfig;
 use node_runtime::near_primitives::shard_layout::ShardLayoutError;
+use node_runtime::replay::DirectoryReceiptRecorder;
 use node_runtime::state_viewer::TrieViewer;
 use node_runtime::{
-    validate_transaction, verify_and_charge_transaction, ApplyState, Runtime,
-    ValidatorAccountsUpdate,
+    transaction_limit_violations, validate_transaction, verify_and_charge_transaction, ApplyState,
+    Runtime, ValidatorAccountsUpdate,
 };
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 
+mod account_filter;
 pub mod errors;
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
@@ -141,6 +147,9 @@ pub struct NightshadeRuntime {
     genesis_state_roots: Vec<StateRoot>,
     migration_data: Arc<MigrationData>,
     gc_num_epochs_to_keep: u64,
+    /// Filters of the accounts of the tracked shards, with
+    /// `account_filter.enabled`.
+    account_filters: Option<Arc<AccountFilters>>,
 }
 
 impl NightshadeRuntime {
@@ -151,7 +160,7 @@ impl NightshadeRuntime {
         trie_viewer_state_size_limit: Option<u64>,
         max_gas_burnt_view: Option<Gas>,
     ) -> Self {
-        Self::new(
+        let mut runtime = Self::new(
             home_dir,
             store,
             &config.genesis,
@@ -160,7 +169,20 @@ impl NightshadeRuntime {
             max_gas_burnt_view,
             None,
             config.config.gc.gc_num_epochs_to_keep(),
-        )
+        );
+        if let Some(dir) = &config.config.record_receipts_dir {
+            let dir = home_dir.join(dir);
+            let recorder = DirectoryReceiptRecorder::new(dir.clone()).unwrap_or_else(|err| {
+                panic!("Failed to create receipt records directory {}: {}", dir.display(), err)
+            });
+            info!(target: "runtime", dir = %dir.display(), "recording applied receipts");
+            runtime.runtime = runtime.runtime.clone().with_receipt_recorder(Arc::new(recorder));
+        }
+        if config.config.account_filter.enabled {
+            runtime.account_filters =
+                Some(Arc::new(AccountFilters::new(config.config.account_filter.bits_per_key)));
+        }
+        runtime
     }
 
     pub fn new(
@@ -175,10 +197,10 @@ impl NightshadeRuntime {
     ) -> Self {
         let runtime_config_store = match runtime_config_store {
             Some(store) => store,
-            None => NightshadeRuntime::create_runtime_config_store(&genesis.config.chain_id),
+            None => RuntimeConfigStore::for_chain_id(&genesis.config.chain_id),
         };
 
-        let runtime = Runtime::new();
+        let runtime = Runtime::new().with_signature_cache();
         let trie_viewer = TrieViewer::new(trie_viewer_state_size_limit, max_gas_burnt_view);
         let genesis_config = genesis.config.clone();
         assert_eq!(
@@ -212,6 +234,7 @@ impl NightshadeRuntime {
             genesis_state_roots: state_roots,
             migration_data: Arc::new(load_migration_data(&genesis.config.chain_id)),
             gc_num_epochs_to_keep: gc_num_epochs_to_keep.max(MIN_GC_NUM_EPOCHS_TO_KEEP),
+            account_filters: None,
         }
     }
 
@@ -251,22 +274,6 @@ impl NightshadeRuntime {
         epoch_manager.get_epoch_id(hash).map_err(Error::from)
     }
 
-    /// Create store of runtime configs for the given chain id.
-    ///
-    /// For mainnet and other chains except testnet we don't need to override runtime config for
-    /// first protocol versions.
-    /// For testnet, runtime config for genesis block was (incorrectly) different, that's why we
-    /// need to override it specifically to preserve compatibility.
-    fn create_runtime_config_store(chain_id: &str) -> RuntimeConfigStore {
-        match chain_id {
-            "testnet" => {
-                let genesis_runtime_config = RuntimeConfig::initial_testnet_config();
-                RuntimeConfigStore::new(Some(&genesis_runtime_config))
-            }
-            _ => RuntimeConfigStore::new(None),
-        }
-    }
-
     fn genesis_state_from_dump(store: Store, home_dir: &Path) -> Vec<StateRoot> {
         error!(target: "near", "Loading genesis from a state dump file. Do not use this outside of genesis-tools");
         let mut state_file = home_dir.to_path_buf();
@@ -307,8 +314,7 @@ impl NightshadeRuntime {
         assert!(has_protocol_account, "Genesis spec doesn't have protocol treasury account");
         let tries = ShardTries::new(store, genesis.config.shard_layout.version(), num_shards);
         let runtime = Runtime::new();
-        let runtime_config_store =
-            NightshadeRuntime::create_runtime_config_store(&genesis.config.chain_id);
+        let runtime_config_store = RuntimeConfigStore::for_chain_id(&genesis.config.chain_id);
         let runtime_config = runtime_config_store.get_config(genesis.config.protocol_version);
 
         for shard_id in 0..num_shards {
@@ -439,7 +445,7 @@ impl NightshadeRuntime {
         random_seed: CryptoHash,
         is_new_chunk: bool,
         is_first_block_with_chunk_of_version: bool,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Result<ApplyTransactionResult, Error> {
         let _span = tracing::debug_span!(target: "runtime", "process_state_update").entered();
         let epoch_id = self.get_epoch_id_from_prev_block(prev_block_hash)?;
@@ -597,6 +603,9 @@ impl NightshadeRuntime {
             })?;
 
         let shard_uid = self.get_shard_uid_from_prev_hash(shard_id, prev_block_hash)?;
+        if let Some(filters) = &self.account_filters {
+            filters.record_changes(&shard_uid, &apply_result.state_changes);
+        }
 
         let result = ApplyTransactionResult {
             trie_changes: WrappedTrieChanges::new(
@@ -613,7 +622,15 @@ impl NightshadeRuntime {
             total_gas_burnt,
             total_balance_burnt,
             proof: apply_result.proof,
+            largest_receipt_proof: apply_result.stats.largest_receipt_proof,
             processed_delayed_receipts: apply_result.processed_delayed_receipts,
+            stage_times: ApplyChunkStageTimes {
+                transactions: apply_result.stage_times.transactions,
+                receipts: apply_result.stage_times.receipts,
+                trie_update: apply_result.stage_times.trie_update,
+                outcome_root: Duration::ZERO,
+                trie_reads: apply_result.trie_reads,
+            },
         };
 
         Ok(result)
@@ -662,6 +679,69 @@ impl NightshadeRuntime {
         });
         Ok(())
     }
+
+    /// Handles `CallFunction` queries, and `CallFunctionWithOverrides` if
+    /// `overrides` is given.  Only the latter report the burnt gas.
+    fn query_call_function(
+        &self,
+        block_height: BlockHeight,
+        block_timestamp: u64,
+        prev_block_hash: &CryptoHash,
+        block_hash: &CryptoHash,
+        epoch_id: &EpochId,
+        shard_uid: &ShardUId,
+        state_root: &StateRoot,
+        account_id: &AccountId,
+        method_name: &str,
+        args: &[u8],
+        overrides: Option<&CallFunctionOverrides>,
+    ) -> Result<QueryResponse, near_chain::near_chain_primitives::error::QueryError> {
+        let mut logs = vec![];
+        let (epoch_height, current_protocol_version) = {
+            let epoch_manager = self.epoch_manager.read();
+            let epoch_info = epoch_manager.get_epoch_info(epoch_id).map_err(|err| {
+                near_chain::near_chain_primitives::error::QueryError::from_epoch_error(
+                    err,
+                    block_height,
+                    *block_hash,
+                )
+            })?;
+            (epoch_info.epoch_height(), epoch_info.protocol_version())
+        };
+
+        let (result, gas_burnt) = self
+            .call_function(
+                shard_uid,
+                *state_root,
+                block_height,
+                block_timestamp,
+                prev_block_hash,
+                block_hash,
+                epoch_height,
+                epoch_id,
+                account_id,
+                method_name,
+                args,
+                overrides.unwrap_or(&CallFunctionOverrides::default()),
+                &mut logs,
+                &self.epoch_manager,
+                current_protocol_version,
+            )
+            .map_err(|err| {
+                near_chain::near_chain_primitives::error::QueryError::from_call_function_error(
+                    err,
+                    block_height,
+                    *block_hash,
+                )
+            })?;
+        let kind = match overrides {
+            None => QueryResponseKind::CallResult(CallResult { result, logs }),
+            Some(_) => {
+                QueryResponseKind::CallResultWithGas(CallResultWithGas { result, logs, gas_burnt })
+            }
+        };
+        Ok(QueryResponse { kind, block_height, block_hash: *block_hash })
+    }
 }
 
 fn format_total_gas_burnt(gas: Gas) -> String {
@@ -671,20 +751,21 @@ fn format_total_gas_burnt(gas: Gas) -> String {
 }
 
 fn apply_delayed_receipts<'a>(
-    tries: &ShardTries,
+    source: &ShardTries,
+    target: &ShardTries,
     orig_shard_uid: ShardUId,
     orig_state_root: StateRoot,
     state_roots: HashMap<ShardUId, StateRoot>,
     account_id_to_shard_id: &(dyn Fn(&AccountId) -> ShardUId + 'a),
 ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
-    let orig_trie_update = tries.new_trie_update_view(orig_shard_uid, orig_state_root);
+    let orig_trie_update = source.new_trie_update_view(orig_shard_uid, orig_state_root);
 
     let mut start_index = None;
     let mut new_state_roots = state_roots;
     while let Some((next_index, receipts)) =
         get_delayed_receipts(&orig_trie_update, start_index, STATE_PART_MEMORY_LIMIT)?
     {
-        let (store_update, updated_state_roots) = tries.apply_delayed_receipts_to_split_states(
+        let (store_update, updated_state_roots) = target.apply_delayed_receipts_to_split_states(
             &new_state_roots,
             &receipts,
             account_id_to_shard_id,
@@ -697,6 +778,65 @@ fn apply_delayed_receipts<'a>(
     Ok(new_state_roots)
 }
 
+/// Builds the states of the shards which the shard `shard_uid` splits into in
+/// `next_epoch_shard_layout` from its state at `state_root`, returning their
+/// roots.  The state of the shard is read from `source` and the new states are
+/// written to `target`: the node builds them in its own tries, while
+/// `state-viewer simulate-resharding` builds them in a temporary store.
+pub fn build_state_for_split_shards(
+    source: &ShardTries,
+    target: &ShardTries,
+    shard_uid: ShardUId,
+    state_root: &StateRoot,
+    next_epoch_shard_layout: &ShardLayout,
+) -> Result<HashMap<ShardUId, StateRoot>, Error> {
+    let trie = source.get_view_trie_for_shard(shard_uid);
+    let shard_id = shard_uid.shard_id();
+    let new_shards = next_epoch_shard_layout
+        .get_split_shard_uids(shard_id)
+        .ok_or(ErrorKind::InvalidShardId(shard_id))?;
+    let mut state_roots: HashMap<_, _> =
+        new_shards.iter().map(|shard_uid| (*shard_uid, StateRoot::default())).collect();
+    let split_shard_ids: HashSet<_> = new_shards.into_iter().collect();
+    let checked_account_id_to_shard_id = |account_id: &AccountId| {
+        let new_shard_uid = account_id_to_shard_uid(account_id, next_epoch_shard_layout);
+        // check that all accounts in the shard are mapped the shards that this shard will split
+        // to according to shard layout
+        assert!(
+            split_shard_ids.contains(&new_shard_uid),
+            "Inconsistent shard_layout specs. Account {:?} in shard {:?} and in shard {:?}, but the former is not parent shard for the latter",
+            account_id,
+            shard_uid,
+            new_shard_uid,
+        );
+        new_shard_uid
+    };
+
+    let state_root_node = trie.retrieve_root_node(state_root)?;
+    let num_parts = get_num_state_parts(state_root_node.memory_usage);
+    debug!(target: "runtime", "splitting state for shard {} to {} parts to build new states", shard_id, num_parts);
+    for part_id in 0..num_parts {
+        let trie_items =
+            trie.get_trie_items_for_part(PartId::new(part_id, num_parts), state_root)?;
+        let (store_update, new_state_roots) = target.add_values_to_split_states(
+            &state_roots,
+            trie_items.into_iter().map(|(key, value)| (key, Some(value))).collect(),
+            &checked_account_id_to_shard_id,
+        )?;
+        state_roots = new_state_roots;
+        store_update.commit()?;
+    }
+    state_roots = apply_delayed_receipts(
+        source,
+        target,
+        shard_uid,
+        *state_root,
+        state_roots,
+        &checked_account_id_to_shard_id,
+    )?;
+    Ok(state_roots)
+}
+
 pub fn state_record_to_shard_id(state_record: &StateRecord, shard_layout: &ShardLayout) -> ShardId {
     account_id_to_shard_id(state_record_to_account_id(state_record), shard_layout)
 }
@@ -808,6 +948,54 @@ impl RuntimeAdapter for NightshadeRuntime {
         }
     }
 
+    fn validate_tx_full(
+        &self,
+        gas_price: Balance,
+        state_root: Option<StateRoot>,
+        transaction: &SignedTransaction,
+        epoch_id: &EpochId,
+        current_protocol_version: ProtocolVersion,
+    ) -> Result<TxValidationResult, Error> {
+        let runtime_config = self.runtime_config_store.get_config(current_protocol_version);
+        let mut result = TxValidationResult {
+            violations: transaction_limit_violations(
+                &runtime_config.wasm_config.limit_config,
+                transaction,
+            ),
+            gas_burnt: None,
+        };
+        if !result.violations.is_empty() {
+            return Ok(result);
+        }
+        match validate_transaction(
+            runtime_config,
+            gas_price,
+            transaction,
+            true,
+            current_protocol_version,
+        ) {
+            Ok(cost) => result.gas_burnt = Some(cost.gas_burnt),
+            Err(RuntimeError::InvalidTxError(err)) => result.violations.push(err),
+            Err(RuntimeError::StorageError(err)) => {
+                return Err(Error::from(ErrorKind::StorageError(err)))
+            }
+            Err(err) => unreachable!("Unexpected RuntimeError error {:?}", err),
+        }
+        if result.violations.is_empty() && state_root.is_some() {
+            if let Some(err) = self.validate_tx(
+                gas_price,
+                state_root,
+                transaction,
+                true,
+                epoch_id,
+                current_protocol_version,
+            )? {
+                result.violations.push(err);
+            }
+        }
+        Ok(result)
+    }
+
     fn prepare_transactions(
         &self,
         gas_price: Balance,
@@ -817,9 +1005,9 @@ impl RuntimeAdapter for NightshadeRuntime {
         state_root: StateRoot,
         next_block_height: BlockHeight,
         pool_iterator: &mut dyn PoolIterator,
-        chain_validate: &mut dyn FnMut(&SignedTransaction) -> bool,
+        chain_validate: &mut dyn FnMut(&SignedTransaction) -> Result<(), InvalidTxError>,
         current_protocol_version: ProtocolVersion,
-    ) -> Result<Vec<SignedTransaction>, Error> {
+    ) -> Result<PreparedTransactions, Error> {
         let shard_uid = self.get_shard_uid_from_epoch_id(shard_id, epoch_id)?;
         let mut state_update = self.tries.new_trie_update(shard_uid, state_root);
 
@@ -828,6 +1016,7 @@ impl RuntimeAdapter for NightshadeRuntime {
         // TODO: Update gas limit for transactions
         let transactions_gas_limit = gas_limit / 2;
         let mut transactions = vec![];
+        let mut rejected = vec![];
         let mut num_checked_transactions = 0;
 
         let runtime_config = self.runtime_config_store.get_config(current_protocol_version);
@@ -837,7 +1026,9 @@ impl RuntimeAdapter for NightshadeRuntime {
                 while let Some(tx) = iter.next() {
                     num_checked_transactions += 1;
                     // Verifying the transaction is on the same chain and hasn't expired yet.
-                    if chain_validate(&tx) {
+                    if let Err(err) = chain_validate(&tx) {
+                        rejected.push((tx, err));
+                    } else {
                         // Verifying the validity of the transaction based on the current state.
                         match verify_and_charge_transaction(
                             runtime_config,
@@ -854,8 +1045,9 @@ impl RuntimeAdapter for NightshadeRuntime {
                                 total_gas_burnt += verification_result.gas_burnt;
                                 break;
                             }
-                            Err(RuntimeError::InvalidTxError(_err)) => {
+                            Err(RuntimeError::InvalidTxError(err)) => {
                                 state_update.rollback();
+                                rejected.push((tx, err));
                             }
                             Err(RuntimeError::StorageError(err)) => {
                                 return Err(Error::from(ErrorKind::StorageError(err)))
@@ -869,7 +1061,7 @@ impl RuntimeAdapter for NightshadeRuntime {
             }
         }
         debug!(target: "runtime", "Transaction filtering results {} valid out of {} pulled from the pool", transactions.len(), num_checked_transactions);
-        Ok(transactions)
+        Ok(PreparedTransactions { transactions, rejected })
     }
 
     fn verify_validator_signature(
@@ -976,12 +1168,19 @@ impl RuntimeAdapter for NightshadeRuntime {
             block_height,
         );
 
-        for (validator, may_be_signature) in info.iter().zip(approvals.iter()) {
-            if let Some(signature) = may_be_signature {
-                if !signature.verify(message_to_sign.as_ref(), &validator.public_key) {
-                    return Err(ErrorKind::InvalidApprovals.into());
-                }
-            }
+        let checks: Vec<_> = info
+            .iter()
+            .zip(approvals.iter())
+            .filter_map(|(validator, may_be_signature)| {
+                may_be_signature.as_ref().map(|signature| SignatureCheck {
+                    signature,
+                    data: message_to_sign.as_ref(),
+                    public_key: &validator.public_key,
+                })
+            })
+            .collect();
+        if verification_pool::find_invalid(&checks).is_some() {
+            return Err(ErrorKind::InvalidApprovals.into());
         }
         let stakes = info
             .iter()
@@ -1018,15 +1217,20 @@ impl RuntimeAdapter for NightshadeRuntime {
             block_height,
         );
 
-        for ((validator, is_slashed), may_be_signature) in info.into_iter().zip(approvals.iter()) {
+        let mut checks = vec![];
+        for ((validator, is_slashed), may_be_signature) in info.iter().zip(approvals.iter()) {
             if let Some(signature) = may_be_signature {
-                if is_slashed || !signature.verify(message_to_sign.as_ref(), &validator.public_key)
-                {
+                if *is_slashed {
                     return Ok(false);
                 }
+                checks.push(SignatureCheck {
+                    signature,
+                    data: message_to_sign.as_ref(),
+                    public_key: &validator.public_key,
+                });
             }
         }
-        Ok(true)
+        Ok(verification_pool::find_invalid(&checks).is_none())
     }
 
     fn get_epoch_block_producers_ordered(
@@ -1236,24 +1440,10 @@ impl RuntimeAdapter for NightshadeRuntime {
     }
 
     fn get_gc_stop_height(&self, block_hash: &CryptoHash) -> BlockHeight {
-        (|| -> Result<BlockHeight, Error> {
-            let epoch_manager = self.epoch_manager.read();
-            // an epoch must have a first block.
-            let epoch_first_block = *epoch_manager.get_block_info(block_hash)?.epoch_first_block();
-            let epoch_first_block_info = epoch_manager.get_block_info(&epoch_first_block)?;
-            // maintain pointers to avoid cloning.
-            let mut last_block_in_prev_epoch = *epoch_first_block_info.prev_hash();
-            let mut epoch_start_height = *epoch_first_block_info.height();
-            for _ in 0..self.gc_num_epochs_to_keep - 1 {
-                let epoch_first_block =
-                    *epoch_manager.get_block_info(&last_block_in_prev_epoch)?.epoch_first_block();
-                let epoch_first_block_info = epoch_manager.get_block_info(&epoch_first_block)?;
-                epoch_start_height = *epoch_first_block_info.height();
-                last_block_in_prev_epoch = *epoch_first_block_info.prev_hash();
-            }
-            Ok(epoch_start_height)
-        }())
-        .unwrap_or(self.genesis_config.genesis_height)
+        let epoch_manager = self.epoch_manager.read();
+        epoch_manager
+            .get_gc_stop_height(block_hash, self.gc_num_epochs_to_keep)
+            .unwrap_or(self.genesis_config.genesis_height)
     }
 
     fn epoch_exists(&self, epoch_id: &EpochId) -> bool {
@@ -1405,15 +1595,10 @@ impl RuntimeAdapter for NightshadeRuntime {
         generate_storage_proof: bool,
         is_new_chunk: bool,
         is_first_block_with_chunk_of_version: bool,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Result<ApplyTransactionResult, Error> {
         let trie = self.get_trie_for_shard(shard_id, prev_block_hash)?;
-
-        // TODO (#6316): support chunk nodes caching for TrieRecordingStorage
-        if generate_storage_proof {
-            panic!("Storage proof generation is not enabled yet");
-        }
-        // let trie = if generate_storage_proof { trie.recording_reads() } else { trie };
+        let trie = if generate_storage_proof { trie.recording_reads() } else { trie };
         match self.process_state_update(
             trie,
             *state_root,
@@ -1497,6 +1682,16 @@ impl RuntimeAdapter for NightshadeRuntime {
     ) -> Result<QueryResponse, near_chain::near_chain_primitives::error::QueryError> {
         match request {
             QueryRequest::ViewAccount { account_id } => {
+                if self.account_certainly_missing(&shard_uid, account_id, block_height, block_hash)
+                {
+                    return Err(
+                        near_chain::near_chain_primitives::error::QueryError::UnknownAccount {
+                            requested_account_id: account_id.clone(),
+                            block_height,
+                            block_hash: *block_hash,
+                        },
+                    );
+                }
                 let account = self
                     .view_account(&shard_uid, *state_root, account_id)
                     .map_err(|err| {
@@ -1522,47 +1717,38 @@ impl RuntimeAdapter for NightshadeRuntime {
                     block_hash: *block_hash,
                 })
             }
-            QueryRequest::CallFunction { account_id, method_name, args } => {
-                let mut logs = vec![];
-                let (epoch_height, current_protocol_version) = {
-                    let epoch_manager = self.epoch_manager.read();
-                    let epoch_info = epoch_manager.get_epoch_info(epoch_id).map_err(|err| {
-                        near_chain::near_chain_primitives::error::QueryError::from_epoch_error(
-                            err,
-                            block_height,
-                            *block_hash,
-                        )
-                    })?;
-                    (epoch_info.epoch_height(), epoch_info.protocol_version())
-                };
-
-                let call_function_result = self
-                    .call_function(
-                        &shard_uid,
-                        *state_root,
-                        block_height,
-                        block_timestamp,
-                        prev_block_hash,
-                        block_hash,
-                        epoch_height,
-                        epoch_id,
-                        account_id,
-                        method_name,
-                        args.as_ref(),
-                        &mut logs,
-                        &self.epoch_manager,
-                        current_protocol_version,
-                    )
-                    .map_err(|err| near_chain::near_chain_primitives::error::QueryError::from_call_function_error(err, block_height, *block_hash))?;
-                Ok(QueryResponse {
-                    kind: QueryResponseKind::CallResult(CallResult {
-                        result: call_function_result,
-                        logs,
-                    }),
+            QueryRequest::CallFunction { account_id, method_name, args } => self
+                .query_call_function(
                     block_height,
-                    block_hash: *block_hash,
-                })
-            }
+                    block_timestamp,
+                    prev_block_hash,
+                    block_hash,
+                    epoch_id,
+                    &shard_uid,
+                    state_root,
+                    account_id,
+                    method_name,
+                    args.as_ref(),
+                    None,
+                ),
+            QueryRequest::CallFunctionWithOverrides {
+                account_id,
+                method_name,
+                args,
+                overrides,
+            } => self.query_call_function(
+                block_height,
+                block_timestamp,
+                prev_block_hash,
+                block_hash,
+                epoch_id,
+                &shard_uid,
+                state_root,
+                account_id,
+                method_name,
+                args.as_ref(),
+                Some(overrides),
+            ),
             QueryRequest::ViewState { account_id, prefix } => {
                 let view_state_result = self
                     .view_state(&shard_uid, *state_root, account_id, prefix.as_ref())
@@ -1602,6 +1788,50 @@ impl RuntimeAdapter for NightshadeRuntime {
                     block_hash: *block_hash,
                 })
             }
+            QueryRequest::ViewAccounts { account_ids } => {
+                // All the accounts are read through the same trie.
+                let state_update = self.tries.new_trie_update_view(shard_uid, *state_root);
+                let mut accounts = BTreeMap::new();
+                for account_id in account_ids {
+                    if self.account_certainly_missing(
+                        &shard_uid,
+                        account_id,
+                        block_height,
+                        block_hash,
+                    ) {
+                        let err =
+                            near_chain::near_chain_primitives::error::QueryError::UnknownAccount {
+                                requested_account_id: account_id.clone(),
+                                block_height,
+                                block_hash: *block_hash,
+                            };
+                        accounts
+                            .insert(account_id.clone(), AccountViewOrError::Error(err.to_string()));
+                        continue;
+                    }
+                    let account = match self.trie_viewer.view_account(&state_update, account_id) {
+                        Ok(account) => AccountViewOrError::Account(account.into()),
+                        // Only the errors about the account are reported for
+                        // the account.
+                        Err(err) => match near_chain::near_chain_primitives::error::QueryError::from_view_account_error(
+                            err,
+                            block_height,
+                            *block_hash,
+                        ) {
+                            err @ near_chain::near_chain_primitives::error::QueryError::InternalError { .. } => {
+                                return Err(err)
+                            }
+                            err => AccountViewOrError::Error(err.to_string()),
+                        },
+                    };
+                    accounts.insert(account_id.clone(), account);
+                }
+                Ok(QueryResponse {
+                    kind: QueryResponseKind::ViewAccounts(ViewAccountsResult { accounts }),
+                    block_height,
+                    block_hash: *block_hash,
+                })
+            }
             QueryRequest::ViewAccessKey { account_id, public_key } => {
                 let access_key = self
                     .view_access_key(&shard_uid, *state_root, account_id, public_key)
@@ -1629,6 +1859,14 @@ impl RuntimeAdapter for NightshadeRuntime {
         epoch_manager.get_validator_info(epoch_id).map_err(|e| e.into())
     }
 
+    fn get_validators_preview(
+        &self,
+        block_hash: &CryptoHash,
+    ) -> Result<EpochValidatorsPreview, Error> {
+        let mut epoch_manager = self.epoch_manager.write();
+        epoch_manager.preview_validators(block_hash).map_err(|e| e.into())
+    }
+
     /// Returns StorageError when storage is inconsistent.
     /// This is possible with the used isolation level + running ViewClient in a separate thread
     /// `block_hash` is a block whose `prev_state_root` is `state_root`
@@ -1706,50 +1944,13 @@ impl RuntimeAdapter for NightshadeRuntime {
         state_root: &StateRoot,
         next_epoch_shard_layout: &ShardLayout,
     ) -> Result<HashMap<ShardUId, StateRoot>, Error> {
-        let trie = self.tries.get_view_trie_for_shard(shard_uid);
-        let shard_id = shard_uid.shard_id();
-        let new_shards = next_epoch_shard_layout
-            .get_split_shard_uids(shard_id)
-            .ok_or(ErrorKind::InvalidShardId(shard_id))?;
-        let mut state_roots: HashMap<_, _> =
-            new_shards.iter().map(|shard_uid| (*shard_uid, StateRoot::default())).collect();
-        let split_shard_ids: HashSet<_> = new_shards.into_iter().collect();
-        let checked_account_id_to_shard_id = |account_id: &AccountId| {
-            let new_shard_uid = account_id_to_shard_uid(account_id, next_epoch_shard_layout);
-            // check that all accounts in the shard are mapped the shards that this shard will split
-            // to according to shard layout
-            assert!(
-                split_shard_ids.contains(&new_shard_uid),
-                "Inconsistent shard_layout specs. Account {:?} in shard {:?} and in shard {:?}, but the former is not parent shard for the latter",
-                account_id,
-                shard_uid,
-                new_shard_uid,
-            );
-            new_shard_uid
-        };
-
-        let state_root_node = trie.retrieve_root_node(state_root)?;
-        let num_parts = get_num_state_parts(state_root_node.memory_usage);
-        debug!(target: "runtime", "splitting state for shard {} to {} parts to build new states", shard_id, num_parts);
-        for part_id in 0..num_parts {
-            let trie_items =
-                trie.get_trie_items_for_part(PartId::new(part_id, num_parts), state_root)?;
-            let (store_update, new_state_roots) = self.tries.add_values_to_split_states(
-                &state_roots,
-                trie_items.into_iter().map(|(key, value)| (key, Some(value))).collect(),
-                &checked_account_id_to_shard_id,
-            )?;
-            state_roots = new_state_roots;
-            store_update.commit()?;
-        }
-        state_roots = apply_delayed_receipts(
+        build_state_for_split_shards(
+            &self.tries,
             &self.tries,
             shard_uid,
-            *state_root,
-            state_roots,
-            &checked_account_id_to_shard_id,
-        )?;
-        Ok(state_roots)
+            state_root,
+            next_epoch_shard_layout,
+        )
     }
 
     fn apply_state_part(
@@ -1766,6 +1967,9 @@ impl RuntimeAdapter for NightshadeRuntime {
             Trie::apply_state_part(state_root, part_id, part);
         let tries = self.get_tries();
         let shard_uid = self.get_shard_uid_from_epoch_id(shard_id, epoch_id)?;
+        if let Some(filters) = &self.account_filters {
+            filters.remove_shard(&shard_uid);
+        }
         let (store_update, _) = tries.apply_all(&trie_changes, shard_uid);
         self.precompile_contracts(epoch_id, contract_codes)?;
         Ok(store_update.commit()?)
@@ -1930,10 +2134,11 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
         contract_id: &AccountId,
         method_name: &str,
         args: &[u8],
+        overrides: &CallFunctionOverrides,
         logs: &mut Vec<String>,
         epoch_info_provider: &dyn EpochInfoProvider,
         current_protocol_version: ProtocolVersion,
-    ) -> Result<Vec<u8>, node_runtime::state_viewer::errors::CallFunctionError> {
+    ) -> Result<(Vec<u8>, Gas), node_runtime::state_viewer::errors::CallFunctionError> {
         let state_update = self.tries.new_trie_update_view(*shard_uid, state_root);
         let view_state = ViewApplyState {
             block_height: height,
@@ -1945,12 +2150,13 @@ impl node_runtime::adapter::ViewRuntimeAdapter for NightshadeRuntime {
             current_protocol_version,
             cache: Some(Arc::new(StoreCompiledContractCache { store: self.tries.get_store() })),
         };
-        self.trie_viewer.call_function(
+        self.trie_viewer.call_function_with_overrides(
             state_update,
             view_state,
             contract_id,
             method_name,
             args,
+            overrides,
             logs,
             epoch_info_provider,
         )
@@ -2929,7 +3135,7 @@ mod test {
             "test_validator_get_validator_info",
             vec![validators.clone(), vec![validators[0].clone()]],
             2,
-            TrackedConfig::Accounts(vec![validators[1].clone()]),
+            TrackedConfig::Accounts(vec![validators[1].clone().into()]),
             true,
         );
         let block_producers: Vec<_> = validators
@@ -3,15 +3,16 @@ use std::sync::{Arc, RwLock};
 use crate::append_only_map::AppendOnlyMap;
 use near_chain_configs::ClientConfig;
 use near_epoch_manager::EpochManager;
+use near_primitives::account_pattern::AccountPattern;
 use near_primitives::errors::EpochError;
 use near_primitives::hash::CryptoHash;
-use near_primitives::shard_layout::account_id_to_shard_id;
 use near_primitives::types::{AccountId, EpochId, ShardId};
 
 const POISONED_LOCK_ERR: &str = "The lock was poisoned.";
 
 pub enum TrackedConfig {
-    Accounts(Vec<AccountId>),
+    /// The shards of the accounts matching any of the patterns.
+    Accounts(Vec<AccountPattern>),
     AllShards,
 }
 
@@ -20,6 +21,8 @@ impl TrackedConfig {
         TrackedConfig::Accounts(vec![])
     }
 
+    /// `tracked_shards` and `tracked_accounts` are mutually exclusive, which
+    /// `load_config` checks.
     pub fn from_config(config: &ClientConfig) -> Self {
         if config.tracked_shards.is_empty() {
             TrackedConfig::Accounts(config.tracked_accounts.clone())
@@ -59,9 +62,10 @@ impl ShardTracker {
                 let shard_layout = epoch_manager.get_shard_layout(epoch_id)?;
                 let tracking_mask = self.tracking_shards.get_or_insert(epoch_id, || {
                     let mut tracking_mask = vec![false; shard_layout.num_shards() as usize];
-                    for account_id in tracked_accounts {
-                        let shard_id = account_id_to_shard_id(account_id, shard_layout);
-                        *tracking_mask.get_mut(shard_id as usize).unwrap() = true;
+                    for pattern in tracked_accounts {
+                        for shard_id in pattern.shard_ids(shard_layout) {
+                            *tracking_mask.get_mut(shard_id as usize).unwrap() = true;
+                        }
                     }
                     tracking_mask
                 });
@@ -144,11 +148,13 @@ mod tests {
     use near_primitives::epoch_manager::{AllEpochConfig, EpochConfig, ShardConfig};
     use near_primitives::hash::CryptoHash;
     use near_primitives::types::validator_stake::ValidatorStake;
-    use near_primitives::types::{BlockHeight, EpochId, NumShards, ProtocolVersion, ShardId};
+    use near_primitives::types::{
+        AccountId, BlockHeight, EpochId, NumShards, ProtocolVersion, ShardId,
+    };
     use near_store::test_utils::create_test_store;
 
-    use super::{account_id_to_shard_id, ShardTracker};
-    use near_primitives::shard_layout::ShardLayout;
+    use super::ShardTracker;
+    use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
 
     use crate::shard_tracker::TrackedConfig;
     use crate::shard_tracker::POISONED_LOCK_ERR;
@@ -283,6 +289,17 @@ mod tests {
             get_all_shards_will_care_about(&tracker, num_shards, &CryptoHash::default()),
             total_tracked_shards
         );
+
+        // Subaccounts can be in any shard.
+        let epoch_manager = get_epoch_manager(PROTOCOL_VERSION, num_shards, None);
+        let tracker = ShardTracker::new(
+            TrackedConfig::Accounts(vec!["*.test1".parse().unwrap()]),
+            Arc::new(RwLock::new(epoch_manager)),
+        );
+        assert_eq!(
+            get_all_shards_care_about(&tracker, num_shards, &CryptoHash::default()),
+            (0..num_shards).collect()
+        );
     }
 
     #[test]
@@ -322,9 +339,10 @@ mod tests {
             1,
             Some(shard_config),
         )));
-        let tracked_accounts = vec!["near".parse().unwrap(), "zoo".parse().unwrap()];
+        let tracked_accounts: Vec<AccountId> =
+            vec!["near".parse().unwrap(), "zoo".parse().unwrap()];
         let tracker = ShardTracker::new(
-            TrackedConfig::Accounts(tracked_accounts.clone()),
+            TrackedConfig::Accounts(tracked_accounts.iter().cloned().map(Into::into).collect()),
             epoch_manager.clone(),
         );
 
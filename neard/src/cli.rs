@@ -1,10 +1,20 @@
 use crate::log_config_watcher::{LogConfigWatcher, UpdateBehavior};
+use actix::Addr;
 use clap::{Args, Parser};
+use near_amend_genesis::AmendGenesisCommand;
 use near_chain_configs::GenesisValidationMode;
-use near_o11y::{default_subscriber, BuildEnvFilterError, ColorOutput, EnvFilterBuilder};
-use near_primitives::types::{Gas, NumSeats, NumShards};
-use near_state_viewer::StateViewerSubCommand;
-use near_store::db::RocksDB;
+use near_network::PeerManagerActor;
+use near_o11y::{
+    default_subscriber_with_format, BuildEnvFilterError, ColorOutput, EnvFilterBuilder,
+    LogFilterChange, LogFilterStatus, LogFormat,
+};
+use near_primitives::runtime::config_store::RuntimeConfigStore;
+use near_primitives::types::{Gas, NumSeats, NumShards, ProtocolVersion, ShardId};
+use near_primitives::version::PROTOCOL_VERSION;
+use near_state_viewer::{
+    ChunkApplyBenchmarkCmd, DataSourceArgs, FailureKind, OutputFormat, StateViewerSubCommand,
+};
+use near_store::db::{DbMetadata, RocksDB};
 use nearcore::get_store_path;
 use std::fs;
 use std::net::SocketAddr;
@@ -26,7 +36,16 @@ pub(super) struct NeardCmd {
 
 impl NeardCmd {
     pub(super) fn parse_and_run() -> Result<(), RunError> {
-        let neard_cmd = Self::parse();
+        let neard_cmd = Self::try_parse().unwrap_or_else(|err| {
+            // The state viewer exits with code 2 when the data isn't found, so
+            // its usage errors exit with its own code for invalid arguments.
+            let mut args = std::env::args().skip(1);
+            if err.use_stderr() && args.any(|arg| arg == "view-state" || arg == "view_state") {
+                let _ = err.print();
+                std::process::exit(FailureKind::InvalidArgument.exit_code());
+            }
+            err.exit()
+        });
         let verbose = neard_cmd.opts.verbose.as_deref();
         let env_filter =
             EnvFilterBuilder::from_env().verbose(verbose).finish().map_err(RunError::EnvFilter)?;
@@ -37,7 +56,12 @@ impl NeardCmd {
         } else {
             env_filter
         };
-        let _subscriber = default_subscriber(env_filter, neard_cmd.opts.color).global();
+        let _subscriber = default_subscriber_with_format(
+            env_filter,
+            neard_cmd.opts.color,
+            neard_cmd.opts.log_format,
+        )
+        .global();
 
         info!(
             target: "neard",
@@ -89,12 +113,33 @@ impl NeardCmd {
             }
 
             NeardSubCommand::StateViewer(cmd) => {
-                cmd.subcmd.run(&home_dir, genesis_validation, cmd.readwrite);
+                cmd.subcmd.run(
+                    &home_dir,
+                    genesis_validation,
+                    cmd.readwrite,
+                    cmd.data_source,
+                    cmd.format,
+                );
             }
 
             NeardSubCommand::RecompressStorage(cmd) => {
                 cmd.run(&home_dir);
             }
+            NeardSubCommand::PrecompileContracts(cmd) => {
+                cmd.run(&home_dir, genesis_validation);
+            }
+            NeardSubCommand::ValidateConfig(cmd) => cmd.run(&home_dir),
+            NeardSubCommand::ProtocolConfigDiff(cmd) => cmd.run(),
+            NeardSubCommand::LogFilter(cmd) => cmd.run(),
+            NeardSubCommand::Network(cmd) => cmd.run(&home_dir),
+            NeardSubCommand::Database(cmd) => cmd.run(&home_dir),
+            NeardSubCommand::Benchmark(cmd) => cmd.run(&home_dir, genesis_validation),
+            NeardSubCommand::AmendGenesis(cmd) => {
+                if let Err(err) = cmd.run() {
+                    error!(target: "neard", "amend-genesis failed: {:#}", err);
+                    std::process::exit(1);
+                }
+            }
         };
         Ok(())
     }
@@ -112,9 +157,17 @@ pub(crate) enum RunError {
 pub(super) struct StateViewerCommand {
     /// By default state viewer opens rocks DB in the read only mode, which allows it to run
     /// multiple instances in parallel and be sure that no unintended changes get written to the DB.
-    /// In case an operation needs to write to caches, a read-write mode may be needed.
+    /// Subcommands which modify the DB open it in read-write mode regardless of this flag.
     #[clap(long, short = 'w')]
     readwrite: bool,
+    #[clap(flatten)]
+    data_source: DataSourceArgs,
+    /// Output of the subcommands, as text or as JSON, or as CSV for the ones
+    /// listing records.  A subcommand refuses a format it doesn't support.
+    /// Failures exit with code 2 if the data isn't found, 3 if it's corrupted,
+    /// 4 if the arguments are invalid and 5 if the database is locked.
+    #[clap(long, arg_enum, default_value = "text")]
+    format: OutputFormat,
     #[clap(subcommand)]
     subcmd: StateViewerSubCommand,
 }
@@ -144,6 +197,10 @@ struct NeardOpts {
     /// Whether the log needs to be colored.
     #[clap(long, arg_enum, default_value = "auto")]
     pub color: ColorOutput,
+    /// Format of the log.  With `json` every record is a line of JSON with
+    /// `timestamp`, `level`, `target`, `message`, `fields` and `spans`.
+    #[clap(long, arg_enum, default_value = "text")]
+    pub log_format: LogFormat,
 }
 
 #[derive(Parser)]
@@ -202,11 +259,73 @@ pub(super) enum NeardSubCommand {
     /// tool, it is planned to be removed by the end of 2022.
     #[clap(alias = "recompress_storage")]
     RecompressStorage(RecompressStorageSubCommand),
+
+    /// Compile contracts deployed in the tracked shards to warm up the compiled
+    /// contract cache.
+    ///
+    /// After an upgrade to a version with a new wasm compiler, the first call
+    /// to every contract pays the compilation latency.  This command compiles
+    /// the contracts with the VM of the current protocol version up front.
+    ///
+    /// The node has to be stopped while this runs.  To compile contracts in
+    /// a running node use `neard run --precompile-contracts` instead.  Progress
+    /// is saved regularly so an interrupted run continues where it stopped.
+    PrecompileContracts(PrecompileContractsCmd),
+
+    /// Writes a copy of a genesis config and its records with the changes
+    /// from a JSON file applied: chain id, genesis time, epoch length,
+    /// protocol version, validators and account balances and keys.
+    ///
+    /// The records are streamed, so this works for dumps of mainnet state.
+    /// The total supply is recomputed and the result is validated like on
+    /// node startup, with all problems reported.
+    AmendGenesis(AmendGenesisCommand),
+
+    /// Checks the config, the genesis and the keys in the home directory and
+    /// prints every problem found, with the path of the offending field.
+    ///
+    /// Exits with a non-zero code if any problem is an error.  Warnings alone
+    /// don't affect the exit code.
+    ValidateConfig(ValidateConfigCmd),
+
+    /// Prints the parameters of the runtime config which differ between two
+    /// protocol versions, including the fees and the VM limits, and the ones
+    /// only one of them has.
+    ///
+    /// The JSON output has the format of the `EXPERIMENTAL_protocol_config_diff`
+    /// RPC method.
+    ProtocolConfigDiff(ProtocolConfigDiffCmd),
+
+    /// Shows or changes the log filter of a running node through its RPC
+    /// server, without restarting it.  The node serves these requests only
+    /// from localhost.
+    ///
+    /// Temporary directives, e.g. `network=debug` for ten minutes, are removed
+    /// by the node once they expire.  Debug and trace records over the
+    /// per-target rate limit are dropped and counted.
+    LogFilter(LogFilterCmd),
+
+    /// Exports the peers banned by the node to a file and bans the peers of
+    /// such a file, to ban a peer on all nodes of a fleet once one of them
+    /// banned it.  Also lists the peers prospective blacklist entries would
+    /// refuse.
+    Network(NetworkCmd),
+
+    /// Maintenance of the database of the node.
+    Database(DatabaseCmd),
+
+    /// Benchmarks of the machine on the data of the node, to tell whether
+    /// it keeps up with the chain before it's made a validator.
+    Benchmark(BenchmarkCmd),
 }
 
 #[derive(Parser)]
 pub(super) struct InitCmd {
-    /// Download the verified NEAR genesis file automatically.
+    /// Download the verified NEAR genesis file automatically.  The download
+    /// is checked against the SHA-256 checksum published at its URL with
+    /// `.sha256` appended, and an interrupted one is resumed by running the
+    /// command again.  The URLs and checksums of the files downloaded are
+    /// recorded in provenance.json.
     #[clap(long)]
     download_genesis: bool,
     /// Download the verified NEAR config file automatically.
@@ -244,6 +363,9 @@ pub(super) struct InitCmd {
     /// from genesis configuration will be taken.
     #[clap(long)]
     max_gas_burnt_view: Option<Gas>,
+    /// Overwrite the config and genesis files if they exist already.
+    #[clap(long)]
+    force: bool,
 }
 
 /// Warns if unsupported build of the executable is used on mainnet or testnet.
@@ -306,6 +428,7 @@ impl InitCmd {
             self.download_config_url.as_deref(),
             self.boot_nodes.as_deref(),
             self.max_gas_burnt_view,
+            self.force,
         ) {
             error!("Failed to initialize configs: {:#}", e);
         }
@@ -353,6 +476,14 @@ pub(super) struct RunCmd {
     /// configuration will be taken.
     #[clap(long)]
     max_gas_burnt_view: Option<Gas>,
+    /// Compile contracts deployed in the tracked shards in the background to
+    /// warm up the compiled contract cache.  See `neard precompile-contracts`.
+    #[clap(long)]
+    precompile_contracts: bool,
+    /// Run with a database made by `neard database clone`, which the node
+    /// refuses to do otherwise so that a clone isn't used by accident.
+    #[clap(long)]
+    allow_cloned_db: bool,
 }
 
 impl RunCmd {
@@ -406,6 +537,8 @@ impl RunCmd {
         if self.max_gas_burnt_view.is_some() {
             near_config.client_config.max_gas_burnt_view = self.max_gas_burnt_view;
         }
+        near_config.precompile_contracts = self.precompile_contracts;
+        near_config.allow_cloned_db = self.allow_cloned_db;
 
         #[cfg(feature = "sandbox")]
         {
@@ -423,11 +556,31 @@ impl RunCmd {
         let (tx, rx) = oneshot::channel::<()>();
         let sys = actix::System::new();
         sys.block_on(async move {
-            let nearcore::NearNode { rpc_servers, .. } =
-                nearcore::start_with_config_and_synchronization(home_dir, near_config, Some(tx))
-                    .expect("start_with_config");
+            let nearcore::NearNode { rpc_servers, network, .. } =
+                match nearcore::start_with_config_and_synchronization(
+                    home_dir,
+                    near_config,
+                    Some(tx),
+                ) {
+                    Ok(node) => node,
+                    Err(err) => match err.downcast_ref::<nearcore::UnsupportedDbVersion>() {
+                        Some(err) => {
+                            error!(
+                                target: "neard",
+                                db_version = err.db_version,
+                                supported_version = err.supported_version,
+                                "{}", err
+                            );
+                            std::process::exit(1);
+                        }
+                        None => panic!("start_with_config: {:?}", err),
+                    },
+                };
 
-            let sig = wait_for_interrupt_signal(home_dir, rx).await;
+            let sig = wait_for_interrupt_signal(home_dir, network, rx).await;
+            if sig == CLIENT_ACTOR_DIED {
+                nearcore::shutdown_report::report_fatal_error(sig);
+            }
             warn!(target: "neard", "{}, stopping... this may take a few minutes.", sig);
             futures::future::join_all(rpc_servers.iter().map(|(name, server)| async move {
                 server.stop(true).await;
@@ -442,15 +595,26 @@ impl RunCmd {
     }
 }
 
+/// What [`wait_for_interrupt_signal`] returns when the client stopped.
+const CLIENT_ACTOR_DIED: &str = "ClientActor died";
+
 #[cfg(not(unix))]
-async fn wait_for_interrupt_signal(_home_dir: &Path, mut _rx_crash: Receiver<()>) -> &str {
+async fn wait_for_interrupt_signal(
+    _home_dir: &Path,
+    _network: Addr<PeerManagerActor>,
+    mut _rx_crash: Receiver<()>,
+) -> &str {
     // TODO(#6372): Support graceful shutdown on windows.
     tokio::signal::ctrl_c().await.unwrap();
     "Ctrl+C"
 }
 
 #[cfg(unix)]
-async fn wait_for_interrupt_signal(home_dir: &Path, mut rx_crash: Receiver<()>) -> &str {
+async fn wait_for_interrupt_signal(
+    home_dir: &Path,
+    network: Addr<PeerManagerActor>,
+    mut rx_crash: Receiver<()>,
+) -> &str {
     let watched_path = home_dir.join("log_config.json");
     let log_config_watcher = LogConfigWatcher { watched_path };
     // Apply the logging config file if it exists.
@@ -467,13 +631,44 @@ async fn wait_for_interrupt_signal(home_dir: &Path, mut rx_crash: Receiver<()>)
              _ = sigterm.recv() => "SIGTERM",
              _ = sighup.recv() => {
                 log_config_watcher.update(UpdateBehavior::UpdateOrReset);
+                reload_blacklists_enabled(home_dir, &network);
                 continue;
              },
-             _ = &mut rx_crash => "ClientActor died",
+             _ = &mut rx_crash => CLIENT_ACTOR_DIED,
         };
     }
 }
 
+/// Hands the `enabled` flags of the blacklists in `config.json` over to the
+/// peer manager.  The entries of the lists are only read at startup.
+#[cfg(unix)]
+fn reload_blacklists_enabled(home_dir: &Path, network: &Addr<PeerManagerActor>) {
+    use near_network::types::PeerManagerMessageRequest;
+    use near_network_primitives::types::SetBlacklistsEnabled;
+
+    let config_path = home_dir.join(nearcore::config::CONFIG_FILENAME);
+    match nearcore::config::Config::from_file(&config_path) {
+        Ok(config) => {
+            let lists = config
+                .network
+                .blacklist
+                .lists()
+                .into_iter()
+                .map(|list| (list.name, list.enabled))
+                .collect();
+            network.do_send(PeerManagerMessageRequest::SetBlacklistsEnabled(
+                SetBlacklistsEnabled { lists },
+            ));
+        }
+        Err(err) => error!(
+            target: "neard",
+            "Failed to reload the blacklists from {}: {:#}",
+            config_path.display(),
+            err
+        ),
+    }
+}
+
 #[derive(Parser)]
 pub(super) struct LocalnetCmd {
     /// Number of non-validators to initialize the localnet with.
@@ -551,6 +746,72 @@ impl RecompressStorageSubCommand {
     }
 }
 
+#[derive(Args)]
+pub(super) struct PrecompileContractsCmd {
+    /// Only compile contracts in the given shard.  May be given multiple
+    /// times.  By default contracts in all tracked shards are compiled.
+    #[clap(long)]
+    shard_id: Vec<ShardId>,
+    /// File with IDs of accounts, one per line, whose contracts to compile.
+    /// By default all deployed contracts are compiled.
+    #[clap(long)]
+    account_ids: Option<PathBuf>,
+    /// Ignore progress saved by an interrupted run and start from scratch.
+    #[clap(long)]
+    restart: bool,
+}
+
+impl PrecompileContractsCmd {
+    pub(super) fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
+        if let Err(err) = self.run_impl(home_dir, genesis_validation) {
+            error!("{:#}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn run_impl(
+        self,
+        home_dir: &Path,
+        genesis_validation: GenesisValidationMode,
+    ) -> anyhow::Result<()> {
+        let near_config = nearcore::config::load_config(home_dir, genesis_validation)?;
+        let account_ids = match self.account_ids {
+            None => None,
+            Some(path) => {
+                let data = fs::read_to_string(&path)
+                    .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+                let account_ids = data
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(|line| {
+                        line.parse().map_err(|err| {
+                            anyhow::anyhow!(
+                                "{}: invalid account ID {:?}: {}",
+                                path.display(),
+                                line,
+                                err
+                            )
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                Some(account_ids)
+            }
+        };
+        let opts = nearcore::PrecompileContractsOpts {
+            shard_ids: self.shard_id,
+            account_ids,
+            restart: self.restart,
+        };
+        let stats = nearcore::precompile_contracts(home_dir, &near_config, opts)?;
+        println!(
+            "Compiled: {}\nAlready cached: {}\nFailed to compile: {}",
+            stats.compiled, stats.already_cached, stats.failed
+        );
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -579,4 +840,526 @@ mod tests {
         ])
         .is_err());
     }
+
+    #[test]
+    fn precompile_contracts_shard_ids() {
+        let cmd =
+            NeardCmd::parse_from(&["test", "precompile-contracts", "--shard-id=0", "--shard-id=2"]);
+        if let NeardSubCommand::PrecompileContracts(scmd) = cmd.subcmd {
+            assert_eq!(scmd.shard_id, vec![0, 2]);
+            assert_eq!(scmd.account_ids, None);
+            assert!(!scmd.restart);
+        } else {
+            panic!("incorrect subcommand");
+        }
+    }
+}
+
+#[derive(Parser)]
+pub(super) struct ValidateConfigCmd {
+    /// Also print every config field which differs from the defaults
+    /// `neard init` uses for the chain.
+    #[clap(long)]
+    against_defaults: bool,
+}
+
+impl ValidateConfigCmd {
+    pub(super) fn run(self, home_dir: &Path) {
+        let validation = nearcore::config_validate::validate_home_dir(home_dir);
+        for problem in &validation.problems {
+            println!("{}", problem);
+        }
+        if self.against_defaults {
+            match (&validation.config, &validation.chain_id) {
+                (Some(config), Some(chain_id)) => {
+                    println!("Fields differing from the {} defaults:", chain_id);
+                    for diff in nearcore::config_validate::diff_against_defaults(config, chain_id) {
+                        println!("  {}", diff);
+                    }
+                }
+                _ => println!("Can't compare against the defaults without config and genesis"),
+            }
+        }
+        if validation.has_errors() {
+            std::process::exit(1);
+        }
+        if validation.problems.is_empty() {
+            println!("No problems found in {}", home_dir.display());
+        }
+    }
+}
+
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ProtocolConfigDiffFormat {
+    Text,
+    Json,
+}
+
+#[derive(Parser)]
+pub(super) struct ProtocolConfigDiffCmd {
+    /// Protocol version to compare from.
+    #[clap(long)]
+    from: ProtocolVersion,
+    /// Protocol version to compare to.
+    #[clap(long)]
+    to: ProtocolVersion,
+    /// Chain whose configs are compared.  Only the configs of old versions of
+    /// testnet differ from the others.
+    #[clap(long, default_value = "mainnet")]
+    chain_id: String,
+    #[clap(long, arg_enum, default_value = "text")]
+    format: ProtocolConfigDiffFormat,
+}
+
+impl ProtocolConfigDiffCmd {
+    pub(super) fn run(self) {
+        for protocol_version in [self.from, self.to] {
+            if protocol_version > PROTOCOL_VERSION {
+                error!(
+                    target: "neard",
+                    "Protocol version {} is above the latest one, {}",
+                    protocol_version,
+                    PROTOCOL_VERSION
+                );
+                std::process::exit(1);
+            }
+        }
+        let store = RuntimeConfigStore::for_chain_id(&self.chain_id);
+        let diff = store.diff(self.from, self.to);
+        match self.format {
+            ProtocolConfigDiffFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&diff).unwrap());
+            }
+            ProtocolConfigDiffFormat::Text => {
+                println!(
+                    "Runtime config of protocol version {} compared to {}:",
+                    diff.to_protocol_version, diff.from_protocol_version
+                );
+                for change in &diff.changed {
+                    println!("  {}: {} -> {}", change.path, change.old_value, change.new_value);
+                }
+                for (what, parameters) in [("added", &diff.added), ("removed", &diff.removed)] {
+                    for parameter in parameters {
+                        println!("  {} {}: {}", what, parameter.path, parameter.value);
+                    }
+                }
+                if diff.changed.is_empty() && diff.added.is_empty() && diff.removed.is_empty() {
+                    println!("  no differences");
+                }
+            }
+        }
+    }
+}
+
+#[derive(Parser)]
+pub(super) struct NetworkCmd {
+    #[clap(subcommand)]
+    subcmd: NetworkSubCommand,
+}
+
+#[derive(Parser)]
+enum NetworkSubCommand {
+    /// Writes the peers currently banned in the peer store to a JSON file.
+    /// Works while the node is running.
+    ExportBans {
+        #[clap(long, parse(from_os_str))]
+        out: PathBuf,
+    },
+    /// Bans the peers of a file written by `export-bans` until the time the
+    /// exporting node unbans them, unless they're banned for longer already.
+    /// The node has to be stopped, the bans take effect once it starts.
+    ImportBans {
+        #[clap(long, parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Prints the peers blacklist entries would refuse, among the peers
+    /// connected to the node and those of its peer store, matched the way the
+    /// `blacklist` is, with the whitelisted and pinned peers exempt.  Works
+    /// while the node is running.
+    AuditBlacklist {
+        /// A blacklist entry, e.g. `10.0.0.0/8` or `1.2.3.4:24567`; may be
+        /// repeated.
+        #[clap(long = "pattern", required = true)]
+        patterns: Vec<String>,
+        /// Address of the RPC server of the node, for its connected peers.
+        #[clap(long, default_value = "127.0.0.1:3030")]
+        rpc_addr: String,
+    },
+    /// Removes the peers last seen over a number of days ago from the peer
+    /// store and compacts it, keeping the whitelisted and pinned peers and
+    /// those still banned.  The node has to be stopped.
+    PurgePeerStore {
+        /// Peers last seen within this many days are kept, 0 to remove the
+        /// peers whatever their age.
+        #[clap(long, default_value = "7")]
+        older_than: u64,
+        /// Only removes the peers the node never connected to.
+        #[clap(long)]
+        unreachable_only: bool,
+    },
+}
+
+impl NetworkCmd {
+    pub(super) fn run(self, home_dir: &Path) {
+        if let Err(err) = self.run_impl(home_dir) {
+            error!(target: "neard", "{:#}", err);
+            std::process::exit(1);
+        }
+    }
+
+    fn run_impl(self, home_dir: &Path) -> anyhow::Result<()> {
+        let config =
+            nearcore::config::Config::from_file(&home_dir.join(nearcore::config::CONFIG_FILENAME))?;
+        let ban_window = config.network.ban_window;
+        let store_path = get_store_path(home_dir);
+        match self.subcmd {
+            NetworkSubCommand::ExportBans { out } => {
+                let store = near_store::create_read_only_store(&store_path, &config.store)?;
+                let ban_file = near_network::BanFile::from_store(&store, ban_window)?;
+                let json = serde_json::to_string_pretty(&ban_file)?;
+                fs::write(&out, json)
+                    .map_err(|err| anyhow::anyhow!("{}: {}", out.display(), err))?;
+                info!(target: "neard", "Exported {} bans to {}", ban_file.bans.len(), out.display());
+            }
+            NetworkSubCommand::ImportBans { file } => {
+                let data = fs::read_to_string(&file)
+                    .map_err(|err| anyhow::anyhow!("{}: {}", file.display(), err))?;
+                let ban_file: near_network::BanFile = serde_json::from_str(&data)
+                    .map_err(|err| anyhow::anyhow!("{}: {}", file.display(), err))?;
+                let store = open_stopped_store(&store_path, &config)?;
+                let imported = ban_file.import_to_store(store, ban_window)?;
+                info!(
+                    target: "neard",
+                    "Imported {} of {} bans from {}",
+                    imported,
+                    ban_file.bans.len(),
+                    file.display()
+                );
+            }
+            NetworkSubCommand::AuditBlacklist { patterns, rpc_addr } => {
+                for pattern in &patterns {
+                    pattern
+                        .parse::<near_network_primitives::types::NormalizedAddr>()
+                        .map_err(|err| anyhow::anyhow!("invalid pattern {:?}: {}", pattern, err))?;
+                }
+                let audit = near_network::BlacklistAudit::new(
+                    &patterns,
+                    &config.network.blacklist_exempt_peers()?,
+                );
+                let url = format!("http://{}", rpc_addr);
+                match actix::System::new().block_on(request_connected_peers(url)) {
+                    Ok(peers) => print_audit("connected", &peers, &audit),
+                    Err(err) => warn!(
+                        target: "neard",
+                        "Skipping the connected peers: {:#}", err
+                    ),
+                }
+                let store = near_store::create_read_only_store(&store_path, &config.store)?;
+                let peers = near_network::stored_peers(&store)?;
+                print_audit("in the peer store", &peers, &audit);
+            }
+            NetworkSubCommand::PurgePeerStore { older_than, unreachable_only } => {
+                let limits = near_network::PeerStoreLimits::new(
+                    config.network.max_peer_store_entries,
+                    ban_window,
+                    &config.network.blacklist_exempt_peers()?,
+                );
+                let store = open_stopped_store(&store_path, &config)?;
+                let purge = near_network::PeerStorePurge {
+                    older_than: std::time::Duration::from_secs(older_than * 24 * 60 * 60),
+                    unreachable_only,
+                };
+                println!("{}", purge.run(&store, &limits)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Opens the database for writing, which fails while the node is running.
+fn open_stopped_store(
+    store_path: &Path,
+    config: &nearcore::config::Config,
+) -> anyhow::Result<near_store::Store> {
+    let store_config = config.store.clone().with_read_only(false);
+    near_store::try_create_store_with_config(store_path, &store_config).map_err(|err| {
+        if err.is_locked() {
+            anyhow::anyhow!(
+                "database at {} is locked, most likely by a running neard; stop the node first",
+                store_path.display()
+            )
+        } else {
+            anyhow::anyhow!("failed to open {}: {}", store_path.display(), err)
+        }
+    })
+}
+
+/// The peers connected to the node, from its `network_info` RPC.
+async fn request_connected_peers(
+    url: String,
+) -> anyhow::Result<Vec<near_network_primitives::types::PeerInfo>> {
+    #[derive(serde::Deserialize)]
+    struct ActivePeer {
+        peer_id: near_primitives::network::PeerId,
+        addr: String,
+    }
+    #[derive(serde::Deserialize)]
+    struct NetworkInfo {
+        active_peers: Vec<ActivePeer>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Response {
+        result: NetworkInfo,
+    }
+
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": "dontcare",
+        "method": "network_info",
+        "params": [],
+    });
+    let mut response = awc::Client::new()
+        .post(&url)
+        .send_json(&request)
+        .await
+        .map_err(|err| anyhow::anyhow!("request to {} failed: {}", url, err))?;
+    let body = response
+        .body()
+        .await
+        .map_err(|err| anyhow::anyhow!("failed to read the response: {}", err))?;
+    if !response.status().is_success() {
+        anyhow::bail!("{}: {}", response.status(), String::from_utf8_lossy(&body));
+    }
+    let response: Response = serde_json::from_slice(&body)?;
+    Ok(response
+        .result
+        .active_peers
+        .into_iter()
+        .map(|peer| near_network_primitives::types::PeerInfo {
+            id: peer.peer_id,
+            // "N/A" for the peers of unknown address, which no entry matches.
+            addr: peer.addr.parse().ok(),
+            account_id: None,
+        })
+        .collect())
+}
+
+fn print_audit(
+    source: &str,
+    peers: &[near_network_primitives::types::PeerInfo],
+    audit: &near_network::BlacklistAudit,
+) {
+    let refused = audit.audit(peers);
+    println!("{} of {} peers {} would be refused", refused.len(), peers.len(), source);
+    for peer in refused {
+        println!("  {} {} by {}", peer.peer_id, peer.addr, peer.entry);
+    }
+}
+
+#[derive(Parser)]
+pub(super) struct DatabaseCmd {
+    #[clap(subcommand)]
+    subcmd: DatabaseSubCommand,
+}
+
+#[derive(Parser)]
+enum DatabaseSubCommand {
+    /// Clones the database into a directory by hard linking its files, so the
+    /// clone takes no space until the databases diverge.  The clone can be
+    /// written to, e.g. by state viewer commands which need to, once linked or
+    /// moved to the `data` directory of a home directory with the config and
+    /// genesis of the node.
+    ///
+    /// The node has to be stopped, but only for the seconds the links take.
+    /// The directory has to be on the file system of the database.  Nodes
+    /// refuse to run with the clone unless given `--allow-cloned-db`.
+    Clone {
+        /// Directory to clone the database into, which must not exist.
+        #[clap(long, parse(from_os_str))]
+        dest: PathBuf,
+    },
+    /// Prints the version of the database, whether it's of an archival node
+    /// and the neard which last opened it.  Works while the node is running
+    /// and on the databases of newer neard versions.
+    Version,
+}
+
+impl DatabaseCmd {
+    pub(super) fn run(self, home_dir: &Path) {
+        match self.subcmd {
+            DatabaseSubCommand::Clone { dest } => match nearcore::clone_database(home_dir, &dest) {
+                Ok(head) => println!(
+                    "Cloned the database to {} at head #{} {}",
+                    dest.display(),
+                    head.height,
+                    head.last_block_hash
+                ),
+                Err(err) => {
+                    error!(target: "neard", "{:#}", err);
+                    std::process::exit(1);
+                }
+            },
+            DatabaseSubCommand::Version => {
+                let store_path = get_store_path(home_dir);
+                match RocksDB::get_metadata(&store_path) {
+                    Ok(metadata) => print_db_metadata(&metadata),
+                    Err(err) => {
+                        error!(target: "neard", "{}: {}", store_path.display(), err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn print_db_metadata(metadata: &DbMetadata) {
+    println!(
+        "Database version: {} (this neard supports versions up to {})",
+        metadata.version,
+        near_primitives::version::DB_VERSION
+    );
+    let kind = if metadata.is_archive { "archival" } else { "non-archival" };
+    if metadata.is_clone {
+        println!("Kind: {}, a clone made by `neard database clone`", kind);
+    } else {
+        println!("Kind: {}", kind);
+    }
+    match &metadata.neard_version {
+        Some(version) => {
+            println!("Last opened by: neard {} (build {})", version.version, version.build)
+        }
+        None => println!("Last opened by: a neard which didn't record its version"),
+    }
+}
+
+#[derive(Parser)]
+pub(super) struct BenchmarkCmd {
+    #[clap(subcommand)]
+    subcmd: BenchmarkSubCommand,
+}
+
+#[derive(Parser)]
+enum BenchmarkSubCommand {
+    /// Applies the chunks of the last blocks of a shard over and over and
+    /// prints the percentiles of the wall time and the CPU time of the
+    /// applies and of the latency of the database reads, with whether the
+    /// 95th percentile of the apply time is within the budget of a chunk.
+    ///
+    /// Nothing is written to the database.  It's read while the node is
+    /// stopped, or a clone made by `neard database clone` is read with
+    /// `--snapshot` at any time.
+    ChunkApply(ChunkApplyBenchmarkCmd),
+}
+
+impl BenchmarkCmd {
+    pub(super) fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
+        match self.subcmd {
+            BenchmarkSubCommand::ChunkApply(cmd) => cmd.run(home_dir, genesis_validation),
+        }
+    }
+}
+
+#[derive(Parser)]
+pub(super) struct LogFilterCmd {
+    /// Address of the node's RPC server.
+    #[clap(long, default_value = "127.0.0.1:3030")]
+    rpc_addr: String,
+    /// Shows the current filter if not given.
+    #[clap(subcommand)]
+    subcmd: Option<LogFilterSubCommand>,
+}
+
+#[derive(Parser)]
+enum LogFilterSubCommand {
+    /// Replaces the base directives, which come from `RUST_LOG` at startup.
+    /// Temporary directives stay in effect.
+    Set {
+        /// Directives in the `RUST_LOG` format.  `RUST_LOG` of the node is
+        /// used if not given.
+        #[clap(long)]
+        rust_log: Option<String>,
+        /// Enables debug logging for the target, or for all targets if empty.
+        #[clap(long)]
+        verbose_module: Option<String>,
+    },
+    /// Adds a directive, e.g. `network=debug`, for a limited time.
+    AddTemporary {
+        directive: String,
+        #[clap(long, default_value = "600")]
+        duration_secs: u64,
+    },
+    /// Removes all temporary directives.
+    ClearTemporary,
+    /// Sets the number of debug and trace records per second allowed for
+    /// a single target.  Zero disables rate limiting.
+    SetRateLimit { records_per_second: u32 },
+}
+
+impl LogFilterCmd {
+    pub(super) fn run(self) {
+        let change = self.subcmd.map(|subcmd| match subcmd {
+            LogFilterSubCommand::Set { rust_log, verbose_module } => {
+                LogFilterChange::Set { rust_log, verbose_module }
+            }
+            LogFilterSubCommand::AddTemporary { directive, duration_secs } => {
+                LogFilterChange::AddTemporary { directive, duration_secs }
+            }
+            LogFilterSubCommand::ClearTemporary => LogFilterChange::ClearTemporary,
+            LogFilterSubCommand::SetRateLimit { records_per_second } => {
+                LogFilterChange::SetRateLimit { records_per_second }
+            }
+        });
+        let url = format!("http://{}/debug/api/log_filter", self.rpc_addr);
+        let result = actix::System::new().block_on(request_log_filter(url, change));
+        match result {
+            Ok(status) => print_log_filter_status(&status),
+            Err(err) => {
+                error!(target: "neard", "{:#}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+async fn request_log_filter(
+    url: String,
+    change: Option<LogFilterChange>,
+) -> anyhow::Result<LogFilterStatus> {
+    let client = awc::Client::new();
+    let response = match &change {
+        Some(change) => client.post(&url).send_json(change).await,
+        None => client.get(&url).send().await,
+    };
+    let mut response =
+        response.map_err(|err| anyhow::anyhow!("request to {} failed: {}", url, err))?;
+    let body = response
+        .body()
+        .await
+        .map_err(|err| anyhow::anyhow!("failed to read the response: {}", err))?;
+    if !response.status().is_success() {
+        anyhow::bail!("{}: {}", response.status(), String::from_utf8_lossy(&body));
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn print_log_filter_status(status: &LogFilterStatus) {
+    println!("Active directives: {}", status.directives);
+    for temporary in &status.temporary_directives {
+        println!(
+            "Temporary directive: {} (expires in {}s)",
+            temporary.directive, temporary.expires_in_secs
+        );
+    }
+    if status.rate_limit_per_target == 0 {
+        println!("Rate limit: disabled");
+    } else {
+        println!(
+            "Rate limit: {} debug and trace records per second per target",
+            status.rate_limit_per_target
+        );
+    }
+    for (target, dropped) in &status.dropped_records {
+        println!("Dropped records of {}: {}", target, dropped);
+    }
 }
@@ -26,6 +26,7 @@ pub use near_vm_logic::with_ext_cost_counter;
 pub use cache::{
     get_contract_cache_key, precompile_contract, precompile_contract_vm, MockCompiledContractCache,
 };
+pub use errors::ContractPrecompilatonResult;
 #[cfg(target_arch = "x86_64")]
 pub use preload::{ContractCallPrepareRequest, ContractCallPrepareResult, ContractCaller};
 pub use runner::{run, VMResult, VM};
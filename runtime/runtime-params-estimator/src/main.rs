@@ -140,6 +140,7 @@ fn main() -> anyhow::Result<()> {
             None,
             None,
             None,
+            false,
         )
         .expect("failed to init config");
 
@@ -4,10 +4,10 @@ use near_primitives::account::{AccessKey, Account};
 use near_primitives::contract::ContractCode;
 use near_primitives::hash::CryptoHash;
 use near_primitives::types::{
-    AccountId, BlockHeight, EpochHeight, EpochId, EpochInfoProvider, MerkleHash,
+    AccountId, BlockHeight, EpochHeight, EpochId, EpochInfoProvider, Gas, MerkleHash,
 };
 use near_primitives::version::ProtocolVersion;
-use near_primitives::views::ViewStateResult;
+use near_primitives::views::{CallFunctionOverrides, ViewStateResult};
 
 /// Adapter for querying runtime.
 pub trait ViewRuntimeAdapter {
@@ -38,10 +38,11 @@ pub trait ViewRuntimeAdapter {
         contract_id: &AccountId,
         method_name: &str,
         args: &[u8],
+        overrides: &CallFunctionOverrides,
         logs: &mut Vec<String>,
         epoch_info_provider: &dyn EpochInfoProvider,
         current_protocol_version: ProtocolVersion,
-    ) -> Result<Vec<u8>, crate::state_viewer::errors::CallFunctionError>;
+    ) -> Result<(Vec<u8>, Gas), crate::state_viewer::errors::CallFunctionError>;
 
     fn view_access_key(
         &self,
@@ -388,6 +388,7 @@ mod tests {
                 gas_deficit_amount: 0,
                 other_burnt_amount: 0,
                 slashed_burnt_amount: 0,
+                largest_receipt_proof: None,
             },
             PROTOCOL_VERSION,
         )
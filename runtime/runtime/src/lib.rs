@@ -2,11 +2,13 @@ use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tracing::debug;
 
 use near_chain_configs::Genesis;
 pub use near_crypto;
+use near_crypto::verification_pool::{self, SignatureCheck};
 use near_crypto::PublicKey;
 pub use near_primitives;
 #[cfg(feature = "sandbox")]
@@ -16,6 +18,8 @@ pub use near_primitives::runtime::apply_state::ApplyState;
 use near_primitives::runtime::fees::RuntimeFeesConfig;
 use near_primitives::runtime::get_insufficient_storage_stake;
 use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+#[cfg(feature = "sandbox")]
+use near_primitives::state_record::StateRecordTombstone;
 use near_primitives::transaction::ExecutionMetadata;
 use near_primitives::version::{
     is_implicit_account_creation_enabled, ProtocolFeature, ProtocolVersion,
@@ -28,7 +32,7 @@ use near_primitives::{
     receipt::{
         ActionReceipt, DataReceipt, DelayedReceiptIndices, Receipt, ReceiptEnum, ReceivedData,
     },
-    state_record::StateRecord,
+    state_record::{StatePatchRecord, StateRecord},
     transaction::{
         Action, ExecutionOutcome, ExecutionOutcomeWithId, ExecutionStatus, LogEntry,
         SignedTransaction,
@@ -36,7 +40,7 @@ use near_primitives::{
     trie_key::TrieKey,
     types::{
         validator_stake::ValidatorStake, AccountId, Balance, EpochInfoProvider, Gas,
-        RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot,
+        RawStateChangesWithTrieKey, ShardId, StateChangeCause, StateRoot, TrieReadStats,
     },
     utils::{
         create_action_hash, create_receipt_id_from_receipt, create_receipt_id_from_transaction,
@@ -48,7 +52,7 @@ use near_store::{
     StorageError, Trie, TrieChanges, TrieUpdate,
 };
 #[cfg(feature = "sandbox")]
-use near_store::{set_access_key, set_code};
+use near_store::{remove_access_key, remove_account, set_access_key, set_code};
 use near_vm_logic::types::PromiseResult;
 use near_vm_logic::ReturnData;
 pub use near_vm_runner::with_ext_cost_counter;
@@ -60,8 +64,12 @@ use crate::config::{
     total_prepaid_exec_fees, total_prepaid_gas, RuntimeConfig,
 };
 use crate::genesis::{GenesisStateApplier, StorageComputer};
+use crate::replay::{ReceiptRecord, ReceiptRecorder};
+use crate::signature_cache::SignatureCache;
 use crate::verifier::validate_receipt;
-pub use crate::verifier::{validate_transaction, verify_and_charge_transaction};
+pub use crate::verifier::{
+    transaction_limit_violations, validate_transaction, verify_and_charge_transaction,
+};
 
 mod actions;
 pub mod adapter;
@@ -71,6 +79,8 @@ pub mod config;
 pub mod ext;
 mod genesis;
 mod metrics;
+pub mod replay;
+pub mod signature_cache;
 pub mod state_viewer;
 mod verifier;
 
@@ -111,6 +121,10 @@ pub struct ApplyStats {
     /// This is a negative amount. This amount was not charged from the account that issued
     /// the transaction. It's likely due to the delayed queue of the receipts.
     pub gas_deficit_amount: Balance,
+    /// Receipt which recorded the most bytes of trie nodes not recorded
+    /// before it, with the number of bytes.  Only set if the trie records
+    /// reads.
+    pub largest_receipt_proof: Option<(CryptoHash, u64)>,
 }
 
 pub struct ApplyResult {
@@ -123,6 +137,21 @@ pub struct ApplyResult {
     pub stats: ApplyStats,
     pub processed_delayed_receipts: Vec<Receipt>,
     pub proof: Option<PartialStorage>,
+    pub stage_times: ApplyStageTimes,
+    /// See [`Trie::get_read_stats`].  Only used for metrics.
+    pub trie_reads: Option<TrieReadStats>,
+}
+
+/// Time spent in the stages of [`Runtime::apply`].  Only used for metrics.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ApplyStageTimes {
+    /// Converting the transactions to receipts.
+    pub transactions: Duration,
+    /// Executing the local, delayed and incoming receipts, balance check
+    /// included.
+    pub receipts: Duration,
+    /// Computing the trie changes from the state update.
+    pub trie_update: Duration,
 }
 
 #[derive(Debug)]
@@ -185,11 +214,28 @@ impl Default for ActionResult {
     }
 }
 
-pub struct Runtime {}
+#[derive(Clone, Default)]
+pub struct Runtime {
+    receipt_recorder: Option<Arc<dyn ReceiptRecorder>>,
+    signature_cache: Option<Arc<SignatureCache>>,
+}
 
 impl Runtime {
     pub fn new() -> Self {
-        Self {}
+        Self { receipt_recorder: None, signature_cache: None }
+    }
+
+    /// Makes the runtime remember which transaction signatures are valid at the last few heights,
+    /// see [`signature_cache`].
+    pub fn with_signature_cache(mut self) -> Self {
+        self.signature_cache = Some(Arc::new(SignatureCache::new()));
+        self
+    }
+
+    /// Makes the runtime pass every receipt it processes to `recorder`, see [`replay`].
+    pub fn with_receipt_recorder(mut self, recorder: Arc<dyn ReceiptRecorder>) -> Self {
+        self.receipt_recorder = Some(recorder);
+        self
     }
 
     fn print_log(log: &[LogEntry]) {
@@ -215,11 +261,14 @@ impl Runtime {
     /// `ExecutionOutcomeWithId` for the transaction.
     /// In case of an error, returns either `InvalidTxError` if the transaction verification failed
     /// or a `StorageError` wrapped into `RuntimeError`.
+    /// The signature is only checked with `verify_signature`, for the transactions whose
+    /// signature wasn't verified in a batch beforehand.
     fn process_transaction(
         &self,
         state_update: &mut TrieUpdate,
         apply_state: &ApplyState,
         signed_transaction: &SignedTransaction,
+        verify_signature: bool,
         stats: &mut ApplyStats,
     ) -> Result<(Receipt, ExecutionOutcomeWithId), RuntimeError> {
         let _span = tracing::debug_span!(target: "runtime", "process_transaction").entered();
@@ -230,7 +279,7 @@ impl Runtime {
             state_update,
             apply_state.gas_price,
             signed_transaction,
-            true,
+            verify_signature,
             Some(apply_state.block_index),
             apply_state.current_protocol_version,
         ) {
@@ -1166,7 +1215,7 @@ impl Runtime {
         incoming_receipts: &[Receipt],
         transactions: &[SignedTransaction],
         epoch_info_provider: &dyn EpochInfoProvider,
-        states_to_patch: Option<Vec<StateRecord>>,
+        states_to_patch: Option<Vec<StatePatchRecord>>,
     ) -> Result<ApplyResult, RuntimeError> {
         let _span = tracing::debug_span!(
             target: "runtime",
@@ -1212,7 +1261,10 @@ impl Runtime {
             && apply_state.current_protocol_version
                 >= ProtocolFeature::FixApplyChunks.protocol_version()
         {
+            let started = Instant::now();
             let (trie_changes, state_changes) = state_update.finalize()?;
+            let stage_times =
+                ApplyStageTimes { trie_update: started.elapsed(), ..Default::default() };
             let proof = trie.recorded_storage();
             return Ok(ApplyResult {
                 state_root: trie_changes.new_root,
@@ -1224,6 +1276,8 @@ impl Runtime {
                 stats,
                 processed_delayed_receipts: vec![],
                 proof,
+                stage_times,
+                trie_reads: trie.get_read_stats(),
             });
         }
 
@@ -1236,12 +1290,35 @@ impl Runtime {
         // charge any gas for refund receipts, we still count the gas use towards the block gas
         // limit
         let mut total_gas_burnt = gas_used_for_migrations;
-
-        for signed_transaction in transactions {
+        let mut stage_times = ApplyStageTimes::default();
+
+        let started = Instant::now();
+        // The signatures are verified in parallel up front, unless they were for a chunk at the same
+        // height on another fork.  The transactions whose signature is invalid verify it again when
+        // they're processed, so that the chunk is rejected for the same transaction as if they were
+        // all verified in order.
+        let tx_hashes: Vec<CryptoHash> = transactions.iter().map(|tx| tx.get_hash()).collect();
+        let valid_signatures = match &self.signature_cache {
+            Some(cache) => cache.verify(apply_state.block_index, transactions, &tx_hashes),
+            None => {
+                let signature_checks: Vec<_> = transactions
+                    .iter()
+                    .zip(&tx_hashes)
+                    .map(|(signed_transaction, tx_hash)| SignatureCheck {
+                        signature: &signed_transaction.signature,
+                        data: tx_hash.as_ref(),
+                        public_key: &signed_transaction.transaction.public_key,
+                    })
+                    .collect();
+                verification_pool::verify_batch(&signature_checks)
+            }
+        };
+        for (signed_transaction, valid_signature) in transactions.iter().zip(valid_signatures) {
             let (receipt, outcome_with_id) = self.process_transaction(
                 &mut state_update,
                 apply_state,
                 signed_transaction,
+                !valid_signature,
                 &mut stats,
             )?;
             if receipt.receiver_id == signed_transaction.transaction.signer_id {
@@ -1254,6 +1331,8 @@ impl Runtime {
 
             outcomes.push(outcome_with_id);
         }
+        stage_times.transactions = started.elapsed();
+        let started = Instant::now();
 
         let mut delayed_receipts_indices: DelayedReceiptIndices =
             get(&state_update, &TrieKey::DelayedReceiptIndices)?.unwrap_or_default();
@@ -1269,6 +1348,11 @@ impl Runtime {
                 receipt_id = %receipt.receipt_id,
                 node_counter = ?state_update.trie.get_trie_nodes_count())
             .entered();
+            let num_outgoing_receipts = outgoing_receipts.len();
+            let recorded_before = state_update.trie.recorded_storage_size();
+            if self.receipt_recorder.is_some() {
+                state_update.start_recording_reads();
+            }
             let result = self.process_receipt(
                 state_update,
                 apply_state,
@@ -1279,7 +1363,25 @@ impl Runtime {
                 epoch_info_provider,
             );
             tracing::debug!(target: "runtime", node_counter = ?state_update.trie.get_trie_nodes_count());
-            result?.into_iter().try_for_each(
+            let result = result?;
+            if let (Some(before), Some(after)) =
+                (recorded_before, state_update.trie.recorded_storage_size())
+            {
+                let size = after - before;
+                if stats.largest_receipt_proof.map_or(true, |(_, largest)| size > largest) {
+                    stats.largest_receipt_proof = Some((receipt.receipt_id, size));
+                }
+            }
+            if let Some(recorder) = &self.receipt_recorder {
+                recorder.record(ReceiptRecord::new(
+                    receipt,
+                    apply_state,
+                    state_update.take_recorded_reads(),
+                    result.as_ref(),
+                    &outgoing_receipts[num_outgoing_receipts..],
+                ));
+            }
+            result.into_iter().try_for_each(
                 |outcome_with_id: ExecutionOutcomeWithId| -> Result<(), RuntimeError> {
                     *total_gas_burnt =
                         safe_add_gas(*total_gas_burnt, outcome_with_id.outcome.gas_burnt)?;
@@ -1363,13 +1465,16 @@ impl Runtime {
         )?;
 
         state_update.commit(StateChangeCause::UpdatedDelayedReceipts);
+        stage_times.receipts = started.elapsed();
 
         #[cfg(feature = "sandbox")]
         if let Some(patch) = states_to_patch {
             self.apply_state_patches(&mut state_update, patch);
         }
 
+        let started = Instant::now();
         let (trie_changes, state_changes) = state_update.finalize()?;
+        stage_times.trie_update = started.elapsed();
 
         // Dedup proposals from the same account.
         // The order is deterministically changed.
@@ -1395,6 +1500,8 @@ impl Runtime {
             stats,
             processed_delayed_receipts,
             proof,
+            stage_times,
+            trie_reads: trie.get_read_stats(),
         })
     }
 
@@ -1423,27 +1530,44 @@ impl Runtime {
     fn apply_state_patches(
         &self,
         state_update: &mut TrieUpdate,
-        states_to_patch: Vec<StateRecord>,
+        states_to_patch: Vec<StatePatchRecord>,
     ) {
         for record in states_to_patch {
             match record {
-                StateRecord::Account { account_id, account } => {
+                StatePatchRecord::Set(StateRecord::Account { account_id, account }) => {
                     set_account(state_update, account_id, &account);
                 }
-                StateRecord::Data { account_id, data_key, value } => {
+                StatePatchRecord::Set(StateRecord::Data { account_id, data_key, value }) => {
                     state_update.set(TrieKey::ContractData { key: data_key, account_id }, value);
                 }
-                StateRecord::Contract { account_id, code } => {
+                StatePatchRecord::Set(StateRecord::Contract { account_id, code }) => {
                     let acc = get_account(&state_update, &account_id).expect("Failed to read state").expect("Code state record should be preceded by the corresponding account record");
                     // Recompute contract code hash.
                     let code = ContractCode::new(code, None);
                     set_code(state_update, account_id, &code);
                     assert_eq!(*code.hash(), acc.code_hash());
                 }
-                StateRecord::AccessKey { account_id, public_key, access_key } => {
+                StatePatchRecord::Set(StateRecord::AccessKey { account_id, public_key, access_key }) => {
                     set_access_key(state_update, account_id, public_key, &access_key);
                 }
-                _ => unimplemented!("patch_state can only patch Account, AccessKey, Contract and Data kind of StateRecord")
+                // Rejected by the RPC, see `StatePatchRecord::is_patchable`.
+                StatePatchRecord::Set(_) => unimplemented!("patch_state can only patch Account, AccessKey, Contract and Data kind of StateRecord"),
+                StatePatchRecord::Delete(StateRecordTombstone::DeleteAccount { account_id }) => {
+                    remove_account(state_update, &account_id).expect("Failed to read state");
+                }
+                StatePatchRecord::Delete(StateRecordTombstone::DeleteData { account_id, data_key }) => {
+                    state_update.remove(TrieKey::ContractData { key: data_key, account_id });
+                }
+                StatePatchRecord::Delete(StateRecordTombstone::DeleteContract { account_id }) => {
+                    if let Some(mut account) = get_account(&state_update, &account_id).expect("Failed to read state") {
+                        account.set_code_hash(CryptoHash::default());
+                        set_account(state_update, account_id.clone(), &account);
+                    }
+                    state_update.remove(TrieKey::ContractCode { account_id });
+                }
+                StatePatchRecord::Delete(StateRecordTombstone::DeleteAccessKey { account_id, public_key }) => {
+                    remove_access_key(state_update, account_id, public_key);
+                }
             }
         }
         state_update.commit(StateChangeCause::Migration);
@@ -1476,9 +1600,11 @@ impl Runtime {
 
 #[cfg(test)]
 mod tests {
-    use near_crypto::{InMemorySigner, KeyType, Signer};
+    use assert_matches::assert_matches;
+    use near_crypto::{InMemorySigner, KeyType, Signature, Signer};
     use near_primitives::account::AccessKey;
     use near_primitives::contract::ContractCode;
+    use near_primitives::errors::InvalidTxError;
     use near_primitives::hash::hash;
     use near_primitives::shard_layout::ShardUId;
     use near_primitives::test_utils::{account_new, MockEpochInfoProvider};
@@ -1628,6 +1754,62 @@ mod tests {
             .unwrap();
     }
 
+    /// The signatures are verified in a batch, but the chunk is rejected for the first invalid
+    /// transaction, whatever made it invalid.
+    #[test]
+    fn test_apply_transactions_with_invalid_signatures() {
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), 0, 10u64.pow(15));
+        let transactions: Vec<_> = (1..=100)
+            .map(|nonce| {
+                SignedTransaction::send_money(
+                    nonce,
+                    alice_account(),
+                    bob_account(),
+                    &*signer,
+                    1,
+                    CryptoHash::default(),
+                )
+            })
+            .collect();
+        let with_invalid_signature = |index: usize| {
+            let mut transactions = transactions.clone();
+            transactions[index] = SignedTransaction::new(
+                Signature::empty(KeyType::ED25519),
+                transactions[index].transaction.clone(),
+            );
+            transactions
+        };
+        let apply = |transactions: &[SignedTransaction]| {
+            runtime.apply(
+                tries.get_trie_for_shard(ShardUId::single_shard()),
+                root,
+                &None,
+                &apply_state,
+                &[],
+                transactions,
+                &epoch_info_provider,
+                None,
+            )
+        };
+
+        assert_eq!(apply(&transactions).unwrap().outcomes.len(), 100);
+        assert_matches!(
+            apply(&with_invalid_signature(70)),
+            Err(RuntimeError::InvalidTxError(InvalidTxError::InvalidSignature))
+        );
+        // An earlier transaction is invalid for another reason.
+        let mut transactions = with_invalid_signature(70);
+        transactions[40] = transactions[10].clone();
+        assert_matches!(
+            apply(&transactions),
+            Err(RuntimeError::InvalidTxError(InvalidTxError::InvalidNonce {
+                tx_nonce: 11,
+                ak_nonce: 40
+            }))
+        );
+    }
+
     #[test]
     fn test_apply_check_balance_validation_rewards() {
         let initial_locked = to_yocto(500_000);
@@ -2413,4 +2595,147 @@ mod tests {
             .expect("Compiled contract should be cached")
             .expect("Compilation result should be non-empty");
     }
+
+    #[test]
+    fn test_record_and_replay_receipts() {
+        let (runtime, tries, root, apply_state, _, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+        let recorder = Arc::new(crate::replay::InMemoryReceiptRecorder::default());
+        let runtime = runtime.with_receipt_recorder(recorder.clone());
+
+        let mut receipts = generate_receipts(to_yocto(10_000), 3);
+        receipts.push(Receipt {
+            predecessor_id: bob_account(),
+            receiver_id: alice_account(),
+            receipt_id: hash(b"function call"),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: bob_account(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: GAS_PRICE,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions: vec![Action::FunctionCall(FunctionCallAction {
+                    method_name: "hello".to_string(),
+                    args: b"world".to_vec(),
+                    gas: 10u64.pow(14),
+                    deposit: 0,
+                })],
+            }),
+        });
+        runtime
+            .apply(
+                tries.get_trie_for_shard(ShardUId::single_shard()),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+
+        let records = recorder.take();
+        assert_eq!(records.len(), receipts.len());
+        for record in &records {
+            let json = serde_json::to_string(record).unwrap();
+            let record: crate::replay::ReceiptRecord = serde_json::from_str(&json).unwrap();
+            record.verify().unwrap();
+        }
+        // The failed function call refunds the prepaid gas.
+        let mut record = records.last().unwrap().clone();
+        assert_eq!(record.outgoing_receipts.len(), 1);
+
+        record.outcome.as_mut().unwrap().outcome.gas_burnt += 1;
+        assert_matches::assert_matches!(
+            record.verify(),
+            Err(crate::replay::ReplayError::OutcomeMismatch { .. })
+        );
+    }
+
+    /// Recording the values the receipts read doesn't change the trie nodes
+    /// charged for, so the gas burnt is the same with and without it.
+    #[test]
+    fn test_receipt_recording_keeps_gas() {
+        let (runtime, tries, root, apply_state, signer, epoch_info_provider) =
+            setup_runtime(to_yocto(1_000_000), to_yocto(500_000), 10u64.pow(15));
+        let function_call = |method_name: &str, args: Vec<u8>| {
+            Action::FunctionCall(FunctionCallAction {
+                method_name: method_name.to_string(),
+                args,
+                gas: 10u64.pow(14),
+                deposit: 0,
+            })
+        };
+        let key = 1u64.to_le_bytes();
+        let value = 2u64.to_le_bytes();
+        let receipts = create_receipts_with_actions(
+            alice_account(),
+            signer.clone(),
+            vec![
+                Action::DeployContract(DeployContractAction {
+                    code: near_test_contracts::rs_contract().to_vec(),
+                }),
+                function_call("write_key_value", [key, value].concat()),
+            ],
+        );
+        let apply_result = runtime
+            .apply(
+                tries.get_trie_for_shard(ShardUId::single_shard()),
+                root,
+                &None,
+                &apply_state,
+                &receipts,
+                &[],
+                &epoch_info_provider,
+                None,
+            )
+            .unwrap();
+        let (store_update, root) =
+            tries.apply_all(&apply_result.trie_changes, ShardUId::single_shard());
+        store_update.commit().unwrap();
+
+        // The second read of the key in the chunk is served from the chunk cache.
+        let receipts: Vec<Receipt> = (0..2u8)
+            .map(|i| Receipt {
+                receipt_id: hash(&[i]),
+                ..create_receipts_with_actions(
+                    alice_account(),
+                    signer.clone(),
+                    vec![function_call("read_value", key.to_vec())],
+                )
+                .remove(0)
+            })
+            .collect();
+        let apply = |runtime: &Runtime| {
+            runtime
+                .apply(
+                    tries.get_trie_for_shard(ShardUId::single_shard()),
+                    root,
+                    &None,
+                    &apply_state,
+                    &receipts,
+                    &[],
+                    &epoch_info_provider,
+                    None,
+                )
+                .unwrap()
+                .outcomes
+        };
+        let outcomes = apply(&runtime);
+        let recorder = Arc::new(crate::replay::InMemoryReceiptRecorder::default());
+        let runtime = runtime.with_receipt_recorder(recorder.clone());
+        let recorded_outcomes = apply(&runtime);
+
+        assert_eq!(outcomes.len(), receipts.len());
+        for (outcome, recorded) in outcomes.iter().zip(&recorded_outcomes) {
+            assert_eq!(outcome.outcome.gas_burnt, recorded.outcome.gas_burnt);
+        }
+        assert_eq!(outcomes, recorded_outcomes);
+        let records = recorder.take();
+        assert_eq!(records.len(), receipts.len());
+        for record in &records {
+            assert!(record.state.iter().any(|read| read.value.as_deref() == Some(&value[..])));
+        }
+    }
 }
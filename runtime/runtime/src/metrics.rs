@@ -31,3 +31,18 @@ pub static TRANSACTION_PROCESSED_FAILED_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
     )
     .unwrap()
 });
+pub static SIGNATURE_CACHE_HITS_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_transaction_signature_cache_hits_total",
+        "The number of transaction signatures whose verification was skipped since it was done \
+         for a chunk at the same height on another fork",
+    )
+    .unwrap()
+});
+pub static SIGNATURE_CACHE_MISSES_TOTAL: Lazy<IntCounter> = Lazy::new(|| {
+    try_create_int_counter(
+        "near_transaction_signature_cache_misses_total",
+        "The number of transaction signatures verified when applying chunks",
+    )
+    .unwrap()
+});
@@ -0,0 +1,267 @@
+//! Recording of applied receipts and their standalone re-execution.
+//!
+//! When [`crate::Runtime`] has a [`ReceiptRecorder`], every receipt it
+//! processes is captured as a [`ReceiptRecord`]: the receipt, the block it
+//! was applied in, the runtime config, the part of the state the receipt read
+//! and what it produced.  [`ReceiptRecord::verify`] re-executes such a record
+//! against an in-memory trie holding only the recorded state and checks that
+//! the results match, which makes records usable as a corpus for differential
+//! testing of runtime and VM changes.
+//!
+//! Values the receipt read through trie iterators aren't recorded, and the
+//! re-execution sees no validators, so receipts which depend on either may
+//! not replay faithfully.
+//!
+//! The re-execution runs on the in-memory test store, so it's only built with
+//! the `replay` feature, which the tools replaying records enable.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::Receipt;
+use near_primitives::runtime::apply_state::ApplyState;
+use near_primitives::runtime::config::RuntimeConfig;
+use near_primitives::serialize::{base64_format, option_base64_format, u128_dec_format};
+use near_primitives::transaction::ExecutionOutcomeWithId;
+use near_primitives::types::{Balance, BlockHeight, EpochHeight, EpochId, Gas};
+use near_primitives::version::ProtocolVersion;
+use near_primitives::views::ExecutionOutcomeView;
+use near_store::RecordedReads;
+
+#[cfg(any(test, feature = "replay"))]
+pub use self::execution::ReplayError;
+
+/// Everything needed to re-execute a single receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReceiptRecord {
+    pub receipt: Receipt,
+    pub block: RecordedBlock,
+    pub config: RuntimeConfig,
+    /// Values the receipt read, as they were before the receipt was applied.
+    pub state: Vec<RecordedValue>,
+    /// Outcome of the receipt, `None` for data receipts.
+    pub outcome: Option<RecordedOutcome>,
+    /// Receipts created by the receipt.
+    pub outgoing_receipts: Vec<Receipt>,
+}
+
+/// The parts of [`ApplyState`] which affect the execution of a receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordedBlock {
+    pub block_index: BlockHeight,
+    pub prev_block_hash: CryptoHash,
+    pub block_hash: CryptoHash,
+    pub epoch_id: EpochId,
+    pub epoch_height: EpochHeight,
+    #[serde(with = "u128_dec_format")]
+    pub gas_price: Balance,
+    pub block_timestamp: u64,
+    pub gas_limit: Option<Gas>,
+    pub random_seed: CryptoHash,
+    pub current_protocol_version: ProtocolVersion,
+    pub is_new_chunk: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordedValue {
+    /// Raw trie key.
+    #[serde(with = "base64_format")]
+    pub key: Vec<u8>,
+    /// `None` if the key didn't exist.
+    #[serde(with = "option_base64_format")]
+    pub value: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordedOutcome {
+    pub id: CryptoHash,
+    pub outcome: ExecutionOutcomeView,
+}
+
+impl From<ExecutionOutcomeWithId> for RecordedOutcome {
+    fn from(outcome: ExecutionOutcomeWithId) -> Self {
+        Self { id: outcome.id, outcome: outcome.outcome.into() }
+    }
+}
+
+/// Receives the records of processed receipts.
+pub trait ReceiptRecorder: Send + Sync {
+    fn record(&self, record: ReceiptRecord);
+}
+
+/// Writes every record as `<receipt id>.json` into a directory.
+pub struct DirectoryReceiptRecorder {
+    dir: PathBuf,
+}
+
+impl DirectoryReceiptRecorder {
+    pub fn new(dir: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+}
+
+impl ReceiptRecorder for DirectoryReceiptRecorder {
+    fn record(&self, record: ReceiptRecord) {
+        let path = self.dir.join(format!("{}.json", record.receipt.receipt_id));
+        let result = serde_json::to_vec_pretty(&record)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&path, json));
+        if let Err(err) = result {
+            tracing::warn!(
+                target: "runtime",
+                path = %path.display(),
+                %err,
+                "failed to write receipt record"
+            );
+        }
+    }
+}
+
+/// Keeps the records in memory.
+#[derive(Default)]
+pub struct InMemoryReceiptRecorder {
+    records: Mutex<Vec<ReceiptRecord>>,
+}
+
+impl InMemoryReceiptRecorder {
+    pub fn take(&self) -> Vec<ReceiptRecord> {
+        std::mem::take(&mut *self.records.lock().unwrap())
+    }
+}
+
+impl ReceiptRecorder for InMemoryReceiptRecorder {
+    fn record(&self, record: ReceiptRecord) {
+        self.records.lock().unwrap().push(record);
+    }
+}
+
+impl ReceiptRecord {
+    pub(crate) fn new(
+        receipt: &Receipt,
+        apply_state: &ApplyState,
+        reads: RecordedReads,
+        outcome: Option<&ExecutionOutcomeWithId>,
+        outgoing_receipts: &[Receipt],
+    ) -> Self {
+        Self {
+            receipt: receipt.clone(),
+            block: RecordedBlock {
+                block_index: apply_state.block_index,
+                prev_block_hash: apply_state.prev_block_hash,
+                block_hash: apply_state.block_hash,
+                epoch_id: apply_state.epoch_id.clone(),
+                epoch_height: apply_state.epoch_height,
+                gas_price: apply_state.gas_price,
+                block_timestamp: apply_state.block_timestamp,
+                gas_limit: apply_state.gas_limit,
+                random_seed: apply_state.random_seed,
+                current_protocol_version: apply_state.current_protocol_version,
+                is_new_chunk: apply_state.is_new_chunk,
+            },
+            config: apply_state.config.as_ref().clone(),
+            state: reads.into_iter().map(|(key, value)| RecordedValue { key, value }).collect(),
+            outcome: outcome.cloned().map(RecordedOutcome::from),
+            outgoing_receipts: outgoing_receipts.to_vec(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "replay"))]
+mod execution {
+    use std::sync::Arc;
+
+    use near_primitives::errors::RuntimeError;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::receipt::Receipt;
+    use near_primitives::runtime::apply_state::ApplyState;
+    use near_primitives::runtime::migration_data::{MigrationData, MigrationFlags};
+    use near_primitives::shard_layout::ShardUId;
+    use near_primitives::test_utils::MockEpochInfoProvider;
+    use near_store::test_utils::{create_tries, test_populate_trie};
+
+    use super::{ReceiptRecord, RecordedOutcome};
+    use crate::{ApplyStats, Runtime};
+
+    #[derive(thiserror::Error, Debug)]
+    pub enum ReplayError {
+        #[error("failed to re-execute the receipt: {0}")]
+        Runtime(#[from] RuntimeError),
+        #[error("outcome differs, recorded: {recorded:?}, replayed: {replayed:?}")]
+        OutcomeMismatch {
+            recorded: Box<Option<RecordedOutcome>>,
+            replayed: Box<Option<RecordedOutcome>>,
+        },
+        #[error("outgoing receipts differ, recorded: {recorded:?}, replayed: {replayed:?}")]
+        OutgoingReceiptsMismatch { recorded: Vec<Receipt>, replayed: Vec<Receipt> },
+    }
+
+    impl ReceiptRecord {
+        /// Executes the receipt on top of the recorded state.
+        ///
+        /// Returns its outcome and the receipts it created.
+        pub fn replay(&self) -> Result<(Option<RecordedOutcome>, Vec<Receipt>), RuntimeError> {
+            let tries = create_tries();
+            let shard_uid = ShardUId::single_shard();
+            let changes = self
+                .state
+                .iter()
+                .filter(|value| value.value.is_some())
+                .map(|value| (value.key.clone(), value.value.clone()))
+                .collect();
+            let root = test_populate_trie(&tries, &CryptoHash::default(), shard_uid, changes);
+            let mut state_update = tries.new_trie_update(shard_uid, root);
+
+            let block = &self.block;
+            let apply_state = ApplyState {
+                block_index: block.block_index,
+                prev_block_hash: block.prev_block_hash,
+                block_hash: block.block_hash,
+                epoch_id: block.epoch_id.clone(),
+                epoch_height: block.epoch_height,
+                gas_price: block.gas_price,
+                block_timestamp: block.block_timestamp,
+                gas_limit: block.gas_limit,
+                random_seed: block.random_seed,
+                current_protocol_version: block.current_protocol_version,
+                config: Arc::new(self.config.clone()),
+                cache: None,
+                is_new_chunk: block.is_new_chunk,
+                migration_data: Arc::new(MigrationData::default()),
+                migration_flags: MigrationFlags::default(),
+            };
+            let mut outgoing_receipts = vec![];
+            let outcome = Runtime::new().process_receipt(
+                &mut state_update,
+                &apply_state,
+                &self.receipt,
+                &mut outgoing_receipts,
+                &mut vec![],
+                &mut ApplyStats::default(),
+                &MockEpochInfoProvider::default(),
+            )?;
+            Ok((outcome.map(RecordedOutcome::from), outgoing_receipts))
+        }
+
+        /// Re-executes the receipt and checks that the results match the recorded ones.
+        pub fn verify(&self) -> Result<(), ReplayError> {
+            let (outcome, outgoing_receipts) = self.replay()?;
+            if outcome != self.outcome {
+                return Err(ReplayError::OutcomeMismatch {
+                    recorded: Box::new(self.outcome.clone()),
+                    replayed: Box::new(outcome),
+                });
+            }
+            if outgoing_receipts != self.outgoing_receipts {
+                return Err(ReplayError::OutgoingReceiptsMismatch {
+                    recorded: self.outgoing_receipts.clone(),
+                    replayed: outgoing_receipts,
+                });
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Results of the signature checks of the transactions of the chunks applied
+//! at the last few heights.
+//!
+//! When there are forks, the chunks of a shard at a height are applied once
+//! per block including them, on top of a different state each time, and the
+//! same transactions have their signatures verified again.  Whether the
+//! signature of a transaction is valid depends on the transaction alone, not on
+//! the shard or the previous block, so one result per transaction and height
+//! serves every fork.  Nothing else is cached: the size limits are cheap to
+//! check, the costs depend on the gas price of the block, the nonce, the access
+//! key and the balance on the state.
+//!
+//! The results are kept for the last [`CACHED_HEIGHTS`] heights, at most
+//! [`MAX_TRANSACTIONS_PER_HEIGHT`] per height.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use near_crypto::verification_pool::{self, SignatureCheck};
+use near_crypto::Signature;
+use near_primitives::hash::CryptoHash;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::BlockHeight;
+
+use crate::metrics;
+
+/// Heights whose results are kept, the highest ones.
+pub const CACHED_HEIGHTS: usize = 16;
+/// Results kept at most per height.
+pub const MAX_TRANSACTIONS_PER_HEIGHT: usize = 100_000;
+
+#[derive(Default)]
+pub struct SignatureCache {
+    /// Signature of every transaction by its hash, with whether it's valid.
+    /// The hash doesn't cover the signature, so it's kept to be compared.
+    heights: Mutex<BTreeMap<BlockHeight, HashMap<CryptoHash, (Signature, bool)>>>,
+}
+
+impl SignatureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verifies the signatures of the transactions applied at `height`, whose
+    /// hashes are `tx_hashes`, returning whether each of them is valid.  Only
+    /// the ones not verified at the height before are verified, in a batch.
+    pub fn verify(
+        &self,
+        height: BlockHeight,
+        transactions: &[SignedTransaction],
+        tx_hashes: &[CryptoHash],
+    ) -> Vec<bool> {
+        let mut valid: Vec<Option<bool>> = {
+            let heights = self.heights.lock().unwrap();
+            let cached = heights.get(&height);
+            transactions
+                .iter()
+                .zip(tx_hashes)
+                .map(|(transaction, tx_hash)| {
+                    let (signature, valid) = cached?.get(tx_hash)?;
+                    (signature == &transaction.signature).then(|| *valid)
+                })
+                .collect()
+        };
+        let missing: Vec<usize> = (0..valid.len()).filter(|&i| valid[i].is_none()).collect();
+        metrics::SIGNATURE_CACHE_HITS_TOTAL.inc_by((valid.len() - missing.len()) as u64);
+        metrics::SIGNATURE_CACHE_MISSES_TOTAL.inc_by(missing.len() as u64);
+        if missing.is_empty() {
+            return valid.into_iter().flatten().collect();
+        }
+
+        let checks: Vec<_> = missing
+            .iter()
+            .map(|&i| SignatureCheck {
+                signature: &transactions[i].signature,
+                data: tx_hashes[i].as_ref(),
+                public_key: &transactions[i].transaction.public_key,
+            })
+            .collect();
+        let verified = verification_pool::verify_batch(&checks);
+        let mut heights = self.heights.lock().unwrap();
+        let lowest_kept = heights.keys().next().copied();
+        if heights.len() < CACHED_HEIGHTS || lowest_kept.map_or(true, |lowest| height > lowest) {
+            let cached = heights.entry(height).or_default();
+            for (&i, &valid_signature) in missing.iter().zip(&verified) {
+                if cached.len() >= MAX_TRANSACTIONS_PER_HEIGHT {
+                    break;
+                }
+                cached.insert(tx_hashes[i], (transactions[i].signature.clone(), valid_signature));
+            }
+            while heights.len() > CACHED_HEIGHTS {
+                let lowest = *heights.keys().next().unwrap();
+                heights.remove(&lowest);
+            }
+        }
+        for (i, verified) in missing.into_iter().zip(verified) {
+            valid[i] = Some(verified);
+        }
+        valid.into_iter().flatten().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{InMemorySigner, KeyType};
+
+    fn transactions(nonces: std::ops::Range<u64>) -> Vec<SignedTransaction> {
+        let signer =
+            InMemorySigner::from_seed("alice.near".parse().unwrap(), KeyType::ED25519, "a");
+        nonces
+            .map(|nonce| {
+                SignedTransaction::send_money(
+                    nonce,
+                    "alice.near".parse().unwrap(),
+                    "bob.near".parse().unwrap(),
+                    &signer,
+                    1,
+                    CryptoHash::default(),
+                )
+            })
+            .collect()
+    }
+
+    fn verify(cache: &SignatureCache, height: BlockHeight, txs: &[SignedTransaction]) -> Vec<bool> {
+        let tx_hashes: Vec<_> = txs.iter().map(SignedTransaction::get_hash).collect();
+        cache.verify(height, txs, &tx_hashes)
+    }
+
+    #[test]
+    fn test_signature_cache() {
+        let cache = SignatureCache::new();
+        let mut txs = transactions(0..10);
+        txs[3].signature = Signature::empty(KeyType::ED25519);
+        let expected: Vec<bool> = (0..10).map(|i| i != 3).collect();
+        assert_eq!(verify(&cache, 100, &txs), expected);
+        assert_eq!(cache.heights.lock().unwrap()[&100].len(), 10);
+
+        // The same transaction with another signature isn't taken from the
+        // cache, neither is it at another height.
+        let mut other_fork = txs.clone();
+        other_fork[3] = transactions(3..4).pop().unwrap();
+        other_fork[5].signature = Signature::empty(KeyType::ED25519);
+        let expected: Vec<bool> = (0..10).map(|i| i != 5).collect();
+        assert_eq!(verify(&cache, 100, &other_fork), expected);
+        assert_eq!(verify(&cache, 101, &other_fork), expected);
+
+        // Only the highest heights are kept.
+        for height in 200..200 + CACHED_HEIGHTS as u64 {
+            verify(&cache, height, &txs[..1]);
+        }
+        let heights = cache.heights.lock().unwrap();
+        assert_eq!(heights.len(), CACHED_HEIGHTS);
+        assert_eq!(heights.keys().next(), Some(&200));
+    }
+}
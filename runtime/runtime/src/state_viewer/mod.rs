@@ -14,9 +14,9 @@ use near_primitives::{
     },
     serialize::to_base64,
     transaction::FunctionCallAction,
-    trie_key::trie_key_parsers,
+    trie_key::{trie_key_parsers, TrieKey},
     types::{AccountId, EpochInfoProvider, Gas},
-    views::{StateItem, ViewApplyState, ViewStateResult},
+    views::{CallFunctionOverrides, StateItem, StorageOverride, ViewApplyState, ViewStateResult},
 };
 use near_store::{get_access_key, get_account, get_code, TrieUpdate};
 use near_vm_logic::{ReturnData, ViewConfig};
@@ -162,7 +162,7 @@ impl TrieViewer {
 
     pub fn call_function(
         &self,
-        mut state_update: TrieUpdate,
+        state_update: TrieUpdate,
         view_state: ViewApplyState,
         contract_id: &AccountId,
         method_name: &str,
@@ -170,6 +170,35 @@ impl TrieViewer {
         logs: &mut Vec<String>,
         epoch_info_provider: &dyn EpochInfoProvider,
     ) -> Result<Vec<u8>, errors::CallFunctionError> {
+        self.call_function_with_overrides(
+            state_update,
+            view_state,
+            contract_id,
+            method_name,
+            args,
+            &CallFunctionOverrides::default(),
+            logs,
+            epoch_info_provider,
+        )
+        .map(|(result, _gas_burnt)| result)
+    }
+
+    /// Same as [`Self::call_function`] but with the environment of the call
+    /// changed by `overrides`.  The changes are only applied to the given
+    /// `state_update` which is discarded afterwards.
+    ///
+    /// Returns the result of the call together with the gas it burnt.
+    pub fn call_function_with_overrides(
+        &self,
+        mut state_update: TrieUpdate,
+        view_state: ViewApplyState,
+        contract_id: &AccountId,
+        method_name: &str,
+        args: &[u8],
+        overrides: &CallFunctionOverrides,
+        logs: &mut Vec<String>,
+        epoch_info_provider: &dyn EpochInfoProvider,
+    ) -> Result<(Vec<u8>, Gas), errors::CallFunctionError> {
         let now = Instant::now();
         let root = state_update.get_root();
         let mut account = get_account(&state_update, contract_id)?.ok_or_else(|| {
@@ -177,6 +206,20 @@ impl TrieViewer {
                 requested_account_id: contract_id.clone(),
             }
         })?;
+        if let Some(balance) = overrides.balance {
+            account.set_amount(balance);
+        }
+        for StorageOverride { key, value } in &overrides.storage {
+            let trie_key = TrieKey::ContractData {
+                account_id: contract_id.clone(),
+                key: key.as_ref().to_vec(),
+            };
+            state_update.set(trie_key, value.as_ref().to_vec());
+        }
+        // The override may only lower the budget, a view call never burns
+        // more than `max_gas_burnt_view`.
+        let max_gas_burnt =
+            overrides.gas.map_or(self.max_gas_burnt_view, |gas| gas.min(self.max_gas_burnt_view));
         // TODO(#1015): Add ability to pass public key and originator_id
         let originator_id = contract_id;
         let public_key = PublicKey::empty(KeyType::ED25519);
@@ -222,7 +265,7 @@ impl TrieViewer {
         let function_call = FunctionCallAction {
             method_name: method_name.to_string(),
             args: args.to_vec(),
-            gas: self.max_gas_burnt_view,
+            gas: max_gas_burnt,
             deposit: 0,
         };
         let (outcome, err) = execute_function_call(
@@ -236,7 +279,7 @@ impl TrieViewer {
             &empty_hash,
             config,
             true,
-            Some(ViewConfig { max_gas_burnt: self.max_gas_burnt_view }),
+            Some(ViewConfig { max_gas_burnt }),
         )
         .outcome_error();
         let elapsed = now.elapsed();
@@ -256,7 +299,7 @@ impl TrieViewer {
                 ReturnData::Value(buf) => buf,
                 ReturnData::ReceiptIndex(_) | ReturnData::None => vec![],
             };
-            Ok(result)
+            Ok((result, outcome.burnt_gas))
         }
     }
 }
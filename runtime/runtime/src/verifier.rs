@@ -45,19 +45,14 @@ pub fn validate_transaction(
         return Err(InvalidTxError::InvalidSignature.into());
     }
 
-    let transaction_size = signed_transaction.get_size();
-    let max_transaction_size = config.wasm_config.limit_config.max_transaction_size;
-    if transaction_size > max_transaction_size {
-        return Err(InvalidTxError::TransactionSizeExceeded {
-            size: transaction_size,
-            limit: max_transaction_size,
-        }
-        .into());
+    if let Some(err) =
+        transaction_limit_violations(&config.wasm_config.limit_config, signed_transaction)
+            .into_iter()
+            .next()
+    {
+        return Err(err.into());
     }
 
-    validate_actions(&config.wasm_config.limit_config, &transaction.actions)
-        .map_err(InvalidTxError::ActionsValidation)?;
-
     let sender_is_receiver = &transaction.receiver_id == signer_id;
 
     tx_cost(
@@ -70,6 +65,28 @@ pub fn validate_transaction(
     .map_err(|_| InvalidTxError::CostOverflow.into())
 }
 
+/// Returns all the ways the transaction exceeds the size and count limits,
+/// in the order `validate_transaction` checks them.
+pub fn transaction_limit_violations(
+    limit_config: &VMLimitConfig,
+    signed_transaction: &SignedTransaction,
+) -> Vec<InvalidTxError> {
+    let mut violations = vec![];
+    let transaction_size = signed_transaction.get_size();
+    if transaction_size > limit_config.max_transaction_size {
+        violations.push(InvalidTxError::TransactionSizeExceeded {
+            size: transaction_size,
+            limit: limit_config.max_transaction_size,
+        });
+    }
+    violations.extend(
+        actions_limit_violations(limit_config, &signed_transaction.transaction.actions)
+            .into_iter()
+            .map(InvalidTxError::ActionsValidation),
+    );
+    violations
+}
+
 /// Verifies the signed transaction on top of given state, charges transaction fees
 /// and balances, and updates the state for the used account and access keys.
 pub fn verify_and_charge_transaction(
@@ -286,8 +303,21 @@ pub(crate) fn validate_actions(
     limit_config: &VMLimitConfig,
     actions: &[Action],
 ) -> Result<(), ActionsValidationError> {
+    match actions_limit_violations(limit_config, actions).into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Returns all the problems `validate_actions` checks for, in order.  Only
+/// the first problem of every action is returned.
+fn actions_limit_violations(
+    limit_config: &VMLimitConfig,
+    actions: &[Action],
+) -> Vec<ActionsValidationError> {
+    let mut violations = vec![];
     if actions.len() as u64 > limit_config.max_actions_per_receipt {
-        return Err(ActionsValidationError::TotalNumberOfActionsExceeded {
+        violations.push(ActionsValidationError::TotalNumberOfActionsExceeded {
             total_number_of_actions: actions.len() as u64,
             limit: limit_config.max_actions_per_receipt,
         });
@@ -297,22 +327,25 @@ pub(crate) fn validate_actions(
     while let Some(action) = iter.next() {
         if let Action::DeleteAccount(_) = action {
             if iter.peek().is_some() {
-                return Err(ActionsValidationError::DeleteActionMustBeFinal);
+                violations.push(ActionsValidationError::DeleteActionMustBeFinal);
             }
         }
-        validate_action(limit_config, action)?;
+        if let Err(err) = validate_action(limit_config, action) {
+            violations.push(err);
+        }
     }
 
-    let total_prepaid_gas =
-        total_prepaid_gas(actions).map_err(|_| ActionsValidationError::IntegerOverflow)?;
-    if total_prepaid_gas > limit_config.max_total_prepaid_gas {
-        return Err(ActionsValidationError::TotalPrepaidGasExceeded {
-            total_prepaid_gas,
-            limit: limit_config.max_total_prepaid_gas,
-        });
+    match total_prepaid_gas(actions) {
+        Ok(total_prepaid_gas) if total_prepaid_gas > limit_config.max_total_prepaid_gas => {
+            violations.push(ActionsValidationError::TotalPrepaidGasExceeded {
+                total_prepaid_gas,
+                limit: limit_config.max_total_prepaid_gas,
+            });
+        }
+        Ok(_) => {}
+        Err(_) => violations.push(ActionsValidationError::IntegerOverflow),
     }
-
-    Ok(())
+    violations
 }
 
 /// Validates a single given action. Checks limits if applicable.
@@ -1148,6 +1181,55 @@ mod tests {
         .expect("valid transaction");
     }
 
+    #[test]
+    fn test_transaction_limit_violations() {
+        let signer =
+            InMemorySigner::from_seed(alice_account(), KeyType::ED25519, alice_account().as_ref());
+        let transaction = SignedTransaction::from_actions(
+            1,
+            alice_account(),
+            bob_account(),
+            &signer,
+            vec![
+                Action::DeleteAccount(DeleteAccountAction { beneficiary_id: bob_account() }),
+                Action::DeployContract(DeployContractAction { code: vec![1; 5] }),
+            ],
+            CryptoHash::default(),
+        );
+        let transaction_size = transaction.get_size();
+        let mut limit_config = VMLimitConfig::test();
+        assert_eq!(
+            transaction_limit_violations(&limit_config, &transaction),
+            vec![InvalidTxError::ActionsValidation(
+                ActionsValidationError::DeleteActionMustBeFinal
+            )]
+        );
+
+        limit_config.max_transaction_size = transaction_size - 1;
+        limit_config.max_actions_per_receipt = 1;
+        limit_config.max_contract_size = 4;
+        assert_eq!(
+            transaction_limit_violations(&limit_config, &transaction),
+            vec![
+                InvalidTxError::TransactionSizeExceeded {
+                    size: transaction_size,
+                    limit: transaction_size - 1
+                },
+                InvalidTxError::ActionsValidation(
+                    ActionsValidationError::TotalNumberOfActionsExceeded {
+                        total_number_of_actions: 2,
+                        limit: 1
+                    }
+                ),
+                InvalidTxError::ActionsValidation(ActionsValidationError::DeleteActionMustBeFinal),
+                InvalidTxError::ActionsValidation(ActionsValidationError::ContractSizeExceeded {
+                    size: 5,
+                    limit: 4
+                }),
+            ]
+        );
+    }
+
     // Receipts
 
     #[test]
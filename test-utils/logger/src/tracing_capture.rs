@@ -8,7 +8,7 @@ use std::sync::{Arc, Mutex};
 /// path.
 pub struct TracingCapture {
     captured: Arc<Mutex<Captured>>,
-    _guard: tracing::subscriber::DefaultGuard,
+    _guard: Option<tracing::subscriber::DefaultGuard>,
 }
 
 #[derive(Default)]
@@ -23,8 +23,18 @@ impl TracingCapture {
     pub fn enable() -> TracingCapture {
         let subscriber = Subscriber::default();
         let captured = subscriber.0.clone();
-        let _guard = tracing::subscriber::set_default(subscriber);
-        TracingCapture { captured, _guard }
+        let guard = tracing::subscriber::set_default(subscriber);
+        TracingCapture { captured, _guard: Some(guard) }
+    }
+    /// Captures the logs of all the threads, e.g. of the actors and the HTTP
+    /// servers.  Panics if a global subscriber is already set, so it's for
+    /// test binaries of their own.
+    pub fn enable_global() -> TracingCapture {
+        let subscriber = Subscriber::default();
+        let captured = subscriber.0.clone();
+        tracing::subscriber::set_global_default(subscriber)
+            .expect("a global subscriber is already set");
+        TracingCapture { captured, _guard: None }
     }
     pub fn drain(&mut self) -> Vec<String> {
         let mut guard = self.captured.lock().unwrap();
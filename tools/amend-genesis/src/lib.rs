@@ -0,0 +1,356 @@
+//! Applies declarative edits to a genesis config and its records file, e.g.
+//! to start a forknet from a dump of mainnet state.
+//!
+//! The records are streamed from the input to the output file, so the edits
+//! work for records files of any size.  The total supply is recomputed from
+//! the edited records and the result is checked with the same validation the
+//! node performs on startup.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::ser::{SerializeSeq, Serializer};
+use serde::Deserialize;
+
+use near_chain_configs::genesis_validate::genesis_validation_errors;
+use near_chain_configs::{stream_records_from_file, Genesis, GenesisConfig, GenesisRecords};
+use near_crypto::PublicKey;
+use near_primitives::account::{AccessKey, Account};
+use near_primitives::hash::CryptoHash;
+use near_primitives::serialize::u128_dec_format;
+use near_primitives::state_record::StateRecord;
+use near_primitives::types::{AccountId, AccountInfo, Balance, BlockHeightDelta};
+use near_primitives::version::ProtocolVersion;
+
+/// Edits to apply to the genesis, read from a JSON file.  Fields which are
+/// missing are left as they are, e.g.:
+///
+/// ```json
+/// {
+///   "chain_id": "forknet",
+///   "genesis_time": "2022-06-01T00:00:00Z",
+///   "epoch_length": 1000,
+///   "validators": [
+///     {"account_id": "node0", "public_key": "ed25519:...", "amount": "50000000000000000000000000000"}
+///   ],
+///   "accounts": [
+///     {"account_id": "test.near", "amount": "1000000000000000000000000000", "public_keys": ["ed25519:..."]}
+///   ]
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisChanges {
+    pub chain_id: Option<String>,
+    pub genesis_time: Option<DateTime<Utc>>,
+    pub epoch_length: Option<BlockHeightDelta>,
+    pub protocol_version: Option<ProtocolVersion>,
+    /// Replaces the validators.  Accounts which stop being validators get
+    /// their stake unlocked, accounts of new validators are created if they
+    /// don't exist, with the validator key as their full access key.
+    pub validators: Option<Vec<AccountInfo>>,
+    /// Accounts to create or whose liquid balance to set.
+    #[serde(default)]
+    pub accounts: Vec<AccountChange>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AccountChange {
+    pub account_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+    /// Full access keys to add to the account.
+    #[serde(default)]
+    pub public_keys: Vec<PublicKey>,
+}
+
+#[derive(clap::Parser)]
+pub struct AmendGenesisCommand {
+    /// Genesis config to amend.
+    #[clap(long, parse(from_os_str))]
+    genesis_file_in: PathBuf,
+    /// Records file belonging to the genesis config.
+    #[clap(long, parse(from_os_str))]
+    records_file_in: PathBuf,
+    /// JSON file with the changes to apply.
+    #[clap(long, parse(from_os_str))]
+    changes: PathBuf,
+    #[clap(long, parse(from_os_str))]
+    genesis_file_out: PathBuf,
+    #[clap(long, parse(from_os_str))]
+    records_file_out: PathBuf,
+}
+
+impl AmendGenesisCommand {
+    pub fn run(self) -> anyhow::Result<()> {
+        let changes = std::fs::read(&self.changes)
+            .with_context(|| format!("failed to read {}", self.changes.display()))?;
+        let changes: GenesisChanges = serde_json::from_slice(&changes)
+            .with_context(|| format!("failed to parse {}", self.changes.display()))?;
+        amend_genesis(
+            &changes,
+            &self.genesis_file_in,
+            &self.records_file_in,
+            &self.genesis_file_out,
+            &self.records_file_out,
+        )
+    }
+}
+
+/// Writes the amended genesis config and records, then validates them.
+///
+/// The output files are written even if the validation fails so that the
+/// problems can be inspected.
+pub fn amend_genesis(
+    changes: &GenesisChanges,
+    genesis_file_in: &Path,
+    records_file_in: &Path,
+    genesis_file_out: &Path,
+    records_file_out: &Path,
+) -> anyhow::Result<()> {
+    let mut config = GenesisConfig::from_file(genesis_file_in)?;
+    if let Some(chain_id) = &changes.chain_id {
+        config.chain_id = chain_id.clone();
+    }
+    if let Some(genesis_time) = changes.genesis_time {
+        config.genesis_time = genesis_time;
+    }
+    if let Some(epoch_length) = changes.epoch_length {
+        config.epoch_length = epoch_length;
+    }
+    if let Some(protocol_version) = changes.protocol_version {
+        config.protocol_version = protocol_version;
+    }
+    let validators: Option<HashMap<&AccountId, Balance>> = changes
+        .validators
+        .as_ref()
+        .map(|v| v.iter().map(|validator| (&validator.account_id, validator.amount)).collect());
+    if let Some(new_validators) = &changes.validators {
+        config.validators = new_validators.clone();
+    }
+    let account_changes: HashMap<&AccountId, &AccountChange> =
+        changes.accounts.iter().map(|change| (&change.account_id, change)).collect();
+
+    let input = File::open(records_file_in)
+        .with_context(|| format!("failed to open {}", records_file_in.display()))?;
+    let output = File::create(records_file_out)
+        .with_context(|| format!("failed to create {}", records_file_out.display()))?;
+    let mut output = BufWriter::new(output);
+    let mut ser = serde_json::Serializer::new(&mut output);
+    let mut seq = ser.serialize_seq(None)?;
+    let mut total_supply: Balance = 0;
+    let mut existing_accounts = HashSet::new();
+    let mut write_error = None;
+    let mut write = |record: StateRecord| {
+        if let StateRecord::Account { account, .. } = &record {
+            total_supply += account.amount() + account.locked();
+        }
+        if write_error.is_none() {
+            write_error = seq.serialize_element(&record).err();
+        }
+    };
+    stream_records_from_file(BufReader::new(input), |record| match record {
+        StateRecord::Account { account_id, mut account } => {
+            if let Some(validators) = &validators {
+                let stake = validators.get(&account_id).copied().unwrap_or(0);
+                if stake == 0 {
+                    account.set_amount(account.amount() + account.locked());
+                }
+                account.set_locked(stake);
+            }
+            if let Some(change) = account_changes.get(&account_id) {
+                account.set_amount(change.amount);
+            }
+            if validators.as_ref().map_or(false, |v| v.contains_key(&account_id))
+                || account_changes.contains_key(&account_id)
+            {
+                existing_accounts.insert(account_id.clone());
+            }
+            write(StateRecord::Account { account_id, account });
+        }
+        record => write(record),
+    })
+    .with_context(|| format!("failed to read records from {}", records_file_in.display()))?;
+
+    for change in &changes.accounts {
+        if !existing_accounts.contains(&change.account_id) {
+            let stake = validators.as_ref().and_then(|v| v.get(&change.account_id).copied());
+            let account = Account::new(change.amount, stake.unwrap_or(0), CryptoHash::default(), 0);
+            write(StateRecord::Account { account_id: change.account_id.clone(), account });
+            existing_accounts.insert(change.account_id.clone());
+        }
+        for public_key in &change.public_keys {
+            write(StateRecord::AccessKey {
+                account_id: change.account_id.clone(),
+                public_key: public_key.clone(),
+                access_key: AccessKey::full_access(),
+            });
+        }
+    }
+    for validator in changes.validators.iter().flatten() {
+        if !existing_accounts.contains(&validator.account_id) {
+            let account = Account::new(0, validator.amount, CryptoHash::default(), 0);
+            write(StateRecord::Account { account_id: validator.account_id.clone(), account });
+            write(StateRecord::AccessKey {
+                account_id: validator.account_id.clone(),
+                public_key: validator.public_key.clone(),
+                access_key: AccessKey::full_access(),
+            });
+            existing_accounts.insert(validator.account_id.clone());
+        }
+    }
+    drop(write);
+    if let Some(err) = write_error {
+        return Err(err).with_context(|| format!("failed to write {}", records_file_out.display()));
+    }
+    seq.end()?;
+    output.flush()?;
+
+    config.total_supply = total_supply;
+    config.to_file(genesis_file_out);
+
+    let genesis = Genesis {
+        config,
+        records: GenesisRecords(vec![]),
+        records_file: records_file_out.to_path_buf(),
+    };
+    let errors = genesis_validation_errors(&genesis);
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "amended genesis is invalid:\n{}",
+            errors.iter().map(|error| format!("  {}", error)).collect::<Vec<_>>().join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use near_chain_configs::{Genesis, GenesisConfig, GenesisRecords};
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::account::Account;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::state_record::StateRecord;
+    use near_primitives::types::AccountInfo;
+
+    use super::{amend_genesis, AccountChange, GenesisChanges};
+
+    const VALID_ED25519_RISTRETTO_KEY: &str = "ed25519:KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7";
+
+    fn write_genesis(dir: &Path) {
+        let mut config = GenesisConfig::default();
+        config.chain_id = "mainnet".to_string();
+        config.validators = vec![AccountInfo {
+            account_id: "old".parse().unwrap(),
+            public_key: VALID_ED25519_RISTRETTO_KEY.parse().unwrap(),
+            amount: 50,
+        }];
+        config.total_supply = 250;
+        config.to_file(dir.join("genesis.json"));
+        let records = GenesisRecords(vec![
+            StateRecord::Account {
+                account_id: "old".parse().unwrap(),
+                account: Account::new(100, 50, CryptoHash::default(), 0),
+            },
+            StateRecord::Account {
+                account_id: "alice".parse().unwrap(),
+                account: Account::new(100, 0, CryptoHash::default(), 0),
+            },
+        ]);
+        records.to_file(dir.join("records.json"));
+    }
+
+    fn amend(dir: &Path, changes: &GenesisChanges) -> anyhow::Result<Genesis> {
+        amend_genesis(
+            changes,
+            &dir.join("genesis.json"),
+            &dir.join("records.json"),
+            &dir.join("new_genesis.json"),
+            &dir.join("new_records.json"),
+        )?;
+        Ok(Genesis {
+            config: GenesisConfig::from_file(dir.join("new_genesis.json")).unwrap(),
+            records: GenesisRecords::from_file(dir.join("new_records.json")),
+            records_file: Default::default(),
+        })
+    }
+
+    #[test]
+    fn test_amend_genesis() {
+        let dir = tempfile::tempdir().unwrap();
+        write_genesis(dir.path());
+        let bob_key = PublicKey::empty(KeyType::ED25519);
+        let changes = GenesisChanges {
+            chain_id: Some("forknet".to_string()),
+            epoch_length: Some(100),
+            validators: Some(vec![AccountInfo {
+                account_id: "new".parse().unwrap(),
+                public_key: VALID_ED25519_RISTRETTO_KEY.parse().unwrap(),
+                amount: 1000,
+            }]),
+            accounts: vec![
+                AccountChange {
+                    account_id: "alice".parse().unwrap(),
+                    amount: 7,
+                    public_keys: vec![],
+                },
+                AccountChange {
+                    account_id: "bob".parse().unwrap(),
+                    amount: 3,
+                    public_keys: vec![bob_key.clone()],
+                },
+            ],
+            ..Default::default()
+        };
+        let genesis = amend(dir.path(), &changes).unwrap();
+
+        assert_eq!(genesis.config.chain_id, "forknet");
+        assert_eq!(genesis.config.epoch_length, 100);
+        assert_eq!(genesis.config.validators, changes.validators.unwrap());
+        // old: 150 unstaked, alice: 7, bob: 3, new: 1000 staked.
+        assert_eq!(genesis.config.total_supply, 1160);
+        let records = genesis.records.0;
+        assert_eq!(records.len(), 6);
+        match &records[0] {
+            StateRecord::Account { account_id, account } => {
+                assert_eq!(account_id.as_ref(), "old");
+                assert_eq!((account.amount(), account.locked()), (150, 0));
+            }
+            record => panic!("unexpected record {:?}", record),
+        }
+        match &records[3] {
+            StateRecord::AccessKey { account_id, public_key, .. } => {
+                assert_eq!(account_id.as_ref(), "bob");
+                assert_eq!(public_key, &bob_key);
+            }
+            record => panic!("unexpected record {:?}", record),
+        }
+    }
+
+    #[test]
+    fn test_amend_genesis_reports_all_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        write_genesis(dir.path());
+        let validator = AccountInfo {
+            account_id: "new".parse().unwrap(),
+            public_key: PublicKey::empty(KeyType::ED25519),
+            amount: 10,
+        };
+        let changes = GenesisChanges {
+            validators: Some(vec![validator.clone(), validator]),
+            ..Default::default()
+        };
+        let err = amend(dir.path(), &changes).unwrap_err().to_string();
+        assert!(err.contains("validator staking key is not valid"), "{}", err);
+        assert!(err.contains("Duplicate account in validators"), "{}", err);
+        // The output is kept for inspection.
+        assert!(dir.path().join("new_records.json").exists());
+    }
+}
@@ -76,6 +76,12 @@ impl MockPeerManagerActor {
             received_bytes_per_sec: 0,
             known_producers: vec![],
             peer_counter: 0,
+            clock_skew_millis: None,
+            dns_seed_peers: Default::default(),
+            recent_handshake_refusals: Default::default(),
+            pinned_peers: Default::default(),
+            blacklist_audit: Default::default(),
+            discounted_shards: Default::default(),
         };
         Self {
             client_addr,
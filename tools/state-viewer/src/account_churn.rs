@@ -0,0 +1,348 @@
+//! Accounts created and deleted in a range of blocks, found in the outcomes of
+//! the receipts executed in them.
+//!
+//! A receipt creates an account with a `CreateAccount` action, or implicitly
+//! when it transfers tokens to a 64 character hex account id which doesn't
+//! exist yet.  The latter is detected by the access key the runtime adds to
+//! the new account, which is in the state changes of the block.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::account_pattern::AccountPattern;
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::ReceiptEnum;
+use near_primitives::transaction::{Action, ExecutionStatus};
+use near_primitives::types::{
+    AccountId, BlockHeight, ShardId, StateChangeCause, StateChangeValue, StateChanges,
+};
+use near_store::Store;
+use nearcore::NearConfig;
+
+use crate::output::{Output, OutputFormat};
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChurnKind {
+    Created,
+    /// Created by a transfer to an implicit account id.
+    ImplicitlyCreated,
+    Deleted,
+}
+
+impl ChurnKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChurnKind::Created => "created",
+            ChurnKind::ImplicitlyCreated => "implicitly_created",
+            ChurnKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct ChurnEvent {
+    pub height: BlockHeight,
+    pub block_hash: CryptoHash,
+    pub kind: ChurnKind,
+    pub account_id: AccountId,
+    pub predecessor_id: AccountId,
+    /// Account which got the balance of a deleted account.
+    pub beneficiary_id: Option<AccountId>,
+}
+
+/// Whether the actions may create the receiver as an implicit account, which
+/// they do if it doesn't exist yet.
+fn may_create_implicit(receiver_id: &AccountId, actions: &[Action]) -> bool {
+    receiver_id.is_implicit() && matches!(actions.first(), Some(Action::Transfer(_)))
+}
+
+/// Creations and deletions of the receiver by the actions of a successful
+/// receipt, with the beneficiary of deletions.
+fn receipt_churn(
+    receiver_id: &AccountId,
+    actions: &[Action],
+    created_implicit: bool,
+) -> Vec<(ChurnKind, Option<AccountId>)> {
+    let mut churn = vec![];
+    if created_implicit && may_create_implicit(receiver_id, actions) {
+        churn.push((ChurnKind::ImplicitlyCreated, None));
+    }
+    for action in actions {
+        match action {
+            Action::CreateAccount(_) => churn.push((ChurnKind::Created, None)),
+            Action::DeleteAccount(action) => {
+                churn.push((ChurnKind::Deleted, Some(action.beneficiary_id.clone())))
+            }
+            _ => {}
+        }
+    }
+    churn
+}
+
+/// Status of the outcome of the transaction or receipt in the block.
+fn outcome_status(
+    chain_store: &ChainStore,
+    id: &CryptoHash,
+    block_hash: &CryptoHash,
+) -> anyhow::Result<Option<ExecutionStatus>> {
+    Ok(chain_store
+        .get_outcomes_by_id(id)?
+        .into_iter()
+        .find(|outcome| &outcome.block_hash == block_hash)
+        .map(|outcome| outcome.outcome_with_id.outcome.status))
+}
+
+/// Whether the receipt added an access key to the account.
+fn added_access_key(
+    state_changes: &StateChanges,
+    receipt_id: &CryptoHash,
+    account: &AccountId,
+) -> bool {
+    state_changes.iter().any(|change| {
+        let by_receipt = matches!(
+            &change.cause,
+            StateChangeCause::ReceiptProcessing { receipt_hash } if receipt_hash == receipt_id
+        );
+        let adds_key = matches!(
+            &change.value,
+            StateChangeValue::AccessKeyUpdate { account_id, .. } if account_id == account
+        );
+        by_receipt && adds_key
+    })
+}
+
+#[derive(Default)]
+struct ChurnStats {
+    events: u64,
+    /// Receipts executed in the range but not in the database.
+    missing_receipts: u64,
+}
+
+fn block_churn(
+    chain_store: &mut ChainStore,
+    height: BlockHeight,
+    block_hash: &CryptoHash,
+    stats: &mut ChurnStats,
+) -> anyhow::Result<Vec<ChurnEvent>> {
+    let num_shards = chain_store.get_block(block_hash)?.chunks().len() as ShardId;
+    let mut state_changes: Option<StateChanges> = None;
+    let mut events = vec![];
+    for shard_id in 0..num_shards {
+        let ids = chain_store.get_outcomes_by_block_hash_and_shard_id(block_hash, shard_id)?;
+        // Transactions signed by their receiver are converted into receipts
+        // which are executed right away and not stored, their actions are
+        // the ones of the transaction.
+        let mut local_receipts = HashMap::new();
+        for id in &ids {
+            let transaction = match chain_store.get_transaction(id)? {
+                Some(tx) if tx.transaction.signer_id == tx.transaction.receiver_id => {
+                    tx.transaction.clone()
+                }
+                _ => continue,
+            };
+            if let Some(ExecutionStatus::SuccessReceiptId(receipt_id)) =
+                outcome_status(chain_store, id, block_hash)?
+            {
+                local_receipts.insert(receipt_id, transaction);
+            }
+        }
+
+        for id in &ids {
+            let (predecessor_id, receiver_id, actions) = match chain_store.get_receipt(id)? {
+                Some(receipt) => match &receipt.receipt {
+                    ReceiptEnum::Action(action_receipt) => (
+                        receipt.predecessor_id.clone(),
+                        receipt.receiver_id.clone(),
+                        action_receipt.actions.clone(),
+                    ),
+                    ReceiptEnum::Data(_) => continue,
+                },
+                None => match local_receipts.remove(id) {
+                    Some(tx) => (tx.signer_id, tx.receiver_id, tx.actions),
+                    None => {
+                        if chain_store.get_transaction(id)?.is_none() {
+                            stats.missing_receipts += 1;
+                        }
+                        continue;
+                    }
+                },
+            };
+            if !matches!(
+                outcome_status(chain_store, id, block_hash)?,
+                Some(ExecutionStatus::SuccessValue(_) | ExecutionStatus::SuccessReceiptId(_))
+            ) {
+                continue;
+            }
+            let created_implicit = may_create_implicit(&receiver_id, &actions) && {
+                if state_changes.is_none() {
+                    state_changes =
+                        Some(chain_store.get_state_changes_with_cause_in_block(block_hash)?);
+                }
+                added_access_key(state_changes.as_ref().unwrap(), id, &receiver_id)
+            };
+            for (kind, beneficiary_id) in receipt_churn(&receiver_id, &actions, created_implicit) {
+                events.push(ChurnEvent {
+                    height,
+                    block_hash: *block_hash,
+                    kind,
+                    account_id: receiver_id.clone(),
+                    predecessor_id: predecessor_id.clone(),
+                    beneficiary_id,
+                });
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Writes the event as a line of JSON, of comma separated values or of text.
+fn write_event(
+    out: &mut impl Write,
+    format: OutputFormat,
+    event: &ChurnEvent,
+) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string(event)?)?,
+        // Neither account ids nor hashes contain commas.
+        OutputFormat::Csv => writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            event.height,
+            event.block_hash,
+            event.kind.as_str(),
+            event.account_id,
+            event.predecessor_id,
+            event.beneficiary_id.as_ref().map_or("", |id| id.as_ref())
+        )?,
+        OutputFormat::Text => {
+            write!(
+                out,
+                "#{} {}: {} {} by {}",
+                event.height,
+                event.block_hash,
+                event.account_id,
+                event.kind.as_str(),
+                event.predecessor_id
+            )?;
+            match &event.beneficiary_id {
+                Some(beneficiary_id) => writeln!(out, ", balance to {}", beneficiary_id)?,
+                None => writeln!(out)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the accounts created and deleted in the blocks of the range as it
+/// goes through them, only the ones matching one of the patterns if there's
+/// any.
+pub(crate) fn account_churn(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    accounts: &[AccountPattern],
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if from_height > to_height {
+        anyhow::bail!("--from-height {} is above --to-height {}", from_height, to_height);
+    }
+    let mut chain_store = ChainStore::new(
+        store,
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    let format = output.format();
+    if format == OutputFormat::Csv {
+        writeln!(out, "height,block_hash,kind,account_id,predecessor_id,beneficiary_id")?;
+    }
+    let mut stats = ChurnStats::default();
+    for height in from_height..=to_height {
+        let block_hash = match chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            Err(_) => continue,
+        };
+        for event in block_churn(&mut chain_store, height, &block_hash, &mut stats)? {
+            if !accounts.is_empty() && !AccountPattern::any_matches(accounts, &event.account_id) {
+                continue;
+            }
+            write_event(&mut out, format, &event)?;
+            stats.events += 1;
+        }
+        out.flush()?;
+    }
+    eprintln!("Found {} creations and deletions", stats.events);
+    if stats.missing_receipts > 0 {
+        eprintln!(
+            "{} executed receipts weren't found and are skipped, is the node archival?",
+            stats.missing_receipts
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::transaction::{CreateAccountAction, DeleteAccountAction, TransferAction};
+
+    #[test]
+    fn test_receipt_churn() {
+        let named: AccountId = "alice.near".parse().unwrap();
+        let implicit: AccountId = "a".repeat(64).parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let transfer = Action::Transfer(TransferAction { deposit: 1 });
+
+        let create = [Action::CreateAccount(CreateAccountAction {}), transfer.clone()];
+        assert_eq!(receipt_churn(&named, &create, false), vec![(ChurnKind::Created, None)]);
+        let delete = [Action::DeleteAccount(DeleteAccountAction { beneficiary_id: bob.clone() })];
+        assert_eq!(receipt_churn(&named, &delete, false), vec![(ChurnKind::Deleted, Some(bob))]);
+
+        let transfer = [transfer];
+        assert!(may_create_implicit(&implicit, &transfer));
+        assert!(!may_create_implicit(&named, &transfer));
+        assert_eq!(
+            receipt_churn(&implicit, &transfer, true),
+            vec![(ChurnKind::ImplicitlyCreated, None)]
+        );
+        // A transfer to an implicit account which exists already.
+        assert_eq!(receipt_churn(&implicit, &transfer, false), vec![]);
+        assert_eq!(receipt_churn(&named, &transfer, true), vec![]);
+    }
+
+    #[test]
+    fn test_write_event() {
+        let event = ChurnEvent {
+            height: 10,
+            block_hash: CryptoHash::default(),
+            kind: ChurnKind::Deleted,
+            account_id: "alice.near".parse().unwrap(),
+            predecessor_id: "alice.near".parse().unwrap(),
+            beneficiary_id: Some("bob.near".parse().unwrap()),
+        };
+        let mut out = vec![];
+        write_event(&mut out, OutputFormat::Csv, &event).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("10,{},deleted,alice.near,alice.near,bob.near\n", CryptoHash::default())
+        );
+        let mut out = vec![];
+        write_event(&mut out, OutputFormat::Json, &event).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["kind"], "deleted");
+        assert_eq!(json["beneficiary_id"], "bob.near");
+        let mut out = vec![];
+        write_event(&mut out, OutputFormat::Text, &event).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "#10 {}: alice.near deleted by alice.near, balance to bob.near\n",
+                CryptoHash::default()
+            )
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Cross-check of the receipts the chunks of a block send to each other.
+//!
+//! Every new chunk of the block is applied, and the outgoing receipts of each
+//! shard are grouped by the shard they're for the same way the chunk producer
+//! of the next chunk of the shard does, see `ShardsManager::make_outgoing_receipts_proofs`.
+//! They're compared with the receipt proofs stored as incoming receipts of the
+//! next block, and the proofs are checked against the outgoing receipts root of
+//! the chunks which sent them.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use anyhow::Context;
+use near_chain::{Chain, ChainStore, ChainStoreAccess, RuntimeAdapter};
+use near_primitives::hash::CryptoHash;
+use near_primitives::merkle::{merklize, verify_path};
+use near_primitives::receipt::Receipt;
+use near_primitives::shard_layout::account_id_to_shard_id;
+use near_primitives::sharding::{ReceiptList, ReceiptProof};
+use near_primitives::types::{BlockHeight, ShardId};
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+
+use crate::commands::apply_block;
+use crate::output::{failure, FailureKind};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ReceiptMismatch {
+    /// Produced by applying the chunk, but not in the incoming receipts of
+    /// the shard it's for.
+    NotDelivered { from_shard_id: ShardId, to_shard_id: ShardId, receipt_id: CryptoHash },
+    /// In the incoming receipts of the shard, but not produced by applying
+    /// the chunk which sent it.
+    NotProduced { from_shard_id: ShardId, to_shard_id: ShardId, receipt_id: CryptoHash },
+}
+
+/// Compares the receipts sent from the shard, grouped by the shard they're
+/// for, with the receipt proofs from the shard delivered to all the shards.
+fn compare_receipts(
+    from_shard_id: ShardId,
+    outgoing: &HashMap<ShardId, Vec<Receipt>>,
+    delivered: &[ReceiptProof],
+) -> Vec<ReceiptMismatch> {
+    let produced: BTreeSet<_> = outgoing
+        .iter()
+        .flat_map(|(to_shard_id, receipts)| {
+            receipts.iter().map(move |receipt| (*to_shard_id, receipt.receipt_id))
+        })
+        .collect();
+    let delivered: BTreeSet<_> = delivered
+        .iter()
+        .flat_map(|ReceiptProof(receipts, shard_proof)| {
+            receipts.iter().map(move |receipt| (shard_proof.to_shard_id, receipt.receipt_id))
+        })
+        .collect();
+    let not_delivered = produced.difference(&delivered).map(|&(to_shard_id, receipt_id)| {
+        ReceiptMismatch::NotDelivered { from_shard_id, to_shard_id, receipt_id }
+    });
+    let not_produced = delivered.difference(&produced).map(|&(to_shard_id, receipt_id)| {
+        ReceiptMismatch::NotProduced { from_shard_id, to_shard_id, receipt_id }
+    });
+    not_delivered.chain(not_produced).collect()
+}
+
+/// Applies the new chunks of the block at the height and checks that what
+/// their shards send is what the next block delivers.  Fails if anything
+/// doesn't match.
+pub(crate) fn apply_block_chunks(
+    height: BlockHeight,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let block_hash = chain_store.get_block_hash_by_height(height)?;
+    let next_block_hash = *chain_store
+        .get_next_block_hash(&block_hash)
+        .context("the block isn't followed by another one on the canonical chain")?;
+    let block = chain_store.get_block(&block_hash)?.clone();
+    let next_block = chain_store.get_block(&next_block_hash)?.clone();
+    let shard_layout = runtime.get_shard_layout(block.header().epoch_id())?;
+    let next_shard_layout = runtime.get_shard_layout_from_prev_block(&block_hash)?;
+    let resharded = shard_layout != next_shard_layout;
+
+    let mut outgoing = HashMap::new();
+    for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+        let shard_id = shard_id as ShardId;
+        if chunk_header.height_included() != height {
+            println!("shard {}: no new chunk in the block, skipping", shard_id);
+            continue;
+        }
+        let (_, apply_result) = apply_block(block_hash, shard_id, &runtime, &mut chain_store);
+        println!(
+            "shard {}: applied the chunk, {} outgoing receipts",
+            shard_id,
+            apply_result.outgoing_receipts.len()
+        );
+        outgoing.insert(shard_id, apply_result.outgoing_receipts);
+    }
+
+    let (mut mismatches, mut bad_roots, mut bad_proofs) = (vec![], 0, 0);
+    let next_height = next_block.header().height();
+    for chunk_header in next_block.chunks().iter() {
+        let shard_id = chunk_header.shard_id();
+        if chunk_header.height_included() != next_height {
+            println!(
+                "shard {}: no new chunk in the next block, its receipts are sent later",
+                shard_id
+            );
+            continue;
+        }
+        // The receipts of the parent shard are split between its children.
+        let from_shard_id =
+            if resharded { next_shard_layout.get_parent_shard_id(shard_id)? } else { shard_id };
+        let mut receipts = match outgoing.get(&from_shard_id) {
+            Some(receipts) => receipts.clone(),
+            None => continue,
+        };
+        if resharded {
+            receipts.retain(|receipt| {
+                account_id_to_shard_id(&receipt.receiver_id, &next_shard_layout) == shard_id
+            });
+        }
+
+        let root = chunk_header.outgoing_receipts_root();
+        let (expected_root, _) =
+            merklize(&Chain::build_receipts_hashes(&receipts, &next_shard_layout));
+        if expected_root != root {
+            println!(
+                "shard {}: outgoing receipts root of the next chunk is {}, the receipts produced \
+                 have root {}",
+                shard_id, root, expected_root
+            );
+            bad_roots += 1;
+        }
+        let mut delivered = vec![];
+        for to_shard_id in 0..next_shard_layout.num_shards() {
+            let proofs = chain_store.get_incoming_receipts(&next_block_hash, to_shard_id).cloned();
+            for proof in proofs.unwrap_or_default() {
+                let ReceiptProof(proof_receipts, shard_proof) = &proof;
+                if shard_proof.from_shard_id != shard_id {
+                    continue;
+                }
+                let list = ReceiptList(shard_proof.to_shard_id, proof_receipts);
+                if !verify_path(root, &shard_proof.proof, &list) {
+                    println!(
+                        "shard {}: proof of the receipts for shard {} doesn't verify",
+                        shard_id, shard_proof.to_shard_id
+                    );
+                    bad_proofs += 1;
+                }
+                delivered.push(proof);
+            }
+        }
+        let grouped = Chain::group_receipts_by_shard(receipts, &next_shard_layout);
+        mismatches.extend(compare_receipts(shard_id, &grouped, &delivered));
+    }
+
+    for mismatch in &mismatches {
+        match mismatch {
+            ReceiptMismatch::NotDelivered { from_shard_id, to_shard_id, receipt_id } => println!(
+                "receipt {} from shard {} to shard {} was produced but not delivered",
+                receipt_id, from_shard_id, to_shard_id
+            ),
+            ReceiptMismatch::NotProduced { from_shard_id, to_shard_id, receipt_id } => println!(
+                "receipt {} from shard {} to shard {} was delivered but not produced",
+                receipt_id, from_shard_id, to_shard_id
+            ),
+        }
+    }
+    if !mismatches.is_empty() || bad_roots > 0 || bad_proofs > 0 {
+        return Err(failure(
+            FailureKind::Corruption,
+            format!(
+                "{} receipts don't match, {} outgoing receipts roots and {} proofs are wrong",
+                mismatches.len(),
+                bad_roots,
+                bad_proofs
+            ),
+        ));
+    }
+    println!("The receipts delivered by block {} match", next_block_hash);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+    use near_primitives::sharding::ShardProof;
+
+    fn receipt(receiver_id: &str, seed: &[u8]) -> Receipt {
+        let mut receipt = Receipt::new_balance_refund(&receiver_id.parse().unwrap(), 1);
+        receipt.receipt_id = hash(seed);
+        receipt
+    }
+
+    fn proof(to_shard_id: ShardId, receipts: Vec<Receipt>) -> ReceiptProof {
+        let shard_proof = ShardProof { from_shard_id: 0, to_shard_id, proof: vec![] };
+        ReceiptProof(receipts, shard_proof)
+    }
+
+    #[test]
+    fn test_compare_receipts() {
+        let (a, b, c) = (receipt("a.near", b"a"), receipt("b.near", b"b"), receipt("c.near", b"c"));
+        let outgoing = HashMap::from([(0, vec![a.clone()]), (1, vec![b.clone()])]);
+        let delivered = [proof(0, vec![a.clone()]), proof(1, vec![b.clone()])];
+        assert!(compare_receipts(0, &outgoing, &delivered).is_empty());
+
+        // Routed to the wrong shard, and delivered out of thin air.
+        let delivered = [proof(0, vec![a, b.clone()]), proof(1, vec![c.clone()])];
+        assert_eq!(
+            compare_receipts(0, &outgoing, &delivered),
+            vec![
+                ReceiptMismatch::NotDelivered {
+                    from_shard_id: 0,
+                    to_shard_id: 1,
+                    receipt_id: b.receipt_id
+                },
+                ReceiptMismatch::NotProduced {
+                    from_shard_id: 0,
+                    to_shard_id: 0,
+                    receipt_id: b.receipt_id
+                },
+                ReceiptMismatch::NotProduced {
+                    from_shard_id: 0,
+                    to_shard_id: 1,
+                    receipt_id: c.receipt_id
+                },
+            ]
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use std::fs::File;
 use std::io::Write;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Mutex;
+use std::time::Instant;
 
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
@@ -13,15 +14,18 @@ use near_chain_configs::Genesis;
 use near_primitives::borsh::maybestd::sync::Arc;
 use near_primitives::hash::CryptoHash;
 use near_primitives::receipt::DelayedReceiptIndices;
+use near_primitives::shard_layout::get_block_shard_uid;
 use near_primitives::transaction::{
     Action, ExecutionOutcomeWithId, ExecutionOutcomeWithIdAndProof,
 };
 use near_primitives::trie_key::TrieKey;
 use near_primitives::types::chunk_extra::ChunkExtra;
-use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::types::{BlockHeight, ShardId, StateRoot};
 use near_store::{get, DBCol, Store};
 use nearcore::NightshadeRuntime;
 
+use crate::witness::{WitnessLimit, WitnessLimits, WitnessSize, WITNESS_CSV_HEADER};
+
 fn timestamp() -> u64 {
     use std::time::{SystemTime, UNIX_EPOCH};
     SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
@@ -33,11 +37,43 @@ fn timestamp_ms() -> u64 {
 }
 pub const TGAS: u64 = 1024 * 1024 * 1024 * 1024;
 
+/// Which heights of the range are applied, by whether the block contains a
+/// new chunk of the shard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkPresenceFilter {
+    All,
+    /// Only heights at which the chunk is missing and the state of the
+    /// previous chunk carries over.
+    OnlyMissingChunks,
+    OnlyNewChunks,
+}
+
+impl ChunkPresenceFilter {
+    fn matches(self, chunk_present: bool) -> bool {
+        match self {
+            ChunkPresenceFilter::All => true,
+            ChunkPresenceFilter::OnlyMissingChunks => !chunk_present,
+            ChunkPresenceFilter::OnlyNewChunks => chunk_present,
+        }
+    }
+}
+
 struct ProgressReporter {
     cnt: AtomicU64,
     ts: AtomicU64,
     all: u64,
     skipped: AtomicU64,
+    // Heights which were applied and how many of them gave a different result
+    // than the one in the database.
+    applied: AtomicU64,
+    diverged: AtomicU64,
+    // Heights whose state witness is above the soft and the hard limit.
+    over_soft_witness_limit: AtomicU64,
+    over_hard_witness_limit: AtomicU64,
+    // Heights at which the state root of the shadow store isn't the one in
+    // the database, and whether it isn't at the last height applied.
+    drifted: AtomicU64,
+    drifting: AtomicBool,
     // Fields below get cleared after each print.
     empty_blocks: AtomicU64,
     non_empty_blocks: AtomicU64,
@@ -47,8 +83,16 @@ struct ProgressReporter {
 
 impl ProgressReporter {
     pub fn inc_and_report_progress(&self, gas_burnt: u64) {
-        let ProgressReporter { cnt, ts, all, skipped, empty_blocks, non_empty_blocks, tgas_burned } =
-            self;
+        let ProgressReporter {
+            cnt,
+            ts,
+            all,
+            skipped,
+            empty_blocks,
+            non_empty_blocks,
+            tgas_burned,
+            ..
+        } = self;
         if gas_burnt == 0 {
             empty_blocks.fetch_add(1, Ordering::Relaxed);
         } else {
@@ -124,11 +168,17 @@ fn apply_block_from_range(
     verbose_output: bool,
     csv_file_mutex: &Arc<Mutex<Option<&mut File>>>,
     only_contracts: bool,
+    chunk_filter: ChunkPresenceFilter,
+    witness_limits: Option<WitnessLimits>,
+    shadow_store: Option<&Store>,
 ) {
     // normally save_trie_changes depends on whether the node is
     // archival, but here we don't care, and can just set it to false
     // since we're not writing anything to the store anyway
     let mut chain_store = ChainStore::new(store.clone(), genesis.config.genesis_height, false);
+    let mut shadow_chain_store = shadow_store
+        .map(|store| ChainStore::new(store.clone(), genesis.config.genesis_height, false));
+    let read_span = tracing::debug_span!(target: "state_viewer", "read_chunk").entered();
     let block_hash = match chain_store.get_block_hash_by_height(height) {
         Ok(block_hash) => block_hash,
         Err(_) => {
@@ -144,7 +194,12 @@ fn apply_block_from_range(
     let mut prev_chunk_extra = None;
     let mut num_tx = 0;
     let mut num_receipt = 0;
-    let chunk_present: bool;
+    let apply_started;
+    let chunk_present = block.chunks()[shard_id as usize].height_included() == height;
+    if !chunk_filter.matches(chunk_present) {
+        progress_reporter.skipped.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
 
     let block_author = runtime_adapter
         .get_block_producer(block.header().epoch_id(), block.header().height())
@@ -156,8 +211,7 @@ fn apply_block_from_range(
         }
         progress_reporter.inc_and_report_progress(0);
         return;
-    } else if block.chunks()[shard_id as usize].height_included() == height {
-        chunk_present = true;
+    } else if chunk_present {
         let res_existing_chunk_extra = chain_store.get_chunk_extra(&block_hash, &shard_uid);
         assert!(
             res_existing_chunk_extra.is_ok(),
@@ -201,6 +255,14 @@ fn apply_block_from_range(
         let receipts = collect_receipts_from_response(&receipt_proof_response);
 
         let chunk_inner = chunk.cloned_header().take_inner();
+        // The shadow state carries on from the state it computed.
+        let prev_state_root = match shadow_chain_store.as_mut() {
+            Some(shadow_chain_store) => *shadow_chain_store
+                .get_chunk_extra(block.header().prev_hash(), &shard_uid)
+                .unwrap()
+                .state_root(),
+            None => chunk_inner.prev_state_root(),
+        };
         let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
             &mut chain_store,
             runtime_adapter.as_ref(),
@@ -227,10 +289,13 @@ fn apply_block_from_range(
                 return;
             }
         }
+        read_span.exit();
+        let _apply_span = tracing::debug_span!(target: "state_viewer", "apply_chunk").entered();
+        apply_started = Instant::now();
         runtime_adapter
-            .apply_transactions(
+            .apply_transactions_with_optional_storage_proof(
                 shard_id,
-                chunk_inner.prev_state_root(),
+                prev_state_root,
                 height,
                 block.header().raw_timestamp(),
                 block.header().prev_hash(),
@@ -242,19 +307,26 @@ fn apply_block_from_range(
                 chunk_inner.gas_limit(),
                 block.header().challenges_result(),
                 *block.header().random_value(),
+                witness_limits.is_some(),
                 true,
                 is_first_block_with_chunk_of_version,
                 None,
             )
             .unwrap()
     } else {
-        chunk_present = false;
-        let chunk_extra =
-            chain_store.get_chunk_extra(block.header().prev_hash(), &shard_uid).unwrap().clone();
+        let chunk_extra = shadow_chain_store
+            .as_mut()
+            .unwrap_or(&mut chain_store)
+            .get_chunk_extra(block.header().prev_hash(), &shard_uid)
+            .unwrap()
+            .clone();
         prev_chunk_extra = Some(chunk_extra.clone());
 
+        read_span.exit();
+        let _apply_span = tracing::debug_span!(target: "state_viewer", "apply_chunk").entered();
+        apply_started = Instant::now();
         runtime_adapter
-            .apply_transactions(
+            .apply_transactions_with_optional_storage_proof(
                 shard_id,
                 chunk_extra.state_root(),
                 block.header().height(),
@@ -268,6 +340,7 @@ fn apply_block_from_range(
                 chunk_extra.gas_limit(),
                 block.header().challenges_result(),
                 *block.header().random_value(),
+                witness_limits.is_some(),
                 false,
                 false,
                 None,
@@ -275,6 +348,13 @@ fn apply_block_from_range(
             .unwrap()
     };
 
+    let apply_duration = apply_started.elapsed();
+    tracing::Span::current()
+        .record("chunk_present", &chunk_present)
+        .record("num_tx", &num_tx)
+        .record("num_receipt", &num_receipt)
+        .record("apply_duration_ms", &(apply_duration.as_millis() as u64));
+
     let (outcome_root, _) = ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
     let chunk_extra = ChunkExtra::new(
         &apply_result.new_root,
@@ -284,19 +364,30 @@ fn apply_block_from_range(
         genesis.config.gas_limit,
         apply_result.total_balance_burnt,
     );
+    if let Some(shadow_store) = shadow_store {
+        let mut store_update = shadow_store.store_update();
+        apply_result.trie_changes.insertions_into(&mut store_update);
+        store_update
+            .set_ser(DBCol::ChunkExtra, &get_block_shard_uid(&block_hash, &shard_uid), &chunk_extra)
+            .unwrap();
+        store_update.commit().unwrap();
+    }
 
     let state_update =
         runtime_adapter.get_tries().new_trie_update(shard_uid, *chunk_extra.state_root());
     let delayed_indices =
         get::<DelayedReceiptIndices>(&state_update, &TrieKey::DelayedReceiptIndices).unwrap();
 
+    let mut historical_state_root: Option<StateRoot> = None;
     match existing_chunk_extra {
         Some(existing_chunk_extra) => {
+            historical_state_root = Some(*existing_chunk_extra.state_root());
             if verbose_output {
                 println!("block_height: {}, block_hash: {}\nchunk_extra: {:#?}\nexisting_chunk_extra: {:#?}\noutcomes: {:#?}", height, block_hash, chunk_extra, existing_chunk_extra, apply_result.outcomes);
             }
             if !smart_equals(&existing_chunk_extra, &chunk_extra) {
-                assert!(false, "Got a different ChunkExtra:\nblock_height: {}, block_hash: {}\nchunk_extra: {:#?}\nexisting_chunk_extra: {:#?}\nnew outcomes: {:#?}\n\nold outcomes: {:#?}\n", height, block_hash, chunk_extra, existing_chunk_extra, apply_result.outcomes, old_outcomes(store, &apply_result.outcomes));
+                println!("Got a different ChunkExtra:\nblock_height: {}, block_hash: {}\nchunk_extra: {:#?}\nexisting_chunk_extra: {:#?}\nnew outcomes: {:#?}\n\nold outcomes: {:#?}\n", height, block_hash, chunk_extra, existing_chunk_extra, apply_result.outcomes, old_outcomes(store.clone(), &apply_result.outcomes));
+                progress_reporter.diverged.fetch_add(1, Ordering::Relaxed);
             }
         }
         None => {
@@ -305,12 +396,69 @@ fn apply_block_from_range(
             if verbose_output {
                 println!("block_height: {}, block_hash: {}\nchunk_extra: {:#?}\nprev_chunk_extra: {:#?}\noutcomes: {:#?}", height, block_hash, chunk_extra, prev_chunk_extra, apply_result.outcomes);
             }
+            // Without a chunk only the state root changes, see
+            // `Chain::process_apply_chunk_result`.
+            if let Ok(existing_chunk_extra) = chain_store.get_chunk_extra(&block_hash, &shard_uid) {
+                historical_state_root = Some(*existing_chunk_extra.state_root());
+                if existing_chunk_extra.state_root() != chunk_extra.state_root() {
+                    println!("Got a different state root without a chunk:\nblock_height: {}, block_hash: {}\nstate_root: {}\nexisting_state_root: {}", height, block_hash, chunk_extra.state_root(), existing_chunk_extra.state_root());
+                    progress_reporter.diverged.fetch_add(1, Ordering::Relaxed);
+                }
+            }
         }
     };
+    progress_reporter.applied.fetch_add(1, Ordering::Relaxed);
+    let mut shadow_fields = String::new();
+    if shadow_store.is_some() {
+        let shadow_state_root = chunk_extra.state_root();
+        let drifted = historical_state_root.map_or(false, |root| root != *shadow_state_root);
+        if drifted {
+            progress_reporter.drifted.fetch_add(1, Ordering::Relaxed);
+        }
+        let was_drifting = progress_reporter.drifting.swap(drifted, Ordering::Relaxed);
+        if verbose_output || drifted != was_drifting {
+            println!(
+                "block_height: {}, block_hash: {}, shadow state root {} {} the historical one {}",
+                height,
+                block_hash,
+                shadow_state_root,
+                if drifted { "differs from" } else { "matches" },
+                historical_state_root.map_or("unknown".to_string(), |root| root.to_string())
+            );
+        }
+        shadow_fields = format!(
+            ",{},{}",
+            shadow_state_root,
+            historical_state_root.map_or(String::new(), |root| root.to_string())
+        );
+    }
+    let mut witness_fields = String::new();
+    if let (Some(limits), Some(size)) =
+        (witness_limits, WitnessSize::from_apply_result(&apply_result))
+    {
+        match size.exceeded_limit(&limits) {
+            Some(WitnessLimit::Soft) => {
+                progress_reporter.over_soft_witness_limit.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(WitnessLimit::Hard) => {
+                progress_reporter.over_hard_witness_limit.fetch_add(1, Ordering::Relaxed);
+            }
+            None => {}
+        }
+        if verbose_output || size.exceeded_limit(&limits).is_some() {
+            println!(
+                "block_height: {}, block_hash: {}, {}",
+                height,
+                block_hash,
+                size.report(&limits)
+            );
+        }
+        witness_fields = format!(",{}", size.csv_fields(&limits));
+    }
     maybe_add_to_csv(
         csv_file_mutex,
         &format!(
-            "{},{},{},{},{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{},{}{}{}",
             height,
             block_hash,
             block_author,
@@ -320,7 +468,9 @@ fn apply_block_from_range(
             apply_result.total_gas_burnt,
             chunk_present,
             apply_result.processed_delayed_receipts.len(),
-            delayed_indices.map_or(0, |d| d.next_available_index - d.first_index)
+            delayed_indices.map_or(0, |d| d.next_available_index - d.first_index),
+            shadow_fields,
+            witness_fields
         ),
     );
     progress_reporter.inc_and_report_progress(apply_result.total_gas_burnt);
@@ -337,7 +487,14 @@ pub fn apply_chain_range(
     csv_file: Option<&mut File>,
     only_contracts: bool,
     sequential: bool,
+    chunk_filter: ChunkPresenceFilter,
+    witness_limits: Option<WitnessLimits>,
+    shadow_store: Option<Store>,
 ) {
+    assert!(
+        shadow_store.is_none() || sequential,
+        "the heights must be applied sequentially to apply them on top of the shadow state"
+    );
     let parent_span = tracing::debug_span!(
         target: "state_viewer",
         "apply_chain_range",
@@ -345,7 +502,8 @@ pub fn apply_chain_range(
         ?end_height,
         %shard_id,
         only_contracts,
-        sequential)
+        sequential,
+        ?chunk_filter)
     .entered();
     let runtime_adapter: Arc<dyn RuntimeAdapter> = Arc::new(runtime);
     let chain_store = ChainStore::new(store.clone(), genesis.config.genesis_height, false);
@@ -359,7 +517,15 @@ pub fn apply_chain_range(
 
     println!("Printing results including outcomes of applying receipts");
     let csv_file_mutex = Arc::new(Mutex::new(csv_file));
-    maybe_add_to_csv(&csv_file_mutex, "Height,Hash,Author,#Tx,#Receipt,Timestamp,GasUsed,ChunkPresent,#ProcessedDelayedReceipts,#DelayedReceipts");
+    let mut csv_header = "Height,Hash,Author,#Tx,#Receipt,Timestamp,GasUsed,ChunkPresent,#ProcessedDelayedReceipts,#DelayedReceipts".to_string();
+    if shadow_store.is_some() {
+        csv_header += ",ShadowStateRoot,HistoricalStateRoot";
+    }
+    if witness_limits.is_some() {
+        csv_header += ",";
+        csv_header += WITNESS_CSV_HEADER;
+    }
+    maybe_add_to_csv(&csv_file_mutex, &csv_header);
 
     let range = start_height..=end_height;
     let progress_reporter = ProgressReporter {
@@ -367,6 +533,12 @@ pub fn apply_chain_range(
         ts: AtomicU64::new(timestamp()),
         all: end_height - start_height,
         skipped: AtomicU64::new(0),
+        applied: AtomicU64::new(0),
+        diverged: AtomicU64::new(0),
+        over_soft_witness_limit: AtomicU64::new(0),
+        over_hard_witness_limit: AtomicU64::new(0),
+        drifted: AtomicU64::new(0),
+        drifting: AtomicBool::new(false),
         empty_blocks: AtomicU64::new(0),
         non_empty_blocks: AtomicU64::new(0),
         tgas_burned: AtomicU64::new(0),
@@ -382,6 +554,9 @@ pub fn apply_chain_range(
             verbose_output,
             &csv_file_mutex,
             only_contracts,
+            chunk_filter,
+            witness_limits,
+            shadow_store.as_ref(),
         );
     };
 
@@ -391,26 +566,64 @@ pub fn apply_chain_range(
                 target: "state_viewer",
                 parent: &parent_span,
                 "process_block_in_order",
-                height)
+                height,
+                %shard_id,
+                chunk_present = tracing::field::Empty,
+                num_tx = tracing::field::Empty,
+                num_receipt = tracing::field::Empty,
+                apply_duration_ms = tracing::field::Empty)
             .entered();
             process_height(height)
         });
     } else {
+        // The worker threads don't inherit the subscriber of this thread, which
+        // is not the global one when tracing to OpenTelemetry.
+        let dispatch = tracing::dispatcher::get_default(|dispatch| dispatch.clone());
         range.into_par_iter().for_each(|height| {
-            let _span = tracing::debug_span!(
-                target: "mock_node",
-                parent: &parent_span,
-                "process_block_in_parallel",
-                height)
-            .entered();
-            process_height(height)
+            tracing::dispatcher::with_default(&dispatch, || {
+                let _span = tracing::debug_span!(
+                    target: "state_viewer",
+                    parent: &parent_span,
+                    "process_block_in_parallel",
+                    height,
+                    %shard_id,
+                    chunk_present = tracing::field::Empty,
+                    num_tx = tracing::field::Empty,
+                    num_receipt = tracing::field::Empty,
+                    apply_duration_ms = tracing::field::Empty)
+                .entered();
+                process_height(height)
+            })
         });
     }
 
+    let applied = progress_reporter.applied.load(Ordering::Relaxed);
+    let diverged = progress_reporter.diverged.load(Ordering::Relaxed);
     println!(
-        "No differences found after applying chunks in the range {}..={} for shard_id {}",
-        start_height, end_height, shard_id
+        "Applied {} heights matching {:?} in the range {}..={} for shard_id {}, {} diverged",
+        applied, chunk_filter, start_height, end_height, shard_id, diverged
     );
+    if witness_limits.is_some() {
+        println!(
+            "{} heights have a state witness above the soft limit, {} above the hard limit",
+            progress_reporter.over_soft_witness_limit.load(Ordering::Relaxed),
+            progress_reporter.over_hard_witness_limit.load(Ordering::Relaxed)
+        );
+    }
+    if shadow_store.is_some() {
+        println!(
+            "The shadow state root differs from the historical one at {} heights, {}",
+            progress_reporter.drifted.load(Ordering::Relaxed),
+            if progress_reporter.drifting.load(Ordering::Relaxed) {
+                "including the last one"
+            } else {
+                "not at the last one"
+            }
+        );
+    }
+    if diverged > 0 {
+        panic!("{} heights gave a different result than the one in the database", diverged);
+    }
 }
 
 /**
@@ -456,12 +669,14 @@ mod test {
     use near_primitives::transaction::SignedTransaction;
     use near_primitives::types::{BlockHeight, BlockHeightDelta, NumBlocks};
     use near_store::test_utils::create_test_store;
-    use near_store::Store;
+    use near_store::{DBCol, Store};
     use nearcore::config::GenesisExt;
     use nearcore::config::TESTING_INIT_STAKE;
     use nearcore::NightshadeRuntime;
+    use strum::IntoEnumIterator;
 
-    use crate::apply_chain_range::apply_chain_range;
+    use crate::apply_chain_range::{apply_chain_range, ChunkPresenceFilter};
+    use crate::witness::WitnessLimits;
 
     fn setup(epoch_length: NumBlocks) -> (Store, Genesis, TestEnv) {
         let mut genesis =
@@ -534,7 +749,21 @@ mod test {
         safe_produce_blocks(&mut env, 1, epoch_length * 2 + 1, None);
 
         let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
-        apply_chain_range(store, &genesis, None, None, 0, runtime, true, None, false, false);
+        apply_chain_range(
+            store,
+            &genesis,
+            None,
+            None,
+            0,
+            runtime,
+            true,
+            None,
+            false,
+            false,
+            ChunkPresenceFilter::All,
+            None,
+            None,
+        );
     }
 
     #[test]
@@ -568,6 +797,9 @@ mod test {
             Some(file.as_file_mut()),
             false,
             false,
+            ChunkPresenceFilter::All,
+            None,
+            None,
         );
         let mut csv = String::new();
         file.as_file_mut().seek(SeekFrom::Start(0)).unwrap();
@@ -587,4 +819,118 @@ mod test {
         assert_eq!(has_tx, 1, "{:#?}", lines);
         assert_eq!(no_tx, 8, "{:#?}", lines);
     }
+
+    #[test]
+    fn test_apply_chain_range_only_missing_chunks() {
+        let epoch_length = 4;
+        let (store, genesis, mut env) = setup(epoch_length);
+        safe_produce_blocks(&mut env, 1, epoch_length * 2 + 1, Some(5));
+
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        apply_chain_range(
+            store,
+            &genesis,
+            None,
+            None,
+            0,
+            runtime,
+            true,
+            Some(file.as_file_mut()),
+            false,
+            false,
+            ChunkPresenceFilter::OnlyMissingChunks,
+            None,
+            None,
+        );
+        let mut csv = String::new();
+        file.as_file_mut().seek(SeekFrom::Start(0)).unwrap();
+        file.as_file_mut().read_to_string(&mut csv).unwrap();
+        let lines: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(lines.len(), 1, "{:#?}", lines);
+        assert!(lines[0].contains(",false,"), "{:#?}", lines);
+    }
+
+    #[test]
+    fn test_apply_chain_range_record_witness() {
+        let epoch_length = 4;
+        let (store, genesis, mut env) = setup(epoch_length);
+        safe_produce_blocks(&mut env, 1, epoch_length * 2 + 1, Some(5));
+
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        apply_chain_range(
+            store,
+            &genesis,
+            None,
+            None,
+            0,
+            runtime,
+            false,
+            Some(file.as_file_mut()),
+            false,
+            false,
+            ChunkPresenceFilter::All,
+            Some(WitnessLimits { witness_soft_limit: 0, witness_hard_limit: u64::MAX }),
+            None,
+        );
+        let mut csv = String::new();
+        file.as_file_mut().seek(SeekFrom::Start(0)).unwrap();
+        file.as_file_mut().read_to_string(&mut csv).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().ends_with(",WitnessOverLimit"));
+        let lines: Vec<&str> = lines.collect();
+        // Applying a new chunk reads trie nodes so its witness is above a zero
+        // limit.
+        let new_chunks: Vec<_> = lines.iter().filter(|line| line.contains(",true,")).collect();
+        assert!(!new_chunks.is_empty());
+        for line in new_chunks {
+            assert!(line.ends_with(",soft"), "{:#?}", lines);
+        }
+    }
+
+    #[test]
+    fn test_apply_chain_range_shadow_store() {
+        let epoch_length = 4;
+        let (store, genesis, mut env) = setup(epoch_length);
+        safe_produce_blocks(&mut env, 1, epoch_length * 2 + 1, Some(5));
+
+        let shadow_store = create_test_store();
+        let mut store_update = shadow_store.store_update();
+        for col in DBCol::iter() {
+            for (key, value) in store.iter_raw_bytes(col) {
+                store_update.set_raw_bytes(col, &key, &value);
+            }
+        }
+        store_update.commit().unwrap();
+
+        let runtime = NightshadeRuntime::test(Path::new("."), shadow_store.clone(), &genesis);
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        apply_chain_range(
+            store,
+            &genesis,
+            None,
+            None,
+            0,
+            runtime,
+            false,
+            Some(file.as_file_mut()),
+            false,
+            true,
+            ChunkPresenceFilter::All,
+            None,
+            Some(shadow_store),
+        );
+        let mut csv = String::new();
+        file.as_file_mut().seek(SeekFrom::Start(0)).unwrap();
+        file.as_file_mut().read_to_string(&mut csv).unwrap();
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().ends_with(",ShadowStateRoot,HistoricalStateRoot"));
+        // Without nondeterminism the shadow state is the historical one.
+        for line in lines {
+            let fields: Vec<&str> = line.split(',').collect();
+            let roots = &fields[fields.len() - 2..];
+            assert_eq!(roots[0], roots[1], "{}", line);
+        }
+    }
 }
@@ -71,12 +71,14 @@ fn get_incoming_receipts(
 }
 
 // returns (apply_result, gas limit)
+// With `record_witness` the trie nodes read are recorded in `apply_result.proof`.
 pub(crate) fn apply_chunk(
     runtime: &NightshadeRuntime,
     chain_store: &mut ChainStore,
     chunk_hash: ChunkHash,
     target_height: Option<u64>,
     rng: Option<StdRng>,
+    record_witness: bool,
 ) -> anyhow::Result<(ApplyTransactionResult, Gas)> {
     let chunk = chain_store.get_chunk(&chunk_hash)?;
     let chunk_header = chunk.cloned_header();
@@ -115,7 +117,7 @@ pub(crate) fn apply_chunk(
     )?;
 
     Ok((
-        runtime.apply_transactions(
+        runtime.apply_transactions_with_optional_storage_proof(
             shard_id,
             &prev_state_root,
             target_height,
@@ -132,6 +134,7 @@ pub(crate) fn apply_chunk(
             chunk_header.gas_limit(),
             &vec![],
             hash("random seed".as_ref()),
+            record_witness,
             true,
             is_first_block_with_chunk_of_version,
             None,
@@ -249,7 +252,7 @@ fn apply_tx_in_chunk(
     for chunk_hash in chunk_hashes {
         println!("found tx in chunk {}. Equivalent command (which will run faster than apply_tx):\nview_state apply_chunk --chunk_hash {}\n", &chunk_hash.0, &chunk_hash.0);
         let (apply_result, gas_limit) =
-            apply_chunk(runtime.clone(), chain_store, chunk_hash, None, None)?;
+            apply_chunk(runtime.clone(), chain_store, chunk_hash, None, None, false)?;
         println!(
             "resulting chunk extra:\n{:?}",
             crate::commands::resulting_chunk_extra(&apply_result, gas_limit)
@@ -378,7 +381,7 @@ fn apply_receipt_in_chunk(
         println!("Applying chunk at height {} in shard {}. Equivalent command (which will run faster than apply_receipt):\nview_state apply_chunk --chunk_hash {}\n",
                  height, shard_id, chunk_hash.0);
         let (apply_result, gas_limit) =
-            apply_chunk(runtime.clone(), chain_store, chunk_hash.clone(), None, None)?;
+            apply_chunk(runtime.clone(), chain_store, chunk_hash.clone(), None, None, false)?;
         let chunk_extra = crate::commands::resulting_chunk_extra(&apply_result, gas_limit);
         println!("resulting chunk extra:\n{:?}", chunk_extra);
         results.push(apply_result);
@@ -403,173 +406,81 @@ pub(crate) fn apply_receipt(
 
 #[cfg(test)]
 mod test {
-    use near_chain::{ChainGenesis, ChainStore, ChainStoreAccess, Provenance, RuntimeAdapter};
-    use near_chain_configs::Genesis;
-    use near_client::test_utils::TestEnv;
-    use near_crypto::{InMemorySigner, KeyType};
-    use near_network::types::NetworkClientResponses;
-    use near_primitives::hash::CryptoHash;
-    use near_primitives::runtime::config_store::RuntimeConfigStore;
+    use crate::test_chain::TestChainBuilder;
+    use near_chain::{ChainStoreAccess, RuntimeAdapter};
     use near_primitives::shard_layout;
-    use near_primitives::transaction::SignedTransaction;
-    use near_primitives::utils::get_num_seats_per_shard;
-    use near_store::test_utils::create_test_store;
-    use nearcore::config::GenesisExt;
-    use nearcore::NightshadeRuntime;
-    use nearcore::TrackedConfig;
     use rand::rngs::StdRng;
     use rand::SeedableRng;
-    use std::path::Path;
-    use std::sync::Arc;
-
-    fn send_txs(env: &mut TestEnv, signers: &[InMemorySigner], height: u64, hash: CryptoHash) {
-        for (i, signer) in signers.iter().enumerate() {
-            let from = format!("test{}", i);
-            let to = format!("test{}", (i + 1) % signers.len());
-            let tx = SignedTransaction::send_money(
-                height,
-                from.parse().unwrap(),
-                to.parse().unwrap(),
-                signer,
-                100,
-                hash,
-            );
-            let response = env.clients[0].process_tx(tx, false, false);
-            assert_eq!(response, NetworkClientResponses::ValidTx);
-        }
-    }
 
     #[test]
     fn test_apply_chunk() {
-        let genesis = Genesis::test_sharded(
-            vec![
-                "test0".parse().unwrap(),
-                "test1".parse().unwrap(),
-                "test2".parse().unwrap(),
-                "test3".parse().unwrap(),
-            ],
-            1,
-            get_num_seats_per_shard(4, 1),
-        );
-
-        let store = create_test_store();
-        let mut chain_store = ChainStore::new(store.clone(), genesis.config.genesis_height, false);
-        let runtime = Arc::new(NightshadeRuntime::test_with_runtime_config_store(
-            Path::new("."),
-            store,
-            &genesis,
-            TrackedConfig::AllShards,
-            RuntimeConfigStore::test(),
-            None,
-        ));
-        let chain_genesis = ChainGenesis::test();
-
-        let signers = (0..4)
-            .map(|i| {
-                let acc = format!("test{}", i);
-                InMemorySigner::from_seed(acc.parse().unwrap(), KeyType::ED25519, &acc)
-            })
-            .collect::<Vec<_>>();
+        let chain = TestChainBuilder::default().build();
+        let runtime = chain.runtime.clone();
+        let mut chain_store = chain.chain_store();
 
-        let mut env =
-            TestEnv::builder(chain_genesis).runtime_adapters(vec![runtime.clone()]).build();
-        let genesis_hash = *env.clients[0].chain.genesis().hash();
-
-        for height in 1..10 {
-            send_txs(&mut env, &signers, height, genesis_hash);
-
-            let block = env.clients[0].produce_block(height).unwrap().unwrap();
-
-            let hash = *block.hash();
-            let chunk_hashes = block.chunks().iter().map(|c| c.chunk_hash()).collect::<Vec<_>>();
+        for height in chain.steady_heights() {
+            let hash = chain_store.get_block_hash_by_height(height).unwrap();
+            let block = chain_store.get_block(&hash).unwrap().clone();
             let epoch_id = block.header().epoch_id().clone();
 
-            env.process_block(0, block, Provenance::PRODUCED);
+            for (shard, chunk_header) in block.chunks().iter().enumerate() {
+                let shard_uid = runtime.shard_id_to_uid(shard as u64, &epoch_id).unwrap();
+                let new_root =
+                    chain_store.get_chunk_extra(&hash, &shard_uid).unwrap().state_root().clone();
 
-            let new_roots = (0..4)
-                .map(|i| {
-                    let shard_uid = runtime.shard_id_to_uid(i, &epoch_id).unwrap();
-                    chain_store.get_chunk_extra(&hash, &shard_uid).unwrap().state_root().clone()
-                })
-                .collect::<Vec<_>>();
-
-            if height >= 2 {
-                for shard in 0..4 {
-                    // we will shuffle receipts the same as in production, otherwise the state roots don't match
-                    let mut slice = [0u8; 32];
-                    slice.copy_from_slice(hash.as_ref());
-                    let rng: StdRng = SeedableRng::from_seed(slice);
+                // we will shuffle receipts the same as in production, otherwise the state roots don't match
+                let mut slice = [0u8; 32];
+                slice.copy_from_slice(hash.as_ref());
+                let rng: StdRng = SeedableRng::from_seed(slice);
 
-                    let chunk_hash = &chunk_hashes[shard];
-                    let new_root = new_roots[shard];
+                let chunk_hash = chunk_header.chunk_hash();
+                let (apply_result, _) = crate::apply_chunk::apply_chunk(
+                    runtime.as_ref(),
+                    &mut chain_store,
+                    chunk_hash.clone(),
+                    None,
+                    Some(rng.clone()),
+                    false,
+                )
+                .unwrap();
+                assert_eq!(apply_result.new_root, new_root);
+                assert!(apply_result.proof.is_none());
+                assert!(apply_result.stage_times.trie_reads.unwrap().reads() > 0);
 
-                    let (apply_result, _) = crate::apply_chunk::apply_chunk(
-                        runtime.as_ref(),
-                        &mut chain_store,
-                        chunk_hash.clone(),
-                        None,
-                        Some(rng),
-                    )
-                    .unwrap();
-                    assert_eq!(apply_result.new_root, new_root);
-                }
+                // Recording the trie nodes doesn't change the result.
+                let (recorded_result, _) = crate::apply_chunk::apply_chunk(
+                    runtime.as_ref(),
+                    &mut chain_store,
+                    chunk_hash,
+                    None,
+                    Some(rng),
+                    true,
+                )
+                .unwrap();
+                assert_eq!(recorded_result.new_root, new_root);
+                assert_eq!(recorded_result.total_gas_burnt, apply_result.total_gas_burnt);
+                assert!(!recorded_result.proof.unwrap().nodes.0.is_empty());
+                assert_eq!(recorded_result.stage_times.trie_reads, None);
             }
         }
     }
 
     #[test]
     fn test_apply_tx_apply_receipt() {
-        let genesis = Genesis::test_sharded(
-            vec![
-                "test0".parse().unwrap(),
-                "test1".parse().unwrap(),
-                "test2".parse().unwrap(),
-                "test3".parse().unwrap(),
-            ],
-            1,
-            get_num_seats_per_shard(4, 1),
-        );
-
-        let store = create_test_store();
-        let mut chain_store = ChainStore::new(store.clone(), genesis.config.genesis_height, false);
-        let runtime = Arc::new(NightshadeRuntime::test_with_runtime_config_store(
-            Path::new("."),
-            store.clone(),
-            &genesis,
-            TrackedConfig::AllShards,
-            RuntimeConfigStore::test(),
-            None,
-        ));
-        let mut chain_genesis = ChainGenesis::test();
-        // receipts get delayed with the small ChainGenesis::test() limit
-        chain_genesis.gas_limit = genesis.config.gas_limit;
-
-        let signers = (0..4)
-            .map(|i| {
-                let acc = format!("test{}", i);
-                InMemorySigner::from_seed(acc.parse().unwrap(), KeyType::ED25519, &acc)
-            })
-            .collect::<Vec<_>>();
-
-        let mut env =
-            TestEnv::builder(chain_genesis).runtime_adapters(vec![runtime.clone()]).build();
-        let genesis_hash = *env.clients[0].chain.genesis().hash();
+        let chain = TestChainBuilder::default().build();
+        let genesis_height = chain.genesis.config.genesis_height;
+        let runtime = chain.runtime.clone();
+        let store = chain.store.clone();
+        let mut chain_store = chain.chain_store();
 
         // first check that applying txs and receipts works when the block exists
 
-        for height in 1..5 {
-            send_txs(&mut env, &signers, height, genesis_hash);
-
-            let block = env.clients[0].produce_block(height).unwrap().unwrap();
-
-            let hash = *block.hash();
+        for height in chain.steady_heights() {
+            let hash = chain_store.get_block_hash_by_height(height).unwrap();
+            let block = chain_store.get_block(&hash).unwrap().clone();
             let prev_hash = *block.header().prev_hash();
-            let chunk_hashes = block.chunks().iter().map(|c| c.chunk_hash()).collect::<Vec<_>>();
             let epoch_id = block.header().epoch_id().clone();
-
-            env.process_block(0, block, Provenance::PRODUCED);
-
-            let new_roots = (0..4)
+            let new_roots = (0..block.chunks().len() as u64)
                 .map(|i| {
                     let shard_uid = runtime.shard_id_to_uid(i, &epoch_id).unwrap();
                     chain_store.get_chunk_extra(&hash, &shard_uid).unwrap().state_root().clone()
@@ -577,58 +488,55 @@ mod test {
                 .collect::<Vec<_>>();
             let shard_layout = runtime.get_shard_layout_from_prev_block(&prev_hash).unwrap();
 
-            if height >= 2 {
-                for shard_id in 0..4 {
-                    let chunk = chain_store.get_chunk(&chunk_hashes[shard_id]).unwrap();
-
-                    for tx in chunk.transactions() {
-                        let results = crate::apply_chunk::apply_tx(
-                            genesis.config.genesis_height,
-                            runtime.as_ref(),
-                            store.clone(),
-                            tx.get_hash(),
-                        )
-                        .unwrap();
-                        assert_eq!(results.len(), 1);
-                        assert_eq!(results[0].new_root, new_roots[shard_id as usize]);
-                    }
+            for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+                let chunk = chain_store.get_chunk(&chunk_header.chunk_hash()).unwrap().clone();
 
-                    for receipt in chunk.receipts() {
-                        let to_shard = shard_layout::account_id_to_shard_id(
-                            &receipt.receiver_id,
-                            &shard_layout,
-                        );
+                for tx in chunk.transactions() {
+                    let results = crate::apply_chunk::apply_tx(
+                        genesis_height,
+                        runtime.as_ref(),
+                        store.clone(),
+                        tx.get_hash(),
+                    )
+                    .unwrap();
+                    assert_eq!(results.len(), 1);
+                    assert_eq!(results[0].new_root, new_roots[shard_id]);
+                }
 
-                        let results = crate::apply_chunk::apply_receipt(
-                            genesis.config.genesis_height,
-                            runtime.as_ref(),
-                            store.clone(),
-                            receipt.get_hash(),
-                        )
-                        .unwrap();
-                        assert_eq!(results.len(), 1);
-                        assert_eq!(results[0].new_root, new_roots[to_shard as usize]);
-                    }
+                for receipt in chunk.receipts() {
+                    let to_shard =
+                        shard_layout::account_id_to_shard_id(&receipt.receiver_id, &shard_layout);
+
+                    let results = crate::apply_chunk::apply_receipt(
+                        genesis_height,
+                        runtime.as_ref(),
+                        store.clone(),
+                        receipt.get_hash(),
+                    )
+                    .unwrap();
+                    assert_eq!(results.len(), 1);
+                    assert_eq!(results[0].new_root, new_roots[to_shard as usize]);
                 }
             }
         }
 
         // then check what happens when the block doesn't exist
-        // it won't exist because the chunks for the last height
-        // in the loop above are produced by env.process_block() but
-        // there was no corresponding env.clients[0].produce_block() after
+        // it won't exist because the chunks for the height after the head
+        // are produced by processing the head block but no block includes
+        // them yet
 
-        let chunks = chain_store.get_all_chunk_hashes_by_height(5).unwrap();
-        let blocks = chain_store.get_all_header_hashes_by_height(5).unwrap();
+        let pending_height = chain.head_height + 1;
+        let chunks = chain_store.get_all_chunk_hashes_by_height(pending_height).unwrap();
+        let blocks = chain_store.get_all_header_hashes_by_height(pending_height).unwrap();
         assert_ne!(chunks.len(), 0);
         assert_eq!(blocks.len(), 0);
 
         for chunk_hash in chunks {
-            let chunk = chain_store.get_chunk(&chunk_hash).unwrap();
+            let chunk = chain_store.get_chunk(&chunk_hash).unwrap().clone();
 
             for tx in chunk.transactions() {
                 let results = crate::apply_chunk::apply_tx(
-                    genesis.config.genesis_height,
+                    genesis_height,
                     runtime.as_ref(),
                     store.clone(),
                     tx.get_hash(),
@@ -647,7 +555,7 @@ mod test {
             }
             for receipt in chunk.receipts() {
                 let results = crate::apply_chunk::apply_receipt(
-                    genesis.config.genesis_height,
+                    genesis_height,
                     runtime.as_ref(),
                     store.clone(),
                     receipt.get_hash(),
@@ -0,0 +1,340 @@
+//! Report of the balances of the accounts in the state: the liquid and locked
+//! totals, a histogram of the balances and the accounts with the most of each.
+//!
+//! The accounts are streamed from the trie, only the histogram and the top
+//! accounts of each measure, in heaps bounded by their number, are kept in
+//! memory.  The balances are the ones of the accounts as the runtime sees
+//! them: tokens held by contracts on behalf of others, e.g. the lockup
+//! contracts and the internal ledgers of the staking pools, aren't unwrapped,
+//! they're counted as the balance of the contract account.
+
+use crate::commands::{load_trie_stop_at_height, LoadTrieMode};
+use crate::output::{failure, FailureKind, Output};
+use crate::sampling::{SampleInfo, Sampler};
+use borsh::BorshDeserialize;
+use near_chain::RuntimeAdapter;
+use near_primitives::account::Account;
+use near_primitives::serialize::{option_u128_dec_format, u128_dec_format};
+use near_primitives::trie_key::{col, trie_key_parsers};
+use near_primitives::types::{AccountId, Balance, BlockHeight, ShardId};
+use near_store::{Store, TrieIterator};
+use nearcore::config::NEAR_BASE;
+use nearcore::NearConfig;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::path::Path;
+
+/// The upper bounds of the histogram buckets by default, in NEAR.
+pub(crate) const DEFAULT_HISTOGRAM_BUCKETS: &[u128] =
+    &[1, 10, 100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TopAccount {
+    pub account_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub balance: Balance,
+}
+
+/// Accounts with a total balance, liquid plus locked, from `min` included to
+/// `max` excluded, `None` for the last bucket.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HistogramBucket {
+    #[serde(with = "u128_dec_format")]
+    pub min: Balance,
+    #[serde(with = "option_u128_dec_format")]
+    pub max: Option<Balance>,
+    pub num_accounts: u64,
+    #[serde(with = "u128_dec_format")]
+    pub total: Balance,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct BalanceReport {
+    pub height: BlockHeight,
+    pub shard_ids: Vec<ShardId>,
+    pub num_accounts: u64,
+    #[serde(with = "u128_dec_format")]
+    pub total_amount: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub total_locked: Balance,
+    pub num_accounts_with_locked: u64,
+    pub histogram: Vec<HistogramBucket>,
+    pub top_by_amount: Vec<TopAccount>,
+    pub top_by_locked: Vec<TopAccount>,
+    /// Set if only a sample of the accounts was scanned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample: Option<SampleInfo>,
+}
+
+/// The `n` accounts with the largest balances seen, the smallest of them on
+/// top of the heap to be pushed out first.
+struct TopAccounts {
+    n: usize,
+    heap: BinaryHeap<Reverse<(Balance, AccountId)>>,
+}
+
+impl TopAccounts {
+    fn new(n: usize) -> Self {
+        Self { n, heap: BinaryHeap::with_capacity(n + 1) }
+    }
+
+    fn add(&mut self, balance: Balance, account_id: &AccountId) {
+        if self.n == 0 || balance == 0 {
+            return;
+        }
+        if self.heap.len() == self.n {
+            match self.heap.peek() {
+                Some(Reverse((smallest, _))) if *smallest >= balance => return,
+                _ => {}
+            }
+        }
+        self.heap.push(Reverse((balance, account_id.clone())));
+        if self.heap.len() > self.n {
+            self.heap.pop();
+        }
+    }
+
+    /// The accounts, the largest balance first.
+    fn into_sorted(self) -> Vec<TopAccount> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((balance, account_id))| TopAccount { account_id, balance })
+            .collect()
+    }
+}
+
+pub(crate) struct BalanceAggregator {
+    num_accounts: u64,
+    total_amount: Balance,
+    total_locked: Balance,
+    num_accounts_with_locked: u64,
+    histogram: Vec<HistogramBucket>,
+    top_by_amount: TopAccounts,
+    top_by_locked: TopAccounts,
+}
+
+impl BalanceAggregator {
+    /// `bucket_bounds` are the upper bounds of the buckets but the last one,
+    /// in increasing order.
+    pub fn new(bucket_bounds: &[Balance], top: usize) -> Self {
+        let mins = std::iter::once(0).chain(bucket_bounds.iter().copied());
+        let maxs = bucket_bounds.iter().copied().map(Some).chain(std::iter::once(None));
+        let histogram = mins
+            .zip(maxs)
+            .map(|(min, max)| HistogramBucket { min, max, num_accounts: 0, total: 0 })
+            .collect();
+        Self {
+            num_accounts: 0,
+            total_amount: 0,
+            total_locked: 0,
+            num_accounts_with_locked: 0,
+            histogram,
+            top_by_amount: TopAccounts::new(top),
+            top_by_locked: TopAccounts::new(top),
+        }
+    }
+
+    pub fn add(&mut self, account_id: &AccountId, account: &Account) {
+        let (amount, locked) = (account.amount(), account.locked());
+        self.num_accounts += 1;
+        self.total_amount += amount;
+        self.total_locked += locked;
+        if locked > 0 {
+            self.num_accounts_with_locked += 1;
+        }
+        let balance = amount + locked;
+        let bucket = self.histogram.partition_point(|bucket| bucket.min <= balance) - 1;
+        self.histogram[bucket].num_accounts += 1;
+        self.histogram[bucket].total += balance;
+        self.top_by_amount.add(amount, account_id);
+        self.top_by_locked.add(locked, account_id);
+    }
+
+    pub fn into_report(
+        self,
+        height: BlockHeight,
+        shard_ids: Vec<ShardId>,
+        sample: Option<SampleInfo>,
+    ) -> BalanceReport {
+        BalanceReport {
+            height,
+            shard_ids,
+            num_accounts: self.num_accounts,
+            total_amount: self.total_amount,
+            total_locked: self.total_locked,
+            num_accounts_with_locked: self.num_accounts_with_locked,
+            histogram: self.histogram,
+            top_by_amount: self.top_by_amount.into_sorted(),
+            top_by_locked: self.top_by_locked.into_sorted(),
+            sample,
+        }
+    }
+}
+
+/// Balance in NEAR, with three decimals.
+pub(crate) struct Near(pub Balance);
+
+impl fmt::Display for Near {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let milli = self.0 / (NEAR_BASE / 1000);
+        write!(f, "{}.{:03} NEAR", milli / 1000, milli % 1000)
+    }
+}
+
+impl fmt::Display for BalanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Balances at #{} in shards {:?}", self.height, self.shard_ids)?;
+        if let Some(sample) = &self.sample {
+            writeln!(f, "{}, the top accounts are the ones of the sample", sample)?;
+        }
+        writeln!(f, "Accounts: {}", self.num_accounts)?;
+        writeln!(f, "Liquid: {}", Near(self.total_amount))?;
+        writeln!(
+            f,
+            "Locked: {} in {} accounts",
+            Near(self.total_locked),
+            self.num_accounts_with_locked
+        )?;
+        writeln!(f, "Histogram of the total balances:")?;
+        for bucket in &self.histogram {
+            let range = match bucket.max {
+                Some(max) => format!("[{}, {})", Near(bucket.min), Near(max)),
+                None => format!(">= {}", Near(bucket.min)),
+            };
+            writeln!(f, "  {}: {} accounts, {}", range, bucket.num_accounts, Near(bucket.total))?;
+        }
+        for (what, top) in [("liquid", &self.top_by_amount), ("locked", &self.top_by_locked)] {
+            writeln!(f, "Top {} accounts by {} balance:", top.len(), what)?;
+            for account in top {
+                writeln!(f, "  {}: {}", account.account_id, Near(account.balance))?;
+            }
+        }
+        write!(
+            f,
+            "Balances held by contracts for others, e.g. lockups and staking pools, \
+             aren't unwrapped"
+        )
+    }
+}
+
+/// Converts the bucket bounds given in NEAR, checking they're increasing.
+pub(crate) fn bucket_bounds(bounds_in_near: &[u128]) -> anyhow::Result<Vec<Balance>> {
+    if bounds_in_near.windows(2).any(|pair| pair[0] >= pair[1])
+        || bounds_in_near.first() == Some(&0)
+    {
+        return Err(failure(
+            FailureKind::InvalidArgument,
+            "the histogram buckets must be increasing and positive",
+        ));
+    }
+    bounds_in_near
+        .iter()
+        .map(|bound| {
+            bound.checked_mul(NEAR_BASE).ok_or_else(|| {
+                failure(FailureKind::InvalidArgument, format!("bucket {} is too large", bound))
+            })
+        })
+        .collect()
+}
+
+/// Scans the accounts of the shard, or of all the shards, in the state before
+/// the block at `height` and prints the report of their balances.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn balance_report(
+    height: BlockHeight,
+    shard_id: Option<ShardId>,
+    bucket_bounds: &[Balance],
+    top: usize,
+    sampler: &Sampler,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let (runtime, state_roots, header) =
+        load_trie_stop_at_height(store, home_dir, &near_config, LoadTrieMode::Height(height));
+    let shard_ids: Vec<ShardId> = match shard_id {
+        Some(shard_id) if shard_id as usize >= state_roots.len() => {
+            return Err(failure(
+                FailureKind::InvalidArgument,
+                format!("there's no shard {}", shard_id),
+            ));
+        }
+        Some(shard_id) => vec![shard_id],
+        None => (0..state_roots.len() as ShardId).collect(),
+    };
+    let mut aggregator = BalanceAggregator::new(bucket_bounds, top);
+    for &shard_id in &shard_ids {
+        eprintln!("Scanning the accounts of shard {}", shard_id);
+        let trie = runtime.get_trie_for_shard(shard_id, header.prev_hash())?;
+        let mut iter = TrieIterator::new(&trie, &state_roots[shard_id as usize])?;
+        iter.seek(&[col::ACCOUNT])?;
+        // The values of the accounts out of the sample aren't read, the first
+        // key past the accounts is let through for the scan to stop at it.
+        while let Some(item) =
+            iter.next_filtered(|key| key.first() != Some(&col::ACCOUNT) || sampler.includes(key))
+        {
+            let (raw_key, value) = item?;
+            if raw_key.first() != Some(&col::ACCOUNT) {
+                break;
+            }
+            let account_id = trie_key_parsers::parse_account_id_from_account_key(&raw_key)?;
+            aggregator.add(&account_id, &Account::try_from_slice(&value)?);
+        }
+    }
+    output.print(&aggregator.into_report(header.height(), shard_ids, sampler.info()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::CryptoHash;
+
+    fn add(aggregator: &mut BalanceAggregator, account_id: &str, amount: u128, locked: u128) {
+        let account =
+            Account::new(amount * NEAR_BASE, locked * NEAR_BASE, CryptoHash::default(), 0);
+        aggregator.add(&account_id.parse().unwrap(), &account);
+    }
+
+    fn top(accounts: &[TopAccount]) -> Vec<(String, u128)> {
+        accounts
+            .iter()
+            .map(|account| (account.account_id.to_string(), account.balance / NEAR_BASE))
+            .collect()
+    }
+
+    #[test]
+    fn test_balance_report() {
+        let mut aggregator = BalanceAggregator::new(&bucket_bounds(&[10, 100]).unwrap(), 2);
+        add(&mut aggregator, "a.near", 5, 0);
+        add(&mut aggregator, "b.near", 10, 0);
+        add(&mut aggregator, "c.near", 50, 200);
+        add(&mut aggregator, "d.near", 1, 30);
+        add(&mut aggregator, "e.near", 70, 0);
+        let report = aggregator.into_report(42, vec![0], None);
+
+        assert_eq!(report.num_accounts, 5);
+        assert_eq!(report.total_amount, 136 * NEAR_BASE);
+        assert_eq!(report.total_locked, 230 * NEAR_BASE);
+        assert_eq!(report.num_accounts_with_locked, 2);
+        let histogram: Vec<_> = report
+            .histogram
+            .iter()
+            .map(|bucket| (bucket.num_accounts, bucket.total / NEAR_BASE))
+            .collect();
+        assert_eq!(histogram, vec![(1, 5), (3, 111), (1, 250)]);
+        assert_eq!(report.histogram[2].max, None);
+        assert_eq!(top(&report.top_by_amount), vec![("e.near".into(), 70), ("c.near".into(), 50)]);
+        assert_eq!(top(&report.top_by_locked), vec![("c.near".into(), 200), ("d.near".into(), 30)]);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["total_locked"], format!("{}", 230 * NEAR_BASE));
+        assert!(report.to_string().contains("Locked: 230.000 NEAR in 2 accounts"));
+
+        assert!(bucket_bounds(&[10, 10]).is_err());
+        assert!(bucket_bounds(&[0, 10]).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Lists what the database keeps of a block: the entries of the columns keyed
+//! by its hash, its height or its shards, with their sizes, and for the
+//! missing ones whether that's expected.
+//!
+//! A missing entry is expected when garbage collection deleted it, when only
+//! the header of the block is stored, when the node doesn't track the shard,
+//! when the block has no new chunk for the shard, or when the column is only
+//! written in some cases, e.g. while the shard layout changes.  Flat storage,
+//! whose deltas would be keyed by block too, isn't in this version.
+
+use crate::output::{failure, FailureKind, Output};
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_epoch_manager::EpochManager;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::{get_block_shard_uid, ShardUId};
+use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::utils::{get_block_shard_id, index_to_bytes};
+use near_store::{DBCol, Store};
+use nearcore::NearConfig;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Missing {
+    expected: bool,
+    reason: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+struct Artifact {
+    column: &'static str,
+    /// For the columns with an entry per shard.
+    shard_id: Option<ShardId>,
+    /// Hex of the key, of the prefix of the keys for `StateChanges`.
+    key: String,
+    /// Number of entries, more than one only for `StateChanges`.
+    entries: u64,
+    value_bytes: u64,
+    /// `None` if there's an entry.
+    missing: Option<Missing>,
+}
+
+#[derive(Serialize)]
+struct BlockArtifacts {
+    block_hash: CryptoHash,
+    height: BlockHeight,
+    tail_height: BlockHeight,
+    canonical: bool,
+    artifacts: Vec<Artifact>,
+}
+
+impl fmt::Display for BlockArtifacts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Block {} at height {}, {}, tail at {}",
+            self.block_hash,
+            self.height,
+            if self.canonical { "canonical" } else { "not canonical" },
+            self.tail_height
+        )?;
+        for artifact in &self.artifacts {
+            let column = match artifact.shard_id {
+                Some(shard_id) => format!("{} shard {}", artifact.column, shard_id),
+                None => artifact.column.to_string(),
+            };
+            write!(f, "\n  {:<35} ", column)?;
+            match &artifact.missing {
+                None if artifact.column == <&str>::from(DBCol::StateChanges) => {
+                    write!(f, "{} entries, {} bytes", artifact.entries, artifact.value_bytes)?
+                }
+                None => write!(f, "{} bytes", artifact.value_bytes)?,
+                Some(Missing { expected: true, reason }) => write!(f, "missing, {}", reason)?,
+                Some(Missing { expected: false, reason }) => {
+                    write!(f, "MISSING, unexpected: {}", reason)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What tells whether a missing entry of the block is expected.
+struct BlockContext {
+    /// The block is below the tail and the node isn't archival.
+    garbage_collected: bool,
+    has_body: bool,
+    canonical: bool,
+}
+
+struct ShardContext {
+    /// In `tracked_shards` or a chunk of the shard was applied at the block.
+    tracked: bool,
+    /// The block includes a new chunk of the shard, `None` if only the header
+    /// of the block is stored.
+    new_chunk: Option<bool>,
+}
+
+fn explain_missing(col: DBCol, block: &BlockContext, shard: Option<&ShardContext>) -> Missing {
+    let expected = |reason| Missing { expected: true, reason };
+    if block.garbage_collected {
+        return expected("deleted by garbage collection");
+    }
+    match col {
+        DBCol::ChallengedBlocks => {
+            return expected("only set for blocks invalidated by a challenge")
+        }
+        DBCol::StateChangesForSplitStates => {
+            return expected("only written while the shard is split at the next epoch")
+        }
+        DBCol::BlockHeight if !block.canonical => {
+            return expected("the block isn't on the canonical chain")
+        }
+        DBCol::NextBlockHashes => return expected("no block built on it was processed yet"),
+        _ => {}
+    }
+    let needs_body = !matches!(
+        col,
+        DBCol::BlockHeader
+            | DBCol::BlockMerkleTree
+            | DBCol::BlockHeight
+            | DBCol::HeaderHashesByHeight
+            | DBCol::BlockPerHeight
+    );
+    if needs_body && !block.has_body {
+        return expected("only the header of the block is stored");
+    }
+    if let Some(shard) = shard {
+        let needs_tracking = !matches!(col, DBCol::PartialChunks | DBCol::ChunkPerHeightShard);
+        if needs_tracking && !shard.tracked {
+            return expected("the shard isn't tracked by this node");
+        }
+        let needs_new_chunk = matches!(
+            col,
+            DBCol::Chunks
+                | DBCol::PartialChunks
+                | DBCol::ChunkPerHeightShard
+                | DBCol::OutgoingReceipts
+                | DBCol::OutcomeIds
+        );
+        if needs_new_chunk && shard.new_chunk == Some(false) {
+            return expected("the block has no new chunk for the shard");
+        }
+    }
+    if col == DBCol::StateChanges {
+        return expected("no state changed at the block");
+    }
+    Missing { expected: false, reason: "a processed block has it" }
+}
+
+struct Prober<'a> {
+    store: &'a Store,
+    block: BlockContext,
+    artifacts: Vec<Artifact>,
+}
+
+impl Prober<'_> {
+    fn probe(
+        &mut self,
+        col: DBCol,
+        key: &[u8],
+        shard: Option<(ShardId, &ShardContext)>,
+    ) -> anyhow::Result<()> {
+        let value = self.store.get(col, key)?;
+        let entries = value.is_some() as u64;
+        let value_bytes = value.map_or(0, |value| value.len() as u64);
+        self.push(col, key, shard, entries, value_bytes);
+        Ok(())
+    }
+
+    fn push(
+        &mut self,
+        col: DBCol,
+        key: &[u8],
+        shard: Option<(ShardId, &ShardContext)>,
+        entries: u64,
+        value_bytes: u64,
+    ) {
+        let missing = if entries == 0 {
+            Some(explain_missing(col, &self.block, shard.map(|(_, shard)| shard)))
+        } else {
+            None
+        };
+        self.artifacts.push(Artifact {
+            column: col.into(),
+            shard_id: shard.map(|(shard_id, _)| shard_id),
+            key: hex::encode(key),
+            entries,
+            value_bytes,
+            missing,
+        });
+    }
+}
+
+/// Prints the entries the database has for the block, and the missing ones.
+pub(crate) fn block_artifacts(
+    block_hash: CryptoHash,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let header = chain_store.get_block_header(&block_hash).cloned().map_err(|_| {
+        failure(FailureKind::NotFound, format!("no header of block {} in the database", block_hash))
+    })?;
+    let height = header.height();
+    let tail_height = chain_store.tail()?;
+    let block = chain_store.get_block(&block_hash).ok().cloned();
+    let canonical = chain_store.get_block_hash_by_height(height).ok() == Some(block_hash);
+    let epoch_manager =
+        EpochManager::new_from_genesis_config(store.clone(), &near_config.genesis.config)?;
+    let shard_layout = epoch_manager.get_shard_layout(header.epoch_id())?.clone();
+
+    let mut prober = Prober {
+        store: &store,
+        block: BlockContext {
+            garbage_collected: !near_config.client_config.archive && height < tail_height,
+            has_body: block.is_some(),
+            canonical,
+        },
+        artifacts: vec![],
+    };
+    for col in [
+        DBCol::BlockHeader,
+        DBCol::Block,
+        DBCol::BlockInfo,
+        DBCol::BlockExtra,
+        DBCol::BlockMerkleTree,
+        DBCol::BlockRefCount,
+        DBCol::NextBlockHashes,
+        DBCol::ChallengedBlocks,
+    ] {
+        prober.probe(col, block_hash.as_ref(), None)?;
+    }
+    let height_key = index_to_bytes(height);
+    // The row maps the height to the canonical block, present for this block
+    // only if it's that one.
+    prober.push(DBCol::BlockHeight, &height_key, None, canonical as u64, 0);
+    for col in [
+        DBCol::BlockPerHeight,
+        DBCol::HeaderHashesByHeight,
+        DBCol::ChunkHashesByHeight,
+        DBCol::ProcessedBlockHeights,
+    ] {
+        prober.probe(col, &height_key, None)?;
+    }
+    let (mut entries, mut value_bytes) = (0, 0);
+    for (_, value) in store.iter_prefix(DBCol::StateChanges, block_hash.as_ref()) {
+        entries += 1;
+        value_bytes += value.len() as u64;
+    }
+    prober.push(DBCol::StateChanges, block_hash.as_ref(), None, entries, value_bytes);
+
+    for shard_id in 0..shard_layout.num_shards() {
+        let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+        let shard_uid_key = get_block_shard_uid(&block_hash, &shard_uid);
+        let chunk_header =
+            block.as_ref().and_then(|block| block.chunks().get(shard_id as usize).cloned());
+        let shard = ShardContext {
+            tracked: near_config.client_config.tracked_shards.contains(&shard_id)
+                || store.exists(DBCol::ChunkExtra, &shard_uid_key)?,
+            new_chunk: block.as_ref().map(|_| {
+                chunk_header.as_ref().map_or(false, |header| header.height_included() == height)
+            }),
+        };
+        let shard = Some((shard_id, &shard));
+        prober.probe(DBCol::ChunkExtra, &shard_uid_key, shard)?;
+        prober.probe(DBCol::TrieChanges, &shard_uid_key, shard)?;
+        let shard_id_key = get_block_shard_id(&block_hash, shard_id);
+        for col in [
+            DBCol::IncomingReceipts,
+            DBCol::OutgoingReceipts,
+            DBCol::OutcomeIds,
+            DBCol::StateChangesForSplitStates,
+        ] {
+            prober.probe(col, &shard_id_key, shard)?;
+        }
+        if let Some(chunk_header) = chunk_header {
+            let mut height_shard_key = chunk_header.height_created().to_le_bytes().to_vec();
+            height_shard_key.extend_from_slice(&shard_id.to_le_bytes());
+            prober.probe(DBCol::ChunkPerHeightShard, &height_shard_key, shard)?;
+            prober.probe(DBCol::Chunks, chunk_header.chunk_hash().as_ref(), shard)?;
+            prober.probe(DBCol::PartialChunks, chunk_header.chunk_hash().as_ref(), shard)?;
+        }
+    }
+
+    output.print(&BlockArtifacts {
+        block_hash,
+        height,
+        tail_height,
+        canonical,
+        artifacts: prober.artifacts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain_missing() {
+        let block = BlockContext { garbage_collected: false, has_body: true, canonical: true };
+        let is_expected =
+            |col, block: &BlockContext, shard| explain_missing(col, block, shard).expected;
+        assert!(!is_expected(DBCol::BlockInfo, &block, None));
+        assert!(is_expected(DBCol::ChallengedBlocks, &block, None));
+        assert!(!is_expected(DBCol::BlockHeight, &block, None));
+        assert!(is_expected(DBCol::BlockHeight, &BlockContext { canonical: false, ..block }, None));
+        assert!(is_expected(
+            DBCol::Block,
+            &BlockContext { garbage_collected: true, ..block },
+            None
+        ));
+
+        let header_only =
+            BlockContext { garbage_collected: false, has_body: false, canonical: true };
+        assert!(is_expected(DBCol::Block, &header_only, None));
+        assert!(!is_expected(DBCol::BlockMerkleTree, &header_only, None));
+
+        let untracked = ShardContext { tracked: false, new_chunk: Some(true) };
+        assert!(is_expected(DBCol::ChunkExtra, &block, Some(&untracked)));
+        assert!(is_expected(DBCol::Chunks, &block, Some(&untracked)));
+        assert!(!is_expected(DBCol::PartialChunks, &block, Some(&untracked)));
+        let old_chunk = ShardContext { tracked: true, new_chunk: Some(false) };
+        assert!(is_expected(DBCol::PartialChunks, &block, Some(&old_chunk)));
+        assert!(!is_expected(DBCol::ChunkExtra, &block, Some(&old_chunk)));
+        assert!(!is_expected(DBCol::TrieChanges, &block, Some(&old_chunk)));
+        assert!(is_expected(DBCol::OutgoingReceipts, &block, Some(&old_chunk)));
+    }
+}
@@ -0,0 +1,390 @@
+//! Read-only access to the chain of a node's database, for the tools which
+//! embed it rather than running `neard view-state`.
+//!
+//! [`ChainReader`] is opened from the home directory of a node with
+//! [`ChainReader::builder`] and hides how the chain store, the epoch manager and
+//! the runtime are set up.  Its functions return the views the JSON RPC returns
+//! and take the types of `near-primitives`, so they don't change with the
+//! internals of the node: a function is only removed or changes its signature
+//! after it's been deprecated for a release, and the tests of this module
+//! exercise each of them.  Missing blocks, chunks, outcomes, accounts and keys
+//! are `None` rather than errors.
+
+use anyhow::Context;
+use near_chain::{ChainStore, ChainStoreAccess, ErrorKind, RuntimeAdapter};
+use near_chain_configs::GenesisValidationMode;
+use near_crypto::PublicKey;
+use near_primitives::block::BlockHeader;
+use near_primitives::hash::CryptoHash;
+use near_primitives::sharding::ChunkHash;
+use near_primitives::types::{AccountId, BlockHeight, BlockId};
+use near_primitives::views::{
+    AccessKeyView, AccountView, BlockView, ChunkView, ExecutionOutcomeWithIdView, QueryRequest,
+    QueryResponseKind, ViewStateResult,
+};
+use near_store::{try_create_store_with_config, Store};
+use nearcore::{get_store_path, load_config, NearConfig, NightshadeRuntime};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Sets up a [`ChainReader`].  By default the config of the home directory is
+/// loaded and its database opened in read-only mode, which a running node
+/// allows.
+pub struct ChainReaderBuilder {
+    home_dir: PathBuf,
+    near_config: Option<NearConfig>,
+    store: Option<Store>,
+}
+
+impl ChainReaderBuilder {
+    /// Uses the given config rather than loading the one of the home
+    /// directory.
+    pub fn near_config(mut self, near_config: NearConfig) -> Self {
+        self.near_config = Some(near_config);
+        self
+    }
+
+    /// Reads the given store rather than the database of the home directory.
+    pub fn store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    pub fn open(self) -> anyhow::Result<ChainReader> {
+        let near_config = match self.near_config {
+            Some(near_config) => near_config,
+            None => load_config(&self.home_dir, GenesisValidationMode::UnsafeFast)
+                .context("Failed to load the config")?,
+        };
+        let store = match self.store {
+            Some(store) => store,
+            None => {
+                let store_path = get_store_path(&self.home_dir);
+                // The runtime needs a `Store` rather than a `ReadOnlyStore`,
+                // the database opened read-only still rejects any write.
+                let store_config = near_config.config.store.clone().with_read_only(true);
+                try_create_store_with_config(&store_path, &store_config).with_context(|| {
+                    format!("Failed to open {} in read-only mode", store_path.display())
+                })?
+            }
+        };
+        let runtime = NightshadeRuntime::with_config(
+            &self.home_dir,
+            store.clone(),
+            &near_config,
+            None,
+            near_config.client_config.max_gas_burnt_view,
+        );
+        Ok(ChainReader::from_parts(
+            store,
+            near_config.genesis.config.genesis_height,
+            near_config.client_config.archive,
+            runtime,
+        ))
+    }
+}
+
+/// Read-only access to the chain of a database, see the module documentation.
+pub struct ChainReader {
+    store: Store,
+    genesis_height: BlockHeight,
+    archive: bool,
+    runtime: NightshadeRuntime,
+}
+
+impl ChainReader {
+    pub fn builder(home_dir: &Path) -> ChainReaderBuilder {
+        ChainReaderBuilder { home_dir: home_dir.to_path_buf(), near_config: None, store: None }
+    }
+
+    fn from_parts(
+        store: Store,
+        genesis_height: BlockHeight,
+        archive: bool,
+        runtime: NightshadeRuntime,
+    ) -> Self {
+        Self { store, genesis_height, archive, runtime }
+    }
+
+    fn chain_store(&self) -> ChainStore {
+        ChainStore::new(self.store.clone(), self.genesis_height, !self.archive)
+    }
+
+    /// Height of the head of the chain.
+    pub fn head_height(&self) -> anyhow::Result<BlockHeight> {
+        Ok(self.chain_store().head().context("Failed to read the head")?.height)
+    }
+
+    /// The block of the canonical chain at the height, `None` at skipped
+    /// heights and past the head.
+    pub fn block_by_height(&self, height: BlockHeight) -> anyhow::Result<Option<BlockView>> {
+        let block_hash =
+            match not_found_to_none(self.chain_store().get_block_hash_by_height(height))
+                .with_context(|| format!("Failed to read the block hash at height {}", height))?
+            {
+                Some(block_hash) => block_hash,
+                None => return Ok(None),
+            };
+        self.block_by_hash(&block_hash)
+    }
+
+    pub fn block_by_hash(&self, block_hash: &CryptoHash) -> anyhow::Result<Option<BlockView>> {
+        let mut chain_store = self.chain_store();
+        let block = match not_found_to_none(chain_store.get_block(block_hash))
+            .with_context(|| format!("Failed to read block {}", block_hash))?
+        {
+            Some(block) => block.clone(),
+            None => return Ok(None),
+        };
+        let author = self
+            .runtime
+            .get_block_producer(block.header().epoch_id(), block.header().height())
+            .with_context(|| format!("Failed to get the producer of block {}", block_hash))?;
+        Ok(Some(BlockView::from_author_block(author, block)))
+    }
+
+    /// The blocks of the canonical chain in the range of heights, in order of
+    /// heights.
+    pub fn blocks(
+        &self,
+        heights: RangeInclusive<BlockHeight>,
+    ) -> impl Iterator<Item = anyhow::Result<BlockView>> + '_ {
+        heights.filter_map(move |height| self.block_by_height(height).transpose())
+    }
+
+    pub fn chunk(&self, chunk_hash: &ChunkHash) -> anyhow::Result<Option<ChunkView>> {
+        let mut chain_store = self.chain_store();
+        let chunk = match not_found_to_none(chain_store.get_chunk(chunk_hash))
+            .with_context(|| format!("Failed to read chunk {:?}", chunk_hash))?
+        {
+            Some(chunk) => chunk.clone(),
+            None => return Ok(None),
+        };
+        let epoch_id = self.runtime.get_epoch_id_from_prev_block(chunk.prev_block())?;
+        let author = self
+            .runtime
+            .get_chunk_producer(&epoch_id, chunk.height_created(), chunk.shard_id())
+            .with_context(|| format!("Failed to get the producer of chunk {:?}", chunk_hash))?;
+        Ok(Some(ChunkView::from_author_chunk(author, chunk)))
+    }
+
+    /// Outcome of the transaction or receipt with the id in the canonical
+    /// chain.  The outcomes in blocks of forks are ignored.
+    pub fn execution_outcome(
+        &self,
+        id: &CryptoHash,
+    ) -> anyhow::Result<Option<ExecutionOutcomeWithIdView>> {
+        let mut chain_store = self.chain_store();
+        let outcomes = chain_store
+            .get_outcomes_by_id(id)
+            .with_context(|| format!("Failed to read the outcomes of {}", id))?;
+        for outcome in outcomes {
+            let height = chain_store.get_block_header(&outcome.block_hash)?.height();
+            if not_found_to_none(chain_store.get_block_hash_by_height(height))?
+                == Some(outcome.block_hash)
+            {
+                return Ok(Some(outcome.into()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The account in the state after the block.
+    pub fn view_account(
+        &self,
+        account_id: &AccountId,
+        block_id: BlockId,
+    ) -> anyhow::Result<Option<AccountView>> {
+        let request = QueryRequest::ViewAccount { account_id: account_id.clone() };
+        match self.query(account_id, block_id, &request)? {
+            Some(QueryResponseKind::ViewAccount(account)) => Ok(Some(account)),
+            Some(_) => unreachable!(),
+            None => Ok(None),
+        }
+    }
+
+    /// The access key of the account in the state after the block.
+    pub fn view_access_key(
+        &self,
+        account_id: &AccountId,
+        public_key: &PublicKey,
+        block_id: BlockId,
+    ) -> anyhow::Result<Option<AccessKeyView>> {
+        let request = QueryRequest::ViewAccessKey {
+            account_id: account_id.clone(),
+            public_key: public_key.clone(),
+        };
+        match self.query(account_id, block_id, &request)? {
+            Some(QueryResponseKind::AccessKey(access_key)) => Ok(Some(access_key)),
+            Some(_) => unreachable!(),
+            None => Ok(None),
+        }
+    }
+
+    /// The values the contract of the account stored under keys with the
+    /// prefix, in the state after the block.  Nodes limit the size of the
+    /// states viewed with `trie_viewer_state_size_limit`, this doesn't.
+    pub fn view_contract_state(
+        &self,
+        account_id: &AccountId,
+        prefix: &[u8],
+        block_id: BlockId,
+    ) -> anyhow::Result<Option<ViewStateResult>> {
+        let request = QueryRequest::ViewState {
+            account_id: account_id.clone(),
+            prefix: prefix.to_vec().into(),
+        };
+        match self.query(account_id, block_id, &request)? {
+            Some(QueryResponseKind::ViewState(state)) => Ok(Some(state)),
+            Some(_) => unreachable!(),
+            None => Ok(None),
+        }
+    }
+
+    fn header(&self, block_id: BlockId) -> anyhow::Result<BlockHeader> {
+        let mut chain_store = self.chain_store();
+        let block_hash = match block_id {
+            BlockId::Height(height) => chain_store
+                .get_block_hash_by_height(height)
+                .with_context(|| format!("No block at height {}", height))?,
+            BlockId::Hash(block_hash) => block_hash,
+        };
+        Ok(chain_store
+            .get_block_header(&block_hash)
+            .with_context(|| format!("Failed to read block {}", block_hash))?
+            .clone())
+    }
+
+    /// Runs the query in the state of the shard of the account after the
+    /// block, `None` if the account or key doesn't exist.
+    fn query(
+        &self,
+        account_id: &AccountId,
+        block_id: BlockId,
+        request: &QueryRequest,
+    ) -> anyhow::Result<Option<QueryResponseKind>> {
+        let header = self.header(block_id)?;
+        let shard_id = self.runtime.account_id_to_shard_id(account_id, header.epoch_id())?;
+        let shard_uid = self.runtime.shard_id_to_uid(shard_id, header.epoch_id())?;
+        let chunk_extra =
+            self.chain_store().get_chunk_extra(header.hash(), &shard_uid).with_context(|| {
+                format!(
+                    "No state of shard {} at block {}, it isn't tracked or was garbage collected",
+                    shard_id,
+                    header.hash()
+                )
+            })?;
+        let result = self.runtime.query(
+            shard_uid,
+            chunk_extra.state_root(),
+            header.height(),
+            header.raw_timestamp(),
+            header.prev_hash(),
+            header.hash(),
+            header.epoch_id(),
+            request,
+        );
+        match result {
+            Ok(response) => Ok(Some(response.kind)),
+            Err(
+                near_chain::near_chain_primitives::error::QueryError::UnknownAccount { .. }
+                | near_chain::near_chain_primitives::error::QueryError::UnknownAccessKey { .. },
+            ) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+fn not_found_to_none<T>(result: Result<T, near_chain::Error>) -> anyhow::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if matches!(err.kind(), ErrorKind::DBNotFoundErr(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChainReader;
+    use near_chain::{ChainGenesis, Provenance};
+    use near_chain_configs::Genesis;
+    use near_client::test_utils::TestEnv;
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_network::types::NetworkClientResponses;
+    use near_primitives::transaction::SignedTransaction;
+    use near_primitives::types::BlockId;
+    use near_store::test_utils::create_test_store;
+    use nearcore::config::GenesisExt;
+    use nearcore::NightshadeRuntime;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_chain_reader() {
+        let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+        let store = create_test_store();
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let mut env = TestEnv::builder(ChainGenesis::test())
+            .runtime_adapters(vec![Arc::new(runtime)])
+            .build();
+        let genesis_hash = *env.clients[0].chain.genesis().hash();
+        let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        );
+        let tx_hash = tx.get_hash();
+        assert_eq!(env.clients[0].process_tx(tx, false, false), NetworkClientResponses::ValidTx);
+        for height in 1..=5 {
+            let block = env.clients[0].produce_block(height).unwrap().unwrap();
+            env.process_block(0, block, Provenance::PRODUCED);
+        }
+
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let reader = ChainReader::from_parts(store, genesis.config.genesis_height, false, runtime);
+        assert_eq!(reader.head_height().unwrap(), 5);
+
+        let block = reader.block_by_height(2).unwrap().unwrap();
+        assert_eq!(block.header.height, 2);
+        assert_eq!(block.author.as_ref(), "test0");
+        let by_hash = reader.block_by_hash(&block.header.hash).unwrap().unwrap();
+        assert_eq!(by_hash.header.height, 2);
+        assert!(reader.block_by_height(6).unwrap().is_none());
+        assert!(reader.block_by_hash(&tx_hash).unwrap().is_none());
+        let heights: Vec<_> =
+            reader.blocks(0..=7).map(|block| block.unwrap().header.height).collect();
+        assert_eq!(heights, vec![0, 1, 2, 3, 4, 5]);
+
+        let chunk_hash = block.chunks[0].chunk_hash.into();
+        let chunk = reader.chunk(&chunk_hash).unwrap().unwrap();
+        assert_eq!(chunk.header.shard_id, 0);
+
+        let outcome = reader.execution_outcome(&tx_hash).unwrap().unwrap();
+        assert_eq!(outcome.id, tx_hash);
+        assert!(reader.execution_outcome(&genesis_hash).unwrap().is_none());
+
+        let test0 = "test0".parse().unwrap();
+        let before = reader.view_account(&test0, BlockId::Height(0)).unwrap().unwrap();
+        let after = reader.view_account(&test0, BlockId::Height(5)).unwrap().unwrap();
+        assert!(after.amount < before.amount);
+        let missing = "missing".parse().unwrap();
+        assert!(reader.view_account(&missing, BlockId::Hash(block.header.hash)).unwrap().is_none());
+
+        let access_key =
+            reader.view_access_key(&test0, &signer.public_key, BlockId::Height(5)).unwrap();
+        assert_eq!(access_key.unwrap().nonce, 1);
+        let other_key = InMemorySigner::from_seed(test0.clone(), KeyType::ED25519, "other");
+        assert!(reader
+            .view_access_key(&test0, &other_key.public_key, BlockId::Height(5))
+            .unwrap()
+            .is_none());
+
+        let state = reader.view_contract_state(&test0, b"", BlockId::Height(5)).unwrap().unwrap();
+        assert!(state.values.is_empty());
+        assert!(reader.view_account(&test0, BlockId::Height(6)).is_err());
+    }
+}
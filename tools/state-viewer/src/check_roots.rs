@@ -0,0 +1,269 @@
+//! Consistency check of the merkle roots stored in block headers.
+//!
+//! All roots are recomputed from data which is already in the database, so
+//! nothing is executed and the check is only as slow as reading the blocks.
+//! Data removed by garbage collection is reported as missing, not as a
+//! mismatch.
+
+use std::time::Instant;
+
+use near_chain::types::ApplyTransactionResult;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_epoch_manager::EpochManager;
+use near_primitives::block::Block;
+use near_primitives::hash::CryptoHash;
+use near_primitives::merkle::{compute_root_from_path_and_item, merklize, PartialMerkleTree};
+use near_primitives::shard_layout::ShardUId;
+use near_primitives::transaction::ExecutionOutcomeWithIdAndProof;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::utils::get_block_shard_id;
+use near_store::{DBCol, Store};
+use nearcore::NearConfig;
+
+use crate::output::{failure, FailureKind};
+
+#[derive(Debug, PartialEq)]
+struct Mismatch {
+    /// Name of the checked field, e.g. `chunk_tx_root` or
+    /// `chunks[1].tx_root`.
+    field: String,
+    stored: CryptoHash,
+    computed: CryptoHash,
+}
+
+#[derive(Default)]
+struct BlockCheck {
+    mismatches: Vec<Mismatch>,
+    /// Data needed by a check which isn't in the database.
+    missing: Vec<String>,
+}
+
+impl BlockCheck {
+    fn compare(&mut self, field: impl Into<String>, stored: &CryptoHash, computed: CryptoHash) {
+        if stored != &computed {
+            self.mismatches.push(Mismatch { field: field.into(), stored: *stored, computed });
+        }
+    }
+}
+
+pub(crate) fn check_roots(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let epoch_manager =
+        EpochManager::new_from_genesis_config(store.clone(), &near_config.genesis.config)?;
+    let started = Instant::now();
+    let (mut blocks, mut bad_blocks, mut missing) = (0, 0, 0);
+    for height in from_height..=to_height {
+        let block_hash = match chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            Err(_) => continue,
+        };
+        let block = chain_store.get_block(&block_hash)?.clone();
+        let check = check_block(&mut chain_store, &epoch_manager, &store, &block)?;
+        blocks += 1;
+        if !check.mismatches.is_empty() {
+            bad_blocks += 1;
+        }
+        missing += check.missing.len();
+        for mismatch in check.mismatches {
+            println!(
+                "#{} {}: {} mismatch: stored {}, computed {}",
+                height, block_hash, mismatch.field, mismatch.stored, mismatch.computed
+            );
+        }
+        for what in check.missing {
+            println!("#{} {}: {} not found, skipped", height, block_hash, what);
+        }
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "Checked {} blocks in {:.1}s ({:.0} blocks/s): {} with mismatches, {} checks skipped",
+        blocks,
+        elapsed,
+        blocks as f64 / elapsed.max(1e-9),
+        bad_blocks,
+        missing
+    );
+    if bad_blocks > 0 {
+        return Err(failure(
+            FailureKind::Corruption,
+            format!("{} blocks have mismatched roots", bad_blocks),
+        ));
+    }
+    Ok(())
+}
+
+fn check_block(
+    chain_store: &mut ChainStore,
+    epoch_manager: &EpochManager,
+    store: &Store,
+    block: &Block,
+) -> anyhow::Result<BlockCheck> {
+    let header = block.header();
+    let mut check = BlockCheck::default();
+    check.compare(
+        "chunk_headers_root",
+        header.chunk_headers_root(),
+        Block::compute_chunk_headers_root(block.chunks().iter()).0,
+    );
+    check.compare(
+        "chunk_tx_root",
+        header.chunk_tx_root(),
+        Block::compute_chunk_tx_root(block.chunks().iter()),
+    );
+    check.compare(
+        "outcome_root",
+        header.outcome_root(),
+        Block::compute_outcome_root(block.chunks().iter()),
+    );
+    check.compare(
+        "challenges_root",
+        header.challenges_root(),
+        Block::compute_challenges_root(block.challenges()),
+    );
+
+    let prev_hash = *header.prev_hash();
+    let prev_tree = if prev_hash == CryptoHash::default() {
+        Some(PartialMerkleTree::default())
+    } else {
+        chain_store.get_block_merkle_tree(&prev_hash).ok().cloned()
+    };
+    match prev_tree {
+        Some(mut tree) => {
+            if prev_hash != CryptoHash::default() {
+                tree.insert(prev_hash);
+            }
+            check.compare("block_merkle_root", header.block_merkle_root(), tree.root());
+        }
+        None => check.missing.push("merkle tree of the previous block".to_string()),
+    }
+
+    let shard_layout = epoch_manager.get_shard_layout(header.epoch_id())?.clone();
+    for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+        if chunk_header.height_included() != header.height() {
+            continue;
+        }
+        let shard_id = shard_id as ShardId;
+        match chain_store.get_chunk(&chunk_header.chunk_hash()) {
+            Ok(chunk) => check.compare(
+                format!("chunks[{}].tx_root", shard_id),
+                &chunk_header.tx_root(),
+                merklize(chunk.transactions()).0,
+            ),
+            Err(_) => check.missing.push(format!("chunk {}", shard_id)),
+        }
+
+        let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &shard_layout);
+        let outcome_root = match chain_store.get_chunk_extra(block.hash(), &shard_uid) {
+            Ok(chunk_extra) => *chunk_extra.outcome_root(),
+            Err(_) => {
+                check.missing.push(format!("chunk extra of shard {}", shard_id));
+                continue;
+            }
+        };
+        match get_outcomes(chain_store, store, block.hash(), shard_id)? {
+            Some(outcomes) => check_outcomes(&mut check, shard_id, &outcome_root, &outcomes),
+            None => check.missing.push(format!("outcomes of shard {}", shard_id)),
+        }
+    }
+    Ok(check)
+}
+
+/// Returns the outcomes of the chunk of the shard applied in the block, in
+/// the order they were saved.
+fn get_outcomes(
+    chain_store: &ChainStore,
+    store: &Store,
+    block_hash: &CryptoHash,
+    shard_id: ShardId,
+) -> anyhow::Result<Option<Vec<ExecutionOutcomeWithIdAndProof>>> {
+    let ids: Vec<CryptoHash> =
+        match store.get_ser(DBCol::OutcomeIds, &get_block_shard_id(block_hash, shard_id))? {
+            Some(ids) => ids,
+            None => return Ok(None),
+        };
+    let mut outcomes = Vec::with_capacity(ids.len());
+    for id in ids {
+        let outcome = chain_store
+            .get_outcomes_by_id(&id)?
+            .into_iter()
+            .find(|outcome| &outcome.block_hash == block_hash);
+        match outcome {
+            Some(outcome) => outcomes.push(outcome),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(outcomes))
+}
+
+/// Compares the root of the outcomes with the one in the chunk extra and
+/// verifies the stored proof of every outcome against it.
+fn check_outcomes(
+    check: &mut BlockCheck,
+    shard_id: ShardId,
+    outcome_root: &CryptoHash,
+    outcomes: &[ExecutionOutcomeWithIdAndProof],
+) {
+    let outcomes_with_id: Vec<_> =
+        outcomes.iter().map(|outcome| outcome.outcome_with_id.clone()).collect();
+    let (computed_root, _) = ApplyTransactionResult::compute_outcomes_proof(&outcomes_with_id);
+    check.compare(format!("chunk_extra[{}].outcome_root", shard_id), outcome_root, computed_root);
+    for outcome in outcomes {
+        // The root the stored proof leads to.
+        let proof_root =
+            compute_root_from_path_and_item(&outcome.proof, &outcome.outcome_with_id.to_hashes());
+        check.compare(
+            format!("outcomes[{}][{}].proof", shard_id, outcome.outcome_with_id.id),
+            &proof_root,
+            *outcome_root,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+    use near_primitives::transaction::{ExecutionOutcome, ExecutionOutcomeWithId};
+
+    #[test]
+    fn test_check_outcomes() {
+        let block_hash = hash(b"block");
+        let outcomes: Vec<_> = (0..3u8)
+            .map(|i| ExecutionOutcomeWithId {
+                id: hash(&[i]),
+                outcome: ExecutionOutcome { gas_burnt: i as u64, ..Default::default() },
+            })
+            .collect();
+        let (root, proofs) = ApplyTransactionResult::compute_outcomes_proof(&outcomes);
+        let mut stored: Vec<_> = outcomes
+            .into_iter()
+            .zip(proofs)
+            .map(|(outcome_with_id, proof)| ExecutionOutcomeWithIdAndProof {
+                proof,
+                block_hash,
+                outcome_with_id,
+            })
+            .collect();
+
+        let mut check = BlockCheck::default();
+        check_outcomes(&mut check, 0, &root, &stored);
+        assert_eq!(check.mismatches, vec![]);
+
+        // A corrupted outcome changes the root and invalidates its proof.
+        stored[1].outcome_with_id.outcome.gas_burnt = 100;
+        let mut check = BlockCheck::default();
+        check_outcomes(&mut check, 2, &root, &stored);
+        let fields: Vec<_> = check.mismatches.iter().map(|m| m.field.as_str()).collect();
+        let proof_field = format!("outcomes[2][{}].proof", stored[1].outcome_with_id.id);
+        assert_eq!(fields, vec!["chunk_extra[2].outcome_root", proof_field.as_str()]);
+    }
+}
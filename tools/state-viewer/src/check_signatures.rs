@@ -0,0 +1,168 @@
+//! Offline check of the signatures of the blocks of a range of heights, with
+//! the functions nodes validate the blocks they receive with.
+//!
+//! The signature of the block producer, the approvals and whether they have
+//! enough stake, and the signatures of the producers of the new chunks are
+//! checked for each block of the canonical chain.  Chunks aren't endorsed by
+//! other validators, the signature of the chunk producer is all that vouches
+//! for a chunk.
+
+use crate::output::{failure, FailureKind};
+use near_chain::{
+    verify_header_approvals, Block, BlockHeader, ChainStore, ChainStoreAccess,
+    DoomslugThresholdMode, RuntimeAdapter,
+};
+use near_primitives::types::{BlockHeight, ShardId};
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SignatureFailure {
+    BlockProducer(String),
+    Approvals(String),
+    ChunkProducer { shard_id: ShardId, error: String },
+    Body(String),
+}
+
+impl std::fmt::Display for SignatureFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BlockProducer(error) => write!(f, "block producer signature: {}", error),
+            Self::Approvals(error) => write!(f, "approvals: {}", error),
+            Self::ChunkProducer { shard_id, error } => {
+                write!(f, "chunk producer signature of shard {}: {}", shard_id, error)
+            }
+            Self::Body(error) => write!(f, "block body: {}", error),
+        }
+    }
+}
+
+/// Everything about the signatures of the block which wouldn't pass the
+/// validation of the block by a node.
+fn check_block(
+    runtime: &dyn RuntimeAdapter,
+    doomslug_threshold_mode: DoomslugThresholdMode,
+    block: &Block,
+    prev_header: &BlockHeader,
+) -> Vec<SignatureFailure> {
+    let header = block.header();
+    let mut failures = vec![];
+    match runtime.verify_header_signature(header) {
+        Ok(true) => {}
+        Ok(false) => failures.push(SignatureFailure::BlockProducer("doesn't verify".to_string())),
+        Err(err) => failures.push(SignatureFailure::BlockProducer(err.to_string())),
+    }
+    if let Err(err) = verify_header_approvals(runtime, doomslug_threshold_mode, header, prev_header)
+    {
+        failures.push(SignatureFailure::Approvals(err.to_string()));
+    }
+    for chunk_header in block.chunks().iter() {
+        // Genesis chunks included later aren't signed.
+        if chunk_header.height_created() != header.height() {
+            continue;
+        }
+        let shard_id = chunk_header.shard_id();
+        match runtime.verify_chunk_header_signature(
+            chunk_header,
+            header.epoch_id(),
+            header.prev_hash(),
+        ) {
+            Ok(true) => {}
+            Ok(false) => failures.push(SignatureFailure::ChunkProducer {
+                shard_id,
+                error: "doesn't verify".to_string(),
+            }),
+            Err(err) => {
+                failures.push(SignatureFailure::ChunkProducer { shard_id, error: err.to_string() })
+            }
+        }
+    }
+    if let Err(err) = block.check_validity() {
+        failures.push(SignatureFailure::Body(format!("{:?}", err)));
+    }
+    failures
+}
+
+/// Checks the blocks of the canonical chain at the heights of the range and
+/// prints every failure found.  Fails if there's any.
+pub(crate) fn check_signatures(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    if from_height > to_height {
+        return Err(failure(
+            FailureKind::InvalidArgument,
+            format!("--from-height {} is above --to-height {}", from_height, to_height),
+        ));
+    }
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let (mut checked, mut failed) = (0, 0);
+    for height in from_height.max(genesis_height + 1)..=to_height {
+        let block_hash = match chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            // No block at the height.
+            Err(_) => continue,
+        };
+        let block = chain_store.get_block(&block_hash)?.clone();
+        let prev_header = chain_store.get_block_header(block.header().prev_hash())?.clone();
+        let failures =
+            check_block(&runtime, DoomslugThresholdMode::TwoThirds, &block, &prev_header);
+        for failure in &failures {
+            println!("#{} {}: {}", height, block_hash, failure);
+        }
+        if !failures.is_empty() {
+            failed += 1;
+        }
+        checked += 1;
+    }
+    if failed > 0 {
+        return Err(failure(
+            FailureKind::Corruption,
+            format!("{} of the {} blocks checked wouldn't pass validation", failed, checked),
+        ));
+    }
+    println!("The signatures of all the {} blocks checked verify", checked);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_chain::test_utils::setup;
+    use near_crypto::KeyType;
+    use near_primitives::validator_signer::InMemoryValidatorSigner;
+
+    #[test]
+    fn test_check_block() {
+        let (chain, runtime, signer) = setup();
+        let genesis = chain.genesis_block().clone();
+        let threshold_mode = DoomslugThresholdMode::NoApprovals;
+        let block = Block::empty(&genesis, &*signer);
+        assert!(check_block(&*runtime, threshold_mode, &block, genesis.header()).is_empty());
+
+        let other =
+            InMemoryValidatorSigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "other");
+        let mut block = Block::empty(&genesis, &*signer);
+        block.mut_header().resign(&other);
+        assert_eq!(
+            check_block(&*runtime, threshold_mode, &block, genesis.header()),
+            vec![SignatureFailure::BlockProducer("doesn't verify".to_string())]
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! Benchmark of the apply of the chunks of a shard, to tell whether the
+//! machine keeps up with the chain before it's asked to produce chunks.
+//!
+//! The chunks of the last blocks with a new chunk of the shard are applied
+//! the way the chain applies them, over and over, on the database of the node
+//! opened read only: the results of the applies are dropped.  The first
+//! iterations warm up the caches and aren't measured.  The wall time and the
+//! CPU time of the process are measured per chunk, and the latency of the
+//! reads of the database from its histogram, whose buckets the percentiles are
+//! rounded up to.  The verdict compares the 95th percentile of the apply time
+//! to the budget of a chunk.
+//!
+//! A running node would compete with the benchmark for the disk and the CPU,
+//! so the database of the node is only benchmarked while the node is stopped.
+//! A clone made by `neard database clone` can be benchmarked at any time.
+
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, Gas, NumBlocks, ShardId};
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use serde::Serialize;
+
+use crate::commands::apply_block;
+use crate::output::{failure, FailureKind, Output};
+
+/// Histogram of the latency of the database operations, by operation.
+const DATABASE_OP_LATENCY: &str = "near_database_op_latency_by_op_and_column";
+
+/// Percentiles of a measurement per chunk, in milliseconds.
+#[derive(Serialize, Debug, PartialEq)]
+struct Percentiles {
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl Percentiles {
+    fn of(mut values: Vec<f64>) -> Self {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Self {
+            p50: percentile(&values, 0.5),
+            p95: percentile(&values, 0.95),
+            p99: percentile(&values, 0.99),
+            max: values.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50 {:.1} ms, p95 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+            self.p50, self.p95, self.p99, self.max
+        )
+    }
+}
+
+/// Percentiles of the latency of the reads of the database, in milliseconds,
+/// rounded up to the bounds of the buckets of the histogram.  `None` above
+/// the last bucket.
+#[derive(Serialize, Debug, PartialEq)]
+struct ReadLatency {
+    reads: u64,
+    p50: Option<f64>,
+    p95: Option<f64>,
+    p99: Option<f64>,
+}
+
+impl fmt::Display for ReadLatency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bound = |value: Option<f64>| match value {
+            Some(ms) => format!("≤ {} ms", ms),
+            None => "over the last bucket".to_string(),
+        };
+        write!(
+            f,
+            "{} reads, p50 {}, p95 {}, p99 {}",
+            self.reads,
+            bound(self.p50),
+            bound(self.p95),
+            bound(self.p99)
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct ChunkApplyBenchmark {
+    shard_id: ShardId,
+    /// Chunks applied per iteration, from the lowest height to the highest.
+    chunks: usize,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    warmup_iterations: usize,
+    /// Measured iterations.
+    iterations: usize,
+    apply_time: Percentiles,
+    cpu_time: Percentiles,
+    read_latency: ReadLatency,
+    mean_gas_burnt: Gas,
+    budget_ms: u64,
+    /// Whether the 95th percentile of the apply time is within the budget.
+    within_budget: bool,
+}
+
+impl fmt::Display for ChunkApplyBenchmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Shard {}: {} chunks from #{} to #{}, {} iterations after {} warm-up",
+            self.shard_id,
+            self.chunks,
+            self.from_height,
+            self.to_height,
+            self.iterations,
+            self.warmup_iterations
+        )?;
+        writeln!(f, "Apply time:   {}", self.apply_time)?;
+        writeln!(f, "CPU time:     {}", self.cpu_time)?;
+        writeln!(f, "Reads:        {}", self.read_latency)?;
+        writeln!(f, "Gas burnt:    {} per chunk on average", self.mean_gas_burnt)?;
+        write!(
+            f,
+            "{}: p95 apply time {:.1} ms against a budget of {} ms",
+            if self.within_budget { "OK" } else { "TOO SLOW" },
+            self.apply_time.p95,
+            self.budget_ms
+        )
+    }
+}
+
+/// Lowest value at or above the `q` share of the sorted values.
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.;
+    }
+    let rank = ((q * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+/// Cumulative counts of the reads of the database by the upper bound of their
+/// latency bucket, in seconds, summed over the columns.
+#[derive(Default, Debug, Clone, PartialEq)]
+struct ReadHistogram {
+    buckets: Vec<(f64, u64)>,
+    count: u64,
+}
+
+impl ReadHistogram {
+    fn gather() -> Self {
+        let mut histogram = Self::default();
+        for family in near_metrics::gather() {
+            if family.get_name() != DATABASE_OP_LATENCY {
+                continue;
+            }
+            for metric in family.get_metric() {
+                let is_read = metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_name() == "op" && label.get_value() == "get");
+                if !is_read {
+                    continue;
+                }
+                let metric = metric.get_histogram();
+                histogram.count += metric.get_sample_count();
+                for (i, bucket) in metric.get_bucket().iter().enumerate() {
+                    if histogram.buckets.len() <= i {
+                        histogram.buckets.push((bucket.get_upper_bound(), 0));
+                    }
+                    histogram.buckets[i].1 += bucket.get_cumulative_count();
+                }
+            }
+        }
+        histogram
+    }
+
+    /// The reads counted since `before` was gathered.
+    fn since(&self, before: &ReadHistogram) -> ReadHistogram {
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, &(bound, count))| {
+                (bound, count - before.buckets.get(i).map_or(0, |&(_, count)| count))
+            })
+            .collect();
+        ReadHistogram { buckets, count: self.count - before.count }
+    }
+
+    /// Upper bound of the bucket of the `q` quantile in milliseconds, `None`
+    /// if it's above the last bucket.
+    fn percentile(&self, q: f64) -> Option<f64> {
+        let rank = ((q * self.count as f64).ceil() as u64).max(1);
+        self.buckets.iter().find(|(_, count)| *count >= rank).map(|(bound, _)| bound * 1000.)
+    }
+
+    fn latency(&self) -> ReadLatency {
+        ReadLatency {
+            reads: self.count,
+            p50: self.percentile(0.5),
+            p95: self.percentile(0.95),
+            p99: self.percentile(0.99),
+        }
+    }
+}
+
+/// CPU time of all the threads of the process.
+#[cfg(unix)]
+fn process_cpu_time() -> Duration {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `time` is valid for writes for the duration of the call.
+    if unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut time) } != 0 {
+        return Duration::ZERO;
+    }
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(not(unix))]
+fn process_cpu_time() -> Duration {
+    Duration::ZERO
+}
+
+/// Whether another process holds the lock of the database, as a running node
+/// does.  RocksDB locks its `LOCK` file with `fcntl`, which can be tested
+/// without taking the lock, so the database isn't opened for writing.
+#[cfg(unix)]
+pub(crate) fn is_store_in_use(store_path: &Path) -> anyhow::Result<bool> {
+    use anyhow::Context;
+    use std::os::unix::io::AsRawFd;
+
+    let lock_path = store_path.join("LOCK");
+    let file = match std::fs::File::open(&lock_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).with_context(|| lock_path.display().to_string()),
+    };
+    // SAFETY: `flock` is plain data, for which zeroes are valid: a lock of the
+    // whole file.
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    // SAFETY: the descriptor is open and `lock` valid for the duration of the
+    // call.
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } == -1 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| lock_path.display().to_string());
+    }
+    Ok(lock.l_type != libc::F_UNLCK as _)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_store_in_use(_store_path: &Path) -> anyhow::Result<bool> {
+    Ok(false)
+}
+
+/// Hashes of the last `blocks` blocks with a new chunk of the shard, the
+/// lowest first, whose previous block isn't garbage collected.
+fn recent_chunk_blocks(
+    chain_store: &mut ChainStore,
+    shard_id: ShardId,
+    blocks: NumBlocks,
+) -> anyhow::Result<Vec<CryptoHash>> {
+    let tail = chain_store.tail()?;
+    let mut block_hash = chain_store.head()?.last_block_hash;
+    let mut hashes = vec![];
+    while (hashes.len() as NumBlocks) < blocks {
+        let block = chain_store.get_block(&block_hash)?;
+        let height = block.header().height();
+        if height <= tail + 1 {
+            break;
+        }
+        let num_shards = block.chunks().len();
+        let chunk = block.chunks().get(shard_id as usize).cloned().ok_or_else(|| {
+            failure(
+                FailureKind::InvalidArgument,
+                format!("Shard {} doesn't exist, there are {} shards", shard_id, num_shards),
+            )
+        })?;
+        if chunk.height_included() == height {
+            hashes.push(block_hash);
+        }
+        block_hash = *block.header().prev_hash();
+    }
+    hashes.reverse();
+    Ok(hashes)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn chunk_apply_benchmark(
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    shard_id: ShardId,
+    blocks: NumBlocks,
+    warmup_iterations: usize,
+    iterations: usize,
+    budget: Duration,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if blocks == 0 || iterations == 0 {
+        return Err(failure(
+            FailureKind::InvalidArgument,
+            "--blocks and --iterations must be at least 1",
+        ));
+    }
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store.clone(),
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let mut chain_store = ChainStore::new(store, near_config.genesis.config.genesis_height, true);
+    let block_hashes = recent_chunk_blocks(&mut chain_store, shard_id, blocks)?;
+    let (first, last) = match (block_hashes.first(), block_hashes.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => {
+            return Err(failure(
+                FailureKind::NotFound,
+                format!("No chunk of shard {} above the tail of the database", shard_id),
+            ))
+        }
+    };
+    let from_height = chain_store.get_block_header(&first)?.height();
+    let to_height = chain_store.get_block_header(&last)?.height();
+
+    let mut apply_times = vec![];
+    let mut cpu_times = vec![];
+    let mut gas_burnt: u128 = 0;
+    let mut reads_before = ReadHistogram::default();
+    for iteration in 0..warmup_iterations + iterations {
+        let measured = iteration >= warmup_iterations;
+        if iteration == warmup_iterations {
+            reads_before = ReadHistogram::gather();
+        }
+        let started = Instant::now();
+        for &block_hash in &block_hashes {
+            let apply_started = Instant::now();
+            let cpu_started = process_cpu_time();
+            let (_, result) = apply_block(block_hash, shard_id, &runtime, &mut chain_store);
+            let cpu_time = process_cpu_time().saturating_sub(cpu_started);
+            if measured {
+                apply_times.push(apply_started.elapsed().as_secs_f64() * 1000.);
+                cpu_times.push(cpu_time.as_secs_f64() * 1000.);
+                gas_burnt += result.total_gas_burnt as u128;
+            }
+        }
+        eprintln!(
+            "Iteration {}{}: applied {} chunks in {:.1?}",
+            iteration + 1,
+            if measured { "" } else { " (warm-up)" },
+            block_hashes.len(),
+            started.elapsed()
+        );
+    }
+    let reads = ReadHistogram::gather().since(&reads_before);
+
+    let apply_time = Percentiles::of(apply_times);
+    let budget_ms = budget.as_millis() as u64;
+    output.print(&ChunkApplyBenchmark {
+        shard_id,
+        chunks: block_hashes.len(),
+        from_height,
+        to_height,
+        warmup_iterations,
+        iterations,
+        within_budget: apply_time.p95 <= budget_ms as f64,
+        apply_time,
+        cpu_time: Percentiles::of(cpu_times),
+        read_latency: reads.latency(),
+        mean_gas_burnt: (gas_burnt / (block_hashes.len() * iterations) as u128) as Gas,
+        budget_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let values: Vec<f64> = (1..=100).rev().map(f64::from).collect();
+        assert_eq!(
+            Percentiles::of(values),
+            Percentiles { p50: 50., p95: 95., p99: 99., max: 100. }
+        );
+        assert_eq!(Percentiles::of(vec![7.]), Percentiles { p50: 7., p95: 7., p99: 7., max: 7. });
+        assert_eq!(percentile(&[], 0.5), 0.);
+    }
+
+    #[test]
+    fn test_read_latency() {
+        let before = ReadHistogram { buckets: vec![(0.25, 10), (0.5, 10), (1., 10)], count: 10 };
+        let after = ReadHistogram { buckets: vec![(0.25, 60), (0.5, 105), (1., 108)], count: 110 };
+        let reads = after.since(&before);
+        assert_eq!(reads.count, 100);
+        assert_eq!(
+            reads.latency(),
+            ReadLatency { reads: 100, p50: Some(250.), p95: Some(500.), p99: None }
+        );
+        assert_eq!(ReadHistogram::default().latency().p50, None);
+    }
+}
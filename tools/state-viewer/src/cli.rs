@@ -1,16 +1,69 @@
+use crate::account_churn::account_churn;
+use crate::apply_block_chunks::apply_block_chunks;
+use crate::balance_report::{balance_report, bucket_bounds, DEFAULT_HISTOGRAM_BUCKETS};
+use crate::block_artifacts::block_artifacts;
+use crate::check_roots::check_roots;
+use crate::check_signatures::check_signatures;
+use crate::chunk_apply_benchmark::{chunk_apply_benchmark, is_store_in_use};
 use crate::commands::*;
+use crate::copy_shard_state::copy_shard_state;
+use crate::data_source::{ChainDataSource, RpcDataSource, StoreDataSource};
+use crate::epoch_analysis::epoch_analysis;
 use crate::epoch_info;
-use crate::rocksdb_stats::get_rocksdb_stats;
+use crate::epoch_sync;
+use crate::export_parquet::{export_parquet, ExportEntity};
+use crate::extract_transactions::extract_transactions;
+use crate::gc_forecast::gc_forecast;
+use crate::key_audit::{key_audit, KeyAuditScope};
+use crate::locate_key::{locate_key, parse_column};
+use crate::misbehavior::scan_misbehavior;
+use crate::output::{failure, FailureKind, Output, OutputFormat};
+use crate::producer_versions::producer_versions;
+use crate::prune_history::prune_history;
+use crate::receipt_stats::receipt_stats;
+use crate::reconcile_stake::reconcile_stake;
+use crate::replay_compare::{local_replayer, replay_compare, serve, RemoteReplayer};
+use crate::rocksdb_stats::{get_rocksdb_stats, watch_rocksdb_stats};
+use crate::runtime_params::runtime_params;
+use crate::sampling::{Sampler, SamplingArgs};
+use crate::simulate_resharding::simulate_resharding;
+use crate::state_changes_retention::state_changes_retention;
+use crate::state_fingerprint::state_fingerprint;
+use crate::trie_gc::trie_gc;
+use crate::tx_index::{build_tx_index, query_tx_index};
+use crate::validate_headers::validate_headers;
+use crate::verify_dump::verify_dump;
+use crate::watch_head::watch_head;
+use crate::witness::WitnessLimits;
+use anyhow::Context;
 use clap::{Args, Parser, Subcommand};
+use near_chain::{ChainStore, ChainStoreAccess};
 use near_chain_configs::GenesisValidationMode;
+use near_crypto::PublicKey;
 use near_primitives::account::id::AccountId;
+use near_primitives::account_pattern::AccountPattern;
 use near_primitives::hash::CryptoHash;
 use near_primitives::sharding::ChunkHash;
-use near_primitives::types::{BlockHeight, ShardId};
-use near_store::{create_store_with_config, Store};
+use near_primitives::types::{Balance, BlockHeight, EpochHeight, NumBlocks, ShardId};
+use near_store::{try_create_store_with_config, Store, StoreConfig};
 use nearcore::{get_store_path, load_config, NearConfig};
+use node_runtime::replay::ReceiptRecord;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
+
+/// Where the commands read the chain data from.
+#[derive(Args)]
+pub struct DataSourceArgs {
+    /// Reads the data from the JSON RPC of the node at this URL rather than
+    /// from the local database.  Supported by `receipts`, `chunks`,
+    /// `dump-code` and `dump-account-storage`.
+    #[clap(long)]
+    remote_rpc: Option<String>,
+    /// Maximum number of requests per second sent to `--remote-rpc`.
+    #[clap(long, default_value = "10")]
+    remote_rpc_max_qps: u32,
+}
 
 #[derive(Subcommand)]
 #[clap(subcommand_required = true, arg_required_else_help = true)]
@@ -46,6 +99,9 @@ pub enum StateViewerSubCommand {
     /// Print `EpochInfo` of an epoch given by `--epoch_id` or by `--epoch_height`.
     #[clap(alias = "epoch_info")]
     EpochInfo(EpochInfoCmd),
+    /// Export a proof of the header chain or bootstrap a fresh database from one.
+    #[clap(alias = "epoch_sync")]
+    EpochSync(EpochSyncCmd),
     /// Dump stats for the RocksDB storage.
     #[clap(name = "rocksdb-stats", alias = "rocksdb_stats")]
     RocksDBStats(RocksDBStatsCmd),
@@ -64,45 +120,358 @@ pub enum StateViewerSubCommand {
     /// even if it's not included in any block on disk
     #[clap(alias = "apply_receipt")]
     ApplyReceipt(ApplyReceiptCmd),
+    /// Apply blocks at a range of heights for a single shard both locally and
+    /// in another neard binary, and report the first difference as JSON.
+    #[clap(alias = "replay_compare")]
+    ReplayCompare(ReplayCompareCmd),
+    /// Serve `replay-compare` requests read from stdin.
+    #[clap(hide = true)]
+    ReplayServer,
+    /// Re-execute receipts recorded with the `record_receipts_dir` config
+    /// option and check that they produce the recorded results.
+    #[clap(alias = "runtime_replay")]
+    RuntimeReplay(RuntimeReplayCmd),
+    /// Print the outcomes of the most recently processed blocks, as recorded
+    /// by the node, e.g. to find out what it was doing before a crash.
+    #[clap(alias = "recent_processing")]
+    RecentProcessing(RecentProcessingCmd),
+    /// Write the transactions included in a range of blocks to a file, to be
+    /// replayed on a forknet.
+    #[clap(alias = "extract_transactions")]
+    ExtractTransactions(ExtractTransactionsCmd),
+    /// Follow the head of the chain of a running node, printing a line of JSON
+    /// for every new head block.
+    #[clap(alias = "watch_head")]
+    WatchHead(WatchHeadCmd),
+    /// Recompute the merkle roots of a range of blocks from the stored data,
+    /// without executing anything, and report the ones which don't match.
+    #[clap(alias = "check_roots")]
+    CheckRoots(CheckRootsCmd),
+    /// Remove the blocks, chunks and state older than the given number of
+    /// epochs from the database of a stopped node, e.g. to turn an archival
+    /// node into a regular one.
+    #[clap(alias = "prune_history")]
+    PruneHistory(PruneHistoryCmd),
+    /// Copy the state of a shard at a block, with the chain data needed to
+    /// look at it, into another database.
+    #[clap(alias = "copy_shard_state")]
+    CopyShardState(CopyShardStateCmd),
+    /// Build an index of the transactions and receipts of a range of blocks
+    /// by signer, public key and receiver, in a database of its own.
+    #[clap(alias = "build_tx_index")]
+    BuildTxIndex(BuildTxIndexCmd),
+    /// Look up the transactions and receipts of an account or public key in
+    /// an index built with `build-tx-index`.
+    #[clap(alias = "query_tx_index")]
+    QueryTxIndex(QueryTxIndexCmd),
+    /// List the accounts created and deleted in a range of blocks.
+    #[clap(alias = "account_churn")]
+    AccountChurn(AccountChurnCmd),
+    /// Look for conflicting blocks, chunks and approvals signed by the same
+    /// validator in a range of heights.
+    Misbehavior(MisbehaviorCmd),
+    /// Forecast what garbage collection deletes, per column, from the data of
+    /// the blocks in the database.
+    GcForecast(GcForecastCmd),
+    /// Audit the function call access keys of a shard or an account, grouped
+    /// by the contract they allow calling.
+    KeyAudit(KeyAuditCmd),
+    /// Apply all the chunks of the block at some height and check the
+    /// receipts they send against the ones the next block delivers.
+    ApplyBlockChunks(ApplyBlockChunksCmd),
+    /// Verifies the signatures of the block producers, the approvals and their
+    /// stake, and the signatures of the chunk producers of the blocks of the
+    /// canonical chain at a range of heights, the way nodes validate blocks.
+    CheckSignatures(CheckSignaturesCmd),
+    /// Compares the records and the total supply of a genesis made by
+    /// `dump-state` with the state of the chain it was dumped from.
+    VerifyDump(VerifyDumpCmd),
+    /// Re-validates the canonical header chain from the genesis or a height:
+    /// the links between the headers, their epochs, the signatures of their
+    /// block producers and their approvals.
+    ValidateHeaders(ValidateHeadersCmd),
+    /// Write the blocks and chunks produced and expected, the online ratio,
+    /// the stake and the kickout of every validator of a range of epochs to a
+    /// CSV file, and their totals per validator to another one.
+    EpochAnalysis(EpochAnalysisCmd),
+    /// Prints a hash of the state and outcome roots of the shards after a
+    /// block, for comparing the state of nodes.
+    StateFingerprint(StateFingerprintCmd),
+    /// Report the liquid and locked balances of the accounts in the state: the
+    /// totals, a histogram and the accounts with the most of each.
+    BalanceReport(BalanceReportCmd),
+    /// Export blocks, transactions, receipts, outcomes or accounts to Parquet
+    /// files partitioned by heights, for analytics.
+    ExportParquet(ExportParquetCmd),
+    /// Compare the stakes of the validators and proposals of an epoch with the
+    /// ones expected in a CSV file, e.g. by a staking dashboard.
+    ReconcileStake(ReconcileStakeCmd),
+    /// Summarize the protocol versions and builds of the producers of the last
+    /// blocks, by number of blocks and by stake, to follow an upgrade.
+    ProducerVersions(ProducerVersionsCmd),
+    /// Rehearse the split of a shard by a resharding on the state at a height,
+    /// in a temporary store, and verify the states of the new shards.
+    SimulateResharding(SimulateReshardingCmd),
+    /// Print the runtime parameters in effect in the epoch of a block, as
+    /// resolved for its protocol version by the node.
+    RuntimeParams(RuntimeParamsCmd),
+    /// List the SST files of RocksDB which may contain a key, or the keys with
+    /// a prefix, of a column, and whether the key is present.
+    LocateKey(LocateKeyCmd),
+    /// Find the trie nodes unreachable from the state roots garbage collection
+    /// keeps, and remove them with `--execute`.  Needs the node to be stopped.
+    TrieGc(TrieGcCmd),
+    /// Print the lowest height whose state changes are kept, which the
+    /// changes RPC methods serve, and the one garbage collection moves it to.
+    StateChangesRetention(StateChangesRetentionCmd),
+    /// Print the distributions of the sizes, function call arguments, gas and
+    /// deposits of the receipts of a range of blocks, and their numbers by the
+    /// kinds of their actions.
+    #[clap(alias = "receipt_stats")]
+    ReceiptStats(ReceiptStatsCmd),
+    /// List the entries the database has for a block in the columns keyed by
+    /// its hash, height or shards, with their sizes, and explain the missing
+    /// ones.
+    #[clap(alias = "block_artifacts")]
+    BlockArtifacts(BlockArtifactsCmd),
 }
 
 impl StateViewerSubCommand {
-    pub fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode, readwrite: bool) {
+    /// Whether the subcommand modifies the database.
+    ///
+    /// Such subcommands get a read-write store even without `--readwrite`.
+    /// All other subcommands run over a database opened in read-only mode
+    /// unless `--readwrite` is given.
+    fn needs_write(&self) -> bool {
+        match self {
+            StateViewerSubCommand::EpochSync(cmd) => cmd.action.needs_write(),
+            StateViewerSubCommand::PruneHistory(cmd) => !cmd.dry_run,
+            StateViewerSubCommand::Misbehavior(cmd) => cmd.save,
+            // Even a dry run, so that it fails on a running node.
+            StateViewerSubCommand::TrieGc(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The values of `--format` the subcommand supports, the others are
+    /// refused rather than ignored.
+    fn output_formats(&self) -> &'static [OutputFormat] {
+        match self {
+            StateViewerSubCommand::AccountChurn(_) | StateViewerSubCommand::KeyAudit(_) => {
+                &[OutputFormat::Text, OutputFormat::Json, OutputFormat::Csv]
+            }
+            StateViewerSubCommand::DumpState(_)
+            | StateViewerSubCommand::ViewChain(_)
+            | StateViewerSubCommand::DumpCode(_)
+            | StateViewerSubCommand::DumpAccountStorage(_)
+            | StateViewerSubCommand::EpochInfo(_)
+            | StateViewerSubCommand::RocksDBStats(_)
+            | StateViewerSubCommand::Receipts(_)
+            | StateViewerSubCommand::Chunks(_)
+            | StateViewerSubCommand::ApplyChunk(_)
+            | StateViewerSubCommand::ReplayCompare(_)
+            | StateViewerSubCommand::Misbehavior(_)
+            | StateViewerSubCommand::GcForecast(_)
+            | StateViewerSubCommand::StateFingerprint(_)
+            | StateViewerSubCommand::BalanceReport(_)
+            | StateViewerSubCommand::ExportParquet(_)
+            | StateViewerSubCommand::ReconcileStake(_)
+            | StateViewerSubCommand::ProducerVersions(_)
+            | StateViewerSubCommand::SimulateResharding(_)
+            | StateViewerSubCommand::RuntimeParams(_)
+            | StateViewerSubCommand::LocateKey(_)
+            | StateViewerSubCommand::TrieGc(_)
+            | StateViewerSubCommand::StateChangesRetention(_)
+            | StateViewerSubCommand::ReceiptStats(_)
+            | StateViewerSubCommand::BlockArtifacts(_) => &[OutputFormat::Text, OutputFormat::Json],
+            _ => &[OutputFormat::Text],
+        }
+    }
+
+    pub fn run(
+        self,
+        home_dir: &Path,
+        genesis_validation: GenesisValidationMode,
+        readwrite: bool,
+        data_source: DataSourceArgs,
+        format: OutputFormat,
+    ) {
+        let output = Output::new(format);
+        if let Err(err) =
+            self.run_with_output(home_dir, genesis_validation, readwrite, data_source, &output)
+        {
+            output.fail(err);
+        }
+    }
+
+    fn run_with_output(
+        self,
+        home_dir: &Path,
+        genesis_validation: GenesisValidationMode,
+        readwrite: bool,
+        data_source: DataSourceArgs,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        output.check_format(self.output_formats())?;
+        if let Some(url) = &data_source.remote_rpc {
+            let source = RpcDataSource::new(url, data_source.remote_rpc_max_qps);
+            return match self {
+                StateViewerSubCommand::Receipts(cmd) => cmd.run(&source, output),
+                StateViewerSubCommand::Chunks(cmd) => cmd.run(&source, output),
+                StateViewerSubCommand::DumpCode(cmd) => cmd.run(&source, output),
+                StateViewerSubCommand::DumpAccountStorage(cmd) => cmd.run(&source, output),
+                _ => Err(failure(
+                    FailureKind::InvalidArgument,
+                    "This command requires local store, it can't use --remote-rpc",
+                )),
+            };
+        }
         let near_config = load_config(home_dir, genesis_validation)
-            .unwrap_or_else(|e| panic!("Error loading config: {:#}", e));
+            .map_err(|err| anyhow::anyhow!("Error loading config: {:#}", err))?;
         let store_path = get_store_path(home_dir);
-        let store_config = &near_config.config.store.clone().with_read_only(!readwrite);
-        let store = create_store_with_config(&store_path, store_config);
+        let store = if readwrite || self.needs_write() {
+            open_read_write_store(&store_path, &near_config.config.store)?
+        } else {
+            open_read_only_store(&store_path, &near_config.config.store)?
+        };
         match self {
-            StateViewerSubCommand::Peers => peers(store),
-            StateViewerSubCommand::State => state(home_dir, near_config, store),
-            StateViewerSubCommand::DumpState(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::Peers => {
+                peers(store);
+                Ok(())
+            }
+            StateViewerSubCommand::State => {
+                state(home_dir, near_config, store);
+                Ok(())
+            }
+            StateViewerSubCommand::DumpState(cmd) => cmd.run(home_dir, near_config, store, output),
             StateViewerSubCommand::DumpStateRedis(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::Chain(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::Replay(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::ApplyRange(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::Apply(cmd) => cmd.run(home_dir, near_config, store),
-            StateViewerSubCommand::ViewChain(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::ViewChain(cmd) => cmd.run(near_config, store, output),
             StateViewerSubCommand::CheckBlock => check_block_chunk_existence(store, near_config),
-            StateViewerSubCommand::DumpCode(cmd) => cmd.run(home_dir, near_config, store),
-            StateViewerSubCommand::DumpAccountStorage(cmd) => cmd.run(home_dir, near_config, store),
-            StateViewerSubCommand::EpochInfo(cmd) => cmd.run(home_dir, near_config, store),
-            StateViewerSubCommand::RocksDBStats(cmd) => cmd.run(home_dir),
-            StateViewerSubCommand::Receipts(cmd) => cmd.run(near_config, store),
-            StateViewerSubCommand::Chunks(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::DumpCode(cmd) => {
+                cmd.run(&StoreDataSource { home_dir, near_config, store }, output)
+            }
+            StateViewerSubCommand::DumpAccountStorage(cmd) => {
+                cmd.run(&StoreDataSource { home_dir, near_config, store }, output)
+            }
+            StateViewerSubCommand::EpochInfo(cmd) => cmd.run(home_dir, near_config, store, output),
+            StateViewerSubCommand::EpochSync(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::RocksDBStats(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::Receipts(cmd) => {
+                cmd.run(&StoreDataSource { home_dir, near_config, store }, output)
+            }
+            StateViewerSubCommand::Chunks(cmd) => {
+                cmd.run(&StoreDataSource { home_dir, near_config, store }, output)
+            }
             StateViewerSubCommand::PartialChunks(cmd) => cmd.run(near_config, store),
-            StateViewerSubCommand::ApplyChunk(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::ApplyChunk(cmd) => cmd.run(home_dir, near_config, store, output),
             StateViewerSubCommand::ApplyTx(cmd) => cmd.run(home_dir, near_config, store),
             StateViewerSubCommand::ApplyReceipt(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::ReplayCompare(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::ReplayServer => replay_server(home_dir, near_config, store),
+            StateViewerSubCommand::RuntimeReplay(cmd) => cmd.run(),
+            StateViewerSubCommand::RecentProcessing(cmd) => cmd.run(store),
+            StateViewerSubCommand::ExtractTransactions(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::WatchHead(cmd) => {
+                // The database is opened again on every poll, don't keep the
+                // files of this snapshot from being removed.
+                drop(store);
+                cmd.run(home_dir, near_config)
+            }
+            StateViewerSubCommand::CheckRoots(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::PruneHistory(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::CopyShardState(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::BuildTxIndex(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::QueryTxIndex(cmd) => cmd.run(),
+            StateViewerSubCommand::AccountChurn(cmd) => cmd.run(near_config, store, output),
+            StateViewerSubCommand::Misbehavior(cmd) => cmd.run(near_config, store, output),
+            StateViewerSubCommand::GcForecast(cmd) => cmd.run(near_config, store, output),
+            StateViewerSubCommand::KeyAudit(cmd) => cmd.run(home_dir, near_config, store, output),
+            StateViewerSubCommand::ApplyBlockChunks(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::CheckSignatures(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::VerifyDump(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::ValidateHeaders(cmd) => cmd.run(home_dir, near_config, store),
+            StateViewerSubCommand::EpochAnalysis(cmd) => cmd.run(near_config, store),
+            StateViewerSubCommand::StateFingerprint(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::BalanceReport(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::ExportParquet(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::ReconcileStake(cmd) => cmd.run(near_config, store, output),
+            StateViewerSubCommand::ProducerVersions(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::SimulateResharding(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::RuntimeParams(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::LocateKey(cmd) => cmd.run(home_dir, near_config, store, output),
+            StateViewerSubCommand::TrieGc(cmd) => cmd.run(home_dir, near_config, store, output),
+            StateViewerSubCommand::StateChangesRetention(cmd) => {
+                cmd.run(home_dir, near_config, store, output)
+            }
+            StateViewerSubCommand::ReceiptStats(cmd) => cmd.run(near_config, store, output),
+            StateViewerSubCommand::BlockArtifacts(cmd) => cmd.run(near_config, store, output),
         }
     }
 }
 
+/// Opens the database in read-only mode, which a running node allows.
+///
+/// The commands get a [`Store`] rather than a [`near_store::ReadOnlyStore`]
+/// since most of them drive the runtime or the epoch manager; the database
+/// still rejects any write.
+fn open_read_only_store(store_path: &Path, store_config: &StoreConfig) -> anyhow::Result<Store> {
+    let store_config = store_config.clone().with_read_only(true);
+    try_create_store_with_config(store_path, &store_config).map_err(|err| {
+        anyhow::anyhow!("Failed to open {} in read-only mode: {}", store_path.display(), err)
+    })
+}
+
+/// Opens the database for writing, failing with a clear message if it’s locked
+/// by a running node rather than somewhere deep inside the command.
+fn open_read_write_store(store_path: &Path, store_config: &StoreConfig) -> anyhow::Result<Store> {
+    let store_config = store_config.clone().with_read_only(false);
+    try_create_store_with_config(store_path, &store_config).map_err(|err| {
+        if err.is_locked() {
+            failure(
+                FailureKind::StoreLocked,
+                format!(
+                    "Database at {} is locked, most likely by a running neard. \
+                     This command needs write access; stop the node first.",
+                    store_path.display()
+                ),
+            )
+        } else {
+            anyhow::anyhow!("Failed to open {} for writing: {}", store_path.display(), err)
+        }
+    })
+}
+
 #[derive(Parser)]
 pub struct DumpStateCmd {
-    /// Optionally, can specify at which height to dump state.
-    #[clap(long)]
+    /// Optionally, the height of the block on the canonical chain to dump the
+    /// state of: the state its chunks were applied to, i.e. the state before
+    /// the block.  The head by default.  The state must not have been garbage
+    /// collected yet.
+    #[clap(long, conflicts_with = "block-hash")]
     height: Option<BlockHeight>,
+    /// Optionally, the hash of the block to dump the state of, see `--height`.
+    #[clap(long)]
+    block_hash: Option<CryptoHash>,
     /// Dumps state records and genesis config into separate files.
     /// Has reasonable RAM requirements.
     /// Use for chains with large state, such as mainnet and testnet.
@@ -114,11 +483,32 @@ pub struct DumpStateCmd {
     /// This is a directory if --stream is set, and a file otherwise.
     #[clap(long, parse(from_os_str))]
     file: Option<PathBuf>,
+    /// Dumps only the accounts matching one of these patterns, and the
+    /// validators: account ids, `*.<account>` for the accounts under an
+    /// account or `*` for the top-level accounts.  All accounts by default.
+    #[clap(long)]
+    accounts: Vec<AccountPattern>,
 }
 
 impl DumpStateCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
-        dump_state(self.height, self.stream, self.file, home_dir, near_config, store);
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        dump_state(
+            self.height,
+            self.block_hash,
+            self.stream,
+            self.file,
+            &self.accounts,
+            home_dir,
+            near_config,
+            store,
+            output,
+        )
     }
 }
 
@@ -130,8 +520,9 @@ pub struct DumpStateRedisCmd {
 }
 
 impl DumpStateRedisCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
         dump_state_redis(self.height, home_dir, near_config, store);
+        Ok(())
     }
 }
 
@@ -144,8 +535,9 @@ pub struct ChainCmd {
 }
 
 impl ChainCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
         print_chain(self.start_index, self.end_index, home_dir, near_config, store);
+        Ok(())
     }
 }
 
@@ -158,8 +550,9 @@ pub struct ReplayCmd {
 }
 
 impl ReplayCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
         replay_chain(self.start_index, self.end_index, home_dir, near_config, store);
+        Ok(())
     }
 }
 
@@ -179,10 +572,38 @@ pub struct ApplyRangeCmd {
     only_contracts: bool,
     #[clap(long)]
     sequential: bool,
+    /// Only apply the heights at which the block has no new chunk of the shard.
+    #[clap(long, conflicts_with = "only-new-chunks")]
+    only_missing_chunks: bool,
+    /// Only apply the heights at which the block has a new chunk of the shard.
+    #[clap(long)]
+    only_new_chunks: bool,
+    /// Apply the chunks with a trie recording the nodes read and report the
+    /// size of their state witness, in the CSV file too.
+    #[clap(long)]
+    record_witness: bool,
+    #[clap(flatten)]
+    witness_limits: WitnessLimits,
+    /// Database made with `neard database clone` into which the state computed
+    /// by applying the chunks is written, and which the following heights are
+    /// applied on top of, to see whether a divergence compounds.
+    #[clap(long, parse(from_os_str), requires = "sequential")]
+    shadow_store: Option<PathBuf>,
+    /// Jaeger agent to export spans of the run, every height and its stages
+    /// to, e.g. `localhost:6831`.  Nothing is traced without it.
+    #[clap(long)]
+    opentelemetry: Option<String>,
 }
 
 impl ApplyRangeCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        let chunk_filter = if self.only_missing_chunks {
+            ChunkPresenceFilter::OnlyMissingChunks
+        } else if self.only_new_chunks {
+            ChunkPresenceFilter::OnlyNewChunks
+        } else {
+            ChunkPresenceFilter::All
+        };
         apply_range(
             self.start_index,
             self.end_index,
@@ -194,7 +615,12 @@ impl ApplyRangeCmd {
             store,
             self.only_contracts,
             self.sequential,
+            chunk_filter,
+            self.record_witness.then(|| self.witness_limits),
+            self.shadow_store,
+            self.opentelemetry,
         );
+        Ok(())
     }
 }
 
@@ -207,8 +633,9 @@ pub struct ApplyCmd {
 }
 
 impl ApplyCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
         apply_block_at_height(self.height, self.shard_id, home_dir, near_config, store);
+        Ok(())
     }
 }
 
@@ -223,8 +650,13 @@ pub struct ViewChainCmd {
 }
 
 impl ViewChainCmd {
-    pub fn run(self, near_config: NearConfig, store: Store) {
-        view_chain(self.height, self.block, self.chunk, near_config, store);
+    pub(crate) fn run(
+        self,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        view_chain(self.height, self.block, self.chunk, near_config, store, output)
     }
 }
 
@@ -237,8 +669,10 @@ pub struct DumpCodeCmd {
 }
 
 impl DumpCodeCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
-        dump_code(self.account_id, &self.output, home_dir, near_config, store);
+    pub(crate) fn run(self, source: &dyn ChainDataSource, output: &Output) -> anyhow::Result<()> {
+        let account_id = parse_account_id(&self.account_id)?;
+        dump_code(account_id, &self.output, source, output)
+            .context("Failed to dump the contract code")
     }
 }
 
@@ -255,36 +689,140 @@ pub struct DumpAccountStorageCmd {
 }
 
 impl DumpAccountStorageCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+    pub(crate) fn run(self, source: &dyn ChainDataSource, output: &Output) -> anyhow::Result<()> {
+        let block_height = if self.block_height == "latest" {
+            None
+        } else if let Ok(height) = self.block_height.parse::<BlockHeight>() {
+            Some(height)
+        } else {
+            return Err(failure(
+                FailureKind::InvalidArgument,
+                "block_height should be either number or \"latest\"",
+            ));
+        };
+        let account_id = parse_account_id(&self.account_id)?;
         dump_account_storage(
-            self.account_id,
+            account_id,
             self.storage_key,
             &self.output,
-            self.block_height,
-            home_dir,
-            near_config,
-            store,
-        );
+            block_height,
+            source,
+            output,
+        )
+        .context("Failed to dump the contract storage")
     }
 }
+
 #[derive(Args)]
 pub struct EpochInfoCmd {
     #[clap(subcommand)]
-    epoch_selection: epoch_info::EpochSelection,
-    /// Displays kickouts of the given validator and expected and missed blocks and chunks produced.
+    epoch_selection: Option<epoch_info::EpochSelection>,
+    /// Selects the epoch with this id instead of a subcommand.
+    #[clap(long)]
+    epoch_id: Option<String>,
+    /// Selects the epoch containing the block with this hash.
+    #[clap(long)]
+    block_hash: Option<String>,
+    /// Selects the epoch containing the canonical block at this height.
     #[clap(long)]
+    block_height: Option<BlockHeight>,
+    /// Selects the epoch at this epoch height.
+    #[clap(long)]
+    epoch_height: Option<EpochHeight>,
+    /// Also displays this many epochs before the selected one, 1 if no
+    /// number is given.
+    #[clap(long, min_values = 0, default_missing_value = "1")]
+    prev: Option<EpochHeight>,
+    /// Also displays this many epochs after the selected one, 1 if no number
+    /// is given.
+    #[clap(long, min_values = 0, default_missing_value = "1")]
+    next: Option<EpochHeight>,
+    /// Displays kickouts of the given validator and expected and missed blocks and chunks produced.
+    /// A prefix or a substring of the account id of a validator of the selected epochs is enough
+    /// if only one of them matches; the command fails with exit code 6 listing the candidates if
+    /// several do.
+    #[clap(long, alias = "validator")]
     validator_account_id: Option<String>,
+    /// Takes `--validator-account-id` as the exact account id, which doesn't need to be a
+    /// validator of the selected epochs.
+    #[clap(long, requires = "validator-account-id")]
+    exact: bool,
+    /// Instead of the epochs, prints as JSON the stake-weighted distribution
+    /// of the protocol versions advertised by their block producers and the
+    /// protocol version it projects for the epoch after the next one.
+    #[clap(long)]
+    upgrade_report: bool,
 }
 
 impl EpochInfoCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+    /// The epoch selected with a flag, if any.
+    fn epoch_ref(&self) -> anyhow::Result<Option<epoch_info::EpochRef>> {
+        let mut epoch_refs = vec![];
+        if let Some(epoch_id) = &self.epoch_id {
+            epoch_refs.push(epoch_info::EpochRef::EpochId(epoch_info::parse_epoch_id(epoch_id)?));
+        }
+        if let Some(block_hash) = &self.block_hash {
+            let block_hash = epoch_info::parse_block_hash(block_hash)?;
+            epoch_refs.push(epoch_info::EpochRef::BlockHash(block_hash));
+        }
+        if let Some(block_height) = self.block_height {
+            epoch_refs.push(epoch_info::EpochRef::BlockHeight(block_height));
+        }
+        if let Some(epoch_height) = self.epoch_height {
+            epoch_refs.push(epoch_info::EpochRef::EpochHeight(epoch_height));
+        }
+        if epoch_refs.len() + self.epoch_selection.is_some() as usize != 1 {
+            anyhow::bail!(
+                "select the epoch with exactly one of a subcommand, --epoch-id, --block-hash, \
+                 --block-height and --epoch-height"
+            );
+        }
+        Ok(epoch_refs.pop())
+    }
+
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        let epoch_ref = self
+            .epoch_ref()
+            .map_err(|err| failure(FailureKind::InvalidArgument, format!("{:#}", err)))?;
+        let validator = match self.validator_account_id {
+            Some(account_id) if self.exact => {
+                Some(epoch_info::ValidatorArg::Exact(parse_account_id(&account_id)?))
+            }
+            Some(query) => Some(epoch_info::ValidatorArg::Fuzzy(query)),
+            None => None,
+        };
         print_epoch_info(
             self.epoch_selection,
-            self.validator_account_id.map(|s| AccountId::from_str(&s).unwrap()),
+            epoch_ref,
+            self.prev.unwrap_or(0),
+            self.next.unwrap_or(0),
+            validator,
+            self.upgrade_report,
             home_dir,
             near_config,
             store,
-        );
+            output,
+        )
+        .context("Failed to print the epoch info")
+    }
+}
+
+#[derive(Args)]
+pub struct EpochSyncCmd {
+    #[clap(subcommand)]
+    action: epoch_sync::EpochSyncAction,
+}
+
+impl EpochSyncCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        self.action.run(home_dir, near_config, store);
+        Ok(())
     }
 }
 
@@ -293,11 +831,32 @@ pub struct RocksDBStatsCmd {
     /// Location of the dumped Rocks DB stats.
     #[clap(long, parse(from_os_str))]
     file: Option<PathBuf>,
+    /// Instead of dumping the stats, print what changed in every column every
+    /// given number of seconds while the node keeps running.
+    #[clap(long, conflicts_with = "file")]
+    watch: Option<u64>,
+    /// With `--watch`, also append every sample to the file as a JSON line.
+    #[clap(long, parse(from_os_str), requires = "watch")]
+    log: Option<PathBuf>,
 }
 
 impl RocksDBStatsCmd {
-    pub fn run(self, home_dir: &Path) {
-        get_rocksdb_stats(home_dir, self.file).expect("Couldn't get RocksDB stats");
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        if let Some(interval) = self.watch {
+            return watch_rocksdb_stats(
+                home_dir,
+                &near_config.config.store,
+                Duration::from_secs(interval),
+                self.log.as_deref(),
+            );
+        }
+        get_rocksdb_stats(home_dir, self.file, &store, output).context("Couldn't get RocksDB stats")
     }
 }
 
@@ -308,8 +867,9 @@ pub struct ReceiptsCmd {
 }
 
 impl ReceiptsCmd {
-    pub fn run(self, near_config: NearConfig, store: Store) {
-        get_receipt(CryptoHash::from_str(&self.receipt_id).unwrap(), near_config, store)
+    pub(crate) fn run(self, source: &dyn ChainDataSource, output: &Output) -> anyhow::Result<()> {
+        let receipt_id = parse_hash("receipt id", &self.receipt_id)?;
+        get_receipt(receipt_id, source, output).context("Failed to get the receipt")
     }
 }
 
@@ -320,11 +880,12 @@ pub struct ChunksCmd {
 }
 
 impl ChunksCmd {
-    pub fn run(self, near_config: NearConfig, store: Store) {
-        let chunk_hash = ChunkHash::from(CryptoHash::from_str(&self.chunk_hash).unwrap());
-        get_chunk(chunk_hash, near_config, store)
+    pub(crate) fn run(self, source: &dyn ChainDataSource, output: &Output) -> anyhow::Result<()> {
+        let chunk_hash = ChunkHash::from(parse_hash("chunk hash", &self.chunk_hash)?);
+        get_chunk(chunk_hash, source, output).context("Failed to get the chunk")
     }
 }
+
 #[derive(Parser)]
 pub struct PartialChunksCmd {
     #[clap(long)]
@@ -332,9 +893,9 @@ pub struct PartialChunksCmd {
 }
 
 impl PartialChunksCmd {
-    pub fn run(self, near_config: NearConfig, store: Store) {
+    pub fn run(self, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
         let partial_chunk_hash =
-            ChunkHash::from(CryptoHash::from_str(&self.partial_chunk_hash).unwrap());
+            ChunkHash::from(parse_hash("partial chunk hash", &self.partial_chunk_hash)?);
         get_partial_chunk(partial_chunk_hash, near_config, store)
     }
 }
@@ -345,12 +906,25 @@ pub struct ApplyChunkCmd {
     chunk_hash: String,
     #[clap(long)]
     target_height: Option<u64>,
+    /// Apply the chunk with a trie recording the nodes read and report the
+    /// size of its state witness.
+    #[clap(long)]
+    record_witness: bool,
+    #[clap(flatten)]
+    witness_limits: WitnessLimits,
 }
 
 impl ApplyChunkCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
-        let hash = ChunkHash::from(CryptoHash::from_str(&self.chunk_hash).unwrap());
-        apply_chunk(home_dir, near_config, store, hash, self.target_height).unwrap()
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        let hash = ChunkHash::from(parse_hash("chunk hash", &self.chunk_hash)?);
+        let witness_limits = self.record_witness.then(|| self.witness_limits);
+        apply_chunk(home_dir, near_config, store, hash, self.target_height, witness_limits, output)
     }
 }
 
@@ -361,9 +935,9 @@ pub struct ApplyTxCmd {
 }
 
 impl ApplyTxCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
-        let hash = CryptoHash::from_str(&self.hash).unwrap();
-        apply_tx(home_dir, near_config, store, hash).unwrap();
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        let hash = parse_hash("transaction hash", &self.hash)?;
+        apply_tx(home_dir, near_config, store, hash)
     }
 }
 
@@ -374,8 +948,932 @@ pub struct ApplyReceiptCmd {
 }
 
 impl ApplyReceiptCmd {
-    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
-        let hash = CryptoHash::from_str(&self.hash).unwrap();
-        apply_receipt(home_dir, near_config, store, hash).unwrap();
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        let hash = parse_hash("receipt hash", &self.hash)?;
+        apply_receipt(home_dir, near_config, store, hash)
+    }
+}
+
+#[derive(Parser)]
+pub struct RecentProcessingCmd {
+    /// Print only this many of the most recent blocks.
+    #[clap(long)]
+    limit: Option<usize>,
+}
+
+impl RecentProcessingCmd {
+    pub fn run(self, store: Store) -> anyhow::Result<()> {
+        print_recent_processing(store, self.limit);
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+pub struct ExtractTransactionsCmd {
+    #[clap(long)]
+    from_height: BlockHeight,
+    #[clap(long)]
+    to_height: BlockHeight,
+    /// File the transactions are written to, the per block summary is
+    /// written next to it with a `.summary.json` suffix.
+    #[clap(long, parse(from_os_str))]
+    out: PathBuf,
+    /// Replace the public key of every transaction with this one and drop the
+    /// signatures, so that the transactions can be signed again on a forknet
+    /// where all accounts have this key.
+    #[clap(long)]
+    remap_keys: Option<PublicKey>,
+}
+
+impl ExtractTransactionsCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        extract_transactions(
+            self.from_height,
+            self.to_height,
+            &self.out,
+            self.remap_keys,
+            near_config,
+            store,
+        )
+        .context("Failed to extract transactions")
+    }
+}
+
+#[derive(Parser)]
+pub struct WatchHeadCmd {
+    /// How often the head is checked.
+    #[clap(long, default_value = "1000")]
+    interval_ms: u64,
+}
+
+impl WatchHeadCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig) -> anyhow::Result<()> {
+        let interval = Duration::from_millis(self.interval_ms);
+        watch_head(home_dir, near_config, interval).context("Failed to watch the head")
+    }
+}
+
+#[derive(Parser)]
+pub struct CheckRootsCmd {
+    #[clap(long)]
+    from_height: BlockHeight,
+    #[clap(long)]
+    to_height: BlockHeight,
+}
+
+impl CheckRootsCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        check_roots(self.from_height, self.to_height, near_config, store)
+    }
+}
+
+#[derive(Parser)]
+pub struct PruneHistoryCmd {
+    /// Number of most recent epochs to keep, like `gc_num_epochs_to_keep`.
+    #[clap(long)]
+    keep_epochs: u64,
+    /// Only print how much space would be freed in every column.  Still
+    /// needs memory for the hashes of all kept trie nodes.
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl PruneHistoryCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        prune_history(self.keep_epochs, self.dry_run, home_dir, near_config, store)
+            .context("Failed to prune history")
+    }
+}
+
+#[derive(Parser)]
+pub struct CopyShardStateCmd {
+    #[clap(long)]
+    shard_id: ShardId,
+    /// Height of the block whose post-state is copied.
+    #[clap(long)]
+    height: BlockHeight,
+    /// Database to copy into, e.g. the `data` directory of another home
+    /// directory with the same genesis.  It's created if it doesn't exist.
+    #[clap(long, parse(from_os_str))]
+    dest_db: PathBuf,
+}
+
+impl CopyShardStateCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        copy_shard_state(self.shard_id, self.height, &self.dest_db, near_config, store)
+            .context("Failed to copy the shard state")
+    }
+}
+
+#[derive(Parser)]
+pub struct BuildTxIndexCmd {
+    #[clap(long)]
+    from_height: BlockHeight,
+    #[clap(long)]
+    to_height: BlockHeight,
+    /// Directory of the index database.  It's created if it doesn't exist,
+    /// running again with the same range resumes an interrupted build.
+    #[clap(long, parse(from_os_str))]
+    out: PathBuf,
+}
+
+impl BuildTxIndexCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        build_tx_index(self.from_height, self.to_height, &self.out, near_config, store)
+            .context("Failed to build the transaction index")
+    }
+}
+
+#[derive(Parser)]
+pub struct QueryTxIndexCmd {
+    /// Directory of the index database.
+    #[clap(long, parse(from_os_str))]
+    index: PathBuf,
+    /// Account whose signed transactions and received receipts are listed.
+    #[clap(long)]
+    account_id: Option<AccountId>,
+    /// Public key whose signed transactions are listed.
+    #[clap(long)]
+    public_key: Option<PublicKey>,
+    #[clap(long, default_value = "0")]
+    from_height: BlockHeight,
+    /// Last height to list, no limit by default.
+    #[clap(long)]
+    to_height: Option<BlockHeight>,
+}
+
+impl QueryTxIndexCmd {
+    pub fn run(self) -> anyhow::Result<()> {
+        query_tx_index(
+            &self.index,
+            self.account_id.as_ref(),
+            self.public_key.as_ref(),
+            self.from_height,
+            self.to_height.unwrap_or(BlockHeight::MAX),
+        )
+        .context("Failed to query the transaction index")
+    }
+}
+
+#[derive(Parser)]
+pub struct ReplayCompareCmd {
+    /// First height to apply, the tail of the chain by default.
+    #[clap(long)]
+    start_index: Option<BlockHeight>,
+    /// Last height to apply, the head of the chain by default.
+    #[clap(long)]
+    end_index: Option<BlockHeight>,
+    #[clap(long, default_value = "0")]
+    shard_id: ShardId,
+    /// The neard binary to compare against.
+    #[clap(long, parse(from_os_str))]
+    other_binary: PathBuf,
+    /// Home directory for the other binary, same as ours by default.
+    #[clap(long, parse(from_os_str))]
+    other_home: Option<PathBuf>,
+    /// Where to write the JSON report, stdout by default.
+    #[clap(long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+impl ReplayCompareCmd {
+    /// Fails with exit code 1 if the binaries diverge, after writing the
+    /// report.
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        let chain_store =
+            ChainStore::new(store.clone(), near_config.genesis.config.genesis_height, false);
+        let start_height = match self.start_index {
+            Some(height) => height,
+            None => chain_store.tail()?,
+        };
+        let end_height = match self.end_index {
+            Some(height) => height,
+            None => chain_store.head()?.height,
+        };
+        let report = RemoteReplayer::spawn(
+            &self.other_binary,
+            self.other_home.as_deref().unwrap_or(home_dir),
+        )
+        .and_then(|mut theirs| {
+            let mut ours = local_replayer(home_dir, &near_config, store);
+            replay_compare(&mut ours, &mut theirs, start_height, end_height, self.shard_id)
+        })
+        .context("replay-compare failed")?;
+        match &self.output {
+            Some(path) => std::fs::write(path, serde_json::to_string_pretty(&report)? + "\n")?,
+            None => output.print_json(&report)?,
+        }
+        if report.divergence.is_some() {
+            anyhow::bail!("The binaries diverge");
+        }
+        Ok(())
+    }
+}
+
+fn replay_server(home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+    let mut replayer = local_replayer(home_dir, &near_config, store);
+    let stdin = std::io::stdin();
+    serve(&mut replayer, stdin.lock(), std::io::stdout()).context("replay-server failed")
+}
+
+#[derive(Parser)]
+pub struct RuntimeReplayCmd {
+    /// A receipt record file or a directory of them.
+    #[clap(parse(from_os_str))]
+    path: PathBuf,
+}
+
+impl RuntimeReplayCmd {
+    /// Fails with exit code 1 if any of the records fails to replay.
+    pub fn run(self) -> anyhow::Result<()> {
+        let paths = if self.path.is_dir() {
+            let mut paths = vec![];
+            for entry in std::fs::read_dir(&self.path)? {
+                let path = entry?.path();
+                if path.extension().map_or(false, |ext| ext == "json") {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+            paths
+        } else {
+            vec![self.path.clone()]
+        };
+        let mut failed = 0;
+        for path in &paths {
+            let result = std::fs::read(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice::<ReceiptRecord>(&data)?))
+                .and_then(|record| Ok(record.verify()?));
+            if let Err(err) = result {
+                println!("{}: {:#}", path.display(), err);
+                failed += 1;
+            }
+        }
+        println!("Replayed {} receipts, {} failed", paths.len(), failed);
+        if failed > 0 {
+            anyhow::bail!("{} of the {} receipts failed to replay", failed, paths.len());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser)]
+pub struct AccountChurnCmd {
+    #[clap(long)]
+    from_height: BlockHeight,
+    #[clap(long)]
+    to_height: BlockHeight,
+    /// Lists only the accounts matching one of these patterns: account ids,
+    /// `*.<account>` for the accounts under an account or `*` for the
+    /// top-level accounts.  All accounts by default.
+    #[clap(long)]
+    accounts: Vec<AccountPattern>,
+}
+
+impl AccountChurnCmd {
+    pub fn run(self, near_config: NearConfig, store: Store, output: &Output) -> anyhow::Result<()> {
+        account_churn(self.from_height, self.to_height, &self.accounts, near_config, store, output)
+            .context("Failed to list the account churn")
+    }
+}
+
+#[derive(Parser)]
+pub struct MisbehaviorCmd {
+    #[clap(long)]
+    from_height: BlockHeight,
+    #[clap(long)]
+    to_height: BlockHeight,
+    /// Also store the evidence found in the database, where the debug RPC of
+    /// the node serves it from.
+    #[clap(long)]
+    save: bool,
+}
+
+impl MisbehaviorCmd {
+    pub fn run(self, near_config: NearConfig, store: Store, output: &Output) -> anyhow::Result<()> {
+        scan_misbehavior(self.from_height, self.to_height, self.save, near_config, store, output)
+            .context("Failed to scan for misbehavior")
+    }
+}
+
+#[derive(Parser)]
+pub struct GcForecastCmd {}
+
+impl GcForecastCmd {
+    pub fn run(self, near_config: NearConfig, store: Store, output: &Output) -> anyhow::Result<()> {
+        gc_forecast(near_config, store, output).context("Failed to forecast garbage collection")
+    }
+}
+
+#[derive(Parser)]
+pub struct KeyAuditCmd {
+    #[clap(long, required_unless_present = "account-id")]
+    shard_id: Option<ShardId>,
+    /// Audit the keys of this account only, on its shard.
+    #[clap(long, conflicts_with = "shard-id")]
+    account_id: Option<AccountId>,
+    /// Only report the keys for this contract.
+    #[clap(long)]
+    receiver_id: Option<String>,
+    /// Audit the state before the block at this height rather than the
+    /// latest one.
+    #[clap(long)]
+    height: Option<BlockHeight>,
+    #[clap(flatten)]
+    sampling: SamplingArgs,
+}
+
+impl KeyAuditCmd {
+    pub fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        let scope = match (self.account_id, self.shard_id) {
+            (Some(account_id), _) => KeyAuditScope::Account(account_id),
+            (None, Some(shard_id)) => KeyAuditScope::Shard(shard_id),
+            (None, None) => unreachable!("clap requires one of them"),
+        };
+        let sampler = Sampler::new(&self.sampling)?;
+        key_audit(
+            scope,
+            self.receiver_id,
+            self.height,
+            &sampler,
+            home_dir,
+            near_config,
+            store,
+            output,
+        )
+        .context("Failed to audit the access keys")
+    }
+}
+
+#[derive(Parser)]
+pub struct ApplyBlockChunksCmd {
+    #[clap(long)]
+    height: BlockHeight,
+}
+
+impl ApplyBlockChunksCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        apply_block_chunks(self.height, home_dir, near_config, store)
+            .context("Failed to check the receipts of the block")
+    }
+}
+
+#[derive(Parser)]
+pub struct CheckSignaturesCmd {
+    #[clap(long)]
+    from_height: BlockHeight,
+    #[clap(long)]
+    to_height: BlockHeight,
+}
+
+impl CheckSignaturesCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        check_signatures(self.from_height, self.to_height, home_dir, near_config, store)
+    }
+}
+
+#[derive(Parser)]
+pub struct VerifyDumpCmd {
+    /// Genesis config of the dump.
+    #[clap(long, parse(from_os_str))]
+    genesis: PathBuf,
+    /// Records of the dump, if they aren't in the genesis file.
+    #[clap(long, parse(from_os_str))]
+    records: Option<PathBuf>,
+    /// Database of the chain the dump was made from, the one of the home
+    /// directory by default.
+    #[clap(long, parse(from_os_str))]
+    source_db: Option<PathBuf>,
+    /// Height the dump was made at, as passed to `dump-state --height`.
+    #[clap(long)]
+    height: BlockHeight,
+}
+
+impl VerifyDumpCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        let store = match &self.source_db {
+            Some(path) => open_read_only_store(path, &near_config.config.store)?,
+            None => store,
+        };
+        verify_dump(
+            &self.genesis,
+            self.records.as_deref(),
+            self.height,
+            home_dir,
+            near_config,
+            store,
+        )
+    }
+}
+
+#[derive(Parser)]
+pub struct ValidateHeadersCmd {
+    /// Height to start from, the genesis by default.
+    #[clap(long)]
+    from_height: Option<BlockHeight>,
+    /// Threads to verify the signatures with, one per CPU by default.
+    #[clap(long)]
+    threads: Option<usize>,
+}
+
+impl ValidateHeadersCmd {
+    pub fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        validate_headers(self.from_height, self.threads, home_dir, near_config, store)
+    }
+}
+
+#[derive(Parser)]
+pub struct EpochAnalysisCmd {
+    #[clap(long)]
+    from_epoch_height: EpochHeight,
+    #[clap(long)]
+    to_epoch_height: EpochHeight,
+    /// CSV file with a row per epoch and validator.
+    #[clap(long, parse(from_os_str))]
+    out: PathBuf,
+    /// CSV file with a row per validator, `<out>` with a `.summary.csv`
+    /// extension by default.
+    #[clap(long, parse(from_os_str))]
+    summary_out: Option<PathBuf>,
+}
+
+impl EpochAnalysisCmd {
+    pub fn run(self, near_config: NearConfig, store: Store) -> anyhow::Result<()> {
+        let summary_out =
+            self.summary_out.unwrap_or_else(|| self.out.with_extension("summary.csv"));
+        epoch_analysis(
+            self.from_epoch_height,
+            self.to_epoch_height,
+            &self.out,
+            &summary_out,
+            near_config,
+            store,
+        )
+    }
+}
+
+#[derive(Parser)]
+pub struct StateFingerprintCmd {
+    /// Height of the block, the head by default.
+    #[clap(long)]
+    height: Option<BlockHeight>,
+}
+
+impl StateFingerprintCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        state_fingerprint(self.height, home_dir, near_config, store, output)
+    }
+}
+
+#[derive(Parser)]
+pub struct BalanceReportCmd {
+    /// Report the state before the block at this height.
+    #[clap(long)]
+    height: BlockHeight,
+    /// Only report the accounts of this shard rather than of all of them.
+    #[clap(long)]
+    shard_id: Option<ShardId>,
+    /// Upper bound of a bucket of the histogram of the balances, in NEAR,
+    /// the last bucket has no bound.  May be given multiple times, in
+    /// increasing order.  From 1 to 10 million NEAR by powers of 10 by default.
+    #[clap(long)]
+    histogram_bucket: Vec<u128>,
+    /// Number of accounts listed with the largest liquid and locked balances.
+    #[clap(long, default_value = "10")]
+    top: usize,
+    #[clap(flatten)]
+    sampling: SamplingArgs,
+}
+
+impl BalanceReportCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        let buckets = match self.histogram_bucket.as_slice() {
+            [] => DEFAULT_HISTOGRAM_BUCKETS,
+            buckets => buckets,
+        };
+        let bucket_bounds = bucket_bounds(buckets)?;
+        let sampler = Sampler::new(&self.sampling)?;
+        balance_report(
+            self.height,
+            self.shard_id,
+            &bucket_bounds,
+            self.top,
+            &sampler,
+            home_dir,
+            near_config,
+            store,
+            output,
+        )
+        .context("Failed to report the balances")
+    }
+}
+
+#[derive(Parser)]
+pub struct ExportParquetCmd {
+    #[clap(long, arg_enum)]
+    what: ExportEntity,
+    /// Not used for the accounts.
+    #[clap(long)]
+    from_height: Option<BlockHeight>,
+    /// The accounts are exported from the state before the block at this
+    /// height.
+    #[clap(long)]
+    to_height: BlockHeight,
+    /// Directory the files are written to, in a subdirectory per entity.  An
+    /// export to where one was interrupted resumes it.
+    #[clap(long)]
+    out_dir: PathBuf,
+    /// Number of heights per file.
+    #[clap(long, default_value = "10000")]
+    partition_size: BlockHeight,
+}
+
+impl ExportParquetCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        export_parquet(
+            self.what,
+            self.from_height,
+            self.to_height,
+            self.partition_size,
+            &self.out_dir,
+            home_dir,
+            near_config,
+            store,
+            output,
+        )
+        .context("Failed to export to Parquet")
+    }
+}
+
+#[derive(Parser)]
+pub struct ReconcileStakeCmd {
+    #[clap(long)]
+    epoch_height: EpochHeight,
+    /// CSV file with the account id and the expected stake in yoctoNEAR on each
+    /// line, with an optional `account_id,...` header.
+    #[clap(long, parse(from_os_str))]
+    expected: PathBuf,
+    /// Largest difference in yoctoNEAR between the stakes which still counts
+    /// as a match.
+    #[clap(long, default_value = "0")]
+    tolerance: Balance,
+    /// CSV file to write the discrepancies to as well.
+    #[clap(long, parse(from_os_str))]
+    out: Option<PathBuf>,
+}
+
+impl ReconcileStakeCmd {
+    pub(crate) fn run(
+        self,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        reconcile_stake(
+            self.epoch_height,
+            &self.expected,
+            self.tolerance,
+            self.out.as_deref(),
+            near_config,
+            store,
+            output,
+        )
+    }
+}
+
+#[derive(Parser)]
+pub struct ProducerVersionsCmd {
+    /// Number of blocks up to the head to summarize.
+    #[clap(long, default_value = "1000")]
+    last_n_blocks: NumBlocks,
+}
+
+impl ProducerVersionsCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        producer_versions(self.last_n_blocks, home_dir, near_config, store, output)
+    }
+}
+
+#[derive(Parser)]
+pub struct SimulateReshardingCmd {
+    /// The shard to split.
+    #[clap(long)]
+    shard_id: ShardId,
+    /// JSON file with the shard layout after the resharding, which must split
+    /// the shard.
+    #[clap(long, parse(from_os_str))]
+    new_layout: PathBuf,
+    /// Split the state before the block at this height.
+    #[clap(long)]
+    height: BlockHeight,
+}
+
+impl SimulateReshardingCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        simulate_resharding(
+            self.shard_id,
+            &self.new_layout,
+            self.height,
+            home_dir,
+            near_config,
+            store,
+            output,
+        )
+    }
+}
+
+#[derive(Parser)]
+pub struct RuntimeParamsCmd {
+    /// Print the parameters of the epoch of the block at this height.
+    #[clap(long)]
+    height: BlockHeight,
+    /// Print only the parameters which differ from the ones of the genesis.
+    #[clap(long)]
+    diff_genesis: bool,
+}
+
+impl RuntimeParamsCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        runtime_params(self.height, self.diff_genesis, home_dir, near_config, store, output)
+    }
+}
+
+#[derive(Parser)]
+pub struct LocateKeyCmd {
+    /// The column, by its name, e.g. `State`, or its column family, e.g.
+    /// `col0`.
+    #[clap(long)]
+    column: String,
+    /// The key, in hex.
+    #[clap(long, required_unless_present = "key-prefix")]
+    key: Option<String>,
+    /// Look for all the keys with this prefix, in hex, instead.
+    #[clap(long, conflicts_with = "key")]
+    key_prefix: Option<String>,
+}
+
+impl LocateKeyCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        locate_key(
+            parse_column(&self.column)?,
+            self.key.as_deref(),
+            self.key_prefix.as_deref(),
+            home_dir,
+            &near_config.config.store,
+            &store,
+            output,
+        )
     }
 }
+
+#[derive(Parser)]
+pub struct TrieGcCmd {
+    /// Only count the unreachable trie nodes.  Still needs memory for the
+    /// hashes of all reachable ones.
+    #[clap(long, required_unless_present = "execute")]
+    dry_run: bool,
+    /// Remove the unreachable trie nodes.
+    #[clap(long, conflicts_with = "dry-run")]
+    execute: bool,
+}
+
+impl TrieGcCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        // Clap makes sure exactly one of them is set.
+        trie_gc(self.execute && !self.dry_run, home_dir, near_config, store, output)
+    }
+}
+
+#[derive(Parser)]
+pub struct StateChangesRetentionCmd {}
+
+impl StateChangesRetentionCmd {
+    pub(crate) fn run(
+        self,
+        home_dir: &Path,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        state_changes_retention(home_dir, near_config, store, output)
+    }
+}
+
+#[derive(Parser)]
+pub struct ReceiptStatsCmd {
+    #[clap(long)]
+    from_height: BlockHeight,
+    #[clap(long)]
+    to_height: BlockHeight,
+    /// Only count the receipts of the shard.
+    #[clap(long)]
+    shard_id: Option<ShardId>,
+    /// File the progress of the scan is saved to.  A scan run again with the
+    /// same file and heights resumes where it stopped.
+    #[clap(long, parse(from_os_str))]
+    state_file: Option<PathBuf>,
+}
+
+impl ReceiptStatsCmd {
+    pub(crate) fn run(
+        self,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        receipt_stats(
+            self.from_height,
+            self.to_height,
+            self.shard_id,
+            self.state_file.as_deref(),
+            near_config,
+            store,
+            output,
+        )
+        .context("Failed to compute the receipt statistics")
+    }
+}
+
+#[derive(Parser)]
+pub struct BlockArtifactsCmd {
+    #[clap(long)]
+    block_hash: CryptoHash,
+}
+
+impl BlockArtifactsCmd {
+    pub(crate) fn run(
+        self,
+        near_config: NearConfig,
+        store: Store,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        block_artifacts(self.block_hash, near_config, store, output)
+            .context("Failed to list the entries of the block")
+    }
+}
+
+/// Benchmark of the apply of the recent chunks of a shard, run as
+/// `neard benchmark chunk-apply`.
+#[derive(Parser)]
+pub struct ChunkApplyBenchmarkCmd {
+    /// Shard whose chunks are applied.
+    #[clap(long)]
+    shard_id: ShardId,
+    /// Number of the last blocks with a new chunk of the shard whose chunks
+    /// are applied.
+    #[clap(long, default_value = "100")]
+    blocks: NumBlocks,
+    /// Times the chunks are applied and measured.
+    #[clap(long, default_value = "3")]
+    iterations: usize,
+    /// Times the chunks are applied to warm up the caches before they're
+    /// measured.
+    #[clap(long, default_value = "1")]
+    warmup_iterations: usize,
+    /// Budget of the apply of a chunk, in milliseconds, which the 95th
+    /// percentile of the apply time is compared to.
+    #[clap(long, default_value = "1300")]
+    budget_ms: u64,
+    /// Database to benchmark instead of the one of the node, e.g. a clone
+    /// made by `neard database clone`.  The database of the node is only
+    /// benchmarked while the node is stopped.
+    #[clap(long, parse(from_os_str))]
+    snapshot: Option<PathBuf>,
+    /// Output of the benchmark, as text or as JSON.
+    #[clap(long, arg_enum, default_value = "text")]
+    format: OutputFormat,
+}
+
+impl ChunkApplyBenchmarkCmd {
+    pub fn run(self, home_dir: &Path, genesis_validation: GenesisValidationMode) {
+        let output = Output::new(self.format);
+        if let Err(err) = self.run_with_output(home_dir, genesis_validation, &output) {
+            output.fail(err);
+        }
+    }
+
+    fn run_with_output(
+        self,
+        home_dir: &Path,
+        genesis_validation: GenesisValidationMode,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        output.check_format(&[OutputFormat::Text, OutputFormat::Json])?;
+        let near_config = load_config(home_dir, genesis_validation)
+            .map_err(|err| anyhow::anyhow!("Error loading config: {:#}", err))?;
+        let store_path = match &self.snapshot {
+            Some(snapshot) => {
+                if !nearcore::store_path_exists(snapshot) {
+                    return Err(failure(
+                        FailureKind::NotFound,
+                        format!("{}: the snapshot doesn't exist", snapshot.display()),
+                    ));
+                }
+                snapshot.clone()
+            }
+            None => {
+                let store_path = get_store_path(home_dir);
+                if is_store_in_use(&store_path)? {
+                    return Err(failure(
+                        FailureKind::StoreLocked,
+                        format!(
+                            "Database at {} is in use, most likely by a running neard, which \
+                             would skew the benchmark.  Stop the node or benchmark a clone \
+                             made by `neard database clone` with --snapshot.",
+                            store_path.display()
+                        ),
+                    ));
+                }
+                store_path
+            }
+        };
+        let store = open_read_only_store(&store_path, &near_config.config.store)?;
+        chunk_apply_benchmark(
+            home_dir,
+            near_config,
+            store,
+            self.shard_id,
+            self.blocks,
+            self.warmup_iterations,
+            self.iterations,
+            Duration::from_millis(self.budget_ms),
+            output,
+        )
+    }
+}
+
+fn parse_account_id(account_id: &str) -> anyhow::Result<AccountId> {
+    AccountId::from_str(account_id).map_err(|err| {
+        failure(
+            FailureKind::InvalidArgument,
+            format!("invalid account id {:?}: {}", account_id, err),
+        )
+    })
+}
+
+fn parse_hash(what: &str, hash: &str) -> anyhow::Result<CryptoHash> {
+    CryptoHash::from_str(hash).map_err(|err| {
+        failure(FailureKind::InvalidArgument, format!("invalid {} {:?}: {}", what, hash, err))
+    })
+}
@@ -1,34 +1,49 @@
-use crate::apply_chain_range::apply_chain_range;
+use crate::apply_chain_range::{apply_chain_range, ChunkPresenceFilter};
+use crate::data_source::ChainDataSource;
+use crate::output::{failure, FailureKind, Output};
 use crate::state_dump::state_dump;
 use crate::state_dump::state_dump_redis;
+use crate::witness::{WitnessLimit, WitnessLimits, WitnessSize};
 use crate::{apply_chunk, epoch_info};
 use ansi_term::Color::Red;
+use anyhow::Context;
+use near_chain::apply_stage_times::ApplyChunkStageTimes;
+use near_chain::block_processing_log::read_block_processing_log;
 use near_chain::chain::collect_receipts_from_response;
 use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
 use near_chain::types::{ApplyTransactionResult, BlockHeaderInfo};
 use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
 use near_epoch_manager::EpochManager;
 use near_network::iter_peers_from_store;
+use near_o11y::tracing_subscriber::EnvFilter;
 use near_primitives::account::id::AccountId;
+use near_primitives::account_pattern::AccountPattern;
 use near_primitives::block::{Block, BlockHeader};
 use near_primitives::hash::CryptoHash;
-use near_primitives::serialize::to_base;
+use near_primitives::serialize::{to_base, u128_dec_format};
 use near_primitives::shard_layout::ShardUId;
 use near_primitives::sharding::ChunkHash;
 use near_primitives::state_record::StateRecord;
-use near_primitives::trie_key::TrieKey;
 use near_primitives::types::chunk_extra::ChunkExtra;
-use near_primitives::types::{BlockHeight, ShardId, StateRoot};
+use near_primitives::types::{
+    Balance, BlockHeight, EpochHeight, EpochId, ProtocolVersion, ShardId, StateRoot,
+};
+use near_primitives::utils::from_timestamp;
+use near_primitives::views::{
+    BlockHeaderView, ChunkApplyStageTimesView, ChunkHeaderView, ValidatorStakeView,
+};
 use near_primitives_core::types::Gas;
+use near_store::db::IS_CLONE_KEY;
 use near_store::test_utils::create_test_store;
-use near_store::{Store, TrieIterator};
+use near_store::{create_store_with_config, DBCol, Store, TrieIterator};
 use nearcore::{NearConfig, NightshadeRuntime};
-use node_runtime::adapter::ViewRuntimeAdapter;
+use serde::Serialize;
 use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::Write;
+use std::fmt::Write;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
 pub(crate) fn peers(store: Store) {
     iter_peers_from_store(store, |(peer_id, peer_info)| {
@@ -51,36 +66,153 @@ pub(crate) fn state(home_dir: &Path, near_config: NearConfig, store: Store) {
     }
 }
 
+/// Where `dump-state` saved the state of which block, and the rules the
+/// dumped genesis starts with.
+#[derive(Serialize)]
+struct StateDumpReport {
+    height: BlockHeight,
+    block_hash: CryptoHash,
+    epoch_id: EpochId,
+    /// The roots the chunks of the block were applied to, by shard.
+    state_roots: Vec<StateRoot>,
+    path: PathBuf,
+    genesis_height: BlockHeight,
+    /// The protocol version of the epoch of the block.
+    protocol_version: ProtocolVersion,
+    /// The validators of the epoch of the block.
+    validators: usize,
+}
+
+impl std::fmt::Display for StateDumpReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Saved state at {:?} @ {} into {}\n\
+             The state is the one the chunks of block {} were applied to, after its previous block.\n\
+             The genesis starts at height {} with protocol version {} and the {} validators of epoch {}.",
+            self.state_roots,
+            self.height,
+            self.path.display(),
+            self.block_hash,
+            self.genesis_height,
+            self.protocol_version,
+            self.validators,
+            self.epoch_id.0
+        )
+    }
+}
+
+/// The block `dump-state` dumps the state of: the one with the hash, the one
+/// at the height on the canonical chain, or the head.
+fn dump_state_block(
+    height: Option<BlockHeight>,
+    block_hash: Option<CryptoHash>,
+    near_config: &NearConfig,
+    store: &Store,
+) -> anyhow::Result<Block> {
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let block_hash = match (block_hash, height) {
+        (Some(block_hash), _) => block_hash,
+        (None, Some(height)) => chain_store.get_block_hash_by_height(height).map_err(|_| {
+            failure(
+                FailureKind::NotFound,
+                format!("No block at height {} on the canonical chain", height),
+            )
+        })?,
+        (None, None) => chain_store.head()?.last_block_hash,
+    };
+    if let Ok(block) = chain_store.get_block(&block_hash) {
+        return Ok(block.clone());
+    }
+    let tail = chain_store.tail()?;
+    let message = match chain_store.get_block_header(&block_hash) {
+        Ok(header) if header.height() < tail => format!(
+            "Block {} at height {} is below the tail at {}, its data and state were garbage \
+             collected; dump it from an archival node",
+            block_hash,
+            header.height(),
+            tail
+        ),
+        Ok(_) => format!("Only the header of block {} is in the database", block_hash),
+        Err(_) => format!("Block {} is not in the database", block_hash),
+    };
+    Err(failure(FailureKind::NotFound, message))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn dump_state(
     height: Option<BlockHeight>,
+    block_hash: Option<CryptoHash>,
     stream: bool,
     file: Option<PathBuf>,
+    accounts: &[AccountPattern],
     home_dir: &Path,
     near_config: NearConfig,
     store: Store,
-) {
-    let mode = match height {
-        Some(h) => LoadTrieMode::LastFinalFromHeight(h),
-        None => LoadTrieMode::Latest,
-    };
-    let (runtime, state_roots, header) =
-        load_trie_stop_at_height(store, home_dir, &near_config, mode);
-    let height = header.height();
+    output: &Output,
+) -> anyhow::Result<()> {
+    let block = dump_state_block(height, block_hash, &near_config, &store)?;
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let state_roots: Vec<StateRoot> =
+        block.chunks().iter().map(|chunk| chunk.prev_state_root()).collect();
+    for (shard_id, state_root) in state_roots.iter().enumerate() {
+        let trie = runtime.get_trie_for_shard(shard_id as ShardId, block.header().prev_hash())?;
+        trie.retrieve_root_node(state_root).map_err(|_| {
+            failure(
+                FailureKind::NotFound,
+                format!(
+                    "The state of shard {} at block {} is not in the database, it was garbage \
+                     collected or the node doesn't track the shard",
+                    shard_id,
+                    block.hash()
+                ),
+            )
+        })?;
+    }
+    let header = block.header().clone();
     let home_dir = PathBuf::from(&home_dir);
 
-    if stream {
+    let (path, new_near_config) = if stream {
         let output_dir = file.unwrap_or(home_dir.join("output"));
         let records_path = output_dir.join("records.json");
-        let new_near_config =
-            state_dump(runtime, &state_roots, header, &near_config, Some(&records_path));
-        println!("Saving state at {:?} @ {} into {}", state_roots, height, output_dir.display(),);
+        let new_near_config = state_dump(
+            runtime,
+            &state_roots,
+            header.clone(),
+            &near_config,
+            Some(&records_path),
+            accounts,
+        );
         new_near_config.save_to_dir(&output_dir);
+        (output_dir, new_near_config)
     } else {
-        let new_near_config = state_dump(runtime, &state_roots, header, &near_config, None);
+        let new_near_config =
+            state_dump(runtime, &state_roots, header.clone(), &near_config, None, accounts);
         let output_file = file.unwrap_or(home_dir.join("output.json"));
-        println!("Saving state at {:?} @ {} into {}", state_roots, height, output_file.display(),);
         new_near_config.genesis.to_file(&output_file);
-    }
+        (output_file, new_near_config)
+    };
+    let genesis_config = &new_near_config.genesis.config;
+    output.print(&StateDumpReport {
+        height: header.height(),
+        block_hash: *header.hash(),
+        epoch_id: header.epoch_id().clone(),
+        state_roots,
+        path,
+        genesis_height: genesis_config.genesis_height,
+        protocol_version: genesis_config.protocol_version,
+        validators: genesis_config.validators.len(),
+    })
 }
 
 pub(crate) fn dump_state_redis(
@@ -111,103 +243,129 @@ pub(crate) fn apply_range(
     store: Store,
     only_contracts: bool,
     sequential: bool,
+    chunk_filter: ChunkPresenceFilter,
+    witness_limits: Option<WitnessLimits>,
+    shadow_store: Option<PathBuf>,
+    opentelemetry: Option<String>,
 ) {
     let mut csv_file = csv_file.map(|filename| std::fs::File::create(filename).unwrap());
 
+    let shadow_store = shadow_store.map(|path| {
+        let shadow_store = create_store_with_config(
+            &path,
+            &near_config.config.store.clone().with_read_only(false),
+        );
+        let is_clone: bool =
+            shadow_store.get_ser(DBCol::BlockMisc, IS_CLONE_KEY).unwrap().unwrap_or_default();
+        assert!(
+            is_clone,
+            "{} isn't a clone of the database, make one with `neard database clone`",
+            path.display()
+        );
+        shadow_store
+    });
+    // Chunks are applied on the shadow state if there's one.
     let runtime = NightshadeRuntime::with_config(
         home_dir,
-        store.clone(),
+        shadow_store.clone().unwrap_or_else(|| store.clone()),
         &near_config,
         None,
         near_config.client_config.max_gas_burnt_view,
     );
-    apply_chain_range(
-        store,
-        &near_config.genesis,
-        start_index,
-        end_index,
-        shard_id,
-        runtime,
-        verbose_output,
-        csv_file.as_mut(),
-        only_contracts,
-        sequential,
-    );
+    let apply = || {
+        apply_chain_range(
+            store,
+            &near_config.genesis,
+            start_index,
+            end_index,
+            shard_id,
+            runtime,
+            verbose_output,
+            csv_file.as_mut(),
+            only_contracts,
+            sequential,
+            chunk_filter,
+            witness_limits,
+            shadow_store,
+        )
+    };
+    match opentelemetry {
+        Some(endpoint) => {
+            let filter = EnvFilter::new("info,state_viewer=debug");
+            let dispatch =
+                near_o11y::opentelemetry_dispatch("apply_chain_range", &endpoint, filter)
+                    .expect("Failed to set up the OpenTelemetry pipeline");
+            tracing::dispatcher::with_default(&dispatch, apply);
+            near_o11y::shutdown_opentelemetry();
+        }
+        None => apply(),
+    }
 }
 
-pub(crate) fn dump_code(
-    account_id: String,
-    output: &Path,
-    home_dir: &Path,
-    near_config: NearConfig,
-    store: Store,
-) {
-    let (runtime, state_roots, header) = load_trie(store, home_dir, &near_config);
-    let epoch_id = &runtime.get_epoch_id(header.hash()).unwrap();
+/// What the dump commands wrote where.
+#[derive(Serialize)]
+struct DumpReport {
+    what: String,
+    path: PathBuf,
+    bytes: usize,
+}
 
-    for (shard_id, state_root) in state_roots.iter().enumerate() {
-        let shard_uid = runtime.shard_id_to_uid(shard_id as u64, epoch_id).unwrap();
-        if let Ok(contract_code) =
-            runtime.view_contract_code(&shard_uid, *state_root, &account_id.parse().unwrap())
-        {
-            let mut file = File::create(output).unwrap();
-            file.write_all(contract_code.code()).unwrap();
-            println!("Dump contract of account {} into file {}", account_id, output.display());
-
-            std::process::exit(0);
+impl std::fmt::Display for DumpReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dump {} into file {}", self.what, self.path.display())
+    }
+}
+
+pub(crate) fn dump_code(
+    account_id: AccountId,
+    path: &Path,
+    source: &dyn ChainDataSource,
+    output: &Output,
+) -> anyhow::Result<()> {
+    match source.contract_code(&account_id)? {
+        Some(code) => {
+            fs::write(path, &code)?;
+            output.print(&DumpReport {
+                what: format!("contract of account {}", account_id),
+                path: path.to_path_buf(),
+                bytes: code.len(),
+            })
         }
+        None => Err(failure(
+            FailureKind::NotFound,
+            format!(
+                "Account {} does not exist or do not have contract deployed in all shards",
+                account_id
+            ),
+        )),
     }
-    println!(
-        "Account {} does not exist or do not have contract deployed in all shards",
-        account_id
-    );
 }
 
 pub(crate) fn dump_account_storage(
-    account_id: String,
+    account_id: AccountId,
     storage_key: String,
-    output: &Path,
-    block_height: String,
-    home_dir: &Path,
-    near_config: NearConfig,
-    store: Store,
-) {
-    let block_height = if block_height == "latest" {
-        LoadTrieMode::Latest
-    } else if let Ok(height) = block_height.parse::<u64>() {
-        LoadTrieMode::Height(height)
-    } else {
-        panic!("block_height should be either number or \"latest\"")
-    };
-    let (runtime, state_roots, header) =
-        load_trie_stop_at_height(store, home_dir, &near_config, block_height);
-    for (shard_id, state_root) in state_roots.iter().enumerate() {
-        let trie = runtime.get_trie_for_shard(shard_id as u64, header.prev_hash()).unwrap();
-        let key = TrieKey::ContractData {
-            account_id: account_id.parse().unwrap(),
-            key: storage_key.as_bytes().to_vec(),
-        };
-        let item = trie.get(state_root, &key.to_vec());
-        let value = item.unwrap();
-        if let Some(value) = value {
-            let record = StateRecord::from_raw_key_value(key.to_vec(), value).unwrap();
-            match record {
-                StateRecord::Data { account_id: _, data_key: _, value } => {
-                    fs::write(output, &value).unwrap();
-                    println!(
-                        "Dump contract storage under key {} of account {} into file {}",
-                        storage_key,
-                        account_id,
-                        output.display()
-                    );
-                    std::process::exit(0);
-                }
-                _ => unreachable!(),
-            }
+    path: &Path,
+    block_height: Option<BlockHeight>,
+    source: &dyn ChainDataSource,
+    output: &Output,
+) -> anyhow::Result<()> {
+    match source.contract_data(&account_id, storage_key.as_bytes(), block_height)? {
+        Some(value) => {
+            fs::write(path, &value)?;
+            output.print(&DumpReport {
+                what: format!(
+                    "contract storage under key {} of account {}",
+                    storage_key, account_id
+                ),
+                path: path.to_path_buf(),
+                bytes: value.len(),
+            })
         }
+        None => Err(failure(
+            FailureKind::NotFound,
+            format!("Storage under key {} of account {} not found", storage_key, account_id),
+        )),
     }
-    println!("Storage under key {} of account {} not found", storage_key, account_id);
-    std::process::exit(1);
 }
 
 pub(crate) fn print_chain(
@@ -491,62 +649,136 @@ pub(crate) fn apply_block_at_height(
     );
 }
 
+/// What `view-chain` prints in the JSON format.
+#[derive(Serialize)]
+struct ChainView {
+    /// The head, if no height was given.
+    head: Option<TipView>,
+    block_height: BlockHeight,
+    block_hash: CryptoHash,
+    chunk_extras: Vec<ChunkExtraView>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block: Option<BlockHeaderView>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<ChunkSummary>,
+}
+
+#[derive(Serialize)]
+struct TipView {
+    height: BlockHeight,
+    last_block_hash: CryptoHash,
+    prev_block_hash: CryptoHash,
+}
+
+#[derive(Serialize)]
+struct ChunkExtraView {
+    shard_id: ShardId,
+    state_root: StateRoot,
+    outcome_root: CryptoHash,
+    validator_proposals: Vec<ValidatorStakeView>,
+    gas_used: Gas,
+    gas_limit: Gas,
+    #[serde(with = "u128_dec_format")]
+    balance_burnt: Balance,
+}
+
+impl ChunkExtraView {
+    fn new(shard_id: ShardId, chunk_extra: &ChunkExtra) -> Self {
+        Self {
+            shard_id,
+            state_root: *chunk_extra.state_root(),
+            outcome_root: *chunk_extra.outcome_root(),
+            validator_proposals: chunk_extra
+                .validator_proposals()
+                .map(ValidatorStakeView::from)
+                .collect(),
+            gas_used: chunk_extra.gas_used(),
+            gas_limit: chunk_extra.gas_limit(),
+            balance_burnt: chunk_extra.balance_burnt(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChunkSummary {
+    shard_id: ShardId,
+    header: ChunkHeaderView,
+    num_transactions: usize,
+    num_receipts: usize,
+}
+
 pub(crate) fn view_chain(
     height: Option<BlockHeight>,
     view_block: bool,
     view_chunks: bool,
     near_config: NearConfig,
     store: Store,
-) {
+    output: &Output,
+) -> anyhow::Result<()> {
     let mut chain_store = ChainStore::new(
         store.clone(),
         near_config.genesis.config.genesis_height,
         !near_config.client_config.archive,
     );
-    let block = {
-        match height {
-            Some(h) => {
-                let block_hash =
-                    chain_store.get_block_hash_by_height(h).expect("Block does not exist");
-                chain_store.get_block(&block_hash).unwrap().clone()
-            }
-            None => {
-                let head = chain_store.head().unwrap();
-                chain_store.get_block(&head.last_block_hash).unwrap().clone()
-            }
+    let head = chain_store.head()?;
+    let block = match height {
+        Some(h) => {
+            let block_hash = chain_store
+                .get_block_hash_by_height(h)
+                .with_context(|| format!("Block at height {} does not exist", h))?;
+            chain_store.get_block(&block_hash)?.clone()
         }
+        None => chain_store.get_block(&head.last_block_hash)?.clone(),
     };
     let epoch_manager =
         EpochManager::new_from_genesis_config(store.clone(), &near_config.genesis.config)
-            .expect("Failed to start Epoch Manager");
-    let shard_layout = epoch_manager.get_shard_layout(block.header().epoch_id()).unwrap();
+            .context("Failed to start Epoch Manager")?;
+    let shard_layout = epoch_manager.get_shard_layout(block.header().epoch_id())?;
 
     let mut chunk_extras = vec![];
     let mut chunks = vec![];
     for (i, chunk_header) in block.chunks().iter().enumerate() {
         if chunk_header.height_included() == block.header().height() {
             let shard_uid = ShardUId::from_shard_id_and_layout(i as ShardId, shard_layout);
-            chunk_extras
-                .push((i, chain_store.get_chunk_extra(block.hash(), &shard_uid).unwrap().clone()));
-            chunks.push((i, chain_store.get_chunk(&chunk_header.chunk_hash()).unwrap().clone()));
+            chunk_extras.push((i, chain_store.get_chunk_extra(block.hash(), &shard_uid)?.clone()));
+            chunks.push((i, chain_store.get_chunk(&chunk_header.chunk_hash())?.clone()));
         }
     }
-    let chunk_extras = block
-        .chunks()
-        .iter()
-        .enumerate()
-        .filter_map(|(i, chunk_header)| {
-            if chunk_header.height_included() == block.header().height() {
-                let shard_uid = ShardUId::from_shard_id_and_layout(i as ShardId, shard_layout);
-                Some((i, chain_store.get_chunk_extra(block.hash(), &shard_uid).unwrap().clone()))
+
+    if output.is_json() {
+        let view = ChainView {
+            head: height.is_none().then(|| TipView {
+                height: head.height,
+                last_block_hash: head.last_block_hash,
+                prev_block_hash: head.prev_block_hash,
+            }),
+            block_height: block.header().height(),
+            block_hash: *block.hash(),
+            chunk_extras: chunk_extras
+                .iter()
+                .map(|(shard_id, chunk_extra)| {
+                    ChunkExtraView::new(*shard_id as ShardId, chunk_extra)
+                })
+                .collect(),
+            block: view_block.then(|| block.header().clone().into()),
+            chunks: if view_chunks {
+                chunks
+                    .iter()
+                    .map(|(shard_id, chunk)| ChunkSummary {
+                        shard_id: *shard_id as ShardId,
+                        header: chunk.cloned_header().into(),
+                        num_transactions: chunk.transactions().len(),
+                        num_receipts: chunk.receipts().len(),
+                    })
+                    .collect()
             } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
+                vec![]
+            },
+        };
+        return output.print_json(&view);
+    }
 
     if height.is_none() {
-        let head = chain_store.head().unwrap();
         println!("head: {:#?}", head);
     } else {
         println!("block height {}, hash {}", block.header().height(), block.hash());
@@ -563,42 +795,86 @@ pub(crate) fn view_chain(
             println!("shard {}, chunk: {:#?}", shard_id, chunk);
         }
     }
+    Ok(())
 }
 
-pub(crate) fn check_block_chunk_existence(store: Store, near_config: NearConfig) {
+pub(crate) fn check_block_chunk_existence(
+    store: Store,
+    near_config: NearConfig,
+) -> anyhow::Result<()> {
     let genesis_height = near_config.genesis.config.genesis_height;
     let mut chain_store =
         ChainStore::new(store.clone(), genesis_height, !near_config.client_config.archive);
-    let head = chain_store.head().unwrap();
-    let mut cur_block = chain_store.get_block(&head.last_block_hash).unwrap().clone();
+    let head = chain_store.head()?;
+    let mut cur_block = chain_store.get_block(&head.last_block_hash)?.clone();
     while cur_block.header().height() > genesis_height {
         for chunk_header in cur_block.chunks().iter() {
             if chunk_header.height_included() == cur_block.header().height() {
                 if let Err(_) = chain_store.get_chunk(&chunk_header.chunk_hash()) {
-                    panic!(
-                        "chunk {:?} cannot be found in storage, last block {:?}",
-                        chunk_header, cur_block
-                    );
+                    return Err(failure(
+                        FailureKind::Corruption,
+                        format!(
+                            "chunk {:?} cannot be found in storage, last block {:?}",
+                            chunk_header, cur_block
+                        ),
+                    ));
                 }
             }
         }
         cur_block = match chain_store.get_block(cur_block.header().prev_hash()) {
             Ok(b) => b.clone(),
             Err(_) => {
-                panic!("last block is {:?}", cur_block);
+                return Err(failure(
+                    FailureKind::Corruption,
+                    format!("last block is {:?}", cur_block),
+                ));
             }
         }
     }
     println!("Block check succeed");
+    Ok(())
+}
+
+pub(crate) fn print_recent_processing(store: Store, limit: Option<usize>) {
+    let records = read_block_processing_log(&store).unwrap();
+    if records.is_empty() {
+        println!("No blocks recorded, see the block_processing_log_size config option");
+        return;
+    }
+    for record in records.into_iter().take(limit.unwrap_or(usize::MAX)) {
+        let shards = record
+            .shard_apply_time_micros
+            .iter()
+            .map(|(shard_id, time)| format!("{}: {}us", shard_id, time))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{} #{} {} took {}us [{}] {:?}",
+            from_timestamp(record.timestamp).to_rfc3339(),
+            record.height,
+            record.hash,
+            record.processing_time_micros,
+            shards,
+            record.outcome,
+        );
+    }
 }
 
+/// Prints the epochs of the selection, or the epoch referenced with `prev`
+/// epochs before it and `next` epochs after it.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn print_epoch_info(
-    epoch_selection: epoch_info::EpochSelection,
-    validator_account_id: Option<AccountId>,
+    epoch_selection: Option<epoch_info::EpochSelection>,
+    epoch_ref: Option<epoch_info::EpochRef>,
+    prev: EpochHeight,
+    next: EpochHeight,
+    validator: Option<epoch_info::ValidatorArg>,
+    upgrade_report: bool,
     home_dir: &Path,
     near_config: NearConfig,
     store: Store,
-) {
+    output: &Output,
+) -> anyhow::Result<()> {
     let genesis_height = near_config.genesis.config.genesis_height;
     let mut chain_store =
         ChainStore::new(store.clone(), genesis_height, !near_config.client_config.archive);
@@ -613,52 +889,103 @@ pub(crate) fn print_epoch_info(
         near_config.client_config.max_gas_burnt_view,
     ));
 
+    let epoch_ref = match (epoch_ref, &epoch_selection) {
+        (Some(epoch_ref), _) => Some(epoch_ref),
+        (None, Some(epoch_selection)) => epoch_selection.as_epoch_ref()?,
+        (None, None) => None,
+    };
+    let epoch_ids = match (epoch_ref, epoch_selection) {
+        (Some(epoch_ref), _) => {
+            let (epoch_ids, epoch_height) = epoch_info::resolve_epoch(
+                &epoch_ref,
+                &store,
+                &mut chain_store,
+                &mut epoch_manager,
+            )?;
+            let names: Vec<String> =
+                epoch_ids.iter().map(|epoch_id| epoch_id.0.to_string()).collect();
+            eprintln!(
+                "Selected {}, which is epoch {} at epoch height {}",
+                epoch_ref,
+                names.join(", "),
+                epoch_height
+            );
+            if prev == 0 && next == 0 {
+                epoch_ids
+            } else {
+                epoch_info::get_adjacent_epoch_ids(epoch_height, prev, next, store)
+            }
+        }
+        (None, Some(epoch_selection)) => {
+            if prev > 0 || next > 0 {
+                anyhow::bail!("--prev and --next need a single epoch to be selected");
+            }
+            epoch_info::get_epoch_ids(epoch_selection, store, &mut chain_store, &mut epoch_manager)
+        }
+        (None, None) => anyhow::bail!("no epoch selected"),
+    };
+    let validator_account_id = validator
+        .map(|validator| epoch_info::resolve_validator(validator, &epoch_ids, &mut epoch_manager))
+        .transpose()?;
+
     epoch_info::print_epoch_info(
-        epoch_selection,
+        epoch_ids,
         validator_account_id,
-        store,
+        upgrade_report,
         &mut chain_store,
         &mut epoch_manager,
         runtime_adapter,
-    );
+        output,
+    )
 }
 
-pub(crate) fn get_receipt(receipt_id: CryptoHash, near_config: NearConfig, store: Store) {
-    let mut chain_store = ChainStore::new(
-        store.clone(),
-        near_config.genesis.config.genesis_height,
-        !near_config.client_config.archive,
-    );
-    let receipt = chain_store.get_receipt(&receipt_id);
+pub(crate) fn get_receipt(
+    receipt_id: CryptoHash,
+    source: &dyn ChainDataSource,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let receipt = source.receipt(&receipt_id)?.ok_or_else(|| {
+        failure(FailureKind::NotFound, format!("Receipt {} not found", receipt_id))
+    })?;
+    if output.is_json() {
+        return output.print_json(&receipt);
+    }
     println!("Receipt: {:#?}", receipt);
+    Ok(())
 }
 
-pub(crate) fn get_chunk(chunk_hash: ChunkHash, near_config: NearConfig, store: Store) {
-    let mut chain_store = ChainStore::new(
-        store.clone(),
-        near_config.genesis.config.genesis_height,
-        !near_config.client_config.archive,
-    );
-    let chunk = chain_store.get_chunk(&chunk_hash);
+pub(crate) fn get_chunk(
+    chunk_hash: ChunkHash,
+    source: &dyn ChainDataSource,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let chunk = source.chunk(&chunk_hash)?.ok_or_else(|| {
+        failure(FailureKind::NotFound, format!("Chunk {} not found", chunk_hash.0))
+    })?;
+    if output.is_json() {
+        return output.print_json(&chunk);
+    }
     println!("Chunk: {:#?}", chunk);
+    Ok(())
 }
 
 pub(crate) fn get_partial_chunk(
     partial_chunk_hash: ChunkHash,
     near_config: NearConfig,
     store: Store,
-) {
+) -> anyhow::Result<()> {
     let mut chain_store = ChainStore::new(
         store,
         near_config.genesis.config.genesis_height,
         !near_config.client_config.archive,
     );
-    let partial_chunk = chain_store.get_partial_chunk(&partial_chunk_hash);
+    let partial_chunk = chain_store.get_partial_chunk(&partial_chunk_hash)?;
     println!("Partial chunk: {:#?}", partial_chunk);
+    Ok(())
 }
 
 #[allow(unused)]
-enum LoadTrieMode {
+pub(crate) enum LoadTrieMode {
     /// Load latest state
     Latest,
     /// Load prev state at some height
@@ -667,7 +994,7 @@ enum LoadTrieMode {
     LastFinalFromHeight(BlockHeight),
 }
 
-fn load_trie(
+pub(crate) fn load_trie(
     store: Store,
     home_dir: &Path,
     near_config: &NearConfig,
@@ -675,7 +1002,7 @@ fn load_trie(
     load_trie_stop_at_height(store, home_dir, near_config, LoadTrieMode::Latest)
 }
 
-fn load_trie_stop_at_height(
+pub(crate) fn load_trie_stop_at_height(
     store: Store,
     home_dir: &Path,
     near_config: &NearConfig,
@@ -739,12 +1066,36 @@ pub fn chunk_mask_to_str(mask: &[bool]) -> String {
     mask.iter().map(|f| if *f { '.' } else { 'X' }).collect()
 }
 
+/// What `apply-chunk` prints in the JSON format.
+#[derive(Serialize)]
+struct AppliedChunkView {
+    chunk_extra: ChunkExtraView,
+    /// As on the debug pages of a node applying the chunk, but with the shard
+    /// cache of the trie cold at the start.
+    stage_times: ChunkApplyStageTimesView,
+    /// Only with `--record-witness`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    witness: Option<WitnessView>,
+}
+
+#[derive(Serialize)]
+struct WitnessView {
+    bytes: u64,
+    nodes: usize,
+    largest_receipt: Option<CryptoHash>,
+    largest_receipt_bytes: Option<u64>,
+    /// `soft` or `hard`, if the witness is above one of the limits.
+    exceeded_limit: Option<&'static str>,
+}
+
 pub(crate) fn apply_chunk(
     home_dir: &Path,
     near_config: NearConfig,
     store: Store,
     chunk_hash: ChunkHash,
     target_height: Option<u64>,
+    witness_limits: Option<WitnessLimits>,
+    output: &Output,
 ) -> anyhow::Result<()> {
     let runtime = NightshadeRuntime::with_config(
         home_dir,
@@ -758,12 +1109,71 @@ pub(crate) fn apply_chunk(
         near_config.genesis.config.genesis_height,
         !near_config.client_config.archive,
     );
-    let (apply_result, gas_limit) =
-        apply_chunk::apply_chunk(&runtime, &mut chain_store, chunk_hash, target_height, None)?;
-    println!("resulting chunk extra:\n{:?}", resulting_chunk_extra(&apply_result, gas_limit));
+    let shard_id = chain_store.get_chunk(&chunk_hash)?.shard_id();
+    let (apply_result, gas_limit) = apply_chunk::apply_chunk(
+        &runtime,
+        &mut chain_store,
+        chunk_hash,
+        target_height,
+        None,
+        witness_limits.is_some(),
+    )?;
+    let started = Instant::now();
+    let chunk_extra = resulting_chunk_extra(&apply_result, gas_limit);
+    let stage_times = ChunkApplyStageTimesView::from(ApplyChunkStageTimes {
+        outcome_root: started.elapsed(),
+        ..apply_result.stage_times
+    });
+    let witness = witness_limits.zip(WitnessSize::from_apply_result(&apply_result));
+    if output.is_json() {
+        let view = AppliedChunkView {
+            chunk_extra: ChunkExtraView::new(shard_id, &chunk_extra),
+            stage_times,
+            witness: witness.map(|(limits, size)| WitnessView {
+                bytes: size.bytes,
+                nodes: size.nodes,
+                largest_receipt: size.largest_receipt.map(|(receipt_id, _)| receipt_id),
+                largest_receipt_bytes: size.largest_receipt.map(|(_, bytes)| bytes),
+                exceeded_limit: size.exceeded_limit(&limits).map(WitnessLimit::as_str),
+            }),
+        };
+        return output.print_json(&view);
+    }
+    println!("resulting chunk extra:\n{:?}", chunk_extra);
+    println!("{}", format_stage_times(&stage_times));
+    if let Some((limits, size)) = witness {
+        println!("{}", size.report(&limits));
+    }
     Ok(())
 }
 
+fn format_stage_times(times: &ChunkApplyStageTimesView) -> String {
+    let ms = |micros| micros as f64 / 1000.0;
+    let mut text = format!(
+        "stage times: transactions {:.1} ms, receipts {:.1} ms, trie update {:.1} ms, outcome root {:.1} ms",
+        ms(times.transactions_micros),
+        ms(times.receipts_micros),
+        ms(times.trie_update_micros),
+        ms(times.outcome_root_micros)
+    );
+    match &times.trie_reads {
+        Some(reads) => write!(
+            text,
+            "\ntrie reads: {} ({} chunk cache, {} shard cache, {} db), hit rate {:.1}%, {} bytes, {} from db",
+            reads.reads(),
+            reads.chunk_cache_hits,
+            reads.shard_cache_hits,
+            reads.db_reads,
+            reads.cache_hit_rate().unwrap_or(0.0) * 100.0,
+            reads.bytes,
+            reads.db_bytes
+        )
+        .unwrap(),
+        None => text.push_str("\ntrie reads: unknown when recording the witness"),
+    }
+    text
+}
+
 pub(crate) fn apply_tx(
     home_dir: &Path,
     near_config: NearConfig,
@@ -797,3 +1207,75 @@ pub(crate) fn apply_receipt(
     apply_chunk::apply_receipt(near_config.genesis.config.genesis_height, &runtime, store, hash)
         .map(|_| ())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{failure_kind, OutputFormat};
+    use crate::test_chain::TestChainBuilder;
+
+    #[test]
+    fn test_view_chain() {
+        let chain = TestChainBuilder::default().build();
+        for format in [OutputFormat::Text, OutputFormat::Json] {
+            let output = Output::new(format);
+            let view = |height| {
+                view_chain(
+                    height,
+                    true,
+                    true,
+                    chain.near_config.clone(),
+                    chain.store.clone(),
+                    &output,
+                )
+            };
+            view(None).unwrap();
+            // The block has none of the chunks to show.
+            view(chain.missed_chunks_height).unwrap();
+            let err = view(chain.fork_height).unwrap_err();
+            assert!(err.to_string().contains("does not exist"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_dump_state_at_block() {
+        let chain = TestChainBuilder::default().build();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("genesis.json");
+        let output = Output::new(OutputFormat::Json);
+        let dump = |height, block_hash| {
+            dump_state(
+                height,
+                block_hash,
+                false,
+                Some(file.clone()),
+                &[],
+                chain.home_dir(),
+                chain.near_config.clone(),
+                chain.store.clone(),
+                &output,
+            )
+        };
+        let genesis_height = || {
+            near_chain_configs::Genesis::from_file(
+                &file,
+                near_chain_configs::GenesisValidationMode::Full,
+            )
+            .config
+            .genesis_height
+        };
+
+        let height = chain.steady_heights()[0];
+        dump(Some(height), None).unwrap();
+        assert_eq!(genesis_height(), height + 1);
+        dump(None, None).unwrap();
+        assert_eq!(genesis_height(), chain.head_height + 1);
+        // The block on the fork can be dumped by hash only.
+        dump(None, chain.fork_block_hash).unwrap();
+        assert_eq!(genesis_height(), chain.fork_height.unwrap() + 1);
+        let err = dump(chain.fork_height, None).unwrap_err();
+        assert_eq!(failure_kind(&err), FailureKind::NotFound);
+        let err = dump(None, Some(CryptoHash::hash_bytes(b"unknown"))).unwrap_err();
+        assert_eq!(failure_kind(&err), FailureKind::NotFound);
+    }
+}
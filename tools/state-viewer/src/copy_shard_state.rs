@@ -0,0 +1,175 @@
+//! Copying the state of a single shard at a single block into another
+//! database, e.g. to debug a shard of a mainnet archival node locally.
+//!
+//! All trie nodes and values reachable from the state root in the chunk extra
+//! of the block are copied, together with the rows other state viewer commands
+//! need to find that state root: the block, its header, block info and chunk
+//! extra, the epoch info of its epoch and the next one, and the heads, which
+//! all point at the block.  The copy is verified by walking the trie in the
+//! destination database and checking the hash of every node against its key.
+//!
+//! The hashes of all reachable trie nodes are kept in memory while copying.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Context;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_epoch_manager::EpochManager;
+use near_primitives::block::Tip;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::shard_layout::{get_block_shard_uid, ShardUId};
+use near_primitives::types::{BlockHeight, ShardId, StateRoot};
+use near_primitives::utils::index_to_bytes;
+use near_store::migrations::set_store_version;
+use near_store::{
+    create_store, DBCol, ShardTries, Store, StoreUpdate, FINAL_HEAD_KEY, HEADER_HEAD_KEY, HEAD_KEY,
+};
+use nearcore::NearConfig;
+
+/// Number of copied trie nodes after which the writes are committed.
+const STATE_BATCH_SIZE: usize = 100_000;
+
+pub(crate) fn copy_shard_state(
+    shard_id: ShardId,
+    height: BlockHeight,
+    dest_db: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let block_hash = chain_store.get_block_hash_by_height(height)?;
+    let header = chain_store.get_block_header(&block_hash)?.clone();
+    let epoch_manager =
+        EpochManager::new_from_genesis_config(store.clone(), &near_config.genesis.config)?;
+    let shard_layout = epoch_manager.get_shard_layout(header.epoch_id())?;
+    if shard_id >= shard_layout.num_shards() {
+        anyhow::bail!("shard {} doesn't exist at #{}", shard_id, height);
+    }
+    let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, shard_layout);
+    let state_root = *chain_store.get_chunk_extra(&block_hash, &shard_uid)?.state_root();
+    println!("Copying state {} of shard {} at #{} {}", state_root, shard_id, height, block_hash);
+
+    let dest = create_store(dest_db);
+    set_store_version(&dest, near_primitives::version::DB_VERSION);
+    let copied = copy_trie(&store, &dest, shard_uid, &state_root)?;
+    println!("Copied {} trie nodes and values", copied);
+
+    let mut store_update = dest.store_update();
+    for col in [DBCol::Block, DBCol::BlockHeader] {
+        copy_row(&store, &mut store_update, col, block_hash.as_ref(), false)?;
+    }
+    copy_row(&store, &mut store_update, DBCol::BlockInfo, block_hash.as_ref(), true)?;
+    let chunk_extra_key = get_block_shard_uid(&block_hash, &shard_uid);
+    copy_row(&store, &mut store_update, DBCol::ChunkExtra, &chunk_extra_key, false)?;
+    for epoch_id in [header.epoch_id(), header.next_epoch_id()] {
+        copy_row(&store, &mut store_update, DBCol::EpochInfo, epoch_id.as_ref(), false)?;
+    }
+    copy_row(&store, &mut store_update, DBCol::EpochStart, header.epoch_id().as_ref(), false)?;
+    store_update.set_ser(DBCol::BlockHeight, &index_to_bytes(height), &block_hash)?;
+    let tip = Tip::from_header(&header);
+    for key in [&HEAD_KEY[..], &HEADER_HEAD_KEY[..], &FINAL_HEAD_KEY[..]] {
+        store_update.set_ser(DBCol::BlockMisc, key, &tip)?;
+    }
+    store_update.commit()?;
+
+    let verified = verify_trie(&dest, shard_uid, &state_root)?;
+    if verified != copied {
+        anyhow::bail!("copied {} trie nodes but {} are reachable in the copy", copied, verified);
+    }
+    println!("Verified the state root {} in {}", state_root, dest_db.display());
+    Ok(())
+}
+
+/// Copies a row from `store` into the update, failing if it's missing.
+fn copy_row(
+    store: &Store,
+    store_update: &mut StoreUpdate,
+    col: DBCol,
+    key: &[u8],
+    insert: bool,
+) -> anyhow::Result<()> {
+    let value = store.get(col, key)?.with_context(|| format!("{} not found", col))?;
+    if insert {
+        store_update.insert(col, key, &value);
+    } else {
+        store_update.set(col, key, &value);
+    }
+    Ok(())
+}
+
+fn state_key(shard_uid: ShardUId, hash: &CryptoHash) -> Vec<u8> {
+    [&shard_uid.to_bytes()[..], hash.as_ref()].concat()
+}
+
+/// Copies the trie nodes and values reachable from `root` and returns how
+/// many there are.
+fn copy_trie(
+    store: &Store,
+    dest: &Store,
+    shard_uid: ShardUId,
+    root: &StateRoot,
+) -> anyhow::Result<usize> {
+    let tries = ShardTries::new(store.clone(), shard_uid.version, shard_uid.shard_id as u64 + 1);
+    let mut marked = HashSet::new();
+    tries.get_trie_for_shard(shard_uid).mark_reachable(root, &mut marked)?;
+    let mut store_update = dest.store_update();
+    let mut batch = 0;
+    for hash in &marked {
+        let key = state_key(shard_uid, hash);
+        let value = store
+            .get(DBCol::State, &key)?
+            .with_context(|| format!("trie node {} not found", hash))?;
+        store_update.update_refcount(DBCol::State, &key, &value, 1);
+        batch += 1;
+        if batch == STATE_BATCH_SIZE {
+            std::mem::replace(&mut store_update, dest.store_update()).commit()?;
+            batch = 0;
+        }
+    }
+    store_update.commit()?;
+    Ok(marked.len())
+}
+
+/// Walks the trie in `store` from `root`, checks that every node and value
+/// hashes to its key and returns how many there are.
+fn verify_trie(store: &Store, shard_uid: ShardUId, root: &StateRoot) -> anyhow::Result<usize> {
+    let tries = ShardTries::new(store.clone(), shard_uid.version, shard_uid.shard_id as u64 + 1);
+    let mut marked = HashSet::new();
+    tries.get_trie_for_shard(shard_uid).mark_reachable(root, &mut marked)?;
+    for node_hash in &marked {
+        let value = store
+            .get(DBCol::State, &state_key(shard_uid, node_hash))?
+            .with_context(|| format!("trie node {} not found in the copy", node_hash))?;
+        if &hash(&value) != node_hash {
+            anyhow::bail!("trie node {} has a different hash in the copy", node_hash);
+        }
+    }
+    Ok(marked.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_store::test_utils::{create_test_store, create_tries, test_populate_trie};
+    use near_store::Trie;
+
+    #[test]
+    fn test_copy_trie() {
+        let tries = create_tries();
+        let shard_uid = ShardUId::single_shard();
+        let changes = (0..100u8).map(|i| (vec![i, i / 10], Some(vec![i; 40]))).collect();
+        let root = test_populate_trie(&tries, &Trie::empty_root(), shard_uid, changes);
+        let dest = create_test_store();
+        let copied = copy_trie(&tries.get_store(), &dest, shard_uid, &root).unwrap();
+        assert!(copied > 100);
+        assert_eq!(verify_trie(&dest, shard_uid, &root).unwrap(), copied);
+        let trie = ShardTries::new(dest, 0, 1).get_trie_for_shard(shard_uid);
+        assert_eq!(trie.get(&root, &[42, 4]), Ok(Some(vec![42; 40])));
+        assert!(verify_trie(&create_test_store(), shard_uid, &root).is_err());
+    }
+}
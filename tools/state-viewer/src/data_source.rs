@@ -0,0 +1,258 @@
+//! Where the commands which don't need the whole database read the chain data
+//! from: the local database or the JSON RPC of a remote node, see
+//! `--remote-rpc`.
+
+use crate::commands::{load_trie, load_trie_stop_at_height, LoadTrieMode};
+use near_chain::{ChainStore, ChainStoreAccess, ErrorKind, RuntimeAdapter};
+use near_epoch_manager::EpochManager;
+use near_jsonrpc_client::{ChunkId, JsonRpcClient};
+use near_jsonrpc_primitives::errors::{RpcError, RpcErrorKind};
+use near_jsonrpc_primitives::types::query::{QueryResponseKind, RpcQueryRequest};
+use near_jsonrpc_primitives::types::receipts::{ReceiptReference, RpcReceiptRequest};
+use near_primitives::hash::CryptoHash;
+use near_primitives::serialize::{from_base64, to_base64};
+use near_primitives::sharding::ChunkHash;
+use near_primitives::state_record::StateRecord;
+use near_primitives::trie_key::TrieKey;
+use near_primitives::types::{AccountId, BlockHeight, BlockId, BlockReference};
+use near_primitives::views::{ChunkView, QueryRequest, ReceiptView};
+use near_store::Store;
+use nearcore::NearConfig;
+use node_runtime::adapter::ViewRuntimeAdapter;
+use std::cell::Cell;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// The reads of chain data the commands supporting `--remote-rpc` do.  The
+/// results are views since that's all RPC can return.
+pub(crate) trait ChainDataSource {
+    fn receipt(&self, receipt_id: &CryptoHash) -> anyhow::Result<Option<ReceiptView>>;
+
+    fn chunk(&self, chunk_hash: &ChunkHash) -> anyhow::Result<Option<ChunkView>>;
+
+    /// Code of the contract deployed to the account in the latest state.
+    fn contract_code(&self, account_id: &AccountId) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Value the contract stored under the key in the state after the block
+    /// at the given height, or in the latest state.
+    fn contract_data(
+        &self,
+        account_id: &AccountId,
+        key: &[u8],
+        height: Option<BlockHeight>,
+    ) -> anyhow::Result<Option<Vec<u8>>>;
+}
+
+pub(crate) struct StoreDataSource<'a> {
+    pub home_dir: &'a Path,
+    pub near_config: NearConfig,
+    pub store: Store,
+}
+
+impl StoreDataSource<'_> {
+    fn chain_store(&self) -> ChainStore {
+        ChainStore::new(
+            self.store.clone(),
+            self.near_config.genesis.config.genesis_height,
+            !self.near_config.client_config.archive,
+        )
+    }
+}
+
+impl ChainDataSource for StoreDataSource<'_> {
+    fn receipt(&self, receipt_id: &CryptoHash) -> anyhow::Result<Option<ReceiptView>> {
+        let mut chain_store = self.chain_store();
+        Ok(chain_store.get_receipt(receipt_id)?.map(|receipt| receipt.clone().into()))
+    }
+
+    fn chunk(&self, chunk_hash: &ChunkHash) -> anyhow::Result<Option<ChunkView>> {
+        let mut chain_store = self.chain_store();
+        let chunk = match chain_store.get_chunk(chunk_hash) {
+            Ok(chunk) => chunk.clone(),
+            Err(err) if matches!(err.kind(), ErrorKind::DBNotFoundErr(_)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let epoch_manager = EpochManager::new_from_genesis_config(
+            self.store.clone(),
+            &self.near_config.genesis.config,
+        )?;
+        let epoch_id = epoch_manager.get_epoch_id_from_prev_block(chunk.prev_block())?;
+        let author = epoch_manager
+            .get_chunk_producer_info(&epoch_id, chunk.height_created(), chunk.shard_id())?
+            .take_account_id();
+        Ok(Some(ChunkView::from_author_chunk(author, chunk)))
+    }
+
+    fn contract_code(&self, account_id: &AccountId) -> anyhow::Result<Option<Vec<u8>>> {
+        let (runtime, state_roots, header) =
+            load_trie(self.store.clone(), self.home_dir, &self.near_config);
+        let epoch_id = runtime.get_epoch_id(header.hash())?;
+        for (shard_id, state_root) in state_roots.iter().enumerate() {
+            let shard_uid = runtime.shard_id_to_uid(shard_id as u64, &epoch_id)?;
+            if let Ok(contract_code) =
+                runtime.view_contract_code(&shard_uid, *state_root, account_id)
+            {
+                return Ok(Some(contract_code.code().to_vec()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn contract_data(
+        &self,
+        account_id: &AccountId,
+        key: &[u8],
+        height: Option<BlockHeight>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let mode = height.map_or(LoadTrieMode::Latest, LoadTrieMode::Height);
+        let (runtime, state_roots, header) =
+            load_trie_stop_at_height(self.store.clone(), self.home_dir, &self.near_config, mode);
+        let key = TrieKey::ContractData { account_id: account_id.clone(), key: key.to_vec() };
+        for (shard_id, state_root) in state_roots.iter().enumerate() {
+            let trie = runtime.get_trie_for_shard(shard_id as u64, header.prev_hash())?;
+            if let Some(value) = trie.get(state_root, &key.to_vec())? {
+                match StateRecord::from_raw_key_value(key.to_vec(), value) {
+                    Some(StateRecord::Data { value, .. }) => return Ok(Some(value)),
+                    _ => unreachable!(),
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Number of times a request failing with a transient error is sent.
+const MAX_ATTEMPTS: u32 = 5;
+/// Time before the first retry, doubled with every one after it.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Reads the data from the JSON RPC of a remote node, with at most
+/// `max_requests_per_second` requests so that scanning a range of blocks
+/// doesn't overload it.
+pub(crate) struct RpcDataSource {
+    system: actix::SystemRunner,
+    client: JsonRpcClient,
+    min_interval: Duration,
+    last_request: Cell<Option<Instant>>,
+}
+
+impl RpcDataSource {
+    pub(crate) fn new(url: &str, max_requests_per_second: u32) -> Self {
+        let system = actix::System::new();
+        let client = system.block_on(async { near_jsonrpc_client::new_client(url) });
+        Self {
+            system,
+            client,
+            min_interval: Duration::from_secs(1) / max_requests_per_second.max(1),
+            last_request: Cell::new(None),
+        }
+    }
+
+    /// Sends the request, retrying internal and transport errors.  Returns
+    /// `None` if the node responds with an error named in `not_found`.
+    fn call<T, F>(
+        &self,
+        not_found: &[&str],
+        request: impl Fn(&JsonRpcClient) -> F,
+    ) -> anyhow::Result<Option<T>>
+    where
+        F: Future<Output = Result<T, RpcError>>,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            if let Some(next) = self.last_request.get().map(|last| last + self.min_interval) {
+                let now = Instant::now();
+                if next > now {
+                    std::thread::sleep(next - now);
+                }
+            }
+            self.last_request.set(Some(Instant::now()));
+            let err = match self.system.block_on(request(&self.client)) {
+                Ok(response) => return Ok(Some(response)),
+                Err(err) => err,
+            };
+            match &err.error_struct {
+                Some(RpcErrorKind::HandlerError(cause))
+                    if cause
+                        .get("name")
+                        .and_then(|name| name.as_str())
+                        .map_or(false, |name| not_found.contains(&name)) =>
+                {
+                    return Ok(None)
+                }
+                None | Some(RpcErrorKind::InternalError(_)) if attempt < MAX_ATTEMPTS => {
+                    warn!(target: "state_viewer", ?err, attempt, "RPC request failed, retrying");
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                _ => anyhow::bail!("RPC request failed: {}", err),
+            }
+        }
+    }
+}
+
+impl ChainDataSource for RpcDataSource {
+    fn receipt(&self, receipt_id: &CryptoHash) -> anyhow::Result<Option<ReceiptView>> {
+        let response = self.call(&["UNKNOWN_RECEIPT"], |client| {
+            client.EXPERIMENTAL_receipt(RpcReceiptRequest {
+                receipt_reference: ReceiptReference { receipt_id: *receipt_id },
+            })
+        })?;
+        Ok(response.map(|response| response.receipt_view))
+    }
+
+    fn chunk(&self, chunk_hash: &ChunkHash) -> anyhow::Result<Option<ChunkView>> {
+        self.call(&["UNKNOWN_CHUNK"], |client| client.chunk(ChunkId::Hash(chunk_hash.0)))
+    }
+
+    fn contract_code(&self, account_id: &AccountId) -> anyhow::Result<Option<Vec<u8>>> {
+        let response = self.call(&["UNKNOWN_ACCOUNT", "NO_CONTRACT_CODE"], |client| {
+            client.query(RpcQueryRequest {
+                block_reference: BlockReference::latest(),
+                request: QueryRequest::ViewCode { account_id: account_id.clone() },
+            })
+        })?;
+        match response.map(|response| response.kind) {
+            None => Ok(None),
+            Some(QueryResponseKind::ViewCode(code)) => Ok(Some(code.code)),
+            Some(kind) => anyhow::bail!("unexpected response to view_code: {:?}", kind),
+        }
+    }
+
+    fn contract_data(
+        &self,
+        account_id: &AccountId,
+        key: &[u8],
+        height: Option<BlockHeight>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let block_reference = height.map_or(BlockReference::latest(), |height| {
+            BlockReference::BlockId(BlockId::Height(height))
+        });
+        let response = self.call(&["UNKNOWN_ACCOUNT"], |client| {
+            client.query(RpcQueryRequest {
+                block_reference: block_reference.clone(),
+                request: QueryRequest::ViewState {
+                    account_id: account_id.clone(),
+                    prefix: key.to_vec().into(),
+                },
+            })
+        })?;
+        let values = match response.map(|response| response.kind) {
+            None => return Ok(None),
+            Some(QueryResponseKind::ViewState(state)) => state.values,
+            Some(kind) => anyhow::bail!("unexpected response to view_state: {:?}", kind),
+        };
+        // The values of all keys with the prefix are returned.
+        let key = to_base64(key);
+        match values.into_iter().find(|item| item.key == key) {
+            None => Ok(None),
+            Some(item) => from_base64(&item.value)
+                .map(Some)
+                .map_err(|err| anyhow::anyhow!("invalid base64 value: {}", err)),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Validator reliability over a range of epochs, in two CSV files.
+//!
+//! The canonical epochs from the head back to the first epoch height of the
+//! range are found by walking the epochs' first blocks, then every epoch of the
+//! range gets one row per validator, from the summary the epoch manager stored
+//! when the epoch ended: the blocks and chunks produced and expected, the
+//! online ratio the rewards are computed from, the stake, and the kickout with
+//! its reason.  The rows are written as the epochs are read, and the summary
+//! file adds them up per validator over the whole range.
+//!
+//! The epoch manager doesn't keep the produced and expected numbers of the
+//! validators kicked out for not producing enough, those come from the kickout
+//! reason, which only has the ones the validator fell short of.  Epochs without
+//! a stored summary, the current one and the ones whose summary is gone, are
+//! skipped with a note.  There are no chunk endorsements in this protocol.
+
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_epoch_manager::EpochManager;
+use near_primitives::epoch_manager::epoch_info::EpochSummary;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{
+    AccountId, Balance, EpochHeight, EpochId, ValidatorKickoutReason, ValidatorStats,
+};
+use near_store::Store;
+use nearcore::NearConfig;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const ROWS_HEADER: &str = "epoch_height,epoch_id,account_id,stake,blocks_produced,\
+                           blocks_expected,chunks_produced,chunks_expected,online_ratio,\
+                           kicked_out,kickout_reason";
+
+const SUMMARY_HEADER: &str = "account_id,epochs,first_epoch_height,last_epoch_height,\
+                              last_stake,blocks_produced,blocks_expected,chunks_produced,\
+                              chunks_expected,online_ratio,epochs_kicked_out";
+
+/// What is known of a validator in an epoch.
+#[derive(Debug, PartialEq)]
+struct ValidatorEpoch {
+    account_id: AccountId,
+    stake: Balance,
+    blocks: Option<ValidatorStats>,
+    chunks: Option<ValidatorStats>,
+    kickout: Option<ValidatorKickoutReason>,
+}
+
+impl ValidatorEpoch {
+    fn new(account_id: AccountId, stake: Balance, summary: &EpochSummary) -> Self {
+        let kickout = summary.validator_kickout.get(&account_id).cloned();
+        let (blocks, chunks) =
+            match (summary.validator_block_chunk_stats.get(&account_id), &kickout) {
+                (Some(stats), _) => {
+                    (Some(stats.block_stats.clone()), Some(stats.chunk_stats.clone()))
+                }
+                (None, Some(ValidatorKickoutReason::NotEnoughBlocks { produced, expected })) => {
+                    (Some(ValidatorStats { produced: *produced, expected: *expected }), None)
+                }
+                (None, Some(ValidatorKickoutReason::NotEnoughChunks { produced, expected })) => {
+                    (None, Some(ValidatorStats { produced: *produced, expected: *expected }))
+                }
+                (None, _) => (None, None),
+            };
+        Self { account_id, stake, blocks, chunks, kickout }
+    }
+}
+
+/// Average of the ratios of the blocks and chunks produced to expected, the
+/// way the reward calculator computes the uptime, or the ratio of the only
+/// one expected.
+fn online_ratio(blocks: Option<&ValidatorStats>, chunks: Option<&ValidatorStats>) -> Option<f64> {
+    let ratios: Vec<f64> = [blocks, chunks]
+        .into_iter()
+        .flatten()
+        .filter(|stats| stats.expected > 0)
+        .map(|stats| stats.produced as f64 / stats.expected as f64)
+        .collect();
+    (!ratios.is_empty()).then(|| ratios.iter().sum::<f64>() / ratios.len() as f64)
+}
+
+fn kickout_label(reason: &ValidatorKickoutReason) -> &'static str {
+    match reason {
+        ValidatorKickoutReason::Slashed => "slashed",
+        ValidatorKickoutReason::NotEnoughBlocks { .. } => "not_enough_blocks",
+        ValidatorKickoutReason::NotEnoughChunks { .. } => "not_enough_chunks",
+        ValidatorKickoutReason::Unstaked => "unstaked",
+        ValidatorKickoutReason::NotEnoughStake { .. } => "not_enough_stake",
+        ValidatorKickoutReason::DidNotGetASeat => "did_not_get_a_seat",
+    }
+}
+
+fn stats_fields(stats: Option<&ValidatorStats>) -> String {
+    stats.map_or(",".to_string(), |stats| format!("{},{}", stats.produced, stats.expected))
+}
+
+fn ratio_field(ratio: Option<f64>) -> String {
+    ratio.map_or(String::new(), |ratio| format!("{:.4}", ratio))
+}
+
+fn write_row(
+    out: &mut impl Write,
+    epoch_height: EpochHeight,
+    epoch_id: &EpochId,
+    validator: &ValidatorEpoch,
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "{},{},{},{},{},{},{},{},{}",
+        epoch_height,
+        epoch_id.0,
+        validator.account_id,
+        validator.stake,
+        stats_fields(validator.blocks.as_ref()),
+        stats_fields(validator.chunks.as_ref()),
+        ratio_field(online_ratio(validator.blocks.as_ref(), validator.chunks.as_ref())),
+        validator.kickout.is_some(),
+        validator.kickout.as_ref().map_or("", kickout_label)
+    )
+}
+
+/// A validator over the epochs of the range it validated in.
+#[derive(Debug, Default, PartialEq)]
+struct ValidatorTotals {
+    epochs: u64,
+    first_epoch_height: EpochHeight,
+    last_epoch_height: EpochHeight,
+    last_stake: Balance,
+    blocks: ValidatorStats,
+    chunks: ValidatorStats,
+    epochs_kicked_out: u64,
+}
+
+impl ValidatorTotals {
+    /// Adds an epoch, the epochs being added in increasing height.
+    fn add(&mut self, epoch_height: EpochHeight, validator: &ValidatorEpoch) {
+        if self.epochs == 0 {
+            self.first_epoch_height = epoch_height;
+        }
+        self.epochs += 1;
+        self.last_epoch_height = epoch_height;
+        self.last_stake = validator.stake;
+        for (total, stats) in
+            [(&mut self.blocks, &validator.blocks), (&mut self.chunks, &validator.chunks)]
+        {
+            if let Some(stats) = stats {
+                total.produced += stats.produced;
+                total.expected += stats.expected;
+            }
+        }
+        self.epochs_kicked_out += validator.kickout.is_some() as u64;
+    }
+}
+
+fn write_summary(
+    out: &mut impl Write,
+    totals: &BTreeMap<AccountId, ValidatorTotals>,
+) -> std::io::Result<()> {
+    writeln!(out, "{}", SUMMARY_HEADER)?;
+    for (account_id, totals) in totals {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{}",
+            account_id,
+            totals.epochs,
+            totals.first_epoch_height,
+            totals.last_epoch_height,
+            totals.last_stake,
+            stats_fields(Some(&totals.blocks)),
+            stats_fields(Some(&totals.chunks)),
+            ratio_field(online_ratio(Some(&totals.blocks), Some(&totals.chunks))),
+            totals.epochs_kicked_out
+        )?;
+    }
+    Ok(())
+}
+
+/// The canonical epochs with heights in the range, in increasing height.
+pub(crate) fn canonical_epochs(
+    chain_store: &ChainStore,
+    epoch_manager: &mut EpochManager,
+    from_epoch_height: EpochHeight,
+    to_epoch_height: EpochHeight,
+) -> anyhow::Result<Vec<(EpochId, EpochHeight)>> {
+    let mut block_info = epoch_manager.get_block_info(&chain_store.head()?.last_block_hash)?;
+    let mut epochs = vec![];
+    loop {
+        let epoch_id = block_info.epoch_id().clone();
+        let epoch_height = epoch_manager.get_epoch_info(&epoch_id)?.epoch_height();
+        if epoch_height < from_epoch_height {
+            break;
+        }
+        if epoch_height <= to_epoch_height {
+            epochs.push((epoch_id, epoch_height));
+        }
+        let first_block_info = epoch_manager.get_block_info(block_info.epoch_first_block())?;
+        if first_block_info.prev_hash() == &CryptoHash::default() {
+            break;
+        }
+        match epoch_manager.get_block_info(first_block_info.prev_hash()) {
+            Ok(prev_block_info) => block_info = prev_block_info,
+            Err(err) => {
+                eprintln!(
+                    "The epochs below epoch height {} are unknown, stopping there: {}",
+                    epoch_height, err
+                );
+                break;
+            }
+        }
+    }
+    epochs.reverse();
+    Ok(epochs)
+}
+
+/// Writes a row per validator of every canonical epoch with a height in the
+/// range to `out`, and a row per validator adding up its epochs to
+/// `summary_out`.
+pub(crate) fn epoch_analysis(
+    from_epoch_height: EpochHeight,
+    to_epoch_height: EpochHeight,
+    out: &Path,
+    summary_out: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    if from_epoch_height > to_epoch_height {
+        anyhow::bail!(
+            "the range of epoch heights {}..={} is empty",
+            from_epoch_height,
+            to_epoch_height
+        );
+    }
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let chain_store =
+        ChainStore::new(store.clone(), genesis_height, !near_config.client_config.archive);
+    let mut epoch_manager =
+        EpochManager::new_from_genesis_config(store, &near_config.genesis.config)?;
+    let epochs =
+        canonical_epochs(&chain_store, &mut epoch_manager, from_epoch_height, to_epoch_height)?;
+
+    let mut rows = BufWriter::new(File::create(out)?);
+    writeln!(rows, "{}", ROWS_HEADER)?;
+    let mut totals: BTreeMap<AccountId, ValidatorTotals> = BTreeMap::new();
+    let (mut analyzed, mut skipped) = (0, 0);
+    for (epoch_id, epoch_height) in epochs {
+        let summary = match epoch_manager.get_epoch_validator_info(&epoch_id) {
+            Ok(summary) => summary,
+            Err(err) => {
+                eprintln!(
+                    "Skipping epoch {} at epoch height {}, it has no stored summary, it isn't \
+                     finished or was garbage collected: {}",
+                    epoch_id.0, epoch_height, err
+                );
+                skipped += 1;
+                continue;
+            }
+        };
+        let epoch_info = epoch_manager.get_epoch_info(&epoch_id)?;
+        for validator in epoch_info.validators_iter() {
+            let validator =
+                ValidatorEpoch::new(validator.account_id().clone(), validator.stake(), &summary);
+            write_row(&mut rows, epoch_height, &epoch_id, &validator)?;
+            totals.entry(validator.account_id.clone()).or_default().add(epoch_height, &validator);
+        }
+        analyzed += 1;
+    }
+    rows.flush()?;
+
+    let mut summary = BufWriter::new(File::create(summary_out)?);
+    write_summary(&mut summary, &totals)?;
+    summary.flush()?;
+    eprintln!(
+        "Analyzed {} epochs, skipped {}, {} validators, written to {} and {}",
+        analyzed,
+        skipped,
+        totals.len(),
+        out.display(),
+        summary_out.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::types::BlockChunkValidatorStats;
+    use std::collections::HashMap;
+
+    fn stats(produced: u64, expected: u64) -> ValidatorStats {
+        ValidatorStats { produced, expected }
+    }
+
+    fn empty_summary() -> EpochSummary {
+        EpochSummary {
+            prev_epoch_last_block_hash: CryptoHash::default(),
+            all_proposals: vec![],
+            validator_kickout: HashMap::new(),
+            validator_block_chunk_stats: HashMap::new(),
+            next_version: 0,
+        }
+    }
+
+    fn summary() -> EpochSummary {
+        let mut summary = empty_summary();
+        summary.validator_block_chunk_stats.insert(
+            "alice".parse().unwrap(),
+            BlockChunkValidatorStats { block_stats: stats(9, 10), chunk_stats: stats(18, 20) },
+        );
+        summary.validator_kickout.insert(
+            "bob".parse().unwrap(),
+            ValidatorKickoutReason::NotEnoughBlocks { produced: 1, expected: 10 },
+        );
+        summary
+    }
+
+    #[test]
+    fn test_validator_epoch() {
+        let summary = summary();
+        let alice = ValidatorEpoch::new("alice".parse().unwrap(), 100, &summary);
+        assert_eq!(alice.blocks, Some(stats(9, 10)));
+        assert_eq!(alice.kickout, None);
+        let bob = ValidatorEpoch::new("bob".parse().unwrap(), 50, &summary);
+        assert_eq!((bob.blocks, bob.chunks), (Some(stats(1, 10)), None));
+
+        let mut out = vec![];
+        let epoch_id = EpochId::default();
+        write_row(&mut out, 7, &epoch_id, &alice).unwrap();
+        write_row(&mut out, 7, &epoch_id, &bob).unwrap();
+        let rows = String::from_utf8(out).unwrap();
+        let fields: Vec<Vec<&str>> = rows.lines().map(|row| row.split(',').collect()).collect();
+        assert_eq!(fields[0][2..], ["alice", "100", "9", "10", "18", "20", "0.9000", "false", ""]);
+        assert_eq!(
+            fields[1][2..],
+            ["bob", "50", "1", "10", "", "", "0.1000", "true", "not_enough_blocks"]
+        );
+        assert_eq!(fields[0].len(), ROWS_HEADER.split(',').count());
+    }
+
+    #[test]
+    fn test_validator_totals() {
+        let summary = summary();
+        let mut totals = ValidatorTotals::default();
+        totals.add(3, &ValidatorEpoch::new("alice".parse().unwrap(), 100, &summary));
+        totals.add(4, &ValidatorEpoch::new("alice".parse().unwrap(), 120, &empty_summary()));
+        assert_eq!(
+            totals,
+            ValidatorTotals {
+                epochs: 2,
+                first_epoch_height: 3,
+                last_epoch_height: 4,
+                last_stake: 120,
+                blocks: stats(9, 10),
+                chunks: stats(18, 20),
+                epochs_kicked_out: 0,
+            }
+        );
+        assert_eq!(online_ratio(Some(&stats(0, 0)), Some(&stats(1, 2))), Some(0.5));
+        assert_eq!(online_ratio(None, Some(&stats(0, 0))), None);
+    }
+}
@@ -1,14 +1,24 @@
+use crate::output::{failure, FailureKind, Output};
 use borsh::BorshDeserialize;
 use clap::Subcommand;
 use core::ops::Range;
 use near_chain::{ChainStore, ChainStoreAccess, RuntimeAdapter};
 use near_epoch_manager::EpochManager;
 use near_primitives::account::id::AccountId;
+use near_primitives::epoch_manager::block_info::BlockInfo;
 use near_primitives::epoch_manager::epoch_info::EpochInfo;
 use near_primitives::epoch_manager::AGGREGATOR_KEY;
 use near_primitives::hash::CryptoHash;
-use near_primitives::types::{BlockHeight, EpochHeight, EpochId, ProtocolVersion, ShardId};
+use near_primitives::serialize::u128_dec_format;
+use near_primitives::types::{
+    Balance, BlockHeight, EpochHeight, EpochId, ProtocolVersion, ShardId, ValidatorId,
+    ValidatorKickoutReason,
+};
+use near_primitives::version::UPGRADABILITY_FIX_PROTOCOL_VERSION;
+use near_primitives::views::ValidatorStakeView;
 use near_store::{DBCol, Store};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -30,20 +40,245 @@ pub(crate) enum EpochSelection {
     ProtocolVersion { protocol_version: ProtocolVersion },
 }
 
+impl EpochSelection {
+    /// The selection as a reference to a single epoch, if it is one.
+    pub(crate) fn as_epoch_ref(&self) -> anyhow::Result<Option<EpochRef>> {
+        Ok(Some(match self {
+            EpochSelection::EpochId { epoch_id } => EpochRef::EpochId(parse_epoch_id(epoch_id)?),
+            EpochSelection::EpochHeight { epoch_height } => EpochRef::EpochHeight(*epoch_height),
+            EpochSelection::BlockHash { block_hash } => {
+                EpochRef::BlockHash(parse_block_hash(block_hash)?)
+            }
+            EpochSelection::BlockHeight { block_height } => EpochRef::BlockHeight(*block_height),
+            EpochSelection::Current
+            | EpochSelection::All
+            | EpochSelection::ProtocolVersion { .. } => return Ok(None),
+        }))
+    }
+}
+
+pub(crate) fn parse_epoch_id(epoch_id: &str) -> anyhow::Result<EpochId> {
+    let hash = CryptoHash::from_str(epoch_id)
+        .map_err(|err| anyhow::anyhow!("invalid epoch id {:?}: {}", epoch_id, err))?;
+    Ok(EpochId(hash))
+}
+
+pub(crate) fn parse_block_hash(block_hash: &str) -> anyhow::Result<CryptoHash> {
+    CryptoHash::from_str(block_hash)
+        .map_err(|err| anyhow::anyhow!("invalid block hash {:?}: {}", block_hash, err))
+}
+
+/// The validator given with `--validator-account-id`.
+#[derive(Debug, Clone)]
+pub(crate) enum ValidatorArg {
+    /// An account id taken as is, with `--exact`.
+    Exact(AccountId),
+    /// A prefix or a substring of the account id of a validator of the
+    /// selected epochs.
+    Fuzzy(String),
+}
+
+/// The account id of the validator the argument designates among the
+/// validators and the kicked out validators of the epochs.
+pub(crate) fn resolve_validator(
+    validator: ValidatorArg,
+    epoch_ids: &[EpochId],
+    epoch_manager: &mut EpochManager,
+) -> anyhow::Result<AccountId> {
+    let query = match validator {
+        ValidatorArg::Exact(account_id) => return Ok(account_id),
+        ValidatorArg::Fuzzy(query) => query,
+    };
+    let mut candidates = BTreeSet::new();
+    for epoch_id in epoch_ids {
+        let epoch_info = epoch_manager.get_epoch_info(epoch_id)?;
+        candidates.extend(epoch_info.validators_iter().map(|stake| stake.take_account_id()));
+        candidates.extend(epoch_info.validator_kickout().keys().cloned());
+    }
+    let account_id = match_validator(&query, &candidates)?;
+    if account_id.as_ref() != query {
+        eprintln!("Resolved validator {:?} to {}", query, account_id);
+    }
+    Ok(account_id)
+}
+
+/// The candidate equal to the query, or else the only one starting with it,
+/// or else the only one containing it.  Fails with `FailureKind::Ambiguous`
+/// listing the candidates if several match at the first of those levels
+/// which has any.
+fn match_validator(query: &str, candidates: &BTreeSet<AccountId>) -> anyhow::Result<AccountId> {
+    let levels: [&dyn Fn(&str) -> bool; 3] = [
+        &|account_id| account_id == query,
+        &|account_id| account_id.starts_with(query),
+        &|account_id| account_id.contains(query),
+    ];
+    for matches in levels {
+        let matching: Vec<&AccountId> =
+            candidates.iter().filter(|account_id| matches(account_id.as_ref())).collect();
+        match matching.as_slice() {
+            [] => continue,
+            [account_id] => return Ok((*account_id).clone()),
+            _ => {
+                let names: Vec<&str> =
+                    matching.iter().map(|account_id| account_id.as_ref()).collect();
+                return Err(failure(
+                    FailureKind::Ambiguous,
+                    format!(
+                        "{:?} matches {} validators: {}; give more of the account id",
+                        query,
+                        matching.len(),
+                        names.join(", ")
+                    ),
+                ));
+            }
+        }
+    }
+    Err(failure(
+        FailureKind::InvalidArgument,
+        format!(
+            "{:?} matches none of the {} validators of the selected epochs; use --exact to \
+             look up an account which isn't one",
+            query,
+            candidates.len()
+        ),
+    ))
+}
+
+/// Anything a single epoch can be found by.
+#[derive(Debug, Clone)]
+pub(crate) enum EpochRef {
+    EpochId(EpochId),
+    /// Epoch containing the block.
+    BlockHash(CryptoHash),
+    /// Epoch containing the canonical block at the height.
+    BlockHeight(BlockHeight),
+    EpochHeight(EpochHeight),
+}
+
+impl std::fmt::Display for EpochRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpochRef::EpochId(epoch_id) => write!(f, "epoch id {}", epoch_id.0),
+            EpochRef::BlockHash(block_hash) => write!(f, "block hash {}", block_hash),
+            EpochRef::BlockHeight(height) => write!(f, "block height {}", height),
+            EpochRef::EpochHeight(epoch_height) => write!(f, "epoch height {}", epoch_height),
+        }
+    }
+}
+
+/// Hash of the canonical block at the height, explaining why there's none.
+fn get_block_hash_at_height(
+    chain_store: &mut ChainStore,
+    height: BlockHeight,
+) -> anyhow::Result<CryptoHash> {
+    if let Ok(block_hash) = chain_store.get_block_hash_by_height(height) {
+        return Ok(block_hash);
+    }
+    let tail = chain_store.tail()?;
+    let head = chain_store.head()?;
+    if height < tail {
+        anyhow::bail!("height {} is below GC tail {}", height, tail);
+    }
+    if height > head.height {
+        anyhow::bail!("height {} is above the head {}", height, head.height);
+    }
+    anyhow::bail!("there's no block at height {}, it was skipped", height)
+}
+
+/// Finds the epoch and its height.  Several epochs are returned only for an
+/// epoch height with more than one epoch, which happens with forks.
+pub(crate) fn resolve_epoch(
+    epoch_ref: &EpochRef,
+    store: &Store,
+    chain_store: &mut ChainStore,
+    epoch_manager: &mut EpochManager,
+) -> anyhow::Result<(Vec<EpochId>, EpochHeight)> {
+    let epoch_id = match epoch_ref {
+        EpochRef::EpochId(epoch_id) => epoch_id.clone(),
+        EpochRef::BlockHash(block_hash) => {
+            epoch_manager.get_epoch_id(block_hash).map_err(|err| {
+                anyhow::anyhow!("block {} isn't known to the epoch manager: {}", block_hash, err)
+            })?
+        }
+        EpochRef::BlockHeight(height) => {
+            let block_hash = get_block_hash_at_height(chain_store, *height)?;
+            epoch_manager.get_epoch_id(&block_hash).map_err(|err| {
+                anyhow::anyhow!("block {} at height {} has no epoch: {}", block_hash, height, err)
+            })?
+        }
+        EpochRef::EpochHeight(epoch_height) => {
+            let epoch_ids = iterate_and_filter(store.clone(), |epoch_info| {
+                epoch_info.epoch_height() == *epoch_height
+            });
+            if epoch_ids.is_empty() {
+                anyhow::bail!("there's no epoch at epoch height {}", epoch_height);
+            }
+            return Ok((epoch_ids, *epoch_height));
+        }
+    };
+    let epoch_info = epoch_manager
+        .get_epoch_info(&epoch_id)
+        .map_err(|err| anyhow::anyhow!("epoch {} not found: {}", epoch_id.0, err))?;
+    Ok((vec![epoch_id], epoch_info.epoch_height()))
+}
+
+/// Epochs of heights from `prev` epochs before the given one to `next` epochs
+/// after it.
+pub(crate) fn get_adjacent_epoch_ids(
+    epoch_height: EpochHeight,
+    prev: EpochHeight,
+    next: EpochHeight,
+    store: Store,
+) -> Vec<EpochId> {
+    let heights = epoch_height.saturating_sub(prev)..=epoch_height.saturating_add(next);
+    iterate_and_filter(store, |epoch_info| heights.contains(&epoch_info.epoch_height()))
+}
+
+/// What `epoch-info` prints for an epoch in the JSON format.
+#[derive(Serialize)]
+struct EpochReport {
+    epoch_id: CryptoHash,
+    epoch_height: EpochHeight,
+    protocol_version: ProtocolVersion,
+    #[serde(with = "u128_dec_format")]
+    seat_price: Balance,
+    #[serde(with = "u128_dec_format")]
+    minted_amount: Balance,
+    validators: Vec<ValidatorStakeView>,
+    kickouts: BTreeMap<AccountId, ValidatorKickoutReason>,
+    /// Only if a validator was given, and for the epochs before the one of the
+    /// head.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    validator: Option<ValidatorReport>,
+}
+
+#[derive(Serialize)]
+struct ValidatorReport {
+    account_id: AccountId,
+    kickout: Option<ValidatorKickoutReason>,
+    /// `None` if the account didn't validate in the epoch.
+    assignments: Option<ValidatorAssignments>,
+}
+
+#[derive(Serialize)]
+struct ValidatorAssignments {
+    block_heights: Vec<BlockHeight>,
+    chunks: Vec<(BlockHeight, ShardId)>,
+    missing_chunks: Vec<(BlockHeight, ShardId)>,
+}
+
 pub(crate) fn print_epoch_info(
-    epoch_selection: EpochSelection,
+    epoch_ids: Vec<EpochId>,
     validator_account_id: Option<AccountId>,
-    store: Store,
+    upgrade_report: bool,
     chain_store: &mut ChainStore,
     epoch_manager: &mut EpochManager,
     runtime_adapter: Arc<dyn RuntimeAdapter>,
-) {
-    let epoch_ids = get_epoch_ids(epoch_selection, store, chain_store, epoch_manager);
-
-    let head_block_info =
-        epoch_manager.get_block_info(&chain_store.head().unwrap().last_block_hash).unwrap();
+    output: &Output,
+) -> anyhow::Result<()> {
+    let head_block_info = epoch_manager.get_block_info(&chain_store.head()?.last_block_hash)?;
     let head_epoch_height =
-        epoch_manager.get_epoch_info(head_block_info.epoch_id()).unwrap().epoch_height();
+        epoch_manager.get_epoch_info(head_block_info.epoch_id())?.epoch_height();
     let mut epoch_infos: Vec<(EpochId, Arc<EpochInfo>)> = epoch_ids
         .iter()
         .map(|epoch_id| (epoch_id.clone(), epoch_manager.get_epoch_info(&epoch_id).unwrap()))
@@ -51,6 +286,48 @@ pub(crate) fn print_epoch_info(
     // Sorted output is much easier to follow.
     epoch_infos.sort_by_key(|(_, epoch_info)| epoch_info.epoch_height());
 
+    if upgrade_report {
+        let reports: Vec<UpgradeReport> = epoch_infos
+            .iter()
+            .filter(|(_, epoch_info)| epoch_info.epoch_height() <= head_epoch_height)
+            .map(|(epoch_id, epoch_info)| {
+                get_upgrade_report(epoch_id, epoch_info, chain_store, epoch_manager)
+            })
+            .collect();
+        return output.print_json(&reports);
+    }
+
+    if output.is_json() {
+        let reports: Vec<EpochReport> = epoch_infos
+            .iter()
+            .map(|(epoch_id, epoch_info)| EpochReport {
+                epoch_id: epoch_id.0,
+                epoch_height: epoch_info.epoch_height(),
+                protocol_version: epoch_info.protocol_version(),
+                seat_price: epoch_info.seat_price(),
+                minted_amount: epoch_info.minted_amount(),
+                validators: epoch_info.validators_iter().map(ValidatorStakeView::from).collect(),
+                kickouts: epoch_info.validator_kickout().clone().into_iter().collect(),
+                validator: validator_account_id
+                    .as_ref()
+                    .filter(|_| epoch_info.epoch_height() < head_epoch_height)
+                    .map(|account_id| ValidatorReport {
+                        account_id: account_id.clone(),
+                        kickout: epoch_info.validator_kickout().get(account_id).cloned(),
+                        assignments: get_validator_assignments(
+                            epoch_id,
+                            epoch_info,
+                            account_id,
+                            chain_store,
+                            epoch_manager,
+                            runtime_adapter.as_ref(),
+                        ),
+                    }),
+            })
+            .collect();
+        return output.print_json(&reports);
+    }
+
     for (epoch_id, epoch_info) in &epoch_infos {
         println!("-------------------------");
         display_epoch_info(
@@ -65,6 +342,7 @@ pub(crate) fn print_epoch_info(
     }
     println!("=========================");
     println!("Found {} epochs", epoch_ids.len());
+    Ok(())
 }
 
 // Iterate over each epoch starting from the head. Find the requested epoch and its previous epoch
@@ -74,6 +352,17 @@ fn get_block_height_range(
     chain_store: &ChainStore,
     epoch_manager: &mut EpochManager,
 ) -> Range<BlockHeight> {
+    let last_block_info = get_epoch_last_block_info(epoch_info, chain_store, epoch_manager);
+    epoch_manager.get_epoch_start_height(last_block_info.hash()).unwrap()
+        ..(last_block_info.height() + 1)
+}
+
+// Returns the last block of the epoch, or the head if the epoch is the current one.
+fn get_epoch_last_block_info(
+    epoch_info: &EpochInfo,
+    chain_store: &ChainStore,
+    epoch_manager: &mut EpochManager,
+) -> Arc<BlockInfo> {
     let head = chain_store.head().unwrap();
     let mut cur_block_info = epoch_manager.get_block_info(&head.last_block_hash).unwrap();
     loop {
@@ -90,15 +379,140 @@ fn get_block_height_range(
         let prev_epoch_last_block_info =
             epoch_manager.get_block_info(epoch_first_block_info.prev_hash()).unwrap();
         if cur_epoch_height == epoch_info.epoch_height() {
-            return epoch_manager.get_epoch_start_height(cur_block_info.hash()).unwrap()
-                ..(cur_block_info.height() + 1);
+            return cur_block_info;
         }
         cur_block_info = prev_epoch_last_block_info;
     }
 }
 
+/// Stake of the block producers advertising a protocol version.
+#[derive(Serialize)]
+struct VersionStake {
+    protocol_version: ProtocolVersion,
+    #[serde(with = "u128_dec_format")]
+    stake: Balance,
+    /// Fraction of the total block producer stake.
+    stake_fraction: f64,
+    block_producers: Vec<AccountId>,
+}
+
+/// Protocol versions advertised by the block producers of an epoch, weighted
+/// by stake, and what they mean for the protocol version two epochs later.
+#[derive(Serialize)]
+struct UpgradeReport {
+    epoch_id: EpochId,
+    epoch_height: EpochHeight,
+    /// Last block of the epoch taken into account, the head for the current
+    /// epoch.
+    last_block_height: BlockHeight,
+    protocol_version: ProtocolVersion,
+    next_epoch_protocol_version: ProtocolVersion,
+    #[serde(with = "u128_dec_format")]
+    total_block_producer_stake: Balance,
+    /// Fraction of the total block producer stake that has to advertise a
+    /// version for the network to switch to it.
+    stake_threshold: String,
+    /// A version is adopted once more stake than this advertises it.
+    #[serde(with = "u128_dec_format")]
+    stake_needed: Balance,
+    /// Most recent version advertised by each block producer, highest
+    /// version first.
+    versions: Vec<VersionStake>,
+    /// Block producers which haven't produced a block in the epoch yet.
+    not_voted: Vec<AccountId>,
+    #[serde(with = "u128_dec_format")]
+    not_voted_stake: Balance,
+    /// Protocol version of the epoch after the next one if the advertised
+    /// versions don't change until the end of the epoch.
+    projected_protocol_version: ProtocolVersion,
+    /// Whether `projected_protocol_version` is an upgrade over the next epoch.
+    will_switch: bool,
+}
+
+// Tallies the versions the same way `EpochManager` does when finalizing the epoch.
+fn get_upgrade_report(
+    epoch_id: &EpochId,
+    epoch_info: &EpochInfo,
+    chain_store: &ChainStore,
+    epoch_manager: &mut EpochManager,
+) -> UpgradeReport {
+    let last_block_info = get_epoch_last_block_info(epoch_info, chain_store, epoch_manager);
+    let next_epoch_id = epoch_manager.get_next_epoch_id(last_block_info.hash()).unwrap();
+    let next_epoch_info = epoch_manager.get_epoch_info(&next_epoch_id).unwrap();
+    let version_tracker = epoch_manager
+        .get_epoch_info_aggregator_upto_last(last_block_info.hash())
+        .unwrap()
+        .version_tracker;
+
+    let block_producers: BTreeSet<ValidatorId> =
+        epoch_info.block_producers_settlement().iter().copied().collect();
+    let total_block_producer_stake: Balance =
+        block_producers.iter().map(|&id| epoch_info.validator_stake(id)).sum();
+
+    let mut versions: BTreeMap<ProtocolVersion, (Balance, Vec<AccountId>)> = BTreeMap::new();
+    for (&validator_id, &version) in &version_tracker {
+        let (stake, accounts) = versions.entry(version).or_default();
+        *stake += epoch_info.validator_stake(validator_id);
+        accounts.push(epoch_info.validator_account_id(validator_id).clone());
+    }
+    let not_voted: Vec<ValidatorId> =
+        block_producers.into_iter().filter(|id| !version_tracker.contains_key(id)).collect();
+    let not_voted_stake = not_voted.iter().map(|&id| epoch_info.validator_stake(id)).sum();
+
+    let (protocol_version, config_epoch_id) =
+        if epoch_info.protocol_version() >= UPGRADABILITY_FIX_PROTOCOL_VERSION {
+            (next_epoch_info.protocol_version(), &next_epoch_id)
+        } else {
+            (epoch_info.protocol_version(), epoch_id)
+        };
+    let threshold = epoch_manager
+        .get_epoch_config(config_epoch_id)
+        .unwrap()
+        .protocol_upgrade_stake_threshold
+        .clone();
+    let stake_needed =
+        total_block_producer_stake * *threshold.numer() as u128 / *threshold.denom() as u128;
+    let projected_protocol_version = versions
+        .iter()
+        .max_by_key(|(_, (stake, _))| *stake)
+        .filter(|(_, (stake, _))| *stake > stake_needed)
+        .map_or(protocol_version, |(&version, _)| version);
+
+    let versions = versions
+        .into_iter()
+        .rev()
+        .map(|(protocol_version, (stake, mut block_producers))| {
+            block_producers.sort();
+            VersionStake {
+                protocol_version,
+                stake,
+                stake_fraction: stake as f64 / total_block_producer_stake.max(1) as f64,
+                block_producers,
+            }
+        })
+        .collect();
+    UpgradeReport {
+        epoch_id: epoch_id.clone(),
+        epoch_height: epoch_info.epoch_height(),
+        last_block_height: *last_block_info.height(),
+        protocol_version: epoch_info.protocol_version(),
+        next_epoch_protocol_version: next_epoch_info.protocol_version(),
+        total_block_producer_stake,
+        stake_threshold: threshold.to_string(),
+        stake_needed,
+        versions,
+        not_voted: not_voted
+            .into_iter()
+            .map(|id| epoch_info.validator_account_id(id).clone())
+            .collect(),
+        not_voted_stake,
+        projected_protocol_version,
+        will_switch: projected_protocol_version > next_epoch_info.protocol_version(),
+    }
+}
+
 // Converts a bunch of optional filtering options into a vector of EpochIds.
-fn get_epoch_ids(
+pub(crate) fn get_epoch_ids(
     epoch_selection: EpochSelection,
     store: Store,
     chain_store: &mut ChainStore,
@@ -185,6 +599,54 @@ fn display_epoch_info(
     }
 }
 
+/// The blocks and chunks the validator was assigned in the epoch, `None` if
+/// it didn't validate in it.
+fn get_validator_assignments(
+    epoch_id: &EpochId,
+    epoch_info: &EpochInfo,
+    account_id: &AccountId,
+    chain_store: &mut ChainStore,
+    epoch_manager: &mut EpochManager,
+    runtime_adapter: &dyn RuntimeAdapter,
+) -> Option<ValidatorAssignments> {
+    let validator_id = epoch_info.get_validator_id(account_id)?;
+    let block_height_range: Range<BlockHeight> =
+        get_block_height_range(&epoch_info, &chain_store, epoch_manager);
+    let block_heights: Vec<BlockHeight> = block_height_range
+        .clone()
+        .into_iter()
+        .filter(|&block_height| epoch_info.sample_block_producer(block_height) == *validator_id)
+        .collect();
+
+    let shard_ids = 0..runtime_adapter.num_shards(epoch_id).unwrap();
+    let chunks: Vec<(BlockHeight, ShardId)> = block_height_range
+        .clone()
+        .into_iter()
+        .map(|block_height| {
+            shard_ids
+                .clone()
+                .map(|shard_id| (block_height, shard_id))
+                .filter(|&(block_height, shard_id)| {
+                    epoch_info.sample_chunk_producer(block_height, shard_id) == *validator_id
+                })
+                .collect::<Vec<(BlockHeight, ShardId)>>()
+        })
+        .flatten()
+        .collect();
+    let mut missing_chunks = vec![];
+    for &(block_height, shard_id) in &chunks {
+        if let Ok(block_hash) = chain_store.get_block_hash_by_height(block_height) {
+            let block = chain_store.get_block(&block_hash).unwrap();
+            if block.chunks()[shard_id as usize].height_included() != block_height {
+                missing_chunks.push((block_height, shard_id));
+            }
+        } else {
+            missing_chunks.push((block_height, shard_id));
+        }
+    }
+    Some(ValidatorAssignments { block_heights, chunks, missing_chunks })
+}
+
 fn display_validator_info(
     epoch_id: &EpochId,
     epoch_info: &EpochInfo,
@@ -196,49 +658,114 @@ fn display_validator_info(
     if let Some(kickout) = epoch_info.validator_kickout().get(&account_id) {
         println!("Validator {} kickout: {:#?}", account_id, kickout);
     }
-    if let Some(validator_id) = epoch_info.get_validator_id(&account_id) {
-        let block_height_range: Range<BlockHeight> =
-            get_block_height_range(&epoch_info, &chain_store, epoch_manager);
-        let bp_for_blocks: Vec<BlockHeight> = block_height_range
-            .clone()
-            .into_iter()
-            .filter(|&block_height| epoch_info.sample_block_producer(block_height) == *validator_id)
-            .collect();
-        println!("Block producer for {} blocks: {:?}", bp_for_blocks.len(), bp_for_blocks);
-
-        let shard_ids = 0..runtime_adapter.num_shards(epoch_id).unwrap();
-        let cp_for_chunks: Vec<(BlockHeight, ShardId)> = block_height_range
-            .clone()
-            .into_iter()
-            .map(|block_height| {
-                shard_ids
-                    .clone()
-                    .map(|shard_id| (block_height, shard_id))
-                    .filter(|&(block_height, shard_id)| {
-                        epoch_info.sample_chunk_producer(block_height, shard_id) == *validator_id
-                    })
-                    .collect::<Vec<(BlockHeight, ShardId)>>()
-            })
-            .flatten()
-            .collect();
-        println!("Chunk producer for {} chunks: {:?}", cp_for_chunks.len(), cp_for_chunks);
-        let mut missing_chunks = vec![];
-        for (block_height, shard_id) in cp_for_chunks {
-            if let Ok(block_hash) = chain_store.get_block_hash_by_height(block_height) {
-                let block = chain_store.get_block(&block_hash).unwrap();
-                if block.chunks()[shard_id as usize].height_included() != block_height {
-                    missing_chunks.push((block_height, shard_id));
-                }
-            } else {
-                missing_chunks.push((block_height, shard_id));
-            }
+    match get_validator_assignments(
+        epoch_id,
+        epoch_info,
+        &account_id,
+        chain_store,
+        epoch_manager,
+        runtime_adapter.as_ref(),
+    ) {
+        Some(ValidatorAssignments { block_heights, chunks, missing_chunks }) => {
+            println!("Block producer for {} blocks: {:?}", block_heights.len(), block_heights);
+            println!("Chunk producer for {} chunks: {:?}", chunks.len(), chunks);
+            println!("Missing {} chunks: {:?}", missing_chunks.len(), missing_chunks);
         }
-        println!("Missing {} chunks: {:?}", missing_chunks.len(), missing_chunks);
-    } else {
-        println!(
+        None => println!(
             "Validator {} didn't validate in epoch #{}",
             account_id,
             epoch_info.epoch_height()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{failure_kind, OutputFormat};
+    use crate::test_chain::TestChainBuilder;
+
+    #[test]
+    fn test_match_validator() {
+        let candidates: BTreeSet<AccountId> =
+            ["aurora.pool.near", "auroranode.poolv1.near", "figment.poolv1.near", "pool.near"]
+                .iter()
+                .map(|account_id| account_id.parse().unwrap())
+                .collect();
+        let resolve = |query| match_validator(query, &candidates);
+
+        assert_eq!(resolve("figment").unwrap().as_ref(), "figment.poolv1.near");
+        // A prefix wins over a substring, an exact match over a prefix.
+        assert_eq!(resolve("pool").unwrap().as_ref(), "pool.near");
+        assert_eq!(resolve("aurora.").unwrap().as_ref(), "aurora.pool.near");
+
+        let err = resolve("aurora").unwrap_err();
+        assert_eq!(failure_kind(&err), FailureKind::Ambiguous);
+        assert!(err.to_string().contains("aurora.pool.near, auroranode.poolv1.near"), "{}", err);
+        assert_eq!(failure_kind(&resolve("poolv1").unwrap_err()), FailureKind::Ambiguous);
+
+        let err = resolve("chorus").unwrap_err();
+        assert_eq!(failure_kind(&err), FailureKind::InvalidArgument);
+    }
+    #[test]
+    fn test_missed_chunks_and_skipped_heights() {
+        let chain = TestChainBuilder::default().build();
+        let mut chain_store = chain.chain_store();
+        let mut epoch_manager =
+            EpochManager::new_from_genesis_config(chain.store.clone(), &chain.genesis.config)
+                .unwrap();
+        let missed_height = chain.missed_chunks_height.unwrap();
+        let fork_height = chain.fork_height.unwrap();
+
+        let (epoch_ids, _) = resolve_epoch(
+            &EpochRef::BlockHeight(missed_height),
+            &chain.store,
+            &mut chain_store,
+            &mut epoch_manager,
+        )
+        .unwrap();
+        assert_eq!(epoch_ids.len(), 1);
+        let err = resolve_epoch(
+            &EpochRef::BlockHeight(fork_height),
+            &chain.store,
+            &mut chain_store,
+            &mut epoch_manager,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("it was skipped"), "{}", err);
+
+        // The only validator produces all the chunks, and misses all of them at
+        // that height.
+        let epoch_info = epoch_manager.get_epoch_info(&epoch_ids[0]).unwrap();
+        let assignments = get_validator_assignments(
+            &epoch_ids[0],
+            &epoch_info,
+            &chain.accounts[0],
+            &mut chain_store,
+            &mut epoch_manager,
+            chain.runtime.as_ref(),
+        )
+        .unwrap();
+        for shard_id in 0..4 {
+            assert!(assignments.chunks.contains(&(missed_height, shard_id)));
+            assert!(assignments.missing_chunks.contains(&(missed_height, shard_id)));
+        }
+
+        let epoch_ids = get_epoch_ids(
+            EpochSelection::All,
+            chain.store.clone(),
+            &mut chain_store,
+            &mut epoch_manager,
         );
+        print_epoch_info(
+            epoch_ids,
+            Some(chain.accounts[0].clone()),
+            false,
+            &mut chain_store,
+            &mut epoch_manager,
+            chain.runtime.clone(),
+            &Output::new(OutputFormat::Json),
+        )
+        .unwrap();
     }
 }
@@ -0,0 +1,470 @@
+//! Offline epoch sync: exporting a proof of the header chain from an archival
+//! database and bootstrapping a fresh database from it.
+//!
+//! The proof consists of one light client block per epoch, each approved by
+//! the block producers of its epoch and announcing the block producers of the
+//! next one, so it can be checked starting from nothing but the genesis.  It
+//! is followed by the headers of the current epoch up to its light client
+//! block and by the data needed to initialise the epoch manager: the last
+//! header of the previous epoch with its block merkle tree, and the block and
+//! epoch infos committed to in the `epoch_sync_data_hash` of the first header
+//! of the current epoch.
+//!
+//! An import sets the header head to the last block of the previous epoch, so
+//! header sync continues from there and state sync provides the state.
+//! Headers of older epochs aren't stored, a node bootstrapped this way cannot
+//! serve them to its peers.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use borsh::{BorshDeserialize, BorshSerialize};
+use clap::Subcommand;
+
+use near_chain::{
+    create_light_client_block_view, get_epoch_block_producers_view, Chain, ChainGenesis,
+    ChainStore, ChainStoreAccess, DoomslugThresholdMode, RuntimeAdapter,
+};
+use near_primitives::block::{BlockHeader, Tip};
+use near_primitives::epoch_manager::block_info::BlockInfo;
+use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::light_client::verify_light_client_block;
+use near_primitives::merkle::PartialMerkleTree;
+use near_primitives::types::validator_stake::ValidatorStake;
+use near_primitives::types::EpochId;
+use near_primitives::views::LightClientBlockView;
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum EpochSyncAction {
+    /// Write a proof of the header chain up to the current epoch.
+    Export {
+        #[clap(long, parse(from_os_str))]
+        out: PathBuf,
+    },
+    /// Verify a proof and initialise a fresh database from it.
+    Import {
+        #[clap(long, parse(from_os_str))]
+        file: PathBuf,
+    },
+}
+
+impl EpochSyncAction {
+    pub(crate) fn needs_write(&self) -> bool {
+        matches!(self, EpochSyncAction::Import { .. })
+    }
+
+    pub(crate) fn run(self, home_dir: &Path, near_config: NearConfig, store: Store) {
+        let runtime_adapter: Arc<dyn RuntimeAdapter> = Arc::new(NightshadeRuntime::with_config(
+            home_dir,
+            store.clone(),
+            &near_config,
+            None,
+            near_config.client_config.max_gas_burnt_view,
+        ));
+        let result = match self {
+            EpochSyncAction::Export { out } => {
+                let mut chain_store = ChainStore::new(
+                    store,
+                    near_config.genesis.config.genesis_height,
+                    !near_config.client_config.archive,
+                );
+                create_proof(&mut chain_store, runtime_adapter.as_ref()).and_then(|proof| {
+                    std::fs::write(&out, proof.try_to_vec()?)
+                        .with_context(|| format!("failed to write {}", out.display()))?;
+                    println!(
+                        "Wrote a proof of {} epochs to {}",
+                        proof.light_client_blocks.len(),
+                        out.display()
+                    );
+                    Ok(())
+                })
+            }
+            EpochSyncAction::Import { file } => std::fs::read(&file)
+                .with_context(|| format!("failed to read {}", file.display()))
+                .and_then(|bytes| Ok(EpochSyncProof::try_from_slice(&bytes)?))
+                .and_then(|proof| {
+                    let mut chain = Chain::new(
+                        runtime_adapter.clone(),
+                        &ChainGenesis::from(&near_config.genesis),
+                        DoomslugThresholdMode::TwoThirds,
+                        !near_config.client_config.archive,
+                    )?;
+                    let tip = import_proof(&proof, &mut chain, runtime_adapter.as_ref())?;
+                    println!(
+                        "Imported {} epochs, header head is #{} {}",
+                        proof.light_client_blocks.len(),
+                        tip.height,
+                        tip.last_block_hash
+                    );
+                    Ok(())
+                }),
+        };
+        if let Err(err) = result {
+            eprintln!("epoch-sync failed: {:#}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub(crate) struct EpochSyncProof {
+    pub genesis_hash: CryptoHash,
+    /// One block per epoch starting from the genesis one.  The last block is
+    /// from the current, possibly unfinished, epoch.
+    pub light_client_blocks: Vec<LightClientBlockView>,
+    /// Headers of the current epoch from its first block up to the last light
+    /// client block.
+    pub epoch_headers: Vec<BlockHeader>,
+    /// Last block of the previous epoch, where header sync continues from.
+    pub prev_epoch_last_header: BlockHeader,
+    pub prev_epoch_last_header_tree: PartialMerkleTree,
+    pub prev_epoch_first_block_info: BlockInfo,
+    pub prev_epoch_prev_last_block_info: BlockInfo,
+    pub prev_epoch_last_block_info: BlockInfo,
+    pub prev_epoch_info: EpochInfo,
+    pub cur_epoch_info: EpochInfo,
+    pub next_epoch_info: EpochInfo,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum EpochSyncProofError {
+    #[error("proof is for genesis {proof}, but this node's genesis is {local}")]
+    GenesisMismatch { proof: CryptoHash, local: CryptoHash },
+    #[error("proof doesn't cover a single finished epoch")]
+    TooShort,
+    #[error("proof breaks at epoch #{index} ({epoch_id}): {reason}")]
+    InvalidEpoch { index: usize, epoch_id: CryptoHash, reason: String },
+    #[error(transparent)]
+    Chain(#[from] near_chain::Error),
+}
+
+/// Builds the proof for the chain up to its head.
+pub(crate) fn create_proof(
+    chain_store: &mut ChainStore,
+    runtime_adapter: &dyn RuntimeAdapter,
+) -> anyhow::Result<EpochSyncProof> {
+    let genesis_hash = chain_store.get_block_hash_by_height(chain_store.get_genesis_height())?;
+    let head = chain_store.head()?;
+
+    // Walk back over the epochs and collect the last block of each finished one.
+    let mut prev_epoch_last_hashes = vec![];
+    let mut epoch_first_hash = None;
+    let mut block_hash = head.last_block_hash;
+    loop {
+        let start_height = runtime_adapter.get_epoch_start_height(&block_hash)?;
+        let first_hash = chain_store.get_block_hash_by_height(start_height)?;
+        let prev_hash = *chain_store.get_block_header(&first_hash)?.prev_hash();
+        if first_hash == genesis_hash || prev_hash == genesis_hash {
+            break;
+        }
+        epoch_first_hash.get_or_insert(first_hash);
+        prev_epoch_last_hashes.push(prev_hash);
+        block_hash = prev_hash;
+    }
+    let epoch_first_hash =
+        epoch_first_hash.context("the chain hasn't finished its first epoch yet")?;
+    prev_epoch_last_hashes.reverse();
+
+    let mut light_client_blocks = vec![];
+    for last_hash in &prev_epoch_last_hashes {
+        let last_header = chain_store.get_block_header(last_hash)?.clone();
+        let block = Chain::create_light_client_block(&last_header, runtime_adapter, chain_store)?;
+        anyhow::ensure!(
+            block.inner_lite.epoch_id == last_header.epoch_id().0,
+            "epoch {} has no final block followed by two blocks of the same epoch",
+            last_header.epoch_id().0
+        );
+        light_client_blocks.push(block);
+    }
+
+    // In the current epoch take the first block followed by two blocks at
+    // consecutive heights, which is enough to get its approvals.
+    let mut epoch_headers = vec![];
+    let mut header = chain_store.get_block_header(&epoch_first_hash)?.clone();
+    loop {
+        epoch_headers.push(header.clone());
+        let next_hash = *chain_store.get_next_block_hash(header.hash()).with_context(|| {
+            format!(
+                "the current epoch {} has no block with approvals yet, try again later",
+                header.epoch_id().0
+            )
+        })?;
+        let next = chain_store.get_block_header(&next_hash)?.clone();
+        if let Ok(after_next_hash) = chain_store.get_next_block_hash(&next_hash).cloned() {
+            let after_next = chain_store.get_block_header(&after_next_hash)?;
+            if next.height() == header.height() + 1 && after_next.height() == header.height() + 2 {
+                break;
+            }
+        }
+        header = next;
+    }
+    let next_block_producers =
+        get_epoch_block_producers_view(header.next_epoch_id(), header.hash(), runtime_adapter)?;
+    light_client_blocks.push(create_light_client_block_view(
+        &header,
+        chain_store,
+        Some(next_block_producers),
+    )?);
+
+    let epoch_first_header = &epoch_headers[0];
+    let prev_epoch_last_hash = *epoch_first_header.prev_hash();
+    let prev_epoch_last_header = chain_store.get_block_header(&prev_epoch_last_hash)?.clone();
+    let prev_epoch_last_header_tree =
+        chain_store.get_block_merkle_tree(&prev_epoch_last_hash)?.clone();
+    let (
+        prev_epoch_first_block_info,
+        prev_epoch_prev_last_block_info,
+        prev_epoch_last_block_info,
+        prev_epoch_info,
+        cur_epoch_info,
+        next_epoch_info,
+    ) = runtime_adapter.get_epoch_sync_data(
+        &prev_epoch_last_hash,
+        epoch_first_header.epoch_id(),
+        epoch_first_header.next_epoch_id(),
+    )?;
+
+    Ok(EpochSyncProof {
+        genesis_hash,
+        light_client_blocks,
+        epoch_headers,
+        prev_epoch_last_header,
+        prev_epoch_last_header_tree,
+        prev_epoch_first_block_info: prev_epoch_first_block_info.as_ref().clone(),
+        prev_epoch_prev_last_block_info: prev_epoch_prev_last_block_info.as_ref().clone(),
+        prev_epoch_last_block_info: prev_epoch_last_block_info.as_ref().clone(),
+        prev_epoch_info: prev_epoch_info.as_ref().clone(),
+        cur_epoch_info: cur_epoch_info.as_ref().clone(),
+        next_epoch_info: next_epoch_info.as_ref().clone(),
+    })
+}
+
+/// Checks the proof against the genesis block producers of this node.
+pub(crate) fn verify_proof(
+    proof: &EpochSyncProof,
+    genesis_hash: &CryptoHash,
+    runtime_adapter: &dyn RuntimeAdapter,
+) -> Result<(), EpochSyncProofError> {
+    if &proof.genesis_hash != genesis_hash {
+        return Err(EpochSyncProofError::GenesisMismatch {
+            proof: proof.genesis_hash,
+            local: *genesis_hash,
+        });
+    }
+    if proof.light_client_blocks.len() < 2 {
+        return Err(EpochSyncProofError::TooShort);
+    }
+
+    let mut epoch_id = EpochId::default();
+    let mut block_producers: Vec<ValidatorStake> = runtime_adapter
+        .get_epoch_block_producers_ordered(&epoch_id, genesis_hash)?
+        .into_iter()
+        .map(|(bp, _)| bp)
+        .collect();
+    let mut last_epoch_id = epoch_id.clone();
+    let mut last_block_hash = CryptoHash::default();
+    for (index, block) in proof.light_client_blocks.iter().enumerate() {
+        let invalid = |reason: String| EpochSyncProofError::InvalidEpoch {
+            index,
+            epoch_id: epoch_id.0,
+            reason,
+        };
+        if block.inner_lite.epoch_id != epoch_id.0 {
+            return Err(invalid(format!(
+                "block #{} is from epoch {}",
+                block.inner_lite.height, block.inner_lite.epoch_id
+            )));
+        }
+        if block.next_bps.is_none() {
+            return Err(invalid(format!(
+                "block #{} has no next block producers",
+                block.inner_lite.height
+            )));
+        }
+        last_block_hash = verify_light_client_block(block, &block_producers).map_err(invalid)?;
+        last_epoch_id = epoch_id;
+        epoch_id = EpochId(block.inner_lite.next_epoch_id);
+        block_producers = block
+            .next_bps
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .map(ValidatorStake::from)
+            .collect();
+    }
+
+    // The epoch manager data belongs to the current epoch, the one of the
+    // last light client block.
+    let index = proof.light_client_blocks.len() - 1;
+    let epoch_id = last_epoch_id;
+    let invalid = |reason: &str| EpochSyncProofError::InvalidEpoch {
+        index,
+        epoch_id: epoch_id.0,
+        reason: reason.to_string(),
+    };
+    let headers = &proof.epoch_headers;
+    let first_header = headers.first().ok_or_else(|| invalid("no headers of the epoch"))?;
+    if headers.last().unwrap().hash() != &last_block_hash {
+        return Err(invalid("headers don't end at the light client block"));
+    }
+    if headers.windows(2).any(|pair| pair[1].prev_hash() != pair[0].hash()) {
+        return Err(invalid("headers don't form a chain"));
+    }
+    if first_header.epoch_id() != &epoch_id {
+        return Err(invalid("first header is from another epoch"));
+    }
+    let last_header = &proof.prev_epoch_last_header;
+    if last_header.hash() != first_header.prev_hash()
+        || last_header.epoch_id().0 != proof.light_client_blocks[index - 1].inner_lite.epoch_id
+    {
+        return Err(invalid("previous header isn't the last block of the previous epoch"));
+    }
+    let mut block_merkle_tree = proof.prev_epoch_last_header_tree.clone();
+    block_merkle_tree.insert(*last_header.hash());
+    if &block_merkle_tree.root() != first_header.block_merkle_root() {
+        return Err(invalid("block merkle tree doesn't match the first header"));
+    }
+    let mut data = proof.prev_epoch_first_block_info.try_to_vec().unwrap();
+    data.extend(proof.prev_epoch_prev_last_block_info.try_to_vec().unwrap());
+    data.extend(proof.prev_epoch_last_block_info.try_to_vec().unwrap());
+    data.extend(proof.prev_epoch_info.try_to_vec().unwrap());
+    data.extend(proof.cur_epoch_info.try_to_vec().unwrap());
+    data.extend(proof.next_epoch_info.try_to_vec().unwrap());
+    match first_header.epoch_sync_data_hash() {
+        Some(data_hash) if data_hash == hash(&data) => Ok(()),
+        Some(_) => Err(invalid("epoch sync data doesn't match the first header")),
+        None => Err(invalid("first header has no epoch sync data hash")),
+    }
+}
+
+/// Verifies the proof and sets up the chain so that header sync continues at
+/// the start of the current epoch.  The chain must contain only the genesis.
+pub(crate) fn import_proof(
+    proof: &EpochSyncProof,
+    chain: &mut Chain,
+    runtime_adapter: &dyn RuntimeAdapter,
+) -> anyhow::Result<Tip> {
+    let header_head = chain.header_head()?;
+    anyhow::ensure!(
+        header_head.height == chain.genesis().height(),
+        "the database already has headers up to #{}, epoch sync needs a fresh one",
+        header_head.height
+    );
+    verify_proof(proof, chain.genesis().hash(), runtime_adapter)?;
+
+    let first_header = &proof.epoch_headers[0];
+    let last_header = &proof.prev_epoch_last_header;
+    runtime_adapter.epoch_sync_init_epoch_manager(
+        proof.prev_epoch_first_block_info.clone(),
+        proof.prev_epoch_prev_last_block_info.clone(),
+        proof.prev_epoch_last_block_info.clone(),
+        last_header.epoch_id(),
+        proof.prev_epoch_info.clone(),
+        first_header.epoch_id(),
+        proof.cur_epoch_info.clone(),
+        first_header.next_epoch_id(),
+        proof.next_epoch_info.clone(),
+    )?;
+
+    let tip = Tip::from_header(last_header);
+    let mut chain_store_update = chain.mut_store().store_update();
+    chain_store_update.save_block_header_no_update_tree(last_header.clone())?;
+    chain_store_update
+        .save_block_merkle_tree(*last_header.hash(), proof.prev_epoch_last_header_tree.clone());
+    chain_store_update.force_save_header_head(&tip)?;
+    chain_store_update.commit()?;
+    Ok(tip)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use near_chain::{Chain, ChainGenesis, DoomslugThresholdMode, RuntimeAdapter};
+    use near_chain_configs::Genesis;
+    use near_client::test_utils::TestEnv;
+    use near_store::test_utils::create_test_store;
+    use nearcore::config::GenesisExt;
+    use nearcore::NightshadeRuntime;
+
+    use super::{create_proof, import_proof, verify_proof, EpochSyncProof, EpochSyncProofError};
+
+    const EPOCH_LENGTH: u64 = 5;
+
+    fn setup() -> (Genesis, ChainGenesis, EpochSyncProof) {
+        let mut genesis = Genesis::test(vec!["test0".parse().unwrap()], 1);
+        genesis.config.epoch_length = EPOCH_LENGTH;
+        let runtime = NightshadeRuntime::test(Path::new("."), create_test_store(), &genesis);
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.epoch_length = EPOCH_LENGTH;
+        chain_genesis.gas_limit = genesis.config.gas_limit;
+        let mut env = TestEnv::builder(chain_genesis.clone())
+            .runtime_adapters(vec![Arc::new(runtime)])
+            .build();
+        for height in 1..=EPOCH_LENGTH * 3 + 3 {
+            env.produce_block(0, height);
+        }
+        let client = &mut env.clients[0];
+        let runtime_adapter = client.runtime_adapter.clone();
+        let proof = create_proof(client.chain.mut_store(), runtime_adapter.as_ref()).unwrap();
+        (genesis, chain_genesis, proof)
+    }
+
+    fn fresh_chain(
+        genesis: &Genesis,
+        chain_genesis: &ChainGenesis,
+    ) -> (Chain, Arc<dyn RuntimeAdapter>) {
+        let runtime: Arc<dyn RuntimeAdapter> =
+            Arc::new(NightshadeRuntime::test(Path::new("."), create_test_store(), genesis));
+        let chain =
+            Chain::new(runtime.clone(), chain_genesis, DoomslugThresholdMode::TwoThirds, true)
+                .unwrap();
+        (chain, runtime)
+    }
+
+    #[test]
+    fn test_epoch_sync_export_import() {
+        let (genesis, chain_genesis, proof) = setup();
+        assert_eq!(proof.light_client_blocks.len(), 4);
+
+        let (mut chain, runtime) = fresh_chain(&genesis, &chain_genesis);
+        let tip = import_proof(&proof, &mut chain, runtime.as_ref()).unwrap();
+        assert_eq!(&tip.last_block_hash, proof.prev_epoch_last_header.hash());
+        assert_eq!(chain.header_head().unwrap(), tip);
+        let first_header = &proof.epoch_headers[0];
+        assert_eq!(
+            runtime.get_epoch_id_from_prev_block(&tip.last_block_hash).unwrap(),
+            first_header.epoch_id().clone()
+        );
+
+        // A second import would overwrite the headers.
+        assert!(import_proof(&proof, &mut chain, runtime.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_epoch_sync_invalid_approval() {
+        let (genesis, chain_genesis, mut proof) = setup();
+        // Use an approval of another block.
+        let approval = proof.light_client_blocks[1].approvals_after_next[0].clone();
+        assert!(approval.is_some());
+        let block = &mut proof.light_client_blocks[2];
+        let epoch_id = block.inner_lite.epoch_id;
+        block.approvals_after_next[0] = approval;
+
+        let (chain, runtime) = fresh_chain(&genesis, &chain_genesis);
+        match verify_proof(&proof, chain.genesis().hash(), runtime.as_ref()) {
+            Err(EpochSyncProofError::InvalidEpoch {
+                index, epoch_id: invalid_epoch_id, ..
+            }) => {
+                assert_eq!(index, 2);
+                assert_eq!(invalid_epoch_id, epoch_id);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
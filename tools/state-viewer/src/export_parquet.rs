@@ -0,0 +1,725 @@
+//! Export of the chain and state data to Parquet files, for the analytics
+//! pipelines.
+//!
+//! The rows of an entity are written to files of `partition_size` heights,
+//! aligned on multiples of it, in a directory of the entity, next to a
+//! `manifest.json` listing the partitions written with their heights and
+//! number of rows.  A partition is written to a temporary file renamed once
+//! complete, and added to the manifest then, so an interrupted export started
+//! again skips the partitions it already wrote.  The accounts are a snapshot
+//! of the state, written to a file per shard.
+//!
+//! The schemas are flat: hashes and public keys are hex and base58 strings,
+//! balances decimal strings since they don't fit an `INT64`, and the actions
+//! are summed up by their kinds, methods, deposits and gas.  See the README
+//! for the columns.
+
+use crate::commands::{load_trie_stop_at_height, LoadTrieMode};
+use crate::output::{failure, FailureKind, Output};
+use crate::parquet::{column, Column, ColumnType, ParquetWriter, Value};
+use borsh::BorshDeserialize;
+use near_chain::{ChainStore, ChainStoreAccess, RuntimeAdapter};
+use near_primitives::account::Account;
+use near_primitives::block::Block;
+use near_primitives::receipt::{Receipt, ReceiptEnum};
+use near_primitives::transaction::{Action, ExecutionStatus, SignedTransaction};
+use near_primitives::trie_key::{col, trie_key_parsers};
+use near_primitives::types::{BlockHeight, ShardId};
+use near_store::{Store, TrieIterator};
+use nearcore::{NearConfig, NightshadeRuntime};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExportEntity {
+    Blocks,
+    Transactions,
+    Receipts,
+    Outcomes,
+    Accounts,
+}
+
+impl ExportEntity {
+    fn name(self) -> &'static str {
+        match self {
+            ExportEntity::Blocks => "blocks",
+            ExportEntity::Transactions => "transactions",
+            ExportEntity::Receipts => "receipts",
+            ExportEntity::Outcomes => "outcomes",
+            ExportEntity::Accounts => "accounts",
+        }
+    }
+
+    fn columns(self) -> Vec<Column> {
+        use ColumnType::{Int64, Utf8};
+        let columns: &[(&str, ColumnType)] = match self {
+            ExportEntity::Blocks => &[
+                ("height", Int64),
+                ("hash", Utf8),
+                ("prev_hash", Utf8),
+                ("epoch_id", Utf8),
+                ("timestamp_nanos", Int64),
+                ("author", Utf8),
+                ("gas_price", Utf8),
+                ("total_supply", Utf8),
+                ("chunks_included", Int64),
+                ("gas_used", Int64),
+                ("gas_limit", Int64),
+            ],
+            ExportEntity::Transactions => &[
+                ("block_height", Int64),
+                ("block_hash", Utf8),
+                ("shard_id", Int64),
+                ("chunk_hash", Utf8),
+                ("hash", Utf8),
+                ("signer_id", Utf8),
+                ("public_key", Utf8),
+                ("nonce", Int64),
+                ("receiver_id", Utf8),
+                ("num_actions", Int64),
+                ("action_kinds", Utf8),
+                ("method_names", Utf8),
+                ("deposit", Utf8),
+                ("attached_gas", Int64),
+            ],
+            ExportEntity::Receipts => &[
+                ("block_height", Int64),
+                ("block_hash", Utf8),
+                ("shard_id", Int64),
+                ("receipt_id", Utf8),
+                ("predecessor_id", Utf8),
+                ("receiver_id", Utf8),
+                ("kind", Utf8),
+                ("signer_id", Utf8),
+                ("num_actions", Int64),
+                ("action_kinds", Utf8),
+                ("method_names", Utf8),
+                ("deposit", Utf8),
+                ("attached_gas", Int64),
+            ],
+            ExportEntity::Outcomes => &[
+                ("block_height", Int64),
+                ("block_hash", Utf8),
+                ("shard_id", Int64),
+                ("id", Utf8),
+                ("executor_id", Utf8),
+                ("status", Utf8),
+                ("gas_burnt", Int64),
+                ("tokens_burnt", Utf8),
+                ("num_receipts", Int64),
+                ("num_logs", Int64),
+            ],
+            ExportEntity::Accounts => &[
+                ("height", Int64),
+                ("shard_id", Int64),
+                ("account_id", Utf8),
+                ("amount", Utf8),
+                ("locked", Utf8),
+                ("code_hash", Utf8),
+                ("storage_usage", Int64),
+            ],
+        };
+        columns.iter().map(|(name, column_type)| column(name, *column_type)).collect()
+    }
+}
+
+/// The partitions of an entity written in its directory.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Manifest {
+    entity: ExportEntity,
+    partition_size: BlockHeight,
+    columns: Vec<Column>,
+    /// Sorted by file.
+    partitions: Vec<PartitionEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct PartitionEntry {
+    file: String,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    rows: u64,
+}
+
+/// The heights of a file, the part of its aligned range which was asked for.
+#[derive(Debug, PartialEq)]
+struct Partition {
+    file: String,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+}
+
+impl Manifest {
+    /// The manifest of the directory if there's one, which must have been
+    /// written with the same schema and partitions.
+    fn load_or_new(
+        dir: &Path,
+        entity: ExportEntity,
+        partition_size: BlockHeight,
+    ) -> anyhow::Result<Self> {
+        let new =
+            Manifest { entity, partition_size, columns: entity.columns(), partitions: vec![] };
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(new);
+        }
+        let manifest: Manifest = serde_json::from_slice(&fs::read(&path)?)?;
+        if manifest.columns != new.columns || manifest.entity != entity {
+            return Err(failure(
+                FailureKind::InvalidArgument,
+                format!(
+                    "{} was written with another schema, export to a new directory",
+                    path.display()
+                ),
+            ));
+        }
+        if entity != ExportEntity::Accounts && manifest.partition_size != partition_size {
+            return Err(failure(
+                FailureKind::InvalidArgument,
+                format!(
+                    "{} was written with --partition-size {}",
+                    path.display(),
+                    manifest.partition_size
+                ),
+            ));
+        }
+        Ok(manifest)
+    }
+
+    /// Whether the partition was written already, and is still there.
+    fn contains(&self, dir: &Path, partition: &Partition) -> bool {
+        self.partitions.iter().any(|entry| {
+            entry.file == partition.file
+                && entry.from_height <= partition.from_height
+                && entry.to_height >= partition.to_height
+        }) && dir.join(&partition.file).exists()
+    }
+
+    fn insert(&mut self, entry: PartitionEntry) {
+        self.partitions.retain(|old| old.file != entry.file);
+        self.partitions.push(entry);
+        self.partitions.sort_by(|a, b| a.file.cmp(&b.file));
+    }
+
+    fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp, dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+}
+
+/// The files covering the heights, one per multiple of `size`.
+fn partitions(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    size: BlockHeight,
+) -> Vec<Partition> {
+    let mut partitions = vec![];
+    let mut start = from_height - from_height % size;
+    while start <= to_height {
+        let end = start.saturating_add(size - 1);
+        partitions.push(Partition {
+            file: format!("{:012}-{:012}.parquet", start, end),
+            from_height: from_height.max(start),
+            to_height: to_height.min(end),
+        });
+        start = match end.checked_add(1) {
+            Some(start) => start,
+            None => break,
+        };
+    }
+    partitions
+}
+
+#[derive(Serialize)]
+struct ExportReport {
+    entity: ExportEntity,
+    dir: PathBuf,
+    partitions_written: u64,
+    partitions_skipped: u64,
+    rows: u64,
+    /// Chunks of the blocks exported which aren't in the database, whose
+    /// transactions and receipts are missing from the export.
+    missing_chunks: u64,
+}
+
+impl fmt::Display for ExportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Exported {} rows of {} in {} partitions to {}, {} partitions were already there",
+            self.rows,
+            self.entity.name(),
+            self.partitions_written,
+            self.dir.display(),
+            self.partitions_skipped
+        )?;
+        if self.missing_chunks > 0 {
+            write!(
+                f,
+                "\n{} chunks were not found, is this an archival node?",
+                self.missing_chunks
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the rows `fill` writes to the file of the partition, returns their
+/// number.
+fn write_partition(
+    dir: &Path,
+    file: &str,
+    columns: Vec<Column>,
+    fill: impl FnOnce(&mut ParquetWriter<BufWriter<File>>) -> anyhow::Result<()>,
+) -> anyhow::Result<u64> {
+    let path = dir.join(file);
+    let tmp = dir.join(format!("{}.tmp", file));
+    let mut writer = ParquetWriter::new(BufWriter::new(File::create(&tmp)?), columns)?;
+    fill(&mut writer)?;
+    let rows = writer.finish()?;
+    fs::rename(&tmp, &path)?;
+    Ok(rows)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn export_parquet(
+    entity: ExportEntity,
+    from_height: Option<BlockHeight>,
+    to_height: BlockHeight,
+    partition_size: BlockHeight,
+    out_dir: &Path,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if partition_size == 0 {
+        return Err(failure(FailureKind::InvalidArgument, "--partition-size must be positive"));
+    }
+    let dir = out_dir.join(entity.name());
+    fs::create_dir_all(&dir)?;
+    let mut manifest = Manifest::load_or_new(&dir, entity, partition_size)?;
+    let mut report = ExportReport {
+        entity,
+        dir: dir.clone(),
+        partitions_written: 0,
+        partitions_skipped: 0,
+        rows: 0,
+        missing_chunks: 0,
+    };
+    if entity == ExportEntity::Accounts {
+        export_accounts(to_height, &dir, &mut manifest, &mut report, home_dir, near_config, store)?;
+        return output.print(&report);
+    }
+
+    let from_height = from_height.ok_or_else(|| {
+        failure(FailureKind::InvalidArgument, "--from-height is required but for the accounts")
+    })?;
+    if from_height > to_height {
+        return Err(failure(
+            FailureKind::InvalidArgument,
+            format!("--from-height {} is above --to-height {}", from_height, to_height),
+        ));
+    }
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store.clone(),
+        &near_config,
+        near_config.client_config.trie_viewer_state_size_limit,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let mut exporter = ChainExporter {
+        chain_store: ChainStore::new(
+            store,
+            near_config.genesis.config.genesis_height,
+            !near_config.client_config.archive,
+        ),
+        runtime,
+        missing_chunks: 0,
+    };
+    for partition in partitions(from_height, to_height, partition_size) {
+        if manifest.contains(&dir, &partition) {
+            report.partitions_skipped += 1;
+            continue;
+        }
+        eprintln!("Exporting {} of heights {}", entity.name(), partition.file);
+        let rows = write_partition(&dir, &partition.file, entity.columns(), |writer| {
+            for height in partition.from_height..=partition.to_height {
+                exporter.export_height(entity, height, writer)?;
+            }
+            Ok(())
+        })?;
+        manifest.insert(PartitionEntry {
+            file: partition.file,
+            from_height: partition.from_height,
+            to_height: partition.to_height,
+            rows,
+        });
+        manifest.save(&dir)?;
+        report.partitions_written += 1;
+        report.rows += rows;
+    }
+    report.missing_chunks = exporter.missing_chunks;
+    output.print(&report)
+}
+
+/// Writes the accounts of each shard in the state before the block at
+/// `height` to a file of its own.
+fn export_accounts(
+    height: BlockHeight,
+    dir: &Path,
+    manifest: &mut Manifest,
+    report: &mut ExportReport,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    let (runtime, state_roots, header) =
+        load_trie_stop_at_height(store, home_dir, &near_config, LoadTrieMode::Height(height));
+    let height = header.height();
+    for (shard_id, state_root) in state_roots.iter().enumerate() {
+        let shard_id = shard_id as ShardId;
+        let partition = Partition {
+            file: format!("{:012}-shard{}.parquet", height, shard_id),
+            from_height: height,
+            to_height: height,
+        };
+        if manifest.contains(dir, &partition) {
+            report.partitions_skipped += 1;
+            continue;
+        }
+        eprintln!("Exporting the accounts of shard {}", shard_id);
+        let trie = runtime.get_trie_for_shard(shard_id, header.prev_hash())?;
+        let rows =
+            write_partition(dir, &partition.file, ExportEntity::Accounts.columns(), |writer| {
+                let mut iter = TrieIterator::new(&trie, state_root)?;
+                iter.seek(&[col::ACCOUNT])?;
+                for item in iter {
+                    let (key, value) = item?;
+                    if key.first() != Some(&col::ACCOUNT) {
+                        break;
+                    }
+                    let account_id = trie_key_parsers::parse_account_id_from_account_key(&key)?;
+                    let account = Account::try_from_slice(&value)?;
+                    writer.write_row(vec![
+                        height.into(),
+                        shard_id.into(),
+                        account_id.as_ref().into(),
+                        account.amount().to_string().into(),
+                        account.locked().to_string().into(),
+                        hex(account.code_hash().as_ref()).into(),
+                        account.storage_usage().into(),
+                    ])?;
+                }
+                Ok(())
+            })?;
+        manifest.insert(PartitionEntry {
+            file: partition.file,
+            from_height: height,
+            to_height: height,
+            rows,
+        });
+        manifest.save(dir)?;
+        report.partitions_written += 1;
+        report.rows += rows;
+    }
+    Ok(())
+}
+
+struct ChainExporter {
+    chain_store: ChainStore,
+    runtime: NightshadeRuntime,
+    missing_chunks: u64,
+}
+
+impl ChainExporter {
+    /// Writes the rows of the block at the height, if there's one.
+    fn export_height(
+        &mut self,
+        entity: ExportEntity,
+        height: BlockHeight,
+        writer: &mut ParquetWriter<impl Write>,
+    ) -> anyhow::Result<()> {
+        let block_hash = match self.chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            Err(_) => return Ok(()),
+        };
+        let block = self.chain_store.get_block(&block_hash)?.clone();
+        match entity {
+            ExportEntity::Blocks => {
+                let author = self.runtime.get_block_producer(block.header().epoch_id(), height)?;
+                writer.write_row(block_row(&block, author.as_ref()))?;
+            }
+            ExportEntity::Transactions | ExportEntity::Receipts => {
+                for chunk_header in block.chunks().iter() {
+                    if chunk_header.height_included() != height {
+                        continue;
+                    }
+                    let chunk = match self.chain_store.get_chunk(&chunk_header.chunk_hash()) {
+                        Ok(chunk) => chunk.clone(),
+                        Err(_) => {
+                            self.missing_chunks += 1;
+                            continue;
+                        }
+                    };
+                    let shard_id = chunk_header.shard_id();
+                    if entity == ExportEntity::Transactions {
+                        for transaction in chunk.transactions() {
+                            let chunk_hash = hex(chunk_header.chunk_hash().as_ref());
+                            writer.write_row(transaction_row(
+                                &block,
+                                shard_id,
+                                chunk_hash,
+                                transaction,
+                            ))?;
+                        }
+                    } else {
+                        for receipt in chunk.receipts() {
+                            writer.write_row(receipt_row(&block, shard_id, receipt))?;
+                        }
+                    }
+                }
+            }
+            ExportEntity::Outcomes => {
+                for shard_id in 0..block.chunks().len() as ShardId {
+                    let ids = self
+                        .chain_store
+                        .get_outcomes_by_block_hash_and_shard_id(&block_hash, shard_id)?;
+                    for id in ids {
+                        let outcome = self
+                            .chain_store
+                            .get_outcomes_by_id(&id)?
+                            .into_iter()
+                            .find(|outcome| outcome.block_hash == block_hash);
+                        let outcome = match outcome {
+                            Some(outcome) => outcome.outcome_with_id.outcome,
+                            None => continue,
+                        };
+                        writer.write_row(vec![
+                            height.into(),
+                            hex(block_hash.as_ref()).into(),
+                            shard_id.into(),
+                            hex(id.as_ref()).into(),
+                            outcome.executor_id.as_ref().into(),
+                            status_name(&outcome.status).into(),
+                            outcome.gas_burnt.into(),
+                            outcome.tokens_burnt.to_string().into(),
+                            (outcome.receipt_ids.len() as u64).into(),
+                            (outcome.logs.len() as u64).into(),
+                        ])?;
+                    }
+                }
+            }
+            ExportEntity::Accounts => unreachable!("the accounts aren't exported by height"),
+        }
+        Ok(())
+    }
+}
+
+fn block_row(block: &Block, author: &str) -> Vec<Value> {
+    let header = block.header();
+    let (mut gas_used, mut gas_limit) = (0, 0);
+    for chunk_header in block.chunks().iter() {
+        if chunk_header.height_included() == header.height() {
+            gas_used += chunk_header.gas_used();
+            gas_limit += chunk_header.gas_limit();
+        }
+    }
+    vec![
+        header.height().into(),
+        hex(header.hash().as_ref()).into(),
+        hex(header.prev_hash().as_ref()).into(),
+        hex(header.epoch_id().0.as_ref()).into(),
+        header.raw_timestamp().into(),
+        author.into(),
+        header.gas_price().to_string().into(),
+        header.total_supply().to_string().into(),
+        header.chunks_included().into(),
+        gas_used.into(),
+        gas_limit.into(),
+    ]
+}
+
+fn transaction_row(
+    block: &Block,
+    shard_id: ShardId,
+    chunk_hash: String,
+    signed: &SignedTransaction,
+) -> Vec<Value> {
+    let transaction = &signed.transaction;
+    let mut row = vec![
+        block.header().height().into(),
+        hex(block.hash().as_ref()).into(),
+        shard_id.into(),
+        chunk_hash.into(),
+        hex(signed.get_hash().as_ref()).into(),
+        transaction.signer_id.as_ref().into(),
+        transaction.public_key.to_string().into(),
+        transaction.nonce.into(),
+        transaction.receiver_id.as_ref().into(),
+    ];
+    row.extend(actions_summary(&transaction.actions));
+    row
+}
+
+fn receipt_row(block: &Block, shard_id: ShardId, receipt: &Receipt) -> Vec<Value> {
+    let mut row = vec![
+        block.header().height().into(),
+        hex(block.hash().as_ref()).into(),
+        shard_id.into(),
+        hex(receipt.receipt_id.as_ref()).into(),
+        receipt.predecessor_id.as_ref().into(),
+        receipt.receiver_id.as_ref().into(),
+    ];
+    match &receipt.receipt {
+        ReceiptEnum::Action(action_receipt) => {
+            row.push("action".into());
+            row.push(action_receipt.signer_id.as_ref().into());
+            row.extend(actions_summary(&action_receipt.actions));
+        }
+        ReceiptEnum::Data(_) => {
+            row.push("data".into());
+            row.push("".into());
+            row.extend(actions_summary(&[]));
+        }
+    }
+    row
+}
+
+/// The number of actions, their kinds and the methods called, comma
+/// separated, the total deposit and the gas attached.
+fn actions_summary(actions: &[Action]) -> Vec<Value> {
+    let kinds: Vec<&str> = actions.iter().map(action_kind).collect();
+    let methods: Vec<&str> = actions
+        .iter()
+        .filter_map(|action| match action {
+            Action::FunctionCall(call) => Some(call.method_name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let deposit: u128 = actions.iter().map(Action::get_deposit_balance).sum();
+    let gas: u64 = actions.iter().map(Action::get_prepaid_gas).sum();
+    vec![
+        (actions.len() as u64).into(),
+        kinds.join(",").into(),
+        methods.join(",").into(),
+        deposit.to_string().into(),
+        gas.into(),
+    ]
+}
+
+fn action_kind(action: &Action) -> &'static str {
+    match action {
+        Action::CreateAccount(_) => "CreateAccount",
+        Action::DeployContract(_) => "DeployContract",
+        Action::FunctionCall(_) => "FunctionCall",
+        Action::Transfer(_) => "Transfer",
+        Action::Stake(_) => "Stake",
+        Action::AddKey(_) => "AddKey",
+        Action::DeleteKey(_) => "DeleteKey",
+        Action::DeleteAccount(_) => "DeleteAccount",
+        #[cfg(feature = "protocol_feature_chunk_only_producers")]
+        Action::StakeChunkOnly(_) => "StakeChunkOnly",
+    }
+}
+
+fn status_name(status: &ExecutionStatus) -> &'static str {
+    match status {
+        ExecutionStatus::Unknown => "unknown",
+        ExecutionStatus::Failure(_) => "failure",
+        ExecutionStatus::SuccessValue(_) => "success_value",
+        ExecutionStatus::SuccessReceiptId(_) => "success_receipt_id",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partitions() {
+        let files = |from, to, size| {
+            partitions(from, to, size)
+                .into_iter()
+                .map(|p| (p.file, p.from_height, p.to_height))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            files(15, 32, 10),
+            [
+                ("000000000010-000000000019.parquet".to_string(), 15, 19),
+                ("000000000020-000000000029.parquet".to_string(), 20, 29),
+                ("000000000030-000000000039.parquet".to_string(), 30, 32),
+            ]
+        );
+        assert_eq!(files(20, 20, 10), [("000000000020-000000000029.parquet".to_string(), 20, 20)]);
+    }
+
+    #[test]
+    fn test_manifest_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manifest = Manifest::load_or_new(dir.path(), ExportEntity::Blocks, 10).unwrap();
+        let mut parts = partitions(15, 32, 10).into_iter();
+        let first = parts.next().unwrap();
+        let last = parts.last().unwrap();
+        for partition in [&first, &last] {
+            fs::write(dir.path().join(&partition.file), b"").unwrap();
+            manifest.insert(PartitionEntry {
+                file: partition.file.clone(),
+                from_height: partition.from_height,
+                to_height: partition.to_height,
+                rows: 1,
+            });
+        }
+        manifest.save(dir.path()).unwrap();
+
+        let manifest = Manifest::load_or_new(dir.path(), ExportEntity::Blocks, 10).unwrap();
+        assert!(manifest.contains(dir.path(), &first));
+        assert!(manifest.contains(dir.path(), &last));
+        // The last partition is written again when more of its heights are
+        // asked for.
+        let longer = partitions(15, 35, 10).pop().unwrap();
+        assert!(!manifest.contains(dir.path(), &longer));
+        fs::remove_file(dir.path().join(&first.file)).unwrap();
+        assert!(!manifest.contains(dir.path(), &first));
+
+        let err = Manifest::load_or_new(dir.path(), ExportEntity::Blocks, 100).unwrap_err();
+        assert_eq!(crate::output::failure_kind(&err), FailureKind::InvalidArgument);
+    }
+
+    #[test]
+    fn test_actions_summary() {
+        use near_primitives::transaction::{FunctionCallAction, TransferAction};
+        let actions = [
+            Action::Transfer(TransferAction { deposit: 10 }),
+            Action::FunctionCall(FunctionCallAction {
+                method_name: "ft_transfer".to_string(),
+                args: vec![],
+                gas: 30,
+                deposit: 1,
+            }),
+        ];
+        let summary = actions_summary(&actions);
+        let strings: Vec<String> = summary
+            .iter()
+            .map(|value| match value {
+                Value::UInt64(value) => value.to_string(),
+                Value::Utf8(value) => value.clone(),
+            })
+            .collect();
+        assert_eq!(strings, ["2", "Transfer,FunctionCall", "ft_transfer", "11", "30"]);
+        assert_eq!(hex(&[0, 0xab]), "00ab");
+    }
+}
@@ -0,0 +1,190 @@
+//! Extraction of the transactions included in a range of blocks, to be
+//! replayed on a forknet.
+//!
+//! The output file is a sequence of records, each a little endian `u32`
+//! length followed by a Borsh serialized [`ExtractedTransaction`].  Next to it
+//! a `.summary.json` file lists the number of transactions and the timestamp
+//! of every block, so that a replayer can keep the original pacing.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_crypto::{PublicKey, Signature};
+use near_primitives::transaction::{SignedTransaction, Transaction};
+use near_primitives::types::BlockHeight;
+use near_store::Store;
+use nearcore::NearConfig;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedTransaction {
+    /// Height of the block which included the transaction.
+    pub height: BlockHeight,
+    pub transaction: Transaction,
+    /// `None` if the public key was remapped, in which case the transaction
+    /// has to be signed again (with a fresh nonce) before it's sent.
+    pub signature: Option<Signature>,
+}
+
+impl ExtractedTransaction {
+    fn new(height: BlockHeight, signed: &SignedTransaction, remap_key: Option<&PublicKey>) -> Self {
+        let mut transaction = signed.transaction.clone();
+        let signature = match remap_key {
+            Some(key) => {
+                transaction.public_key = key.clone();
+                None
+            }
+            None => Some(signed.signature.clone()),
+        };
+        Self { height, transaction, signature }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ExtractionSummary {
+    pub from_height: BlockHeight,
+    pub to_height: BlockHeight,
+    pub total_transactions: u64,
+    /// Blocks of the range, heights without a block are skipped.
+    pub blocks: Vec<BlockSummary>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct BlockSummary {
+    pub height: BlockHeight,
+    /// Timestamp of the block in nanoseconds.
+    pub timestamp: u64,
+    pub transactions: u64,
+}
+
+pub(crate) fn extract_transactions(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    out: &Path,
+    remap_key: Option<PublicKey>,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    let mut chain_store = ChainStore::new(
+        store,
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let mut writer = BufWriter::new(File::create(out)?);
+    let mut summary =
+        ExtractionSummary { from_height, to_height, total_transactions: 0, blocks: vec![] };
+    let mut missing_chunks = 0;
+    for height in from_height..=to_height {
+        let block_hash = match chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            Err(_) => continue,
+        };
+        let block = chain_store.get_block(&block_hash)?.clone();
+        let mut transactions = 0;
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height {
+                continue;
+            }
+            let chunk = match chain_store.get_chunk(&chunk_header.chunk_hash()) {
+                Ok(chunk) => chunk,
+                Err(_) => {
+                    missing_chunks += 1;
+                    continue;
+                }
+            };
+            for signed in chunk.transactions() {
+                let record = ExtractedTransaction::new(height, signed, remap_key.as_ref());
+                write_record(&mut writer, &record)?;
+                transactions += 1;
+            }
+        }
+        summary.total_transactions += transactions;
+        summary.blocks.push(BlockSummary {
+            height,
+            timestamp: block.header().raw_timestamp(),
+            transactions,
+        });
+    }
+    writer.flush()?;
+    let summary_path = summary_path(out);
+    std::fs::write(&summary_path, serde_json::to_vec_pretty(&summary)?)?;
+    println!(
+        "Extracted {} transactions from {} blocks to {}, summary in {}",
+        summary.total_transactions,
+        summary.blocks.len(),
+        out.display(),
+        summary_path.display()
+    );
+    if missing_chunks > 0 {
+        println!("{} chunks were not found, is this an archival node?", missing_chunks);
+    }
+    Ok(())
+}
+
+fn summary_path(out: &Path) -> PathBuf {
+    let mut path = out.as_os_str().to_owned();
+    path.push(".summary.json");
+    path.into()
+}
+
+fn write_record(writer: &mut impl Write, record: &ExtractedTransaction) -> io::Result<()> {
+    let data = record.try_to_vec()?;
+    writer.write_all(&(data.len() as u32).to_le_bytes())?;
+    writer.write_all(&data)
+}
+
+/// Reads the records written by `state-viewer extract-transactions`.
+pub fn read_extracted_transactions(
+    reader: &mut impl Read,
+) -> io::Result<Vec<ExtractedTransaction>> {
+    let mut records = vec![];
+    let mut len = [0u8; 4];
+    loop {
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(records),
+            Err(err) => return Err(err),
+        }
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        reader.read_exact(&mut data)?;
+        records.push(ExtractedTransaction::try_from_slice(&data)?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::hash::CryptoHash;
+
+    #[test]
+    fn test_records_roundtrip() {
+        let signer =
+            InMemorySigner::from_seed("alice.near".parse().unwrap(), KeyType::ED25519, "a");
+        let signed = SignedTransaction::send_money(
+            7,
+            "alice.near".parse().unwrap(),
+            "bob.near".parse().unwrap(),
+            &signer,
+            100,
+            CryptoHash::default(),
+        );
+        let forknet_key = PublicKey::from_seed(KeyType::ED25519, "forknet");
+        let records = vec![
+            ExtractedTransaction::new(10, &signed, None),
+            ExtractedTransaction::new(11, &signed, Some(&forknet_key)),
+        ];
+        assert_eq!(records[0].signature, Some(signed.signature.clone()));
+        assert_eq!(records[1].signature, None);
+        assert_eq!(records[1].transaction.public_key, forknet_key);
+        assert_eq!(records[1].transaction.actions, signed.transaction.actions);
+
+        let mut data = vec![];
+        for record in &records {
+            write_record(&mut data, record).unwrap();
+        }
+        assert_eq!(read_extracted_transactions(&mut data.as_slice()).unwrap(), records);
+    }
+}
@@ -0,0 +1,301 @@
+//! Forecast of what garbage collection deletes, from the data of the blocks
+//! in the database and the GC configuration of the node.
+//!
+//! The heights garbage collection keeps the data of are computed the same way
+//! as by the node, see `EpochManager::get_gc_epoch_start_heights`.  The data
+//! of a block is measured in the columns garbage collection deletes its rows
+//! from.  The state isn't measured: its rows are shared between blocks and
+//! refcounted, so what deleting the trie changes of a block frees isn't known
+//! until it's done.
+
+use std::io::Write;
+
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_epoch_manager::EpochManager;
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::get_block_shard_uid;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::utils::get_block_shard_id;
+use near_store::{DBCol, Store};
+use nearcore::NearConfig;
+
+use crate::output::Output;
+
+/// Columns measured, in the order they're reported in.
+const MEASURED_COLUMNS: [DBCol; 13] = [
+    DBCol::Block,
+    DBCol::BlockExtra,
+    DBCol::BlockInfo,
+    DBCol::BlockRefCount,
+    DBCol::NextBlockHashes,
+    DBCol::StateChanges,
+    DBCol::TrieChanges,
+    DBCol::ChunkExtra,
+    DBCol::IncomingReceipts,
+    DBCol::OutgoingReceipts,
+    DBCol::ChunkPerHeightShard,
+    DBCol::Chunks,
+    DBCol::PartialChunks,
+];
+
+/// Bytes of keys and values in each of [`MEASURED_COLUMNS`].
+type ColumnBytes = [u64; MEASURED_COLUMNS.len()];
+
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct ColumnForecast {
+    pub column: &'static str,
+    /// Bytes below the GC horizon which aren't deleted yet.
+    pub backlog_bytes: u64,
+    /// Bytes of the epochs kept.
+    pub retained_bytes: u64,
+    /// Average bytes of the complete epochs kept, which is about what's
+    /// deleted each epoch once garbage collection caught up.
+    pub bytes_per_epoch: u64,
+    /// Bytes kept once garbage collection caught up, at the current rate.
+    pub steady_state_bytes: u64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub(crate) struct GcForecast {
+    pub head_height: BlockHeight,
+    pub tail_height: BlockHeight,
+    /// Height below which the data is deleted.
+    pub gc_stop_height: BlockHeight,
+    /// Epochs kept, the current one included.
+    pub retention_epochs: u64,
+    pub columns: Vec<ColumnForecast>,
+}
+
+/// `epochs` are the bytes of the epochs kept, the current one first.  All but
+/// the current one are complete.
+fn forecast_columns(
+    backlog: &ColumnBytes,
+    epochs: &[ColumnBytes],
+    retention_epochs: u64,
+) -> Vec<ColumnForecast> {
+    let complete_epochs = epochs.get(1..).unwrap_or_default();
+    MEASURED_COLUMNS
+        .iter()
+        .enumerate()
+        .map(|(i, col)| {
+            let bytes_per_epoch = if complete_epochs.is_empty() {
+                0
+            } else {
+                complete_epochs.iter().map(|epoch| epoch[i]).sum::<u64>()
+                    / complete_epochs.len() as u64
+            };
+            ColumnForecast {
+                column: (*col).into(),
+                backlog_bytes: backlog[i],
+                retained_bytes: epochs.iter().map(|epoch| epoch[i]).sum(),
+                bytes_per_epoch,
+                steady_state_bytes: bytes_per_epoch * retention_epochs,
+            }
+        })
+        .collect()
+}
+
+fn column_index(col: DBCol) -> usize {
+    MEASURED_COLUMNS.iter().position(|measured| *measured == col).unwrap()
+}
+
+fn block_bytes(
+    store: &Store,
+    chain_store: &mut ChainStore,
+    epoch_manager: &EpochManager,
+    block_hash: &CryptoHash,
+    bytes: &mut ColumnBytes,
+) -> anyhow::Result<()> {
+    let mut add = |col: DBCol, key: &[u8]| -> anyhow::Result<()> {
+        if let Some(value) = store.get(col, key)? {
+            bytes[column_index(col)] += (key.len() + value.len()) as u64;
+        }
+        Ok(())
+    };
+    let block = match chain_store.get_block(block_hash) {
+        Ok(block) => block.clone(),
+        // Deleted already by an interrupted run of garbage collection.
+        Err(_) => return Ok(()),
+    };
+    let height = block.header().height();
+    for col in [
+        DBCol::Block,
+        DBCol::BlockExtra,
+        DBCol::BlockInfo,
+        DBCol::BlockRefCount,
+        DBCol::NextBlockHashes,
+    ] {
+        add(col, block_hash.as_ref())?;
+    }
+    for (key, _) in store.iter_prefix(DBCol::StateChanges, block_hash.as_ref()) {
+        add(DBCol::StateChanges, &key)?;
+    }
+    let shard_layout = epoch_manager.get_shard_layout(block.header().epoch_id())?;
+    for shard_uid in shard_layout.get_shard_uids() {
+        let key = get_block_shard_uid(block_hash, &shard_uid);
+        add(DBCol::TrieChanges, &key)?;
+        add(DBCol::ChunkExtra, &key)?;
+    }
+    for shard_id in 0..block.header().chunk_mask().len() as ShardId {
+        let key = get_block_shard_id(block_hash, shard_id);
+        add(DBCol::IncomingReceipts, &key)?;
+        add(DBCol::OutgoingReceipts, &key)?;
+        add(DBCol::ChunkPerHeightShard, &key)?;
+    }
+    for chunk_header in block.chunks().iter() {
+        // Chunks are counted in the block which includes them first.
+        if chunk_header.height_included() == height {
+            add(DBCol::Chunks, chunk_header.chunk_hash().as_ref())?;
+            add(DBCol::PartialChunks, chunk_header.chunk_hash().as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes of all the blocks, forks included, at the heights of the range.
+fn range_bytes(
+    store: &Store,
+    chain_store: &mut ChainStore,
+    epoch_manager: &EpochManager,
+    heights: std::ops::Range<BlockHeight>,
+) -> anyhow::Result<ColumnBytes> {
+    let mut bytes = [0; MEASURED_COLUMNS.len()];
+    for height in heights {
+        let blocks =
+            chain_store.get_all_block_hashes_by_height(height).cloned().unwrap_or_default();
+        for block_hash in blocks.values().flatten() {
+            block_bytes(store, chain_store, epoch_manager, block_hash, &mut bytes)?;
+        }
+    }
+    Ok(bytes)
+}
+
+fn write_table(out: &mut impl Write, forecast: &GcForecast) -> anyhow::Result<()> {
+    writeln!(
+        out,
+        "head {}, tail {}, deleting below {}, keeping {} epochs",
+        forecast.head_height,
+        forecast.tail_height,
+        forecast.gc_stop_height,
+        forecast.retention_epochs
+    )?;
+    writeln!(
+        out,
+        "{:<20} {:>15} {:>15} {:>15} {:>15}",
+        "column", "backlog", "retained", "per epoch", "steady state"
+    )?;
+    let mut total = [0; 4];
+    for col in &forecast.columns {
+        let row =
+            [col.backlog_bytes, col.retained_bytes, col.bytes_per_epoch, col.steady_state_bytes];
+        writeln!(
+            out,
+            "{:<20} {:>15} {:>15} {:>15} {:>15}",
+            col.column, row[0], row[1], row[2], row[3]
+        )?;
+        total.iter_mut().zip(row).for_each(|(total, bytes)| *total += bytes);
+    }
+    writeln!(
+        out,
+        "{:<20} {:>15} {:>15} {:>15} {:>15}",
+        "total", total[0], total[1], total[2], total[3]
+    )?;
+    Ok(())
+}
+
+/// Prints the forecast of garbage collection of the database, as a table in
+/// the text format.
+pub(crate) fn gc_forecast(
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let epoch_manager =
+        EpochManager::new_from_genesis_config(store.clone(), &near_config.genesis.config)?;
+    let retention_epochs = near_config.client_config.gc.gc_num_epochs_to_keep();
+    let head = chain_store.head()?;
+    let tail = chain_store.tail()?;
+    let start_heights = epoch_manager
+        .get_gc_epoch_start_heights(&head.last_block_hash, retention_epochs)
+        .map_err(|err| {
+            anyhow::anyhow!(
+                "can't find the first blocks of the last {} epochs, garbage collection doesn't \
+                 delete anything until there are that many: {}",
+                retention_epochs,
+                err
+            )
+        })?;
+    let gc_stop_height = *start_heights.last().unwrap();
+
+    let backlog = range_bytes(&store, &mut chain_store, &epoch_manager, tail + 1..gc_stop_height)?;
+    let mut epochs = vec![];
+    let mut end_height = head.height + 1;
+    for start_height in start_heights {
+        let heights = start_height..end_height;
+        epochs.push(range_bytes(&store, &mut chain_store, &epoch_manager, heights)?);
+        end_height = start_height;
+    }
+    let forecast = GcForecast {
+        head_height: head.height,
+        tail_height: tail,
+        gc_stop_height,
+        retention_epochs,
+        columns: forecast_columns(&backlog, &epochs, retention_epochs),
+    };
+
+    if output.is_json() {
+        output.print_json(&forecast)
+    } else {
+        write_table(&mut std::io::stdout().lock(), &forecast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forecast_columns() {
+        let mut backlog = [0; MEASURED_COLUMNS.len()];
+        backlog[0] = 500;
+        let mut epochs = vec![[0; MEASURED_COLUMNS.len()]; 3];
+        // The current epoch is only started and isn't in the average.
+        epochs[0][0] = 10;
+        epochs[1][0] = 100;
+        epochs[2][0] = 300;
+        let columns = forecast_columns(&backlog, &epochs, 3);
+        assert_eq!(columns.len(), MEASURED_COLUMNS.len());
+        assert_eq!(
+            columns[0],
+            ColumnForecast {
+                column: "Block",
+                backlog_bytes: 500,
+                retained_bytes: 410,
+                bytes_per_epoch: 200,
+                steady_state_bytes: 600,
+            }
+        );
+        assert_eq!(columns[1].bytes_per_epoch, 0);
+
+        // Only the current epoch.
+        assert_eq!(forecast_columns(&backlog, &epochs[..1], 3)[0].bytes_per_epoch, 0);
+
+        let forecast = GcForecast {
+            head_height: 30,
+            tail_height: 5,
+            gc_stop_height: 11,
+            retention_epochs: 3,
+            columns,
+        };
+        let mut out = vec![];
+        write_table(&mut out, &forecast).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let total = out.lines().last().unwrap().split_whitespace().collect::<Vec<_>>();
+        assert_eq!(total, ["total", "500", "410", "200", "600"]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Audit of the function call access keys in the state, grouped by the
+//! contract they allow calling.
+//!
+//! Keys are flagged when their allowance is unlimited, when they allow calling
+//! any method, or when the contract they're for doesn't exist, which leaves
+//! them usable by whoever creates the account.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+
+use borsh::BorshDeserialize;
+use near_chain::RuntimeAdapter;
+use near_crypto::PublicKey;
+use near_primitives::account::{AccessKey, AccessKeyPermission};
+use near_primitives::serialize::option_u128_dec_format;
+use near_primitives::shard_layout::account_id_to_shard_id;
+use near_primitives::trie_key::{col, trie_key_parsers, TrieKey};
+use near_primitives::types::{AccountId, Balance, BlockHeight, ShardId};
+use near_store::{Store, TrieIterator};
+use nearcore::NearConfig;
+
+use crate::commands::{load_trie_stop_at_height, LoadTrieMode};
+use crate::output::{Output, OutputFormat};
+use crate::sampling::Sampler;
+
+/// The keys which are audited.
+pub(crate) enum KeyAuditScope {
+    Shard(ShardId),
+    Account(AccountId),
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AuditedKey {
+    pub account_id: AccountId,
+    pub public_key: PublicKey,
+    /// `None` if the allowance is unlimited.
+    #[serde(with = "option_u128_dec_format")]
+    pub allowance: Option<Balance>,
+    pub method_names: Vec<String>,
+    pub unlimited_allowance: bool,
+    pub any_method: bool,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct ReceiverReport {
+    /// Not necessarily a valid account id, old keys may have others.
+    pub receiver_id: String,
+    pub receiver_exists: bool,
+    pub num_keys: usize,
+    pub unlimited_allowance: usize,
+    pub any_method: usize,
+    pub keys: Vec<AuditedKey>,
+}
+
+/// The receiver and the audit of the access key in the trie, `None` if it
+/// has full access.
+fn audit_key(raw_key: &[u8], value: &[u8]) -> anyhow::Result<Option<(String, AuditedKey)>> {
+    let account_id = trie_key_parsers::parse_account_id_from_access_key_key(raw_key)?;
+    let public_key = trie_key_parsers::parse_public_key_from_access_key_key(raw_key, &account_id)?;
+    let permission = match AccessKey::try_from_slice(value)?.permission {
+        AccessKeyPermission::FunctionCall(permission) => permission,
+        AccessKeyPermission::FullAccess => return Ok(None),
+    };
+    let key = AuditedKey {
+        account_id,
+        public_key,
+        allowance: permission.allowance,
+        unlimited_allowance: permission.allowance.is_none(),
+        any_method: permission.method_names.is_empty(),
+        method_names: permission.method_names,
+    };
+    Ok(Some((permission.receiver_id, key)))
+}
+
+/// Reports of the receivers, the ones with the most keys first.
+fn group_by_receiver(
+    keys: BTreeMap<String, Vec<AuditedKey>>,
+    mut receiver_exists: impl FnMut(&str) -> anyhow::Result<bool>,
+) -> anyhow::Result<Vec<ReceiverReport>> {
+    let mut reports = vec![];
+    for (receiver_id, keys) in keys {
+        reports.push(ReceiverReport {
+            receiver_exists: receiver_exists(&receiver_id)?,
+            receiver_id,
+            num_keys: keys.len(),
+            unlimited_allowance: keys.iter().filter(|key| key.unlimited_allowance).count(),
+            any_method: keys.iter().filter(|key| key.any_method).count(),
+            keys,
+        });
+    }
+    reports.sort_by(|a, b| {
+        b.num_keys.cmp(&a.num_keys).then_with(|| a.receiver_id.cmp(&b.receiver_id))
+    });
+    Ok(reports)
+}
+
+/// Quotes the field if it has characters which would break the line up.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_csv(out: &mut impl Write, reports: &[ReceiverReport]) -> anyhow::Result<()> {
+    writeln!(
+        out,
+        "receiver_id,receiver_exists,account_id,public_key,allowance,method_names,\
+         unlimited_allowance,any_method"
+    )?;
+    for report in reports {
+        for key in &report.keys {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{}",
+                csv_field(&report.receiver_id),
+                report.receiver_exists,
+                key.account_id,
+                key.public_key,
+                key.allowance.map_or(String::new(), |allowance| allowance.to_string()),
+                csv_field(&key.method_names.join(";")),
+                key.unlimited_allowance,
+                key.any_method
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Scans the function call access keys of the shard or of the account in the
+/// state before the block at the height, the latest one by default, and prints
+/// them grouped by the contract they allow calling.
+///
+/// The report is JSON in the text format too.  In the CSV format there's a
+/// line per key, and the counts per contract are printed to stderr.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn key_audit(
+    scope: KeyAuditScope,
+    receiver_id: Option<String>,
+    height: Option<BlockHeight>,
+    sampler: &Sampler,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let mode = height.map_or(LoadTrieMode::Latest, LoadTrieMode::Height);
+    let (runtime, state_roots, header) =
+        load_trie_stop_at_height(store, home_dir, &near_config, mode);
+    let shard_layout = runtime.get_shard_layout(header.epoch_id())?;
+    let (shard_id, prefix) = match &scope {
+        KeyAuditScope::Shard(shard_id) => (*shard_id, col::ACCESS_KEY.to_vec()),
+        KeyAuditScope::Account(account_id) => (
+            account_id_to_shard_id(account_id, &shard_layout),
+            trie_key_parsers::get_raw_prefix_for_access_keys(account_id),
+        ),
+    };
+    let state_root = state_roots
+        .get(shard_id as usize)
+        .ok_or_else(|| anyhow::anyhow!("there's no shard {}", shard_id))?;
+
+    let trie = runtime.get_trie_for_shard(shard_id, header.prev_hash())?;
+    let mut iter = TrieIterator::new(&trie, state_root)?;
+    iter.seek(&prefix)?;
+    let mut keys: BTreeMap<String, Vec<AuditedKey>> = BTreeMap::new();
+    // The first key past the prefix is let through for the scan to stop at it.
+    while let Some(item) =
+        iter.next_filtered(|key| !key.starts_with(&prefix) || sampler.includes(key))
+    {
+        let (raw_key, value) = item?;
+        if !raw_key.starts_with(&prefix) {
+            break;
+        }
+        if let Some((receiver, key)) = audit_key(&raw_key, &value)? {
+            if receiver_id.as_ref().map_or(true, |receiver_id| receiver_id == &receiver) {
+                keys.entry(receiver).or_default().push(key);
+            }
+        }
+    }
+
+    // Receivers may be on any shard.
+    let mut tries = HashMap::new();
+    let reports = group_by_receiver(keys, |receiver_id| {
+        let receiver_id: AccountId = match receiver_id.parse() {
+            Ok(receiver_id) => receiver_id,
+            Err(_) => return Ok(false),
+        };
+        let shard_id = account_id_to_shard_id(&receiver_id, &shard_layout);
+        if !tries.contains_key(&shard_id) {
+            tries.insert(shard_id, runtime.get_trie_for_shard(shard_id, header.prev_hash())?);
+        }
+        let key = TrieKey::Account { account_id: receiver_id }.to_vec();
+        Ok(tries[&shard_id].get(&state_roots[shard_id as usize], &key)?.is_some())
+    })?;
+
+    match output.format() {
+        OutputFormat::Text | OutputFormat::Json => {
+            let json = match sampler.info() {
+                Some(sample) => serde_json::json!({ "sample": sample, "receivers": reports }),
+                None => serde_json::to_value(&reports)?,
+            };
+            output.print_json(&json)?;
+        }
+        OutputFormat::Csv => {
+            write_csv(&mut std::io::stdout().lock(), &reports)?;
+            if let Some(sample) = sampler.info() {
+                eprintln!("{}", sample);
+            }
+            for report in &reports {
+                eprintln!(
+                    "{}: {} keys, {} with unlimited allowance, {} for any method{}",
+                    report.receiver_id,
+                    report.num_keys,
+                    report.unlimited_allowance,
+                    report.any_method,
+                    if report.receiver_exists { "" } else { ", the account doesn't exist" }
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use near_crypto::{KeyType, SecretKey};
+    use near_primitives::account::FunctionCallPermission;
+
+    fn access_key_record(
+        account_id: &str,
+        seed: &str,
+        permission: AccessKeyPermission,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let public_key = SecretKey::from_seed(KeyType::ED25519, seed).public_key();
+        let key = TrieKey::AccessKey { account_id: account_id.parse().unwrap(), public_key };
+        let value = AccessKey { nonce: 0, permission }.try_to_vec().unwrap();
+        (key.to_vec(), value)
+    }
+
+    fn function_call(
+        receiver_id: &str,
+        allowance: Option<Balance>,
+        methods: &[&str],
+    ) -> AccessKeyPermission {
+        AccessKeyPermission::FunctionCall(FunctionCallPermission {
+            allowance,
+            receiver_id: receiver_id.to_string(),
+            method_names: methods.iter().map(|method| method.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn test_key_audit() {
+        let records = [
+            access_key_record("alice.near", "a", function_call("dex.near", None, &[])),
+            access_key_record("alice.near", "b", AccessKeyPermission::FullAccess),
+            access_key_record("bob.near", "c", function_call("dex.near", Some(10), &["swap"])),
+            access_key_record("bob.near", "d", function_call("gone.near", Some(1), &["a", "b"])),
+        ];
+        let mut keys: BTreeMap<String, Vec<AuditedKey>> = BTreeMap::new();
+        for (raw_key, value) in &records {
+            if let Some((receiver_id, key)) = audit_key(raw_key, value).unwrap() {
+                keys.entry(receiver_id).or_default().push(key);
+            }
+        }
+        let reports =
+            group_by_receiver(keys, |receiver_id| Ok(receiver_id != "gone.near")).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].receiver_id, "dex.near");
+        assert!(reports[0].receiver_exists);
+        assert_eq!(
+            (reports[0].num_keys, reports[0].unlimited_allowance, reports[0].any_method),
+            (2, 1, 1)
+        );
+        assert!(!reports[1].receiver_exists);
+
+        let mut out = vec![];
+        write_csv(&mut out, &reports[1..]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let public_key = SecretKey::from_seed(KeyType::ED25519, "d").public_key();
+        assert_eq!(
+            out.lines().nth(1).unwrap(),
+            format!("gone.near,false,bob.near,{},1,a;b,false,false", public_key)
+        );
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+    }
+}
@@ -1,11 +1,51 @@
 #![doc = include_str!("../README.md")]
 
+mod account_churn;
+mod apply_block_chunks;
 mod apply_chain_range;
 mod apply_chunk;
+mod balance_report;
+mod block_artifacts;
+pub mod chain_reader;
+mod check_roots;
+mod check_signatures;
+mod chunk_apply_benchmark;
 pub mod cli;
 mod commands;
+mod copy_shard_state;
+mod data_source;
+mod epoch_analysis;
 mod epoch_info;
+mod epoch_sync;
+mod export_parquet;
+pub mod extract_transactions;
+mod gc_forecast;
+mod key_audit;
+mod locate_key;
+mod misbehavior;
+mod output;
+mod parquet;
+mod producer_versions;
+mod prune_history;
+mod receipt_stats;
+mod reconcile_stake;
+mod replay_compare;
 mod rocksdb_stats;
+mod runtime_params;
+mod sampling;
+mod simulate_resharding;
+mod state_changes_retention;
 mod state_dump;
+mod state_fingerprint;
+#[cfg(test)]
+mod test_chain;
+mod trie_gc;
+mod tx_index;
+mod validate_headers;
+mod verify_dump;
+mod watch_head;
+mod witness;
 
-pub use cli::StateViewerSubCommand;
+pub use chain_reader::{ChainReader, ChainReaderBuilder};
+pub use cli::{ChunkApplyBenchmarkCmd, DataSourceArgs, StateViewerSubCommand};
+pub use output::{FailureKind, OutputFormat};
@@ -0,0 +1,259 @@
+//! Maps a key, or the keys with a prefix, of a column to the SST files of
+//! RocksDB which could contain it, to correlate a slow read or a suspected
+//! corruption with the compaction logs.
+//!
+//! The files are the live files of the column family of the column whose key
+//! range covers the key, or overlaps the range of the prefix, at any level.
+//! Every file on level 0 can overlap the others, on the other levels at most
+//! one file per level covers a key.  A key which is present but in no file is
+//! still in the memtable.
+
+use crate::output::{failure, FailureKind, Output};
+use near_store::{col_name, DBCol, RocksDB, Store, StoreConfig};
+use nearcore::get_store_path;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use strum::IntoEnumIterator;
+
+/// Number of keys with the prefix counted at most.
+const MAX_PREFIX_KEYS: u64 = 100_000;
+
+#[derive(Serialize, Debug, PartialEq)]
+struct SstFile {
+    name: String,
+    level: i32,
+    size: u64,
+    num_entries: u64,
+    num_deletions: u64,
+    /// Hex of the smallest and largest keys of the file, if RocksDB knows
+    /// them.
+    smallest_key: Option<String>,
+    largest_key: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Lookup {
+    Key { key: String, present: bool, value_size: Option<usize> },
+    Prefix { key_prefix: String, num_keys: u64, value_bytes: u64, truncated: bool },
+}
+
+#[derive(Serialize)]
+struct KeyLocation {
+    column: &'static str,
+    column_family: String,
+    #[serde(flatten)]
+    lookup: Lookup,
+    files: Vec<SstFile>,
+}
+
+impl fmt::Display for KeyLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.lookup {
+            Lookup::Key { key, present, value_size } => {
+                write!(f, "Key {} of {} ({}): ", key, self.column, self.column_family)?;
+                match value_size {
+                    Some(size) if *present => write!(f, "present, value of {} bytes", size)?,
+                    _ => write!(f, "absent")?,
+                }
+            }
+            Lookup::Prefix { key_prefix, num_keys, value_bytes, truncated } => {
+                write!(
+                    f,
+                    "Keys with prefix {} of {} ({}): {}{} keys, values of {} bytes",
+                    key_prefix,
+                    self.column,
+                    self.column_family,
+                    if *truncated { "at least " } else { "" },
+                    num_keys,
+                    value_bytes
+                )?;
+            }
+        }
+        if self.files.is_empty() {
+            return write!(f, "\n  in no SST file");
+        }
+        for file in &self.files {
+            let unknown = || "?".to_string();
+            write!(
+                f,
+                "\n  L{} {} {} bytes, {} entries, {} deletions, keys {}..={}",
+                file.level,
+                file.name,
+                file.size,
+                file.num_entries,
+                file.num_deletions,
+                file.smallest_key.clone().unwrap_or_else(unknown),
+                file.largest_key.clone().unwrap_or_else(unknown)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a column by its name, e.g. `State`, or its column family, e.g.
+/// `col0`.
+pub(crate) fn parse_column(name: &str) -> anyhow::Result<DBCol> {
+    DBCol::iter()
+        .find(|col| <&str>::from(*col) == name || col_name(*col) == name)
+        .ok_or_else(|| failure(FailureKind::InvalidArgument, format!("unknown column {:?}", name)))
+}
+
+fn parse_hex(what: &str, value: &str) -> anyhow::Result<Vec<u8>> {
+    hex::decode(value).map_err(|err| {
+        failure(FailureKind::InvalidArgument, format!("invalid {} {:?}: {}", what, value, err))
+    })
+}
+
+/// The smallest key greater than all the keys with the prefix, `None` if
+/// there is none.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last != u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Whether a file with keys from `smallest` to `largest`, unbounded if
+/// unknown, can have keys in `[start, end)`, `end` being unbounded if `None`.
+fn overlaps(
+    smallest: Option<&[u8]>,
+    largest: Option<&[u8]>,
+    start: &[u8],
+    end: Option<&[u8]>,
+) -> bool {
+    largest.map_or(true, |largest| largest >= start)
+        && match (smallest, end) {
+            (Some(smallest), Some(end)) => smallest < end,
+            _ => true,
+        }
+}
+
+/// Prints the SST files which could contain `key`, or the keys with
+/// `key_prefix`, of the column, and whether the key is present.
+pub(crate) fn locate_key(
+    column: DBCol,
+    key: Option<&str>,
+    key_prefix: Option<&str>,
+    home_dir: &Path,
+    store_config: &StoreConfig,
+    store: &Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let (lookup, start, end) = match (key, key_prefix) {
+        (Some(key), None) => {
+            let key_bytes = parse_hex("key", key)?;
+            let value = store.get(column, &key_bytes)?;
+            let mut end = key_bytes.clone();
+            end.push(0);
+            let lookup = Lookup::Key {
+                key: key.to_lowercase(),
+                present: value.is_some(),
+                value_size: value.map(|value| value.len()),
+            };
+            (lookup, key_bytes, Some(end))
+        }
+        (None, Some(prefix)) => {
+            let prefix_bytes = parse_hex("key prefix", prefix)?;
+            let (mut num_keys, mut value_bytes) = (0, 0);
+            let mut truncated = false;
+            for (_, value) in store.iter_prefix(column, &prefix_bytes) {
+                if num_keys == MAX_PREFIX_KEYS {
+                    truncated = true;
+                    break;
+                }
+                num_keys += 1;
+                value_bytes += value.len() as u64;
+            }
+            let end = prefix_end(&prefix_bytes);
+            let lookup = Lookup::Prefix {
+                key_prefix: prefix.to_lowercase(),
+                num_keys,
+                value_bytes,
+                truncated,
+            };
+            (lookup, prefix_bytes, end)
+        }
+        _ => {
+            return Err(failure(
+                FailureKind::InvalidArgument,
+                "exactly one of --key and --key-prefix is needed",
+            ))
+        }
+    };
+
+    let secondary_dir = tempfile::tempdir()?;
+    let db =
+        RocksDB::open_as_secondary(&get_store_path(home_dir), secondary_dir.path(), store_config)
+            .map_err(|err| {
+            anyhow::anyhow!("Failed to open the store as a secondary instance: {}", err)
+        })?;
+    let column_family = col_name(column);
+    let mut files: Vec<SstFile> = db
+        .live_files()
+        .map_err(|err| anyhow::anyhow!("Failed to list the SST files: {}", err))?
+        .into_iter()
+        .filter(|file| {
+            file.column_family_name == column_family
+                && overlaps(
+                    file.start_key.as_deref(),
+                    file.end_key.as_deref(),
+                    &start,
+                    end.as_deref(),
+                )
+        })
+        .map(|file| SstFile {
+            name: file.name,
+            level: file.level,
+            size: file.size as u64,
+            num_entries: file.num_entries,
+            num_deletions: file.num_deletions,
+            smallest_key: file.start_key.map(hex::encode),
+            largest_key: file.end_key.map(hex::encode),
+        })
+        .collect();
+    files.sort_by(|a, b| (a.level, &a.name).cmp(&(b.level, &b.name)));
+    output.print(&KeyLocation { column: column.into(), column_family, lookup, files })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_column() {
+        assert_eq!(parse_column("State").unwrap(), DBCol::State);
+        assert_eq!(parse_column(&col_name(DBCol::State)).unwrap(), DBCol::State);
+        assert!(parse_column("state").is_err());
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(b"ab"), Some(b"ac".to_vec()));
+        assert_eq!(prefix_end(&[1, 0xff, 0xff]), Some(vec![2]));
+        assert_eq!(prefix_end(&[0xff]), None);
+        assert_eq!(prefix_end(&[]), None);
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let file = (Some(&b"b"[..]), Some(&b"d"[..]));
+        let key = |key: &[u8]| {
+            let mut end = key.to_vec();
+            end.push(0);
+            overlaps(file.0, file.1, key, Some(&end))
+        };
+        assert!(key(b"b") && key(b"c") && key(b"d"));
+        assert!(!key(b"a") && !key(b"d\x00") && !key(b"e"));
+        // The file ends with a key with the prefix.
+        assert!(overlaps(file.0, file.1, b"d", prefix_end(b"d").as_deref()));
+        assert!(!overlaps(file.0, file.1, b"a", prefix_end(b"a").as_deref()));
+        assert!(overlaps(file.0, file.1, b"", None));
+        assert!(overlaps(None, None, b"z", None));
+    }
+}
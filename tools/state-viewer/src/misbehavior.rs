@@ -0,0 +1,212 @@
+//! Conflicting messages signed by the same validator found in the headers,
+//! chunks and approvals of a range of heights, see `near_chain::misbehavior`.
+//!
+//! Blocks and chunks conflict if they're at the same height and, for chunks,
+//! of the same shard, in the same epoch.  The approvals included in blocks
+//! aren't stored on their own but can be rebuilt from the block and its
+//! parent, so two blocks at the same height with different parents which
+//! include the approval of the same validator show that it approved both.
+
+use std::collections::HashMap;
+
+use near_chain::misbehavior::save_evidence;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_crypto::PublicKey;
+use near_epoch_manager::EpochManager;
+use near_primitives::block_header::{Approval, ApprovalInner, BlockHeader};
+use near_primitives::challenge::{EquivocationEvidence, EquivocationKind};
+use near_primitives::sharding::ShardChunkHeader;
+use near_primitives::types::{AccountId, BlockHeight, EpochId, ShardId};
+use near_primitives::views::EquivocationView;
+use near_store::Store;
+use nearcore::NearConfig;
+
+use crate::output::Output;
+
+/// Evidence for each pair of the messages signed by the same key which
+/// conflict with the first one, skipping the ones which don't verify.
+fn pairs_with_first<T: Clone>(
+    signer: &AccountId,
+    public_key: &PublicKey,
+    messages: &[T],
+    kind: impl Fn(T, T) -> EquivocationKind,
+) -> Vec<EquivocationEvidence> {
+    let first = match messages.first() {
+        Some(first) => first,
+        None => return vec![],
+    };
+    messages[1..]
+        .iter()
+        .map(|other| {
+            EquivocationEvidence::new(
+                signer.clone(),
+                public_key.clone(),
+                kind(first.clone(), other.clone()),
+            )
+        })
+        .filter(|evidence| evidence.verify().is_ok())
+        .collect()
+}
+
+/// Approvals of the validators included in the blocks, with the keys they
+/// were checked against.
+fn block_approvals(
+    chain_store: &mut ChainStore,
+    epoch_manager: &EpochManager,
+    header: &BlockHeader,
+) -> anyhow::Result<Vec<(Approval, PublicKey)>> {
+    let prev_header = match chain_store.get_block_header(header.prev_hash()) {
+        Ok(prev_header) => prev_header.clone(),
+        Err(_) => return Ok(vec![]),
+    };
+    let inner = ApprovalInner::new(prev_header.hash(), prev_header.height(), header.height());
+    let approvers = epoch_manager.get_all_block_approvers_ordered(header.prev_hash())?;
+    Ok(approvers
+        .into_iter()
+        .zip(header.approvals())
+        .filter_map(|((approver, _), signature)| {
+            let approval = Approval {
+                inner: inner.clone(),
+                target_height: header.height(),
+                signature: signature.clone()?,
+                account_id: approver.account_id,
+            };
+            Some((approval, approver.public_key))
+        })
+        .collect())
+}
+
+fn height_misbehavior(
+    chain_store: &mut ChainStore,
+    epoch_manager: &EpochManager,
+    height: BlockHeight,
+) -> anyhow::Result<Vec<EquivocationEvidence>> {
+    let mut evidence = vec![];
+    let mut approvals: HashMap<(AccountId, PublicKey), Vec<Approval>> = HashMap::new();
+    let blocks = chain_store.get_all_block_hashes_by_height(height).cloned().unwrap_or_default();
+    for (epoch_id, hashes) in blocks {
+        let mut headers = vec![];
+        for hash in hashes {
+            headers.push(chain_store.get_block_header(&hash)?.clone());
+        }
+        headers.sort_by(|a, b| a.hash().cmp(b.hash()));
+        for header in &headers {
+            for (approval, public_key) in block_approvals(chain_store, epoch_manager, header)? {
+                let seen = approvals.entry((approval.account_id.clone(), public_key)).or_default();
+                if !seen.iter().any(|other| other.inner == approval.inner) {
+                    seen.push(approval);
+                }
+            }
+        }
+        if headers.len() > 1 {
+            let producer = epoch_manager.get_block_producer_info(&epoch_id, height)?;
+            evidence.extend(pairs_with_first(
+                producer.account_id(),
+                producer.public_key(),
+                &headers,
+                |left, right| EquivocationKind::Block { left, right },
+            ));
+        }
+    }
+    for ((account_id, public_key), approvals) in approvals {
+        evidence.extend(pairs_with_first(&account_id, &public_key, &approvals, |left, right| {
+            EquivocationKind::Approval { left, right }
+        }));
+    }
+
+    let mut chunks: HashMap<(EpochId, ShardId), Vec<ShardChunkHeader>> = HashMap::new();
+    for chunk_hash in chain_store.get_all_chunk_hashes_by_height(height)? {
+        let header = match chain_store.get_partial_chunk(&chunk_hash) {
+            Ok(partial_chunk) => partial_chunk.cloned_header(),
+            Err(_) => match chain_store.get_chunk(&chunk_hash) {
+                Ok(chunk) => chunk.cloned_header(),
+                Err(_) => continue,
+            },
+        };
+        let epoch_id = epoch_manager.get_epoch_id_from_prev_block(header.prev_block_hash_ref())?;
+        chunks.entry((epoch_id, header.shard_id())).or_default().push(header);
+    }
+    for ((epoch_id, shard_id), mut headers) in chunks {
+        if headers.len() < 2 {
+            continue;
+        }
+        headers.sort_by_key(|header| header.chunk_hash());
+        let producer = epoch_manager.get_chunk_producer_info(&epoch_id, height, shard_id)?;
+        evidence.extend(pairs_with_first(
+            producer.account_id(),
+            producer.public_key(),
+            &headers,
+            |left, right| EquivocationKind::Chunk { left, right },
+        ));
+    }
+    evidence.sort_by_key(|evidence| evidence.hashes());
+    Ok(evidence)
+}
+
+/// Prints the evidence found at the heights of the range as it goes through
+/// them, a line of JSON per pair in the JSON format, and with `save` stores it
+/// in the database too.
+pub(crate) fn scan_misbehavior(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    save: bool,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if from_height > to_height {
+        anyhow::bail!("--from-height {} is above --to-height {}", from_height, to_height);
+    }
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let epoch_manager =
+        EpochManager::new_from_genesis_config(store.clone(), &near_config.genesis.config)?;
+    let (mut found, mut saved) = (0, 0);
+    for height in from_height..=to_height {
+        for evidence in height_misbehavior(&mut chain_store, &epoch_manager, height)? {
+            output.print_json(&EquivocationView::from(&evidence))?;
+            found += 1;
+            if save && save_evidence(&store, &evidence)? {
+                saved += 1;
+            }
+        }
+    }
+    eprintln!("Found {} pairs of conflicting messages", found);
+    if save {
+        eprintln!("Saved {} of them which weren't in the database yet", saved);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::KeyType;
+    use near_primitives::hash::hash;
+    use near_primitives::validator_signer::{InMemoryValidatorSigner, ValidatorSigner};
+
+    #[test]
+    fn test_pairs_with_first() {
+        let signer =
+            InMemoryValidatorSigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "test");
+        let approvals = [
+            Approval::new(hash(b"a"), 9, 10, &signer),
+            Approval::new(hash(b"a"), 9, 10, &signer),
+            Approval::new(hash(b"b"), 9, 10, &signer),
+            Approval::new(hash(b"c"), 8, 10, &signer),
+        ];
+        let approval = |left, right| EquivocationKind::Approval { left, right };
+        let evidence =
+            pairs_with_first(signer.validator_id(), &signer.public_key(), &approvals, approval);
+        // The second approval is the same as the first one.
+        assert_eq!(evidence.len(), 2);
+        // Signed with another key than the one staked.
+        let other =
+            InMemoryValidatorSigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "other");
+        assert!(pairs_with_first(signer.validator_id(), &other.public_key(), &approvals, approval)
+            .is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+//! Output of the subcommands, as text for people or as JSON for scripts, and
+//! exit codes which tell the kinds of failures apart.
+//!
+//! Every subcommand returns its error to [`Output::fail`], which prints it and
+//! exits with the code of its [`FailureKind`].  The kind is the one of the
+//! first [`Failure`] in the chain of the error, or not found for a
+//! `near_chain` error about data missing from the database, which is how the
+//! data garbage collected shows up too.  Any other error exits with code 1.
+
+use near_chain::ErrorKind;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// A single JSON document on stdout, and the error as JSON on failure.
+    Json,
+    /// A header line and a line of comma separated values per record, for the
+    /// commands listing records.
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    Other,
+    /// The data asked for isn't in the database, it may have been garbage
+    /// collected.
+    NotFound,
+    /// The data in the database is inconsistent, the command checked it.
+    Corruption,
+    InvalidArgument,
+    /// The database is locked by a running node.
+    StoreLocked,
+    /// The argument matches several things, e.g. several validators.
+    Ambiguous,
+}
+
+impl FailureKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            FailureKind::Other => 1,
+            FailureKind::NotFound => 2,
+            FailureKind::Corruption => 3,
+            FailureKind::InvalidArgument => 4,
+            FailureKind::StoreLocked => 5,
+            FailureKind::Ambiguous => 6,
+        }
+    }
+}
+
+/// An error of a known kind, returned or attached as context by the commands.
+#[derive(thiserror::Error, Debug)]
+#[error("{message}")]
+pub(crate) struct Failure {
+    kind: FailureKind,
+    message: String,
+}
+
+pub(crate) fn failure(kind: FailureKind, message: impl fmt::Display) -> anyhow::Error {
+    Failure { kind, message: message.to_string() }.into()
+}
+
+pub(crate) fn failure_kind(err: &anyhow::Error) -> FailureKind {
+    if let Some(failure) = err.downcast_ref::<Failure>() {
+        return failure.kind;
+    }
+    for cause in err.chain() {
+        if let Some(failure) = cause.downcast_ref::<Failure>() {
+            return failure.kind;
+        }
+        if let Some(err) = cause.downcast_ref::<near_chain::Error>() {
+            if matches!(err.kind(), ErrorKind::DBNotFoundErr(_)) {
+                return FailureKind::NotFound;
+            }
+        }
+    }
+    FailureKind::Other
+}
+
+#[derive(Serialize)]
+struct ErrorReport {
+    error: String,
+    kind: FailureKind,
+}
+
+pub(crate) struct Output {
+    format: OutputFormat,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Refuses the format unless the command supports it, so that e.g. a
+    /// script asking for JSON doesn't get text.
+    pub fn check_format(&self, supported: &[OutputFormat]) -> anyhow::Result<()> {
+        if supported.contains(&self.format) {
+            return Ok(());
+        }
+        Err(failure(
+            FailureKind::InvalidArgument,
+            format!("This command doesn't support --format {}", self.format.name()),
+        ))
+    }
+
+    /// Prints the result of a command, its `Display` text or its JSON on a
+    /// single line.
+    pub fn print<T: Serialize + fmt::Display>(&self, value: &T) -> anyhow::Result<()> {
+        match self.format {
+            OutputFormat::Text | OutputFormat::Csv => println!("{}", value),
+            OutputFormat::Json => println!("{}", serde_json::to_string(value)?),
+        }
+        Ok(())
+    }
+
+    /// Prints the result of a command which is JSON in both formats, pretty
+    /// printed in the text format.
+    pub fn print_json<T: Serialize>(&self, value: &T) -> anyhow::Result<()> {
+        match self.format {
+            OutputFormat::Text | OutputFormat::Csv => {
+                println!("{}", serde_json::to_string_pretty(value)?)
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string(value)?),
+        }
+        Ok(())
+    }
+
+    /// Prints the error, to stdout as JSON as well in the JSON format, and
+    /// exits with the code of its kind.
+    pub fn fail(&self, err: anyhow::Error) -> ! {
+        let kind = failure_kind(&err);
+        eprintln!("{:#}", err);
+        if self.is_json() {
+            let report = ErrorReport { error: format!("{:#}", err), kind };
+            println!("{}", serde_json::to_string(&report).unwrap());
+        }
+        std::process::exit(kind.exit_code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_failure_kind() {
+        assert_eq!(failure_kind(&anyhow::anyhow!("no kind")), FailureKind::Other);
+        let err = failure(FailureKind::Corruption, "roots differ");
+        assert_eq!(err.to_string(), "roots differ");
+        assert_eq!(failure_kind(&err), FailureKind::Corruption);
+        let err: anyhow::Result<()> = Err(failure(FailureKind::InvalidArgument, "bad hash"));
+        let err = err.context("Failed to apply the chunk").unwrap_err();
+        assert_eq!(failure_kind(&err), FailureKind::InvalidArgument);
+        let err: anyhow::Result<()> = Err(anyhow::anyhow!("locked"));
+        let err = err.context(Failure { kind: FailureKind::StoreLocked, message: "db".into() });
+        assert_eq!(failure_kind(&err.unwrap_err()), FailureKind::StoreLocked);
+
+        let not_found: near_chain::Error = ErrorKind::DBNotFoundErr("block".to_string()).into();
+        let err = anyhow::Error::from(not_found).context("Failed to view the chain");
+        assert_eq!(failure_kind(&err), FailureKind::NotFound);
+        assert_eq!(FailureKind::NotFound.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_check_format() {
+        let supported = [OutputFormat::Text, OutputFormat::Json];
+        assert!(Output::new(OutputFormat::Json).check_format(&supported).is_ok());
+        let err = Output::new(OutputFormat::Csv).check_format(&supported).unwrap_err();
+        assert_eq!(err.to_string(), "This command doesn't support --format csv");
+        assert_eq!(failure_kind(&err), FailureKind::InvalidArgument);
+    }
+}
@@ -0,0 +1,556 @@
+//! A minimal writer of Parquet files, for `export-parquet`.
+//!
+//! Only what the exports need: flat schemas of required `INT64` and `UTF8`
+//! columns, plain encoding, no compression and a data page per column chunk.
+//! The rows are buffered until they make `ROW_GROUP_BYTES` of data, which are
+//! written as a row group, so the memory taken doesn't depend on the size of
+//! the file.  The page headers and the footer are Thrift structs in the
+//! compact protocol, see <https://github.com/apache/parquet-format>.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+const MAGIC: &[u8] = b"PAR1";
+
+/// Data buffered before a row group is written.
+const ROW_GROUP_BYTES: usize = 64 << 20;
+
+// Parquet enum values.
+const TYPE_INT64: i32 = 2;
+const TYPE_BYTE_ARRAY: i32 = 6;
+const REPETITION_REQUIRED: i32 = 0;
+const CONVERTED_TYPE_UTF8: i32 = 0;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+const PAGE_TYPE_DATA: i32 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ColumnType {
+    Int64,
+    Utf8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+}
+
+pub(crate) fn column(name: &str, column_type: ColumnType) -> Column {
+    Column { name: name.to_string(), column_type }
+}
+
+pub(crate) enum Value {
+    /// Heights, gas and nonces, written to `INT64` columns, which fails for
+    /// the values above `i64::MAX` rather than wrapping them.
+    UInt64(u64),
+    Utf8(String),
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::UInt64(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Utf8(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Utf8(value.to_string())
+    }
+}
+
+/// Where a column chunk was written.
+struct ColumnChunk {
+    offset: u64,
+    size: u64,
+}
+
+struct RowGroup {
+    num_rows: u64,
+    columns: Vec<ColumnChunk>,
+}
+
+pub(crate) struct ParquetWriter<W: Write> {
+    out: W,
+    offset: u64,
+    columns: Vec<Column>,
+    /// Plain encoded values of each column of the rows not written yet.
+    buffers: Vec<Vec<u8>>,
+    buffered_rows: u64,
+    num_rows: u64,
+    row_groups: Vec<RowGroup>,
+}
+
+impl<W: Write> ParquetWriter<W> {
+    pub fn new(mut out: W, columns: Vec<Column>) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        Ok(Self {
+            out,
+            offset: MAGIC.len() as u64,
+            buffers: vec![vec![]; columns.len()],
+            columns,
+            buffered_rows: 0,
+            num_rows: 0,
+            row_groups: vec![],
+        })
+    }
+
+    /// Adds a row, with a value of the right type for every column.  Fails,
+    /// without adding anything, if an integer doesn't fit its column.
+    pub fn write_row(&mut self, row: Vec<Value>) -> io::Result<()> {
+        assert_eq!(row.len(), self.columns.len(), "wrong number of values");
+        for (value, column) in row.iter().zip(&self.columns) {
+            if let Value::UInt64(value) = value {
+                if i64::try_from(*value).is_err() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} doesn't fit the INT64 column {}", value, column.name),
+                    ));
+                }
+            }
+        }
+        for ((value, column), buffer) in row.into_iter().zip(&self.columns).zip(&mut self.buffers) {
+            match (value, column.column_type) {
+                (Value::UInt64(value), ColumnType::Int64) => {
+                    buffer.extend_from_slice(&(value as i64).to_le_bytes())
+                }
+                (Value::Utf8(value), ColumnType::Utf8) => {
+                    buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    buffer.extend_from_slice(value.as_bytes());
+                }
+                _ => panic!("value of the wrong type for column {}", column.name),
+            }
+        }
+        self.buffered_rows += 1;
+        if self.buffers.iter().map(Vec::len).sum::<usize>() >= ROW_GROUP_BYTES {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> io::Result<()> {
+        if self.buffered_rows == 0 {
+            return Ok(());
+        }
+        let mut columns = vec![];
+        for buffer in &mut self.buffers {
+            let header = page_header(self.buffered_rows, buffer.len());
+            self.out.write_all(&header)?;
+            self.out.write_all(buffer)?;
+            let size = (header.len() + buffer.len()) as u64;
+            columns.push(ColumnChunk { offset: self.offset, size });
+            self.offset += size;
+            buffer.clear();
+        }
+        self.row_groups.push(RowGroup { num_rows: self.buffered_rows, columns });
+        self.num_rows += self.buffered_rows;
+        self.buffered_rows = 0;
+        Ok(())
+    }
+
+    /// Writes the rows left and the footer, returns the number of rows.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.write_row_group()?;
+        let footer = self.file_metadata();
+        self.out.write_all(&footer)?;
+        self.out.write_all(&(footer.len() as u32).to_le_bytes())?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.num_rows)
+    }
+
+    fn file_metadata(&self) -> Vec<u8> {
+        let mut t = Thrift::default();
+        t.i32(1, 1);
+        t.list(2, STRUCT, self.columns.len() + 1);
+        t.begin_element();
+        t.binary(4, b"schema");
+        t.i32(5, self.columns.len() as i32);
+        t.end_struct();
+        for column in &self.columns {
+            t.begin_element();
+            t.i32(1, physical_type(column.column_type));
+            t.i32(3, REPETITION_REQUIRED);
+            t.binary(4, column.name.as_bytes());
+            if column.column_type == ColumnType::Utf8 {
+                t.i32(6, CONVERTED_TYPE_UTF8);
+            }
+            t.end_struct();
+        }
+        t.i64(3, self.num_rows as i64);
+        t.list(4, STRUCT, self.row_groups.len());
+        for row_group in &self.row_groups {
+            t.begin_element();
+            t.list(1, STRUCT, self.columns.len());
+            for (column, chunk) in self.columns.iter().zip(&row_group.columns) {
+                t.begin_element();
+                t.i64(2, chunk.offset as i64);
+                t.begin_struct(3);
+                t.i32(1, physical_type(column.column_type));
+                t.list(2, I32, 1);
+                t.varint(zigzag(ENCODING_PLAIN as i64));
+                t.list(3, BINARY, 1);
+                t.varint(column.name.len() as u64);
+                t.buf.extend_from_slice(column.name.as_bytes());
+                t.i32(4, CODEC_UNCOMPRESSED);
+                t.i64(5, row_group.num_rows as i64);
+                t.i64(6, chunk.size as i64);
+                t.i64(7, chunk.size as i64);
+                t.i64(9, chunk.offset as i64);
+                t.end_struct();
+                t.end_struct();
+            }
+            let total_size: u64 = row_group.columns.iter().map(|chunk| chunk.size).sum();
+            t.i64(2, total_size as i64);
+            t.i64(3, row_group.num_rows as i64);
+            t.end_struct();
+        }
+        t.binary(6, b"near state-viewer");
+        t.stop();
+        t.buf
+    }
+}
+
+fn physical_type(column_type: ColumnType) -> i32 {
+    match column_type {
+        ColumnType::Int64 => TYPE_INT64,
+        ColumnType::Utf8 => TYPE_BYTE_ARRAY,
+    }
+}
+
+/// Header of a data page of required values, which has no levels.
+fn page_header(num_values: u64, size: usize) -> Vec<u8> {
+    let mut t = Thrift::default();
+    t.i32(1, PAGE_TYPE_DATA);
+    t.i32(2, size as i32);
+    t.i32(3, size as i32);
+    t.begin_struct(5);
+    t.i32(1, num_values as i32);
+    t.i32(2, ENCODING_PLAIN);
+    t.i32(3, ENCODING_RLE);
+    t.i32(4, ENCODING_RLE);
+    t.end_struct();
+    t.stop();
+    t.buf
+}
+
+// Compact protocol types.
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Writes a Thrift struct in the compact protocol.  The field ids are encoded
+/// as deltas from the previous field of the struct.
+#[derive(Default)]
+struct Thrift {
+    buf: Vec<u8>,
+    last_id: i16,
+    /// Last field ids of the enclosing structs.
+    outer_ids: Vec<i16>,
+}
+
+impl Thrift {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn field(&mut self, id: i16, field_type: u8) {
+        let delta = id - self.last_id;
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | field_type);
+        } else {
+            self.buf.push(field_type);
+            self.varint(zigzag(id as i64));
+        }
+        self.last_id = id;
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, I32);
+        self.varint(zigzag(value as i64));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, I64);
+        self.varint(zigzag(value));
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, BINARY);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    /// The elements follow, written without field headers.
+    fn list(&mut self, id: i16, element_type: u8, len: usize) {
+        self.field(id, LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | element_type);
+        } else {
+            self.buf.push(0xf0 | element_type);
+            self.varint(len as u64);
+        }
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.begin_element();
+    }
+
+    /// Starts a struct element of a list.
+    fn begin_element(&mut self) {
+        self.outer_ids.push(self.last_id);
+        self.last_id = 0;
+    }
+
+    fn end_struct(&mut self) {
+        self.stop();
+        self.last_id = self.outer_ids.pop().unwrap();
+    }
+
+    fn stop(&mut self) {
+        self.buf.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use thrift::protocol::{TCompactInputProtocol, TInputProtocol, TType};
+
+    #[test]
+    fn test_page_header() {
+        assert_eq!(
+            page_header(2, 16),
+            [
+                0x15, 0x00, 0x15, 0x20, 0x15, 0x20, 0x2c, 0x15, 0x04, 0x15, 0x00, 0x15, 0x06, 0x15,
+                0x06, 0x00, 0x00
+            ]
+        );
+        let mut t = Thrift::default();
+        t.i64(20, -300);
+        assert_eq!(t.buf, [0x06, 0x28, 0xd7, 0x04]);
+    }
+
+    #[test]
+    fn test_parquet_writer() {
+        let columns = vec![column("height", ColumnType::Int64), column("hash", ColumnType::Utf8)];
+        let mut file = vec![];
+        let mut writer = ParquetWriter::new(&mut file, columns).unwrap();
+        writer.write_row(vec![7u64.into(), "ab".into()]).unwrap();
+        writer.write_row(vec![8u64.into(), "cde".into()]).unwrap();
+        writer.write_row_group().unwrap();
+        writer.write_row(vec![9u64.into(), "".into()]).unwrap();
+        let header_len = page_header(2, 16).len() as u64;
+        let columns = &writer.row_groups[0].columns;
+        assert_eq!((columns[0].offset, columns[0].size), (4, header_len + 16));
+        assert_eq!(columns[1].offset, 4 + header_len + 16);
+        assert_eq!(writer.finish().unwrap(), 3);
+
+        let mut data_page = MAGIC.to_vec();
+        data_page.extend(page_header(2, 16));
+        data_page.extend(7i64.to_le_bytes());
+        data_page.extend(8i64.to_le_bytes());
+        assert!(file.starts_with(&data_page));
+        assert!(file.ends_with(MAGIC));
+        let footer_end = file.len() - 8;
+        let footer_len = u32::from_le_bytes(file[footer_end..footer_end + 4].try_into().unwrap());
+        let footer = &file[footer_end - footer_len as usize..footer_end];
+        // Version 1, then the root of the schema and the two columns.
+        assert!(footer.starts_with(&[0x15, 0x02, 0x19, 0x3c]));
+        assert!(footer.ends_with(b"near state-viewer\x00"));
+    }
+
+    #[test]
+    fn test_value_out_of_range() {
+        let mut file = vec![];
+        let mut writer =
+            ParquetWriter::new(&mut file, vec![column("nonce", ColumnType::Int64)]).unwrap();
+        let err = writer.write_row(vec![(i64::MAX as u64 + 1).into()]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        writer.write_row(vec![(i64::MAX as u64).into()]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 1);
+    }
+
+    /// A Thrift value decoded by the compact protocol of the `thrift` crate.
+    #[derive(Debug)]
+    enum Thing {
+        Int(i64),
+        Bytes(Vec<u8>),
+        List(Vec<Thing>),
+        Struct(HashMap<i16, Thing>),
+    }
+
+    impl Thing {
+        fn get(&self, id: i16) -> Option<&Thing> {
+            match self {
+                Thing::Struct(fields) => fields.get(&id),
+                _ => panic!("not a struct: {:?}", self),
+            }
+        }
+
+        fn field(&self, id: i16) -> &Thing {
+            self.get(id).unwrap_or_else(|| panic!("no field {} in {:?}", id, self))
+        }
+
+        fn int(&self, id: i16) -> i64 {
+            match self.field(id) {
+                Thing::Int(value) => *value,
+                value => panic!("not an integer: {:?}", value),
+            }
+        }
+
+        fn bytes(&self, id: i16) -> &[u8] {
+            match self.field(id) {
+                Thing::Bytes(value) => value,
+                value => panic!("not a binary: {:?}", value),
+            }
+        }
+
+        fn list(&self, id: i16) -> &[Thing] {
+            match self.field(id) {
+                Thing::List(values) => values,
+                value => panic!("not a list: {:?}", value),
+            }
+        }
+    }
+
+    fn read_thing(p: &mut dyn TInputProtocol, field_type: TType) -> Thing {
+        match field_type {
+            TType::I32 => Thing::Int(p.read_i32().unwrap().into()),
+            TType::I64 => Thing::Int(p.read_i64().unwrap()),
+            TType::String => Thing::Bytes(p.read_bytes().unwrap()),
+            TType::List => {
+                let list = p.read_list_begin().unwrap();
+                let values = (0..list.size).map(|_| read_thing(p, list.element_type)).collect();
+                p.read_list_end().unwrap();
+                Thing::List(values)
+            }
+            TType::Struct => {
+                p.read_struct_begin().unwrap();
+                let mut fields = HashMap::new();
+                loop {
+                    let field = p.read_field_begin().unwrap();
+                    if field.field_type == TType::Stop {
+                        break;
+                    }
+                    fields.insert(field.id.unwrap(), read_thing(p, field.field_type));
+                    p.read_field_end().unwrap();
+                }
+                p.read_struct_end().unwrap();
+                Thing::Struct(fields)
+            }
+            field_type => panic!("unexpected type {:?}", field_type),
+        }
+    }
+
+    /// Reads the rows of a file back, following the field ids of
+    /// `parquet.thrift` and the plain encoding, as strings.
+    fn read_parquet(file: &[u8]) -> (Vec<(String, i64, Option<i64>)>, Vec<Vec<String>>) {
+        assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+        let footer_end = file.len() - 8;
+        let footer_len = u32::from_le_bytes(file[footer_end..footer_end + 4].try_into().unwrap());
+        let mut footer = &file[footer_end - footer_len as usize..footer_end];
+        let metadata = read_thing(&mut TCompactInputProtocol::new(&mut footer), TType::Struct);
+        assert!(footer.is_empty());
+
+        let schema = metadata.list(2);
+        assert_eq!(schema[0].int(5) as usize, schema.len() - 1);
+        let columns: Vec<(String, i64, Option<i64>)> = schema[1..]
+            .iter()
+            .map(|element| {
+                assert_eq!(element.int(3), REPETITION_REQUIRED as i64);
+                let name = String::from_utf8(element.bytes(4).to_vec()).unwrap();
+                (name, element.int(1), element.get(6).map(|_| element.int(6)))
+            })
+            .collect();
+
+        let mut rows = vec![];
+        for row_group in metadata.list(4) {
+            let num_rows = row_group.int(3) as usize;
+            let mut values = vec![];
+            for (chunk, (_, physical_type, _)) in row_group.list(1).iter().zip(&columns) {
+                let chunk_metadata = chunk.field(3);
+                assert_eq!(chunk_metadata.int(1), *physical_type);
+                assert_eq!(chunk_metadata.int(4), CODEC_UNCOMPRESSED as i64);
+                assert_eq!(chunk_metadata.int(5) as usize, num_rows);
+                let mut page = &file[chunk_metadata.int(9) as usize..];
+                let header = read_thing(&mut TCompactInputProtocol::new(&mut page), TType::Struct);
+                assert_eq!(header.int(1), PAGE_TYPE_DATA as i64);
+                assert_eq!(header.field(5).int(1) as usize, num_rows);
+                assert_eq!(header.field(5).int(2), ENCODING_PLAIN as i64);
+                let mut data = &page[..header.int(3) as usize];
+                let mut column = vec![];
+                for _ in 0..num_rows {
+                    if *physical_type == TYPE_INT64 as i64 {
+                        let (value, rest) = data.split_at(8);
+                        column.push(i64::from_le_bytes(value.try_into().unwrap()).to_string());
+                        data = rest;
+                    } else {
+                        let (len, rest) = data.split_at(4);
+                        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+                        column.push(String::from_utf8(rest[..len].to_vec()).unwrap());
+                        data = &rest[len..];
+                    }
+                }
+                assert!(data.is_empty());
+                values.push(column);
+            }
+            for i in 0..num_rows {
+                rows.push(values.iter().map(|column| column[i].clone()).collect());
+            }
+        }
+        assert_eq!(metadata.int(3) as usize, rows.len());
+        (columns, rows)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let columns = vec![column("height", ColumnType::Int64), column("hash", ColumnType::Utf8)];
+        let mut file = vec![];
+        let mut writer = ParquetWriter::new(&mut file, columns).unwrap();
+        // More than 15 row groups, for a list written with a varint size.
+        let mut expected = vec![];
+        for i in 0..20u64 {
+            let hash = "x".repeat(i as usize);
+            writer.write_row(vec![(i * 1_000_000_007).into(), hash.as_str().into()]).unwrap();
+            expected.push(vec![(i * 1_000_000_007).to_string(), hash]);
+            if i < 16 {
+                writer.write_row_group().unwrap();
+            }
+        }
+        writer.write_row(vec![(i64::MAX as u64).into(), "ü".into()]).unwrap();
+        expected.push(vec![i64::MAX.to_string(), "ü".to_string()]);
+        assert_eq!(writer.finish().unwrap(), 21);
+
+        let (columns, rows) = read_parquet(&file);
+        assert_eq!(
+            columns,
+            [
+                ("height".to_string(), TYPE_INT64 as i64, None),
+                ("hash".to_string(), TYPE_BYTE_ARRAY as i64, Some(CONVERTED_TYPE_UTF8 as i64)),
+            ]
+        );
+        assert_eq!(rows, expected);
+    }
+}
@@ -0,0 +1,130 @@
+//! Report of the protocol versions and builds the producers of the last blocks
+//! ran, to follow the progress of a rolling upgrade of the validators.
+
+use crate::balance_report::Near;
+use crate::output::{failure, FailureKind, Output};
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::types::NumBlocks;
+use near_primitives::views::ProducerVersionsView;
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct ProducerVersions(ProducerVersionsView);
+
+fn percent(part: u128, total: u128) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+impl fmt::Display for ProducerVersions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let view = &self.0;
+        write!(
+            f,
+            "Producers of the {} blocks #{}..=#{}, total stake {}",
+            view.num_blocks,
+            view.first_height,
+            view.last_height,
+            Near(view.total_stake)
+        )?;
+        for version in &view.versions {
+            let build_id = version.build_id.map_or("-".to_string(), |id| id.to_string());
+            write!(
+                f,
+                "\n  protocol {} build {}: {} blocks ({:.1}%), {} producers with {} ({:.1}%)",
+                version.latest_protocol_version,
+                build_id,
+                version.num_blocks,
+                percent(version.num_blocks.into(), view.num_blocks.into()),
+                version.producers.len(),
+                Near(version.stake),
+                percent(version.stake, view.total_stake),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the versions of the producers of the last `last_n_blocks` blocks of
+/// the canonical chain, up to the head.
+pub(crate) fn producer_versions(
+    last_n_blocks: NumBlocks,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if last_n_blocks == 0 {
+        return Err(failure(FailureKind::InvalidArgument, "--last-n-blocks must be positive"));
+    }
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let head = chain_store.head()?;
+    let view = near_chain::producer_versions::producer_versions(
+        &mut chain_store,
+        &runtime,
+        &head.last_block_hash,
+        last_n_blocks,
+    )?;
+    output.print(&ProducerVersions(view))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::types::Balance;
+    use near_primitives::version::BuildId;
+    use near_primitives::views::ProducerVersionView;
+
+    const NEAR: Balance = nearcore::config::NEAR_BASE;
+
+    #[test]
+    fn test_display() {
+        let view = ProducerVersionsView {
+            first_height: 11,
+            last_height: 14,
+            num_blocks: 4,
+            total_stake: 40 * NEAR,
+            versions: vec![
+                ProducerVersionView {
+                    latest_protocol_version: 55,
+                    build_id: Some(BuildId([0xab; 8])),
+                    num_blocks: 3,
+                    producers: vec!["alice".parse().unwrap()],
+                    stake: 30 * NEAR,
+                },
+                ProducerVersionView {
+                    latest_protocol_version: 54,
+                    build_id: None,
+                    num_blocks: 1,
+                    producers: vec!["bob".parse().unwrap()],
+                    stake: 10 * NEAR,
+                },
+            ],
+        };
+        assert_eq!(
+            ProducerVersions(view).to_string(),
+            "Producers of the 4 blocks #11..=#14, total stake 40.000 NEAR\n  \
+             protocol 55 build abababababababab: 3 blocks (75.0%), 1 producers with 30.000 NEAR (75.0%)\n  \
+             protocol 54 build -: 1 blocks (25.0%), 1 producers with 10.000 NEAR (25.0%)"
+        );
+    }
+}
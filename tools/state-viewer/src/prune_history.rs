@@ -0,0 +1,222 @@
+//! Offline conversion of a node, archival or not, to one which keeps only the
+//! last few epochs.
+//!
+//! Blocks, chunks and everything indexed by them are removed by the regular
+//! garbage collection, run once over the whole range with no limits.  The
+//! state can't be removed that way on archival nodes since they don't keep
+//! the trie changes of the blocks, so the state is done with a mark and sweep:
+//! every trie node reachable from a state root in a remaining chunk extra is
+//! kept and the rest is deleted.
+//!
+//! The kept trie nodes keep their reference counts.  On an archival node those
+//! are never decreased, so some of the state of the kept blocks won't be
+//! removed by garbage collection once the node runs; running the command
+//! again removes it.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use near_chain::types::RuntimeAdapter;
+use near_chain::{Chain, ChainGenesis, ChainStore, ChainStoreAccess, DoomslugThresholdMode};
+use near_chain_configs::{GCConfig, MIN_GC_NUM_EPOCHS_TO_KEEP};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_primitives::utils::{get_block_shard_id, index_to_bytes};
+use near_store::{DBCol, KeyForStateChanges, Store};
+use nearcore::{NearConfig, NightshadeRuntime};
+
+use crate::trie_gc::{mark_state, sweep_state};
+
+/// Keys and bytes removed from the columns.
+#[derive(Default)]
+struct Savings {
+    columns: HashMap<DBCol, (u64, u64)>,
+}
+
+impl Savings {
+    fn add(&mut self, col: DBCol, key: &[u8], value_len: usize) {
+        let entry = self.columns.entry(col).or_default();
+        entry.0 += 1;
+        entry.1 += (key.len() + value_len) as u64;
+    }
+
+    fn add_total(&mut self, col: DBCol, keys: u64, bytes: u64) {
+        let entry = self.columns.entry(col).or_default();
+        entry.0 += keys;
+        entry.1 += bytes;
+    }
+
+    /// Counts the row if it's in the store.
+    fn add_stored(&mut self, store: &Store, col: DBCol, key: &[u8]) -> anyhow::Result<()> {
+        if let Some(value) = store.get(col, key)? {
+            self.add(col, key, value.len());
+        }
+        Ok(())
+    }
+
+    fn print(&self) {
+        let mut columns: Vec<_> = self.columns.iter().collect();
+        columns.sort_by_key(|(_, (_, bytes))| std::cmp::Reverse(*bytes));
+        for (col, (keys, bytes)) in columns {
+            println!("{:>24} {:>12} keys {:>10.1} MiB", col, keys, *bytes as f64 / 1048576.0);
+        }
+        let total: u64 = self.columns.values().map(|(_, bytes)| bytes).sum();
+        println!("{:>24} {:>28.1} MiB", "total", total as f64 / 1048576.0);
+    }
+}
+
+pub(crate) fn prune_history(
+    keep_epochs: u64,
+    dry_run: bool,
+    home_dir: &Path,
+    mut near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    if keep_epochs < MIN_GC_NUM_EPOCHS_TO_KEEP {
+        anyhow::bail!("at least {} epochs have to be kept", MIN_GC_NUM_EPOCHS_TO_KEEP);
+    }
+    near_config.config.gc.gc_num_epochs_to_keep = keep_epochs;
+    let runtime: Arc<dyn RuntimeAdapter> = Arc::new(NightshadeRuntime::with_config(
+        home_dir,
+        store.clone(),
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    ));
+    let mut chain_store =
+        ChainStore::new(store.clone(), near_config.genesis.config.genesis_height, true);
+    let head = chain_store.head()?;
+    let tail = chain_store.tail()?;
+    let gc_stop_height = runtime.get_gc_stop_height(&head.last_block_hash);
+    // Garbage collection keeps the block just below the stop height.
+    let new_tail = gc_stop_height.saturating_sub(1).max(tail);
+    println!(
+        "Head is #{}, keeping {} epochs moves the tail from #{} to #{}",
+        head.height, keep_epochs, tail, new_tail
+    );
+
+    let mut savings = Savings::default();
+    estimate_block_data(&mut chain_store, &store, tail, new_tail, &mut savings)?;
+    // The state of the blocks at or above this height is kept.
+    let mut keep_from = new_tail;
+    if !dry_run && new_tail > tail {
+        let mut chain = Chain::new(
+            runtime.clone(),
+            &ChainGenesis::from(&near_config.genesis),
+            DoomslugThresholdMode::TwoThirds,
+            true,
+        )?;
+        // Archival nodes move the chunk tail without removing the chunks and
+        // never move the fork tail, start both from the tail.
+        let mut store_update = chain.mut_store().store_update();
+        store_update.update_chunk_tail(tail);
+        store_update.update_fork_tail(gc_stop_height);
+        store_update.commit()?;
+        let gc_config = GCConfig {
+            gc_blocks_limit: u64::MAX,
+            gc_fork_clean_step: gc_stop_height - tail,
+            gc_num_epochs_to_keep: keep_epochs,
+            ..GCConfig::default()
+        };
+        chain.clear_data(runtime.get_tries(), &gc_config)?;
+        keep_from = chain.store().tail()?;
+        if keep_from < new_tail {
+            println!(
+                "Garbage collection stopped at #{} on a fork, run the command again",
+                keep_from
+            );
+        }
+    }
+
+    let marked = mark_state(&mut chain_store, &runtime.get_tries(), &store, keep_from)?;
+    println!(
+        "Marked {} trie nodes and values reachable from {} state roots",
+        marked.num_nodes(),
+        marked.roots
+    );
+    for shard in sweep_state(&store, &marked, dry_run)?.values() {
+        savings.add_total(DBCol::State, shard.unreachable, shard.unreachable_bytes);
+    }
+    if dry_run {
+        println!("Expected savings:");
+    } else {
+        println!("Removed:");
+    }
+    savings.print();
+    if !dry_run && near_config.client_config.archive {
+        println!("Set `archive` to false in config.json before starting the node");
+    }
+    Ok(())
+}
+
+/// Counts the rows garbage collection removes for the blocks below
+/// `new_tail`.
+fn estimate_block_data(
+    chain_store: &mut ChainStore,
+    store: &Store,
+    tail: BlockHeight,
+    new_tail: BlockHeight,
+    savings: &mut Savings,
+) -> anyhow::Result<()> {
+    for height in tail..new_tail {
+        let block_hashes: Vec<CryptoHash> = match chain_store.get_all_block_hashes_by_height(height)
+        {
+            Ok(hashes) => hashes.values().flatten().copied().collect(),
+            Err(_) => continue,
+        };
+        savings.add_stored(store, DBCol::BlockPerHeight, &index_to_bytes(height))?;
+        for block_hash in block_hashes {
+            let block = match chain_store.get_block(&block_hash) {
+                Ok(block) => block.clone(),
+                Err(_) => continue,
+            };
+            for col in [
+                DBCol::Block,
+                DBCol::BlockExtra,
+                DBCol::BlockInfo,
+                DBCol::NextBlockHashes,
+                DBCol::BlockRefCount,
+            ] {
+                savings.add_stored(store, col, block_hash.as_ref())?;
+            }
+            let prefix = KeyForStateChanges::for_block(&block_hash);
+            for (key, value) in store.iter_prefix(DBCol::StateChanges, prefix.as_ref()) {
+                savings.add(DBCol::StateChanges, &key, value.len());
+            }
+            for (key, value) in store.iter_prefix(DBCol::ChunkExtra, block_hash.as_ref()) {
+                savings.add(DBCol::ChunkExtra, &key, value.len());
+            }
+            for (shard_id, chunk_header) in block.chunks().iter().enumerate() {
+                let key = get_block_shard_id(&block_hash, shard_id as ShardId);
+                savings.add_stored(store, DBCol::IncomingReceipts, &key)?;
+                savings.add_stored(store, DBCol::OutgoingReceipts, &key)?;
+                let outcome_ids: Vec<CryptoHash> =
+                    store.get_ser(DBCol::OutcomeIds, &key)?.unwrap_or_default();
+                savings.add_stored(store, DBCol::OutcomeIds, &key)?;
+                for id in outcome_ids {
+                    savings.add_stored(store, DBCol::TransactionResult, id.as_ref())?;
+                }
+                if chunk_header.height_included() != height {
+                    continue;
+                }
+                let chunk_hash = chunk_header.chunk_hash();
+                savings.add_stored(store, DBCol::PartialChunks, chunk_hash.as_ref())?;
+                if let Ok(chunk) = chain_store.get_chunk(&chunk_hash) {
+                    for transaction in chunk.transactions() {
+                        savings.add_stored(
+                            store,
+                            DBCol::Transactions,
+                            transaction.get_hash().as_ref(),
+                        )?;
+                    }
+                    for receipt in chunk.receipts() {
+                        savings.add_stored(store, DBCol::Receipts, receipt.get_hash().as_ref())?;
+                    }
+                    savings.add_stored(store, DBCol::Chunks, chunk_hash.as_ref())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
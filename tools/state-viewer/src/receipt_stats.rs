@@ -0,0 +1,579 @@
+//! Statistics of the receipts of a range of blocks, for tuning the receipt
+//! and argument size limits and the gas and deposit defaults of the protocol:
+//! the distributions of the sizes of the receipts, of the arguments of their
+//! function calls, of the gas and deposits they attach, and their numbers by
+//! the combination of the kinds of their actions.
+//!
+//! The receipts counted are the ones the new chunks of the blocks carry, the
+//! receipts the previous chunks of the shards sent.  A receipt a transaction
+//! is converted to and executed in the chunk of the transaction, when its
+//! signer is its receiver, isn't stored in a chunk and isn't counted.  Chunks
+//! garbage collected, everything but the last epochs on a node that isn't
+//! archival, are counted as missing.
+//!
+//! The distributions are kept in histograms of a bounded size, with buckets of
+//! 1/16 of a power of two, so a percentile is the upper bound of its bucket,
+//! within 1/16 of the value.  With a state file the heights scanned and the
+//! statistics so far are saved every few seconds, and a scan run again with
+//! the same file and arguments resumes where it stopped.
+
+use crate::output::{failure, FailureKind, Output};
+use borsh::BorshSerialize;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::{Receipt, ReceiptEnum};
+use near_primitives::serialize::u128_dec_format;
+use near_primitives::transaction::Action;
+use near_primitives::types::{BlockHeight, ShardId};
+use near_store::Store;
+use nearcore::NearConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const PROGRESS_PERIOD: Duration = Duration::from_secs(10);
+
+/// Buckets per power of two, the values below it have a bucket each.
+const SUB_BUCKETS: u32 = 16;
+
+/// Histogram of the values on a log scale.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+struct LogHistogram {
+    /// Number of values by bucket, the empty buckets left out.
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+    /// Saturates, the deposits of enough receipts add up above `u128::MAX`.
+    #[serde(with = "u128_dec_format")]
+    total: u128,
+    #[serde(with = "u128_dec_format")]
+    max: u128,
+}
+
+impl LogHistogram {
+    fn bucket(value: u128) -> u32 {
+        if value < SUB_BUCKETS as u128 {
+            return value as u32;
+        }
+        // The bucket is given by the highest 5 bits of the value.
+        let shift = 127 - value.leading_zeros() - SUB_BUCKETS.trailing_zeros();
+        shift * SUB_BUCKETS + (value >> shift) as u32
+    }
+
+    /// The largest value of the bucket.
+    fn upper_bound(bucket: u32) -> u128 {
+        if bucket < SUB_BUCKETS {
+            return bucket as u128;
+        }
+        let shift = bucket / SUB_BUCKETS - 1;
+        let mantissa = (bucket % SUB_BUCKETS + SUB_BUCKETS) as u128;
+        // Wraps around to `u128::MAX` for the last bucket.
+        ((mantissa + 1) << shift).wrapping_sub(1)
+    }
+
+    fn add(&mut self, value: u128) {
+        *self.buckets.entry(Self::bucket(value)).or_default() += 1;
+        self.count += 1;
+        self.total = self.total.saturating_add(value);
+        self.max = self.max.max(value);
+    }
+
+    /// The smallest bucket bound which at least the `quantile` of the values
+    /// are below or at, and at most the largest value.
+    fn percentile(&self, quantile: f64) -> u128 {
+        let rank = ((quantile * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (&bucket, &count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                return Self::upper_bound(bucket).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn distribution(&self) -> Distribution {
+        Distribution {
+            count: self.count,
+            total: self.total,
+            p50: self.percentile(0.5),
+            p90: self.percentile(0.9),
+            p99: self.percentile(0.99),
+            p999: self.percentile(0.999),
+            max: self.max,
+        }
+    }
+}
+
+/// The values are decimal strings in JSON, the deposits don't fit a number.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub(crate) struct Distribution {
+    pub count: u64,
+    #[serde(with = "u128_dec_format")]
+    pub total: u128,
+    #[serde(with = "u128_dec_format")]
+    pub p50: u128,
+    #[serde(with = "u128_dec_format")]
+    pub p90: u128,
+    #[serde(with = "u128_dec_format")]
+    pub p99: u128,
+    #[serde(with = "u128_dec_format")]
+    pub p999: u128,
+    #[serde(with = "u128_dec_format")]
+    pub max: u128,
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} values, p50 {}, p90 {}, p99 {}, p99.9 {}, max {}, total {}",
+            self.count, self.p50, self.p90, self.p99, self.p999, self.max, self.total
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LargestReceipt {
+    pub receipt_id: CryptoHash,
+    pub size: u64,
+    pub block_height: BlockHeight,
+    pub shard_id: ShardId,
+}
+
+/// The kinds of the actions of a receipt, once each and in alphabetical
+/// order, e.g. `AddKey+CreateAccount+Transfer`.
+fn action_kinds(actions: &[Action]) -> String {
+    let kinds: BTreeSet<&str> = actions.iter().map(|action| action.as_ref()).collect();
+    if kinds.is_empty() {
+        return "(none)".to_string();
+    }
+    kinds.into_iter().collect::<Vec<_>>().join("+")
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+struct ReceiptStatsAccumulator {
+    num_blocks: u64,
+    missing_chunks: u64,
+    num_data_receipts: u64,
+    /// Borsh serialized sizes of all the receipts.
+    size: LogHistogram,
+    largest: Option<LargestReceipt>,
+    /// Number of the action receipts by the kinds of their actions.
+    action_kinds: BTreeMap<String, u64>,
+    /// Sizes of the arguments of each function call.
+    function_call_args_size: LogHistogram,
+    /// Deposits of the action receipts, of all their actions.
+    deposit: LogHistogram,
+    /// Gas attached to the action receipts with function calls, to all of
+    /// them.
+    attached_gas: LogHistogram,
+}
+
+impl ReceiptStatsAccumulator {
+    /// Adds the receipts of the new chunks of the block at the height, if
+    /// there's one, of the shard if one is given.
+    fn add_height(
+        &mut self,
+        chain_store: &mut ChainStore,
+        height: BlockHeight,
+        shard_id: Option<ShardId>,
+    ) -> anyhow::Result<()> {
+        let block_hash = match chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            Err(_) => return Ok(()),
+        };
+        let block = chain_store.get_block(&block_hash)?.clone();
+        self.num_blocks += 1;
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height
+                || shard_id.map_or(false, |shard_id| shard_id != chunk_header.shard_id())
+            {
+                continue;
+            }
+            let chunk = match chain_store.get_chunk(&chunk_header.chunk_hash()) {
+                Ok(chunk) => chunk,
+                Err(_) => {
+                    self.missing_chunks += 1;
+                    continue;
+                }
+            };
+            for receipt in chunk.receipts() {
+                self.add_receipt(receipt, height, chunk_header.shard_id());
+            }
+        }
+        Ok(())
+    }
+
+    fn add_receipt(&mut self, receipt: &Receipt, height: BlockHeight, shard_id: ShardId) {
+        let size = receipt.try_to_vec().expect("Failed to serialize a receipt").len() as u64;
+        self.size.add(size as u128);
+        if self.largest.as_ref().map_or(true, |largest| size > largest.size) {
+            self.largest = Some(LargestReceipt {
+                receipt_id: receipt.receipt_id,
+                size,
+                block_height: height,
+                shard_id,
+            });
+        }
+        let action_receipt = match &receipt.receipt {
+            ReceiptEnum::Action(action_receipt) => action_receipt,
+            ReceiptEnum::Data(_) => {
+                self.num_data_receipts += 1;
+                return;
+            }
+        };
+        let actions = &action_receipt.actions;
+        *self.action_kinds.entry(action_kinds(actions)).or_default() += 1;
+        let mut has_function_call = false;
+        for action in actions {
+            if let Action::FunctionCall(call) = action {
+                has_function_call = true;
+                self.function_call_args_size.add(call.args.len() as u128);
+            }
+        }
+        self.deposit.add(actions.iter().map(Action::get_deposit_balance).sum());
+        if has_function_call {
+            self.attached_gas
+                .add(actions.iter().map(|action| action.get_prepaid_gas() as u128).sum());
+        }
+    }
+
+    fn report(
+        &self,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+        shard_id: Option<ShardId>,
+    ) -> ReceiptStatsReport {
+        let mut action_kinds: Vec<ActionKindsCount> = self
+            .action_kinds
+            .iter()
+            .map(|(kinds, &num_receipts)| ActionKindsCount { kinds: kinds.clone(), num_receipts })
+            .collect();
+        action_kinds.sort_by(|a, b| b.num_receipts.cmp(&a.num_receipts));
+        ReceiptStatsReport {
+            from_height,
+            to_height,
+            shard_id,
+            num_blocks: self.num_blocks,
+            missing_chunks: self.missing_chunks,
+            num_receipts: self.size.count,
+            num_data_receipts: self.num_data_receipts,
+            size: self.size.distribution(),
+            largest: self.largest.clone(),
+            action_kinds,
+            function_call_args_size: self.function_call_args_size.distribution(),
+            deposit: self.deposit.distribution(),
+            attached_gas: self.attached_gas.distribution(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ActionKindsCount {
+    pub kinds: String,
+    pub num_receipts: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ReceiptStatsReport {
+    pub from_height: BlockHeight,
+    pub to_height: BlockHeight,
+    pub shard_id: Option<ShardId>,
+    pub num_blocks: u64,
+    pub missing_chunks: u64,
+    pub num_receipts: u64,
+    pub num_data_receipts: u64,
+    /// In bytes, Borsh serialized.
+    pub size: Distribution,
+    pub largest: Option<LargestReceipt>,
+    /// The action receipts by the kinds of their actions, the most frequent
+    /// first.
+    pub action_kinds: Vec<ActionKindsCount>,
+    /// In bytes.
+    pub function_call_args_size: Distribution,
+    /// In yoctoNEAR.
+    pub deposit: Distribution,
+    pub attached_gas: Distribution,
+}
+
+impl fmt::Display for ReceiptStatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shards = match self.shard_id {
+            Some(shard_id) => format!("shard {}", shard_id),
+            None => "all the shards".to_string(),
+        };
+        writeln!(
+            f,
+            "Receipts of the {} blocks from #{} to #{} in {}: {}, {} of them data receipts",
+            self.num_blocks,
+            self.from_height,
+            self.to_height,
+            shards,
+            self.num_receipts,
+            self.num_data_receipts
+        )?;
+        if self.missing_chunks > 0 {
+            writeln!(
+                f,
+                "{} chunks are missing, their receipts aren't counted",
+                self.missing_chunks
+            )?;
+        }
+        writeln!(f, "Size in bytes: {}", self.size)?;
+        if let Some(largest) = &self.largest {
+            writeln!(
+                f,
+                "Largest receipt: {}, {} bytes, at #{} in shard {}",
+                largest.receipt_id, largest.size, largest.block_height, largest.shard_id
+            )?;
+        }
+        writeln!(f, "Function call arguments in bytes: {}", self.function_call_args_size)?;
+        writeln!(f, "Deposit in yoctoNEAR: {}", self.deposit)?;
+        writeln!(f, "Attached gas: {}", self.attached_gas)?;
+        write!(f, "Action receipts by the kinds of their actions:")?;
+        for count in &self.action_kinds {
+            write!(f, "\n  {}: {}", count.kinds, count.num_receipts)?;
+        }
+        Ok(())
+    }
+}
+
+/// The arguments of a scan and where it stands.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct ScanState {
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    shard_id: Option<ShardId>,
+    /// Past `to_height` once the scan is done.
+    next_height: BlockHeight,
+    stats: ReceiptStatsAccumulator,
+}
+
+impl ScanState {
+    /// The state saved in the file if there's one, which must have been
+    /// saved by a scan with the same arguments.
+    fn load_or_new(
+        path: Option<&Path>,
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+        shard_id: Option<ShardId>,
+    ) -> anyhow::Result<Self> {
+        let new = ScanState {
+            from_height,
+            to_height,
+            shard_id,
+            next_height: from_height,
+            stats: Default::default(),
+        };
+        let path = match path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(new),
+        };
+        let state: ScanState = serde_json::from_slice(&fs::read(path)?)?;
+        if (state.from_height, state.to_height, state.shard_id)
+            != (from_height, to_height, shard_id)
+        {
+            return Err(failure(
+                FailureKind::InvalidArgument,
+                format!(
+                    "{} was saved by a scan of the heights #{} to #{} in shard {:?}, \
+                     use another state file",
+                    path.display(),
+                    state.from_height,
+                    state.to_height,
+                    state.shard_id
+                ),
+            ));
+        }
+        Ok(state)
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn receipt_stats(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    shard_id: Option<ShardId>,
+    state_file: Option<&Path>,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if from_height > to_height {
+        return Err(failure(
+            FailureKind::InvalidArgument,
+            format!("--from-height {} is above --to-height {}", from_height, to_height),
+        ));
+    }
+    let mut state = ScanState::load_or_new(state_file, from_height, to_height, shard_id)?;
+    let resumed_at = state.next_height;
+    if resumed_at > from_height {
+        eprintln!("Resuming the scan at #{}", resumed_at);
+    }
+    let mut chain_store = ChainStore::new(
+        store,
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let started = Instant::now();
+    let mut last_progress = started;
+    while state.next_height <= to_height {
+        let height = state.next_height;
+        state.stats.add_height(&mut chain_store, height, shard_id)?;
+        state.next_height += 1;
+
+        if last_progress.elapsed() >= PROGRESS_PERIOD || state.next_height > to_height {
+            last_progress = Instant::now();
+            if let Some(path) = state_file {
+                state.save(path)?;
+            }
+            // To stderr, the report may be JSON.
+            eprintln!(
+                "Scanned the heights up to #{} of #{}, {} receipts, {:.0} heights/s",
+                height,
+                to_height,
+                state.stats.size.count,
+                (state.next_height - resumed_at) as f64
+                    / started.elapsed().as_secs_f64().max(f64::EPSILON)
+            );
+        }
+    }
+    output.print(&state.stats.report(from_height, to_height, shard_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{KeyType, PublicKey};
+    use near_primitives::receipt::{ActionReceipt, DataReceipt};
+    use near_primitives::transaction::{FunctionCallAction, TransferAction};
+
+    #[test]
+    fn test_log_histogram() {
+        let mut histogram = LogHistogram::default();
+        assert_eq!(histogram.percentile(0.5), 0);
+        for value in 1..=1000 {
+            histogram.add(value);
+        }
+        let distribution = histogram.distribution();
+        assert_eq!(
+            (distribution.count, distribution.total, distribution.max),
+            (1000, 500500, 1000)
+        );
+        // Within 1/16 above the value.
+        assert!((500..=500 + 500 / 16).contains(&distribution.p50), "{}", distribution.p50);
+        assert!((990..=1000).contains(&distribution.p99), "{}", distribution.p99);
+        assert_eq!(distribution.p999, 1000);
+
+        // Small values have a bucket each, the buckets above are contiguous.
+        for value in 0..SUB_BUCKETS as u128 * 2 {
+            assert_eq!(LogHistogram::upper_bound(LogHistogram::bucket(value)), value);
+        }
+        for value in [33, 1000, 1 << 40, u128::MAX / 3, u128::MAX] {
+            let bucket = LogHistogram::bucket(value);
+            assert!(LogHistogram::upper_bound(bucket) >= value);
+            assert!(LogHistogram::upper_bound(bucket - 1) < value);
+        }
+        histogram.add(u128::MAX);
+        histogram.add(u128::MAX);
+        assert_eq!(histogram.total, u128::MAX);
+        assert_eq!(histogram.percentile(1.0), u128::MAX);
+    }
+
+    fn action_receipt(id: u8, actions: Vec<Action>) -> Receipt {
+        Receipt {
+            predecessor_id: "alice.near".parse().unwrap(),
+            receiver_id: "bob.near".parse().unwrap(),
+            receipt_id: CryptoHash([id; 32]),
+            receipt: ReceiptEnum::Action(ActionReceipt {
+                signer_id: "alice.near".parse().unwrap(),
+                signer_public_key: PublicKey::empty(KeyType::ED25519),
+                gas_price: 0,
+                output_data_receivers: vec![],
+                input_data_ids: vec![],
+                actions,
+            }),
+        }
+    }
+
+    fn function_call(args_size: usize, gas: u64, deposit: u128) -> Action {
+        Action::FunctionCall(FunctionCallAction {
+            method_name: "method".to_string(),
+            args: vec![0; args_size],
+            gas,
+            deposit,
+        })
+    }
+
+    #[test]
+    fn test_add_receipt() {
+        let mut stats = ReceiptStatsAccumulator::default();
+        stats.add_receipt(&action_receipt(0, vec![function_call(10, 100, 1)]), 5, 0);
+        stats.add_receipt(
+            &action_receipt(
+                1,
+                vec![
+                    function_call(1000, 200, 0),
+                    Action::Transfer(TransferAction { deposit: 7 }),
+                    function_call(0, 300, 0),
+                ],
+            ),
+            6,
+            1,
+        );
+        for _ in 0..2 {
+            let refund = Receipt::new_balance_refund(&"alice.near".parse().unwrap(), 3);
+            stats.add_receipt(&refund, 6, 1);
+        }
+        let data = Receipt {
+            predecessor_id: "alice.near".parse().unwrap(),
+            receiver_id: "bob.near".parse().unwrap(),
+            receipt_id: CryptoHash([3; 32]),
+            receipt: ReceiptEnum::Data(DataReceipt { data_id: CryptoHash([4; 32]), data: None }),
+        };
+        stats.add_receipt(&data, 7, 0);
+
+        let report = stats.report(5, 7, None);
+        assert_eq!((report.num_receipts, report.num_data_receipts), (5, 1));
+        let largest = report.largest.unwrap();
+        assert_eq!((largest.receipt_id, largest.block_height), (CryptoHash([1; 32]), 6));
+        let kinds: Vec<_> = report
+            .action_kinds
+            .iter()
+            .map(|count| (count.kinds.as_str(), count.num_receipts))
+            .collect();
+        assert_eq!(kinds, [("Transfer", 2), ("FunctionCall", 1), ("FunctionCall+Transfer", 1)]);
+        assert_eq!(
+            (report.function_call_args_size.count, report.function_call_args_size.max),
+            (3, 1000)
+        );
+        assert_eq!((report.deposit.count, report.deposit.total, report.deposit.max), (4, 14, 7));
+        assert_eq!((report.attached_gas.count, report.attached_gas.max), (2, 500));
+        assert_eq!(action_kinds(&[]), "(none)");
+    }
+
+    #[test]
+    fn test_scan_state_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let mut state = ScanState::load_or_new(Some(&path), 10, 20, Some(1)).unwrap();
+        assert_eq!(state.next_height, 10);
+        state.next_height = 15;
+        state.stats.add_receipt(&action_receipt(0, vec![function_call(10, 100, 1)]), 12, 1);
+        state.save(&path).unwrap();
+
+        assert_eq!(ScanState::load_or_new(Some(&path), 10, 20, Some(1)).unwrap(), state);
+        assert!(ScanState::load_or_new(Some(&path), 10, 20, None).is_err());
+        assert_eq!(ScanState::load_or_new(None, 10, 20, None).unwrap().next_height, 10);
+    }
+}
@@ -0,0 +1,335 @@
+//! Reconciliation of the stakes of an epoch with the ones a staking provider
+//! expects, listed in a CSV file.
+//!
+//! The stakes of the chain are the ones of the validators of the canonical
+//! epoch at the epoch height, from its `EpochInfo`, and the stakes proposed
+//! during the epoch by the accounts which aren't validators in it, from the
+//! summary the epoch manager stores when the epoch ends.  An account is a
+//! discrepancy when its stake differs from the expected one by more than the
+//! tolerance, or when it's on only one side.
+
+use crate::balance_report::Near;
+use crate::epoch_analysis::canonical_epochs;
+use crate::output::{failure, FailureKind, Output};
+use near_chain::ChainStore;
+use near_epoch_manager::EpochManager;
+use near_primitives::serialize::{option_u128_dec_format, u128_dec_format};
+use near_primitives::types::{AccountId, Balance, EpochHeight, EpochId};
+use near_store::Store;
+use nearcore::NearConfig;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const CSV_HEADER: &str = "account_id,expected_stake,chain_stake,difference,source,kind";
+
+/// Where the stake of the chain comes from.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum StakeSource {
+    Validator,
+    Proposal,
+}
+
+impl StakeSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            StakeSource::Validator => "validator",
+            StakeSource::Proposal => "proposal",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum DiscrepancyKind {
+    Mismatch,
+    OnlyExpected,
+    OnlyChain,
+}
+
+impl DiscrepancyKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            DiscrepancyKind::Mismatch => "mismatch",
+            DiscrepancyKind::OnlyExpected => "only_expected",
+            DiscrepancyKind::OnlyChain => "only_chain",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Discrepancy {
+    account_id: AccountId,
+    #[serde(with = "option_u128_dec_format")]
+    expected: Option<Balance>,
+    #[serde(with = "option_u128_dec_format")]
+    chain: Option<Balance>,
+    source: Option<StakeSource>,
+    kind: DiscrepancyKind,
+}
+
+impl Discrepancy {
+    /// The chain stake minus the expected one, missing stakes being zero.
+    fn difference(&self) -> String {
+        let (chain, expected) = (self.chain.unwrap_or(0), self.expected.unwrap_or(0));
+        if chain >= expected {
+            (chain - expected).to_string()
+        } else {
+            format!("-{}", expected - chain)
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct StakeReconciliation {
+    epoch_height: EpochHeight,
+    epoch_id: EpochId,
+    #[serde(with = "u128_dec_format")]
+    tolerance: Balance,
+    /// Whether the proposals of the epoch were known, they are once it ended.
+    proposals_known: bool,
+    num_matching: usize,
+    discrepancies: Vec<Discrepancy>,
+}
+
+impl fmt::Display for StakeReconciliation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Stakes of epoch {} at epoch height {}, tolerance {} yoctoNEAR",
+            self.epoch_id.0, self.epoch_height, self.tolerance
+        )?;
+        if !self.proposals_known {
+            writeln!(f, "The epoch has no stored summary, only its validators are compared")?;
+        }
+        writeln!(f, "Matching: {}", self.num_matching)?;
+        write!(f, "Discrepancies: {}", self.discrepancies.len())?;
+        let stake =
+            |stake: Option<Balance>| stake.map_or("-".to_string(), |stake| Near(stake).to_string());
+        for discrepancy in &self.discrepancies {
+            write!(
+                f,
+                "\n  {} {}: expected {}, chain {}",
+                discrepancy.kind.as_str(),
+                discrepancy.account_id,
+                stake(discrepancy.expected),
+                stake(discrepancy.chain),
+            )?;
+            if let Some(source) = discrepancy.source {
+                write!(f, " ({})", source.as_str())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the expected stakes, a line per account with the account id and the
+/// stake in yoctoNEAR.  Empty lines and an `account_id` header are skipped.
+fn parse_expected(csv: &str) -> anyhow::Result<BTreeMap<AccountId, Balance>> {
+    let mut expected = BTreeMap::new();
+    for (i, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.starts_with("account_id")) {
+            continue;
+        }
+        let invalid = |message: String| {
+            failure(FailureKind::InvalidArgument, format!("line {}: {}", i + 1, message))
+        };
+        let (account_id, stake) = match line.split(',').map(str::trim).collect::<Vec<_>>()[..] {
+            [account_id, stake] => (account_id, stake),
+            _ => return Err(invalid(format!("expected `account_id,stake`, got {:?}", line))),
+        };
+        let account_id: AccountId = account_id
+            .parse()
+            .map_err(|err| invalid(format!("invalid account id {:?}: {}", account_id, err)))?;
+        let stake: Balance =
+            stake.parse().map_err(|err| invalid(format!("invalid stake {:?}: {}", stake, err)))?;
+        if expected.insert(account_id.clone(), stake).is_some() {
+            return Err(invalid(format!("duplicate account {}", account_id)));
+        }
+    }
+    Ok(expected)
+}
+
+/// Joins the stakes of the chain with the expected ones, returning the number
+/// of matching accounts and the discrepancies, by account id.
+fn reconcile(
+    chain: &BTreeMap<AccountId, (Balance, StakeSource)>,
+    expected: &BTreeMap<AccountId, Balance>,
+    tolerance: Balance,
+) -> (usize, Vec<Discrepancy>) {
+    let mut num_matching = 0;
+    let mut discrepancies = vec![];
+    let account_ids: BTreeSet<&AccountId> = chain.keys().chain(expected.keys()).collect();
+    for account_id in account_ids {
+        let (chain_stake, source) = match chain.get(account_id) {
+            Some((stake, source)) => (Some(*stake), Some(*source)),
+            None => (None, None),
+        };
+        let expected_stake = expected.get(account_id).copied();
+        let kind = match (chain_stake, expected_stake) {
+            (Some(chain), Some(expected)) => {
+                let difference = chain.max(expected) - chain.min(expected);
+                if difference <= tolerance {
+                    num_matching += 1;
+                    continue;
+                }
+                DiscrepancyKind::Mismatch
+            }
+            (Some(_), None) => DiscrepancyKind::OnlyChain,
+            (None, _) => DiscrepancyKind::OnlyExpected,
+        };
+        discrepancies.push(Discrepancy {
+            account_id: account_id.clone(),
+            expected: expected_stake,
+            chain: chain_stake,
+            source,
+            kind,
+        });
+    }
+    (num_matching, discrepancies)
+}
+
+fn write_csv(out: &mut impl Write, discrepancies: &[Discrepancy]) -> std::io::Result<()> {
+    writeln!(out, "{}", CSV_HEADER)?;
+    for discrepancy in discrepancies {
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            discrepancy.account_id,
+            discrepancy.expected.map_or(String::new(), |stake| stake.to_string()),
+            discrepancy.chain.map_or(String::new(), |stake| stake.to_string()),
+            discrepancy.difference(),
+            discrepancy.source.map_or("", StakeSource::as_str),
+            discrepancy.kind.as_str()
+        )?;
+    }
+    Ok(())
+}
+
+/// Compares the stakes of the canonical epoch at `epoch_height` with the ones
+/// in the `expected` CSV file, printing the discrepancies and writing them to
+/// the `out` CSV file if given.
+pub(crate) fn reconcile_stake(
+    epoch_height: EpochHeight,
+    expected: &Path,
+    tolerance: Balance,
+    out: Option<&Path>,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let expected_csv = std::fs::read_to_string(expected).map_err(|err| {
+        failure(FailureKind::InvalidArgument, format!("can't read {}: {}", expected.display(), err))
+    })?;
+    let expected = parse_expected(&expected_csv)?;
+
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let chain_store =
+        ChainStore::new(store.clone(), genesis_height, !near_config.client_config.archive);
+    let mut epoch_manager =
+        EpochManager::new_from_genesis_config(store, &near_config.genesis.config)?;
+    let (epoch_id, _) =
+        canonical_epochs(&chain_store, &mut epoch_manager, epoch_height, epoch_height)?
+            .pop()
+            .ok_or_else(|| {
+                failure(
+                    FailureKind::NotFound,
+                    format!("no canonical epoch at epoch height {}", epoch_height),
+                )
+            })?;
+
+    let mut chain = BTreeMap::new();
+    let epoch_info = epoch_manager.get_epoch_info(&epoch_id)?;
+    for validator in epoch_info.validators_iter() {
+        chain.insert(validator.account_id().clone(), (validator.stake(), StakeSource::Validator));
+    }
+    let summary = epoch_manager.get_epoch_validator_info(&epoch_id).ok();
+    for proposal in summary.iter().flat_map(|summary| summary.all_proposals.iter()) {
+        chain
+            .entry(proposal.account_id().clone())
+            .or_insert((proposal.stake(), StakeSource::Proposal));
+    }
+
+    let (num_matching, discrepancies) = reconcile(&chain, &expected, tolerance);
+    if let Some(out) = out {
+        let mut file = BufWriter::new(File::create(out)?);
+        write_csv(&mut file, &discrepancies)?;
+        file.flush()?;
+    }
+    output.print(&StakeReconciliation {
+        epoch_height,
+        epoch_id,
+        tolerance,
+        proposals_known: summary.is_some(),
+        num_matching,
+        discrepancies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_expected() {
+        let expected = parse_expected("account_id,stake\nalice,100\n\n bob , 20 \n").unwrap();
+        assert_eq!(expected, BTreeMap::from([(account("alice"), 100), (account("bob"), 20)]));
+        for csv in ["alice", "alice,1,2", "Alice!,1", "alice,-1", "alice,1\nalice,2"] {
+            let err = parse_expected(csv).unwrap_err();
+            assert_eq!(crate::output::failure_kind(&err), FailureKind::InvalidArgument, "{}", csv);
+        }
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let chain = BTreeMap::from([
+            (account("alice"), (100, StakeSource::Validator)),
+            (account("bob"), (50, StakeSource::Validator)),
+            (account("carol"), (30, StakeSource::Proposal)),
+            (account("dave"), (10, StakeSource::Validator)),
+        ]);
+        let expected = BTreeMap::from([
+            (account("alice"), 98),
+            (account("bob"), 60),
+            (account("dave"), 10),
+            (account("erin"), 5),
+        ]);
+        let (num_matching, discrepancies) = reconcile(&chain, &expected, 2);
+        assert_eq!(num_matching, 2);
+        let kinds: Vec<_> = discrepancies
+            .iter()
+            .map(|discrepancy| (discrepancy.account_id.as_str(), discrepancy.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("bob", DiscrepancyKind::Mismatch),
+                ("carol", DiscrepancyKind::OnlyChain),
+                ("erin", DiscrepancyKind::OnlyExpected),
+            ]
+        );
+
+        let mut out = vec![];
+        write_csv(&mut out, &discrepancies).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            rows,
+            [
+                CSV_HEADER,
+                "bob,60,50,-10,validator,mismatch",
+                "carol,,30,30,proposal,only_chain",
+                "erin,5,,-5,,only_expected",
+            ]
+        );
+    }
+}
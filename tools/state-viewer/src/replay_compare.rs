@@ -0,0 +1,551 @@
+//! Replays a range of chunks in two binaries and compares the results.
+//!
+//! The local side applies chunks in-process.  The other side is a second
+//! `neard` binary started as `<binary> --home <dir> view-state replay-server`
+//! which reads one JSON [`ReplayRequest`] per line on stdin and answers with
+//! one JSON [`ReplayResponse`] per line on stdout.  Anything else the server
+//! wants to say goes to stderr, which is passed through.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use near_chain::chain::collect_receipts_from_response;
+use near_chain::migrations::check_if_block_is_first_with_chunk_of_version;
+use near_chain::types::ApplyTransactionResult;
+use near_chain::{ChainStore, ChainStoreAccess, ChainStoreUpdate, RuntimeAdapter};
+use near_primitives::hash::CryptoHash;
+use near_primitives::serialize::u128_dec_format;
+use near_primitives::types::{Balance, BlockHeight, Gas, ShardId, StateRoot};
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+
+/// A single request sent to the replay server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ReplayRequest {
+    pub height: BlockHeight,
+    pub shard_id: ShardId,
+}
+
+/// Answer of the replay server to a single [`ReplayRequest`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum ReplayResponse {
+    Applied(ChunkReplay),
+    /// There is nothing to apply at the height, e.g. the block is missing.
+    Skipped,
+    Error {
+        message: String,
+    },
+}
+
+/// Result of applying the chunk of a shard at a given height.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ChunkReplay {
+    pub block_hash: CryptoHash,
+    /// Whether the block contains a new chunk for the shard.  If not, only
+    /// the block-level part of the state transition has been applied.
+    pub chunk_present: bool,
+    pub state_root: StateRoot,
+    pub outcome_root: CryptoHash,
+    pub gas_burnt: Gas,
+    #[serde(with = "u128_dec_format")]
+    pub balance_burnt: Balance,
+    pub outcomes: Vec<OutcomeDigest>,
+}
+
+/// Condensed execution outcome of a single transaction or receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OutcomeDigest {
+    /// Id of the transaction or receipt.
+    pub id: CryptoHash,
+    pub gas_burnt: Gas,
+    /// Hash of the borsh-serialised outcome.
+    pub hash: CryptoHash,
+}
+
+/// Machine-readable summary of a `replay-compare` run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ReplayCompareReport {
+    pub shard_id: ShardId,
+    pub start_height: BlockHeight,
+    pub end_height: BlockHeight,
+    /// Number of heights at which both sides applied a chunk.
+    pub compared: u64,
+    /// Number of heights both sides had nothing to apply at.
+    pub skipped: u64,
+    /// The first difference found; comparison stops there.
+    pub divergence: Option<Divergence>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Divergence {
+    pub height: BlockHeight,
+    pub shard_id: ShardId,
+    pub block_hash: Option<CryptoHash>,
+    /// Name of the first field which differs, e.g. `state_root` or `outcome`.
+    pub field: String,
+    /// Transaction or receipt whose outcome differs first, if any.
+    pub receipt_id: Option<CryptoHash>,
+    pub ours: Value,
+    pub theirs: Value,
+}
+
+/// Something which can apply chunks at given heights.
+pub(crate) trait ChunkReplayer {
+    fn replay(&mut self, request: &ReplayRequest) -> anyhow::Result<Option<ChunkReplay>>;
+}
+
+/// Applies chunks in-process using the given runtime.
+pub(crate) struct LocalReplayer {
+    chain_store: ChainStore,
+    runtime_adapter: Arc<dyn RuntimeAdapter>,
+}
+
+impl LocalReplayer {
+    pub(crate) fn new(chain_store: ChainStore, runtime_adapter: Arc<dyn RuntimeAdapter>) -> Self {
+        Self { chain_store, runtime_adapter }
+    }
+}
+
+impl ChunkReplayer for LocalReplayer {
+    fn replay(&mut self, request: &ReplayRequest) -> anyhow::Result<Option<ChunkReplay>> {
+        replay_chunk(&mut self.chain_store, self.runtime_adapter.as_ref(), request)
+    }
+}
+
+/// Creates a [`LocalReplayer`] over the node's own store and config.
+pub(crate) fn local_replayer(
+    home_dir: &Path,
+    near_config: &NearConfig,
+    store: Store,
+) -> LocalReplayer {
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store.clone(),
+        near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let chain_store = ChainStore::new(store, near_config.genesis.config.genesis_height, false);
+    LocalReplayer::new(chain_store, Arc::new(runtime))
+}
+
+/// Applies chunks in a child process speaking the replay protocol.
+pub(crate) struct RemoteReplayer {
+    child: Child,
+    /// Always `Some` until dropped; closing it tells the server to exit.
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl RemoteReplayer {
+    /// Starts `binary --home home_dir view-state replay-server`.
+    pub(crate) fn spawn(binary: &Path, home_dir: &Path) -> anyhow::Result<Self> {
+        let mut child = Command::new(binary)
+            .arg("--home")
+            .arg(home_dir)
+            .args(["view-state", "replay-server"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("failed to start {}", binary.display()))?;
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self { child, stdin, stdout })
+    }
+}
+
+impl ChunkReplayer for RemoteReplayer {
+    fn replay(&mut self, request: &ReplayRequest) -> anyhow::Result<Option<ChunkReplay>> {
+        let stdin = self.stdin.as_mut().unwrap();
+        serde_json::to_writer(&mut *stdin, request)?;
+        stdin.write_all(b"\n")?;
+        stdin.flush()?;
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            anyhow::bail!("replay server exited before answering {:?}", request);
+        }
+        let response: ReplayResponse = serde_json::from_str(&line)
+            .with_context(|| format!("malformed response from replay server: {}", line.trim()))?;
+        match response {
+            ReplayResponse::Applied(replay) => Ok(Some(replay)),
+            ReplayResponse::Skipped => Ok(None),
+            ReplayResponse::Error { message } => {
+                anyhow::bail!("replay server failed at {:?}: {}", request, message)
+            }
+        }
+    }
+}
+
+impl Drop for RemoteReplayer {
+    fn drop(&mut self) {
+        drop(self.stdin.take());
+        if let Err(err) = self.child.wait() {
+            tracing::warn!(target: "state_viewer", ?err, "failed to wait for the replay server");
+        }
+    }
+}
+
+/// Serves replay requests read from `input` until it is exhausted.
+pub(crate) fn serve(
+    replayer: &mut dyn ChunkReplayer,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> anyhow::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<ReplayRequest>(&line) {
+            Ok(request) => match replayer.replay(&request) {
+                Ok(Some(replay)) => ReplayResponse::Applied(replay),
+                Ok(None) => ReplayResponse::Skipped,
+                Err(err) => ReplayResponse::Error { message: format!("{:#}", err) },
+            },
+            Err(err) => ReplayResponse::Error { message: format!("malformed request: {}", err) },
+        };
+        serde_json::to_writer(&mut output, &response)?;
+        output.write_all(b"\n")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Applies every height in `start_height..=end_height` on both sides and
+/// stops at the first height where the results differ.
+pub(crate) fn replay_compare(
+    ours: &mut dyn ChunkReplayer,
+    theirs: &mut dyn ChunkReplayer,
+    start_height: BlockHeight,
+    end_height: BlockHeight,
+    shard_id: ShardId,
+) -> anyhow::Result<ReplayCompareReport> {
+    let mut report = ReplayCompareReport {
+        shard_id,
+        start_height,
+        end_height,
+        compared: 0,
+        skipped: 0,
+        divergence: None,
+    };
+    for height in start_height..=end_height {
+        let request = ReplayRequest { height, shard_id };
+        let our_replay = ours.replay(&request)?;
+        let their_replay = theirs.replay(&request)?;
+        match (&our_replay, &their_replay) {
+            (None, None) => report.skipped += 1,
+            (Some(_), Some(_)) => report.compared += 1,
+            _ => {}
+        }
+        report.divergence = find_divergence(&request, our_replay.as_ref(), their_replay.as_ref());
+        if report.divergence.is_some() {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+fn find_divergence(
+    request: &ReplayRequest,
+    ours: Option<&ChunkReplay>,
+    theirs: Option<&ChunkReplay>,
+) -> Option<Divergence> {
+    let divergence = |block_hash: Option<CryptoHash>,
+                      field: &str,
+                      receipt_id: Option<CryptoHash>,
+                      ours: Value,
+                      theirs: Value| Divergence {
+        height: request.height,
+        shard_id: request.shard_id,
+        block_hash,
+        field: field.to_string(),
+        receipt_id,
+        ours,
+        theirs,
+    };
+    let (ours, theirs) = match (ours, theirs) {
+        (None, None) => return None,
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        (ours, theirs) => {
+            let block_hash = ours.or(theirs).map(|replay| replay.block_hash);
+            return Some(divergence(
+                block_hash,
+                "applied",
+                None,
+                ours.is_some().into(),
+                theirs.is_some().into(),
+            ));
+        }
+    };
+    let block_hash = Some(ours.block_hash);
+    if ours.block_hash != theirs.block_hash || ours.chunk_present != theirs.chunk_present {
+        return Some(divergence(
+            block_hash,
+            "block",
+            None,
+            json!({"block_hash": ours.block_hash, "chunk_present": ours.chunk_present}),
+            json!({"block_hash": theirs.block_hash, "chunk_present": theirs.chunk_present}),
+        ));
+    }
+    // Outcomes come first because they point at the offending receipt.
+    let outcomes = ours.outcomes.iter().zip(theirs.outcomes.iter());
+    if let Some((our_outcome, their_outcome)) = outcomes.clone().find(|(a, b)| a != b) {
+        let receipt_id = Some(our_outcome.id);
+        return Some(divergence(
+            block_hash,
+            "outcome",
+            receipt_id,
+            json!(our_outcome),
+            json!(their_outcome),
+        ));
+    }
+    if ours.outcomes.len() != theirs.outcomes.len() {
+        let common = outcomes.count();
+        let receipt_id = ours.outcomes.get(common).or(theirs.outcomes.get(common)).map(|o| o.id);
+        return Some(divergence(
+            block_hash,
+            "outcomes_count",
+            receipt_id,
+            ours.outcomes.len().into(),
+            theirs.outcomes.len().into(),
+        ));
+    }
+    let fields = [
+        ("state_root", json!(ours.state_root), json!(theirs.state_root)),
+        ("outcome_root", json!(ours.outcome_root), json!(theirs.outcome_root)),
+        ("gas_burnt", json!(ours.gas_burnt), json!(theirs.gas_burnt)),
+        (
+            "balance_burnt",
+            json!(ours.balance_burnt.to_string()),
+            json!(theirs.balance_burnt.to_string()),
+        ),
+    ];
+    fields
+        .into_iter()
+        .find(|(_, a, b)| a != b)
+        .map(|(field, a, b)| divergence(block_hash, field, None, a, b))
+}
+
+/// Applies the chunk of `request.shard_id` at `request.height` on top of the
+/// state recorded in `chain_store`.
+///
+/// Returns `None` if there is nothing to apply, i.e. there is no block at the
+/// height, the block is the genesis or its predecessor is unavailable.
+pub(crate) fn replay_chunk(
+    chain_store: &mut ChainStore,
+    runtime_adapter: &dyn RuntimeAdapter,
+    request: &ReplayRequest,
+) -> anyhow::Result<Option<ChunkReplay>> {
+    let ReplayRequest { height, shard_id } = *request;
+    let block_hash = match chain_store.get_block_hash_by_height(height) {
+        Ok(block_hash) => block_hash,
+        Err(_) => return Ok(None),
+    };
+    let block = chain_store.get_block(&block_hash)?.clone();
+    if *block.header().prev_hash() == CryptoHash::default() {
+        return Ok(None);
+    }
+    let prev_block = match chain_store.get_block(block.header().prev_hash()) {
+        Ok(prev_block) => prev_block.clone(),
+        Err(_) => return Ok(None),
+    };
+    let chunk_header = block
+        .chunks()
+        .get(shard_id as usize)
+        .cloned()
+        .with_context(|| format!("block {} has no shard {}", block_hash, shard_id))?;
+    let chunk_present = chunk_header.height_included() == height;
+
+    let apply_result = if chunk_present {
+        let chunk = chain_store.get_chunk(&chunk_header.chunk_hash())?.clone();
+        let receipt_proof_response = ChainStoreUpdate::new(chain_store)
+            .get_incoming_receipts_for_shard(
+                shard_id,
+                block_hash,
+                prev_block.chunks()[shard_id as usize].height_included(),
+            )?;
+        let receipts = collect_receipts_from_response(&receipt_proof_response);
+        let is_first_block_with_chunk_of_version = check_if_block_is_first_with_chunk_of_version(
+            chain_store,
+            runtime_adapter,
+            block.header().prev_hash(),
+            shard_id,
+        )?;
+        let chunk_inner = chunk.cloned_header().take_inner();
+        runtime_adapter.apply_transactions(
+            shard_id,
+            chunk_inner.prev_state_root(),
+            height,
+            block.header().raw_timestamp(),
+            block.header().prev_hash(),
+            block.hash(),
+            &receipts,
+            chunk.transactions(),
+            chunk_inner.validator_proposals(),
+            prev_block.header().gas_price(),
+            chunk_inner.gas_limit(),
+            block.header().challenges_result(),
+            *block.header().random_value(),
+            true,
+            is_first_block_with_chunk_of_version,
+            None,
+        )?
+    } else {
+        let shard_uid = runtime_adapter.shard_id_to_uid(shard_id, block.header().epoch_id())?;
+        let chunk_extra = chain_store.get_chunk_extra(block.header().prev_hash(), &shard_uid)?;
+        runtime_adapter.apply_transactions(
+            shard_id,
+            chunk_extra.state_root(),
+            height,
+            block.header().raw_timestamp(),
+            block.header().prev_hash(),
+            block.hash(),
+            &[],
+            &[],
+            chunk_extra.validator_proposals(),
+            block.header().gas_price(),
+            chunk_extra.gas_limit(),
+            block.header().challenges_result(),
+            *block.header().random_value(),
+            false,
+            false,
+            None,
+        )?
+    };
+
+    let (outcome_root, _) = ApplyTransactionResult::compute_outcomes_proof(&apply_result.outcomes);
+    let outcomes = apply_result
+        .outcomes
+        .iter()
+        .map(|outcome| OutcomeDigest {
+            id: outcome.id,
+            gas_burnt: outcome.outcome.gas_burnt,
+            hash: CryptoHash::hash_borsh(&outcome.outcome),
+        })
+        .collect();
+    Ok(Some(ChunkReplay {
+        block_hash,
+        chunk_present,
+        state_root: apply_result.new_root,
+        outcome_root,
+        gas_burnt: apply_result.total_gas_burnt,
+        balance_burnt: apply_result.total_balance_burnt,
+        outcomes,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use near_chain::{ChainGenesis, ChainStore, Provenance, RuntimeAdapter};
+    use near_chain_configs::Genesis;
+    use near_client::test_utils::TestEnv;
+    use near_crypto::{InMemorySigner, KeyType};
+    use near_primitives::transaction::SignedTransaction;
+    use near_store::test_utils::create_test_store;
+    use nearcore::config::GenesisExt;
+    use nearcore::NightshadeRuntime;
+
+    use super::*;
+
+    fn setup() -> (near_store::Store, Genesis) {
+        let genesis = Genesis::test(vec!["test0".parse().unwrap(), "test1".parse().unwrap()], 1);
+        let store = create_test_store();
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let mut chain_genesis = ChainGenesis::test();
+        chain_genesis.gas_limit = genesis.config.gas_limit;
+        let mut env =
+            TestEnv::builder(chain_genesis).runtime_adapters(vec![Arc::new(runtime)]).build();
+        let genesis_hash = *env.clients[0].chain.genesis().hash();
+        let signer = InMemorySigner::from_seed("test0".parse().unwrap(), KeyType::ED25519, "test0");
+        let tx = SignedTransaction::send_money(
+            1,
+            "test0".parse().unwrap(),
+            "test1".parse().unwrap(),
+            &signer,
+            100,
+            genesis_hash,
+        );
+        env.clients[0].process_tx(tx, false, false);
+        for height in 1..=5 {
+            let block = env.clients[0].produce_block(height).unwrap().unwrap();
+            env.process_block(0, block, Provenance::PRODUCED);
+        }
+        (store, genesis)
+    }
+
+    fn test_replayer(store: &near_store::Store, genesis: &Genesis) -> LocalReplayer {
+        let runtime: Arc<dyn RuntimeAdapter> =
+            Arc::new(NightshadeRuntime::test(Path::new("."), store.clone(), genesis));
+        let chain_store = ChainStore::new(store.clone(), genesis.config.genesis_height, false);
+        LocalReplayer::new(chain_store, runtime)
+    }
+
+    /// Replays requests through [`serve`] to emulate the other binary.
+    struct PipeReplayer(LocalReplayer);
+
+    impl ChunkReplayer for PipeReplayer {
+        fn replay(&mut self, request: &ReplayRequest) -> anyhow::Result<Option<ChunkReplay>> {
+            let input = serde_json::to_string(request).unwrap() + "\n";
+            let mut output = Vec::new();
+            serve(&mut self.0, input.as_bytes(), &mut output).unwrap();
+            match serde_json::from_slice(&output).unwrap() {
+                ReplayResponse::Applied(replay) => Ok(Some(replay)),
+                ReplayResponse::Skipped => Ok(None),
+                ReplayResponse::Error { message } => anyhow::bail!(message),
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_compare_identical() {
+        let (store, genesis) = setup();
+        let mut ours = test_replayer(&store, &genesis);
+        let mut theirs = PipeReplayer(test_replayer(&store, &genesis));
+        let report = replay_compare(&mut ours, &mut theirs, 0, 6, 0).unwrap();
+        assert_eq!(report.divergence, None);
+        // Genesis and the height past the head are skipped.
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.compared, 5);
+    }
+
+    #[test]
+    fn test_find_divergence() {
+        let (store, genesis) = setup();
+        let mut replayer = test_replayer(&store, &genesis);
+        let (request, ours) = (1..=5)
+            .map(|height| ReplayRequest { height, shard_id: 0 })
+            .find_map(|request| {
+                let replay = replayer.replay(&request).unwrap()?;
+                (!replay.outcomes.is_empty()).then(|| (request, replay))
+            })
+            .expect("the transfer should produce outcomes");
+        assert_eq!(find_divergence(&request, Some(&ours), Some(&ours)), None);
+
+        let mut theirs = ours.clone();
+        theirs.gas_burnt += 1;
+        let divergence = find_divergence(&request, Some(&ours), Some(&theirs)).unwrap();
+        assert_eq!(divergence.field, "gas_burnt");
+        assert_eq!(divergence.receipt_id, None);
+
+        let mut theirs = ours.clone();
+        theirs.outcomes[0].gas_burnt += 1;
+        let divergence = find_divergence(&request, Some(&ours), Some(&theirs)).unwrap();
+        assert_eq!(divergence.field, "outcome");
+        assert_eq!(divergence.receipt_id, Some(ours.outcomes[0].id));
+        assert_eq!(divergence.block_hash, Some(ours.block_hash));
+
+        let divergence = find_divergence(&request, Some(&ours), None).unwrap();
+        assert_eq!(divergence.field, "applied");
+    }
+}
@@ -1,8 +1,15 @@
+use crate::output::{failure, FailureKind, Output};
+use near_primitives::time::Clock;
+use near_store::{col_name, ColumnProperties, DBCol, RocksDB, Store, StoreConfig};
 use nearcore::get_store_path;
 use serde::Serialize;
 use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
+use strum::IntoEnumIterator;
 
 #[derive(Serialize, Debug)]
 struct Data {
@@ -13,6 +20,9 @@ struct Data {
     raw_key_size: u64,
     // Total size of all values in bytes.
     raw_value_size: u64,
+    // Flush and compaction backlog, the same the node exports as metrics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backlog: Option<ColumnProperties>,
 }
 
 // SST file dump keys we use to collect statistics.
@@ -61,6 +71,7 @@ impl Data {
                 .unwrap(),
             raw_key_size: values.get(SST_FILE_DUMP_LINES[3]).unwrap().parse::<u64>().unwrap(),
             raw_value_size: values.get(SST_FILE_DUMP_LINES[4]).unwrap().parse::<u64>().unwrap(),
+            backlog: None,
         })
     }
 
@@ -72,24 +83,29 @@ impl Data {
     }
 }
 
-pub fn get_rocksdb_stats(home_dir: &Path, file: Option<PathBuf>) -> anyhow::Result<()> {
+pub fn get_rocksdb_stats(
+    home_dir: &Path,
+    file: Option<PathBuf>,
+    store: &Store,
+    output: &Output,
+) -> anyhow::Result<()> {
     let store_dir = get_store_path(&home_dir);
     let mut cmd = Command::new("sst_dump");
     cmd.arg(format!("--file={}", store_dir.to_str().unwrap()))
         .arg("--show_properties")
         .arg("--command=none"); // For some reason, adding this argument makes execution 20x faster
     eprintln!("Running {:?} ...", cmd);
-    let output = cmd.output()?;
-    if !output.status.success() {
+    let sst_dump = cmd.output()?;
+    if !sst_dump.status.success() {
         anyhow::bail!(
             "failed to run sst_dump, {}, stderr: {}",
-            output.status,
-            String::from_utf8_lossy(&output.stderr)
+            sst_dump.status,
+            String::from_utf8_lossy(&sst_dump.stderr)
         );
     }
 
     eprintln!("Parsing output ...");
-    let out = std::str::from_utf8(&output.stdout).unwrap();
+    let out = std::str::from_utf8(&sst_dump.stdout).unwrap();
     let lines: Vec<&str> = out.lines().collect();
     let mut column_data: HashMap<String, Data> = HashMap::new();
     for sst_file_slice in lines.split(|line| line.contains("Process")).skip(1) {
@@ -104,14 +120,277 @@ pub fn get_rocksdb_stats(home_dir: &Path, file: Option<PathBuf>) -> anyhow::Resu
         }
     }
 
+    if let Some(db) = store.get_rocksdb() {
+        for col in DBCol::iter() {
+            if let Some(data) = column_data.get_mut(&col_name(col)) {
+                data.backlog = Some(db.get_column_properties(col)?);
+            }
+        }
+    }
+
     let mut column_data_list: Vec<&Data> = column_data.values().collect();
     column_data_list.sort_by_key(|data| std::cmp::Reverse(data.estimated_table_size));
-    let result = serde_json::to_string_pretty(&column_data_list).unwrap();
 
     eprintln!("Dumping stats ...");
     match file {
-        None => println!("{}", result),
-        Some(file) => std::fs::write(file, result)?,
+        None => output.print_json(&column_data_list)?,
+        Some(file) => std::fs::write(file, serde_json::to_string_pretty(&column_data_list)?)?,
     }
     Ok(())
 }
+
+/// SST files of a column and its compaction backlog at one point.
+struct ColumnSample {
+    col: String,
+    /// Size and number of entries of the SST files, by file name, `None` if
+    /// the files couldn't be listed.
+    files: Option<HashMap<String, (u64, u64)>>,
+    pending_compaction_bytes: Option<u64>,
+}
+
+/// SST files written and compacted away between two samples.  Flushes and
+/// compactions write new files, and the files a compaction read are deleted
+/// once it's done.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+struct FilesDelta {
+    files_added: u64,
+    files_compacted: u64,
+    bytes_written: u64,
+    bytes_read: u64,
+    /// Entries, including deletions, of the files added and removed.
+    keys_added: u64,
+    keys_removed: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ColumnDelta {
+    col: String,
+    #[serde(flatten)]
+    files: Option<FilesDelta>,
+    pending_compaction_bytes: Option<u64>,
+    pending_compaction_bytes_delta: Option<i64>,
+}
+
+impl ColumnDelta {
+    fn new(prev: &ColumnSample, next: &ColumnSample) -> Self {
+        let files = match (&prev.files, &next.files) {
+            (Some(prev), Some(next)) => {
+                let mut delta = FilesDelta::default();
+                for (name, &(size, entries)) in next {
+                    if !prev.contains_key(name) {
+                        delta.files_added += 1;
+                        delta.bytes_written += size;
+                        delta.keys_added += entries;
+                    }
+                }
+                for (name, &(size, entries)) in prev {
+                    if !next.contains_key(name) {
+                        delta.files_compacted += 1;
+                        delta.bytes_read += size;
+                        delta.keys_removed += entries;
+                    }
+                }
+                Some(delta)
+            }
+            _ => None,
+        };
+        let pending_compaction_bytes_delta =
+            match (prev.pending_compaction_bytes, next.pending_compaction_bytes) {
+                (Some(prev), Some(next)) => Some(next as i64 - prev as i64),
+                _ => None,
+            };
+        Self {
+            col: next.col.clone(),
+            files,
+            pending_compaction_bytes: next.pending_compaction_bytes,
+            pending_compaction_bytes_delta,
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.files.map_or(true, |files| files == FilesDelta::default())
+            && self.pending_compaction_bytes_delta.unwrap_or(0) == 0
+    }
+}
+
+#[derive(Serialize)]
+struct WatchSample<'a> {
+    timestamp: String,
+    interval_secs: u64,
+    columns: &'a [ColumnDelta],
+}
+
+fn sample(db: &RocksDB) -> Vec<ColumnSample> {
+    let live_files = db
+        .live_files()
+        .map_err(|err| tracing::debug!(target: "state_viewer", %err, "Can't list SST files"))
+        .ok();
+    DBCol::iter()
+        .map(|col| {
+            let name = col_name(col);
+            let files = live_files.as_ref().map(|live_files| {
+                live_files
+                    .iter()
+                    .filter(|file| file.column_family_name == name)
+                    .map(|file| (file.name.clone(), (file.size as u64, file.num_entries)))
+                    .collect()
+            });
+            let pending_compaction_bytes = db
+                .property_int_value(col, "rocksdb.estimate-pending-compaction-bytes")
+                .ok()
+                .flatten();
+            ColumnSample { col: name, files, pending_compaction_bytes }
+        })
+        .collect()
+}
+
+fn mib(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / 1048576.0)
+}
+
+fn print_deltas(timestamp: &str, columns: &[ColumnDelta]) {
+    let mut active: Vec<&ColumnDelta> = columns.iter().filter(|delta| !delta.is_idle()).collect();
+    if active.is_empty() {
+        println!("{} no activity", timestamp);
+        return;
+    }
+    active.sort_by_key(|delta| {
+        std::cmp::Reverse(delta.files.map_or(0, |files| files.bytes_written + files.bytes_read))
+    });
+    println!(
+        "{}\n{:>24} {:>9} {:>13} {:>13} {:>11} {:>11} {:>14} {:>14}",
+        timestamp,
+        "column",
+        "files +/-",
+        "written",
+        "read",
+        "keys +",
+        "keys -",
+        "pending",
+        "pending delta"
+    );
+    let unavailable = || "-".to_string();
+    for delta in active {
+        let files = |f: fn(&FilesDelta) -> String| delta.files.as_ref().map_or_else(unavailable, f);
+        println!(
+            "{:>24} {:>9} {:>13} {:>13} {:>11} {:>11} {:>14} {:>14}",
+            delta.col,
+            files(|files| format!("+{}/-{}", files.files_added, files.files_compacted)),
+            files(|files| mib(files.bytes_written)),
+            files(|files| mib(files.bytes_read)),
+            files(|files| files.keys_added.to_string()),
+            files(|files| files.keys_removed.to_string()),
+            delta.pending_compaction_bytes.map_or_else(unavailable, mib),
+            delta
+                .pending_compaction_bytes_delta
+                .map_or_else(unavailable, |bytes| format!("{:+.1} MiB", bytes as f64 / 1048576.0)),
+        );
+    }
+}
+
+/// Prints what changed in every column every `interval`, until interrupted.
+/// The database is opened as a secondary instance, which follows the writes of
+/// the node running on it.
+pub(crate) fn watch_rocksdb_stats(
+    home_dir: &Path,
+    store_config: &StoreConfig,
+    interval: Duration,
+    log: Option<&Path>,
+) -> anyhow::Result<()> {
+    if interval.is_zero() {
+        return Err(failure(FailureKind::InvalidArgument, "the watch interval must be positive"));
+    }
+    let secondary_dir = tempfile::tempdir()?;
+    let db =
+        RocksDB::open_as_secondary(&get_store_path(home_dir), secondary_dir.path(), store_config)
+            .map_err(|err| {
+            anyhow::anyhow!("Failed to open the store as a secondary instance: {}", err)
+        })?;
+    let mut log = match log {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+
+    let mut prev = sample(&db);
+    if prev.iter().any(|sample| sample.files.is_none()) {
+        eprintln!("The SST files can't be listed, the file, byte and key deltas are unavailable");
+    }
+    if prev.iter().any(|sample| sample.pending_compaction_bytes.is_none()) {
+        eprintln!("The pending compaction bytes of some columns are unavailable");
+    }
+    loop {
+        std::thread::sleep(interval);
+        if let Err(err) = db.try_catch_up_with_primary() {
+            eprintln!("Failed to catch up with the node, the deltas may be stale: {}", err);
+        }
+        let next = sample(&db);
+        let columns: Vec<ColumnDelta> =
+            prev.iter().zip(&next).map(|(prev, next)| ColumnDelta::new(prev, next)).collect();
+        let timestamp = Clock::utc().to_rfc3339();
+        print_deltas(&timestamp, &columns);
+        if let Some(log) = &mut log {
+            let sample =
+                WatchSample { timestamp, interval_secs: interval.as_secs(), columns: &columns };
+            writeln!(log, "{}", serde_json::to_string(&sample)?)?;
+        }
+        prev = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(files: &[(&str, u64, u64)], pending_compaction_bytes: Option<u64>) -> ColumnSample {
+        ColumnSample {
+            col: "col".to_string(),
+            files: Some(
+                files.iter().map(|&(name, size, keys)| (name.to_string(), (size, keys))).collect(),
+            ),
+            pending_compaction_bytes,
+        }
+    }
+
+    #[test]
+    fn test_column_delta() {
+        // A flush wrote file 3, a compaction rewrote files 1 and 2 into 4.
+        let prev = sample(&[("1", 100, 10), ("2", 50, 5)], Some(1000));
+        let next = sample(&[("3", 20, 2), ("4", 120, 12)], Some(400));
+        let delta = ColumnDelta::new(&prev, &next);
+        assert_eq!(
+            delta.files,
+            Some(FilesDelta {
+                files_added: 2,
+                files_compacted: 2,
+                bytes_written: 140,
+                bytes_read: 150,
+                keys_added: 14,
+                keys_removed: 15,
+            })
+        );
+        assert_eq!(delta.pending_compaction_bytes_delta, Some(-600));
+        assert!(!delta.is_idle());
+        assert!(ColumnDelta::new(&next, &next).is_idle());
+
+        // What's unavailable in either sample is left out.
+        let unavailable =
+            ColumnSample { col: "col".to_string(), files: None, pending_compaction_bytes: None };
+        let delta = ColumnDelta::new(&prev, &unavailable);
+        assert_eq!((delta.files, delta.pending_compaction_bytes_delta), (None, None));
+        assert!(delta.is_idle());
+        assert_eq!(
+            serde_json::to_value(&ColumnDelta::new(&next, &next)).unwrap(),
+            serde_json::json!({
+                "col": "col",
+                "files_added": 0,
+                "files_compacted": 0,
+                "bytes_written": 0,
+                "bytes_read": 0,
+                "keys_added": 0,
+                "keys_removed": 0,
+                "pending_compaction_bytes": 400,
+                "pending_compaction_bytes_delta": 0,
+            })
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! The runtime parameters in effect in the epoch of a block, resolved for the
+//! protocol version of the epoch by the runtime config store of the node, as
+//! opposed to the ones of the genesis.
+//!
+//! The parameters are flattened to dotted paths into the JSON of
+//! `RuntimeConfig`, so that the output of two heights can be compared key by
+//! key.  This version of the runtime has no congestion control parameters.
+
+use crate::output::{failure, FailureKind, Output};
+use near_chain::{ChainStore, ChainStoreAccess, RuntimeAdapter};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, EpochId, ProtocolVersion};
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Serialize, Debug, PartialEq)]
+struct ParamDiff {
+    genesis: Option<Value>,
+    current: Option<Value>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum Params {
+    All(BTreeMap<String, Value>),
+    DiffGenesis(BTreeMap<String, ParamDiff>),
+}
+
+#[derive(Serialize)]
+struct RuntimeParams {
+    height: BlockHeight,
+    epoch_id: CryptoHash,
+    protocol_version: ProtocolVersion,
+    genesis_protocol_version: ProtocolVersion,
+    params: Params,
+}
+
+impl fmt::Display for RuntimeParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Runtime parameters at #{} in epoch {}, protocol version {} (genesis {})",
+            self.height, self.epoch_id, self.protocol_version, self.genesis_protocol_version
+        )?;
+        let show = |value: &Option<Value>| value.as_ref().map_or("-".to_string(), Value::to_string);
+        match &self.params {
+            Params::All(params) => {
+                for (path, value) in params {
+                    write!(f, "\n  {} = {}", path, value)?;
+                }
+            }
+            Params::DiffGenesis(diffs) if diffs.is_empty() => {
+                write!(f, "\n  same as the genesis")?;
+            }
+            Params::DiffGenesis(diffs) => {
+                for (path, diff) in diffs {
+                    write!(
+                        f,
+                        "\n  {} = {} (genesis {})",
+                        path,
+                        show(&diff.current),
+                        show(&diff.genesis)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Flattens the JSON into its leaves, keyed by their dotted paths.
+fn flatten(value: &Value) -> BTreeMap<String, Value> {
+    fn visit(path: &mut String, value: &Value, out: &mut BTreeMap<String, Value>) {
+        let mut visit_child = |key: &str, child: &Value, out: &mut BTreeMap<String, Value>| {
+            let len = path.len();
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(key);
+            visit(path, child, out);
+            path.truncate(len);
+        };
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (key, child) in map {
+                    visit_child(key, child, out);
+                }
+            }
+            Value::Array(items) if !items.is_empty() => {
+                for (index, child) in items.iter().enumerate() {
+                    visit_child(&index.to_string(), child, out);
+                }
+            }
+            _ => {
+                out.insert(path.clone(), value.clone());
+            }
+        }
+    }
+    let mut out = BTreeMap::new();
+    visit(&mut String::new(), value, &mut out);
+    out
+}
+
+/// The parameters which differ between the genesis and the current ones.
+fn diff(
+    genesis: &BTreeMap<String, Value>,
+    current: &BTreeMap<String, Value>,
+) -> BTreeMap<String, ParamDiff> {
+    let mut diffs = BTreeMap::new();
+    for path in genesis.keys().chain(current.keys()) {
+        let (genesis, current) = (genesis.get(path), current.get(path));
+        if genesis != current {
+            diffs.insert(
+                path.clone(),
+                ParamDiff { genesis: genesis.cloned(), current: current.cloned() },
+            );
+        }
+    }
+    diffs
+}
+
+/// Prints the runtime parameters of the epoch of the block at `height`, or
+/// only the ones which differ from the genesis with `diff_genesis`.
+pub(crate) fn runtime_params(
+    height: BlockHeight,
+    diff_genesis: bool,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let block_hash = chain_store.get_block_hash_by_height(height).map_err(|_| {
+        failure(
+            FailureKind::NotFound,
+            format!("no block at height {} on the canonical chain", height),
+        )
+    })?;
+    let epoch_id = chain_store.get_block_header(&block_hash)?.epoch_id().clone();
+    let protocol_config = runtime.get_protocol_config(&epoch_id)?;
+    let genesis_protocol_config = runtime.get_protocol_config(&EpochId::default())?;
+    let current = flatten(&serde_json::to_value(&protocol_config.runtime_config)?);
+    let params = if diff_genesis {
+        let genesis = flatten(&serde_json::to_value(&genesis_protocol_config.runtime_config)?);
+        Params::DiffGenesis(diff(&genesis, &current))
+    } else {
+        Params::All(current)
+    };
+    output.print(&RuntimeParams {
+        height,
+        epoch_id: epoch_id.0,
+        protocol_version: protocol_config.genesis_config.protocol_version,
+        genesis_protocol_version: genesis_protocol_config.genesis_config.protocol_version,
+        params,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::runtime::config_store::RuntimeConfigStore;
+    use serde_json::json;
+
+    #[test]
+    fn test_flatten_and_diff() {
+        let genesis = flatten(&json!({
+            "fees": {"action": {"create_account": 1, "transfer": 2}},
+            "limits": {"max_length": 10, "sizes": [1, 2]},
+            "empty": {},
+        }));
+        assert_eq!(
+            genesis.keys().collect::<Vec<_>>(),
+            vec![
+                "empty",
+                "fees.action.create_account",
+                "fees.action.transfer",
+                "limits.max_length",
+                "limits.sizes.0",
+                "limits.sizes.1"
+            ]
+        );
+        let current = flatten(&json!({
+            "fees": {"action": {"create_account": 1, "transfer": 3}},
+            "limits": {"max_length": 10, "sizes": [1]},
+            "empty": {},
+            "new": true,
+        }));
+        let diffs = diff(&genesis, &current);
+        assert_eq!(
+            diffs.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    "fees.action.transfer".to_string(),
+                    ParamDiff { genesis: Some(json!(2)), current: Some(json!(3)) }
+                ),
+                (
+                    "limits.sizes.1".to_string(),
+                    ParamDiff { genesis: Some(json!(2)), current: None }
+                ),
+                ("new".to_string(), ParamDiff { genesis: None, current: Some(json!(true)) }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_between_versions() {
+        let store = RuntimeConfigStore::new(None);
+        let params =
+            |version| flatten(&serde_json::to_value(store.get_config(version).as_ref()).unwrap());
+        let diffs = diff(&params(0), &params(near_primitives::version::PROTOCOL_VERSION));
+        // A limit which came with protocol version 49.
+        assert_eq!(
+            diffs["wasm_config.limit_config.max_functions_number_per_contract"],
+            ParamDiff { genesis: None, current: Some(json!(10_000)) }
+        );
+        assert!(!diffs.contains_key("wasm_config.limit_config.max_stack_height"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Sampling of the items the heavy scans go through, for answering a question
+//! about the whole state from a fraction of it.
+//!
+//! An item is sampled when the hash of the seed and its key, e.g. the trie key
+//! of an access key, falls below the rate.  The same seed samples the same
+//! items on every run and machine, and the decision only needs the key, so
+//! the scans make it before reading and decoding the value.  The reports say
+//! they're sampled, with the rate and the factor to extrapolate their counts
+//! and totals to the whole state with.
+
+use crate::output::{failure, FailureKind};
+use near_primitives::hash::hash;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(clap::Args, Clone, Debug)]
+pub(crate) struct SamplingArgs {
+    /// Only scan this fraction of the items, from 0 excluded to 1, picked by
+    /// the hash of their key.
+    #[clap(long, default_value = "1")]
+    sample_rate: f64,
+    /// Seed of the hash picking the items, another seed picks another sample.
+    #[clap(long, default_value = "0")]
+    sample_seed: u64,
+}
+
+/// The sampling a report was made with.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SampleInfo {
+    pub rate: f64,
+    pub seed: u64,
+    /// What to multiply the counts and totals in the report with to estimate
+    /// the ones of all the items.
+    pub extrapolation_factor: f64,
+}
+
+impl fmt::Display for SampleInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sampled {}% of the items with seed {}, multiply the counts and totals by {:.2} to \
+             extrapolate",
+            self.rate * 100.0,
+            self.seed,
+            self.extrapolation_factor
+        )
+    }
+}
+
+pub(crate) struct Sampler {
+    seed: u64,
+    rate: f64,
+    /// The hashes below it are sampled, `None` for all items.
+    threshold: Option<u64>,
+}
+
+impl Sampler {
+    pub fn new(args: &SamplingArgs) -> anyhow::Result<Self> {
+        let rate = args.sample_rate;
+        if !(rate > 0.0 && rate <= 1.0) {
+            return Err(failure(
+                FailureKind::InvalidArgument,
+                format!("the sample rate must be above 0 and at most 1, got {}", rate),
+            ));
+        }
+        let threshold = (rate < 1.0).then(|| (rate * u64::MAX as f64) as u64);
+        Ok(Self { seed: args.sample_seed, rate, threshold })
+    }
+
+    /// Whether the item with the key is in the sample.
+    pub fn includes(&self, key: &[u8]) -> bool {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold,
+            None => return true,
+        };
+        let mut data = Vec::with_capacity(8 + key.len());
+        data.extend_from_slice(&self.seed.to_le_bytes());
+        data.extend_from_slice(key);
+        let hash = hash(&data);
+        u64::from_le_bytes(hash.as_ref()[..8].try_into().unwrap()) < threshold
+    }
+
+    /// The sampling to label the report with, `None` if all items are scanned.
+    pub fn info(&self) -> Option<SampleInfo> {
+        self.threshold.map(|_| SampleInfo {
+            rate: self.rate,
+            seed: self.seed,
+            extrapolation_factor: 1.0 / self.rate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampler(sample_rate: f64, sample_seed: u64) -> anyhow::Result<Sampler> {
+        Sampler::new(&SamplingArgs { sample_rate, sample_seed })
+    }
+
+    #[test]
+    fn test_sampler() {
+        let keys: Vec<Vec<u8>> = (0..10_000u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        let sampled = |sampler: &Sampler| -> Vec<usize> {
+            (0..keys.len()).filter(|&i| sampler.includes(&keys[i])).collect()
+        };
+
+        let all = sampler(1.0, 0).unwrap();
+        assert_eq!(sampled(&all).len(), keys.len());
+        assert_eq!(all.info(), None);
+
+        let tenth = sampler(0.1, 7).unwrap();
+        let sample = sampled(&tenth);
+        assert!((900..1100).contains(&sample.len()), "{}", sample.len());
+        // The same seed picks the same items, another one others.
+        assert_eq!(sampled(&sampler(0.1, 7).unwrap()), sample);
+        assert_ne!(sampled(&sampler(0.1, 8).unwrap()), sample);
+        let info = tenth.info().unwrap();
+        assert_eq!((info.rate, info.seed), (0.1, 7));
+        assert!((info.extrapolation_factor - 10.0).abs() < 1e-9);
+
+        for rate in [0.0, -0.5, 1.5, f64::NAN] {
+            let err = sampler(rate, 0).err().unwrap();
+            assert_eq!(crate::output::failure_kind(&err), FailureKind::InvalidArgument);
+        }
+    }
+}
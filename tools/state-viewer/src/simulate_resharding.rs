@@ -0,0 +1,396 @@
+//! Rehearsal of the split of a shard by a resharding on the state of the node.
+//!
+//! The states of the children of the shard are built by the same code the node
+//! runs, [`nearcore::build_state_for_split_shards`], from the state of the
+//! shard before the block at a height, into a temporary store so that the
+//! database of the node is only read.  The build is timed and run twice, into
+//! two stores, for the children roots to be compared.  The verification then
+//! goes through the items of the shard and checks that every item of an
+//! account is in the child of the account with the same value, that the
+//! children have no other items of accounts, and that they have as many
+//! delayed receipts as the shard, since those are renumbered in the children.
+
+use crate::commands::{load_trie_stop_at_height, LoadTrieMode};
+use crate::output::{failure, FailureKind, Output};
+use anyhow::Context;
+use near_chain::RuntimeAdapter;
+use near_primitives::serialize::to_base;
+use near_primitives::shard_layout::{account_id_to_shard_uid, ShardLayout, ShardUId};
+use near_primitives::trie_key::col;
+use near_primitives::trie_key::trie_key_parsers::parse_account_id_from_raw_key;
+use near_primitives::types::{BlockHeight, ShardId, StateRoot};
+use near_store::{ShardTries, Store};
+use nearcore::NearConfig;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Number of misplaced items listed in the report, the others are counted.
+const MAX_LISTED_MISPLACED: usize = 10;
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct ChildState {
+    shard_id: ShardId,
+    state_root: StateRoot,
+    num_accounts: u64,
+    /// Items of the accounts, the delayed receipts excluded.
+    num_items: u64,
+    num_delayed_receipts: u64,
+    /// Bytes of the keys and values of the items.
+    bytes: u64,
+    /// Memory usage of the trie, its nodes included.
+    memory_usage: u64,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct Verification {
+    num_items: u64,
+    num_delayed_receipts: u64,
+    /// Items of the shard which aren't in the child of their account with the
+    /// same value, or which are in another child.
+    num_misplaced: u64,
+    misplaced: Vec<String>,
+    /// Whether the children have the same number of items in total as the
+    /// shard, and as many delayed receipts.
+    counts_match: bool,
+    /// Whether the second build got the same children roots.
+    reproducible: bool,
+}
+
+impl Verification {
+    fn passed(&self) -> bool {
+        self.num_misplaced == 0 && self.counts_match && self.reproducible
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ReshardingSimulation {
+    height: BlockHeight,
+    shard_id: ShardId,
+    state_root: StateRoot,
+    children: Vec<ChildState>,
+    /// Duration of the first build of the children.
+    build_secs: f64,
+    /// Duration of the second build, for the reproducibility check.
+    rebuild_secs: f64,
+    /// Peak resident memory of the process, where the OS tells it.
+    peak_memory_bytes: Option<u64>,
+    verification: Verification,
+}
+
+impl fmt::Display for ReshardingSimulation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Split of shard {} before #{} (state root {}) into {} shards",
+            self.shard_id,
+            self.height,
+            self.state_root,
+            self.children.len()
+        )?;
+        for child in &self.children {
+            writeln!(
+                f,
+                "  shard {}: state root {}, {} accounts, {} items, {} delayed receipts, {} bytes, \
+                 memory usage {}",
+                child.shard_id,
+                child.state_root,
+                child.num_accounts,
+                child.num_items,
+                child.num_delayed_receipts,
+                child.bytes,
+                child.memory_usage
+            )?;
+        }
+        write!(f, "Built in {:.1}s, rebuilt in {:.1}s", self.build_secs, self.rebuild_secs)?;
+        if let Some(peak_memory_bytes) = self.peak_memory_bytes {
+            write!(f, ", peak memory {} bytes", peak_memory_bytes)?;
+        }
+        let verification = &self.verification;
+        write!(
+            f,
+            "\nVerified {} items and {} delayed receipts: {} misplaced, counts {}, roots {}",
+            verification.num_items,
+            verification.num_delayed_receipts,
+            verification.num_misplaced,
+            if verification.counts_match { "match" } else { "differ" },
+            if verification.reproducible { "reproducible" } else { "differ between the builds" }
+        )?;
+        for misplaced in &verification.misplaced {
+            write!(f, "\n  {}", misplaced)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the states of the children of the shard in `target_store`,
+/// returning their tries and roots and the time it took.
+fn build_children(
+    source: &ShardTries,
+    shard_uid: ShardUId,
+    state_root: &StateRoot,
+    new_layout: &ShardLayout,
+    target_store: Store,
+) -> anyhow::Result<(ShardTries, HashMap<ShardUId, StateRoot>, Duration)> {
+    let target = ShardTries::new(target_store, new_layout.version(), new_layout.num_shards());
+    let start = Instant::now();
+    let roots =
+        nearcore::build_state_for_split_shards(source, &target, shard_uid, state_root, new_layout)?;
+    Ok((target, roots, start.elapsed()))
+}
+
+fn child_state(
+    tries: &ShardTries,
+    shard_uid: ShardUId,
+    state_root: &StateRoot,
+) -> anyhow::Result<ChildState> {
+    let trie = tries.get_view_trie_for_shard(shard_uid);
+    let mut child = ChildState {
+        shard_id: shard_uid.shard_id(),
+        state_root: *state_root,
+        num_accounts: 0,
+        num_items: 0,
+        num_delayed_receipts: 0,
+        bytes: 0,
+        memory_usage: trie.retrieve_root_node(state_root)?.memory_usage,
+    };
+    for item in trie.iter(state_root)? {
+        let (key, value) = item?;
+        child.bytes += (key.len() + value.len()) as u64;
+        match key.first() {
+            Some(&column) if column == col::DELAYED_RECEIPT[0] => child.num_delayed_receipts += 1,
+            Some(&column) if column == col::DELAYED_RECEIPT_INDICES[0] => {}
+            Some(&column) => {
+                child.num_items += 1;
+                if column == col::ACCOUNT[0] {
+                    child.num_accounts += 1;
+                }
+            }
+            None => {}
+        }
+    }
+    Ok(child)
+}
+
+/// Checks the children built against the shard, see the module documentation.
+fn verify(
+    source: &ShardTries,
+    shard_uid: ShardUId,
+    state_root: &StateRoot,
+    new_layout: &ShardLayout,
+    target: &ShardTries,
+    roots: &HashMap<ShardUId, StateRoot>,
+    children: &[ChildState],
+) -> anyhow::Result<Verification> {
+    let mut verification = Verification::default();
+    let trie = source.get_view_trie_for_shard(shard_uid);
+    let child_tries: HashMap<ShardUId, _> = roots
+        .keys()
+        .map(|child_uid| (*child_uid, target.get_view_trie_for_shard(*child_uid)))
+        .collect();
+    for item in trie.iter(state_root)? {
+        let (key, value) = item?;
+        let account_id = match parse_account_id_from_raw_key(&key)? {
+            Some(account_id) => account_id,
+            None => {
+                if key.first() == Some(&col::DELAYED_RECEIPT[0]) {
+                    verification.num_delayed_receipts += 1;
+                }
+                continue;
+            }
+        };
+        verification.num_items += 1;
+        let expected_uid = account_id_to_shard_uid(&account_id, new_layout);
+        let mut found_in = vec![];
+        for (child_uid, child_trie) in &child_tries {
+            if let Some(child_value) = child_trie.get(&roots[child_uid], &key)? {
+                found_in.push((child_uid.shard_id(), child_value == value));
+            }
+        }
+        if found_in != [(expected_uid.shard_id(), true)] {
+            verification.num_misplaced += 1;
+            if verification.misplaced.len() < MAX_LISTED_MISPLACED {
+                verification.misplaced.push(format!(
+                    "key {} of {} expected in shard {}, found in {:?} (shard, same value)",
+                    to_base(&key),
+                    account_id,
+                    expected_uid.shard_id(),
+                    found_in
+                ));
+            }
+        }
+    }
+    let num_items: u64 = children.iter().map(|child| child.num_items).sum();
+    let num_delayed_receipts: u64 = children.iter().map(|child| child.num_delayed_receipts).sum();
+    verification.counts_match = num_items == verification.num_items
+        && num_delayed_receipts == verification.num_delayed_receipts;
+    Ok(verification)
+}
+
+/// Peak resident memory of the process, from `/proc` on Linux.
+fn peak_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 =
+        line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn simulate(
+    source: &ShardTries,
+    shard_uid: ShardUId,
+    state_root: &StateRoot,
+    new_layout: &ShardLayout,
+    stores: [Store; 2],
+) -> anyhow::Result<(Vec<ChildState>, Duration, Duration, Verification)> {
+    let [first_store, second_store] = stores;
+    let (target, roots, build_time) =
+        build_children(source, shard_uid, state_root, new_layout, first_store)?;
+    let (_, second_roots, rebuild_time) =
+        build_children(source, shard_uid, state_root, new_layout, second_store)?;
+    let mut child_uids: Vec<ShardUId> = roots.keys().copied().collect();
+    child_uids.sort_by_key(|child_uid| child_uid.shard_id());
+    let children = child_uids
+        .iter()
+        .map(|child_uid| child_state(&target, *child_uid, &roots[child_uid]))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut verification =
+        verify(source, shard_uid, state_root, new_layout, &target, &roots, &children)?;
+    verification.reproducible = roots == second_roots;
+    Ok((children, build_time, rebuild_time, verification))
+}
+
+/// Simulates the split of the shard `shard_id` into the shards of the layout
+/// in the `new_layout` JSON file, on the state before the block at `height`.
+pub(crate) fn simulate_resharding(
+    shard_id: ShardId,
+    new_layout: &Path,
+    height: BlockHeight,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let new_layout: ShardLayout = std::fs::read_to_string(new_layout)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| Ok(serde_json::from_str(&json)?))
+        .map_err(|err| {
+            failure(
+                FailureKind::InvalidArgument,
+                format!("can't read the shard layout from {}: {}", new_layout.display(), err),
+            )
+        })?;
+    if new_layout.get_split_shard_ids(shard_id).map_or(true, |children| children.is_empty()) {
+        return Err(failure(
+            FailureKind::InvalidArgument,
+            format!("the new shard layout doesn't split shard {}", shard_id),
+        ));
+    }
+
+    let (runtime, state_roots, header) =
+        load_trie_stop_at_height(store, home_dir, &near_config, LoadTrieMode::Height(height));
+    let state_root = *state_roots.get(shard_id as usize).ok_or_else(|| {
+        failure(FailureKind::InvalidArgument, format!("there's no shard {}", shard_id))
+    })?;
+    let layout = runtime.get_shard_layout(header.epoch_id())?;
+    let shard_uid = ShardUId::from_shard_id_and_layout(shard_id, &layout);
+
+    let dirs = [tempfile::tempdir()?, tempfile::tempdir()?];
+    let stores =
+        [near_store::create_store(dirs[0].path()), near_store::create_store(dirs[1].path())];
+    let (children, build_time, rebuild_time, verification) =
+        simulate(&runtime.get_tries(), shard_uid, &state_root, &new_layout, stores)
+            .context("Failed to simulate the split")?;
+    let passed = verification.passed();
+    output.print(&ReshardingSimulation {
+        height: header.height(),
+        shard_id,
+        state_root,
+        children,
+        build_secs: build_time.as_secs_f64(),
+        rebuild_secs: rebuild_time.as_secs_f64(),
+        peak_memory_bytes: peak_memory_bytes(),
+        verification,
+    })?;
+    if !passed {
+        return Err(failure(FailureKind::Other, "The split failed the verification"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::account::Account;
+    use near_primitives::hash::CryptoHash;
+    use near_primitives::receipt::{DelayedReceiptIndices, Receipt};
+    use near_primitives::trie_key::TrieKey;
+    use near_primitives::types::StateChangeCause;
+    use near_store::test_utils::create_test_store;
+
+    /// A shard with accounts on both sides of the boundaries of
+    /// `ShardLayout::v1_test` and delayed receipts for some of them.
+    fn parent_state() -> (ShardTries, ShardUId, StateRoot) {
+        let tries = ShardTries::new(create_test_store(), 0, 1);
+        let shard_uid = ShardUId::single_shard();
+        let mut update = tries.new_trie_update(shard_uid, StateRoot::default());
+        let account_ids = ["aaa", "abd", "bob", "foo", "zed", "test0"];
+        for (i, account_id) in account_ids.iter().enumerate() {
+            let account_id = account_id.parse().unwrap();
+            let account = Account::new(i as u128 + 1, 0, CryptoHash::default(), 100);
+            near_store::set_account(&mut update, account_id, &account);
+        }
+        let receivers = ["aaa", "zed", "foo"];
+        for (index, receiver_id) in receivers.iter().enumerate() {
+            let receipt = Receipt::new_balance_refund(&receiver_id.parse().unwrap(), 1);
+            near_store::set(&mut update, TrieKey::DelayedReceipt { index: index as u64 }, &receipt);
+        }
+        let indices =
+            DelayedReceiptIndices { first_index: 0, next_available_index: receivers.len() as u64 };
+        near_store::set(&mut update, TrieKey::DelayedReceiptIndices, &indices);
+        update.commit(StateChangeCause::InitialState);
+        let (trie_changes, _) = update.finalize().unwrap();
+        let (store_update, state_root) = tries.apply_all(&trie_changes, shard_uid);
+        store_update.commit().unwrap();
+        (tries, shard_uid, state_root)
+    }
+
+    #[test]
+    fn test_simulate() {
+        let (tries, shard_uid, state_root) = parent_state();
+        let layout = ShardLayout::v1_test();
+        let stores = [create_test_store(), create_test_store()];
+        let (children, _, _, verification) =
+            simulate(&tries, shard_uid, &state_root, &layout, stores).unwrap();
+        assert!(verification.passed(), "{:?}", verification);
+        assert_eq!((verification.num_items, verification.num_delayed_receipts), (6, 3));
+        // "test0" is a fixed shard, the others split by "abc" and "foo".
+        let accounts: Vec<_> =
+            children.iter().map(|child| (child.shard_id, child.num_accounts)).collect();
+        assert_eq!(accounts, [(0, 1), (1, 1), (2, 2), (3, 2)]);
+        let receipts: Vec<_> = children.iter().map(|child| child.num_delayed_receipts).collect();
+        assert_eq!(receipts, [0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn test_verify_finds_misplaced_items() {
+        let (tries, shard_uid, state_root) = parent_state();
+        let layout = ShardLayout::v1_test();
+        let (target, mut roots, _) =
+            build_children(&tries, shard_uid, &state_root, &layout, create_test_store()).unwrap();
+        // Drop a child: its items are missing from the others.
+        let dropped = ShardUId::from_shard_id_and_layout(3, &layout);
+        roots.insert(dropped, StateRoot::default());
+        let children = roots
+            .iter()
+            .map(|(child_uid, root)| child_state(&target, *child_uid, root))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        let verification =
+            verify(&tries, shard_uid, &state_root, &layout, &target, &roots, &children).unwrap();
+        assert_eq!(verification.num_misplaced, 2);
+        assert!(!verification.counts_match);
+    }
+}
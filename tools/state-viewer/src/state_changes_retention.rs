@@ -0,0 +1,76 @@
+//! Boundary of the state changes kept by garbage collection, which can be
+//! kept for `gc_state_changes_extra_epochs` epochs on top of the rest of the
+//! data of the blocks.  The changes RPC methods serve the blocks at or above
+//! the boundary only.
+
+use std::fmt;
+use std::path::Path;
+
+use near_chain::types::RuntimeAdapter;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::types::BlockHeight;
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use serde::Serialize;
+
+use crate::output::Output;
+
+#[derive(Serialize)]
+struct StateChangesRetention {
+    head_height: BlockHeight,
+    tail_height: BlockHeight,
+    gc_stop_height: BlockHeight,
+    extra_epochs: u64,
+    /// Lowest height whose state changes are kept.
+    retained_from: BlockHeight,
+    /// Height the boundary moves up to once garbage collection catches up.
+    stop_height: BlockHeight,
+}
+
+impl fmt::Display for StateChangesRetention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Head #{}, tail #{}, garbage collection stop height #{}",
+            self.head_height, self.tail_height, self.gc_stop_height
+        )?;
+        write!(
+            f,
+            "State changes kept from #{}, for {} epochs more than the rest, down to #{} once \
+             garbage collection catches up",
+            self.retained_from, self.extra_epochs, self.stop_height
+        )
+    }
+}
+
+pub(crate) fn state_changes_retention(
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store.clone(),
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let chain_store = ChainStore::new(store, genesis_height, true);
+    let head = chain_store.head()?;
+    let gc_config = &near_config.client_config.gc;
+    let gc_stop_height = runtime.get_gc_stop_height(&head.last_block_hash);
+    output.print(&StateChangesRetention {
+        head_height: head.height,
+        tail_height: chain_store.tail()?,
+        gc_stop_height,
+        extra_epochs: gc_config.gc_state_changes_extra_epochs,
+        retained_from: chain_store.state_changes_retained_from()?,
+        stop_height: gc_config.state_changes_stop_height(
+            gc_stop_height,
+            genesis_height,
+            near_config.genesis.config.epoch_length,
+        ),
+    })
+}
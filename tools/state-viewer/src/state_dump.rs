@@ -3,8 +3,9 @@ use near_chain::RuntimeAdapter;
 use near_chain_configs::Genesis;
 use near_crypto::PublicKey;
 use near_primitives::account::id::AccountId;
+use near_primitives::account_pattern::AccountPattern;
 use near_primitives::block::BlockHeader;
-use near_primitives::state_record::StateRecord;
+use near_primitives::state_record::{state_record_to_account_id, StateRecord};
 use near_primitives::time::Utc;
 use near_primitives::types::{AccountInfo, Balance, StateRoot};
 use near_store::TrieIterator;
@@ -21,12 +22,15 @@ use std::path::Path;
 /// Returns a `NearConfig` with genesis records taken from the current state.
 /// If `records_path` argument is provided, then records will be streamed into a separate file,
 /// otherwise the returned `NearConfig` will contain all the records within itself.
+/// If `accounts` isn't empty, only the records of the accounts matching one of the patterns
+/// and of the validators are dumped.
 pub fn state_dump(
     runtime: NightshadeRuntime,
     state_roots: &[StateRoot],
     last_block_header: BlockHeader,
     near_config: &NearConfig,
     records_path: Option<&Path>,
+    accounts: &[AccountPattern],
 ) -> NearConfig {
     println!(
         "Generating genesis from state data of #{} / {}",
@@ -34,20 +38,7 @@ pub fn state_dump(
         last_block_header.hash()
     );
     let genesis_height = last_block_header.height() + 1;
-    let block_producers = runtime
-        .get_epoch_block_producers_ordered(last_block_header.epoch_id(), last_block_header.hash())
-        .unwrap();
-    let validators = block_producers
-        .into_iter()
-        .filter_map(|(info, is_slashed)| {
-            if !is_slashed {
-                let (account_id, public_key, stake) = info.destructure();
-                Some((account_id, (public_key, stake)))
-            } else {
-                None
-            }
-        })
-        .collect::<HashMap<_, _>>();
+    let validators = dump_validators(&runtime, &last_block_header);
 
     let mut near_config = near_config.clone();
 
@@ -63,10 +54,12 @@ pub fn state_dump(
         })
         .collect();
     genesis_config.validators.sort_by_key(|account_info| account_info.account_id.clone());
-    // Record the protocol version of the latest block. Otherwise, the state
-    // dump ignores the fact that the nodes can be running a newer protocol
-    // version than the protocol version of the genesis.
-    genesis_config.protocol_version = last_block_header.latest_protocol_version();
+    // Record the protocol version of the epoch of the block, so that the chain
+    // started from the dump follows the rules the block was produced under
+    // rather than those of the genesis.  The version the block producer voted
+    // for only takes effect in a later epoch, if at all.
+    genesis_config.protocol_version =
+        runtime.get_epoch_protocol_version(last_block_header.epoch_id()).unwrap();
     let shard_config = runtime.get_shard_config(last_block_header.epoch_id()).unwrap();
     genesis_config.shard_layout = shard_config.shard_layout;
     genesis_config.num_block_producer_seats_per_shard =
@@ -86,11 +79,12 @@ pub fn state_dump(
             let mut ser = serde_json::Serializer::new(records_file);
             let mut seq = ser.serialize_seq(None).unwrap();
             let total_supply = iterate_over_records(
-                runtime,
+                &runtime,
                 state_roots,
-                last_block_header,
+                &last_block_header,
                 &validators,
-                &mut |sr| seq.serialize_element(&sr).unwrap(),
+                accounts,
+                |sr| seq.serialize_element(&sr).unwrap(),
             );
             seq.end().unwrap();
             // `total_supply` is expected to change due to the natural processes of burning tokens and
@@ -104,11 +98,12 @@ pub fn state_dump(
         None => {
             let mut records: Vec<StateRecord> = vec![];
             let total_supply = iterate_over_records(
-                runtime,
+                &runtime,
                 state_roots,
-                last_block_header,
+                &last_block_header,
                 &validators,
-                &mut |sr| records.push(sr),
+                accounts,
+                |sr| records.push(sr),
             );
             // `total_supply` is expected to change due to the natural processes of burning tokens and
             // minting tokens every epoch.
@@ -189,12 +184,37 @@ pub fn state_dump_redis(
     Ok(())
 }
 
-/// Iterates over the state, calling `callback` for every record that genesis needs to contain.
-fn iterate_over_records(
-    runtime: NightshadeRuntime,
+/// The block producers of the epoch of the block which aren't slashed, the
+/// validators of the dumped genesis.
+pub(crate) fn dump_validators(
+    runtime: &NightshadeRuntime,
+    last_block_header: &BlockHeader,
+) -> HashMap<AccountId, (PublicKey, Balance)> {
+    let block_producers = runtime
+        .get_epoch_block_producers_ordered(last_block_header.epoch_id(), last_block_header.hash())
+        .unwrap();
+    block_producers
+        .into_iter()
+        .filter_map(|(info, is_slashed)| {
+            if !is_slashed {
+                let (account_id, public_key, stake) = info.destructure();
+                Some((account_id, (public_key, stake)))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Iterates over the state, calling `callback` for every record that genesis needs to contain,
+/// and returns the total supply of the accounts of the records.  If `accounts` isn't empty, only
+/// the records of the accounts matching one of the patterns and of the validators are included.
+pub(crate) fn iterate_over_records(
+    runtime: &NightshadeRuntime,
     state_roots: &[StateRoot],
-    last_block_header: BlockHeader,
+    last_block_header: &BlockHeader,
     validators: &HashMap<AccountId, (PublicKey, Balance)>,
+    accounts: &[AccountPattern],
     mut callback: impl FnMut(StateRecord),
 ) -> Balance {
     let mut total_supply = 0;
@@ -205,6 +225,13 @@ fn iterate_over_records(
         for item in trie {
             let (key, value) = item.unwrap();
             if let Some(mut sr) = StateRecord::from_raw_key_value(key, value) {
+                let account_id = state_record_to_account_id(&sr);
+                if !accounts.is_empty()
+                    && !validators.contains_key(account_id)
+                    && !AccountPattern::any_matches(accounts, account_id)
+                {
+                    continue;
+                }
                 if let StateRecord::Account { account_id, account } = &mut sr {
                     total_supply += account.amount() + account.locked();
                     if account.locked() > 0 {
@@ -232,6 +259,7 @@ mod test {
     use near_client::test_utils::TestEnv;
     use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, SecretKey};
     use near_primitives::shard_layout::ShardLayout;
+    use near_primitives::state_record::state_record_to_account_id;
     use near_primitives::transaction::SignedTransaction;
     use near_primitives::types::{BlockHeight, BlockHeightDelta, NumBlocks, ProtocolVersion};
     use near_primitives::version::ProtocolFeature::SimpleNightshade;
@@ -348,6 +376,7 @@ mod test {
             last_block.header().clone(),
             &near_config,
             Some(&records_file.path().to_path_buf()),
+            &[],
         );
         let new_genesis = new_near_config.genesis;
         assert_eq!(new_genesis.config.validators.len(), 2);
@@ -389,12 +418,44 @@ mod test {
             last_block.chunks().iter().map(|chunk| chunk.prev_state_root()).collect();
         let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
         let new_near_config =
-            state_dump(runtime, &state_roots, last_block.header().clone(), &near_config, None);
+            state_dump(runtime, &state_roots, last_block.header().clone(), &near_config, None, &[]);
         let new_genesis = new_near_config.genesis;
         assert_eq!(new_genesis.config.validators.len(), 2);
         validate_genesis(&new_genesis);
     }
 
+    /// Test that only the accounts matching the patterns and the validators are dumped.
+    #[test]
+    fn test_dump_state_accounts() {
+        let epoch_length = 4;
+        let (store, genesis, mut env, near_config) = setup(epoch_length, PROTOCOL_VERSION, None);
+        safe_produce_blocks(&mut env, 1, 2);
+
+        let head = env.clients[0].chain.head().unwrap();
+        let last_block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap().clone();
+        let state_roots: Vec<CryptoHash> =
+            last_block.chunks().iter().map(|chunk| chunk.prev_state_root()).collect();
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let new_near_config = state_dump(
+            runtime,
+            &state_roots,
+            last_block.header().clone(),
+            &near_config,
+            None,
+            &["*.near".parse().unwrap(), "test1".parse().unwrap()],
+        );
+        let new_genesis = new_near_config.genesis;
+        let account_ids: HashSet<&str> = new_genesis
+            .records
+            .0
+            .iter()
+            .map(|record| state_record_to_account_id(record).as_str())
+            .collect();
+        // The validator is kept, the treasury isn't under `near`.
+        assert_eq!(account_ids, HashSet::from(["test0", "test1"]));
+        validate_genesis(&new_genesis);
+    }
+
     /// Test that we return locked tokens for accounts that are not validators.
     #[test]
     fn test_dump_state_return_locked() {
@@ -428,6 +489,7 @@ mod test {
             last_block.header().clone(),
             &near_config,
             Some(&records_file.path().to_path_buf()),
+            &[],
         );
         let new_genesis = new_near_config.genesis;
         assert_eq!(
@@ -471,6 +533,7 @@ mod test {
             last_block.header().clone(),
             &near_config,
             Some(&records_file.path().to_path_buf()),
+            &[],
         );
         let new_genesis = new_near_config.genesis;
 
@@ -552,6 +615,7 @@ mod test {
             last_block.header().clone(),
             &near_config,
             Some(&records_file.path().to_path_buf()),
+            &[],
         );
     }
 
@@ -620,6 +684,7 @@ mod test {
             last_block.header().clone(),
             &near_config,
             Some(&records_file.path().to_path_buf()),
+            &[],
         );
         let new_genesis = new_near_config.genesis;
 
@@ -664,4 +729,73 @@ mod test {
         let res = state_dump_redis(runtime, &state_roots, last_block.header().clone());
         assert_eq!(res, Ok(()));
     }
+
+    /// Test that a dump matches the state it was made from, and that changes
+    /// to the dumped records are found.
+    #[test]
+    fn test_verify_dump() {
+        use crate::verify_dump::compare_dump;
+        use near_chain_configs::stream_records_from_file;
+        use near_primitives::state_record::StateRecord;
+
+        let epoch_length = 4;
+        let (store, genesis, mut env, near_config) = setup(epoch_length, PROTOCOL_VERSION, None);
+        safe_produce_blocks(&mut env, 1, epoch_length + 1);
+        let head = env.clients[0].chain.head().unwrap();
+        let last_block = env.clients[0].chain.get_block(&head.last_block_hash).unwrap().clone();
+        let state_roots: Vec<CryptoHash> =
+            last_block.chunks().iter().map(|chunk| chunk.prev_state_root()).collect();
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let records_file = tempfile::NamedTempFile::new().unwrap();
+        let new_near_config = state_dump(
+            runtime,
+            &state_roots,
+            last_block.header().clone(),
+            &near_config,
+            Some(&records_file.path().to_path_buf()),
+            &[],
+        );
+        let genesis_config = new_near_config.genesis.config;
+
+        let runtime = NightshadeRuntime::test(Path::new("."), store.clone(), &genesis);
+        let comparison = compare_dump(
+            &runtime,
+            &state_roots,
+            last_block.header(),
+            &genesis_config,
+            records_file.path(),
+        )
+        .unwrap();
+        assert!(comparison.matches(), "{:?}", comparison);
+
+        // Drop an access key and change the balance of an account.
+        let mut records = vec![];
+        let file = std::fs::File::open(records_file.path()).unwrap();
+        stream_records_from_file(file, |record| records.push(record)).unwrap();
+        let access_key =
+            records.iter().position(|record| matches!(record, StateRecord::AccessKey { .. }));
+        records.remove(access_key.unwrap());
+        for record in records.iter_mut() {
+            if let StateRecord::Account { account, .. } = record {
+                account.set_amount(account.amount() + 1);
+                break;
+            }
+        }
+        let tampered_file = tempfile::NamedTempFile::new().unwrap();
+        serde_json::to_writer(tampered_file.as_file(), &records).unwrap();
+        let comparison = compare_dump(
+            &runtime,
+            &state_roots,
+            last_block.header(),
+            &genesis_config,
+            tampered_file.path(),
+        )
+        .unwrap();
+        assert!(!comparison.matches());
+        assert_eq!(comparison.missing.len(), 1);
+        assert!(comparison.missing[0].starts_with("AccessKey "));
+        assert!(comparison.extra.is_empty());
+        assert_eq!(comparison.differing.len(), 1);
+        assert_eq!(comparison.dumped_records_total_supply, genesis_config.total_supply + 1);
+    }
 }
@@ -0,0 +1,42 @@
+//! Fingerprint of the state of the node after a block, for comparing the state
+//! of nodes without dumping it: two nodes which agree on the fingerprint of a
+//! block have the same state and outcome roots for every shard they both track.
+
+use crate::output::Output;
+use anyhow::Context;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::types::BlockHeight;
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use std::path::Path;
+
+/// Prints the fingerprint of the canonical block at `height`, the head by
+/// default, as JSON.
+pub(crate) fn state_fingerprint(
+    height: Option<BlockHeight>,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    let mut chain_store = ChainStore::new(
+        store.clone(),
+        near_config.genesis.config.genesis_height,
+        !near_config.client_config.archive,
+    );
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let block_hash = match height {
+        Some(height) => chain_store
+            .get_block_hash_by_height(height)
+            .with_context(|| format!("No canonical block at #{}", height))?,
+        None => chain_store.head()?.last_block_hash,
+    };
+    let fingerprint = chain_store.get_state_fingerprint(&runtime, &block_hash)?;
+    output.print_json(&fingerprint)
+}
@@ -0,0 +1,359 @@
+//! Synthetic chain in an on-disk store, for the tests of the subcommands.
+//!
+//! [`TestChainBuilder`] produces a few epochs of blocks with a single validator
+//! which produces all the blocks and chunks, the way the tests of `near-client`
+//! do.  At every height each account sends a transfer to the next one, which
+//! makes receipts between the shards.  One account gets a contract and another
+//! a function call access key to it.  The chunks of one height are missed and
+//! one block ends up on a fork; [`TestChain`] tells where.
+
+use near_chain::{ChainGenesis, ChainStore, ChainStoreAccess, Provenance, RuntimeAdapter};
+use near_chain_configs::Genesis;
+use near_client::test_utils::TestEnv;
+use near_crypto::{InMemorySigner, KeyFile, KeyType, PublicKey, SecretKey, Signer};
+use near_network::types::NetworkClientResponses;
+use near_primitives::account::{AccessKey, AccessKeyPermission, FunctionCallPermission};
+use near_primitives::hash::CryptoHash;
+use near_primitives::runtime::config_store::RuntimeConfigStore;
+use near_primitives::transaction::{Action, AddKeyAction, DeployContractAction, SignedTransaction};
+use near_primitives::types::{AccountId, BlockHeight, BlockHeightDelta, NumShards};
+use near_primitives::utils::get_num_seats_per_shard;
+use near_primitives::validator_signer::InMemoryValidatorSigner;
+use near_primitives::views::{QueryRequest, QueryResponseKind};
+use near_store::{create_store, Store};
+use nearcore::config::{Config, GenesisExt};
+use nearcore::{NearConfig, NightshadeRuntime, TrackedConfig};
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+pub(crate) struct TestChainBuilder {
+    num_shards: NumShards,
+    epoch_length: BlockHeightDelta,
+    num_epochs: u64,
+    missed_chunks_height: Option<BlockHeight>,
+    fork_height: Option<BlockHeight>,
+}
+
+impl Default for TestChainBuilder {
+    fn default() -> Self {
+        Self {
+            num_shards: 4,
+            epoch_length: 5,
+            num_epochs: 3,
+            missed_chunks_height: Some(3),
+            fork_height: Some(8),
+        }
+    }
+}
+
+pub(crate) struct TestChain {
+    /// Home directory with the store, deleted with the chain.
+    home_dir: TempDir,
+    pub store: Store,
+    pub genesis: Genesis,
+    pub near_config: NearConfig,
+    pub runtime: Arc<NightshadeRuntime>,
+    /// The environment which produced the chain, to produce more blocks.
+    pub env: TestEnv,
+    /// `test0`, the validator, to `test3` or the number of shards.
+    pub accounts: Vec<AccountId>,
+    pub contract_account: AccountId,
+    /// Account with a function call access key to the contract.
+    pub access_key_account: AccountId,
+    pub function_call_key: PublicKey,
+    /// Height of the canonical block without new chunks.
+    pub missed_chunks_height: Option<BlockHeight>,
+    /// Height of the block on the fork, which the canonical chain skips.
+    pub fork_height: Option<BlockHeight>,
+    pub fork_block_hash: Option<CryptoHash>,
+    pub head_height: BlockHeight,
+}
+
+impl TestChainBuilder {
+    pub fn num_shards(mut self, num_shards: NumShards) -> Self {
+        self.num_shards = num_shards;
+        self
+    }
+
+    pub fn epoch_length(mut self, epoch_length: BlockHeightDelta) -> Self {
+        self.epoch_length = epoch_length;
+        self
+    }
+
+    pub fn num_epochs(mut self, num_epochs: u64) -> Self {
+        self.num_epochs = num_epochs;
+        self
+    }
+
+    /// Height whose block has none of the new chunks, `None` for none.
+    pub fn missed_chunks_height(mut self, height: Option<BlockHeight>) -> Self {
+        self.missed_chunks_height = height;
+        self
+    }
+
+    /// Height of the block left on a fork by a block at the next height,
+    /// `None` for no fork.
+    pub fn fork_height(mut self, height: Option<BlockHeight>) -> Self {
+        self.fork_height = height;
+        self
+    }
+
+    pub fn build(self) -> TestChain {
+        let accounts: Vec<AccountId> =
+            (0..self.num_shards.max(4)).map(|i| format!("test{}", i).parse().unwrap()).collect();
+        let mut genesis =
+            Genesis::test_sharded(accounts.clone(), 1, get_num_seats_per_shard(self.num_shards, 1));
+        genesis.config.epoch_length = self.epoch_length;
+        // The only validator misses chunks on purpose, it mustn't be kicked
+        // out for that.
+        genesis.config.block_producer_kickout_threshold = 0;
+        genesis.config.chunk_producer_kickout_threshold = 0;
+
+        let home_dir = tempfile::tempdir().unwrap();
+        let store = create_store(home_dir.path());
+        let runtime = Arc::new(NightshadeRuntime::test_with_runtime_config_store(
+            home_dir.path(),
+            store.clone(),
+            &genesis,
+            TrackedConfig::AllShards,
+            RuntimeConfigStore::test(),
+            None,
+        ));
+        let mut env = TestEnv::builder(ChainGenesis::from(&genesis))
+            .runtime_adapters(vec![runtime.clone()])
+            .build();
+        let signers: Vec<_> = accounts
+            .iter()
+            .map(|account_id| {
+                InMemorySigner::from_seed(account_id.clone(), KeyType::ED25519, account_id.as_ref())
+            })
+            .collect();
+
+        // At nonce 1, the transfers start at height 2 with the height as the
+        // nonce.
+        let contract_account = accounts[1].clone();
+        let access_key_account = accounts[2].clone();
+        let function_call_key = InMemorySigner::from_seed(
+            access_key_account.clone(),
+            KeyType::ED25519,
+            "function call key",
+        )
+        .public_key();
+        let head_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+        send_tx(
+            &mut env,
+            SignedTransaction::from_actions(
+                1,
+                contract_account.clone(),
+                contract_account.clone(),
+                &signers[1],
+                vec![Action::DeployContract(DeployContractAction {
+                    code: near_test_contracts::trivial_contract().to_vec(),
+                })],
+                head_hash,
+            ),
+        );
+        send_tx(
+            &mut env,
+            SignedTransaction::from_actions(
+                1,
+                access_key_account.clone(),
+                access_key_account.clone(),
+                &signers[2],
+                vec![Action::AddKey(AddKeyAction {
+                    public_key: function_call_key.clone(),
+                    access_key: AccessKey {
+                        nonce: 0,
+                        permission: AccessKeyPermission::FunctionCall(FunctionCallPermission {
+                            allowance: None,
+                            receiver_id: contract_account.to_string(),
+                            method_names: vec![],
+                        }),
+                    },
+                })],
+                head_hash,
+            ),
+        );
+
+        let mut fork_block_hash = None;
+        let mut height = 1;
+        while height <= self.num_epochs * self.epoch_length {
+            if height >= 2 {
+                let head_hash = env.clients[0].chain.head().unwrap().last_block_hash;
+                for (i, signer) in signers.iter().enumerate() {
+                    let tx = SignedTransaction::send_money(
+                        height,
+                        accounts[i].clone(),
+                        accounts[(i + 1) % accounts.len()].clone(),
+                        signer,
+                        100,
+                        head_hash,
+                    );
+                    send_tx(&mut env, tx);
+                }
+            }
+            let block = env.clients[0].produce_block(height).unwrap().unwrap();
+            if self.fork_height == Some(height) {
+                // Built on the same block, the one at the next height wins.
+                let canonical_block = env.clients[0].produce_block(height + 1).unwrap().unwrap();
+                fork_block_hash = Some(*block.hash());
+                env.process_block(0, block, Provenance::PRODUCED);
+                env.process_block(0, canonical_block, Provenance::PRODUCED);
+                height += 2;
+                continue;
+            }
+            let produce_chunks = self.missed_chunks_height != Some(height + 1);
+            env.process_block_with_options(0, block, Provenance::PRODUCED, true, produce_chunks);
+            height += 1;
+        }
+        let head_height = env.clients[0].chain.head().unwrap().height;
+
+        let near_config = NearConfig::new(
+            Config::default(),
+            genesis.clone(),
+            KeyFile {
+                account_id: "node".parse().unwrap(),
+                public_key: PublicKey::empty(KeyType::ED25519),
+                secret_key: SecretKey::from_random(KeyType::ED25519),
+            },
+            Some(Arc::new(InMemoryValidatorSigner::from_seed(
+                accounts[0].clone(),
+                KeyType::ED25519,
+                accounts[0].as_ref(),
+            ))),
+        );
+        TestChain {
+            home_dir,
+            store,
+            genesis,
+            near_config,
+            runtime,
+            env,
+            accounts,
+            contract_account,
+            access_key_account,
+            function_call_key,
+            missed_chunks_height: self.missed_chunks_height,
+            fork_height: self.fork_height,
+            fork_block_hash,
+            head_height,
+        }
+    }
+}
+
+fn send_tx(env: &mut TestEnv, tx: SignedTransaction) {
+    let response = env.clients[0].process_tx(tx, false, false);
+    assert_eq!(response, NetworkClientResponses::ValidTx);
+}
+
+/// Whether the canonical block at the height has new chunks of all the shards,
+/// produced for it.
+fn has_all_new_chunks(chain_store: &mut ChainStore, height: BlockHeight) -> bool {
+    let block_hash = match chain_store.get_block_hash_by_height(height) {
+        Ok(block_hash) => block_hash,
+        Err(_) => return false,
+    };
+    let block = chain_store.get_block(&block_hash).unwrap();
+    block.chunks().iter().all(|chunk_header| {
+        chunk_header.height_created() == height && chunk_header.height_included() == height
+    })
+}
+
+impl TestChain {
+    pub fn home_dir(&self) -> &Path {
+        self.home_dir.path()
+    }
+
+    pub fn chain_store(&self) -> ChainStore {
+        ChainStore::new(self.store.clone(), self.genesis.config.genesis_height, false)
+    }
+
+    /// Canonical heights whose block and the block at the height before have
+    /// new chunks of all the shards.  The chunks of these blocks apply on top
+    /// of the previous chunks alone, without the receipts of missed heights.
+    pub fn steady_heights(&self) -> Vec<BlockHeight> {
+        let mut chain_store = self.chain_store();
+        (2..=self.head_height)
+            .filter(|&height| {
+                has_all_new_chunks(&mut chain_store, height - 1)
+                    && has_all_new_chunks(&mut chain_store, height)
+            })
+            .collect()
+    }
+
+    /// Answers the query at the head, on the shard of the account.
+    pub fn query(&self, account_id: &AccountId, request: QueryRequest) -> QueryResponseKind {
+        let mut chain_store = self.chain_store();
+        let head = chain_store.head().unwrap();
+        let header = chain_store.get_block_header(&head.last_block_hash).unwrap().clone();
+        let shard_id = self.runtime.account_id_to_shard_id(account_id, &head.epoch_id).unwrap();
+        let shard_uid = self.runtime.shard_id_to_uid(shard_id, &head.epoch_id).unwrap();
+        let chunk_extra = chain_store.get_chunk_extra(header.hash(), &shard_uid).unwrap();
+        self.runtime
+            .query(
+                shard_uid,
+                chunk_extra.state_root(),
+                header.height(),
+                header.raw_timestamp(),
+                header.prev_hash(),
+                header.hash(),
+                header.epoch_id(),
+                &request,
+            )
+            .unwrap()
+            .kind
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::views::AccessKeyPermissionView;
+
+    #[test]
+    fn test_test_chain() {
+        let chain = TestChainBuilder::default().build();
+        assert_eq!(chain.head_height, 15);
+        assert_eq!(chain.env.clients[0].chain.head().unwrap().height, chain.head_height);
+        let mut chain_store = chain.chain_store();
+
+        let missed_height = chain.missed_chunks_height.unwrap();
+        let block_hash = chain_store.get_block_hash_by_height(missed_height).unwrap();
+        let block = chain_store.get_block(&block_hash).unwrap();
+        assert_eq!(block.chunks().len(), 4);
+        assert!(block.chunks().iter().all(|chunk| chunk.height_included() < missed_height));
+
+        let fork_height = chain.fork_height.unwrap();
+        assert!(chain_store.get_block_hash_by_height(fork_height).is_err());
+        let fork_block = chain_store.get_block(&chain.fork_block_hash.unwrap()).unwrap();
+        assert_eq!(fork_block.header().height(), fork_height);
+
+        let heights = chain.steady_heights();
+        assert!(!heights.contains(&missed_height));
+        assert!(!heights.contains(&(missed_height + 1)));
+        assert!(!heights.contains(&(fork_height + 1)));
+        assert!(heights.contains(&chain.head_height));
+
+        let request = QueryRequest::ViewAccount { account_id: chain.contract_account.clone() };
+        match chain.query(&chain.contract_account, request) {
+            QueryResponseKind::ViewAccount(account) => {
+                assert_ne!(account.code_hash, CryptoHash::default())
+            }
+            kind => panic!("unexpected response {:?}", kind),
+        }
+        let request = QueryRequest::ViewAccessKey {
+            account_id: chain.access_key_account.clone(),
+            public_key: chain.function_call_key.clone(),
+        };
+        match chain.query(&chain.access_key_account, request) {
+            QueryResponseKind::AccessKey(access_key) => match access_key.permission {
+                AccessKeyPermissionView::FunctionCall { receiver_id, .. } => {
+                    assert_eq!(receiver_id, chain.contract_account.as_ref())
+                }
+                permission => panic!("unexpected permission {:?}", permission),
+            },
+            kind => panic!("unexpected response {:?}", kind),
+        }
+    }
+}
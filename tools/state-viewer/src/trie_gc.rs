@@ -0,0 +1,276 @@
+//! Offline mark and sweep of the State column, removing the trie nodes and
+//! values which garbage collection missed.
+//!
+//! Every trie node reachable from a state root in the chunk extra of a block
+//! which garbage collection keeps, at or above the tail, is marked; the chunk
+//! extras being only there for the shards the node tracked, so is the marked
+//! state.  Every other node with a positive reference count is unreachable and
+//! has its reference count brought down to zero by a single update, which is
+//! what garbage collection would have eventually done.  The rows whose
+//! reference count is negative are left alone and reported.
+//!
+//! The state of a shard being synced has no chunk extra yet, so the command
+//! refuses to run while a state sync or a catchup is in progress.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use borsh::BorshDeserialize;
+use near_chain::types::RuntimeAdapter;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::hash::CryptoHash;
+use near_primitives::shard_layout::{get_block_shard_uid_rev, ShardUId};
+use near_primitives::types::chunk_extra::ChunkExtra;
+use near_primitives::types::BlockHeight;
+use near_store::{decode_value_with_rc, DBCol, ShardTries, Store};
+use nearcore::{NearConfig, NightshadeRuntime};
+use serde::Serialize;
+
+use crate::output::{failure, FailureKind, Output};
+
+/// Number of deleted trie nodes after which the deletions are committed.
+const STATE_BATCH_SIZE: usize = 100_000;
+
+/// Hashes of the trie nodes and values reachable from the kept state roots.
+pub(crate) struct MarkedState {
+    nodes: HashMap<ShardUId, HashSet<CryptoHash>>,
+    pub roots: usize,
+}
+
+impl MarkedState {
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.values().map(HashSet::len).sum()
+    }
+
+    fn contains(&self, shard_uid: &ShardUId, hash: &CryptoHash) -> bool {
+        self.nodes.get(shard_uid).map_or(false, |nodes| nodes.contains(hash))
+    }
+}
+
+/// Rows of the State column of a shard, by whether they're reachable.
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub(crate) struct ShardSweep {
+    pub shard_id: u32,
+    pub shard_version: u32,
+    pub reachable: u64,
+    pub unreachable: u64,
+    /// Bytes of the keys and values of the unreachable rows.
+    pub unreachable_bytes: u64,
+    /// Rows with a negative reference count, which aren't touched.
+    pub negative_refcount: u64,
+}
+
+/// Marks the trie nodes and values reachable from the state roots of the
+/// blocks at or above `keep_from`.
+pub(crate) fn mark_state(
+    chain_store: &mut ChainStore,
+    tries: &ShardTries,
+    store: &Store,
+    keep_from: BlockHeight,
+) -> anyhow::Result<MarkedState> {
+    let mut nodes: HashMap<ShardUId, HashSet<CryptoHash>> = HashMap::new();
+    let mut roots = 0;
+    for (key, value) in store.iter(DBCol::ChunkExtra) {
+        let (block_hash, shard_uid) = get_block_shard_uid_rev(&key)
+            .map_err(|err| anyhow::anyhow!("invalid chunk extra key: {}", err))?;
+        // Blocks without a header are kept to be on the safe side.
+        if let Ok(header) = chain_store.get_block_header(&block_hash) {
+            if header.height() < keep_from {
+                continue;
+            }
+        }
+        let chunk_extra = ChunkExtra::try_from_slice(&value)?;
+        let trie = tries.get_trie_for_shard(shard_uid);
+        trie.mark_reachable(chunk_extra.state_root(), nodes.entry(shard_uid).or_default())?;
+        roots += 1;
+    }
+    Ok(MarkedState { nodes, roots })
+}
+
+/// Brings the reference counts of the trie nodes which aren't marked down to
+/// zero, or only counts them if `dry_run`.
+pub(crate) fn sweep_state(
+    store: &Store,
+    marked: &MarkedState,
+    dry_run: bool,
+) -> anyhow::Result<BTreeMap<ShardUId, ShardSweep>> {
+    let mut shards: BTreeMap<ShardUId, ShardSweep> = BTreeMap::new();
+    let mut store_update = store.store_update();
+    let mut batch = 0;
+    for (key, raw_value) in store.iter_raw_bytes(DBCol::State) {
+        if key.len() != 40 {
+            continue;
+        }
+        let shard_uid = ShardUId::try_from(&key[..8])
+            .map_err(|err| anyhow::anyhow!("invalid state key: {}", err))?;
+        let hash = CryptoHash::try_from(&key[8..])
+            .map_err(|err| anyhow::anyhow!("invalid state key: {}", err))?;
+        let shard = shards.entry(shard_uid).or_insert_with(|| ShardSweep {
+            shard_id: shard_uid.shard_id,
+            shard_version: shard_uid.version,
+            ..ShardSweep::default()
+        });
+        let (value, rc) = decode_value_with_rc(&raw_value);
+        let value = match value {
+            Some(value) if rc > 0 => value,
+            // Removed, waiting for a compaction.
+            _ if rc == 0 => continue,
+            _ => {
+                shard.negative_refcount += 1;
+                continue;
+            }
+        };
+        if marked.contains(&shard_uid, &hash) {
+            shard.reachable += 1;
+            continue;
+        }
+        shard.unreachable += 1;
+        shard.unreachable_bytes += (key.len() + value.len()) as u64;
+        if !dry_run {
+            store_update.update_refcount(DBCol::State, &key, value, -rc);
+            batch += 1;
+            if batch == STATE_BATCH_SIZE {
+                std::mem::replace(&mut store_update, store.store_update()).commit()?;
+                batch = 0;
+            }
+        }
+    }
+    store_update.commit()?;
+    Ok(shards)
+}
+
+#[derive(Serialize)]
+struct TrieGcReport {
+    head_height: BlockHeight,
+    tail_height: BlockHeight,
+    state_roots: usize,
+    marked: usize,
+    executed: bool,
+    shards: Vec<ShardSweep>,
+}
+
+impl fmt::Display for TrieGcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Head #{}, tail #{}: {} trie nodes and values reachable from {} state roots",
+            self.head_height, self.tail_height, self.marked, self.state_roots
+        )?;
+        let action = if self.executed { "removed" } else { "unreachable" };
+        for shard in &self.shards {
+            write!(
+                f,
+                "\n  shard {} (version {}): {} reachable, {} {} ({:.1} MiB), \
+                 {} with a negative reference count",
+                shard.shard_id,
+                shard.shard_version,
+                shard.reachable,
+                shard.unreachable,
+                action,
+                shard.unreachable_bytes as f64 / 1048576.0,
+                shard.negative_refcount
+            )?;
+        }
+        let total: u64 = self.shards.iter().map(|shard| shard.unreachable).sum();
+        let bytes: u64 = self.shards.iter().map(|shard| shard.unreachable_bytes).sum();
+        write!(f, "\n  total: {} {} ({:.1} MiB)", total, action, bytes as f64 / 1048576.0)
+    }
+}
+
+/// Sweeps the trie nodes unreachable from the state roots garbage collection
+/// keeps, only counting them unless `execute`.
+pub(crate) fn trie_gc(
+    execute: bool,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if store.iter(DBCol::StateDlInfos).next().is_some() {
+        return Err(failure(
+            FailureKind::Other,
+            "A state sync or catchup is in progress and the state it downloaded has no \
+             state root yet; run the command once the node synced.",
+        ));
+    }
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store.clone(),
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let mut chain_store =
+        ChainStore::new(store.clone(), near_config.genesis.config.genesis_height, true);
+    let head = chain_store.head()?;
+    let tail = chain_store.tail()?;
+    let marked = mark_state(&mut chain_store, &runtime.get_tries(), &store, tail)?;
+    let shards = sweep_state(&store, &marked, !execute)?;
+    output.print(&TrieGcReport {
+        head_height: head.height,
+        tail_height: tail,
+        state_roots: marked.roots,
+        marked: marked.num_nodes(),
+        executed: execute,
+        shards: shards.into_values().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+    use near_store::test_utils::create_test_store;
+
+    fn state_key(shard_uid: ShardUId, hash: &CryptoHash) -> Vec<u8> {
+        [&shard_uid.to_bytes()[..], hash.as_ref()].concat()
+    }
+
+    #[test]
+    fn test_sweep_state() {
+        let store = create_test_store();
+        let shard_uid = ShardUId::single_shard();
+        let rows: Vec<(Vec<u8>, Vec<u8>, i64)> = (0..6u8)
+            .map(|i| {
+                let value = vec![i; 10];
+                (state_key(shard_uid, &hash(&value)), value, [1, 2, 1, 3, 1, -1][i as usize])
+            })
+            .collect();
+        let mut store_update = store.store_update();
+        for (key, value, rc) in &rows {
+            store_update.update_refcount(DBCol::State, key, value, *rc);
+        }
+        store_update.commit().unwrap();
+        // Rows 0 and 1 are reachable.
+        let marked = MarkedState {
+            nodes: HashMap::from([(shard_uid, (0..2u8).map(|i| hash(&[i; 10])).collect())]),
+            roots: 1,
+        };
+        let expected = ShardSweep {
+            shard_id: shard_uid.shard_id,
+            shard_version: shard_uid.version,
+            reachable: 2,
+            unreachable: 3,
+            unreachable_bytes: 3 * 50,
+            negative_refcount: 1,
+        };
+
+        let shards = sweep_state(&store, &marked, true).unwrap();
+        assert_eq!(shards.get(&shard_uid), Some(&expected));
+        assert!(store.get(DBCol::State, &rows[3].0).unwrap().is_some());
+
+        let shards = sweep_state(&store, &marked, false).unwrap();
+        assert_eq!(shards.get(&shard_uid), Some(&expected));
+        // The reachable rows keep their reference counts.
+        let raw: HashMap<_, _> = store.iter_raw_bytes(DBCol::State).collect();
+        for (key, value, rc) in &rows[..2] {
+            assert_eq!(decode_value_with_rc(&raw[&key[..]]), (Some(&value[..]), *rc));
+        }
+        for (key, _, _) in &rows[2..5] {
+            assert_eq!(store.get(DBCol::State, key).unwrap(), None);
+        }
+        let shards = sweep_state(&store, &marked, false).unwrap();
+        assert_eq!(shards[&shard_uid].unreachable, 0);
+    }
+}
@@ -0,0 +1,331 @@
+//! Index of the transactions and receipts of a range of blocks by account,
+//! to find the transactions of an account in an archival database without
+//! scanning it every time.
+//!
+//! The index is a RocksDB database of its own, separate from the node's one,
+//! where every key is an entry and every value the timestamp of its block:
+//!
+//! * signer account id → height and hash of the transaction,
+//! * signer public key → height and hash of the transaction,
+//! * receiver account id → height and id of the receipt.
+//!
+//! Entries of an account are sorted by height.  The range is indexed in
+//! parallel over sub-ranges of [`SUB_RANGE_SIZE`] heights, each written in a
+//! single batch together with a marker, so an interrupted build can be run
+//! again and skips the sub-ranges which are done.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Context;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_crypto::PublicKey;
+use near_primitives::hash::CryptoHash;
+use near_primitives::receipt::Receipt;
+use near_primitives::transaction::SignedTransaction;
+use near_primitives::types::{AccountId, BlockHeight};
+use near_primitives::utils::from_timestamp;
+use near_store::Store;
+use nearcore::NearConfig;
+use rayon::prelude::*;
+use rocksdb::{Direction, IteratorMode, WriteBatch, DB};
+
+/// Number of heights indexed in a single batch.
+const SUB_RANGE_SIZE: BlockHeight = 1000;
+
+const SIGNER: u8 = b's';
+const PUBLIC_KEY: u8 = b'k';
+const RECEIVER: u8 = b'r';
+const DONE: u8 = b'd';
+
+/// Kind of an index entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    /// Transaction signed by the account or with the public key.
+    Transaction,
+    /// Receipt received by the account.
+    Receipt,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct IndexEntry {
+    pub kind: EntryKind,
+    pub height: BlockHeight,
+    /// Hash of the transaction or id of the receipt.
+    pub hash: CryptoHash,
+    /// Timestamp of the block in nanoseconds.
+    pub timestamp: u64,
+}
+
+/// Key prefix of the entries of a signer, public key or receiver.
+fn prefix(kind: u8, name: &str) -> Vec<u8> {
+    // Neither account ids nor public keys contain zero bytes.
+    [&[kind][..], name.as_bytes(), &[0]].concat()
+}
+
+fn entry_key(kind: u8, name: &str, height: BlockHeight, hash: &CryptoHash) -> Vec<u8> {
+    [prefix(kind, name), height.to_be_bytes().to_vec(), hash.as_ref().to_vec()].concat()
+}
+
+fn done_key(from_height: BlockHeight, to_height: BlockHeight) -> Vec<u8> {
+    [&[DONE][..], &from_height.to_be_bytes(), &to_height.to_be_bytes()].concat()
+}
+
+/// Adds the entries of the transactions and receipts of a chunk to the batch.
+fn index_chunk(
+    batch: &mut WriteBatch,
+    height: BlockHeight,
+    timestamp: u64,
+    transactions: &[SignedTransaction],
+    receipts: &[Receipt],
+) {
+    let value = timestamp.to_be_bytes();
+    for tx in transactions {
+        let signer_id = tx.transaction.signer_id.as_ref();
+        batch.put(entry_key(SIGNER, signer_id, height, &tx.get_hash()), value);
+        let public_key = tx.transaction.public_key.to_string();
+        batch.put(entry_key(PUBLIC_KEY, &public_key, height, &tx.get_hash()), value);
+    }
+    for receipt in receipts {
+        let receiver_id = receipt.receiver_id.as_ref();
+        batch.put(entry_key(RECEIVER, receiver_id, height, &receipt.receipt_id), value);
+    }
+}
+
+#[derive(Default)]
+struct BuildStats {
+    transactions: AtomicU64,
+    receipts: AtomicU64,
+    missing_chunks: AtomicU64,
+}
+
+/// Indexes a sub-range in a single batch.
+fn index_sub_range(
+    index: &DB,
+    chain_store: &mut ChainStore,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    stats: &BuildStats,
+) -> anyhow::Result<()> {
+    let mut batch = WriteBatch::default();
+    for height in from_height..=to_height {
+        let block_hash = match chain_store.get_block_hash_by_height(height) {
+            Ok(block_hash) => block_hash,
+            Err(_) => continue,
+        };
+        let block = chain_store.get_block(&block_hash)?.clone();
+        let timestamp = block.header().raw_timestamp();
+        for chunk_header in block.chunks().iter() {
+            if chunk_header.height_included() != height {
+                continue;
+            }
+            let chunk = match chain_store.get_chunk(&chunk_header.chunk_hash()) {
+                Ok(chunk) => chunk,
+                Err(_) => {
+                    stats.missing_chunks.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            index_chunk(&mut batch, height, timestamp, chunk.transactions(), chunk.receipts());
+            stats.transactions.fetch_add(chunk.transactions().len() as u64, Ordering::Relaxed);
+            stats.receipts.fetch_add(chunk.receipts().len() as u64, Ordering::Relaxed);
+        }
+    }
+    batch.put(done_key(from_height, to_height), b"");
+    index.write(batch)?;
+    Ok(())
+}
+
+pub(crate) fn build_tx_index(
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+    out: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    if from_height > to_height {
+        anyhow::bail!("--from-height {} is above --to-height {}", from_height, to_height);
+    }
+    let index = DB::open_default(out).with_context(|| format!("opening {}", out.display()))?;
+    let sub_ranges: Vec<(BlockHeight, BlockHeight)> = (from_height..=to_height)
+        .step_by(SUB_RANGE_SIZE as usize)
+        .map(|start| (start, to_height.min(start.saturating_add(SUB_RANGE_SIZE - 1))))
+        .collect();
+    let mut pending = vec![];
+    for (start, end) in sub_ranges.iter().copied() {
+        if index.get(done_key(start, end))?.is_none() {
+            pending.push((start, end));
+        }
+    }
+    println!(
+        "Indexing #{}..=#{} into {}, {} of {} sub-ranges left",
+        from_height,
+        to_height,
+        out.display(),
+        pending.len(),
+        sub_ranges.len()
+    );
+
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let save_trie_changes = !near_config.client_config.archive;
+    let stats = BuildStats::default();
+    let done = AtomicU64::new(0);
+    pending.par_iter().try_for_each(|(start, end)| -> anyhow::Result<()> {
+        let mut chain_store = ChainStore::new(store.clone(), genesis_height, save_trie_changes);
+        index_sub_range(&index, &mut chain_store, *start, *end, &stats)
+            .with_context(|| format!("indexing #{}..=#{}", start, end))?;
+        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+        if done % 100 == 0 {
+            println!("Indexed {} of {} sub-ranges", done, pending.len());
+        }
+        Ok(())
+    })?;
+
+    println!(
+        "Indexed {} transactions and {} receipts",
+        stats.transactions.load(Ordering::Relaxed),
+        stats.receipts.load(Ordering::Relaxed)
+    );
+    let missing_chunks = stats.missing_chunks.load(Ordering::Relaxed);
+    if missing_chunks > 0 {
+        println!(
+            "{} chunks weren't found and aren't indexed, is the node archival?",
+            missing_chunks
+        );
+    }
+    Ok(())
+}
+
+/// Reads the entries of a signer, public key or receiver.
+fn read_entries(
+    index: &DB,
+    kind: u8,
+    name: &str,
+    entry_kind: EntryKind,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+) -> anyhow::Result<Vec<IndexEntry>> {
+    let prefix = prefix(kind, name);
+    let start = [prefix.clone(), from_height.to_be_bytes().to_vec()].concat();
+    let mut entries = vec![];
+    for (key, value) in index.iterator(IteratorMode::From(start.as_slice(), Direction::Forward)) {
+        let rest = match key.strip_prefix(prefix.as_slice()) {
+            Some(rest) => rest,
+            None => break,
+        };
+        let (height, hash) = rest.split_at(8);
+        let height = BlockHeight::from_be_bytes(height.try_into()?);
+        if height > to_height {
+            break;
+        }
+        let hash = CryptoHash::try_from(hash).map_err(|err| anyhow::anyhow!("{}", err))?;
+        let timestamp = u64::from_be_bytes(value.as_ref().try_into()?);
+        entries.push(IndexEntry { kind: entry_kind, height, hash, timestamp });
+    }
+    Ok(entries)
+}
+
+/// Returns the transactions signed by the account or with the public key and
+/// the receipts received by the account, sorted by height.
+pub(crate) fn query_index(
+    index: &DB,
+    account_id: Option<&AccountId>,
+    public_key: Option<&PublicKey>,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+) -> anyhow::Result<Vec<IndexEntry>> {
+    let mut entries = vec![];
+    if let Some(account_id) = account_id {
+        let account_id = account_id.as_ref();
+        let (from, to) = (from_height, to_height);
+        entries.extend(read_entries(index, SIGNER, account_id, EntryKind::Transaction, from, to)?);
+        entries.extend(read_entries(index, RECEIVER, account_id, EntryKind::Receipt, from, to)?);
+    }
+    if let Some(public_key) = public_key {
+        let public_key = public_key.to_string();
+        entries.extend(read_entries(
+            index,
+            PUBLIC_KEY,
+            &public_key,
+            EntryKind::Transaction,
+            from_height,
+            to_height,
+        )?);
+    }
+    entries.sort_by_key(|entry| (entry.height, entry.hash));
+    entries.dedup();
+    Ok(entries)
+}
+
+pub(crate) fn query_tx_index(
+    index_path: &Path,
+    account_id: Option<&AccountId>,
+    public_key: Option<&PublicKey>,
+    from_height: BlockHeight,
+    to_height: BlockHeight,
+) -> anyhow::Result<()> {
+    if account_id.is_none() && public_key.is_none() {
+        anyhow::bail!("either --account-id or --public-key has to be given");
+    }
+    let index = DB::open_for_read_only(&rocksdb::Options::default(), index_path, false)
+        .with_context(|| format!("opening {}", index_path.display()))?;
+    let entries = query_index(&index, account_id, public_key, from_height, to_height)?;
+    for entry in &entries {
+        let kind = match entry.kind {
+            EntryKind::Transaction => "transaction",
+            EntryKind::Receipt => "receipt",
+        };
+        println!(
+            "#{} {} {} {}",
+            entry.height,
+            from_timestamp(entry.timestamp).to_rfc3339(),
+            kind,
+            entry.hash
+        );
+    }
+    println!("{} entries", entries.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_crypto::{InMemorySigner, KeyType};
+
+    #[test]
+    fn test_query_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = DB::open_default(dir.path()).unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let signer = InMemorySigner::from_seed(alice.clone(), KeyType::ED25519, "alice.near");
+        let tx = |nonce| {
+            let hash = CryptoHash::default();
+            SignedTransaction::send_money(nonce, alice.clone(), bob.clone(), &signer, 1, hash)
+        };
+        let (tx1, tx2) = (tx(1), tx(2));
+        let receipt = Receipt::new_balance_refund(&bob, 1);
+
+        let mut batch = WriteBatch::default();
+        index_chunk(&mut batch, 10, 1000, &[tx1.clone()], &[]);
+        index_chunk(&mut batch, 20, 2000, &[tx2.clone()], &[receipt.clone()]);
+        index.write(batch).unwrap();
+
+        let entry = |kind, height, hash, timestamp| IndexEntry { kind, height, hash, timestamp };
+        let alice_entries = query_index(&index, Some(&alice), None, 0, 100).unwrap();
+        assert_eq!(
+            alice_entries,
+            vec![
+                entry(EntryKind::Transaction, 10, tx1.get_hash(), 1000),
+                entry(EntryKind::Transaction, 20, tx2.get_hash(), 2000),
+            ]
+        );
+        let bob_entries = query_index(&index, Some(&bob), None, 15, 20).unwrap();
+        assert_eq!(bob_entries, vec![entry(EntryKind::Receipt, 20, receipt.receipt_id, 2000)]);
+        let key_entries = query_index(&index, None, Some(&signer.public_key), 0, 15).unwrap();
+        assert_eq!(key_entries, vec![entry(EntryKind::Transaction, 10, tx1.get_hash(), 1000)]);
+        // "alice.near" is not a prefix match for "alice.nea".
+        let other: AccountId = "alice.nea".parse().unwrap();
+        assert_eq!(query_index(&index, Some(&other), None, 0, 100).unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,272 @@
+//! Offline re-validation of the canonical header chain, for the header chain of
+//! an archival database to be checked without trusting the node which built it.
+//!
+//! The canonical headers are walked by height up to the header head.  Each one
+//! is checked to be stored under its own hash, to point to the previous
+//! canonical header and to be above it, to be in the epoch and to name the next
+//! epoch its previous block determines, to be signed by the block producer of
+//! its epoch and height, and to carry approvals which verify and have enough
+//! stake.  Skipped heights have no header.
+//!
+//! The headers are read sequentially in batches and the checks which look into
+//! the epoch manager and verify signatures, which take most of the time, are
+//! run for every header of a batch over a thread pool.
+
+use crate::output::{failure, FailureKind};
+use near_chain::{
+    verify_header_approvals, BlockHeader, ChainStore, ChainStoreAccess, DoomslugThresholdMode,
+    RuntimeAdapter,
+};
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{BlockHeight, EpochId};
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use rayon::prelude::*;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Number of headers read before their checks are run in parallel.
+const BATCH_SIZE: usize = 10_000;
+
+const PROGRESS_PERIOD: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum HeaderFailure {
+    Hash { stored_as: CryptoHash },
+    PrevHash { expected: CryptoHash },
+    Height { prev_height: BlockHeight },
+    EpochId(String),
+    NextEpochId(String),
+    Signature(String),
+    Approvals(String),
+}
+
+impl std::fmt::Display for HeaderFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hash { stored_as } => write!(f, "stored under another hash, {}", stored_as),
+            Self::PrevHash { expected } => {
+                write!(f, "prev hash isn't the previous canonical header {}", expected)
+            }
+            Self::Height { prev_height } => {
+                write!(f, "height isn't above the one of the previous header, {}", prev_height)
+            }
+            Self::EpochId(error) => write!(f, "epoch id: {}", error),
+            Self::NextEpochId(error) => write!(f, "next epoch id: {}", error),
+            Self::Signature(error) => write!(f, "block producer signature: {}", error),
+            Self::Approvals(error) => write!(f, "approvals: {}", error),
+        }
+    }
+}
+
+/// How the header fits in the chain after the previous canonical header, cheap
+/// enough to be checked while reading.
+fn check_linkage(
+    header: &BlockHeader,
+    stored_as: &CryptoHash,
+    prev_header: &BlockHeader,
+) -> Vec<HeaderFailure> {
+    let mut failures = vec![];
+    if header.hash() != stored_as {
+        failures.push(HeaderFailure::Hash { stored_as: *stored_as });
+    }
+    if header.prev_hash() != prev_header.hash() {
+        failures.push(HeaderFailure::PrevHash { expected: *prev_header.hash() });
+    }
+    if header.height() <= prev_header.height() {
+        failures.push(HeaderFailure::Height { prev_height: prev_header.height() });
+    }
+    failures
+}
+
+fn check_epoch_id(
+    header_epoch_id: &EpochId,
+    derived: Result<EpochId, near_chain::Error>,
+) -> Option<String> {
+    match derived {
+        Ok(epoch_id) if &epoch_id == header_epoch_id => None,
+        Ok(epoch_id) => Some(format!("{:?} instead of {:?}", header_epoch_id, epoch_id)),
+        Err(err) => Some(err.to_string()),
+    }
+}
+
+/// Everything about the header which wouldn't pass the validation of the header
+/// by a node, given its previous header.
+fn check_header(
+    runtime: &dyn RuntimeAdapter,
+    doomslug_threshold_mode: DoomslugThresholdMode,
+    header: &BlockHeader,
+    prev_header: &BlockHeader,
+) -> Vec<HeaderFailure> {
+    let mut failures = vec![];
+    let epoch_id = runtime.get_epoch_id_from_prev_block(header.prev_hash());
+    if let Some(error) = check_epoch_id(header.epoch_id(), epoch_id) {
+        failures.push(HeaderFailure::EpochId(error));
+    }
+    let next_epoch_id = runtime.get_next_epoch_id_from_prev_block(header.prev_hash());
+    if let Some(error) = check_epoch_id(header.next_epoch_id(), next_epoch_id) {
+        failures.push(HeaderFailure::NextEpochId(error));
+    }
+    match runtime.verify_header_signature(header) {
+        Ok(true) => {}
+        Ok(false) => {
+            let producer = runtime
+                .get_block_producer(header.epoch_id(), header.height())
+                .map_or_else(|err| err.to_string(), |account_id| account_id.to_string());
+            failures.push(HeaderFailure::Signature(format!(
+                "doesn't verify with the key of {} or the producer is slashed",
+                producer
+            )));
+        }
+        Err(err) => failures.push(HeaderFailure::Signature(err.to_string())),
+    }
+    if let Err(err) = verify_header_approvals(runtime, doomslug_threshold_mode, header, prev_header)
+    {
+        failures.push(HeaderFailure::Approvals(err.to_string()));
+    }
+    failures
+}
+
+/// Re-validates the canonical headers from `from_height` up to the header
+/// head, with `threads` threads or one per CPU, printing every failure found
+/// and the first invalid header at the end.  Fails if there's any.
+pub(crate) fn validate_headers(
+    from_height: Option<BlockHeight>,
+    threads: Option<usize>,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    let genesis_height = near_config.genesis.config.genesis_height;
+    let mut chain_store =
+        ChainStore::new(store.clone(), genesis_height, !near_config.client_config.archive);
+    let runtime = NightshadeRuntime::with_config(
+        home_dir,
+        store,
+        &near_config,
+        None,
+        near_config.client_config.max_gas_burnt_view,
+    );
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads.unwrap_or(0)).build()?;
+
+    let start_height = from_height.unwrap_or(genesis_height).max(genesis_height + 1);
+    let head_height = chain_store.header_head()?.height;
+    // The canonical header right below the range, the genesis at worst.
+    let mut prev_header = (genesis_height..start_height)
+        .rev()
+        .find_map(|height| chain_store.get_block_hash_by_height(height).ok())
+        .map(|block_hash| chain_store.get_block_header(&block_hash).cloned())
+        .ok_or_else(|| anyhow::anyhow!("No canonical header below #{}", start_height))??;
+    println!(
+        "Validating the headers from #{} to the header head #{} with {} threads",
+        start_height,
+        head_height,
+        pool.current_num_threads()
+    );
+
+    let started = Instant::now();
+    let mut last_progress = started;
+    let (mut checked, mut failed) = (0u64, 0u64);
+    let mut first_failure: Option<(BlockHeight, CryptoHash)> = None;
+    let mut height = start_height;
+    while height <= head_height {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while batch.len() < BATCH_SIZE && height <= head_height {
+            if let Ok(block_hash) = chain_store.get_block_hash_by_height(height) {
+                let header = chain_store.get_block_header(&block_hash)?.clone();
+                let failures = check_linkage(&header, &block_hash, &prev_header);
+                let prev_header = std::mem::replace(&mut prev_header, header.clone());
+                batch.push((header, prev_header, failures));
+            }
+            height += 1;
+        }
+        let results: Vec<(BlockHeight, CryptoHash, Vec<HeaderFailure>)> = pool.install(|| {
+            batch
+                .into_par_iter()
+                .map(|(header, prev_header, mut failures)| {
+                    failures.extend(check_header(
+                        &runtime,
+                        DoomslugThresholdMode::TwoThirds,
+                        &header,
+                        &prev_header,
+                    ));
+                    (header.height(), *header.hash(), failures)
+                })
+                .collect()
+        });
+        for (header_height, block_hash, failures) in results {
+            for failure in &failures {
+                println!("#{} {}: {}", header_height, block_hash, failure);
+            }
+            if !failures.is_empty() {
+                failed += 1;
+                first_failure.get_or_insert((header_height, block_hash));
+            }
+            checked += 1;
+        }
+
+        if last_progress.elapsed() >= PROGRESS_PERIOD || height > head_height {
+            last_progress = Instant::now();
+            let elapsed = started.elapsed().as_secs_f64();
+            println!(
+                "Validated {} headers up to #{} of #{}, {} invalid, {:.0} headers/s",
+                checked,
+                height - 1,
+                head_height,
+                failed,
+                checked as f64 / elapsed.max(f64::EPSILON)
+            );
+        }
+    }
+
+    match first_failure {
+        Some((height, block_hash)) => Err(failure(
+            FailureKind::Corruption,
+            format!(
+                "{} of the {} headers validated are invalid, the first one is #{} {}",
+                failed, checked, height, block_hash
+            ),
+        )),
+        None => {
+            println!("All the {} headers validated are valid", checked);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_chain::test_utils::setup;
+    use near_crypto::KeyType;
+    use near_primitives::block::Block;
+    use near_primitives::validator_signer::InMemoryValidatorSigner;
+
+    #[test]
+    fn test_check_header() {
+        let (chain, runtime, signer) = setup();
+        let genesis = chain.genesis_block().clone();
+        let threshold_mode = DoomslugThresholdMode::NoApprovals;
+        let block = Block::empty(&genesis, &*signer);
+        let header = block.header();
+        assert!(check_linkage(header, header.hash(), genesis.header()).is_empty());
+        assert!(check_header(&*runtime, threshold_mode, header, genesis.header()).is_empty());
+
+        let other =
+            InMemoryValidatorSigner::from_seed("test".parse().unwrap(), KeyType::ED25519, "other");
+        let mut block = Block::empty(&genesis, &*signer);
+        block.mut_header().resign(&other);
+        let failures = check_header(&*runtime, threshold_mode, block.header(), genesis.header());
+        assert!(matches!(failures.as_slice(), [HeaderFailure::Signature(_)]), "{:?}", failures);
+
+        // A header is never its own predecessor.
+        assert_eq!(
+            check_linkage(header, genesis.hash(), header),
+            vec![
+                HeaderFailure::Hash { stored_as: *genesis.hash() },
+                HeaderFailure::PrevHash { expected: *header.hash() },
+                HeaderFailure::Height { prev_height: header.height() },
+            ]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Check of a genesis made by `dump-state` against the chain it was dumped
+//! from.
+//!
+//! The records are recomputed from the state of the source database at the
+//! height of the dump, the way `dump-state` computes them, and compared with the
+//! dumped ones.  Both sides are streamed: every record is assigned to a shard by
+//! its account and each shard gets a digest which doesn't depend on the order
+//! of the records, the sum of the hashes of their JSON encodings.  Only the
+//! shards whose digests differ are compared record by record, keeping the
+//! recomputed records of those shards in memory.  The total supply of the dumped
+//! genesis is checked against both the source state and the dumped records.
+
+use crate::commands::{load_trie_stop_at_height, LoadTrieMode};
+use crate::output::{failure, FailureKind};
+use crate::state_dump::{dump_validators, iterate_over_records};
+use near_chain_configs::{stream_records_from_file, GenesisConfig};
+use near_primitives::block::BlockHeader;
+use near_primitives::hash::{hash, CryptoHash};
+use near_primitives::serialize::to_base64;
+use near_primitives::shard_layout::{account_id_to_shard_id, ShardLayout};
+use near_primitives::state_record::{state_record_to_account_id, StateRecord};
+use near_primitives::types::{Balance, BlockHeight, ShardId, StateRoot};
+use near_store::Store;
+use nearcore::{NearConfig, NightshadeRuntime};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Records of each kind of difference printed at most.
+const MAX_PRINTED: usize = 100;
+
+/// Order-insensitive digest of the records of a shard.
+#[derive(Default, Debug, PartialEq, Eq)]
+struct ShardDigest {
+    num_records: u64,
+    sum: u128,
+}
+
+impl ShardDigest {
+    fn add(&mut self, record_hash: &CryptoHash) {
+        self.num_records += 1;
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&record_hash.as_ref()[..16]);
+        self.sum = self.sum.wrapping_add(u128::from_le_bytes(bytes));
+    }
+}
+
+/// What identifies the record in the state, for the records of both sides to
+/// be matched.
+fn record_id(record: &StateRecord) -> String {
+    match record {
+        StateRecord::Account { account_id, .. } => format!("Account {}", account_id),
+        StateRecord::Data { account_id, data_key, .. } => {
+            format!("Data {} {}", account_id, to_base64(data_key))
+        }
+        StateRecord::Contract { account_id, .. } => format!("Contract {}", account_id),
+        StateRecord::AccessKey { account_id, public_key, .. } => {
+            format!("AccessKey {} {}", account_id, public_key)
+        }
+        StateRecord::PostponedReceipt(receipt) => {
+            format!("PostponedReceipt {} {}", receipt.receiver_id, receipt.receipt_id)
+        }
+        StateRecord::ReceivedData { account_id, data_id, .. } => {
+            format!("ReceivedData {} {}", account_id, data_id)
+        }
+        StateRecord::DelayedReceipt(receipt) => {
+            format!("DelayedReceipt {} {}", receipt.receiver_id, receipt.receipt_id)
+        }
+    }
+}
+
+fn record_hash(record: &StateRecord) -> CryptoHash {
+    hash(&serde_json::to_vec(record).unwrap())
+}
+
+fn record_shard(record: &StateRecord, shard_layout: &ShardLayout) -> ShardId {
+    account_id_to_shard_id(state_record_to_account_id(record), shard_layout)
+}
+
+/// Where the records of the dump are, calling `callback` for each one.
+fn for_each_dumped_record(
+    records_path: &Path,
+    callback: impl FnMut(StateRecord),
+) -> anyhow::Result<()> {
+    let reader = BufReader::new(File::open(records_path)?);
+    stream_records_from_file(reader, callback)?;
+    Ok(())
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct DumpComparison {
+    /// Records in the source state missing from the dump.
+    pub missing: Vec<String>,
+    /// Records in the dump not in the source state.
+    pub extra: Vec<String>,
+    /// Records in both with different values.
+    pub differing: Vec<String>,
+    pub source_total_supply: Balance,
+    pub dumped_records_total_supply: Balance,
+    pub genesis_total_supply: Balance,
+}
+
+impl DumpComparison {
+    pub fn matches(&self) -> bool {
+        self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.differing.is_empty()
+            && self.source_total_supply == self.genesis_total_supply
+            && self.dumped_records_total_supply == self.genesis_total_supply
+    }
+}
+
+/// Compares the records of the dump with the ones recomputed from the state
+/// roots of the block the dump was made at.
+pub(crate) fn compare_dump(
+    runtime: &NightshadeRuntime,
+    state_roots: &[StateRoot],
+    last_block_header: &BlockHeader,
+    genesis_config: &GenesisConfig,
+    records_path: &Path,
+) -> anyhow::Result<DumpComparison> {
+    let shard_layout = &genesis_config.shard_layout;
+    let validators = dump_validators(runtime, last_block_header);
+    let mut comparison =
+        DumpComparison { genesis_total_supply: genesis_config.total_supply, ..Default::default() };
+
+    let mut source_digests: BTreeMap<ShardId, ShardDigest> = BTreeMap::new();
+    comparison.source_total_supply =
+        iterate_over_records(runtime, state_roots, last_block_header, &validators, &[], |record| {
+            let shard_id = record_shard(&record, shard_layout);
+            source_digests.entry(shard_id).or_default().add(&record_hash(&record));
+        });
+    let mut dumped_digests: BTreeMap<ShardId, ShardDigest> = BTreeMap::new();
+    for_each_dumped_record(records_path, |record| {
+        if let StateRecord::Account { account, .. } = &record {
+            comparison.dumped_records_total_supply += account.amount() + account.locked();
+        }
+        let shard_id = record_shard(&record, shard_layout);
+        dumped_digests.entry(shard_id).or_default().add(&record_hash(&record));
+    })?;
+
+    let shard_ids: BTreeSet<ShardId> =
+        source_digests.keys().chain(dumped_digests.keys()).copied().collect();
+    let differing_shards: BTreeSet<ShardId> = shard_ids
+        .into_iter()
+        .filter(|shard_id| source_digests.get(shard_id) != dumped_digests.get(shard_id))
+        .collect();
+    for shard_id in &differing_shards {
+        let source = source_digests.get(shard_id).map_or(0, |digest| digest.num_records);
+        let dumped = dumped_digests.get(shard_id).map_or(0, |digest| digest.num_records);
+        println!(
+            "Shard {} differs: {} records in the source state, {} in the dump",
+            shard_id, source, dumped
+        );
+    }
+    if differing_shards.is_empty() {
+        return Ok(comparison);
+    }
+
+    let mut source_records: HashMap<String, CryptoHash> = HashMap::new();
+    iterate_over_records(runtime, state_roots, last_block_header, &validators, &[], |record| {
+        if differing_shards.contains(&record_shard(&record, shard_layout)) {
+            source_records.insert(record_id(&record), record_hash(&record));
+        }
+    });
+    for_each_dumped_record(records_path, |record| {
+        if !differing_shards.contains(&record_shard(&record, shard_layout)) {
+            return;
+        }
+        let id = record_id(&record);
+        match source_records.remove(&id) {
+            None => comparison.extra.push(id),
+            Some(source_hash) if source_hash != record_hash(&record) => {
+                comparison.differing.push(id)
+            }
+            Some(_) => {}
+        }
+    })?;
+    comparison.missing = source_records.into_keys().collect();
+    comparison.missing.sort();
+    Ok(comparison)
+}
+
+fn print_records(what: &str, records: &[String]) {
+    if records.is_empty() {
+        return;
+    }
+    println!("{} {}:", records.len(), what);
+    for record in records.iter().take(MAX_PRINTED) {
+        println!("  {}", record);
+    }
+    if records.len() > MAX_PRINTED {
+        println!("  ...");
+    }
+}
+
+/// Checks the dump at `genesis_path` and `records_path` against the state of
+/// the source database at `height`, printing every difference.  Fails if there's
+/// any.
+pub(crate) fn verify_dump(
+    genesis_path: &Path,
+    records_path: Option<&Path>,
+    height: BlockHeight,
+    home_dir: &Path,
+    near_config: NearConfig,
+    store: Store,
+) -> anyhow::Result<()> {
+    let genesis_config = GenesisConfig::from_file(genesis_path)?;
+    let (runtime, state_roots, header) = load_trie_stop_at_height(
+        store,
+        home_dir,
+        &near_config,
+        LoadTrieMode::LastFinalFromHeight(height),
+    );
+    if header.height() + 1 != genesis_config.genesis_height {
+        return Err(failure(
+            FailureKind::InvalidArgument,
+            format!(
+                "The dump starts at height {}, a dump at height {} of the source chain would \
+                 start at {}",
+                genesis_config.genesis_height,
+                height,
+                header.height() + 1
+            ),
+        ));
+    }
+    println!("Comparing the dump with the state of #{} / {}", header.height(), header.hash());
+    // Records inline in the genesis file are streamed from it the same way.
+    let records_path = records_path.unwrap_or(genesis_path);
+    let comparison = compare_dump(&runtime, &state_roots, &header, &genesis_config, records_path)?;
+    print_records("records of the source state missing from the dump", &comparison.missing);
+    print_records("records of the dump not in the source state", &comparison.extra);
+    print_records("records with values differing from the source state", &comparison.differing);
+    println!(
+        "Total supply: {} in the genesis, {} in the source state, {} in the dumped records",
+        comparison.genesis_total_supply,
+        comparison.source_total_supply,
+        comparison.dumped_records_total_supply
+    );
+    if !comparison.matches() {
+        return Err(failure(FailureKind::Corruption, "The dump doesn't match the source chain"));
+    }
+    println!("The dump matches the source chain");
+    Ok(())
+}
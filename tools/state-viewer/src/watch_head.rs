@@ -0,0 +1,237 @@
+//! Following the head of the chain of a running node, like `tail -f`.
+//!
+//! A read-only RocksDB instance only sees the data which was there when it was
+//! opened, so the database is opened again on every poll.  This also means
+//! that files removed by compactions of the running node never matter for
+//! longer than a single poll: a failed poll is reported and retried.
+//!
+//! Every new head block is printed as a line of JSON.  If the head moves to
+//! another fork, a `"reorg": true` record with the hashes of the replaced
+//! blocks is printed before the blocks of the new fork.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use near_chain::{ChainStore, ChainStoreAccess};
+use near_primitives::block::Block;
+use near_primitives::epoch_manager::epoch_info::EpochInfo;
+use near_primitives::hash::CryptoHash;
+use near_primitives::types::{AccountId, BlockHeight};
+use near_store::{create_read_only_store, DBCol, ReadOnlyStore, StoreRead};
+use nearcore::{get_store_path, NearConfig};
+
+/// Number of printed blocks remembered to detect reorgs.
+const TRACKED_BLOCKS: usize = 1000;
+
+#[derive(serde::Serialize, Debug)]
+struct BlockRecord {
+    height: BlockHeight,
+    hash: CryptoHash,
+    prev_hash: CryptoHash,
+    producer: Option<AccountId>,
+    chunk_mask: Vec<bool>,
+    /// `None` if some of the chunks included in the block aren't available.
+    tx_count: Option<usize>,
+    /// Timestamp of the block in nanoseconds.
+    timestamp: u64,
+}
+
+#[derive(serde::Serialize, Debug)]
+struct ReorgRecord {
+    reorg: bool,
+    /// Height of the last block common to both forks, `None` if it's older
+    /// than any of the remembered blocks.
+    fork_height: Option<BlockHeight>,
+    replaced: Vec<CryptoHash>,
+}
+
+/// Blocks on the printed fork, by height.
+#[derive(Default)]
+struct HeadTracker {
+    blocks: BTreeMap<BlockHeight, CryptoHash>,
+}
+
+#[derive(Debug, PartialEq)]
+struct HeadChange {
+    /// Remembered blocks which are no longer on the fork of the head,
+    /// together with the height of the fork.
+    reorg: Option<(Option<BlockHeight>, Vec<CryptoHash>)>,
+    /// New blocks on the fork of the head, from the oldest one.
+    new_blocks: Vec<CryptoHash>,
+}
+
+impl HeadTracker {
+    /// Moves to the new head.  `parent` returns the height and hash of the
+    /// parent of a block.  Only the head itself is returned the first time.
+    fn update(
+        &mut self,
+        head_height: BlockHeight,
+        head_hash: CryptoHash,
+        mut parent: impl FnMut(&CryptoHash) -> anyhow::Result<(BlockHeight, CryptoHash)>,
+    ) -> anyhow::Result<HeadChange> {
+        let mut new_blocks = vec![];
+        let fork_height = match self.blocks.keys().next() {
+            None => None,
+            Some(&lowest) => {
+                let (mut height, mut hash) = (head_height, head_hash);
+                loop {
+                    if self.blocks.get(&height) == Some(&hash) {
+                        break Some(height);
+                    }
+                    new_blocks.push((height, hash));
+                    if height <= lowest {
+                        break None;
+                    }
+                    (height, hash) = parent(&hash)?;
+                }
+            }
+        };
+        if self.blocks.is_empty() {
+            new_blocks.push((head_height, head_hash));
+        }
+        let replaced: Vec<CryptoHash> = match fork_height {
+            Some(fork_height) => self.blocks.split_off(&(fork_height + 1)).into_values().collect(),
+            None => std::mem::take(&mut self.blocks).into_values().collect(),
+        };
+        let reorg = (!replaced.is_empty()).then(|| (fork_height, replaced));
+        new_blocks.reverse();
+        self.blocks.extend(new_blocks.iter().copied());
+        while self.blocks.len() > TRACKED_BLOCKS {
+            let lowest = *self.blocks.keys().next().unwrap();
+            self.blocks.remove(&lowest);
+        }
+        Ok(HeadChange { reorg, new_blocks: new_blocks.into_iter().map(|(_, hash)| hash).collect() })
+    }
+}
+
+pub(crate) fn watch_head(
+    home_dir: &Path,
+    near_config: NearConfig,
+    interval: Duration,
+) -> anyhow::Result<()> {
+    let store_path = get_store_path(home_dir);
+    let mut tracker = HeadTracker::default();
+    loop {
+        let store = create_read_only_store(&store_path, &near_config.config.store)
+            .with_context(|| format!("failed to open {}", store_path.display()));
+        let result = store.and_then(|store| poll(&mut tracker, &near_config, store));
+        if let Err(err) = result {
+            eprintln!("Failed to read the head, retrying: {:#}", err);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn poll(
+    tracker: &mut HeadTracker,
+    near_config: &NearConfig,
+    store: ReadOnlyStore,
+) -> anyhow::Result<()> {
+    let mut chain_store =
+        ChainStore::new(store.clone(), near_config.genesis.config.genesis_height, true);
+    let head = chain_store.head()?;
+    let change = tracker.update(head.height, head.last_block_hash, |hash| {
+        let prev_hash = *chain_store.get_block_header(hash)?.prev_hash();
+        Ok((chain_store.get_block_header(&prev_hash)?.height(), prev_hash))
+    })?;
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    if let Some((fork_height, replaced)) = change.reorg {
+        let record = ReorgRecord { reorg: true, fork_height, replaced };
+        writeln!(stdout, "{}", serde_json::to_string(&record)?)?;
+    }
+    for hash in change.new_blocks {
+        let block = chain_store.get_block(&hash)?.clone();
+        let record = block_record(&mut chain_store, &store, &block);
+        writeln!(stdout, "{}", serde_json::to_string(&record)?)?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+fn block_record(
+    chain_store: &mut ChainStore<ReadOnlyStore>,
+    store: &ReadOnlyStore,
+    block: &Block,
+) -> BlockRecord {
+    let header = block.header();
+    let tx_count = block
+        .chunks()
+        .iter()
+        .filter(|chunk_header| chunk_header.height_included() == header.height())
+        .map(|chunk_header| {
+            chain_store
+                .get_chunk(&chunk_header.chunk_hash())
+                .map(|chunk| chunk.transactions().len())
+        })
+        .sum::<Result<usize, _>>()
+        .ok();
+    BlockRecord {
+        height: header.height(),
+        hash: *header.hash(),
+        prev_hash: *header.prev_hash(),
+        producer: store
+            .get_ser::<EpochInfo>(DBCol::EpochInfo, header.epoch_id().as_ref())
+            .ok()
+            .flatten()
+            .map(|epoch_info| {
+                let validator_id = epoch_info.sample_block_producer(header.height());
+                epoch_info.get_validator(validator_id).take_account_id()
+            }),
+        chunk_mask: header.chunk_mask().to_vec(),
+        tx_count,
+        timestamp: header.raw_timestamp(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_primitives::hash::hash;
+    use std::collections::HashMap;
+
+    /// Returns the hash of the block at `height` on the fork `fork`.
+    fn block(fork: u8, height: BlockHeight) -> CryptoHash {
+        hash(&[&[fork][..], &height.to_le_bytes()[..]].concat())
+    }
+
+    #[test]
+    fn test_head_tracker() {
+        // Fork 1 branches off fork 0 after height 3.
+        let mut parents = HashMap::new();
+        for height in 1..10 {
+            parents.insert(block(0, height), (height - 1, block(0, height - 1)));
+            let parent_fork = if height == 4 { 0 } else { 1 };
+            parents.insert(block(1, height), (height - 1, block(parent_fork, height - 1)));
+        }
+        let parent = |hash: &CryptoHash| -> anyhow::Result<_> { Ok(parents[hash]) };
+        let mut tracker = HeadTracker::default();
+
+        let change = tracker.update(2, block(0, 2), parent).unwrap();
+        assert_eq!(change, HeadChange { reorg: None, new_blocks: vec![block(0, 2)] });
+        let change = tracker.update(2, block(0, 2), parent).unwrap();
+        assert_eq!(change, HeadChange { reorg: None, new_blocks: vec![] });
+        let change = tracker.update(5, block(0, 5), parent).unwrap();
+        assert_eq!(
+            change,
+            HeadChange { reorg: None, new_blocks: vec![block(0, 3), block(0, 4), block(0, 5)] }
+        );
+        let change = tracker.update(6, block(1, 6), parent).unwrap();
+        assert_eq!(
+            change,
+            HeadChange {
+                reorg: Some((Some(3), vec![block(0, 4), block(0, 5)])),
+                new_blocks: vec![block(1, 4), block(1, 5), block(1, 6)],
+            }
+        );
+        // The head moving back to a printed block is a reorg too.
+        let change = tracker.update(5, block(1, 5), parent).unwrap();
+        assert_eq!(
+            change,
+            HeadChange { reorg: Some((Some(5), vec![block(1, 6)])), new_blocks: vec![] }
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Size of the state witness of chunks, measured by applying them with a trie
+//! recording the nodes it reads, see `--record-witness` of `apply-chunk` and
+//! `apply-range`.
+//!
+//! Chunks aren't validated statelessly in this version so the protocol has no
+//! limits of the witness size, the ones the chunks are checked against are
+//! given on the command line.
+
+use near_chain::types::ApplyTransactionResult;
+use near_primitives::hash::CryptoHash;
+
+/// Columns added to the CSV file of `apply-range` by `--record-witness`.
+pub(crate) const WITNESS_CSV_HEADER: &str =
+    "WitnessBytes,WitnessNodes,LargestReceipt,LargestReceiptBytes,WitnessOverLimit";
+
+#[derive(clap::Args, Clone, Copy, Debug)]
+pub(crate) struct WitnessLimits {
+    /// Witness size in bytes above which a chunk is flagged as over the soft
+    /// limit.
+    #[clap(long, default_value = "3000000")]
+    pub witness_soft_limit: u64,
+    /// Witness size in bytes above which a chunk is flagged as over the hard
+    /// limit.
+    #[clap(long, default_value = "16000000")]
+    pub witness_hard_limit: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WitnessLimit {
+    Soft,
+    Hard,
+}
+
+impl WitnessLimit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WitnessLimit::Soft => "soft",
+            WitnessLimit::Hard => "hard",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WitnessSize {
+    /// Total size of the recorded trie nodes and values.
+    pub bytes: u64,
+    pub nodes: usize,
+    /// Receipt which recorded the most bytes not recorded before it, with
+    /// the number of bytes.
+    pub largest_receipt: Option<(CryptoHash, u64)>,
+}
+
+impl WitnessSize {
+    /// `None` if the chunk was applied without recording.
+    pub fn from_apply_result(apply_result: &ApplyTransactionResult) -> Option<Self> {
+        let nodes = &apply_result.proof.as_ref()?.nodes.0;
+        Some(Self {
+            bytes: nodes.iter().map(|node| node.len() as u64).sum(),
+            nodes: nodes.len(),
+            largest_receipt: apply_result.largest_receipt_proof,
+        })
+    }
+
+    /// The highest limit the witness is above.
+    pub fn exceeded_limit(&self, limits: &WitnessLimits) -> Option<WitnessLimit> {
+        if self.bytes > limits.witness_hard_limit {
+            Some(WitnessLimit::Hard)
+        } else if self.bytes > limits.witness_soft_limit {
+            Some(WitnessLimit::Soft)
+        } else {
+            None
+        }
+    }
+
+    /// Values of the columns in [`WITNESS_CSV_HEADER`].
+    pub fn csv_fields(&self, limits: &WitnessLimits) -> String {
+        let (receipt_id, receipt_bytes) = match self.largest_receipt {
+            Some((receipt_id, bytes)) => (receipt_id.to_string(), bytes.to_string()),
+            None => (String::new(), String::new()),
+        };
+        format!(
+            "{},{},{},{},{}",
+            self.bytes,
+            self.nodes,
+            receipt_id,
+            receipt_bytes,
+            self.exceeded_limit(limits).map_or("", WitnessLimit::as_str)
+        )
+    }
+
+    pub fn report(&self, limits: &WitnessLimits) -> String {
+        let mut report = format!("witness: {} bytes in {} nodes", self.bytes, self.nodes);
+        if let Some((receipt_id, bytes)) = self.largest_receipt {
+            report += &format!(", largest receipt {} with {} bytes", receipt_id, bytes);
+        }
+        if let Some(limit) = self.exceeded_limit(limits) {
+            report += &format!(", above the {} limit", limit.as_str());
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_witness_limits() {
+        let limits = WitnessLimits { witness_soft_limit: 100, witness_hard_limit: 200 };
+        let mut size = WitnessSize { bytes: 100, nodes: 3, largest_receipt: None };
+        assert_eq!(size.exceeded_limit(&limits), None);
+        assert_eq!(size.csv_fields(&limits), "100,3,,,");
+
+        size.bytes = 150;
+        size.largest_receipt = Some((CryptoHash::default(), 120));
+        assert_eq!(size.exceeded_limit(&limits), Some(WitnessLimit::Soft));
+        assert_eq!(size.csv_fields(&limits), format!("150,3,{},120,soft", CryptoHash::default()));
+
+        size.bytes = 201;
+        assert_eq!(size.exceeded_limit(&limits), Some(WitnessLimit::Hard));
+        assert!(size.report(&limits).ends_with("above the hard limit"));
+    }
+}